@@ -185,7 +185,7 @@ document.addEventListener('DOMContentLoaded', function () {
 
             if (response.ok) {
                 // Remove from local config
-                configData.cameras = configData.cameras.filter(camera => camera.index !== cameraIndex);
+                configData.cameras = configData.cameras.filter(camera => camera.usb?.index !== cameraIndex);
                 updateCamerasList();
                 showToast('Camera deleted successfully', 'success');
             } else {
@@ -313,17 +313,19 @@ document.addEventListener('DOMContentLoaded', function () {
             return;
         }
 
-        const camerasHTML = configData.cameras.map(camera => `
-            <div class="camera-config-item" data-camera-index="${camera.index}">
+        const camerasHTML = configData.cameras.map(camera => {
+            const usbIndex = camera.usb?.index;
+            return `
+            <div class="camera-config-item" data-camera-id="${camera.id}">
                 <div class="camera-config-info">
                     <div class="camera-config-header">
-                        <h3>${camera.name}</h3>
+                        <h3>${camera.nickname || camera.name}</h3>
                         <span class="camera-status status-${camera.is_active ? 'active' : 'inactive'}">
                             ${camera.is_active ? 'Active' : 'Inactive'}
                         </span>
                     </div>
                     <div class="camera-config-details">
-                        <span class="camera-detail">Index: ${camera.index}</span>
+                        ${usbIndex !== undefined ? `<span class="camera-detail">Index: ${usbIndex}</span>` : ''}
                         <span class="camera-detail">Resolution: ${camera.resolution}</span>
                         ${camera.view_type ? `<span class="camera-detail">View: ${camera.view_type}</span>` : ''}
                         ${camera.region_x !== null && camera.region_x !== undefined ?
@@ -333,12 +335,13 @@ document.addEventListener('DOMContentLoaded', function () {
                     </div>
                 </div>
                 <div class="camera-config-actions">
-                    <button class="btn btn-sm btn-danger delete-camera-btn" data-camera-index="${camera.index}">
+                    ${usbIndex !== undefined ? `<button class="btn btn-sm btn-danger delete-camera-btn" data-camera-index="${usbIndex}">
                         Delete
-                    </button>
+                    </button>` : ''}
                 </div>
             </div>
-        `).join('');
+        `;
+        }).join('');
 
         camerasConfigList.innerHTML = camerasHTML;
     }
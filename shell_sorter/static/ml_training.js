@@ -203,9 +203,9 @@ class MLTrainingInterface {
             ? shell.captured_images 
             : shell.image_filenames.map(filename => ({ filename, view_type: null }));
         
-        // Sort images: side views first, then tail views, then unknown/unspecified
+        // Sort images: side, tail, then mouth views, then unknown/unspecified
         const sortedImages = [...images].sort((a, b) => {
-            const viewOrder = { 'side': 0, 'tail': 1, 'unknown': 2, null: 3, undefined: 3 };
+            const viewOrder = { 'side': 0, 'tail': 1, 'mouth': 2, 'unknown': 3, null: 4, undefined: 4 };
             return viewOrder[a.view_type] - viewOrder[b.view_type];
         });
         
@@ -250,6 +250,7 @@ class MLTrainingInterface {
         switch(viewType) {
             case 'side': return 'Side View';
             case 'tail': return 'Tail View';
+            case 'mouth': return 'Mouth View';
             case 'unknown': return 'Unknown';
             default: return 'Unknown';
         }
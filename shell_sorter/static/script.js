@@ -116,7 +116,7 @@ const RegionStorage = {
         }
 
         cameraData.forEach(camera => {
-            if (camera.region_x !== null && camera.region_x !== undefined) {
+            if (camera.usb && camera.region_x !== null && camera.region_x !== undefined) {
                 const serverRegion = {
                     x: camera.region_x,
                     y: camera.region_y,
@@ -125,8 +125,8 @@ const RegionStorage = {
                 };
 
                 // Update localStorage if server has newer data
-                if (!regions[camera.index] || JSON.stringify(regions[camera.index]) !== JSON.stringify(serverRegion)) {
-                    regions[camera.index] = serverRegion;
+                if (!regions[camera.usb.index] || JSON.stringify(regions[camera.usb.index]) !== JSON.stringify(serverRegion)) {
+                    regions[camera.usb.index] = serverRegion;
                     updated = true;
                 }
             }
@@ -148,6 +148,7 @@ async function loadCameras() {
             if (apiResponse.success && apiResponse.data) {
                 const cameras = apiResponse.data;
                 displayCameras(cameras);
+                document.getElementById('camera-list')?.classList.remove('stale');
             } else {
                 console.warn('Failed to load cameras:', apiResponse.message);
                 displayNoCameras();
@@ -177,44 +178,49 @@ function displayCameras(cameras) {
 
     cameras.forEach(camera => {
         const cameraItem = document.createElement('div');
-        cameraItem.className = 'camera-item';
+        cameraItem.className = camera.stale ? 'camera-item camera-stale' : 'camera-item';
         cameraItem.dataset.cameraId = camera.id;
         // Also set camera-index for backward compatibility with existing region code
-        if (camera.index !== undefined) {
-            cameraItem.dataset.cameraIndex = camera.index;
+        const usbIndex = camera.usb ? camera.usb.index : undefined;
+        if (usbIndex !== undefined) {
+            cameraItem.dataset.cameraIndex = usbIndex;
         }
 
         cameraItem.innerHTML = `
             <div class="camera-header">
                 <label class="camera-checkbox-label">
-                    <input type="checkbox" class="camera-checkbox" data-camera-id="${camera.id}" ${camera.is_selected ? 'checked' : ''}>
-                    <span class="camera-name">${camera.name}</span>
-                    <span class="camera-type">(${camera.camera_type})</span>
+                    <input type="checkbox" class="camera-checkbox" data-camera-id="${camera.id}" data-mutates ${camera.is_selected ? 'checked' : ''}>
+                    <span class="camera-name">${camera.nickname || camera.name}</span>
+                    <span class="camera-type">(${camera.kind})</span>
                     <span class="camera-details">
                         <span class="camera-info-icon" title="Camera Details">ℹ️</span>
                         <div class="camera-info-tooltip">
                             <div><strong>ID:</strong> ${camera.id}</div>
-                            ${camera.hostname ? `<div><strong>Host:</strong> ${camera.hostname}</div>` : ''}
-                            ${camera.index !== undefined ? `<div><strong>Index:</strong> ${camera.index}</div>` : ''}
-                            ${camera.vendor_id ? `<div><strong>Vendor:</strong> ${camera.vendor_id}</div>` : ''}
-                            ${camera.product_id ? `<div><strong>Product:</strong> ${camera.product_id}</div>` : ''}
-                            ${camera.serial_number ? `<div><strong>Serial:</strong> ${camera.serial_number}</div>` : ''}
+                            ${camera.nickname ? `<div><strong>Name:</strong> ${camera.name}</div>` : ''}
+                            ${camera.esp ? `<div><strong>Host:</strong> ${camera.esp.hostname}</div>` : ''}
+                            ${usbIndex !== undefined ? `<div><strong>Index:</strong> ${usbIndex}</div>` : ''}
+                            ${camera.usb && camera.usb.vendor_id ? `<div><strong>Vendor:</strong> ${camera.usb.vendor_id}</div>` : ''}
+                            ${camera.usb && camera.usb.product_id ? `<div><strong>Product:</strong> ${camera.usb.product_id}</div>` : ''}
+                            ${camera.usb && camera.usb.serial_number ? `<div><strong>Serial:</strong> ${camera.usb.serial_number}</div>` : ''}
                         </div>
                     </span>
                 </label>
+                ${camera.capture_stats && camera.capture_stats.failed > 0 ? `<span class="capture-error-badge" title="${camera.capture_stats.last_error || ''}">${camera.capture_stats.failed} failed</span>` : ''}
+                ${streamHealthBadge(camera)}
+                ${offlineBadge(camera)}
                 <span class="camera-status ${camera.is_active ? 'status-active' : 'status-inactive'}">${camera.is_active ? 'Active' : 'Inactive'}</span>
             </div>
             <div class="camera-controls">
-                <button class="btn btn-sm btn-secondary camera-view-type-btn" data-camera-id="${camera.id}" ${camera.index !== undefined ? `data-camera-index="${camera.index}"` : ''}>
+                <button class="btn btn-sm btn-secondary camera-view-type-btn" data-camera-id="${camera.id}" ${usbIndex !== undefined ? `data-camera-index="${usbIndex}"` : ''}>
                     Set View Type
                 </button>
-                <button class="btn btn-sm btn-secondary camera-region-btn" data-camera-id="${camera.id}" ${camera.index !== undefined ? `data-camera-index="${camera.index}"` : ''}>
+                <button class="btn btn-sm btn-secondary camera-region-btn" data-camera-id="${camera.id}" ${usbIndex !== undefined ? `data-camera-index="${usbIndex}"` : ''}>
                     Set Region
                 </button>
-                <button class="btn btn-sm btn-secondary camera-autofocus-btn" data-camera-id="${camera.id}" ${camera.index !== undefined ? `data-camera-index="${camera.index}"` : ''}>
+                <button class="btn btn-sm btn-secondary camera-autofocus-btn" data-camera-id="${camera.id}" ${usbIndex !== undefined ? `data-camera-index="${usbIndex}"` : ''}>
                     Autofocus
                 </button>
-                ${camera.camera_type === 'usb' ? `
+                ${camera.kind === 'usb' ? `
                     <div class="brightness-control">
                         <label for="brightness-${camera.id}" class="brightness-label">Brightness:</label>
                         <input type="range" id="brightness-${camera.id}" class="brightness-slider" 
@@ -238,6 +244,28 @@ function displayCameras(cameras) {
     // the restored selections that were just applied from the backend
 }
 
+// Render the initial state the server embedded in the page
+function hydrateFromBootstrap() {
+    const bootstrapElement = document.getElementById('dashboard-bootstrap');
+    if (!bootstrapElement) return;
+
+    let bootstrap;
+    try {
+        bootstrap = JSON.parse(bootstrapElement.textContent);
+    } catch (error) {
+        console.warn('Failed to parse dashboard bootstrap data:', error);
+        return;
+    }
+
+    if (bootstrap.cameras && bootstrap.cameras.length > 0) {
+        displayCameras(bootstrap.cameras);
+    }
+    updateStatusDisplay({
+        status: bootstrap.machine_status.toLowerCase(),
+        total_sorted: bootstrap.total_sorted
+    });
+}
+
 // Function to display "no cameras" message
 function displayNoCameras() {
     const cameraList = document.getElementById('camera-list');
@@ -279,8 +307,8 @@ async function updateCameraFeeds() {
                         feedDiv.className = 'camera-feed';
                         feedDiv.dataset.cameraId = camera.id;
                         // Also set camera-index for backward compatibility with region code
-                        if (camera.index !== undefined) {
-                            feedDiv.dataset.cameraIndex = camera.index.toString();
+                        if (camera.usb) {
+                            feedDiv.dataset.cameraIndex = camera.usb.index.toString();
                         }
 
                         // Add region data if available
@@ -293,10 +321,7 @@ async function updateCameraFeeds() {
                             });
                         }
 
-                        feedDiv.innerHTML = `<img src="/api/cameras/${camera.id}/stream" 
-                                                     alt="Camera ${camera.name} feed"
-                                                     class="camera-stream"
-                                                     onerror="console.error('Failed to load camera stream for ${camera.id}')">`;
+                        feedDiv.appendChild(createStreamImage(camera));
                         cameraItem.appendChild(feedDiv);
                         console.log(`Created camera feed for ${camera.id} with stream URL: /api/cameras/${camera.id}/stream`);
                     } else if (!camera.is_active && existingFeed) {
@@ -304,6 +329,10 @@ async function updateCameraFeeds() {
                         console.log(`Removed camera feed for ${camera.id}`);
                     } else if (camera.is_active && existingFeed) {
                         console.log(`Camera feed already exists for ${camera.id}`);
+                        const img = existingFeed.querySelector('.camera-stream');
+                        if (img && camera.stream_health && camera.stream_health.stalled) {
+                            markStreamStalled(img, camera.id);
+                        }
                     }
                 } else {
                     console.warn(`Could not find camera item for ID: ${camera.id}`);
@@ -321,6 +350,84 @@ async function updateCameraFeeds() {
     return null;
 }
 
+// Badge showing a stalled stream, or the measured frame rate of a live one
+function streamHealthBadge(camera) {
+    const health = camera.stream_health;
+    if (!health) {
+        return '';
+    }
+    const polled = camera.stream_mode === 'snapshot_poll'
+        ? '<span class="stream-health-badge stream-polled" title="The camera has no MJPEG stream, so its snapshots are polled">Snapshots</span> '
+        : '';
+    return polled + streamHealthState(health);
+}
+
+function streamHealthState(health) {
+    const lastFrame = health.last_frame ? `Last frame ${new Date(health.last_frame).toLocaleTimeString()}` : 'No frames yet';
+    if (health.stalled) {
+        return `<span class="stream-health-badge stream-stalled" title="${lastFrame}">Stalled</span>`;
+    }
+    if (health.fps > 0) {
+        return `<span class="stream-health-badge" title="${lastFrame}">${health.fps.toFixed(1)} fps</span>`;
+    }
+    return '';
+}
+
+// How long a network camera has been offline, with the last error as its tooltip
+function offlineBadge(camera) {
+    if (camera.online) {
+        return '';
+    }
+    const title = (camera.esp && camera.esp.last_error) || '';
+    if (camera.offline_for_secs === null || camera.offline_for_secs === undefined) {
+        return `<span class="offline-badge" title="${title}">Offline</span>`;
+    }
+    const minutes = Math.floor(camera.offline_for_secs / 60);
+    const duration = minutes > 0 ? `${minutes}m` : `${camera.offline_for_secs}s`;
+    const staleClass = camera.stale ? ' offline-stale' : '';
+    return `<span class="offline-badge${staleClass}" title="${title}">Offline ${duration}</span>`;
+}
+
+// The live MJPEG feed for a camera, offering a reconnect when the stream fails or is ended as stalled
+function createStreamImage(camera) {
+    const img = document.createElement('img');
+    img.src = `/api/cameras/${camera.id}/stream`;
+    img.alt = `Camera ${camera.name} feed`;
+    img.className = 'camera-stream';
+    // Streams the server doesn't turn upright itself are turned in the browser
+    if (!camera.stream_oriented && camera.orientation) {
+        if (camera.orientation.rotation) {
+            img.classList.add(`rotate-${camera.orientation.rotation}`);
+        }
+        if (camera.orientation.mirror) {
+            img.classList.add('mirrored');
+        }
+    }
+    img.addEventListener('error', () => {
+        console.error(`Failed to load camera stream for ${camera.id}`);
+        markStreamStalled(img, camera.id);
+    });
+    return img;
+}
+
+function markStreamStalled(img, cameraId) {
+    const feedDiv = img.closest('.camera-feed');
+    if (!feedDiv || feedDiv.querySelector('.stream-reconnect-btn')) {
+        return;
+    }
+    feedDiv.classList.add('stream-stalled');
+    const button = document.createElement('button');
+    button.className = 'btn btn-sm btn-secondary stream-reconnect-btn';
+    button.textContent = 'Reconnect';
+    button.addEventListener('click', () => {
+        feedDiv.classList.remove('stream-stalled');
+        button.remove();
+        // A fresh URL forces the browser to open a new stream
+        img.src = `/api/cameras/${cameraId}/stream?t=${Date.now()}`;
+    });
+    feedDiv.appendChild(button);
+}
+
 let currentCameraPollInterval = null;
 
 function pollForCameraUpdates() {
@@ -374,6 +481,63 @@ function updateStatusDisplay(status) {
     if (totalSorted) {
         totalSorted.textContent = status.total_sorted;
     }
+
+    // Buttons marked data-mutates are greyed out while the server refuses changes
+    const readOnly = Boolean(status.read_only);
+    document.body.classList.toggle('read-only', readOnly);
+    const readOnlyBanner = document.getElementById('read-only-banner');
+    if (readOnlyBanner) {
+        readOnlyBanner.hidden = !readOnly;
+    }
+
+    const presetIndicator = document.getElementById('camera-preset');
+    const presetName = document.getElementById('camera-preset-name');
+    if (presetIndicator && presetName) {
+        presetIndicator.hidden = !status.active_camera_preset;
+        presetName.textContent = status.active_camera_preset || '';
+    }
+
+    const maintenanceBanner = document.getElementById('maintenance-banner');
+    if (maintenanceBanner && status.maintenance) {
+        const maintenance = status.maintenance;
+        maintenanceBanner.hidden = !maintenance.enabled;
+        maintenanceBanner.textContent = maintenance.enabled
+            ? `Maintenance mode${maintenance.reason ? `: ${maintenance.reason}` : ''}. ` +
+              'Nothing will move until it is turned off' +
+              (maintenance.expires_at
+                  ? ` (turns itself off at ${new Date(maintenance.expires_at).toLocaleTimeString()})`
+                  : '')
+            : '';
+    }
+
+    const overheatBanner = document.getElementById('overheat-banner');
+    if (overheatBanner && status.overheating) {
+        overheatBanner.hidden = status.overheating.length === 0;
+        overheatBanner.textContent = status.overheating
+            .map(reading => `${reading.sensor} is overheating at ${reading.celsius.toFixed(1)} °C`)
+            .join('. ');
+    }
+}
+
+// Fetch /api/status and update the header, camera preset, read-only, maintenance and overheating banners
+async function refreshStatus() {
+    try {
+        const controller = new AbortController();
+        const timeoutId = setTimeout(() => controller.abort(), 2500);
+
+        const response = await fetch('/api/status', {
+            signal: controller.signal
+        });
+
+        clearTimeout(timeoutId);
+
+        if (response.ok) {
+            const status = await response.json();
+            updateStatusDisplay(status);
+        }
+    } catch (error) {
+        console.error('Error fetching status:', error);
+    }
 }
 
 // Initialize overlay state on page load - always show overlays
@@ -530,6 +694,17 @@ async function updateESPHomeStatus() {
                     statusElement.classList.add('esphome-status-offline');
                     statusText.textContent = 'Offline';
                 }
+                // The age comes from the server, whose clock may not match this one
+                const lastSeenAge = status.data && status.data.last_seen_age_seconds;
+                statusElement.title = lastSeenAge != null ? `Last seen ${lastSeenAge}s ago` : '';
+
+                // Make it obvious when the controller data is recorded or replayed
+                const controllerMode = status.data && status.data.controller_mode;
+                if (controllerMode && controllerMode !== 'live') {
+                    statusElement.classList.add(`esphome-status-${controllerMode}`);
+                    statusText.textContent += ` (${controllerMode})`;
+                    statusElement.title = status.data.controller_recording || '';
+                }
 
                 // If status changed, update polling interval
                 if (wasOnline !== isControllerOnline && esphomeStatusInterval) {
@@ -576,7 +751,42 @@ async function updateESPHomeStatusWithAdaptivePolling() {
     esphomeStatusInterval = setInterval(updateESPHomeStatusWithAdaptivePolling, pollInterval);
 }
 
+// Send a fresh install to the first-run setup page, unless it was skipped
+async function redirectIfSetupNeeded() {
+    if (sessionStorage.getItem('setupSkipped')) {
+        return;
+    }
+    try {
+        const response = await fetch('/api/status');
+        if (response.ok) {
+            const status = await response.json();
+            if (status.needs_setup) {
+                window.location.href = '/setup';
+            }
+        }
+    } catch (error) {
+        console.error('Error checking whether setup is needed:', error);
+    }
+}
+
+// Toast case types reaching their shell count target
+function watchTargetEvents() {
+    if (!window.EventSource) {
+        return;
+    }
+    const events = new EventSource('/api/ml/progress/events');
+    events.addEventListener('target_reached', function (event) {
+        const reached = JSON.parse(event.data);
+        showToast(reached.message, 'success');
+    });
+}
+
 document.addEventListener('DOMContentLoaded', function () {
+    if (window.location.pathname === '/') {
+        redirectIfSetupNeeded();
+    }
+    watchTargetEvents();
+
     // Camera management elements
     const detectCamerasBtn = document.getElementById('detect-cameras-btn');
     const startSelectedBtn = document.getElementById('start-selected-btn');
@@ -601,9 +811,13 @@ document.addEventListener('DOMContentLoaded', function () {
 
                 if (response.ok) {
                     const result = await response.json();
-                    showToast(result.message, 'success');
+                    if (result.data.missing_cameras.length > 0) {
+                        showToast(`No image from ${result.data.missing_cameras.join(', ')}`, 'warning');
+                    } else {
+                        showToast(result.message, 'success');
+                    }
                     // Redirect to tagging interface
-                    window.location.href = `/tagging/${result.session_id}`;
+                    window.location.href = `/tagging/${result.data.session_id}`;
                 } else {
                     const error = await response.text();
                     showToast('Error capturing images: ' + error, 'error');
@@ -665,25 +879,8 @@ document.addEventListener('DOMContentLoaded', function () {
     }
 
     // Auto-refresh status every 25 seconds
-    let statusInterval = setInterval(async function () {
-        try {
-            const controller = new AbortController();
-            const timeoutId = setTimeout(() => controller.abort(), 2500);
-
-            const response = await fetch('/api/status', {
-                signal: controller.signal
-            });
-
-            clearTimeout(timeoutId);
-
-            if (response.ok) {
-                const status = await response.json();
-                updateStatusDisplay(status);
-            }
-        } catch (error) {
-            console.error('Error fetching status:', error);
-        }
-    }, 25000);
+    refreshStatus();
+    let statusInterval = setInterval(refreshStatus, 25000);
 
 
 
@@ -712,8 +909,8 @@ document.addEventListener('DOMContentLoaded', function () {
             showToast('Detecting cameras...', 'info');
 
             try {
-                // Trigger async detection
-                const detectResponse = await fetch('/api/cameras/detect');
+                // Trigger async detection, even if another tab detected moments ago
+                const detectResponse = await fetch('/api/cameras/detect?force=true');
                 
                 if (detectResponse.ok) {
                     const detectApiResponse = await detectResponse.json();
@@ -943,6 +1140,9 @@ document.addEventListener('DOMContentLoaded', function () {
 
 
 
+    // Show the server-rendered state straight away, then refresh it from the API
+    hydrateFromBootstrap();
+
     // Load and display cameras on page load
     loadCameras();
 
@@ -1095,6 +1295,7 @@ document.addEventListener('DOMContentLoaded', function () {
                     connectDebugWebSocket();
                     addDebugEntry('info', 'Debug console connected');
                 }
+                connectLogStream();
             });
         }
 
@@ -1109,8 +1310,9 @@ document.addEventListener('DOMContentLoaded', function () {
                     showDebugBtn.style.display = 'inline-block';
                 }
 
-                // Disconnect WebSocket
+                // Disconnect WebSocket and server log stream
                 disconnectDebugWebSocket();
+                disconnectLogStream();
                 addDebugEntry('info', 'Debug console disconnected');
                 showToast('Debug console closed and disconnected', 'info');
             });
@@ -1152,17 +1354,21 @@ document.addEventListener('DOMContentLoaded', function () {
                 const entryElement = document.createElement('div');
                 entryElement.className = `debug-entry debug-${type.toLowerCase()}`;
 
-                let entryHTML = `
-                    <span class="debug-timestamp">${timestamp}</span>
-                    <span class="debug-type">${type.toUpperCase()}</span>
-                    <span class="debug-message">${message}</span>
-                `;
-
+                // Log messages can contain anything, so they are never parsed as HTML
+                const parts = [
+                    ['debug-timestamp', timestamp],
+                    ['debug-type', type.toUpperCase()],
+                    ['debug-message', message]
+                ];
                 if (data) {
-                    entryHTML += `<span class="debug-data">${JSON.stringify(data, null, 2)}</span>`;
+                    parts.push(['debug-data', JSON.stringify(data, null, 2)]);
                 }
-
-                entryElement.innerHTML = entryHTML;
+                parts.forEach(([className, text]) => {
+                    const span = document.createElement('span');
+                    span.className = className;
+                    span.textContent = text;
+                    entryElement.appendChild(span);
+                });
                 debugLog.appendChild(entryElement);
 
                 // Auto-scroll to bottom
@@ -1299,6 +1505,55 @@ document.addEventListener('DOMContentLoaded', function () {
             debugIndicator.className = 'debug-indicator';
         }
 
+        // Server log output, streamed while the console is open
+        let logStream = null;
+        let lastLogSequence = -1;
+
+        function addLogEntry(entry) {
+            // Reopening the console fetches entries that may already be shown
+            if (entry.sequence <= lastLogSequence) {
+                return;
+            }
+            lastLogSequence = entry.sequence;
+            addDebugEntry(entry.level, `${entry.target}: ${entry.message}`,
+                Object.keys(entry.fields).length > 0 ? entry.fields : null);
+        }
+
+        async function connectLogStream() {
+            if (logStream) {
+                return;
+            }
+            // Show what happened just before the console was opened
+            try {
+                const response = await fetch('/api/logs?limit=50');
+                if (response.ok) {
+                    const result = await response.json();
+                    (result.data ? result.data.entries : []).forEach(addLogEntry);
+                }
+            } catch (error) {
+                console.debug('Failed to fetch recent logs:', error);
+            }
+
+            logStream = new EventSource('/api/logs/stream');
+            logStream.addEventListener('log', function (event) {
+                try {
+                    addLogEntry(JSON.parse(event.data));
+                } catch (error) {
+                    console.error('Error parsing log entry:', error);
+                }
+            });
+            logStream.addEventListener('lagged', function (event) {
+                addDebugEntry('warn', `Skipped ${event.data} log entries`);
+            });
+        }
+
+        function disconnectLogStream() {
+            if (logStream) {
+                logStream.close();
+                logStream = null;
+            }
+        }
+
         // Don't automatically connect - wait for user to show console
     }
 
@@ -0,0 +1,155 @@
+// First-run setup page JavaScript
+document.addEventListener('DOMContentLoaded', function () {
+    const skipSetupBtn = document.getElementById('skip-setup-btn');
+    const controllerHostnameInput = document.getElementById('controller-hostname');
+    const scanNetworkCamerasCheckbox = document.getElementById('scan-network-cameras');
+    const scanBtn = document.getElementById('scan-btn');
+    const controllerResult = document.getElementById('controller-result');
+    const camerasList = document.getElementById('setup-cameras-list');
+    const dataDirectoryInput = document.getElementById('data-directory');
+    const applySetupBtn = document.getElementById('apply-setup-btn');
+    const viewTypeOptions = document.getElementById('view-type-options');
+
+    skipSetupBtn.addEventListener('click', function () {
+        // Stop the dashboard sending us straight back here for this browser session
+        sessionStorage.setItem('setupSkipped', 'true');
+        window.location.href = '/';
+    });
+
+    function showController(controller) {
+        controllerResult.textContent = controller.reachable
+            ? `Controller ${controller.hostname} answered` +
+              (controller.response_time_ms !== null ? ` in ${controller.response_time_ms}ms` : '')
+            : `Controller ${controller.hostname} not reachable` +
+              (controller.error ? `: ${controller.error}` : '');
+        controllerResult.classList.toggle('setup-ok', controller.reachable);
+        controllerResult.classList.toggle('setup-failed', !controller.reachable);
+    }
+
+    function showCameras(cameras) {
+        camerasList.replaceChildren();
+        if (cameras.length === 0) {
+            const empty = document.createElement('div');
+            empty.className = 'no-cameras-config';
+            empty.textContent = 'No cameras found. Check they are plugged in or online and scan again.';
+            camerasList.appendChild(empty);
+            return;
+        }
+        for (const camera of cameras) {
+            const row = document.createElement('div');
+            row.className = 'setup-camera';
+            row.dataset.cameraId = camera.id;
+
+            const label = document.createElement('label');
+            const checkbox = document.createElement('input');
+            checkbox.type = 'checkbox';
+            checkbox.className = 'setup-camera-selected';
+            checkbox.checked = camera.is_selected;
+            label.append(checkbox, ` ${camera.name} (${camera.id})`);
+
+            const nickname = document.createElement('input');
+            nickname.type = 'text';
+            nickname.className = 'setup-camera-nickname';
+            nickname.placeholder = 'Nickname';
+
+            const viewType = document.createElement('select');
+            viewType.className = 'setup-camera-view-type';
+            viewType.appendChild(viewTypeOptions.content.cloneNode(true));
+            if (camera.view_type) {
+                viewType.value = camera.view_type;
+            }
+
+            row.append(label, nickname, viewType);
+            camerasList.appendChild(row);
+        }
+    }
+
+    async function loadStatus() {
+        try {
+            const response = await fetch('/api/setup/status');
+            const result = await response.json();
+            if (result.success) {
+                controllerHostnameInput.value = result.data.controller.hostname;
+                showController(result.data.controller);
+                showCameras(result.data.cameras);
+            }
+        } catch (error) {
+            console.error('Error loading setup status:', error);
+        }
+    }
+
+    scanBtn.addEventListener('click', async function () {
+        scanBtn.disabled = true;
+        showToast('Scanning for hardware...', 'info');
+        try {
+            const response = await fetch('/api/setup/scan', {
+                method: 'POST',
+                headers: { 'Content-Type': 'application/json' },
+                body: JSON.stringify({
+                    controller_hostname: controllerHostnameInput.value.trim(),
+                    skip_network_cameras: !scanNetworkCamerasCheckbox.checked
+                })
+            });
+            const result = await response.json();
+            if (!result.success) {
+                showToast(result.message, 'error');
+                return;
+            }
+            showController(result.data.controller);
+            showCameras(result.data.cameras);
+            for (const error of result.data.errors) {
+                showToast(error, 'warning');
+            }
+        } catch (error) {
+            showToast(`Scan failed: ${error.message}`, 'error');
+        } finally {
+            scanBtn.disabled = false;
+        }
+    });
+
+    applySetupBtn.addEventListener('click', async function () {
+        const cameras = [];
+        for (const row of camerasList.querySelectorAll('.setup-camera')) {
+            if (!row.querySelector('.setup-camera-selected').checked) {
+                continue;
+            }
+            const nickname = row.querySelector('.setup-camera-nickname').value.trim();
+            cameras.push({
+                camera_id: row.dataset.cameraId,
+                nickname: nickname || null,
+                view_type: row.querySelector('.setup-camera-view-type').value
+            });
+        }
+        const dataDirectory = dataDirectoryInput.value.trim();
+
+        try {
+            const response = await fetch('/api/setup/apply', {
+                method: 'POST',
+                headers: { 'Content-Type': 'application/json' },
+                body: JSON.stringify({
+                    controller_hostname: controllerHostnameInput.value.trim(),
+                    cameras: cameras,
+                    data_directory: dataDirectory || null
+                })
+            });
+            const result = await response.json();
+            if (!result.success) {
+                showToast(result.message, 'error');
+                return;
+            }
+            showToast(
+                result.data.restart_required
+                    ? 'Setup saved. Restart the server to use the new data directory.'
+                    : 'Setup saved!',
+                'success'
+            );
+            setTimeout(() => {
+                window.location.href = '/';
+            }, 2000);
+        } catch (error) {
+            showToast(`Saving setup failed: ${error.message}`, 'error');
+        }
+    });
+
+    loadStatus();
+});
@@ -16,6 +16,9 @@ class ShellEditInterface {
         
         // Delete button
         document.getElementById('delete-shell-btn').addEventListener('click', () => this.deleteShell());
+
+        // Upload photos taken with another camera
+        document.getElementById('upload-images-btn').addEventListener('click', () => this.uploadImages());
         
         // Regenerate composite button
         document.querySelectorAll('.regenerate-composite-btn').forEach(btn => {
@@ -79,9 +82,9 @@ class ShellEditInterface {
             ? this.shell.captured_images 
             : this.shell.image_filenames.map(filename => ({ filename, view_type: null }));
 
-        // Sort images: side views first, then tail views, then unknown/unspecified
+        // Sort images: side, tail, then mouth views, then unknown/unspecified
         const sortedImages = [...images].sort((a, b) => {
-            const viewOrder = { 'side': 0, 'tail': 1, 'unknown': 2, null: 3, undefined: 3 };
+            const viewOrder = { 'side': 0, 'tail': 1, 'mouth': 2, 'unknown': 3, null: 4, undefined: 4 };
             return viewOrder[a.view_type] - viewOrder[b.view_type];
         });
 
@@ -108,6 +111,7 @@ class ShellEditInterface {
                             <option value="unknown" ${(img.view_type || 'unknown') === 'unknown' ? 'selected' : ''}>Unknown</option>
                             <option value="side" ${img.view_type === 'side' ? 'selected' : ''}>Side View</option>
                             <option value="tail" ${img.view_type === 'tail' ? 'selected' : ''}>Tail View</option>
+                            <option value="mouth" ${img.view_type === 'mouth' ? 'selected' : ''}>Mouth View</option>
                         </select>
                     </div>
                     <div class="control-row">
@@ -185,29 +189,40 @@ class ShellEditInterface {
             }
 
             // Collect view type changes
-            const viewTypeUpdates = [];
+            const viewTypes = {};
             document.querySelectorAll('.edit-view-type').forEach(select => {
-                viewTypeUpdates.push({
-                    filename: select.dataset.filename,
-                    view_type: select.value
-                });
+                viewTypes[select.dataset.filename] = select.value;
             });
 
-            // Save basic shell data
-            const response = await fetch(`/api/ml/shells/${this.sessionId}/update`, {
-                method: 'POST',
+            // Save basic shell data, sending the revision this page loaded
+            const response = await fetch(`/api/shells/${this.sessionId}`, {
+                method: 'PATCH',
                 headers: {
                     'Content-Type': 'application/json',
                 },
                 body: JSON.stringify({
+                    revision: this.shell.revision || 0,
                     brand: brand,
                     shell_type: shellType,
                     include: include,
-                    view_type_updates: viewTypeUpdates
+                    view_types: viewTypes,
+                    allow_new_type: shellTypeSelect === '__custom__'
                 })
             });
 
+            if (response.status === 409) {
+                // Someone saved this shell from another tab or device since it was loaded
+                const result = await response.json();
+                this.showToast(result.message, 'warning');
+                if (confirm('This shell was changed elsewhere. Reload to see the latest version? Your unsaved changes will be lost.')) {
+                    window.location.reload();
+                }
+                return;
+            }
+
             if (response.ok) {
+                const result = await response.json();
+                this.shell.revision = result.data.revision;
                 this.showToast('Shell updated successfully', 'success');
                 // Update the page title and header
                 document.title = `Edit Shell: ${brand} ${shellType} - Shell Sorter`;
@@ -225,6 +240,56 @@ class ShellEditInterface {
         }
     }
 
+    async uploadImages() {
+        const input = document.getElementById('upload-images-input');
+        const viewType = document.getElementById('upload-view-type').value;
+        if (input.files.length === 0) {
+            this.showToast('Choose one or more photos to upload', 'warning');
+            return;
+        }
+
+        const formData = new FormData();
+        const metadata = {};
+        for (const file of input.files) {
+            formData.append('files', file);
+            metadata[file.name] = { view_type: viewType };
+        }
+        formData.append('metadata', JSON.stringify(metadata));
+
+        try {
+            const response = await fetch(`/api/shells/${this.sessionId}/images`, {
+                method: 'POST',
+                body: formData
+            });
+            const result = await response.json();
+            if (!response.ok) {
+                this.showToast(result.message || 'Failed to upload photos', 'error');
+                return;
+            }
+
+            this.shell.revision = result.data.revision;
+            this.shell.captured_images = this.shell.captured_images || [];
+            result.data.filenames.forEach(filename => {
+                this.shell.image_filenames.push(filename);
+                this.shell.captured_images.push({
+                    filename,
+                    camera_name: 'external',
+                    view_type: viewType,
+                    region_x: null,
+                    region_y: null,
+                    region_width: null,
+                    region_height: null
+                });
+            });
+            input.value = '';
+            this.renderShellData();
+            this.showToast(`Added ${result.data.filenames.length} photos`, 'success');
+        } catch (error) {
+            console.error('Error uploading photos:', error);
+            this.showToast('Error uploading photos: ' + error.message, 'error');
+        }
+    }
+
     async deleteShell() {
         if (!confirm(`Are you sure you want to delete the shell "${this.shell.brand} ${this.shell.shell_type}" and all its images? This action cannot be undone.`)) {
             return;
@@ -0,0 +1,266 @@
+// Tagging page: warn while tagging when no image shows a view the chosen case type requires
+document.addEventListener('DOMContentLoaded', function () {
+    const shellTypeSelect = document.getElementById('shell_type');
+    const warning = document.getElementById('missing-views-warning');
+    if (!shellTypeSelect || !warning) {
+        return;
+    }
+
+    function updateMissingViews() {
+        const option = shellTypeSelect.selectedOptions[0];
+        const required = option && option.dataset.requiredViews
+            ? option.dataset.requiredViews.split(',').filter(view => view)
+            : [];
+        const tagged = new Set(
+            Array.from(document.querySelectorAll('.tagging-view-type-select'))
+                .map(select => select.value)
+                .filter(view => view)
+        );
+        const missing = required.filter(view => !tagged.has(view));
+
+        warning.hidden = missing.length === 0;
+        warning.textContent = missing.length === 0
+            ? ''
+            : `No image is tagged with the ${missing.join(', ')} view. ` +
+              `${option.value} needs ${required.join(', ')} before this shell is used for training.`;
+    }
+
+    shellTypeSelect.addEventListener('change', updateMissingViews);
+    document.querySelectorAll('.tagging-view-type-select').forEach(select => {
+        select.addEventListener('change', updateMissingViews);
+    });
+    updateMissingViews();
+});
+
+// Tagging page: a guessed view type that needs checking stops being flagged once someone picks one
+document.addEventListener('DOMContentLoaded', function () {
+    document.querySelectorAll('.view-guess-review').forEach(note => {
+        const select = note.parentElement.querySelector('.tagging-view-type-select');
+        if (select) {
+            select.addEventListener('change', () => { note.hidden = true; });
+        }
+    });
+});
+
+// Tagging page: capture the cameras a partial capture missed, then show their images
+document.addEventListener('DOMContentLoaded', function () {
+    const recaptureBtn = document.getElementById('recapture-btn');
+    if (!recaptureBtn) {
+        return;
+    }
+
+    recaptureBtn.addEventListener('click', async function () {
+        const sessionId = document.getElementById('session_id').value;
+        recaptureBtn.disabled = true;
+        try {
+            const response = await fetch(`/api/sessions/${sessionId}/recapture`, { method: 'POST' });
+            const result = await response.json();
+            if (!result.success) {
+                throw new Error(result.message);
+            }
+            if (result.data.missing_cameras.length > 0) {
+                showToast(`Still missing ${result.data.missing_cameras.join(', ')}`, 'warning');
+            } else {
+                showToast('Every camera has an image now', 'success');
+            }
+            window.location.reload();
+        } catch (error) {
+            console.error('Error recapturing cameras:', error);
+            showToast('Error recapturing cameras: ' + error.message, 'error');
+            recaptureBtn.disabled = false;
+        }
+    });
+});
+
+// Tagging page: claim the session while it is open, so someone else tagging
+// the same session is warned before either of you overwrites the other
+const tagging = {
+    claimant: null,
+    renewTimer: null,
+
+    claimUrl() {
+        return `/api/sessions/${document.getElementById('session_id').value}/claim`;
+    },
+
+    // The name other taggers see, asked for once and remembered in this browser
+    askClaimant() {
+        let claimant = localStorage.getItem('taggerName');
+        if (!claimant) {
+            claimant = (prompt('Your name, so others can see you are tagging this session:') || '').trim();
+            if (claimant) {
+                localStorage.setItem('taggerName', claimant);
+            }
+        }
+        return claimant || null;
+    },
+
+    async claim() {
+        const claimStatus = document.getElementById('claim-status');
+        try {
+            const response = await fetch(this.claimUrl(), {
+                method: 'POST',
+                headers: { 'Content-Type': 'application/json' },
+                body: JSON.stringify({ claimant: this.claimant })
+            });
+            const result = await response.json();
+            if (response.status === 409) {
+                claimStatus.textContent = `Being tagged by ${result.data.claimant}`;
+                claimStatus.classList.add('claim-held');
+                showToast(result.message, 'warning');
+                return null;
+            }
+            if (!result.success) {
+                throw new Error(result.message);
+            }
+            claimStatus.textContent = `Claimed by you until ${new Date(result.data.expires_at).toLocaleTimeString()}`;
+            claimStatus.classList.remove('claim-held');
+            return result.data;
+        } catch (error) {
+            console.error('Error claiming session:', error);
+            return null;
+        }
+    },
+
+    // Renew the claim halfway through its lifetime for as long as the page is open
+    async start() {
+        this.claimant = this.askClaimant();
+        if (!this.claimant) {
+            return;
+        }
+        const claim = await this.claim();
+        const lifetime = claim
+            ? new Date(claim.expires_at) - new Date(claim.claimed_at)
+            : 60 * 1000;
+        this.renewTimer = setInterval(() => this.claim(), Math.max(lifetime / 2, 30 * 1000));
+    },
+
+    release() {
+        clearInterval(this.renewTimer);
+        if (this.claimant) {
+            fetch(`${this.claimUrl()}?claimant=${encodeURIComponent(this.claimant)}`, {
+                method: 'DELETE',
+                keepalive: true
+            });
+        }
+    },
+
+    async save(payload) {
+        const controller = new AbortController();
+        const timeoutId = setTimeout(() => controller.abort(), 10000);
+        try {
+            return await fetch('/api/shells/save', {
+                method: 'POST',
+                headers: { 'Content-Type': 'application/json' },
+                body: JSON.stringify(payload),
+                signal: controller.signal
+            });
+        } finally {
+            clearTimeout(timeoutId);
+        }
+    }
+};
+
+document.addEventListener('DOMContentLoaded', function () {
+    const saveBtn = document.getElementById('save-btn');
+    const cancelBtn = document.getElementById('cancel-btn');
+
+    tagging.start();
+
+    if (saveBtn) {
+        saveBtn.addEventListener('click', async function (e) {
+            e.preventDefault();
+
+            // Get form values
+            const sessionId = document.getElementById('session_id').value;
+            const brand = document.getElementById('brand').value.trim();
+            const shellType = document.getElementById('shell_type').value;
+            const imageFilenamesInput = document.getElementById('image_filenames').value;
+
+            // Validate form
+            if (!brand) {
+                showToast('Please enter a brand', 'warning');
+                document.getElementById('brand').focus();
+                return;
+            }
+
+            if (!shellType) {
+                showToast('Please select a shell type', 'warning');
+                document.getElementById('shell_type').focus();
+                return;
+            }
+
+            // Parse image filenames from comma-delimited string
+            const imageFilenames = imageFilenamesInput.split(',').filter(name => name.trim());
+
+            // Collect view types for each image
+            const viewTypes = {};
+            document.querySelectorAll('.tagging-view-type-select').forEach(select => {
+                const filename = select.dataset.filename;
+                const viewType = select.value;
+                if (viewType) {
+                    viewTypes[filename] = viewType;
+                }
+            });
+
+            // Validate that all images have view types selected
+            const missingViewTypes = imageFilenames.filter(filename => !viewTypes[filename]);
+            if (missingViewTypes.length > 0) {
+                showToast('Please select view type for all images', 'warning');
+                return;
+            }
+
+            const payload = {
+                session_id: sessionId,
+                brand: brand,
+                shell_type: shellType,
+                image_filenames: imageFilenames,
+                view_types: viewTypes,
+                claimant: tagging.claimant
+            };
+
+            try {
+                let response = await tagging.save(payload);
+                if (response.status === 409) {
+                    const result = await response.json();
+                    if (!result.data || !result.data.claimed_by) {
+                        showToast(result.message, 'error');
+                        return;
+                    }
+                    const holder = result.data.claimed_by.claimant;
+                    if (!confirm(`${holder} is tagging this session. Save anyway and overwrite their work?`)) {
+                        return;
+                    }
+                    response = await tagging.save({ ...payload, force: true });
+                }
+
+                if (response.ok) {
+                    tagging.release();
+                    showToast('Shell data saved successfully!', 'success');
+                    // Redirect back to dashboard after short delay
+                    setTimeout(() => {
+                        window.location.href = '/';
+                    }, 2000);
+                } else {
+                    const error = await response.text();
+                    showToast('Error saving shell data: ' + error, 'error');
+                }
+            } catch (error) {
+                console.error('Error:', error);
+                if (error.name === 'AbortError') {
+                    showToast('Request timed out. Please try again.', 'warning');
+                } else {
+                    showToast('Error saving shell data: ' + error.message, 'error');
+                }
+            }
+        });
+    }
+
+    if (cancelBtn) {
+        cancelBtn.addEventListener('click', function () {
+            if (confirm('Are you sure you want to cancel? Captured images will be discarded.')) {
+                tagging.release();
+                window.location.href = '/';
+            }
+        });
+    }
+});
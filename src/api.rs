@@ -0,0 +1,323 @@
+//! The JSON envelope every API handler answers with, and the error codes in it.
+//!
+//! A failed request carries an [`ApiError`] whose `code` is one of the
+//! [`ErrorCode`]s below, so clients can branch on what went wrong without
+//! matching the wording of the message. Validation failures also name each bad
+//! field in `field_errors`. The envelope's `message` holds the same human text
+//! as before codes existed, for clients that only read that; errors built from
+//! a [`Message`] word it in the request's language.
+//!
+//! This is the registry of error codes: a code is only added here, with a line
+//! saying when it's used, and the names are never changed once released.
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::messages::Message;
+
+/// Why a request failed, stable across releases
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+    /// Fields in the request are invalid; `field_errors` says which and why
+    ValidationFailed,
+    /// The request can't be used as sent, such as an upload with no file in it
+    InvalidRequest,
+    /// No camera has the ID in the request
+    CameraNotFound,
+    /// The camera can't do what was asked, such as brightness on an ESPHome camera
+    CameraUnsupported,
+    /// The camera failed or didn't answer
+    CameraError,
+    /// The camera already has as many open streams as it is allowed
+    StreamLimitReached,
+    /// Selected cameras are offline; `data.offline` lists each with the reason
+    CamerasOffline,
+    /// The controller failed or didn't answer
+    ControllerError,
+    /// The controller monitor didn't answer in time
+    ControllerTimeout,
+    /// The machine is busy with something that has to finish first, such as a sort cycle
+    MachineBusy,
+    /// Maintenance mode is on, so nothing that moves the machine is allowed
+    MaintenanceMode,
+    /// Too much heavy work is running already; retry after the `Retry-After` header's seconds
+    ServerBusy,
+    /// No shell has the session ID in the request
+    ShellNotFound,
+    /// A shell with that session ID exists already, so a trashed one can't be restored over it
+    ShellExists,
+    /// The shell changed since it was loaded; `data.current` holds the newer copy
+    RevisionConflict,
+    /// Someone else has claimed the session; `data.claimed_by` says who
+    SessionClaimed,
+    /// No case type has the name in the request
+    CaseTypeNotFound,
+    /// A case type with that name already exists
+    CaseTypeExists,
+    /// No batch has the ID in the request
+    BatchNotFound,
+    /// A batch with that name already exists
+    BatchExists,
+    /// No camera preset has the name in the request
+    CameraPresetNotFound,
+    /// A camera preset with that name already exists
+    CameraPresetExists,
+    /// There isn't enough training data to train a model on
+    DatasetTooSmall,
+    /// No training job has the ID in the request
+    TrainingJobNotFound,
+    /// No model has the name in the request, or it has no metrics
+    ModelNotFound,
+    /// The request body is larger than the route accepts; the message gives the limit
+    PayloadTooLarge,
+    /// Not enough disk space to take what was sent
+    InsufficientStorage,
+    /// The server was started read-only, so nothing that changes data is allowed
+    ReadOnly,
+    /// Something failed on the server; the message has the details
+    InternalError,
+}
+
+impl ErrorCode {
+    /// Every code, in registry order
+    pub const ALL: [ErrorCode; 29] = [
+        ErrorCode::ValidationFailed,
+        ErrorCode::InvalidRequest,
+        ErrorCode::CameraNotFound,
+        ErrorCode::CameraUnsupported,
+        ErrorCode::CameraError,
+        ErrorCode::StreamLimitReached,
+        ErrorCode::CamerasOffline,
+        ErrorCode::ControllerError,
+        ErrorCode::ControllerTimeout,
+        ErrorCode::MachineBusy,
+        ErrorCode::MaintenanceMode,
+        ErrorCode::ServerBusy,
+        ErrorCode::ShellNotFound,
+        ErrorCode::ShellExists,
+        ErrorCode::RevisionConflict,
+        ErrorCode::SessionClaimed,
+        ErrorCode::CaseTypeNotFound,
+        ErrorCode::CaseTypeExists,
+        ErrorCode::BatchNotFound,
+        ErrorCode::BatchExists,
+        ErrorCode::CameraPresetNotFound,
+        ErrorCode::CameraPresetExists,
+        ErrorCode::DatasetTooSmall,
+        ErrorCode::TrainingJobNotFound,
+        ErrorCode::ModelNotFound,
+        ErrorCode::PayloadTooLarge,
+        ErrorCode::InsufficientStorage,
+        ErrorCode::ReadOnly,
+        ErrorCode::InternalError,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ErrorCode::ValidationFailed => "validation_failed",
+            ErrorCode::InvalidRequest => "invalid_request",
+            ErrorCode::CameraNotFound => "camera_not_found",
+            ErrorCode::CameraUnsupported => "camera_unsupported",
+            ErrorCode::CameraError => "camera_error",
+            ErrorCode::StreamLimitReached => "stream_limit_reached",
+            ErrorCode::CamerasOffline => "cameras_offline",
+            ErrorCode::ControllerError => "controller_error",
+            ErrorCode::ControllerTimeout => "controller_timeout",
+            ErrorCode::MachineBusy => "machine_busy",
+            ErrorCode::MaintenanceMode => "maintenance_mode",
+            ErrorCode::ServerBusy => "server_busy",
+            ErrorCode::ShellNotFound => "shell_not_found",
+            ErrorCode::ShellExists => "shell_exists",
+            ErrorCode::RevisionConflict => "revision_conflict",
+            ErrorCode::SessionClaimed => "session_claimed",
+            ErrorCode::CaseTypeNotFound => "case_type_not_found",
+            ErrorCode::CaseTypeExists => "case_type_exists",
+            ErrorCode::BatchNotFound => "batch_not_found",
+            ErrorCode::BatchExists => "batch_exists",
+            ErrorCode::CameraPresetNotFound => "camera_preset_not_found",
+            ErrorCode::CameraPresetExists => "camera_preset_exists",
+            ErrorCode::DatasetTooSmall => "dataset_too_small",
+            ErrorCode::TrainingJobNotFound => "training_job_not_found",
+            ErrorCode::ModelNotFound => "model_not_found",
+            ErrorCode::PayloadTooLarge => "payload_too_large",
+            ErrorCode::InsufficientStorage => "insufficient_storage",
+            ErrorCode::ReadOnly => "read_only",
+            ErrorCode::InternalError => "internal_error",
+        }
+    }
+}
+
+impl fmt::Display for ErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// What went wrong with a request
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct ApiError {
+    pub code: ErrorCode,
+    pub message: String,
+    /// Why each invalid field was refused, keyed by field name
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub field_errors: BTreeMap<String, String>,
+}
+
+impl ApiError {
+    pub fn new(code: ErrorCode, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+            field_errors: BTreeMap::new(),
+        }
+    }
+
+    /// An error whose message comes from the catalogue, in the request's language
+    pub fn localized(code: ErrorCode, message: &Message) -> Self {
+        Self::new(code, message.render())
+    }
+
+    /// A [`ErrorCode::ValidationFailed`] error listing every field error after `context`
+    pub fn validation(context: &str, field_errors: BTreeMap<String, String>) -> Self {
+        let errors = field_errors
+            .iter()
+            .map(|(field, error)| format!("{field}: {error}"))
+            .collect::<Vec<_>>()
+            .join("; ");
+        Self {
+            code: ErrorCode::ValidationFailed,
+            message: format!("{context}: {errors}"),
+            field_errors,
+        }
+    }
+
+    /// The error in a response body, if it is a failure that carries one
+    pub fn from_response(body: &serde_json::Value) -> Option<Self> {
+        serde_json::from_value(body.get("error")?.clone()).ok()
+    }
+}
+
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ({})", self.message, self.code)
+    }
+}
+
+/// Generic API response
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct ApiResponse<T> {
+    pub success: bool,
+    pub data: Option<T>,
+    /// Human-readable outcome; on failure the same text as `error.message`
+    pub message: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<ApiError>,
+}
+
+impl<T> ApiResponse<T> {
+    pub fn success(data: T) -> Self {
+        Self {
+            success: true,
+            data: Some(data),
+            message: "Success".to_string(),
+            error: None,
+        }
+    }
+
+    pub fn error(code: ErrorCode, message: String) -> Self {
+        Self::failure(ApiError::new(code, message))
+    }
+
+    /// [`Self::error`] with the message from the catalogue, in the request's language
+    pub fn localized(code: ErrorCode, message: Message) -> Self {
+        Self::failure(ApiError::localized(code, &message))
+    }
+
+    pub fn failure(error: ApiError) -> Self {
+        Self {
+            success: false,
+            data: None,
+            message: error.message.clone(),
+            error: Some(error),
+        }
+    }
+
+    /// A failure that still carries data, such as the current copy of something that changed
+    pub fn error_with_data(code: ErrorCode, message: String, data: T) -> Self {
+        Self {
+            data: Some(data),
+            ..Self::error(code, message)
+        }
+    }
+
+    /// [`Self::error_with_data`] with the message from the catalogue
+    pub fn localized_with_data(code: ErrorCode, message: Message, data: T) -> Self {
+        Self {
+            data: Some(data),
+            ..Self::localized(code, message)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_error_codes_are_registered() {
+        for code in ErrorCode::ALL {
+            let json = serde_json::to_value(code).expect("code should serialise");
+            assert_eq!(json, code.as_str());
+            let parsed: ErrorCode = serde_json::from_value(json).expect("code should parse");
+            assert_eq!(parsed, code);
+        }
+        let names: std::collections::HashSet<&str> =
+            ErrorCode::ALL.iter().map(ErrorCode::as_str).collect();
+        assert_eq!(names.len(), ErrorCode::ALL.len());
+
+        // Clients only ever see registered codes
+        for unknown in ["not_found", "CameraNotFound", "camera-not-found", ""] {
+            assert!(
+                serde_json::from_value::<ErrorCode>(serde_json::json!(unknown)).is_err(),
+                "{unknown:?} should not be a code"
+            );
+        }
+        let body = serde_json::json!({
+            "success": false,
+            "data": null,
+            "message": "Something failed",
+            "error": {"code": "made_up", "message": "Something failed"},
+        });
+        assert_eq!(ApiError::from_response(&body), None);
+    }
+
+    #[test]
+    fn test_validation_error_names_fields() {
+        let error = ApiError::validation(
+            "Invalid shell",
+            BTreeMap::from([
+                ("notes".to_string(), "too long".to_string()),
+                ("brand".to_string(), "must not be empty".to_string()),
+            ]),
+        );
+        assert_eq!(error.code, ErrorCode::ValidationFailed);
+        assert_eq!(
+            error.message,
+            "Invalid shell: brand: must not be empty; notes: too long"
+        );
+
+        let response = ApiResponse::<()>::failure(error.clone());
+        assert_eq!(response.message, error.message);
+        let body = serde_json::to_value(&response).expect("response should serialise");
+        assert_eq!(ApiError::from_response(&body), Some(error));
+        assert_eq!(
+            ApiError::from_response(&serde_json::json!({"success": true, "data": []})),
+            None
+        );
+    }
+}
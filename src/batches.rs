@@ -0,0 +1,86 @@
+//! Named lots of brass, so shells can be traced back to where they came from.
+//!
+//! A [`Batch`] is one lot, such as "gun show lot 3" or "range pickup June".
+//! Shells captured with the batch's ID, or while it is the active batch,
+//! record it as their `batch_id`. If a lot turns out to be contaminated,
+//! excluding it from training takes every one of its shells out of the
+//! training set at once. The batches, and which one is active, are kept in
+//! [`BATCHES_FILENAME`] in the data directory by
+//! [`crate::shell_data::ShellDataManager`].
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+/// File in the data directory holding the batches
+pub const BATCHES_FILENAME: &str = "batches.json";
+/// Longest batch name accepted
+pub const MAX_BATCH_NAME_LENGTH: usize = 100;
+
+/// A lot of shells that came from the same place
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct Batch {
+    pub id: String,
+    pub name: String,
+    #[serde(default)]
+    pub notes: Option<String>,
+    pub created_at: DateTime<Utc>,
+    /// The batch's shells are kept out of training
+    #[serde(default)]
+    pub exclude_from_training: bool,
+}
+
+/// Every batch, and the one new captures go into
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct BatchList {
+    /// Captures that don't name a batch are put in this one
+    #[serde(default)]
+    pub active_batch_id: Option<String>,
+    #[serde(default)]
+    pub batches: Vec<Batch>,
+}
+
+impl BatchList {
+    pub fn get(&self, batch_id: &str) -> Option<&Batch> {
+        self.batches.iter().find(|batch| batch.id == batch_id)
+    }
+
+    /// The active batch, if it still exists
+    pub fn active(&self) -> Option<&Batch> {
+        self.active_batch_id
+            .as_deref()
+            .and_then(|batch_id| self.get(batch_id))
+    }
+
+    /// A batch with this name, ignoring case and surrounding spaces
+    pub fn named(&self, name: &str) -> Option<&Batch> {
+        let name = name.trim();
+        self.batches
+            .iter()
+            .find(|batch| batch.name.eq_ignore_ascii_case(name))
+    }
+}
+
+/// What changed when a batch was excluded from or returned to training
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub struct BatchExclusion {
+    pub batch: Batch,
+    /// Shells whose include flag was changed
+    pub shells_changed: usize,
+}
+
+/// Why a batch name can't be used, if it can't
+pub fn batch_name_error(name: &str) -> Option<String> {
+    let name = name.trim();
+    if name.is_empty() {
+        Some("must not be empty".to_string())
+    } else if name.chars().count() > MAX_BATCH_NAME_LENGTH {
+        Some(format!(
+            "must be at most {MAX_BATCH_NAME_LENGTH} characters"
+        ))
+    } else {
+        None
+    }
+}
@@ -0,0 +1,125 @@
+//! Keeping broadcast subscribers alive when they fall behind.
+//!
+//! A `tokio::sync::broadcast` receiver that is slower than its sender gets
+//! `RecvError::Lagged` with the number of messages it missed. That isn't a
+//! reason to stop listening, so subscribers receive through
+//! [`recv_skipping_lag`], which logs and counts the skipped messages and only
+//! gives up once the channel is closed. The counts are reported per subscriber
+//! by `/api/health`.
+
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+
+use serde::Serialize;
+use tokio::sync::broadcast;
+use tracing::warn;
+
+/// How often one subscriber fell behind
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub struct LagStats {
+    /// Times the subscriber was told it lagged
+    pub events: u64,
+    /// Messages it missed in total
+    pub skipped: u64,
+}
+
+/// Lag statistics by subscriber name, shared by every subscriber
+#[derive(Debug, Clone, Default)]
+pub struct LagCounters {
+    stats: Arc<Mutex<BTreeMap<String, LagStats>>>,
+}
+
+impl LagCounters {
+    /// Note that `subscriber` missed `skipped` messages
+    pub fn record(&self, subscriber: &str, skipped: u64) {
+        warn!("Broadcast subscriber {subscriber} fell behind and skipped {skipped} messages");
+        let Ok(mut stats) = self.stats.lock() else {
+            return;
+        };
+        let entry = stats.entry(subscriber.to_string()).or_default();
+        entry.events += 1;
+        entry.skipped += skipped;
+    }
+
+    /// Lag statistics for every subscriber that has lagged so far
+    pub fn snapshot(&self) -> BTreeMap<String, LagStats> {
+        let Ok(stats) = self.stats.lock() else {
+            return BTreeMap::new();
+        };
+        stats.clone()
+    }
+}
+
+/// The next message for `subscriber`, skipping past lag; `None` once the channel is closed
+pub async fn recv_skipping_lag<T: Clone>(
+    receiver: &mut broadcast::Receiver<T>,
+    subscriber: &str,
+    counters: &LagCounters,
+) -> Option<T> {
+    loop {
+        match receiver.recv().await {
+            Ok(message) => return Some(message),
+            Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                counters.record(subscriber, skipped);
+            }
+            Err(broadcast::error::RecvError::Closed) => return None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_slow_subscriber_survives_a_flood() {
+        const CAPACITY: usize = 16;
+        let (sender, mut receiver) = broadcast::channel::<usize>(CAPACITY);
+        let counters = LagCounters::default();
+
+        let subscriber = tokio::spawn({
+            let counters = counters.clone();
+            async move {
+                let mut received = Vec::new();
+                while let Some(message) = recv_skipping_lag(&mut receiver, "slow", &counters).await
+                {
+                    if received.is_empty() {
+                        // Fall well behind while the sender floods the channel
+                        tokio::time::sleep(Duration::from_millis(100)).await;
+                    }
+                    received.push(message);
+                }
+                received
+            }
+        });
+
+        let flood = CAPACITY * 10;
+        for message in 0..flood {
+            sender.send(message).expect("the subscriber is listening");
+        }
+        // Give the subscriber time to drain, then check it still hears new messages
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        sender
+            .send(flood)
+            .expect("the subscriber should still be listening");
+        drop(sender);
+
+        let received = tokio::time::timeout(Duration::from_secs(5), subscriber)
+            .await
+            .expect("the subscriber should stop once the channel closes")
+            .expect("the subscriber task should finish");
+        assert_eq!(received.last(), Some(&flood));
+        assert!(received.len() < flood, "some messages were skipped");
+
+        let stats = counters.snapshot();
+        let slow = stats.get("slow").expect("the lag should be counted");
+        assert!(slow.events >= 1, "{slow:?}");
+        assert_eq!(
+            slow.skipped as usize + received.len(),
+            flood + 1,
+            "every message was either received or counted as skipped"
+        );
+    }
+}
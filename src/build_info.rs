@@ -0,0 +1,50 @@
+//! Version and build details, reported at startup, by `--version --json` and at `/api/version`.
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+use crate::config::Settings;
+
+/// What's running, for bug reports
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub struct BuildInfo {
+    pub version: &'static str,
+    /// Full git commit hash, or "unknown" when built outside a git checkout
+    pub commit: &'static str,
+    pub build_timestamp: Option<DateTime<Utc>>,
+}
+
+impl BuildInfo {
+    /// Details of this build
+    pub fn current() -> Self {
+        Self {
+            version: env!("CARGO_PKG_VERSION"),
+            commit: env!("SHELL_SORTER_GIT_COMMIT"),
+            build_timestamp: env!("SHELL_SORTER_BUILD_TIMESTAMP")
+                .parse()
+                .ok()
+                .and_then(|seconds| DateTime::from_timestamp(seconds, 0)),
+        }
+    }
+
+    /// The commit shortened to 7 characters, for display
+    pub fn short_commit(&self) -> &'static str {
+        self.commit.get(..7).unwrap_or(self.commit)
+    }
+
+    /// One-line `key=value` banner logged when the web server starts
+    pub fn startup_banner(&self, settings: &Settings, listen_address: &str) -> String {
+        format!(
+            "shell-sorter version={} commit={} built={} config={} data_dir={} listen={}",
+            self.version,
+            self.short_commit(),
+            self.build_timestamp
+                .map(|timestamp| timestamp.to_rfc3339())
+                .unwrap_or_else(|| "unknown".to_string()),
+            settings.sources.user_config_path.display(),
+            settings.data_directory.display(),
+            listen_address,
+        )
+    }
+}
@@ -0,0 +1,240 @@
+//! Burst captures: several frames per camera, keeping the sharpest.
+//!
+//! A burst takes `frames` images from every selected camera, `interval_ms`
+//! apart, all in one session. Each frame is scored by how sharp it is (the
+//! variance of its Laplacian: edges in focus give large swings either way, blur
+//! flattens them) and the sharpest frame from each camera is marked preferred,
+//! so training uses it and skips the rest of the burst.
+
+use std::collections::{BTreeMap, HashMap};
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::OurResult;
+use crate::shell_data::CapturedImage;
+
+/// Most frames a burst may take from each camera
+pub const MAX_BURST_FRAMES: u32 = 10;
+/// Shortest interval allowed between the frames of a burst
+pub const MIN_BURST_INTERVAL_MS: u64 = 50;
+
+/// How many frames to take from each camera, and how far apart
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+pub struct BurstRequest {
+    pub frames: u32,
+    pub interval_ms: u64,
+}
+
+impl BurstRequest {
+    /// Check the burst against the server's caps, naming every field that is wrong
+    pub fn validate(&self) -> Result<(), BTreeMap<String, String>> {
+        let mut errors = BTreeMap::new();
+        if self.frames == 0 || self.frames > MAX_BURST_FRAMES {
+            errors.insert(
+                "burst.frames".to_string(),
+                format!("must be between 1 and {MAX_BURST_FRAMES}"),
+            );
+        }
+        if self.interval_ms < MIN_BURST_INTERVAL_MS {
+            errors.insert(
+                "burst.interval_ms".to_string(),
+                format!("must be at least {MIN_BURST_INTERVAL_MS}"),
+            );
+        }
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+
+    pub fn interval(&self) -> Duration {
+        Duration::from_millis(self.interval_ms)
+    }
+}
+
+/// The file a burst frame is saved as
+pub fn burst_filename(session_id: &str, camera_index: u32, sequence: u32) -> String {
+    format!("{session_id}_camera_{camera_index}_{sequence}.jpg")
+}
+
+/// How sharp an encoded image is: the variance of its greyscale Laplacian
+///
+/// Higher is sharper. Scores only compare frames of the same scene, so there is
+/// no threshold for "sharp enough". This decodes the image, so run it off async tasks.
+pub fn sharpness(image_bytes: &[u8]) -> OurResult<f64> {
+    let luma = image::load_from_memory(image_bytes)?.to_luma8();
+    let laplacian = imageproc::filter::laplacian_filter(&luma);
+    let count = laplacian.len() as f64;
+    if count == 0.0 {
+        return Ok(0.0);
+    }
+    let mean = laplacian.iter().map(|&value| f64::from(value)).sum::<f64>() / count;
+    let variance = laplacian
+        .iter()
+        .map(|&value| (f64::from(value) - mean).powi(2))
+        .sum::<f64>()
+        / count;
+    Ok(variance)
+}
+
+/// Mark the sharpest burst frame from each camera as preferred
+///
+/// Images without a sequence number aren't part of a burst and are left alone.
+/// Frames without a score are never preferred over one that has a score, and
+/// on a tie the earlier frame wins.
+pub fn mark_preferred(images: &mut [CapturedImage]) {
+    let mut sharpest: HashMap<u32, usize> = HashMap::new();
+    for (position, image) in images.iter().enumerate() {
+        if image.sequence.is_none() {
+            continue;
+        }
+        let best = sharpest.entry(image.camera_index).or_insert(position);
+        if is_sharper(image, &images[*best]) {
+            *best = position;
+        }
+    }
+    for (position, image) in images.iter_mut().enumerate() {
+        if image.sequence.is_some() {
+            image.preferred = sharpest.get(&image.camera_index) == Some(&position);
+        }
+    }
+}
+
+fn is_sharper(image: &CapturedImage, than: &CapturedImage) -> bool {
+    match (image.sharpness, than.sharpness) {
+        (Some(score), Some(best)) => {
+            score > best || (score == best && image.sequence < than.sequence)
+        }
+        (Some(_), None) => true,
+        (None, Some(_)) => false,
+        (None, None) => image.sequence < than.sequence,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ViewType;
+    use image::{GrayImage, Luma};
+
+    /// A checkerboard blurred by `sigma`, encoded as a JPEG
+    fn blurred_frame(sigma: f32) -> Vec<u8> {
+        let board = GrayImage::from_fn(64, 48, |x, y| {
+            if (x / 8 + y / 8) % 2 == 0 {
+                Luma([30])
+            } else {
+                Luma([220])
+            }
+        });
+        let frame = if sigma > 0.0 {
+            imageproc::filter::gaussian_blur_f32(&board, sigma)
+        } else {
+            board
+        };
+        let rgb = image::DynamicImage::ImageLuma8(frame).to_rgb8();
+        crate::image_ingest::encode_jpeg(&rgb, 90).expect("JPEG should encode")
+    }
+
+    fn burst_frame(camera_index: u32, sequence: u32, jpeg: &[u8]) -> CapturedImage {
+        let mut image = CapturedImage::new(
+            camera_index,
+            burst_filename("20260101_120000", camera_index, sequence),
+            format!("camera {camera_index}"),
+            ViewType::Side,
+        );
+        image.sequence = Some(sequence);
+        image.sharpness = Some(sharpness(jpeg).expect("frame should be scored"));
+        image
+    }
+
+    #[test]
+    fn test_the_sharpest_frame_of_each_camera_is_preferred() {
+        let scores: Vec<f64> = [4.0, 2.0, 0.0, 1.0]
+            .iter()
+            .map(|&sigma| sharpness(&blurred_frame(sigma)).expect("frame should be scored"))
+            .collect();
+        assert!(
+            scores[2] > scores[3] && scores[3] > scores[1] && scores[1] > scores[0],
+            "less blur should score higher: {scores:?}"
+        );
+
+        // Camera 0 comes into focus on its third frame, camera 1 is sharpest first
+        let mut images: Vec<CapturedImage> = [4.0, 2.0, 0.0, 1.0]
+            .iter()
+            .enumerate()
+            .map(|(sequence, &sigma)| burst_frame(0, sequence as u32, &blurred_frame(sigma)))
+            .chain(
+                [0.5, 3.0, 3.0]
+                    .iter()
+                    .enumerate()
+                    .map(|(sequence, &sigma)| {
+                        burst_frame(1, sequence as u32, &blurred_frame(sigma))
+                    }),
+            )
+            .collect();
+        let single =
+            CapturedImage::new(2, "single.jpg".to_string(), "2".to_string(), ViewType::Tail);
+        images.push(single.clone());
+        mark_preferred(&mut images);
+
+        let preferred: Vec<&str> = images
+            .iter()
+            .filter(|image| image.preferred)
+            .map(|image| image.filename.as_str())
+            .collect();
+        assert_eq!(
+            preferred,
+            vec![
+                "20260101_120000_camera_0_2.jpg",
+                "20260101_120000_camera_1_0.jpg"
+            ]
+        );
+        // An image that isn't part of a burst is untouched
+        assert_eq!(images.last(), Some(&single));
+    }
+
+    #[test]
+    fn test_ties_and_unscored_frames() {
+        let jpeg = blurred_frame(1.0);
+        let mut images = vec![burst_frame(0, 1, &jpeg), burst_frame(0, 0, &jpeg)];
+        images.push(CapturedImage {
+            sharpness: None,
+            ..burst_frame(0, 2, &jpeg)
+        });
+        mark_preferred(&mut images);
+        assert_eq!(
+            images
+                .iter()
+                .map(|image| image.preferred)
+                .collect::<Vec<_>>(),
+            vec![false, true, false]
+        );
+    }
+
+    #[test]
+    fn test_burst_caps() {
+        let burst = |frames, interval_ms| {
+            BurstRequest {
+                frames,
+                interval_ms,
+            }
+            .validate()
+        };
+        assert!(burst(1, MIN_BURST_INTERVAL_MS).is_ok());
+        assert!(burst(MAX_BURST_FRAMES, 1000).is_ok());
+        let errors = burst(MAX_BURST_FRAMES + 1, MIN_BURST_INTERVAL_MS - 1)
+            .expect_err("both caps should be enforced");
+        assert_eq!(
+            errors.into_keys().collect::<Vec<_>>(),
+            vec!["burst.frames", "burst.interval_ms"]
+        );
+        assert!(burst(0, 100).is_err());
+        assert_eq!(
+            burst_filename("20260101_120000", 3, 7),
+            "20260101_120000_camera_3_7.jpg"
+        );
+    }
+}
@@ -0,0 +1,305 @@
+//! One description of a camera, whichever manager drives it.
+//!
+//! ESPHome and USB cameras are tracked by separate managers with their own
+//! records ([`crate::camera_manager::CameraInfo`] and
+//! [`crate::usb_camera_controller::UsbCameraInfo`]). Everything outside the
+//! managers speaks [`Descriptor`] and [`CameraId`] instead: the ID says which
+//! manager owns the camera, and the descriptor carries what both kinds share
+//! plus a section for whatever only one kind has.
+//!
+//! IDs are stored as the same plain strings the managers have always used, so
+//! selections saved before this module existed still load.
+
+use std::fmt;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+use crate::camera_manager::CameraInfo;
+use crate::constants::USB_DEVICE_PREFIX_WITH_COLON;
+use crate::usb_camera_controller::{CameraFormatInfo, UsbCameraInfo};
+
+/// Which manager a camera belongs to, serialised as a plain string: `"esphome"` or `"usb"`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CameraKind {
+    EspHome,
+    Usb,
+}
+
+impl CameraKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CameraKind::EspHome => "esphome",
+            CameraKind::Usb => "usb",
+        }
+    }
+}
+
+impl fmt::Display for CameraKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// A camera's stable ID, e.g. `esphome_left` or `usb:046d:0825:ABC123`
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct CameraId(String);
+
+impl CameraId {
+    /// The manager that owns the camera: USB hardware IDs start with `usb:`, anything else is ESPHome
+    pub fn kind(&self) -> CameraKind {
+        if self.0.starts_with(USB_DEVICE_PREFIX_WITH_COLON) {
+            CameraKind::Usb
+        } else {
+            CameraKind::EspHome
+        }
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    pub fn into_string(self) -> String {
+        self.0
+    }
+}
+
+impl From<String> for CameraId {
+    fn from(id: String) -> Self {
+        Self(id)
+    }
+}
+
+impl From<&str> for CameraId {
+    fn from(id: &str) -> Self {
+        Self(id.to_string())
+    }
+}
+
+impl From<CameraId> for String {
+    fn from(id: CameraId) -> Self {
+        id.0
+    }
+}
+
+impl AsRef<str> for CameraId {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Display for CameraId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// Camera IDs split by the manager that owns them, each list in the order given
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct IdsByKind {
+    pub esphome: Vec<CameraId>,
+    pub usb: Vec<CameraId>,
+}
+
+impl FromIterator<CameraId> for IdsByKind {
+    fn from_iter<I: IntoIterator<Item = CameraId>>(ids: I) -> Self {
+        let mut split = Self::default();
+        for id in ids {
+            match id.kind() {
+                CameraKind::EspHome => split.esphome.push(id),
+                CameraKind::Usb => split.usb.push(id),
+            }
+        }
+        split
+    }
+}
+
+/// What is known about an ESPHome camera
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct EspDetails {
+    pub hostname: String,
+    pub stream_url: Url,
+    pub snapshot_url: Url,
+    /// When the camera last answered a probe, snapshot or stream
+    pub last_seen: Option<DateTime<Utc>>,
+    /// Why the camera last failed to answer
+    pub last_error: Option<String>,
+}
+
+/// What is known about a USB camera
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct UsbDetails {
+    /// Index the camera is opened by
+    pub index: u32,
+    pub vendor_id: Option<String>,
+    pub product_id: Option<String>,
+    pub serial_number: Option<String>,
+    pub supported_formats: Vec<CameraFormatInfo>,
+    pub current_format: Option<CameraFormatInfo>,
+}
+
+/// A camera of either kind, with the section for its kind filled in
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct Descriptor {
+    pub id: CameraId,
+    pub kind: CameraKind,
+    /// The name the camera reports
+    pub name: String,
+    /// The name given to the camera in the config
+    pub nickname: Option<String>,
+    pub online: bool,
+    pub esp: Option<EspDetails>,
+    pub usb: Option<UsbDetails>,
+}
+
+impl Descriptor {
+    pub fn with_nickname(self, nickname: Option<String>) -> Self {
+        Self { nickname, ..self }
+    }
+
+    /// The nickname if the camera has one, otherwise the name it reports
+    pub fn display_name(&self) -> &str {
+        self.nickname.as_deref().unwrap_or(&self.name)
+    }
+}
+
+impl From<CameraInfo> for Descriptor {
+    fn from(camera: CameraInfo) -> Self {
+        Self {
+            id: CameraId(camera.id),
+            kind: CameraKind::EspHome,
+            name: camera.name,
+            nickname: None,
+            online: camera.online,
+            esp: Some(EspDetails {
+                hostname: camera.hostname,
+                stream_url: camera.stream_url,
+                snapshot_url: camera.snapshot_url,
+                last_seen: camera.last_seen,
+                last_error: camera.last_error,
+            }),
+            usb: None,
+        }
+    }
+}
+
+impl From<UsbCameraInfo> for Descriptor {
+    fn from(camera: UsbCameraInfo) -> Self {
+        Self {
+            id: CameraId(camera.hardware_id),
+            kind: CameraKind::Usb,
+            name: camera.name,
+            nickname: None,
+            online: camera.connected,
+            esp: None,
+            usb: Some(UsbDetails {
+                index: camera.index,
+                vendor_id: camera.vendor_id,
+                product_id: camera.product_id,
+                serial_number: camera.serial_number,
+                supported_formats: camera.supported_formats,
+                current_format: camera.current_format,
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::UserConfig;
+
+    #[test]
+    fn test_saved_ids_keep_their_kind_and_spelling() {
+        let saved = [
+            ("esphome_left", CameraKind::EspHome),
+            ("esphome_192_168_1_20", CameraKind::EspHome),
+            ("usb:046d:0825:ABC123", CameraKind::Usb),
+            ("usb:046d:0825:hd_webcam_c270", CameraKind::Usb),
+            ("usb:facetime_hd_camera:0", CameraKind::Usb),
+            // Not a USB hardware ID, only a name that starts like one
+            ("usbcam", CameraKind::EspHome),
+        ];
+        for (text, kind) in saved {
+            let id: CameraId =
+                serde_json::from_value(serde_json::json!(text)).expect("ID should deserialise");
+            assert_eq!(id.kind(), kind, "{text}");
+            assert_eq!(id.to_string(), text);
+            assert_eq!(
+                serde_json::to_value(&id).expect("ID should serialise"),
+                serde_json::json!(text)
+            );
+            assert_eq!(CameraId::from(text), id);
+        }
+    }
+
+    #[test]
+    fn test_saved_selections_load_and_save_unchanged() {
+        // A config file written while selections were plain strings
+        let mut saved =
+            serde_json::to_value(UserConfig::default()).expect("config should serialise");
+        saved["selected_cameras"] = serde_json::json!([
+            "usb:046d:0825:ABC123",
+            "esphome_left",
+            "usb:facetime_hd_camera:0"
+        ]);
+        let user_config: UserConfig =
+            serde_json::from_value(saved.clone()).expect("old config should load");
+        let split: IdsByKind = user_config.get_selected_cameras().iter().cloned().collect();
+        assert_eq!(split.esphome, vec![CameraId::from("esphome_left")]);
+        assert_eq!(
+            split.usb,
+            vec![
+                CameraId::from("usb:046d:0825:ABC123"),
+                CameraId::from("usb:facetime_hd_camera:0")
+            ]
+        );
+
+        let written = serde_json::to_value(&user_config).expect("config should serialise");
+        assert_eq!(written["selected_cameras"], saved["selected_cameras"]);
+    }
+
+    #[test]
+    fn test_descriptors_from_both_managers() {
+        let esp: Descriptor = CameraInfo {
+            id: "esphome_left".to_string(),
+            name: "left".to_string(),
+            hostname: "left.local".to_string(),
+            stream_url: Url::parse("http://left.local:81/stream").expect("URL should parse"),
+            snapshot_url: Url::parse("http://left.local/camera/snapshot")
+                .expect("URL should parse"),
+            online: true,
+            last_seen: None,
+            last_error: None,
+        }
+        .into();
+        assert_eq!(esp.kind, esp.id.kind());
+        assert!(esp.usb.is_none());
+        let details = esp.esp.as_ref().expect("ESPHome section should be set");
+        assert_eq!(details.stream_url.as_str(), "http://left.local:81/stream");
+
+        let usb = Descriptor::from(UsbCameraInfo {
+            index: 2,
+            name: "HD Webcam".to_string(),
+            vendor_id: Some("046d".to_string()),
+            product_id: Some("0825".to_string()),
+            serial_number: Some("ABC123".to_string()),
+            hardware_id: "usb:046d:0825:ABC123".to_string(),
+            connected: false,
+            supported_formats: Vec::new(),
+            current_format: None,
+        })
+        .with_nickname(Some("top".to_string()));
+        assert_eq!(usb.kind, usb.id.kind());
+        assert!(usb.esp.is_none());
+        assert_eq!(usb.usb.as_ref().map(|details| details.index), Some(2));
+        assert_eq!(usb.display_name(), "top");
+        assert_eq!(esp.display_name(), "left");
+    }
+}
@@ -0,0 +1,215 @@
+//! ESPHome camera inventory import and export.
+//!
+//! An inventory is a list of network cameras with their hostname, friendly
+//! name, view type and physical position, written as JSON or YAML. Importing
+//! merges it into the user config so hostnames don't have to be re-typed on the
+//! config page.
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+
+use crate::camera_manager::esphome_camera_id;
+use crate::config::{UserConfig, ViewType};
+use crate::{OurError, OurResult};
+
+/// A single camera in an inventory document
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+pub struct InventoryEntry {
+    pub hostname: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub view_type: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub position: Option<String>,
+}
+
+/// Document format for inventory import and export
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum InventoryFormat {
+    Json,
+    Yaml,
+}
+
+/// Outcome of merging an inventory, listed by hostname
+#[derive(Debug, Default, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub struct ImportReport {
+    pub added: Vec<String>,
+    pub updated: Vec<String>,
+    pub unchanged: Vec<String>,
+}
+
+/// Parse an inventory document, detecting JSON by its leading bracket and treating anything else as YAML
+pub fn parse_inventory(document: &str) -> OurResult<Vec<InventoryEntry>> {
+    let entries: Vec<InventoryEntry> = if document.trim_start().starts_with('[') {
+        serde_json::from_str(document)?
+    } else {
+        serde_yaml::from_str(document)
+            .map_err(|e| OurError::App(format!("Invalid inventory YAML: {e}")))?
+    };
+    validate_inventory(&entries)?;
+    Ok(entries)
+}
+
+/// Check hostnames are unique and plausible and that view types parse
+pub fn validate_inventory(entries: &[InventoryEntry]) -> OurResult<()> {
+    let mut seen = HashSet::new();
+    for entry in entries {
+        let hostname = entry.hostname.trim();
+        validate_hostname(&entry.hostname)?;
+        if !seen.insert(hostname.to_ascii_lowercase()) {
+            return Err(OurError::App(format!(
+                "Duplicate camera hostname: {hostname}"
+            )));
+        }
+        if let Some(view_type) = &entry.view_type {
+            view_type.parse::<ViewType>()?;
+        }
+    }
+    Ok(())
+}
+
+/// Check a hostname is non-empty and contains only hostname characters (an optional port is allowed)
+pub fn validate_hostname(hostname: &str) -> OurResult<()> {
+    let trimmed = hostname.trim();
+    if trimmed.is_empty()
+        || !trimmed
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '_' | ':'))
+    {
+        return Err(OurError::App(format!(
+            "Invalid camera hostname: {hostname:?}"
+        )));
+    }
+    Ok(())
+}
+
+/// Merge validated inventory entries into the user config
+pub fn merge_inventory(user_config: &mut UserConfig, entries: &[InventoryEntry]) -> ImportReport {
+    let mut report = ImportReport::default();
+
+    for entry in entries {
+        let hostname = entry.hostname.trim().to_string();
+        let camera_id = esphome_camera_id(&hostname);
+        let existing = user_config.camera_configs.get(&camera_id).cloned();
+        let known_hostname = user_config.network_camera_hostnames.contains(&hostname);
+
+        let mut camera_config = existing.clone().unwrap_or_default();
+        if entry.name.is_some() {
+            camera_config.nickname = entry.name.clone();
+        }
+        if entry.position.is_some() {
+            camera_config.position = entry.position.clone();
+        }
+        if let Some(view_type) = &entry.view_type {
+            camera_config.view_type = view_type.parse().ok();
+        }
+
+        let config_changed = existing.as_ref().is_none_or(|existing| {
+            existing.nickname != camera_config.nickname
+                || existing.position != camera_config.position
+                || existing.view_type != camera_config.view_type
+        });
+
+        if !known_hostname {
+            user_config.network_camera_hostnames.push(hostname.clone());
+            report.added.push(hostname);
+        } else if config_changed {
+            report.updated.push(hostname);
+        } else {
+            report.unchanged.push(hostname);
+        }
+        user_config.set_camera_config(camera_id, camera_config);
+    }
+
+    report
+}
+
+/// Build an inventory from the network cameras in the user config
+pub fn export_inventory(user_config: &UserConfig) -> Vec<InventoryEntry> {
+    user_config
+        .network_camera_hostnames
+        .iter()
+        .map(|hostname| {
+            let camera_config = user_config.get_camera_config(&esphome_camera_id(hostname));
+            InventoryEntry {
+                hostname: hostname.clone(),
+                name: camera_config.nickname,
+                view_type: camera_config
+                    .view_type
+                    .map(|view_type| view_type.to_string()),
+                position: camera_config.position,
+            }
+        })
+        .collect()
+}
+
+/// Render an inventory in the requested format
+pub fn render_inventory(entries: &[InventoryEntry], format: InventoryFormat) -> OurResult<String> {
+    match format {
+        InventoryFormat::Json => Ok(serde_json::to_string_pretty(entries)?),
+        InventoryFormat::Yaml => serde_yaml::to_string(entries)
+            .map_err(|e| OurError::App(format!("Failed to render inventory YAML: {e}"))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const INVENTORY_YAML: &str = "
+- hostname: esp32cam1.local
+  name: Left side
+  view_type: side
+  position: left rail
+- hostname: esp32cam2.local
+  view_type: Tail
+";
+
+    #[test]
+    fn test_import_merge_and_round_trip() {
+        let entries = parse_inventory(INVENTORY_YAML).expect("inventory should parse");
+        let mut user_config = UserConfig::default();
+
+        // esp32cam1.local is in the default hostnames, so it's updated rather than added
+        let report = merge_inventory(&mut user_config, &entries);
+        assert_eq!(report.added, vec!["esp32cam2.local"]);
+        assert_eq!(report.updated, vec!["esp32cam1.local"]);
+        assert!(report.unchanged.is_empty());
+
+        let camera_config = user_config.get_camera_config("esphome_esp32cam1.local");
+        assert_eq!(camera_config.nickname.as_deref(), Some("Left side"));
+        assert_eq!(camera_config.position.as_deref(), Some("left rail"));
+        assert_eq!(camera_config.view_type, Some(ViewType::Side));
+
+        // Importing again changes nothing
+        let report = merge_inventory(&mut user_config, &entries);
+        assert_eq!(report.unchanged.len(), 2);
+
+        // Exported JSON imports back to the same config
+        let exported = render_inventory(&export_inventory(&user_config), InventoryFormat::Json)
+            .expect("inventory should render");
+        let reimported = parse_inventory(&exported).expect("exported inventory should parse");
+        let report = merge_inventory(&mut user_config, &reimported);
+        assert_eq!(report.unchanged.len(), 2);
+        assert_eq!(reimported[1].view_type.as_deref(), Some("tail"));
+    }
+
+    #[test]
+    fn test_inventory_validation() {
+        let duplicate = "[{\"hostname\": \"cam.local\"}, {\"hostname\": \"CAM.local\"}]";
+        assert!(parse_inventory(duplicate).is_err());
+
+        let spaces = "- hostname: my camera.local\n";
+        assert!(parse_inventory(spaces).is_err());
+
+        let bad_view_type = "- hostname: cam.local\n  view_type: top\n";
+        assert!(parse_inventory(bad_view_type).is_err());
+
+        let unknown_field = "- hostname: cam.local\n  nickname: typo\n";
+        assert!(parse_inventory(unknown_field).is_err());
+    }
+}
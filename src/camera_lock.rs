@@ -0,0 +1,222 @@
+//! Keeping camera settings steady while a capture session is running.
+//!
+//! The images of one tagging capture should all be taken with the same
+//! brightness and format, so a capture session holds its cameras for its whole
+//! duration. Control writes for a held camera are either queued until the
+//! session releases it or rejected, as the request asks. Streaming frame grabs
+//! never take the lock.
+
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{OurError, OurResult};
+
+/// What to do with a control write for a camera that is mid-capture
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BusyPolicy {
+    /// Hold the write until the capture session finishes
+    #[default]
+    Wait,
+    /// Fail straight away with a "camera busy" error
+    Reject,
+}
+
+/// Whether a control write may go ahead
+#[derive(Debug)]
+pub enum Admission<T> {
+    /// The camera is free, apply the write now
+    Proceed(T),
+    /// The write was queued and comes back from [`CameraLocks::release`]
+    Queued,
+    /// The camera is busy and the write asked not to wait
+    Busy(T, OurError),
+}
+
+/// Cameras held by capture sessions, and the writes waiting on them
+#[derive(Debug)]
+pub struct CameraLocks<T> {
+    /// Session ID holding each camera, by hardware ID
+    held: HashMap<String, String>,
+    /// Writes waiting for a camera, in arrival order
+    waiting: Vec<(String, T)>,
+}
+
+impl<T> Default for CameraLocks<T> {
+    fn default() -> Self {
+        Self {
+            held: HashMap::new(),
+            waiting: Vec::new(),
+        }
+    }
+}
+
+impl<T> CameraLocks<T> {
+    /// Hold `hardware_ids` for `session_id`, failing if another session has any of them
+    pub fn acquire(&mut self, session_id: &str, hardware_ids: &[String]) -> OurResult<()> {
+        if let Some((hardware_id, holder)) = hardware_ids.iter().find_map(|hardware_id| {
+            self.held
+                .get(hardware_id)
+                .filter(|holder| holder.as_str() != session_id)
+                .map(|holder| (hardware_id, holder))
+        }) {
+            return Err(busy_error(hardware_id, holder));
+        }
+        for hardware_id in hardware_ids {
+            self.held
+                .insert(hardware_id.clone(), session_id.to_string());
+        }
+        Ok(())
+    }
+
+    /// Let go of every camera `session_id` holds, returning the writes that were waiting on them
+    pub fn release(&mut self, session_id: &str) -> Vec<T> {
+        self.held.retain(|_, holder| holder != session_id);
+        let (ready, still_waiting) = std::mem::take(&mut self.waiting)
+            .into_iter()
+            .partition(|(hardware_id, _)| !self.held.contains_key(hardware_id));
+        self.waiting = still_waiting;
+        ready.into_iter().map(|(_, write)| write).collect()
+    }
+
+    /// Decide whether `write` for `hardware_id` can be applied now
+    pub fn admit(&mut self, hardware_id: &str, policy: BusyPolicy, write: T) -> Admission<T> {
+        let Some(holder) = self.held.get(hardware_id) else {
+            return Admission::Proceed(write);
+        };
+        match policy {
+            BusyPolicy::Wait => {
+                self.waiting.push((hardware_id.to_string(), write));
+                Admission::Queued
+            }
+            BusyPolicy::Reject => Admission::Busy(write, busy_error(hardware_id, holder)),
+        }
+    }
+
+    /// Session ID holding each camera, by hardware ID
+    pub fn holders(&self) -> HashMap<String, String> {
+        self.held.clone()
+    }
+}
+
+fn busy_error(hardware_id: &str, session_id: &str) -> OurError {
+    OurError::App(format!(
+        "Camera {hardware_id} busy capturing session {session_id}"
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A camera whose captured frames are just the brightness they were taken with
+    struct SimulatedCamera {
+        brightness: i64,
+        locks: CameraLocks<i64>,
+        applied: Vec<i64>,
+    }
+
+    impl SimulatedCamera {
+        fn new() -> Self {
+            Self {
+                brightness: 50,
+                locks: CameraLocks::default(),
+                applied: Vec::new(),
+            }
+        }
+
+        fn set_brightness(&mut self, brightness: i64, policy: BusyPolicy) -> OurResult<()> {
+            match self.locks.admit("usb:1", policy, brightness) {
+                Admission::Proceed(brightness) => {
+                    self.apply(brightness);
+                    Ok(())
+                }
+                Admission::Queued => Ok(()),
+                Admission::Busy(_, error) => Err(error),
+            }
+        }
+
+        fn apply(&mut self, brightness: i64) {
+            self.brightness = brightness;
+            self.applied.push(brightness);
+        }
+
+        fn end_session(&mut self, session_id: &str) {
+            for brightness in self.locks.release(session_id) {
+                self.apply(brightness);
+            }
+        }
+    }
+
+    #[test]
+    fn test_brightness_waits_for_capture_session() {
+        let mut camera = SimulatedCamera::new();
+        let cameras = vec!["usb:1".to_string()];
+        camera
+            .locks
+            .acquire("session-a", &cameras)
+            .expect("camera should be free");
+
+        // The slider moves between the frames of a four image capture
+        let mut frames = vec![camera.brightness, camera.brightness];
+        camera
+            .set_brightness(80, BusyPolicy::Wait)
+            .expect("waiting writes are accepted");
+        frames.push(camera.brightness);
+        camera
+            .set_brightness(90, BusyPolicy::Wait)
+            .expect("waiting writes are accepted");
+        frames.push(camera.brightness);
+        assert_eq!(frames, vec![50, 50, 50, 50]);
+        assert!(camera.applied.is_empty());
+        assert_eq!(
+            camera.locks.holders().get("usb:1").map(String::as_str),
+            Some("session-a")
+        );
+
+        // Both writes land after the session, in the order they were made
+        camera.end_session("session-a");
+        assert_eq!(camera.applied, vec![80, 90]);
+        assert_eq!(camera.brightness, 90);
+        assert!(camera.locks.holders().is_empty());
+
+        camera
+            .set_brightness(30, BusyPolicy::Wait)
+            .expect("free camera accepts writes");
+        assert_eq!(camera.applied, vec![80, 90, 30]);
+    }
+
+    #[test]
+    fn test_rejecting_writes_and_competing_sessions() {
+        let mut camera = SimulatedCamera::new();
+        let cameras = vec!["usb:1".to_string()];
+        camera
+            .locks
+            .acquire("session-a", &cameras)
+            .expect("camera should be free");
+
+        let error = camera
+            .set_brightness(80, BusyPolicy::Reject)
+            .expect_err("busy camera should reject");
+        assert!(
+            error
+                .to_string()
+                .contains("busy capturing session session-a"),
+            "{error}"
+        );
+        assert!(camera.locks.acquire("session-b", &cameras).is_err());
+        // Re-acquiring within the same session is harmless
+        camera
+            .locks
+            .acquire("session-a", &cameras)
+            .expect("same session may re-acquire");
+
+        // Releasing another session's cameras leaves the hold in place
+        camera.end_session("session-b");
+        assert_eq!(camera.locks.holders().len(), 1);
+        camera.end_session("session-a");
+        assert!(camera.applied.is_empty(), "rejected write must not apply");
+        assert_eq!(camera.brightness, 50);
+    }
+}
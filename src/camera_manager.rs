@@ -1,18 +1,30 @@
 use std::collections::HashMap;
 use std::str::FromStr;
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use tokio::sync::RwLock;
 
+use chrono::{DateTime, Utc};
 use reqwest::Url;
 use serde::{Deserialize, Serialize};
 
 use tokio::sync::{mpsc, oneshot};
 use tracing::{debug, error, info, warn};
 
+use crate::camera::CameraId;
+use crate::capture_stats::CaptureStats;
+use crate::etag::DataVersion;
+use crate::event_log::EventRecorder;
+use crate::orientation::Orientation;
+use crate::protocol::request;
+use crate::stream_health::{StreamHealth, StreamStalled, check_streams};
 use crate::{OurError, OurResult};
 
+/// Name used in errors about the camera manager's channel
+const MANAGER_NAME: &str = "Camera manager";
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 #[serde_with::serde_as]
 pub struct CameraInfo {
     pub id: String,
@@ -23,13 +35,62 @@ pub struct CameraInfo {
     #[serde_as(as = "DisplayFromStr")]
     pub snapshot_url: Url,
     pub online: bool,
+    /// When the camera last answered a probe, snapshot or stream
+    #[serde(default)]
+    pub last_seen: Option<DateTime<Utc>>,
+    /// Why the camera last failed to answer
+    #[serde(default)]
+    pub last_error: Option<String>,
+}
+
+/// Cameras offline for longer than this are flagged as stale unless configured otherwise
+pub const DEFAULT_STALE_THRESHOLD: Duration = Duration::from_secs(300);
+
+impl CameraInfo {
+    /// Note that the camera answered at `now`
+    pub fn mark_seen(&mut self, now: DateTime<Utc>) {
+        self.online = true;
+        self.last_seen = Some(now);
+        self.last_error = None;
+    }
+
+    /// Note that the camera didn't answer, taking it offline
+    pub fn mark_offline(&mut self, error: String) {
+        self.online = false;
+        self.last_error = Some(error);
+    }
+
+    /// How long an offline camera has been gone, if it was ever seen
+    pub fn offline_for(&self, now: DateTime<Utc>) -> Option<Duration> {
+        if self.online {
+            return None;
+        }
+        let last_seen = self.last_seen?;
+        Some((now - last_seen).to_std().unwrap_or(Duration::ZERO))
+    }
+
+    /// Offline for longer than `threshold`, or offline and never seen at all
+    pub fn is_stale(&self, now: DateTime<Utc>, threshold: Duration) -> bool {
+        if self.online {
+            return false;
+        }
+        self.offline_for(now)
+            .is_none_or(|offline_for| offline_for > threshold)
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[serde(rename_all = "snake_case")]
 pub struct CameraStatus {
     pub cameras: HashMap<String, CameraInfo>,
     pub selected_cameras: Vec<String>,
     pub streaming: bool,
+    /// Capture counters by camera ID
+    #[serde(default)]
+    pub capture_stats: HashMap<String, CaptureStats>,
+    /// Live stream health by camera ID
+    #[serde(default)]
+    pub stream_health: HashMap<String, StreamHealth>,
 }
 
 #[derive(Debug)]
@@ -39,7 +100,7 @@ pub enum CameraRequest {
         respond_to: oneshot::Sender<OurResult<Vec<CameraInfo>>>,
     },
     SelectCameras {
-        camera_ids: Vec<String>,
+        camera_ids: Vec<CameraId>,
         respond_to: oneshot::Sender<OurResult<()>>,
     },
     StartStreaming {
@@ -55,6 +116,216 @@ pub enum CameraRequest {
     GetStatus {
         respond_to: oneshot::Sender<OurResult<CameraStatus>>,
     },
+    /// Answers whether the camera is known
+    ResetCaptureStats {
+        camera_id: String,
+        respond_to: oneshot::Sender<OurResult<bool>>,
+    },
+    GetEspSettings {
+        camera_id: String,
+        entities: Vec<EspEntity>,
+        respond_to: oneshot::Sender<OurResult<Vec<EspEntityState>>>,
+    },
+    SetEspSettings {
+        camera_id: String,
+        updates: Vec<(EspEntity, String)>,
+        respond_to: oneshot::Sender<OurResult<Vec<EspSettingResult>>>,
+    },
+    /// Frames that passed through a proxied stream
+    StreamFrames {
+        camera_id: String,
+        frames: usize,
+    },
+    /// A proxied stream failed
+    StreamFailure {
+        camera_id: String,
+        error: String,
+    },
+    CheckStreamHealth {
+        open_streams: Vec<String>,
+        threshold: Duration,
+        respond_to: oneshot::Sender<OurResult<Vec<StreamStalled>>>,
+    },
+    /// Replace the orientation of every camera; cameras not listed are used as-is
+    SetOrientations {
+        orientations: HashMap<String, Orientation>,
+        respond_to: oneshot::Sender<OurResult<()>>,
+    },
+    /// Probe configured cameras again, each given at most `timeout` to answer
+    ProbeCameras {
+        camera_ids: Vec<String>,
+        timeout: Duration,
+        respond_to: oneshot::Sender<OurResult<Vec<CameraInfo>>>,
+    },
+}
+
+impl CameraRequest {
+    /// The request variant name, used as the event kind
+    pub fn kind(&self) -> &'static str {
+        match self {
+            CameraRequest::DetectCameras => "DetectCameras",
+            CameraRequest::ListCameras { .. } => "ListCameras",
+            CameraRequest::SelectCameras { .. } => "SelectCameras",
+            CameraRequest::StartStreaming { .. } => "StartStreaming",
+            CameraRequest::StopStreaming { .. } => "StopStreaming",
+            CameraRequest::CaptureImage { .. } => "CaptureImage",
+            CameraRequest::GetStatus { .. } => "GetStatus",
+            CameraRequest::ResetCaptureStats { .. } => "ResetCaptureStats",
+            CameraRequest::GetEspSettings { .. } => "GetEspSettings",
+            CameraRequest::SetEspSettings { .. } => "SetEspSettings",
+            CameraRequest::StreamFrames { .. } => "StreamFrames",
+            CameraRequest::StreamFailure { .. } => "StreamFailure",
+            CameraRequest::CheckStreamHealth { .. } => "CheckStreamHealth",
+            CameraRequest::SetOrientations { .. } => "SetOrientations",
+            CameraRequest::ProbeCameras { .. } => "ProbeCameras",
+        }
+    }
+
+    /// Compact rendering of the request without its responder handle
+    pub fn summary(&self) -> String {
+        match self {
+            CameraRequest::SelectCameras { camera_ids, .. } => {
+                format!("SelectCameras {{ camera_ids: {camera_ids:?} }}")
+            }
+            CameraRequest::CaptureImage { camera_id, .. }
+            | CameraRequest::ResetCaptureStats { camera_id, .. }
+            | CameraRequest::GetEspSettings { camera_id, .. }
+            | CameraRequest::StreamFailure { camera_id, .. } => {
+                format!("{} {{ camera_id: {camera_id:?} }}", self.kind())
+            }
+            CameraRequest::SetEspSettings {
+                camera_id, updates, ..
+            } => {
+                let updates: Vec<String> = updates
+                    .iter()
+                    .map(|(entity, value)| format!("{entity}={value}"))
+                    .collect();
+                format!("SetEspSettings {{ camera_id: {camera_id:?}, updates: {updates:?} }}")
+            }
+            CameraRequest::SetOrientations { orientations, .. } => {
+                format!("SetOrientations {{ orientations: {orientations:?} }}")
+            }
+            CameraRequest::ProbeCameras { camera_ids, .. } => {
+                format!("ProbeCameras {{ camera_ids: {camera_ids:?} }}")
+            }
+            other => other.kind().to_string(),
+        }
+    }
+}
+
+/// ESPHome entity domains the camera settings passthrough can read and write
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum EspEntityDomain {
+    Select,
+    Number,
+    Switch,
+}
+
+impl std::fmt::Display for EspEntityDomain {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            EspEntityDomain::Select => write!(f, "select"),
+            EspEntityDomain::Number => write!(f, "number"),
+            EspEntityDomain::Switch => write!(f, "switch"),
+        }
+    }
+}
+
+/// An ESPHome entity exposed over the device's REST API, written as `domain/object_id`
+#[derive(
+    Debug, Clone, PartialEq, Eq, Hash, serde_with::SerializeDisplay, serde_with::DeserializeFromStr,
+)]
+pub struct EspEntity {
+    pub domain: EspEntityDomain,
+    pub object_id: String,
+}
+
+impl EspEntity {
+    /// Whether changing this entity changes the camera's frame size
+    pub fn is_framesize(&self) -> bool {
+        self.domain == EspEntityDomain::Select && self.object_id == "framesize"
+    }
+}
+
+impl std::fmt::Display for EspEntity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}/{}", self.domain, self.object_id)
+    }
+}
+
+impl FromStr for EspEntity {
+    type Err = OurError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (domain, object_id) = s
+            .split_once('/')
+            .ok_or_else(|| OurError::App(format!("Entity '{s}' should be domain/object_id")))?;
+        let domain = match domain {
+            "select" => EspEntityDomain::Select,
+            "number" => EspEntityDomain::Number,
+            "switch" => EspEntityDomain::Switch,
+            other => {
+                return Err(OurError::App(format!(
+                    "Unsupported ESPHome entity domain '{other}' in '{s}'"
+                )));
+            }
+        };
+        if !is_esphome_object_id(object_id) {
+            return Err(OurError::App(format!(
+                "Invalid ESPHome entity object id in '{s}'"
+            )));
+        }
+        Ok(Self {
+            domain,
+            object_id: object_id.to_string(),
+        })
+    }
+}
+
+/// Whether `object_id` could name an ESPHome entity
+pub fn is_esphome_object_id(object_id: &str) -> bool {
+    !object_id.is_empty()
+        && object_id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+}
+
+/// Current state of an ESPHome camera entity, or why it couldn't be read
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct EspEntityState {
+    pub entity: EspEntity,
+    pub state: Option<String>,
+    /// Choices for select entities
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub options: Vec<String>,
+    pub error: Option<String>,
+}
+
+/// Outcome of writing one ESPHome camera entity
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct EspSettingResult {
+    pub entity: EspEntity,
+    pub value: String,
+    pub success: bool,
+    pub error: Option<String>,
+}
+
+/// Camera name for an ESPHome hostname, without protocol or port
+pub fn esphome_camera_name(hostname: &str) -> String {
+    hostname
+        .replace("http://", "")
+        .replace("https://", "")
+        .split(':')
+        .next()
+        .unwrap_or(hostname)
+        .to_string()
+}
+
+/// Camera ID assigned to the ESPHome camera at `hostname`
+pub fn esphome_camera_id(hostname: &str) -> String {
+    format!("esphome_{}", esphome_camera_name(hostname))
 }
 
 pub struct CameraManager {
@@ -62,6 +333,10 @@ pub struct CameraManager {
     status: Arc<RwLock<CameraStatus>>,
     request_receiver: mpsc::UnboundedReceiver<CameraRequest>,
     client: reqwest::Client,
+    events: EventRecorder,
+    version: DataVersion,
+    /// Rotation and mirroring applied to snapshots, by camera ID
+    orientations: HashMap<String, Orientation>,
 }
 
 #[derive(Clone)]
@@ -69,9 +344,15 @@ pub struct CameraHandle {
     request_sender: mpsc::UnboundedSender<CameraRequest>,
     #[allow(dead_code)]
     status: Arc<RwLock<CameraStatus>>,
+    version: DataVersion,
 }
 
 impl CameraHandle {
+    /// Bumped whenever the camera list, selection, streaming state or capture stats change
+    pub fn version(&self) -> u64 {
+        self.version.get()
+    }
+
     pub async fn detect_cameras(&self) -> OurResult<()> {
         self.request_sender
             .send(CameraRequest::DetectCameras)
@@ -80,69 +361,164 @@ impl CameraHandle {
     }
 
     pub async fn list_cameras(&self) -> OurResult<Vec<CameraInfo>> {
-        let (sender, receiver) = oneshot::channel();
-        self.request_sender
-            .send(CameraRequest::ListCameras { respond_to: sender })
-            .map_err(|_| OurError::App("Camera manager channel closed".to_string()))?;
-        receiver
-            .await
-            .map_err(|_| OurError::App("Camera manager response failed".to_string()))?
+        request(&self.request_sender, MANAGER_NAME, |respond_to| {
+            CameraRequest::ListCameras { respond_to }
+        })
+        .await?
     }
 
-    pub async fn select_cameras(&self, camera_ids: Vec<String>) -> OurResult<()> {
-        let (sender, receiver) = oneshot::channel();
-        self.request_sender
-            .send(CameraRequest::SelectCameras {
+    pub async fn select_cameras(&self, camera_ids: Vec<CameraId>) -> OurResult<()> {
+        request(&self.request_sender, MANAGER_NAME, |respond_to| {
+            CameraRequest::SelectCameras {
                 camera_ids,
-                respond_to: sender,
-            })
-            .map_err(|_| OurError::App("Camera manager channel closed".to_string()))?;
-        receiver
-            .await
-            .map_err(|_| OurError::App("Camera manager response failed".to_string()))?
+                respond_to,
+            }
+        })
+        .await?
     }
 
     pub async fn start_streaming(&self) -> OurResult<()> {
-        let (sender, receiver) = oneshot::channel();
-        self.request_sender
-            .send(CameraRequest::StartStreaming { respond_to: sender })
-            .map_err(|_| OurError::App("Camera manager channel closed".to_string()))?;
-        receiver
-            .await
-            .map_err(|_| OurError::App("Camera manager response failed".to_string()))?
+        request(&self.request_sender, MANAGER_NAME, |respond_to| {
+            CameraRequest::StartStreaming { respond_to }
+        })
+        .await?
     }
 
     pub async fn stop_streaming(&self) -> OurResult<()> {
-        let (sender, receiver) = oneshot::channel();
-        self.request_sender
-            .send(CameraRequest::StopStreaming { respond_to: sender })
-            .map_err(|_| OurError::App("Camera manager channel closed".to_string()))?;
-        receiver
-            .await
-            .map_err(|_| OurError::App("Camera manager response failed".to_string()))?
+        request(&self.request_sender, MANAGER_NAME, |respond_to| {
+            CameraRequest::StopStreaming { respond_to }
+        })
+        .await?
     }
 
     pub async fn capture_image(&self, camera_id: String) -> OurResult<Vec<u8>> {
-        let (sender, receiver) = oneshot::channel();
-        self.request_sender
-            .send(CameraRequest::CaptureImage {
+        request(&self.request_sender, MANAGER_NAME, |respond_to| {
+            CameraRequest::CaptureImage {
                 camera_id,
-                respond_to: sender,
-            })
-            .map_err(|_| OurError::App("Camera manager channel closed".to_string()))?;
-        receiver
-            .await
-            .map_err(|_| OurError::App("Camera manager response failed".to_string()))?
+                respond_to,
+            }
+        })
+        .await?
     }
 
     pub async fn get_status(&self) -> OurResult<CameraStatus> {
-        let (sender, receiver) = oneshot::channel();
+        request(&self.request_sender, MANAGER_NAME, |respond_to| {
+            CameraRequest::GetStatus { respond_to }
+        })
+        .await?
+    }
+
+    /// Reset capture counters for a camera, returning false if there's no such camera
+    pub async fn reset_capture_stats(&self, camera_id: String) -> OurResult<bool> {
+        request(&self.request_sender, MANAGER_NAME, |respond_to| {
+            CameraRequest::ResetCaptureStats {
+                camera_id,
+                respond_to,
+            }
+        })
+        .await?
+    }
+
+    /// Read the given entities from an ESPHome camera
+    pub async fn get_esp_settings(
+        &self,
+        camera_id: String,
+        entities: Vec<EspEntity>,
+    ) -> OurResult<Vec<EspEntityState>> {
+        request(&self.request_sender, MANAGER_NAME, |respond_to| {
+            CameraRequest::GetEspSettings {
+                camera_id,
+                entities,
+                respond_to,
+            }
+        })
+        .await?
+    }
+
+    /// Write entity values to an ESPHome camera, reporting each separately
+    pub async fn set_esp_settings(
+        &self,
+        camera_id: String,
+        updates: Vec<(EspEntity, String)>,
+    ) -> OurResult<Vec<EspSettingResult>> {
+        request(&self.request_sender, MANAGER_NAME, |respond_to| {
+            CameraRequest::SetEspSettings {
+                camera_id,
+                updates,
+                respond_to,
+            }
+        })
+        .await?
+    }
+}
+
+impl CameraHandle {
+    /// Record frames seen in a proxied stream, without waiting for the manager
+    pub fn record_stream_frames(&self, camera_id: &str, frames: usize) -> OurResult<()> {
         self.request_sender
-            .send(CameraRequest::GetStatus { respond_to: sender })
-            .map_err(|_| OurError::App("Camera manager channel closed".to_string()))?;
-        receiver
-            .await
-            .map_err(|_| OurError::App("Camera manager response failed".to_string()))?
+            .send(CameraRequest::StreamFrames {
+                camera_id: camera_id.to_string(),
+                frames,
+            })
+            .map_err(|_| OurError::App("Camera manager channel closed".to_string()))
+    }
+
+    /// Record that a proxied stream failed
+    pub fn record_stream_failure(&self, camera_id: &str, error: &str) -> OurResult<()> {
+        self.request_sender
+            .send(CameraRequest::StreamFailure {
+                camera_id: camera_id.to_string(),
+                error: error.to_string(),
+            })
+            .map_err(|_| OurError::App("Camera manager channel closed".to_string()))
+    }
+
+    /// Replace the orientation of every camera
+    pub async fn set_orientations(
+        &self,
+        orientations: HashMap<String, Orientation>,
+    ) -> OurResult<()> {
+        request(&self.request_sender, MANAGER_NAME, |respond_to| {
+            CameraRequest::SetOrientations {
+                orientations,
+                respond_to,
+            }
+        })
+        .await?
+    }
+
+    /// Probe configured cameras again, returning what's now known about the ones that exist
+    ///
+    /// Cameras that answer are marked online; known cameras that don't are marked offline.
+    pub async fn probe_cameras(
+        &self,
+        camera_ids: Vec<String>,
+        timeout: Duration,
+    ) -> OurResult<Vec<CameraInfo>> {
+        request(&self.request_sender, MANAGER_NAME, |respond_to| {
+            CameraRequest::ProbeCameras {
+                camera_ids,
+                timeout,
+                respond_to,
+            }
+        })
+        .await?
+    }
+
+    /// Check the cameras with open streams for stalls, returning the ones that just stalled
+    pub async fn check_stream_health(
+        &self,
+        open_streams: Vec<String>,
+        threshold: Duration,
+    ) -> OurResult<Vec<StreamStalled>> {
+        request(&self.request_sender, MANAGER_NAME, |respond_to| {
+            CameraRequest::CheckStreamHealth {
+                open_streams,
+                threshold,
+                respond_to,
+            }
+        })
+        .await?
     }
 }
 
@@ -152,15 +528,18 @@ impl CameraManager {
     }
 
     async fn lock_status_write(&self) -> tokio::sync::RwLockWriteGuard<'_, CameraStatus> {
+        self.version.bump();
         self.status.write().await
     }
 
     pub fn new(
         network_camera_hostnames: Vec<String>,
+        events: EventRecorder,
     ) -> Result<(Self, CameraHandle), Box<dyn std::error::Error>> {
         let (request_sender, request_receiver) = mpsc::unbounded_channel();
 
         let status = Arc::new(RwLock::new(CameraStatus::default()));
+        let version = DataVersion::default();
 
         let client = reqwest::Client::builder()
             .timeout(Duration::from_secs(10))
@@ -171,11 +550,15 @@ impl CameraManager {
             status: status.clone(),
             request_receiver,
             client,
+            events,
+            version: version.clone(),
+            orientations: HashMap::new(),
         };
 
         let handle = CameraHandle {
             request_sender,
             status,
+            version,
         };
 
         Ok((manager, handle))
@@ -185,6 +568,14 @@ impl CameraManager {
         info!("Starting camera manager");
 
         while let Some(request) = self.request_receiver.recv().await {
+            // Per-frame reports and the periodic stall check would crowd everything else out
+            if !matches!(
+                request,
+                CameraRequest::StreamFrames { .. } | CameraRequest::CheckStreamHealth { .. }
+            ) {
+                self.events
+                    .record("camera_manager", request.kind(), request.summary());
+            }
             match request {
                 CameraRequest::DetectCameras => {
                     let result = self.detect_cameras().await;
@@ -221,7 +612,23 @@ impl CameraManager {
                     camera_id,
                     respond_to,
                 } => {
+                    let started = Instant::now();
                     let result = self.capture_image(&camera_id).await;
+                    {
+                        let mut status = self.lock_status_write().await;
+                        if let Some(camera) = status.cameras.get_mut(&camera_id) {
+                            match &result {
+                                Ok(_) => camera.mark_seen(Utc::now()),
+                                // A failed snapshot alone doesn't take the camera offline
+                                Err(e) => camera.last_error = Some(e.to_string()),
+                            }
+                        }
+                        status
+                            .capture_stats
+                            .entry(camera_id)
+                            .or_default()
+                            .record(&result, started.elapsed());
+                    }
                     if respond_to.send(result).is_err() {
                         error!("Failed to send image capture response");
                     }
@@ -232,6 +639,89 @@ impl CameraManager {
                         error!("Failed to send status response: {err:?}");
                     }
                 }
+                CameraRequest::ResetCaptureStats {
+                    camera_id,
+                    respond_to,
+                } => {
+                    let mut status = self.lock_status_write().await;
+                    let known = status.cameras.contains_key(&camera_id);
+                    status.capture_stats.remove(&camera_id);
+                    drop(status);
+                    if respond_to.send(Ok(known)).is_err() {
+                        error!("Failed to send capture stats reset response");
+                    }
+                }
+                CameraRequest::GetEspSettings {
+                    camera_id,
+                    entities,
+                    respond_to,
+                } => {
+                    let result = self.get_esp_settings(&camera_id, &entities).await;
+                    if respond_to.send(result).is_err() {
+                        error!("Failed to send ESP settings response");
+                    }
+                }
+                CameraRequest::SetEspSettings {
+                    camera_id,
+                    updates,
+                    respond_to,
+                } => {
+                    let result = self.set_esp_settings(&camera_id, updates).await;
+                    if respond_to.send(result).is_err() {
+                        error!("Failed to send ESP settings update response");
+                    }
+                }
+                CameraRequest::StreamFrames { camera_id, frames } => {
+                    let mut status = self.lock_status_write().await;
+                    if let Some(camera) = status.cameras.get_mut(&camera_id) {
+                        camera.mark_seen(Utc::now());
+                    }
+                    status
+                        .stream_health
+                        .entry(camera_id)
+                        .or_default()
+                        .record_frames(frames, Instant::now());
+                }
+                CameraRequest::StreamFailure { camera_id, error } => {
+                    let mut status = self.lock_status_write().await;
+                    if let Some(camera) = status.cameras.get_mut(&camera_id) {
+                        camera.last_error = Some(error);
+                    }
+                    status
+                        .stream_health
+                        .entry(camera_id)
+                        .or_default()
+                        .record_failure();
+                }
+                CameraRequest::CheckStreamHealth {
+                    open_streams,
+                    threshold,
+                    respond_to,
+                } => {
+                    let stalled = self.check_stream_health(&open_streams, threshold).await;
+                    if respond_to.send(Ok(stalled)).is_err() {
+                        error!("Failed to send stream health response");
+                    }
+                }
+                CameraRequest::SetOrientations {
+                    orientations,
+                    respond_to,
+                } => {
+                    self.orientations = orientations;
+                    if respond_to.send(Ok(())).is_err() {
+                        error!("Failed to send orientation response");
+                    }
+                }
+                CameraRequest::ProbeCameras {
+                    camera_ids,
+                    timeout,
+                    respond_to,
+                } => {
+                    let probed = self.probe_cameras(&camera_ids, timeout).await;
+                    if respond_to.send(Ok(probed)).is_err() {
+                        error!("Failed to send camera probe response");
+                    }
+                }
             }
         }
 
@@ -242,23 +732,32 @@ impl CameraManager {
     async fn detect_cameras(&mut self) -> OurResult<Vec<CameraInfo>> {
         debug!("Detecting ESPHome cameras");
         let mut cameras = Vec::new();
+        let mut failures = Vec::new();
 
         for hostname in &self.network_camera_hostnames {
             match self.probe_esphome_camera(hostname).await {
-                Ok(camera_info) => {
+                Ok(mut camera_info) => {
+                    camera_info.mark_seen(Utc::now());
                     cameras.push(camera_info);
                     info!("Detected camera at {hostname}");
                 }
                 Err(e) => {
                     warn!("Failed to detect camera at {hostname}: {e}");
+                    failures.push((esphome_camera_id(hostname), e.to_string()));
                 }
             }
         }
 
-        // Update status
+        // Update status, keeping cameras that were seen before but didn't answer this time
         {
             let mut status = self.lock_status_write().await;
-            status.cameras.clear();
+            let mut previous = std::mem::take(&mut status.cameras);
+            for (camera_id, error) in failures {
+                if let Some(mut camera) = previous.remove(&camera_id) {
+                    camera.mark_offline(error);
+                    status.cameras.insert(camera_id, camera);
+                }
+            }
             for camera in &cameras {
                 status.cameras.insert(camera.id.clone(), camera.clone());
             }
@@ -267,22 +766,96 @@ impl CameraManager {
         Ok(cameras)
     }
 
+    /// Probe the configured cameras among `camera_ids`, updating whether they're online
+    async fn probe_cameras(&mut self, camera_ids: &[String], timeout: Duration) -> Vec<CameraInfo> {
+        let mut probed = Vec::new();
+        for camera_id in camera_ids {
+            let Some(hostname) = self
+                .network_camera_hostnames
+                .iter()
+                .find(|hostname| esphome_camera_id(hostname) == *camera_id)
+                .cloned()
+            else {
+                continue;
+            };
+            let result =
+                match tokio::time::timeout(timeout, self.probe_esphome_camera(&hostname)).await {
+                    Ok(result) => result,
+                    Err(_) => Err(OurError::App(format!(
+                        "Failed to probe camera: no answer within {}ms",
+                        timeout.as_millis()
+                    ))),
+                };
+            let mut status = self.lock_status_write().await;
+            match result {
+                Ok(answered) => {
+                    let camera = status.cameras.entry(camera_id.clone()).or_insert(answered);
+                    camera.mark_seen(Utc::now());
+                    probed.push(camera.clone());
+                }
+                Err(e) => {
+                    debug!("Camera {camera_id} didn't answer a probe: {e}");
+                    if let Some(camera) = status.cameras.get_mut(camera_id) {
+                        camera.mark_offline(e.to_string());
+                        probed.push(camera.clone());
+                    }
+                }
+            }
+        }
+        probed
+    }
+
+    /// Watch the open streams of known cameras and record the ones that just stalled
+    async fn check_stream_health(
+        &self,
+        open_streams: &[String],
+        threshold: Duration,
+    ) -> Vec<StreamStalled> {
+        let mut status = self.lock_status_write().await;
+        let open_streams: Vec<String> = open_streams
+            .iter()
+            .filter(|camera_id| status.cameras.contains_key(*camera_id))
+            .cloned()
+            .collect();
+        let stalled = check_streams(
+            &mut status.stream_health,
+            &open_streams,
+            Instant::now(),
+            threshold,
+        );
+        drop(status);
+
+        for stall in &stalled {
+            warn!(
+                "Camera {} stream stalled: no frame for {}s",
+                stall.camera_id,
+                threshold.as_secs()
+            );
+            self.events.record(
+                "camera_manager",
+                "StreamStalled",
+                format!("StreamStalled {{ camera_id: {:?} }}", stall.camera_id),
+            );
+        }
+        stalled
+    }
+
     async fn list_cameras(&self) -> OurResult<Vec<CameraInfo>> {
         let status = self.lock_status().await;
         Ok(status.cameras.values().cloned().collect())
     }
 
-    async fn select_cameras(&mut self, camera_ids: Vec<String>) -> OurResult<()> {
+    async fn select_cameras(&mut self, camera_ids: Vec<CameraId>) -> OurResult<()> {
         let mut status = self.lock_status_write().await;
 
         // Validate all camera IDs exist
         for id in &camera_ids {
-            if !status.cameras.contains_key(id) {
+            if !status.cameras.contains_key(id.as_str()) {
                 return Err(OurError::App(format!("Camera with ID '{id}' not found")));
             }
         }
 
-        status.selected_cameras = camera_ids;
+        status.selected_cameras = camera_ids.into_iter().map(CameraId::into_string).collect();
         info!("Selected cameras: {:?}", status.selected_cameras);
         Ok(())
     }
@@ -348,7 +921,174 @@ impl CameraManager {
 
         let len = image_bytes.len();
         info!("Captured {len} bytes from camera '{camera_id}'");
-        Ok(image_bytes.to_vec())
+
+        let Some(orientation) = self.orientations.get(camera_id).copied() else {
+            return Ok(image_bytes.to_vec());
+        };
+        let jpeg = image_bytes.to_vec();
+        tokio::task::spawn_blocking(move || orientation.apply_to_jpeg(jpeg))
+            .await
+            .map_err(|e| OurError::App(format!("Snapshot orientation task failed: {e}")))?
+    }
+
+    /// Base URL of an ESPHome camera's REST API
+    async fn esphome_base_url(&self, camera_id: &str) -> OurResult<Url> {
+        let status = self.lock_status().await;
+        let camera = status
+            .cameras
+            .get(camera_id)
+            .ok_or_else(|| OurError::App(format!("Camera with ID '{camera_id}' not found")))?;
+        if !camera.online {
+            return Err(OurError::App(format!("Camera '{camera_id}' is offline")));
+        }
+        Ok(camera.snapshot_url.join("/")?)
+    }
+
+    async fn get_esp_settings(
+        &self,
+        camera_id: &str,
+        entities: &[EspEntity],
+    ) -> OurResult<Vec<EspEntityState>> {
+        let base_url = self.esphome_base_url(camera_id).await?;
+
+        let mut states = Vec::with_capacity(entities.len());
+        for entity in entities {
+            let state = match self.read_esp_entity(&base_url, entity).await {
+                Ok((state, options)) => EspEntityState {
+                    entity: entity.clone(),
+                    state,
+                    options,
+                    error: None,
+                },
+                Err(e) => {
+                    warn!("Failed to read {entity} from camera '{camera_id}': {e}");
+                    EspEntityState {
+                        entity: entity.clone(),
+                        state: None,
+                        options: Vec::new(),
+                        error: Some(e.to_string()),
+                    }
+                }
+            };
+            states.push(state);
+        }
+        Ok(states)
+    }
+
+    /// Read an entity's state and, for selects, its options
+    async fn read_esp_entity(
+        &self,
+        base_url: &Url,
+        entity: &EspEntity,
+    ) -> OurResult<(Option<String>, Vec<String>)> {
+        #[derive(Deserialize)]
+        struct EntityResponse {
+            state: Option<serde_json::Value>,
+            #[serde(default, rename = "option")]
+            options: Vec<String>,
+        }
+
+        let response = self
+            .client
+            .get(base_url.join(&entity.to_string())?)
+            .send()
+            .await
+            .map_err(|e| OurError::App(format!("Failed to request {entity}: {e}")))?;
+        if !response.status().is_success() {
+            let status = response.status();
+            return Err(OurError::App(format!(
+                "Reading {entity} failed with status: {status}"
+            )));
+        }
+        let body: EntityResponse = response
+            .json()
+            .await
+            .map_err(|e| OurError::App(format!("Invalid response for {entity}: {e}")))?;
+
+        let state = body.state.map(|state| match state {
+            serde_json::Value::String(state) => state,
+            other => other.to_string(),
+        });
+        Ok((state, body.options))
+    }
+
+    async fn set_esp_settings(
+        &self,
+        camera_id: &str,
+        updates: Vec<(EspEntity, String)>,
+    ) -> OurResult<Vec<EspSettingResult>> {
+        let base_url = self.esphome_base_url(camera_id).await?;
+
+        let mut results = Vec::with_capacity(updates.len());
+        for (entity, value) in updates {
+            let error = match self.write_esp_entity(&base_url, &entity, &value).await {
+                Ok(()) => {
+                    info!("Set {entity} to '{value}' on camera '{camera_id}'");
+                    None
+                }
+                Err(e) => {
+                    warn!("Failed to set {entity} on camera '{camera_id}': {e}");
+                    Some(e.to_string())
+                }
+            };
+            results.push(EspSettingResult {
+                entity,
+                value,
+                success: error.is_none(),
+                error,
+            });
+        }
+        Ok(results)
+    }
+
+    async fn write_esp_entity(
+        &self,
+        base_url: &Url,
+        entity: &EspEntity,
+        value: &str,
+    ) -> OurResult<()> {
+        let url = match entity.domain {
+            EspEntityDomain::Select => {
+                let mut url = base_url.join(&format!("{entity}/set"))?;
+                url.query_pairs_mut().append_pair("option", value);
+                url
+            }
+            EspEntityDomain::Number => {
+                let number: f64 = value
+                    .parse()
+                    .map_err(|_| OurError::App(format!("'{value}' is not a number")))?;
+                let mut url = base_url.join(&format!("{entity}/set"))?;
+                url.query_pairs_mut()
+                    .append_pair("value", &number.to_string());
+                url
+            }
+            EspEntityDomain::Switch => {
+                let action = match value.to_lowercase().as_str() {
+                    "true" | "on" | "1" => "turn_on",
+                    "false" | "off" | "0" => "turn_off",
+                    _ => {
+                        return Err(OurError::App(format!(
+                            "'{value}' is not a switch state, use on or off"
+                        )));
+                    }
+                };
+                base_url.join(&format!("{entity}/{action}"))?
+            }
+        };
+
+        let response = self
+            .client
+            .post(url)
+            .send()
+            .await
+            .map_err(|e| OurError::App(format!("Failed to update {entity}: {e}")))?;
+        if !response.status().is_success() {
+            let status = response.status();
+            return Err(OurError::App(format!(
+                "Updating {entity} failed with status: {status}"
+            )));
+        }
+        Ok(())
     }
 
     async fn probe_esphome_camera(&self, hostname: &str) -> OurResult<CameraInfo> {
@@ -375,16 +1115,8 @@ impl CameraManager {
             )));
         }
 
-        // Extract camera name from hostname (remove protocol and port)
-        let camera_name = hostname
-            .replace("http://", "")
-            .replace("https://", "")
-            .split(':')
-            .next()
-            .unwrap_or(hostname)
-            .to_string();
-
-        let camera_id = format!("esphome_{camera_name}");
+        let camera_name = esphome_camera_name(hostname);
+        let camera_id = esphome_camera_id(hostname);
 
         Ok(CameraInfo {
             id: camera_id,
@@ -393,6 +1125,72 @@ impl CameraManager {
             stream_url: base_url.join("/camera/stream")?,
             snapshot_url: base_url.join("/camera/snapshot")?,
             online: true,
+            last_seen: None,
+            last_error: None,
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn camera() -> CameraInfo {
+        CameraInfo {
+            id: "esphome_camera1".to_string(),
+            name: "camera1".to_string(),
+            hostname: "camera1.local".to_string(),
+            stream_url: Url::from_str("http://camera1.local/camera/stream").expect("valid url"),
+            snapshot_url: Url::from_str("http://camera1.local/camera/snapshot").expect("valid url"),
+            online: false,
+            last_seen: None,
+            last_error: None,
+        }
+    }
+
+    #[test]
+    fn test_staleness_follows_last_seen() {
+        let threshold = Duration::from_secs(300);
+        let seen_at = DateTime::parse_from_rfc3339("2025-06-01T12:00:00Z")
+            .expect("valid timestamp")
+            .with_timezone(&Utc);
+        let mut camera = camera();
+
+        // Never seen at all
+        assert_eq!(camera.offline_for(seen_at), None);
+        assert!(camera.is_stale(seen_at, threshold));
+
+        camera.mark_seen(seen_at);
+        assert!(camera.online);
+        assert_eq!(camera.offline_for(seen_at), None);
+        assert!(!camera.is_stale(seen_at + chrono::Duration::hours(1), threshold));
+
+        camera.mark_offline("Failed to probe camera: timed out".to_string());
+        let shortly = seen_at + chrono::Duration::seconds(60);
+        assert_eq!(camera.offline_for(shortly), Some(Duration::from_secs(60)));
+        assert!(!camera.is_stale(shortly, threshold));
+
+        let at_threshold = seen_at + chrono::Duration::seconds(300);
+        assert!(!camera.is_stale(at_threshold, threshold));
+        let past_threshold = seen_at + chrono::Duration::seconds(301);
+        assert!(camera.is_stale(past_threshold, threshold));
+        assert_eq!(
+            camera.last_error.as_deref(),
+            Some("Failed to probe camera: timed out")
+        );
+
+        // Coming back clears the error and the staleness
+        camera.mark_seen(past_threshold);
+        assert!(!camera.is_stale(past_threshold, threshold));
+        assert_eq!(camera.last_error, None);
+    }
+
+    #[test]
+    fn test_clock_skew_counts_as_just_seen() {
+        let now = Utc::now();
+        let mut camera = camera();
+        camera.mark_seen(now + chrono::Duration::seconds(30));
+        camera.mark_offline("gone".to_string());
+        assert_eq!(camera.offline_for(now), Some(Duration::ZERO));
+    }
+}
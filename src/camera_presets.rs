@@ -0,0 +1,131 @@
+//! Named sets of cameras and their settings, for switching between rigs.
+//!
+//! A [`CameraPreset`] is the cameras to select for one way of working, such as
+//! "bench" or "sorter", with each camera's [`CameraConfig`] and brightness.
+//! Applying it selects those cameras and gives them their stored settings in
+//! one go, instead of re-selecting and re-configuring every camera by hand.
+//! Presets, and the name of the one applied last, are kept in the user config.
+
+use std::collections::{BTreeMap, HashSet};
+
+use serde::{Deserialize, Serialize};
+
+use crate::camera::{CameraId, CameraKind};
+use crate::config::CameraConfig;
+
+/// Longest preset name accepted
+pub const MAX_PRESET_NAME_LENGTH: usize = 100;
+
+/// One camera in a preset and the settings it gets when the preset is applied
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+pub struct PresetCamera {
+    pub camera_id: CameraId,
+    /// Nickname, view type, region, resolution and orientation
+    #[serde(default)]
+    pub config: CameraConfig,
+    /// Brightness for a USB camera; ESPHome cameras don't have one
+    #[serde(default)]
+    pub brightness: Option<i64>,
+}
+
+/// The cameras to select, in order, and their settings
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct CameraPreset {
+    #[serde(default)]
+    pub cameras: Vec<PresetCamera>,
+}
+
+impl CameraPreset {
+    pub fn camera_ids(&self) -> Vec<CameraId> {
+        self.cameras
+            .iter()
+            .map(|camera| camera.camera_id.clone())
+            .collect()
+    }
+
+    pub fn contains(&self, camera_id: &str) -> bool {
+        self.cameras
+            .iter()
+            .any(|camera| camera.camera_id.as_str() == camera_id)
+    }
+
+    /// Problems with the cameras, keyed by field, e.g. `cameras.1.brightness`
+    pub fn field_errors(&self) -> BTreeMap<String, String> {
+        let mut errors = BTreeMap::new();
+        let mut seen = HashSet::new();
+        for (index, camera) in self.cameras.iter().enumerate() {
+            if !seen.insert(&camera.camera_id) {
+                errors.insert(
+                    format!("cameras.{index}.camera_id"),
+                    format!("{} is listed more than once", camera.camera_id),
+                );
+            }
+            let error = match camera.brightness {
+                Some(_) if camera.camera_id.kind() != CameraKind::Usb => {
+                    Some("only USB cameras have a brightness")
+                }
+                Some(brightness) if !(0..=255).contains(&brightness) => {
+                    Some("must be between 0 and 255")
+                }
+                _ => None,
+            };
+            if let Some(error) = error {
+                errors.insert(format!("cameras.{index}.brightness"), error.to_string());
+            }
+        }
+        errors
+    }
+}
+
+/// The stored name of the preset called `name`, ignoring case and surrounding spaces
+pub fn preset_named<'a>(
+    presets: &'a BTreeMap<String, CameraPreset>,
+    name: &str,
+) -> Option<&'a str> {
+    let name = name.trim();
+    presets
+        .keys()
+        .find(|preset| preset.eq_ignore_ascii_case(name))
+        .map(String::as_str)
+}
+
+/// Why a preset name can't be used, if it can't
+pub fn preset_name_error(name: &str) -> Option<String> {
+    let name = name.trim();
+    if name.is_empty() {
+        Some("must not be empty".to_string())
+    } else if name.chars().count() > MAX_PRESET_NAME_LENGTH {
+        Some(format!(
+            "must be at most {MAX_PRESET_NAME_LENGTH} characters"
+        ))
+    } else if name.contains('/') {
+        // The name is a path segment in /api/camera-presets/{name}
+        Some("must not contain '/'".to_string())
+    } else {
+        None
+    }
+}
+
+/// How applying a preset went for one camera
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PresetCameraStatus {
+    /// Selected, with its settings applied
+    Applied,
+    /// Not connected; its settings are saved for when it is
+    Missing,
+    /// Its manager refused the selection or the brightness
+    Failed,
+}
+
+/// One camera's part in applying a preset
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub struct PresetCameraResult {
+    pub camera_id: CameraId,
+    pub status: PresetCameraStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
@@ -0,0 +1,204 @@
+//! Letting a USB camera's auto-exposure settle before the frame that is kept.
+//!
+//! Every USB capture opens the camera, and the first frames after
+//! `open_stream` come out dark because auto-exposure hasn't converged yet. A
+//! capture therefore pulls and discards [`CaptureWarmup::frames`] frames, and
+//! keeps pulling until [`CaptureWarmup::duration`] has passed, before taking the
+//! frame it returns. A camera that delivered a frame within
+//! [`CONTINUOUS_STREAM_WINDOW`] is still exposed correctly, so the
+//! [`WarmupTracker`] lets live streams skip the warm-up after their first frame.
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use nokhwa::{Buffer, Camera};
+use serde::{Deserialize, Serialize};
+
+use crate::config::Settings;
+use crate::{OurError, OurResult};
+
+/// Frames discarded after opening a camera unless configured otherwise
+pub const DEFAULT_WARMUP_FRAMES: u32 = 5;
+
+/// A camera that produced a frame this recently skips the warm-up
+pub const CONTINUOUS_STREAM_WINDOW: Duration = Duration::from_secs(2);
+
+/// How long to let a freshly opened camera settle
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CaptureWarmup {
+    /// Frames pulled and thrown away before the kept frame
+    pub frames: u32,
+    /// Minimum time between opening the stream and the kept frame
+    pub duration: Duration,
+}
+
+impl CaptureWarmup {
+    /// No warm-up at all, used when the stream has been running continuously
+    pub const NONE: CaptureWarmup = CaptureWarmup {
+        frames: 0,
+        duration: Duration::ZERO,
+    };
+
+    /// The warm-up configured by `capture_warmup_frames` and `capture_warmup_ms`
+    pub fn from_settings(settings: &Settings) -> Self {
+        Self {
+            frames: settings.capture_warmup_frames,
+            duration: Duration::from_millis(settings.capture_warmup_ms),
+        }
+    }
+}
+
+impl Default for CaptureWarmup {
+    fn default() -> Self {
+        Self {
+            frames: DEFAULT_WARMUP_FRAMES,
+            duration: Duration::ZERO,
+        }
+    }
+}
+
+/// What the warm-up before a capture cost
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct WarmupTiming {
+    /// Frames discarded before the kept frame
+    pub frames_discarded: u32,
+    /// Time spent discarding them
+    pub duration_ms: f64,
+}
+
+/// Anything that hands out frames one after another
+pub trait FrameSource {
+    type Frame;
+
+    /// Block until the next frame is available
+    fn next_frame(&mut self) -> OurResult<Self::Frame>;
+}
+
+impl FrameSource for Camera {
+    type Frame = Buffer;
+
+    fn next_frame(&mut self) -> OurResult<Buffer> {
+        self.frame()
+            .map_err(|e| OurError::App(format!("Failed to capture frame: {e}")))
+    }
+}
+
+/// Discard frames from a just-opened `source` per `warmup`, then return the next one
+pub fn capture_settled<S: FrameSource>(
+    source: &mut S,
+    warmup: CaptureWarmup,
+) -> OurResult<(S::Frame, WarmupTiming)> {
+    let started = Instant::now();
+    let mut frames_discarded = 0;
+    // Pulling frames rather than sleeping keeps the driver's buffer fresh, so
+    // the kept frame was exposed after the wait rather than before it
+    while frames_discarded < warmup.frames || started.elapsed() < warmup.duration {
+        source
+            .next_frame()
+            .map_err(|e| OurError::App(format!("Camera warm-up failed: {e}")))?;
+        frames_discarded += 1;
+    }
+    let timing = WarmupTiming {
+        frames_discarded,
+        duration_ms: started.elapsed().as_secs_f64() * 1000.0,
+    };
+    Ok((source.next_frame()?, timing))
+}
+
+/// When each camera last produced a frame, to tell a running stream from a cold start
+#[derive(Debug, Default)]
+pub struct WarmupTracker {
+    last_frames: HashMap<String, Instant>,
+}
+
+impl WarmupTracker {
+    /// The warm-up a capture from `hardware_id` needs at `now`
+    pub fn warmup_for(
+        &self,
+        hardware_id: &str,
+        configured: CaptureWarmup,
+        now: Instant,
+    ) -> CaptureWarmup {
+        match self.last_frames.get(hardware_id) {
+            Some(last) if now.saturating_duration_since(*last) <= CONTINUOUS_STREAM_WINDOW => {
+                CaptureWarmup::NONE
+            }
+            _ => configured,
+        }
+    }
+
+    /// Note that `hardware_id` produced a frame at `now`
+    pub fn record_frame(&mut self, hardware_id: &str, now: Instant) {
+        self.last_frames.insert(hardware_id.to_string(), now);
+    }
+
+    /// Forget every camera, so the next capture from each warms up again
+    pub fn clear(&mut self) {
+        self.last_frames.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A camera whose frames are just their index since the stream opened
+    #[derive(Default)]
+    struct SimulatedCamera {
+        next_index: u32,
+    }
+
+    impl FrameSource for SimulatedCamera {
+        type Frame = u32;
+
+        fn next_frame(&mut self) -> OurResult<u32> {
+            let index = self.next_index;
+            self.next_index += 1;
+            Ok(index)
+        }
+    }
+
+    #[test]
+    fn test_first_saved_frame_follows_warmup() {
+        let warmup = CaptureWarmup::default();
+        let mut tracker = WarmupTracker::default();
+        let opened = Instant::now();
+
+        let mut camera = SimulatedCamera::default();
+        let needed = tracker.warmup_for("usb:1", warmup, opened);
+        let (frame, timing) = capture_settled(&mut camera, needed).expect("capture should work");
+        assert!(frame >= DEFAULT_WARMUP_FRAMES, "kept frame {frame}");
+        assert_eq!(timing.frames_discarded, DEFAULT_WARMUP_FRAMES);
+        tracker.record_frame("usb:1", opened);
+
+        // A stream that is still running reopens the camera but doesn't wait again
+        let mut camera = SimulatedCamera::default();
+        let soon = opened + Duration::from_millis(200);
+        let (frame, timing) =
+            capture_settled(&mut camera, tracker.warmup_for("usb:1", warmup, soon))
+                .expect("capture should work");
+        assert_eq!(frame, 0);
+        assert_eq!(timing.frames_discarded, 0);
+
+        // Other cameras and stale streams warm up as usual
+        assert_eq!(tracker.warmup_for("usb:2", warmup, soon), warmup);
+        let later = opened + CONTINUOUS_STREAM_WINDOW + Duration::from_millis(1);
+        assert_eq!(tracker.warmup_for("usb:1", warmup, later), warmup);
+        tracker.clear();
+        assert_eq!(tracker.warmup_for("usb:1", warmup, soon), warmup);
+    }
+
+    #[test]
+    fn test_duration_warmup_keeps_pulling_frames() {
+        let warmup = CaptureWarmup {
+            frames: 0,
+            duration: Duration::from_millis(20),
+        };
+        let mut camera = SimulatedCamera::default();
+        let (frame, timing) = capture_settled(&mut camera, warmup).expect("capture should work");
+        assert!(frame >= 1, "kept frame {frame}");
+        assert_eq!(frame, timing.frames_discarded);
+        assert!(timing.duration_ms >= 20.0, "{timing:?}");
+    }
+}
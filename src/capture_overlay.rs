@@ -0,0 +1,325 @@
+//! A text strip stamped onto captured images, naming when and where they were taken.
+//!
+//! With `capture_overlay` on (or a camera's own `capture_overlay` override), each
+//! captured frame gets a small strip in the `capture_overlay_corner` with the
+//! capture time, the camera and the short form of the session ID, so a training
+//! image can be placed without opening its shell record. The strip is drawn
+//! with the embedded DejaVu Sans Mono font. It is never drawn over the camera's
+//! region of interest: when the strip would touch the region it is left off and
+//! a warning logged. Only saved captures are stamped, never streamed frames.
+
+use std::fmt;
+use std::str::FromStr;
+
+use ab_glyph::FontRef;
+use chrono::{DateTime, Utc};
+use image::{Rgb, RgbImage};
+use imageproc::drawing::{draw_filled_rect_mut, draw_text_mut, text_size};
+use imageproc::rect::Rect;
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use crate::composite::LABEL_FONT;
+use crate::config::{CameraConfig, Settings};
+use crate::frame_region::{FrameRegion, FrameSize};
+use crate::image_ingest::encode_jpeg;
+use crate::{OurError, OurResult};
+
+/// Smallest text height in pixels, however small the frame
+const MIN_TEXT_HEIGHT: u32 = 12;
+/// Characters of the session ID shown
+const SESSION_ID_CHARS: usize = 8;
+
+/// Corner of the frame the overlay strip is drawn in
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OverlayCorner {
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    #[default]
+    BottomRight,
+}
+
+impl OverlayCorner {
+    /// Every corner, as written in the config
+    pub const NAMES: [&str; 4] = ["top_left", "top_right", "bottom_left", "bottom_right"];
+}
+
+impl FromStr for OverlayCorner {
+    type Err = OurError;
+
+    fn from_str(s: &str) -> OurResult<Self> {
+        match s.trim().to_ascii_lowercase().replace('-', "_").as_str() {
+            "top_left" => Ok(Self::TopLeft),
+            "top_right" => Ok(Self::TopRight),
+            "bottom_left" => Ok(Self::BottomLeft),
+            "bottom_right" => Ok(Self::BottomRight),
+            _ => Err(OurError::Config(format!(
+                "Invalid overlay corner {s:?}, expected one of: {}",
+                Self::NAMES.join(", ")
+            ))),
+        }
+    }
+}
+
+impl fmt::Display for OverlayCorner {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Self::TopLeft => "top_left",
+            Self::TopRight => "top_right",
+            Self::BottomLeft => "bottom_left",
+            Self::BottomRight => "bottom_right",
+        };
+        f.write_str(name)
+    }
+}
+
+/// The strip to stamp on one captured frame
+#[derive(Debug, Clone, PartialEq)]
+pub struct CaptureOverlay {
+    pub corner: OverlayCorner,
+    pub text: String,
+    /// The camera's region of interest, kept clear of the strip
+    pub region: Option<FrameRegion>,
+    /// The frame the region was drawn on, to scale it to the captured frame
+    pub region_frame: Option<FrameSize>,
+}
+
+impl CaptureOverlay {
+    /// The overlay for a frame from `camera_id`, or `None` when the camera doesn't have one
+    pub fn for_capture(
+        settings: &Settings,
+        camera_id: &str,
+        config: Option<&CameraConfig>,
+        session_id: &str,
+        taken_at: DateTime<Utc>,
+    ) -> Option<Self> {
+        let enabled = config
+            .and_then(|config| config.capture_overlay)
+            .unwrap_or(settings.capture_overlay);
+        if !enabled {
+            return None;
+        }
+        let camera = match config.and_then(|config| config.nickname.as_deref()) {
+            Some(nickname) => format!("{nickname} ({camera_id})"),
+            None => camera_id.to_string(),
+        };
+        let session: String = session_id.chars().take(SESSION_ID_CHARS).collect();
+        Some(Self {
+            corner: settings.capture_overlay_corner,
+            text: format!(
+                "{}  {camera}  {session}",
+                taken_at.format("%Y-%m-%d %H:%M:%S%.3fZ")
+            ),
+            region: config.and_then(CameraConfig::region),
+            region_frame: config.and_then(CameraConfig::region_frame),
+        })
+    }
+
+    /// The region of interest in the coordinates of a `width` by `height` frame
+    fn scaled_region(&self, width: u32, height: u32) -> Option<Rect> {
+        let region = self.region?;
+        let scale = |value: u32, to: u32, from: Option<u32>| match from {
+            Some(from) if from > 0 => (u64::from(value) * u64::from(to) / u64::from(from)) as u32,
+            _ => value,
+        };
+        let frame_width = self.region_frame.map(|frame| frame.width);
+        let frame_height = self.region_frame.map(|frame| frame.height);
+        let x = scale(region.x, width, frame_width);
+        let y = scale(region.y, height, frame_height);
+        let region_width = scale(region.width, width, frame_width).max(1);
+        let region_height = scale(region.height, height, frame_height).max(1);
+        Some(Rect::at(x as i32, y as i32).of_size(region_width, region_height))
+    }
+
+    /// Draw the strip onto `image`, or say why it was left off
+    pub fn draw(&self, image: &mut RgbImage) -> Result<(), String> {
+        let font = FontRef::try_from_slice(LABEL_FONT)
+            .map_err(|e| format!("the overlay font couldn't be loaded: {e}"))?;
+        let (width, height) = image.dimensions();
+        let scale = (height / 45).max(MIN_TEXT_HEIGHT) as f32;
+        let (text_width, text_height) = text_size(scale, &font, &self.text);
+        let padding = (scale / 4.0).ceil() as u32;
+        let strip_width = text_width + padding * 2;
+        let strip_height = text_height.max(scale as u32) + padding * 2;
+        if strip_width > width || strip_height > height {
+            return Err(format!(
+                "a {strip_width}x{strip_height} strip doesn't fit a {width}x{height} frame"
+            ));
+        }
+
+        let x = match self.corner {
+            OverlayCorner::TopLeft | OverlayCorner::BottomLeft => 0,
+            OverlayCorner::TopRight | OverlayCorner::BottomRight => width - strip_width,
+        };
+        let y = match self.corner {
+            OverlayCorner::TopLeft | OverlayCorner::TopRight => 0,
+            OverlayCorner::BottomLeft | OverlayCorner::BottomRight => height - strip_height,
+        };
+        let strip = Rect::at(x as i32, y as i32).of_size(strip_width, strip_height);
+        if let Some(region) = self.scaled_region(width, height)
+            && strip.intersect(region).is_some()
+        {
+            return Err(format!(
+                "the {} corner overlaps the region of interest",
+                self.corner
+            ));
+        }
+
+        draw_filled_rect_mut(image, strip, Rgb([0, 0, 0]));
+        draw_text_mut(
+            image,
+            Rgb([255, 255, 255]),
+            (x + padding) as i32,
+            (y + padding) as i32,
+            scale,
+            &font,
+            &self.text,
+        );
+        Ok(())
+    }
+
+    /// Decode a captured JPEG, draw the strip and encode it again
+    ///
+    /// `Ok(None)` means the strip was left off and the frame should be kept as it was.
+    pub fn apply_to_jpeg(&self, jpeg: &[u8], quality: u8) -> OurResult<Option<Vec<u8>>> {
+        let mut image = image::load_from_memory(jpeg)?.to_rgb8();
+        match self.draw(&mut image) {
+            Ok(()) => Ok(Some(encode_jpeg(&image, quality)?)),
+            Err(reason) => {
+                warn!("Left the capture overlay off: {reason}");
+                Ok(None)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn overlay(corner: OverlayCorner) -> CaptureOverlay {
+        CaptureOverlay {
+            corner,
+            text: "2026-10-16 09:30:00.000Z  Top (usb:0)  a1b2c3d4".to_string(),
+            region: None,
+            region_frame: None,
+        }
+    }
+
+    /// Whether every pixel in the `size` square at the corner is the same colour
+    fn corner_is_uniform(image: &RgbImage, corner: OverlayCorner, size: u32) -> bool {
+        let (width, height) = image.dimensions();
+        let (x0, y0) = match corner {
+            OverlayCorner::TopLeft => (0, 0),
+            OverlayCorner::TopRight => (width - size, 0),
+            OverlayCorner::BottomLeft => (0, height - size),
+            OverlayCorner::BottomRight => (width - size, height - size),
+        };
+        let first = image.get_pixel(x0, y0);
+        (x0..x0 + size).all(|x| (y0..y0 + size).all(|y| image.get_pixel(x, y) == first))
+    }
+
+    #[test]
+    fn test_overlay_is_drawn_in_the_corner_before_encoding() {
+        let grey = RgbImage::from_pixel(640, 480, Rgb([128, 128, 128]));
+        let jpeg = encode_jpeg(&grey, 90).expect("JPEG should encode");
+        let decoded = image::load_from_memory(&jpeg)
+            .expect("JPEG should decode")
+            .to_rgb8();
+        assert!(corner_is_uniform(&decoded, OverlayCorner::BottomRight, 40));
+
+        let stamped = overlay(OverlayCorner::BottomRight)
+            .apply_to_jpeg(&jpeg, 90)
+            .expect("overlay should apply")
+            .expect("overlay should fit");
+        let stamped = image::load_from_memory(&stamped)
+            .expect("stamped JPEG should decode")
+            .to_rgb8();
+        assert!(!corner_is_uniform(&stamped, OverlayCorner::BottomRight, 40));
+        // The other corners are left alone
+        assert!(corner_is_uniform(&stamped, OverlayCorner::TopLeft, 40));
+        assert!(corner_is_uniform(&stamped, OverlayCorner::TopRight, 40));
+    }
+
+    #[test]
+    fn test_overlay_is_left_off_the_region_of_interest() {
+        let mut image = RgbImage::from_pixel(640, 480, Rgb([128, 128, 128]));
+        let mut stamped = overlay(OverlayCorner::TopLeft);
+        // Drawn on a frame twice the size, so it reaches the top left corner once scaled
+        stamped.region = Some(FrameRegion {
+            x: 20,
+            y: 20,
+            width: 400,
+            height: 400,
+        });
+        stamped.region_frame = Some(FrameSize {
+            width: 1280,
+            height: 960,
+        });
+        let reason = stamped.draw(&mut image).expect_err("strip overlaps region");
+        assert!(reason.contains("top_left"), "{reason}");
+        assert!(corner_is_uniform(&image, OverlayCorner::TopLeft, 40));
+
+        stamped.corner = OverlayCorner::BottomRight;
+        stamped
+            .draw(&mut image)
+            .expect("the opposite corner is clear");
+        assert!(!corner_is_uniform(&image, OverlayCorner::BottomRight, 20));
+
+        let mut tiny = RgbImage::new(32, 32);
+        assert!(overlay(OverlayCorner::TopLeft).draw(&mut tiny).is_err());
+    }
+
+    #[test]
+    fn test_overlay_follows_the_camera_override() {
+        let taken_at = DateTime::parse_from_rfc3339("2026-10-16T09:30:00Z")
+            .expect("timestamp should parse")
+            .with_timezone(&Utc);
+        let mut settings = Settings::default();
+        let config = CameraConfig {
+            nickname: Some("Top".to_string()),
+            ..CameraConfig::default()
+        };
+        assert_eq!(
+            CaptureOverlay::for_capture(
+                &settings,
+                "usb:0",
+                Some(&config),
+                "abcdef123456",
+                taken_at
+            ),
+            None
+        );
+
+        settings.capture_overlay = true;
+        let stamped = CaptureOverlay::for_capture(
+            &settings,
+            "usb:0",
+            Some(&config),
+            "abcdef123456",
+            taken_at,
+        )
+        .expect("overlay is on");
+        assert_eq!(
+            stamped.text,
+            "2026-10-16 09:30:00.000Z  Top (usb:0)  abcdef12"
+        );
+
+        let opted_out = CameraConfig {
+            capture_overlay: Some(false),
+            ..config
+        };
+        assert_eq!(
+            CaptureOverlay::for_capture(&settings, "usb:0", Some(&opted_out), "abc", taken_at),
+            None
+        );
+        assert_eq!(
+            "Top-Right"
+                .parse::<OverlayCorner>()
+                .expect("corner should parse"),
+            OverlayCorner::TopRight
+        );
+    }
+}
@@ -0,0 +1,273 @@
+//! Checking every selected camera is there before a capture starts.
+//!
+//! A capture with a selected camera that is offline used to quietly save a
+//! session short of that camera's image, which was only noticed at tagging.
+//! [`check`] looks at every selected camera first: the saved selection as well
+//! as each manager's, so a USB camera that dropped off its manager's selection
+//! when it was unplugged still counts. ESPHome cameras that are offline, or
+//! haven't been heard from in [`PREFLIGHT_FRESHNESS`], are probed again for up
+//! to [`PREFLIGHT_PROBE_TIMEOUT`] before they are judged. USB cameras are
+//! offline when their manager doesn't have them connected.
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use tracing::warn;
+
+use crate::camera::{CameraId, IdsByKind};
+use crate::camera_manager::{CameraHandle, CameraInfo};
+use crate::usb_camera_controller::{UsbCameraHandle, UsbCameraInfo};
+
+/// ESPHome cameras not heard from for longer than this are probed again
+pub const PREFLIGHT_FRESHNESS: Duration = Duration::from_secs(30);
+/// How long each of those cameras gets to answer the probe
+pub const PREFLIGHT_PROBE_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// A selected camera that can't be captured from
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub struct OfflineCamera {
+    pub camera_id: String,
+    pub reason: String,
+}
+
+/// The selected cameras, split into the ones ready to capture and the ones that aren't
+#[derive(Debug, Default)]
+pub struct Preflight {
+    /// Every selected camera, in capture order
+    pub selected: Vec<String>,
+    /// Selected cameras that are online, in capture order
+    pub ready: IdsByKind,
+    pub offline: Vec<OfflineCamera>,
+}
+
+impl Preflight {
+    /// The offline cameras as `id (reason)`, for messages and logs
+    pub fn offline_summary(&self) -> String {
+        self.offline
+            .iter()
+            .map(|camera| format!("{} ({})", camera.camera_id, camera.reason))
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
+
+/// The managers' selections followed by saved selections they don't have, without repeats
+pub fn selected_cameras(managers: IdsByKind, saved: &[CameraId]) -> IdsByKind {
+    let mut selected: Vec<CameraId> = Vec::new();
+    for camera_id in managers
+        .esphome
+        .into_iter()
+        .chain(managers.usb)
+        .chain(saved.iter().cloned())
+    {
+        if !selected.contains(&camera_id) {
+            selected.push(camera_id);
+        }
+    }
+    selected.into_iter().collect()
+}
+
+/// Whether an ESPHome camera should be probed before it is judged
+pub fn needs_probe(camera: Option<&CameraInfo>, now: DateTime<Utc>, freshness: Duration) -> bool {
+    let Some(camera) = camera else {
+        return true;
+    };
+    let fresh = camera.last_seen.is_some_and(|last_seen| {
+        (now - last_seen)
+            .to_std()
+            .is_ok_and(|since| since <= freshness)
+    });
+    !camera.online || !fresh
+}
+
+/// Why an ESPHome camera can't be captured from, if it can't
+pub fn esphome_offline_reason(camera: Option<&CameraInfo>) -> Option<String> {
+    match camera {
+        None => Some("not detected".to_string()),
+        Some(camera) if !camera.online => Some(
+            camera
+                .last_error
+                .clone()
+                .unwrap_or_else(|| "offline".to_string()),
+        ),
+        Some(_) => None,
+    }
+}
+
+/// Why a USB camera can't be captured from, if it can't
+pub fn usb_offline_reason(camera: Option<&UsbCameraInfo>) -> Option<String> {
+    match camera {
+        None => Some("not detected".to_string()),
+        Some(camera) if !camera.connected => Some("not connected".to_string()),
+        Some(_) => None,
+    }
+}
+
+/// Check every selected camera, probing ESPHome cameras that haven't been heard from lately
+pub async fn check(
+    camera_manager: &CameraHandle,
+    usb_camera_manager: &UsbCameraHandle,
+    saved: &[CameraId],
+) -> Preflight {
+    let esphome_status = camera_manager
+        .get_status()
+        .await
+        .inspect_err(|e| warn!("Couldn't get ESPHome camera status for the capture check: {e}"))
+        .unwrap_or_default();
+    let usb_status = usb_camera_manager
+        .get_status()
+        .await
+        .inspect_err(|e| warn!("Couldn't get USB camera status for the capture check: {e}"))
+        .unwrap_or_default();
+    let managers = IdsByKind {
+        esphome: esphome_status
+            .selected_cameras
+            .iter()
+            .cloned()
+            .map(CameraId::from)
+            .collect(),
+        usb: usb_status
+            .selected_cameras()
+            .into_iter()
+            .map(CameraId::from)
+            .collect(),
+    };
+    let selected = selected_cameras(managers, saved);
+
+    let now = Utc::now();
+    let mut esphome: HashMap<String, CameraInfo> = esphome_status.cameras;
+    let to_probe: Vec<String> = selected
+        .esphome
+        .iter()
+        .map(CameraId::to_string)
+        .filter(|camera_id| needs_probe(esphome.get(camera_id), now, PREFLIGHT_FRESHNESS))
+        .collect();
+    if !to_probe.is_empty() {
+        match camera_manager
+            .probe_cameras(to_probe, PREFLIGHT_PROBE_TIMEOUT)
+            .await
+        {
+            Ok(probed) => {
+                for camera in probed {
+                    esphome.insert(camera.id.clone(), camera);
+                }
+            }
+            Err(e) => warn!("Couldn't probe ESPHome cameras for the capture check: {e}"),
+        }
+    }
+
+    let mut preflight = Preflight {
+        selected: selected
+            .esphome
+            .iter()
+            .chain(&selected.usb)
+            .map(CameraId::to_string)
+            .collect(),
+        ..Preflight::default()
+    };
+    for camera_id in selected.esphome {
+        match esphome_offline_reason(esphome.get(camera_id.as_str())) {
+            Some(reason) => preflight.offline.push(OfflineCamera {
+                camera_id: camera_id.to_string(),
+                reason,
+            }),
+            None => preflight.ready.esphome.push(camera_id),
+        }
+    }
+    for camera_id in selected.usb {
+        match usb_offline_reason(usb_status.cameras.get(camera_id.as_str())) {
+            Some(reason) => preflight.offline.push(OfflineCamera {
+                camera_id: camera_id.to_string(),
+                reason,
+            }),
+            None => preflight.ready.usb.push(camera_id),
+        }
+    }
+    preflight
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn esp_camera(online: bool, last_seen: Option<DateTime<Utc>>) -> CameraInfo {
+        CameraInfo {
+            id: "esphome:cam1".to_string(),
+            name: "cam1".to_string(),
+            hostname: "cam1.local".to_string(),
+            stream_url: "http://cam1.local:81/stream".parse().expect("URL"),
+            snapshot_url: "http://cam1.local/snapshot".parse().expect("URL"),
+            online,
+            last_seen,
+            last_error: (!online).then(|| "Failed to probe camera: timed out".to_string()),
+        }
+    }
+
+    #[test]
+    fn test_stale_or_offline_esphome_cameras_are_probed() {
+        let now = Utc::now();
+        let recent = Some(now - chrono::Duration::seconds(5));
+        let stale = Some(now - chrono::Duration::seconds(120));
+        assert!(!needs_probe(
+            Some(&esp_camera(true, recent)),
+            now,
+            PREFLIGHT_FRESHNESS
+        ));
+        assert!(needs_probe(
+            Some(&esp_camera(true, stale)),
+            now,
+            PREFLIGHT_FRESHNESS
+        ));
+        assert!(needs_probe(
+            Some(&esp_camera(true, None)),
+            now,
+            PREFLIGHT_FRESHNESS
+        ));
+        assert!(needs_probe(
+            Some(&esp_camera(false, recent)),
+            now,
+            PREFLIGHT_FRESHNESS
+        ));
+        assert!(needs_probe(None, now, PREFLIGHT_FRESHNESS));
+
+        assert_eq!(
+            esphome_offline_reason(Some(&esp_camera(true, recent))),
+            None
+        );
+        assert_eq!(
+            esphome_offline_reason(Some(&esp_camera(false, recent))).as_deref(),
+            Some("Failed to probe camera: timed out")
+        );
+        assert_eq!(
+            esphome_offline_reason(None).as_deref(),
+            Some("not detected")
+        );
+    }
+
+    #[test]
+    fn test_saved_selections_join_the_managers_without_repeats() {
+        let managers: IdsByKind = ["esphome:cam1", "usb:0"]
+            .into_iter()
+            .map(CameraId::from)
+            .collect();
+        let saved: Vec<CameraId> = ["usb:1", "usb:0", "esphome:cam2"]
+            .into_iter()
+            .map(CameraId::from)
+            .collect();
+        let selected = selected_cameras(managers, &saved);
+        assert_eq!(
+            selected.esphome,
+            vec![
+                CameraId::from("esphome:cam1"),
+                CameraId::from("esphome:cam2")
+            ]
+        );
+        assert_eq!(
+            selected.usb,
+            vec![CameraId::from("usb:0"), CameraId::from("usb:1")]
+        );
+    }
+}
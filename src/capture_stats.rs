@@ -0,0 +1,141 @@
+//! Per-camera capture counters.
+//!
+//! Both camera managers keep a [`CaptureStats`] per camera inside their status
+//! structures so intermittent capture failures show up before a training batch
+//! turns out to be missing a view.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+use crate::OurResult;
+use crate::camera_warmup::WarmupTiming;
+use crate::frame_processing::FramePath;
+
+/// Weight given to the newest sample in the rolling latency average
+const LATENCY_SMOOTHING: f64 = 0.2;
+
+/// Capture counters for a single camera
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct CaptureStats {
+    /// Captures attempted since start or the last reset
+    pub attempted: u64,
+    /// Captures that returned an image
+    pub succeeded: u64,
+    /// Captures that failed
+    pub failed: u64,
+    /// Most recent failure message
+    pub last_error: Option<String>,
+    /// When the last successful capture finished
+    pub last_success: Option<DateTime<Utc>>,
+    /// Exponentially weighted average latency of successful captures
+    pub average_latency_ms: Option<f64>,
+    /// Warm-up before the most recent successful capture, for cameras that warm up
+    pub last_warmup: Option<WarmupTiming>,
+    /// Exponentially weighted average time spent decoding and encoding a frame,
+    /// for cameras whose frames are processed on the server
+    #[serde(default)]
+    pub average_processing_ms: Option<f64>,
+    /// MJPEG frames passed through without decoding
+    #[serde(default)]
+    pub passthrough_frames: u64,
+    /// Frames decoded, adjusted and encoded again
+    #[serde(default)]
+    pub processed_frames: u64,
+    /// Times the camera was found again at a new index after its USB hub reset
+    #[serde(default)]
+    pub index_recoveries: u64,
+}
+
+impl CaptureStats {
+    /// Record the outcome of a capture that took `latency`
+    pub fn record<T>(&mut self, result: &OurResult<T>, latency: Duration) {
+        self.attempted += 1;
+        match result {
+            Ok(_) => self.record_success(latency),
+            Err(e) => {
+                self.failed += 1;
+                self.last_error = Some(e.to_string());
+            }
+        }
+    }
+
+    /// Record the warm-up that preceded a successful capture
+    pub fn record_warmup(&mut self, warmup: WarmupTiming) {
+        self.last_warmup = Some(warmup);
+    }
+
+    /// Record how a captured frame was processed and how long it took
+    pub fn record_processing(&mut self, processing: Duration, path: FramePath) {
+        match path {
+            FramePath::Passthrough => self.passthrough_frames += 1,
+            FramePath::Processed => self.processed_frames += 1,
+        }
+        self.average_processing_ms = Some(smoothed(self.average_processing_ms, processing));
+    }
+
+    fn record_success(&mut self, latency: Duration) {
+        self.succeeded += 1;
+        self.last_success = Some(Utc::now());
+        self.average_latency_ms = Some(smoothed(self.average_latency_ms, latency));
+    }
+}
+
+/// Fold a new sample into a rolling average in milliseconds
+fn smoothed(average: Option<f64>, sample: Duration) -> f64 {
+    let sample_ms = sample.as_secs_f64() * 1000.0;
+    match average {
+        Some(average) => average + LATENCY_SMOOTHING * (sample_ms - average),
+        None => sample_ms,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::OurError;
+
+    #[test]
+    fn test_latency_averaging() {
+        let mut stats = CaptureStats::default();
+        let ok: OurResult<()> = Ok(());
+
+        stats.record(&ok, Duration::from_millis(100));
+        assert_eq!(stats.average_latency_ms, Some(100.0));
+
+        stats.record(&ok, Duration::from_millis(200));
+        let average = stats.average_latency_ms.unwrap_or_default();
+        assert!((average - 120.0).abs() < 1e-9);
+
+        // Failures count but don't affect the latency average
+        let failed: OurResult<()> = Err(OurError::App("Failed to decode frame".to_string()));
+        stats.record(&failed, Duration::from_secs(10));
+        let after_failure = stats.average_latency_ms.unwrap_or_default();
+        assert!((after_failure - 120.0).abs() < 1e-9);
+
+        // A steady latency converges on that value
+        for _ in 0..100 {
+            stats.record(&ok, Duration::from_millis(50));
+        }
+        let converged = stats.average_latency_ms.unwrap_or_default();
+        assert!((converged - 50.0).abs() < 0.01);
+
+        assert_eq!(stats.attempted, 103);
+        assert_eq!(stats.succeeded, 102);
+        assert_eq!(stats.failed, 1);
+        assert_eq!(
+            stats.last_error.as_deref(),
+            Some("Application error: Failed to decode frame")
+        );
+        assert!(stats.last_success.is_some());
+        assert_eq!(stats.average_processing_ms, None);
+
+        stats.record_processing(Duration::from_millis(40), FramePath::Processed);
+        stats.record_processing(Duration::from_millis(90), FramePath::Processed);
+        let processing = stats.average_processing_ms.unwrap_or_default();
+        assert!((processing - 50.0).abs() < 1e-9);
+        stats.record_processing(Duration::from_millis(1), FramePath::Passthrough);
+        assert_eq!((stats.passthrough_frames, stats.processed_frames), (1, 2));
+    }
+}
@@ -0,0 +1,147 @@
+//! Canonical case designations for shell records.
+//!
+//! Training counts group shells by their `shell_type`, so "9 mm", "9MM" and
+//! "9x19" must all be stored as "9mm". [`CaseDesignations`] matches a value
+//! against the supported case types ignoring case and whitespace, and through
+//! a table of common aliases, and returns the canonical form.
+
+use serde::{Deserialize, Serialize};
+
+/// Other names shells get tagged with, and the case type each one means
+const ALIASES: &[(&str, &str)] = &[
+    ("9x19", "9mm"),
+    ("9x19mm", "9mm"),
+    ("9mm luger", "9mm"),
+    ("9mm parabellum", "9mm"),
+    ("40 s&w", "40sw"),
+    (".40 s&w", "40sw"),
+    ("45 auto", "45acp"),
+    (".45 acp", "45acp"),
+    ("5.56", "223rem"),
+    (".223 remington", "223rem"),
+    ("7.62x51", "308win"),
+    (".308 winchester", "308win"),
+    ("30-06", "3006spr"),
+    (".30-06 springfield", "3006spr"),
+    (".38 special", "38special"),
+    ("38 spl", "38special"),
+    (".357 magnum", "357mag"),
+];
+
+/// A supported case type and the aliases that resolve to it
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct CaseDesignation {
+    pub designation: String,
+    pub aliases: Vec<String>,
+}
+
+/// The supported case types and the aliases for them
+#[derive(Debug, Clone)]
+pub struct CaseDesignations {
+    designations: Vec<CaseDesignation>,
+}
+
+impl CaseDesignations {
+    /// Designations for `case_types`, with the built-in aliases of those types
+    pub fn new<'a>(case_types: impl IntoIterator<Item = &'a String>) -> Self {
+        let mut designations: Vec<CaseDesignation> = Vec::new();
+        for case_type in case_types {
+            let case_type = case_type.trim();
+            if case_type.is_empty()
+                || designations
+                    .iter()
+                    .any(|known| match_key(&known.designation) == match_key(case_type))
+            {
+                continue;
+            }
+            designations.push(CaseDesignation {
+                designation: case_type.to_string(),
+                aliases: ALIASES
+                    .iter()
+                    .filter(|(_, canonical)| match_key(canonical) == match_key(case_type))
+                    .map(|(alias, _)| alias.to_string())
+                    .collect(),
+            });
+        }
+        Self { designations }
+    }
+
+    /// The canonical form of `value`, if it names a supported case type
+    pub fn normalize(&self, value: &str) -> Option<&str> {
+        let key = match_key(value);
+        self.designations
+            .iter()
+            .find(|known| {
+                match_key(&known.designation) == key
+                    || known.aliases.iter().any(|alias| match_key(alias) == key)
+            })
+            .map(|known| known.designation.as_str())
+    }
+
+    /// Every supported case type, in configured order
+    pub fn designations(&self) -> &[CaseDesignation] {
+        &self.designations
+    }
+}
+
+/// `value` with whitespace removed and lowercased, so "9 MM" matches "9mm"
+fn match_key(value: &str) -> String {
+    value
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Settings;
+
+    #[test]
+    fn test_normalisation_matrix() {
+        let designations = CaseDesignations::new(&Settings::default().supported_case_types);
+        for (value, expected) in [
+            ("9mm", Some("9mm")),
+            ("9MM", Some("9mm")),
+            (" 9mm ", Some("9mm")),
+            ("9 mm", Some("9mm")),
+            ("9x19", Some("9mm")),
+            ("9X19", Some("9mm")),
+            ("9mm Luger", Some("9mm")),
+            (".40 S&W", Some("40sw")),
+            ("30-06", Some("3006spr")),
+            ("10mm", None),
+            ("", None),
+        ] {
+            assert_eq!(designations.normalize(value), expected, "{value:?}");
+        }
+    }
+
+    #[test]
+    fn test_aliases_follow_the_configured_types() {
+        let case_types = vec!["9mm".to_string(), " 9MM ".to_string(), "10mm".to_string()];
+        let designations = CaseDesignations::new(&case_types);
+        assert_eq!(
+            designations.designations(),
+            &[
+                CaseDesignation {
+                    designation: "9mm".to_string(),
+                    aliases: vec![
+                        "9x19".to_string(),
+                        "9x19mm".to_string(),
+                        "9mm luger".to_string(),
+                        "9mm parabellum".to_string(),
+                    ],
+                },
+                CaseDesignation {
+                    designation: "10mm".to_string(),
+                    aliases: Vec::new(),
+                },
+            ]
+        );
+        // Aliases of types that aren't configured don't resolve
+        assert_eq!(designations.normalize("30-06"), None);
+    }
+}
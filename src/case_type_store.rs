@@ -0,0 +1,415 @@
+//! Where the ML trainer keeps case type records, their images and trained models.
+//!
+//! [`crate::ml_training::MLTrainer`] does its file handling through a
+//! [`CaseTypeStore`], so the rules about which case types are ready, what a
+//! summary says and what gets trained can be tested without a data directory.
+//! [`FsCaseTypeStore`] is what the trainer has always done on disk. Directory
+//! and file operations return [`io::Result`] so the trainer can say which of
+//! its steps failed; loading and saving the records report their own errors.
+
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::ml_training::CaseType;
+use crate::shell_data::{SkippedFile, json_files_in};
+use crate::{OurError, OurResult};
+
+/// Storage for the ML trainer, see the module docs
+pub trait CaseTypeStore: Send + Sync {
+    /// The saved case type records, `None` when none have been saved yet
+    fn load_case_types(&self) -> OurResult<Option<HashMap<String, CaseType>>>;
+
+    /// Replace the saved case type records
+    fn save_case_types(&self, case_types: &HashMap<String, CaseType>) -> OurResult<()>;
+
+    /// Create a directory and any missing parents
+    fn create_dir(&self, dir: &Path) -> io::Result<()>;
+
+    /// Remove a directory and everything in it
+    fn remove_dir(&self, dir: &Path) -> io::Result<()>;
+
+    fn exists(&self, path: &Path) -> bool;
+
+    fn is_dir(&self, path: &Path) -> bool;
+
+    fn is_file(&self, path: &Path) -> bool;
+
+    /// Names of the directories directly inside `dir`
+    fn list_dirs(&self, dir: &Path) -> io::Result<Vec<String>>;
+
+    /// Files directly inside `dir`
+    fn list_files(&self, dir: &Path) -> io::Result<Vec<PathBuf>>;
+
+    fn read_file(&self, path: &Path) -> io::Result<Vec<u8>>;
+
+    /// Write an image into a case type directory
+    fn copy_image(&self, target: &Path, data: &[u8]) -> io::Result<()>;
+
+    /// Move an upload spooled beside a case type directory into it
+    fn move_image(&self, upload: &Path, target: &Path) -> io::Result<()>;
+
+    fn write_model_file(&self, path: &Path, contents: &[u8]) -> io::Result<()>;
+
+    /// The `.json` files in the models directory, by file stem
+    fn list_model_files(
+        &self,
+        dir: &Path,
+        skipped: &mut Vec<SkippedFile>,
+    ) -> OurResult<Vec<(String, PathBuf)>>;
+
+    fn remove_model_file(&self, path: &Path) -> io::Result<()>;
+}
+
+/// Keeps everything on disk, with the records in `case_types.json` in the data directory
+#[derive(Debug, Clone)]
+pub struct FsCaseTypeStore {
+    case_types_file: PathBuf,
+}
+
+impl FsCaseTypeStore {
+    pub fn new(data_directory: &Path) -> Self {
+        Self {
+            case_types_file: data_directory.join("case_types.json"),
+        }
+    }
+}
+
+impl CaseTypeStore for FsCaseTypeStore {
+    fn load_case_types(&self) -> OurResult<Option<HashMap<String, CaseType>>> {
+        if !self.case_types_file.exists() {
+            return Ok(None);
+        }
+
+        let json_data = fs::read_to_string(&self.case_types_file).map_err(|e| {
+            OurError::App(format!(
+                "Failed to read case types file: {} {e}",
+                self.case_types_file.display()
+            ))
+        })?;
+
+        serde_json::from_str(&json_data).map(Some).map_err(|e| {
+            OurError::App(format!(
+                "Failed to parse case types file: {} {e}",
+                self.case_types_file.display()
+            ))
+        })
+    }
+
+    fn save_case_types(&self, case_types: &HashMap<String, CaseType>) -> OurResult<()> {
+        let json_data = serde_json::to_string_pretty(case_types)
+            .map_err(|e| OurError::App(format!("Failed to serialize case types: {e}")))?;
+
+        if let Some(parent) = self.case_types_file.parent() {
+            fs::create_dir_all(parent)
+                .map_err(|e| OurError::App(format!("Failed to create data directory: {e}")))?;
+        }
+        fs::write(&self.case_types_file, json_data)
+            .map_err(|e| OurError::App(format!("Failed to write case types file: {e}")))
+    }
+
+    fn create_dir(&self, dir: &Path) -> io::Result<()> {
+        fs::create_dir_all(dir)
+    }
+
+    fn remove_dir(&self, dir: &Path) -> io::Result<()> {
+        fs::remove_dir_all(dir)
+    }
+
+    fn exists(&self, path: &Path) -> bool {
+        path.exists()
+    }
+
+    fn is_dir(&self, path: &Path) -> bool {
+        path.is_dir()
+    }
+
+    fn is_file(&self, path: &Path) -> bool {
+        path.is_file()
+    }
+
+    fn list_dirs(&self, dir: &Path) -> io::Result<Vec<String>> {
+        let mut names = Vec::new();
+        for entry in fs::read_dir(dir)? {
+            let entry = entry?;
+            if entry.path().is_dir() {
+                names.push(entry.file_name().to_string_lossy().to_string());
+            }
+        }
+        Ok(names)
+    }
+
+    fn list_files(&self, dir: &Path) -> io::Result<Vec<PathBuf>> {
+        let mut files = Vec::new();
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path.is_file() {
+                files.push(path);
+            }
+        }
+        Ok(files)
+    }
+
+    fn read_file(&self, path: &Path) -> io::Result<Vec<u8>> {
+        fs::read(path)
+    }
+
+    fn copy_image(&self, target: &Path, data: &[u8]) -> io::Result<()> {
+        fs::write(target, data)
+    }
+
+    fn move_image(&self, upload: &Path, target: &Path) -> io::Result<()> {
+        fs::rename(upload, target)
+    }
+
+    fn write_model_file(&self, path: &Path, contents: &[u8]) -> io::Result<()> {
+        fs::write(path, contents)
+    }
+
+    fn list_model_files(
+        &self,
+        dir: &Path,
+        skipped: &mut Vec<SkippedFile>,
+    ) -> OurResult<Vec<(String, PathBuf)>> {
+        if !dir.exists() {
+            return Ok(Vec::new());
+        }
+        json_files_in(dir, skipped)
+    }
+
+    fn remove_model_file(&self, path: &Path) -> io::Result<()> {
+        fs::remove_file(path)
+    }
+}
+
+#[cfg(test)]
+pub(crate) use memory::MemoryCaseTypeStore;
+
+#[cfg(test)]
+mod memory {
+    use std::collections::{BTreeMap, BTreeSet, HashMap};
+    use std::io;
+    use std::path::{Path, PathBuf};
+    use std::sync::Mutex;
+
+    use super::CaseTypeStore;
+    use crate::ml_training::CaseType;
+    use crate::shell_data::SkippedFile;
+    use crate::{OurError, OurResult};
+
+    /// Keeps everything in memory, and fails the operations it is told to
+    #[derive(Debug, Default)]
+    pub(crate) struct MemoryCaseTypeStore {
+        state: Mutex<MemoryState>,
+    }
+
+    #[derive(Debug, Default)]
+    struct MemoryState {
+        case_types: Option<HashMap<String, CaseType>>,
+        dirs: BTreeSet<PathBuf>,
+        files: BTreeMap<PathBuf, Vec<u8>>,
+        /// Calls of each failing operation still to succeed
+        failing: BTreeMap<&'static str, usize>,
+    }
+
+    impl MemoryState {
+        fn check(&mut self, operation: &'static str) -> io::Result<()> {
+            match self.failing.get_mut(operation) {
+                Some(0) => Err(io::Error::other(format!("{operation} failed"))),
+                Some(successes) => {
+                    *successes -= 1;
+                    Ok(())
+                }
+                None => Ok(()),
+            }
+        }
+
+        fn write(&mut self, path: &Path, data: Vec<u8>) -> io::Result<()> {
+            match path.parent() {
+                Some(parent) if !self.dirs.contains(parent) => Err(not_found(parent)),
+                _ => {
+                    self.files.insert(path.to_path_buf(), data);
+                    Ok(())
+                }
+            }
+        }
+    }
+
+    fn not_found(path: &Path) -> io::Error {
+        io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("{} does not exist", path.display()),
+        )
+    }
+
+    impl MemoryCaseTypeStore {
+        fn state(&self) -> std::sync::MutexGuard<'_, MemoryState> {
+            self.state.lock().expect("lock should not be poisoned")
+        }
+
+        /// Make every later call of the trait method named `operation` fail
+        pub(crate) fn fail(&self, operation: &'static str) {
+            self.fail_after(operation, 0);
+        }
+
+        /// Let `successes` more calls of `operation` through, then fail the rest
+        pub(crate) fn fail_after(&self, operation: &'static str, successes: usize) {
+            self.state().failing.insert(operation, successes);
+        }
+
+        /// Put a file in place, creating its directory
+        pub(crate) fn insert_file(&self, path: &Path, data: &[u8]) {
+            let mut state = self.state();
+            if let Some(parent) = path.parent() {
+                state.dirs.extend(parent.ancestors().map(Path::to_path_buf));
+            }
+            state.files.insert(path.to_path_buf(), data.to_vec());
+        }
+
+        /// Every file held, sorted
+        pub(crate) fn files(&self) -> Vec<PathBuf> {
+            self.state().files.keys().cloned().collect()
+        }
+
+        /// The records as last saved
+        pub(crate) fn saved_case_types(&self) -> Option<HashMap<String, CaseType>> {
+            self.state().case_types.clone()
+        }
+    }
+
+    impl CaseTypeStore for MemoryCaseTypeStore {
+        fn load_case_types(&self) -> OurResult<Option<HashMap<String, CaseType>>> {
+            let mut state = self.state();
+            state
+                .check("load_case_types")
+                .map_err(|e| OurError::App(format!("Failed to read case types: {e}")))?;
+            Ok(state.case_types.clone())
+        }
+
+        fn save_case_types(&self, case_types: &HashMap<String, CaseType>) -> OurResult<()> {
+            let mut state = self.state();
+            state
+                .check("save_case_types")
+                .map_err(|e| OurError::App(format!("Failed to write case types: {e}")))?;
+            state.case_types = Some(case_types.clone());
+            Ok(())
+        }
+
+        fn create_dir(&self, dir: &Path) -> io::Result<()> {
+            let mut state = self.state();
+            state.check("create_dir")?;
+            state.dirs.extend(dir.ancestors().map(Path::to_path_buf));
+            Ok(())
+        }
+
+        fn remove_dir(&self, dir: &Path) -> io::Result<()> {
+            let mut state = self.state();
+            state.check("remove_dir")?;
+            if !state.dirs.contains(dir) {
+                return Err(not_found(dir));
+            }
+            state.dirs.retain(|path| !path.starts_with(dir));
+            state.files.retain(|path, _| !path.starts_with(dir));
+            Ok(())
+        }
+
+        fn exists(&self, path: &Path) -> bool {
+            self.is_dir(path) || self.is_file(path)
+        }
+
+        fn is_dir(&self, path: &Path) -> bool {
+            self.state().dirs.contains(path)
+        }
+
+        fn is_file(&self, path: &Path) -> bool {
+            self.state().files.contains_key(path)
+        }
+
+        fn list_dirs(&self, dir: &Path) -> io::Result<Vec<String>> {
+            let mut state = self.state();
+            state.check("list_dirs")?;
+            Ok(state
+                .dirs
+                .iter()
+                .filter(|path| path.parent() == Some(dir))
+                .filter_map(|path| path.file_name())
+                .map(|name| name.to_string_lossy().to_string())
+                .collect())
+        }
+
+        fn list_files(&self, dir: &Path) -> io::Result<Vec<PathBuf>> {
+            let mut state = self.state();
+            state.check("list_files")?;
+            Ok(state
+                .files
+                .keys()
+                .filter(|path| path.parent() == Some(dir))
+                .cloned()
+                .collect())
+        }
+
+        fn read_file(&self, path: &Path) -> io::Result<Vec<u8>> {
+            let mut state = self.state();
+            state.check("read_file")?;
+            state
+                .files
+                .get(path)
+                .cloned()
+                .ok_or_else(|| not_found(path))
+        }
+
+        fn copy_image(&self, target: &Path, data: &[u8]) -> io::Result<()> {
+            let mut state = self.state();
+            state.check("copy_image")?;
+            state.write(target, data.to_vec())
+        }
+
+        fn move_image(&self, upload: &Path, target: &Path) -> io::Result<()> {
+            let mut state = self.state();
+            state.check("move_image")?;
+            let data = state
+                .files
+                .remove(upload)
+                .ok_or_else(|| not_found(upload))?;
+            state.write(target, data)
+        }
+
+        fn write_model_file(&self, path: &Path, contents: &[u8]) -> io::Result<()> {
+            let mut state = self.state();
+            state.check("write_model_file")?;
+            state.write(path, contents.to_vec())
+        }
+
+        fn list_model_files(
+            &self,
+            dir: &Path,
+            _skipped: &mut Vec<SkippedFile>,
+        ) -> OurResult<Vec<(String, PathBuf)>> {
+            let mut state = self.state();
+            state
+                .check("list_model_files")
+                .map_err(|e| OurError::App(format!("Failed to read {}: {e}", dir.display())))?;
+            Ok(state
+                .files
+                .keys()
+                .filter(|path| path.parent() == Some(dir))
+                .filter_map(|path| {
+                    let name = path.file_name()?.to_str()?;
+                    let stem = name.strip_suffix(".json")?;
+                    (!name.starts_with('.')).then(|| (stem.to_string(), path.clone()))
+                })
+                .collect())
+        }
+
+        fn remove_model_file(&self, path: &Path) -> io::Result<()> {
+            let mut state = self.state();
+            state.check("remove_model_file")?;
+            state
+                .files
+                .remove(path)
+                .map(drop)
+                .ok_or_else(|| not_found(path))
+        }
+    }
+}
@@ -0,0 +1,214 @@
+//! Plain text tables for the command line listings.
+
+use chrono::{DateTime, Utc};
+
+/// Render rows under a header line, padding each column to its widest cell
+pub fn render_table(headers: &[&str], rows: &[Vec<String>]) -> String {
+    let mut widths: Vec<usize> = headers.iter().map(|h| h.chars().count()).collect();
+    for row in rows {
+        for (index, cell) in row.iter().enumerate() {
+            let width = cell.chars().count();
+            match widths.get_mut(index) {
+                Some(current) => *current = (*current).max(width),
+                None => widths.push(width),
+            }
+        }
+    }
+
+    let render_line = |cells: &mut dyn Iterator<Item = &str>| {
+        let line = cells
+            .zip(&widths)
+            .map(|(cell, width)| format!("{cell:<width$}"))
+            .collect::<Vec<_>>()
+            .join("  ");
+        line.trim_end().to_string()
+    };
+
+    let mut lines = vec![render_line(&mut headers.iter().copied())];
+    for row in rows {
+        lines.push(render_line(&mut row.iter().map(String::as_str)));
+    }
+    lines.join("\n")
+}
+
+/// Narrowest a column is cut to when fitting a table to the terminal
+const MIN_COLUMN_WIDTH: usize = 4;
+
+/// Render a table no wider than `max_width`, cutting columns down in
+/// `shrink_order` until it fits. Cut cells end with `…`.
+pub fn render_table_within(
+    headers: &[&str],
+    rows: &[Vec<String>],
+    max_width: usize,
+    shrink_order: &[usize],
+) -> String {
+    let mut widths: Vec<usize> = headers.iter().map(|h| h.chars().count()).collect();
+    for row in rows {
+        for (index, cell) in row.iter().enumerate() {
+            if let Some(width) = widths.get_mut(index) {
+                *width = (*width).max(cell.chars().count());
+            }
+        }
+    }
+
+    let gaps = 2 * widths.len().saturating_sub(1);
+    let mut excess = (widths.iter().sum::<usize>() + gaps).saturating_sub(max_width);
+    for &column in shrink_order {
+        let Some(width) = widths.get_mut(column) else {
+            continue;
+        };
+        let cut = excess.min(width.saturating_sub(MIN_COLUMN_WIDTH));
+        *width -= cut;
+        excess -= cut;
+    }
+
+    let fit = |index: usize, cell: &str| truncate(cell, widths.get(index).copied());
+    let headers: Vec<String> = headers
+        .iter()
+        .enumerate()
+        .map(|(index, header)| fit(index, header))
+        .collect();
+    let rows: Vec<Vec<String>> = rows
+        .iter()
+        .map(|row| {
+            row.iter()
+                .enumerate()
+                .map(|(index, cell)| fit(index, cell))
+                .collect()
+        })
+        .collect();
+    let headers: Vec<&str> = headers.iter().map(String::as_str).collect();
+    render_table(&headers, &rows)
+}
+
+fn truncate(cell: &str, width: Option<usize>) -> String {
+    match width {
+        Some(width) if cell.chars().count() > width => {
+            let mut cut: String = cell.chars().take(width.saturating_sub(1)).collect();
+            cut.push('…');
+            cut
+        }
+        _ => cell.to_string(),
+    }
+}
+
+/// Width of the terminal, from `COLUMNS` or the terminal on stdout
+pub fn terminal_width() -> Option<usize> {
+    if let Some(columns) = std::env::var("COLUMNS")
+        .ok()
+        .and_then(|columns| columns.parse().ok())
+    {
+        return Some(columns);
+    }
+    #[cfg(unix)]
+    {
+        rustix::termios::tcgetwinsize(std::io::stdout())
+            .ok()
+            .map(|size| usize::from(size.ws_col))
+            .filter(|width| *width > 0)
+    }
+    #[cfg(not(unix))]
+    {
+        None
+    }
+}
+
+/// How long ago something happened, e.g. `5m ago` or `2d ago`
+pub fn format_age(then: DateTime<Utc>, now: DateTime<Utc>) -> String {
+    let seconds = (now - then).num_seconds();
+    match seconds {
+        ..60 => "just now".to_string(),
+        60..3600 => format!("{}m ago", seconds / 60),
+        3600..86400 => format!("{}h ago", seconds / 3600),
+        _ => format!("{}d ago", seconds / 86400),
+    }
+}
+
+/// A byte count in the largest binary unit that keeps it at least 1, e.g. `1.5 MiB`
+pub fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["KiB", "MiB", "GiB", "TiB", "PiB"];
+    if bytes < 1024 {
+        return format!("{bytes} B");
+    }
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit + 1 < UNITS.len() {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{size:.1} {}", UNITS[unit])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_table() {
+        let table = render_table(
+            &["NAME", "COUNT"],
+            &[
+                vec!["Winchester_9mm".to_string(), "12".to_string()],
+                vec!["Fed".to_string(), "3".to_string()],
+            ],
+        );
+        assert_eq!(
+            table,
+            "NAME            COUNT\nWinchester_9mm  12\nFed             3"
+        );
+    }
+
+    #[test]
+    fn test_render_table_within_shrinks_in_order() {
+        let headers = ["SESSION", "BRAND", "TYPE"];
+        let rows = vec![vec![
+            "3f2a9c1e".to_string(),
+            "Winchester".to_string(),
+            "9mm".to_string(),
+        ]];
+
+        // Wide enough: unchanged
+        assert_eq!(
+            render_table_within(&headers, &rows, 80, &[0, 1]),
+            render_table(&headers, &rows)
+        );
+
+        // 26 columns wide; the session column gives up 2 first
+        assert_eq!(
+            render_table_within(&headers, &rows, 24, &[0, 1]),
+            "SESSI…  BRAND       TYPE\n3f2a9…  Winchester  9mm"
+        );
+
+        // Once the session column is at its minimum the brand column is cut
+        assert_eq!(
+            render_table_within(&headers, &rows, 18, &[0, 1]),
+            "SES…  BRAND   TYPE\n3f2…  Winch…  9mm"
+        );
+
+        // Too narrow to fit at all, everything shrinkable is at its minimum
+        assert_eq!(
+            render_table_within(&headers, &rows, 5, &[0, 1]),
+            "SES…  BRA…  TYPE\n3f2…  Win…  9mm"
+        );
+    }
+
+    #[test]
+    fn test_format_age() {
+        let now = Utc::now();
+        let ago = |seconds| format_age(now - chrono::Duration::seconds(seconds), now);
+        assert_eq!(ago(-30), "just now");
+        assert_eq!(ago(59), "just now");
+        assert_eq!(ago(60 * 5), "5m ago");
+        assert_eq!(ago(3600 * 3 + 59), "3h ago");
+        assert_eq!(ago(86400 * 2), "2d ago");
+    }
+
+    #[test]
+    fn test_format_bytes() {
+        assert_eq!(format_bytes(0), "0 B");
+        assert_eq!(format_bytes(1023), "1023 B");
+        assert_eq!(format_bytes(1024), "1.0 KiB");
+        assert_eq!(format_bytes(1536 * 1024), "1.5 MiB");
+        assert_eq!(format_bytes(5 * 1024 * 1024 * 1024), "5.0 GiB");
+    }
+}
@@ -0,0 +1,430 @@
+//! HTTP client the CLI uses to talk to a running server.
+//!
+//! Read-only requests made through [`ApiClient::get_cached`] remember their
+//! last successful response in a small JSON cache next to the user config.
+//! When the server can't be reached, the cached response is returned instead,
+//! marked with its age, so `shell-sorter camera list` still shows something
+//! useful while the server box is off. `--no-cache` skips the cache entirely
+//! and `--cached-only` never contacts the server.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+use tracing::{debug, warn};
+
+use crate::config::Settings;
+use crate::shell_data::write_atomic;
+use crate::{OurError, OurResult};
+
+/// File the CLI keeps cached responses in, next to the user config
+pub const CACHE_FILENAME: &str = "shell-sorter-cache.json";
+
+/// Cached responses older than this are thrown away unless configured otherwise
+pub const DEFAULT_CACHE_MAX_AGE: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// How the CLI uses its response cache
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CacheMode {
+    /// Ask the server, falling back to the cache when it's unreachable
+    #[default]
+    Fallback,
+    /// Never read or write the cache
+    Disabled,
+    /// Answer from the cache without contacting the server
+    CachedOnly,
+}
+
+/// Why a response came from the cache
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CacheReason {
+    /// The server couldn't be reached
+    Unreachable,
+    /// `--cached-only` was given
+    CachedOnly,
+}
+
+/// Where a response came from
+#[derive(Debug, Clone, PartialEq)]
+pub enum ResponseSource {
+    /// Straight from the server
+    Live,
+    /// From the cache, saved at `fetched_at`
+    Cache {
+        fetched_at: DateTime<Utc>,
+        reason: CacheReason,
+    },
+}
+
+/// A JSON response from the server or the cache
+#[derive(Debug, Clone)]
+pub struct Fetched {
+    pub body: serde_json::Value,
+    pub source: ResponseSource,
+}
+
+impl Fetched {
+    /// The line telling the user they're looking at cached data, if they are
+    pub fn cache_notice(&self, now: DateTime<Utc>) -> Option<String> {
+        let ResponseSource::Cache { fetched_at, reason } = &self.source else {
+            return None;
+        };
+        let minutes = (now - *fetched_at).num_minutes().max(0);
+        let age = if minutes == 1 {
+            "1 minute old".to_string()
+        } else {
+            format!("{minutes} minutes old")
+        };
+        Some(match reason {
+            CacheReason::Unreachable => format!("(cached, {age}, server unreachable)"),
+            CacheReason::CachedOnly => format!("(cached, {age}, server not contacted)"),
+        })
+    }
+}
+
+/// A cached response and when it was fetched
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+struct CacheEntry {
+    fetched_at: DateTime<Utc>,
+    body: serde_json::Value,
+}
+
+/// Responses by request URL, kept in one JSON file
+#[derive(Debug, Clone)]
+pub struct ResponseCache {
+    path: PathBuf,
+    max_age: Duration,
+}
+
+impl ResponseCache {
+    pub fn new(path: PathBuf, max_age: Duration) -> Self {
+        Self { path, max_age }
+    }
+
+    /// The cache next to the user config, expiring after `cli_cache_max_age_seconds`
+    pub fn from_settings(settings: &Settings) -> Self {
+        Self::new(
+            settings
+                .sources
+                .user_config_path
+                .with_file_name(CACHE_FILENAME),
+            Duration::from_secs(settings.cli_cache_max_age_seconds),
+        )
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Unexpired entries; a missing or unreadable file is an empty cache
+    fn load(&self, now: DateTime<Utc>) -> BTreeMap<String, CacheEntry> {
+        let Ok(contents) = std::fs::read_to_string(&self.path) else {
+            return BTreeMap::new();
+        };
+        let mut entries: BTreeMap<String, CacheEntry> = match serde_json::from_str(&contents) {
+            Ok(entries) => entries,
+            Err(e) => {
+                warn!("Ignoring unreadable CLI cache {}: {e}", self.path.display());
+                return BTreeMap::new();
+            }
+        };
+        entries.retain(|_, entry| !self.expired(entry, now));
+        entries
+    }
+
+    fn expired(&self, entry: &CacheEntry, now: DateTime<Utc>) -> bool {
+        (now - entry.fetched_at)
+            .to_std()
+            .is_ok_and(|age| age > self.max_age)
+    }
+
+    /// The cached response for `key`, unless it has expired
+    pub fn get(&self, key: &str, now: DateTime<Utc>) -> Option<(serde_json::Value, DateTime<Utc>)> {
+        self.load(now)
+            .remove(key)
+            .map(|entry| (entry.body, entry.fetched_at))
+    }
+
+    /// Remember `body` for `key`, dropping expired entries on the way
+    pub fn store(&self, key: &str, body: &serde_json::Value, now: DateTime<Utc>) -> OurResult<()> {
+        let mut entries = self.load(now);
+        entries.insert(
+            key.to_string(),
+            CacheEntry {
+                fetched_at: now,
+                body: body.clone(),
+            },
+        );
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        write_atomic(
+            &self.path,
+            serde_json::to_string_pretty(&entries)?.as_bytes(),
+        )?;
+        Ok(())
+    }
+}
+
+/// Client for the server's JSON API
+#[derive(Debug, Clone)]
+pub struct ApiClient {
+    base_url: String,
+    http: reqwest::Client,
+    cache: ResponseCache,
+    mode: CacheMode,
+}
+
+impl ApiClient {
+    pub fn new(base_url: String, cache: ResponseCache, mode: CacheMode) -> Self {
+        Self {
+            base_url,
+            http: reqwest::Client::new(),
+            cache,
+            mode,
+        }
+    }
+
+    /// A client for the configured server
+    pub fn from_settings(settings: &Settings, mode: CacheMode) -> OurResult<Self> {
+        // Paths are appended with their leading slash
+        let base_url = settings
+            .base_url()?
+            .as_str()
+            .trim_end_matches('/')
+            .to_string();
+        Ok(Self::new(
+            base_url,
+            ResponseCache::from_settings(settings),
+            mode,
+        ))
+    }
+
+    pub fn base_url(&self) -> &str {
+        &self.base_url
+    }
+
+    fn url(&self, path: &str, query: &[(&str, String)]) -> OurResult<Url> {
+        let mut url = Url::parse(&format!("{}{path}", self.base_url))?;
+        if !query.is_empty() {
+            url.query_pairs_mut().extend_pairs(query);
+        }
+        Ok(url)
+    }
+
+    /// Error for a request that couldn't reach the server, with a hint to start it
+    pub fn unreachable(&self, e: reqwest::Error) -> OurError {
+        OurError::App(format!(
+            "Failed to connect to server at {}: {e}\nMake sure the server is running with: shell-sorter serve",
+            self.base_url
+        ))
+    }
+
+    /// Send a request built from this client, mapping connection failures to [`Self::unreachable`]
+    pub async fn send(&self, request: reqwest::RequestBuilder) -> OurResult<reqwest::Response> {
+        request.send().await.map_err(|e| self.unreachable(e))
+    }
+
+    /// A GET request for `path`
+    pub fn get(&self, path: &str) -> reqwest::RequestBuilder {
+        self.http.get(format!("{}{path}", self.base_url))
+    }
+
+    /// A POST request for `path`
+    pub fn post(&self, path: &str) -> reqwest::RequestBuilder {
+        self.http.post(format!("{}{path}", self.base_url))
+    }
+
+    /// A PATCH request for `path`
+    pub fn patch(&self, path: &str) -> reqwest::RequestBuilder {
+        self.http.patch(format!("{}{path}", self.base_url))
+    }
+
+    /// GET `path` as JSON, answering from the cache when the server is unreachable
+    pub async fn get_cached(&self, path: &str, query: &[(&str, String)]) -> OurResult<Fetched> {
+        let url = self.url(path, query)?;
+        let key = url.to_string();
+        let now = Utc::now();
+
+        if self.mode == CacheMode::CachedOnly {
+            let (body, fetched_at) = self.cache.get(&key, now).ok_or_else(|| {
+                OurError::App(format!("No cached response for {key} (--cached-only)"))
+            })?;
+            return Ok(Fetched {
+                body,
+                source: ResponseSource::Cache {
+                    fetched_at,
+                    reason: CacheReason::CachedOnly,
+                },
+            });
+        }
+
+        let response = match self.http.get(url).send().await {
+            Ok(response) => response,
+            Err(e) => {
+                let cached = match self.mode {
+                    CacheMode::Fallback => self.cache.get(&key, now),
+                    CacheMode::Disabled | CacheMode::CachedOnly => None,
+                };
+                let Some((body, fetched_at)) = cached else {
+                    return Err(self.unreachable(e));
+                };
+                debug!("Server unreachable, using cached response for {key}: {e}");
+                return Ok(Fetched {
+                    body,
+                    source: ResponseSource::Cache {
+                        fetched_at,
+                        reason: CacheReason::Unreachable,
+                    },
+                });
+            }
+        };
+
+        let body: serde_json::Value = response.json().await?;
+        // Only successful answers are worth showing again later
+        if self.mode == CacheMode::Fallback
+            && body["success"].as_bool() == Some(true)
+            && let Err(e) = self.cache.store(&key, &body, now)
+        {
+            warn!(
+                "Failed to update CLI cache {}: {e}",
+                self.cache.path().display()
+            );
+        }
+        Ok(Fetched {
+            body,
+            source: ResponseSource::Live,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{Json, Router, routing::get};
+
+    /// A base URL nothing is listening on
+    async fn closed_port() -> String {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("bind should work");
+        let address = listener.local_addr().expect("listener has an address");
+        drop(listener);
+        format!("http://{address}")
+    }
+
+    fn cache_in(dir: &tempfile::TempDir) -> ResponseCache {
+        ResponseCache::new(dir.path().join(CACHE_FILENAME), DEFAULT_CACHE_MAX_AGE)
+    }
+
+    #[tokio::test]
+    async fn test_unreachable_server_falls_back_to_cache() {
+        let dir = tempfile::tempdir().expect("tempdir");
+        let cameras = serde_json::json!({"success": true, "data": [{"id": "esphome_camera1"}]});
+
+        // Serve one answer, then take the server away
+        let app = Router::new().route(
+            "/api/cameras",
+            get({
+                let cameras = cameras.clone();
+                move || async move { Json(cameras) }
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("bind should work");
+        let base_url = format!("http://{}", listener.local_addr().expect("address"));
+        let server = tokio::spawn(async move { axum::serve(listener, app).await });
+
+        let client = ApiClient::new(base_url, cache_in(&dir), CacheMode::Fallback);
+        let live = client
+            .get_cached("/api/cameras", &[])
+            .await
+            .expect("live request should work");
+        assert_eq!(live.source, ResponseSource::Live);
+        assert_eq!(live.cache_notice(Utc::now()), None);
+
+        server.abort();
+        let _ = server.await;
+
+        let cached = client
+            .get_cached("/api/cameras", &[])
+            .await
+            .expect("cache should answer");
+        assert_eq!(cached.body, cameras);
+        let ResponseSource::Cache { fetched_at, reason } = cached.source.clone() else {
+            panic!("expected a cached response, got {:?}", cached.source);
+        };
+        assert_eq!(reason, CacheReason::Unreachable);
+        let notice = cached
+            .cache_notice(fetched_at + chrono::Duration::minutes(12))
+            .expect("cached responses carry a notice");
+        assert_eq!(notice, "(cached, 12 minutes old, server unreachable)");
+
+        // Other requests were never cached
+        assert!(client.get_cached("/api/status", &[]).await.is_err());
+        let no_cache = ApiClient::new(
+            client.base_url().to_string(),
+            cache_in(&dir),
+            CacheMode::Disabled,
+        );
+        assert!(no_cache.get_cached("/api/cameras", &[]).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_cached_only_and_expiry() {
+        let dir = tempfile::tempdir().expect("tempdir");
+        let base_url = closed_port().await;
+        let cache = cache_in(&dir);
+        let client = ApiClient::new(base_url.clone(), cache.clone(), CacheMode::CachedOnly);
+        let query = [("brand", "Federal".to_string())];
+        let key = client
+            .url("/api/shells/search", &query)
+            .expect("valid url")
+            .to_string();
+
+        let error = client
+            .get_cached("/api/shells/search", &query)
+            .await
+            .expect_err("nothing cached yet");
+        assert!(error.to_string().contains("--cached-only"), "{error}");
+
+        let body = serde_json::json!({"success": true, "data": []});
+        let long_ago = Utc::now() - chrono::Duration::days(2);
+        cache
+            .store(&key, &body, long_ago)
+            .expect("store should work");
+        assert!(
+            client
+                .get_cached("/api/shells/search", &query)
+                .await
+                .is_err(),
+            "expired entries are ignored"
+        );
+
+        let recently = Utc::now() - chrono::Duration::minutes(3);
+        cache
+            .store(&key, &body, recently)
+            .expect("store should work");
+        let cached = client
+            .get_cached("/api/shells/search", &query)
+            .await
+            .expect("fresh entry should answer");
+        assert_eq!(
+            cached.cache_notice(recently + chrono::Duration::minutes(3)),
+            Some("(cached, 3 minutes old, server not contacted)".to_string())
+        );
+
+        // The store went through a temporary file and left none behind
+        let files: Vec<_> = std::fs::read_dir(dir.path())
+            .expect("read dir")
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.file_name())
+            .collect();
+        assert_eq!(files, vec![std::ffi::OsString::from(CACHE_FILENAME)]);
+    }
+}
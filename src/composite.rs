@@ -0,0 +1,405 @@
+//! Composite image layouts.
+//!
+//! Every shell's composite is drawn onto the same canvas with one slot per
+//! view type, so a human or the model always sees the same geometry. Shells
+//! missing a view get a grey placeholder in that slot rather than a shifted
+//! layout. Each composite is written with a sidecar recording the hash of the
+//! layout it was drawn with, so a layout change can be detected and the
+//! composites regenerated.
+
+use ab_glyph::FontRef;
+use chrono::{DateTime, Utc};
+use image::{DynamicImage, Rgb, RgbImage, imageops};
+use imageproc::drawing::{draw_text_mut, text_size};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::config::ViewType;
+use crate::training_runs::content_hash;
+use crate::{OurError, OurResult};
+
+/// File the composite layout is stored in, inside the data directory
+pub const LAYOUT_FILENAME: &str = "composite_layout.json";
+
+/// Fill colour for slots with no image
+pub const PLACEHOLDER_GREY: [u8; 3] = [128, 128, 128];
+
+/// DejaVu Sans Mono for text drawn onto images, see `shell_sorter/fonts/LICENSE-DejaVu`
+pub const LABEL_FONT: &[u8] = include_bytes!("../shell_sorter/fonts/DejaVuSansMono.ttf");
+
+/// Height of the label text as a fraction of the label strip's height
+const LABEL_TEXT_SCALE: f32 = 0.6;
+
+/// A rectangle on the composite canvas
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct SlotRect {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// Band along the bottom of the canvas reserved for the shell's label
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct LabelStrip {
+    pub height: u32,
+    pub background: [u8; 3],
+}
+
+impl LabelStrip {
+    /// Black on a light background, white on a dark one
+    fn text_colour(&self) -> [u8; 3] {
+        let [r, g, b] = self.background.map(u32::from);
+        if r + g + b > 3 * 128 {
+            [0, 0, 0]
+        } else {
+            [255, 255, 255]
+        }
+    }
+}
+
+/// Canvas size, slot positions and colours used for every composite
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+pub struct CompositeLayout {
+    pub canvas_width: u32,
+    pub canvas_height: u32,
+    pub slots: BTreeMap<ViewType, SlotRect>,
+    pub background: [u8; 3],
+    #[serde(default)]
+    pub label_strip: Option<LabelStrip>,
+}
+
+impl Default for CompositeLayout {
+    /// Side view on the left and tail view on the right, matching the two-camera rig
+    fn default() -> Self {
+        let slot = |x| SlotRect {
+            x,
+            y: 0,
+            width: 800,
+            height: 600,
+        };
+        Self {
+            canvas_width: 1600,
+            canvas_height: 640,
+            slots: BTreeMap::from([(ViewType::Side, slot(0)), (ViewType::Tail, slot(800))]),
+            background: [0, 0, 0],
+            label_strip: Some(LabelStrip {
+                height: 40,
+                background: [255, 255, 255],
+            }),
+        }
+    }
+}
+
+impl CompositeLayout {
+    /// Check the canvas is non-empty and every slot and the label strip fit on it
+    pub fn validate(&self) -> OurResult<()> {
+        if self.canvas_width == 0 || self.canvas_height == 0 {
+            return Err(OurError::App(
+                "Composite canvas must have a non-zero size".to_string(),
+            ));
+        }
+        if self.slots.is_empty() {
+            return Err(OurError::App(
+                "Composite layout needs at least one slot".to_string(),
+            ));
+        }
+
+        let image_height = self.image_area_height()?;
+        for (view_type, slot) in &self.slots {
+            let fits = slot.width > 0
+                && slot.height > 0
+                && slot
+                    .x
+                    .checked_add(slot.width)
+                    .is_some_and(|right| right <= self.canvas_width)
+                && slot
+                    .y
+                    .checked_add(slot.height)
+                    .is_some_and(|bottom| bottom <= image_height);
+            if !fits {
+                return Err(OurError::App(format!(
+                    "Slot for {view_type} view does not fit on the {}x{image_height} image area",
+                    self.canvas_width
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// Height of the canvas above the label strip
+    fn image_area_height(&self) -> OurResult<u32> {
+        let strip_height = self.label_strip.map_or(0, |strip| strip.height);
+        self.canvas_height
+            .checked_sub(strip_height)
+            .ok_or_else(|| OurError::App("Label strip is taller than the canvas".to_string()))
+    }
+
+    /// Stable hash of the layout, stored with each composite to detect layout changes
+    pub fn hash(&self) -> OurResult<String> {
+        // Slots are a BTreeMap, so the JSON is canonical
+        Ok(content_hash(&serde_json::to_vec(self)?))
+    }
+
+    /// Draw the images onto the layout, scaling each to fit its slot, and the
+    /// label centred in the label strip
+    pub fn render(
+        &self,
+        images: &HashMap<ViewType, DynamicImage>,
+        label: &str,
+    ) -> OurResult<RgbImage> {
+        self.validate()?;
+
+        let mut canvas =
+            RgbImage::from_pixel(self.canvas_width, self.canvas_height, Rgb(self.background));
+
+        for (view_type, slot) in &self.slots {
+            match images.get(view_type) {
+                Some(image) => draw_fitted(&mut canvas, image, *slot),
+                None => fill(&mut canvas, *slot, PLACEHOLDER_GREY),
+            }
+        }
+
+        if let Some(strip) = self.label_strip {
+            let image_height = self.image_area_height()?;
+            fill(
+                &mut canvas,
+                SlotRect {
+                    x: 0,
+                    y: image_height,
+                    width: self.canvas_width,
+                    height: strip.height,
+                },
+                strip.background,
+            );
+
+            let font = FontRef::try_from_slice(LABEL_FONT)
+                .map_err(|e| OurError::App(format!("The label font couldn't be loaded: {e}")))?;
+            let text_height = strip.height as f32 * LABEL_TEXT_SCALE;
+            let (text_width, _) = text_size(text_height, &font, label);
+            let text_x = self.canvas_width.saturating_sub(text_width) / 2;
+            let text_y = image_height + strip.height.saturating_sub(text_height as u32) / 2;
+            draw_text_mut(
+                &mut canvas,
+                Rgb(strip.text_colour()),
+                text_x as i32,
+                text_y as i32,
+                text_height,
+                &font,
+                label,
+            );
+        }
+
+        Ok(canvas)
+    }
+}
+
+/// Scale the image to fit the slot, keeping its aspect ratio, and centre it there
+pub fn draw_fitted(canvas: &mut RgbImage, image: &DynamicImage, slot: SlotRect) {
+    let scaled = image
+        .resize(slot.width, slot.height, imageops::FilterType::Triangle)
+        .to_rgb8();
+    let x = slot.x + (slot.width - scaled.width()) / 2;
+    let y = slot.y + (slot.height - scaled.height()) / 2;
+    imageops::replace(canvas, &scaled, i64::from(x), i64::from(y));
+}
+
+/// Fill a rectangle of the canvas with one colour
+pub fn fill(canvas: &mut RgbImage, rect: SlotRect, colour: [u8; 3]) {
+    for y in rect.y..rect.y + rect.height {
+        for x in rect.x..rect.x + rect.width {
+            canvas.put_pixel(x, y, Rgb(colour));
+        }
+    }
+}
+
+/// Load the layout from the data directory, falling back to the default
+pub fn load_layout(data_directory: &Path) -> OurResult<CompositeLayout> {
+    let path = data_directory.join(LAYOUT_FILENAME);
+    if !path.exists() {
+        return Ok(CompositeLayout::default());
+    }
+    let contents = fs::read_to_string(&path)
+        .map_err(|e| OurError::App(format!("Failed to read {}: {e}", path.display())))?;
+    let layout: CompositeLayout = serde_json::from_str(&contents)?;
+    layout.validate()?;
+    Ok(layout)
+}
+
+/// Validate and save the layout to the data directory
+pub fn save_layout(data_directory: &Path, layout: &CompositeLayout) -> OurResult<()> {
+    layout.validate()?;
+    fs::create_dir_all(data_directory)
+        .map_err(|e| OurError::App(format!("Failed to create data directory: {e}")))?;
+    fs::write(
+        data_directory.join(LAYOUT_FILENAME),
+        serde_json::to_string_pretty(layout)?,
+    )
+    .map_err(|e| OurError::App(format!("Failed to save composite layout: {e}")))
+}
+
+/// Metadata written next to each composite image
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct CompositeMetadata {
+    /// Hash of the layout the composite was drawn with
+    pub layout_hash: String,
+    /// Brand and shell type drawn into the label strip
+    pub brand: String,
+    pub shell_type: String,
+    pub generated_at: DateTime<Utc>,
+}
+
+/// Where the composite for a session is written
+pub fn composite_path(data_directory: &Path, session_id: &str) -> PathBuf {
+    data_directory
+        .join("composites")
+        .join(format!("{session_id}_composite.jpg"))
+}
+
+/// Path of the sidecar metadata for a composite image
+pub fn metadata_path(composite_path: &Path) -> PathBuf {
+    composite_path.with_extension("json")
+}
+
+/// Whether a composite exists, was drawn with the layout with this hash, and
+/// postdates the last change to the shell's regions
+pub fn is_current(
+    composite_path: &Path,
+    layout_hash: &str,
+    regions_updated_at: Option<DateTime<Utc>>,
+) -> bool {
+    composite_path.exists()
+        && fs::read_to_string(metadata_path(composite_path))
+            .ok()
+            .and_then(|contents| serde_json::from_str::<CompositeMetadata>(&contents).ok())
+            .is_some_and(|metadata| {
+                metadata.layout_hash == layout_hash
+                    && regions_updated_at.is_none_or(|updated| metadata.generated_at >= updated)
+            })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn small_layout() -> CompositeLayout {
+        CompositeLayout {
+            canvas_width: 8,
+            canvas_height: 6,
+            slots: BTreeMap::from([
+                (
+                    ViewType::Side,
+                    SlotRect {
+                        x: 0,
+                        y: 0,
+                        width: 4,
+                        height: 4,
+                    },
+                ),
+                (
+                    ViewType::Tail,
+                    SlotRect {
+                        x: 4,
+                        y: 0,
+                        width: 4,
+                        height: 4,
+                    },
+                ),
+            ]),
+            background: [0, 0, 0],
+            label_strip: Some(LabelStrip {
+                height: 2,
+                background: [255, 255, 255],
+            }),
+        }
+    }
+
+    #[test]
+    fn test_slot_placement() {
+        let layout = small_layout();
+        let red = DynamicImage::ImageRgb8(RgbImage::from_pixel(4, 4, Rgb([255, 0, 0])));
+        let images = HashMap::from([(ViewType::Side, red)]);
+
+        let canvas = layout.render(&images, "").expect("layout should render");
+        assert_eq!(canvas.dimensions(), (8, 6));
+
+        // Side slot holds the image, the missing tail view is grey, the strip is at the bottom
+        assert_eq!(canvas.get_pixel(0, 0), &Rgb([255, 0, 0]));
+        assert_eq!(canvas.get_pixel(3, 3), &Rgb([255, 0, 0]));
+        assert_eq!(canvas.get_pixel(4, 0), &Rgb(PLACEHOLDER_GREY));
+        assert_eq!(canvas.get_pixel(7, 3), &Rgb(PLACEHOLDER_GREY));
+        assert_eq!(canvas.get_pixel(0, 4), &Rgb([255, 255, 255]));
+        assert_eq!(canvas.get_pixel(7, 5), &Rgb([255, 255, 255]));
+
+        // A wide image is centred vertically, leaving background above and below
+        let wide = DynamicImage::ImageRgb8(RgbImage::from_pixel(4, 2, Rgb([0, 0, 255])));
+        let canvas = layout
+            .render(&HashMap::from([(ViewType::Tail, wide)]), "")
+            .expect("layout should render");
+        assert_eq!(canvas.get_pixel(4, 0), &Rgb([0, 0, 0]));
+        assert_eq!(canvas.get_pixel(4, 1), &Rgb([0, 0, 255]));
+        assert_eq!(canvas.get_pixel(7, 2), &Rgb([0, 0, 255]));
+        assert_eq!(canvas.get_pixel(7, 3), &Rgb([0, 0, 0]));
+    }
+
+    #[test]
+    fn test_label_is_drawn_into_the_strip() {
+        let layout = CompositeLayout::default();
+        let strip_top = layout.canvas_height - 40;
+        let strip_pixels = |canvas: &RgbImage| {
+            (strip_top..layout.canvas_height)
+                .flat_map(|y| (0..layout.canvas_width).map(move |x| (x, y)))
+                .map(|(x, y)| *canvas.get_pixel(x, y))
+                .collect::<Vec<_>>()
+        };
+
+        let blank = layout
+            .render(&HashMap::new(), "")
+            .expect("layout should render");
+        assert!(
+            strip_pixels(&blank)
+                .iter()
+                .all(|pixel| *pixel == Rgb([255, 255, 255]))
+        );
+
+        let labelled = layout
+            .render(&HashMap::new(), "Winchester 12 gauge")
+            .expect("layout should render");
+        let pixels = strip_pixels(&labelled);
+        assert!(pixels.iter().any(|pixel| *pixel == Rgb([255, 255, 255])));
+        assert!(pixels.iter().any(|pixel| pixel.0.iter().all(|&c| c < 128)));
+    }
+
+    #[test]
+    fn test_layout_validation_and_hash() {
+        let layout = small_layout();
+        layout.validate().expect("layout should be valid");
+        CompositeLayout::default()
+            .validate()
+            .expect("default layout should be valid");
+
+        // Slots can't overlap the label strip
+        let mut overlapping = small_layout();
+        overlapping.label_strip = Some(LabelStrip {
+            height: 3,
+            background: [255, 255, 255],
+        });
+        assert!(overlapping.validate().is_err());
+
+        let mut moved = small_layout();
+        if let Some(slot) = moved.slots.get_mut(&ViewType::Tail) {
+            slot.x = 5;
+        }
+        assert!(moved.validate().is_err());
+
+        let hash = layout.hash().expect("layout should hash");
+        assert_eq!(hash, small_layout().hash().expect("layout should hash"));
+        assert_ne!(hash, moved.hash().expect("layout should hash"));
+    }
+}
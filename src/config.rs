@@ -4,23 +4,62 @@
 //! camera configurations, and user preferences. It uses Serde for serialization
 //! and supports environment variable overrides.
 
+use reqwest::Url;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::env;
 use std::fs;
-use std::path::PathBuf;
+use std::net::{IpAddr, Ipv4Addr};
+use std::path::{Path, PathBuf};
+
+use crate::camera::CameraId;
+use crate::camera_manager::{EspEntity, EspEntityDomain};
+use crate::camera_presets::CameraPreset;
+use crate::capture_overlay::OverlayCorner;
+use crate::data_directories::{self, DataDirectories};
+use crate::frame_region::{FrameRegion, FrameSize};
+use crate::log_buffer::LogLevel;
+use crate::measurements::MeasurementRange;
+use crate::orientation::{Orientation, Rotation};
+use crate::shell_data::ShellFlag;
+use crate::static_assets::StaticAssetSource;
+use crate::temperature::TemperatureSensor;
+use crate::{OurError, OurResult};
+
+/// File names searched for, in order, when looking for a project-local config
+pub const PROJECT_CONFIG_FILENAMES: [&str; 2] = ["shell-sorter.toml", "shell-sorter.json"];
+
+/// Where the loaded settings came from, for `config show`
+#[derive(Debug, Clone, Default)]
+pub struct ConfigSources {
+    /// Project-local config file, if one was found
+    pub project_file: Option<PathBuf>,
+    /// User config file path (read if it exists, and where changes are saved)
+    pub user_config_path: PathBuf,
+    /// Whether the user config file existed and was applied
+    pub user_config_loaded: bool,
+    /// Environment variables that overrode settings
+    pub env_overrides: Vec<String>,
+}
 
 /// Configuration settings for the Shell Sorter application.
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub struct Settings {
     /// Server host address
     pub host: String,
     /// Server port
     pub port: u16,
+    /// Clients reach the server over HTTPS, through a TLS proxy answering on `host` and `port`
+    pub server_tls: bool,
+    /// Server the CLI talks to, instead of one built from `host` and `port`
+    pub server_url: Option<Url>,
     /// Enable debug mode
     pub debug: bool,
     /// Machine identifier
     pub machine_name: String,
+    /// Language of API messages when the request's Accept-Language names none with a catalogue
+    pub ui_language: Option<String>,
     /// Camera device paths
     pub cameras: Vec<String>,
     /// Number of cameras on the machine
@@ -51,6 +90,101 @@ pub struct Settings {
     pub auto_detect_cameras: bool,
     /// Automatically start configured ESP32 cameras when they come online
     pub auto_start_esp32_cameras: bool,
+    /// JPEG quality used when converting ingested PNG/HEIC images
+    pub image_jpeg_quality: u8,
+    /// Largest file upload accepted, in megabytes
+    pub max_upload_mb: u64,
+    /// Maximum concurrent live streams per camera
+    pub max_concurrent_streams: usize,
+    /// Composite batches, training, disk usage scans and reference sheets allowed to run at once
+    pub heavy_work_permits: usize,
+    /// Fewest pixels a camera's region of interest may cover
+    pub min_region_area: u64,
+    /// Seconds an open stream may go without a frame before it is reported as stalled
+    pub stream_stall_seconds: u64,
+    /// Milliseconds between snapshots of an ESPHome camera streamed by polling, never less than 200
+    pub esp_snapshot_poll_ms: u64,
+    /// Seconds a network camera may stay offline before it is flagged as stale
+    pub camera_stale_seconds: u64,
+    /// Frames a USB camera discards after opening, while auto-exposure settles
+    pub capture_warmup_frames: u32,
+    /// Milliseconds a USB camera keeps discarding frames after opening
+    pub capture_warmup_ms: u64,
+    /// Recent frames kept per streaming USB camera for captures to pick from, 0 to always capture live
+    pub pre_capture_buffer_frames: usize,
+    /// How many milliseconds before the capture request the buffered frame was taken
+    pub capture_offset_ms: u64,
+    /// Stamp captured images with the time, camera and session, unless a camera says otherwise
+    pub capture_overlay: bool,
+    /// Corner of captured images the overlay is drawn in
+    pub capture_overlay_corner: OverlayCorner,
+    /// Seconds the CLI keeps cached server responses for use while the server is unreachable
+    pub cli_cache_max_age_seconds: u64,
+    /// Milliseconds an API request waits on the controller or cameras before answering 504
+    pub hardware_request_timeout_ms: u64,
+    /// Seconds a camera detection is reused for instead of scanning again, 0 to always scan
+    pub detect_freshness_s: u64,
+    /// Seconds between background rescans of data disk usage, 0 to only scan on request
+    pub data_usage_refresh_seconds: u64,
+    /// Hours of controller health checks kept for the status page
+    pub controller_health_retention_hours: u64,
+    /// Days deleted shells stay in the trash before they are purged for good
+    pub trash_retention_days: u32,
+    /// Seconds a tagging claim on a session lasts unless it is renewed
+    pub session_claim_seconds: u64,
+    /// Keep tagging claims in this file across restarts; only in memory when unset
+    pub session_claims_path: Option<PathBuf>,
+    /// Captures and training batches refuse to start with less free disk space than this
+    pub min_free_disk_mb: u64,
+    /// Shells carrying any of these flags are left out of training
+    pub training_excluded_flags: Vec<ShellFlag>,
+    /// Expected weight and length by case designation; readings outside them warn
+    pub measurement_ranges: BTreeMap<String, MeasurementRange>,
+    /// Columns in the case type reference sheet unless the request asks for others
+    pub reference_sheet_columns: u32,
+    /// ESPHome camera entities exposed through the camera settings passthrough
+    pub esphome_camera_entities: Vec<EspEntity>,
+    /// Servo positions (0-100%) by ESPHome number entity, as saved by calibration
+    pub servo_positions: BTreeMap<String, u8>,
+    /// ESPHome button that feeds the next case
+    pub controller_next_case_button: String,
+    /// ESPHome binary sensor that is on while a case waits to be fed
+    pub controller_case_ready_sensor: String,
+    /// ESPHome binary sensor that is on while a case is under the cameras
+    pub controller_case_in_view_sensor: String,
+    /// ESPHome switch for the vibration motor
+    pub controller_vibration_switch: String,
+    /// ESPHome light for the flash, unset on machines without one
+    pub controller_flash_light: Option<String>,
+    /// Append every controller request and response to this JSONL file
+    pub controller_record_path: Option<PathBuf>,
+    /// Serve controller responses from this recording instead of the network
+    pub controller_replay_path: Option<PathBuf>,
+    /// How many times faster than recorded a replay runs
+    pub controller_replay_speed: f64,
+    /// Seconds after the controller last answered that commands failing to connect are retried, 0 to never retry
+    pub controller_retry_window_s: u64,
+    /// Minutes before maintenance mode turns itself off, 0 to wait for it to be turned off
+    pub maintenance_auto_expire_min: u64,
+    /// ESPHome temperature sensors read with every health check, as `device/object_id`
+    pub esphome_temperature_entities: Vec<TemperatureSensor>,
+    /// A temperature sensor reaching this many °C warns
+    pub temperature_warning_c: f64,
+    /// Turn maintenance mode on when a temperature sensor warns
+    pub overheat_pauses_automation: bool,
+    /// Log entries kept in memory for `/api/logs`
+    pub log_buffer_capacity: usize,
+    /// Least severe level kept for `/api/logs`
+    pub log_buffer_level: LogLevel,
+    /// Origins allowed to call `/api` from a browser, none to refuse cross-origin requests
+    pub cors_allowed_origins: Vec<String>,
+    /// Serve `/static` from the directory on disk or the copy built into the binary
+    pub static_assets: StaticAssetSource,
+    /// Refuse every request that would change data, and write nothing in the background
+    pub read_only: bool,
+    /// Where these settings were loaded from
+    #[serde(skip)]
+    pub sources: ConfigSources,
 }
 
 impl Default for Settings {
@@ -58,8 +192,11 @@ impl Default for Settings {
         Self {
             host: "127.0.0.1".to_string(),
             port: 8000,
+            server_tls: false,
+            server_url: None,
             debug: false,
             machine_name: "Shell Sorter v1.0".to_string(),
+            ui_language: None,
             cameras: Vec::new(),
             camera_count: 4,
             camera_resolution: "1920x1080".to_string(),
@@ -84,25 +221,143 @@ impl Default for Settings {
             network_camera_hostnames: vec!["esp32cam1.local".to_string()],
             auto_detect_cameras: false,
             auto_start_esp32_cameras: true,
+            image_jpeg_quality: crate::image_ingest::DEFAULT_JPEG_QUALITY,
+            max_upload_mb: crate::upload::DEFAULT_MAX_UPLOAD_MB,
+            max_concurrent_streams: 4,
+            heavy_work_permits: crate::heavy_work::DEFAULT_HEAVY_WORK_PERMITS,
+            min_region_area: 1024,
+            stream_stall_seconds: crate::stream_health::DEFAULT_STALL_THRESHOLD.as_secs(),
+            esp_snapshot_poll_ms: crate::snapshot_stream::DEFAULT_SNAPSHOT_POLL_INTERVAL.as_millis()
+                as u64,
+            camera_stale_seconds: crate::camera_manager::DEFAULT_STALE_THRESHOLD.as_secs(),
+            capture_warmup_frames: crate::camera_warmup::DEFAULT_WARMUP_FRAMES,
+            capture_warmup_ms: 0,
+            pre_capture_buffer_frames: 0,
+            capture_offset_ms: crate::pre_capture::DEFAULT_CAPTURE_OFFSET.as_millis() as u64,
+            capture_overlay: false,
+            capture_overlay_corner: OverlayCorner::default(),
+            cli_cache_max_age_seconds: crate::client::DEFAULT_CACHE_MAX_AGE.as_secs(),
+            hardware_request_timeout_ms: crate::protocol::DEFAULT_HARDWARE_REQUEST_TIMEOUT
+                .as_millis() as u64,
+            detect_freshness_s: crate::detection_gate::DEFAULT_DETECT_FRESHNESS.as_secs(),
+            data_usage_refresh_seconds: crate::data_usage::DEFAULT_USAGE_REFRESH.as_secs(),
+            controller_health_retention_hours: crate::health_history::DEFAULT_HEALTH_RETENTION
+                .as_secs()
+                / 3600,
+            trash_retention_days: crate::trash::DEFAULT_TRASH_RETENTION_DAYS,
+            session_claim_seconds: crate::session_claims::DEFAULT_CLAIM_DURATION.as_secs(),
+            session_claims_path: None,
+            min_free_disk_mb: 500,
+            training_excluded_flags: vec![ShellFlag::Blurry, ShellFlag::WrongOrientation],
+            measurement_ranges: crate::measurements::default_measurement_ranges(),
+            reference_sheet_columns: crate::reference_sheet::DEFAULT_SHEET_COLUMNS,
+            esphome_camera_entities: default_esphome_camera_entities(),
+            servo_positions: default_servo_positions(),
+            controller_next_case_button: "trigger_next_case".to_string(),
+            controller_case_ready_sensor: "case_ready_to_feed".to_string(),
+            controller_case_in_view_sensor: "case_in_camera_view".to_string(),
+            controller_vibration_switch: "vibration_motor".to_string(),
+            controller_flash_light: Some("flash".to_string()),
+            controller_record_path: None,
+            controller_replay_path: None,
+            controller_replay_speed: crate::controller_recording::DEFAULT_REPLAY_SPEED,
+            controller_retry_window_s: crate::controller_monitor::DEFAULT_RETRY_WINDOW.as_secs(),
+            maintenance_auto_expire_min: 0,
+            esphome_temperature_entities: Vec::new(),
+            temperature_warning_c: crate::temperature::DEFAULT_TEMPERATURE_WARNING_C,
+            overheat_pauses_automation: false,
+            log_buffer_capacity: crate::log_buffer::DEFAULT_LOG_CAPACITY,
+            log_buffer_level: LogLevel::Info,
+            cors_allowed_origins: Vec::new(),
+            static_assets: StaticAssetSource::Auto,
+            read_only: false,
+            sources: ConfigSources::default(),
+        }
+    }
+}
+
+/// ESP32-CAM frame size, JPEG quality and vertical flip controls
+/// An RFC 3339 time, or the seconds since the epoch older configs saved
+fn deserialize_timestamp<'de, D>(
+    deserializer: D,
+) -> Result<Option<chrono::DateTime<chrono::Utc>>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Timestamp {
+        Time(chrono::DateTime<chrono::Utc>),
+        EpochSeconds(f64),
+    }
+
+    match Option::<Timestamp>::deserialize(deserializer)? {
+        None => Ok(None),
+        Some(Timestamp::Time(time)) => Ok(Some(time)),
+        Some(Timestamp::EpochSeconds(seconds)) => {
+            chrono::DateTime::from_timestamp_millis((seconds * 1000.0) as i64)
+                .map(Some)
+                .ok_or_else(|| {
+                    serde::de::Error::custom(format!(
+                        "{seconds} seconds is out of range for a time"
+                    ))
+                })
         }
     }
 }
 
+fn default_esphome_camera_entities() -> Vec<EspEntity> {
+    [
+        (EspEntityDomain::Select, "framesize"),
+        (EspEntityDomain::Number, "jpeg_quality"),
+        (EspEntityDomain::Switch, "vflip"),
+    ]
+    .into_iter()
+    .map(|(domain, object_id)| EspEntity {
+        domain,
+        object_id: object_id.to_string(),
+    })
+    .collect()
+}
+
+/// The feeder and positioning servos, both at their neutral position
+fn default_servo_positions() -> BTreeMap<String, u8> {
+    BTreeMap::from([
+        ("case_feeder_servo_position".to_string(), 50),
+        ("case_position_servo_position".to_string(), 50),
+    ])
+}
+
 /// Camera view type
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, Default)]
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize, Default,
+)]
 #[serde(rename_all = "lowercase")]
 pub enum ViewType {
     Side,
     Tail,
+    /// Looking into the case mouth, for rifle cases
+    Mouth,
     #[default]
     Unknown,
 }
 
+impl ViewType {
+    /// Every view type, in the order the UI offers them
+    pub const ALL: [ViewType; 4] = [
+        ViewType::Side,
+        ViewType::Tail,
+        ViewType::Mouth,
+        ViewType::Unknown,
+    ];
+}
+
 impl std::fmt::Display for ViewType {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             ViewType::Side => write!(f, "side"),
             ViewType::Tail => write!(f, "tail"),
+            ViewType::Mouth => write!(f, "mouth"),
             ViewType::Unknown => write!(f, "unknown"),
         }
     }
@@ -115,6 +370,7 @@ impl std::str::FromStr for ViewType {
         match s.to_lowercase().as_str() {
             "side" => Ok(ViewType::Side),
             "tail" => Ok(ViewType::Tail),
+            "mouth" => Ok(ViewType::Mouth),
             "unknown" => Ok(ViewType::Unknown),
             _ => Err(crate::OurError::App(format!("Invalid view type: {s}"))),
         }
@@ -123,7 +379,12 @@ impl std::str::FromStr for ViewType {
 
 /// Configuration for a specific camera
 #[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub struct CameraConfig {
+    /// Friendly name for the camera
+    pub nickname: Option<String>,
+    /// Physical position of the camera on the machine
+    pub position: Option<String>,
     /// Camera view type
     pub view_type: Option<ViewType>,
     /// Region x coordinate
@@ -134,6 +395,10 @@ pub struct CameraConfig {
     pub region_width: Option<u32>,
     /// Region height
     pub region_height: Option<i32>,
+    /// Width of the oriented frame the region was drawn on
+    pub region_frame_width: Option<u32>,
+    /// Height of the oriented frame the region was drawn on
+    pub region_frame_height: Option<u32>,
     /// Detected resolution width for ESP cameras
     pub detected_resolution_width: Option<i32>,
     /// Detected resolution height for ESP cameras
@@ -142,12 +407,70 @@ pub struct CameraConfig {
     pub manual_resolution_width: Option<i32>,
     /// Manual resolution height
     pub manual_resolution_height: Option<i32>,
-    /// Resolution detection timestamp
-    pub resolution_detection_timestamp: Option<f64>,
+    /// When the resolution was detected
+    #[serde(default, deserialize_with = "deserialize_timestamp")]
+    pub resolution_detection_timestamp: Option<chrono::DateTime<chrono::Utc>>,
+    /// Clockwise rotation applied to every frame; regions are in the rotated frame
+    #[serde(default)]
+    pub rotation: Rotation,
+    /// Mirror every frame left to right, after rotating
+    #[serde(default)]
+    pub mirror: bool,
+    /// Stamp this camera's captures with the overlay, whatever `capture_overlay` says
+    #[serde(default)]
+    pub capture_overlay: Option<bool>,
+}
+
+impl CameraConfig {
+    /// How this camera's frames are turned upright
+    pub fn orientation(&self) -> Orientation {
+        Orientation {
+            rotation: self.rotation,
+            mirror: self.mirror,
+        }
+    }
+
+    /// The region of interest, if all of it is set
+    pub fn region(&self) -> Option<FrameRegion> {
+        Some(FrameRegion {
+            x: u32::try_from(self.region_x?).ok()?,
+            y: u32::try_from(self.region_y?).ok()?,
+            width: self.region_width?,
+            height: u32::try_from(self.region_height?).ok()?,
+        })
+    }
+
+    /// The size of the frame the region of interest was drawn on
+    pub fn region_frame(&self) -> Option<FrameSize> {
+        Some(FrameSize {
+            width: self.region_frame_width?,
+            height: self.region_frame_height?,
+        })
+    }
+
+    /// Store a region of interest along with the frame it was drawn on
+    pub fn set_region(&mut self, region: FrameRegion, frame: FrameSize) {
+        self.region_x = i32::try_from(region.x).ok();
+        self.region_y = i32::try_from(region.y).ok();
+        self.region_width = Some(region.width);
+        self.region_height = i32::try_from(region.height).ok();
+        self.region_frame_width = Some(frame.width);
+        self.region_frame_height = Some(frame.height);
+    }
+
+    pub fn clear_region(&mut self) {
+        self.region_x = None;
+        self.region_y = None;
+        self.region_width = None;
+        self.region_height = None;
+        self.region_frame_width = None;
+        self.region_frame_height = None;
+    }
 }
 
 /// User configuration that persists across application restarts
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub struct UserConfig {
     /// Camera configurations by name
     pub camera_configs: HashMap<String, CameraConfig>,
@@ -160,7 +483,22 @@ pub struct UserConfig {
     /// ESPHome device hostname for API communication
     pub esphome_hostname: String,
     /// Selected camera IDs that should be restored when cameras are detected
-    pub selected_cameras: Vec<String>,
+    pub selected_cameras: Vec<CameraId>,
+    /// Servo positions saved by calibration
+    #[serde(default)]
+    pub servo_positions: BTreeMap<String, u8>,
+    /// Case types added through the API on top of `supported_case_types`
+    #[serde(default)]
+    pub extra_case_types: Vec<String>,
+    /// Data directory chosen during first-run setup
+    #[serde(default)]
+    pub data_directory: Option<PathBuf>,
+    /// Named sets of cameras and their settings, see [`crate::camera_presets`]
+    #[serde(default)]
+    pub camera_presets: BTreeMap<String, CameraPreset>,
+    /// The preset applied last
+    #[serde(default)]
+    pub active_camera_preset: Option<String>,
 }
 
 impl Default for UserConfig {
@@ -172,6 +510,11 @@ impl Default for UserConfig {
             auto_start_esp32_cameras: true,
             esphome_hostname: "shell-sorter-controller.local".to_string(),
             selected_cameras: Vec::new(),
+            servo_positions: BTreeMap::new(),
+            extra_case_types: Vec::new(),
+            data_directory: None,
+            camera_presets: BTreeMap::new(),
+            active_camera_preset: None,
         }
     }
 }
@@ -201,74 +544,388 @@ impl UserConfig {
     }
 
     /// Set the selected camera IDs
-    pub fn set_selected_cameras(&mut self, camera_ids: Vec<String>) {
+    pub fn set_selected_cameras(&mut self, camera_ids: Vec<CameraId>) {
         self.selected_cameras = camera_ids;
     }
 
     /// Get the selected camera IDs
-    pub fn get_selected_cameras(&self) -> &Vec<String> {
+    pub fn get_selected_cameras(&self) -> &[CameraId] {
         &self.selected_cameras
     }
 
     /// Check if a camera ID is selected
     pub fn is_camera_selected(&self, camera_id: &str) -> bool {
-        self.selected_cameras.contains(&camera_id.to_string())
+        self.selected_cameras
+            .iter()
+            .any(|selected| selected.as_str() == camera_id)
+    }
+
+    /// The preset applied last, with its name, if it still exists
+    pub fn active_camera_preset(&self) -> Option<(&str, &CameraPreset)> {
+        let name = self.active_camera_preset.as_deref()?;
+        self.camera_presets.get(name).map(|preset| (name, preset))
     }
 }
 
 impl Settings {
+    /// `max_upload_mb` in bytes
+    pub fn max_upload_bytes(&self) -> usize {
+        usize::try_from(self.max_upload_mb.saturating_mul(1024 * 1024)).unwrap_or(usize::MAX)
+    }
+
     /// Create a new instance of Settings with environment variable overrides
     pub fn new() -> Result<Self, Box<dyn std::error::Error>> {
+        Self::load(None)
+    }
+
+    /// Load settings, using `config_path` as the user config file if given
+    ///
+    /// Precedence, lowest to highest: defaults, project-local config file found
+    /// by walking up from the current directory, user config file, then
+    /// `SHELL_SORTER_*` environment variables.
+    pub fn load(config_path: Option<PathBuf>) -> Result<Self, Box<dyn std::error::Error>> {
+        let env_vars: HashMap<String, String> = env::vars()
+            .filter(|(key, _)| key.starts_with("SHELL_SORTER_"))
+            .collect();
+        let mut settings = Self::from_sources(config_path, &env::current_dir()?, &env_vars)?;
+
+        // Create all necessary directories, then settle on their canonical paths
+        let directories = DataDirectories::from_settings(&settings);
+        data_directories::check_distinct(&directories)?;
+        settings.create_directories()?;
+        settings.set_directories(data_directories::canonicalize(&directories)?);
+
+        Ok(settings)
+    }
+
+    /// Use `directories` for the image, data, models and references directories
+    pub fn set_directories(&mut self, directories: DataDirectories) {
+        self.image_directory = directories.image_directory;
+        self.data_directory = directories.data_directory;
+        self.models_directory = directories.models_directory;
+        self.references_directory = directories.references_directory;
+    }
+
+    /// Make the directory settings that are still relative absolute against `base`
+    fn resolve_directories(&mut self, base: &Path) {
+        for directory in [
+            &mut self.image_directory,
+            &mut self.data_directory,
+            &mut self.models_directory,
+            &mut self.references_directory,
+        ] {
+            *directory = data_directories::resolve(directory, base);
+        }
+    }
+
+    /// Build settings from the layered config sources without touching the process environment
+    fn from_sources(
+        config_path: Option<PathBuf>,
+        start_dir: &Path,
+        env_vars: &HashMap<String, String>,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
         let mut settings = Settings::default();
 
-        // Load user configuration from file if it exists
-        let user_config = Self::load_user_config();
-        settings.esphome_hostname = user_config.esphome_hostname;
-        settings.network_camera_hostnames = user_config.network_camera_hostnames;
-        settings.auto_detect_cameras = user_config.auto_detect_cameras;
-        settings.auto_start_esp32_cameras = user_config.auto_start_esp32_cameras;
+        // Project-local config file; its directories, and the defaults, are relative to it
+        if let Some(project_file) = Self::find_project_config(start_dir) {
+            settings = settings.merge_file(&project_file)?;
+            if let Some(project_dir) = project_file.parent() {
+                settings.resolve_directories(project_dir);
+            }
+            settings.sources.project_file = Some(project_file);
+        }
+
+        // User configuration file, --config taking precedence over SHELL_SORTER_CONFIG_PATH
+        let chosen_config_path = config_path
+            .or_else(|| env_vars.get("SHELL_SORTER_CONFIG_PATH").map(PathBuf::from))
+            .map(|path| data_directories::resolve(&path, start_dir));
+        // Without a config file of their own the defaults go in the home directory
+        let directory_base = match &chosen_config_path {
+            Some(path) => path.parent().map(Path::to_path_buf),
+            None => dirs::home_dir(),
+        }
+        .unwrap_or_else(|| start_dir.to_path_buf());
+        let user_config_path = chosen_config_path.unwrap_or_else(Self::get_config_path);
+        if user_config_path.exists() {
+            let user_config = Self::load_user_config_from(&user_config_path);
+            settings.esphome_hostname = user_config.esphome_hostname;
+            settings.network_camera_hostnames = user_config.network_camera_hostnames;
+            settings.auto_detect_cameras = user_config.auto_detect_cameras;
+            settings.auto_start_esp32_cameras = user_config.auto_start_esp32_cameras;
+            settings.servo_positions.extend(user_config.servo_positions);
+            if let Some(data_directory) = user_config.data_directory {
+                settings.data_directory = match user_config_path.parent() {
+                    Some(config_dir) => data_directories::resolve(&data_directory, config_dir),
+                    None => data_directory,
+                };
+            }
+            for case_type in user_config.extra_case_types {
+                if !settings.supported_case_types.contains(&case_type) {
+                    settings.supported_case_types.push(case_type);
+                }
+            }
+            settings.sources.user_config_loaded = true;
+        }
+        settings.sources.user_config_path = user_config_path;
+        settings.resolve_directories(&directory_base);
 
         // Override with environment variables if present
-        if let Ok(host) = env::var("SHELL_SORTER_HOST") {
+        let mut env_overrides = Vec::new();
+        let mut env_var = |name: &str| {
+            let value = env_vars.get(name).cloned();
+            if value.is_some() {
+                env_overrides.push(name.to_string());
+            }
+            value
+        };
+        if let Some(host) = env_var("SHELL_SORTER_HOST") {
             settings.host = host;
         }
-        if let Ok(port) = env::var("SHELL_SORTER_PORT") {
+        if let Some(port) = env_var("SHELL_SORTER_PORT") {
             settings.port = port.parse()?;
         }
-        if let Ok(debug) = env::var("SHELL_SORTER_DEBUG") {
+        if let Some(server_tls) = env_var("SHELL_SORTER_SERVER_TLS") {
+            settings.server_tls = server_tls.parse()?;
+        }
+        if let Some(server_url) = env_var("SHELL_SORTER_SERVER_URL") {
+            settings.server_url = Some(server_url.parse()?);
+        }
+        if let Some(debug) = env_var("SHELL_SORTER_DEBUG") {
             settings.debug = debug.parse()?;
         }
-        if let Ok(machine_name) = env::var("SHELL_SORTER_MACHINE_NAME") {
+        if let Some(machine_name) = env_var("SHELL_SORTER_MACHINE_NAME") {
             settings.machine_name = machine_name;
         }
-        if let Ok(camera_count) = env::var("SHELL_SORTER_CAMERA_COUNT") {
+        if let Some(ui_language) = env_var("SHELL_SORTER_UI_LANGUAGE") {
+            settings.ui_language = Some(ui_language);
+        }
+        if let Some(camera_count) = env_var("SHELL_SORTER_CAMERA_COUNT") {
             settings.camera_count = camera_count.parse()?;
         }
-        if let Ok(camera_resolution) = env::var("SHELL_SORTER_CAMERA_RESOLUTION") {
+        if let Some(camera_resolution) = env_var("SHELL_SORTER_CAMERA_RESOLUTION") {
             settings.camera_resolution = camera_resolution;
         }
-        if let Ok(ml_enabled) = env::var("SHELL_SORTER_ML_ENABLED") {
+        if let Some(ml_enabled) = env_var("SHELL_SORTER_ML_ENABLED") {
             settings.ml_enabled = ml_enabled.parse()?;
         }
-        if let Ok(confidence_threshold) = env::var("SHELL_SORTER_CONFIDENCE_THRESHOLD") {
+        if let Some(confidence_threshold) = env_var("SHELL_SORTER_CONFIDENCE_THRESHOLD") {
             settings.confidence_threshold = confidence_threshold.parse()?;
         }
-        if let Ok(model_name) = env::var("SHELL_SORTER_MODEL_NAME") {
+        if let Some(model_name) = env_var("SHELL_SORTER_MODEL_NAME") {
             settings.model_name = Some(model_name);
         }
-        if let Ok(esphome_hostname) = env::var("SHELL_SORTER_ESPHOME_HOSTNAME") {
+        if let Some(esphome_hostname) = env_var("SHELL_SORTER_ESPHOME_HOSTNAME") {
             settings.esphome_hostname = esphome_hostname;
         }
-        if let Ok(auto_detect_cameras) = env::var("SHELL_SORTER_AUTO_DETECT_CAMERAS") {
+        if let Some(auto_detect_cameras) = env_var("SHELL_SORTER_AUTO_DETECT_CAMERAS") {
             settings.auto_detect_cameras = auto_detect_cameras.parse()?;
         }
-        if let Ok(auto_start_esp32_cameras) = env::var("SHELL_SORTER_AUTO_START_ESP32_CAMERAS") {
+        if let Some(auto_start_esp32_cameras) = env_var("SHELL_SORTER_AUTO_START_ESP32_CAMERAS") {
             settings.auto_start_esp32_cameras = auto_start_esp32_cameras.parse()?;
         }
+        if let Some(image_jpeg_quality) = env_var("SHELL_SORTER_IMAGE_JPEG_QUALITY") {
+            settings.image_jpeg_quality = image_jpeg_quality.parse()?;
+        }
+        if let Some(max_upload_mb) = env_var("SHELL_SORTER_MAX_UPLOAD_MB") {
+            settings.max_upload_mb = max_upload_mb.parse()?;
+        }
+        if let Some(max_concurrent_streams) = env_var("SHELL_SORTER_MAX_CONCURRENT_STREAMS") {
+            settings.max_concurrent_streams = max_concurrent_streams.parse()?;
+        }
+        if let Some(permits) = env_var("SHELL_SORTER_HEAVY_WORK_PERMITS") {
+            settings.heavy_work_permits = permits.parse()?;
+        }
+        if let Some(min_region_area) = env_var("SHELL_SORTER_MIN_REGION_AREA") {
+            settings.min_region_area = min_region_area.parse()?;
+        }
+        if let Some(stream_stall_seconds) = env_var("SHELL_SORTER_STREAM_STALL_SECONDS") {
+            settings.stream_stall_seconds = stream_stall_seconds.parse()?;
+        }
+        if let Some(poll_ms) = env_var("SHELL_SORTER_ESP_SNAPSHOT_POLL_MS") {
+            settings.esp_snapshot_poll_ms = poll_ms.parse()?;
+        }
+        if let Some(camera_stale_seconds) = env_var("SHELL_SORTER_CAMERA_STALE_SECONDS") {
+            settings.camera_stale_seconds = camera_stale_seconds.parse()?;
+        }
+        if let Some(capture_warmup_frames) = env_var("SHELL_SORTER_CAPTURE_WARMUP_FRAMES") {
+            settings.capture_warmup_frames = capture_warmup_frames.parse()?;
+        }
+        if let Some(capture_warmup_ms) = env_var("SHELL_SORTER_CAPTURE_WARMUP_MS") {
+            settings.capture_warmup_ms = capture_warmup_ms.parse()?;
+        }
+        if let Some(buffer_frames) = env_var("SHELL_SORTER_PRE_CAPTURE_BUFFER_FRAMES") {
+            settings.pre_capture_buffer_frames = buffer_frames.parse()?;
+        }
+        if let Some(capture_offset_ms) = env_var("SHELL_SORTER_CAPTURE_OFFSET_MS") {
+            settings.capture_offset_ms = capture_offset_ms.parse()?;
+        }
+        if let Some(capture_overlay) = env_var("SHELL_SORTER_CAPTURE_OVERLAY") {
+            settings.capture_overlay = capture_overlay.parse()?;
+        }
+        if let Some(corner) = env_var("SHELL_SORTER_CAPTURE_OVERLAY_CORNER") {
+            settings.capture_overlay_corner = corner.parse()?;
+        }
+        if let Some(max_age) = env_var("SHELL_SORTER_CLI_CACHE_MAX_AGE_SECONDS") {
+            settings.cli_cache_max_age_seconds = max_age.parse()?;
+        }
+        if let Some(timeout_ms) = env_var("SHELL_SORTER_HARDWARE_REQUEST_TIMEOUT_MS") {
+            settings.hardware_request_timeout_ms = timeout_ms.parse()?;
+        }
+        if let Some(freshness_s) = env_var("SHELL_SORTER_DETECT_FRESHNESS_S") {
+            settings.detect_freshness_s = freshness_s.parse()?;
+        }
+        if let Some(refresh_seconds) = env_var("SHELL_SORTER_DATA_USAGE_REFRESH_SECONDS") {
+            settings.data_usage_refresh_seconds = refresh_seconds.parse()?;
+        }
+        if let Some(retention_hours) = env_var("SHELL_SORTER_CONTROLLER_HEALTH_RETENTION_HOURS") {
+            settings.controller_health_retention_hours = retention_hours.parse()?;
+        }
+        if let Some(retention_days) = env_var("SHELL_SORTER_TRASH_RETENTION_DAYS") {
+            settings.trash_retention_days = retention_days.parse()?;
+        }
+        if let Some(claim_seconds) = env_var("SHELL_SORTER_SESSION_CLAIM_SECONDS") {
+            settings.session_claim_seconds = claim_seconds.parse()?;
+        }
+        if let Some(claims_path) = env_var("SHELL_SORTER_SESSION_CLAIMS_PATH") {
+            settings.session_claims_path = Some(PathBuf::from(claims_path));
+        }
+        if let Some(min_free_disk_mb) = env_var("SHELL_SORTER_MIN_FREE_DISK_MB") {
+            settings.min_free_disk_mb = min_free_disk_mb.parse()?;
+        }
+        if let Some(training_excluded_flags) = env_var("SHELL_SORTER_TRAINING_EXCLUDED_FLAGS") {
+            settings.training_excluded_flags = training_excluded_flags
+                .split(',')
+                .filter(|flag| !flag.trim().is_empty())
+                .map(str::parse)
+                .collect::<OurResult<_>>()?;
+        }
+        if let Some(columns) = env_var("SHELL_SORTER_REFERENCE_SHEET_COLUMNS") {
+            settings.reference_sheet_columns = columns.parse()?;
+        }
+        if let Some(esphome_camera_entities) = env_var("SHELL_SORTER_ESPHOME_CAMERA_ENTITIES") {
+            settings.esphome_camera_entities = esphome_camera_entities
+                .split(',')
+                .map(str::trim)
+                .filter(|entity| !entity.is_empty())
+                .map(str::parse)
+                .collect::<OurResult<_>>()?;
+        }
+        if let Some(button) = env_var("SHELL_SORTER_CONTROLLER_NEXT_CASE_BUTTON") {
+            settings.controller_next_case_button = button;
+        }
+        if let Some(sensor) = env_var("SHELL_SORTER_CONTROLLER_CASE_READY_SENSOR") {
+            settings.controller_case_ready_sensor = sensor;
+        }
+        if let Some(sensor) = env_var("SHELL_SORTER_CONTROLLER_CASE_IN_VIEW_SENSOR") {
+            settings.controller_case_in_view_sensor = sensor;
+        }
+        if let Some(switch) = env_var("SHELL_SORTER_CONTROLLER_VIBRATION_SWITCH") {
+            settings.controller_vibration_switch = switch;
+        }
+        if let Some(light) = env_var("SHELL_SORTER_CONTROLLER_FLASH_LIGHT") {
+            settings.controller_flash_light = Some(light).filter(|light| !light.is_empty());
+        }
+        if let Some(controller_record_path) = env_var("SHELL_SORTER_CONTROLLER_RECORD_PATH") {
+            settings.controller_record_path = Some(PathBuf::from(controller_record_path));
+        }
+        if let Some(controller_replay_path) = env_var("SHELL_SORTER_CONTROLLER_REPLAY_PATH") {
+            settings.controller_replay_path = Some(PathBuf::from(controller_replay_path));
+        }
+        if let Some(controller_replay_speed) = env_var("SHELL_SORTER_CONTROLLER_REPLAY_SPEED") {
+            settings.controller_replay_speed = controller_replay_speed.parse()?;
+        }
+        if let Some(retry_window_s) = env_var("SHELL_SORTER_CONTROLLER_RETRY_WINDOW_S") {
+            settings.controller_retry_window_s = retry_window_s.parse()?;
+        }
+        if let Some(auto_expire_min) = env_var("SHELL_SORTER_MAINTENANCE_AUTO_EXPIRE_MIN") {
+            settings.maintenance_auto_expire_min = auto_expire_min.parse()?;
+        }
+        if let Some(sensors) = env_var("SHELL_SORTER_ESPHOME_TEMPERATURE_ENTITIES") {
+            settings.esphome_temperature_entities = sensors
+                .split(',')
+                .map(str::trim)
+                .filter(|sensor| !sensor.is_empty())
+                .map(str::parse)
+                .collect::<OurResult<_>>()?;
+        }
+        if let Some(warning_c) = env_var("SHELL_SORTER_TEMPERATURE_WARNING_C") {
+            settings.temperature_warning_c = warning_c.parse()?;
+        }
+        if let Some(pauses) = env_var("SHELL_SORTER_OVERHEAT_PAUSES_AUTOMATION") {
+            settings.overheat_pauses_automation = pauses.parse()?;
+        }
+        if let Some(log_buffer_capacity) = env_var("SHELL_SORTER_LOG_BUFFER_CAPACITY") {
+            settings.log_buffer_capacity = log_buffer_capacity.parse()?;
+        }
+        if let Some(log_buffer_level) = env_var("SHELL_SORTER_LOG_BUFFER_LEVEL") {
+            settings.log_buffer_level = log_buffer_level.parse()?;
+        }
+        if let Some(origins) = env_var("SHELL_SORTER_CORS_ALLOWED_ORIGINS") {
+            settings.cors_allowed_origins = origins
+                .split(',')
+                .map(str::trim)
+                .filter(|origin| !origin.is_empty())
+                .map(str::to_string)
+                .collect();
+        }
+        if let Some(static_assets) = env_var("SHELL_SORTER_STATIC_ASSETS") {
+            settings.static_assets = static_assets.parse()?;
+        }
+        if let Some(read_only) = env_var("SHELL_SORTER_READ_ONLY") {
+            settings.read_only = read_only.parse()?;
+        }
+        settings.sources.env_overrides = env_overrides;
+        crate::cors::validate_origins(&settings.cors_allowed_origins)?;
+        crate::measurements::validate_ranges(&settings.measurement_ranges)?;
 
-        // Create all necessary directories
-        settings.create_directories()?;
+        Ok(settings)
+    }
 
+    /// Find a project-local config file by walking up from `start_dir`
+    pub fn find_project_config(start_dir: &Path) -> Option<PathBuf> {
+        start_dir.ancestors().find_map(|dir| {
+            PROJECT_CONFIG_FILENAMES
+                .iter()
+                .map(|filename| dir.join(filename))
+                .find(|candidate| candidate.is_file())
+        })
+    }
+
+    /// Overlay the settings in a TOML or JSON file onto these settings
+    fn merge_file(self, path: &Path) -> Result<Self, Box<dyn std::error::Error>> {
+        let contents = fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read config file {}: {e}", path.display()))?;
+        let overlay: serde_json::Value = if path.extension().is_some_and(|ext| ext == "toml") {
+            serde_json::to_value(
+                toml::from_str::<toml::Table>(&contents)
+                    .map_err(|e| format!("Failed to parse config file {}: {e}", path.display()))?,
+            )?
+        } else {
+            serde_json::from_str(&contents)
+                .map_err(|e| format!("Failed to parse config file {}: {e}", path.display()))?
+        };
+        let serde_json::Value::Object(overlay) = overlay else {
+            return Err(format!("Config file {} must contain a table", path.display()).into());
+        };
+
+        let sources = self.sources.clone();
+        let mut merged = serde_json::to_value(self)?;
+        if let serde_json::Value::Object(merged) = &mut merged {
+            for (key, value) in overlay {
+                if !merged.contains_key(&key) {
+                    return Err(format!(
+                        "Unknown setting '{key}' in config file {}",
+                        path.display()
+                    )
+                    .into());
+                }
+                merged.insert(key, value);
+            }
+        }
+
+        let mut settings: Settings = serde_json::from_value(merged)
+            .map_err(|e| format!("Invalid config file {}: {e}", path.display()))?;
+        settings.sources = sources;
         Ok(settings)
     }
 
@@ -316,14 +973,18 @@ impl Settings {
         config_dir.join("shell-sorter.json")
     }
 
-    /// Load user configuration from shell-sorter.json
-    pub fn load_user_config() -> UserConfig {
-        let config_path = Self::get_config_path();
+    /// Load the user configuration file these settings were loaded with
+    pub fn load_user_config(&self) -> UserConfig {
+        Self::load_user_config_from(&self.sources.user_config_path)
+    }
+
+    /// Load user configuration from the given file, falling back to defaults
+    pub fn load_user_config_from(config_path: &Path) -> UserConfig {
         if !config_path.exists() {
             return UserConfig::default();
         }
 
-        match fs::read_to_string(&config_path) {
+        match fs::read_to_string(config_path) {
             Ok(contents) => match serde_json::from_str::<UserConfig>(&contents) {
                 Ok(config) => config,
                 Err(e) => {
@@ -338,9 +999,9 @@ impl Settings {
         }
     }
 
-    /// Save user configuration to shell-sorter.json
-    pub fn save_user_config(config: &UserConfig) -> Result<(), Box<dyn std::error::Error>> {
-        let config_path = Self::get_config_path();
+    /// Save user configuration to the user config file these settings were loaded with
+    pub fn save_user_config(&self, config: &UserConfig) -> Result<(), Box<dyn std::error::Error>> {
+        let config_path = &self.sources.user_config_path;
 
         // Ensure directory exists
         if let Some(parent) = config_path.parent() {
@@ -348,15 +1009,46 @@ impl Settings {
         }
 
         let contents = serde_json::to_string_pretty(config)?;
-        fs::write(&config_path, contents)?;
+        crate::shell_data::write_atomic(config_path, contents.as_bytes())?;
 
         println!("Saved user config to {config_path:?}");
         Ok(())
     }
 
-    /// Get the base URL for the API server
-    pub fn base_url(&self) -> String {
-        format!("http://{}:{}", self.host, self.port)
+    /// The URL the CLI reaches the server at
+    ///
+    /// `server_url` wins when set. Otherwise it's built from `host` and `port`:
+    /// an all-interfaces address can be listened on but not connected to, so
+    /// loopback stands in for it, IPv6 addresses are bracketed, and the scheme
+    /// is https when `server_tls` is set.
+    pub fn base_url(&self) -> OurResult<Url> {
+        if let Some(url) = &self.server_url {
+            return match url.scheme() {
+                "http" | "https" => Ok(url.clone()),
+                scheme => Err(OurError::App(format!(
+                    "server_url must be an http or https URL, not {scheme}"
+                ))),
+            };
+        }
+        let host = self.host.trim();
+        let host = host
+            .strip_prefix('[')
+            .and_then(|host| host.strip_suffix(']'))
+            .unwrap_or(host);
+        let host = match host.parse::<IpAddr>() {
+            Ok(address) if address.is_unspecified() => Ipv4Addr::LOCALHOST.to_string(),
+            Ok(IpAddr::V6(address)) => format!("[{address}]"),
+            Ok(IpAddr::V4(address)) => address.to_string(),
+            Err(_) if host.is_empty() => Ipv4Addr::LOCALHOST.to_string(),
+            Err(_) => host.to_string(),
+        };
+        let scheme = if self.server_tls { "https" } else { "http" };
+        Url::parse(&format!("{scheme}://{host}:{}/", self.port)).map_err(|e| {
+            OurError::App(format!(
+                "Can't build a server URL from host {:?}: {e}",
+                self.host
+            ))
+        })
     }
 }
 
@@ -391,6 +1083,32 @@ mod tests {
         assert!(config.region_height.is_none());
     }
 
+    #[test]
+    fn test_camera_config_reads_old_epoch_timestamps() {
+        let old: CameraConfig =
+            serde_json::from_str(r#"{"resolution_detection_timestamp": 1751371200.5}"#)
+                .expect("epoch seconds should load");
+        let time = old
+            .resolution_detection_timestamp
+            .expect("timestamp should be kept");
+        assert_eq!(time.to_rfc3339(), "2025-07-01T12:00:00.500+00:00");
+
+        let saved = serde_json::to_value(&old).expect("config should serialise");
+        assert_eq!(
+            saved["resolution_detection_timestamp"],
+            "2025-07-01T12:00:00.500Z"
+        );
+        let reloaded: CameraConfig =
+            serde_json::from_value(saved).expect("saved config should load");
+        assert_eq!(reloaded.resolution_detection_timestamp, Some(time));
+        assert!(
+            serde_json::from_str::<CameraConfig>("{}")
+                .expect("config should load")
+                .resolution_detection_timestamp
+                .is_none()
+        );
+    }
+
     #[test]
     fn test_user_config_default() {
         let config = UserConfig::default();
@@ -436,14 +1154,248 @@ mod tests {
 
     #[test]
     fn test_base_url() {
+        let url = |host: &str, port: u16, server_tls: bool| {
+            Settings {
+                host: host.to_string(),
+                port,
+                server_tls,
+                ..Settings::default()
+            }
+            .base_url()
+            .expect("URL should build")
+            .to_string()
+        };
+        assert_eq!(url("localhost", 3000, false), "http://localhost:3000/");
+        assert_eq!(
+            Settings::default()
+                .base_url()
+                .expect("URL should build")
+                .as_str(),
+            "http://127.0.0.1:8000/"
+        );
+
+        // Listening on every interface is reached through loopback
+        assert_eq!(url("0.0.0.0", 8000, false), "http://127.0.0.1:8000/");
+        assert_eq!(url("::", 8000, false), "http://127.0.0.1:8000/");
+        assert_eq!(url("[::]", 8000, false), "http://127.0.0.1:8000/");
+
+        // IPv6 literals are bracketed whether or not they were written that way
+        assert_eq!(url("::1", 8000, false), "http://[::1]:8000/");
+        assert_eq!(url("[fe80::1]", 8000, false), "http://[fe80::1]:8000/");
+
+        assert_eq!(
+            url("sorter.local", 8443, true),
+            "https://sorter.local:8443/"
+        );
+        assert!(
+            Settings {
+                host: "not a host".to_string(),
+                ..Settings::default()
+            }
+            .base_url()
+            .is_err()
+        );
+    }
+
+    #[test]
+    fn test_server_url_overrides_host_and_port() {
+        let env_vars = HashMap::from([
+            ("SHELL_SORTER_HOST".to_string(), "0.0.0.0".to_string()),
+            (
+                "SHELL_SORTER_SERVER_URL".to_string(),
+                "https://sorter.example.com/shells/".to_string(),
+            ),
+        ]);
+        let temp_dir = tempfile::TempDir::new().expect("temp dir should be created");
+        let settings = Settings::from_sources(
+            Some(temp_dir.path().join("missing.json")),
+            temp_dir.path(),
+            &env_vars,
+        )
+        .expect("settings should load");
+        assert_eq!(
+            settings.base_url().expect("URL should build").as_str(),
+            "https://sorter.example.com/shells/"
+        );
+
         let settings = Settings {
-            host: "localhost".to_string(),
-            port: 3000,
+            server_url: Some("ftp://sorter.local/".parse().expect("URL should parse")),
             ..Settings::default()
         };
-        assert_eq!(settings.base_url(), "http://localhost:3000");
+        assert!(settings.base_url().is_err());
+    }
+
+    #[test]
+    fn test_cors_origins_from_the_environment() {
+        let temp_dir = tempfile::TempDir::new().expect("temp dir should be created");
+        let load = |origins: &str| {
+            Settings::from_sources(
+                Some(temp_dir.path().join("missing.json")),
+                temp_dir.path(),
+                &HashMap::from([(
+                    "SHELL_SORTER_CORS_ALLOWED_ORIGINS".to_string(),
+                    origins.to_string(),
+                )]),
+            )
+        };
+        let settings =
+            load("http://localhost:5173, http://127.0.0.1:5173").expect("settings should load");
+        assert_eq!(
+            settings.cors_allowed_origins,
+            vec!["http://localhost:5173", "http://127.0.0.1:5173"]
+        );
+        assert!(load("*").is_err(), "a wildcard origin should be refused");
+        assert!(load("http://localhost:5173/app").is_err());
+    }
+
+    #[test]
+    fn test_config_precedence_chain() {
+        let temp_dir = tempfile::TempDir::new().expect("temp dir should be created");
+        let project_dir = temp_dir.path().join("project");
+        let nested_dir = project_dir.join("sub").join("dir");
+        fs::create_dir_all(&nested_dir).expect("nested dir should be created");
+
+        // Project-local file, found by walking up from a nested directory
+        fs::write(
+            project_dir.join("shell-sorter.toml"),
+            "machine_name = \"Project Sorter\"\nport = 9000\nesphome_hostname = \"project.local\"\n",
+        )
+        .expect("project config should be written");
+
+        // Defaults only: no user config, no env
+        let settings = Settings::from_sources(
+            Some(temp_dir.path().join("missing.json")),
+            &nested_dir,
+            &HashMap::new(),
+        )
+        .expect("settings should load");
+        assert_eq!(
+            settings.sources.project_file,
+            Some(project_dir.join("shell-sorter.toml"))
+        );
+        assert!(!settings.sources.user_config_loaded);
+        assert_eq!(settings.machine_name, "Project Sorter");
+        assert_eq!(settings.port, 9000);
+        assert_eq!(settings.esphome_hostname, "project.local");
+        assert_eq!(settings.host, "127.0.0.1");
+
+        // The user config file beats the project file
+        let user_config_path = temp_dir.path().join("user.json");
+        let user_config = UserConfig {
+            esphome_hostname: "user.local".to_string(),
+            ..UserConfig::default()
+        };
+        fs::write(
+            &user_config_path,
+            serde_json::to_string(&user_config).expect("user config should serialize"),
+        )
+        .expect("user config should be written");
+
+        // --config beats SHELL_SORTER_CONFIG_PATH, and env vars beat everything
+        let env_vars = HashMap::from([
+            (
+                "SHELL_SORTER_CONFIG_PATH".to_string(),
+                temp_dir.path().join("other.json").display().to_string(),
+            ),
+            ("SHELL_SORTER_PORT".to_string(), "9100".to_string()),
+        ]);
+        let settings =
+            Settings::from_sources(Some(user_config_path.clone()), &nested_dir, &env_vars)
+                .expect("settings should load");
+        assert_eq!(settings.sources.user_config_path, user_config_path);
+        assert!(settings.sources.user_config_loaded);
+        assert_eq!(settings.esphome_hostname, "user.local");
+        assert_eq!(settings.machine_name, "Project Sorter");
+        assert_eq!(settings.port, 9100);
+        assert_eq!(settings.sources.env_overrides, vec!["SHELL_SORTER_PORT"]);
+
+        // Without --config the env var picks the user config file
+        let settings =
+            Settings::from_sources(None, &nested_dir, &env_vars).expect("settings should load");
+        assert_eq!(
+            settings.sources.user_config_path,
+            temp_dir.path().join("other.json")
+        );
+        assert_eq!(settings.esphome_hostname, "project.local");
+    }
+
+    #[test]
+    fn test_relative_directories_follow_the_config_not_the_cwd() {
+        let temp_dir = tempfile::TempDir::new().expect("temp dir should be created");
+        let project_dir = temp_dir.path().join("project");
+        let nested_dir = project_dir.join("sub").join("dir");
+        fs::create_dir_all(&nested_dir).expect("nested dir should be created");
+        fs::write(
+            project_dir.join("shell-sorter.toml"),
+            "image_directory = \"../pictures\"\nmodels_directory = \"./store/models\"\n",
+        )
+        .expect("project config should be written");
+
+        // The same directories wherever in the project the command is run from
+        for start_dir in [&project_dir, &nested_dir] {
+            let settings = Settings::from_sources(
+                Some(temp_dir.path().join("missing.json")),
+                start_dir,
+                &HashMap::new(),
+            )
+            .expect("settings should load");
+            assert_eq!(settings.image_directory, temp_dir.path().join("pictures"));
+            assert_eq!(settings.data_directory, project_dir.join("data"));
+            assert_eq!(
+                settings.models_directory,
+                project_dir.join("store").join("models")
+            );
+            assert_eq!(
+                settings.references_directory,
+                project_dir.join("data").join("references")
+            );
+        }
+
+        // Without a project file, a --config path is taken from the current
+        // directory and the defaults and its data_directory sit beside it
+        let config_dir = temp_dir.path().join("config");
+        fs::create_dir_all(&config_dir).expect("config dir should be created");
+        let user_config = UserConfig {
+            data_directory: Some(PathBuf::from("./shells")),
+            ..UserConfig::default()
+        };
+        fs::write(
+            config_dir.join("user.json"),
+            serde_json::to_string(&user_config).expect("user config should serialize"),
+        )
+        .expect("user config should be written");
+        let settings = Settings::from_sources(
+            Some(PathBuf::from("config/user.json")),
+            temp_dir.path(),
+            &HashMap::new(),
+        )
+        .expect("settings should load");
+        assert_eq!(
+            settings.sources.user_config_path,
+            config_dir.join("user.json")
+        );
+        assert_eq!(settings.data_directory, config_dir.join("shells"));
+        assert_eq!(settings.image_directory, config_dir.join("images"));
+        assert_eq!(
+            settings.models_directory,
+            config_dir.join("data").join("models")
+        );
+    }
+
+    #[test]
+    fn test_project_config_rejects_unknown_keys() {
+        let temp_dir = tempfile::TempDir::new().expect("temp dir should be created");
+        fs::write(
+            temp_dir.path().join("shell-sorter.json"),
+            r#"{"machine_nmae": "typo"}"#,
+        )
+        .expect("project config should be written");
 
-        let default_settings = Settings::default();
-        assert_eq!(default_settings.base_url(), "http://127.0.0.1:8000");
+        let result = Settings::from_sources(
+            Some(temp_dir.path().join("missing.json")),
+            temp_dir.path(),
+            &HashMap::new(),
+        );
+        assert!(result.is_err());
     }
 }
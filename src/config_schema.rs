@@ -0,0 +1,922 @@
+//! Machine-readable description of the configurable settings.
+//!
+//! The tables here sit alongside [`Settings`] and [`CameraConfig`] and are the
+//! single source the config page and `/api/config/validate` work from. Defaults
+//! are read from the structs' own `Default` impls, and the tests check every
+//! field appears, so a new setting can't be added without describing it.
+
+use std::collections::BTreeMap;
+
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::camera_inventory::validate_hostname;
+use crate::camera_manager::{EspEntity, is_esphome_object_id};
+use crate::capture_overlay::OverlayCorner;
+use crate::config::{CameraConfig, Settings, ViewType};
+use crate::controller_monitor::SERVO_MAX_POSITION;
+use crate::log_buffer::LogLevel;
+use crate::measurements::{MeasurementRange, validate_ranges};
+use crate::orientation::Rotation;
+use crate::pre_capture::MAX_BUFFER_FRAMES;
+use crate::reference_sheet::MAX_SHEET_COLUMNS;
+use crate::static_assets::StaticAssetSource;
+use crate::temperature::TemperatureSensor;
+
+/// JSON type of a setting's value
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FieldType {
+    Boolean,
+    Integer,
+    Number,
+    String,
+    Path,
+    StringList,
+    /// String keys with integer values
+    IntegerMap,
+    /// Case designations with `weight_grains` and `length_mm` bounds, each a `min` and `max`
+    RangeMap,
+}
+
+/// Extra rules for string values
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FieldFormat {
+    /// A hostname, optionally with a port
+    Hostname,
+    /// `WIDTHxHEIGHT`, e.g. `1920x1080`
+    Resolution,
+    /// An ESPHome entity written as `domain/object_id`
+    EspEntity,
+    /// The object ID of an ESPHome entity, e.g. `trigger_next_case`
+    EspObjectId,
+    /// An http or https URL
+    Url,
+    /// An ESPHome temperature sensor written as `device/object_id`
+    TemperatureSensor,
+    /// A browser origin: scheme, host and optional port, e.g. `http://localhost:5173`
+    Origin,
+}
+
+/// Description of a single configurable field
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub struct ConfigField {
+    pub name: &'static str,
+    #[serde(rename = "type")]
+    pub field_type: FieldType,
+    pub default: Value,
+    /// Whether the value may be null
+    pub nullable: bool,
+    /// Smallest allowed number, or smallest map value
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub min: Option<f64>,
+    /// Largest allowed number, or largest map value
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max: Option<f64>,
+    /// The only allowed values, for enumerations
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub values: Vec<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub format: Option<FieldFormat>,
+    /// Whether a change only takes effect after the server restarts
+    pub restart_required: bool,
+    /// Environment variable that overrides the setting, if there is one
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub env_var: Option<&'static str>,
+    pub description: &'static str,
+}
+
+impl ConfigField {
+    fn new(name: &'static str, field_type: FieldType, description: &'static str) -> Self {
+        Self {
+            name,
+            field_type,
+            default: Value::Null,
+            nullable: false,
+            min: None,
+            max: None,
+            values: Vec::new(),
+            format: None,
+            restart_required: true,
+            env_var: None,
+            description,
+        }
+    }
+
+    fn range(mut self, min: Option<f64>, max: Option<f64>) -> Self {
+        self.min = min;
+        self.max = max;
+        self
+    }
+
+    fn format(mut self, format: FieldFormat) -> Self {
+        self.format = Some(format);
+        self
+    }
+
+    fn values<V: Into<Value>>(mut self, values: impl IntoIterator<Item = V>) -> Self {
+        self.values = values.into_iter().map(Into::into).collect();
+        self
+    }
+
+    fn nullable(mut self) -> Self {
+        self.nullable = true;
+        self
+    }
+
+    /// Applied as soon as it is saved
+    fn live(mut self) -> Self {
+        self.restart_required = false;
+        self
+    }
+
+    fn env(mut self, env_var: &'static str) -> Self {
+        self.env_var = Some(env_var);
+        self
+    }
+
+    /// Check a candidate value against the field's type and constraints
+    pub fn validate(&self, value: &Value) -> Result<(), String> {
+        if value.is_null() {
+            return if self.nullable {
+                Ok(())
+            } else {
+                Err("A value is required".to_string())
+            };
+        }
+        match self.field_type {
+            FieldType::Boolean => {
+                if !value.is_boolean() {
+                    return Err("Must be true or false".to_string());
+                }
+            }
+            FieldType::Integer => {
+                let Some(number) = value
+                    .as_i64()
+                    .map(|n| n as f64)
+                    .or_else(|| value.as_u64().map(|n| n as f64))
+                else {
+                    return Err("Must be a whole number".to_string());
+                };
+                self.check_range(number)?;
+                self.check_values(value)?;
+            }
+            FieldType::Number => {
+                let Some(number) = value.as_f64() else {
+                    return Err("Must be a number".to_string());
+                };
+                self.check_range(number)?;
+            }
+            FieldType::String | FieldType::Path => {
+                let Some(text) = value.as_str() else {
+                    return Err("Must be a string".to_string());
+                };
+                self.check_string(text)?;
+            }
+            FieldType::StringList => {
+                let Some(items) = value.as_array() else {
+                    return Err("Must be a list".to_string());
+                };
+                let mut seen = Vec::new();
+                for item in items {
+                    let Some(text) = item.as_str() else {
+                        return Err("Every item must be a string".to_string());
+                    };
+                    self.check_string(text)?;
+                    let key = text.trim().to_ascii_lowercase();
+                    if seen.contains(&key) {
+                        return Err(format!("Duplicate entry: {}", text.trim()));
+                    }
+                    seen.push(key);
+                }
+            }
+            FieldType::IntegerMap => {
+                let Some(entries) = value.as_object() else {
+                    return Err("Must be an object".to_string());
+                };
+                for (key, value) in entries {
+                    let Some(number) = value.as_i64() else {
+                        return Err(format!("{key} must be a whole number"));
+                    };
+                    self.check_range(number as f64)
+                        .map_err(|e| format!("{key}: {e}"))?;
+                }
+            }
+            FieldType::RangeMap => {
+                let ranges: BTreeMap<String, MeasurementRange> =
+                    serde_json::from_value(value.clone()).map_err(|e| e.to_string())?;
+                validate_ranges(&ranges).map_err(|e| e.to_string())?;
+            }
+        }
+        Ok(())
+    }
+
+    fn check_range(&self, number: f64) -> Result<(), String> {
+        match (self.min, self.max) {
+            (Some(min), _) if number < min => Err(format!("Must be at least {min}")),
+            (_, Some(max)) if number > max => Err(format!("Must be at most {max}")),
+            _ => Ok(()),
+        }
+    }
+
+    fn check_values(&self, value: &Value) -> Result<(), String> {
+        if self.values.is_empty() || self.values.contains(value) {
+            return Ok(());
+        }
+        let allowed = self
+            .values
+            .iter()
+            .map(|allowed| match allowed {
+                Value::String(text) => text.clone(),
+                other => other.to_string(),
+            })
+            .collect::<Vec<_>>();
+        Err(format!("Must be one of: {}", allowed.join(", ")))
+    }
+
+    fn check_string(&self, text: &str) -> Result<(), String> {
+        self.check_values(&Value::from(text))?;
+        match self.format {
+            Some(FieldFormat::Hostname) => validate_hostname(text)
+                .map_err(|_| format!("{text:?} is not a hostname, e.g. esp32cam1.local")),
+            Some(FieldFormat::Resolution) => {
+                let valid = text.split_once('x').is_some_and(|(width, height)| {
+                    [width, height]
+                        .iter()
+                        .all(|side| side.parse::<u32>().is_ok_and(|side| side > 0))
+                });
+                if valid {
+                    Ok(())
+                } else {
+                    Err("Must be WIDTHxHEIGHT, e.g. 1920x1080".to_string())
+                }
+            }
+            Some(FieldFormat::EspEntity) => text
+                .parse::<EspEntity>()
+                .map(|_| ())
+                .map_err(|_| format!("{text:?} is not domain/object_id, e.g. select/framesize")),
+            Some(FieldFormat::EspObjectId) if is_esphome_object_id(text) => Ok(()),
+            Some(FieldFormat::EspObjectId) => Err(format!(
+                "{text:?} is not an ESPHome object ID, e.g. trigger_next_case"
+            )),
+            Some(FieldFormat::TemperatureSensor) => {
+                text.parse::<TemperatureSensor>().map(|_| ()).map_err(|_| {
+                    format!(
+                        "{text:?} is not device/object_id, e.g. esp32cam1.local/esp_temperature"
+                    )
+                })
+            }
+            Some(FieldFormat::Origin) => crate::cors::parse_origin(text)
+                .map(|_| ())
+                .map_err(|_| format!("{text:?} is not an origin, e.g. http://localhost:5173")),
+            Some(FieldFormat::Url) => match reqwest::Url::parse(text) {
+                Ok(url) if matches!(url.scheme(), "http" | "https") => Ok(()),
+                _ => Err(format!(
+                    "{text:?} is not an http or https URL, e.g. http://sorter.local:8000"
+                )),
+            },
+            None => Ok(()),
+        }
+    }
+}
+
+/// The full schema served by `GET /api/config/schema`
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub struct ConfigSchema {
+    /// Machine-wide settings
+    pub settings: Vec<ConfigField>,
+    /// Per-camera settings
+    pub camera: Vec<ConfigField>,
+}
+
+/// Describe every setting and per-camera setting
+pub fn config_schema() -> ConfigSchema {
+    ConfigSchema {
+        settings: with_defaults(settings_fields(), &Settings::default()),
+        camera: with_defaults(camera_fields(), &CameraConfig::default()),
+    }
+}
+
+/// Look up a machine-wide setting by name
+pub fn settings_field(name: &str) -> Option<ConfigField> {
+    config_schema()
+        .settings
+        .into_iter()
+        .find(|field| field.name == name)
+}
+
+/// Look up a per-camera setting by name
+pub fn camera_field(name: &str) -> Option<ConfigField> {
+    config_schema()
+        .camera
+        .into_iter()
+        .find(|field| field.name == name)
+}
+
+/// Fill in each field's default from the serialised defaults
+fn with_defaults<T: Serialize>(fields: Vec<ConfigField>, defaults: &T) -> Vec<ConfigField> {
+    let defaults = serde_json::to_value(defaults).unwrap_or_default();
+    fields
+        .into_iter()
+        .map(|mut field| {
+            field.default = defaults.get(field.name).cloned().unwrap_or_default();
+            field
+        })
+        .collect()
+}
+
+fn settings_fields() -> Vec<ConfigField> {
+    use FieldType::*;
+    vec![
+        ConfigField::new("host", String, "Address the web server listens on")
+            .env("SHELL_SORTER_HOST"),
+        ConfigField::new("port", Integer, "Port the web server listens on")
+            .range(Some(1.0), Some(65535.0))
+            .env("SHELL_SORTER_PORT"),
+        ConfigField::new(
+            "server_tls",
+            Boolean,
+            "Clients reach the server over HTTPS, through a TLS proxy on the host and port",
+        )
+        .env("SHELL_SORTER_SERVER_TLS"),
+        ConfigField::new(
+            "server_url",
+            String,
+            "Server the CLI talks to, e.g. https://sorter.local, instead of one built from host and port",
+        )
+        .nullable()
+        .format(FieldFormat::Url)
+        .env("SHELL_SORTER_SERVER_URL"),
+        ConfigField::new("debug", Boolean, "Enable debug mode").env("SHELL_SORTER_DEBUG"),
+        ConfigField::new("machine_name", String, "Name shown on the dashboard")
+            .env("SHELL_SORTER_MACHINE_NAME"),
+        ConfigField::new(
+            "ui_language",
+            String,
+            "Language of API messages, such as de, for requests whose Accept-Language names none with a catalogue",
+        )
+        .nullable()
+        .env("SHELL_SORTER_UI_LANGUAGE"),
+        ConfigField::new("cameras", StringList, "Camera device paths"),
+        ConfigField::new("camera_count", Integer, "Number of cameras on the machine")
+            .range(Some(1.0), None)
+            .env("SHELL_SORTER_CAMERA_COUNT"),
+        ConfigField::new("camera_resolution", String, "Camera resolution")
+            .format(FieldFormat::Resolution)
+            .env("SHELL_SORTER_CAMERA_RESOLUTION"),
+        ConfigField::new(
+            "image_directory",
+            Path,
+            "Where captured training images are stored",
+        ),
+        ConfigField::new(
+            "data_directory",
+            Path,
+            "Where shell data and uploads are stored",
+        ),
+        ConfigField::new("models_directory", Path, "Where trained models are stored"),
+        ConfigField::new(
+            "references_directory",
+            Path,
+            "Where reference images are stored",
+        ),
+        ConfigField::new("ml_enabled", Boolean, "Enable ML case identification")
+            .env("SHELL_SORTER_ML_ENABLED"),
+        ConfigField::new(
+            "confidence_threshold",
+            Number,
+            "Minimum confidence for an ML identification",
+        )
+        .range(Some(0.0), Some(1.0))
+        .env("SHELL_SORTER_CONFIDENCE_THRESHOLD"),
+        ConfigField::new("model_name", String, "Active ML model")
+            .nullable()
+            .env("SHELL_SORTER_MODEL_NAME"),
+        ConfigField::new(
+            "supported_case_types",
+            StringList,
+            "Ammunition case types the sorter knows",
+        ),
+        ConfigField::new(
+            "esphome_hostname",
+            String,
+            "Hostname of the ESPHome controller",
+        )
+        .format(FieldFormat::Hostname)
+        .live()
+        .env("SHELL_SORTER_ESPHOME_HOSTNAME"),
+        ConfigField::new(
+            "network_camera_hostnames",
+            StringList,
+            "ESPHome camera hostnames to detect",
+        )
+        .format(FieldFormat::Hostname),
+        ConfigField::new(
+            "auto_detect_cameras",
+            Boolean,
+            "Detect and configure cameras on startup",
+        )
+        .env("SHELL_SORTER_AUTO_DETECT_CAMERAS"),
+        ConfigField::new(
+            "auto_start_esp32_cameras",
+            Boolean,
+            "Start selected ESP32 cameras when they come online",
+        )
+        .env("SHELL_SORTER_AUTO_START_ESP32_CAMERAS"),
+        ConfigField::new(
+            "image_jpeg_quality",
+            Integer,
+            "JPEG quality for converted PNG and HEIC images",
+        )
+        .range(Some(1.0), Some(100.0))
+        .env("SHELL_SORTER_IMAGE_JPEG_QUALITY"),
+        ConfigField::new(
+            "max_upload_mb",
+            Integer,
+            "Largest image upload accepted, in megabytes",
+        )
+        .range(Some(1.0), Some(1024.0))
+        .env("SHELL_SORTER_MAX_UPLOAD_MB"),
+        ConfigField::new(
+            "max_concurrent_streams",
+            Integer,
+            "Live streams allowed per camera",
+        )
+        .range(Some(1.0), None)
+        .env("SHELL_SORTER_MAX_CONCURRENT_STREAMS"),
+        ConfigField::new(
+            "heavy_work_permits",
+            Integer,
+            "Composite batches, training runs, disk usage scans and reference sheets run at once",
+        )
+        .range(Some(1.0), None)
+        .env("SHELL_SORTER_HEAVY_WORK_PERMITS"),
+        ConfigField::new(
+            "min_region_area",
+            Integer,
+            "Fewest pixels a camera's region of interest may cover",
+        )
+        .range(Some(0.0), None)
+        .env("SHELL_SORTER_MIN_REGION_AREA"),
+        ConfigField::new(
+            "stream_stall_seconds",
+            Integer,
+            "Seconds without a frame before an open stream counts as stalled",
+        )
+        .range(Some(1.0), None)
+        .env("SHELL_SORTER_STREAM_STALL_SECONDS"),
+        ConfigField::new(
+            "esp_snapshot_poll_ms",
+            Integer,
+            "Milliseconds between snapshots of an ESPHome camera without a stream",
+        )
+        .range(Some(1.0), None)
+        .env("SHELL_SORTER_ESP_SNAPSHOT_POLL_MS"),
+        ConfigField::new(
+            "camera_stale_seconds",
+            Integer,
+            "Seconds a network camera may stay offline before it is flagged as stale",
+        )
+        .range(Some(0.0), None)
+        .env("SHELL_SORTER_CAMERA_STALE_SECONDS"),
+        ConfigField::new(
+            "capture_warmup_frames",
+            Integer,
+            "Frames a USB camera discards after opening while auto-exposure settles",
+        )
+        .range(Some(0.0), None)
+        .env("SHELL_SORTER_CAPTURE_WARMUP_FRAMES"),
+        ConfigField::new(
+            "capture_warmup_ms",
+            Integer,
+            "Milliseconds a USB camera keeps discarding frames after opening",
+        )
+        .range(Some(0.0), None)
+        .env("SHELL_SORTER_CAPTURE_WARMUP_MS"),
+        ConfigField::new(
+            "pre_capture_buffer_frames",
+            Integer,
+            "Recent frames kept per streaming USB camera so a capture can use one from before it was triggered; 0 always captures live",
+        )
+        .range(Some(0.0), Some(MAX_BUFFER_FRAMES as f64))
+        .env("SHELL_SORTER_PRE_CAPTURE_BUFFER_FRAMES"),
+        ConfigField::new(
+            "capture_offset_ms",
+            Integer,
+            "How many milliseconds before the capture request the buffered frame was taken",
+        )
+        .range(Some(0.0), None)
+        .env("SHELL_SORTER_CAPTURE_OFFSET_MS"),
+        ConfigField::new(
+            "capture_overlay",
+            Boolean,
+            "Stamp captured images with the capture time, camera and session",
+        )
+        .env("SHELL_SORTER_CAPTURE_OVERLAY"),
+        ConfigField::new(
+            "capture_overlay_corner",
+            String,
+            "Corner of captured images the overlay is drawn in",
+        )
+        .values(OverlayCorner::NAMES)
+        .env("SHELL_SORTER_CAPTURE_OVERLAY_CORNER"),
+        ConfigField::new(
+            "cli_cache_max_age_seconds",
+            Integer,
+            "Seconds the CLI keeps cached server responses for when the server is unreachable",
+        )
+        .range(Some(0.0), None)
+        .env("SHELL_SORTER_CLI_CACHE_MAX_AGE_SECONDS"),
+        ConfigField::new(
+            "hardware_request_timeout_ms",
+            Integer,
+            "Milliseconds an API request waits on the controller or cameras before giving up",
+        )
+        .range(Some(1.0), None)
+        .env("SHELL_SORTER_HARDWARE_REQUEST_TIMEOUT_MS"),
+        ConfigField::new(
+            "detect_freshness_s",
+            Integer,
+            "Seconds a camera detection is reused for instead of scanning again, 0 to always scan",
+        )
+        .range(Some(0.0), None)
+        .env("SHELL_SORTER_DETECT_FRESHNESS_S"),
+        ConfigField::new(
+            "data_usage_refresh_seconds",
+            Integer,
+            "Seconds between background rescans of data disk usage, 0 to only scan on request",
+        )
+        .range(Some(0.0), None)
+        .env("SHELL_SORTER_DATA_USAGE_REFRESH_SECONDS"),
+        ConfigField::new(
+            "controller_health_retention_hours",
+            Integer,
+            "Hours of controller health checks kept for the status page's uptime chart",
+        )
+        .range(Some(1.0), None)
+        .env("SHELL_SORTER_CONTROLLER_HEALTH_RETENTION_HOURS"),
+        ConfigField::new(
+            "trash_retention_days",
+            Integer,
+            "Days deleted shells stay in the trash before they are purged for good, 0 to purge at the next sweep",
+        )
+        .range(Some(0.0), None)
+        .env("SHELL_SORTER_TRASH_RETENTION_DAYS"),
+        ConfigField::new(
+            "session_claim_seconds",
+            Integer,
+            "Seconds a tagging claim on a session lasts unless the tagging page renews it",
+        )
+        .range(Some(1.0), None)
+        .env("SHELL_SORTER_SESSION_CLAIM_SECONDS"),
+        ConfigField::new(
+            "session_claims_path",
+            Path,
+            "Keep tagging claims in this file across restarts; only in memory when unset",
+        )
+        .nullable()
+        .env("SHELL_SORTER_SESSION_CLAIMS_PATH"),
+        ConfigField::new(
+            "min_free_disk_mb",
+            Integer,
+            "Free disk space needed before captures and training start",
+        )
+        .range(Some(0.0), None)
+        .env("SHELL_SORTER_MIN_FREE_DISK_MB"),
+        ConfigField::new(
+            "training_excluded_flags",
+            StringList,
+            "Shells with any of these flags are left out of training",
+        )
+        .env("SHELL_SORTER_TRAINING_EXCLUDED_FLAGS"),
+        ConfigField::new(
+            "measurement_ranges",
+            RangeMap,
+            "Expected weight in grains and length in mm by case designation; measurements outside them warn",
+        ),
+        ConfigField::new(
+            "reference_sheet_columns",
+            Integer,
+            "Columns in the case type reference sheet",
+        )
+        .range(Some(1.0), Some(f64::from(MAX_SHEET_COLUMNS)))
+        .env("SHELL_SORTER_REFERENCE_SHEET_COLUMNS"),
+        ConfigField::new(
+            "esphome_camera_entities",
+            StringList,
+            "ESPHome camera entities exposed through the settings passthrough",
+        )
+        .format(FieldFormat::EspEntity)
+        .env("SHELL_SORTER_ESPHOME_CAMERA_ENTITIES"),
+        ConfigField::new(
+            "servo_positions",
+            IntegerMap,
+            "Servo positions in percent by ESPHome number entity, saved by calibration",
+        )
+        .range(Some(0.0), Some(f64::from(SERVO_MAX_POSITION)))
+        .live(),
+        ConfigField::new(
+            "controller_next_case_button",
+            String,
+            "ESPHome button on the controller that feeds the next case",
+        )
+        .format(FieldFormat::EspObjectId)
+        .env("SHELL_SORTER_CONTROLLER_NEXT_CASE_BUTTON"),
+        ConfigField::new(
+            "controller_case_ready_sensor",
+            String,
+            "ESPHome binary sensor that is on while a case waits to be fed",
+        )
+        .format(FieldFormat::EspObjectId)
+        .env("SHELL_SORTER_CONTROLLER_CASE_READY_SENSOR"),
+        ConfigField::new(
+            "controller_case_in_view_sensor",
+            String,
+            "ESPHome binary sensor that is on while a case is under the cameras",
+        )
+        .format(FieldFormat::EspObjectId)
+        .env("SHELL_SORTER_CONTROLLER_CASE_IN_VIEW_SENSOR"),
+        ConfigField::new(
+            "controller_vibration_switch",
+            String,
+            "ESPHome switch for the vibration motor",
+        )
+        .format(FieldFormat::EspObjectId)
+        .env("SHELL_SORTER_CONTROLLER_VIBRATION_SWITCH"),
+        ConfigField::new(
+            "controller_flash_light",
+            String,
+            "ESPHome light for the flash; unset on machines without one",
+        )
+        .format(FieldFormat::EspObjectId)
+        .nullable()
+        .env("SHELL_SORTER_CONTROLLER_FLASH_LIGHT"),
+        ConfigField::new(
+            "controller_record_path",
+            Path,
+            "Record every controller request and response to this JSONL file",
+        )
+        .nullable()
+        .env("SHELL_SORTER_CONTROLLER_RECORD_PATH"),
+        ConfigField::new(
+            "controller_replay_path",
+            Path,
+            "Replay controller responses from this recording instead of the network",
+        )
+        .nullable()
+        .env("SHELL_SORTER_CONTROLLER_REPLAY_PATH"),
+        ConfigField::new(
+            "controller_replay_speed",
+            Number,
+            "How many times faster than recorded a replay runs",
+        )
+        .range(Some(0.001), None)
+        .env("SHELL_SORTER_CONTROLLER_REPLAY_SPEED"),
+        ConfigField::new(
+            "controller_retry_window_s",
+            Integer,
+            "Seconds after the controller last answered that commands failing to connect are retried, 0 to never retry",
+        )
+        .range(Some(0.0), None)
+        .env("SHELL_SORTER_CONTROLLER_RETRY_WINDOW_S"),
+        ConfigField::new(
+            "maintenance_auto_expire_min",
+            Integer,
+            "Minutes before maintenance mode turns itself off, 0 to wait for it to be turned off",
+        )
+        .range(Some(0.0), None)
+        .env("SHELL_SORTER_MAINTENANCE_AUTO_EXPIRE_MIN"),
+        ConfigField::new(
+            "esphome_temperature_entities",
+            StringList,
+            "ESPHome temperature sensors read with every controller health check",
+        )
+        .format(FieldFormat::TemperatureSensor)
+        .env("SHELL_SORTER_ESPHOME_TEMPERATURE_ENTITIES"),
+        ConfigField::new(
+            "temperature_warning_c",
+            Number,
+            "Temperature in °C at which a sensor warns",
+        )
+        .env("SHELL_SORTER_TEMPERATURE_WARNING_C"),
+        ConfigField::new(
+            "overheat_pauses_automation",
+            Boolean,
+            "Turn maintenance mode on when a temperature sensor warns",
+        )
+        .env("SHELL_SORTER_OVERHEAT_PAUSES_AUTOMATION"),
+        ConfigField::new(
+            "log_buffer_capacity",
+            Integer,
+            "Log entries kept in memory for the diagnostics panel",
+        )
+        .range(Some(0.0), None)
+        .env("SHELL_SORTER_LOG_BUFFER_CAPACITY"),
+        ConfigField::new(
+            "log_buffer_level",
+            String,
+            "Least severe log level kept for the diagnostics panel",
+        )
+        .values(LogLevel::NAMES)
+        .env("SHELL_SORTER_LOG_BUFFER_LEVEL"),
+        ConfigField::new(
+            "cors_allowed_origins",
+            StringList,
+            "Origins allowed to call /api from a browser, e.g. a dev server on http://localhost:5173; empty to refuse cross-origin requests",
+        )
+        .format(FieldFormat::Origin)
+        .env("SHELL_SORTER_CORS_ALLOWED_ORIGINS"),
+        ConfigField::new(
+            "static_assets",
+            String,
+            "Serve CSS and JavaScript from shell_sorter/static (directory), the copy built into the binary (embedded), or the directory when it exists (auto)",
+        )
+        .values(StaticAssetSource::NAMES)
+        .env("SHELL_SORTER_STATIC_ASSETS"),
+        ConfigField::new(
+            "read_only",
+            Boolean,
+            "Refuse requests that change data and stop background writes, for serving a copy of a dataset",
+        )
+        .env("SHELL_SORTER_READ_ONLY"),
+    ]
+}
+
+fn camera_fields() -> Vec<ConfigField> {
+    use FieldType::*;
+    let region = |name, description| {
+        ConfigField::new(name, Integer, description)
+            .nullable()
+            .range(Some(0.0), None)
+            .live()
+    };
+    let resolution = |name, description| {
+        ConfigField::new(name, Integer, description)
+            .nullable()
+            .range(Some(1.0), None)
+            .live()
+    };
+    vec![
+        ConfigField::new("nickname", String, "Friendly name for the camera")
+            .nullable()
+            .live(),
+        ConfigField::new("position", String, "Where the camera sits on the machine")
+            .nullable()
+            .live(),
+        ConfigField::new("view_type", String, "What the camera sees of the case")
+            .nullable()
+            .values(ViewType::ALL.iter().map(ToString::to_string))
+            .live(),
+        ConfigField::new(
+            "rotation",
+            Integer,
+            "Clockwise rotation in degrees for a camera that isn't mounted upright",
+        )
+        .values(Rotation::DEGREES)
+        .live(),
+        ConfigField::new("mirror", Boolean, "Mirror left to right, after rotating").live(),
+        ConfigField::new(
+            "capture_overlay",
+            Boolean,
+            "Stamp this camera's captures with the time, camera and session; unset follows the setting",
+        )
+        .nullable()
+        .live(),
+        region("region_x", "Left edge of the region of interest"),
+        region("region_y", "Top edge of the region of interest"),
+        region("region_width", "Width of the region of interest"),
+        region("region_height", "Height of the region of interest"),
+        resolution(
+            "region_frame_width",
+            "Width of the frame the region of interest was drawn on",
+        ),
+        resolution(
+            "region_frame_height",
+            "Height of the frame the region of interest was drawn on",
+        ),
+        resolution(
+            "detected_resolution_width",
+            "Resolution width detected from an ESPHome camera",
+        ),
+        resolution(
+            "detected_resolution_height",
+            "Resolution height detected from an ESPHome camera",
+        ),
+        resolution("manual_resolution_width", "Resolution width set by hand"),
+        resolution("manual_resolution_height", "Resolution height set by hand"),
+        ConfigField::new(
+            "resolution_detection_timestamp",
+            String,
+            "When the resolution was detected, as an RFC 3339 time",
+        )
+        .nullable()
+        .live(),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn field_names<T: Serialize>(value: &T) -> Vec<std::string::String> {
+        let mut names: Vec<_> = serde_json::to_value(value)
+            .expect("defaults should serialise")
+            .as_object()
+            .expect("defaults should be an object")
+            .keys()
+            .cloned()
+            .collect();
+        names.sort();
+        names
+    }
+
+    fn schema_names(fields: &[ConfigField]) -> Vec<std::string::String> {
+        let mut names: Vec<_> = fields.iter().map(|field| field.name.to_string()).collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn test_schema_covers_every_field() {
+        let schema = config_schema();
+        assert_eq!(
+            schema_names(&schema.settings),
+            field_names(&Settings::default())
+        );
+        assert_eq!(
+            schema_names(&schema.camera),
+            field_names(&CameraConfig::default())
+        );
+
+        // Non-nullable defaults pass their own constraints
+        for field in schema.settings.iter().chain(&schema.camera) {
+            if let Err(e) = field.validate(&field.default) {
+                assert!(field.nullable, "default for {} is invalid: {e}", field.name);
+            }
+        }
+    }
+
+    #[test]
+    fn test_validate_field_constraints() {
+        let port = settings_field("port").expect("port should be described");
+        assert!(port.validate(&serde_json::json!(8080)).is_ok());
+        assert!(port.validate(&serde_json::json!(0)).is_err());
+        assert!(port.validate(&serde_json::json!("8080")).is_err());
+
+        let hostnames =
+            settings_field("network_camera_hostnames").expect("hostnames should be described");
+        assert!(
+            hostnames
+                .validate(&serde_json::json!(["esp32cam1.local", "10.0.0.5:8080"]))
+                .is_ok()
+        );
+        assert!(
+            hostnames
+                .validate(&serde_json::json!(["bad host"]))
+                .is_err()
+        );
+        assert!(
+            hostnames
+                .validate(&serde_json::json!(["cam.local", "CAM.local"]))
+                .is_err()
+        );
+
+        let view_type = config_schema()
+            .camera
+            .into_iter()
+            .find(|field| field.name == "view_type")
+            .expect("view type should be described");
+        assert_eq!(view_type.values, vec!["side", "tail", "mouth", "unknown"]);
+        assert!(view_type.validate(&serde_json::json!("side")).is_ok());
+        assert!(view_type.validate(&serde_json::json!("top")).is_err());
+        assert!(view_type.validate(&Value::Null).is_ok());
+
+        let servos = settings_field("servo_positions").expect("servos should be described");
+        assert!(
+            servos
+                .validate(&serde_json::json!({"feeder": 101}))
+                .is_err()
+        );
+
+        let ranges =
+            settings_field("measurement_ranges").expect("measurement ranges should be described");
+        assert!(
+            ranges
+                .validate(&serde_json::json!({"380acp": {"weight_grains": {"min": 45, "max": 52}}}))
+                .is_ok()
+        );
+        assert!(
+            ranges
+                .validate(&serde_json::json!({"380acp": {"weight_grains": {"min": 52, "max": 45}}}))
+                .is_err()
+        );
+        assert!(
+            ranges
+                .validate(&serde_json::json!({"380acp": {"weight": {"min": 45, "max": 52}}}))
+                .is_err()
+        );
+    }
+}
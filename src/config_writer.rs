@@ -0,0 +1,306 @@
+//! Coalescing writer for the user config file.
+//!
+//! The web server changes the user config from many handlers, sometimes several
+//! times a second (the dashboard sends a camera selection per checkbox). Rather
+//! than each handler reading, modifying and synchronously rewriting the file,
+//! changes are sent to a single task that keeps the current config in memory,
+//! collects changes for a short window and then writes the file once, via a
+//! temporary file and rename so a reader never sees it half written.
+//!
+//! A read-only server keeps the config [`ConfigWriter::in_memory`]: changes are
+//! applied and answered as usual but the file is never touched.
+
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use tokio::sync::{mpsc, oneshot};
+use tracing::{debug, error, info};
+
+use crate::config::UserConfig;
+use crate::etag::DataVersion;
+use crate::protocol::request;
+use crate::{OurError, OurResult};
+
+/// How long changes are collected before the file is written
+pub const DEFAULT_COALESCE_WINDOW: Duration = Duration::from_millis(250);
+
+/// Name used in errors about the config writer's channel
+const WRITER_NAME: &str = "Config writer";
+
+/// A change to apply to the user config
+pub type ConfigMutation = Box<dyn FnOnce(&mut UserConfig) + Send>;
+
+/// Requests handled by the config writer task
+pub enum ConfigWriterRequest {
+    Mutate(ConfigMutation),
+    Current {
+        respond_to: oneshot::Sender<UserConfig>,
+    },
+    Flush {
+        respond_to: oneshot::Sender<Result<(), String>>,
+    },
+}
+
+/// Handle for queueing changes to the user config
+#[derive(Clone)]
+pub struct ConfigWriterHandle {
+    request_sender: mpsc::UnboundedSender<ConfigWriterRequest>,
+    writes: Arc<AtomicU64>,
+    version: DataVersion,
+}
+
+impl ConfigWriterHandle {
+    /// Queue a change; it is written to disk with any others made in the same window
+    pub fn mutate(&self, mutation: impl FnOnce(&mut UserConfig) + Send + 'static) -> OurResult<()> {
+        self.request_sender
+            .send(ConfigWriterRequest::Mutate(Box::new(mutation)))
+            .map_err(|_| OurError::App("Config writer channel closed".to_string()))
+    }
+
+    /// The user config including changes not yet written
+    pub async fn current(&self) -> OurResult<UserConfig> {
+        request(&self.request_sender, WRITER_NAME, |respond_to| {
+            ConfigWriterRequest::Current { respond_to }
+        })
+        .await
+    }
+
+    /// Write any pending changes now
+    pub async fn flush(&self) -> OurResult<()> {
+        request(&self.request_sender, WRITER_NAME, |respond_to| {
+            ConfigWriterRequest::Flush { respond_to }
+        })
+        .await?
+        .map_err(OurError::App)
+    }
+
+    /// Queue a change and wait for it to be written, for callers that report save failures
+    pub async fn update(
+        &self,
+        mutation: impl FnOnce(&mut UserConfig) + Send + 'static,
+    ) -> OurResult<()> {
+        self.mutate(mutation)?;
+        self.flush().await
+    }
+
+    /// Bumped whenever a change is applied to the in-memory config
+    pub fn version(&self) -> u64 {
+        self.version.get()
+    }
+
+    /// Number of times the file has been written
+    pub fn write_count(&self) -> u64 {
+        self.writes.load(Ordering::Relaxed)
+    }
+}
+
+/// Task owning the in-memory user config and its file
+pub struct ConfigWriter {
+    path: PathBuf,
+    config: UserConfig,
+    dirty: bool,
+    /// Write changes to `path`; off, they only live in memory
+    persist: bool,
+    window: Duration,
+    request_receiver: mpsc::UnboundedReceiver<ConfigWriterRequest>,
+    writes: Arc<AtomicU64>,
+    version: DataVersion,
+}
+
+impl ConfigWriter {
+    /// Create a writer for the config file at `path`, starting from `config`
+    pub fn new(path: PathBuf, config: UserConfig, window: Duration) -> (Self, ConfigWriterHandle) {
+        let (request_sender, request_receiver) = mpsc::unbounded_channel();
+        let writes = Arc::new(AtomicU64::new(0));
+        let version = DataVersion::default();
+
+        let writer = Self {
+            path,
+            config,
+            dirty: false,
+            persist: true,
+            window,
+            request_receiver,
+            writes: writes.clone(),
+            version: version.clone(),
+        };
+        let handle = ConfigWriterHandle {
+            request_sender,
+            writes,
+            version,
+        };
+        (writer, handle)
+    }
+
+    /// Keep changes in memory and never write the file
+    pub fn in_memory(mut self) -> Self {
+        self.persist = false;
+        self
+    }
+
+    /// Process requests until every handle is dropped, writing anything still pending
+    pub async fn run(mut self) {
+        info!("Starting config writer for {}", self.path.display());
+
+        while let Some(request) = self.request_receiver.recv().await {
+            let mut flush_waiters = Vec::new();
+            self.handle_request(request, &mut flush_waiters);
+
+            // Collect further changes for a while before writing
+            if self.dirty && flush_waiters.is_empty() {
+                let deadline = tokio::time::Instant::now() + self.window;
+                while let Ok(Some(request)) =
+                    tokio::time::timeout_at(deadline, self.request_receiver.recv()).await
+                {
+                    self.handle_request(request, &mut flush_waiters);
+                    if !flush_waiters.is_empty() {
+                        break;
+                    }
+                }
+            }
+
+            let result = self.write_if_dirty().await;
+            for waiter in flush_waiters {
+                if waiter.send(result.clone()).is_err() {
+                    debug!("Config flush requester went away");
+                }
+            }
+        }
+
+        if let Err(e) = self.write_if_dirty().await {
+            error!("Failed to write user config on shutdown: {e}");
+        }
+    }
+
+    fn handle_request(
+        &mut self,
+        request: ConfigWriterRequest,
+        flush_waiters: &mut Vec<oneshot::Sender<Result<(), String>>>,
+    ) {
+        match request {
+            ConfigWriterRequest::Mutate(mutation) => {
+                mutation(&mut self.config);
+                self.dirty = true;
+                self.version.bump();
+            }
+            ConfigWriterRequest::Current { respond_to } => {
+                if respond_to.send(self.config.clone()).is_err() {
+                    debug!("Config requester went away");
+                }
+            }
+            ConfigWriterRequest::Flush { respond_to } => flush_waiters.push(respond_to),
+        }
+    }
+
+    async fn write_if_dirty(&mut self) -> Result<(), String> {
+        if !self.dirty {
+            return Ok(());
+        }
+        if !self.persist {
+            self.dirty = false;
+            debug!("Kept user config changes in memory only");
+            return Ok(());
+        }
+        let result = self.write().await.map_err(|e| e.to_string());
+        match &result {
+            Ok(()) => {
+                self.dirty = false;
+                self.writes.fetch_add(1, Ordering::Relaxed);
+                debug!("Saved user config to {}", self.path.display());
+            }
+            Err(e) => error!("Failed to save user config to {}: {e}", self.path.display()),
+        }
+        result
+    }
+
+    async fn write(&self) -> OurResult<()> {
+        if let Some(parent) = self.path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+        let contents = serde_json::to_string_pretty(&self.config)?;
+
+        let file_name = self
+            .path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .ok_or_else(|| OurError::App(format!("{} has no file name", self.path.display())))?;
+        let temp_path = self.path.with_file_name(format!(".{file_name}.tmp"));
+
+        tokio::fs::write(&temp_path, contents).await?;
+        if let Err(e) = tokio::fs::rename(&temp_path, &self.path).await {
+            tokio::fs::remove_file(&temp_path).await.ok();
+            return Err(e.into());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Settings;
+
+    #[tokio::test]
+    async fn test_rapid_mutations_are_coalesced() {
+        let temp_dir = tempfile::TempDir::new().expect("temp dir should be created");
+        let path = temp_dir.path().join("shell-sorter.json");
+        let (writer, handle) = ConfigWriter::new(
+            path.clone(),
+            UserConfig::default(),
+            Duration::from_millis(100),
+        );
+        let task = tokio::spawn(writer.run());
+
+        for i in 0..50 {
+            handle
+                .mutate(move |config| {
+                    config.set_selected_cameras(vec![format!("camera-{i}").into()])
+                })
+                .expect("mutation should queue");
+        }
+        handle.flush().await.expect("flush should succeed");
+
+        assert!(
+            handle.write_count() <= 2,
+            "{} writes for 50 mutations",
+            handle.write_count()
+        );
+        let saved = Settings::load_user_config_from(&path);
+        assert_eq!(saved.selected_cameras, vec!["camera-49".into()]);
+
+        // Pending changes are written when the last handle is dropped
+        handle
+            .mutate(|config| config.set_selected_cameras(Vec::new()))
+            .expect("mutation should queue");
+        drop(handle);
+        task.await.expect("writer should finish");
+        assert!(
+            Settings::load_user_config_from(&path)
+                .selected_cameras
+                .is_empty()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_in_memory_writer_never_writes_the_file() {
+        let temp_dir = tempfile::TempDir::new().expect("temp dir should be created");
+        let path = temp_dir.path().join("shell-sorter.json");
+        let (writer, handle) =
+            ConfigWriter::new(path.clone(), UserConfig::default(), Duration::ZERO);
+        let task = tokio::spawn(writer.in_memory().run());
+
+        handle
+            .update(|config| config.set_selected_cameras(vec!["camera-1".into()]))
+            .await
+            .expect("update should succeed");
+        let current = handle.current().await.expect("config should be answered");
+        assert_eq!(current.selected_cameras, vec!["camera-1".into()]);
+        assert_eq!(handle.write_count(), 0);
+
+        drop(handle);
+        task.await.expect("writer should finish");
+        assert!(!path.exists());
+    }
+}
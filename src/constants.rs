@@ -1,2 +1,6 @@
 pub(crate) const USB_DEVICE_PREFIX: &str = "usb";
 pub(crate) const USB_DEVICE_PREFIX_WITH_COLON: &str = "usb:";
+
+/// HTTP basic auth credentials for the ESPHome controller's web server
+pub(crate) const ESPHOME_USERNAME: &str = "admin";
+pub(crate) const ESPHOME_PASSWORD: &str = "shellsorter";
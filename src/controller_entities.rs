@@ -0,0 +1,204 @@
+//! The ESPHome entities the sorter drives, and checking the controller has them.
+//!
+//! Every URL the sorter calls is built from an entity name in the controller's
+//! ESPHome config. When the controller is reflashed with renamed entities those
+//! requests just 404, so the controller monitor asks for each entity it needs
+//! when it starts, after the settings change and whenever the controller comes
+//! back online. Anything missing marks the firmware as incompatible, and
+//! commands that need a missing entity fail straight away and name it. The
+//! names are settings, so a machine built from custom YAML can remap them.
+
+use std::fmt;
+
+use chrono::{DateTime, Utc};
+use reqwest::StatusCode;
+use serde::Serialize;
+use tracing::{info, warn};
+
+use crate::config::Settings;
+use crate::controller_recording::ControllerTransport;
+
+/// An ESPHome entity on the controller, written as `domain/object_id`
+#[derive(Debug, Clone, PartialEq, Eq, serde_with::SerializeDisplay)]
+pub struct ControllerEntity {
+    pub domain: &'static str,
+    pub object_id: String,
+}
+
+impl ControllerEntity {
+    pub fn new(domain: &'static str, object_id: &str) -> Self {
+        Self {
+            domain,
+            object_id: object_id.to_string(),
+        }
+    }
+
+    /// The number entity for a servo
+    pub fn servo(servo: &str) -> Self {
+        Self::new("number", servo)
+    }
+
+    /// URL of `action` on this entity, e.g. `press` or `set?value=50`
+    pub fn url(&self, hostname: &str, action: &str) -> String {
+        format!("http://{hostname}/{self}/{action}")
+    }
+}
+
+impl fmt::Display for ControllerEntity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.domain, self.object_id)
+    }
+}
+
+/// The entities the sorter drives, as named in the controller's ESPHome config
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ControllerEntities {
+    pub next_case_button: ControllerEntity,
+    pub case_ready_sensor: ControllerEntity,
+    pub case_in_view_sensor: ControllerEntity,
+    pub vibration_switch: ControllerEntity,
+    /// Unset on machines without a flash
+    pub flash_light: Option<ControllerEntity>,
+    pub servos: Vec<ControllerEntity>,
+}
+
+impl ControllerEntities {
+    pub fn from_settings(settings: &Settings) -> Self {
+        Self {
+            next_case_button: ControllerEntity::new(
+                "button",
+                &settings.controller_next_case_button,
+            ),
+            case_ready_sensor: ControllerEntity::new(
+                "binary_sensor",
+                &settings.controller_case_ready_sensor,
+            ),
+            case_in_view_sensor: ControllerEntity::new(
+                "binary_sensor",
+                &settings.controller_case_in_view_sensor,
+            ),
+            vibration_switch: ControllerEntity::new(
+                "switch",
+                &settings.controller_vibration_switch,
+            ),
+            flash_light: settings
+                .controller_flash_light
+                .as_deref()
+                .map(|light| ControllerEntity::new("light", light)),
+            servos: settings
+                .servo_positions
+                .keys()
+                .map(|servo| ControllerEntity::servo(servo))
+                .collect(),
+        }
+    }
+
+    /// Every entity the firmware must have
+    pub fn required(&self) -> Vec<ControllerEntity> {
+        [
+            &self.next_case_button,
+            &self.case_ready_sensor,
+            &self.case_in_view_sensor,
+            &self.vibration_switch,
+        ]
+        .into_iter()
+        .chain(&self.flash_light)
+        .chain(&self.servos)
+        .cloned()
+        .collect()
+    }
+}
+
+/// Whether the controller's firmware has every entity the sorter needs
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FirmwareState {
+    /// Not checked yet, or the controller couldn't be reached
+    #[default]
+    Unknown,
+    Compatible,
+    /// At least one entity is missing
+    Incompatible,
+}
+
+impl fmt::Display for FirmwareState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            FirmwareState::Unknown => "unknown",
+            FirmwareState::Compatible => "compatible",
+            FirmwareState::Incompatible => "incompatible",
+        };
+        f.write_str(name)
+    }
+}
+
+/// The result of the last entity check
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub struct FirmwareCheck {
+    pub state: FirmwareState,
+    pub missing_entities: Vec<ControllerEntity>,
+    pub checked_at: Option<DateTime<Utc>>,
+}
+
+impl FirmwareCheck {
+    /// Refuse to use `entity` if the last check found it missing
+    pub fn require(&self, entity: &ControllerEntity) -> Result<(), String> {
+        if self.missing_entities.contains(entity) {
+            Err(format!(
+                "Controller firmware has no {entity} entity; flash firmware that has it or change the entity name in the settings"
+            ))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// Ask the controller at `hostname` for each of `entities`
+///
+/// A 404 means the firmware doesn't have the entity. Returns `None` if any
+/// other answer came back, since then nothing can be said about the firmware.
+pub async fn check(
+    transport: &ControllerTransport,
+    hostname: &str,
+    entities: &[ControllerEntity],
+) -> Option<FirmwareCheck> {
+    let mut missing_entities = Vec::new();
+    for entity in entities {
+        let url = format!("http://{hostname}/{entity}");
+        match transport.send("GET", &url).await {
+            Ok(reply) if reply.status == StatusCode::NOT_FOUND => {
+                missing_entities.push(entity.clone())
+            }
+            Ok(reply) if reply.status.is_success() => {}
+            Ok(reply) => {
+                warn!(
+                    "Couldn't check controller entity {entity}: HTTP error: {}",
+                    reply.status
+                );
+                return None;
+            }
+            Err(e) => {
+                warn!("Couldn't check controller entity {entity}: {e}");
+                return None;
+            }
+        }
+    }
+
+    let state = if missing_entities.is_empty() {
+        info!("Controller firmware has all {} entities", entities.len());
+        FirmwareState::Compatible
+    } else {
+        let names: Vec<String> = missing_entities.iter().map(ToString::to_string).collect();
+        warn!(
+            "Controller firmware is incompatible, missing: {}",
+            names.join(", ")
+        );
+        FirmwareState::Incompatible
+    };
+    Some(FirmwareCheck {
+        state,
+        missing_entities,
+        checked_at: Some(Utc::now()),
+    })
+}
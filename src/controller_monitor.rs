@@ -4,44 +4,383 @@
 //! and communicates with the web server using oneshot channels for request/response patterns.
 
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::sync::Arc;
 use std::sync::RwLock;
 use std::time::{Duration, Instant};
 use tokio::sync::RwLock as AsyncRwLock;
-use tokio::sync::{mpsc, oneshot};
+use tokio::sync::{Notify, broadcast, mpsc, oneshot};
 use tokio::time::{interval, sleep};
 use tracing::{debug, error, info, warn};
 
 use crate::config::Settings;
+use crate::controller_entities::{
+    self, ControllerEntities, ControllerEntity, FirmwareCheck, FirmwareState,
+};
+use crate::controller_recording::{
+    ControllerMode, ControllerReply, ControllerTransport, is_connection_error,
+};
+use crate::event_log::EventRecorder;
+use crate::health_history::{HealthHistory, HealthSample};
+use crate::protocol::{request, request_with_timeout};
+use crate::self_test::{self, SelfTestOptions, SelfTestReport, StepOutcome};
+use crate::temperature::{
+    Overheated, TemperatureReading, TemperatureSensor, TemperatureWatch, parse_sensor_state,
+};
 use crate::{OurError, OurResult};
 
+const MANAGER_NAME: &str = "Controller monitor";
+
+/// A point in time, monotonic for working out ages and wall clock for reporting it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Moment {
+    pub instant: Instant,
+    pub at: chrono::DateTime<chrono::Utc>,
+}
+
+impl Moment {
+    pub fn now() -> Self {
+        Self {
+            instant: Instant::now(),
+            at: chrono::Utc::now(),
+        }
+    }
+
+    /// Whole seconds since this moment, unaffected by changes to the system clock
+    pub fn age_seconds(&self) -> u64 {
+        self.instant.elapsed().as_secs()
+    }
+}
+
 /// Controller status information
 #[derive(Debug, Clone, Default)]
 pub struct ControllerStatus {
     pub online: bool,
     pub hostname: String,
-    pub last_seen: Option<Instant>,
+    /// When the controller last answered a health check or command
+    pub last_seen: Option<Moment>,
+    /// When the last health check ran, whether or not the controller answered
+    pub last_check: Option<Moment>,
     pub response_time_ms: Option<u64>,
     pub error_count: u32,
     pub uptime_seconds: Option<u64>,
+    /// Whether the firmware has every entity the sorter uses
+    pub firmware: FirmwareCheck,
+    /// The temperature sensors that answered the last health check
+    pub temperatures: Vec<TemperatureReading>,
+}
+
+impl ControllerStatus {
+    /// When the controller was last seen and checked, for the API
+    pub fn times(&self) -> ControllerTimes {
+        ControllerTimes {
+            last_seen: self.last_seen.map(|moment| moment.at),
+            last_seen_age_seconds: self.last_seen.map(|moment| moment.age_seconds()),
+            last_check: self.last_check.map(|moment| moment.at),
+            last_check_age_seconds: self.last_check.map(|moment| moment.age_seconds()),
+        }
+    }
+}
+
+/// When the controller last answered and was last checked
+///
+/// The ages are worked out on the server, so the web UI doesn't compare the
+/// times with a clock that may disagree with the server's.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub struct ControllerTimes {
+    pub last_seen: Option<chrono::DateTime<chrono::Utc>>,
+    pub last_seen_age_seconds: Option<u64>,
+    pub last_check: Option<chrono::DateTime<chrono::Utc>>,
+    pub last_check_age_seconds: Option<u64>,
+}
+
+/// Whether a controller answered at a hostname
+#[derive(Debug, Clone, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub struct ControllerProbe {
+    pub hostname: String,
+    pub reachable: bool,
+    pub response_time_ms: Option<u64>,
+    /// Why the controller couldn't be reached
+    pub error: Option<String>,
+}
+
+impl ControllerProbe {
+    /// The last health check of the configured controller, without contacting it
+    pub fn from_status(status: &ControllerStatus) -> Self {
+        Self {
+            hostname: status.hostname.clone(),
+            reachable: status.online,
+            response_time_ms: status.response_time_ms,
+            error: None,
+        }
+    }
 }
 
 /// Sensor readings from the controller
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub struct SensorReadings {
     pub case_ready: bool,
     pub case_in_view: bool,
-    pub timestamp: u64,
+    /// When the sensors were read
+    pub timestamp: chrono::DateTime<chrono::Utc>,
 }
 
 /// Machine status information
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub struct MachineStatus {
     pub status: String,
     pub ready: bool,
     pub active_jobs: u32,
     pub last_update: chrono::DateTime<chrono::Utc>,
+    #[serde(default)]
+    pub maintenance: MaintenanceStatus,
+    /// Temperature sensors over the warning threshold
+    #[serde(default)]
+    pub overheating: Vec<TemperatureReading>,
+}
+
+/// Whether maintenance mode is on, as reported to the web UI and CLI
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub struct MaintenanceStatus {
+    pub enabled: bool,
+    /// Why it was turned on
+    pub reason: Option<String>,
+    pub since: Option<chrono::DateTime<chrono::Utc>>,
+    /// When it turns itself off, if `maintenance_auto_expire_min` is set
+    pub expires_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+/// Highest position the ESPHome servo numbers accept (they are percentages)
+pub const SERVO_MAX_POSITION: u8 = 100;
+
+/// How long a sort cycle is treated as running after NextCase.
+///
+/// The controller runs the feed sequence itself and doesn't report when it
+/// finishes, so this covers the longest sequence in the ESPHome config.
+pub const SORT_CYCLE_DURATION: Duration = Duration::from_secs(10);
+
+/// How long after the controller last answered a command that fails to connect is retried.
+///
+/// The controller drops off WiFi for a few seconds now and then; retrying
+/// over that gap saves the operator pressing the button again.
+pub const DEFAULT_RETRY_WINDOW: Duration = Duration::from_secs(30);
+
+/// Wait before the first retry, doubling up to [`MAX_RETRY_DELAY`]
+const FIRST_RETRY_DELAY: Duration = Duration::from_millis(250);
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(4);
+
+/// Why a calibration step, a sort cycle or another motion command was refused
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CalibrationError {
+    /// Maintenance mode is on, with the reason given for it
+    Maintenance(Option<String>),
+    /// Calibration can't start while the machine is feeding a case
+    SortCycleActive,
+    /// Automation is paused until calibration is saved
+    Calibrating,
+    /// The step needs calibration mode
+    NotCalibrating,
+    /// The servo isn't one of the configured servo positions
+    UnknownServo(String),
+    /// The controller rejected or didn't answer the servo move
+    Device(String),
+}
+
+impl std::fmt::Display for CalibrationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CalibrationError::Maintenance(Some(reason)) => {
+                write!(
+                    f,
+                    "Maintenance mode is on ({reason}); nothing will move until it is turned off"
+                )
+            }
+            CalibrationError::Maintenance(None) => {
+                write!(
+                    f,
+                    "Maintenance mode is on; nothing will move until it is turned off"
+                )
+            }
+            CalibrationError::SortCycleActive => {
+                write!(f, "A sort cycle is running; try again when it finishes")
+            }
+            CalibrationError::Calibrating => {
+                write!(f, "Calibration is in progress; automation is paused")
+            }
+            CalibrationError::NotCalibrating => write!(f, "Calibration mode is not active"),
+            CalibrationError::UnknownServo(servo) => write!(f, "Unknown servo '{servo}'"),
+            CalibrationError::Device(e) => write!(f, "Failed to move servo: {e}"),
+        }
+    }
+}
+
+/// Servo positions tracked during calibration.
+///
+/// ESPHome numbers only take absolute values, so the last position sent to
+/// each servo is kept here and jogs are applied relative to it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CalibrationSession {
+    positions: BTreeMap<String, u8>,
+}
+
+impl CalibrationSession {
+    /// Start from the positions the servos have been moved to
+    pub fn new(positions: BTreeMap<String, u8>) -> Self {
+        Self { positions }
+    }
+
+    /// Current position of each servo
+    pub fn positions(&self) -> &BTreeMap<String, u8> {
+        &self.positions
+    }
+
+    /// Position a jog of `delta` moves `servo` to, kept within the servo's range
+    pub fn jog_target(&self, servo: &str, delta: i16) -> Result<u8, CalibrationError> {
+        let current = self
+            .positions
+            .get(servo)
+            .ok_or_else(|| CalibrationError::UnknownServo(servo.to_string()))?;
+        let target =
+            (i32::from(*current) + i32::from(delta)).clamp(0, i32::from(SERVO_MAX_POSITION));
+        Ok(u8::try_from(target).unwrap_or(SERVO_MAX_POSITION))
+    }
+
+    /// Record that `servo` has been moved to `position`
+    pub fn record(&mut self, servo: &str, position: u8) -> Result<(), CalibrationError> {
+        let current = self
+            .positions
+            .get_mut(servo)
+            .ok_or_else(|| CalibrationError::UnknownServo(servo.to_string()))?;
+        *current = position;
+        Ok(())
+    }
+}
+
+/// Whether the machine is running automation or paused for calibration or maintenance
+#[derive(Debug, Clone, Default)]
+pub struct MachineState {
+    last_cycle_started: Option<Instant>,
+    calibration: Option<CalibrationSession>,
+    maintenance: MaintenanceStatus,
+}
+
+impl MachineState {
+    /// Maintenance mode as it stands
+    pub fn maintenance(&self) -> &MaintenanceStatus {
+        &self.maintenance
+    }
+
+    /// Refuse anything that moves the machine while maintenance mode is on
+    pub fn check_motion_allowed(&self) -> Result<(), CalibrationError> {
+        if self.maintenance.enabled {
+            Err(CalibrationError::Maintenance(
+                self.maintenance.reason.clone(),
+            ))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Turn maintenance mode on, or replace its reason and expiry if it already is
+    pub fn enter_maintenance(
+        &mut self,
+        reason: Option<String>,
+        now: chrono::DateTime<chrono::Utc>,
+        auto_expire: Option<chrono::Duration>,
+    ) -> &MaintenanceStatus {
+        self.maintenance = MaintenanceStatus {
+            enabled: true,
+            reason,
+            since: Some(self.maintenance.since.unwrap_or(now)),
+            expires_at: auto_expire.and_then(|expire| now.checked_add_signed(expire)),
+        };
+        &self.maintenance
+    }
+
+    /// Turn maintenance mode off, returning whether it was on
+    pub fn leave_maintenance(&mut self) -> bool {
+        std::mem::take(&mut self.maintenance).enabled
+    }
+
+    /// Turn maintenance mode off if its time is up, returning the status it had
+    pub fn expire_maintenance(
+        &mut self,
+        now: chrono::DateTime<chrono::Utc>,
+    ) -> Option<MaintenanceStatus> {
+        if self
+            .maintenance
+            .expires_at
+            .is_some_and(|expires_at| now >= expires_at)
+        {
+            Some(std::mem::take(&mut self.maintenance))
+        } else {
+            None
+        }
+    }
+
+    /// Whether a sort cycle started recently enough to still be running
+    pub fn sort_cycle_active(&self, now: Instant) -> bool {
+        self.last_cycle_started
+            .is_some_and(|started| now.saturating_duration_since(started) < SORT_CYCLE_DURATION)
+    }
+
+    /// The calibration session, if calibration mode is active
+    pub fn calibration(&self) -> Option<&CalibrationSession> {
+        self.calibration.as_ref()
+    }
+
+    /// The calibration session, for applying a jog
+    pub fn calibration_mut(&mut self) -> Result<&mut CalibrationSession, CalibrationError> {
+        self.calibration
+            .as_mut()
+            .ok_or(CalibrationError::NotCalibrating)
+    }
+
+    /// Note that a sort cycle started, unless automation is paused
+    pub fn start_sort_cycle(&mut self, now: Instant) -> Result<(), CalibrationError> {
+        self.check_motion_allowed()?;
+        if self.calibration.is_some() {
+            return Err(CalibrationError::Calibrating);
+        }
+        self.last_cycle_started = Some(now);
+        Ok(())
+    }
+
+    /// Pause automation and start tracking servo positions from `positions`
+    pub fn start_calibration(
+        &mut self,
+        positions: BTreeMap<String, u8>,
+        now: Instant,
+    ) -> Result<&CalibrationSession, CalibrationError> {
+        self.check_motion_allowed()?;
+        if self.sort_cycle_active(now) {
+            return Err(CalibrationError::SortCycleActive);
+        }
+        Ok(self
+            .calibration
+            .get_or_insert_with(|| CalibrationSession::new(positions)))
+    }
+
+    /// Leave calibration mode, returning the positions to save
+    pub fn finish_calibration(&mut self) -> Result<BTreeMap<String, u8>, CalibrationError> {
+        self.calibration
+            .take()
+            .map(|session| session.positions)
+            .ok_or(CalibrationError::NotCalibrating)
+    }
+}
+
+/// Calibration mode and servo positions, as reported to the web UI
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub struct CalibrationStatus {
+    pub active: bool,
+    pub positions: BTreeMap<String, u8>,
 }
 
 /// Commands that can be sent to the controller
@@ -52,19 +391,118 @@ pub enum ControllerCommand {
     GetSensors,
     GetHardwareStatus,
     TriggerVibration,
-    SetServoPosition { servo: String, position: u8 },
-    UpdateConfig { new_settings: Box<Settings> },
+    SetServoPosition {
+        servo: String,
+        position: u8,
+    },
+    UpdateConfig {
+        new_settings: Box<Settings>,
+    },
+    StartCalibration,
+    JogServo {
+        servo: String,
+        delta: i16,
+    },
+    GetCalibration,
+    SaveCalibration,
+    SelfTest(SelfTestOptions),
+    /// Check whether a controller answers at `hostname`, without switching to it
+    Probe {
+        hostname: String,
+    },
+    /// Turn maintenance mode on or off
+    SetMaintenance {
+        enabled: bool,
+        reason: Option<String>,
+    },
+}
+
+impl ControllerCommand {
+    /// The command variant name, used as the event kind
+    pub fn kind(&self) -> &'static str {
+        match self {
+            ControllerCommand::NextCase => "NextCase",
+            ControllerCommand::GetStatus => "GetStatus",
+            ControllerCommand::GetSensors => "GetSensors",
+            ControllerCommand::GetHardwareStatus => "GetHardwareStatus",
+            ControllerCommand::TriggerVibration => "TriggerVibration",
+            ControllerCommand::SetServoPosition { .. } => "SetServoPosition",
+            ControllerCommand::UpdateConfig { .. } => "UpdateConfig",
+            ControllerCommand::StartCalibration => "StartCalibration",
+            ControllerCommand::JogServo { .. } => "JogServo",
+            ControllerCommand::GetCalibration => "GetCalibration",
+            ControllerCommand::SaveCalibration => "SaveCalibration",
+            ControllerCommand::SelfTest(_) => "SelfTest",
+            ControllerCommand::Probe { .. } => "Probe",
+            ControllerCommand::SetMaintenance { .. } => "SetMaintenance",
+        }
+    }
+
+    /// Whether the command can move the machine, so is refused in maintenance mode
+    pub fn moves_machine(&self) -> bool {
+        match self {
+            ControllerCommand::NextCase
+            | ControllerCommand::TriggerVibration
+            | ControllerCommand::SetServoPosition { .. }
+            | ControllerCommand::StartCalibration
+            | ControllerCommand::JogServo { .. }
+            | ControllerCommand::SaveCalibration => true,
+            ControllerCommand::SelfTest(options) => options.trigger_next_case,
+            ControllerCommand::GetStatus
+            | ControllerCommand::GetSensors
+            | ControllerCommand::GetHardwareStatus
+            | ControllerCommand::UpdateConfig { .. }
+            | ControllerCommand::GetCalibration
+            | ControllerCommand::Probe { .. }
+            | ControllerCommand::SetMaintenance { .. } => false,
+        }
+    }
+
+    /// Compact rendering of the command, leaving out the full settings payload
+    pub fn summary(&self) -> String {
+        match self {
+            ControllerCommand::SetServoPosition { servo, position } => {
+                format!("SetServoPosition {{ servo: {servo:?}, position: {position} }}")
+            }
+            ControllerCommand::JogServo { servo, delta } => {
+                format!("JogServo {{ servo: {servo:?}, delta: {delta} }}")
+            }
+            ControllerCommand::Probe { hostname } => format!("Probe {{ hostname: {hostname:?} }}"),
+            ControllerCommand::SetMaintenance { enabled, reason } => {
+                format!("SetMaintenance {{ enabled: {enabled}, reason: {reason:?} }}")
+            }
+            ControllerCommand::SelfTest(options) => format!(
+                "SelfTest {{ trigger_next_case: {}, step_timeout_ms: {} }}",
+                options.trigger_next_case,
+                options.step_timeout.as_millis()
+            ),
+            ControllerCommand::UpdateConfig { new_settings } => format!(
+                "UpdateConfig {{ esphome_hostname: {:?} }}",
+                new_settings.esphome_hostname
+            ),
+            other => other.kind().to_string(),
+        }
+    }
 }
 
 /// Responses from controller operations
 #[derive(Debug, Clone)]
 pub enum ControllerResponse {
-    Success(String),
+    /// A command reached the controller, after `attempts` tries
+    Sent {
+        message: String,
+        attempts: u32,
+    },
     SensorData(SensorReadings),
     StatusData(MachineStatus),
     HardwareData(HashMap<String, String>),
     Error(String),
     ConfigUpdated,
+    Calibration(CalibrationStatus),
+    CalibrationFailed(CalibrationError),
+    SelfTest(Box<SelfTestReport>),
+    Probe(ControllerProbe),
+    Maintenance(MaintenanceStatus),
 }
 
 /// Request structure for communication with the controller monitor
@@ -79,7 +517,14 @@ pub struct ControllerMonitor {
     settings: Arc<RwLock<Settings>>,
     status: Arc<AsyncRwLock<ControllerStatus>>,
     request_receiver: mpsc::UnboundedReceiver<ControllerRequest>,
-    client: reqwest::Client,
+    transport: ControllerTransport,
+    mode: ControllerMode,
+    events: EventRecorder,
+    machine: MachineState,
+    /// Wakes the health check to check the firmware's entities again
+    recheck: Arc<Notify>,
+    health_history: HealthHistory,
+    overheats: broadcast::Sender<Overheated>,
 }
 
 /// Handle for communicating with the controller monitor
@@ -87,6 +532,8 @@ pub struct ControllerMonitor {
 pub struct ControllerHandle {
     request_sender: mpsc::UnboundedSender<ControllerRequest>,
     status: Arc<AsyncRwLock<ControllerStatus>>,
+    health_history: HealthHistory,
+    overheats: broadcast::Sender<Overheated>,
 }
 
 impl ControllerHandle {
@@ -96,25 +543,22 @@ impl ControllerHandle {
     }
 
     /// Update the controller configuration
-    pub async fn update_config(&self, new_settings: Settings) -> Result<(), String> {
-        let (response_sender, response_receiver) = oneshot::channel();
-
-        let request = ControllerRequest {
-            command: ControllerCommand::UpdateConfig {
-                new_settings: Box::new(new_settings),
-            },
-            response_sender,
-        };
-
-        if self.request_sender.send(request).is_err() {
-            return Err("Controller monitor is not running".to_string());
-        }
-
-        match response_receiver.await {
-            Ok(ControllerResponse::ConfigUpdated) => Ok(()),
-            Ok(ControllerResponse::Error(e)) => Err(e),
-            Ok(_) => Err("Unexpected response from controller monitor".to_string()),
-            Err(_) => Err("Failed to receive response from controller monitor".to_string()),
+    pub async fn update_config(&self, new_settings: Settings) -> OurResult<()> {
+        let response = request(&self.request_sender, MANAGER_NAME, |response_sender| {
+            ControllerRequest {
+                command: ControllerCommand::UpdateConfig {
+                    new_settings: Box::new(new_settings),
+                },
+                response_sender,
+            }
+        })
+        .await?;
+        match response {
+            ControllerResponse::ConfigUpdated => Ok(()),
+            ControllerResponse::Error(e) => Err(OurError::App(e)),
+            _ => Err(OurError::App(format!(
+                "Unexpected response from {MANAGER_NAME}"
+            ))),
         }
     }
 
@@ -122,6 +566,16 @@ impl ControllerHandle {
     pub async fn get_status(&self) -> ControllerStatus {
         self.lock_status().await.clone()
     }
+
+    /// The record of the controller's health checks
+    pub fn health_history(&self) -> &HealthHistory {
+        &self.health_history
+    }
+
+    /// Temperature sensors reaching the warning threshold, once per excursion
+    pub fn subscribe_overheats(&self) -> broadcast::Receiver<Overheated> {
+        self.overheats.subscribe()
+    }
 }
 
 impl ControllerMonitor {
@@ -150,7 +604,10 @@ impl ControllerMonitor {
     }
 
     /// Create a new controller monitor and return a handle for communication
-    pub fn new(settings: Settings) -> Result<(Self, ControllerHandle), Box<dyn std::error::Error>> {
+    pub fn new(
+        settings: Settings,
+        events: EventRecorder,
+    ) -> Result<(Self, ControllerHandle), Box<dyn std::error::Error>> {
         let (request_sender, request_receiver) = mpsc::unbounded_channel();
 
         let settings = Arc::new(RwLock::new(settings.clone()));
@@ -164,25 +621,48 @@ impl ControllerMonitor {
             online: false,
             hostname,
             last_seen: None,
+            last_check: None,
             response_time_ms: None,
             error_count: 0,
             uptime_seconds: None,
+            firmware: FirmwareCheck::default(),
+            temperatures: Vec::new(),
         }));
 
-        let client = reqwest::Client::builder()
-            .timeout(Duration::from_secs(15))
-            .build()?;
+        let (mode, transport, health_history) = {
+            let settings = settings.read().map_err(|_| "Settings lock poisoned")?;
+            let mode = ControllerMode::from_settings(&settings)?;
+            let transport = ControllerTransport::new(&mode, settings.controller_replay_speed)?;
+            let health_history = HealthHistory::new(Duration::from_secs(
+                settings
+                    .controller_health_retention_hours
+                    .saturating_mul(3600),
+            ));
+            (mode, transport, health_history)
+        };
+        if mode != ControllerMode::Live {
+            warn!("Controller monitor running in {mode} mode");
+        }
+        let overheats = broadcast::channel(16).0;
 
         let monitor = Self {
             settings,
             status: status.clone(),
             request_receiver,
-            client,
+            transport,
+            mode,
+            events,
+            machine: MachineState::default(),
+            recheck: Arc::new(Notify::new()),
+            health_history: health_history.clone(),
+            overheats: overheats.clone(),
         };
 
         let handle = ControllerHandle {
             request_sender,
             status,
+            health_history,
+            overheats,
         };
 
         Ok((monitor, handle))
@@ -199,24 +679,109 @@ impl ControllerMonitor {
 
         // Start periodic health check
         let health_check_status = self.status.clone();
-        let health_check_client = self.client.clone();
+        let health_check_transport = self.transport.clone();
         let health_check_settings = self.settings.clone();
-
-        tokio::spawn(async move {
+        let recheck = self.recheck.clone();
+        let health_history = self.health_history.clone();
+        let health_check_events = self.events.clone();
+        let overheats = self.overheats.clone();
+        let mut overheat_receiver = self.overheats.subscribe();
+        // A replay only holds the requests that were recorded
+        let check_entities = !matches!(self.mode, ControllerMode::Replay(_));
+
+        // Dropping the set aborts the health check when the monitor stops or is cancelled
+        let mut background = tokio::task::JoinSet::new();
+        background.spawn(async move {
             let mut interval = interval(Duration::from_secs(30));
+            let mut was_online = false;
+            let mut watch = TemperatureWatch::default();
             loop {
-                interval.tick().await;
-                let hostname = {
+                tokio::select! {
+                    _ = interval.tick() => {}
+                    _ = recheck.notified() => {}
+                }
+                let (hostname, entities, sensors, warning_c) = {
                     match health_check_settings.read() {
-                        Ok(settings) => settings.esphome_hostname.clone(),
+                        Ok(settings) => (
+                            settings.esphome_hostname.clone(),
+                            ControllerEntities::from_settings(&settings),
+                            settings.esphome_temperature_entities.clone(),
+                            settings.temperature_warning_c,
+                        ),
                         Err(_) => {
                             tracing::error!("Settings lock poisoned in health check");
                             return;
                         }
                     }
                 };
-                Self::perform_health_check(&health_check_client, &hostname, &health_check_status)
-                    .await;
+                let online = Self::perform_health_check(
+                    &health_check_transport,
+                    &hostname,
+                    &health_check_status,
+                )
+                .await;
+                let temperatures = if check_entities {
+                    Self::read_temperatures(&health_check_transport, &sensors).await
+                } else {
+                    Vec::new()
+                };
+                watch.retain(&sensors);
+                let mut readings = Vec::with_capacity(temperatures.len());
+                for (sensor, celsius) in temperatures {
+                    let read_at = chrono::Utc::now();
+                    if let Some(overheated) = watch.observe(&sensor, celsius, warning_c, read_at) {
+                        warn!("Overheating: {}", overheated.message());
+                        health_check_events.record(
+                            "controller",
+                            "Overheated",
+                            format!(
+                                "Overheated {{ sensor: \"{}\", celsius: {:.1}, threshold_c: {:.1} }}",
+                                overheated.sensor, overheated.celsius, overheated.threshold_c
+                            ),
+                        );
+                        // Nobody listening is fine; the status still shows it
+                        overheats.send(overheated).ok();
+                    }
+                    readings.push(TemperatureReading {
+                        overheated: watch.is_overheated(&sensor),
+                        sensor,
+                        celsius,
+                        read_at,
+                    });
+                }
+                let max_temperature_c = readings
+                    .iter()
+                    .map(|reading| reading.celsius)
+                    .reduce(f64::max);
+
+                let response_ms = {
+                    let mut status = health_check_status.write().await;
+                    status.temperatures = readings;
+                    status.response_time_ms
+                };
+                health_history.record(HealthSample {
+                    timestamp: chrono::Utc::now(),
+                    online,
+                    response_ms,
+                    max_temperature_c,
+                });
+
+                // Reflashing restarts the controller, so it's checked every time it comes back
+                let unchecked =
+                    health_check_status.read().await.firmware.state == FirmwareState::Unknown;
+                if online
+                    && check_entities
+                    && (!was_online || unchecked)
+                    && let Some(firmware) = controller_entities::check(
+                        &health_check_transport,
+                        &hostname,
+                        &entities.required(),
+                    )
+                    .await
+                {
+                    health_check_status.write().await.firmware = firmware;
+                }
+                was_online = online;
             }
         });
 
@@ -226,6 +791,9 @@ impl ControllerMonitor {
                 Some(request) = self.request_receiver.recv() => {
                     self.handle_request(request).await;
                 }
+                Ok(overheated) = overheat_receiver.recv() => {
+                    self.pause_for_overheating(&overheated);
+                }
                 else => {
                     warn!("Controller monitor request channel closed, shutting down");
                     break;
@@ -237,19 +805,60 @@ impl ControllerMonitor {
     }
 
     /// Handle a single request from the web server
-    async fn handle_request(&self, request: ControllerRequest) {
+    async fn handle_request(&mut self, request: ControllerRequest) {
+        self.events.record(
+            "controller",
+            request.command.kind(),
+            request.command.summary(),
+        );
+        if let Some(expired) = self.machine.expire_maintenance(chrono::Utc::now()) {
+            warn!(
+                "Maintenance mode ({}) expired, motion commands are allowed again",
+                expired.reason.as_deref().unwrap_or("no reason given")
+            );
+            self.events.record(
+                "controller",
+                "MaintenanceExpired",
+                format!("MaintenanceExpired {{ reason: {:?} }}", expired.reason),
+            );
+        }
+        if request.command.moves_machine()
+            && let Err(e) = self.machine.check_motion_allowed()
+        {
+            warn!("Refused {} in maintenance mode", request.command.kind());
+            if request
+                .response_sender
+                .send(ControllerResponse::CalibrationFailed(e))
+                .is_err()
+            {
+                debug!("Failed to send maintenance refusal back to web server");
+            }
+            return;
+        }
         let response = match request.command {
-            ControllerCommand::NextCase => self.trigger_next_case().await,
+            ControllerCommand::NextCase => self.trigger_next_case(&request.response_sender).await,
             ControllerCommand::GetStatus => self.get_machine_status().await,
             ControllerCommand::GetSensors => self.get_sensor_readings().await,
             ControllerCommand::GetHardwareStatus => self.get_hardware_status().await,
-            ControllerCommand::TriggerVibration => self.trigger_vibration().await,
+            ControllerCommand::TriggerVibration => {
+                self.trigger_vibration(&request.response_sender).await
+            }
             ControllerCommand::SetServoPosition { servo, position } => {
-                self.set_servo_position(&servo, position).await
+                self.set_servo_position(&servo, position, &request.response_sender)
+                    .await
             }
             ControllerCommand::UpdateConfig { new_settings } => {
                 self.update_config(*new_settings).await
             }
+            ControllerCommand::StartCalibration => self.start_calibration().await,
+            ControllerCommand::JogServo { servo, delta } => self.jog_servo(&servo, delta).await,
+            ControllerCommand::GetCalibration => self.calibration_status(),
+            ControllerCommand::SaveCalibration => self.save_calibration(),
+            ControllerCommand::SelfTest(options) => self.self_test(&options).await,
+            ControllerCommand::Probe { hostname } => self.probe(hostname).await,
+            ControllerCommand::SetMaintenance { enabled, reason } => {
+                self.set_maintenance(enabled, reason)
+            }
         };
 
         if let Err(err) = request.response_sender.send(response) {
@@ -270,10 +879,15 @@ impl ControllerMonitor {
             }
         };
 
-        // Update settings
+        // Update settings, keeping the servo positions: they only change through
+        // calibration, and the sender's copy of the settings may predate the last save
         {
             match self.lock_settings_write() {
-                Ok(mut settings) => *settings = new_settings,
+                Ok(mut settings) => {
+                    let servo_positions = std::mem::take(&mut settings.servo_positions);
+                    *settings = new_settings;
+                    settings.servo_positions = servo_positions;
+                }
                 Err(e) => {
                     return ControllerResponse::Error(format!("Failed to write settings: {e}"));
                 }
@@ -289,6 +903,10 @@ impl ControllerMonitor {
             }
         };
 
+        // The entity names or the controller itself may have changed
+        self.lock_status_write().await.firmware = FirmwareCheck::default();
+        self.recheck.notify_one();
+
         // Update status with new hostname if it changed
         if old_hostname != new_hostname {
             {
@@ -296,6 +914,7 @@ impl ControllerMonitor {
                 status.hostname = new_hostname.clone();
                 status.online = false; // Reset online status until next health check
                 status.last_seen = None;
+                status.last_check = None;
                 status.response_time_ms = None;
                 status.error_count = 0;
             }
@@ -310,41 +929,126 @@ impl ControllerMonitor {
     }
 
     /// Trigger the next case sequence on the controller
-    async fn trigger_next_case(&self) -> ControllerResponse {
-        let hostname = {
-            match self.lock_settings_read() {
-                Ok(settings) => settings.esphome_hostname.clone(),
-                Err(e) => {
-                    return ControllerResponse::Error(format!("Failed to read settings: {e}"));
-                }
-            }
+    ///
+    /// Other requests, another NextCase included, wait behind any retries, and
+    /// the web server's idempotency keys see a single result however many
+    /// attempts it took.
+    async fn trigger_next_case(
+        &mut self,
+        caller: &oneshot::Sender<ControllerResponse>,
+    ) -> ControllerResponse {
+        if self.machine.calibration().is_some() {
+            return ControllerResponse::Error(CalibrationError::Calibrating.to_string());
+        }
+        let url = match self
+            .entity_url(|entities| entities.next_case_button, "press")
+            .await
+        {
+            Ok(url) => url,
+            Err(e) => return ControllerResponse::Error(e),
         };
 
-        let url = format!("http://{hostname}/button/trigger_next_case/press");
-
-        match self.make_request(&url, "POST").await {
-            Ok(_) => {
+        match self.send_command_request(&url, caller).await {
+            (Ok(_), attempts) => {
+                if let Err(e) = self.machine.start_sort_cycle(Instant::now()) {
+                    warn!("Sort cycle started during calibration: {e}");
+                }
                 info!("Successfully triggered next case sequence");
-                ControllerResponse::Success("Next case sequence triggered".to_string())
+                ControllerResponse::Sent {
+                    message: "Next case sequence triggered".to_string(),
+                    attempts,
+                }
             }
-            Err(e) => {
+            (Err(e), attempts) => {
                 error!("Failed to trigger next case: {e}");
-                ControllerResponse::Error(format!("Failed to trigger next case: {e}"))
+                ControllerResponse::Error(format!(
+                    "Failed to trigger next case{}: {e}",
+                    after_attempts(attempts)
+                ))
+            }
+        }
+    }
+
+    /// Turn maintenance mode on or off; turning it on again replaces the reason
+    fn set_maintenance(&mut self, enabled: bool, reason: Option<String>) -> ControllerResponse {
+        if !enabled {
+            if self.machine.leave_maintenance() {
+                info!("Maintenance mode turned off");
+            }
+            return ControllerResponse::Maintenance(self.machine.maintenance().clone());
+        }
+
+        let auto_expire_min = match self.lock_settings_read() {
+            Ok(settings) => settings.maintenance_auto_expire_min,
+            Err(e) => return ControllerResponse::Error(format!("Failed to read settings: {e}")),
+        };
+        let auto_expire = i64::try_from(auto_expire_min)
+            .ok()
+            .filter(|minutes| *minutes > 0)
+            .and_then(chrono::Duration::try_minutes);
+        let status = self
+            .machine
+            .enter_maintenance(reason, chrono::Utc::now(), auto_expire)
+            .clone();
+        warn!(
+            "Maintenance mode turned on ({}), motion commands are refused",
+            status.reason.as_deref().unwrap_or("no reason given")
+        );
+        ControllerResponse::Maintenance(status)
+    }
+
+    /// Turn maintenance mode on for an overheating sensor, if `overheat_pauses_automation` is set
+    fn pause_for_overheating(&mut self, overheated: &Overheated) {
+        let pauses = match self.lock_settings_read() {
+            Ok(settings) => settings.overheat_pauses_automation,
+            Err(e) => {
+                error!("Failed to read settings: {e}");
+                return;
             }
+        };
+        if !pauses || self.machine.maintenance().enabled {
+            return;
         }
+        let reason = format!("Overheating: {}", overheated.message());
+        warn!("{reason}; maintenance mode turned on, motion commands are refused");
+        self.events.record(
+            "controller",
+            "OverheatPause",
+            format!(
+                "OverheatPause {{ sensor: {:?} }}",
+                overheated.sensor.to_string()
+            ),
+        );
+        self.machine
+            .enter_maintenance(Some(reason), chrono::Utc::now(), None);
     }
 
     /// Get machine status from the controller
     async fn get_machine_status(&self) -> ControllerResponse {
+        let calibrating = self.machine.calibration().is_some();
+        let maintenance = self.machine.maintenance().clone();
         let status = MachineStatus {
-            status: if self.is_online().await {
+            status: if maintenance.enabled {
+                "Maintenance".to_string()
+            } else if calibrating {
+                "Calibrating".to_string()
+            } else if self.is_online().await {
                 "Ready".to_string()
             } else {
                 "Offline".to_string()
             },
-            ready: self.is_online().await,
+            ready: !maintenance.enabled && !calibrating && self.is_online().await,
             active_jobs: 0,
             last_update: chrono::Utc::now(),
+            maintenance,
+            overheating: self
+                .lock_status()
+                .await
+                .temperatures
+                .iter()
+                .filter(|reading| reading.overheated)
+                .cloned()
+                .collect(),
         };
 
         ControllerResponse::StatusData(status)
@@ -354,21 +1058,18 @@ impl ControllerMonitor {
     async fn get_sensor_readings(&self) -> ControllerResponse {
         // Try to get sensor data from ESPHome API
         let case_ready = self
-            .get_binary_sensor("case_ready_to_feed")
+            .get_binary_sensor(|entities| entities.case_ready_sensor)
             .await
             .unwrap_or(false);
         let case_in_view = self
-            .get_binary_sensor("case_in_camera_view")
+            .get_binary_sensor(|entities| entities.case_in_view_sensor)
             .await
             .unwrap_or(false);
 
         let readings = SensorReadings {
             case_ready,
             case_in_view,
-            timestamp: std::time::SystemTime::now()
-                .duration_since(std::time::UNIX_EPOCH)
-                .map(|d| d.as_secs())
-                .unwrap_or(0),
+            timestamp: chrono::Utc::now(),
         };
 
         ControllerResponse::SensorData(readings)
@@ -399,48 +1100,279 @@ impl ControllerMonitor {
             status.insert("esphome_hostname".to_string(), hostname);
         }
 
+        for reading in &self.lock_status().await.temperatures {
+            status.insert(
+                format!("temperature_{}", reading.sensor),
+                format!(
+                    "{:.1} °C{}",
+                    reading.celsius,
+                    if reading.overheated {
+                        " (overheated)"
+                    } else {
+                        ""
+                    }
+                ),
+            );
+        }
+
+        let firmware = self.lock_status().await.firmware.clone();
+        status.insert(
+            "controller_firmware".to_string(),
+            firmware.state.to_string(),
+        );
+        if !firmware.missing_entities.is_empty() {
+            let missing: Vec<String> = firmware
+                .missing_entities
+                .iter()
+                .map(ToString::to_string)
+                .collect();
+            status.insert("missing_entities".to_string(), missing.join(", "));
+        }
+
+        // Replayed data must never be mistaken for a live machine
+        status.insert("controller_mode".to_string(), self.mode.name().to_string());
+        if let Some(path) = self.mode.path() {
+            status.insert(
+                "controller_recording".to_string(),
+                path.display().to_string(),
+            );
+        }
+
         ControllerResponse::HardwareData(status)
     }
 
+    /// Request the controller's root page at `hostname`, as the health check does
+    async fn probe(&self, hostname: String) -> ControllerResponse {
+        let url = format!("http://{hostname}/");
+        let (reachable, response_time_ms, error) = match self.transport.send("GET", &url).await {
+            Ok(reply) => {
+                let latency = Some(reply.latency.as_millis() as u64);
+                if reply.status.is_success() {
+                    (true, latency, None)
+                } else {
+                    (
+                        false,
+                        latency,
+                        Some(format!("HTTP error: {}", reply.status)),
+                    )
+                }
+            }
+            Err(e) => (false, None, Some(e.to_string())),
+        };
+        ControllerResponse::Probe(ControllerProbe {
+            hostname,
+            reachable,
+            response_time_ms,
+            error,
+        })
+    }
+
     /// Trigger vibration motor
-    async fn trigger_vibration(&self) -> ControllerResponse {
-        let hostname = match self.lock_settings_read() {
-            Ok(settings) => settings.esphome_hostname.clone(),
-            Err(e) => return ControllerResponse::Error(format!("Failed to read settings: {e}")),
+    async fn trigger_vibration(
+        &self,
+        caller: &oneshot::Sender<ControllerResponse>,
+    ) -> ControllerResponse {
+        let url = match self
+            .entity_url(|entities| entities.vibration_switch, "turn_on")
+            .await
+        {
+            Ok(url) => url,
+            Err(e) => return ControllerResponse::Error(e),
         };
-        let url = format!("http://{hostname}/switch/vibration_motor/turn_on");
 
-        match self.make_request(&url, "POST").await {
-            Ok(_) => {
+        match self.send_command_request(&url, caller).await {
+            (Ok(_), attempts) => {
                 // ESPHome will automatically turn off after configured time
                 info!("Successfully triggered vibration motor");
-                ControllerResponse::Success("Vibration motor triggered".to_string())
+                ControllerResponse::Sent {
+                    message: "Vibration motor triggered".to_string(),
+                    attempts,
+                }
             }
-            Err(e) => {
+            (Err(e), attempts) => {
                 error!("Failed to trigger vibration: {e}");
-                ControllerResponse::Error(format!("Failed to trigger vibration: {e}"))
+                ControllerResponse::Error(format!(
+                    "Failed to trigger vibration{}: {e}",
+                    after_attempts(attempts)
+                ))
             }
         }
     }
 
     /// Set servo position
-    async fn set_servo_position(&self, servo: &str, position: u8) -> ControllerResponse {
-        let hostname = match self.lock_settings_read() {
-            Ok(settings) => settings.esphome_hostname.clone(),
-            Err(e) => return ControllerResponse::Error(format!("Failed to read settings: {e}")),
+    async fn set_servo_position(
+        &self,
+        servo: &str,
+        position: u8,
+        caller: &oneshot::Sender<ControllerResponse>,
+    ) -> ControllerResponse {
+        let url = match self
+            .entity_url(
+                |_| ControllerEntity::servo(servo),
+                &format!("set?value={position}"),
+            )
+            .await
+        {
+            Ok(url) => url,
+            Err(e) => return ControllerResponse::Error(e),
         };
-        let url = format!("http://{hostname}/number/{servo}/set?value={position}");
 
-        match self.make_request(&url, "POST").await {
-            Ok(_) => {
+        match self.send_command_request(&url, caller).await {
+            (Ok(_), attempts) => {
                 info!("Successfully set {servo} servo to position {position}");
-                ControllerResponse::Success(format!("Servo {servo} set to position {position}"))
+                ControllerResponse::Sent {
+                    message: format!("Servo {servo} set to position {position}"),
+                    attempts,
+                }
             }
-            Err(e) => {
+            (Err(e), attempts) => {
                 error!("Failed to set servo position: {e}");
-                ControllerResponse::Error(format!("Failed to set servo position: {e}"))
+                ControllerResponse::Error(format!(
+                    "Failed to set servo position{}: {e}",
+                    after_attempts(attempts)
+                ))
+            }
+        }
+    }
+
+    /// Move a servo, reporting the device error rather than logging it
+    async fn write_servo_position(&self, servo: &str, position: u8) -> Result<(), String> {
+        let url = self
+            .entity_url(
+                |_| ControllerEntity::servo(servo),
+                &format!("set?value={position}"),
+            )
+            .await?;
+        self.make_request(&url, "POST")
+            .await
+            .map(|_| ())
+            .map_err(|e| e.to_string())
+    }
+
+    /// Pause automation and move the servos to their saved positions so the
+    /// tracked positions match the hardware
+    async fn start_calibration(&mut self) -> ControllerResponse {
+        if self.machine.calibration().is_some() {
+            return self.calibration_status();
+        }
+        if self.machine.sort_cycle_active(Instant::now()) {
+            return ControllerResponse::CalibrationFailed(CalibrationError::SortCycleActive);
+        }
+        let positions = match self.lock_settings_read() {
+            Ok(settings) => settings.servo_positions.clone(),
+            Err(e) => return ControllerResponse::Error(format!("Failed to read settings: {e}")),
+        };
+
+        for (servo, position) in &positions {
+            if let Err(e) = self.write_servo_position(servo, *position).await {
+                error!("Failed to move {servo} to its saved position: {e}");
+                return ControllerResponse::CalibrationFailed(CalibrationError::Device(e));
             }
         }
+
+        match self.machine.start_calibration(positions, Instant::now()) {
+            Ok(_) => {
+                info!("Entered calibration mode, automation paused");
+                self.calibration_status()
+            }
+            Err(e) => ControllerResponse::CalibrationFailed(e),
+        }
+    }
+
+    /// Nudge a servo relative to its tracked position
+    async fn jog_servo(&mut self, servo: &str, delta: i16) -> ControllerResponse {
+        let target = match self
+            .machine
+            .calibration_mut()
+            .and_then(|session| session.jog_target(servo, delta))
+        {
+            Ok(target) => target,
+            Err(e) => return ControllerResponse::CalibrationFailed(e),
+        };
+
+        if let Err(e) = self.write_servo_position(servo, target).await {
+            error!("Failed to jog {servo} to {target}: {e}");
+            return ControllerResponse::CalibrationFailed(CalibrationError::Device(e));
+        }
+
+        match self
+            .machine
+            .calibration_mut()
+            .and_then(|session| session.record(servo, target))
+        {
+            Ok(()) => {
+                debug!("Jogged {servo} by {delta} to {target}");
+                self.calibration_status()
+            }
+            Err(e) => ControllerResponse::CalibrationFailed(e),
+        }
+    }
+
+    /// Current calibration positions, or the saved positions outside calibration
+    fn calibration_status(&self) -> ControllerResponse {
+        let status = match self.machine.calibration() {
+            Some(session) => CalibrationStatus {
+                active: true,
+                positions: session.positions().clone(),
+            },
+            None => match self.lock_settings_read() {
+                Ok(settings) => CalibrationStatus {
+                    active: false,
+                    positions: settings.servo_positions.clone(),
+                },
+                Err(e) => {
+                    return ControllerResponse::Error(format!("Failed to read settings: {e}"));
+                }
+            },
+        };
+        ControllerResponse::Calibration(status)
+    }
+
+    /// Leave calibration, keeping the final positions in the settings
+    fn save_calibration(&mut self) -> ControllerResponse {
+        let positions = match self.machine.finish_calibration() {
+            Ok(positions) => positions,
+            Err(e) => return ControllerResponse::CalibrationFailed(e),
+        };
+        match self.lock_settings_write() {
+            Ok(mut settings) => settings.servo_positions = positions.clone(),
+            Err(e) => return ControllerResponse::Error(format!("Failed to write settings: {e}")),
+        }
+        info!("Saved servo calibration, automation resumed");
+        ControllerResponse::Calibration(CalibrationStatus {
+            active: false,
+            positions,
+        })
+    }
+
+    /// Exercise every actuator and sensor once, unless the machine is busy
+    async fn self_test(&mut self, options: &SelfTestOptions) -> ControllerResponse {
+        if self.machine.calibration().is_some() {
+            return ControllerResponse::Error(CalibrationError::Calibrating.to_string());
+        }
+        if self.machine.sort_cycle_active(Instant::now()) {
+            return ControllerResponse::Error(CalibrationError::SortCycleActive.to_string());
+        }
+        let (hostname, entities, positions) = match self.lock_settings_read() {
+            Ok(settings) => (
+                settings.esphome_hostname.clone(),
+                ControllerEntities::from_settings(&settings),
+                settings.servo_positions.clone(),
+            ),
+            Err(e) => return ControllerResponse::Error(format!("Failed to read settings: {e}")),
+        };
+
+        let report =
+            self_test::run(&self.transport, &hostname, &entities, &positions, options).await;
+        let fed_case = report.steps.iter().any(|step| {
+            step.name == self_test::NEXT_CASE_STEP && step.outcome == StepOutcome::Passed
+        });
+        if fed_case && let Err(e) = self.machine.start_sort_cycle(Instant::now()) {
+            warn!("Sort cycle started during calibration: {e}");
+        }
+        self.events
+            .record("controller", "SelfTestReport", report.summary());
+        ControllerResponse::SelfTest(Box::new(report))
     }
 
     /// Check if the controller is online
@@ -448,16 +1380,31 @@ impl ControllerMonitor {
         self.lock_status().await.online
     }
 
+    /// URL of `action` on the entity `pick` chooses, unless the last firmware check found it missing
+    async fn entity_url(
+        &self,
+        pick: impl FnOnce(ControllerEntities) -> ControllerEntity,
+        action: &str,
+    ) -> Result<String, String> {
+        let (hostname, entity) = {
+            let settings = self
+                .lock_settings_read()
+                .map_err(|e| format!("Failed to read settings: {e}"))?;
+            (
+                settings.esphome_hostname.clone(),
+                pick(ControllerEntities::from_settings(&settings)),
+            )
+        };
+        self.lock_status().await.firmware.require(&entity)?;
+        Ok(entity.url(&hostname, action))
+    }
+
     /// Get binary sensor state from ESPHome
     async fn get_binary_sensor(
         &self,
-        sensor_name: &str,
+        pick: impl FnOnce(ControllerEntities) -> ControllerEntity,
     ) -> Result<bool, Box<dyn std::error::Error>> {
-        let hostname = match self.lock_settings_read() {
-            Ok(settings) => settings.esphome_hostname.clone(),
-            Err(e) => return Err(format!("Failed to read settings: {e}").into()),
-        };
-        let url = format!("http://{hostname}/binary_sensor/{sensor_name}/state");
+        let url = self.entity_url(pick, "state").await?;
         let response = self.make_request(&url, "GET").await?;
 
         // ESPHome returns "ON" or "OFF" for binary sensors
@@ -486,70 +1433,126 @@ impl ControllerMonitor {
         Ok(info)
     }
 
-    /// Make HTTP request to the controller
+    /// Make HTTP request to the controller, or answer it from a replay
     async fn make_request(
         &self,
         url: &str,
         method: &str,
     ) -> Result<String, Box<dyn std::error::Error>> {
-        let start_time = Instant::now();
-
-        let response = match method {
-            "GET" => {
-                self.client
-                    .get(url)
-                    .basic_auth("admin", Some("shellsorter"))
-                    .send()
-                    .await?
-            }
-            "POST" => {
-                self.client
-                    .post(url)
-                    .basic_auth("admin", Some("shellsorter"))
-                    .send()
-                    .await?
-            }
-            _ => return Err("Unsupported HTTP method".into()),
-        };
-
-        let elapsed = start_time.elapsed();
-
-        if response.status().is_success() {
-            let text = response.text().await?;
+        let reply = self.transport.send(method, url).await?;
+        self.reply_body(reply).await
+    }
 
+    /// The body of a successful reply, noting that the controller answered
+    async fn reply_body(
+        &self,
+        reply: ControllerReply,
+    ) -> Result<String, Box<dyn std::error::Error>> {
+        if reply.status.is_success() {
             // Update response time in status
             {
                 let mut status = self.lock_status_write().await;
-                status.response_time_ms = Some(elapsed.as_millis() as u64);
-                status.last_seen = Some(Instant::now());
+                status.response_time_ms = Some(reply.latency.as_millis() as u64);
+                status.last_seen = Some(Moment::now());
             }
 
-            Ok(text)
+            Ok(reply.body)
         } else {
-            Err(format!("HTTP error: {}", response.status()).into())
+            Err(format!("HTTP error: {}", reply.status).into())
+        }
+    }
+
+    /// POST a command to the controller, retrying while it has only briefly dropped off
+    ///
+    /// A request that couldn't connect is tried again with backoff until
+    /// `controller_retry_window_s` after the controller last answered. Retrying
+    /// stops once `caller` has given up waiting, so a command is never sent
+    /// after its failure was reported. Returns the outcome and how many
+    /// attempts were made.
+    async fn send_command_request(
+        &self,
+        url: &str,
+        caller: &oneshot::Sender<ControllerResponse>,
+    ) -> (Result<String, Box<dyn std::error::Error>>, u32) {
+        let deadline = self.retry_deadline().await;
+        let mut delay = FIRST_RETRY_DELAY;
+        let mut attempts = 0;
+        loop {
+            attempts += 1;
+            let error = match self.transport.send("POST", url).await {
+                Ok(reply) => return (self.reply_body(reply).await, attempts),
+                Err(e) => e,
+            };
+            let remaining = deadline
+                .map(|deadline| deadline.saturating_duration_since(Instant::now()))
+                .unwrap_or_default();
+            if !is_connection_error(&error) || remaining.is_zero() || caller.is_closed() {
+                return (Err(error.into()), attempts);
+            }
+            let wait = delay.min(remaining);
+            warn!(
+                "Controller unreachable ({error}), retrying in {}ms",
+                wait.as_millis()
+            );
+            sleep(wait).await;
+            delay = (delay * 2).min(MAX_RETRY_DELAY);
+            if caller.is_closed() {
+                return (Err(error.into()), attempts);
+            }
+        }
+    }
+
+    /// Until when a command that can't connect is retried, if the controller answered recently enough
+    async fn retry_deadline(&self) -> Option<Instant> {
+        let window = match self.lock_settings_read() {
+            Ok(settings) => Duration::from_secs(settings.controller_retry_window_s),
+            Err(e) => {
+                error!("Failed to read settings: {e}");
+                return None;
+            }
+        };
+        if window.is_zero() {
+            return None;
         }
+        self.lock_status()
+            .await
+            .last_seen
+            .map(|last_seen| last_seen.instant + window)
     }
 
-    /// Perform periodic health check
+    /// Read each temperature sensor, skipping the ones that don't answer with a temperature
+    async fn read_temperatures(
+        transport: &ControllerTransport,
+        sensors: &[TemperatureSensor],
+    ) -> Vec<(TemperatureSensor, f64)> {
+        let mut temperatures = Vec::with_capacity(sensors.len());
+        for sensor in sensors {
+            match transport.send("GET", &sensor.url()).await {
+                Ok(reply) if reply.status.is_success() => match parse_sensor_state(&reply.body) {
+                    Some(celsius) => temperatures.push((sensor.clone(), celsius)),
+                    None => debug!("Temperature sensor {sensor} has no reading: {}", reply.body),
+                },
+                Ok(reply) => debug!("Temperature sensor {sensor} answered {}", reply.status),
+                Err(e) => debug!("Temperature sensor {sensor} couldn't be read: {e}"),
+            }
+        }
+        temperatures
+    }
+
+    /// Perform periodic health check, returning whether the controller answered
     async fn perform_health_check(
-        client: &reqwest::Client,
+        transport: &ControllerTransport,
         hostname: &str,
         status: &Arc<AsyncRwLock<ControllerStatus>>,
-    ) {
+    ) -> bool {
         let url = format!("http://{hostname}/");
-        let start_time = Instant::now();
 
         debug!("Performing health check for {hostname}");
 
-        let is_online = match client
-            .get(&url)
-            .basic_auth("admin", Some("shellsorter"))
-            .send()
-            .await
-        {
+        let is_online = match transport.send("GET", &url).await {
             Ok(response) => {
-                let elapsed = start_time.elapsed();
-                let success = response.status().is_success();
+                let elapsed = response.latency;
+                let success = response.status.is_success();
 
                 if success {
                     debug!(
@@ -557,14 +1560,16 @@ impl ControllerMonitor {
                         elapsed.as_millis()
                     );
                 } else {
-                    warn!("Health check failed with status: {}", response.status());
+                    warn!("Health check failed with status: {}", response.status);
                 }
 
                 // Update status
                 {
                     let mut status_lock = status.write().await;
+                    let checked = Moment::now();
                     status_lock.online = success;
-                    status_lock.last_seen = Some(Instant::now());
+                    status_lock.last_check = Some(checked);
+                    status_lock.last_seen = Some(checked);
                     status_lock.response_time_ms = Some(elapsed.as_millis() as u64);
 
                     if success {
@@ -583,6 +1588,7 @@ impl ControllerMonitor {
                 {
                     let mut status_lock = status.write().await;
                     status_lock.online = false;
+                    status_lock.last_check = Some(Moment::now());
                     status_lock.error_count += 1;
                     status_lock.response_time_ms = None;
                 }
@@ -595,27 +1601,212 @@ impl ControllerMonitor {
             // Wait a bit before next attempt to avoid spam
             sleep(Duration::from_secs(5)).await;
         }
+        is_online
+    }
+}
+
+/// ` after N attempts` for a failure that was retried
+fn after_attempts(attempts: u32) -> String {
+    if attempts > 1 {
+        format!(" after {attempts} attempts")
+    } else {
+        String::new()
     }
 }
 
 impl ControllerHandle {
     /// Send a command to the controller and wait for response
-    pub async fn send_command(
+    pub async fn send_command(&self, command: ControllerCommand) -> OurResult<ControllerResponse> {
+        request(&self.request_sender, MANAGER_NAME, |response_sender| {
+            ControllerRequest {
+                command,
+                response_sender,
+            }
+        })
+        .await
+    }
+
+    /// [`ControllerHandle::send_command`], giving up if the monitor hasn't answered within `timeout`
+    pub async fn send_command_with_timeout(
         &self,
         command: ControllerCommand,
-    ) -> Result<ControllerResponse, Box<dyn std::error::Error>> {
-        let (response_sender, response_receiver) = oneshot::channel();
+        timeout: Duration,
+    ) -> OurResult<ControllerResponse> {
+        request_with_timeout(
+            &self.request_sender,
+            MANAGER_NAME,
+            timeout,
+            |response_sender| ControllerRequest {
+                command,
+                response_sender,
+            },
+        )
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn positions() -> BTreeMap<String, u8> {
+        BTreeMap::from([("feeder".to_string(), 50), ("position".to_string(), 95)])
+    }
+
+    #[test]
+    fn test_jog_is_relative_and_clamped() {
+        let mut session = CalibrationSession::new(positions());
+
+        assert_eq!(session.jog_target("feeder", 5), Ok(55));
+        session.record("feeder", 55).expect("feeder is known");
+        assert_eq!(session.jog_target("feeder", -10), Ok(45));
+
+        assert_eq!(session.jog_target("position", 10), Ok(SERVO_MAX_POSITION));
+        assert_eq!(session.jog_target("feeder", i16::MIN), Ok(0));
+        assert_eq!(
+            session.jog_target("hopper", 1),
+            Err(CalibrationError::UnknownServo("hopper".to_string()))
+        );
+        assert_eq!(session.positions().get("feeder"), Some(&55));
+    }
 
-        let request = ControllerRequest {
-            command,
-            response_sender,
+    #[test]
+    fn test_calibration_state_transitions() {
+        let now = Instant::now();
+        let mut machine = MachineState::default();
+
+        // Refused while a sort cycle is running, allowed once it has finished
+        machine
+            .start_sort_cycle(now)
+            .expect("automation is running");
+        assert_eq!(
+            machine.start_calibration(positions(), now + Duration::from_secs(1)),
+            Err(CalibrationError::SortCycleActive)
+        );
+        assert_eq!(
+            machine.calibration_mut(),
+            Err(CalibrationError::NotCalibrating)
+        );
+        let later = now + SORT_CYCLE_DURATION;
+        assert!(machine.start_calibration(positions(), later).is_ok());
+
+        // Automation is paused, and starting again keeps the jogged positions
+        assert_eq!(
+            machine.start_sort_cycle(later),
+            Err(CalibrationError::Calibrating)
+        );
+        machine
+            .calibration_mut()
+            .and_then(|session| session.record("feeder", 60))
+            .expect("calibrating");
+        let session = machine
+            .start_calibration(BTreeMap::new(), later)
+            .expect("already calibrating");
+        assert_eq!(session.positions().get("feeder"), Some(&60));
+
+        // Saving returns the final positions and resumes automation
+        let saved = machine.finish_calibration().expect("calibrating");
+        assert_eq!(saved.get("feeder"), Some(&60));
+        assert!(machine.calibration().is_none());
+        assert_eq!(
+            machine.finish_calibration(),
+            Err(CalibrationError::NotCalibrating)
+        );
+        machine.start_sort_cycle(later).expect("automation resumed");
+    }
+
+    #[test]
+    fn test_maintenance_refuses_motion_until_it_is_turned_off_or_expires() {
+        let now = Instant::now();
+        let since = chrono::Utc::now();
+        let mut machine = MachineState::default();
+        let refused = CalibrationError::Maintenance(Some("clearing jam".to_string()));
+
+        machine.enter_maintenance(Some("clearing jam".to_string()), since, None);
+        assert_eq!(machine.start_sort_cycle(now), Err(refused.clone()));
+        assert_eq!(
+            machine.start_calibration(positions(), now).map(|_| ()),
+            Err(refused)
+        );
+        // Without an expiry it waits for someone to turn it off
+        assert_eq!(
+            machine.expire_maintenance(since + chrono::Duration::days(7)),
+            None
+        );
+        assert!(machine.leave_maintenance());
+        assert!(!machine.leave_maintenance());
+        machine.start_sort_cycle(now).expect("motion allowed again");
+
+        let expiry = chrono::Duration::minutes(30);
+        let status = machine.enter_maintenance(None, since, Some(expiry)).clone();
+        assert_eq!(status.expires_at, Some(since + expiry));
+        // Turning it on again keeps when it started
+        let renewed = machine
+            .enter_maintenance(Some("oiling".to_string()), since + expiry / 2, Some(expiry))
+            .clone();
+        assert_eq!(renewed.since, Some(since));
+        assert_eq!(
+            machine.expire_maintenance(since + expiry),
+            None,
+            "renewing pushed the expiry back"
+        );
+        let expired = machine
+            .expire_maintenance(since + expiry / 2 + expiry)
+            .expect("maintenance should expire");
+        assert_eq!(expired.reason.as_deref(), Some("oiling"));
+        assert!(!machine.maintenance().enabled);
+        assert_eq!(machine.check_motion_allowed(), Ok(()));
+    }
+
+    #[tokio::test]
+    async fn test_replayed_jam_sequence() {
+        let settings = Settings {
+            controller_replay_path: Some(
+                std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
+                    .join("tests/fixtures/controller-jam.jsonl"),
+            ),
+            controller_replay_speed: 1000.0,
+            ..Settings::default()
         };
+        let (monitor, controller) = ControllerMonitor::new(settings, EventRecorder::default())
+            .expect("controller monitor should be created");
+        tokio::spawn(monitor.run());
 
-        self.request_sender.send(request)?;
+        let sensors = |response| match response {
+            Ok(ControllerResponse::SensorData(readings)) => {
+                (readings.case_ready, readings.case_in_view)
+            }
+            other => panic!("expected sensor readings, got {other:?}"),
+        };
+        let command = |command| controller.send_command(command);
+
+        // A case is waiting, it's fed, and then the second feed times out
+        assert_eq!(
+            sensors(command(ControllerCommand::GetSensors).await),
+            (true, false)
+        );
+        assert!(matches!(
+            command(ControllerCommand::NextCase).await,
+            Ok(ControllerResponse::Sent { .. })
+        ));
+        assert_eq!(
+            sensors(command(ControllerCommand::GetSensors).await),
+            (false, true)
+        );
+        match command(ControllerCommand::NextCase).await {
+            Ok(ControllerResponse::Error(e)) => assert!(e.contains("timed out"), "{e}"),
+            other => panic!("expected the jam to fail, got {other:?}"),
+        }
 
-        match response_receiver.await {
-            Ok(response) => Ok(response),
-            Err(_) => Err("Controller monitor did not respond".into()),
+        match command(ControllerCommand::GetHardwareStatus).await {
+            Ok(ControllerResponse::HardwareData(status)) => {
+                assert_eq!(
+                    status.get("controller_mode").map(String::as_str),
+                    Some("replay")
+                );
+                assert!(status.contains_key("controller_recording"));
+            }
+            other => panic!("expected hardware status, got {other:?}"),
         }
     }
 }
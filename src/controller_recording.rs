@@ -0,0 +1,425 @@
+//! Record and replay of the HTTP exchanges with the ESPHome controller.
+//!
+//! In record mode every request the [`ControllerMonitor`](crate::controller_monitor::ControllerMonitor)
+//! sends is appended to a JSONL file along with the response and how long it
+//! took. In replay mode those responses are served back, matched by method and
+//! URL path, without touching the network. Requests to the same URL are
+//! answered in the order they were recorded, and the last answer is repeated
+//! once they run out, so a jam sequence plays out the same way every time.
+
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Utc};
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+use tokio::io::AsyncWriteExt;
+use tracing::{info, warn};
+
+use crate::config::Settings;
+use crate::constants::{ESPHOME_PASSWORD, ESPHOME_USERNAME};
+use crate::{OurError, OurResult};
+
+/// Replay with the latencies as they were recorded
+pub const DEFAULT_REPLAY_SPEED: f64 = 1.0;
+
+/// One request to the controller and what came back, a line in a recording
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct RecordedExchange {
+    pub timestamp: DateTime<Utc>,
+    pub method: String,
+    pub url: String,
+    /// HTTP status, missing when the request failed without a response
+    pub status: Option<u16>,
+    /// Response body, or the error when there was no response
+    pub body: String,
+    pub latency_ms: u64,
+}
+
+/// Where the controller's responses come from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ControllerMode {
+    /// Talk to the controller
+    Live,
+    /// Talk to the controller and append every exchange to a recording
+    Record(PathBuf),
+    /// Serve responses from a recording instead of the controller
+    Replay(PathBuf),
+}
+
+impl ControllerMode {
+    /// The mode selected by `controller_record_path` and `controller_replay_path`
+    pub fn from_settings(settings: &Settings) -> OurResult<Self> {
+        match (
+            &settings.controller_record_path,
+            &settings.controller_replay_path,
+        ) {
+            (None, None) => Ok(ControllerMode::Live),
+            (Some(path), None) => Ok(ControllerMode::Record(path.clone())),
+            (None, Some(path)) => Ok(ControllerMode::Replay(path.clone())),
+            (Some(_), Some(_)) => Err(OurError::Config(
+                "controller_record_path and controller_replay_path can't both be set".to_string(),
+            )),
+        }
+    }
+
+    /// Name shown in hardware status
+    pub fn name(&self) -> &'static str {
+        match self {
+            ControllerMode::Live => "live",
+            ControllerMode::Record(_) => "record",
+            ControllerMode::Replay(_) => "replay",
+        }
+    }
+
+    /// The recording being written or replayed
+    pub fn path(&self) -> Option<&Path> {
+        match self {
+            ControllerMode::Live => None,
+            ControllerMode::Record(path) | ControllerMode::Replay(path) => Some(path),
+        }
+    }
+}
+
+impl fmt::Display for ControllerMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.path() {
+            Some(path) => write!(f, "{} ({})", self.name(), path.display()),
+            None => write!(f, "{}", self.name()),
+        }
+    }
+}
+
+/// A response from the controller, live or replayed
+#[derive(Debug, Clone)]
+pub struct ControllerReply {
+    pub status: StatusCode,
+    pub body: String,
+    pub latency: Duration,
+}
+
+/// Sends controller requests according to the [`ControllerMode`]
+#[derive(Clone)]
+pub enum ControllerTransport {
+    Live {
+        client: reqwest::Client,
+        recorder: Option<Arc<Recorder>>,
+    },
+    Replay(Arc<Replay>),
+}
+
+impl ControllerTransport {
+    /// Open the recording for the mode, if it has one
+    pub fn new(mode: &ControllerMode, replay_speed: f64) -> OurResult<Self> {
+        let live = |recorder| -> OurResult<Self> {
+            let client = reqwest::Client::builder()
+                .timeout(Duration::from_secs(15))
+                .build()?;
+            Ok(ControllerTransport::Live { client, recorder })
+        };
+        match mode {
+            ControllerMode::Live => live(None),
+            ControllerMode::Record(path) => live(Some(Arc::new(Recorder::open(path)?))),
+            ControllerMode::Replay(path) => Ok(ControllerTransport::Replay(Arc::new(
+                Replay::load(path, replay_speed)?,
+            ))),
+        }
+    }
+
+    /// Send a request, or answer it from the recording
+    pub async fn send(&self, method: &str, url: &str) -> OurResult<ControllerReply> {
+        match self {
+            ControllerTransport::Live { client, recorder } => {
+                let start_time = Instant::now();
+                let result = Self::send_live(client, method, url).await;
+                let latency = start_time.elapsed();
+                if let Some(recorder) = recorder {
+                    let (status, body) = match &result {
+                        Ok((status, body)) => (Some(status.as_u16()), body.clone()),
+                        Err(e) => (None, e.to_string()),
+                    };
+                    recorder
+                        .append(RecordedExchange {
+                            timestamp: Utc::now(),
+                            method: method.to_string(),
+                            url: url.to_string(),
+                            status,
+                            body,
+                            latency_ms: latency.as_millis() as u64,
+                        })
+                        .await;
+                }
+                let (status, body) = result?;
+                Ok(ControllerReply {
+                    status,
+                    body,
+                    latency,
+                })
+            }
+            ControllerTransport::Replay(replay) => replay.answer(method, url).await,
+        }
+    }
+
+    async fn send_live(
+        client: &reqwest::Client,
+        method: &str,
+        url: &str,
+    ) -> OurResult<(StatusCode, String)> {
+        let request = match method {
+            "GET" => client.get(url),
+            "POST" => client.post(url),
+            _ => return Err(OurError::App(format!("Unsupported HTTP method {method}"))),
+        };
+        let response = request
+            .basic_auth(ESPHOME_USERNAME, Some(ESPHOME_PASSWORD))
+            .send()
+            .await?;
+        let status = response.status();
+        Ok((status, response.text().await?))
+    }
+}
+
+/// Whether a request failed before the controller could have acted on it
+///
+/// The controller refusing or resetting the connection, or not being reachable
+/// at all, is what dropping off WiFi looks like. A timeout or a broken
+/// response body may come after the controller acted, so they don't count.
+pub fn is_connection_error(error: &OurError) -> bool {
+    let OurError::Http(error) = error else {
+        return false;
+    };
+    if error.is_connect() {
+        return true;
+    }
+    if error.is_timeout() || error.is_body() || error.is_decode() {
+        return false;
+    }
+    let mut source = std::error::Error::source(error);
+    while let Some(cause) = source {
+        if let Some(io_error) = cause.downcast_ref::<std::io::Error>() {
+            return matches!(
+                io_error.kind(),
+                std::io::ErrorKind::ConnectionRefused
+                    | std::io::ErrorKind::ConnectionReset
+                    | std::io::ErrorKind::ConnectionAborted
+                    | std::io::ErrorKind::NotConnected
+                    | std::io::ErrorKind::BrokenPipe
+            );
+        }
+        source = cause.source();
+    }
+    false
+}
+
+/// Appends exchanges to a recording
+pub struct Recorder {
+    path: PathBuf,
+    file: tokio::sync::Mutex<tokio::fs::File>,
+}
+
+impl Recorder {
+    fn open(path: &Path) -> OurResult<Self> {
+        let file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)?;
+        info!("Recording controller exchanges to {}", path.display());
+        Ok(Self {
+            path: path.to_path_buf(),
+            file: tokio::sync::Mutex::new(tokio::fs::File::from_std(file)),
+        })
+    }
+
+    // A failed write loses one line of the recording, it mustn't fail the request
+    async fn append(&self, exchange: RecordedExchange) {
+        let mut line = match serde_json::to_string(&exchange) {
+            Ok(line) => line,
+            Err(e) => {
+                warn!("Failed to serialise controller exchange: {e}");
+                return;
+            }
+        };
+        line.push('\n');
+        let mut file = self.file.lock().await;
+        // Flushed per line so the recording is complete even if the process is killed
+        let written = match file.write_all(line.as_bytes()).await {
+            Ok(()) => file.flush().await,
+            Err(e) => Err(e),
+        };
+        if let Err(e) = written {
+            warn!(
+                "Failed to append to controller recording {}: {e}",
+                self.path.display()
+            );
+        }
+    }
+}
+
+/// Responses loaded from a recording, queued by method and URL path
+pub struct Replay {
+    exchanges: Mutex<HashMap<(String, String), VecDeque<RecordedExchange>>>,
+    speed: f64,
+}
+
+impl Replay {
+    /// Load a recording; latencies are divided by `speed`
+    pub fn load(path: &Path, speed: f64) -> OurResult<Self> {
+        if !(speed > 0.0 && speed.is_finite()) {
+            return Err(OurError::Config(format!(
+                "controller_replay_speed must be a positive number, got {speed}"
+            )));
+        }
+        let contents = std::fs::read_to_string(path)?;
+        let mut exchanges: HashMap<_, VecDeque<_>> = HashMap::new();
+        for (index, line) in contents.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let exchange: RecordedExchange = serde_json::from_str(line).map_err(|e| {
+                OurError::Config(format!(
+                    "Line {} of controller recording {} is invalid: {e}",
+                    index + 1,
+                    path.display()
+                ))
+            })?;
+            exchanges
+                .entry(Self::key(&exchange.method, &exchange.url))
+                .or_default()
+                .push_back(exchange);
+        }
+        info!(
+            "Replaying controller exchanges from {} at {speed}x",
+            path.display()
+        );
+        Ok(Self {
+            exchanges: Mutex::new(exchanges),
+            speed,
+        })
+    }
+
+    /// Recordings are matched on the path so they replay against any hostname
+    fn key(method: &str, url: &str) -> (String, String) {
+        let path = match url::Url::parse(url) {
+            Ok(url) => match url.query() {
+                Some(query) => format!("{}?{query}", url.path()),
+                None => url.path().to_string(),
+            },
+            Err(_) => url.to_string(),
+        };
+        (method.to_string(), path)
+    }
+
+    fn next(&self, method: &str, url: &str) -> OurResult<RecordedExchange> {
+        let mut exchanges = self
+            .exchanges
+            .lock()
+            .map_err(|_| OurError::App("Controller replay lock poisoned".to_string()))?;
+        let queue = exchanges.get_mut(&Self::key(method, url)).ok_or_else(|| {
+            OurError::Hardware(format!("No recorded response for {method} {url}"))
+        })?;
+        // The last response keeps being served once the queue runs out
+        let exchange = if queue.len() > 1 {
+            queue.pop_front()
+        } else {
+            queue.front().cloned()
+        };
+        exchange
+            .ok_or_else(|| OurError::Hardware(format!("No recorded response for {method} {url}")))
+    }
+
+    async fn answer(&self, method: &str, url: &str) -> OurResult<ControllerReply> {
+        let exchange = self.next(method, url)?;
+        let latency = Duration::from_millis(exchange.latency_ms).div_f64(self.speed);
+        tokio::time::sleep(latency).await;
+        let status = exchange
+            .status
+            .map(StatusCode::from_u16)
+            .transpose()
+            .map_err(|e| OurError::Hardware(format!("Recorded status is invalid: {e}")))?;
+        match status {
+            Some(status) => Ok(ControllerReply {
+                status,
+                body: exchange.body,
+                latency,
+            }),
+            None => Err(OurError::Hardware(exchange.body)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_record_then_replay() {
+        let device = axum::Router::new().route(
+            "/binary_sensor/case_ready_to_feed/state",
+            axum::routing::get(|| async { "ON" }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("listener should bind");
+        let address = listener
+            .local_addr()
+            .expect("listener should have an address");
+        tokio::spawn(async move { axum::serve(listener, device).await });
+
+        let temp_dir = tempfile::TempDir::new().expect("temp dir should be created");
+        let path = temp_dir.path().join("controller.jsonl");
+        let recording = ControllerTransport::new(&ControllerMode::Record(path.clone()), 1.0)
+            .expect("recording should open");
+        let url = format!("http://{address}/binary_sensor/case_ready_to_feed/state");
+        let reply = recording
+            .send("GET", &url)
+            .await
+            .expect("live request should succeed");
+        assert_eq!(reply.body, "ON");
+        recording
+            .send("GET", &format!("http://{address}/missing"))
+            .await
+            .expect("a 404 is still a response");
+
+        let lines = std::fs::read_to_string(&path).expect("recording should be written");
+        assert_eq!(lines.lines().count(), 2);
+
+        // Replayed against another hostname, with no network
+        let replay = ControllerTransport::new(&ControllerMode::Replay(path), 1000.0)
+            .expect("recording should load");
+        let reply = replay
+            .send(
+                "GET",
+                "http://elsewhere.local/binary_sensor/case_ready_to_feed/state",
+            )
+            .await
+            .expect("recorded response should be served");
+        assert_eq!((reply.status, reply.body.as_str()), (StatusCode::OK, "ON"));
+        let reply = replay
+            .send("GET", "http://elsewhere.local/missing")
+            .await
+            .expect("recorded response should be served");
+        assert_eq!(reply.status, StatusCode::NOT_FOUND);
+        assert!(
+            replay
+                .send("POST", "http://elsewhere.local/")
+                .await
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_record_and_replay_are_exclusive() {
+        let mut settings = Settings::default();
+        assert_eq!(
+            ControllerMode::from_settings(&settings).expect("live is valid"),
+            ControllerMode::Live
+        );
+        settings.controller_record_path = Some(PathBuf::from("a.jsonl"));
+        settings.controller_replay_path = Some(PathBuf::from("b.jsonl"));
+        assert!(ControllerMode::from_settings(&settings).is_err());
+    }
+}
@@ -0,0 +1,161 @@
+//! Cross-origin access to the API, for running the web UI from a dev server.
+//!
+//! Off unless `cors_allowed_origins` lists at least one origin, so a normal
+//! install answers exactly as it did before. Only the origins listed are
+//! allowed; a request from anywhere else gets no `Access-Control-Allow-Origin`
+//! and the browser refuses it. A wildcard is never accepted, because the API
+//! allows credentials and because it is too easy to ship by accident.
+
+use std::time::Duration;
+
+use axum::http::header::{
+    AUTHORIZATION, CONTENT_DISPOSITION, CONTENT_TYPE, ETAG, HeaderName, IF_NONE_MATCH,
+};
+use axum::http::{HeaderValue, Method};
+use serde::Serialize;
+use tower_http::cors::{AllowOrigin, CorsLayer};
+
+use crate::idempotency::IDEMPOTENCY_KEY_HEADER;
+use crate::server_timing::{DEBUG_TIMING_HEADER, SERVER_TIMING_HEADER};
+use crate::{OurError, OurResult};
+
+/// How long browsers may cache a preflight answer
+const PREFLIGHT_MAX_AGE: Duration = Duration::from_secs(600);
+
+/// Methods the API routes use
+const ALLOWED_METHODS: [Method; 5] = [
+    Method::GET,
+    Method::POST,
+    Method::PUT,
+    Method::PATCH,
+    Method::DELETE,
+];
+
+/// Whether cross-origin requests are allowed, for `/api/health` and `config show`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub struct CorsStatus {
+    pub enabled: bool,
+    pub allowed_origins: Vec<String>,
+}
+
+impl CorsStatus {
+    pub fn new(origins: &[String]) -> Self {
+        Self {
+            enabled: !origins.is_empty(),
+            allowed_origins: origins.to_vec(),
+        }
+    }
+}
+
+/// `origin` as browsers send it in the `Origin` header, e.g. `http://localhost:5173`
+///
+/// Paths, queries, credentials and wildcards are refused rather than dropped,
+/// so a typo doesn't quietly allow a different origin than the one intended.
+pub fn parse_origin(origin: &str) -> OurResult<HeaderValue> {
+    let invalid = |reason: &str| {
+        OurError::Config(format!(
+            "Invalid CORS origin '{origin}': {reason}, e.g. http://localhost:5173"
+        ))
+    };
+    if origin.trim() == "*" {
+        return Err(invalid("wildcards are not allowed, list each origin"));
+    }
+    let url = url::Url::parse(origin.trim()).map_err(|e| invalid(&e.to_string()))?;
+    if !matches!(url.scheme(), "http" | "https") {
+        return Err(invalid("must be http or https"));
+    }
+    if url.host().is_none() {
+        return Err(invalid("must have a host"));
+    }
+    if !url.username().is_empty() || url.password().is_some() {
+        return Err(invalid("must not contain credentials"));
+    }
+    if url.path() != "/" || url.query().is_some() || url.fragment().is_some() {
+        return Err(invalid("must be only a scheme, host and port"));
+    }
+    let origin = url.origin().ascii_serialization();
+    HeaderValue::from_str(&origin).map_err(|e| invalid(&e.to_string()))
+}
+
+/// Check every configured origin
+pub fn validate_origins(origins: &[String]) -> OurResult<()> {
+    origins
+        .iter()
+        .try_for_each(|origin| parse_origin(origin).map(drop))
+}
+
+/// The layer for `/api` routes, or `None` when no origins are configured
+pub fn cors_layer(origins: &[String]) -> OurResult<Option<CorsLayer>> {
+    if origins.is_empty() {
+        return Ok(None);
+    }
+    let origins = origins
+        .iter()
+        .map(|origin| parse_origin(origin))
+        .collect::<OurResult<Vec<_>>>()?;
+    Ok(Some(
+        CorsLayer::new()
+            .allow_origin(AllowOrigin::list(origins))
+            .allow_methods(ALLOWED_METHODS)
+            .allow_headers([
+                AUTHORIZATION,
+                CONTENT_TYPE,
+                IF_NONE_MATCH,
+                HeaderName::from_static(IDEMPOTENCY_KEY_HEADER),
+                HeaderName::from_static(DEBUG_TIMING_HEADER),
+            ])
+            .expose_headers([
+                ETAG,
+                CONTENT_DISPOSITION,
+                HeaderName::from_static(SERVER_TIMING_HEADER),
+            ])
+            .allow_credentials(true)
+            .max_age(PREFLIGHT_MAX_AGE),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_origins_are_normalised_like_browsers_send_them() {
+        for (origin, expected) in [
+            ("http://localhost:5173", "http://localhost:5173"),
+            ("http://LocalHost:5173/", "http://localhost:5173"),
+            ("https://sorter.example:443", "https://sorter.example"),
+            ("http://127.0.0.1:8080", "http://127.0.0.1:8080"),
+        ] {
+            assert_eq!(
+                parse_origin(origin).expect("origin should parse"),
+                HeaderValue::from_static(expected),
+                "{origin}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_wildcards_and_urls_are_not_origins() {
+        for origin in [
+            "*",
+            "localhost:5173",
+            "ftp://localhost",
+            "http://localhost:5173/app",
+            "http://localhost:5173/?debug=1",
+            "http://user:pw@localhost:5173",
+            "",
+        ] {
+            assert!(parse_origin(origin).is_err(), "{origin} should be refused");
+        }
+        assert!(
+            cors_layer(&["*".to_string()]).is_err(),
+            "a wildcard should never build a layer"
+        );
+        assert!(
+            cors_layer(&[])
+                .expect("no origins should be accepted")
+                .is_none()
+        );
+    }
+}
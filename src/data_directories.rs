@@ -0,0 +1,286 @@
+//! Where the image and data directories are on disk.
+//!
+//! The default directories are relative (`./images`, `./data`), and a relative
+//! path used as-is follows the current directory: starting the server from
+//! somewhere else quietly creates a second, empty data tree and every shell
+//! seems to have vanished. So relative directories are resolved against the
+//! config file that set them, or a fixed base for the defaults, and then
+//! canonicalised once they exist. Two settings pointing at the same directory,
+//! or a path that exists but is a file, stop startup rather than mixing or
+//! losing data.
+//!
+//! [`other_data_trees`] looks for the data trees earlier versions may have
+//! left in the usual places, so the split can be noticed and merged.
+
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::config::Settings;
+use crate::shell_data::json_files_in;
+use crate::{OurError, OurResult};
+
+/// File every data directory gets once a case type is added
+const CASE_TYPES_FILENAME: &str = "case_types.json";
+
+/// The directories shell-sorter reads and writes, as absolute paths
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct DataDirectories {
+    pub image_directory: PathBuf,
+    pub data_directory: PathBuf,
+    pub models_directory: PathBuf,
+    pub references_directory: PathBuf,
+}
+
+impl DataDirectories {
+    pub fn from_settings(settings: &Settings) -> Self {
+        Self {
+            image_directory: settings.image_directory.clone(),
+            data_directory: settings.data_directory.clone(),
+            models_directory: settings.models_directory.clone(),
+            references_directory: settings.references_directory.clone(),
+        }
+    }
+
+    /// Each directory with the name of its setting
+    pub fn named(&self) -> [(&'static str, &Path); 4] {
+        [
+            ("image_directory", &self.image_directory),
+            ("data_directory", &self.data_directory),
+            ("models_directory", &self.models_directory),
+            ("references_directory", &self.references_directory),
+        ]
+    }
+}
+
+/// `path` made absolute against `base`, with `.` and `..` worked out without touching the disk
+pub fn resolve(path: &Path, base: &Path) -> PathBuf {
+    let joined = if path.is_absolute() {
+        path.to_path_buf()
+    } else {
+        base.join(path)
+    };
+    let mut resolved = PathBuf::new();
+    for component in joined.components() {
+        match component {
+            Component::CurDir => {}
+            // `..` at the root stays at the root
+            Component::ParentDir => {
+                resolved.pop();
+            }
+            component => resolved.push(component),
+        }
+    }
+    resolved
+}
+
+/// Refuse directories that are files, or that two settings share
+pub fn check_distinct(directories: &DataDirectories) -> OurResult<()> {
+    let named = directories.named();
+    for (setting, path) in named {
+        if path.exists() && !path.is_dir() {
+            return Err(OurError::Config(format!(
+                "{setting} {} exists but isn't a directory",
+                path.display()
+            )));
+        }
+    }
+    for (index, (setting, path)) in named.iter().enumerate() {
+        if let Some((other, _)) = named[index + 1..]
+            .iter()
+            .find(|(_, other_path)| other_path == path)
+        {
+            return Err(OurError::Config(format!(
+                "{setting} and {other} both resolve to {}; give them separate directories",
+                path.display()
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// The directories with symlinks resolved, once they exist
+pub fn canonicalize(directories: &DataDirectories) -> OurResult<DataDirectories> {
+    let canonical = |setting: &str, path: &Path| {
+        fs::canonicalize(path)
+            .map(without_verbatim_prefix)
+            .map_err(|e| {
+                OurError::Config(format!(
+                    "Failed to resolve {setting} {}: {e}",
+                    path.display()
+                ))
+            })
+    };
+    let canonical = DataDirectories {
+        image_directory: canonical("image_directory", &directories.image_directory)?,
+        data_directory: canonical("data_directory", &directories.data_directory)?,
+        models_directory: canonical("models_directory", &directories.models_directory)?,
+        references_directory: canonical("references_directory", &directories.references_directory)?,
+    };
+    // Symlinks can make different paths the same directory
+    check_distinct(&canonical)?;
+    Ok(canonical)
+}
+
+/// `fs::canonicalize` gives `\\?\C:\...` on Windows, which other programs and people don't expect
+#[cfg(windows)]
+fn without_verbatim_prefix(path: PathBuf) -> PathBuf {
+    match path.to_str().and_then(|path| path.strip_prefix(r"\\?\")) {
+        // Only drive paths; verbatim UNC paths need the prefix
+        Some(stripped) if stripped.as_bytes().get(1) == Some(&b':') => PathBuf::from(stripped),
+        _ => path,
+    }
+}
+
+#[cfg(not(windows))]
+fn without_verbatim_prefix(path: PathBuf) -> PathBuf {
+    path
+}
+
+/// Whether `directory` holds shell records or case types
+fn is_data_tree(directory: &Path) -> bool {
+    if directory.join(CASE_TYPES_FILENAME).is_file() {
+        return true;
+    }
+    let mut skipped = Vec::new();
+    json_files_in(directory, &mut skipped).is_ok_and(|files| {
+        files
+            .iter()
+            .any(|(stem, _)| uuid::Uuid::parse_str(stem).is_ok())
+    })
+}
+
+/// Data trees under `data` in each of `locations` other than `data_directory`
+///
+/// The usual locations are the current directory, the home directory and the
+/// config file's directory, where the relative default used to land.
+pub fn other_data_trees(data_directory: &Path, locations: &[PathBuf]) -> Vec<PathBuf> {
+    let current = fs::canonicalize(data_directory).map(without_verbatim_prefix);
+    let mut found: Vec<PathBuf> = Vec::new();
+    for location in locations {
+        let Ok(candidate) = fs::canonicalize(location.join("data")).map(without_verbatim_prefix)
+        else {
+            continue;
+        };
+        if current.as_ref().is_ok_and(|current| *current == candidate) || found.contains(&candidate)
+        {
+            continue;
+        }
+        if is_data_tree(&candidate) {
+            found.push(candidate);
+        }
+    }
+    found
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn directories(root: &Path) -> DataDirectories {
+        DataDirectories {
+            image_directory: root.join("images"),
+            data_directory: root.join("data"),
+            models_directory: root.join("data").join("models"),
+            references_directory: root.join("data").join("references"),
+        }
+    }
+
+    #[test]
+    fn test_resolve_relative_directories() {
+        let base = Path::new("/srv/sorter");
+        assert_eq!(
+            resolve(Path::new("./data"), base),
+            PathBuf::from("/srv/sorter/data")
+        );
+        assert_eq!(
+            resolve(Path::new("../shared/./images"), base),
+            PathBuf::from("/srv/shared/images")
+        );
+        assert_eq!(
+            resolve(Path::new("/var/lib/sorter/data"), base),
+            PathBuf::from("/var/lib/sorter/data")
+        );
+        assert_eq!(resolve(Path::new("../../../.."), base), PathBuf::from("/"));
+    }
+
+    #[test]
+    fn test_shared_or_file_directories_are_refused() {
+        let temp_dir = tempfile::TempDir::new().expect("temp dir should be created");
+        let mut checked = directories(temp_dir.path());
+        check_distinct(&checked).expect("separate directories are fine");
+
+        checked.references_directory = resolve(Path::new("images/../data"), temp_dir.path());
+        let error = check_distinct(&checked).expect_err("shared directories should be refused");
+        assert!(
+            error
+                .to_string()
+                .contains("data_directory and references_directory")
+        );
+
+        let mut checked = directories(temp_dir.path());
+        fs::write(temp_dir.path().join("images"), b"not a directory")
+            .expect("file should be written");
+        let error = check_distinct(&checked).expect_err("a file should be refused");
+        assert!(error.to_string().contains("isn't a directory"));
+
+        // Through a symlink, two different paths can be the same directory
+        #[cfg(unix)]
+        {
+            fs::remove_file(temp_dir.path().join("images")).expect("file should be removed");
+            for directory in checked.named().map(|(_, path)| path.to_path_buf()) {
+                fs::create_dir_all(directory).expect("directory should be created");
+            }
+            fs::remove_dir(&checked.models_directory).expect("directory should be removed");
+            std::os::unix::fs::symlink(&checked.image_directory, &checked.models_directory)
+                .expect("symlink should be created");
+            check_distinct(&checked).expect("the paths differ");
+            assert!(canonicalize(&checked).is_err());
+            checked.models_directory = temp_dir.path().join("data").join("references");
+            assert!(check_distinct(&checked).is_err());
+        }
+    }
+
+    #[test]
+    fn test_other_data_trees_are_found() {
+        let temp_dir = tempfile::TempDir::new().expect("temp dir should be created");
+        let [current, old, empty, configs] =
+            ["current", "old", "empty", "configs"].map(|name| temp_dir.path().join(name));
+        fs::create_dir_all(current.join("data")).expect("directory should be created");
+        fs::create_dir_all(old.join("data")).expect("directory should be created");
+        fs::create_dir_all(empty.join("data")).expect("directory should be created");
+        fs::create_dir_all(configs.join("data")).expect("directory should be created");
+        fs::write(
+            old.join("data")
+                .join(format!("{}.json", uuid::Uuid::new_v4())),
+            b"{}",
+        )
+        .expect("shell should be written");
+        fs::write(configs.join("data").join(CASE_TYPES_FILENAME), b"{}")
+            .expect("case types should be written");
+        fs::write(empty.join("data").join("notes.json"), b"{}").expect("file should be written");
+        fs::write(current.join("data").join(CASE_TYPES_FILENAME), b"{}")
+            .expect("case types should be written");
+
+        let found = other_data_trees(
+            &current.join("data"),
+            &[
+                current.clone(),
+                old.clone(),
+                empty,
+                configs.clone(),
+                old.clone(),
+                temp_dir.path().join("missing"),
+            ],
+        );
+        let expected: Vec<PathBuf> = [old, configs]
+            .iter()
+            .map(|location| {
+                fs::canonicalize(location.join("data")).expect("directory should exist")
+            })
+            .collect();
+        assert_eq!(found, expected);
+    }
+}
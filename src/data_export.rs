@@ -0,0 +1,746 @@
+//! Whole-dataset exports, in full or only what changed since an earlier one.
+//!
+//! An export is either one JSON document holding the manifest and every shell
+//! record, or a tar.gz holding `manifest.json`, `shells/<session_id>.json` and
+//! the images the shells reference under `images/`. `--since` narrows it to
+//! shells captured, or whose record was written, after a time; `last` means
+//! after the previous export, read from [`EXPORT_MANIFEST_FILENAME`] in the
+//! data directory, which every successful export replaces with its own
+//! manifest.
+//!
+//! Each manifest lists every session in the dataset when it was exported,
+//! included or not, so importing a delta without the exports before it can
+//! name the sessions that are still missing.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use chrono::{DateTime, Utc};
+use flate2::Compression;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+
+use crate::config::Settings;
+use crate::safe_name::SafeName;
+use crate::session_bundle::{TempBundle, referenced_images};
+use crate::shell_data::{Shell, ShellDataManager, write_atomic};
+use crate::training_runs::content_hash;
+use crate::{OurError, OurResult};
+
+/// The last export's manifest, kept in the data directory for `--since last`
+pub const EXPORT_MANIFEST_FILENAME: &str = "export_manifest.json";
+
+/// Archive entry describing the export
+pub const MANIFEST_ENTRY: &str = "manifest.json";
+/// Prefix of the archive entries holding shell records
+pub const SHELLS_PREFIX: &str = "shells/";
+/// Prefix of the archive entries holding images
+pub const IMAGES_PREFIX: &str = "images/";
+
+/// Written into every manifest; exports from a newer format are refused
+pub const EXPORT_FORMAT_VERSION: u32 = 1;
+
+/// Largest manifest or shell record accepted on import
+const MAX_JSON_BYTES: u64 = 16 * 1024 * 1024;
+
+/// How an export is written
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ExportFormat {
+    /// Shell records only, as one JSON document
+    #[serde(rename = "json")]
+    Json,
+    /// Shell records and their images, as a gzipped tar
+    #[serde(rename = "tar.gz", alias = "tgz")]
+    TarGz,
+}
+
+impl ExportFormat {
+    /// File extension, without the leading dot
+    pub fn extension(self) -> &'static str {
+        match self {
+            ExportFormat::Json => "json",
+            ExportFormat::TarGz => "tar.gz",
+        }
+    }
+
+    pub fn content_type(self) -> &'static str {
+        match self {
+            ExportFormat::Json => "application/json",
+            ExportFormat::TarGz => "application/gzip",
+        }
+    }
+}
+
+impl fmt::Display for ExportFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.extension())
+    }
+}
+
+impl FromStr for ExportFormat {
+    type Err = OurError;
+
+    fn from_str(format: &str) -> OurResult<Self> {
+        match format {
+            "json" => Ok(ExportFormat::Json),
+            "tar.gz" | "tgz" => Ok(ExportFormat::TarGz),
+            other => Err(OurError::App(format!(
+                "Unknown export format {other:?}, expected json or tar.gz"
+            ))),
+        }
+    }
+}
+
+/// Which shells an export includes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ExportSince {
+    #[default]
+    Everything,
+    /// Changed since the export recorded in [`EXPORT_MANIFEST_FILENAME`]
+    LastExport,
+    /// Changed after this time
+    Time(DateTime<Utc>),
+}
+
+impl FromStr for ExportSince {
+    type Err = OurError;
+
+    /// `last`, or an RFC 3339 time
+    fn from_str(since: &str) -> OurResult<Self> {
+        if since == "last" {
+            return Ok(ExportSince::LastExport);
+        }
+        DateTime::parse_from_rfc3339(since)
+            .map(|time| ExportSince::Time(time.with_timezone(&Utc)))
+            .map_err(|e| {
+                OurError::App(format!(
+                    "since must be \"last\" or an RFC 3339 time, got {since:?}: {e}"
+                ))
+            })
+    }
+}
+
+/// What an export holds and what it follows on from
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct ExportManifest {
+    pub format_version: u32,
+    /// Version of shell-sorter that wrote the export
+    pub crate_version: String,
+    pub format: ExportFormat,
+    /// When the export started; shells written after this go in the next `--since last`
+    pub exported_at: DateTime<Utc>,
+    /// Only shells captured or written after this are included; unset for a full export
+    pub since: Option<DateTime<Utc>>,
+    /// Content hash of the export this one follows on from, when it was `--since last`
+    pub previous_content_hash: Option<String>,
+    pub shell_count: usize,
+    pub image_count: usize,
+    /// Hash of the exported shell records
+    pub content_hash: String,
+    /// Every session in the dataset when exported, included or not
+    pub sessions: Vec<String>,
+}
+
+/// The JSON export format
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+struct JsonExport {
+    manifest: ExportManifest,
+    shells: BTreeMap<String, Shell>,
+}
+
+/// What importing an export did
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct ImportedExport {
+    pub manifest: ExportManifest,
+    /// Sessions written, replacing any copy already here
+    pub sessions: Vec<String>,
+    /// Images written; images already in the image directory are kept
+    pub images: usize,
+    /// Sessions the export's dataset had that neither it nor this one has,
+    /// because an earlier export wasn't imported
+    pub missing_sessions: Vec<String>,
+}
+
+fn export_error(action: &str, e: impl fmt::Display) -> OurError {
+    OurError::App(format!("Failed to {action}: {e}"))
+}
+
+/// The manifest of the last export from `data_directory`, if there was one
+pub fn read_manifest(data_directory: &Path) -> OurResult<Option<ExportManifest>> {
+    let path = data_directory.join(EXPORT_MANIFEST_FILENAME);
+    let contents = match fs::read(&path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(export_error(&format!("read {}", path.display()), e)),
+    };
+    serde_json::from_slice(&contents)
+        .map(Some)
+        .map_err(|e| OurError::App(format!("Invalid {}: {e}", path.display())))
+}
+
+fn write_manifest(data_directory: &Path, manifest: &ExportManifest) -> OurResult<()> {
+    fs::create_dir_all(data_directory).map_err(|e| export_error("create data directory", e))?;
+    let path = data_directory.join(EXPORT_MANIFEST_FILENAME);
+    write_atomic(&path, &serde_json::to_vec_pretty(manifest)?)
+        .map_err(|e| export_error(&format!("write {}", path.display()), e))
+}
+
+/// Write the shells `since` picks out to `writer`
+pub fn export_dataset<W: Write>(
+    shell_data: &ShellDataManager,
+    settings: &Settings,
+    format: ExportFormat,
+    since: ExportSince,
+    writer: W,
+) -> OurResult<ExportManifest> {
+    let exported_at = Utc::now();
+    let (since, previous_content_hash) = match since {
+        ExportSince::Everything => (None, None),
+        ExportSince::Time(time) => (Some(time), None),
+        ExportSince::LastExport => match read_manifest(&settings.data_directory)? {
+            Some(last) => (Some(last.exported_at), Some(last.content_hash)),
+            None => {
+                return Err(OurError::App(format!(
+                    "No earlier export recorded in {}; run a full export first",
+                    settings
+                        .data_directory
+                        .join(EXPORT_MANIFEST_FILENAME)
+                        .display()
+                )));
+            }
+        },
+    };
+
+    let all = shell_data.list_shells()?;
+    let sessions: Vec<String> = all
+        .iter()
+        .map(|(session_id, _)| session_id.clone())
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .collect();
+    let mut shells = BTreeMap::new();
+    for (session_id, shell) in all {
+        let changed = match since {
+            None => true,
+            Some(since) => {
+                shell.date_captured > since
+                    || shell_data
+                        .modified_at(&session_id)?
+                        .is_some_and(|modified| modified > since)
+            }
+        };
+        if changed {
+            shells.insert(session_id, shell);
+        }
+    }
+
+    let mut manifest = ExportManifest {
+        format_version: EXPORT_FORMAT_VERSION,
+        crate_version: env!("CARGO_PKG_VERSION").to_string(),
+        format,
+        exported_at,
+        since,
+        previous_content_hash,
+        shell_count: shells.len(),
+        image_count: 0,
+        content_hash: content_hash(&serde_json::to_vec(&shells)?),
+        sessions,
+    };
+    match format {
+        ExportFormat::Json => {
+            let mut writer = io::BufWriter::new(writer);
+            let export = JsonExport { manifest, shells };
+            serde_json::to_writer_pretty(&mut writer, &export)?;
+            writer
+                .flush()
+                .map_err(|e| export_error("finish export", e))?;
+            manifest = export.manifest;
+        }
+        ExportFormat::TarGz => {
+            let images = existing_images(settings, &shells);
+            manifest.image_count = images.len();
+            write_tar_gz(&manifest, &shells, &images, writer)?;
+        }
+    }
+
+    info!(
+        "Exported {} shells and {} images{}",
+        manifest.shell_count,
+        manifest.image_count,
+        manifest
+            .since
+            .map(|since| format!(" changed since {}", since.to_rfc3339()))
+            .unwrap_or_default()
+    );
+    Ok(manifest)
+}
+
+/// The images the shells reference that are on disk, by filename
+fn existing_images(
+    settings: &Settings,
+    shells: &BTreeMap<String, Shell>,
+) -> BTreeMap<String, PathBuf> {
+    let mut images = BTreeMap::new();
+    for (session_id, shell) in shells {
+        for filename in referenced_images(shell) {
+            let path = match SafeName::file_name("image filename", &filename) {
+                Ok(safe) => settings.image_directory.join(safe.to_string()),
+                Err(e) => {
+                    warn!("Left image {filename:?} of session {session_id} out of the export: {e}");
+                    continue;
+                }
+            };
+            if path.is_file() {
+                images.insert(filename, path);
+            } else {
+                warn!(
+                    "Image {filename} of session {session_id} is missing, left out of the export"
+                );
+            }
+        }
+    }
+    images
+}
+
+fn write_tar_gz<W: Write>(
+    manifest: &ExportManifest,
+    shells: &BTreeMap<String, Shell>,
+    images: &BTreeMap<String, PathBuf>,
+    writer: W,
+) -> OurResult<()> {
+    let mut tar = tar::Builder::new(GzEncoder::new(writer, Compression::default()));
+    let mtime = u64::try_from(manifest.exported_at.timestamp()).unwrap_or_default();
+    let mut append_json = |name: &str, contents: Vec<u8>| -> OurResult<()> {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(contents.len() as u64);
+        header.set_mode(0o644);
+        header.set_mtime(mtime);
+        tar.append_data(&mut header, name, contents.as_slice())
+            .map_err(|e| export_error(&format!("add {name} to export"), e))
+    };
+
+    // The manifest goes first so an import can check it before reading the rest
+    append_json(MANIFEST_ENTRY, serde_json::to_vec_pretty(manifest)?)?;
+    for (session_id, shell) in shells {
+        append_json(
+            &format!("{SHELLS_PREFIX}{session_id}.json"),
+            serde_json::to_vec_pretty(shell)?,
+        )?;
+    }
+    for (filename, path) in images {
+        let name = format!("{IMAGES_PREFIX}{filename}");
+        tar.append_path_with_name(path, &name)
+            .map_err(|e| export_error(&format!("add {name} to export"), e))?;
+    }
+    tar.into_inner()
+        .and_then(GzEncoder::finish)
+        .map_err(|e| export_error("finish export", e))?
+        .flush()
+        .map_err(|e| export_error("finish export", e))
+}
+
+/// Export to a temporary file, ready to be streamed, and with `record` keep
+/// its manifest for the next `--since last`
+pub fn export_to_temp_file(
+    shell_data: &ShellDataManager,
+    settings: &Settings,
+    format: ExportFormat,
+    since: ExportSince,
+    record: bool,
+) -> OurResult<(TempBundle, ExportManifest)> {
+    let export = TempBundle::new("dataset-export");
+    let file = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(export.path())
+        .map_err(|e| export_error("create export file", e))?;
+    let manifest = export_dataset(shell_data, settings, format, since, file)?;
+    if record {
+        write_manifest(&settings.data_directory, &manifest)?;
+    }
+    Ok((export, manifest))
+}
+
+fn read_json<T: for<'de> Deserialize<'de>>(entry: &mut impl Read, name: &str) -> OurResult<T> {
+    let mut contents = Vec::new();
+    entry
+        .take(MAX_JSON_BYTES)
+        .read_to_end(&mut contents)
+        .map_err(|e| export_error(&format!("read {name}"), e))?;
+    serde_json::from_slice(&contents)
+        .map_err(|e| OurError::App(format!("Invalid {name} in export: {e}")))
+}
+
+fn check_format_version(manifest: &ExportManifest) -> OurResult<()> {
+    if manifest.format_version > EXPORT_FORMAT_VERSION {
+        return Err(OurError::App(format!(
+            "Export format {} is newer than this version of shell-sorter supports ({EXPORT_FORMAT_VERSION})",
+            manifest.format_version
+        )));
+    }
+    Ok(())
+}
+
+/// Import an export of either format, telling them apart by the gzip header
+///
+/// Shell records replace any copy already here and are only written once the
+/// whole export has been read; images written before an invalid entry turns up
+/// are removed again.
+pub fn import_dataset<R: Read>(
+    shell_data: &ShellDataManager,
+    settings: &Settings,
+    reader: R,
+) -> OurResult<ImportedExport> {
+    let mut reader = BufReader::new(reader);
+    let gzipped = reader
+        .fill_buf()
+        .map_err(|e| export_error("read export", e))?
+        .starts_with(&[0x1f, 0x8b]);
+
+    let mut written = Vec::new();
+    let read = if gzipped {
+        read_tar_gz(settings, reader, &mut written)
+    } else {
+        serde_json::from_reader(reader)
+            .map_err(|e| OurError::App(format!("Not a shell-sorter export: {e}")))
+            .and_then(|export: JsonExport| {
+                check_format_version(&export.manifest)?;
+                Ok((export.manifest, export.shells))
+            })
+    };
+    let result = read.and_then(|(manifest, shells)| {
+        for (session_id, shell) in &shells {
+            SafeName::new("session_id", session_id)?;
+            shell_data.save_shell(session_id, shell)?;
+        }
+        Ok((manifest, shells))
+    });
+    let (manifest, shells) = match result {
+        Ok(imported) => imported,
+        Err(e) => {
+            for path in &written {
+                if let Err(remove_error) = fs::remove_file(path) {
+                    warn!("Failed to remove {}: {remove_error}", path.display());
+                }
+            }
+            return Err(e);
+        }
+    };
+
+    let present: BTreeSet<String> = shell_data
+        .list_shells()?
+        .into_iter()
+        .map(|(session_id, _)| session_id)
+        .collect();
+    let missing_sessions: Vec<String> = manifest
+        .sessions
+        .iter()
+        .filter(|session_id| !present.contains(*session_id))
+        .cloned()
+        .collect();
+    if !missing_sessions.is_empty() {
+        warn!(
+            "Imported export is missing {} sessions its dataset had; import the exports before it",
+            missing_sessions.len()
+        );
+    }
+    info!(
+        "Imported {} shells and {} images from a {} export",
+        shells.len(),
+        written.len(),
+        manifest.format
+    );
+    Ok(ImportedExport {
+        sessions: shells.into_keys().collect(),
+        images: written.len(),
+        missing_sessions,
+        manifest,
+    })
+}
+
+/// Read a tar.gz export, writing its images as they come and returning its shell records
+fn read_tar_gz<R: Read>(
+    settings: &Settings,
+    reader: R,
+    written: &mut Vec<PathBuf>,
+) -> OurResult<(ExportManifest, BTreeMap<String, Shell>)> {
+    let mut archive = tar::Archive::new(GzDecoder::new(reader));
+    let mut manifest: Option<ExportManifest> = None;
+    let mut shells = BTreeMap::new();
+    fs::create_dir_all(&settings.image_directory)
+        .map_err(|e| export_error("create image directory", e))?;
+
+    for entry in archive
+        .entries()
+        .map_err(|e| OurError::App(format!("Not a shell-sorter export: {e}")))?
+    {
+        let mut entry = entry.map_err(|e| export_error("read export", e))?;
+        if !entry.header().entry_type().is_file() {
+            continue;
+        }
+        let name = entry
+            .path()
+            .map_err(|e| export_error("read export", e))?
+            .to_string_lossy()
+            .into_owned();
+        if name == MANIFEST_ENTRY {
+            let read: ExportManifest = read_json(&mut entry, MANIFEST_ENTRY)?;
+            check_format_version(&read)?;
+            manifest = Some(read);
+        } else if let Some(session_id) = name
+            .strip_prefix(SHELLS_PREFIX)
+            .and_then(|file| file.strip_suffix(".json"))
+        {
+            let session_id = SafeName::new("session_id", session_id)?;
+            shells.insert(session_id.to_string(), read_json(&mut entry, &name)?);
+        } else if let Some(filename) = name.strip_prefix(IMAGES_PREFIX) {
+            let filename = SafeName::file_name("export image", filename)?;
+            let path = settings.image_directory.join(filename.to_string());
+            let mut file = match OpenOptions::new().write(true).create_new(true).open(&path) {
+                Ok(file) => file,
+                // The same image from an earlier import
+                Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+                Err(e) => return Err(export_error(&format!("create {}", path.display()), e)),
+            };
+            written.push(path.clone());
+            io::copy(&mut entry, &mut file)
+                .map_err(|e| export_error(&format!("write {}", path.display()), e))?;
+        } else {
+            return Err(OurError::InvalidName {
+                field: "export entry".to_string(),
+                reason: format!("{name:?} is not part of a shell-sorter export"),
+            });
+        }
+    }
+
+    let manifest =
+        manifest.ok_or_else(|| OurError::App(format!("Export has no {MANIFEST_ENTRY}")))?;
+    Ok((manifest, shells))
+}
+
+/// Open `path` for [`import_dataset`]
+pub fn open_export(path: &Path) -> OurResult<File> {
+    File::open(path).map_err(|e| export_error(&format!("open {}", path.display()), e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn test_settings(temp_dir: &TempDir) -> Settings {
+        Settings {
+            image_directory: temp_dir.path().join("images"),
+            data_directory: temp_dir.path().join("data"),
+            ..Settings::default()
+        }
+    }
+
+    fn add_shell(settings: &Settings, shell_data: &ShellDataManager, brand: &str) -> String {
+        let session_id = ShellDataManager::generate_session_id();
+        let image = format!("{session_id}_camera_0.jpg");
+        fs::create_dir_all(&settings.image_directory).expect("image directory should be created");
+        fs::write(settings.image_directory.join(&image), b"jpeg").expect("image should be written");
+        let mut shell = Shell::new(brand.to_string(), "9mm".to_string());
+        shell.add_image(image);
+        shell_data
+            .save_shell(&session_id, &shell)
+            .expect("shell should be saved");
+        session_id
+    }
+
+    /// Session IDs and image names in a tar.gz export, with its manifest
+    fn tar_gz_contents(bytes: &[u8]) -> (ExportManifest, Vec<String>, Vec<String>) {
+        let temp_dir = TempDir::new().expect("Failed to create temp dir");
+        let settings = test_settings(&temp_dir);
+        let mut written = Vec::new();
+        let (manifest, shells) =
+            read_tar_gz(&settings, bytes, &mut written).expect("export should read back");
+        let images = written
+            .iter()
+            .filter_map(|path| path.file_name())
+            .map(|name| name.to_string_lossy().into_owned())
+            .collect();
+        (manifest, shells.into_keys().collect(), images)
+    }
+
+    #[test]
+    fn test_incremental_export_holds_only_new_shells() {
+        let temp_dir = TempDir::new().expect("Failed to create temp dir");
+        let settings = test_settings(&temp_dir);
+        let shell_data = ShellDataManager::new(settings.data_directory.clone());
+        let first = add_shell(&settings, &shell_data, "Winchester");
+        let second = add_shell(&settings, &shell_data, "Federal");
+
+        assert!(
+            export_to_temp_file(
+                &shell_data,
+                &settings,
+                ExportFormat::TarGz,
+                ExportSince::LastExport,
+                true,
+            )
+            .is_err(),
+            "there's no earlier export to continue from"
+        );
+        let (full, full_manifest) = export_to_temp_file(
+            &shell_data,
+            &settings,
+            ExportFormat::TarGz,
+            ExportSince::Everything,
+            true,
+        )
+        .expect("full export should be written");
+        let (manifest, sessions, images) =
+            tar_gz_contents(&fs::read(full.path()).expect("export should be readable"));
+        assert_eq!(manifest, full_manifest);
+        let mut expected = vec![first.clone(), second.clone()];
+        expected.sort();
+        assert_eq!(sessions, expected);
+        assert_eq!(images.len(), 2);
+        assert_eq!(
+            read_manifest(&settings.data_directory).expect("manifest should read"),
+            Some(full_manifest.clone())
+        );
+
+        let third = add_shell(&settings, &shell_data, "Remington");
+        let fourth = add_shell(&settings, &shell_data, "Speer");
+        let (delta, delta_manifest) = export_to_temp_file(
+            &shell_data,
+            &settings,
+            ExportFormat::TarGz,
+            ExportSince::LastExport,
+            true,
+        )
+        .expect("incremental export should be written");
+        let (manifest, sessions, images) =
+            tar_gz_contents(&fs::read(delta.path()).expect("export should be readable"));
+        let mut expected = vec![third.clone(), fourth.clone()];
+        expected.sort();
+        assert_eq!(sessions, expected);
+        assert_eq!(images.len(), 2);
+        assert_eq!(manifest.shell_count, 2);
+        assert_eq!(manifest.since, Some(full_manifest.exported_at));
+        assert_eq!(
+            manifest.previous_content_hash.as_deref(),
+            Some(full_manifest.content_hash.as_str())
+        );
+        assert_eq!(manifest.sessions.len(), 4);
+        // The manifest advances, so the next delta is empty
+        assert!(delta_manifest.exported_at > full_manifest.exported_at);
+        assert_eq!(
+            read_manifest(&settings.data_directory).expect("manifest should read"),
+            Some(delta_manifest.clone())
+        );
+
+        // The JSON format picks the same shells
+        let (json, json_manifest) = export_to_temp_file(
+            &shell_data,
+            &settings,
+            ExportFormat::Json,
+            ExportSince::Time(full_manifest.exported_at),
+            true,
+        )
+        .expect("JSON export should be written");
+        let export: JsonExport =
+            serde_json::from_slice(&fs::read(json.path()).expect("export should be readable"))
+                .expect("JSON export should parse");
+        assert_eq!(export.manifest, json_manifest);
+        assert_eq!(export.shells.into_keys().collect::<Vec<_>>(), expected);
+        let (empty, _) = export_to_temp_file(
+            &shell_data,
+            &settings,
+            ExportFormat::Json,
+            ExportSince::LastExport,
+            true,
+        )
+        .expect("empty export should be written");
+        let export: JsonExport =
+            serde_json::from_slice(&fs::read(empty.path()).expect("export should be readable"))
+                .expect("JSON export should parse");
+        assert!(export.shells.is_empty());
+    }
+
+    #[test]
+    fn test_importing_a_delta_alone_names_the_missing_sessions() {
+        let source_dir = TempDir::new().expect("Failed to create temp dir");
+        let source = test_settings(&source_dir);
+        let source_data = ShellDataManager::new(source.data_directory.clone());
+        let earlier = add_shell(&source, &source_data, "Winchester");
+        export_to_temp_file(
+            &source_data,
+            &source,
+            ExportFormat::TarGz,
+            ExportSince::Everything,
+            true,
+        )
+        .expect("full export should be written");
+        let later = add_shell(&source, &source_data, "Federal");
+        let (delta, _) = export_to_temp_file(
+            &source_data,
+            &source,
+            ExportFormat::TarGz,
+            ExportSince::LastExport,
+            true,
+        )
+        .expect("incremental export should be written");
+
+        let target_dir = TempDir::new().expect("Failed to create temp dir");
+        let target = test_settings(&target_dir);
+        let target_data = ShellDataManager::new(target.data_directory.clone());
+        let imported = import_dataset(
+            &target_data,
+            &target,
+            open_export(delta.path()).expect("export should open"),
+        )
+        .expect("delta should import");
+        assert_eq!(imported.sessions, vec![later.clone()]);
+        assert_eq!(imported.images, 1);
+        assert_eq!(imported.missing_sessions, vec![earlier]);
+        assert!(
+            target
+                .image_directory
+                .join(format!("{later}_camera_0.jpg"))
+                .is_file()
+        );
+        assert_eq!(
+            target_data
+                .load_shell(&later)
+                .expect("shell should be imported")
+                .brand,
+            "Federal"
+        );
+    }
+
+    #[test]
+    fn test_since_parses_last_or_a_time() {
+        assert_eq!(
+            "last".parse::<ExportSince>().expect("last should parse"),
+            ExportSince::LastExport
+        );
+        assert_eq!(
+            "2024-05-01T12:00:00+02:00"
+                .parse::<ExportSince>()
+                .expect("time should parse"),
+            ExportSince::Time(
+                DateTime::parse_from_rfc3339("2024-05-01T10:00:00Z")
+                    .expect("time should parse")
+                    .with_timezone(&Utc)
+            )
+        );
+        assert!("yesterday".parse::<ExportSince>().is_err());
+        assert_eq!(
+            "tgz".parse::<ExportFormat>().expect("tgz should parse"),
+            ExportFormat::TarGz
+        );
+    }
+}
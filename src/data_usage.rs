@@ -0,0 +1,364 @@
+//! Disk usage of the image and data directories, by session and case type.
+//!
+//! Walking the directories touches every file, so the last report is cached
+//! and refreshed on a timer or when a caller asks for it. Files are credited
+//! to a session when its shell record lists them as images, when they are the
+//! record itself, or when their name starts with the session ID (composites
+//! and their metadata). Anything else counts as unattributed.
+
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::shell_data::Shell;
+
+/// How deep below the image and data directories the walk goes
+pub const MAX_SCAN_DEPTH: usize = 4;
+
+/// How often the server rescans in the background by default
+pub const DEFAULT_USAGE_REFRESH: Duration = Duration::from_secs(600);
+
+/// Bytes used by one session's files
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct SessionUsage {
+    pub session_id: String,
+    /// `brand_shell_type` of the session's shell, if its record loaded
+    pub case_type: Option<String>,
+    pub files: usize,
+    pub bytes: u64,
+}
+
+/// Bytes used by every session of one case type
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct CaseTypeUsage {
+    pub case_type: String,
+    pub sessions: usize,
+    pub bytes: u64,
+}
+
+/// A file or directory the walk couldn't read
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct UsageError {
+    pub path: PathBuf,
+    pub reason: String,
+}
+
+/// The result of one walk of the image and data directories
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct UsageReport {
+    pub total_bytes: u64,
+    pub image_bytes: u64,
+    pub data_bytes: u64,
+    pub files: usize,
+    /// Bytes in files that belong to no known session
+    pub unattributed_bytes: u64,
+    /// Every session with files, largest first
+    pub sessions: Vec<SessionUsage>,
+    /// Case types, largest first
+    pub case_types: Vec<CaseTypeUsage>,
+    /// Entries that were skipped, e.g. for permissions or depth
+    pub errors: Vec<UsageError>,
+    pub scanned_at: DateTime<Utc>,
+    pub scan_duration_ms: u64,
+}
+
+impl UsageReport {
+    /// The report with only the `limit` largest sessions
+    pub fn top_sessions(mut self, limit: usize) -> Self {
+        self.sessions.truncate(limit);
+        self
+    }
+}
+
+/// Walk `image_directory` and `data_directory` and credit their files to `shells`
+pub fn scan(
+    image_directory: &Path,
+    data_directory: &Path,
+    shells: &[(String, Shell)],
+    max_depth: usize,
+) -> UsageReport {
+    let started = Instant::now();
+    let mut errors = Vec::new();
+    let mut image_files = Vec::new();
+    let mut data_files = Vec::new();
+    walk(image_directory, max_depth, &mut image_files, &mut errors);
+    walk(data_directory, max_depth, &mut data_files, &mut errors);
+
+    let session_ids: HashSet<&str> = shells.iter().map(|(id, _)| id.as_str()).collect();
+    let images: HashMap<&str, &str> = shells
+        .iter()
+        .flat_map(|(session_id, shell)| {
+            shell
+                .image_filenames
+                .iter()
+                .map(move |filename| (filename.as_str(), session_id.as_str()))
+        })
+        .collect();
+    let owner = |path: &Path| -> Option<String> {
+        let name = path.file_name()?.to_str()?;
+        if let Some(session_id) = images.get(name) {
+            return Some(session_id.to_string());
+        }
+        let prefix = name
+            .split_once('_')
+            .map(|(prefix, _)| prefix)
+            .or_else(|| name.split_once('.').map(|(stem, _)| stem))?;
+        session_ids.contains(prefix).then(|| prefix.to_string())
+    };
+
+    let mut by_session: HashMap<String, (usize, u64)> = HashMap::new();
+    let mut unattributed_bytes = 0;
+    let files = image_files.len() + data_files.len();
+    let image_bytes = image_files.iter().map(|(_, size)| size).sum();
+    let data_bytes = data_files.iter().map(|(_, size)| size).sum();
+    for (path, size) in image_files.iter().chain(&data_files) {
+        match owner(path) {
+            Some(session_id) => {
+                let entry = by_session.entry(session_id).or_default();
+                entry.0 += 1;
+                entry.1 += size;
+            }
+            None => unattributed_bytes += size,
+        }
+    }
+
+    let case_type_of: HashMap<&str, String> = shells
+        .iter()
+        .map(|(session_id, shell)| (session_id.as_str(), shell.get_case_type_key()))
+        .collect();
+    let mut sessions: Vec<SessionUsage> = by_session
+        .into_iter()
+        .map(|(session_id, (files, bytes))| SessionUsage {
+            case_type: case_type_of.get(session_id.as_str()).cloned(),
+            session_id,
+            files,
+            bytes,
+        })
+        .collect();
+    sessions.sort_by(|a, b| {
+        b.bytes
+            .cmp(&a.bytes)
+            .then_with(|| a.session_id.cmp(&b.session_id))
+    });
+
+    let mut by_case_type: HashMap<&str, (usize, u64)> = HashMap::new();
+    for session in &sessions {
+        if let Some(case_type) = &session.case_type {
+            let entry = by_case_type.entry(case_type).or_default();
+            entry.0 += 1;
+            entry.1 += session.bytes;
+        }
+    }
+    let mut case_types: Vec<CaseTypeUsage> = by_case_type
+        .into_iter()
+        .map(|(case_type, (sessions, bytes))| CaseTypeUsage {
+            case_type: case_type.to_string(),
+            sessions,
+            bytes,
+        })
+        .collect();
+    case_types.sort_by(|a, b| {
+        b.bytes
+            .cmp(&a.bytes)
+            .then_with(|| a.case_type.cmp(&b.case_type))
+    });
+
+    UsageReport {
+        total_bytes: image_bytes + data_bytes,
+        image_bytes,
+        data_bytes,
+        files,
+        unattributed_bytes,
+        sessions,
+        case_types,
+        errors,
+        scanned_at: Utc::now(),
+        scan_duration_ms: started.elapsed().as_millis() as u64,
+    }
+}
+
+/// Collect the size of every regular file under `directory`, going `depth` levels down
+fn walk(
+    directory: &Path,
+    depth: usize,
+    files: &mut Vec<(PathBuf, u64)>,
+    errors: &mut Vec<UsageError>,
+) {
+    let skip = |errors: &mut Vec<UsageError>, path: &Path, reason: String| {
+        errors.push(UsageError {
+            path: path.to_path_buf(),
+            reason,
+        })
+    };
+    let entries = match std::fs::read_dir(directory) {
+        Ok(entries) => entries,
+        // A directory that was never created holds nothing
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return,
+        Err(e) => return skip(errors, directory, e.to_string()),
+    };
+    let mut subdirectories = Vec::new();
+    for entry in entries {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(e) => {
+                skip(errors, directory, e.to_string());
+                continue;
+            }
+        };
+        let path = entry.path();
+        // Symlinks aren't followed, so nothing is counted twice or escapes the directory
+        match entry.file_type() {
+            Ok(file_type) if file_type.is_dir() => subdirectories.push(path),
+            Ok(file_type) if file_type.is_file() => match entry.metadata() {
+                Ok(metadata) => files.push((path, metadata.len())),
+                Err(e) => skip(errors, &path, e.to_string()),
+            },
+            Ok(_) => {}
+            Err(e) => skip(errors, &path, e.to_string()),
+        }
+    }
+    for subdirectory in subdirectories {
+        if depth == 0 {
+            skip(
+                errors,
+                &subdirectory,
+                "deeper than the scan depth limit".to_string(),
+            );
+        } else {
+            walk(&subdirectory, depth - 1, files, errors);
+        }
+    }
+}
+
+/// The last usage report, shared between the refresh timer and requests
+#[derive(Debug, Clone, Default)]
+pub struct UsageCache {
+    report: Arc<Mutex<Option<UsageReport>>>,
+}
+
+impl UsageCache {
+    /// The last report, if a scan has finished
+    pub fn get(&self) -> Option<UsageReport> {
+        self.report.lock().ok().and_then(|report| report.clone())
+    }
+
+    pub fn store(&self, report: UsageReport) {
+        if let Ok(mut cached) = self.report.lock() {
+            *cached = Some(report);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write(path: &Path, bytes: usize) {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent).expect("directory should be created");
+        }
+        std::fs::write(path, vec![0u8; bytes]).expect("file should be written");
+    }
+
+    fn shell(brand: &str, shell_type: &str, images: &[&str]) -> Shell {
+        let mut shell = Shell::new(brand.to_string(), shell_type.to_string());
+        shell.image_filenames = images.iter().map(|image| image.to_string()).collect();
+        shell
+    }
+
+    #[test]
+    fn test_scan_credits_files_to_sessions_and_case_types() {
+        let temp_dir = tempfile::TempDir::new().expect("temp dir should be created");
+        let images = temp_dir.path().join("images");
+        let data = temp_dir.path().join("data");
+
+        let shells = vec![
+            (
+                "a".to_string(),
+                shell("Winchester", "9mm", &["a_camera_0.jpg", "renamed.jpg"]),
+            ),
+            (
+                "b".to_string(),
+                shell("Winchester", "9mm", &["b_camera_0.jpg"]),
+            ),
+            (
+                "c".to_string(),
+                shell("Federal", "308win", &["c_camera_0.jpg"]),
+            ),
+        ];
+        write(&images.join("a_camera_0.jpg"), 1000);
+        write(&images.join("renamed.jpg"), 500);
+        write(&images.join("b_camera_0.jpg"), 300);
+        write(&images.join("c_camera_0.jpg"), 4000);
+        write(&images.join("stray.jpg"), 70);
+        write(&data.join("a.json"), 10);
+        write(&data.join("b.json"), 20);
+        write(&data.join("c.json"), 30);
+        write(&data.join("composites/a_composite.jpg"), 200);
+        write(&data.join("composites/a_composite.json"), 5);
+        write(&data.join("too/deep/for/the/scan/file.bin"), 9999);
+
+        let report = scan(&images, &data, &shells, 3);
+
+        assert_eq!(report.image_bytes, 1000 + 500 + 300 + 4000 + 70);
+        assert_eq!(report.data_bytes, 10 + 20 + 30 + 200 + 5);
+        assert_eq!(report.total_bytes, report.image_bytes + report.data_bytes);
+        assert_eq!(report.files, 10);
+        assert_eq!(report.unattributed_bytes, 70);
+        assert_eq!(
+            report.sessions,
+            vec![
+                SessionUsage {
+                    session_id: "c".to_string(),
+                    case_type: Some("Federal_308win".to_string()),
+                    files: 2,
+                    bytes: 4030,
+                },
+                SessionUsage {
+                    session_id: "a".to_string(),
+                    case_type: Some("Winchester_9mm".to_string()),
+                    files: 5,
+                    bytes: 1715,
+                },
+                SessionUsage {
+                    session_id: "b".to_string(),
+                    case_type: Some("Winchester_9mm".to_string()),
+                    files: 2,
+                    bytes: 320,
+                },
+            ]
+        );
+        assert_eq!(
+            report.case_types,
+            vec![
+                CaseTypeUsage {
+                    case_type: "Federal_308win".to_string(),
+                    sessions: 1,
+                    bytes: 4030,
+                },
+                CaseTypeUsage {
+                    case_type: "Winchester_9mm".to_string(),
+                    sessions: 2,
+                    bytes: 2035,
+                },
+            ]
+        );
+        assert_eq!(report.errors.len(), 1, "{:?}", report.errors);
+        assert!(report.errors[0].path.ends_with("too/deep/for/the"));
+        assert_eq!(report.clone().top_sessions(1).sessions.len(), 1);
+
+        // Missing directories are empty, not errors
+        let empty = scan(&temp_dir.path().join("nowhere"), &data, &[], 3);
+        assert_eq!(empty.image_bytes, 0);
+        assert_eq!(empty.unattributed_bytes, empty.data_bytes);
+        assert!(empty.sessions.is_empty());
+    }
+}
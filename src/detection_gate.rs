@@ -0,0 +1,275 @@
+//! Holding camera detection to one scan at a time and one per freshness window.
+//!
+//! Every dashboard load asks for a detection, so a few open tabs kept the
+//! camera managers rescanning, and streams stuttered while they did. A
+//! [`DetectionGate`] sits in front of one manager's detection: a request made
+//! while a scan runs shares that scan instead of queueing another, and one
+//! made within `detect_freshness_s` of the last scan finishing gets that
+//! scan's result back, marked cached. Forcing skips the window, for the
+//! Detect Cameras button, but still never starts a second scan alongside one
+//! that is running.
+
+use std::future::Future;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use serde::Serialize;
+use tokio::sync::{Mutex, OwnedMutexGuard};
+use tokio::time::Instant;
+
+/// How long a detection is reused for unless `detect_freshness_s` says otherwise
+pub const DEFAULT_DETECT_FRESHNESS: Duration = Duration::from_secs(10);
+
+/// How many detection requests scanned and how many were answered without one
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub struct DetectionStats {
+    pub scans: u64,
+    pub cached: u64,
+}
+
+/// The last scan to finish
+#[derive(Debug)]
+struct LastScan<T> {
+    finished: Instant,
+    result: T,
+}
+
+/// One manager's detection, see the module docs
+#[derive(Debug)]
+pub struct DetectionGate<T> {
+    last: Arc<Mutex<Option<LastScan<T>>>>,
+    scans: Arc<AtomicU64>,
+    cached: Arc<AtomicU64>,
+}
+
+impl<T> Clone for DetectionGate<T> {
+    fn clone(&self) -> Self {
+        Self {
+            last: self.last.clone(),
+            scans: self.scans.clone(),
+            cached: self.cached.clone(),
+        }
+    }
+}
+
+impl<T> Default for DetectionGate<T> {
+    fn default() -> Self {
+        Self {
+            last: Arc::new(Mutex::new(None)),
+            scans: Arc::default(),
+            cached: Arc::default(),
+        }
+    }
+}
+
+impl<T: Clone> DetectionGate<T> {
+    /// The result of `scan`, or of the last scan if it finished less than `freshness` ago
+    ///
+    /// Callers arriving while a scan runs wait for it and get its result as
+    /// cached. With `force` the window is ignored and a new scan runs once any
+    /// running one has finished. Only a successful scan is kept, so a failed
+    /// one can be retried straight away. The flag is whether the result was
+    /// cached.
+    pub async fn run<F, Fut, E>(
+        &self,
+        force: bool,
+        freshness: Duration,
+        scan: F,
+    ) -> (Result<T, E>, bool)
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<T, E>>,
+    {
+        let started = Instant::now();
+        let mut last = self.last.lock().await;
+        if let Some(last) = last.as_ref() {
+            // Finished while this caller waited, so it is the scan it would have run
+            let joined = last.finished >= started;
+            if joined || (!force && last.finished.elapsed() < freshness) {
+                self.cached.fetch_add(1, Ordering::Relaxed);
+                return (Ok(last.result.clone()), true);
+            }
+        }
+        self.scans.fetch_add(1, Ordering::Relaxed);
+        let result = scan().await;
+        if let Ok(result) = &result {
+            *last = Some(LastScan {
+                finished: Instant::now(),
+                result: result.clone(),
+            });
+        }
+        (result, false)
+    }
+
+    /// Start a scan that finishes in the background, or `None` if one is running or fresh
+    ///
+    /// The scan holds the returned permit until it is done, and until then
+    /// every other request is told to rely on it.
+    pub fn try_start(&self, force: bool, freshness: Duration) -> Option<ScanPermit<T>> {
+        let fresh = |last: &Option<LastScan<T>>| {
+            last.as_ref()
+                .is_some_and(|last| last.finished.elapsed() < freshness)
+        };
+        match self.last.clone().try_lock_owned() {
+            Ok(last) if force || !fresh(&last) => {
+                self.scans.fetch_add(1, Ordering::Relaxed);
+                Some(ScanPermit { last })
+            }
+            _ => {
+                self.cached.fetch_add(1, Ordering::Relaxed);
+                None
+            }
+        }
+    }
+
+    /// Counts since the server started
+    pub fn stats(&self) -> DetectionStats {
+        DetectionStats {
+            scans: self.scans.load(Ordering::Relaxed),
+            cached: self.cached.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// A background scan's hold on its gate
+#[derive(Debug)]
+pub struct ScanPermit<T> {
+    last: OwnedMutexGuard<Option<LastScan<T>>>,
+}
+
+impl<T> ScanPermit<T> {
+    /// Record the scan's result and let the next one start
+    ///
+    /// A permit dropped without finishing frees the gate without starting a
+    /// freshness window, so a failed scan can be retried straight away.
+    pub fn finish(mut self, result: T) {
+        *self.last = Some(LastScan {
+            finished: Instant::now(),
+            result,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::AtomicU32;
+
+    #[tokio::test]
+    async fn test_a_burst_of_requests_scans_once() {
+        let gate = DetectionGate::default();
+        let scans = Arc::new(AtomicU32::new(0));
+        let requests = (0..8).map(|_| {
+            let gate = gate.clone();
+            let scans = scans.clone();
+            async move {
+                gate.run(false, DEFAULT_DETECT_FRESHNESS, || async move {
+                    tokio::time::sleep(Duration::from_millis(20)).await;
+                    Ok::<_, ()>(scans.fetch_add(1, Ordering::SeqCst) + 1)
+                })
+                .await
+            }
+        });
+        let results = futures_util::future::join_all(requests).await;
+
+        assert_eq!(scans.load(Ordering::SeqCst), 1);
+        assert!(
+            results.iter().all(|(scan, _)| *scan == Ok(1)),
+            "{results:?}"
+        );
+        assert_eq!(results.iter().filter(|(_, cached)| !cached).count(), 1);
+        assert_eq!(
+            gate.stats(),
+            DetectionStats {
+                scans: 1,
+                cached: 7
+            }
+        );
+
+        // Forcing scans again, however fresh the last scan is
+        let (scan, cached) = gate
+            .run(true, DEFAULT_DETECT_FRESHNESS, || async { Ok::<_, ()>(2) })
+            .await;
+        assert_eq!((scan, cached), (Ok(2), false));
+        // Without a window every request scans
+        let (scan, cached) = gate
+            .run(false, Duration::ZERO, || async { Ok::<_, ()>(3) })
+            .await;
+        assert_eq!((scan, cached), (Ok(3), false));
+        assert_eq!(
+            gate.stats(),
+            DetectionStats {
+                scans: 3,
+                cached: 7
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn test_a_failed_scan_is_not_reused() {
+        let gate = DetectionGate::default();
+        let (scan, cached) = gate
+            .run(false, DEFAULT_DETECT_FRESHNESS, || async {
+                Err::<u32, _>("no cameras")
+            })
+            .await;
+        assert_eq!((scan, cached), (Err("no cameras"), false));
+
+        // Within the window, but there is nothing to reuse, so it scans again
+        let (scan, cached) = gate
+            .run(false, DEFAULT_DETECT_FRESHNESS, || async {
+                Ok::<_, &str>(1)
+            })
+            .await;
+        assert_eq!((scan, cached), (Ok(1), false));
+        let (scan, cached) = gate
+            .run(false, DEFAULT_DETECT_FRESHNESS, || async {
+                Ok::<_, &str>(2)
+            })
+            .await;
+        assert_eq!((scan, cached), (Ok(1), true));
+        assert_eq!(
+            gate.stats(),
+            DetectionStats {
+                scans: 2,
+                cached: 1
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn test_background_scans_are_not_started_twice() {
+        let gate = DetectionGate::default();
+        let permit = gate
+            .try_start(false, DEFAULT_DETECT_FRESHNESS)
+            .expect("the first scan should start");
+        // Running, so even a forced request relies on it
+        assert!(gate.try_start(false, DEFAULT_DETECT_FRESHNESS).is_none());
+        assert!(gate.try_start(true, DEFAULT_DETECT_FRESHNESS).is_none());
+        permit.finish(());
+
+        assert!(gate.try_start(false, DEFAULT_DETECT_FRESHNESS).is_none());
+        let permit = gate
+            .try_start(true, DEFAULT_DETECT_FRESHNESS)
+            .expect("forcing should start a scan");
+        drop(permit);
+        assert_eq!(
+            gate.stats(),
+            DetectionStats {
+                scans: 2,
+                cached: 3
+            }
+        );
+
+        // A scan that never finished leaves the window closed
+        let unfinished = DetectionGate::<()>::default();
+        drop(unfinished.try_start(false, DEFAULT_DETECT_FRESHNESS));
+        assert!(
+            unfinished
+                .try_start(false, DEFAULT_DETECT_FRESHNESS)
+                .is_some()
+        );
+    }
+}
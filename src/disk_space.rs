@@ -0,0 +1,185 @@
+//! Free disk space checks.
+//!
+//! Captures and training batches write a lot of images. When the disk fills
+//! part way through they leave zero-byte JPEGs and truncated JSON behind, so
+//! they refuse to start when the image or data directory's filesystem is low on
+//! space.
+
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use crate::config::Settings;
+use crate::{OurError, OurResult};
+
+const BYTES_PER_MB: u64 = 1024 * 1024;
+
+/// Source of free space figures, replaceable in tests
+pub trait DiskUsage: Send + Sync {
+    /// Bytes available to unprivileged users on the filesystem containing `path`
+    fn free_bytes(&self, path: &Path) -> OurResult<u64>;
+}
+
+/// Reads free space from the operating system
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SystemDiskUsage;
+
+impl DiskUsage for SystemDiskUsage {
+    #[cfg(unix)]
+    fn free_bytes(&self, path: &Path) -> OurResult<u64> {
+        let stats = rustix::fs::statvfs(path).map_err(|e| {
+            OurError::App(format!(
+                "Failed to read free space for {}: {e}",
+                path.display()
+            ))
+        })?;
+        Ok(stats.f_bavail.saturating_mul(stats.f_frsize))
+    }
+
+    #[cfg(not(unix))]
+    fn free_bytes(&self, path: &Path) -> OurResult<u64> {
+        Err(OurError::App(format!(
+            "Free space checks are not supported on this platform ({})",
+            path.display()
+        )))
+    }
+}
+
+/// Free space on the emptiest watched filesystem
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub struct DiskSpaceReport {
+    pub path: PathBuf,
+    pub free_mb: u64,
+    pub min_free_mb: u64,
+    /// Free space is under twice the minimum
+    pub low: bool,
+}
+
+/// Checks the image and data directories have room before large writes
+#[derive(Clone)]
+pub struct DiskSpaceGuard {
+    provider: Arc<dyn DiskUsage>,
+    paths: Vec<PathBuf>,
+    min_free_mb: u64,
+}
+
+impl DiskSpaceGuard {
+    /// Watch the image and data directories with the configured minimum
+    pub fn new(settings: &Settings) -> Self {
+        Self::with_provider(
+            Arc::new(SystemDiskUsage),
+            vec![
+                settings.image_directory.clone(),
+                settings.data_directory.clone(),
+            ],
+            settings.min_free_disk_mb,
+        )
+    }
+
+    /// Watch `paths` using a specific free space source
+    pub fn with_provider(
+        provider: Arc<dyn DiskUsage>,
+        paths: Vec<PathBuf>,
+        min_free_mb: u64,
+    ) -> Self {
+        Self {
+            provider,
+            paths,
+            min_free_mb,
+        }
+    }
+
+    /// Report the watched path with the least free space
+    pub fn report(&self) -> OurResult<DiskSpaceReport> {
+        let mut emptiest: Option<(PathBuf, u64)> = None;
+        for path in &self.paths {
+            // Directories may not have been created yet; their filesystem is their nearest ancestor's
+            let existing = path
+                .ancestors()
+                .find(|ancestor| ancestor.exists())
+                .unwrap_or(path);
+            let free = self.provider.free_bytes(existing)?;
+            if emptiest.as_ref().is_none_or(|(_, least)| free < *least) {
+                emptiest = Some((path.clone(), free));
+            }
+        }
+
+        let (path, free_bytes) = emptiest
+            .ok_or_else(|| OurError::App("No directories to check for free space".to_string()))?;
+        let free_mb = free_bytes / BYTES_PER_MB;
+        Ok(DiskSpaceReport {
+            path,
+            free_mb,
+            min_free_mb: self.min_free_mb,
+            low: free_mb < self.min_free_mb.saturating_mul(2),
+        })
+    }
+
+    /// Fail if any watched filesystem has less than the minimum free
+    pub fn check(&self, operation: &str) -> OurResult<()> {
+        let report = self.report()?;
+        if report.free_mb < report.min_free_mb {
+            return Err(OurError::App(format!(
+                "Not enough disk space to start {operation}: {} MB free on {}, at least {} MB required",
+                report.free_mb,
+                report.path.display(),
+                report.min_free_mb
+            )));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    struct FakeDiskUsage(HashMap<PathBuf, u64>);
+
+    impl DiskUsage for FakeDiskUsage {
+        fn free_bytes(&self, path: &Path) -> OurResult<u64> {
+            self.0
+                .get(path)
+                .copied()
+                .ok_or_else(|| OurError::App(format!("unknown path {}", path.display())))
+        }
+    }
+
+    fn guard(root: &Path, images_mb: u64, data_mb: u64) -> DiskSpaceGuard {
+        let images = root.join("images");
+        std::fs::create_dir_all(&images).expect("image directory should be created");
+        // The data directory doesn't exist, so it's checked via the root it will be created in
+        let provider = FakeDiskUsage(HashMap::from([
+            (images.clone(), images_mb * BYTES_PER_MB),
+            (root.to_path_buf(), data_mb * BYTES_PER_MB),
+        ]));
+        DiskSpaceGuard::with_provider(Arc::new(provider), vec![images, root.join("data")], 500)
+    }
+
+    #[test]
+    fn test_disk_space_guard() {
+        let temp_dir = tempfile::TempDir::new().expect("temp dir should be created");
+        let root = temp_dir.path();
+
+        let plenty = guard(root, 5000, 9000);
+        plenty.check("capture").expect("check should pass");
+        let report = plenty.report().expect("report should succeed");
+        assert_eq!(report.free_mb, 5000);
+        assert!(!report.low);
+
+        // Between the minimum and twice the minimum only warns
+        let tight = guard(root, 800, 9000);
+        tight.check("capture").expect("check should pass");
+        assert!(tight.report().expect("report should succeed").low);
+
+        let full = guard(root, 9000, 100);
+        let error = full
+            .check("training")
+            .expect_err("check should fail")
+            .to_string();
+        assert!(error.contains("Not enough disk space to start training"));
+        assert!(error.contains("100 MB free"));
+    }
+}
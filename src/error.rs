@@ -41,6 +41,14 @@ pub enum OurError {
     #[error("Application error: {0}")]
     App(String),
 
+    /// Not enough training data to train a model on
+    #[error("Dataset too small: {0}")]
+    DatasetTooSmall(String),
+
+    /// A name that isn't safe to use as a path component
+    #[error("Invalid {field}: {reason}")]
+    InvalidName { field: String, reason: String },
+
     /// Parsing error
     #[error("Parsing error: {0}")]
     Parse(#[from] std::num::ParseIntError),
@@ -0,0 +1,133 @@
+//! Weak ETags for the JSON endpoints the dashboard polls.
+//!
+//! Data owned by an actor carries a [`DataVersion`] the actor bumps whenever it
+//! changes, so a poll with a matching `If-None-Match` can be answered without
+//! asking the actor for the data at all. Responses with no single owner are
+//! tagged with a hash of their serialised body instead.
+
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use axum::http::header::{CACHE_CONTROL, ETAG, IF_NONE_MATCH};
+use axum::http::{HeaderMap, HeaderValue, StatusCode};
+use axum::response::{IntoResponse, Response};
+use serde::Serialize;
+use tracing::error;
+
+use crate::OurResult;
+
+/// Change counter for a data source, shared by its owner and the web server
+#[derive(Debug, Clone)]
+pub struct DataVersion(Arc<AtomicU64>);
+
+impl Default for DataVersion {
+    /// Counters start from the current time, so a client holding an ETag from
+    /// before a restart doesn't match the restarted server's first versions
+    fn default() -> Self {
+        let now = chrono::Utc::now();
+        let start = u64::try_from(now.timestamp_millis()).unwrap_or_else(|_| {
+            error!("The system clock reads {now}, so ETags may repeat after a restart");
+            0
+        });
+        Self(Arc::new(AtomicU64::new(start)))
+    }
+}
+
+impl DataVersion {
+    /// Record that the data changed
+    pub fn bump(&self) {
+        self.0.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// The current version
+    pub fn get(&self) -> u64 {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// Weak ETag for a response built from data sources at the given versions
+pub fn version_etag(tag: &str, versions: &[u64]) -> String {
+    let versions = versions
+        .iter()
+        .map(u64::to_string)
+        .collect::<Vec<_>>()
+        .join("-");
+    format!("W/\"{tag}-{versions}\"")
+}
+
+/// A version number for data that has no [`DataVersion`], from a hash of the data
+pub fn hash_version<T: Hash>(value: &T) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    value.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Weak ETag from a hash of the serialised body
+pub fn body_etag<T: Serialize>(tag: &str, body: &T) -> OurResult<String> {
+    let mut hasher = DefaultHasher::new();
+    serde_json::to_vec(body)?.hash(&mut hasher);
+    Ok(format!("W/\"{tag}-{:016x}\"", hasher.finish()))
+}
+
+/// Whether the request's `If-None-Match` matches `etag`, using weak comparison
+pub fn if_none_match(headers: &HeaderMap, etag: &str) -> bool {
+    let opaque = |tag: &str| tag.trim().trim_start_matches("W/").to_string();
+    let etag = opaque(etag);
+    headers
+        .get_all(IF_NONE_MATCH)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .any(|candidate| candidate.trim() == "*" || opaque(candidate) == etag)
+}
+
+/// An empty 304 carrying the ETag
+pub fn not_modified(etag: &str) -> Response {
+    with_etag(etag, StatusCode::NOT_MODIFIED)
+}
+
+/// Attach the ETag header to a response, letting the browser keep it but revalidate every time
+pub fn with_etag(etag: &str, response: impl IntoResponse) -> Response {
+    let mut response = response.into_response();
+    response
+        .headers_mut()
+        .insert(CACHE_CONTROL, HeaderValue::from_static("no-cache"));
+    match HeaderValue::from_str(etag) {
+        Ok(value) => {
+            response.headers_mut().insert(ETAG, value);
+        }
+        Err(e) => error!("Invalid ETag {etag:?}: {e}"),
+    }
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_if_none_match() {
+        let version = DataVersion::default();
+        let etag = version_etag("shells", &[version.get(), 7]);
+        assert_eq!(etag, format!("W/\"shells-{}-7\"", version.get()));
+
+        let mut headers = HeaderMap::new();
+        assert!(!if_none_match(&headers, &etag));
+        let header = format!("\"cameras-0\", {}", etag.trim_start_matches("W/"));
+        headers.insert(
+            IF_NONE_MATCH,
+            HeaderValue::from_str(&header).expect("header should be valid"),
+        );
+        assert!(if_none_match(&headers, &etag));
+
+        version.bump();
+        assert!(!if_none_match(
+            &headers,
+            &version_etag("shells", &[version.get(), 7])
+        ));
+
+        headers.insert(IF_NONE_MATCH, HeaderValue::from_static("*"));
+        assert!(if_none_match(&headers, "W/\"anything\""));
+    }
+}
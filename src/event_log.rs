@@ -0,0 +1,175 @@
+//! In-memory event log of manager activity.
+//!
+//! Records the requests flowing into the camera, USB camera and controller
+//! managers so the diagnostics panel can show what actually happened when a
+//! button "did nothing". Recording never blocks the managers: when the buffer
+//! is contended the event is dropped and counted instead.
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+/// Default number of events kept in memory
+pub const DEFAULT_EVENT_CAPACITY: usize = 500;
+
+/// A single recorded event
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub struct RecordedEvent {
+    /// Monotonically increasing sequence number
+    pub sequence: u64,
+    /// When the event was recorded
+    pub timestamp: DateTime<Utc>,
+    /// Which manager received the message
+    pub source: &'static str,
+    /// Message kind, e.g. "SelectCameras"
+    pub kind: String,
+    /// Compact human-readable rendering of the message
+    pub summary: String,
+}
+
+struct EventBuffer {
+    events: VecDeque<RecordedEvent>,
+    next_sequence: u64,
+}
+
+/// Bounded recorder shared between the managers and the web server
+#[derive(Clone)]
+pub struct EventRecorder {
+    buffer: Arc<Mutex<EventBuffer>>,
+    dropped: Arc<AtomicU64>,
+    capacity: usize,
+}
+
+impl Default for EventRecorder {
+    fn default() -> Self {
+        Self::new(DEFAULT_EVENT_CAPACITY)
+    }
+}
+
+impl EventRecorder {
+    /// Create a recorder holding at most `capacity` events
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            buffer: Arc::new(Mutex::new(EventBuffer {
+                events: VecDeque::with_capacity(capacity),
+                next_sequence: 0,
+            })),
+            dropped: Arc::new(AtomicU64::new(0)),
+            capacity,
+        }
+    }
+
+    /// Record an event, dropping it if the buffer is currently contended
+    pub fn record(
+        &self,
+        source: &'static str,
+        kind: impl Into<String>,
+        summary: impl Into<String>,
+    ) {
+        let Ok(mut buffer) = self.buffer.try_lock() else {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+            return;
+        };
+
+        let sequence = buffer.next_sequence;
+        buffer.next_sequence += 1;
+        buffer.events.push_back(RecordedEvent {
+            sequence,
+            timestamp: Utc::now(),
+            source,
+            kind: kind.into(),
+            summary: summary.into(),
+        });
+        while buffer.events.len() > self.capacity {
+            buffer.events.pop_front();
+        }
+    }
+
+    /// Get up to `limit` of the most recent events, oldest first, optionally filtered by kind
+    pub fn recent(&self, limit: usize, kind: Option<&str>) -> Vec<RecordedEvent> {
+        let Ok(buffer) = self.buffer.lock() else {
+            return Vec::new();
+        };
+
+        let mut events: Vec<RecordedEvent> = buffer
+            .events
+            .iter()
+            .rev()
+            .filter(|event| kind.is_none_or(|kind| event.kind == kind))
+            .take(limit)
+            .cloned()
+            .collect();
+        events.reverse();
+        events
+    }
+
+    /// Number of events dropped because the buffer was contended
+    pub fn dropped_count(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    /// Maximum number of events held
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_event_recorder_bound_ordering_and_filtering() {
+        let recorder = EventRecorder::new(100);
+
+        for i in 0..300 {
+            let kind = if i % 3 == 0 {
+                "SelectCameras"
+            } else {
+                "GetStatus"
+            };
+            recorder.record("camera_manager", kind, format!("message {i}"));
+        }
+
+        // Only the most recent events are kept, oldest first
+        let all = recorder.recent(usize::MAX, None);
+        assert_eq!(all.len(), 100);
+        assert_eq!(all[0].summary, "message 200");
+        assert_eq!(all[99].summary, "message 299");
+        assert!(
+            all.windows(2)
+                .all(|pair| pair[0].sequence < pair[1].sequence)
+        );
+
+        // Limit picks the newest events
+        let latest = recorder.recent(5, None);
+        assert_eq!(latest.len(), 5);
+        assert_eq!(latest[4].summary, "message 299");
+        assert_eq!(latest[0].summary, "message 295");
+
+        // Filtering by kind
+        let selects = recorder.recent(usize::MAX, Some("SelectCameras"));
+        assert!(!selects.is_empty());
+        assert!(selects.iter().all(|event| event.kind == "SelectCameras"));
+        assert_eq!(
+            selects.last().map(|event| event.summary.as_str()),
+            Some("message 297")
+        );
+
+        assert_eq!(recorder.dropped_count(), 0);
+    }
+
+    #[test]
+    fn test_event_recorder_drops_when_contended() {
+        let recorder = EventRecorder::new(10);
+        let guard = recorder.buffer.lock().expect("lock should not be poisoned");
+        recorder.record("controller", "NextCase", "NextCase");
+        drop(guard);
+
+        assert_eq!(recorder.dropped_count(), 1);
+        assert!(recorder.recent(10, None).is_empty());
+    }
+}
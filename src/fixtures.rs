@@ -0,0 +1,423 @@
+//! Synthetic shells for tests and for trying the UI with a populated dataset.
+//!
+//! [`FixtureBuilder`] generates shells spread across case types, each with one
+//! image per view. The images are solid or gradient JPEGs. Each shell's record
+//! carries its regions and view types, and the shells are saved through
+//! [`ShellDataManager`] like captured ones. `case_types.json` can be written
+//! too. All of it comes from a seeded generator, so a seed always writes
+//! byte-identical JSON and images. Built for tests, and with the `fixtures`
+//! feature for the hidden `shell-sorter dev generate-fixtures` command.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Duration, Utc};
+use image::{Rgb, RgbImage};
+use uuid::Builder;
+
+use crate::config::ViewType;
+use crate::image_ingest::{DEFAULT_JPEG_QUALITY, encode_jpeg};
+use crate::ml_training::CaseType;
+use crate::shell_data::{CameraRegion, CapturedImage, Shell, ShellDataManager};
+use crate::{OurError, OurResult};
+
+/// Seed used when none is given
+pub const DEFAULT_FIXTURE_SEED: u64 = 42;
+/// Case types used when none are given, as (brand, designation)
+pub const DEFAULT_FIXTURE_CASE_TYPES: [(&str, &str); 3] = [
+    ("Winchester", "9mm"),
+    ("Federal", "308win"),
+    ("Remington", "45acp"),
+];
+/// The first shell's capture time, 2025-01-01T00:00:00Z
+const FIXTURE_EPOCH_SECONDS: i64 = 1_735_689_600;
+
+/// SplitMix64, used instead of `rand` because its output never changes between
+/// platforms or releases
+struct FixtureRng(u64);
+
+impl FixtureRng {
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// A number in `0..bound`, or 0 when `bound` is 0
+    fn below(&mut self, bound: u32) -> u32 {
+        if bound == 0 {
+            return 0;
+        }
+        (self.next_u64() % u64::from(bound)) as u32
+    }
+
+    fn colour(&mut self) -> Rgb<u8> {
+        let [r, g, b, ..] = self.next_u64().to_le_bytes();
+        Rgb([r, g, b])
+    }
+
+    fn bytes(&mut self) -> [u8; 16] {
+        let mut bytes = [0u8; 16];
+        bytes[..8].copy_from_slice(&self.next_u64().to_le_bytes());
+        bytes[8..].copy_from_slice(&self.next_u64().to_le_bytes());
+        bytes
+    }
+}
+
+/// How generated images are filled
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum FixtureFill {
+    Solid,
+    Gradient,
+    /// Solid or gradient, picked per image
+    #[default]
+    Mixed,
+}
+
+/// Builds a set of synthetic shells from a seed
+#[derive(Debug, Clone)]
+pub struct FixtureBuilder {
+    seed: u64,
+    shells: usize,
+    case_types: Vec<(String, String)>,
+    views: Vec<ViewType>,
+    width: u32,
+    height: u32,
+    fill: FixtureFill,
+    case_types_file: bool,
+}
+
+impl Default for FixtureBuilder {
+    fn default() -> Self {
+        Self::new(DEFAULT_FIXTURE_SEED)
+    }
+}
+
+impl FixtureBuilder {
+    /// Ten shells across the default case types, with a side and tail image each
+    pub fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            shells: 10,
+            case_types: DEFAULT_FIXTURE_CASE_TYPES
+                .iter()
+                .map(|(brand, designation)| (brand.to_string(), designation.to_string()))
+                .collect(),
+            views: vec![ViewType::Side, ViewType::Tail],
+            width: 64,
+            height: 48,
+            fill: FixtureFill::default(),
+            case_types_file: false,
+        }
+    }
+
+    pub fn shells(mut self, shells: usize) -> Self {
+        self.shells = shells;
+        self
+    }
+
+    /// Case types as (brand, designation), given to the shells in turn
+    pub fn case_types<'a>(
+        mut self,
+        case_types: impl IntoIterator<Item = (&'a str, &'a str)>,
+    ) -> Self {
+        self.case_types = case_types
+            .into_iter()
+            .map(|(brand, designation)| (brand.to_string(), designation.to_string()))
+            .collect();
+        self
+    }
+
+    /// The views each shell has an image of, one camera per view
+    pub fn views(mut self, views: &[ViewType]) -> Self {
+        self.views = views.to_vec();
+        self
+    }
+
+    pub fn image_size(mut self, width: u32, height: u32) -> Self {
+        self.width = width;
+        self.height = height;
+        self
+    }
+
+    pub fn fill(mut self, fill: FixtureFill) -> Self {
+        self.fill = fill;
+        self
+    }
+
+    /// Also write `case_types.json` with a record for each case type
+    pub fn with_case_types_file(mut self) -> Self {
+        self.case_types_file = true;
+        self
+    }
+
+    /// The shells, keyed by session ID, without writing anything
+    pub fn build(&self) -> OurResult<Vec<(String, Shell)>> {
+        if self.case_types.is_empty() {
+            return Err(OurError::App(
+                "Fixtures need at least one case type".to_string(),
+            ));
+        }
+        if self.width < 4 || self.height < 4 {
+            return Err(OurError::App(format!(
+                "Fixture images must be at least 4x4, not {}x{}",
+                self.width, self.height
+            )));
+        }
+
+        let mut rng = FixtureRng(self.seed);
+        let mut date_captured = fixture_epoch();
+        let mut shells = Vec::with_capacity(self.shells);
+        for index in 0..self.shells {
+            let (brand, designation) = &self.case_types[index % self.case_types.len()];
+            let session_id = Builder::from_random_bytes(rng.bytes())
+                .into_uuid()
+                .to_string();
+            date_captured += Duration::seconds(i64::from(60 + rng.below(540)));
+
+            let mut shell = Shell::new(brand.clone(), designation.clone());
+            shell.date_captured = date_captured;
+            for (camera_index, view_type) in self.views.iter().enumerate() {
+                let camera_index = camera_index as u32;
+                let filename = format!("{session_id}_camera_{camera_index}.jpg");
+                let mut image = CapturedImage::new(
+                    camera_index,
+                    filename.clone(),
+                    format!("Camera {camera_index}"),
+                    *view_type,
+                );
+                image.set_region(&self.region(&mut rng, *view_type));
+                shell.add_image(filename);
+                shell.add_captured_image(image);
+            }
+            shells.push((session_id, shell));
+        }
+        Ok(shells)
+    }
+
+    /// A region covering a quarter to three quarters of each side of the image
+    fn region(&self, rng: &mut FixtureRng, view_type: ViewType) -> CameraRegion {
+        let width = self.width / 4 + rng.below(self.width / 2);
+        let height = self.height / 4 + rng.below(self.height / 2);
+        let x = rng.below(self.width - width);
+        let y = rng.below(self.height - height);
+        CameraRegion::new(
+            view_type,
+            i32::try_from(x).ok(),
+            i32::try_from(y).ok(),
+            i32::try_from(width).ok(),
+            i32::try_from(height).ok(),
+        )
+    }
+
+    /// Write the shells to `data_directory` and their images to `image_directory`
+    pub fn write(&self, data_directory: &Path, image_directory: &Path) -> OurResult<Fixtures> {
+        let shells = self.build()?;
+        fs::create_dir_all(image_directory).map_err(|e| {
+            OurError::App(format!(
+                "Failed to create image directory {}: {e}",
+                image_directory.display()
+            ))
+        })?;
+
+        // Images get their own generator so the shells match `build` whatever the image settings
+        let mut rng = FixtureRng(self.seed.rotate_left(32) ^ 0x5EED);
+        let shell_data_manager = ShellDataManager::new(data_directory.to_path_buf());
+        let mut images = Vec::new();
+        for (session_id, shell) in &shells {
+            for filename in &shell.image_filenames {
+                let path = image_directory.join(filename);
+                let jpeg = encode_jpeg(&self.image(&mut rng), DEFAULT_JPEG_QUALITY)?;
+                fs::write(&path, jpeg).map_err(|e| {
+                    OurError::App(format!("Failed to write {}: {e}", path.display()))
+                })?;
+                images.push(path);
+            }
+            shell_data_manager.save_shell(session_id, shell)?;
+        }
+
+        let case_types = if self.case_types_file {
+            self.write_case_types_file(data_directory)?
+        } else {
+            Vec::new()
+        };
+        Ok(Fixtures {
+            shells,
+            images,
+            case_types,
+        })
+    }
+
+    fn image(&self, rng: &mut FixtureRng) -> RgbImage {
+        let from = rng.colour();
+        let gradient = match self.fill {
+            FixtureFill::Solid => false,
+            FixtureFill::Gradient => true,
+            FixtureFill::Mixed => rng.below(2) == 1,
+        };
+        if !gradient {
+            return RgbImage::from_pixel(self.width, self.height, from);
+        }
+        let to = rng.colour();
+        let span = self.width.saturating_sub(1).max(1);
+        RgbImage::from_fn(self.width, self.height, |x, _| {
+            let mix = |from: u8, to: u8| {
+                let (from, to) = (u32::from(from), u32::from(to));
+                ((from * (span - x) + to * x) / span) as u8
+            };
+            Rgb([
+                mix(from[0], to[0]),
+                mix(from[1], to[1]),
+                mix(from[2], to[2]),
+            ])
+        })
+    }
+
+    /// Write `case_types.json`, sorted by name so it is the same every time
+    fn write_case_types_file(&self, data_directory: &Path) -> OurResult<Vec<CaseType>> {
+        let case_types: BTreeMap<String, CaseType> = self
+            .case_types
+            .iter()
+            .map(|(brand, designation)| {
+                let name = format!("{brand}_{designation}");
+                let mut case_type =
+                    CaseType::new(name.clone(), designation.clone(), Some(brand.clone()));
+                case_type.created_at = fixture_epoch();
+                case_type.updated_at = fixture_epoch();
+                (name, case_type)
+            })
+            .collect();
+        let json = serde_json::to_string_pretty(&case_types)
+            .map_err(|e| OurError::App(format!("Failed to serialize case types: {e}")))?;
+        let path = data_directory.join("case_types.json");
+        fs::write(&path, json)
+            .map_err(|e| OurError::App(format!("Failed to write {}: {e}", path.display())))?;
+        Ok(case_types.into_values().collect())
+    }
+}
+
+/// What [`FixtureBuilder::write`] wrote
+#[derive(Debug, Clone)]
+pub struct Fixtures {
+    /// The shells by session ID, in capture order
+    pub shells: Vec<(String, Shell)>,
+    /// Every image written, in the shells' order
+    pub images: Vec<PathBuf>,
+    /// The case types in `case_types.json`, empty when it wasn't written
+    pub case_types: Vec<CaseType>,
+}
+
+impl Fixtures {
+    /// The session IDs, in capture order
+    pub fn session_ids(&self) -> Vec<&str> {
+        self.shells
+            .iter()
+            .map(|(session_id, _)| session_id.as_str())
+            .collect()
+    }
+}
+
+fn fixture_epoch() -> DateTime<Utc> {
+    DateTime::<Utc>::UNIX_EPOCH + Duration::seconds(FIXTURE_EPOCH_SECONDS)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Every file under `directory` with its contents, by path relative to it
+    fn contents(directory: &Path) -> BTreeMap<PathBuf, Vec<u8>> {
+        let mut files = BTreeMap::new();
+        for entry in fs::read_dir(directory).expect("directory should list") {
+            let path = entry.expect("entry should read").path();
+            if path.is_file() {
+                let relative = path
+                    .strip_prefix(directory)
+                    .expect("entry is in the directory")
+                    .to_path_buf();
+                files.insert(relative, fs::read(&path).expect("file should read"));
+            }
+        }
+        files
+    }
+
+    #[test]
+    fn test_the_same_seed_writes_identical_fixtures() {
+        let builder = FixtureBuilder::new(7)
+            .shells(12)
+            .views(&[ViewType::Side, ViewType::Tail, ViewType::Mouth])
+            .with_case_types_file();
+        let first = tempfile::TempDir::new().expect("temp dir should be created");
+        let second = tempfile::TempDir::new().expect("temp dir should be created");
+        let written = builder
+            .write(&first.path().join("data"), &first.path().join("images"))
+            .expect("fixtures should be written");
+        builder
+            .write(&second.path().join("data"), &second.path().join("images"))
+            .expect("fixtures should be written");
+
+        assert_eq!(written.shells.len(), 12);
+        assert_eq!(written.images.len(), 36);
+        assert_eq!(written.case_types.len(), 3);
+        for directory in ["data", "images"] {
+            let first = contents(&first.path().join(directory));
+            assert!(!first.is_empty());
+            assert_eq!(first, contents(&second.path().join(directory)));
+        }
+
+        let other = FixtureBuilder::new(8)
+            .shells(12)
+            .build()
+            .expect("fixtures should build");
+        assert_ne!(other[0].0, written.shells[0].0);
+    }
+
+    #[test]
+    fn test_fixtures_spread_across_case_types_with_regions() {
+        let temp_dir = tempfile::TempDir::new().expect("temp dir should be created");
+        let data = temp_dir.path().join("data");
+        let fixtures = FixtureBuilder::new(DEFAULT_FIXTURE_SEED)
+            .shells(7)
+            .case_types([("Winchester", "9mm"), ("Federal", "308win")])
+            .image_size(80, 60)
+            .fill(FixtureFill::Gradient)
+            .write(&data, &temp_dir.path().join("images"))
+            .expect("fixtures should be written");
+
+        let shells = ShellDataManager::new(data)
+            .list_shells()
+            .expect("shells should list");
+        assert_eq!(shells.len(), 7);
+        let winchester = shells
+            .iter()
+            .filter(|(_, shell)| shell.get_case_type_key() == "Winchester_9mm")
+            .count();
+        assert_eq!(winchester, 4);
+
+        for (_, shell) in &fixtures.shells {
+            let images = shell.captured_images.as_deref().unwrap_or_default();
+            assert_eq!(images.len(), 2);
+            for image in images {
+                let (x, y, width, height) = image
+                    .get_region()
+                    .as_rect()
+                    .expect("region should be complete");
+                assert!(x + width <= 80 && y + height <= 60);
+            }
+        }
+        let decoded = image::open(&fixtures.images[0])
+            .expect("image should decode")
+            .to_rgb8();
+        assert_eq!(decoded.dimensions(), (80, 60));
+        assert!(!temp_dir.path().join("data/case_types.json").exists());
+        assert!(
+            fixtures
+                .shells
+                .windows(2)
+                .all(|pair| { pair[0].1.date_captured < pair[1].1.date_captured })
+        );
+    }
+}
@@ -0,0 +1,255 @@
+//! Turning frames grabbed from USB cameras into JPEGs.
+//!
+//! Decoding a frame, adjusting its brightness, turning it upright and encoding
+//! the JPEG take far longer than grabbing it. The USB camera manager only grabs
+//! frames itself and hands each one to [`process_frame`] on tokio's blocking
+//! pool, so frames from several cameras are processed in parallel while the
+//! manager carries on answering requests.
+//!
+//! Most cameras deliver MJPEG, and when a camera's frames need no brightness
+//! adjustment or turning they are passed through as the camera compressed
+//! them. Decoding and encoding them again would only cost CPU and add a second
+//! round of compression artefacts. The JPEG's start and end markers are
+//! checked first, and a frame missing either is decoded as before.
+
+use std::time::{Duration, Instant};
+
+use image::{DynamicImage, RgbImage};
+use nokhwa::Buffer;
+use nokhwa::pixel_format::RgbFormat;
+use nokhwa::utils::FrameFormat;
+use serde::{Deserialize, Serialize};
+use tracing::debug;
+
+use crate::orientation::Orientation;
+use crate::{OurError, OurResult};
+
+/// A frame as the camera produced it, not yet decoded
+pub trait RawFrame: Send {
+    fn decode(self: Box<Self>) -> OurResult<RgbImage>;
+
+    /// The frame as JPEG, when the camera delivered it compressed
+    fn jpeg(&self) -> Option<&[u8]> {
+        None
+    }
+}
+
+impl RawFrame for Buffer {
+    fn decode(self: Box<Self>) -> OurResult<RgbImage> {
+        self.decode_image::<RgbFormat>()
+            .map_err(|e| OurError::App(format!("Failed to decode frame: {e}")))
+    }
+
+    fn jpeg(&self) -> Option<&[u8]> {
+        (self.source_frame_format() == FrameFormat::MJPEG).then(|| self.buffer())
+    }
+}
+
+/// How a camera's frames are turned into JPEGs
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct FrameProcessing {
+    /// Software brightness offset, from -100 to +100
+    pub brightness_offset: f32,
+    pub orientation: Orientation,
+}
+
+impl FrameProcessing {
+    /// Whether frames come out as they went in
+    pub fn is_noop(&self) -> bool {
+        self.brightness_offset == 0.0 && self.orientation.is_identity()
+    }
+}
+
+/// How a frame became a JPEG
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FramePath {
+    /// The camera's own MJPEG frame, untouched
+    Passthrough,
+    /// Decoded, adjusted and encoded again
+    Processed,
+}
+
+/// A frame ready to save or stream
+#[derive(Debug)]
+pub struct ProcessedFrame {
+    pub jpeg: Vec<u8>,
+    /// Time spent decoding, adjusting and encoding it
+    pub duration: Duration,
+    pub path: FramePath,
+}
+
+/// Whether `data` starts with a JPEG's SOI marker and ends with its EOI marker
+///
+/// Some cameras pad their MJPEG frames with zeros, so trailing zeros are ignored.
+pub fn is_complete_jpeg(data: &[u8]) -> bool {
+    let end = data
+        .iter()
+        .rposition(|&byte| byte != 0)
+        .map_or(0, |last| last + 1);
+    let data = &data[..end];
+    data.len() >= 4 && data.starts_with(&[0xFF, 0xD8]) && data.ends_with(&[0xFF, 0xD9])
+}
+
+/// Decode, brighten, orient and encode a frame, or pass an MJPEG frame that
+/// needs none of that straight through. This blocks, so run it off async tasks
+pub fn process_frame(
+    frame: Box<dyn RawFrame>,
+    processing: FrameProcessing,
+) -> OurResult<ProcessedFrame> {
+    let started = Instant::now();
+    match frame.jpeg() {
+        Some(jpeg) if processing.is_noop() && is_complete_jpeg(jpeg) => {
+            debug!("Passing an MJPEG frame through, it needs no processing");
+            return Ok(ProcessedFrame {
+                jpeg: jpeg.to_vec(),
+                duration: started.elapsed(),
+                path: FramePath::Passthrough,
+            });
+        }
+        Some(_) if processing.is_noop() => {
+            debug!("Re-encoding an MJPEG frame without its start and end markers");
+        }
+        Some(_) => debug!("Re-encoding an MJPEG frame for {processing:?}"),
+        None => {}
+    }
+    let mut image = frame.decode()?;
+    adjust_brightness(&mut image, processing.brightness_offset);
+    let image = processing.orientation.apply(image);
+
+    let mut jpeg = Vec::new();
+    DynamicImage::ImageRgb8(image)
+        .write_to(
+            &mut std::io::Cursor::new(&mut jpeg),
+            image::ImageFormat::Jpeg,
+        )
+        .map_err(|e| OurError::App(format!("Failed to encode JPEG: {e}")))?;
+    Ok(ProcessedFrame {
+        jpeg,
+        duration: started.elapsed(),
+        path: FramePath::Processed,
+    })
+}
+
+/// Scale every pixel by a software brightness offset
+///
+/// -100 is black, 0 leaves the image alone and +100 quadruples it, which gives
+/// much brighter images for dark cameras like FaceTime.
+pub fn adjust_brightness(image: &mut RgbImage, brightness_offset: f32) {
+    if brightness_offset == 0.0 {
+        return;
+    }
+    let multiplier = if brightness_offset >= 0.0 {
+        // 0 to +100 maps to 1.0 to 4.0
+        1.0 + (brightness_offset / 100.0) * 3.0
+    } else {
+        // -100 to 0 maps to 0.0 to 1.0
+        (brightness_offset + 100.0) / 100.0
+    };
+    for pixel in image.pixels_mut() {
+        for channel in pixel.0.iter_mut() {
+            *channel = (f32::from(*channel) * multiplier).clamp(0.0, 255.0) as u8;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::orientation::Rotation;
+
+    impl RawFrame for RgbImage {
+        fn decode(self: Box<Self>) -> OurResult<RgbImage> {
+            Ok(*self)
+        }
+    }
+
+    /// A frame delivered as MJPEG, like most USB cameras send
+    struct MjpegFrame(Vec<u8>);
+
+    impl RawFrame for MjpegFrame {
+        fn decode(self: Box<Self>) -> OurResult<RgbImage> {
+            Ok(image::load_from_memory(&self.0)?.to_rgb8())
+        }
+
+        fn jpeg(&self) -> Option<&[u8]> {
+            Some(&self.0)
+        }
+    }
+
+    #[test]
+    fn test_frames_are_brightened_oriented_and_encoded() {
+        let frame = RgbImage::from_pixel(8, 4, image::Rgb([40, 80, 100]));
+        let processed = process_frame(
+            Box::new(frame),
+            FrameProcessing {
+                brightness_offset: 100.0,
+                orientation: Orientation {
+                    rotation: Rotation::Clockwise90,
+                    mirror: false,
+                },
+            },
+        )
+        .expect("frame should be processed");
+
+        let jpeg = image::load_from_memory(&processed.jpeg)
+            .expect("JPEG should decode")
+            .to_rgb8();
+        assert_eq!(jpeg.dimensions(), (4, 8));
+        // Quadrupled and clamped, give or take JPEG's rounding
+        let [r, g, b] = jpeg.get_pixel(2, 4).0;
+        assert!(r.abs_diff(160) <= 4 && g.abs_diff(255) <= 4 && b.abs_diff(255) <= 4);
+    }
+
+    #[test]
+    fn test_mjpeg_frames_needing_no_processing_pass_through() {
+        let mut jpeg = Vec::new();
+        DynamicImage::ImageRgb8(RgbImage::from_pixel(16, 8, image::Rgb([40, 80, 100])))
+            .write_to(
+                &mut std::io::Cursor::new(&mut jpeg),
+                image::ImageFormat::Jpeg,
+            )
+            .expect("JPEG should encode");
+
+        let passed = process_frame(
+            Box::new(MjpegFrame(jpeg.clone())),
+            FrameProcessing::default(),
+        )
+        .expect("frame should pass through");
+        assert_eq!(passed.path, FramePath::Passthrough);
+        assert_eq!(passed.jpeg, jpeg);
+
+        let brightened = process_frame(
+            Box::new(MjpegFrame(jpeg.clone())),
+            FrameProcessing {
+                brightness_offset: 20.0,
+                ..FrameProcessing::default()
+            },
+        )
+        .expect("frame should be processed");
+        assert_eq!(brightened.path, FramePath::Processed);
+        assert_ne!(brightened.jpeg, jpeg);
+
+        // Padding is allowed, but a frame without its end marker is decoded rather than passed on
+        let mut padded = jpeg.clone();
+        padded.extend([0, 0, 0]);
+        assert!(is_complete_jpeg(&padded));
+        let truncated = jpeg[..jpeg.len() - 2].to_vec();
+        assert!(!is_complete_jpeg(&truncated));
+        let reencoded = process_frame(Box::new(MjpegFrame(truncated)), FrameProcessing::default())
+            .expect("the decoder tolerates a missing end marker");
+        assert_eq!(reencoded.path, FramePath::Processed);
+        assert!(!is_complete_jpeg(&[0xFF, 0xD8]));
+    }
+
+    #[test]
+    fn test_brightness_offsets() {
+        let mut image = RgbImage::from_pixel(1, 1, image::Rgb([100, 200, 0]));
+        adjust_brightness(&mut image, 0.0);
+        assert_eq!(image.get_pixel(0, 0).0, [100, 200, 0]);
+        adjust_brightness(&mut image, -50.0);
+        assert_eq!(image.get_pixel(0, 0).0, [50, 100, 0]);
+        adjust_brightness(&mut image, -100.0);
+        assert_eq!(image.get_pixel(0, 0).0, [0, 0, 0]);
+    }
+}
@@ -0,0 +1,216 @@
+//! Camera regions of interest checked against the frame they are drawn on.
+//!
+//! The dashboard draws a camera's region on its snapshot, in the oriented frame
+//! (see [`crate::orientation`]). Before a region is stored it is checked
+//! against that frame's real size: it needs a size, has to fit inside the
+//! frame, and has to cover at least `min_region_area` pixels. The frame size is
+//! stored alongside the region, so a camera whose resolution changes later can
+//! be flagged as having a region drawn for a different frame.
+
+use std::collections::BTreeMap;
+use std::io::Cursor;
+
+use image::ImageReader;
+use serde::{Deserialize, Serialize};
+
+use crate::{OurError, OurResult};
+
+/// Width and height of a camera's oriented frame, in pixels
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct FrameSize {
+    pub width: u32,
+    pub height: u32,
+}
+
+impl FrameSize {
+    /// The size of an encoded image, read from its header without decoding it
+    pub fn of_image(bytes: &[u8]) -> OurResult<Self> {
+        let (width, height) = ImageReader::new(Cursor::new(bytes))
+            .with_guessed_format()?
+            .into_dimensions()?;
+        if width == 0 || height == 0 {
+            return Err(OurError::App(format!(
+                "Frame has no pixels ({width}x{height})"
+            )));
+        }
+        Ok(Self { width, height })
+    }
+}
+
+/// A region of interest that fits inside its frame
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct FrameRegion {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl FrameRegion {
+    /// Check a region drawn on `frame`, naming each field that is wrong
+    pub fn validate(
+        x: i32,
+        y: i32,
+        width: i32,
+        height: i32,
+        frame: FrameSize,
+        min_area: u64,
+    ) -> Result<Self, BTreeMap<String, String>> {
+        let mut errors = BTreeMap::new();
+        // One axis at a time, in i64 so an origin plus a huge size can't overflow
+        let mut check_axis = |origin_name: &str,
+                              origin: i32,
+                              size_name: &str,
+                              size: i32,
+                              frame_size: u32,
+                              edge: &str| {
+            let (origin, size, frame_size) =
+                (i64::from(origin), i64::from(size), i64::from(frame_size));
+            if origin < 0 {
+                errors.insert(origin_name.to_string(), "must not be negative".to_string());
+            } else if origin >= frame_size {
+                errors.insert(
+                    origin_name.to_string(),
+                    format!("must be less than the frame {size_name} of {frame_size}"),
+                );
+            }
+            if size <= 0 {
+                errors.insert(size_name.to_string(), "must be positive".to_string());
+            } else if origin >= 0 && origin + size > frame_size {
+                errors.insert(
+                    size_name.to_string(),
+                    format!(
+                        "runs past the {edge} edge: {origin_name} + {size_name} is {}, the frame {size_name} is {frame_size}",
+                        origin + size
+                    ),
+                );
+            }
+        };
+        check_axis("x", x, "width", width, frame.width, "right");
+        check_axis("y", y, "height", height, frame.height, "bottom");
+        if !errors.is_empty() {
+            return Err(errors);
+        }
+
+        // Every value was checked above to be non-negative and inside the frame
+        let region = Self {
+            x: x.unsigned_abs(),
+            y: y.unsigned_abs(),
+            width: width.unsigned_abs(),
+            height: height.unsigned_abs(),
+        };
+        if region.area() < min_area {
+            errors.insert(
+                "region".to_string(),
+                format!(
+                    "covers {} pixels, less than the minimum of {min_area}",
+                    region.area()
+                ),
+            );
+            return Err(errors);
+        }
+        Ok(region)
+    }
+
+    pub fn area(&self) -> u64 {
+        u64::from(self.width) * u64::from(self.height)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FRAME: FrameSize = FrameSize {
+        width: 640,
+        height: 480,
+    };
+
+    fn fields(errors: BTreeMap<String, String>) -> Vec<String> {
+        errors.into_keys().collect()
+    }
+
+    #[test]
+    fn test_region_may_touch_the_frame_edges() {
+        let whole = FrameRegion::validate(0, 0, 640, 480, FRAME, 0).expect("whole frame fits");
+        assert_eq!(whole.area(), 640 * 480);
+        let corner =
+            FrameRegion::validate(639, 479, 1, 1, FRAME, 1).expect("last pixel fits exactly");
+        assert_eq!((corner.x, corner.y), (639, 479));
+        FrameRegion::validate(600, 400, 40, 80, FRAME, 0).expect("region ends on the border");
+    }
+
+    #[test]
+    fn test_region_one_pixel_past_the_edge_is_refused() {
+        let errors = FrameRegion::validate(600, 0, 41, 10, FRAME, 0)
+            .expect_err("one column too many should be refused");
+        assert_eq!(
+            errors["width"],
+            "runs past the right edge: x + width is 641, the frame width is 640"
+        );
+        assert_eq!(
+            fields(
+                FrameRegion::validate(0, 400, 10, 81, FRAME, 0)
+                    .expect_err("one row too many should be refused")
+            ),
+            vec!["height"]
+        );
+        assert_eq!(
+            fields(
+                FrameRegion::validate(640, 480, 1, 1, FRAME, 0)
+                    .expect_err("origin on the border is outside the frame")
+            ),
+            vec!["height", "width", "x", "y"]
+        );
+        // Sizes near i32::MAX don't overflow the check
+        assert_eq!(
+            fields(
+                FrameRegion::validate(1, 1, i32::MAX, i32::MAX, FRAME, 0)
+                    .expect_err("huge sizes should be refused")
+            ),
+            vec!["height", "width"]
+        );
+    }
+
+    #[test]
+    fn test_region_needs_a_size_and_the_minimum_area() {
+        assert_eq!(
+            fields(
+                FrameRegion::validate(10, 10, 0, 20, FRAME, 0)
+                    .expect_err("zero width should be refused")
+            ),
+            vec!["width"]
+        );
+        assert_eq!(
+            fields(
+                FrameRegion::validate(-1, 10, 20, -5, FRAME, 0)
+                    .expect_err("negative values should be refused")
+            ),
+            vec!["height", "x"]
+        );
+
+        let errors = FrameRegion::validate(10, 10, 31, 32, FRAME, 1024)
+            .expect_err("too small a region should be refused");
+        assert_eq!(
+            errors["region"],
+            "covers 992 pixels, less than the minimum of 1024"
+        );
+        FrameRegion::validate(10, 10, 32, 32, FRAME, 1024).expect("the minimum area is allowed");
+    }
+
+    #[test]
+    fn test_frame_size_is_read_from_the_image() {
+        let jpeg = crate::image_ingest::encode_jpeg(&image::RgbImage::new(12, 7), 80)
+            .expect("JPEG should encode");
+        assert_eq!(
+            FrameSize::of_image(&jpeg).expect("size should be read"),
+            FrameSize {
+                width: 12,
+                height: 7
+            }
+        );
+        assert!(FrameSize::of_image(b"not an image").is_err());
+    }
+}
@@ -0,0 +1,516 @@
+//! A record of the controller's health checks, for the status page's uptime sparkline.
+//!
+//! Every health check adds a [`HealthSample`]. Samples are kept in memory for
+//! the retention period, and appended to [`HEALTH_HISTORY_FILENAME`] in the data
+//! directory [`FLUSH_EVERY`] at a time, so the health check isn't writing to
+//! the SD card every 30 seconds; whatever is still buffered is written when the
+//! history is dropped. The file is a ring of two segments: once the current
+//! segment holds a retention period's worth of checks it replaces the previous
+//! one, so the file never grows past twice the retention and always covers it.
+//!
+//! [`HealthHistory::report`] buckets the samples of a window into at most
+//! [`MAX_HISTORY_POINTS`] points for charting, with the availability of each
+//! point and of the whole window. Samples also carry the hottest temperature
+//! sensor reading taken with the check, if any sensors are configured.
+
+use std::collections::VecDeque;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+
+use crate::{OurError, OurResult};
+
+/// File in the data directory the history is kept in
+pub const HEALTH_HISTORY_FILENAME: &str = "controller_health.jsonl";
+/// How long health checks are kept unless configured otherwise
+pub const DEFAULT_HEALTH_RETENTION: Duration = Duration::from_secs(24 * 60 * 60);
+/// How often the controller monitor checks the controller
+pub const HEALTH_CHECK_INTERVAL: Duration = Duration::from_secs(30);
+/// Samples buffered in memory before they are appended to the file
+pub const FLUSH_EVERY: usize = 10;
+/// Most points a history report holds
+pub const MAX_HISTORY_POINTS: usize = 500;
+
+/// The outcome of one health check
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct HealthSample {
+    pub timestamp: DateTime<Utc>,
+    pub online: bool,
+    /// How long the controller took to answer, if it did
+    pub response_ms: Option<u64>,
+    /// Hottest temperature sensor reading in °C, if any sensor answered
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_temperature_c: Option<f64>,
+}
+
+/// The health checks in one bucket of a report
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub struct HealthPoint {
+    /// Start of the bucket
+    pub timestamp: DateTime<Utc>,
+    pub checks: usize,
+    pub online_checks: usize,
+    /// Share of the bucket's checks the controller answered
+    pub availability: f64,
+    /// Mean response time of the checks that got one
+    pub response_ms: Option<u64>,
+    /// Hottest temperature sensor reading in the bucket
+    pub max_temperature_c: Option<f64>,
+}
+
+/// The health checks of a window, bucketed for charting
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub struct HealthHistoryReport {
+    pub hours: u64,
+    pub from: DateTime<Utc>,
+    pub to: DateTime<Utc>,
+    /// Width of each point's bucket
+    pub bucket_seconds: u64,
+    pub checks: usize,
+    /// Share of the window's checks the controller answered; `None` without checks
+    pub availability: Option<f64>,
+    /// Buckets that had checks, oldest first
+    pub points: Vec<HealthPoint>,
+}
+
+/// Bucket the samples between `from` and `to` into at most `max_points` points
+///
+/// Buckets are at least a second wide and all the same width, starting at
+/// `from`; buckets without samples are left out, so gaps in the checks show
+/// as gaps in the chart. Returns the bucket width in seconds and the points.
+pub fn decimate(
+    samples: &[HealthSample],
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+    max_points: usize,
+) -> (u64, Vec<HealthPoint>) {
+    let window = u64::try_from((to - from).num_seconds()).unwrap_or(0);
+    let bucket_seconds = window.div_ceil(max_points.max(1) as u64).max(1);
+
+    let mut points: Vec<HealthPoint> = Vec::new();
+    let mut response_totals: Vec<(u64, u64)> = Vec::new();
+    for sample in samples
+        .iter()
+        .filter(|sample| sample.timestamp >= from && sample.timestamp < to)
+    {
+        let offset = u64::try_from((sample.timestamp - from).num_seconds()).unwrap_or(0);
+        let bucket = offset / bucket_seconds;
+        let start = from + chrono::Duration::seconds((bucket * bucket_seconds) as i64);
+        if points.last().is_none_or(|point| point.timestamp != start) {
+            points.push(HealthPoint {
+                timestamp: start,
+                checks: 0,
+                online_checks: 0,
+                availability: 0.0,
+                response_ms: None,
+                max_temperature_c: None,
+            });
+            response_totals.push((0, 0));
+        }
+        let (Some(point), Some((total_ms, answered))) =
+            (points.last_mut(), response_totals.last_mut())
+        else {
+            continue;
+        };
+        point.checks += 1;
+        if sample.online {
+            point.online_checks += 1;
+        }
+        if let Some(response_ms) = sample.response_ms {
+            *total_ms += response_ms;
+            *answered += 1;
+        }
+        if let Some(celsius) = sample.max_temperature_c {
+            point.max_temperature_c = Some(
+                point
+                    .max_temperature_c
+                    .map_or(celsius, |max| max.max(celsius)),
+            );
+        }
+    }
+
+    for (point, (total_ms, answered)) in points.iter_mut().zip(response_totals) {
+        point.availability = point.online_checks as f64 / point.checks as f64;
+        point.response_ms = (answered > 0).then(|| total_ms / answered);
+    }
+    (bucket_seconds, points)
+}
+
+/// The on-disk ring, current segment and the one before it
+struct HistoryFile {
+    path: PathBuf,
+    previous_path: PathBuf,
+    /// Samples a segment holds before it is rotated
+    segment_capacity: usize,
+    /// Samples in the current segment
+    current_count: usize,
+}
+
+impl HistoryFile {
+    fn new(path: PathBuf, retention: Duration) -> Self {
+        let previous_path = path.with_extension("previous.jsonl");
+        let segment_capacity =
+            (retention.as_secs() / HEALTH_CHECK_INTERVAL.as_secs()).max(1) as usize;
+        Self {
+            path,
+            previous_path,
+            segment_capacity,
+            current_count: 0,
+        }
+    }
+
+    /// Append `samples`, first rotating the current segment if they wouldn't fit in it
+    fn append(&mut self, samples: &[HealthSample]) -> std::io::Result<()> {
+        if self.current_count > 0 && self.current_count + samples.len() > self.segment_capacity {
+            fs::rename(&self.path, &self.previous_path)?;
+            self.current_count = 0;
+        }
+        if let Some(directory) = self.path.parent() {
+            fs::create_dir_all(directory)?;
+        }
+        let mut lines = String::new();
+        for sample in samples {
+            lines.push_str(&serde_json::to_string(sample).map_err(std::io::Error::other)?);
+            lines.push('\n');
+        }
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        file.write_all(lines.as_bytes())?;
+        self.current_count += samples.len();
+        Ok(())
+    }
+}
+
+/// Samples read from a segment, skipping lines that don't parse; a missing segment has none
+fn read_segment(path: &Path) -> OurResult<Vec<HealthSample>> {
+    let contents = match fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => {
+            return Err(OurError::App(format!(
+                "Failed to read {}: {e}",
+                path.display()
+            )));
+        }
+    };
+    let mut skipped = 0;
+    let samples = contents
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .filter_map(|line| match serde_json::from_str(line) {
+            Ok(sample) => Some(sample),
+            Err(_) => {
+                skipped += 1;
+                None
+            }
+        })
+        .collect();
+    if skipped > 0 {
+        warn!("Skipped {skipped} unreadable lines in {}", path.display());
+    }
+    Ok(samples)
+}
+
+struct Inner {
+    retention: Duration,
+    /// Every sample within the retention, oldest first
+    samples: VecDeque<HealthSample>,
+    /// Samples not yet written to the file
+    pending: Vec<HealthSample>,
+    file: Option<HistoryFile>,
+}
+
+impl Inner {
+    fn flush(&mut self) {
+        let Some(file) = self.file.as_mut() else {
+            self.pending.clear();
+            return;
+        };
+        if self.pending.is_empty() {
+            return;
+        }
+        if let Err(e) = file.append(&self.pending) {
+            // They're still in memory; dropping them keeps a failing disk from growing the buffer
+            warn!(
+                "Failed to write {} controller health checks to {}: {e}",
+                self.pending.len(),
+                file.path.display()
+            );
+        }
+        self.pending.clear();
+    }
+
+    fn forget_expired(&mut self, now: DateTime<Utc>) {
+        let Ok(retention) = chrono::Duration::from_std(self.retention) else {
+            return;
+        };
+        while self
+            .samples
+            .front()
+            .is_some_and(|sample| now - sample.timestamp > retention)
+        {
+            self.samples.pop_front();
+        }
+    }
+}
+
+impl Drop for Inner {
+    fn drop(&mut self) {
+        self.flush();
+    }
+}
+
+/// Recent health checks of the controller, shared by the monitor and its handles
+#[derive(Clone)]
+pub struct HealthHistory {
+    inner: Arc<Mutex<Inner>>,
+}
+
+impl Default for HealthHistory {
+    fn default() -> Self {
+        Self::new(DEFAULT_HEALTH_RETENTION)
+    }
+}
+
+impl HealthHistory {
+    /// A history kept in memory until [`HealthHistory::persist_to`] gives it a file
+    pub fn new(retention: Duration) -> Self {
+        Self {
+            inner: Arc::new(Mutex::new(Inner {
+                retention: retention.max(Duration::from_secs(1)),
+                samples: VecDeque::new(),
+                pending: Vec::new(),
+                file: None,
+            })),
+        }
+    }
+
+    // A poisoned lock only means a recording panicked; the samples are still usable
+    fn lock(&self) -> MutexGuard<'_, Inner> {
+        self.inner
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Load the checks kept in `path` and keep writing new ones there
+    pub fn persist_to(&self, path: PathBuf) -> OurResult<()> {
+        let mut inner = self.lock();
+        let mut file = HistoryFile::new(path, inner.retention);
+        let previous = read_segment(&file.previous_path)?;
+        let current = read_segment(&file.path)?;
+        file.current_count = current.len();
+
+        let mut loaded: Vec<HealthSample> = previous.into_iter().chain(current).collect();
+        loaded.extend(inner.samples.drain(..));
+        loaded.sort_by_key(|sample| sample.timestamp);
+        info!(
+            "Loaded {} controller health checks from {}",
+            loaded.len(),
+            file.path.display()
+        );
+        inner.samples = loaded.into();
+        inner.forget_expired(Utc::now());
+        inner.file = Some(file);
+        Ok(())
+    }
+
+    /// Add a health check, writing the buffered checks out once there are enough
+    pub fn record(&self, sample: HealthSample) {
+        let mut inner = self.lock();
+        inner.samples.push_back(sample);
+        inner.forget_expired(sample.timestamp);
+        inner.pending.push(sample);
+        if inner.pending.len() >= FLUSH_EVERY {
+            inner.flush();
+        }
+    }
+
+    /// Write out the buffered checks now
+    pub fn flush(&self) {
+        self.lock().flush();
+    }
+
+    /// How many hours of checks are kept
+    pub fn retention_hours(&self) -> u64 {
+        self.lock().retention.as_secs().div_ceil(3600)
+    }
+
+    /// The checks of the last `hours` before `now`, limited to the retention, bucketed for charting
+    pub fn report(&self, hours: u64, now: DateTime<Utc>) -> HealthHistoryReport {
+        let hours = hours.clamp(1, self.retention_hours().max(1));
+        let from = now - chrono::Duration::hours(hours as i64);
+        let inner = self.lock();
+        let samples: Vec<HealthSample> = inner
+            .samples
+            .iter()
+            .filter(|sample| sample.timestamp >= from)
+            .copied()
+            .collect();
+        drop(inner);
+
+        // A check taken right at `now` still counts
+        let to = now + chrono::Duration::seconds(1);
+        let (bucket_seconds, points) = decimate(&samples, from, to, MAX_HISTORY_POINTS);
+        let checks: usize = points.iter().map(|point| point.checks).sum();
+        let online: usize = points.iter().map(|point| point.online_checks).sum();
+        HealthHistoryReport {
+            hours,
+            from,
+            to: now,
+            bucket_seconds,
+            checks,
+            availability: (checks > 0).then(|| online as f64 / checks as f64),
+            points,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn sample(start: DateTime<Utc>, seconds: i64, online: bool) -> HealthSample {
+        HealthSample {
+            timestamp: start + chrono::Duration::seconds(seconds),
+            online,
+            response_ms: online.then_some(40 + seconds as u64 % 20),
+            max_temperature_c: None,
+        }
+    }
+
+    #[test]
+    fn test_decimation_buckets_and_availability() {
+        let from = Utc::now();
+        let to = from + chrono::Duration::hours(24);
+        // A check every 30 seconds for a day, offline for the first hour
+        let samples: Vec<HealthSample> = (0..2880)
+            .map(|index| sample(from, index * 30, index >= 120))
+            .collect();
+
+        let (bucket_seconds, points) = decimate(&samples, from, to, MAX_HISTORY_POINTS);
+        // 86400 seconds in 500 buckets rounds up to 173 seconds each
+        assert_eq!(bucket_seconds, 173);
+        assert_eq!(points.len(), 500);
+        assert!(points.len() <= MAX_HISTORY_POINTS);
+        assert_eq!(points.iter().map(|point| point.checks).sum::<usize>(), 2880);
+        assert_eq!(points[0].timestamp, from);
+        assert_eq!(points[0].availability, 0.0);
+        assert_eq!(points[0].response_ms, None);
+        assert_eq!(points[499].availability, 1.0);
+        assert!(points[499].response_ms.is_some());
+
+        assert_eq!(points[0].max_temperature_c, None);
+
+        // Fewer samples than points get a bucket each, gaps stay gaps
+        let sparse = [
+            sample(from, 0, true),
+            HealthSample {
+                max_temperature_c: Some(71.5),
+                ..sample(from, 3600, false)
+            },
+            HealthSample {
+                max_temperature_c: Some(64.0),
+                ..sample(from, 3601, false)
+            },
+        ];
+        let (bucket_seconds, points) = decimate(
+            &sparse,
+            from,
+            from + chrono::Duration::hours(1) + chrono::Duration::seconds(2),
+            500,
+        );
+        assert_eq!(bucket_seconds, 8);
+        assert_eq!(
+            points
+                .iter()
+                .map(|point| (point.checks, point.online_checks))
+                .collect::<Vec<_>>(),
+            vec![(1, 1), (2, 0)]
+        );
+        assert_eq!(points[1].timestamp, from + chrono::Duration::seconds(3600));
+        assert_eq!(points[1].max_temperature_c, Some(71.5));
+        assert!(decimate(&[], from, from, 500).1.is_empty());
+    }
+
+    #[test]
+    fn test_report_covers_the_window_with_its_availability() {
+        let history = HealthHistory::new(Duration::from_secs(2 * 60 * 60));
+        let now = Utc::now();
+        let start = now - chrono::Duration::minutes(90);
+        for index in 0..=180 {
+            history.record(sample(start, index * 30, index % 4 != 0));
+        }
+        let report = history.report(1, now);
+        assert_eq!(report.hours, 1);
+        assert_eq!(report.checks, 121);
+        let availability = report.availability.expect("window has checks");
+        assert!((availability - 90.0 / 121.0).abs() < 1e-9, "{availability}");
+        // Asking past the retention gets the retention
+        assert_eq!(history.report(48, now).hours, 2);
+        assert_eq!(history.report(2, now).checks, 181);
+        assert_eq!(HealthHistory::default().report(24, now).availability, None);
+    }
+
+    #[test]
+    fn test_file_is_written_in_batches_and_rotated() {
+        let temp_dir = TempDir::new().expect("temp dir should be created");
+        let path = temp_dir.path().join(HEALTH_HISTORY_FILENAME);
+        let previous = path.with_extension("previous.jsonl");
+        let lines = |path: &Path| {
+            fs::read_to_string(path)
+                .map(|contents| contents.lines().count())
+                .unwrap_or(0)
+        };
+        // Ten minutes of retention is twenty checks a segment
+        let retention = Duration::from_secs(600);
+        // The last check is 15 seconds ago, so the ones within ten minutes are 31 onwards
+        let start = Utc::now() - chrono::Duration::seconds(50 * 30 + 15);
+
+        let history = HealthHistory::new(retention);
+        history
+            .persist_to(path.clone())
+            .expect("history should open");
+        for index in 0..FLUSH_EVERY as i64 - 1 {
+            history.record(sample(start, index * 30, true));
+        }
+        assert_eq!(lines(&path), 0, "nothing is written until a batch is full");
+        history.record(sample(start, 270, true));
+        assert_eq!(lines(&path), FLUSH_EVERY);
+
+        for index in FLUSH_EVERY as i64..50 {
+            history.record(sample(start, index * 30, index % 2 == 0));
+        }
+        // 50 checks: two full segments rotated through, the last ten in the new one
+        assert_eq!(lines(&previous), 20);
+        assert_eq!(lines(&path), 10);
+
+        history.record(sample(start, 50 * 30, true));
+        drop(history);
+        assert_eq!(
+            lines(&path),
+            11,
+            "dropping the history writes what's buffered"
+        );
+
+        // Reopening loads what's within the retention from both segments
+        let reopened = HealthHistory::new(retention);
+        reopened
+            .persist_to(path.clone())
+            .expect("history should open");
+        let report = reopened.report(1, Utc::now());
+        // 31 to 39 from the previous segment, 40 to 50 from the current one
+        assert_eq!(report.checks, 20);
+    }
+}
@@ -0,0 +1,210 @@
+//! A shared limit on the expensive jobs, so they don't all run at once.
+//!
+//! Composite generation, model training, disk usage scans and reference sheets
+//! each take a [`HeavyPermit`] before doing their real work. On a Raspberry Pi
+//! two of them together are enough to starve the web UI, so there is one
+//! permit by default (`heavy_work_permits`). Requests that can't get a permit
+//! straight away are refused with [`HeavyWorkBusy`] so the client can retry;
+//! background jobs wait in [`HeavyWork::acquire`] instead. The permit is
+//! returned when it is dropped.
+
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+use crate::{OurError, OurResult};
+
+/// Heavy jobs allowed to run at once unless configured otherwise
+pub const DEFAULT_HEAVY_WORK_PERMITS: usize = 1;
+/// How long a refused request is told to wait before retrying
+pub const HEAVY_WORK_RETRY_AFTER: Duration = Duration::from_secs(10);
+
+/// A heavy job holding a permit
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub struct HeavyTask {
+    pub name: String,
+    pub started_at: DateTime<Utc>,
+}
+
+/// How the permits are being used, for `/api/health`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub struct HeavyWorkUsage {
+    pub permits: usize,
+    pub in_use: usize,
+    /// Background jobs waiting for a permit
+    pub waiting: usize,
+    /// The jobs holding permits, oldest first
+    pub running: Vec<HeavyTask>,
+}
+
+/// Why a request for a permit was refused
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HeavyWorkBusy {
+    /// The jobs holding the permits
+    pub running: Vec<String>,
+    pub retry_after: Duration,
+}
+
+impl HeavyWorkBusy {
+    pub fn message(&self, wanted: &str) -> String {
+        format!(
+            "Can't start {wanted} while {} is running; retry in {}s",
+            self.running.join(", "),
+            self.retry_after.as_secs()
+        )
+    }
+}
+
+/// The permits for heavy jobs, shared by every handler and background job
+#[derive(Debug, Clone)]
+pub struct HeavyWork {
+    permits: usize,
+    semaphore: Arc<Semaphore>,
+    running: Arc<Mutex<BTreeMap<u64, HeavyTask>>>,
+    next_id: Arc<AtomicU64>,
+    waiting: Arc<AtomicUsize>,
+}
+
+impl Default for HeavyWork {
+    fn default() -> Self {
+        Self::new(DEFAULT_HEAVY_WORK_PERMITS)
+    }
+}
+
+impl HeavyWork {
+    /// Allow `permits` heavy jobs at once, at least one
+    pub fn new(permits: usize) -> Self {
+        let permits = permits.max(1);
+        Self {
+            permits,
+            semaphore: Arc::new(Semaphore::new(permits)),
+            running: Arc::new(Mutex::new(BTreeMap::new())),
+            next_id: Arc::new(AtomicU64::new(0)),
+            waiting: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+
+    // A poisoned lock only means a job panicked while starting or finishing; the list is still usable
+    fn lock_running(&self) -> MutexGuard<'_, BTreeMap<u64, HeavyTask>> {
+        self.running
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn permit(&self, permit: OwnedSemaphorePermit, name: &str) -> HeavyPermit {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        self.lock_running().insert(
+            id,
+            HeavyTask {
+                name: name.to_string(),
+                started_at: Utc::now(),
+            },
+        );
+        HeavyPermit {
+            work: self.clone(),
+            id,
+            _permit: permit,
+        }
+    }
+
+    /// A permit for `name` if one is free now
+    pub fn try_acquire(&self, name: &str) -> Result<HeavyPermit, HeavyWorkBusy> {
+        match self.semaphore.clone().try_acquire_owned() {
+            Ok(permit) => Ok(self.permit(permit, name)),
+            Err(_) => Err(HeavyWorkBusy {
+                running: self
+                    .lock_running()
+                    .values()
+                    .map(|task| task.name.clone())
+                    .collect(),
+                retry_after: HEAVY_WORK_RETRY_AFTER,
+            }),
+        }
+    }
+
+    /// Wait for a permit for `name`
+    pub async fn acquire(&self, name: &str) -> OurResult<HeavyPermit> {
+        self.waiting.fetch_add(1, Ordering::Relaxed);
+        let permit = self.semaphore.clone().acquire_owned().await;
+        self.waiting.fetch_sub(1, Ordering::Relaxed);
+        let permit =
+            permit.map_err(|e| OurError::App(format!("Heavy work permits are gone: {e}")))?;
+        Ok(self.permit(permit, name))
+    }
+
+    pub fn usage(&self) -> HeavyWorkUsage {
+        let mut running: Vec<HeavyTask> = self.lock_running().values().cloned().collect();
+        running.sort_by_key(|task| task.started_at);
+        HeavyWorkUsage {
+            permits: self.permits,
+            in_use: self.permits - self.semaphore.available_permits(),
+            waiting: self.waiting.load(Ordering::Relaxed),
+            running,
+        }
+    }
+}
+
+/// A heavy job's permit, returned when dropped
+#[derive(Debug)]
+pub struct HeavyPermit {
+    work: HeavyWork,
+    id: u64,
+    _permit: OwnedSemaphorePermit,
+}
+
+impl Drop for HeavyPermit {
+    fn drop(&mut self) {
+        self.work.lock_running().remove(&self.id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_jobs_wait_or_are_refused_while_permits_are_held() {
+        let work = HeavyWork::new(1);
+        let training = work
+            .try_acquire("model training")
+            .expect("the permit should be free");
+        let busy = work
+            .try_acquire("composite generation")
+            .expect_err("the permit is held");
+        assert_eq!(busy.running, vec!["model training".to_string()]);
+        assert_eq!(
+            busy.message("composite generation"),
+            "Can't start composite generation while model training is running; retry in 10s"
+        );
+
+        let queued = tokio::spawn({
+            let work = work.clone();
+            async move { work.acquire("disk usage scan").await }
+        });
+        while work.usage().waiting == 0 {
+            tokio::task::yield_now().await;
+        }
+        let usage = work.usage();
+        assert_eq!((usage.permits, usage.in_use), (1, 1));
+        assert_eq!(usage.running[0].name, "model training");
+
+        drop(training);
+        let scan = queued
+            .await
+            .expect("task should finish")
+            .expect("the permit should be handed over");
+        let usage = work.usage();
+        assert_eq!((usage.in_use, usage.waiting), (1, 0));
+        assert_eq!(usage.running[0].name, "disk usage scan");
+        drop(scan);
+        assert_eq!(work.usage().in_use, 0);
+        assert!(work.usage().running.is_empty());
+    }
+}
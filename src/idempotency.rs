@@ -0,0 +1,328 @@
+//! Replaying retried requests instead of running them twice.
+//!
+//! The tablet's WiFi sometimes loses a response, and a client that retries the
+//! POST would otherwise capture a second session or advance the feeder again.
+//! Capture and next-case requests may carry an `Idempotency-Key` header, or an
+//! `idempotency_key` field in their JSON body. The first request with a key
+//! runs and its response is kept for [`IDEMPOTENCY_TTL`]; a request repeating
+//! the key in that time gets the kept response, marked with
+//! `Idempotent-Replayed: true`, without running again. A retry that arrives
+//! while the original is still running waits for it, then gets its response.
+//!
+//! Keys are kept per endpoint, at most [`MAX_KEYS_PER_ENDPOINT`] of them, the
+//! oldest going first when there are too many. Requests without a key run as
+//! they always have.
+
+use std::collections::{BTreeMap, HashMap};
+use std::future::Future;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+use axum::body::{Body, Bytes, to_bytes};
+use axum::http::{HeaderMap, HeaderValue, StatusCode};
+use axum::response::{IntoResponse, Response};
+use serde::Serialize;
+use tracing::{debug, error};
+
+/// How long a key's response is replayed for
+pub const IDEMPOTENCY_TTL: Duration = Duration::from_secs(10 * 60);
+/// Keys remembered for each endpoint before the oldest are forgotten
+pub const MAX_KEYS_PER_ENDPOINT: usize = 256;
+/// Longest key accepted
+pub const MAX_KEY_LENGTH: usize = 255;
+/// Header a client sends its key in
+pub const IDEMPOTENCY_KEY_HEADER: &str = "idempotency-key";
+/// Header marking a replayed response
+pub const REPLAYED_HEADER: &str = "idempotent-replayed";
+
+/// How often an endpoint's keys were replayed
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub struct IdempotencyStats {
+    /// Requests answered with a kept response
+    pub hits: u64,
+    /// Requests with a key that hadn't been seen, which ran
+    pub misses: u64,
+    /// Keys currently remembered
+    pub keys: usize,
+}
+
+struct KeptResponse {
+    status: StatusCode,
+    headers: HeaderMap,
+    body: Bytes,
+    kept_at: Instant,
+}
+
+impl KeptResponse {
+    fn replay(&self) -> Response {
+        let mut response = (self.status, self.headers.clone(), self.body.clone()).into_response();
+        response
+            .headers_mut()
+            .insert(REPLAYED_HEADER, HeaderValue::from_static("true"));
+        response
+    }
+}
+
+#[derive(Default)]
+struct EndpointKeys {
+    responses: HashMap<String, KeptResponse>,
+    hits: u64,
+    misses: u64,
+    /// Held while a request with a key runs, so a retry waits for it
+    running: Arc<tokio::sync::Mutex<()>>,
+}
+
+impl EndpointKeys {
+    fn forget_expired(&mut self, ttl: Duration, now: Instant) {
+        self.responses
+            .retain(|_, kept| now.saturating_duration_since(kept.kept_at) < ttl);
+    }
+}
+
+/// Responses to recent requests with an idempotency key, by endpoint
+#[derive(Clone)]
+pub struct IdempotencyCache {
+    ttl: Duration,
+    endpoints: Arc<Mutex<BTreeMap<&'static str, EndpointKeys>>>,
+}
+
+impl Default for IdempotencyCache {
+    fn default() -> Self {
+        Self::new(IDEMPOTENCY_TTL)
+    }
+}
+
+impl IdempotencyCache {
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            endpoints: Arc::new(Mutex::new(BTreeMap::new())),
+        }
+    }
+
+    // A poisoned lock only means a request panicked mid-update; the keys are still usable
+    fn lock_endpoints(&self) -> MutexGuard<'_, BTreeMap<&'static str, EndpointKeys>> {
+        self.endpoints
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Run `action` unless `key` was already used on `endpoint`, in which case its response is replayed
+    ///
+    /// Requests with a key on the same endpoint run one at a time.
+    pub async fn run<F, Fut>(
+        &self,
+        endpoint: &'static str,
+        key: Option<String>,
+        action: F,
+    ) -> Response
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Response>,
+    {
+        let Some(key) = key else {
+            return action().await;
+        };
+        let running = self
+            .lock_endpoints()
+            .entry(endpoint)
+            .or_default()
+            .running
+            .clone();
+        let _running = running.lock().await;
+
+        if let Some(replayed) = self.replay(endpoint, &key, Instant::now()) {
+            debug!("Replaying the response to {endpoint} request with key {key}");
+            return replayed;
+        }
+        let response = action().await;
+        self.keep(endpoint, key, response, Instant::now()).await
+    }
+
+    /// The kept response to `key`, counting a hit or a miss
+    fn replay(&self, endpoint: &'static str, key: &str, now: Instant) -> Option<Response> {
+        let mut endpoints = self.lock_endpoints();
+        let keys = endpoints.entry(endpoint).or_default();
+        keys.forget_expired(self.ttl, now);
+        match keys.responses.get(key) {
+            Some(kept) => {
+                keys.hits += 1;
+                Some(kept.replay())
+            }
+            None => {
+                keys.misses += 1;
+                None
+            }
+        }
+    }
+
+    /// Keep `response` for replaying to `key`, and return it
+    async fn keep(
+        &self,
+        endpoint: &'static str,
+        key: String,
+        response: Response,
+        now: Instant,
+    ) -> Response {
+        let (parts, body) = response.into_parts();
+        let body = match to_bytes(body, usize::MAX).await {
+            Ok(body) => body,
+            Err(e) => {
+                error!("Failed to keep the response to {endpoint} request with key {key}: {e}");
+                return (parts.status, parts.headers, Body::empty()).into_response();
+            }
+        };
+
+        let mut endpoints = self.lock_endpoints();
+        let keys = endpoints.entry(endpoint).or_default();
+        keys.forget_expired(self.ttl, now);
+        while keys.responses.len() >= MAX_KEYS_PER_ENDPOINT {
+            let Some(oldest) = keys
+                .responses
+                .iter()
+                .min_by_key(|(_, kept)| kept.kept_at)
+                .map(|(key, _)| key.clone())
+            else {
+                break;
+            };
+            keys.responses.remove(&oldest);
+        }
+        keys.responses.insert(
+            key,
+            KeptResponse {
+                status: parts.status,
+                headers: parts.headers.clone(),
+                body: body.clone(),
+                kept_at: now,
+            },
+        );
+        Response::from_parts(parts, Body::from(body))
+    }
+
+    /// Hits, misses and remembered keys by endpoint
+    pub fn snapshot(&self) -> BTreeMap<String, IdempotencyStats> {
+        let now = Instant::now();
+        self.lock_endpoints()
+            .iter()
+            .map(|(endpoint, keys)| {
+                let live = keys
+                    .responses
+                    .values()
+                    .filter(|kept| now.saturating_duration_since(kept.kept_at) < self.ttl)
+                    .count();
+                let stats = IdempotencyStats {
+                    hits: keys.hits,
+                    misses: keys.misses,
+                    keys: live,
+                };
+                (endpoint.to_string(), stats)
+            })
+            .collect()
+    }
+}
+
+/// The request's idempotency key: the header if sent, otherwise the body's `idempotency_key`
+pub fn request_key(headers: &HeaderMap, field: Option<String>) -> Result<Option<String>, String> {
+    let key = match headers.get(IDEMPOTENCY_KEY_HEADER) {
+        Some(value) => value
+            .to_str()
+            .map_err(|_| "Idempotency-Key must be printable ASCII".to_string())?
+            .to_string(),
+        None => match field {
+            Some(key) => key,
+            None => return Ok(None),
+        },
+    };
+    if key.is_empty() || key.len() > MAX_KEY_LENGTH {
+        return Err(format!(
+            "Idempotency-Key must be 1 to {MAX_KEY_LENGTH} characters"
+        ));
+    }
+    if !key.bytes().all(|byte| byte.is_ascii_graphic()) {
+        return Err("Idempotency-Key must be printable ASCII without spaces".to_string());
+    }
+    Ok(Some(key))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    async fn counted(cache: &IdempotencyCache, key: Option<&str>, runs: &AtomicUsize) -> Bytes {
+        let response = cache
+            .run("next_case", key.map(str::to_string), || async {
+                let run = runs.fetch_add(1, Ordering::SeqCst);
+                format!("run {run}").into_response()
+            })
+            .await;
+        to_bytes(response.into_body(), usize::MAX)
+            .await
+            .expect("body should read")
+    }
+
+    #[tokio::test]
+    async fn test_keys_replay_until_they_expire() {
+        let ttl = Duration::from_millis(200);
+        let cache = IdempotencyCache::new(ttl);
+        let runs = AtomicUsize::new(0);
+
+        assert_eq!(counted(&cache, Some("a"), &runs).await, "run 0");
+        assert_eq!(counted(&cache, Some("a"), &runs).await, "run 0");
+        assert_eq!(counted(&cache, Some("b"), &runs).await, "run 1");
+        assert_eq!(counted(&cache, None, &runs).await, "run 2");
+        assert_eq!(counted(&cache, None, &runs).await, "run 3");
+        assert_eq!(
+            cache.snapshot()["next_case"],
+            IdempotencyStats {
+                hits: 1,
+                misses: 2,
+                keys: 2
+            }
+        );
+
+        tokio::time::sleep(ttl).await;
+        assert_eq!(counted(&cache, Some("a"), &runs).await, "run 4");
+        assert_eq!(cache.snapshot()["next_case"].keys, 1);
+    }
+
+    #[tokio::test]
+    async fn test_oldest_keys_go_first_when_full() {
+        let cache = IdempotencyCache::default();
+        let runs = AtomicUsize::new(0);
+        for index in 0..=MAX_KEYS_PER_ENDPOINT {
+            counted(&cache, Some(&format!("key-{index}")), &runs).await;
+        }
+        assert_eq!(cache.snapshot()["next_case"].keys, MAX_KEYS_PER_ENDPOINT);
+        // The newest is still replayed, the first was forgotten and runs again
+        let newest = format!("key-{MAX_KEYS_PER_ENDPOINT}");
+        assert_eq!(
+            counted(&cache, Some(&newest), &runs).await,
+            format!("run {MAX_KEYS_PER_ENDPOINT}")
+        );
+        assert_eq!(
+            counted(&cache, Some("key-0"), &runs).await,
+            format!("run {}", MAX_KEYS_PER_ENDPOINT + 1)
+        );
+    }
+
+    #[test]
+    fn test_request_key_prefers_the_header_and_checks_it() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(request_key(&headers, None), Ok(None));
+        assert_eq!(
+            request_key(&headers, Some("body".to_string())),
+            Ok(Some("body".to_string()))
+        );
+        headers.insert(IDEMPOTENCY_KEY_HEADER, HeaderValue::from_static("header"));
+        assert_eq!(
+            request_key(&headers, Some("body".to_string())),
+            Ok(Some("header".to_string()))
+        );
+
+        assert!(request_key(&HeaderMap::new(), Some(String::new())).is_err());
+        assert!(request_key(&HeaderMap::new(), Some("has space".to_string())).is_err());
+        assert!(request_key(&HeaderMap::new(), Some("k".repeat(MAX_KEY_LENGTH + 1))).is_err());
+    }
+}
@@ -0,0 +1,267 @@
+//! Image ingestion and normalisation
+//!
+//! Reference and training photos arrive in whatever format the source device
+//! produced (phones commonly emit HEIC or PNG). The ML pipeline and composite
+//! generation assume JPEG throughout, so everything is normalised to JPEG here
+//! before it is stored.
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::path::Path;
+
+use image::ImageFormat;
+use image::codecs::jpeg::JpegEncoder;
+use tracing::{debug, warn};
+
+use crate::{OurError, OurResult};
+
+/// Default JPEG quality used when converting non-JPEG images
+pub const DEFAULT_JPEG_QUALITY: u8 = 90;
+
+/// Image container formats accepted for ingestion
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImageKind {
+    Jpeg,
+    Png,
+    Heic,
+}
+
+impl ImageKind {
+    /// The conventional file extension for this image kind
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ImageKind::Jpeg => "jpg",
+            ImageKind::Png => "png",
+            ImageKind::Heic => "heic",
+        }
+    }
+}
+
+impl std::fmt::Display for ImageKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.extension())
+    }
+}
+
+/// An image that has been normalised to JPEG
+#[derive(Debug, Clone)]
+pub struct IngestedImage {
+    /// JPEG-encoded image data
+    pub data: Vec<u8>,
+    /// The format the image was supplied in
+    pub original_kind: ImageKind,
+}
+
+impl IngestedImage {
+    /// Whether the image was re-encoded during ingestion
+    pub fn was_converted(&self) -> bool {
+        self.original_kind != ImageKind::Jpeg
+    }
+}
+
+/// Detect the image kind from the leading magic bytes, ignoring any file extension
+pub fn detect_image_kind(bytes: &[u8]) -> OurResult<ImageKind> {
+    if bytes.starts_with(&[0xFF, 0xD8, 0xFF]) {
+        return Ok(ImageKind::Jpeg);
+    }
+    if bytes.starts_with(b"\x89PNG\r\n\x1a\n") {
+        return Ok(ImageKind::Png);
+    }
+    // ISO-BMFF: 4 byte box size, then "ftyp" and the major brand
+    if bytes.len() >= 12 && &bytes[4..8] == b"ftyp" {
+        let brand = &bytes[8..12];
+        if [
+            b"heic", b"heix", b"hevc", b"hevx", b"heim", b"heis", b"mif1", b"msf1",
+        ]
+        .iter()
+        .any(|known| brand == *known)
+        {
+            return Ok(ImageKind::Heic);
+        }
+    }
+
+    Err(OurError::App(
+        "Unsupported image data: expected a JPEG, PNG or HEIC file".to_string(),
+    ))
+}
+
+/// Normalise an image to JPEG, passing existing JPEGs through untouched
+pub fn normalise_to_jpeg(bytes: &[u8], quality: u8) -> OurResult<IngestedImage> {
+    let kind = detect_image_kind(bytes)?;
+    debug!("Ingesting {} image ({} bytes)", kind, bytes.len());
+
+    let data = match kind {
+        ImageKind::Jpeg => bytes.to_vec(),
+        ImageKind::Png => {
+            let image = image::load_from_memory_with_format(bytes, ImageFormat::Png)?;
+            encode_jpeg(&image.to_rgb8(), quality)?
+        }
+        ImageKind::Heic => encode_jpeg(&decode_heic(bytes)?, quality)?,
+    };
+
+    Ok(IngestedImage {
+        data,
+        original_kind: kind,
+    })
+}
+
+/// Encode an RGB image as JPEG at the given quality
+pub(crate) fn encode_jpeg(image: &image::RgbImage, quality: u8) -> OurResult<Vec<u8>> {
+    let mut jpeg_data = Vec::new();
+    JpegEncoder::new_with_quality(&mut jpeg_data, quality.clamp(1, 100)).encode_image(image)?;
+    Ok(jpeg_data)
+}
+
+/// Bytes read from an upload to tell its kind
+const UPLOAD_HEADER_BYTES: u64 = 12;
+
+/// The kind of an uploaded file, from its first bytes
+pub fn detect_upload_kind(upload: &Path) -> OurResult<ImageKind> {
+    let mut header = Vec::new();
+    File::open(upload)
+        .and_then(|file| file.take(UPLOAD_HEADER_BYTES).read_to_end(&mut header))
+        .map_err(|e| OurError::App(format!("Failed to read upload: {e}")))?;
+    detect_image_kind(&header)
+}
+
+/// Store an uploaded file in `directory` as `{stem}_{n}.jpg`, converting it to JPEG as needed
+///
+/// `n` counts up from 1 past any file already there, so nothing is
+/// overwritten. Returns the stored file name.
+pub fn store_upload_as_jpeg(
+    upload: &Path,
+    directory: &Path,
+    stem: &str,
+    quality: u8,
+) -> OurResult<String> {
+    let read_error = |e: io::Error| OurError::App(format!("Failed to read upload: {e}"));
+    let converted = match detect_upload_kind(upload)? {
+        ImageKind::Jpeg => None,
+        _ => Some(normalise_to_jpeg(&fs::read(upload).map_err(read_error)?, quality)?.data),
+    };
+
+    for n in 1..=u32::MAX {
+        let name = format!("{stem}_{n}.jpg");
+        let path = directory.join(&name);
+        let mut file = match OpenOptions::new().write(true).create_new(true).open(&path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(OurError::App(format!("Failed to write image: {e}"))),
+        };
+        let written = match &converted {
+            Some(data) => file.write_all(data),
+            None => File::open(upload)
+                .and_then(|mut source| io::copy(&mut source, &mut file).map(|_| ())),
+        };
+        if let Err(e) = written {
+            drop(file);
+            if let Err(e) = fs::remove_file(&path) {
+                warn!("Failed to remove partial image {}: {e}", path.display());
+            }
+            return Err(OurError::App(format!("Failed to write image: {e}")));
+        }
+        return Ok(name);
+    }
+    Err(OurError::App(format!("No free file name for {stem}")))
+}
+
+#[cfg(feature = "heic")]
+fn decode_heic(bytes: &[u8]) -> OurResult<image::RgbImage> {
+    use libheif_rs::{ColorSpace, HeifContext, LibHeif, RgbChroma};
+
+    let heif_error =
+        |e: libheif_rs::HeifError| OurError::App(format!("Failed to decode HEIC: {e}"));
+
+    let lib_heif = LibHeif::new();
+    let context = HeifContext::read_from_bytes(bytes).map_err(heif_error)?;
+    let handle = context.primary_image_handle().map_err(heif_error)?;
+    let decoded = lib_heif
+        .decode(&handle, ColorSpace::Rgb(RgbChroma::Rgb), None)
+        .map_err(heif_error)?;
+
+    let planes = decoded.planes();
+    let plane = planes
+        .interleaved
+        .ok_or_else(|| OurError::App("HEIC image has no interleaved RGB plane".to_string()))?;
+
+    // Rows may be padded, so copy them out using the stride
+    let row_len = plane.width as usize * 3;
+    let mut pixels = Vec::with_capacity(row_len * plane.height as usize);
+    for row in plane.data.chunks(plane.stride).take(plane.height as usize) {
+        pixels.extend_from_slice(
+            row.get(..row_len).ok_or_else(|| {
+                OurError::App("HEIC image row shorter than its width".to_string())
+            })?,
+        );
+    }
+
+    image::RgbImage::from_raw(plane.width, plane.height, pixels)
+        .ok_or_else(|| OurError::App("HEIC image buffer has unexpected size".to_string()))
+}
+
+#[cfg(not(feature = "heic"))]
+fn decode_heic(_bytes: &[u8]) -> OurResult<image::RgbImage> {
+    Err(OurError::App(
+        "HEIC images are not supported by this build (enable the `heic` feature)".to_string(),
+    ))
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// Build a small PNG fixture in memory
+    pub(crate) fn png_fixture() -> Vec<u8> {
+        let image =
+            image::RgbImage::from_fn(8, 6, |x, y| image::Rgb([x as u8 * 30, y as u8 * 40, 128]));
+        let mut data = Vec::new();
+        image::DynamicImage::ImageRgb8(image)
+            .write_to(&mut std::io::Cursor::new(&mut data), ImageFormat::Png)
+            .expect("PNG fixture should encode");
+        data
+    }
+
+    /// Build a small JPEG fixture in memory
+    pub(crate) fn jpeg_fixture() -> Vec<u8> {
+        let image = image::RgbImage::from_pixel(8, 6, image::Rgb([200, 100, 50]));
+        encode_jpeg(&image, DEFAULT_JPEG_QUALITY).expect("JPEG fixture should encode")
+    }
+
+    #[test]
+    fn test_detect_image_kind() {
+        assert_eq!(
+            detect_image_kind(&png_fixture()).expect("PNG should be detected"),
+            ImageKind::Png
+        );
+        assert_eq!(
+            detect_image_kind(&jpeg_fixture()).expect("JPEG should be detected"),
+            ImageKind::Jpeg
+        );
+        let heic_header = b"\x00\x00\x00\x18ftypheic\x00\x00\x00\x00";
+        assert_eq!(
+            detect_image_kind(heic_header).expect("HEIC should be detected"),
+            ImageKind::Heic
+        );
+        assert!(detect_image_kind(b"not an image at all").is_err());
+        assert!(detect_image_kind(&[]).is_err());
+    }
+
+    #[test]
+    fn test_png_is_converted_to_decodable_jpeg() {
+        let ingested = normalise_to_jpeg(&png_fixture(), 80).expect("PNG should convert");
+        assert!(ingested.was_converted());
+        assert_eq!(ingested.original_kind, ImageKind::Png);
+
+        let decoded = image::load_from_memory_with_format(&ingested.data, ImageFormat::Jpeg)
+            .expect("converted data should decode as JPEG");
+        assert_eq!((decoded.width(), decoded.height()), (8, 6));
+    }
+
+    #[test]
+    fn test_jpeg_passes_through_untouched() {
+        let jpeg = jpeg_fixture();
+        let ingested = normalise_to_jpeg(&jpeg, 10).expect("JPEG should pass through");
+        assert!(!ingested.was_converted());
+        assert_eq!(ingested.data, jpeg);
+    }
+}
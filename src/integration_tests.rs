@@ -2,6 +2,7 @@
 
 use crate::constants::USB_DEVICE_PREFIX;
 use serde_json::Value;
+use shell_sorter::camera::{CameraId, CameraKind};
 use shell_sorter::camera_manager::CameraManager;
 use shell_sorter::config::Settings;
 use shell_sorter::controller_monitor::ControllerMonitor;
@@ -190,7 +191,7 @@ async fn test_camera_detection_endpoint() {
     for camera in cameras {
         if let (Some(name), Some(camera_type), Some(id)) = (
             camera.get("name").and_then(|v| v.as_str()),
-            camera.get("camera_type").and_then(|v| v.as_str()),
+            camera.get("kind").and_then(|v| v.as_str()),
             camera.get("id").and_then(|v| v.as_str()),
         ) {
             println!("  • {name} ({camera_type}) - {id}");
@@ -207,22 +208,22 @@ async fn test_camera_detection_endpoint() {
             match camera_type {
                 "usb" => {
                     assert!(
-                        camera.get("index").is_some(),
-                        "USB camera missing 'index' field"
+                        camera.pointer("/usb/index").is_some(),
+                        "USB camera missing 'usb.index' field"
                     );
                     assert!(
-                        camera.get("hostname").is_none() || camera["hostname"].is_null(),
-                        "USB camera should not have hostname"
+                        camera["esp"].is_null(),
+                        "USB camera should not have an ESPHome section"
                     );
                 }
                 "esphome" => {
                     assert!(
-                        camera.get("hostname").is_some(),
-                        "ESPHome camera missing 'hostname' field"
+                        camera.pointer("/esp/hostname").is_some(),
+                        "ESPHome camera missing 'esp.hostname' field"
                     );
                     assert!(
-                        camera.get("index").is_none() || camera["index"].is_null(),
-                        "ESPHome camera should not have index"
+                        camera["usb"].is_null(),
+                        "ESPHome camera should not have a USB section"
                     );
                 }
                 _ => unreachable!(),
@@ -295,7 +296,7 @@ async fn test_camera_list_endpoint() {
     for camera in cameras {
         if let (Some(name), Some(camera_type)) = (
             camera.get("name").and_then(|v| v.as_str()),
-            camera.get("camera_type").and_then(|v| v.as_str()),
+            camera.get("kind").and_then(|v| v.as_str()),
         ) {
             println!("  • {name} ({camera_type})");
         }
@@ -337,7 +338,7 @@ async fn test_usb_camera_detection() {
         .iter()
         .filter(|camera| {
             camera
-                .get("camera_type")
+                .get("kind")
                 .and_then(|v| v.as_str())
                 .map(|t| t == "usb")
                 .unwrap_or(false)
@@ -350,14 +351,18 @@ async fn test_usb_camera_detection() {
         println!("USB Camera: {camera:?}");
 
         // Verify USB-specific fields
-        assert!(camera.get("index").is_some(), "USB camera missing index");
+        assert!(
+            camera.pointer("/usb/index").is_some(),
+            "USB camera missing index"
+        );
         assert!(camera.get("name").is_some(), "USB camera missing name");
         assert!(camera.get("id").is_some(), "USB camera missing id");
 
-        // Hardware ID should be USB-prefixed
+        // The ID should say the camera is USB too
         if let Some(id) = camera.get("id").and_then(|v| v.as_str()) {
-            assert!(
-                id.starts_with(USB_DEVICE_PREFIX_WITH_COLON),
+            assert_eq!(
+                CameraId::from(id).kind(),
+                CameraKind::Usb,
                 "USB camera ID should start with 'usb:': {id}",
             );
         }
@@ -1,14 +1,86 @@
 #![deny(clippy::expect_used)]
 #![deny(clippy::unwrap_used)]
 
+pub mod api;
+pub mod batches;
+pub mod broadcast_lag;
+pub mod build_info;
+pub mod burst;
+pub mod camera;
+pub mod camera_inventory;
+pub mod camera_lock;
 pub mod camera_manager;
+pub mod camera_presets;
+pub mod camera_warmup;
+pub mod capture_overlay;
+pub mod capture_preflight;
+pub mod capture_stats;
+pub mod case_designation;
+pub mod case_type_store;
+pub mod cli_table;
+pub mod client;
+pub mod composite;
 pub mod config;
+pub mod config_schema;
+pub mod config_writer;
 pub mod constants;
+pub mod controller_entities;
 pub mod controller_monitor;
+pub mod controller_recording;
+pub mod cors;
+pub mod data_directories;
+pub mod data_export;
+pub mod data_usage;
+pub mod detection_gate;
+pub mod disk_space;
 pub mod error;
+pub mod etag;
+pub mod event_log;
+#[cfg(any(test, feature = "fixtures"))]
+pub mod fixtures;
+pub mod frame_processing;
+pub mod frame_region;
+pub mod health_history;
+pub mod heavy_work;
+pub mod idempotency;
+pub mod image_ingest;
+pub mod log_buffer;
+pub mod measurements;
+pub mod messages;
 pub mod ml_training;
+pub mod model_metrics;
+pub mod orientation;
+pub mod pre_capture;
+pub mod protocol;
+pub mod reference_sheet;
+pub mod safe_name;
+pub mod self_test;
 pub mod server;
+pub mod server_timing;
+pub mod session_bundle;
+pub mod session_claims;
+pub mod session_summary;
+pub mod settings_snapshot;
+pub mod setup;
 pub mod shell_data;
+pub mod shell_migrations;
+pub mod shell_targets;
+pub mod snapshot_stream;
+pub mod static_assets;
+pub mod stream_health;
+pub mod stream_limits;
+pub mod tagging;
+pub mod task_registry;
+pub mod temperature;
+pub mod training_jobs;
+pub mod training_runs;
+pub mod trash;
+pub mod upload;
 pub mod usb_camera_controller;
+pub mod usb_camera_test;
+pub mod usb_formats;
+pub mod usb_ids;
+pub mod view_classifier;
+pub mod work_queue;
 
 pub use error::{OurError, OurResult};
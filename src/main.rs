@@ -1,26 +1,82 @@
 use std::num::NonZeroU16;
+use std::path::PathBuf;
+use std::time::Duration;
 
-use clap::{Parser, Subcommand};
+use clap::{CommandFactory, Parser, Subcommand};
+use indicatif::{ProgressBar, ProgressStyle};
+use shell_sorter::api::{ApiError, ErrorCode};
+use shell_sorter::build_info::BuildInfo;
+use shell_sorter::camera::CameraId;
+use shell_sorter::camera_inventory;
 use shell_sorter::camera_manager::CameraManager;
+use shell_sorter::camera_warmup::CaptureWarmup;
+use shell_sorter::case_designation::{CaseDesignation, CaseDesignations};
+use shell_sorter::cli_table::{
+    format_age, format_bytes, render_table, render_table_within, terminal_width,
+};
+use shell_sorter::client::{ApiClient, CacheMode, Fetched};
 use shell_sorter::config::Settings;
 use shell_sorter::controller_monitor::ControllerMonitor;
-use shell_sorter::server;
+use shell_sorter::data_directories::{self, DataDirectories};
+use shell_sorter::data_export::{ExportFormat, ExportSince, ImportedExport};
+use shell_sorter::data_usage::UsageReport;
+use shell_sorter::event_log::EventRecorder;
+use shell_sorter::frame_region::FrameSize;
+use shell_sorter::log_buffer::LogBuffer;
+use shell_sorter::ml_training::MLTrainer;
+use shell_sorter::pre_capture::PreCapture;
+use shell_sorter::safe_name::SafeName;
+use shell_sorter::self_test::{SelfTestReport, SelfTestStep, StepOutcome};
+use shell_sorter::server::{self, ServerComponents};
+use shell_sorter::session_bundle::ImportedSession;
+use shell_sorter::shell_data::Shell;
+use shell_sorter::tagging::{PresetTags, SessionImage, TerminalPrompter, confirm, prompt_tags};
+use shell_sorter::task_registry::TaskRegistry;
+use shell_sorter::training_jobs::TrainingJob;
 use shell_sorter::usb_camera_controller::start_usb_camera_manager;
+use shell_sorter::usb_camera_test::{self, SkippableStep};
 use shell_sorter::{OurError, OurResult};
-use tracing::{debug, info};
+use tokio::io::AsyncWriteExt;
+use tracing::{debug, info, warn};
 use tracing_subscriber::{filter::EnvFilter, layer::SubscriberExt, util::SubscriberInitExt};
 
 #[derive(Parser)]
 #[command(name = "shell-sorter")]
 #[command(about = "Ammunition shell case sorting machine controller")]
-#[command(version)]
+#[command(disable_version_flag = true, arg_required_else_help = true)]
 struct Cli {
     #[command(subcommand)]
-    command: Commands,
+    command: Option<Commands>,
+
+    /// Print version information
+    #[arg(short = 'V', long)]
+    version: bool,
+
+    /// Print version information as JSON, for scripts
+    #[arg(long, requires = "version")]
+    json: bool,
 
     /// Enable debug output
     #[arg(short, long, global = true)]
     debug: bool,
+
+    /// User config file to use instead of ~/.config/shell-sorter.json
+    /// (takes precedence over SHELL_SORTER_CONFIG_PATH)
+    #[arg(long, global = true, value_name = "PATH")]
+    config: Option<PathBuf>,
+
+    /// Don't read or write the cache of server responses
+    #[arg(long, global = true, conflicts_with = "cached_only")]
+    no_cache: bool,
+
+    /// Show cached server responses without contacting the server
+    #[arg(long, global = true)]
+    cached_only: bool,
+
+    /// Server to talk to, e.g. https://sorter.local, instead of the configured one
+    /// (takes precedence over SHELL_SORTER_SERVER_URL)
+    #[arg(long, global = true, value_name = "URL")]
+    server_url: Option<reqwest::Url>,
 }
 
 #[derive(Subcommand)]
@@ -58,6 +114,45 @@ enum Commands {
         /// Port to bind to
         #[arg(long, default_value = "8000")]
         port: NonZeroU16,
+        /// Refuse every change and write nothing, to serve a copy of a dataset
+        #[arg(long)]
+        read_only: bool,
+    },
+    /// Developer tools
+    #[cfg(feature = "fixtures")]
+    #[command(hide = true)]
+    Dev {
+        #[command(subcommand)]
+        action: DevAction,
+    },
+}
+
+#[cfg(feature = "fixtures")]
+#[derive(Subcommand)]
+enum DevAction {
+    /// Write synthetic shells and images, to try the UI with a populated dataset
+    GenerateFixtures {
+        /// Number of shells to write
+        #[arg(long, default_value_t = 50)]
+        shells: usize,
+        /// Directory to write `data/` and `images/` into
+        #[arg(long)]
+        out: PathBuf,
+        /// The same seed always writes the same files
+        #[arg(long, default_value_t = shell_sorter::fixtures::DEFAULT_FIXTURE_SEED)]
+        seed: u64,
+        /// Case type as BRAND:DESIGNATION, may be repeated (default: a mix of three)
+        #[arg(long = "case-type", value_name = "BRAND:DESIGNATION")]
+        case_types: Vec<String>,
+        /// Image width in pixels
+        #[arg(long, default_value_t = 640)]
+        width: u32,
+        /// Image height in pixels
+        #[arg(long, default_value_t = 480)]
+        height: u32,
+        /// Also write case_types.json
+        #[arg(long)]
+        with_case_types: bool,
     },
 }
 
@@ -77,6 +172,26 @@ enum MachineAction {
         #[arg(long)]
         brightness: Option<u8>,
     },
+    /// Exercise every actuator and sensor once and report pass/fail
+    SelfTest {
+        /// Also feed one case at the end
+        #[arg(long)]
+        next_case: bool,
+        /// Milliseconds allowed for each controller request
+        #[arg(long)]
+        step_timeout_ms: Option<u64>,
+        /// Print the report as JSON
+        #[arg(long)]
+        json: bool,
+    },
+    /// Turn maintenance mode on or off; nothing moves while it is on
+    Maintenance {
+        /// Maintenance mode state (on/off)
+        state: String,
+        /// Why, recorded in the event log
+        #[arg(long)]
+        reason: Option<String>,
+    },
 }
 
 #[derive(Subcommand)]
@@ -119,53 +234,202 @@ enum UsbCameraAction {
     Test {
         /// Hardware ID of camera to test
         hardware_id: String,
+        /// Steps to leave out for cameras without the control, e.g. brightness,format
+        #[arg(long)]
+        skip: Option<String>,
+        /// Print the report as JSON
+        #[arg(long)]
+        json: bool,
     },
 }
 
 #[derive(Subcommand)]
 enum DataAction {
     /// List shell case data
-    ListShells,
-    /// Tag captured images
+    ListShells {
+        /// Only list shells whose brand, type or session ID contains this text
+        #[arg(long)]
+        search: Option<String>,
+        /// Only shells of this brand
+        #[arg(long)]
+        brand: Option<String>,
+        /// Only shells of this case type, e.g. 9mm
+        #[arg(long = "type")]
+        shell_type: Option<String>,
+        /// Only shells not marked for training
+        #[arg(long)]
+        untrained_only: bool,
+        /// Only shells in this batch, by ID or name
+        #[arg(long)]
+        batch: Option<String>,
+        /// Show at most this many shells, newest first
+        #[arg(long)]
+        limit: Option<usize>,
+        /// Print the shells as JSON
+        #[arg(long)]
+        json: bool,
+        /// Refresh the listing every few seconds (default 5), e.g. during a capture batch
+        #[arg(long, value_name = "SECONDS", num_args = 0..=1, default_missing_value = "5")]
+        watch: Option<u64>,
+    },
+    /// Tag a captured session from the terminal, asking for each tag not given as a flag
     Tag {
         /// Session ID to tag
         #[arg(long)]
-        session_id: Option<String>,
+        session_id: String,
+        /// Brand, instead of asking
+        #[arg(long)]
+        brand: Option<String>,
+        /// Case type, e.g. 9mm, instead of asking
+        #[arg(long = "type")]
+        shell_type: Option<String>,
+        /// Whether to include the shell in training, instead of asking
+        #[arg(long, action = clap::ArgAction::Set)]
+        include: Option<bool>,
+        /// Notes, instead of asking; empty clears them
+        #[arg(long)]
+        notes: Option<String>,
+        /// Save the session's images to a temporary directory and print their paths
+        #[arg(long)]
+        open_images: bool,
     },
-    /// Export data
+    /// Export shell records, and with tar.gz their images, for another machine or a backup
     Export {
-        /// Export format (json/csv)
+        /// Export format (json/tar.gz); only tar.gz includes images
         #[arg(long, default_value = "json")]
         format: String,
+        /// Only shells captured or changed after this: an RFC 3339 time, or "last" for the last export
+        #[arg(long)]
+        since: Option<String>,
+        /// Where to write the export (default: the name the server gives it)
+        #[arg(long)]
+        output: Option<PathBuf>,
     },
-    /// Import data
+    /// Import an export written by `data export`, replacing sessions already here
     Import {
         /// File to import
         #[arg(long)]
-        file: String,
+        file: PathBuf,
+    },
+    /// Save one session's shell record, images and composite as a zip bundle
+    ExportSession {
+        /// Session ID to export
+        session_id: String,
+        /// Where to write the bundle (default: <session_id>.zip)
+        #[arg(long)]
+        output: Option<PathBuf>,
+    },
+    /// Recreate a session from a bundle under a new session ID
+    ImportSession {
+        /// Bundle written by export-session
+        file: PathBuf,
+    },
+    /// Check that every shell record loads and has a supported case type
+    Verify,
+    /// Show disk used by images and shell data, by case type and largest session
+    Usage {
+        /// List this many of the largest sessions
+        #[arg(long, default_value_t = 20)]
+        limit: usize,
+        /// Rescan now instead of showing the server's last scan
+        #[arg(long)]
+        refresh: bool,
+        /// Print the report as JSON
+        #[arg(long)]
+        json: bool,
+    },
+    /// Rewrite shell records saved in an older schema in the current one
+    Migrate {
+        /// Rewrite the files without copying them to a backup directory first
+        #[arg(long)]
+        no_backup: bool,
+    },
+    /// Batches of shells that came from the same lot
+    Batches {
+        #[command(subcommand)]
+        action: BatchAction,
+    },
+}
+
+#[derive(Subcommand)]
+enum BatchAction {
+    /// List batches with their shell counts
+    List {
+        /// Print the batches as JSON
+        #[arg(long)]
+        json: bool,
+    },
+    /// Create a batch
+    Create {
+        /// Name, e.g. "gun show lot 3"
+        name: String,
+        #[arg(long)]
+        notes: Option<String>,
+        /// Put every following capture into it
+        #[arg(long)]
+        activate: bool,
+    },
+    /// Put every following capture into a batch, by ID or name
+    Activate { batch: String },
+    /// Stop putting captures into a batch
+    Deactivate,
+    /// Take a batch's shells out of training, by ID or name
+    Exclude {
+        batch: String,
+        /// Put the batch's tagged shells back into training instead
+        #[arg(long)]
+        include: bool,
     },
 }
 
 #[derive(Subcommand)]
 enum MlAction {
     /// List case types
-    ListTypes,
+    ListTypes {
+        /// Print the case types as JSON
+        #[arg(long)]
+        json: bool,
+    },
     /// Add new case type
     AddType {
-        /// Case type name
+        /// Case type name, used as its directory name
         #[arg(long)]
         name: String,
-        /// Case designation
+        /// Case designation, one of the supported case types
+        #[arg(long)]
+        designation: String,
+        /// Brand name
         #[arg(long)]
-        designation: Option<String>,
+        brand: Option<String>,
+        /// Allow a designation that isn't in supported_case_types
+        #[arg(long)]
+        force: bool,
+        /// Print the created case type as JSON
+        #[arg(long)]
+        json: bool,
     },
     /// Generate composite images
     GenerateComposites,
-    /// Train model
+    /// Download a sheet with one example image of each case type
+    ReferenceSheet {
+        /// Where to write the sheet
+        #[arg(long)]
+        output: PathBuf,
+        /// Columns in the grid, the server's reference_sheet_columns when unset
+        #[arg(long)]
+        columns: Option<u32>,
+    },
+    /// Train a model on the server, following the job until it finishes
     Train {
         /// Specific case types to train
         #[arg(long)]
         types: Option<Vec<String>>,
+        /// Wait for the job to finish; with --wait=false only the job ID is printed
+        #[arg(long, default_value_t = true, action = clap::ArgAction::Set)]
+        wait: bool,
+        /// Print the trained model's metadata as JSON
+        #[arg(long)]
+        json: bool,
     },
 }
 
@@ -182,20 +446,43 @@ enum ConfigAction {
     },
     /// Reset configuration to defaults
     Reset,
+    /// Import ESPHome cameras from an inventory file (JSON or YAML)
+    ImportCameras {
+        /// Inventory file to import
+        #[arg(long)]
+        file: PathBuf,
+    },
 }
 
 #[tokio::main]
 async fn main() -> OurResult<()> {
     let cli = Cli::parse();
 
+    if cli.version {
+        let build_info = BuildInfo::current();
+        if cli.json {
+            println!("{}", serde_json::to_string(&build_info)?);
+        } else {
+            println!(
+                "shell-sorter {} ({})",
+                build_info.version,
+                build_info.short_commit()
+            );
+        }
+        return Ok(());
+    }
+
     // Initialize configuration
-    let settings = match Settings::new() {
+    let mut settings = match Settings::load(cli.config.clone()) {
         Ok(settings) => settings,
         Err(e) => {
             eprintln!("Failed to load configuration: {e}");
             std::process::exit(1);
         }
     };
+    if let Some(server_url) = cli.server_url.clone() {
+        settings.server_url = Some(server_url);
+    }
 
     // Initialize tracing
     let base_level = if cli.debug { "debug" } else { "info" };
@@ -221,9 +508,13 @@ async fn main() -> OurResult<()> {
         .from_env_lossy()
         .add_directive(hyper_directive);
 
+    // Recent log output for the diagnostics panel, alongside the terminal output
+    let logs = LogBuffer::new(settings.log_buffer_capacity, settings.log_buffer_level);
+
     tracing_subscriber::registry()
         .with(filter)
         .with(tracing_subscriber::fmt::layer())
+        .with(logs.layer())
         .init();
 
     if cli.debug {
@@ -231,17 +522,45 @@ async fn main() -> OurResult<()> {
     }
     info!("Shell Sorter starting up");
 
-    match cli.command {
-        Commands::Machine { action } => handle_machine_command(action, &settings).await,
-        Commands::Camera { action } => handle_camera_command(action, &settings).await,
-        Commands::Data { action } => handle_data_command(action, &settings).await,
-        Commands::Ml { action } => handle_ml_command(action, &settings).await,
+    let Some(command) = cli.command else {
+        Cli::command().print_help()?;
+        return Ok(());
+    };
+    let cache_mode = if cli.no_cache {
+        CacheMode::Disabled
+    } else if cli.cached_only {
+        CacheMode::CachedOnly
+    } else {
+        CacheMode::Fallback
+    };
+    let client = ApiClient::from_settings(&settings, cache_mode)?;
+    let result = match command {
+        Commands::Machine { action } => handle_machine_command(action, &client).await,
+        Commands::Camera { action } => handle_camera_command(action, &settings, &client).await,
+        Commands::Data { action } => handle_data_command(action, &client).await,
+        Commands::Ml { action } => handle_ml_command(action, &settings, &client).await,
         Commands::Config { action } => handle_config_command(action, &settings).await,
-        Commands::Serve { host, port } => start_web_server(host, port, settings).await,
+        Commands::Serve {
+            host,
+            port,
+            read_only,
+        } => {
+            let mut settings = settings;
+            settings.read_only |= read_only;
+            start_web_server(host, port, settings, logs).await
+        }
+        #[cfg(feature = "fixtures")]
+        Commands::Dev { action } => handle_dev_command(action),
+    };
+    // Print the message rather than the error's debug form
+    if let Err(e) = result {
+        eprintln!("Error: {e}");
+        std::process::exit(1);
     }
+    Ok(())
 }
 
-async fn handle_machine_command(action: MachineAction, _settings: &Settings) -> OurResult<()> {
+async fn handle_machine_command(action: MachineAction, client: &ApiClient) -> OurResult<()> {
     match action {
         MachineAction::NextCase => {
             info!("Triggering next case sequence...");
@@ -249,8 +568,33 @@ async fn handle_machine_command(action: MachineAction, _settings: &Settings) ->
             Ok(())
         }
         MachineAction::Status => {
-            info!("Machine status: Ready");
-            // TODO: Implement status check
+            let fetched = client.get_cached("/api/machine/status", &[]).await?;
+            print_cache_notice(&fetched);
+            let status = api_data(&fetched.body, "Failed to get machine status")?;
+            println!("Status: {}", status["status"].as_str().unwrap_or("unknown"));
+            println!(
+                "Ready: {}",
+                if status["ready"].as_bool() == Some(true) {
+                    "yes"
+                } else {
+                    "no"
+                }
+            );
+            println!(
+                "Active jobs: {}",
+                status["active_jobs"].as_u64().unwrap_or(0)
+            );
+            if let Some(last_update) = status["last_update"].as_str() {
+                println!("Last update: {last_update}");
+            }
+            if status["maintenance"]["enabled"].as_bool() == Some(true) {
+                println!(
+                    "Maintenance: on ({})",
+                    status["maintenance"]["reason"]
+                        .as_str()
+                        .unwrap_or("no reason given")
+                );
+            }
             Ok(())
         }
         MachineAction::Sensors => {
@@ -265,21 +609,75 @@ async fn handle_machine_command(action: MachineAction, _settings: &Settings) ->
             // TODO: Implement flash control
             Ok(())
         }
+        MachineAction::SelfTest {
+            next_case,
+            step_timeout_ms,
+            json,
+        } => {
+            let request = client
+                .post("/api/machine/self-test")
+                .json(&serde_json::json!({
+                    "trigger_next_case": next_case,
+                    "step_timeout_ms": step_timeout_ms,
+                }));
+            let response: serde_json::Value = client.send(request).await?.json().await?;
+            let report: SelfTestReport =
+                serde_json::from_value(api_data(&response, "Self-test failed to run")?.clone())?;
+
+            if json {
+                println!("{}", serde_json::to_string_pretty(&report)?);
+            } else {
+                print_self_test(&report);
+            }
+            if report.passed {
+                Ok(())
+            } else {
+                Err(OurError::App("Self-test failed".to_string()))
+            }
+        }
+        MachineAction::Maintenance { state, reason } => {
+            let enabled = match state.as_str() {
+                "on" => true,
+                "off" => false,
+                other => {
+                    return Err(OurError::App(format!(
+                        "Maintenance state must be 'on' or 'off', not '{other}'"
+                    )));
+                }
+            };
+            let request = client
+                .post("/api/machine/maintenance")
+                .json(&serde_json::json!({"enabled": enabled, "reason": reason}));
+            let response: serde_json::Value = client.send(request).await?.json().await?;
+            let status = api_data(&response, "Failed to change maintenance mode")?;
+            if status["enabled"].as_bool() == Some(true) {
+                match status["reason"].as_str() {
+                    Some(reason) => println!("Maintenance mode is on: {reason}"),
+                    None => println!("Maintenance mode is on"),
+                }
+                println!("Motion commands are refused until `machine maintenance off`");
+                if let Some(expires_at) = status["expires_at"].as_str() {
+                    println!("Turns itself off at {expires_at}");
+                }
+            } else {
+                println!("Maintenance mode is off");
+            }
+            Ok(())
+        }
     }
 }
 
-async fn handle_camera_command(action: CameraAction, settings: &Settings) -> OurResult<()> {
+async fn handle_camera_command(
+    action: CameraAction,
+    settings: &Settings,
+    client: &ApiClient,
+) -> OurResult<()> {
     match action {
         CameraAction::Detect => {
             info!("Detecting cameras...");
             debug!("Camera detection process starting");
 
-            // Create HTTP client to communicate with running server
-            let client = reqwest::Client::new();
-            let base_url = settings.base_url();
-            let detect_url = format!("{base_url}/api/cameras/detect");
-
-            match client.get(&detect_url).send().await {
+            match client.get("/api/cameras/detect").send().await {
                 Ok(response) => {
                     if response.status().is_success() {
                         match response.json::<serde_json::Value>().await {
@@ -330,9 +728,7 @@ async fn handle_camera_command(action: CameraAction, settings: &Settings) -> Our
                     }
                 }
                 Err(e) => {
-                    return Err(OurError::App(format!(
-                        "Failed to connect to server at {base_url}: {e}\nMake sure the server is running with: shell-sorter serve"
-                    )));
+                    return Err(client.unreachable(e));
                 }
             }
 
@@ -341,67 +737,32 @@ async fn handle_camera_command(action: CameraAction, settings: &Settings) -> Our
         CameraAction::List => {
             info!("Listing cameras...");
 
-            // Create HTTP client to communicate with running server
-            let client = reqwest::Client::new();
-            let base_url = settings.base_url();
-            let cameras_url = format!("{base_url}/api/cameras");
-
-            match client.get(&cameras_url).send().await {
-                Ok(response) => {
-                    if response.status().is_success() {
-                        match response.json::<serde_json::Value>().await {
-                            Ok(json) => {
-                                if let Some(data) = json.get("data").and_then(|d| d.as_array()) {
-                                    if data.is_empty() {
-                                        println!("No cameras found");
-                                    } else {
-                                        println!("Found {} camera(s):", data.len());
-                                        for camera in data {
-                                            if let (
-                                                Some(name),
-                                                Some(id),
-                                                Some(hostname),
-                                                Some(online),
-                                            ) = (
-                                                camera.get("name").and_then(|n| n.as_str()),
-                                                camera.get("id").and_then(|i| i.as_str()),
-                                                camera.get("hostname").and_then(|h| h.as_str()),
-                                                camera.get("online").and_then(|o| o.as_bool()),
-                                            ) {
-                                                println!("  • {name} ({id})");
-                                                println!("    Hostname: {hostname}");
-                                                println!(
-                                                    "    Status: {}",
-                                                    if online { "Online" } else { "Offline" }
-                                                );
-                                                println!();
-                                            }
-                                        }
-                                    }
-                                } else {
-                                    println!("No camera data found in response");
-                                }
-                            }
-                            Err(e) => {
-                                return Err(OurError::App(format!(
-                                    "Failed to parse response: {e}"
-                                )));
-                            }
-                        }
-                    } else {
-                        return Err(OurError::App(format!(
-                            "Server returned error: {}",
-                            response.status()
-                        )));
+            let fetched = client.get_cached("/api/cameras", &[]).await?;
+            print_cache_notice(&fetched);
+            let cameras = api_data(&fetched.body, "Failed to list cameras")?
+                .as_array()
+                .cloned()
+                .unwrap_or_default();
+            if cameras.is_empty() {
+                println!("No cameras found");
+                return Ok(());
+            }
+            println!("Found {} camera(s):", cameras.len());
+            for camera in &cameras {
+                if let (Some(name), Some(id), Some(online)) = (
+                    camera["name"].as_str(),
+                    camera["id"].as_str(),
+                    camera["online"].as_bool(),
+                ) {
+                    let name = camera["nickname"].as_str().unwrap_or(name);
+                    println!("  • {name} ({id})");
+                    if let Some(hostname) = camera["esp"]["hostname"].as_str() {
+                        println!("    Hostname: {hostname}");
                     }
-                }
-                Err(e) => {
-                    return Err(OurError::App(format!(
-                        "Failed to connect to server at {base_url}: {e}\nMake sure the server is running with: shell-sorter serve"
-                    )));
+                    println!("    Status: {}", if online { "Online" } else { "Offline" });
+                    println!();
                 }
             }
-
             Ok(())
         }
         CameraAction::Capture { session_id } => {
@@ -416,22 +777,36 @@ async fn handle_camera_command(action: CameraAction, settings: &Settings) -> Our
             // TODO: Implement camera streaming
             Ok(())
         }
-        CameraAction::Usb { action } => handle_usb_camera_command(action).await,
+        CameraAction::Usb { action } => handle_usb_camera_command(action, settings).await,
     }
 }
 
-async fn handle_usb_camera_command(action: UsbCameraAction) -> OurResult<()> {
+async fn handle_usb_camera_command(action: UsbCameraAction, settings: &Settings) -> OurResult<()> {
     match action {
         UsbCameraAction::Detect => {
             info!("Detecting USB cameras with hardware identification...");
 
-            let usb_camera_manager = start_usb_camera_manager().await?;
-            let cameras = usb_camera_manager.detect_cameras().await?;
+            let usb_camera_manager = start_usb_camera_manager(
+                EventRecorder::default(),
+                CaptureWarmup::from_settings(settings),
+                PreCapture::from_settings(settings),
+                &TaskRegistry::default(),
+            )
+            .await?;
+            let detection = usb_camera_manager.detect_cameras(false).await?;
+            let cameras = detection.cameras;
 
             if cameras.is_empty() {
-                println!("No USB cameras detected");
+                println!(
+                    "No USB cameras detected ({}ms)",
+                    detection.report.duration_ms
+                );
             } else {
-                println!("Detected {} USB camera(s):", cameras.len());
+                println!(
+                    "Detected {} USB camera(s) in {}ms:",
+                    cameras.len(),
+                    detection.report.duration_ms
+                );
                 for camera in cameras {
                     println!("  • {} ({})", camera.name, camera.hardware_id);
                     println!("    Index: {}", camera.index);
@@ -471,7 +846,13 @@ async fn handle_usb_camera_command(action: UsbCameraAction) -> OurResult<()> {
         UsbCameraAction::List => {
             info!("Listing detected USB cameras...");
 
-            let usb_camera_manager = start_usb_camera_manager().await?;
+            let usb_camera_manager = start_usb_camera_manager(
+                EventRecorder::default(),
+                CaptureWarmup::from_settings(settings),
+                PreCapture::from_settings(settings),
+                &TaskRegistry::default(),
+            )
+            .await?;
             let cameras = usb_camera_manager.list_cameras().await?;
 
             if cameras.is_empty() {
@@ -496,17 +877,23 @@ async fn handle_usb_camera_command(action: UsbCameraAction) -> OurResult<()> {
         UsbCameraAction::Capture { hardware_id } => {
             info!("Capturing image from USB camera: {hardware_id}");
 
-            let usb_camera_manager = start_usb_camera_manager().await?;
+            let usb_camera_manager = start_usb_camera_manager(
+                EventRecorder::default(),
+                CaptureWarmup::from_settings(settings),
+                PreCapture::from_settings(settings),
+                &TaskRegistry::default(),
+            )
+            .await?;
 
             // First detect cameras to ensure the hardware_id exists
-            let cameras = usb_camera_manager.detect_cameras().await?;
+            let cameras = usb_camera_manager.detect_cameras(false).await?.cameras;
             if !cameras.iter().any(|c| c.hardware_id == hardware_id) {
                 return Err(OurError::App(format!("Camera not found: {hardware_id}")));
             }
 
             // Select and start streaming for this camera
             usb_camera_manager
-                .select_cameras(vec![hardware_id.clone()])
+                .select_cameras(vec![CameraId::from(hardware_id.clone())])
                 .await?;
             usb_camera_manager.start_streaming().await?;
 
@@ -535,108 +922,1074 @@ async fn handle_usb_camera_command(action: UsbCameraAction) -> OurResult<()> {
 
             Ok(())
         }
-        UsbCameraAction::Test { hardware_id } => {
+        UsbCameraAction::Test {
+            hardware_id,
+            skip,
+            json,
+        } => {
+            let skip = match skip {
+                Some(skip) => SkippableStep::parse_list(&skip)?,
+                None => Vec::new(),
+            };
             info!("Testing USB camera: {hardware_id}");
 
-            let usb_camera_manager = start_usb_camera_manager().await?;
-
-            // Detect cameras
-            println!("1. Detecting cameras...");
-            let cameras = usb_camera_manager.detect_cameras().await?;
+            let usb_camera_manager = start_usb_camera_manager(
+                EventRecorder::default(),
+                CaptureWarmup::from_settings(settings),
+                PreCapture::from_settings(settings),
+                &TaskRegistry::default(),
+            )
+            .await?;
+            let report = usb_camera_test::run(&usb_camera_manager, &hardware_id, &skip).await;
 
-            let camera = cameras
-                .iter()
-                .find(|c| c.hardware_id == hardware_id)
-                .ok_or_else(|| OurError::App(format!("Camera not found: {hardware_id}")))?;
+            if json {
+                println!("{}", serde_json::to_string_pretty(&report)?);
+            } else {
+                if let Some(name) = &report.camera_name {
+                    println!("{name} ({})\n", report.hardware_id);
+                }
+                print_steps(&report.steps);
+                println!();
+                let result = if report.passed { "PASSED" } else { "FAILED" };
+                println!("{result} in {}ms", report.duration_ms);
+            }
+            if report.passed {
+                Ok(())
+            } else {
+                Err(OurError::App(format!(
+                    "USB camera test failed: {}",
+                    report.failed_steps().join(", ")
+                )))
+            }
+        }
+    }
+}
 
-            println!("   ✓ Camera found: {}", camera.name);
+async fn handle_data_command(action: DataAction, client: &ApiClient) -> OurResult<()> {
+    match action {
+        DataAction::ListShells {
+            search,
+            brand,
+            shell_type,
+            untrained_only,
+            batch,
+            limit,
+            json,
+            watch,
+        } => {
+            let mut query: Vec<(&str, String)> = Vec::new();
+            if let Some(search) = search {
+                query.push(("q", search));
+            }
+            if let Some(brand) = brand {
+                query.push(("brand", brand));
+            }
+            if let Some(shell_type) = shell_type {
+                query.push(("shell_type", shell_type));
+            }
+            if untrained_only {
+                query.push(("include", "false".to_string()));
+            }
+            if let Some(batch) = batch {
+                query.push(("batch_id", find_batch(client, &batch).await?));
+            }
+            if let Some(limit) = limit {
+                query.push(("limit", limit.to_string()));
+            }
 
-            // Test camera selection
-            println!("2. Selecting camera...");
-            usb_camera_manager
-                .select_cameras(vec![hardware_id.clone()])
+            loop {
+                let fetched = client.get_cached("/api/shells/search", &query).await?;
+                let shells = api_data(&fetched.body, "Failed to list shells")?
+                    .as_array()
+                    .cloned()
+                    .unwrap_or_default();
+                if json {
+                    print_cache_notice(&fetched);
+                    println!("{}", serde_json::to_string_pretty(&shells)?);
+                } else {
+                    if watch.is_some() {
+                        // Clear the screen and go to the top left before redrawing
+                        print!("\x1b[2J\x1b[H");
+                        println!(
+                            "{} shells, updated {}\n",
+                            shells.len(),
+                            chrono::Local::now().format("%H:%M:%S")
+                        );
+                    }
+                    print_cache_notice(&fetched);
+                    print_shells(&shells);
+                }
+                match watch {
+                    Some(seconds) => tokio::time::sleep(Duration::from_secs(seconds.max(1))).await,
+                    None => return Ok(()),
+                }
+            }
+        }
+        DataAction::Tag {
+            session_id,
+            brand,
+            shell_type,
+            include,
+            notes,
+            open_images,
+        } => {
+            let preset = PresetTags {
+                brand,
+                shell_type,
+                include,
+                notes,
+            };
+            tag_session(client, &session_id, preset, open_images).await
+        }
+        DataAction::Export {
+            format,
+            since,
+            output,
+        } => {
+            let format: ExportFormat = format.parse()?;
+            let mut query = vec![("format", format.to_string())];
+            if let Some(since) = since {
+                // Catch a mistyped time before the server builds an export
+                since.parse::<ExportSince>()?;
+                query.push(("since", since));
+            }
+            let mut response = client
+                .send(client.get("/api/data/export").query(&query))
                 .await?;
-            println!("   ✓ Camera selected");
+            if !response.status().is_success() {
+                let status = response.status();
+                let body: serde_json::Value = response.json().await.unwrap_or_default();
+                return Err(match ApiError::from_response(&body) {
+                    Some(error) => OurError::App(format!("Export failed: {error}")),
+                    None => OurError::App(format!("Export failed: {status}")),
+                });
+            }
+            let output = output.unwrap_or_else(|| {
+                response
+                    .headers()
+                    .get("Content-Disposition")
+                    .and_then(|value| value.to_str().ok())
+                    .and_then(|value| value.split_once("filename=\""))
+                    .and_then(|(_, filename)| filename.strip_suffix('"'))
+                    .and_then(|filename| SafeName::file_name("export filename", filename).ok())
+                    .map(|filename| PathBuf::from(filename.to_string()))
+                    .unwrap_or_else(|| {
+                        PathBuf::from(format!("shell-sorter-export.{}", format.extension()))
+                    })
+            });
 
-            // Test streaming
-            println!("3. Starting streaming...");
-            usb_camera_manager.start_streaming().await?;
-            println!("   ✓ Streaming started");
+            // Refuse to overwrite an earlier export
+            let mut file = tokio::fs::OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(&output)
+                .await
+                .map_err(|e| {
+                    OurError::App(format!("Failed to create {}: {e}", output.display()))
+                })?;
+            let mut written = 0;
+            while let Some(chunk) = response.chunk().await? {
+                file.write_all(&chunk).await?;
+                written += chunk.len();
+            }
+            file.flush().await?;
+            println!("Exported data to {} ({written} bytes)", output.display());
+            Ok(())
+        }
+        DataAction::Import { file } => {
+            let form = reqwest::multipart::Form::new()
+                .file("export", &file)
+                .await
+                .map_err(|e| OurError::App(format!("Failed to read {}: {e}", file.display())))?;
+            let request = client.post("/api/data/import").multipart(form);
+            let response: serde_json::Value = client.send(request).await?.json().await?;
+            let imported: ImportedExport =
+                serde_json::from_value(api_data(&response, "Import failed")?.clone())?;
+            println!(
+                "Imported {} shells and {} images",
+                imported.sessions.len(),
+                imported.images
+            );
+            if !imported.missing_sessions.is_empty() {
+                println!(
+                    "{} sessions in the exported dataset aren't here yet; import the earlier exports too:",
+                    imported.missing_sessions.len()
+                );
+                for session_id in &imported.missing_sessions {
+                    println!("  {session_id}");
+                }
+            }
+            Ok(())
+        }
+        DataAction::ExportSession { session_id, output } => {
+            let output = output.unwrap_or_else(|| PathBuf::from(format!("{session_id}.zip")));
+            let mut response = client
+                .send(client.get(&format!("/api/shells/{session_id}/export")))
+                .await?;
+            if !response.status().is_success() {
+                let status = response.status();
+                let body: serde_json::Value = response.json().await.unwrap_or_default();
+                return Err(match ApiError::from_response(&body) {
+                    Some(error) if error.code == ErrorCode::ShellNotFound => {
+                        OurError::App(format!("No session {session_id} on the server"))
+                    }
+                    Some(error) => OurError::App(format!("Export failed: {error}")),
+                    None => OurError::App(format!("Export failed: {status}")),
+                });
+            }
 
-            // Test capture
-            println!("4. Capturing test image...");
-            match usb_camera_manager.capture_image(hardware_id.clone()).await {
-                Ok(image_data) => {
-                    println!("   ✓ Image captured ({} bytes)", image_data.len());
+            // Refuse to overwrite an earlier export
+            let mut file = tokio::fs::OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(&output)
+                .await
+                .map_err(|e| {
+                    OurError::App(format!("Failed to create {}: {e}", output.display()))
+                })?;
+            let mut written = 0;
+            while let Some(chunk) = response.chunk().await? {
+                file.write_all(&chunk).await?;
+                written += chunk.len();
+            }
+            file.flush().await?;
+            println!(
+                "Exported session {session_id} to {} ({written} bytes)",
+                output.display()
+            );
+            Ok(())
+        }
+        DataAction::ImportSession { file } => {
+            let form = reqwest::multipart::Form::new()
+                .file("bundle", &file)
+                .await
+                .map_err(|e| OurError::App(format!("Failed to read {}: {e}", file.display())))?;
+            let request = client.post("/api/shells/import-bundle").multipart(form);
+            let response: serde_json::Value = client.send(request).await?.json().await?;
+            let imported: ImportedSession =
+                serde_json::from_value(api_data(&response, "Import failed")?.clone())?;
+            println!(
+                "Imported session {} as {} with {} images{}",
+                imported.source_session_id,
+                imported.session_id,
+                imported.images.len(),
+                if imported.composite {
+                    " and its composite"
+                } else {
+                    ""
                 }
-                Err(e) => {
-                    println!("   ✗ Capture failed: {e}");
+            );
+            Ok(())
+        }
+        DataAction::Verify => {
+            let response: serde_json::Value = client
+                .send(client.get("/api/shells/integrity"))
+                .await?
+                .json()
+                .await?;
+            let report = api_data(&response, "Verify failed")?;
+            let skipped = report["skipped"].as_array().cloned().unwrap_or_default();
+            let unknown_types = report["unknown_types"]
+                .as_array()
+                .cloned()
+                .unwrap_or_default();
+            let summary_mismatches = report["summary_mismatches"]
+                .as_array()
+                .cloned()
+                .unwrap_or_default();
+
+            println!(
+                "{} shell records load",
+                report["valid"].as_u64().unwrap_or(0)
+            );
+            for file in &skipped {
+                println!(
+                    "Skipped {}: {}",
+                    file["path"].as_str().unwrap_or("?"),
+                    file["reason"].as_str().unwrap_or("?")
+                );
+            }
+            for shell in &unknown_types {
+                let session_id = shell["session_id"].as_str().unwrap_or("?");
+                let shell_type = shell["shell_type"].as_str().unwrap_or("?");
+                match shell["canonical"].as_str() {
+                    Some(canonical) => {
+                        println!("{session_id}: type {shell_type:?} should be {canonical:?}")
+                    }
+                    None => {
+                        println!("{session_id}: type {shell_type:?} is not a supported case type")
+                    }
                 }
             }
 
-            // Stop streaming
-            println!("5. Stopping streaming...");
-            usb_camera_manager.stop_streaming().await?;
-            println!("   ✓ Streaming stopped");
+            for session in &summary_mismatches {
+                let session_id = session["session_id"].as_str().unwrap_or("?");
+                for difference in session["differences"].as_array().into_iter().flatten() {
+                    println!("{session_id}: {}", difference.as_str().unwrap_or("?"));
+                }
+            }
 
-            println!("\nUSB camera test completed successfully!");
+            let problems = skipped.len() + unknown_types.len() + summary_mismatches.len();
+            if problems > 0 {
+                return Err(OurError::App(format!(
+                    "{problems} problems found in the shell data"
+                )));
+            }
+            Ok(())
+        }
+        DataAction::Usage {
+            limit,
+            refresh,
+            json,
+        } => {
+            let request = client.get("/api/data/usage").query(&[
+                ("limit", limit.to_string()),
+                ("refresh", refresh.to_string()),
+            ]);
+            let response: serde_json::Value = client.send(request).await?.json().await?;
+            let data = api_data(&response, "Failed to get data usage")?;
+            if json {
+                println!("{}", serde_json::to_string_pretty(data)?);
+                return Ok(());
+            }
+            let report: UsageReport = serde_json::from_value(data.clone())?;
+            print_data_usage(&report);
+            Ok(())
+        }
+        DataAction::Batches { action } => handle_batch_command(action, client).await,
+        DataAction::Migrate { no_backup } => {
+            let request = client
+                .post("/api/data/migrate")
+                .json(&serde_json::json!({ "backup": !no_backup }));
+            let response: serde_json::Value = client.send(request).await?.json().await?;
+            let report = api_data(&response, "Migration failed")?;
+            let migrated = report["migrated"].as_object().cloned().unwrap_or_default();
+            let skipped = report["skipped"].as_array().cloned().unwrap_or_default();
 
+            for (session_id, from) in &migrated {
+                println!("Migrated {session_id} from schema version {from}");
+            }
+            println!(
+                "{} shell records migrated, {} already current",
+                migrated.len(),
+                report["current"].as_u64().unwrap_or(0)
+            );
+            if let Some(backup_directory) = report["backup_directory"].as_str() {
+                println!("Originals backed up to {backup_directory}");
+            }
+            for file in &skipped {
+                println!(
+                    "Skipped {}: {}",
+                    file["path"].as_str().unwrap_or("?"),
+                    file["reason"].as_str().unwrap_or("?")
+                );
+            }
+            if !skipped.is_empty() {
+                return Err(OurError::App(format!(
+                    "{} shell records couldn't be migrated",
+                    skipped.len()
+                )));
+            }
             Ok(())
         }
     }
 }
 
-async fn handle_data_command(action: DataAction, _settings: &Settings) -> OurResult<()> {
+async fn handle_batch_command(action: BatchAction, client: &ApiClient) -> OurResult<()> {
     match action {
-        DataAction::ListShells => {
-            info!("Shell case data:");
-            // TODO: Implement shell listing
+        BatchAction::List { json } => {
+            let response: serde_json::Value = client
+                .send(client.get("/api/batches"))
+                .await?
+                .json()
+                .await?;
+            let data = api_data(&response, "Failed to list batches")?;
+            if json {
+                println!("{}", serde_json::to_string_pretty(data)?);
+                return Ok(());
+            }
+            let batches = data["batches"].as_array().cloned().unwrap_or_default();
+            if batches.is_empty() {
+                println!("No batches");
+                return Ok(());
+            }
+            let active = data["active_batch_id"].as_str();
+            let rows: Vec<Vec<String>> = batches
+                .iter()
+                .map(|batch| {
+                    let id = batch["id"].as_str().unwrap_or_default();
+                    vec![
+                        if Some(id) == active { "*" } else { "" }.to_string(),
+                        id.chars().take(8).collect(),
+                        batch["name"].as_str().unwrap_or_default().to_string(),
+                        batch["shell_count"].as_u64().unwrap_or(0).to_string(),
+                        batch["included_count"].as_u64().unwrap_or(0).to_string(),
+                        if batch["exclude_from_training"].as_bool() == Some(true) {
+                            "excluded"
+                        } else {
+                            ""
+                        }
+                        .to_string(),
+                    ]
+                })
+                .collect();
+            println!(
+                "{}",
+                render_table(
+                    &["ACTIVE", "ID", "NAME", "SHELLS", "INCLUDED", "TRAINING"],
+                    &rows
+                )
+            );
             Ok(())
         }
-        DataAction::Tag { session_id } => {
-            info!("Tagging images...");
-            debug!("Session ID: {:?}", session_id);
-            // TODO: Implement image tagging
+        BatchAction::Create {
+            name,
+            notes,
+            activate,
+        } => {
+            let request = client.post("/api/batches").json(&serde_json::json!({
+                "name": name,
+                "notes": notes,
+                "activate": activate,
+            }));
+            let response: serde_json::Value = client.send(request).await?.json().await?;
+            let batch = api_data(&response, "Failed to create batch")?;
+            println!(
+                "Created batch {} ({})",
+                batch["name"].as_str().unwrap_or(&name),
+                batch["id"].as_str().unwrap_or("?")
+            );
+            if activate {
+                println!("Captures now go into it");
+            }
             Ok(())
         }
-        DataAction::Export { format } => {
-            info!("Exporting data in {} format...", format);
-            // TODO: Implement data export
+        BatchAction::Activate { batch } => {
+            let batch_id = find_batch(client, &batch).await?;
+            let request = client.post(&format!("/api/batches/{batch_id}/activate"));
+            let response: serde_json::Value = client.send(request).await?.json().await?;
+            let batch = api_data(&response, "Failed to activate batch")?;
+            println!(
+                "Captures now go into batch {}",
+                batch["name"].as_str().unwrap_or(&batch_id)
+            );
             Ok(())
         }
-        DataAction::Import { file } => {
-            info!("Importing data from file: {}", file);
-            // TODO: Implement data import
+        BatchAction::Deactivate => {
+            let response: serde_json::Value = client
+                .send(client.post("/api/batches/deactivate"))
+                .await?
+                .json()
+                .await?;
+            api_data(&response, "Failed to deactivate batch")?;
+            println!("Captures no longer go into a batch");
+            Ok(())
+        }
+        BatchAction::Exclude { batch, include } => {
+            let batch_id = find_batch(client, &batch).await?;
+            let request = client
+                .post(&format!("/api/batches/{batch_id}/training"))
+                .json(&serde_json::json!({ "exclude_from_training": !include }));
+            let response: serde_json::Value = client.send(request).await?.json().await?;
+            let exclusion = api_data(&response, "Failed to update batch")?;
+            println!(
+                "Batch {} is {} training, {} shells changed",
+                exclusion["batch"]["name"].as_str().unwrap_or(&batch_id),
+                if include { "back in" } else { "excluded from" },
+                exclusion["shells_changed"].as_u64().unwrap_or(0)
+            );
             Ok(())
         }
     }
 }
 
-async fn handle_ml_command(action: MlAction, _settings: &Settings) -> OurResult<()> {
+/// The ID of the batch with this ID or name, ignoring case
+async fn find_batch(client: &ApiClient, batch: &str) -> OurResult<String> {
+    let response: serde_json::Value = client
+        .send(client.get("/api/batches"))
+        .await?
+        .json()
+        .await?;
+    let batches = api_data(&response, "Failed to list batches")?["batches"]
+        .as_array()
+        .cloned()
+        .unwrap_or_default();
+    batches
+        .iter()
+        .find(|candidate| {
+            candidate["id"].as_str() == Some(batch)
+                || candidate["name"]
+                    .as_str()
+                    .is_some_and(|name| name.eq_ignore_ascii_case(batch.trim()))
+        })
+        .and_then(|found| found["id"].as_str())
+        .map(str::to_string)
+        .ok_or_else(|| OurError::App(format!("No batch has the ID or name {batch}")))
+}
+
+/// Print a usage report as totals, then case types and sessions largest first
+fn print_data_usage(report: &UsageReport) {
+    println!(
+        "Total {} in {} files (images {}, data {}, unattributed {})",
+        format_bytes(report.total_bytes),
+        report.files,
+        format_bytes(report.image_bytes),
+        format_bytes(report.data_bytes),
+        format_bytes(report.unattributed_bytes)
+    );
+    println!(
+        "Scanned {} in {}ms",
+        format_age(report.scanned_at, chrono::Utc::now()),
+        report.scan_duration_ms
+    );
+
+    if !report.case_types.is_empty() {
+        let rows: Vec<Vec<String>> = report
+            .case_types
+            .iter()
+            .map(|case_type| {
+                vec![
+                    case_type.case_type.clone(),
+                    case_type.sessions.to_string(),
+                    format_bytes(case_type.bytes),
+                ]
+            })
+            .collect();
+        println!();
+        println!(
+            "{}",
+            render_table(&["CASE TYPE", "SESSIONS", "SIZE"], &rows)
+        );
+    }
+
+    if !report.sessions.is_empty() {
+        let rows: Vec<Vec<String>> = report
+            .sessions
+            .iter()
+            .map(|session| {
+                vec![
+                    session.session_id.clone(),
+                    session.case_type.clone().unwrap_or_default(),
+                    session.files.to_string(),
+                    format_bytes(session.bytes),
+                ]
+            })
+            .collect();
+        println!();
+        println!(
+            "{}",
+            render_table(&["SESSION", "CASE TYPE", "FILES", "SIZE"], &rows)
+        );
+    }
+
+    for error in &report.errors {
+        eprintln!("Skipped {}: {}", error.path.display(), error.reason);
+    }
+}
+
+/// How often `ml train` checks on its job
+const TRAINING_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+async fn handle_ml_command(
+    action: MlAction,
+    settings: &Settings,
+    client: &ApiClient,
+) -> OurResult<()> {
     match action {
-        MlAction::ListTypes => {
-            info!("Case types:");
-            // TODO: Implement case type listing
+        MlAction::ListTypes { json } => {
+            let fetched = client.get_cached("/api/case-types", &[]).await?;
+            print_cache_notice(&fetched);
+            let mut case_types = api_data(&fetched.body, "Failed to list case types")?
+                .as_array()
+                .cloned()
+                .unwrap_or_default();
+            case_types.sort_by(|a, b| a["name"].as_str().cmp(&b["name"].as_str()));
+
+            if json {
+                println!("{}", serde_json::to_string_pretty(&case_types)?);
+                return Ok(());
+            }
+            if case_types.is_empty() {
+                println!("No case types found");
+                return Ok(());
+            }
+
+            let rows: Vec<Vec<String>> = case_types
+                .iter()
+                .map(|case_type| {
+                    let text = |name: &str| case_type[name].as_str().unwrap_or("").to_string();
+                    let count = |name: &str| case_type[name].as_u64().unwrap_or(0).to_string();
+                    vec![
+                        text("name"),
+                        text("designation"),
+                        text("brand"),
+                        count("reference_count"),
+                        count("training_count"),
+                        count("shell_count"),
+                        match case_type["target"].as_object() {
+                            Some(target) => format!(
+                                "{}/{} ({:.1}%)",
+                                target["current"],
+                                target["target"],
+                                target["percent"].as_f64().unwrap_or(0.0)
+                            ),
+                            None => "-".to_string(),
+                        },
+                        if case_type["ready_for_training"].as_bool() == Some(true) {
+                            "yes".to_string()
+                        } else {
+                            "no".to_string()
+                        },
+                    ]
+                })
+                .collect();
+            println!(
+                "{}",
+                render_table(
+                    &[
+                        "NAME",
+                        "DESIGNATION",
+                        "BRAND",
+                        "REFERENCE",
+                        "TRAINING",
+                        "SHELLS",
+                        "TARGET",
+                        "READY"
+                    ],
+                    &rows
+                )
+            );
             Ok(())
         }
-        MlAction::AddType { name, designation } => {
-            info!(
-                "Adding case type: {} (designation: {:?})",
-                name, designation
-            );
-            // TODO: Implement case type addition
+        MlAction::AddType {
+            name,
+            designation,
+            brand,
+            force,
+            json,
+        } => {
+            SafeName::new("name", &name)?;
+            if !force
+                && !settings
+                    .supported_case_types
+                    .iter()
+                    .any(|supported| supported.eq_ignore_ascii_case(&designation))
+            {
+                return Err(OurError::App(format!(
+                    "Unknown designation '{designation}', expected one of: {} (use --force to add it anyway)",
+                    settings.supported_case_types.join(", ")
+                )));
+            }
+
+            let request = client.post("/api/case-types").json(&serde_json::json!({
+                "name": name,
+                "designation": designation,
+                "brand": brand,
+            }));
+            let response: serde_json::Value = client.send(request).await?.json().await?;
+            let case_type = api_data(&response, "Failed to add case type")?;
+
+            if json {
+                println!("{}", serde_json::to_string_pretty(case_type)?);
+            } else {
+                println!("Added case type {name} ({designation})");
+            }
             Ok(())
         }
         MlAction::GenerateComposites => {
             info!("Generating composite images...");
-            debug!("Starting composite generation process");
-            // TODO: Implement composite generation
+            let mut ml_trainer = MLTrainer::new(settings.clone());
+            ml_trainer.initialize()?;
+            let report = ml_trainer.generate_all_composites()?;
+
+            println!("Generated: {}", report.generated.len());
+            println!("Up to date: {}", report.up_to_date.len());
+            if !report.failed.is_empty() {
+                println!("Failed: {}", report.failed.join(", "));
+            }
             Ok(())
         }
-        MlAction::Train { types } => {
-            info!("Training model...");
+        MlAction::ReferenceSheet { output, columns } => {
+            let format = match output.extension().and_then(|extension| extension.to_str()) {
+                Some(extension) if extension.eq_ignore_ascii_case("pdf") => "pdf",
+                _ => "jpg",
+            };
+            let mut request = client
+                .get("/api/ml/reference-sheet")
+                .query(&[("format", format)]);
+            if let Some(columns) = columns {
+                request = request.query(&[("columns", columns)]);
+            }
+            let response = client.send(request).await?;
+            if !response.status().is_success() {
+                let status = response.status();
+                let body: serde_json::Value = response.json().await.unwrap_or_default();
+                api_data(&body, "Failed to get the reference sheet")?;
+                return Err(OurError::App(format!(
+                    "Failed to get the reference sheet: {status}"
+                )));
+            }
+            let sheet = response.bytes().await?;
+            tokio::fs::write(&output, &sheet)
+                .await
+                .map_err(|e| OurError::App(format!("Failed to write {}: {e}", output.display())))?;
+            println!("Wrote the reference sheet to {}", output.display());
+            Ok(())
+        }
+        MlAction::Train { types, wait, json } => {
             debug!("Training for types: {:?}", types);
-            // TODO: Implement model training
+            if let Some(types) = &types {
+                // Unknown names would be skipped by the server, so refuse them before starting
+                let response: serde_json::Value = client
+                    .send(client.get("/api/case-types"))
+                    .await?
+                    .json()
+                    .await?;
+                let known: Vec<&str> = api_data(&response, "Failed to list case types")?
+                    .as_array()
+                    .map(|case_types| {
+                        case_types
+                            .iter()
+                            .filter_map(|case_type| case_type["name"].as_str())
+                            .collect()
+                    })
+                    .unwrap_or_default();
+                let unknown: Vec<&str> = types
+                    .iter()
+                    .map(String::as_str)
+                    .filter(|name| !known.contains(name))
+                    .collect();
+                if !unknown.is_empty() {
+                    return Err(OurError::App(format!(
+                        "Unknown case types: {} (known: {})",
+                        unknown.join(", "),
+                        known.join(", ")
+                    )));
+                }
+            }
+
+            let request = client
+                .post("/api/training-jobs")
+                .json(&serde_json::json!({ "case_types": types }));
+            let response: serde_json::Value = client.send(request).await?.json().await?;
+            let mut job: TrainingJob =
+                serde_json::from_value(api_data(&response, "Failed to start training")?.clone())?;
+            if !wait {
+                println!("{}", job.id);
+                return Ok(());
+            }
+
+            let progress = ProgressBar::new(100);
+            progress.set_style(
+                ProgressStyle::with_template("{bar:40} {pos:>3}% {msg}")
+                    .unwrap_or_else(|_| ProgressStyle::default_bar()),
+            );
+            while !job.phase.is_finished() {
+                progress.set_position(job.percent.into());
+                progress.set_message(job.phase.as_str());
+                tokio::time::sleep(TRAINING_POLL_INTERVAL).await;
+                let response: serde_json::Value = client
+                    .send(client.get(&format!("/api/training-jobs/{}", job.id)))
+                    .await?
+                    .json()
+                    .await?;
+                job = serde_json::from_value(
+                    api_data(&response, "Failed to get training job")?.clone(),
+                )?;
+            }
+            progress.finish_and_clear();
+
+            let Some(model) = job.model else {
+                return Err(OurError::App(format!(
+                    "Training job {} failed: {}",
+                    job.id,
+                    job.error.as_deref().unwrap_or("no reason given")
+                )));
+            };
+            if json {
+                println!("{}", serde_json::to_string_pretty(&model)?);
+                return Ok(());
+            }
+            println!(
+                "Trained {} on {} shells and {} images (dataset {})",
+                model.name,
+                model.shell_count,
+                model.image_count,
+                model.dataset_hash.as_deref().unwrap_or("unknown")
+            );
+            println!("Validation accuracy: {:.1}%", model.accuracy * 100.0);
+            for (case_type, count) in &model.class_counts {
+                println!("  {case_type}: {count} shells");
+            }
+            if !job.worst_confused.is_empty() {
+                println!("Most confused:");
+                for pair in &job.worst_confused {
+                    println!(
+                        "  {} taken for {}: {} shells ({:.1}%)",
+                        pair.actual,
+                        pair.predicted,
+                        pair.count,
+                        pair.rate * 100.0
+                    );
+                }
+            }
+            if let Some(path) = &job.metadata_path {
+                println!("Metadata: {}", path.display());
+            }
+            Ok(())
+        }
+    }
+}
+
+/// Tell the user, on stderr so JSON output stays clean, when a response came from the cache
+fn print_cache_notice(fetched: &Fetched) {
+    if let Some(notice) = fetched.cache_notice(chrono::Utc::now()) {
+        eprintln!("{notice}");
+    }
+}
+
+/// Print a self-test report as a checklist
+/// Print steps as a checklist with their timings
+fn print_steps(steps: &[SelfTestStep]) {
+    for step in steps {
+        let mark = match step.outcome {
+            StepOutcome::Passed => "[x]",
+            StepOutcome::Failed => "[ ]",
+            StepOutcome::Skipped => "[-]",
+        };
+        match &step.detail {
+            Some(detail) => println!("{mark} {} ({}ms): {detail}", step.name, step.duration_ms),
+            None => println!("{mark} {} ({}ms)", step.name, step.duration_ms),
+        }
+    }
+}
+
+fn print_self_test(report: &SelfTestReport) {
+    print_steps(&report.steps);
+    println!();
+    match (&report.aborted_at, report.passed) {
+        (Some(step), _) => {
+            println!("FAILED: aborted at {step:?}, actuators returned to safe positions")
+        }
+        (None, true) => println!("PASSED in {}ms", report.duration_ms),
+        (None, false) => println!("FAILED in {}ms", report.duration_ms),
+    }
+}
+
+/// Print shells as a table fitted to the terminal, cutting the session ID first
+fn print_shells(shells: &[serde_json::Value]) {
+    if shells.is_empty() {
+        println!("No shells found");
+        return;
+    }
+
+    let now = chrono::Utc::now();
+    let yes_no = |value: &serde_json::Value| {
+        if value.as_bool() == Some(true) {
+            "yes".to_string()
+        } else {
+            "no".to_string()
+        }
+    };
+    let rows: Vec<Vec<String>> = shells
+        .iter()
+        .map(|shell| {
+            let field = |name: &str| shell[name].as_str().unwrap_or("").to_string();
+            let captured = field("date_captured")
+                .parse::<chrono::DateTime<chrono::Utc>>()
+                .map(|captured| format_age(captured, now))
+                .unwrap_or_default();
+            vec![
+                field("session_id").chars().take(8).collect(),
+                field("brand"),
+                field("shell_type"),
+                captured,
+                shell["image_count"].as_u64().unwrap_or(0).to_string(),
+                yes_no(&shell["include"]),
+                yes_no(&shell["has_complete_regions"]),
+            ]
+        })
+        .collect();
+    let headers = [
+        "SESSION", "BRAND", "TYPE", "CAPTURED", "IMAGES", "INCLUDE", "REGIONS",
+    ];
+    let table = match terminal_width() {
+        Some(width) => render_table_within(&headers, &rows, width, &[0, 1]),
+        None => render_table(&headers, &rows),
+    };
+    println!("{table}");
+}
+
+/// The `data` of a successful API response, or the server's error with its code
+/// Tag a session's shell from the terminal, or from the flags alone when they give every tag
+async fn tag_session(
+    client: &ApiClient,
+    session_id: &str,
+    preset: PresetTags,
+    open_images: bool,
+) -> OurResult<()> {
+    let session_id = SafeName::new("session_id", session_id)?;
+    let response: serde_json::Value = client
+        .send(client.get(&format!("/api/shells/{session_id}")))
+        .await?
+        .json()
+        .await?;
+    let shell: Shell = serde_json::from_value(
+        api_data(&response, &format!("Failed to load session {session_id}"))?.clone(),
+    )?;
+
+    let image_directory = std::env::temp_dir().join(format!("shell-sorter-{session_id}"));
+    if open_images {
+        tokio::fs::create_dir_all(&image_directory).await?;
+    }
+    let mut images = SessionImage::of_shell(&shell);
+    let mut saved_images = Vec::new();
+    println!("Session {session_id}: {} images", images.len());
+    for image in &mut images {
+        match fetch_image(client, &image.filename).await {
+            Ok(bytes) => {
+                image.size = FrameSize::of_image(&bytes).ok();
+                if open_images {
+                    let filename = SafeName::file_name("filename", &image.filename)?;
+                    let path = image_directory.join(&filename);
+                    tokio::fs::write(&path, &bytes).await?;
+                    saved_images.push(path);
+                }
+            }
+            Err(e) => debug!("Failed to fetch image {}: {e}", image.filename),
+        }
+        println!("  {image}");
+    }
+    if !saved_images.is_empty() {
+        println!("Images saved for viewing:");
+        for path in &saved_images {
+            println!("  {}", path.display());
+        }
+    }
+
+    let response: serde_json::Value = client
+        .send(client.get("/api/case-designations"))
+        .await?
+        .json()
+        .await?;
+    let known: Vec<CaseDesignation> =
+        serde_json::from_value(api_data(&response, "Failed to list case types")?.clone())?;
+    let designations = CaseDesignations::new(known.iter().map(|known| &known.designation));
+    let scripted = preset.is_complete();
+    let brands: Vec<String> = if scripted {
+        Vec::new()
+    } else {
+        let response: serde_json::Value = client
+            .send(client.get("/api/shells/brands"))
+            .await?
+            .json()
+            .await?;
+        serde_json::from_value(api_data(&response, "Failed to list brands")?.clone())?
+    };
+
+    let mut prompter = TerminalPrompter;
+    let tags = prompt_tags(&mut prompter, &shell, &brands, &designations, preset)?
+        .checked(&designations)?;
+    if !scripted && !confirm(&mut prompter, &format!("Save {tags}?"), true)? {
+        println!("Not saved");
+        return Ok(());
+    }
+
+    let mut update = serde_json::to_value(&tags)?;
+    update["revision"] = shell.revision.into();
+    let response: serde_json::Value = client
+        .send(
+            client
+                .patch(&format!("/api/shells/{session_id}"))
+                .json(&update),
+        )
+        .await?
+        .json()
+        .await?;
+    let saved = api_data(&response, "Failed to save tags")?;
+    println!(
+        "Saved {session_id} at revision {}: {tags}",
+        saved["revision"]
+    );
+    Ok(())
+}
+
+/// The bytes of a captured image
+async fn fetch_image(client: &ApiClient, filename: &str) -> OurResult<Vec<u8>> {
+    let response = client
+        .send(client.get(&format!("/images/{filename}")))
+        .await?;
+    if !response.status().is_success() {
+        return Err(OurError::App(format!(
+            "Failed to fetch {filename}: {}",
+            response.status()
+        )));
+    }
+    Ok(response.bytes().await?.to_vec())
+}
+
+fn api_data<'a>(
+    response: &'a serde_json::Value,
+    context: &str,
+) -> OurResult<&'a serde_json::Value> {
+    if response["success"].as_bool() == Some(true) {
+        return Ok(&response["data"]);
+    }
+    let message = match ApiError::from_response(response) {
+        Some(error) => error.to_string(),
+        // Servers from before error codes only send the message
+        None => response["message"]
+            .as_str()
+            .unwrap_or("unexpected response")
+            .to_string(),
+    };
+    Err(OurError::App(format!("{context}: {message}")))
+}
+
+#[cfg(feature = "fixtures")]
+fn handle_dev_command(action: DevAction) -> OurResult<()> {
+    use shell_sorter::fixtures::FixtureBuilder;
+
+    match action {
+        DevAction::GenerateFixtures {
+            shells,
+            out,
+            seed,
+            case_types,
+            width,
+            height,
+            with_case_types,
+        } => {
+            let case_types = case_types
+                .iter()
+                .map(|case_type| {
+                    case_type.split_once(':').ok_or_else(|| {
+                        OurError::Config(format!(
+                            "Invalid case type {case_type:?}, expected BRAND:DESIGNATION"
+                        ))
+                    })
+                })
+                .collect::<OurResult<Vec<_>>>()?;
+            let mut builder = FixtureBuilder::new(seed)
+                .shells(shells)
+                .image_size(width, height);
+            if !case_types.is_empty() {
+                builder = builder.case_types(case_types);
+            }
+            if with_case_types {
+                builder = builder.with_case_types_file();
+            }
+            let fixtures = builder.write(&out.join("data"), &out.join("images"))?;
+            println!(
+                "Wrote {} shells and {} images to {}",
+                fixtures.shells.len(),
+                fixtures.images.len(),
+                out.display()
+            );
+            println!(
+                "Run `shell-sorter serve` from {} to browse them",
+                out.display()
+            );
             Ok(())
         }
     }
@@ -653,6 +2006,42 @@ async fn handle_config_command(action: ConfigAction, settings: &Settings) -> Our
             println!("  Camera count: {}", settings.camera_count);
             println!("  ML enabled: {}", settings.ml_enabled);
             println!("  Confidence threshold: {}", settings.confidence_threshold);
+            if settings.cors_allowed_origins.is_empty() {
+                println!("  CORS: disabled");
+            } else {
+                println!(
+                    "  CORS: enabled for {}",
+                    settings.cors_allowed_origins.join(", ")
+                );
+            }
+            println!();
+            println!("Directories:");
+            for (setting, path) in DataDirectories::from_settings(settings).named() {
+                println!("  {setting}: {}", path.display());
+            }
+            println!();
+            println!("Sources:");
+            match &settings.sources.project_file {
+                Some(path) => println!("  Project config: {} (loaded)", path.display()),
+                None => println!("  Project config: none found"),
+            }
+            println!(
+                "  User config: {} ({})",
+                settings.sources.user_config_path.display(),
+                if settings.sources.user_config_loaded {
+                    "loaded"
+                } else {
+                    "not found"
+                }
+            );
+            if settings.sources.env_overrides.is_empty() {
+                println!("  Environment overrides: none");
+            } else {
+                println!("  Environment overrides:");
+                for name in &settings.sources.env_overrides {
+                    println!("    {name}");
+                }
+            }
             Ok(())
         }
         ConfigAction::Set { key: _, value: _ } => {
@@ -665,33 +2054,101 @@ async fn handle_config_command(action: ConfigAction, settings: &Settings) -> Our
             // TODO: Implement config reset
             Ok(())
         }
+        ConfigAction::ImportCameras { file } => {
+            let document = std::fs::read_to_string(&file)
+                .map_err(|e| OurError::App(format!("Failed to read {}: {e}", file.display())))?;
+            let entries = camera_inventory::parse_inventory(&document)?;
+
+            let mut user_config = settings.load_user_config();
+            let report = camera_inventory::merge_inventory(&mut user_config, &entries);
+            settings
+                .save_user_config(&user_config)
+                .map_err(|e| OurError::App(format!("Failed to save configuration: {e}")))?;
+
+            println!("Added: {}", report.added.join(", "));
+            println!("Updated: {}", report.updated.join(", "));
+            println!("Unchanged: {}", report.unchanged.join(", "));
+            Ok(())
+        }
+    }
+}
+
+/// Point out data trees left in the usual places by runs that used a different directory
+fn warn_about_other_data_trees(settings: &Settings) {
+    let mut locations: Vec<PathBuf> = [std::env::current_dir().ok(), dirs::home_dir()]
+        .into_iter()
+        .flatten()
+        .collect();
+    locations.extend(
+        [
+            settings.sources.project_file.as_deref(),
+            Some(settings.sources.user_config_path.as_path()),
+        ]
+        .into_iter()
+        .flatten()
+        .filter_map(|config_file| config_file.parent().map(PathBuf::from)),
+    );
+    let others = data_directories::other_data_trees(&settings.data_directory, &locations);
+    if !others.is_empty() {
+        warn!(
+            "Using data directory {}, but found other shell-sorter data in {}; move anything you need into the data directory",
+            settings.data_directory.display(),
+            others
+                .iter()
+                .map(|path| path.display().to_string())
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
     }
 }
 
-async fn start_web_server(host: String, port: NonZeroU16, settings: Settings) -> OurResult<()> {
+async fn start_web_server(
+    host: String,
+    port: NonZeroU16,
+    settings: Settings,
+    logs: LogBuffer,
+) -> OurResult<()> {
+    info!(
+        "{}",
+        BuildInfo::current().startup_banner(&settings, &format!("{host}:{port}"))
+    );
+    warn_about_other_data_trees(&settings);
+
+    // Shared recorder for the diagnostics event log
+    let events = EventRecorder::default();
+
+    // Every long-lived component is tracked here so shutdown can stop them all
+    let tasks = TaskRegistry::default();
+
     // Create the controller monitor and get a handle for communication
-    let (controller_monitor, controller_handle) = ControllerMonitor::new(settings.clone())
-        .map_err(|e| OurError::App(format!("Failed to create controller monitor: {e}")))?;
+    let (controller_monitor, controller_handle) =
+        ControllerMonitor::new(settings.clone(), events.clone())
+            .map_err(|e| OurError::App(format!("Failed to create controller monitor: {e}")))?;
 
     // Create the camera manager and get a handle for communication
     let (camera_manager, camera_handle) =
-        CameraManager::new(settings.network_camera_hostnames.clone())
+        CameraManager::new(settings.network_camera_hostnames.clone(), events.clone())
             .map_err(|e| OurError::App(format!("Failed to create camera manager: {e}")))?;
 
     // Create the USB camera manager and get a handle for communication
-    let usb_camera_handle = start_usb_camera_manager()
-        .await
-        .map_err(|e| OurError::App(format!("Failed to create USB camera manager: {e}")))?;
+    let usb_camera_handle = start_usb_camera_manager(
+        events.clone(),
+        CaptureWarmup::from_settings(&settings),
+        PreCapture::from_settings(&settings),
+        &tasks,
+    )
+    .await
+    .map_err(|e| OurError::App(format!("Failed to create USB camera manager: {e}")))?;
 
     // Spawn the controller monitor in a separate task
-    tokio::spawn(async move {
+    tasks.spawn_tracked("controller_monitor", async move {
         if let Err(e) = controller_monitor.run().await {
             tracing::error!("Controller monitor error: {e}");
         }
     });
 
     // Spawn the camera manager in a separate task
-    tokio::spawn(async move {
+    tasks.spawn_tracked("camera_manager", async move {
         if let Err(e) = camera_manager.run().await {
             tracing::error!("Camera manager error: {e}");
         }
@@ -702,9 +2159,14 @@ async fn start_web_server(host: String, port: NonZeroU16, settings: Settings) ->
         host,
         port,
         settings,
-        controller_handle,
-        camera_handle,
-        usb_camera_handle,
+        ServerComponents {
+            controller: controller_handle,
+            camera_manager: camera_handle,
+            usb_camera_manager: usb_camera_handle,
+            events,
+            logs,
+            tasks,
+        },
     )
     .await
 }
@@ -0,0 +1,398 @@
+//! Weights and lengths of shells, and how they compare within a case type.
+//!
+//! A shell can carry a weight in grains and a length in millimetres, typed in
+//! while tagging or pushed by a scale to `POST /api/shells/{id}/measurements`.
+//! Cases of one designation weigh much the same, so a reading outside the
+//! designation's [`MeasurementRange`] warns: a "9mm" at 48 grains is more
+//! likely a 380 ACP. Ranges come from `measurement_ranges`, which starts with
+//! [`default_measurement_ranges`] for the built-in case types.
+//!
+//! [`measurement_report`] gives the mean and spread of each case type's
+//! measurements and lists the shells far from the rest of their type, which
+//! catches strays the ranges are too loose to.
+
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::shell_data::ShellSummary;
+use crate::{OurError, OurResult};
+
+/// Shells this many standard deviations from their case type's mean are outliers
+pub const OUTLIER_DEVIATIONS: f64 = 2.0;
+
+/// Lowest and highest acceptable value of a measurement
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+pub struct Bounds {
+    pub min: f32,
+    pub max: f32,
+}
+
+impl Bounds {
+    const fn new(min: f32, max: f32) -> Self {
+        Self { min, max }
+    }
+
+    fn contains(&self, value: f32) -> bool {
+        (self.min..=self.max).contains(&value)
+    }
+}
+
+/// What a case of one designation should measure; unset bounds aren't checked
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+pub struct MeasurementRange {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub weight_grains: Option<Bounds>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub length_mm: Option<Bounds>,
+}
+
+/// Fired brass of the built-in case types, wide enough for every common maker
+const DEFAULT_RANGES: &[(&str, Bounds, Bounds)] = &[
+    ("9mm", Bounds::new(55.0, 66.0), Bounds::new(18.6, 19.3)),
+    ("40sw", Bounds::new(68.0, 82.0), Bounds::new(21.2, 21.8)),
+    ("45acp", Bounds::new(85.0, 105.0), Bounds::new(22.4, 23.0)),
+    ("223rem", Bounds::new(88.0, 105.0), Bounds::new(44.0, 45.0)),
+    ("308win", Bounds::new(150.0, 180.0), Bounds::new(50.8, 51.4)),
+    (
+        "3006spr",
+        Bounds::new(185.0, 210.0),
+        Bounds::new(62.8, 63.5),
+    ),
+    (
+        "38special",
+        Bounds::new(65.0, 82.0),
+        Bounds::new(28.9, 29.4),
+    ),
+    ("357mag", Bounds::new(75.0, 95.0), Bounds::new(32.5, 33.0)),
+];
+
+/// Ranges for the case types in the default `supported_case_types`
+pub fn default_measurement_ranges() -> BTreeMap<String, MeasurementRange> {
+    DEFAULT_RANGES
+        .iter()
+        .map(|(designation, weight_grains, length_mm)| {
+            (
+                designation.to_string(),
+                MeasurementRange {
+                    weight_grains: Some(*weight_grains),
+                    length_mm: Some(*length_mm),
+                },
+            )
+        })
+        .collect()
+}
+
+/// Check every range has its bounds the right way round
+pub fn validate_ranges(ranges: &BTreeMap<String, MeasurementRange>) -> OurResult<()> {
+    for (designation, range) in ranges {
+        for (measurement, bounds) in [
+            ("weight_grains", range.weight_grains),
+            ("length_mm", range.length_mm),
+        ] {
+            if let Some(Bounds { min, max }) = bounds
+                && !(min.is_finite() && max.is_finite() && 0.0 <= min && min <= max)
+            {
+                return Err(OurError::Config(format!(
+                    "Invalid measurement range for {designation}: {measurement} must have 0 <= min <= max, got {min} to {max}"
+                )));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// The problem with a weight or length reading, if there is one
+pub fn measurement_error(value: f32) -> Option<String> {
+    (!(value.is_finite() && value > 0.0)).then(|| "must be a positive number".to_string())
+}
+
+/// The range for `designation`, ignoring case
+pub fn range_for<'a>(
+    ranges: &'a BTreeMap<String, MeasurementRange>,
+    designation: &str,
+) -> Option<&'a MeasurementRange> {
+    ranges
+        .iter()
+        .find(|(known, _)| known.eq_ignore_ascii_case(designation.trim()))
+        .map(|(_, range)| range)
+}
+
+/// A measurement outside its designation's range
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub struct MeasurementWarning {
+    /// `weight_grains` or `length_mm`
+    pub measurement: &'static str,
+    pub value: f32,
+    pub expected: Bounds,
+    pub message: String,
+}
+
+/// Warnings for a shell of `designation` weighing `weight_grains` and `length_mm` long
+///
+/// Designations without a range, and measurements not taken, never warn.
+pub fn check_measurements(
+    ranges: &BTreeMap<String, MeasurementRange>,
+    designation: &str,
+    weight_grains: Option<f32>,
+    length_mm: Option<f32>,
+) -> Vec<MeasurementWarning> {
+    let Some(range) = range_for(ranges, designation) else {
+        return Vec::new();
+    };
+    [
+        ("weight_grains", "grains", weight_grains, range.weight_grains),
+        ("length_mm", "mm", length_mm, range.length_mm),
+    ]
+    .into_iter()
+    .filter_map(|(measurement, unit, value, bounds)| {
+        let (value, bounds) = (value?, bounds?);
+        (!bounds.contains(value)).then(|| MeasurementWarning {
+            measurement,
+            value,
+            expected: bounds,
+            message: format!(
+                "A {designation} case is expected to be {} to {} {unit}, this one is {value} {unit}",
+                bounds.min, bounds.max
+            ),
+        })
+    })
+    .collect()
+}
+
+/// The spread of one measurement across a case type
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub struct MeasurementStats {
+    pub count: usize,
+    pub mean: f64,
+    /// Population standard deviation
+    pub stddev: f64,
+    pub min: f64,
+    pub max: f64,
+}
+
+impl MeasurementStats {
+    /// Stats of `values`, `None` when there are none
+    pub fn new(values: &[f32]) -> Option<Self> {
+        if values.is_empty() {
+            return None;
+        }
+        let count = values.len();
+        let values = values.iter().map(|value| f64::from(*value));
+        let mean = values.clone().sum::<f64>() / count as f64;
+        let variance = values
+            .clone()
+            .map(|value| (value - mean).powi(2))
+            .sum::<f64>()
+            / count as f64;
+        // Readings are f32, so round off the noise widening them adds
+        let round = |value: f64| (value * 1000.0).round() / 1000.0;
+        Some(Self {
+            count,
+            mean: round(mean),
+            stddev: round(variance.sqrt()),
+            min: round(values.clone().fold(f64::INFINITY, f64::min)),
+            max: round(values.fold(f64::NEG_INFINITY, f64::max)),
+        })
+    }
+
+    /// How many standard deviations `value` is from the mean, `None` when every value is the same
+    pub fn deviations(&self, value: f32) -> Option<f64> {
+        (self.stddev > 0.0).then(|| (f64::from(value) - self.mean) / self.stddev)
+    }
+}
+
+/// A shell whose measurement stands out from the rest of its case type
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub struct MeasurementOutlier {
+    pub session_id: String,
+    pub measurement: &'static str,
+    pub value: f32,
+    /// Standard deviations from the case type's mean, negative when below it
+    pub deviations: f64,
+}
+
+/// The measurements of one case type
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub struct CaseTypeMeasurements {
+    /// `brand_shell_type`, as case types are named
+    pub case_type: String,
+    pub designation: String,
+    /// Shells of the type, measured or not
+    pub shells: usize,
+    pub weight_grains: Option<MeasurementStats>,
+    pub length_mm: Option<MeasurementStats>,
+    pub outliers: Vec<MeasurementOutlier>,
+    /// Shells measuring outside the designation's range
+    pub out_of_range: Vec<String>,
+}
+
+/// Measurements of every case type with a tagged shell, in case type order
+pub fn measurement_report(
+    summaries: &[ShellSummary],
+    ranges: &BTreeMap<String, MeasurementRange>,
+) -> Vec<CaseTypeMeasurements> {
+    let mut case_types: BTreeMap<String, Vec<&ShellSummary>> = BTreeMap::new();
+    let tagged = summaries
+        .iter()
+        .filter(|summary| !summary.brand.is_empty() && !summary.shell_type.is_empty());
+    for summary in tagged {
+        case_types
+            .entry(format!("{}_{}", summary.brand, summary.shell_type))
+            .or_default()
+            .push(summary);
+    }
+
+    case_types
+        .into_iter()
+        .map(|(case_type, shells)| {
+            let designation = shells[0].shell_type.clone();
+            let weights: Vec<(&str, f32)> = shells
+                .iter()
+                .filter_map(|shell| Some((shell.session_id.as_str(), shell.weight_grains?)))
+                .collect();
+            let lengths: Vec<(&str, f32)> = shells
+                .iter()
+                .filter_map(|shell| Some((shell.session_id.as_str(), shell.length_mm?)))
+                .collect();
+            let weight_grains = stats(&weights);
+            let length_mm = stats(&lengths);
+
+            let mut outliers = outliers_of("weight_grains", &weights, weight_grains.as_ref());
+            outliers.extend(outliers_of("length_mm", &lengths, length_mm.as_ref()));
+            let out_of_range = shells
+                .iter()
+                .filter(|shell| {
+                    !check_measurements(ranges, &designation, shell.weight_grains, shell.length_mm)
+                        .is_empty()
+                })
+                .map(|shell| shell.session_id.clone())
+                .collect();
+            CaseTypeMeasurements {
+                case_type,
+                designation,
+                shells: shells.len(),
+                weight_grains,
+                length_mm,
+                outliers,
+                out_of_range,
+            }
+        })
+        .collect()
+}
+
+fn stats(measured: &[(&str, f32)]) -> Option<MeasurementStats> {
+    let values: Vec<f32> = measured.iter().map(|(_, value)| *value).collect();
+    MeasurementStats::new(&values)
+}
+
+/// The shells in `measured` at least [`OUTLIER_DEVIATIONS`] from the mean
+///
+/// No shell can be that far out until a case type has five measured.
+fn outliers_of(
+    measurement: &'static str,
+    measured: &[(&str, f32)],
+    stats: Option<&MeasurementStats>,
+) -> Vec<MeasurementOutlier> {
+    let Some(stats) = stats else {
+        return Vec::new();
+    };
+    measured
+        .iter()
+        .filter_map(|(session_id, value)| {
+            let deviations = stats.deviations(*value)?;
+            (deviations.abs() >= OUTLIER_DEVIATIONS).then(|| MeasurementOutlier {
+                session_id: session_id.to_string(),
+                measurement,
+                value: *value,
+                deviations: (deviations * 100.0).round() / 100.0,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shell_data::Shell;
+
+    fn measured(session_id: &str, shell_type: &str, weight: Option<f32>) -> ShellSummary {
+        let mut shell = Shell::new("Winchester".to_string(), shell_type.to_string());
+        shell.weight_grains = weight;
+        ShellSummary::new(session_id, &shell)
+    }
+
+    #[test]
+    fn test_readings_outside_the_designations_range_warn() {
+        let ranges = default_measurement_ranges();
+        assert!(check_measurements(&ranges, "9mm", Some(61.5), Some(19.0)).is_empty());
+
+        let warnings = check_measurements(&ranges, "9MM", Some(180.0), Some(19.0));
+        assert_eq!(warnings.len(), 1, "{warnings:?}");
+        assert_eq!(warnings[0].measurement, "weight_grains");
+        assert_eq!(warnings[0].expected, Bounds::new(55.0, 66.0));
+        assert!(warnings[0].message.contains("180 grains"), "{warnings:?}");
+        // A 380 ACP is short and light for a 9mm
+        let warnings = check_measurements(&ranges, "9mm", Some(48.0), Some(17.3));
+        let measurements: Vec<&str> = warnings.iter().map(|w| w.measurement).collect();
+        assert_eq!(measurements, vec!["weight_grains", "length_mm"]);
+
+        assert!(check_measurements(&ranges, "380acp", Some(180.0), None).is_empty());
+        assert!(check_measurements(&ranges, "9mm", None, None).is_empty());
+
+        assert!(validate_ranges(&ranges).is_ok());
+        let backwards = BTreeMap::from([(
+            "9mm".to_string(),
+            MeasurementRange {
+                weight_grains: Some(Bounds::new(66.0, 55.0)),
+                length_mm: None,
+            },
+        )]);
+        assert!(validate_ranges(&backwards).is_err());
+    }
+
+    #[test]
+    fn test_report_gives_each_case_types_spread_and_outliers() {
+        let mut summaries: Vec<ShellSummary> = (0..8)
+            .map(|i| measured(&format!("nine-{i}"), "9mm", Some(60.0 + i as f32 % 2.0)))
+            .collect();
+        summaries.push(measured("stray", "9mm", Some(48.0)));
+        summaries.push(measured("unweighed", "9mm", None));
+        summaries.push(measured("forty", "40sw", Some(75.0)));
+        summaries.push(measured("untagged", "", Some(10.0)));
+
+        let report = measurement_report(&summaries, &default_measurement_ranges());
+        let case_types: Vec<&str> = report.iter().map(|m| m.case_type.as_str()).collect();
+        assert_eq!(case_types, vec!["Winchester_40sw", "Winchester_9mm"]);
+
+        let nine = &report[1];
+        assert_eq!(nine.designation, "9mm");
+        assert_eq!(nine.shells, 10);
+        let weight = nine.weight_grains.expect("9mm shells were weighed");
+        assert_eq!(weight.count, 9);
+        assert_eq!(weight.mean, 59.111);
+        assert_eq!((weight.min, weight.max), (48.0, 61.0));
+        assert!(weight.stddev > 3.0 && weight.stddev < 4.0, "{weight:?}");
+        assert_eq!(
+            nine.outliers
+                .iter()
+                .map(|outlier| (outlier.session_id.as_str(), outlier.measurement))
+                .collect::<Vec<_>>(),
+            vec![("stray", "weight_grains")]
+        );
+        assert!(nine.outliers[0].deviations < -2.0);
+        assert_eq!(nine.out_of_range, vec!["stray".to_string()]);
+
+        // One shell has no spread to stand out from
+        let forty = &report[0];
+        assert_eq!(forty.weight_grains.map(|stats| stats.stddev), Some(0.0));
+        assert!(forty.outliers.is_empty());
+        assert!(forty.out_of_range.is_empty());
+    }
+}
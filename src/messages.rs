@@ -0,0 +1,667 @@
+//! Catalogue of the messages API responses show to people, in English or a translation.
+//!
+//! Handlers describe what went wrong as a [`Message`]: a [`MessageKey`] with
+//! the values it mentions. [`Message::render`] fills in the template for the
+//! language of the request being answered, which the server picks from the
+//! request's `Accept-Language`, then `ui_language`, then English. English is
+//! compiled in; a translation is a `messages.<lang>.json` file in the data
+//! directory mapping keys to templates, loaded at startup over the English
+//! ones, so a partial translation falls back to English for the rest.
+//!
+//! Only the wording changes: an error's `code` stays the same in every
+//! language, and log messages stay in English.
+
+use std::collections::HashMap;
+use std::fs;
+use std::future::Future;
+use std::path::Path;
+use std::sync::Arc;
+
+use tracing::{info, warn};
+
+use crate::{OurError, OurResult};
+
+/// Language of the compiled-in templates
+pub const DEFAULT_LANGUAGE: &str = "en";
+
+tokio::task_local! {
+    static CATALOGUE: Arc<Catalogue>;
+}
+
+/// Every user-facing message, named by its key in the catalogue files
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MessageKey {
+    ShellNotFound,
+    ShellGone,
+    InvalidSessionId,
+    ShellLoadFailed,
+    ShellSaveFailed,
+    ShellListFailed,
+    ShellSearchFailed,
+    ShellBrandsFailed,
+    ShellRecordsCheckFailed,
+    ShellToggleFailed,
+    ShellDeleteFailed,
+    ShellNotInTrash,
+    ShellRestoreConflict,
+    ShellRestoreFailed,
+    TrashListFailed,
+    CameraNotFound,
+    CameraReadFailed,
+    CamerasStartFailed,
+    CamerasStopFailed,
+    CamerasOffline,
+    NoCamerasSelected,
+    NoCameraDeliveredImage,
+    NoMissingCameraDeliveredImage,
+    ImageDirectoryFailed,
+    CaptureSaveFailed,
+    CaptureStatsFailed,
+    CaptureStatsResetFailed,
+    StreamLimitReached,
+    SnapshotFailed,
+    EspSettingsUnsupported,
+    EspSettingsReadFailed,
+    EspSettingsUpdateFailed,
+    BrightnessUnsupported,
+    BrightnessReadFailed,
+    BrightnessSetFailed,
+    InvalidConfiguration,
+    ControllerConfigFailed,
+    ConfigSaveFailed,
+    ServerReadOnly,
+}
+
+impl MessageKey {
+    /// Every key, in catalogue order
+    pub const ALL: [MessageKey; 39] = [
+        MessageKey::ShellNotFound,
+        MessageKey::ShellGone,
+        MessageKey::InvalidSessionId,
+        MessageKey::ShellLoadFailed,
+        MessageKey::ShellSaveFailed,
+        MessageKey::ShellListFailed,
+        MessageKey::ShellSearchFailed,
+        MessageKey::ShellBrandsFailed,
+        MessageKey::ShellRecordsCheckFailed,
+        MessageKey::ShellToggleFailed,
+        MessageKey::ShellDeleteFailed,
+        MessageKey::ShellNotInTrash,
+        MessageKey::ShellRestoreConflict,
+        MessageKey::ShellRestoreFailed,
+        MessageKey::TrashListFailed,
+        MessageKey::CameraNotFound,
+        MessageKey::CameraReadFailed,
+        MessageKey::CamerasStartFailed,
+        MessageKey::CamerasStopFailed,
+        MessageKey::CamerasOffline,
+        MessageKey::NoCamerasSelected,
+        MessageKey::NoCameraDeliveredImage,
+        MessageKey::NoMissingCameraDeliveredImage,
+        MessageKey::ImageDirectoryFailed,
+        MessageKey::CaptureSaveFailed,
+        MessageKey::CaptureStatsFailed,
+        MessageKey::CaptureStatsResetFailed,
+        MessageKey::StreamLimitReached,
+        MessageKey::SnapshotFailed,
+        MessageKey::EspSettingsUnsupported,
+        MessageKey::EspSettingsReadFailed,
+        MessageKey::EspSettingsUpdateFailed,
+        MessageKey::BrightnessUnsupported,
+        MessageKey::BrightnessReadFailed,
+        MessageKey::BrightnessSetFailed,
+        MessageKey::InvalidConfiguration,
+        MessageKey::ControllerConfigFailed,
+        MessageKey::ConfigSaveFailed,
+        MessageKey::ServerReadOnly,
+    ];
+
+    /// Key used in catalogue files
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            MessageKey::ShellNotFound => "shell_not_found",
+            MessageKey::ShellGone => "shell_gone",
+            MessageKey::InvalidSessionId => "invalid_session_id",
+            MessageKey::ShellLoadFailed => "shell_load_failed",
+            MessageKey::ShellSaveFailed => "shell_save_failed",
+            MessageKey::ShellListFailed => "shell_list_failed",
+            MessageKey::ShellSearchFailed => "shell_search_failed",
+            MessageKey::ShellBrandsFailed => "shell_brands_failed",
+            MessageKey::ShellRecordsCheckFailed => "shell_records_check_failed",
+            MessageKey::ShellToggleFailed => "shell_toggle_failed",
+            MessageKey::ShellDeleteFailed => "shell_delete_failed",
+            MessageKey::ShellNotInTrash => "shell_not_in_trash",
+            MessageKey::ShellRestoreConflict => "shell_restore_conflict",
+            MessageKey::ShellRestoreFailed => "shell_restore_failed",
+            MessageKey::TrashListFailed => "trash_list_failed",
+            MessageKey::CameraNotFound => "camera_not_found",
+            MessageKey::CameraReadFailed => "camera_read_failed",
+            MessageKey::CamerasStartFailed => "cameras_start_failed",
+            MessageKey::CamerasStopFailed => "cameras_stop_failed",
+            MessageKey::CamerasOffline => "cameras_offline",
+            MessageKey::NoCamerasSelected => "no_cameras_selected",
+            MessageKey::NoCameraDeliveredImage => "no_camera_delivered_image",
+            MessageKey::NoMissingCameraDeliveredImage => "no_missing_camera_delivered_image",
+            MessageKey::ImageDirectoryFailed => "image_directory_failed",
+            MessageKey::CaptureSaveFailed => "capture_save_failed",
+            MessageKey::CaptureStatsFailed => "capture_stats_failed",
+            MessageKey::CaptureStatsResetFailed => "capture_stats_reset_failed",
+            MessageKey::StreamLimitReached => "stream_limit_reached",
+            MessageKey::SnapshotFailed => "snapshot_failed",
+            MessageKey::EspSettingsUnsupported => "esp_settings_unsupported",
+            MessageKey::EspSettingsReadFailed => "esp_settings_read_failed",
+            MessageKey::EspSettingsUpdateFailed => "esp_settings_update_failed",
+            MessageKey::BrightnessUnsupported => "brightness_unsupported",
+            MessageKey::BrightnessReadFailed => "brightness_read_failed",
+            MessageKey::BrightnessSetFailed => "brightness_set_failed",
+            MessageKey::InvalidConfiguration => "invalid_configuration",
+            MessageKey::ControllerConfigFailed => "controller_config_failed",
+            MessageKey::ConfigSaveFailed => "config_save_failed",
+            MessageKey::ServerReadOnly => "server_read_only",
+        }
+    }
+
+    /// The English template, with `{name}` where each parameter goes
+    pub fn english(&self) -> &'static str {
+        match self {
+            MessageKey::ShellNotFound => "Shell {session_id} not found",
+            MessageKey::ShellGone => "Shell {session_id} no longer exists",
+            MessageKey::InvalidSessionId => "Invalid session_id",
+            MessageKey::ShellLoadFailed => "Failed to load shell data: {error}",
+            MessageKey::ShellSaveFailed => "Failed to save shell data: {error}",
+            MessageKey::ShellListFailed => "Failed to list shells: {error}",
+            MessageKey::ShellSearchFailed => "Failed to search shells: {error}",
+            MessageKey::ShellBrandsFailed => "Failed to list shell brands: {error}",
+            MessageKey::ShellRecordsCheckFailed => "Failed to check shell records: {error}",
+            MessageKey::ShellToggleFailed => "Failed to toggle training: {error}",
+            MessageKey::ShellDeleteFailed => "Failed to delete shell: {error}",
+            MessageKey::ShellNotInTrash => "Shell {session_id} is not in the trash",
+            MessageKey::ShellRestoreConflict => {
+                "Can't restore shell {session_id}: {path} exists already"
+            }
+            MessageKey::ShellRestoreFailed => "Failed to restore shell: {error}",
+            MessageKey::TrashListFailed => "Failed to list the trash: {error}",
+            MessageKey::CameraNotFound => "Camera {camera_id} not found",
+            MessageKey::CameraReadFailed => "Failed to read camera {camera_id}: {error}",
+            MessageKey::CamerasStartFailed => "Failed to start cameras: {errors}",
+            MessageKey::CamerasStopFailed => "Failed to stop cameras: {errors}",
+            MessageKey::CamerasOffline => "Selected cameras are offline: {summary}",
+            MessageKey::NoCamerasSelected => "No cameras are selected",
+            MessageKey::NoCameraDeliveredImage => "No camera delivered an image",
+            MessageKey::NoMissingCameraDeliveredImage => "No missing camera delivered an image",
+            MessageKey::ImageDirectoryFailed => "Failed to create image directory: {error}",
+            MessageKey::CaptureSaveFailed => "Failed to save capture session: {error}",
+            MessageKey::CaptureStatsFailed => "Failed to get capture stats: {error}",
+            MessageKey::CaptureStatsResetFailed => "Failed to reset capture stats: {error}",
+            MessageKey::StreamLimitReached => {
+                "Camera {camera_id} already has {limit} open streams (max_concurrent_streams); close another tab or viewer and retry"
+            }
+            MessageKey::SnapshotFailed => "Failed to take snapshot: {error}",
+            MessageKey::EspSettingsUnsupported => {
+                "Camera settings are only available for ESPHome cameras"
+            }
+            MessageKey::EspSettingsReadFailed => "Failed to read camera settings: {error}",
+            MessageKey::EspSettingsUpdateFailed => "Failed to update camera settings: {error}",
+            MessageKey::BrightnessUnsupported => {
+                "ESPHome cameras do not support brightness control"
+            }
+            MessageKey::BrightnessReadFailed => "Failed to get camera brightness: {error}",
+            MessageKey::BrightnessSetFailed => "Failed to set camera brightness: {error}",
+            MessageKey::InvalidConfiguration => "Invalid configuration",
+            MessageKey::ControllerConfigFailed => {
+                "Failed to update controller configuration: {error}"
+            }
+            MessageKey::ConfigSaveFailed => "Failed to save configuration to file: {error}",
+            MessageKey::ServerReadOnly => "The server is read-only, so nothing can be changed",
+        }
+    }
+
+    /// The key named `key` in a catalogue file
+    pub fn parse(key: &str) -> Option<MessageKey> {
+        MessageKey::ALL
+            .into_iter()
+            .find(|candidate| candidate.as_str() == key)
+    }
+}
+
+/// A message with the values it mentions, see the module docs
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Message {
+    ShellNotFound { session_id: String },
+    ShellGone { session_id: String },
+    InvalidSessionId,
+    ShellLoadFailed { error: String },
+    ShellSaveFailed { error: String },
+    ShellListFailed { error: String },
+    ShellSearchFailed { error: String },
+    ShellBrandsFailed { error: String },
+    ShellRecordsCheckFailed { error: String },
+    ShellToggleFailed { error: String },
+    ShellDeleteFailed { error: String },
+    ShellNotInTrash { session_id: String },
+    ShellRestoreConflict { session_id: String, path: String },
+    ShellRestoreFailed { error: String },
+    TrashListFailed { error: String },
+    CameraNotFound { camera_id: String },
+    CameraReadFailed { camera_id: String, error: String },
+    CamerasStartFailed { errors: String },
+    CamerasStopFailed { errors: String },
+    CamerasOffline { summary: String },
+    NoCamerasSelected,
+    NoCameraDeliveredImage,
+    NoMissingCameraDeliveredImage,
+    ImageDirectoryFailed { error: String },
+    CaptureSaveFailed { error: String },
+    CaptureStatsFailed { error: String },
+    CaptureStatsResetFailed { error: String },
+    StreamLimitReached { camera_id: String, limit: usize },
+    SnapshotFailed { error: String },
+    EspSettingsUnsupported,
+    EspSettingsReadFailed { error: String },
+    EspSettingsUpdateFailed { error: String },
+    BrightnessUnsupported,
+    BrightnessReadFailed { error: String },
+    BrightnessSetFailed { error: String },
+    InvalidConfiguration,
+    ControllerConfigFailed { error: String },
+    ConfigSaveFailed { error: String },
+    ServerReadOnly,
+}
+
+impl Message {
+    /// The message's key and its parameters by name
+    pub fn parts(&self) -> (MessageKey, Vec<(&'static str, String)>) {
+        let error = |error: &String| vec![("error", error.clone())];
+        let session = |session_id: &String| vec![("session_id", session_id.clone())];
+        match self {
+            Message::ShellNotFound { session_id } => {
+                (MessageKey::ShellNotFound, session(session_id))
+            }
+            Message::ShellGone { session_id } => (MessageKey::ShellGone, session(session_id)),
+            Message::InvalidSessionId => (MessageKey::InvalidSessionId, Vec::new()),
+            Message::ShellLoadFailed { error: e } => (MessageKey::ShellLoadFailed, error(e)),
+            Message::ShellSaveFailed { error: e } => (MessageKey::ShellSaveFailed, error(e)),
+            Message::ShellListFailed { error: e } => (MessageKey::ShellListFailed, error(e)),
+            Message::ShellSearchFailed { error: e } => (MessageKey::ShellSearchFailed, error(e)),
+            Message::ShellBrandsFailed { error: e } => (MessageKey::ShellBrandsFailed, error(e)),
+            Message::ShellRecordsCheckFailed { error: e } => {
+                (MessageKey::ShellRecordsCheckFailed, error(e))
+            }
+            Message::ShellToggleFailed { error: e } => (MessageKey::ShellToggleFailed, error(e)),
+            Message::ShellDeleteFailed { error: e } => (MessageKey::ShellDeleteFailed, error(e)),
+            Message::ShellNotInTrash { session_id } => {
+                (MessageKey::ShellNotInTrash, session(session_id))
+            }
+            Message::ShellRestoreConflict { session_id, path } => (
+                MessageKey::ShellRestoreConflict,
+                vec![("session_id", session_id.clone()), ("path", path.clone())],
+            ),
+            Message::ShellRestoreFailed { error: e } => (MessageKey::ShellRestoreFailed, error(e)),
+            Message::TrashListFailed { error: e } => (MessageKey::TrashListFailed, error(e)),
+            Message::CameraNotFound { camera_id } => (
+                MessageKey::CameraNotFound,
+                vec![("camera_id", camera_id.clone())],
+            ),
+            Message::CameraReadFailed { camera_id, error } => (
+                MessageKey::CameraReadFailed,
+                vec![("camera_id", camera_id.clone()), ("error", error.clone())],
+            ),
+            Message::CamerasStartFailed { errors } => (
+                MessageKey::CamerasStartFailed,
+                vec![("errors", errors.clone())],
+            ),
+            Message::CamerasStopFailed { errors } => (
+                MessageKey::CamerasStopFailed,
+                vec![("errors", errors.clone())],
+            ),
+            Message::CamerasOffline { summary } => (
+                MessageKey::CamerasOffline,
+                vec![("summary", summary.clone())],
+            ),
+            Message::NoCamerasSelected => (MessageKey::NoCamerasSelected, Vec::new()),
+            Message::NoCameraDeliveredImage => (MessageKey::NoCameraDeliveredImage, Vec::new()),
+            Message::NoMissingCameraDeliveredImage => {
+                (MessageKey::NoMissingCameraDeliveredImage, Vec::new())
+            }
+            Message::ImageDirectoryFailed { error: e } => {
+                (MessageKey::ImageDirectoryFailed, error(e))
+            }
+            Message::CaptureSaveFailed { error: e } => (MessageKey::CaptureSaveFailed, error(e)),
+            Message::CaptureStatsFailed { error: e } => (MessageKey::CaptureStatsFailed, error(e)),
+            Message::CaptureStatsResetFailed { error: e } => {
+                (MessageKey::CaptureStatsResetFailed, error(e))
+            }
+            Message::StreamLimitReached { camera_id, limit } => (
+                MessageKey::StreamLimitReached,
+                vec![
+                    ("camera_id", camera_id.clone()),
+                    ("limit", limit.to_string()),
+                ],
+            ),
+            Message::SnapshotFailed { error: e } => (MessageKey::SnapshotFailed, error(e)),
+            Message::EspSettingsUnsupported => (MessageKey::EspSettingsUnsupported, Vec::new()),
+            Message::EspSettingsReadFailed { error: e } => {
+                (MessageKey::EspSettingsReadFailed, error(e))
+            }
+            Message::EspSettingsUpdateFailed { error: e } => {
+                (MessageKey::EspSettingsUpdateFailed, error(e))
+            }
+            Message::BrightnessUnsupported => (MessageKey::BrightnessUnsupported, Vec::new()),
+            Message::BrightnessReadFailed { error: e } => {
+                (MessageKey::BrightnessReadFailed, error(e))
+            }
+            Message::BrightnessSetFailed { error: e } => {
+                (MessageKey::BrightnessSetFailed, error(e))
+            }
+            Message::InvalidConfiguration => (MessageKey::InvalidConfiguration, Vec::new()),
+            Message::ControllerConfigFailed { error: e } => {
+                (MessageKey::ControllerConfigFailed, error(e))
+            }
+            Message::ConfigSaveFailed { error: e } => (MessageKey::ConfigSaveFailed, error(e)),
+            Message::ServerReadOnly => (MessageKey::ServerReadOnly, Vec::new()),
+        }
+    }
+
+    /// The message in the language of the request being answered, English outside one
+    pub fn render(&self) -> String {
+        CATALOGUE
+            .try_with(|catalogue| catalogue.render(self))
+            .unwrap_or_else(|_| Catalogue::english().render(self))
+    }
+}
+
+/// Templates for one language, English where it has none
+#[derive(Debug, Clone, PartialEq)]
+pub struct Catalogue {
+    language: String,
+    templates: HashMap<MessageKey, String>,
+}
+
+impl Catalogue {
+    /// The compiled-in English templates
+    pub fn english() -> Self {
+        Self {
+            language: DEFAULT_LANGUAGE.to_string(),
+            templates: HashMap::new(),
+        }
+    }
+
+    /// Templates for `language` from a catalogue file's JSON, also returning keys it doesn't know
+    pub fn from_json(language: &str, json: &str) -> OurResult<(Self, Vec<String>)> {
+        let entries: HashMap<String, String> = serde_json::from_str(json)?;
+        let mut templates = HashMap::new();
+        let mut unknown = Vec::new();
+        for (key, template) in entries {
+            match MessageKey::parse(&key) {
+                Some(key) => {
+                    templates.insert(key, template);
+                }
+                None => unknown.push(key),
+            }
+        }
+        unknown.sort();
+        Ok((
+            Self {
+                language: language.to_string(),
+                templates,
+            },
+            unknown,
+        ))
+    }
+
+    pub fn language(&self) -> &str {
+        &self.language
+    }
+
+    /// The template for `key`, translated if this catalogue has it
+    pub fn template(&self, key: MessageKey) -> &str {
+        self.templates
+            .get(&key)
+            .map(String::as_str)
+            .unwrap_or_else(|| key.english())
+    }
+
+    pub fn render(&self, message: &Message) -> String {
+        let (key, parameters) = message.parts();
+        let mut rendered = self.template(key).to_string();
+        for (name, value) in parameters {
+            rendered = rendered.replace(&format!("{{{name}}}"), &value);
+        }
+        rendered
+    }
+}
+
+/// Every loaded catalogue, and which to use for a request
+#[derive(Debug, Clone)]
+pub struct Catalogues {
+    english: Arc<Catalogue>,
+    /// Translations by lowercase language tag, e.g. `de` or `pt-br`
+    translations: HashMap<String, Arc<Catalogue>>,
+    /// Used when the request names no language with a catalogue
+    default_language: Option<String>,
+}
+
+impl Default for Catalogues {
+    fn default() -> Self {
+        Self {
+            english: Arc::new(Catalogue::english()),
+            translations: HashMap::new(),
+            default_language: None,
+        }
+    }
+}
+
+impl Catalogues {
+    /// Load every `messages.<lang>.json` in `data_directory`, skipping any that can't be read
+    pub fn load(data_directory: &Path, default_language: Option<&str>) -> Self {
+        let mut catalogues = Self {
+            default_language: default_language.map(str::to_lowercase),
+            ..Self::default()
+        };
+        let entries = match fs::read_dir(data_directory) {
+            Ok(entries) => entries,
+            Err(_) => return catalogues,
+        };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            let Some(language) = path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .and_then(|name| name.strip_prefix("messages."))
+                .and_then(|name| name.strip_suffix(".json"))
+                .map(str::to_lowercase)
+            else {
+                continue;
+            };
+            match catalogues.load_file(&language, &path) {
+                Ok(()) => info!("Loaded {language} API messages from {}", path.display()),
+                Err(e) => warn!("Skipping {}: {e}", path.display()),
+            }
+        }
+        if let Some(language) = &catalogues.default_language
+            && language != DEFAULT_LANGUAGE
+            && !catalogues.translations.contains_key(language)
+        {
+            warn!("ui_language is {language}, but there is no messages.{language}.json");
+        }
+        catalogues
+    }
+
+    fn load_file(&mut self, language: &str, path: &Path) -> OurResult<()> {
+        if language.is_empty()
+            || !language
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-')
+        {
+            return Err(OurError::App(format!("{language:?} is not a language tag")));
+        }
+        let json = fs::read_to_string(path)
+            .map_err(|e| OurError::App(format!("Failed to read message catalogue: {e}")))?;
+        let (catalogue, unknown) = Catalogue::from_json(language, &json)?;
+        if !unknown.is_empty() {
+            warn!(
+                "{} names messages that don't exist: {}",
+                path.display(),
+                unknown.join(", ")
+            );
+        }
+        self.translations
+            .insert(language.to_string(), Arc::new(catalogue));
+        Ok(())
+    }
+
+    /// Languages with a translation loaded, sorted
+    pub fn languages(&self) -> Vec<String> {
+        let mut languages: Vec<String> = self.translations.keys().cloned().collect();
+        languages.sort();
+        languages
+    }
+
+    fn find(&self, language: &str) -> Option<Arc<Catalogue>> {
+        if language == DEFAULT_LANGUAGE {
+            return Some(self.english.clone());
+        }
+        self.translations.get(language).cloned()
+    }
+
+    /// The catalogue for a request with this `Accept-Language` header
+    ///
+    /// The most preferred language with a catalogue wins, trying `de` for
+    /// `de-AT`; English counts as having one. Failing that, `ui_language`,
+    /// then English.
+    pub fn negotiate(&self, accept_language: Option<&str>) -> Arc<Catalogue> {
+        let mut preferences: Vec<(f32, String)> = accept_language
+            .unwrap_or_default()
+            .split(',')
+            .filter_map(|entry| {
+                let mut parts = entry.split(';');
+                let language = parts.next()?.trim().to_lowercase();
+                let quality = parts
+                    .filter_map(|part| part.trim().strip_prefix("q="))
+                    .find_map(|quality| quality.parse::<f32>().ok())
+                    .unwrap_or(1.0);
+                (!language.is_empty() && language != "*" && quality > 0.0)
+                    .then_some((quality, language))
+            })
+            .collect();
+        // Stable, so equally preferred languages keep the header's order
+        preferences.sort_by(|a, b| b.0.total_cmp(&a.0));
+
+        preferences
+            .iter()
+            .find_map(|(_, language)| {
+                self.find(language).or_else(|| {
+                    let primary = language.split('-').next()?;
+                    self.find(primary)
+                })
+            })
+            .or_else(|| self.find(self.default_language.as_deref()?))
+            .unwrap_or_else(|| self.english.clone())
+    }
+}
+
+/// Run `future` with its messages rendered from `catalogue`
+pub async fn scope<F: Future>(catalogue: Arc<Catalogue>, future: F) -> F::Output {
+    CATALOGUE.scope(catalogue, future).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_keys_are_registered_and_templates_use_their_parameters() {
+        let keys: std::collections::HashSet<&str> =
+            MessageKey::ALL.iter().map(MessageKey::as_str).collect();
+        assert_eq!(keys.len(), MessageKey::ALL.len());
+        for key in MessageKey::ALL {
+            assert_eq!(MessageKey::parse(key.as_str()), Some(key));
+        }
+
+        let message = Message::StreamLimitReached {
+            camera_id: "usb:1".to_string(),
+            limit: 2,
+        };
+        assert_eq!(
+            Catalogue::english().render(&message),
+            "Camera usb:1 already has 2 open streams (max_concurrent_streams); close another tab or viewer and retry"
+        );
+    }
+
+    /// Data directory with a German catalogue translating only some messages
+    fn partial_german() -> TempDir {
+        let temp_dir = TempDir::new().expect("temp dir should be created");
+        fs::write(
+            temp_dir.path().join("messages.de.json"),
+            serde_json::json!({
+                "shell_not_found": "Hülse {session_id} nicht gefunden",
+                "no_cameras_selected": "Keine Kameras ausgewählt",
+                "not_a_message": "Wird ignoriert",
+            })
+            .to_string(),
+        )
+        .expect("catalogue should be written");
+        fs::write(temp_dir.path().join("messages.fr.json"), "not json")
+            .expect("catalogue should be written");
+        temp_dir
+    }
+
+    #[tokio::test]
+    async fn test_partial_translation_falls_back_to_english() {
+        let temp_dir = partial_german();
+        let catalogues = Catalogues::load(temp_dir.path(), None);
+        assert_eq!(catalogues.languages(), vec!["de"]);
+
+        let not_found = Message::ShellNotFound {
+            session_id: "abc".to_string(),
+        };
+        let german = catalogues.negotiate(Some("de-AT,de;q=0.9,en;q=0.5"));
+        assert_eq!(german.language(), "de");
+        let rendered = scope(german, async {
+            (
+                not_found.render(),
+                Message::NoCamerasSelected.render(),
+                Message::CameraNotFound {
+                    camera_id: "usb:1".to_string(),
+                }
+                .render(),
+            )
+        })
+        .await;
+        assert_eq!(
+            rendered,
+            (
+                "Hülse abc nicht gefunden".to_string(),
+                "Keine Kameras ausgewählt".to_string(),
+                "Camera usb:1 not found".to_string(),
+            )
+        );
+
+        // Outside a request, and for requests preferring English or nothing loaded
+        assert_eq!(not_found.render(), "Shell abc not found");
+        for accept_language in [Some("en-GB,de;q=0.8"), Some("fr"), Some("de;q=0"), None] {
+            assert_eq!(
+                catalogues.negotiate(accept_language).language(),
+                DEFAULT_LANGUAGE,
+                "{accept_language:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_ui_language_applies_when_the_request_names_none() {
+        let temp_dir = partial_german();
+        let catalogues = Catalogues::load(temp_dir.path(), Some("DE"));
+        assert_eq!(catalogues.negotiate(None).language(), "de");
+        assert_eq!(catalogues.negotiate(Some("fr, ja")).language(), "de");
+        assert_eq!(
+            catalogues.negotiate(Some("en")).language(),
+            DEFAULT_LANGUAGE
+        );
+
+        // A language without a catalogue is English
+        let catalogues = Catalogues::load(temp_dir.path(), Some("fr"));
+        assert_eq!(catalogues.negotiate(None).language(), DEFAULT_LANGUAGE);
+    }
+}
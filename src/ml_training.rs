@@ -6,17 +6,28 @@
 
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::fs;
+use std::io::Read;
 use std::path::{Path, PathBuf};
-use tracing::{info, warn};
-
-use crate::config::Settings;
-use crate::shell_data::ShellDataManager;
+use std::sync::Arc;
+use tracing::{debug, info, warn};
+
+use crate::case_type_store::{CaseTypeStore, FsCaseTypeStore};
+use crate::composite::{self, CompositeLayout, CompositeMetadata};
+use crate::config::{Settings, ViewType};
+use crate::disk_space::DiskSpaceGuard;
+use crate::image_ingest::{self, ImageKind};
+use crate::model_metrics::{self, ModelMetrics};
+use crate::safe_name::SafeName;
+use crate::shell_data::{CapturedImage, Shell, ShellDataManager};
+use crate::shell_targets::{self, TargetProgress};
+use crate::training_runs::{DatasetSnapshot, SnapshotEntry, TrainingRunGuard, TrainingRuns};
 use crate::{OurError, OurResult};
 
 /// Represents a shell case type with training data
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub struct CaseType {
     /// Case type name (e.g., "Winchester_9mm")
     pub name: String,
@@ -32,6 +43,22 @@ pub struct CaseType {
     pub created_at: DateTime<Utc>,
     /// When this case type was last updated
     pub updated_at: DateTime<Utc>,
+    /// Original file extension of images that were converted to JPEG, by stored file name
+    #[serde(default)]
+    pub original_extensions: HashMap<String, String>,
+    /// Views a shell of this type needs at least one image of before it is used for training
+    #[serde(default = "default_required_views")]
+    pub required_views: Vec<ViewType>,
+    /// How many training shells of this type to aim for; only used for planning
+    #[serde(default)]
+    pub target_shell_count: Option<u32>,
+}
+
+/// Views required of case types that don't say otherwise; rifle types usually add `Mouth`
+pub const DEFAULT_REQUIRED_VIEWS: [ViewType; 2] = [ViewType::Side, ViewType::Tail];
+
+fn default_required_views() -> Vec<ViewType> {
+    DEFAULT_REQUIRED_VIEWS.to_vec()
 }
 
 impl CaseType {
@@ -46,9 +73,17 @@ impl CaseType {
             training_images: Vec::new(),
             created_at: now,
             updated_at: now,
+            original_extensions: HashMap::new(),
+            required_views: default_required_views(),
+            target_shell_count: None,
         }
     }
 
+    /// The views this type requires that `shell` has no image of
+    pub fn missing_views(&self, shell: &Shell) -> Vec<ViewType> {
+        shell.missing_views(&self.required_views)
+    }
+
     /// Add a reference image to this case type
     pub fn add_reference_image(&mut self, image_path: PathBuf) {
         self.reference_images.push(image_path);
@@ -76,13 +111,13 @@ impl CaseType {
         !self.training_images.is_empty() // Reduced minimum for testing
     }
 
-    /// Remove images that no longer exist on disk
-    pub fn cleanup_missing_images(&mut self) {
+    /// Remove images that no longer exist
+    pub fn cleanup_missing_images(&mut self, exists: impl Fn(&Path) -> bool) {
         let initial_ref_count = self.reference_images.len();
         let initial_train_count = self.training_images.len();
 
-        self.reference_images.retain(|path| path.exists());
-        self.training_images.retain(|path| path.exists());
+        self.reference_images.retain(|path| exists(path));
+        self.training_images.retain(|path| exists(path));
 
         let ref_removed = initial_ref_count - self.reference_images.len();
         let train_removed = initial_train_count - self.training_images.len();
@@ -99,6 +134,7 @@ impl CaseType {
 
 /// Training summary for a case type
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub struct TrainingSummary {
     pub designation: String,
     pub brand: Option<String>,
@@ -107,10 +143,24 @@ pub struct TrainingSummary {
     pub shell_count: usize,
     pub ready_for_training: bool,
     pub updated_at: DateTime<Utc>,
+    pub required_views: Vec<ViewType>,
+    /// Shells of this type left out of training for lack of a required view
+    pub incomplete_shells: Vec<IncompleteShell>,
+    /// `shell_count` against the type's target, when it has one
+    pub target: Option<TargetProgress>,
+}
+
+/// A shell marked for training that is missing views its case type requires
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct IncompleteShell {
+    pub session_id: String,
+    pub missing_views: Vec<ViewType>,
 }
 
 /// ML model metadata
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub struct ModelMetadata {
     pub name: String,
     pub case_types: Vec<String>,
@@ -119,6 +169,80 @@ pub struct ModelMetadata {
     pub version: String,
     pub shell_count: usize,
     pub image_count: usize,
+    /// Hash of the shells and images the run was started with
+    #[serde(default)]
+    pub dataset_hash: Option<String>,
+    /// Whether training data was changed while the run was in progress
+    #[serde(default)]
+    pub dataset_changed: bool,
+    /// The changes made while the run was in progress
+    #[serde(default)]
+    pub dataset_changes: Vec<String>,
+    /// Shells trained on for each case type
+    #[serde(default)]
+    pub class_counts: BTreeMap<String, usize>,
+}
+
+/// A training run in progress, holding the dataset it was started with
+pub struct TrainingRun {
+    pub case_types: Vec<String>,
+    pub snapshot: DatasetSnapshot,
+    guard: TrainingRunGuard,
+}
+
+/// A composite image and whether this call drew it
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub struct GeneratedComposite {
+    pub path: PathBuf,
+    /// False when an existing composite already matched the layout
+    pub regenerated: bool,
+}
+
+/// Outcome of generating composites for the training set, by session ID
+#[derive(Debug, Default, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub struct CompositeBatchReport {
+    pub generated: Vec<String>,
+    pub up_to_date: Vec<String>,
+    pub failed: Vec<String>,
+}
+
+/// Outcome of reconciling the case type records with the reference and training directories
+#[derive(Debug, Default, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub struct ReconcileReport {
+    /// Image files found on disk that weren't recorded against their case type
+    pub registered_images: Vec<PathBuf>,
+    /// Case types created for directories with no record
+    pub created_case_types: Vec<String>,
+    /// Directories with no record whose designation couldn't be inferred from the name
+    pub needs_review: Vec<String>,
+    /// Case types removed because both their directories are gone
+    pub removed_case_types: Vec<String>,
+}
+
+impl ReconcileReport {
+    /// Whether the records were already consistent with the disk
+    pub fn is_clean(&self) -> bool {
+        self.registered_images.is_empty()
+            && self.created_case_types.is_empty()
+            && self.needs_review.is_empty()
+            && self.removed_case_types.is_empty()
+    }
+}
+
+/// Image extensions picked up from case type directories
+const CASE_TYPE_IMAGE_EXTENSIONS: [&str; 3] = ["jpg", "jpeg", "png"];
+
+/// Enough of an image file to tell its kind by
+const IMAGE_HEADER_BYTES: usize = 12;
+
+/// Where an image being added to a case type comes from
+enum ImageSource<'a> {
+    Data(&'a [u8]),
+    /// A file spooled to disk, moved into place when it needs no conversion
+    Upload(&'a Path),
 }
 
 /// Machine learning trainer for shell case identification
@@ -128,34 +252,53 @@ pub struct MLTrainer {
     models_dir: PathBuf,
     references_dir: PathBuf,
     images_dir: PathBuf,
-    case_types_file: PathBuf,
+    store: Arc<dyn CaseTypeStore>,
     shell_data_manager: ShellDataManager,
+    disk_space: DiskSpaceGuard,
+    last_reconcile: Option<ReconcileReport>,
 }
 
 impl MLTrainer {
-    /// Create a new ML trainer
+    /// Create a new ML trainer keeping its files in the configured directories
     pub fn new(settings: Settings) -> Self {
+        let store = Arc::new(FsCaseTypeStore::new(&settings.data_directory));
+        Self::with_store(settings, store)
+    }
+
+    /// Create a new ML trainer keeping its files in `store`
+    pub fn with_store(settings: Settings, store: Arc<dyn CaseTypeStore>) -> Self {
         let shell_data_manager = ShellDataManager::new(settings.data_directory.clone());
 
         Self {
             models_dir: settings.models_directory.clone(),
             references_dir: settings.references_directory.clone(),
             images_dir: settings.image_directory.clone(),
-            case_types_file: settings.data_directory.join("case_types.json"),
+            store,
             shell_data_manager,
+            disk_space: DiskSpaceGuard::new(&settings),
             settings,
             case_types: HashMap::new(),
+            last_reconcile: None,
         }
     }
 
     /// Initialize the ML trainer and load existing data
+    ///
+    /// A read-only server only loads what is there, creating and reconciling nothing.
     pub fn initialize(&mut self) -> OurResult<()> {
         // Create necessary directories
-        self.create_directories()?;
+        if !self.settings.read_only {
+            self.create_directories()?;
+        }
 
         // Load existing case types
         self.load_case_types()?;
 
+        // Pick up changes made to the directories outside the API
+        if !self.settings.read_only {
+            self.reconcile_case_types()?;
+        }
+
         info!(
             "ML trainer initialized with {} case types",
             self.case_types.len()
@@ -168,15 +311,13 @@ impl MLTrainer {
         let directories = [&self.models_dir, &self.references_dir, &self.images_dir];
 
         for dir in directories {
-            if !dir.exists() {
-                fs::create_dir_all(dir).map_err(|e| {
-                    OurError::App(format!(
-                        "Failed to create directory {}: {}",
-                        dir.display(),
-                        e
-                    ))
-                })?;
-            }
+            self.store.create_dir(dir).map_err(|e| {
+                OurError::App(format!(
+                    "Failed to create directory {}: {}",
+                    dir.display(),
+                    e
+                ))
+            })?;
         }
 
         Ok(())
@@ -184,31 +325,16 @@ impl MLTrainer {
 
     /// Load case types from storage
     pub fn load_case_types(&mut self) -> OurResult<()> {
-        if !self.case_types_file.exists() {
+        let Some(case_types) = self.store.load_case_types()? else {
             info!("No existing case types file found, starting with empty set");
             return Ok(());
-        }
-
-        let json_data = fs::read_to_string(&self.case_types_file).map_err(|e| {
-            OurError::App(format!(
-                "Failed to read case types file: {} {e}",
-                self.case_types_file.display()
-            ))
-        })?;
-
-        let case_types_data: HashMap<String, CaseType> =
-            serde_json::from_str(&json_data).map_err(|e| {
-                OurError::App(format!(
-                    "Failed to parse case types file: {} {e}",
-                    self.case_types_file.display()
-                ))
-            })?;
-
-        self.case_types = case_types_data;
+        };
+        self.case_types = case_types;
 
         // Clean up missing images for all case types
+        let store = &self.store;
         for case_type in self.case_types.values_mut() {
-            case_type.cleanup_missing_images();
+            case_type.cleanup_missing_images(|path| store.exists(path));
         }
 
         info!("Loaded {} case types", self.case_types.len());
@@ -217,11 +343,7 @@ impl MLTrainer {
 
     /// Save case types to storage
     pub fn save_case_types(&self) -> OurResult<()> {
-        let json_data = serde_json::to_string_pretty(&self.case_types)
-            .map_err(|e| OurError::App(format!("Failed to serialize case types: {e}")))?;
-
-        fs::write(&self.case_types_file, json_data)
-            .map_err(|e| OurError::App(format!("Failed to write case types file: {e}")))?;
+        self.store.save_case_types(&self.case_types)?;
 
         info!("Saved {} case types", self.case_types.len());
         Ok(())
@@ -234,6 +356,7 @@ impl MLTrainer {
         designation: String,
         brand: Option<String>,
     ) -> OurResult<CaseType> {
+        SafeName::new("name", &name)?;
         if self.case_types.contains_key(&name) {
             return Err(OurError::App(format!("Case type '{name}' already exists")));
         }
@@ -244,13 +367,13 @@ impl MLTrainer {
         let case_ref_dir = self.references_dir.join(&name);
         let case_train_dir = self.images_dir.join(&name);
 
-        fs::create_dir_all(&case_ref_dir).map_err(|e| {
+        self.store.create_dir(&case_ref_dir).map_err(|e| {
             OurError::App(format!(
                 "Failed to create reference directory for {name}: {e}"
             ))
         })?;
 
-        fs::create_dir_all(&case_train_dir).map_err(|e| {
+        self.store.create_dir(&case_train_dir).map_err(|e| {
             OurError::App(format!(
                 "Failed to create training directory for {name}: {e}"
             ))
@@ -268,6 +391,91 @@ impl MLTrainer {
         self.case_types.get(name)
     }
 
+    /// Views a shell of `case_type` needs, the defaults for case types without a record
+    pub fn required_views(&self, case_type: &str) -> Vec<ViewType> {
+        required_views_of(&self.case_types, case_type)
+    }
+
+    /// Change the views a case type requires, returning the updated case type
+    pub fn set_required_views(
+        &mut self,
+        name: &str,
+        required_views: Vec<ViewType>,
+    ) -> OurResult<CaseType> {
+        if required_views.is_empty() {
+            return Err(OurError::App(
+                "required_views must name at least one view".to_string(),
+            ));
+        }
+        if required_views.contains(&ViewType::Unknown) {
+            return Err(OurError::App(
+                "required_views can't include the unknown view".to_string(),
+            ));
+        }
+        let Some(case_type) = self.case_types.get_mut(name) else {
+            return Err(OurError::App(format!("Case type '{name}' not found")));
+        };
+
+        let mut deduplicated = Vec::new();
+        for view_type in required_views {
+            if !deduplicated.contains(&view_type) {
+                deduplicated.push(view_type);
+            }
+        }
+        case_type.required_views = deduplicated;
+        case_type.updated_at = Utc::now();
+        let case_type = case_type.clone();
+        self.save_case_types()?;
+        // Shells may join or leave the training set
+        self.shell_data_manager
+            .training_runs()
+            .record_mutation(format!("case type {name} required views changed"));
+
+        info!(
+            "Case type {} now requires views: {:?}",
+            name, case_type.required_views
+        );
+        Ok(case_type)
+    }
+
+    /// Set or clear how many training shells a case type is aiming for, returning the updated case type
+    pub fn set_target_shell_count(
+        &mut self,
+        name: &str,
+        target_shell_count: Option<u32>,
+    ) -> OurResult<CaseType> {
+        let Some(case_type) = self.case_types.get_mut(name) else {
+            return Err(OurError::App(format!("Case type '{name}' not found")));
+        };
+        case_type.target_shell_count = target_shell_count;
+        case_type.updated_at = Utc::now();
+        let case_type = case_type.clone();
+        self.save_case_types()?;
+
+        match target_shell_count {
+            Some(target) => info!("Case type {name} now targets {target} shells"),
+            None => info!("Case type {name} no longer has a target"),
+        }
+        Ok(case_type)
+    }
+
+    /// Progress of every case type with a target, least complete first
+    pub fn target_progress(&self) -> OurResult<Vec<TargetProgress>> {
+        let shell_stats = self.training_stats()?;
+        let mut progress: Vec<TargetProgress> = self
+            .case_types
+            .values()
+            .filter_map(|case_type| {
+                TargetProgress::new(
+                    case_type,
+                    shell_stats.get(&case_type.name).copied().unwrap_or(0),
+                )
+            })
+            .collect();
+        shell_targets::sort_by_completion(&mut progress);
+        Ok(progress)
+    }
+
     /// Get all case types
     pub fn get_case_types(&self) -> &HashMap<String, CaseType> {
         &self.case_types
@@ -286,24 +494,8 @@ impl MLTrainer {
         case_type_name: &str,
         image_path: &Path,
     ) -> OurResult<()> {
-        let case_type = self
-            .case_types
-            .get_mut(case_type_name)
-            .ok_or_else(|| OurError::App(format!("Case type '{case_type_name}' not found")))?;
-
-        let target_dir = self.references_dir.join(case_type_name);
-        let target_path = target_dir.join(
-            image_path
-                .file_name()
-                .ok_or_else(|| OurError::App("Invalid image file name".to_string()))?,
-        );
-
-        // Copy image to reference directory
-        fs::copy(image_path, &target_path)
-            .map_err(|e| OurError::App(format!("Failed to copy reference image: {e}")))?;
-
-        case_type.add_reference_image(target_path);
-        self.save_case_types()?;
+        let (file_name, data) = self.read_image_file(image_path)?;
+        self.add_reference_image_data(case_type_name, &file_name, &data)?;
 
         info!(
             "Added reference image for {}: {}",
@@ -313,26 +505,61 @@ impl MLTrainer {
         Ok(())
     }
 
-    /// Add a training image for a case type
-    pub fn add_training_image(&mut self, case_type_name: &str, image_path: &Path) -> OurResult<()> {
+    /// Add a reference image for a case type from raw image data
+    ///
+    /// PNG and HEIC images are converted to JPEG before being stored.
+    pub fn add_reference_image_data(
+        &mut self,
+        case_type_name: &str,
+        file_name: &str,
+        data: &[u8],
+    ) -> OurResult<PathBuf> {
+        self.add_reference(case_type_name, file_name, ImageSource::Data(data))
+    }
+
+    /// Add a reference image for a case type from an upload spooled into
+    /// [`Self::reference_image_dir`], moving it into place if it is already a JPEG
+    pub fn add_reference_image_upload(
+        &mut self,
+        case_type_name: &str,
+        file_name: &str,
+        upload: &Path,
+    ) -> OurResult<PathBuf> {
+        self.add_reference(case_type_name, file_name, ImageSource::Upload(upload))
+    }
+
+    /// The directory a case type's reference images are stored in
+    pub fn reference_image_dir(&self, case_type_name: &str) -> OurResult<PathBuf> {
+        Ok(self
+            .references_dir
+            .join(SafeName::new("case type", case_type_name)?))
+    }
+
+    fn add_reference(
+        &mut self,
+        case_type_name: &str,
+        file_name: &str,
+        source: ImageSource<'_>,
+    ) -> OurResult<PathBuf> {
+        let target_dir = self.reference_image_dir(case_type_name)?;
+        let (target_path, original_extension) =
+            self.store_case_type_image(case_type_name, &target_dir, file_name, source)?;
+
         let case_type = self
             .case_types
             .get_mut(case_type_name)
             .ok_or_else(|| OurError::App(format!("Case type '{case_type_name}' not found")))?;
+        Self::record_original_extension(case_type, &target_path, original_extension);
+        case_type.add_reference_image(target_path.clone());
+        self.save_case_types()?;
 
-        let target_dir = self.images_dir.join(case_type_name);
-        let target_path = target_dir.join(
-            image_path
-                .file_name()
-                .ok_or_else(|| OurError::App("Invalid image file name".to_string()))?,
-        );
-
-        // Copy image to training directory
-        fs::copy(image_path, &target_path)
-            .map_err(|e| OurError::App(format!("Failed to copy training image: {e}")))?;
+        Ok(target_path)
+    }
 
-        case_type.add_training_image(target_path);
-        self.save_case_types()?;
+    /// Add a training image for a case type
+    pub fn add_training_image(&mut self, case_type_name: &str, image_path: &Path) -> OurResult<()> {
+        let (file_name, data) = self.read_image_file(image_path)?;
+        self.add_training_image_data(case_type_name, &file_name, &data)?;
 
         info!(
             "Added training image for {}: {}",
@@ -342,50 +569,201 @@ impl MLTrainer {
         Ok(())
     }
 
-    /// Get training summary for all case types
-    pub fn get_training_summary(&self) -> OurResult<HashMap<String, TrainingSummary>> {
-        let mut summary = HashMap::new();
+    /// Add a training image for a case type from raw image data
+    ///
+    /// PNG and HEIC images are converted to JPEG before being stored.
+    pub fn add_training_image_data(
+        &mut self,
+        case_type_name: &str,
+        file_name: &str,
+        data: &[u8],
+    ) -> OurResult<PathBuf> {
+        self.add_training(case_type_name, file_name, ImageSource::Data(data))
+    }
+
+    /// Add a training image for a case type from an upload spooled into
+    /// [`Self::training_image_dir`], moving it into place if it is already a JPEG
+    pub fn add_training_image_upload(
+        &mut self,
+        case_type_name: &str,
+        file_name: &str,
+        upload: &Path,
+    ) -> OurResult<PathBuf> {
+        self.add_training(case_type_name, file_name, ImageSource::Upload(upload))
+    }
 
-        // Get shell statistics
-        let shell_stats = self.shell_data_manager.get_training_stats()?;
+    /// The directory a case type's training images are stored in
+    pub fn training_image_dir(&self, case_type_name: &str) -> OurResult<PathBuf> {
+        Ok(self
+            .images_dir
+            .join(SafeName::new("case type", case_type_name)?))
+    }
 
-        for (name, case_type) in &self.case_types {
-            let shell_count = shell_stats.get(name).copied().unwrap_or(0);
+    fn add_training(
+        &mut self,
+        case_type_name: &str,
+        file_name: &str,
+        source: ImageSource<'_>,
+    ) -> OurResult<PathBuf> {
+        let target_dir = self.training_image_dir(case_type_name)?;
+        let (target_path, original_extension) =
+            self.store_case_type_image(case_type_name, &target_dir, file_name, source)?;
 
-            summary.insert(
-                name.clone(),
-                TrainingSummary {
-                    designation: case_type.designation.clone(),
-                    brand: case_type.brand.clone(),
-                    reference_count: case_type.reference_count(),
-                    training_count: case_type.training_count(),
-                    shell_count,
-                    ready_for_training: case_type.is_ready_for_training() || shell_count > 0,
-                    updated_at: case_type.updated_at,
-                },
-            );
+        let case_type = self
+            .case_types
+            .get_mut(case_type_name)
+            .ok_or_else(|| OurError::App(format!("Case type '{case_type_name}' not found")))?;
+        Self::record_original_extension(case_type, &target_path, original_extension);
+        case_type.add_training_image(target_path.clone());
+        self.save_case_types()?;
+
+        Ok(target_path)
+    }
+
+    /// Read an image file, returning its file name and contents
+    fn read_image_file(&self, image_path: &Path) -> OurResult<(String, Vec<u8>)> {
+        let file_name = image_path
+            .file_name()
+            .ok_or_else(|| OurError::App("Invalid image file name".to_string()))?
+            .to_string_lossy()
+            .to_string();
+        let data = self
+            .store
+            .read_file(image_path)
+            .map_err(|e| OurError::App(format!("Failed to read image file: {e}")))?;
+        Ok((file_name, data))
+    }
+
+    /// Normalise image data to JPEG and write it into a case type directory
+    ///
+    /// Returns the stored path and, if the image was converted, its original extension.
+    fn store_case_type_image(
+        &self,
+        case_type_name: &str,
+        target_dir: &Path,
+        file_name: &str,
+        source: ImageSource<'_>,
+    ) -> OurResult<(PathBuf, Option<String>)> {
+        if !self.case_types.contains_key(case_type_name) {
+            return Err(OurError::App(format!(
+                "Case type '{case_type_name}' not found"
+            )));
+        }
+
+        let quality = self.settings.image_jpeg_quality;
+        let (original_kind, jpeg) = match source {
+            ImageSource::Data(data) => {
+                let ingested = image_ingest::normalise_to_jpeg(data, quality)?;
+                (ingested.original_kind, Some(ingested.data))
+            }
+            // The server spools uploads to disk, so they're read from there whatever the store
+            ImageSource::Upload(upload) => {
+                let kind = image_ingest::detect_image_kind(&Self::read_image_header(upload)?)?;
+                if kind == ImageKind::Jpeg {
+                    (kind, None)
+                } else {
+                    // Decoding needs the whole image, which the upload limit keeps bounded
+                    let data = fs::read(upload)
+                        .map_err(|e| OurError::App(format!("Failed to read upload: {e}")))?;
+                    (
+                        kind,
+                        Some(image_ingest::normalise_to_jpeg(&data, quality)?.data),
+                    )
+                }
+            }
+        };
+
+        let source_name = Path::new(file_name);
+        let stem = source_name
+            .file_stem()
+            .ok_or_else(|| OurError::App("Invalid image file name".to_string()))?
+            .to_string_lossy();
+
+        let (target_path, original_extension) = if original_kind != ImageKind::Jpeg {
+            let original_extension = source_name
+                .extension()
+                .map(|ext| ext.to_string_lossy().to_string())
+                .unwrap_or_else(|| original_kind.extension().to_string());
+            (
+                self.free_jpeg_path(target_dir, &stem)?,
+                Some(original_extension),
+            )
+        } else {
+            (
+                target_dir.join(source_name.file_name().unwrap_or_default()),
+                None,
+            )
+        };
+
+        self.store
+            .create_dir(target_dir)
+            .map_err(|e| OurError::App(format!("Failed to create image directory: {e}")))?;
+        match (jpeg, source) {
+            (Some(data), _) => self.store.copy_image(&target_path, &data),
+            (None, ImageSource::Upload(upload)) => self.store.move_image(upload, &target_path),
+            (None, ImageSource::Data(data)) => self.store.copy_image(&target_path, data),
+        }
+        .map_err(|e| OurError::App(format!("Failed to write image: {e}")))?;
+
+        Ok((target_path, original_extension))
+    }
+
+    /// `{stem}.jpg` in `target_dir`, or `{stem}_{n}.jpg` if that is taken, so
+    /// a converted image never overwrites one stored earlier
+    fn free_jpeg_path(&self, target_dir: &Path, stem: &str) -> OurResult<PathBuf> {
+        let path = target_dir.join(format!("{stem}.jpg"));
+        if !self.store.exists(&path) {
+            return Ok(path);
+        }
+        (1..=u32::MAX)
+            .map(|n| target_dir.join(format!("{stem}_{n}.jpg")))
+            .find(|path| !self.store.exists(path))
+            .ok_or_else(|| OurError::App(format!("No free file name for {stem}")))
+    }
+
+    /// The first bytes of an image file, enough to tell its kind
+    fn read_image_header(path: &Path) -> OurResult<Vec<u8>> {
+        let mut header = Vec::with_capacity(IMAGE_HEADER_BYTES);
+        fs::File::open(path)
+            .and_then(|file| {
+                file.take(IMAGE_HEADER_BYTES as u64)
+                    .read_to_end(&mut header)
+            })
+            .map_err(|e| OurError::App(format!("Failed to read upload: {e}")))?;
+        Ok(header)
+    }
+
+    /// Note the original extension of a converted image on its case type
+    fn record_original_extension(
+        case_type: &mut CaseType,
+        target_path: &Path,
+        original_extension: Option<String>,
+    ) {
+        if let (Some(extension), Some(stored_name)) = (original_extension, target_path.file_name())
+        {
+            case_type
+                .original_extensions
+                .insert(stored_name.to_string_lossy().to_string(), extension);
         }
+    }
 
-        Ok(summary)
+    /// Get training summary for all case types
+    pub fn get_training_summary(&self) -> OurResult<HashMap<String, TrainingSummary>> {
+        Ok(training_summary(&self.case_types, self.eligible_shells()?))
     }
 
     /// Auto-create case types from shell data
     pub fn auto_create_case_types_from_shells(&mut self) -> OurResult<Vec<String>> {
-        let shells = self.shell_data_manager.get_shells_for_training()?;
+        let shells = self.eligible_shells()?;
         let mut created_types = Vec::new();
 
-        for (_, shell) in shells {
-            let case_type_key = shell.get_case_type_key();
-
-            if !self.case_types.contains_key(&case_type_key) {
-                info!(
-                    "Auto-creating case type: {} (brand: {}, type: {})",
-                    case_type_key, shell.brand, shell.shell_type
-                );
-
-                self.add_case_type(case_type_key.clone(), shell.shell_type, Some(shell.brand))?;
-                created_types.push(case_type_key);
-            }
+        for case_type in case_types_to_create(&self.case_types, &shells) {
+            self.add_case_type(
+                case_type.name.clone(),
+                case_type.designation,
+                case_type.brand,
+            )?;
+            created_types.push(case_type.name);
         }
 
         if !created_types.is_empty() {
@@ -395,161 +773,586 @@ impl MLTrainer {
         Ok(created_types)
     }
 
+    /// Shells that go into training: eligible, with an image of every view their case type requires
+    pub fn training_shells(&self) -> OurResult<Vec<(String, Shell)>> {
+        let (complete, incomplete) =
+            split_by_required_views(&self.case_types, self.eligible_shells()?);
+
+        if !incomplete.is_empty() {
+            info!(
+                "Skipping {} shells missing required views: {}",
+                incomplete.len(),
+                incomplete
+                    .iter()
+                    .map(|(session_id, _)| session_id.as_str())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            );
+        }
+        Ok(complete)
+    }
+
+    /// Shells marked for training, less those carrying an excluded flag
+    pub fn eligible_shells(&self) -> OurResult<Vec<(String, Shell)>> {
+        let excluded = &self.settings.training_excluded_flags;
+        let (skipped, shells): (Vec<_>, Vec<_>) = self
+            .shell_data_manager
+            .get_shells_for_training()?
+            .into_iter()
+            .partition(|(_, shell)| shell.has_any_flag(excluded));
+
+        if !skipped.is_empty() {
+            info!(
+                "Skipping {} flagged shells: {}",
+                skipped.len(),
+                skipped
+                    .iter()
+                    .map(|(session_id, _)| session_id.as_str())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            );
+        }
+        Ok(shells)
+    }
+
+    /// Number of training shells per case type
+    fn training_stats(&self) -> OurResult<HashMap<String, usize>> {
+        Ok(shell_counts(&self.training_shells()?))
+    }
+
     /// Train ML model with available data
     pub fn train_model(&mut self, case_types: Option<Vec<String>>) -> OurResult<ModelMetadata> {
+        let run = self.start_training(case_types)?;
+        self.finish_training(run)
+    }
+
+    /// Snapshot the training data and mark a training run as started
+    pub fn start_training(&mut self, case_types: Option<Vec<String>>) -> OurResult<TrainingRun> {
+        self.disk_space.check("training")?;
+
         // Auto-create case types from shell data if they don't exist
         self.auto_create_case_types_from_shells()?;
 
         let target_case_types =
             case_types.unwrap_or_else(|| self.case_types.keys().cloned().collect());
 
-        let mut trainable_types = Vec::new();
-        let mut total_shell_count = 0;
-        let mut total_image_count = 0;
-
         // Get shell statistics for validation
-        let shell_stats = self.shell_data_manager.get_training_stats()?;
-
-        for case_type_name in &target_case_types {
-            if let Some(case_type) = self.case_types.get(case_type_name) {
-                let shell_count = shell_stats.get(case_type_name).copied().unwrap_or(0);
-
-                if shell_count >= 1 || case_type.is_ready_for_training() {
-                    trainable_types.push(case_type_name.clone());
-                    total_shell_count += shell_count;
-                    total_image_count += case_type.training_count();
-
-                    info!(
-                        "Case type {} has {} shell samples and {} training images",
-                        case_type_name,
-                        shell_count,
-                        case_type.training_count()
-                    );
-                }
-            }
-        }
+        let shell_stats = self.training_stats()?;
+        let trainable_types = trainable_types(&self.case_types, &target_case_types, &shell_stats);
 
         if trainable_types.is_empty() {
-            return Err(OurError::App(
-                "No case types have sufficient training data (minimum 1 shell per type)"
+            return Err(OurError::DatasetTooSmall(
+                "no case types have sufficient training data (minimum 1 shell per type)"
                     .to_string(),
             ));
         }
 
-        // Create model metadata
+        let entries = self
+            .training_shells()?
+            .into_iter()
+            .filter(|(_, shell)| trainable_types.contains(&shell.get_case_type_key()))
+            .map(|(session_id, shell)| SnapshotEntry {
+                case_type: shell.get_case_type_key(),
+                image_paths: shell
+                    .training_images()
+                    .map(|image| image.filename.clone())
+                    .collect(),
+                session_id,
+            })
+            .collect();
+        let training_images = trainable_types
+            .iter()
+            .filter_map(|name| self.case_types.get(name))
+            .flat_map(|case_type| &case_type.training_images)
+            .map(|path| path.display().to_string())
+            .collect();
+        let snapshot = DatasetSnapshot::new(entries, training_images)?;
+
+        let guard = self.shell_data_manager.training_runs().start()?;
+        info!(
+            "Training run started on dataset {} ({} shells, {} images)",
+            snapshot.hash,
+            snapshot.shell_count(),
+            snapshot.image_count()
+        );
+
+        Ok(TrainingRun {
+            case_types: trainable_types,
+            snapshot,
+            guard,
+        })
+    }
+
+    /// Finish a training run and save the model metadata
+    pub fn finish_training(&self, run: TrainingRun) -> OurResult<ModelMetadata> {
+        let TrainingRun {
+            case_types,
+            snapshot,
+            guard,
+        } = run;
+        let dataset_changes = guard.finish();
+        let mut class_counts: BTreeMap<String, usize> =
+            case_types.iter().map(|name| (name.clone(), 0)).collect();
+        for entry in &snapshot.entries {
+            *class_counts.entry(entry.case_type.clone()).or_default() += 1;
+        }
+
+        // The placeholder model predicts the most common case type it was
+        // trained on, so the metrics measure that until a real classifier exists
         let model_name = format!("shell_classifier_{}", Utc::now().format("%Y%m%d_%H%M%S"));
+        let (training, validation) = model_metrics::validation_split(
+            snapshot.entries.iter().collect(),
+            |entry| &entry.case_type,
+            |entry| &entry.session_id,
+        );
+        let predictions: Vec<(String, String)> = match most_common_case_type(&training) {
+            Some(prediction) => validation
+                .iter()
+                .map(|entry| (entry.case_type.clone(), prediction.clone()))
+                .collect(),
+            None => Vec::new(),
+        };
+        let metrics = ModelMetrics::from_predictions(&model_name, &case_types, &predictions);
+
+        // Create model metadata
         let model_metadata = ModelMetadata {
             name: model_name.clone(),
-            case_types: trainable_types.clone(),
+            case_types,
             training_date: Utc::now(),
-            accuracy: 0.95, // Placeholder for now
+            accuracy: metrics.accuracy,
             version: "1.0".to_string(),
-            shell_count: total_shell_count,
-            image_count: total_image_count,
+            shell_count: snapshot.shell_count(),
+            image_count: snapshot.image_count(),
+            dataset_hash: Some(snapshot.hash.clone()),
+            dataset_changed: !dataset_changes.is_empty(),
+            dataset_changes,
+            class_counts,
         };
 
-        // Save model metadata
-        let metadata_path = self.models_dir.join(format!("{model_name}.json"));
+        // Save model metadata and metrics beside a placeholder model file
         let metadata_json = serde_json::to_string_pretty(&model_metadata)
             .map_err(|e| OurError::App(format!("Failed to serialize model metadata: {e}")))?;
-
-        fs::write(&metadata_path, metadata_json)
-            .map_err(|e| OurError::App(format!("Failed to write model metadata: {e}")))?;
-
-        // Create placeholder model file
-        let model_path = self.models_dir.join(format!("{model_name}.model"));
-        fs::write(&model_path, "Placeholder model file")
-            .map_err(|e| OurError::App(format!("Failed to create model file: {e}")))?;
-
+        let metrics_json = serde_json::to_string_pretty(&metrics)
+            .map_err(|e| OurError::App(format!("Failed to serialize model metrics: {e}")))?;
+        self.write_model_files(&[
+            (
+                self.metadata_path(&model_name),
+                metadata_json.as_bytes(),
+                "write model metadata",
+            ),
+            (
+                self.metrics_path(&model_name),
+                metrics_json.as_bytes(),
+                "write model metrics",
+            ),
+            (
+                self.models_dir.join(format!("{model_name}.model")),
+                "Placeholder model file".as_bytes(),
+                "create model file",
+            ),
+        ])?;
+
+        if model_metadata.dataset_changed {
+            warn!(
+                "Training data changed during training of {}: {}",
+                model_name,
+                model_metadata.dataset_changes.join(", ")
+            );
+        }
         info!(
             "Model training completed: {} with {} case types, {} shells, {} images",
             model_name,
-            trainable_types.len(),
-            total_shell_count,
-            total_image_count
+            model_metadata.case_types.len(),
+            model_metadata.shell_count,
+            model_metadata.image_count
         );
 
         Ok(model_metadata)
     }
 
+    /// Write a model's files, removing those already written if one fails so
+    /// half a model is never listed
+    fn write_model_files(&self, files: &[(PathBuf, &[u8], &str)]) -> OurResult<()> {
+        for (index, (path, contents, action)) in files.iter().enumerate() {
+            if let Err(e) = self.store.write_model_file(path, contents) {
+                for (written, _, _) in &files[..index] {
+                    if let Err(e) = self.store.remove_model_file(written) {
+                        warn!("Failed to remove {}: {e}", written.display());
+                    }
+                }
+                return Err(OurError::App(format!("Failed to {action}: {e}")));
+            }
+        }
+        Ok(())
+    }
+
+    /// Where the metadata for the model called `model_name` is saved
+    pub fn metadata_path(&self, model_name: &str) -> PathBuf {
+        self.models_dir.join(format!("{model_name}.json"))
+    }
+
+    /// Where the validation metrics for the model called `model_name` are saved
+    pub fn metrics_path(&self, model_name: &str) -> PathBuf {
+        self.models_dir.join(format!("{model_name}.metrics.json"))
+    }
+
+    /// The validation metrics of a model, or `None` for models trained before they were recorded
+    pub fn model_metrics(&self, model_name: &SafeName) -> OurResult<Option<ModelMetrics>> {
+        let path = self.metrics_path(model_name.as_str());
+        let json_data = match self.store.read_file(&path) {
+            Ok(json_data) => json_data,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => {
+                return Err(OurError::App(format!(
+                    "Failed to read model metrics {}: {e}",
+                    path.display()
+                )));
+            }
+        };
+        serde_json::from_slice(&json_data).map(Some).map_err(|e| {
+            OurError::App(format!(
+                "Failed to parse model metrics {}: {e}",
+                path.display()
+            ))
+        })
+    }
+
+    /// Whether a model called `model_name` has been trained
+    pub fn has_model(&self, model_name: &SafeName) -> bool {
+        self.store.is_file(&self.metadata_path(model_name.as_str()))
+    }
+
+    /// The configured `model_name`, or the newest model when none is configured
+    pub fn active_model(&self) -> OurResult<Option<ModelMetadata>> {
+        let models = self.list_models()?;
+        Ok(match &self.settings.model_name {
+            Some(name) => models.into_iter().find(|model| &model.name == name),
+            None => models.into_iter().next(),
+        })
+    }
+
     /// List available trained models
     pub fn list_models(&self) -> OurResult<Vec<ModelMetadata>> {
         let mut models = Vec::new();
 
-        if !self.models_dir.exists() {
-            return Ok(models);
+        let mut skipped = Vec::new();
+        for (stem, path) in self
+            .store
+            .list_model_files(&self.models_dir, &mut skipped)?
+        {
+            // Metrics are saved beside the metadata and aren't models themselves
+            if stem.ends_with(".metrics") {
+                continue;
+            }
+            match self.store.read_file(&path) {
+                Ok(json_data) => match serde_json::from_slice::<ModelMetadata>(&json_data) {
+                    Ok(metadata) => models.push(metadata),
+                    Err(e) => warn!("Failed to parse model metadata {}: {}", path.display(), e),
+                },
+                Err(e) => warn!("Failed to read model metadata {}: {}", path.display(), e),
+            }
+        }
+        for file in skipped {
+            warn!("Skipped {}: {}", file.path.display(), file.reason);
         }
 
-        let entries = fs::read_dir(&self.models_dir)
-            .map_err(|e| OurError::App(format!("Failed to read models directory: {e}")))?;
+        // Sort by training date, newest first
+        models.sort_by_key(|model| std::cmp::Reverse(model.training_date));
+
+        Ok(models)
+    }
+
+    /// The tracker for training runs, shared with other shell data managers so their changes are recorded
+    pub fn training_runs(&self) -> TrainingRuns {
+        self.shell_data_manager.training_runs().clone()
+    }
+
+    /// Load the composite layout from the data directory
+    pub fn composite_layout(&self) -> OurResult<CompositeLayout> {
+        composite::load_layout(&self.settings.data_directory)
+    }
+
+    /// Validate and save a new composite layout
+    pub fn set_composite_layout(&self, layout: &CompositeLayout) -> OurResult<()> {
+        composite::save_layout(&self.settings.data_directory, layout)
+    }
 
-        for entry in entries {
-            let entry =
-                entry.map_err(|e| OurError::App(format!("Failed to read directory entry: {e}")))?;
-            let path = entry.path();
+    /// Generate the composite image for a shell, unless one drawn with the current layout exists
+    pub fn generate_composites(&self, session_id: &str) -> OurResult<GeneratedComposite> {
+        let layout = self.composite_layout()?;
+        self.generate_composite_with_layout(session_id, &layout, &layout.hash()?)
+    }
 
-            if path.is_file() && path.extension() == Some(std::ffi::OsStr::new("json")) {
-                match fs::read_to_string(&path) {
-                    Ok(json_data) => match serde_json::from_str::<ModelMetadata>(&json_data) {
-                        Ok(metadata) => models.push(metadata),
-                        Err(e) => warn!("Failed to parse model metadata {}: {}", path.display(), e),
-                    },
-                    Err(e) => warn!("Failed to read model metadata {}: {}", path.display(), e),
+    /// Generate composites for every shell included in training, including ones still missing views
+    pub fn generate_all_composites(&self) -> OurResult<CompositeBatchReport> {
+        self.disk_space.check("composite generation")?;
+        let layout = self.composite_layout()?;
+        let layout_hash = layout.hash()?;
+        let mut report = CompositeBatchReport::default();
+
+        for (session_id, _) in self.eligible_shells()? {
+            match self.generate_composite_with_layout(&session_id, &layout, &layout_hash) {
+                Ok(composite) if composite.regenerated => report.generated.push(session_id),
+                Ok(_) => report.up_to_date.push(session_id),
+                Err(e) => {
+                    warn!("Failed to generate composite for {}: {}", session_id, e);
+                    report.failed.push(session_id);
                 }
             }
         }
+        Ok(report)
+    }
 
-        // Sort by training date, newest first
-        models.sort_by(|a, b| b.training_date.cmp(&a.training_date));
+    /// Redraw the existing composites of these shells that are out of date,
+    /// leaving shells without a composite for the next full generation
+    pub fn refresh_composites(&self, session_ids: &[String]) -> OurResult<CompositeBatchReport> {
+        self.disk_space.check("composite generation")?;
+        let layout = self.composite_layout()?;
+        let layout_hash = layout.hash()?;
+        let mut report = CompositeBatchReport::default();
+
+        for session_id in session_ids {
+            if !self.composite_path(session_id).exists() {
+                continue;
+            }
+            match self.generate_composite_with_layout(session_id, &layout, &layout_hash) {
+                Ok(composite) if composite.regenerated => report.generated.push(session_id.clone()),
+                Ok(_) => report.up_to_date.push(session_id.clone()),
+                Err(e) => {
+                    warn!("Failed to refresh composite for {}: {}", session_id, e);
+                    report.failed.push(session_id.clone());
+                }
+            }
+        }
+        Ok(report)
+    }
 
-        Ok(models)
+    fn composite_path(&self, session_id: &str) -> PathBuf {
+        composite::composite_path(&self.settings.data_directory, session_id)
     }
 
-    /// Generate composite images for training visualization
-    pub fn generate_composites(&self, session_id: &str) -> OurResult<PathBuf> {
-        // Load shell data
+    fn generate_composite_with_layout(
+        &self,
+        session_id: &str,
+        layout: &CompositeLayout,
+        layout_hash: &str,
+    ) -> OurResult<GeneratedComposite> {
         let shell = self.shell_data_manager.load_shell(session_id)?;
 
-        if shell
-            .captured_images
-            .as_ref()
-            .is_none_or(|images| images.is_empty())
-        {
+        let captured_images: Vec<&CapturedImage> = shell.training_images().collect();
+        if captured_images.is_empty() {
             return Err(OurError::App(
                 "No captured images found for composite generation".to_string(),
             ));
         }
 
-        // For now, return a placeholder path
-        // In a real implementation, this would:
-        // 1. Load all captured images
-        // 2. Apply region cropping if available
-        // 3. Create a composite image layout
-        // 4. Save the composite image
+        let composite_path = self.composite_path(session_id);
+
+        if composite::is_current(&composite_path, layout_hash, shell.region_updated_at) {
+            return Ok(GeneratedComposite {
+                path: composite_path,
+                regenerated: false,
+            });
+        }
+
+        // The first image of each view type fills its slot, cropped to its region when set
+        let mut images = HashMap::new();
+        for captured in captured_images {
+            if images.contains_key(&captured.view_type) {
+                continue;
+            }
+            let image_path = self.settings.image_directory.join(&captured.filename);
+            let image = image::open(&image_path).map_err(|e| {
+                OurError::App(format!("Failed to open {}: {e}", image_path.display()))
+            })?;
+            let image = match captured.get_region().as_rect() {
+                Some((x, y, width, height)) => image.crop_imm(
+                    x.max(0) as u32,
+                    y.max(0) as u32,
+                    width.max(1) as u32,
+                    height.max(1) as u32,
+                ),
+                None => image,
+            };
+            images.insert(captured.view_type, image);
+        }
 
-        let composite_path = self
-            .settings
-            .data_directory
-            .join("composites")
-            .join(format!("{session_id}_composite.jpg"));
+        let label = format!("{} {}", shell.brand, shell.shell_type);
+        let canvas = layout.render(&images, &label)?;
 
-        // Create composites directory if it doesn't exist
         if let Some(parent) = composite_path.parent() {
             fs::create_dir_all(parent).map_err(|e| {
                 OurError::App(format!("Failed to create composites directory: {e}"))
             })?;
         }
-
-        // Create placeholder composite file
-        fs::write(&composite_path, "Placeholder composite image")
-            .map_err(|e| OurError::App(format!("Failed to create composite file: {e}")))?;
+        let jpeg_data = image_ingest::encode_jpeg(&canvas, self.settings.image_jpeg_quality)?;
+        fs::write(&composite_path, jpeg_data)
+            .map_err(|e| OurError::App(format!("Failed to write composite image: {e}")))?;
+
+        let metadata = CompositeMetadata {
+            layout_hash: layout_hash.to_string(),
+            brand: shell.brand,
+            shell_type: shell.shell_type,
+            generated_at: Utc::now(),
+        };
+        fs::write(
+            composite::metadata_path(&composite_path),
+            serde_json::to_string_pretty(&metadata)?,
+        )
+        .map_err(|e| OurError::App(format!("Failed to write composite metadata: {e}")))?;
 
         info!("Generated composite for session {}", session_id);
-        Ok(composite_path)
+        Ok(GeneratedComposite {
+            path: composite_path,
+            regenerated: true,
+        })
     }
 
-    /// Delete a case type and its associated data
-    pub fn delete_case_type(&mut self, name: &str) -> OurResult<()> {
+    /// The report from the last reconciliation, if one has run
+    pub fn last_reconcile(&self) -> Option<&ReconcileReport> {
+        self.last_reconcile.as_ref()
+    }
+
+    /// Bring the case type records in line with the reference and training directories
+    ///
+    /// Images on disk are registered against the case type named by their directory,
+    /// directories without a record get one when their name ends in a supported
+    /// designation, and records whose directories are both gone are removed.
+    pub fn reconcile_case_types(&mut self) -> OurResult<ReconcileReport> {
+        let mut report = ReconcileReport::default();
+
+        let mut removed: Vec<String> = self
+            .case_types
+            .keys()
+            .filter(|name| {
+                !self.store.is_dir(&self.references_dir.join(name))
+                    && !self.store.is_dir(&self.images_dir.join(name))
+            })
+            .cloned()
+            .collect();
+        removed.sort();
+        for name in &removed {
+            warn!("Removing case type {name}: its reference and training directories are gone");
+            self.case_types.remove(name);
+            self.shell_data_manager
+                .training_runs()
+                .record_mutation(format!("case type {name} removed by reconciliation"));
+        }
+        report.removed_case_types = removed;
+
+        let mut directory_names = self.case_type_directories(&self.references_dir)?;
+        directory_names.extend(self.case_type_directories(&self.images_dir)?);
+        directory_names.sort();
+        directory_names.dedup();
+
+        for name in directory_names {
+            if !self.case_types.contains_key(&name) {
+                if let Err(e) = SafeName::new("case type", &name) {
+                    warn!("Directory {name} can't be a case type, it needs manual review: {e}");
+                    report.needs_review.push(name);
+                    continue;
+                }
+                match self.infer_designation(&name) {
+                    Some((designation, brand)) => {
+                        info!("Creating case type {name} ({designation}) found on disk");
+                        self.case_types.insert(
+                            name.clone(),
+                            CaseType::new(name.clone(), designation, brand),
+                        );
+                        report.created_case_types.push(name.clone());
+                    }
+                    None => {
+                        warn!(
+                            "Directory {name} has no case type and no recognisable designation, it needs manual review"
+                        );
+                        report.needs_review.push(name);
+                        continue;
+                    }
+                }
+            }
+
+            let reference_images = self.case_type_images(&self.references_dir.join(&name))?;
+            let training_images = self.case_type_images(&self.images_dir.join(&name))?;
+            let Some(case_type) = self.case_types.get_mut(&name) else {
+                continue;
+            };
+            for image in reference_images {
+                if !case_type.reference_images.contains(&image) {
+                    case_type.add_reference_image(image.clone());
+                    report.registered_images.push(image);
+                }
+            }
+            for image in training_images {
+                if !case_type.training_images.contains(&image) {
+                    case_type.add_training_image(image.clone());
+                    report.registered_images.push(image);
+                }
+            }
+        }
+
+        if report.is_clean() {
+            debug!("Case type records match the image directories");
+        } else {
+            info!(
+                "Reconciled case types: {} images registered, {} created, {} removed, {} need review",
+                report.registered_images.len(),
+                report.created_case_types.len(),
+                report.removed_case_types.len(),
+                report.needs_review.len()
+            );
+            self.save_case_types()?;
+        }
+
+        self.last_reconcile = Some(report.clone());
+        Ok(report)
+    }
+
+    /// Designation and brand for a directory named like `Brand_designation`
+    fn infer_designation(&self, name: &str) -> Option<(String, Option<String>)> {
+        let (brand, suffix) = match name.rsplit_once('_') {
+            Some((brand, suffix)) if !brand.is_empty() => (Some(brand.to_string()), suffix),
+            _ => (None, name),
+        };
+        self.settings
+            .supported_case_types
+            .iter()
+            .find(|supported| supported.eq_ignore_ascii_case(suffix))
+            .map(|designation| (designation.clone(), brand))
+    }
+
+    /// Names of the subdirectories of a case type root, skipping hidden ones
+    fn case_type_directories(&self, root: &Path) -> OurResult<Vec<String>> {
+        if !self.store.is_dir(root) {
+            return Ok(Vec::new());
+        }
+        let mut names = self
+            .store
+            .list_dirs(root)
+            .map_err(|e| OurError::App(format!("Failed to read {}: {e}", root.display())))?;
+        names.retain(|name| !name.starts_with('.'));
+        Ok(names)
+    }
+
+    /// Image files directly inside a case type directory, sorted
+    fn case_type_images(&self, directory: &Path) -> OurResult<Vec<PathBuf>> {
+        if !self.store.is_dir(directory) {
+            return Ok(Vec::new());
+        }
+        let mut images = self
+            .store
+            .list_files(directory)
+            .map_err(|e| OurError::App(format!("Failed to read {}: {e}", directory.display())))?;
+        images.retain(|path| {
+            path.extension()
+                .map(|ext| ext.to_string_lossy().to_lowercase())
+                .is_some_and(|ext| CASE_TYPE_IMAGE_EXTENSIONS.contains(&ext.as_str()))
+        });
+        images.sort();
+        Ok(images)
+    }
+
+    /// Delete a case type and its associated data
+    pub fn delete_case_type(&mut self, name: &str) -> OurResult<()> {
         if !self.case_types.contains_key(name) {
             return Err(OurError::App(format!("Case type '{name}' not found")));
         }
@@ -558,30 +1361,213 @@ impl MLTrainer {
         let ref_dir = self.references_dir.join(name);
         let train_dir = self.images_dir.join(name);
 
-        if ref_dir.exists() {
-            fs::remove_dir_all(&ref_dir)
+        if self.store.exists(&ref_dir) {
+            self.store
+                .remove_dir(&ref_dir)
                 .map_err(|e| OurError::App(format!("Failed to remove reference directory: {e}")))?;
         }
 
-        if train_dir.exists() {
-            fs::remove_dir_all(&train_dir)
+        if self.store.exists(&train_dir) {
+            self.store
+                .remove_dir(&train_dir)
                 .map_err(|e| OurError::App(format!("Failed to remove training directory: {e}")))?;
         }
 
         // Remove from case types
         self.case_types.remove(name);
         self.save_case_types()?;
+        self.shell_data_manager
+            .training_runs()
+            .record_mutation(format!("case type {name} deleted"));
 
         info!("Deleted case type: {}", name);
         Ok(())
     }
 }
 
+/// Whether a case type has something to train on: a training shell or a training image of its own
+pub fn is_trainable(case_type: &CaseType, shell_count: usize) -> bool {
+    shell_count > 0 || case_type.is_ready_for_training()
+}
+
+/// Views a shell of `case_type` needs, the defaults for case types without a record
+fn required_views_of(case_types: &HashMap<String, CaseType>, case_type: &str) -> Vec<ViewType> {
+    case_types
+        .get(case_type)
+        .map(|case_type| case_type.required_views.clone())
+        .unwrap_or_else(default_required_views)
+}
+
+/// Shells with an image of every view their case type requires, and shells without
+pub fn split_by_required_views(
+    case_types: &HashMap<String, CaseType>,
+    shells: Vec<(String, Shell)>,
+) -> (Vec<(String, Shell)>, Vec<(String, Shell)>) {
+    shells.into_iter().partition(|(_, shell)| {
+        shell
+            .missing_views(&required_views_of(case_types, &shell.get_case_type_key()))
+            .is_empty()
+    })
+}
+
+/// Number of shells of each case type
+pub fn shell_counts(shells: &[(String, Shell)]) -> HashMap<String, usize> {
+    let mut counts = HashMap::new();
+    for (_, shell) in shells {
+        *counts.entry(shell.get_case_type_key()).or_insert(0) += 1;
+    }
+    counts
+}
+
+/// The training summary of every case type, given the shells eligible for training
+pub fn training_summary(
+    case_types: &HashMap<String, CaseType>,
+    eligible: Vec<(String, Shell)>,
+) -> HashMap<String, TrainingSummary> {
+    let (complete, incomplete_shells) = split_by_required_views(case_types, eligible);
+    let shell_stats = shell_counts(&complete);
+    let mut incomplete: HashMap<String, Vec<IncompleteShell>> = HashMap::new();
+    for (session_id, shell) in incomplete_shells {
+        let case_type = shell.get_case_type_key();
+        let missing_views = shell.missing_views(&required_views_of(case_types, &case_type));
+        incomplete
+            .entry(case_type)
+            .or_default()
+            .push(IncompleteShell {
+                session_id,
+                missing_views,
+            });
+    }
+
+    case_types
+        .iter()
+        .map(|(name, case_type)| {
+            let shell_count = shell_stats.get(name).copied().unwrap_or(0);
+            let summary = TrainingSummary {
+                designation: case_type.designation.clone(),
+                brand: case_type.brand.clone(),
+                reference_count: case_type.reference_count(),
+                training_count: case_type.training_count(),
+                shell_count,
+                ready_for_training: is_trainable(case_type, shell_count),
+                updated_at: case_type.updated_at,
+                required_views: case_type.required_views.clone(),
+                incomplete_shells: incomplete.remove(name).unwrap_or_default(),
+                target: TargetProgress::new(case_type, shell_count),
+            };
+            (name.clone(), summary)
+        })
+        .collect()
+}
+
+/// The case types in `requested` that have a record and something to train on, in order
+pub fn trainable_types(
+    case_types: &HashMap<String, CaseType>,
+    requested: &[String],
+    shell_stats: &HashMap<String, usize>,
+) -> Vec<String> {
+    let mut trainable = Vec::new();
+    for case_type_name in requested {
+        let Some(case_type) = case_types.get(case_type_name) else {
+            continue;
+        };
+        let shell_count = shell_stats.get(case_type_name).copied().unwrap_or(0);
+        if is_trainable(case_type, shell_count) {
+            trainable.push(case_type_name.clone());
+
+            info!(
+                "Case type {} has {} shell samples and {} training images",
+                case_type_name,
+                shell_count,
+                case_type.training_count()
+            );
+        }
+    }
+    trainable
+}
+
+/// New records for the case types of `shells` that have none, once each
+///
+/// Case types whose name can't be a directory name are skipped with a warning.
+pub fn case_types_to_create(
+    case_types: &HashMap<String, CaseType>,
+    shells: &[(String, Shell)],
+) -> Vec<CaseType> {
+    let mut created: Vec<CaseType> = Vec::new();
+    for (_, shell) in shells {
+        let case_type_key = shell.get_case_type_key();
+        if case_types.contains_key(&case_type_key)
+            || created.iter().any(|created| created.name == case_type_key)
+        {
+            continue;
+        }
+        if let Err(e) = SafeName::new("name", &case_type_key) {
+            warn!("Not auto-creating case type: {e}");
+            continue;
+        }
+        info!(
+            "Auto-creating case type: {} (brand: {}, type: {})",
+            case_type_key, shell.brand, shell.shell_type
+        );
+        created.push(CaseType::new(
+            case_type_key,
+            shell.shell_type.clone(),
+            Some(shell.brand.clone()),
+        ));
+    }
+    created
+}
+
+/// The case type of the most entries, the alphabetically first on a tie
+fn most_common_case_type(entries: &[&SnapshotEntry]) -> Option<String> {
+    let mut counts: BTreeMap<&str, usize> = BTreeMap::new();
+    for entry in entries {
+        *counts.entry(entry.case_type.as_str()).or_default() += 1;
+    }
+    counts
+        .into_iter()
+        .max_by(|a, b| a.1.cmp(&b.1).then(b.0.cmp(a.0)))
+        .map(|(case_type, _)| case_type.to_string())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::case_type_store::MemoryCaseTypeStore;
+    use crate::fixtures::{DEFAULT_FIXTURE_SEED, FixtureBuilder};
+    use crate::shell_data::CapturedImage;
     use tempfile::TempDir;
 
+    /// A Winchester 9mm shell with one image of each view
+    fn shell_with_views(views: &[ViewType]) -> Shell {
+        let mut shell = Shell::new("Winchester".to_string(), "9mm".to_string());
+        for (index, view_type) in views.iter().enumerate() {
+            shell.add_captured_image(CapturedImage::new(
+                index as u32,
+                format!("{view_type}.jpg"),
+                format!("Camera {index}"),
+                *view_type,
+            ));
+        }
+        shell
+    }
+
+    /// A trainer keeping its case types, images and models in memory
+    fn memory_trainer() -> (MLTrainer, Arc<MemoryCaseTypeStore>) {
+        let settings = crate::config::Settings {
+            data_directory: PathBuf::from("/memory/data"),
+            models_directory: PathBuf::from("/memory/models"),
+            references_directory: PathBuf::from("/memory/references"),
+            image_directory: PathBuf::from("/memory/images"),
+            min_free_disk_mb: 0,
+            ..Default::default()
+        };
+        let store = Arc::new(MemoryCaseTypeStore::default());
+        let mut trainer = MLTrainer::with_store(settings, store.clone());
+        trainer.initialize().expect("trainer should initialise");
+        (trainer, store)
+    }
+
     #[test]
     fn test_case_type_creation() {
         let case_type = CaseType::new(
@@ -622,6 +1608,7 @@ mod tests {
             models_directory: temp_dir.path().join("models"),
             references_directory: temp_dir.path().join("references"),
             image_directory: temp_dir.path().join("images"),
+            min_free_disk_mb: 0,
             ..Default::default()
         };
 
@@ -640,6 +1627,22 @@ mod tests {
         assert_eq!(case_type.name, "Test_9mm");
         assert_eq!(trainer.get_case_types().len(), 1);
 
+        // Names become directory names
+        for name in ["../escape", "a/b", ".hidden", "", "bad:name", "with space"] {
+            assert!(
+                trainer
+                    .add_case_type(name.to_string(), "9mm".to_string(), None)
+                    .is_err(),
+                "{name:?} should be rejected"
+            );
+        }
+        assert!(
+            trainer
+                .add_reference_image_data("../escape", "x.jpg", b"not read")
+                .is_err()
+        );
+        assert!(!temp_dir.path().join("escape").exists());
+
         // Test persistence
         trainer
             .save_case_types()
@@ -650,4 +1653,658 @@ mod tests {
             .expect("Test operation should succeed");
         assert_eq!(trainer.get_case_types().len(), 1);
     }
+
+    #[test]
+    fn test_reference_image_png_conversion() {
+        let temp_dir = TempDir::new().expect("Test operation should succeed");
+        let settings = crate::config::Settings {
+            data_directory: temp_dir.path().to_path_buf(),
+            models_directory: temp_dir.path().join("models"),
+            references_directory: temp_dir.path().join("references"),
+            image_directory: temp_dir.path().join("images"),
+            min_free_disk_mb: 0,
+            ..Default::default()
+        };
+
+        let mut trainer = MLTrainer::new(settings);
+        trainer.initialize().expect("Test operation should succeed");
+        trainer
+            .add_case_type("Test_9mm".to_string(), "9mm".to_string(), None)
+            .expect("Test operation should succeed");
+
+        // PNG files are converted and the original extension is noted
+        let png_path = temp_dir.path().join("headstamp.PNG");
+        fs::write(&png_path, crate::image_ingest::tests::png_fixture())
+            .expect("Test operation should succeed");
+        trainer
+            .add_reference_image("Test_9mm", &png_path)
+            .expect("Test operation should succeed");
+
+        let case_type = trainer
+            .get_case_type("Test_9mm")
+            .expect("Test operation should succeed");
+        let stored = &case_type.reference_images[0];
+        assert_eq!(
+            stored.file_name().and_then(|n| n.to_str()),
+            Some("headstamp.jpg")
+        );
+        let stored_data = fs::read(stored).expect("Test operation should succeed");
+        image::load_from_memory_with_format(&stored_data, image::ImageFormat::Jpeg)
+            .expect("Stored reference image should be a JPEG");
+        assert_eq!(
+            case_type.original_extensions.get("headstamp.jpg"),
+            Some(&"PNG".to_string())
+        );
+
+        // A second headstamp.png doesn't overwrite the first
+        let second = trainer
+            .add_reference_image_data(
+                "Test_9mm",
+                "headstamp.png",
+                &crate::image_ingest::tests::png_fixture(),
+            )
+            .expect("Test operation should succeed");
+        assert_eq!(
+            second.file_name().and_then(|n| n.to_str()),
+            Some("headstamp_1.jpg")
+        );
+        let case_type = trainer
+            .get_case_type("Test_9mm")
+            .expect("Test operation should succeed");
+        assert_eq!(case_type.reference_images.len(), 2);
+        assert!(case_type.reference_images[0].exists());
+        assert_eq!(
+            case_type.original_extensions.get("headstamp_1.jpg"),
+            Some(&"png".to_string())
+        );
+
+        // JPEG files are stored unchanged
+        let jpeg = crate::image_ingest::tests::jpeg_fixture();
+        let stored = trainer
+            .add_training_image_data("Test_9mm", "side.jpeg", &jpeg)
+            .expect("Test operation should succeed");
+        assert_eq!(
+            fs::read(&stored).expect("Test operation should succeed"),
+            jpeg
+        );
+
+        // Anything that isn't an image is rejected
+        assert!(
+            trainer
+                .add_training_image_data("Test_9mm", "notes.jpg", b"not an image")
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_composites_regenerate_on_layout_change() {
+        let temp_dir = TempDir::new().expect("Test operation should succeed");
+        let settings = crate::config::Settings {
+            data_directory: temp_dir.path().to_path_buf(),
+            models_directory: temp_dir.path().join("models"),
+            references_directory: temp_dir.path().join("references"),
+            image_directory: temp_dir.path().join("images"),
+            min_free_disk_mb: 0,
+            ..Default::default()
+        };
+
+        fs::create_dir_all(&settings.image_directory).expect("Test operation should succeed");
+        image::RgbImage::from_pixel(16, 12, image::Rgb([200, 10, 10]))
+            .save(settings.image_directory.join("side.png"))
+            .expect("Test operation should succeed");
+
+        let mut shell = crate::shell_data::Shell::new("Winchester".to_string(), "9mm".to_string());
+        shell.add_captured_image(crate::shell_data::CapturedImage::new(
+            0,
+            "side.png".to_string(),
+            "Camera 0".to_string(),
+            crate::config::ViewType::Side,
+        ));
+        let shell_data_manager = ShellDataManager::new(settings.data_directory.clone());
+        shell_data_manager
+            .save_shell("session", &shell)
+            .expect("Test operation should succeed");
+
+        let trainer = MLTrainer::new(settings);
+        let first = trainer
+            .generate_composites("session")
+            .expect("Test operation should succeed");
+        assert!(first.regenerated);
+        let composite = image::open(&first.path).expect("Test operation should succeed");
+        assert_eq!((composite.width(), composite.height()), (1600, 640));
+
+        let again = trainer
+            .generate_composites("session")
+            .expect("Test operation should succeed");
+        assert!(!again.regenerated);
+
+        let mut layout = trainer
+            .composite_layout()
+            .expect("Test operation should succeed");
+        layout.label_strip = None;
+        layout.canvas_height = 600;
+        trainer
+            .set_composite_layout(&layout)
+            .expect("Test operation should succeed");
+
+        let report = trainer
+            .generate_all_composites()
+            .expect("Test operation should succeed");
+        assert_eq!(report.generated, vec!["session"]);
+
+        // A region change after drawing makes the composite stale
+        shell.region_updated_at = Some(Utc::now());
+        shell_data_manager
+            .save_shell("session", &shell)
+            .expect("Test operation should succeed");
+        let refreshed = trainer
+            .refresh_composites(&["session".to_string(), "no-composite".to_string()])
+            .expect("Test operation should succeed");
+        assert_eq!(refreshed.generated, vec!["session"]);
+        assert!(refreshed.failed.is_empty());
+    }
+
+    #[test]
+    fn test_training_run_flags_dataset_changes() {
+        let temp_dir = TempDir::new().expect("Test operation should succeed");
+        let settings = crate::config::Settings {
+            data_directory: temp_dir.path().to_path_buf(),
+            models_directory: temp_dir.path().join("models"),
+            references_directory: temp_dir.path().join("references"),
+            image_directory: temp_dir.path().join("images"),
+            min_free_disk_mb: 0,
+            ..Default::default()
+        };
+
+        let mut trainer = MLTrainer::new(settings.clone());
+        trainer.initialize().expect("Test operation should succeed");
+        // The web server's shell manager shares the trainer's run tracker
+        let shell_data_manager = ShellDataManager::new(settings.data_directory.clone())
+            .with_training_runs(trainer.training_runs());
+
+        let fixtures = FixtureBuilder::new(DEFAULT_FIXTURE_SEED)
+            .shells(2)
+            .case_types([("Winchester", "9mm")])
+            .views(&DEFAULT_REQUIRED_VIEWS)
+            .write(&settings.data_directory, &settings.image_directory)
+            .expect("Test operation should succeed");
+        let second = fixtures.session_ids()[1];
+
+        // An undisturbed run records the dataset it used
+        let clean = trainer
+            .train_model(None)
+            .expect("Test operation should succeed");
+        assert_eq!(clean.shell_count, 2);
+        assert!(clean.dataset_hash.is_some());
+        assert!(!clean.dataset_changed);
+        // One of the two shells is held back, and the metrics are saved beside the model
+        let name = SafeName::new("name", &clean.name).expect("model name should be safe");
+        let metrics = trainer
+            .model_metrics(&name)
+            .expect("Test operation should succeed")
+            .expect("metrics should be saved");
+        assert_eq!(metrics.validation_count, 1);
+        assert_eq!(metrics.accuracy, clean.accuracy);
+        assert_eq!(metrics.recall("Winchester_9mm"), Some(1.0));
+
+        // Toggling a shell part way through a run flags the result
+        let run = trainer
+            .start_training(None)
+            .expect("Test operation should succeed");
+        assert!(trainer.start_training(None).is_err());
+        shell_data_manager
+            .toggle_shell_training(second)
+            .expect("Test operation should succeed");
+        let stale = trainer
+            .finish_training(run)
+            .expect("Test operation should succeed");
+        assert!(stale.dataset_changed);
+        assert_eq!(stale.dataset_changes, vec![format!("shell {second} saved")]);
+        assert_eq!(stale.shell_count, 2);
+        assert_eq!(stale.dataset_hash, clean.dataset_hash);
+
+        // The next run sees the new dataset
+        let next = trainer
+            .train_model(None)
+            .expect("Test operation should succeed");
+        assert_eq!(next.shell_count, 1);
+        assert_ne!(next.dataset_hash, clean.dataset_hash);
+        // A single shell is only trained on, so nothing is held back to score
+        assert_eq!(next.accuracy, 0.0);
+
+        // Without a configured model the newest is active
+        let active = trainer
+            .active_model()
+            .expect("Test operation should succeed")
+            .expect("a model should be active");
+        assert_eq!(active.name, next.name);
+    }
+
+    #[test]
+    fn test_training_skips_excluded_flags() {
+        let temp_dir = TempDir::new().expect("Test operation should succeed");
+        let settings = crate::config::Settings {
+            data_directory: temp_dir.path().to_path_buf(),
+            models_directory: temp_dir.path().join("models"),
+            references_directory: temp_dir.path().join("references"),
+            image_directory: temp_dir.path().join("images"),
+            min_free_disk_mb: 0,
+            ..Default::default()
+        };
+        let trainer = MLTrainer::new(settings.clone());
+
+        let mut shell = shell_with_views(&DEFAULT_REQUIRED_VIEWS);
+        shell.flags = vec![crate::shell_data::ShellFlag::Damaged];
+        let mut blurry = shell.clone();
+        blurry.flags.push(crate::shell_data::ShellFlag::Blurry);
+        let shell_data_manager = ShellDataManager::new(settings.data_directory.clone());
+        for (session_id, shell) in [("damaged", &shell), ("blurry", &blurry)] {
+            shell_data_manager
+                .save_shell(session_id, shell)
+                .expect("Test operation should succeed");
+        }
+
+        // Blurry is excluded by default, Damaged isn't
+        let shells = trainer
+            .training_shells()
+            .expect("Test operation should succeed");
+        assert_eq!(shells.len(), 1);
+        assert_eq!(shells[0].0, "damaged");
+    }
+
+    #[test]
+    fn test_required_views_per_case_type() {
+        let side_only = shell_with_views(&[ViewType::Side]);
+        let side_and_tail = shell_with_views(&[ViewType::Side, ViewType::Tail]);
+
+        let mut pistol = CaseType::new("Winchester_9mm".to_string(), "9mm".to_string(), None);
+        let mut rifle = CaseType::new("Federal_308win".to_string(), "308win".to_string(), None);
+        assert_eq!(pistol.required_views, DEFAULT_REQUIRED_VIEWS);
+        assert_eq!(pistol.missing_views(&side_and_tail), Vec::new());
+        assert_eq!(pistol.missing_views(&side_only), vec![ViewType::Tail]);
+
+        // A pistol type that only asks for a side view, a rifle type that also wants the mouth
+        pistol.required_views = vec![ViewType::Side];
+        rifle.required_views = vec![ViewType::Side, ViewType::Tail, ViewType::Mouth];
+        assert_eq!(pistol.missing_views(&side_only), Vec::new());
+        assert_eq!(
+            rifle.missing_views(&side_only),
+            vec![ViewType::Tail, ViewType::Mouth]
+        );
+
+        // Records written before required views existed get the defaults
+        let mut stored = serde_json::to_value(&rifle).expect("case type should serialise");
+        if let Some(fields) = stored.as_object_mut() {
+            fields.remove("required_views");
+        }
+        let loaded: CaseType = serde_json::from_value(stored).expect("case type should load");
+        assert_eq!(loaded.required_views, DEFAULT_REQUIRED_VIEWS);
+    }
+
+    #[test]
+    fn test_target_shell_counts_persist() {
+        let temp_dir = TempDir::new().expect("Test operation should succeed");
+        let settings = crate::config::Settings {
+            data_directory: temp_dir.path().to_path_buf(),
+            models_directory: temp_dir.path().join("models"),
+            references_directory: temp_dir.path().join("references"),
+            image_directory: temp_dir.path().join("images"),
+            min_free_disk_mb: 0,
+            ..Default::default()
+        };
+        let mut trainer = MLTrainer::new(settings.clone());
+        trainer.initialize().expect("Test operation should succeed");
+        for name in ["Winchester_9mm", "Federal_308win"] {
+            trainer
+                .add_case_type(name.to_string(), "9mm".to_string(), None)
+                .expect("Test operation should succeed");
+        }
+        assert!(
+            trainer
+                .set_target_shell_count("Missing_9mm", Some(5))
+                .is_err()
+        );
+
+        let case_type = trainer
+            .set_target_shell_count("Winchester_9mm", Some(50))
+            .expect("Test operation should succeed");
+        assert_eq!(case_type.target_shell_count, Some(50));
+
+        let mut reloaded = MLTrainer::new(settings);
+        reloaded
+            .load_case_types()
+            .expect("Test operation should succeed");
+        let targets: BTreeMap<String, Option<u32>> = reloaded
+            .get_case_types()
+            .iter()
+            .map(|(name, case_type)| (name.clone(), case_type.target_shell_count))
+            .collect();
+        assert_eq!(
+            targets,
+            BTreeMap::from([
+                ("Federal_308win".to_string(), None),
+                ("Winchester_9mm".to_string(), Some(50)),
+            ])
+        );
+        let progress = reloaded
+            .target_progress()
+            .expect("Test operation should succeed");
+        assert_eq!(progress.len(), 1);
+        assert_eq!((progress[0].current, progress[0].target), (0, 50));
+
+        reloaded
+            .set_target_shell_count("Winchester_9mm", None)
+            .expect("Test operation should succeed");
+        assert!(
+            reloaded
+                .target_progress()
+                .expect("Test operation should succeed")
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn test_training_set_needs_required_views() {
+        let temp_dir = TempDir::new().expect("Test operation should succeed");
+        let settings = crate::config::Settings {
+            data_directory: temp_dir.path().to_path_buf(),
+            models_directory: temp_dir.path().join("models"),
+            references_directory: temp_dir.path().join("references"),
+            image_directory: temp_dir.path().join("images"),
+            min_free_disk_mb: 0,
+            ..Default::default()
+        };
+        let mut trainer = MLTrainer::new(settings.clone());
+        trainer.initialize().expect("Test operation should succeed");
+        let shell_data_manager = ShellDataManager::new(settings.data_directory.clone());
+        shell_data_manager
+            .save_shell("side-only", &shell_with_views(&[ViewType::Side]))
+            .expect("Test operation should succeed");
+        shell_data_manager
+            .save_shell(
+                "complete",
+                &shell_with_views(&[ViewType::Side, ViewType::Tail]),
+            )
+            .expect("Test operation should succeed");
+
+        let session_ids = |trainer: &MLTrainer| -> Vec<String> {
+            let mut ids: Vec<String> = trainer
+                .training_shells()
+                .expect("Test operation should succeed")
+                .into_iter()
+                .map(|(session_id, _)| session_id)
+                .collect();
+            ids.sort();
+            ids
+        };
+        assert_eq!(session_ids(&trainer), vec!["complete"]);
+
+        trainer
+            .auto_create_case_types_from_shells()
+            .expect("Test operation should succeed");
+        let summary = trainer
+            .get_training_summary()
+            .expect("Test operation should succeed");
+        let pistol = &summary["Winchester_9mm"];
+        assert_eq!(pistol.shell_count, 1);
+        assert_eq!(
+            pistol.incomplete_shells,
+            vec![IncompleteShell {
+                session_id: "side-only".to_string(),
+                missing_views: vec![ViewType::Tail],
+            }]
+        );
+
+        trainer
+            .set_required_views("Winchester_9mm", vec![ViewType::Side, ViewType::Side])
+            .expect("Test operation should succeed");
+        assert_eq!(session_ids(&trainer), vec!["complete", "side-only"]);
+        assert_eq!(
+            trainer.required_views("Winchester_9mm"),
+            vec![ViewType::Side]
+        );
+        assert!(
+            trainer
+                .set_required_views("Winchester_9mm", Vec::new())
+                .is_err()
+        );
+        assert!(
+            trainer
+                .set_required_views("Winchester_9mm", vec![ViewType::Unknown])
+                .is_err()
+        );
+        assert!(
+            trainer
+                .set_required_views("missing", vec![ViewType::Side])
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_reconcile_case_types_with_disk() {
+        let temp_dir = TempDir::new().expect("temp dir should be created");
+        let settings = crate::config::Settings {
+            data_directory: temp_dir.path().to_path_buf(),
+            models_directory: temp_dir.path().join("models"),
+            references_directory: temp_dir.path().join("references"),
+            image_directory: temp_dir.path().join("images"),
+            min_free_disk_mb: 0,
+            ..Default::default()
+        };
+        let references = settings.references_directory.clone();
+        let images = settings.image_directory.clone();
+
+        let mut trainer = MLTrainer::new(settings.clone());
+        trainer.initialize().expect("trainer should initialise");
+        trainer
+            .add_case_type("Winchester_9mm".to_string(), "9mm".to_string(), None)
+            .expect("case type should be added");
+        trainer
+            .add_case_type("Gone_45acp".to_string(), "45acp".to_string(), None)
+            .expect("case type should be added");
+
+        // Drift: an image copied in by hand, a directory deleted by hand, and three
+        // directories created outside the API, one with a name that isn't safe
+        fs::write(images.join("Winchester_9mm").join("manual.jpg"), b"jpeg")
+            .expect("image should be written");
+        fs::write(images.join("Winchester_9mm").join("notes.txt"), b"text")
+            .expect("file should be written");
+        fs::remove_dir_all(references.join("Gone_45acp")).expect("directory should be removed");
+        fs::remove_dir_all(images.join("Gone_45acp")).expect("directory should be removed");
+        fs::create_dir_all(references.join("Federal_223REM")).expect("directory should be created");
+        fs::write(references.join("Federal_223REM").join("ref.png"), b"png")
+            .expect("image should be written");
+        fs::create_dir_all(images.join("mystery")).expect("directory should be created");
+        fs::create_dir_all(images.join("Remington 9mm")).expect("directory should be created");
+
+        // A fresh trainer reconciles on startup
+        let mut trainer = MLTrainer::new(settings);
+        trainer.initialize().expect("trainer should initialise");
+        let report = trainer
+            .last_reconcile()
+            .cloned()
+            .expect("startup should reconcile");
+
+        assert_eq!(report.removed_case_types, vec!["Gone_45acp"]);
+        assert_eq!(report.created_case_types, vec!["Federal_223REM"]);
+        assert_eq!(report.needs_review, vec!["Remington 9mm", "mystery"]);
+        assert_eq!(
+            report.registered_images,
+            vec![
+                references.join("Federal_223REM").join("ref.png"),
+                images.join("Winchester_9mm").join("manual.jpg"),
+            ]
+        );
+
+        let created = trainer
+            .get_case_type("Federal_223REM")
+            .expect("case type should be created");
+        assert_eq!(created.designation, "223rem");
+        assert_eq!(created.brand.as_deref(), Some("Federal"));
+        assert_eq!(created.reference_count(), 1);
+        assert_eq!(
+            trainer
+                .get_case_type("Winchester_9mm")
+                .map(CaseType::training_count),
+            Some(1)
+        );
+        assert!(trainer.get_case_type("Gone_45acp").is_none());
+        assert!(trainer.get_case_type("mystery").is_none());
+        assert!(trainer.get_case_type("Remington 9mm").is_none());
+
+        // The fixes were saved, so running again only reports the directories needing review
+        let report = trainer
+            .reconcile_case_types()
+            .expect("reconcile should succeed");
+        assert_eq!(
+            report,
+            ReconcileReport {
+                needs_review: vec!["Remington 9mm".to_string(), "mystery".to_string()],
+                ..Default::default()
+            }
+        );
+    }
+
+    #[test]
+    fn test_failed_copy_leaves_no_reference_record() {
+        let (mut trainer, store) = memory_trainer();
+        trainer
+            .add_case_type("Winchester_9mm".to_string(), "9mm".to_string(), None)
+            .expect("case type should be added");
+        let source = PathBuf::from("/memory/incoming/headstamp.jpg");
+        store.insert_file(&source, &crate::image_ingest::tests::jpeg_fixture());
+
+        store.fail("copy_image");
+        assert!(
+            trainer
+                .add_reference_image("Winchester_9mm", &source)
+                .is_err()
+        );
+
+        let reference_count = |case_types: &HashMap<String, CaseType>| {
+            case_types
+                .get("Winchester_9mm")
+                .map(CaseType::reference_count)
+        };
+        assert_eq!(reference_count(trainer.get_case_types()), Some(0));
+        let saved = store
+            .saved_case_types()
+            .expect("case types should be saved");
+        assert_eq!(reference_count(&saved), Some(0));
+        assert_eq!(store.files(), vec![source]);
+    }
+
+    #[test]
+    fn test_failed_model_write_leaves_no_partial_model() {
+        let (mut trainer, store) = memory_trainer();
+        trainer
+            .add_case_type("Winchester_9mm".to_string(), "9mm".to_string(), None)
+            .expect("case type should be added");
+        trainer
+            .add_training_image_data(
+                "Winchester_9mm",
+                "side.jpg",
+                &crate::image_ingest::tests::jpeg_fixture(),
+            )
+            .expect("training image should be added");
+
+        // The metadata is written, then the metrics fail
+        store.fail_after("write_model_file", 1);
+        assert!(trainer.train_model(None).is_err());
+        assert!(
+            store
+                .files()
+                .iter()
+                .all(|path| !path.starts_with("/memory/models"))
+        );
+        assert!(
+            trainer
+                .list_models()
+                .expect("models should be listed")
+                .is_empty()
+        );
+        // The failed run doesn't block the next one
+        assert!(!trainer.training_runs().is_running());
+    }
+
+    #[test]
+    fn test_training_rules_without_storage() {
+        let mut case_types = HashMap::new();
+        for (name, designation) in [
+            ("Winchester_9mm", "9mm"),
+            ("Federal_308win", "308win"),
+            ("Remington_45acp", "45acp"),
+        ] {
+            case_types.insert(
+                name.to_string(),
+                CaseType::new(name.to_string(), designation.to_string(), None),
+            );
+        }
+        if let Some(federal) = case_types.get_mut("Federal_308win") {
+            federal.add_training_image(PathBuf::from("side.jpg"));
+        }
+
+        let complete = shell_with_views(&DEFAULT_REQUIRED_VIEWS);
+        let mut hornady = complete.clone();
+        hornady.brand = "Hornady".to_string();
+        hornady.shell_type = "40sw".to_string();
+        let mut unsafe_name = complete.clone();
+        unsafe_name.brand = "../escape".to_string();
+        let eligible = vec![
+            ("a".to_string(), complete.clone()),
+            ("b".to_string(), complete),
+            ("c".to_string(), shell_with_views(&[ViewType::Side])),
+            ("d".to_string(), hornady.clone()),
+            ("e".to_string(), hornady),
+            ("f".to_string(), unsafe_name),
+        ];
+
+        let (training, incomplete) = split_by_required_views(&case_types, eligible.clone());
+        assert_eq!(training.len(), 5);
+        assert_eq!(incomplete.len(), 1);
+        let stats = shell_counts(&training);
+        assert_eq!(stats.get("Winchester_9mm"), Some(&2));
+
+        let summary = training_summary(&case_types, eligible.clone());
+        let pistol = &summary["Winchester_9mm"];
+        assert_eq!(pistol.shell_count, 2);
+        assert!(pistol.ready_for_training);
+        assert_eq!(
+            pistol.incomplete_shells,
+            vec![IncompleteShell {
+                session_id: "c".to_string(),
+                missing_views: vec![ViewType::Tail],
+            }]
+        );
+        // Training images alone are enough, neither is nothing
+        assert!(summary["Federal_308win"].ready_for_training);
+        assert!(!summary["Remington_45acp"].ready_for_training);
+        assert_eq!(summary.len(), 3);
+
+        let requested = [
+            "Remington_45acp",
+            "Federal_308win",
+            "Winchester_9mm",
+            "Missing_9mm",
+        ]
+        .map(String::from);
+        assert_eq!(
+            trainable_types(&case_types, &requested, &stats),
+            vec!["Federal_308win", "Winchester_9mm"]
+        );
+
+        // Each unknown type once, and never one that can't be a directory name
+        let created = case_types_to_create(&case_types, &eligible);
+        let created: Vec<(&str, &str, Option<&str>)> = created
+            .iter()
+            .map(|case_type| {
+                (
+                    case_type.name.as_str(),
+                    case_type.designation.as_str(),
+                    case_type.brand.as_deref(),
+                )
+            })
+            .collect();
+        assert_eq!(created, vec![("Hornady_40sw", "40sw", Some("Hornady"))]);
+    }
 }
@@ -0,0 +1,304 @@
+//! How well a model does on the shells held back from training.
+//!
+//! Training holds back part of each case type's shells as a validation split
+//! ([`validation_split`]). The model's predictions for those shells make a
+//! [`ModelMetrics`]: the confusion matrix plus precision, recall and F1 for
+//! each case type, saved next to the model as `{model}.metrics.json`. A single
+//! accuracy figure hides which case types get mixed up with which;
+//! [`ModelMetrics::worst_confused`] names them.
+//!
+//! Every ratio is 0 when there is nothing to divide by, so a model trained on
+//! one case type, or with no shells held back, still gets metrics.
+
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+/// One shell in this many of each case type is held back for validation
+pub const VALIDATION_SHARE: usize = 5;
+
+/// How the model did on one case type's validation shells
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct ClassMetrics {
+    /// Validation shells of this case type
+    pub support: usize,
+    /// Validation shells the model said were this case type
+    pub predicted: usize,
+    /// Share of the shells predicted as this case type that were
+    pub precision: f64,
+    /// Share of this case type's shells the model got right
+    pub recall: f64,
+    pub f1: f64,
+}
+
+/// Validation shells of one case type the model took for another
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct ConfusedPair {
+    pub actual: String,
+    pub predicted: String,
+    pub count: usize,
+    /// Share of `actual`'s validation shells predicted as `predicted`
+    pub rate: f64,
+}
+
+/// A model's results on its validation split
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct ModelMetrics {
+    pub model_name: String,
+    /// Case types in the order of the matrix rows and columns
+    pub classes: Vec<String>,
+    /// `confusion[actual][predicted]` counts validation shells of `classes[actual]`
+    /// that the model predicted as `classes[predicted]`
+    pub confusion: Vec<Vec<usize>>,
+    pub per_class: BTreeMap<String, ClassMetrics>,
+    /// Share of all validation shells predicted correctly
+    pub accuracy: f64,
+    pub validation_count: usize,
+}
+
+impl ModelMetrics {
+    /// Metrics from `(actual, predicted)` case types of the validation shells
+    ///
+    /// `classes` are the case types the model was trained on; any other case
+    /// type in the predictions gets a row and column too.
+    pub fn from_predictions(
+        model_name: &str,
+        classes: &[String],
+        predictions: &[(String, String)],
+    ) -> Self {
+        let mut classes = classes.to_vec();
+        for (actual, predicted) in predictions {
+            for class in [actual, predicted] {
+                if !classes.contains(class) {
+                    classes.push(class.clone());
+                }
+            }
+        }
+        classes.sort();
+        classes.dedup();
+
+        let position = |class: &str| classes.iter().position(|name| name == class);
+        let mut confusion = vec![vec![0; classes.len()]; classes.len()];
+        for (actual, predicted) in predictions {
+            if let (Some(actual), Some(predicted)) = (position(actual), position(predicted)) {
+                confusion[actual][predicted] += 1;
+            }
+        }
+
+        let per_class = classes
+            .iter()
+            .enumerate()
+            .map(|(index, class)| {
+                let correct = confusion[index][index];
+                let support: usize = confusion[index].iter().sum();
+                let predicted: usize = confusion.iter().map(|row| row[index]).sum();
+                let precision = ratio(correct, predicted);
+                let recall = ratio(correct, support);
+                let f1 = if precision + recall == 0.0 {
+                    0.0
+                } else {
+                    2.0 * precision * recall / (precision + recall)
+                };
+                let metrics = ClassMetrics {
+                    support,
+                    predicted,
+                    precision,
+                    recall,
+                    f1,
+                };
+                (class.clone(), metrics)
+            })
+            .collect();
+
+        let correct: usize = (0..classes.len())
+            .map(|index| confusion[index][index])
+            .sum();
+        Self {
+            model_name: model_name.to_string(),
+            classes,
+            confusion,
+            per_class,
+            accuracy: ratio(correct, predictions.len()),
+            validation_count: predictions.len(),
+        }
+    }
+
+    /// Recall for a case type, if any of its shells were held back for validation
+    pub fn recall(&self, class: &str) -> Option<f64> {
+        self.per_class
+            .get(class)
+            .filter(|metrics| metrics.support > 0)
+            .map(|metrics| metrics.recall)
+    }
+
+    /// The `limit` most common mistakes, by how many shells were mistaken
+    pub fn worst_confused(&self, limit: usize) -> Vec<ConfusedPair> {
+        let mut pairs: Vec<ConfusedPair> = self
+            .confusion
+            .iter()
+            .enumerate()
+            .flat_map(|(actual, row)| {
+                let support: usize = row.iter().sum();
+                row.iter()
+                    .enumerate()
+                    .filter(move |&(predicted, &count)| predicted != actual && count > 0)
+                    .map(move |(predicted, &count)| ConfusedPair {
+                        actual: self.classes[actual].clone(),
+                        predicted: self.classes[predicted].clone(),
+                        count,
+                        rate: ratio(count, support),
+                    })
+            })
+            .collect();
+        // Ties go to the pair with the higher rate, then alphabetically
+        pairs.sort_by(|a, b| {
+            b.count
+                .cmp(&a.count)
+                .then(b.rate.total_cmp(&a.rate))
+                .then_with(|| (&a.actual, &a.predicted).cmp(&(&b.actual, &b.predicted)))
+        });
+        pairs.truncate(limit);
+        pairs
+    }
+}
+
+fn ratio(numerator: usize, denominator: usize) -> f64 {
+    if denominator == 0 {
+        0.0
+    } else {
+        numerator as f64 / denominator as f64
+    }
+}
+
+/// Split items into training and validation, holding back one in
+/// [`VALIDATION_SHARE`] of each case type
+///
+/// Items are ordered by `key` within their case type and the last ones are
+/// held back, so the same dataset always splits the same way. A case type with
+/// two or more items always keeps at least one back; a case type with one item
+/// is only trained on.
+pub fn validation_split<T>(
+    items: Vec<T>,
+    case_type: impl Fn(&T) -> &str,
+    key: impl Fn(&T) -> &str,
+) -> (Vec<T>, Vec<T>) {
+    let mut by_case_type: BTreeMap<String, Vec<T>> = BTreeMap::new();
+    for item in items {
+        by_case_type
+            .entry(case_type(&item).to_string())
+            .or_default()
+            .push(item);
+    }
+
+    let mut training = Vec::new();
+    let mut validation = Vec::new();
+    for (_, mut items) in by_case_type {
+        items.sort_by(|a, b| key(a).cmp(key(b)));
+        let held_back = if items.len() >= 2 {
+            (items.len() / VALIDATION_SHARE).max(1)
+        } else {
+            0
+        };
+        validation.extend(items.split_off(items.len() - held_back));
+        training.extend(items);
+    }
+    (training, validation)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn predictions(pairs: &[(&str, &str, usize)]) -> Vec<(String, String)> {
+        pairs
+            .iter()
+            .flat_map(|&(actual, predicted, count)| {
+                std::iter::repeat_n((actual.to_string(), predicted.to_string()), count)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_confusion_and_per_class_metrics() {
+        let classes = vec!["40sw".to_string(), "9mm".to_string(), "45acp".to_string()];
+        let metrics = ModelMetrics::from_predictions(
+            "model",
+            &classes,
+            &predictions(&[
+                ("9mm", "9mm", 8),
+                ("9mm", "40sw", 2),
+                ("40sw", "9mm", 3),
+                ("40sw", "40sw", 1),
+                ("45acp", "45acp", 4),
+            ]),
+        );
+        assert_eq!(metrics.classes, vec!["40sw", "45acp", "9mm"]);
+        assert_eq!(
+            metrics.confusion,
+            vec![vec![1, 0, 3], vec![0, 4, 0], vec![2, 0, 8]]
+        );
+        assert_eq!(metrics.validation_count, 18);
+        assert!((metrics.accuracy - 13.0 / 18.0).abs() < 1e-9);
+
+        let sw = &metrics.per_class["40sw"];
+        assert_eq!((sw.support, sw.predicted), (4, 3));
+        assert!((sw.precision - 1.0 / 3.0).abs() < 1e-9);
+        assert!((sw.recall - 0.25).abs() < 1e-9);
+        assert!((sw.f1 - 2.0 / 7.0).abs() < 1e-9);
+        assert_eq!(metrics.recall("45acp"), Some(1.0));
+
+        let worst = metrics.worst_confused(5);
+        assert_eq!(
+            worst
+                .iter()
+                .map(|pair| (pair.actual.as_str(), pair.predicted.as_str(), pair.count))
+                .collect::<Vec<_>>(),
+            vec![("40sw", "9mm", 3), ("9mm", "40sw", 2)]
+        );
+        assert!((worst[0].rate - 0.75).abs() < 1e-9);
+        assert_eq!(metrics.worst_confused(1).len(), 1);
+    }
+
+    #[test]
+    fn test_one_class_and_empty_validation_do_not_divide_by_zero() {
+        let classes = vec!["9mm".to_string()];
+        let single =
+            ModelMetrics::from_predictions("single", &classes, &predictions(&[("9mm", "9mm", 2)]));
+        assert_eq!(single.accuracy, 1.0);
+        assert_eq!(single.per_class["9mm"].f1, 1.0);
+        assert!(single.worst_confused(3).is_empty());
+
+        let empty = ModelMetrics::from_predictions("empty", &classes, &[]);
+        assert_eq!(empty.confusion, vec![vec![0]]);
+        assert_eq!(empty.accuracy, 0.0);
+        let class = &empty.per_class["9mm"];
+        assert_eq!((class.precision, class.recall, class.f1), (0.0, 0.0, 0.0));
+        assert_eq!(empty.recall("9mm"), None);
+        assert!(
+            serde_json::to_string(&empty)
+                .expect("metrics should serialise")
+                .contains("\"accuracy\":0.0")
+        );
+    }
+
+    #[test]
+    fn test_validation_split_holds_back_each_case_type() {
+        let items: Vec<(String, String)> = (0..10)
+            .map(|index| ("9mm".to_string(), format!("shell_{index:02}")))
+            .chain([
+                ("40sw".to_string(), "shell_b".to_string()),
+                ("40sw".to_string(), "shell_a".to_string()),
+                ("45acp".to_string(), "shell_only".to_string()),
+            ])
+            .collect();
+        let (training, validation) =
+            validation_split(items, |(case_type, _)| case_type, |(_, key)| key);
+        let held_back: Vec<&str> = validation.iter().map(|(_, key)| key.as_str()).collect();
+        assert_eq!(held_back, vec!["shell_b", "shell_08", "shell_09"]);
+        assert_eq!(training.len(), 10);
+        assert!(training.iter().any(|(_, key)| key == "shell_only"));
+    }
+}
@@ -0,0 +1,206 @@
+//! Image orientation for cameras that aren't mounted upright.
+//!
+//! Each camera's [`Orientation`] is applied to the raw sensor frame before
+//! anything else sees it: first a clockwise [`Rotation`], then an optional
+//! horizontal mirror. Captures, snapshots and USB streams are transformed on
+//! the server; ESPHome MJPEG streams are proxied untouched and the dashboard
+//! applies the same transform with CSS. Region coordinates are always in the
+//! transformed frame, which is what the dashboard shows and captures store.
+
+use std::collections::HashMap;
+
+use image::{RgbImage, imageops};
+use serde::{Deserialize, Serialize};
+
+use crate::config::UserConfig;
+use crate::image_ingest::{DEFAULT_JPEG_QUALITY, encode_jpeg};
+use crate::{OurError, OurResult};
+
+/// Clockwise rotation, serialised as degrees
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "u16", into = "u16")]
+pub enum Rotation {
+    #[default]
+    None,
+    Clockwise90,
+    Half,
+    Clockwise270,
+}
+
+impl Rotation {
+    /// Every rotation, in degrees
+    pub const DEGREES: [u16; 4] = [0, 90, 180, 270];
+}
+
+impl TryFrom<u16> for Rotation {
+    type Error = OurError;
+
+    fn try_from(degrees: u16) -> Result<Self, Self::Error> {
+        match degrees {
+            0 => Ok(Rotation::None),
+            90 => Ok(Rotation::Clockwise90),
+            180 => Ok(Rotation::Half),
+            270 => Ok(Rotation::Clockwise270),
+            _ => Err(OurError::App(format!(
+                "Invalid rotation {degrees}, expected 0, 90, 180 or 270"
+            ))),
+        }
+    }
+}
+
+impl From<Rotation> for u16 {
+    fn from(rotation: Rotation) -> Self {
+        match rotation {
+            Rotation::None => 0,
+            Rotation::Clockwise90 => 90,
+            Rotation::Half => 180,
+            Rotation::Clockwise270 => 270,
+        }
+    }
+}
+
+/// How a camera's frames are turned upright
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct Orientation {
+    pub rotation: Rotation,
+    /// Mirror left to right, after rotating
+    pub mirror: bool,
+}
+
+impl Orientation {
+    /// Frames are used as the sensor produces them
+    pub fn is_identity(&self) -> bool {
+        self.rotation == Rotation::None && !self.mirror
+    }
+
+    /// Rotate then mirror a frame
+    pub fn apply(&self, image: RgbImage) -> RgbImage {
+        let rotated = match self.rotation {
+            Rotation::None => image,
+            Rotation::Clockwise90 => imageops::rotate90(&image),
+            Rotation::Half => imageops::rotate180(&image),
+            Rotation::Clockwise270 => imageops::rotate270(&image),
+        };
+        if self.mirror {
+            imageops::flip_horizontal(&rotated)
+        } else {
+            rotated
+        }
+    }
+
+    /// The size of a `width` by `height` sensor frame once it is turned upright
+    pub fn oriented_size(&self, width: u32, height: u32) -> (u32, u32) {
+        match self.rotation {
+            Rotation::Clockwise90 | Rotation::Clockwise270 => (height, width),
+            Rotation::None | Rotation::Half => (width, height),
+        }
+    }
+
+    /// Decode, transform and re-encode a JPEG, returning it untouched when there is nothing to do
+    pub fn apply_to_jpeg(&self, jpeg: Vec<u8>) -> OurResult<Vec<u8>> {
+        if self.is_identity() {
+            return Ok(jpeg);
+        }
+        let image = image::load_from_memory(&jpeg)?.to_rgb8();
+        encode_jpeg(&self.apply(image), DEFAULT_JPEG_QUALITY)
+    }
+}
+
+/// The configured orientation of every camera that isn't used as-is
+pub fn configured_orientations(user_config: &UserConfig) -> HashMap<String, Orientation> {
+    user_config
+        .camera_configs
+        .iter()
+        .filter_map(|(camera_id, config)| {
+            let orientation = config.orientation();
+            (!orientation.is_identity()).then(|| (camera_id.clone(), orientation))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A 3x2 image with a different colour in every pixel
+    fn asymmetric() -> RgbImage {
+        RgbImage::from_fn(3, 2, |x, y| image::Rgb([x as u8 * 100, y as u8 * 100, 7]))
+    }
+
+    /// Colour of a pixel in the transformed image
+    fn colour_at(image: &RgbImage, x: u32, y: u32) -> [u8; 3] {
+        image.get_pixel(x, y).0
+    }
+
+    #[test]
+    fn test_each_rotation() {
+        let top_left = [0, 0, 7];
+        let top_right = [200, 0, 7];
+
+        let cases = [
+            (0, (3, 2), (0, 0), (2, 0)),
+            // Clockwise: the top row becomes the right-hand column, read downwards
+            (90, (2, 3), (1, 0), (1, 2)),
+            (180, (3, 2), (2, 1), (0, 1)),
+            (270, (2, 3), (0, 2), (0, 0)),
+        ];
+        for (degrees, size, top_left_at, top_right_at) in cases {
+            let orientation = Orientation {
+                rotation: Rotation::try_from(degrees).expect("rotation should be valid"),
+                mirror: false,
+            };
+            let image = orientation.apply(asymmetric());
+            assert_eq!(image.dimensions(), size, "{degrees}");
+            assert_eq!(
+                colour_at(&image, top_left_at.0, top_left_at.1),
+                top_left,
+                "{degrees}"
+            );
+            assert_eq!(
+                colour_at(&image, top_right_at.0, top_right_at.1),
+                top_right,
+                "{degrees}"
+            );
+        }
+        assert!(Rotation::try_from(45).is_err());
+    }
+
+    #[test]
+    fn test_mirror_after_rotation() {
+        let orientation = Orientation {
+            rotation: Rotation::Clockwise90,
+            mirror: true,
+        };
+        let image = orientation.apply(asymmetric());
+        // Rotated puts the original top-left at (1, 0); mirroring moves it to (0, 0)
+        assert_eq!(colour_at(&image, 0, 0), [0, 0, 7]);
+        assert_eq!(colour_at(&image, 0, 2), [200, 0, 7]);
+
+        let json = serde_json::to_string(&orientation).expect("orientation should serialise");
+        assert_eq!(json, r#"{"rotation":90,"mirror":true}"#);
+        assert!(serde_json::from_str::<Orientation>(r#"{"rotation":45,"mirror":false}"#).is_err());
+    }
+
+    #[test]
+    fn test_jpeg_round_trip() {
+        let jpeg = encode_jpeg(
+            &RgbImage::from_pixel(8, 4, image::Rgb([10, 200, 10])),
+            DEFAULT_JPEG_QUALITY,
+        )
+        .expect("JPEG should encode");
+        let untouched = Orientation::default()
+            .apply_to_jpeg(jpeg.clone())
+            .expect("identity should pass through");
+        assert_eq!(untouched, jpeg);
+
+        let rotated = Orientation {
+            rotation: Rotation::Clockwise270,
+            mirror: false,
+        }
+        .apply_to_jpeg(jpeg)
+        .expect("JPEG should transform");
+        let decoded = image::load_from_memory(&rotated).expect("result should decode");
+        assert_eq!((decoded.width(), decoded.height()), (4, 8));
+    }
+}
@@ -0,0 +1,210 @@
+//! A rolling buffer of recent USB stream frames, so a capture can use the frame
+//! from just before it was triggered.
+//!
+//! Cases bounce as they land under the cameras, and by the time the sensor has
+//! fired and the capture runs the case may have turned out of its best
+//! orientation. While a camera streams, each encoded frame is kept with the
+//! time it was taken, up to `pre_capture_buffer_frames` per camera, and a
+//! capture takes the frame closest to `capture_offset_ms` before it was
+//! requested. A buffer never holds more than its frame count. When buffering
+//! is off, the buffer is empty or nothing in it is near the wanted time, the
+//! capture grabs a live frame instead.
+
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+
+use crate::config::Settings;
+
+/// How far before the capture request the wanted frame is, unless configured otherwise
+pub const DEFAULT_CAPTURE_OFFSET: Duration = Duration::from_millis(200);
+
+/// Most frames a buffer may be configured to hold per camera
+pub const MAX_BUFFER_FRAMES: usize = 120;
+
+/// A buffered frame further than this from the wanted time isn't used
+pub const MAX_FRAME_DISTANCE: Duration = Duration::from_millis(500);
+
+/// How many frames to keep and which one a capture wants
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PreCapture {
+    /// Frames kept per camera, 0 to capture live every time
+    pub frames: usize,
+    /// How long before the capture request the wanted frame was taken
+    pub offset: Duration,
+}
+
+impl PreCapture {
+    /// The buffer configured by `pre_capture_buffer_frames` and `capture_offset_ms`
+    pub fn from_settings(settings: &Settings) -> Self {
+        Self {
+            frames: settings.pre_capture_buffer_frames.min(MAX_BUFFER_FRAMES),
+            offset: Duration::from_millis(settings.capture_offset_ms),
+        }
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.frames > 0
+    }
+}
+
+impl Default for PreCapture {
+    fn default() -> Self {
+        Self {
+            frames: 0,
+            offset: DEFAULT_CAPTURE_OFFSET,
+        }
+    }
+}
+
+/// How full a camera's buffer is and the memory it uses
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct PreCaptureStats {
+    pub frames: usize,
+    pub capacity: usize,
+    /// Bytes of the frames held now
+    pub bytes: u64,
+    /// Bytes a full buffer would hold at the current average frame size
+    pub estimated_full_bytes: u64,
+}
+
+struct BufferedFrame {
+    taken_at: Instant,
+    jpeg: Vec<u8>,
+}
+
+/// The last few encoded frames from one camera, oldest first
+pub struct FrameRing {
+    capacity: usize,
+    frames: VecDeque<BufferedFrame>,
+    bytes: usize,
+}
+
+impl FrameRing {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            frames: VecDeque::with_capacity(capacity),
+            bytes: 0,
+        }
+    }
+
+    /// Keep a frame taken at `taken_at`, dropping the oldest once the buffer is full
+    pub fn push(&mut self, jpeg: Vec<u8>, taken_at: Instant) {
+        if self.capacity == 0 {
+            return;
+        }
+        while self.frames.len() >= self.capacity {
+            if let Some(oldest) = self.frames.pop_front() {
+                self.bytes -= oldest.jpeg.len();
+            }
+        }
+        self.bytes += jpeg.len();
+        self.frames.push_back(BufferedFrame { taken_at, jpeg });
+    }
+
+    /// The frame taken closest to `target`, if one is within [`MAX_FRAME_DISTANCE`]
+    ///
+    /// On a tie the earlier frame wins, since it's nearer the moment the case landed.
+    pub fn closest_to(&self, target: Instant) -> Option<&[u8]> {
+        let distance = |frame: &BufferedFrame| {
+            frame
+                .taken_at
+                .checked_duration_since(target)
+                .unwrap_or_else(|| target.duration_since(frame.taken_at))
+        };
+        self.frames
+            .iter()
+            .map(|frame| (distance(frame), frame))
+            .filter(|(distance, _)| *distance <= MAX_FRAME_DISTANCE)
+            .min_by_key(|(distance, _)| *distance)
+            .map(|(_, frame)| frame.jpeg.as_slice())
+    }
+
+    pub fn stats(&self) -> PreCaptureStats {
+        let average = self
+            .bytes
+            .checked_div(self.frames.len())
+            .unwrap_or_default();
+        PreCaptureStats {
+            frames: self.frames.len(),
+            capacity: self.capacity,
+            bytes: self.bytes as u64,
+            estimated_full_bytes: (average * self.capacity) as u64,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MS: Duration = Duration::from_millis(1);
+
+    /// A ring holding one frame per `interval`, each frame's byte a label
+    fn ring_of(capacity: usize, count: u8, interval: Duration, start: Instant) -> FrameRing {
+        let mut ring = FrameRing::new(capacity);
+        for label in 0..count {
+            ring.push(vec![label; 10], start + interval * u32::from(label));
+        }
+        ring
+    }
+
+    #[test]
+    fn test_closest_frame_is_chosen() {
+        let start = Instant::now();
+        // Frames every 33ms at 0, 33, 66 ... 297ms
+        let ring = ring_of(10, 10, 33 * MS, start);
+
+        for (target_ms, expected) in [
+            (0, 0),
+            (40, 1),
+            (50, 2), // 17ms past frame 1, 16ms before frame 2
+            (99, 3),
+            (700, 9),
+        ] {
+            let frame = ring
+                .closest_to(start + target_ms * MS)
+                .map(|jpeg| jpeg[0])
+                .expect("a frame is in range");
+            assert_eq!(frame, expected, "target {target_ms}ms");
+        }
+        // Frames equally far either side go to the earlier one
+        let tie = ring_of(2, 2, 100 * MS, start);
+        assert_eq!(tie.closest_to(start + 50 * MS).map(|jpeg| jpeg[0]), Some(0));
+
+        // Nothing near the target falls back to a live grab
+        assert_eq!(ring.closest_to(start + 2000 * MS), None);
+        assert_eq!(FrameRing::new(10).closest_to(start), None);
+    }
+
+    #[test]
+    fn test_ring_is_bounded() {
+        let start = Instant::now();
+        let ring = ring_of(4, 10, 10 * MS, start);
+
+        // Only the last four frames are kept
+        assert_eq!(ring.closest_to(start).map(|jpeg| jpeg[0]), Some(6));
+        assert_eq!(
+            ring.stats(),
+            PreCaptureStats {
+                frames: 4,
+                capacity: 4,
+                bytes: 40,
+                estimated_full_bytes: 40,
+            }
+        );
+
+        let mut half_full = FrameRing::new(6);
+        half_full.push(vec![0; 100], start);
+        half_full.push(vec![0; 300], start + MS);
+        assert_eq!(half_full.stats().bytes, 400);
+        assert_eq!(half_full.stats().estimated_full_bytes, 1200);
+
+        let mut disabled = FrameRing::new(0);
+        disabled.push(vec![0; 100], start);
+        assert_eq!(disabled.stats(), PreCaptureStats::default());
+    }
+}
@@ -0,0 +1,141 @@
+//! Request/response plumbing between handles and the manager tasks behind them.
+//!
+//! Each manager owns an unbounded request channel, and requests that want an
+//! answer carry a [`oneshot::Sender`] for it. [`request`] builds the oneshot,
+//! sends the request and waits for the reply, so a handle method only has to
+//! say which request variant to send.
+
+use std::time::Duration;
+
+use tokio::sync::{mpsc, oneshot};
+
+use crate::server_timing;
+use crate::{OurError, OurResult};
+
+/// How long an API request waits on a hardware-facing manager by default.
+///
+/// Kept well under the 30-60 second timeouts reverse proxies use, so the
+/// client gets the server's error rather than the proxy's.
+pub const DEFAULT_HARDWARE_REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Send the request `build` makes around a responder to `manager` and wait for its answer
+pub async fn request<M, R>(
+    sender: &mpsc::UnboundedSender<M>,
+    manager: &str,
+    build: impl FnOnce(oneshot::Sender<R>) -> M,
+) -> OurResult<R> {
+    let (respond_to, receiver) = oneshot::channel();
+    sender
+        .send(build(respond_to))
+        .map_err(|_| OurError::App(format!("{manager} channel closed")))?;
+    server_timing::time(server_timing::ACTOR, Some(manager), receiver)
+        .await
+        .map_err(|_| OurError::App(format!("{manager} response failed")))
+}
+
+/// [`request`], giving up if `manager` hasn't answered within `timeout`
+pub async fn request_with_timeout<M, R>(
+    sender: &mpsc::UnboundedSender<M>,
+    manager: &str,
+    timeout: Duration,
+    build: impl FnOnce(oneshot::Sender<R>) -> M,
+) -> OurResult<R> {
+    tokio::time::timeout(timeout, request(sender, manager, build))
+        .await
+        .map_err(|_| {
+            OurError::App(format!(
+                "{manager} did not respond within {}ms",
+                timeout.as_millis()
+            ))
+        })?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug)]
+    enum TestRequest {
+        Double {
+            value: u32,
+            respond_to: oneshot::Sender<u32>,
+        },
+    }
+
+    #[tokio::test]
+    async fn test_request_round_trip_and_closed_channel() {
+        let (sender, mut receiver) = mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            while let Some(TestRequest::Double { value, respond_to }) = receiver.recv().await {
+                let _ = respond_to.send(value * 2);
+            }
+        });
+        let doubled = request(&sender, "Test manager", |respond_to| TestRequest::Double {
+            value: 21,
+            respond_to,
+        })
+        .await
+        .expect("manager should answer");
+        assert_eq!(doubled, 42);
+
+        // A manager that has gone away
+        let (sender, receiver) = mpsc::unbounded_channel();
+        drop(receiver);
+        let error = request(&sender, "Test manager", |respond_to| TestRequest::Double {
+            value: 1,
+            respond_to,
+        })
+        .await
+        .expect_err("closed channel should fail");
+        assert!(
+            error.to_string().ends_with("Test manager channel closed"),
+            "{error}"
+        );
+
+        // A manager that drops the request without answering
+        let (sender, mut receiver) = mpsc::unbounded_channel::<TestRequest>();
+        tokio::spawn(async move { while receiver.recv().await.is_some() {} });
+        let error = request(&sender, "Test manager", |respond_to| TestRequest::Double {
+            value: 1,
+            respond_to,
+        })
+        .await
+        .expect_err("dropped responder should fail");
+        assert!(
+            error.to_string().ends_with("Test manager response failed"),
+            "{error}"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_request_with_timeout_gives_up() {
+        // Keep the requests, and so their responders, alive without answering
+        let (sender, mut receiver) = mpsc::unbounded_channel::<TestRequest>();
+        let held = tokio::spawn(async move {
+            let mut held = Vec::new();
+            while let Some(request) = receiver.recv().await {
+                held.push(request);
+            }
+            held.len()
+        });
+        let error = request_with_timeout(
+            &sender,
+            "Test manager",
+            Duration::from_millis(20),
+            |respond_to| TestRequest::Double {
+                value: 1,
+                respond_to,
+            },
+        )
+        .await
+        .expect_err("silent manager should time out");
+        assert!(
+            error
+                .to_string()
+                .ends_with("Test manager did not respond within 20ms"),
+            "{error}"
+        );
+        drop(sender);
+        assert_eq!(held.await.expect("task should finish"), 1);
+    }
+}
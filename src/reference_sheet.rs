@@ -0,0 +1,380 @@
+//! A printable sheet with one example image of each case type.
+//!
+//! Each case type gets a tile in a grid: its sharpest reference image, or
+//! without references the preferred frame of its most recent training shell,
+//! scaled to fit and labelled with the brand and designation. Case types with
+//! neither get a grey tile, so the sheet still lists every type. The rendered
+//! JPEG is cached under a key built from the case types and the size and age of
+//! every image that could be picked, so adding a case type or reference image
+//! draws the sheet again.
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::UNIX_EPOCH;
+
+use ab_glyph::FontRef;
+use image::{DynamicImage, Rgb, RgbImage};
+use imageproc::drawing::{draw_text_mut, text_size};
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use crate::burst;
+use crate::composite::{LABEL_FONT, PLACEHOLDER_GREY, SlotRect, draw_fitted, fill};
+use crate::ml_training::CaseType;
+use crate::shell_data::Shell;
+use crate::training_runs::content_hash;
+use crate::{OurError, OurResult};
+
+/// Columns in the grid when the request doesn't say
+pub const DEFAULT_SHEET_COLUMNS: u32 = 4;
+/// Most columns a sheet can have
+pub const MAX_SHEET_COLUMNS: u32 = 12;
+/// Size of each case type's image on the sheet
+pub const TILE_WIDTH: u32 = 320;
+pub const TILE_HEIGHT: u32 = 240;
+/// Band under each image holding its label
+pub const LABEL_HEIGHT: u32 = 36;
+const LABEL_TEXT_HEIGHT: f32 = 20.0;
+
+/// What the sheet is drawn as
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SheetFormat {
+    #[default]
+    #[serde(alias = "jpeg")]
+    Jpg,
+    Pdf,
+}
+
+/// An image that could stand for a case type on the sheet
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SheetImage {
+    pub path: PathBuf,
+    /// Crop before scaling, as (x, y, width, height)
+    pub region: Option<(i32, i32, i32, i32)>,
+}
+
+/// A case type's tile on the sheet
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SheetEntry {
+    pub case_type: String,
+    pub designation: String,
+    pub brand: Option<String>,
+    /// Reference images to pick the sharpest of, or the one training frame
+    pub candidates: Vec<SheetImage>,
+}
+
+impl SheetEntry {
+    /// The text under the tile
+    pub fn label(&self) -> String {
+        match &self.brand {
+            Some(brand) => format!("{brand} {}", self.designation),
+            None => self.designation.clone(),
+        }
+    }
+}
+
+/// The sheet's tiles, one per case type in name order
+///
+/// `shells` are the training shells, searched for the most recent one of each
+/// case type without reference images.
+pub fn sheet_entries(
+    case_types: &HashMap<String, CaseType>,
+    shells: &[(String, Shell)],
+    image_directory: &Path,
+) -> Vec<SheetEntry> {
+    let mut entries: Vec<SheetEntry> = case_types
+        .values()
+        .map(|case_type| {
+            let mut candidates: Vec<SheetImage> = case_type
+                .reference_images
+                .iter()
+                .filter(|path| path.is_file())
+                .map(|path| SheetImage {
+                    path: path.clone(),
+                    region: None,
+                })
+                .collect();
+            if candidates.is_empty() {
+                candidates.extend(latest_training_frame(
+                    &case_type.name,
+                    shells,
+                    image_directory,
+                ));
+            }
+            SheetEntry {
+                case_type: case_type.name.clone(),
+                designation: case_type.designation.clone(),
+                brand: case_type.brand.clone(),
+                candidates,
+            }
+        })
+        .collect();
+    entries.sort_by(|a, b| a.case_type.cmp(&b.case_type));
+    entries
+}
+
+/// The preferred frame of the most recent training shell of the case type
+fn latest_training_frame(
+    case_type: &str,
+    shells: &[(String, Shell)],
+    image_directory: &Path,
+) -> Option<SheetImage> {
+    let (_, shell) = shells
+        .iter()
+        .filter(|(_, shell)| shell.get_case_type_key() == case_type)
+        .max_by_key(|(_, shell)| shell.date_captured)?;
+    let mut frames: Vec<_> = shell.training_images().collect();
+    // Burst sessions mark their sharpest frames preferred
+    frames.sort_by_key(|image| !image.preferred);
+    frames.into_iter().find_map(|image| {
+        let path = image_directory.join(&image.filename);
+        path.is_file().then(|| SheetImage {
+            path,
+            region: image.get_region().as_rect(),
+        })
+    })
+}
+
+/// Cache key for a sheet of these tiles, changing whenever a tile would
+pub fn sheet_key(entries: &[SheetEntry], columns: u32) -> OurResult<String> {
+    // Size and modification time stand in for the image contents
+    let stamps: Vec<Vec<(u64, u128)>> = entries
+        .iter()
+        .map(|entry| {
+            entry
+                .candidates
+                .iter()
+                .map(|candidate| {
+                    fs::metadata(&candidate.path)
+                        .map(|metadata| {
+                            let modified = metadata
+                                .modified()
+                                .ok()
+                                .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+                                .map_or(0, |since| since.as_nanos());
+                            (metadata.len(), modified)
+                        })
+                        .unwrap_or_default()
+                })
+                .collect()
+        })
+        .collect();
+    Ok(content_hash(&serde_json::to_vec(&(
+        entries, stamps, columns,
+    ))?))
+}
+
+/// The sharpest candidate that decodes, cropped to its region
+fn best_image(candidates: &[SheetImage]) -> Option<DynamicImage> {
+    let mut best: Option<(f64, DynamicImage)> = None;
+    for candidate in candidates {
+        let loaded = fs::read(&candidate.path)
+            .map_err(OurError::from)
+            .and_then(|bytes| {
+                let image = image::load_from_memory(&bytes)?;
+                // A single candidate needs no score
+                let score = if candidates.len() > 1 {
+                    burst::sharpness(&bytes)?
+                } else {
+                    0.0
+                };
+                Ok((score, image))
+            });
+        match loaded {
+            Ok((score, image)) if best.as_ref().is_none_or(|(best, _)| score > *best) => {
+                let image = match candidate.region {
+                    Some((x, y, width, height)) => image.crop_imm(
+                        x.max(0) as u32,
+                        y.max(0) as u32,
+                        width.max(1) as u32,
+                        height.max(1) as u32,
+                    ),
+                    None => image,
+                };
+                best = Some((score, image));
+            }
+            Ok(_) => {}
+            Err(e) => warn!(
+                "Left {} off the reference sheet: {e}",
+                candidate.path.display()
+            ),
+        }
+    }
+    best.map(|(_, image)| image)
+}
+
+/// Lay the tiles out in a grid `columns` wide, each labelled underneath
+pub fn render_sheet(entries: &[SheetEntry], columns: u32) -> OurResult<RgbImage> {
+    if entries.is_empty() {
+        return Err(OurError::App(
+            "There are no case types to put on the reference sheet".to_string(),
+        ));
+    }
+    if !(1..=MAX_SHEET_COLUMNS).contains(&columns) {
+        return Err(OurError::App(format!(
+            "The reference sheet needs 1 to {MAX_SHEET_COLUMNS} columns, not {columns}"
+        )));
+    }
+    let font = FontRef::try_from_slice(LABEL_FONT)
+        .map_err(|e| OurError::App(format!("The label font couldn't be loaded: {e}")))?;
+
+    let count = u32::try_from(entries.len())
+        .map_err(|_| OurError::App("Too many case types for one sheet".to_string()))?;
+    let used_columns = columns.min(count);
+    let rows = count.div_ceil(columns);
+    let cell_height = TILE_HEIGHT + LABEL_HEIGHT;
+    let mut sheet = RgbImage::from_pixel(
+        used_columns * TILE_WIDTH,
+        rows * cell_height,
+        Rgb([255, 255, 255]),
+    );
+    for (index, entry) in (0..count).zip(entries) {
+        let x = (index % columns) * TILE_WIDTH;
+        let y = (index / columns) * cell_height;
+        let tile = SlotRect {
+            x,
+            y,
+            width: TILE_WIDTH,
+            height: TILE_HEIGHT,
+        };
+        match best_image(&entry.candidates) {
+            Some(image) => draw_fitted(&mut sheet, &image, tile),
+            None => fill(&mut sheet, tile, PLACEHOLDER_GREY),
+        }
+
+        let label = entry.label();
+        let (text_width, _) = text_size(LABEL_TEXT_HEIGHT, &font, &label);
+        let text_x = x + TILE_WIDTH.saturating_sub(text_width) / 2;
+        let text_y = y + TILE_HEIGHT + (LABEL_HEIGHT - LABEL_TEXT_HEIGHT as u32) / 2;
+        draw_text_mut(
+            &mut sheet,
+            Rgb([0, 0, 0]),
+            text_x as i32,
+            text_y as i32,
+            LABEL_TEXT_HEIGHT,
+            &font,
+            &label,
+        );
+    }
+    Ok(sheet)
+}
+
+/// A drawn sheet as a JPEG, with the key it was drawn for
+#[derive(Debug)]
+struct CachedSheet {
+    key: String,
+    jpeg: Arc<Vec<u8>>,
+}
+
+/// The last sheet drawn
+#[derive(Debug, Clone, Default)]
+pub struct ReferenceSheetCache(Arc<Mutex<Option<CachedSheet>>>);
+
+impl ReferenceSheetCache {
+    /// The cached sheet, if it was drawn for `key`
+    pub fn get(&self, key: &str) -> Option<Arc<Vec<u8>>> {
+        self.0.lock().ok().and_then(|cached| match cached.as_ref() {
+            Some(cached) if cached.key == key => Some(cached.jpeg.clone()),
+            _ => None,
+        })
+    }
+
+    pub fn store(&self, key: String, jpeg: Arc<Vec<u8>>) {
+        if let Ok(mut cached) = self.0.lock() {
+            *cached = Some(CachedSheet { key, jpeg });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{DEFAULT_FIXTURE_SEED, FixtureBuilder};
+
+    #[test]
+    fn test_sheet_grid_fits_the_case_types() {
+        let temp_dir = tempfile::TempDir::new().expect("temp dir should be created");
+        let images = temp_dir.path().join("images");
+        let fixtures = FixtureBuilder::new(DEFAULT_FIXTURE_SEED)
+            .shells(5)
+            .case_types([
+                ("Winchester", "9mm"),
+                ("Federal", "308win"),
+                ("Remington", "45acp"),
+                ("Hornady", "223rem"),
+                ("PMC", "40sw"),
+            ])
+            .image_size(96, 72)
+            .with_case_types_file()
+            .write(&temp_dir.path().join("data"), &images)
+            .expect("fixtures should be written");
+        let mut case_types: HashMap<String, CaseType> = fixtures
+            .case_types
+            .iter()
+            .map(|case_type| (case_type.name.clone(), case_type.clone()))
+            .collect();
+        // References win over training frames; a missing one is skipped
+        let federal = case_types
+            .get_mut("Federal_308win")
+            .expect("case type should exist");
+        federal.reference_images = vec![fixtures.images[0].clone(), images.join("gone.jpg")];
+        case_types.insert(
+            "Lapua_65cm".to_string(),
+            CaseType::new(
+                "Lapua_65cm".to_string(),
+                "65cm".to_string(),
+                Some("Lapua".to_string()),
+            ),
+        );
+
+        let entries = sheet_entries(&case_types, &fixtures.shells, &images);
+        assert_eq!(entries.len(), 6);
+        assert_eq!(entries[0].case_type, "Federal_308win");
+        assert_eq!(entries[0].candidates[0].path, fixtures.images[0]);
+        assert_eq!(entries[0].candidates.len(), 1);
+        assert!(entries[0].candidates[0].region.is_none());
+        let lapua = entries
+            .iter()
+            .find(|entry| entry.case_type == "Lapua_65cm")
+            .expect("entry should exist");
+        assert!(lapua.candidates.is_empty());
+        assert_eq!(lapua.label(), "Lapua 65cm");
+        let pmc = entries
+            .iter()
+            .find(|entry| entry.case_type == "PMC_40sw")
+            .expect("entry should exist");
+        assert!(pmc.candidates[0].region.is_some());
+
+        let sheet = render_sheet(&entries, 4).expect("sheet should render");
+        assert_eq!(
+            sheet.dimensions(),
+            (4 * TILE_WIDTH, 2 * (TILE_HEIGHT + LABEL_HEIGHT))
+        );
+        // The case type without an image gets a grey tile
+        let lapua_index = entries
+            .iter()
+            .position(|entry| entry.case_type == "Lapua_65cm")
+            .expect("entry should exist") as u32;
+        let (x, y) = (
+            (lapua_index % 4) * TILE_WIDTH,
+            (lapua_index / 4) * (TILE_HEIGHT + LABEL_HEIGHT),
+        );
+        assert_eq!(sheet.get_pixel(x + 5, y + 5), &Rgb(PLACEHOLDER_GREY));
+
+        let narrow = render_sheet(&entries[..2], 4).expect("sheet should render");
+        assert_eq!(
+            narrow.dimensions(),
+            (2 * TILE_WIDTH, TILE_HEIGHT + LABEL_HEIGHT)
+        );
+        assert!(render_sheet(&[], 4).is_err());
+        assert!(render_sheet(&entries, 0).is_err());
+
+        let key = sheet_key(&entries, 4).expect("key should hash");
+        assert_eq!(key, sheet_key(&entries, 4).expect("key should hash"));
+        assert_ne!(key, sheet_key(&entries, 3).expect("key should hash"));
+        assert_ne!(key, sheet_key(&entries[1..], 4).expect("key should hash"));
+    }
+}
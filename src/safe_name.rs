@@ -0,0 +1,141 @@
+//! Names that are safe to use as a single filesystem path component.
+//!
+//! Case type names become directory names, session ids become `{id}.json`
+//! and image filenames arrive from clients, so every one of them goes through
+//! [`SafeName`] before it is joined onto a data directory. Anything that could
+//! climb out of the directory (separators, `..`) or hide a file (a leading
+//! `.`) is rejected rather than cleaned up, so a name always refers to exactly
+//! the file the caller asked for.
+
+use std::fmt;
+use std::path::Path;
+
+use serde::Serialize;
+
+use crate::{OurError, OurResult};
+
+/// Longest name accepted, well under any filesystem's component limit
+pub const MAX_NAME_LENGTH: usize = 128;
+
+/// A validated single path component
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize)]
+#[serde(transparent)]
+pub struct SafeName(String);
+
+impl SafeName {
+    /// Accept ASCII letters, digits, `-` and `_` only
+    ///
+    /// `field` names the input in the error, so a client can tell which part
+    /// of its request was refused.
+    pub fn new(field: &str, value: &str) -> OurResult<Self> {
+        Self::check(field, value, |c| {
+            c.is_ascii_alphanumeric() || c == '-' || c == '_'
+        })?;
+        Ok(Self(value.to_string()))
+    }
+
+    /// As [`SafeName::new`] but also allowing `.`, for names with an extension
+    ///
+    /// A leading `.` and `..` anywhere are still refused.
+    pub fn file_name(field: &str, value: &str) -> OurResult<Self> {
+        Self::check(field, value, |c| {
+            c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.'
+        })?;
+        if value.starts_with('.') {
+            return Err(invalid(field, "must not start with '.'"));
+        }
+        if value.contains("..") {
+            return Err(invalid(field, "must not contain '..'"));
+        }
+        Ok(Self(value.to_string()))
+    }
+
+    fn check(field: &str, value: &str, allowed: impl Fn(char) -> bool) -> OurResult<()> {
+        if value.is_empty() {
+            return Err(invalid(field, "must not be empty"));
+        }
+        if value.chars().count() > MAX_NAME_LENGTH {
+            return Err(invalid(
+                field,
+                &format!("must be at most {MAX_NAME_LENGTH} characters"),
+            ));
+        }
+        if let Some(c) = value.chars().find(|c| !allowed(*c)) {
+            return Err(invalid(field, &format!("{value:?} must not contain {c:?}")));
+        }
+        Ok(())
+    }
+
+    /// The validated name
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+fn invalid(field: &str, reason: &str) -> OurError {
+    OurError::InvalidName {
+        field: field.to_string(),
+        reason: reason.to_string(),
+    }
+}
+
+impl fmt::Display for SafeName {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl AsRef<str> for SafeName {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl AsRef<Path> for SafeName {
+    fn as_ref(&self) -> &Path {
+        Path::new(&self.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_names() {
+        for good in [
+            "Winchester_9mm",
+            "45-acp",
+            "a",
+            &"x".repeat(MAX_NAME_LENGTH),
+        ] {
+            assert!(SafeName::new("name", good).is_ok(), "{good:?}");
+        }
+        for bad in [
+            "",
+            "../../tmp/evil",
+            "a/b",
+            "a\\b",
+            ".hidden",
+            "with space",
+            "nul\0",
+            "café",
+            &"x".repeat(MAX_NAME_LENGTH + 1),
+        ] {
+            assert!(SafeName::new("name", bad).is_err(), "{bad:?}");
+        }
+
+        let error = SafeName::new("session_id", "../x").expect_err("traversal should fail");
+        assert!(error.to_string().contains("session_id"), "{error}");
+    }
+
+    #[test]
+    fn test_file_names() {
+        for good in ["abc_camera_0.jpg", "shot.v2.png"] {
+            assert!(SafeName::file_name("filename", good).is_ok(), "{good:?}");
+        }
+        for bad in ["..", "../a.jpg", "a..jpg", ".jpg", "a/b.jpg", "/etc/passwd"] {
+            assert!(SafeName::file_name("filename", bad).is_err(), "{bad:?}");
+        }
+    }
+}
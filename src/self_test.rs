@@ -0,0 +1,548 @@
+//! Hardware dry cycle for commissioning a machine.
+//!
+//! Exercises every actuator and sensor on the controller once, without
+//! cameras or ML, and reports each step with its timing. Each request has its
+//! own timeout. If a motion step fails the rest of the sequence is abandoned
+//! and the vibration motor, flash and servos are returned to their safe state,
+//! with those recovery steps included in the report.
+
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Utc};
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+use tokio::io::AsyncWriteExt;
+use tracing::{info, warn};
+
+use crate::controller_entities::{ControllerEntities, ControllerEntity};
+use crate::controller_monitor::SERVO_MAX_POSITION;
+use crate::controller_recording::ControllerTransport;
+use crate::{OurError, OurResult};
+
+/// File in the data directory every self-test report is appended to
+pub const SELF_TEST_LOG_FILE: &str = "self-tests.jsonl";
+
+/// Default time allowed for each controller request
+pub const DEFAULT_STEP_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How long the vibration motor runs for
+const VIBRATION_PULSE: Duration = Duration::from_millis(300);
+
+/// Name of the step that feeds a case
+pub const NEXT_CASE_STEP: &str = "trigger next case";
+
+/// How a self-test is run
+#[derive(Debug, Clone)]
+pub struct SelfTestOptions {
+    /// Feed one case at the end
+    pub trigger_next_case: bool,
+    /// Time allowed for each controller request
+    pub step_timeout: Duration,
+}
+
+impl Default for SelfTestOptions {
+    fn default() -> Self {
+        Self {
+            trigger_next_case: false,
+            step_timeout: DEFAULT_STEP_TIMEOUT,
+        }
+    }
+}
+
+/// Result of a single step
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StepOutcome {
+    Passed,
+    Failed,
+    Skipped,
+}
+
+/// One step of the self-test
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct SelfTestStep {
+    pub name: String,
+    pub outcome: StepOutcome,
+    pub duration_ms: u64,
+    /// Sensor state, failure reason or why the step was skipped
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+}
+
+/// Everything the self-test did, in order
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct SelfTestReport {
+    pub started_at: DateTime<Utc>,
+    pub duration_ms: u64,
+    /// No step failed
+    pub passed: bool,
+    /// The motion step that failed and stopped the sequence, if one did
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub aborted_at: Option<String>,
+    pub steps: Vec<SelfTestStep>,
+}
+
+impl SelfTestReport {
+    /// One line for the event log
+    pub fn summary(&self) -> String {
+        let passed = self
+            .steps
+            .iter()
+            .filter(|step| step.outcome == StepOutcome::Passed)
+            .count();
+        let verdict = if self.passed { "passed" } else { "failed" };
+        match &self.aborted_at {
+            Some(step) => format!(
+                "Self-test {verdict}, aborted at {step:?} ({passed}/{} steps passed)",
+                self.steps.len()
+            ),
+            None => format!(
+                "Self-test {verdict} ({passed}/{} steps passed)",
+                self.steps.len()
+            ),
+        }
+    }
+}
+
+/// Why a request step didn't pass
+enum StepError {
+    /// The controller doesn't have the entity
+    Missing,
+    Failed(String),
+}
+
+/// Runs the sequence against one controller
+struct SelfTest<'a> {
+    transport: &'a ControllerTransport,
+    hostname: &'a str,
+    entities: &'a ControllerEntities,
+    timeout: Duration,
+    steps: Vec<SelfTestStep>,
+}
+
+impl SelfTest<'_> {
+    /// Send one request within the step timeout, returning the body
+    async fn request(&self, method: &str, path: &str) -> Result<String, StepError> {
+        let url = format!("http://{}/{path}", self.hostname);
+        match tokio::time::timeout(self.timeout, self.transport.send(method, &url)).await {
+            Err(_) => Err(StepError::Failed(format!(
+                "timed out after {}ms",
+                self.timeout.as_millis()
+            ))),
+            Ok(Err(e)) => Err(StepError::Failed(e.to_string())),
+            Ok(Ok(reply)) if reply.status == StatusCode::NOT_FOUND => Err(StepError::Missing),
+            Ok(Ok(reply)) if !reply.status.is_success() => {
+                Err(StepError::Failed(format!("HTTP error: {}", reply.status)))
+            }
+            Ok(Ok(reply)) => Ok(reply.body),
+        }
+    }
+
+    /// Run a request as a step and record it, returning whether it passed
+    async fn step(&mut self, name: String, method: &str, path: &str, optional: bool) -> bool {
+        let started = Instant::now();
+        let result = self.request(method, path).await;
+        let (outcome, detail) = match result {
+            Ok(_) => (StepOutcome::Passed, None),
+            Err(StepError::Missing) if optional => (
+                StepOutcome::Skipped,
+                Some("not present on the controller".to_string()),
+            ),
+            Err(StepError::Missing) => (StepOutcome::Failed, Some("HTTP error: 404".to_string())),
+            Err(StepError::Failed(e)) => (StepOutcome::Failed, Some(e)),
+        };
+        self.record(name, outcome, started, detail)
+    }
+
+    /// Read a binary sensor, which must answer ON or OFF
+    async fn read_sensor(&mut self, sensor: &ControllerEntity) {
+        let started = Instant::now();
+        let (outcome, detail) = match self.request("GET", &format!("{sensor}/state")).await {
+            Ok(body) => match body.trim().to_ascii_uppercase().as_str() {
+                state @ ("ON" | "OFF") => (StepOutcome::Passed, Some(state.to_string())),
+                _ => (
+                    StepOutcome::Failed,
+                    Some(format!("unexpected state {:?}", body.trim())),
+                ),
+            },
+            Err(StepError::Missing) => (StepOutcome::Failed, Some("HTTP error: 404".to_string())),
+            Err(StepError::Failed(e)) => (StepOutcome::Failed, Some(e)),
+        };
+        self.record(
+            format!("read {}", sensor.object_id),
+            outcome,
+            started,
+            detail,
+        );
+    }
+
+    fn record(
+        &mut self,
+        name: String,
+        outcome: StepOutcome,
+        started: Instant,
+        detail: Option<String>,
+    ) -> bool {
+        match outcome {
+            StepOutcome::Failed => warn!(
+                "Self-test step {name:?} failed: {}",
+                detail.as_deref().unwrap_or_default()
+            ),
+            _ => info!("Self-test step {name:?}: {outcome:?}"),
+        }
+        self.steps.push(SelfTestStep {
+            name,
+            outcome,
+            duration_ms: started.elapsed().as_millis() as u64,
+            detail,
+        });
+        outcome != StepOutcome::Failed
+    }
+
+    /// Every motion step, stopping at the first failure and returning its name
+    async fn motion(
+        &mut self,
+        servo_positions: &BTreeMap<String, u8>,
+        trigger_next_case: bool,
+    ) -> Option<String> {
+        let entities = self.entities;
+        let started = Instant::now();
+        let name = "pulse vibration motor".to_string();
+        let vibration = &entities.vibration_switch;
+        let pulsed = match self.request("POST", &format!("{vibration}/turn_on")).await {
+            Ok(_) => {
+                tokio::time::sleep(VIBRATION_PULSE).await;
+                self.request("POST", &format!("{vibration}/turn_off"))
+                    .await
+                    .map(|_| ())
+            }
+            Err(e) => Err(e),
+        };
+        let detail = match pulsed {
+            Ok(()) => None,
+            Err(StepError::Missing) => Some("HTTP error: 404".to_string()),
+            Err(StepError::Failed(e)) => Some(e),
+        };
+        let outcome = if detail.is_none() {
+            StepOutcome::Passed
+        } else {
+            StepOutcome::Failed
+        };
+        if !self.record(name.clone(), outcome, started, detail) {
+            return Some(name);
+        }
+
+        let sweep = [0, SERVO_MAX_POSITION / 2, SERVO_MAX_POSITION];
+        for (servo, saved) in servo_positions {
+            for position in sweep.into_iter().chain([*saved]) {
+                let name = format!("move {servo} to {position}");
+                let path = format!("number/{servo}/set?value={position}");
+                if !self.step(name.clone(), "POST", &path, false).await {
+                    return Some(name);
+                }
+            }
+        }
+
+        for state in ["on", "off"] {
+            let name = format!("turn flash {state}");
+            let Some(flash) = &entities.flash_light else {
+                self.record(
+                    name,
+                    StepOutcome::Skipped,
+                    Instant::now(),
+                    Some("no flash light configured".to_string()),
+                );
+                continue;
+            };
+            let path = format!("{flash}/turn_{state}");
+            if !self.step(name.clone(), "POST", &path, true).await {
+                return Some(name);
+            }
+        }
+
+        let name = NEXT_CASE_STEP.to_string();
+        if trigger_next_case {
+            let path = format!("{}/press", entities.next_case_button);
+            if !self.step(name.clone(), "POST", &path, false).await {
+                return Some(name);
+            }
+        } else {
+            self.record(
+                name,
+                StepOutcome::Skipped,
+                Instant::now(),
+                Some("not requested".to_string()),
+            );
+        }
+        None
+    }
+
+    /// Stop the motor and flash and put every servo back at its saved position
+    async fn make_safe(&mut self, servo_positions: &BTreeMap<String, u8>) {
+        let entities = self.entities;
+        self.step(
+            "safe: stop vibration motor".to_string(),
+            "POST",
+            &format!("{}/turn_off", entities.vibration_switch),
+            false,
+        )
+        .await;
+        if let Some(flash) = &entities.flash_light {
+            self.step(
+                "safe: turn flash off".to_string(),
+                "POST",
+                &format!("{flash}/turn_off"),
+                true,
+            )
+            .await;
+        }
+        for (servo, saved) in servo_positions {
+            self.step(
+                format!("safe: return {servo} to {saved}"),
+                "POST",
+                &format!("number/{servo}/set?value={saved}"),
+                false,
+            )
+            .await;
+        }
+    }
+}
+
+/// Run the self-test against the controller at `hostname`, using `entities`
+///
+/// Servos are swept from `servo_positions`, which are also the positions
+/// they are left at.
+pub async fn run(
+    transport: &ControllerTransport,
+    hostname: &str,
+    entities: &ControllerEntities,
+    servo_positions: &BTreeMap<String, u8>,
+    options: &SelfTestOptions,
+) -> SelfTestReport {
+    let started_at = Utc::now();
+    let started = Instant::now();
+    let mut test = SelfTest {
+        transport,
+        hostname,
+        entities,
+        timeout: options.step_timeout,
+        steps: Vec::new(),
+    };
+    info!("Starting controller self-test on {hostname}");
+
+    // Nothing else can work if the controller doesn't answer, and nothing has moved yet
+    let aborted_at = if test
+        .step("controller reachable".to_string(), "GET", "", false)
+        .await
+    {
+        for sensor in [&entities.case_ready_sensor, &entities.case_in_view_sensor] {
+            test.read_sensor(sensor).await;
+        }
+        let failed = test
+            .motion(servo_positions, options.trigger_next_case)
+            .await;
+        if failed.is_some() {
+            test.make_safe(servo_positions).await;
+        }
+        failed
+    } else {
+        Some("controller reachable".to_string())
+    };
+
+    let passed = test
+        .steps
+        .iter()
+        .all(|step| step.outcome != StepOutcome::Failed);
+    SelfTestReport {
+        started_at,
+        duration_ms: started.elapsed().as_millis() as u64,
+        passed,
+        aborted_at,
+        steps: test.steps,
+    }
+}
+
+/// Append a report to the self-test log as one JSON line
+pub async fn append_to_log(path: &Path, report: &SelfTestReport) -> OurResult<()> {
+    let mut line = serde_json::to_string(report)?;
+    line.push('\n');
+    let mut file = tokio::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .await
+        .map_err(|e| OurError::App(format!("Failed to open {}: {e}", path.display())))?;
+    file.write_all(line.as_bytes()).await?;
+    file.flush().await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::controller_recording::ControllerMode;
+    use axum::Router;
+    use axum::extract::{OriginalUri, State};
+    use std::sync::{Arc, Mutex};
+    use tokio::net::TcpListener;
+
+    /// A simulated controller that records every request and fails the ones matching `fail`
+    async fn simulated_controller(fail: &'static str) -> (String, Arc<Mutex<Vec<String>>>) {
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let device = Router::new()
+            .fallback(
+                move |State(requests): State<Arc<Mutex<Vec<String>>>>,
+                      method: axum::http::Method,
+                      OriginalUri(uri): OriginalUri| async move {
+                    let request = format!("{method} {uri}");
+                    requests
+                        .lock()
+                        .expect("lock should not be poisoned")
+                        .push(request.clone());
+                    if request.contains(fail) {
+                        (StatusCode::INTERNAL_SERVER_ERROR, "servo fault".to_string())
+                    } else if uri.path().starts_with("/light/") {
+                        (StatusCode::NOT_FOUND, String::new())
+                    } else if uri.path().starts_with("/binary_sensor/") {
+                        (StatusCode::OK, "OFF".to_string())
+                    } else {
+                        (StatusCode::OK, "OK".to_string())
+                    }
+                },
+            )
+            .with_state(requests.clone());
+        let listener = TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("listener should bind");
+        let hostname = listener
+            .local_addr()
+            .expect("listener should have an address")
+            .to_string();
+        tokio::spawn(async move { axum::serve(listener, device).await });
+        (hostname, requests)
+    }
+
+    fn positions() -> BTreeMap<String, u8> {
+        BTreeMap::from([("feeder".to_string(), 40), ("position".to_string(), 60)])
+    }
+
+    fn entities() -> ControllerEntities {
+        ControllerEntities::from_settings(&crate::config::Settings::default())
+    }
+
+    fn transport() -> ControllerTransport {
+        ControllerTransport::new(&ControllerMode::Live, 1.0).expect("transport should be created")
+    }
+
+    #[tokio::test]
+    async fn test_full_sequence_passes() {
+        let (hostname, requests) = simulated_controller("never").await;
+        let options = SelfTestOptions {
+            trigger_next_case: true,
+            ..SelfTestOptions::default()
+        };
+        let report = run(&transport(), &hostname, &entities(), &positions(), &options).await;
+
+        assert!(report.passed, "{report:#?}");
+        assert_eq!(report.aborted_at, None);
+        let outcome = |name: &str| {
+            report
+                .steps
+                .iter()
+                .find(|step| step.name == name)
+                .map(|step| step.outcome)
+        };
+        assert_eq!(
+            outcome("read case_ready_to_feed"),
+            Some(StepOutcome::Passed)
+        );
+        assert_eq!(outcome("move feeder to 100"), Some(StepOutcome::Passed));
+        assert_eq!(outcome("move position to 60"), Some(StepOutcome::Passed));
+        // The simulated controller has no flash light
+        assert_eq!(outcome("turn flash on"), Some(StepOutcome::Skipped));
+        assert_eq!(outcome("trigger next case"), Some(StepOutcome::Passed));
+
+        let requests = requests.lock().expect("lock should not be poisoned");
+        assert!(
+            requests
+                .iter()
+                .any(|request| request == "POST /button/trigger_next_case/press")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_motion_failure_aborts_and_makes_safe() {
+        let (hostname, requests) = simulated_controller("/number/feeder/set?value=100").await;
+        let report = run(
+            &transport(),
+            &hostname,
+            &entities(),
+            &positions(),
+            &SelfTestOptions {
+                trigger_next_case: true,
+                ..SelfTestOptions::default()
+            },
+        )
+        .await;
+
+        assert!(!report.passed);
+        assert_eq!(report.aborted_at.as_deref(), Some("move feeder to 100"));
+        let names: Vec<&str> = report.steps.iter().map(|step| step.name.as_str()).collect();
+        // Nothing after the failure ran, apart from making the machine safe
+        assert!(!names.contains(&"move position to 0"), "{names:?}");
+        assert!(!names.contains(&"trigger next case"), "{names:?}");
+        assert_eq!(
+            names[names.len() - 4..],
+            [
+                "safe: stop vibration motor",
+                "safe: turn flash off",
+                "safe: return feeder to 40",
+                "safe: return position to 60",
+            ]
+        );
+
+        let requests = requests.lock().expect("lock should not be poisoned");
+        assert!(
+            !requests
+                .iter()
+                .any(|request| request.contains("trigger_next_case"))
+        );
+        assert_eq!(
+            requests.last().map(String::as_str),
+            Some("POST /number/position/set?value=60")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_unreachable_controller_stops_immediately() {
+        let listener = TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("listener should bind");
+        let hostname = listener
+            .local_addr()
+            .expect("listener should have an address")
+            .to_string();
+        // Accepts connections but never answers
+        let _listener = listener;
+
+        let options = SelfTestOptions {
+            step_timeout: Duration::from_millis(100),
+            ..SelfTestOptions::default()
+        };
+        let report = run(&transport(), &hostname, &entities(), &positions(), &options).await;
+        assert!(!report.passed);
+        assert_eq!(report.steps.len(), 1);
+        assert_eq!(report.aborted_at.as_deref(), Some("controller reachable"));
+        assert!(
+            report.steps[0]
+                .detail
+                .as_deref()
+                .is_some_and(|detail| detail.contains("timed out")),
+            "{report:#?}"
+        );
+    }
+}
@@ -5,35 +5,266 @@ use askama_web::WebTemplate;
 use axum::{
     Router,
     body::Body,
-    extract::{Json as ExtractJson, Path, Request, State},
-    http::{HeaderValue, StatusCode},
+    extract::{
+        DefaultBodyLimit, Json as ExtractJson, Multipart, Path, Query, Request, State,
+        multipart::MultipartError,
+    },
+    handler::Handler,
+    http::{HeaderMap, HeaderValue, Method, StatusCode, Uri},
     middleware::{self, Next},
-    response::{Html, Json, Response},
-    routing::{delete, get, post},
+    response::{
+        Html, IntoResponse, Json, Response,
+        sse::{Event as SseEvent, KeepAlive, Sse},
+    },
+    routing::{MethodRouter, delete, get, patch, post, put},
 };
 use futures_util::StreamExt;
 use serde::{Deserialize, Serialize};
 use std::sync::{Arc, Mutex};
-use std::{collections::HashMap, num::NonZeroU16};
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    convert::Infallible,
+    num::NonZeroU16,
+    path::PathBuf,
+};
+use tokio::io::AsyncReadExt;
 use tokio::net::TcpListener;
-
-use tower_http::services::ServeDir;
-
-use crate::config::Settings;
-use crate::controller_monitor::{ControllerCommand, ControllerHandle, ControllerResponse};
-use crate::ml_training::MLTrainer;
-use crate::shell_data::{Shell, ShellDataManager};
+use tokio::sync::broadcast;
+use tokio_util::sync::CancellationToken;
+
+use crate::api::{ApiError, ApiResponse, ErrorCode};
+use crate::batches::{Batch, BatchExclusion, BatchList, batch_name_error};
+use crate::broadcast_lag::{LagCounters, LagStats, recv_skipping_lag};
+use crate::build_info::BuildInfo;
+use crate::burst::{self, BurstRequest};
+use crate::camera::{CameraId, CameraKind, Descriptor, IdsByKind};
+use crate::camera_inventory::{self, ImportReport, InventoryFormat};
+use crate::camera_lock::BusyPolicy;
+use crate::camera_manager::CameraHandle;
+use crate::camera_manager::{EspEntity, EspEntityState, EspSettingResult};
+use crate::camera_presets::{
+    CameraPreset, PresetCamera, PresetCameraResult, PresetCameraStatus, preset_name_error,
+    preset_named,
+};
+use crate::capture_overlay::CaptureOverlay;
+use crate::capture_preflight::{self, OfflineCamera, Preflight};
+use crate::capture_stats::CaptureStats;
+use crate::case_designation::{CaseDesignation, CaseDesignations};
+use crate::composite::CompositeLayout;
+use crate::config::{CameraConfig, Settings, UserConfig, ViewType};
+use crate::config_schema;
+use crate::config_writer::{ConfigWriter, ConfigWriterHandle, DEFAULT_COALESCE_WINDOW};
+use crate::controller_entities::FirmwareCheck;
+use crate::controller_monitor::{
+    CalibrationError, CalibrationStatus, ControllerCommand, ControllerHandle, ControllerProbe,
+    ControllerResponse, ControllerTimes, MaintenanceStatus,
+};
+use crate::cors::{self, CorsStatus};
+use crate::data_directories::DataDirectories;
+use crate::data_export::{self, ExportFormat, ExportSince, ImportedExport};
+use crate::data_usage::{self, MAX_SCAN_DEPTH, UsageCache, UsageReport};
+use crate::detection_gate::{DetectionGate, DetectionStats};
+use crate::disk_space::{DiskSpaceGuard, DiskSpaceReport};
+use crate::etag::{body_etag, hash_version, if_none_match, not_modified, version_etag, with_etag};
+use crate::event_log::{EventRecorder, RecordedEvent};
+use crate::frame_region::{FrameRegion, FrameSize};
+use crate::health_history::{HEALTH_HISTORY_FILENAME, HealthHistoryReport};
+use crate::heavy_work::{HeavyWork, HeavyWorkBusy, HeavyWorkUsage};
+use crate::idempotency::{self, IdempotencyCache, IdempotencyStats};
+use crate::image_ingest::{self, DEFAULT_JPEG_QUALITY, encode_jpeg};
+use crate::log_buffer::{LogBuffer, LogEntry, LogLevel};
+use crate::measurements::{
+    self, CaseTypeMeasurements, MeasurementWarning, check_measurements, measurement_error,
+};
+use crate::messages::{self, Catalogues, Message};
+use crate::ml_training::{
+    CaseType, CompositeBatchReport, DEFAULT_REQUIRED_VIEWS, MLTrainer, ModelMetadata,
+    ReconcileReport,
+};
+use crate::model_metrics::{ConfusedPair, ModelMetrics};
+use crate::orientation::{Orientation, configured_orientations};
+use crate::pre_capture::PreCaptureStats;
+use crate::reference_sheet::{
+    MAX_SHEET_COLUMNS, ReferenceSheetCache, SheetFormat, render_sheet, sheet_entries, sheet_key,
+};
+use crate::safe_name::SafeName;
+use crate::self_test::{
+    self, DEFAULT_STEP_TIMEOUT, SELF_TEST_LOG_FILE, SelfTestOptions, SelfTestReport,
+};
+use crate::server_timing::{self, DISK, SERIALIZE, SERVER_TIMING_HEADER};
+use crate::session_bundle::{self, ImportedSession, MAX_BUNDLE_BYTES, TempBundle};
+use crate::session_claims::{ClaimOutcome, MAX_CLAIMANT_LENGTH, SessionClaim, SessionClaims};
+use crate::session_summary::{self, CameraStatus, CameraSummary, SessionSummary};
+use crate::settings_snapshot::{ObservedCamera, SettingDifference, SettingsSnapshot};
+use crate::setup::SetupChoices;
+use crate::shell_data::{
+    CameraRegion, CameraSelector, CaptureCoverage, CapturedImage, MigrationReport,
+    RegionPropagation, RegionPropagationReport, RevisionCheck, SearchField, Shell,
+    ShellDataManager, ShellFilter, ShellFlag, ShellSummary, SkippedFile, notes_error,
+};
+use crate::shell_targets::{ShellTargets, TargetProgress};
+use crate::snapshot_stream::{EspStream, MIN_SNAPSHOT_POLL_INTERVAL, SnapshotStreams, StreamMode};
+use crate::static_assets::{
+    STATIC_DIRECTORY, StaticAssetSource, WebManifest, is_browser_probe, root_asset_router,
+    static_router,
+};
+use crate::stream_health::{FrameCounter, StreamHealth, StreamStalled};
+use crate::stream_limits::{StreamGuard, StreamLimiter};
+use crate::task_registry::{DEFAULT_SHUTDOWN_DEADLINE, TaskInfo, TaskRegistry};
+use crate::temperature::TemperatureReading;
+use crate::training_jobs::{TrainingJob, TrainingJobs, TrainingPhase, WORST_CONFUSED_PAIRS};
+use crate::trash::{Restore, TRASH_SWEEP_INTERVAL, Trash, TrashEntry, TrashRecord};
+use crate::upload::{self, MAX_JSON_BODY_BYTES, SpoolError, TempUpload};
 use crate::usb_camera_controller::UsbCameraHandle;
+use crate::usb_formats::DetectionReport;
+use crate::view_classifier::{self, ViewGuess};
+use crate::work_queue::{self, DEFAULT_WORK_QUEUE_LIMIT, WorkQueue};
 use crate::{OurError, OurResult};
-use crate::{camera_manager::CameraHandle, constants::USB_DEVICE_PREFIX_WITH_COLON};
-use std::time::{SystemTime, UNIX_EPOCH};
-use tracing::{error, info, instrument};
+use std::time::Duration;
+use tracing::{debug, error, info, instrument, warn};
+
+/// The manager a route waits on and how long it may take
+#[derive(Clone, Copy)]
+struct HardwareDeadline {
+    backend: &'static str,
+    timeout: Duration,
+}
+
+/// Middleware turning a handler stuck on a hardware-facing manager into a 504
+async fn hardware_deadline_middleware(
+    State(deadline): State<HardwareDeadline>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let path = request.uri().path().to_string();
+    match tokio::time::timeout(deadline.timeout, next.run(request)).await {
+        Ok(response) => response,
+        Err(_) => {
+            let message = format!(
+                "{} did not respond within {}ms",
+                deadline.backend,
+                deadline.timeout.as_millis()
+            );
+            warn!("{path}: {message}");
+            (
+                StatusCode::GATEWAY_TIMEOUT,
+                Json(ApiResponse::<()>::error(
+                    ErrorCode::ControllerTimeout,
+                    message,
+                )),
+            )
+                .into_response()
+        }
+    }
+}
+
+/// The 413 answer to a request body over `limit_bytes`
+fn payload_too_large<T: Serialize>(limit_bytes: usize) -> (StatusCode, Json<ApiResponse<T>>) {
+    (
+        StatusCode::PAYLOAD_TOO_LARGE,
+        Json(ApiResponse::error(
+            ErrorCode::PayloadTooLarge,
+            upload::too_large_message(limit_bytes),
+        )),
+    )
+}
+
+/// The answer to a multipart upload that couldn't be read
+fn multipart_rejection<T: Serialize>(
+    e: MultipartError,
+    limit_bytes: usize,
+) -> (StatusCode, Json<ApiResponse<T>>) {
+    if e.status() == StatusCode::PAYLOAD_TOO_LARGE {
+        return payload_too_large(limit_bytes);
+    }
+    warn!("Failed to read multipart upload: {e}");
+    (
+        StatusCode::BAD_REQUEST,
+        Json(ApiResponse::error(
+            ErrorCode::InvalidRequest,
+            format!("Invalid upload: {}", e.body_text()),
+        )),
+    )
+}
+
+/// Middleware giving axum's plain-text 413, from extractors that hit the body limit, the API envelope
+async fn body_limit_middleware(
+    State(limit_bytes): State<usize>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let path = request.uri().path().to_string();
+    let response = next.run(request).await;
+    let is_json = response
+        .headers()
+        .get(axum::http::header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("application/json"));
+    if response.status() != StatusCode::PAYLOAD_TOO_LARGE || is_json {
+        return response;
+    }
+    warn!("{path}: request body over the {limit_bytes} byte limit");
+    payload_too_large::<()>(limit_bytes).into_response()
+}
+
+/// Refuse a mutating route with 403 `read_only` without running its handler
+async fn read_only_middleware(request: Request, _next: Next) -> Response {
+    warn!(
+        "{} {}: refused, the server is read-only",
+        request.method(),
+        request.uri().path()
+    );
+    (
+        StatusCode::FORBIDDEN,
+        Json(ApiResponse::<()>::localized(
+            ErrorCode::ReadOnly,
+            Message::ServerReadOnly,
+        )),
+    )
+        .into_response()
+}
+
+/// Add a `Server-Timing` breakdown of the request when `debug` is on or the request asks for one
+async fn server_timing_middleware(
+    State(debug): State<bool>,
+    request: Request,
+    next: Next,
+) -> Response {
+    if !debug && !server_timing::requested(request.headers()) {
+        return next.run(request).await;
+    }
+    let (mut response, timing) = server_timing::collect(next.run(request)).await;
+    if let Some(value) = timing.header_value() {
+        response.headers_mut().insert(SERVER_TIMING_HEADER, value);
+    }
+    response
+}
+
+/// Render the request's API messages in the language its `Accept-Language` prefers
+async fn messages_middleware(
+    State(catalogues): State<Arc<Catalogues>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let catalogue = catalogues.negotiate(
+        request
+            .headers()
+            .get(axum::http::header::ACCEPT_LANGUAGE)
+            .and_then(|value| value.to_str().ok()),
+    );
+    messages::scope(catalogue, next.run(request)).await
+}
 
 /// Middleware to add no-cache headers to prevent browser caching
 async fn no_cache_middleware(request: Request, next: Next) -> Response {
     let path = request.uri().path().to_string();
     let mut response = next.run(request).await;
 
+    // Handlers that set their own caching policy, like camera snapshots, keep it
+    if response.headers().contains_key("Cache-Control") {
+        return response;
+    }
+
     // Get the headers map mutably
     let headers = response.headers_mut();
 
@@ -46,11 +277,9 @@ async fn no_cache_middleware(request: Request, next: Next) -> Response {
     headers.insert("Expires", HeaderValue::from_static("0"));
 
     // Generate ETag with current timestamp
-    if let Ok(timestamp) = SystemTime::now().duration_since(UNIX_EPOCH) {
-        let etag_value = format!("\"{}\"", timestamp.as_secs());
-        if let Ok(etag_header) = HeaderValue::from_str(&etag_value) {
-            headers.insert("ETag", etag_header);
-        }
+    let etag_value = format!("\"{}\"", chrono::Utc::now().timestamp());
+    if let Ok(etag_header) = HeaderValue::from_str(&etag_value) {
+        headers.insert("ETag", etag_header);
     }
 
     // Additional headers for static files (JS, CSS, HTML)
@@ -77,6 +306,77 @@ pub struct AppState {
     pub usb_camera_manager: Box<UsbCameraHandle>,
     pub ml_trainer: Arc<Mutex<MLTrainer>>,
     pub shell_data_manager: Arc<ShellDataManager>,
+    pub events: EventRecorder,
+    /// Recent log output, filled by the tracing layer registered in main
+    pub logs: LogBuffer,
+    pub stream_limiter: StreamLimiter,
+    /// Permits for composite batches, training, disk usage scans and reference sheets
+    pub heavy_work: HeavyWork,
+    /// ESPHome streams, and the snapshot pollers of cameras without one
+    pub snapshot_streams: SnapshotStreams,
+    pub disk_space: DiskSpaceGuard,
+    pub config_writer: ConfigWriterHandle,
+    pub camera_cache: CameraListCache,
+    /// The last disk usage scan of the image and data directories
+    pub data_usage: UsageCache,
+    /// Who is tagging which session
+    pub session_claims: SessionClaims,
+    /// Stalled streams, so open MJPEG responses can end and let the browser reconnect
+    pub stream_stalls: broadcast::Sender<StreamStalled>,
+    /// How often each broadcast subscriber fell behind
+    pub broadcast_lag: LagCounters,
+    pub tasks: TaskRegistry,
+    /// Recent model training jobs started through the API
+    pub training_jobs: TrainingJobs,
+    /// Responses to recent capture and next-case requests, for replaying retries
+    pub idempotency: IdempotencyCache,
+    /// The last reference sheet drawn
+    pub reference_sheet: ReferenceSheetCache,
+    /// Case type targets already reached, and the channel announcing new ones
+    pub shell_targets: ShellTargets,
+    /// Detection scans in progress or recent enough to reuse, per manager
+    pub detection: DetectionGates,
+    /// API message translations, see [`crate::messages`]
+    pub messages: Arc<Catalogues>,
+}
+
+/// How often open streams are checked for stalls
+const STREAM_HEALTH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How long the dashboard waits for the managers before rendering from cached data
+const DASHBOARD_MANAGER_TIMEOUT: Duration = Duration::from_millis(250);
+
+/// Each camera manager's detection gate, see [`crate::detection_gate`]
+#[derive(Clone, Default)]
+pub struct DetectionGates {
+    /// The USB report and its summary, which the response repeats when cached
+    usb: DetectionGate<(DetectionReport, String)>,
+    /// ESPHome detection finishes in the background, so only when it did is kept
+    esphome: DetectionGate<()>,
+}
+
+/// Scans and cached answers per camera manager, for `/api/health`
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "snake_case")]
+struct DetectionHealth {
+    usb: DetectionStats,
+    esphome: DetectionStats,
+}
+
+/// The last camera list the managers returned, for when they are too busy to answer
+#[derive(Clone, Default)]
+pub struct CameraListCache(Arc<Mutex<Option<Vec<CameraInfo>>>>);
+
+impl CameraListCache {
+    fn store(&self, cameras: &[CameraInfo]) {
+        if let Ok(mut cached) = self.0.lock() {
+            *cached = Some(cameras.to_vec());
+        }
+    }
+
+    fn get(&self) -> Option<Vec<CameraInfo>> {
+        self.0.lock().ok().and_then(|cached| cached.clone())
+    }
 }
 
 /// Dashboard template
@@ -86,6 +386,26 @@ struct DashboardTemplate {
     machine_name: String,
     host: String,
     port: u16,
+    version: String,
+    /// Grey out the controls that change things, see `Settings::read_only`
+    read_only: bool,
+    bootstrap: DashboardBootstrap,
+}
+
+/// Initial dashboard state rendered into the page so it is useful before the first API call
+#[derive(Serialize)]
+#[serde(rename_all = "snake_case")]
+struct DashboardBootstrap {
+    cameras: Vec<CameraInfo>,
+    /// The managers didn't answer in time, so the cameras are the last known list
+    cameras_stale: bool,
+    machine_status: String,
+    /// The controller didn't answer in time
+    machine_status_stale: bool,
+    total_sorted: u32,
+    selected_cameras: Vec<CameraId>,
+    /// The camera preset applied last
+    active_camera_preset: Option<String>,
 }
 
 /// Config template
@@ -93,6 +413,13 @@ struct DashboardTemplate {
 #[template(path = "config.html")]
 struct ConfigTemplate {}
 
+/// First-run setup template
+#[derive(Template, WebTemplate)]
+#[template(path = "setup.html")]
+struct SetupTemplate {
+    view_types: Vec<String>,
+}
+
 /// Shell edit template
 #[derive(Template, WebTemplate)]
 #[template(path = "shell_edit.html")]
@@ -107,161 +434,631 @@ struct ShellEditTemplate {
 struct TaggingTemplate {
     session_id: String,
     captured_images: Vec<CapturedImageData>,
-    supported_case_types: Vec<String>,
+    supported_case_types: Vec<TaggingCaseType>,
     image_filenames: String,
+    /// Selected cameras the capture got no image from
+    missing_cameras: Vec<String>,
+}
+
+/// A case type offered on the tagging page, with the views it requires
+struct TaggingCaseType {
+    name: String,
+    /// Comma-separated, for the page to warn about views no image is tagged with
+    required_views: String,
 }
 
 #[derive(Serialize)]
+#[serde(rename_all = "snake_case")]
 struct CapturedImageData {
     filename: String,
-    camera_index: i32,
+    camera_index: u32,
     camera_name: String,
+    view_type: String,
+    /// Why the guessed view type needs checking, empty when it doesn't
+    view_review: String,
 }
 
-#[derive(Deserialize, Serialize)]
-enum CameraType {
-    #[serde(rename = "esphome")]
-    EspHome,
-    #[serde(rename = "usb")]
-    Usb,
-}
-
-/// Camera info response
-#[derive(Serialize)]
+/// Camera info response: the camera's descriptor and how it is doing right now
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "snake_case")]
 struct CameraInfo {
-    id: String,
-    name: String,
-    hostname: Option<String>,
-    online: bool,
+    #[serde(flatten)]
+    camera: Descriptor,
     view_type: Option<String>,
-    camera_type: CameraType,
-    index: Option<u32>, // For USB cameras
-    vendor_id: Option<String>,
-    product_id: Option<String>,
-    serial_number: Option<String>,
     is_active: bool,
     is_selected: bool,
-}
-
-/// Generic API response
-#[derive(Serialize)]
-struct ApiResponse<T> {
-    success: bool,
-    data: Option<T>,
-    message: String,
+    capture_stats: CaptureStats,
+    active_streams: usize,
+    stream_health: StreamHealth,
+    orientation: Orientation,
+    /// The live stream is already turned upright; otherwise the dashboard applies `orientation` itself
+    stream_oriented: bool,
+    /// Seconds an offline network camera has been gone
+    offline_for_secs: Option<u64>,
+    /// Offline for longer than `camera_stale_seconds`
+    stale: bool,
+    /// Frames and memory held by a USB camera's pre-capture buffer, when it is buffering
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pre_capture: Option<PreCaptureStats>,
+    /// Whether an ESPHome camera's live stream is its own or polled from snapshots
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stream_mode: Option<StreamMode>,
+    /// The active camera preset, when this camera is one of its cameras
+    #[serde(skip_serializing_if = "Option::is_none")]
+    preset: Option<String>,
 }
 
 /// Configuration data for API responses
 #[derive(Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 struct ConfigData {
     auto_start_cameras: bool,
     auto_detect_cameras: bool,
     esphome_hostname: String,
     network_camera_hostnames: Vec<String>,
+    /// Rotation and mirroring by camera ID; cameras left out keep their current orientation
+    #[serde(default)]
+    camera_orientations: Option<BTreeMap<String, Orientation>>,
+    /// Where images and data are kept, resolved at startup; ignored when saving
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    directories: Option<DataDirectories>,
+}
+
+/// Each ConfigData field and the setting it edits
+const CONFIG_DATA_SETTINGS: [(&str, &str); 4] = [
+    ("auto_start_cameras", "auto_start_esp32_cameras"),
+    ("auto_detect_cameras", "auto_detect_cameras"),
+    ("esphome_hostname", "esphome_hostname"),
+    ("network_camera_hostnames", "network_camera_hostnames"),
+];
+
+/// Outcome of checking a candidate config, with an error per invalid field
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "snake_case")]
+struct ConfigValidation {
+    valid: bool,
+    errors: BTreeMap<String, String>,
+}
+
+impl ConfigValidation {
+    /// Check a candidate ConfigData against the constraints in the config schema
+    fn check(candidate: &serde_json::Value) -> Self {
+        let mut errors: BTreeMap<String, String> = CONFIG_DATA_SETTINGS
+            .iter()
+            .filter_map(|(key, setting)| {
+                let field = config_schema::settings_field(setting)?;
+                let value = candidate.get(key).unwrap_or(&serde_json::Value::Null);
+                field.validate(value).err().map(|e| (key.to_string(), e))
+            })
+            .collect();
+        let orientations = candidate
+            .get("camera_orientations")
+            .filter(|orientations| !orientations.is_null());
+        if let Some(orientations) = orientations {
+            match orientations.as_object() {
+                Some(orientations) => {
+                    for (camera_id, orientation) in orientations {
+                        for name in ["rotation", "mirror"] {
+                            let Some(field) = config_schema::camera_field(name) else {
+                                continue;
+                            };
+                            let value = orientation.get(name).unwrap_or(&serde_json::Value::Null);
+                            if let Err(e) = field.validate(value) {
+                                errors.insert(format!("camera_orientations.{camera_id}.{name}"), e);
+                            }
+                        }
+                    }
+                }
+                None => {
+                    errors.insert(
+                        "camera_orientations".to_string(),
+                        "Must be an object keyed by camera ID".to_string(),
+                    );
+                }
+            }
+        }
+
+        Self {
+            valid: errors.is_empty(),
+            errors,
+        }
+    }
 }
 
 /// Status data for frontend status updates
 #[derive(Serialize)]
+#[serde(rename_all = "snake_case")]
 struct StatusData {
     status: String,
     total_sorted: u32,
+    /// Free space on the image and data disks, if it could be read
+    disk_space: Option<DiskSpaceReport>,
+    /// No user config file has been written yet, so the dashboard offers first-run setup
+    needs_setup: bool,
+    /// Shown as a banner on the dashboard while it is on
+    maintenance: MaintenanceStatus,
+    /// Temperature sensors over the warning threshold, shown as a banner on the dashboard
+    overheating: Vec<TemperatureReading>,
+    /// The server refuses changes, so the dashboard greys out the controls that make them
+    read_only: bool,
+    /// The camera preset applied last, shown in the dashboard header
+    active_camera_preset: Option<String>,
 }
 
-impl<T> ApiResponse<T> {
-    fn success(data: T) -> Self {
-        Self {
-            success: true,
-            data: Some(data),
-            message: "Success".to_string(),
+/// HTTP method of a registered route
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum RouteMethod {
+    Get,
+    Post,
+    Put,
+    Patch,
+    Delete,
+}
+
+impl RouteMethod {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RouteMethod::Get => "GET",
+            RouteMethod::Post => "POST",
+            RouteMethod::Put => "PUT",
+            RouteMethod::Patch => "PATCH",
+            RouteMethod::Delete => "DELETE",
+        }
+    }
+}
+
+/// How large a request body a route accepts
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BodyLimit {
+    /// [`MAX_JSON_BODY_BYTES`], for routes that take JSON or nothing
+    Json,
+    /// The `max_upload_mb` setting, for file uploads
+    Upload,
+    /// A fixed number of bytes
+    Bytes(usize),
+}
+
+impl BodyLimit {
+    pub fn bytes(&self, settings: &Settings) -> usize {
+        match self {
+            BodyLimit::Json => MAX_JSON_BODY_BYTES,
+            BodyLimit::Upload => settings.max_upload_bytes(),
+            BodyLimit::Bytes(bytes) => *bytes,
         }
     }
-    #[allow(dead_code)]
-    fn error(message: String) -> Self {
+}
+
+/// One method on one path, as registered on the router
+pub struct RouteSpec {
+    pub method: RouteMethod,
+    pub path: &'static str,
+    /// Name of the handler function
+    pub handler: &'static str,
+    /// The hardware-facing manager the handler waits on, if its wait has a deadline
+    pub hardware_backend: Option<&'static str>,
+    pub body_limit: BodyLimit,
+    /// The route changes data or drives hardware, so a read-only server refuses it
+    pub mutating: bool,
+    method_router: MethodRouter<Arc<AppState>>,
+}
+
+impl RouteSpec {
+    fn new<H, T>(method: RouteMethod, path: &'static str, handler: H) -> Self
+    where
+        H: Handler<T, Arc<AppState>>,
+        T: 'static,
+    {
+        let handler_name = std::any::type_name::<H>()
+            .rsplit("::")
+            .next()
+            .unwrap_or_default();
+        let method_router = match method {
+            RouteMethod::Get => get(handler),
+            RouteMethod::Post => post(handler),
+            RouteMethod::Put => put(handler),
+            RouteMethod::Patch => patch(handler),
+            RouteMethod::Delete => delete(handler),
+        };
         Self {
-            success: false,
-            data: None,
-            message,
+            method,
+            path,
+            handler: handler_name,
+            hardware_backend: None,
+            body_limit: BodyLimit::Json,
+            mutating: method != RouteMethod::Get,
+            method_router,
         }
     }
+
+    /// Answer 504 if the handler is still waiting on `backend` after `hardware_request_timeout_ms`.
+    ///
+    /// Only for handlers whose response is a single wait on the manager; streams
+    /// and long-running jobs like the self-test have their own limits.
+    fn with_hardware_deadline(mut self, backend: &'static str) -> Self {
+        self.hardware_backend = Some(backend);
+        self
+    }
+
+    /// Accept request bodies up to `body_limit` instead of [`MAX_JSON_BODY_BYTES`]
+    fn with_body_limit(mut self, body_limit: BodyLimit) -> Self {
+        self.body_limit = body_limit;
+        self
+    }
+
+    /// Keep a route that only looks at what it is sent, but isn't a GET, working when read-only
+    fn read_only(mut self) -> Self {
+        self.mutating = false;
+        self
+    }
 }
 
-/// Create a test router for integration testing
-pub fn create_router(state: Arc<AppState>) -> Router {
-    Router::new()
-        // Static files
-        .nest_service("/static", ServeDir::new("shell_sorter/static"))
+/// Every API and page route, in the order they are registered
+///
+/// The router is built from this table, so it is also the list to use for
+/// anything that needs to know which endpoints exist.
+pub fn route_table() -> Vec<RouteSpec> {
+    use RouteMethod::*;
+    vec![
         // Main dashboard and pages
-        .route("/", get(dashboard))
-        .route("/config", get(config_page))
-        .route("/shell-edit/{session_id}", get(shell_edit_page))
-        .route("/tagging/{session_id}", get(tagging_page))
+        RouteSpec::new(Get, "/", dashboard),
+        RouteSpec::new(Get, "/config", config_page),
+        RouteSpec::new(Get, "/setup", setup_page),
+        RouteSpec::new(Get, "/shell-edit/{session_id}", shell_edit_page),
+        RouteSpec::new(Get, "/tagging/{session_id}", tagging_page),
+        RouteSpec::new(Get, "/images/{filename}", serve_image),
         // Machine control API
-        .route("/api/status", get(status))
-        .route("/api/machine/next-case", post(trigger_next_case))
-        .route("/api/machine/status", get(machine_status))
-        .route("/api/machine/sensors", get(sensor_readings))
-        .route("/api/machine/hardware-status", get(hardware_status))
+        RouteSpec::new(Get, "/api/status", status).with_hardware_deadline("Controller monitor"),
+        RouteSpec::new(Post, "/api/machine/next-case", trigger_next_case)
+            .with_hardware_deadline("Controller monitor"),
+        RouteSpec::new(Get, "/api/machine/status", machine_status)
+            .with_hardware_deadline("Controller monitor"),
+        RouteSpec::new(Get, "/api/machine/health-history", health_history),
+        RouteSpec::new(Get, "/api/machine/sensors", sensor_readings)
+            .with_hardware_deadline("Controller monitor"),
+        RouteSpec::new(Get, "/api/machine/hardware-status", hardware_status)
+            .with_hardware_deadline("Controller monitor"),
+        RouteSpec::new(Get, "/api/machine/calibration", get_calibration)
+            .with_hardware_deadline("Controller monitor"),
+        RouteSpec::new(Post, "/api/machine/calibration/start", start_calibration)
+            .with_hardware_deadline("Controller monitor"),
+        RouteSpec::new(Post, "/api/machine/calibration/jog", jog_servo)
+            .with_hardware_deadline("Controller monitor"),
+        RouteSpec::new(Post, "/api/machine/calibration/save", save_calibration)
+            .with_hardware_deadline("Controller monitor"),
+        RouteSpec::new(Post, "/api/machine/self-test", run_self_test),
+        RouteSpec::new(Post, "/api/machine/maintenance", set_maintenance)
+            .with_hardware_deadline("Controller monitor"),
         // Camera management API
-        .route("/api/cameras", get(list_cameras))
-        .route("/api/cameras/detect", get(detect_cameras))
-        .route("/api/cameras/select", post(select_cameras))
-        .route("/api/cameras/start-selected", post(start_cameras))
-        .route("/api/cameras/stop-all", post(stop_cameras))
-        .route("/api/cameras/capture", post(capture_images))
-        .route("/api/cameras/{camera_id}/stream", get(camera_stream))
-        .route(
-            "/api/cameras/{camera_id}/brightness",
-            get(get_camera_brightness),
+        RouteSpec::new(Get, "/api/cameras", list_cameras),
+        RouteSpec::new(Get, "/api/cameras/detect", detect_cameras),
+        RouteSpec::new(Post, "/api/cameras/select", select_cameras),
+        RouteSpec::new(Post, "/api/cameras/start-selected", start_cameras),
+        RouteSpec::new(Post, "/api/cameras/stop-all", stop_cameras),
+        RouteSpec::new(Post, "/api/cameras/capture", capture_images),
+        RouteSpec::new(Get, "/api/cameras/{camera_id}/stream", camera_stream),
+        RouteSpec::new(Get, "/api/cameras/{camera_id}/snapshot", camera_snapshot)
+            .with_hardware_deadline("Camera manager"),
+        RouteSpec::new(
+            Get,
+            "/api/cameras/{camera_id}/esp-settings",
+            get_esp_settings,
+        )
+        .with_hardware_deadline("Camera manager"),
+        RouteSpec::new(
+            Post,
+            "/api/cameras/{camera_id}/esp-settings",
+            set_esp_settings,
         )
-        .route(
+        .with_hardware_deadline("Camera manager"),
+        RouteSpec::new(Get, "/api/cameras/{camera_id}/stats", get_camera_stats),
+        RouteSpec::new(
+            Post,
+            "/api/cameras/{camera_id}/stats/reset",
+            reset_camera_stats,
+        ),
+        RouteSpec::new(
+            Get,
             "/api/cameras/{camera_id}/brightness",
-            post(set_camera_brightness),
+            get_camera_brightness,
         )
-        .route("/api/cameras/{index}/view-type", post(set_camera_view_type))
-        .route("/api/cameras/{index}/region", post(set_camera_region))
-        .route("/api/cameras/{index}/region", delete(clear_camera_region))
+        .with_hardware_deadline("Camera manager"),
+        RouteSpec::new(
+            Post,
+            "/api/cameras/{camera_id}/brightness",
+            set_camera_brightness,
+        ),
+        RouteSpec::new(Post, "/api/cameras/{index}/view-type", set_camera_view_type),
+        RouteSpec::new(Get, "/api/cameras/{camera_id}/frame-info", get_frame_info)
+            .with_hardware_deadline("Camera manager"),
+        RouteSpec::new(Post, "/api/cameras/{camera_id}/region", set_camera_region)
+            .with_hardware_deadline("Camera manager"),
+        RouteSpec::new(
+            Delete,
+            "/api/cameras/{camera_id}/region",
+            clear_camera_region,
+        ),
+        RouteSpec::new(Get, "/api/camera-presets", list_camera_presets),
+        RouteSpec::new(Post, "/api/camera-presets", create_camera_preset),
+        RouteSpec::new(Get, "/api/camera-presets/{name}", get_camera_preset),
+        RouteSpec::new(Put, "/api/camera-presets/{name}", update_camera_preset),
+        RouteSpec::new(Delete, "/api/camera-presets/{name}", delete_camera_preset),
+        RouteSpec::new(
+            Post,
+            "/api/camera-presets/{name}/apply",
+            apply_camera_preset,
+        ),
         // Data management API
-        .route("/api/shells", get(list_shells))
-        .route("/api/shells/save", post(save_shell_data))
-        .route(
+        RouteSpec::new(Get, "/api/case-designations", list_case_designations),
+        RouteSpec::new(Get, "/api/data/usage", get_data_usage),
+        RouteSpec::new(Post, "/api/data/migrate", migrate_shell_data),
+        RouteSpec::new(Get, "/api/data/export", export_dataset),
+        RouteSpec::new(Get, "/api/data/measurements", get_measurement_report),
+        RouteSpec::new(Post, "/api/data/import", import_dataset)
+            .with_body_limit(BodyLimit::Bytes(MAX_BUNDLE_BYTES as usize)),
+        RouteSpec::new(Get, "/api/batches", list_batches),
+        RouteSpec::new(Post, "/api/batches", create_batch),
+        RouteSpec::new(Post, "/api/batches/deactivate", deactivate_batch),
+        RouteSpec::new(Delete, "/api/batches/{batch_id}", delete_batch),
+        RouteSpec::new(Post, "/api/batches/{batch_id}/activate", activate_batch),
+        RouteSpec::new(Post, "/api/batches/{batch_id}/training", set_batch_training),
+        RouteSpec::new(Get, "/api/shells", list_shells),
+        RouteSpec::new(Get, "/api/shells/search", search_shells),
+        RouteSpec::new(Get, "/api/shells/brands", list_shell_brands),
+        RouteSpec::new(Get, "/api/shells/integrity", shell_integrity),
+        RouteSpec::new(Post, "/api/shells/propagate-region", propagate_region),
+        RouteSpec::new(Post, "/api/shells/save", save_shell_data),
+        RouteSpec::new(Post, "/api/shells/import-bundle", import_shell_bundle)
+            .with_body_limit(BodyLimit::Bytes(MAX_BUNDLE_BYTES as usize)),
+        RouteSpec::new(Get, "/api/shells/{session_id}", get_shell),
+        RouteSpec::new(Patch, "/api/shells/{session_id}", update_shell),
+        RouteSpec::new(Delete, "/api/shells/{session_id}", delete_shell),
+        RouteSpec::new(Get, "/api/shells/{session_id}/export", export_shell_bundle),
+        RouteSpec::new(Post, "/api/shells/{session_id}/images", upload_shell_images)
+            .with_body_limit(BodyLimit::Upload),
+        RouteSpec::new(
+            Post,
+            "/api/shells/{session_id}/images/{filename}/classify-view",
+            classify_shell_image_view,
+        ),
+        RouteSpec::new(
+            Post,
             "/api/shells/{session_id}/toggle",
-            post(toggle_shell_training),
-        )
+            toggle_shell_training,
+        ),
+        RouteSpec::new(
+            Post,
+            "/api/shells/{session_id}/measurements",
+            record_shell_measurements,
+        ),
+        RouteSpec::new(Get, "/api/trash", list_trash),
+        RouteSpec::new(Post, "/api/trash/{session_id}/restore", restore_shell),
+        RouteSpec::new(Get, "/api/sessions/claims", list_session_claims),
+        RouteSpec::new(Post, "/api/sessions/{session_id}/claim", claim_session),
+        RouteSpec::new(Delete, "/api/sessions/{session_id}/claim", release_session),
+        RouteSpec::new(
+            Post,
+            "/api/sessions/{session_id}/recapture",
+            recapture_session,
+        ),
+        RouteSpec::new(
+            Get,
+            "/api/sessions/{session_id}/settings",
+            get_session_settings,
+        ),
+        RouteSpec::new(
+            Get,
+            "/api/sessions/{session_id}/diff/{other_session_id}",
+            diff_session_settings,
+        ),
         // ML API
-        .route("/api/ml/shells", get(ml_list_shells))
-        .route("/api/ml/generate-composites", post(generate_composites))
-        .route("/api/case-types", get(list_case_types))
-        .route("/api/case-types", post(create_case_type))
-        .route("/api/train-model", post(train_model))
+        RouteSpec::new(Get, "/api/ml/shells", ml_list_shells),
+        RouteSpec::new(Post, "/api/ml/generate-composites", generate_composites),
+        RouteSpec::new(Get, "/api/ml/reference-sheet", get_reference_sheet),
+        RouteSpec::new(Get, "/api/ml/composite-layout", get_composite_layout),
+        RouteSpec::new(Put, "/api/ml/composite-layout", update_composite_layout),
+        RouteSpec::new(Get, "/api/ml/reconcile", get_reconcile_report),
+        RouteSpec::new(Post, "/api/ml/reconcile", reconcile_case_types),
+        RouteSpec::new(Get, "/api/ml/progress", get_ml_progress),
+        RouteSpec::new(Get, "/api/ml/progress/events", stream_target_events),
+        RouteSpec::new(Get, "/api/work-queue", get_work_queue),
+        RouteSpec::new(Get, "/api/case-types", list_case_types),
+        RouteSpec::new(Post, "/api/case-types", create_case_type),
+        RouteSpec::new(Patch, "/api/case-types/{name}", update_case_type),
+        RouteSpec::new(
+            Post,
+            "/api/case-types/{name}/reference-images",
+            upload_reference_images,
+        )
+        .with_body_limit(BodyLimit::Upload),
+        RouteSpec::new(
+            Post,
+            "/api/case-types/{name}/training-images",
+            upload_training_images,
+        )
+        .with_body_limit(BodyLimit::Upload),
+        RouteSpec::new(Post, "/api/train-model", train_model),
+        RouteSpec::new(Post, "/api/training-jobs", start_training_job),
+        RouteSpec::new(Get, "/api/training-jobs/{job_id}", get_training_job),
+        RouteSpec::new(Get, "/api/ml/models/{name}/metrics", get_model_metrics),
         // Configuration API
-        .route("/api/config", get(get_config))
-        .route("/api/config", post(save_config))
-        .route("/api/config/cameras/{index}", delete(delete_camera_config))
-        .route("/api/config/cameras", delete(clear_camera_configs))
-        .route("/api/config/reset", post(reset_config))
+        // First-run setup API
+        RouteSpec::new(Get, "/api/setup/status", setup_status),
+        RouteSpec::new(Post, "/api/setup/scan", setup_scan).read_only(),
+        RouteSpec::new(Post, "/api/setup/apply", apply_setup),
+        RouteSpec::new(Get, "/api/config", get_config),
+        RouteSpec::new(Post, "/api/config", save_config),
+        RouteSpec::new(Delete, "/api/config/cameras/{index}", delete_camera_config),
+        RouteSpec::new(Post, "/api/config/cameras/import", import_camera_inventory),
+        RouteSpec::new(Get, "/api/config/cameras/export", export_camera_inventory),
+        RouteSpec::new(Delete, "/api/config/cameras", clear_camera_configs),
+        RouteSpec::new(Post, "/api/config/reset", reset_config),
+        RouteSpec::new(Get, "/api/config/schema", get_config_schema),
+        RouteSpec::new(Post, "/api/config/validate", validate_config).read_only(),
+        // Diagnostics API
+        RouteSpec::new(Get, "/api/events", list_events),
+        RouteSpec::new(Get, "/api/logs", list_logs),
+        RouteSpec::new(Get, "/api/logs/stream", stream_logs),
+        RouteSpec::new(Get, "/api/health", health),
+        RouteSpec::new(Get, "/api/version", version),
+    ]
+}
+
+/// Create a test router for integration testing
+pub fn create_router(state: Arc<AppState>) -> Router {
+    let static_directory = std::path::Path::new(STATIC_DIRECTORY);
+    let mut router =
+        static_router(state.settings.static_assets, static_directory).merge(root_asset_router(
+            state.settings.static_assets,
+            static_directory,
+            WebManifest::new(&state.settings.machine_name),
+        ));
+    let timeout = Duration::from_millis(state.settings.hardware_request_timeout_ms);
+    let cors_layer = match cors::cors_layer(&state.settings.cors_allowed_origins) {
+        Ok(layer) => layer,
+        Err(e) => {
+            error!("Cross-origin requests are refused: {e}");
+            None
+        }
+    };
+    for route in route_table() {
+        let body_limit = route.body_limit.bytes(&state.settings);
+        let mut method_router = route
+            .method_router
+            .layer(DefaultBodyLimit::max(body_limit))
+            .layer(middleware::from_fn_with_state(
+                body_limit,
+                body_limit_middleware,
+            ));
+        if route.mutating && state.settings.read_only {
+            method_router = method_router.layer(middleware::from_fn(read_only_middleware));
+        }
+        if let Some(backend) = route.hardware_backend {
+            method_router = method_router.layer(middleware::from_fn_with_state(
+                HardwareDeadline { backend, timeout },
+                hardware_deadline_middleware,
+            ));
+        }
+        if let Some(layer) = cors_layer
+            .as_ref()
+            .filter(|_| route.path.starts_with("/api/"))
+        {
+            method_router = method_router.layer(layer.clone());
+        }
+        router = router.route(route.path, method_router);
+    }
+    router
+        .fallback(route_not_found)
         .layer(middleware::from_fn(no_cache_middleware))
+        .layer(middleware::from_fn_with_state(
+            state.messages.clone(),
+            messages_middleware,
+        ))
+        .layer(middleware::from_fn_with_state(
+            state.settings.debug,
+            server_timing_middleware,
+        ))
         .with_state(state)
 }
 
+/// 404 for paths nothing serves; the ones browsers try unprompted are only logged at debug
+async fn route_not_found(method: Method, uri: Uri) -> StatusCode {
+    if is_browser_probe(uri.path()) {
+        debug!("No route for {method} {uri}");
+    } else {
+        warn!("No route for {method} {uri}");
+    }
+    StatusCode::NOT_FOUND
+}
+
+/// The running components the web server hands requests to
+pub struct ServerComponents {
+    pub controller: ControllerHandle,
+    pub camera_manager: CameraHandle,
+    pub usb_camera_manager: UsbCameraHandle,
+    pub events: EventRecorder,
+    pub logs: LogBuffer,
+    /// Background tasks, stopped when the server shuts down
+    pub tasks: TaskRegistry,
+}
+
 /// Start the web server
 pub async fn start_server(
     host: String,
     port: NonZeroU16,
     settings: Settings,
-    controller: ControllerHandle,
-    camera_manager: CameraHandle,
-    usb_camera_manager: UsbCameraHandle,
+    components: ServerComponents,
 ) -> OurResult<()> {
+    let ServerComponents {
+        controller,
+        camera_manager,
+        usb_camera_manager,
+        events,
+        logs,
+        tasks,
+    } = components;
+
     // Initialize ML trainer and shell data manager
     let mut ml_trainer = MLTrainer::new(settings.clone());
     ml_trainer
         .initialize()
         .map_err(|e| OurError::App(format!("Failed to initialize ML trainer: {e}")))?;
 
-    let shell_data_manager = ShellDataManager::new(settings.data_directory.clone());
-    shell_data_manager
-        .validate_data_directory()
-        .map_err(|e| OurError::App(format!("Failed to validate data directory: {e}")))?;
+    // Shares the trainer's run tracker so shell changes made mid-training are flagged on the model
+    let shell_data_manager = ShellDataManager::new(settings.data_directory.clone())
+        .with_training_runs(ml_trainer.training_runs());
+    if settings.read_only {
+        // A snapshot may well be on a read-only mount, so only check it's there
+        if !settings.data_directory.is_dir() {
+            return Err(OurError::App(format!(
+                "Data directory {} does not exist",
+                settings.data_directory.display()
+            )));
+        }
+        info!("Read-only mode: refusing changes and writing nothing to the data directory");
+    } else {
+        shell_data_manager
+            .validate_data_directory()
+            .map_err(|e| OurError::App(format!("Failed to validate data directory: {e}")))?;
+        // The uptime chart still works from memory if the old checks can't be read
+        if let Err(e) = controller
+            .health_history()
+            .persist_to(settings.data_directory.join(HEALTH_HISTORY_FILENAME))
+        {
+            warn!("Failed to load the controller health history: {e}");
+        }
+    }
+
+    let stream_limiter = StreamLimiter::new(settings.max_concurrent_streams);
+    let heavy_work = HeavyWork::new(settings.heavy_work_permits);
+    let snapshot_poll = Duration::from_millis(settings.esp_snapshot_poll_ms);
+    if snapshot_poll < MIN_SNAPSHOT_POLL_INTERVAL {
+        warn!(
+            "esp_snapshot_poll_ms is {}, polling every {}ms instead to spare the cameras",
+            settings.esp_snapshot_poll_ms,
+            MIN_SNAPSHOT_POLL_INTERVAL.as_millis()
+        );
+    }
+    let snapshot_streams = SnapshotStreams::new(camera_manager.clone(), snapshot_poll)?;
+    let disk_space = DiskSpaceGuard::new(&settings);
+    let session_claims = match &settings.session_claims_path {
+        Some(path) if !settings.read_only => SessionClaims::persisted(path.clone()),
+        _ => SessionClaims::default(),
+    };
+
+    let (mut config_writer_task, config_writer) = ConfigWriter::new(
+        settings.sources.user_config_path.clone(),
+        settings.load_user_config(),
+        DEFAULT_COALESCE_WINDOW,
+    );
+    if settings.read_only {
+        config_writer_task = config_writer_task.in_memory();
+    }
+    tasks.spawn_tracked("config_writer", config_writer_task.run());
+    let messages = Arc::new(Catalogues::load(
+        &settings.data_directory,
+        settings.ui_language.as_deref(),
+    ));
 
     let state = Arc::new(AppState {
         settings,
@@ -270,8 +1067,64 @@ pub async fn start_server(
         usb_camera_manager: Box::new(usb_camera_manager),
         ml_trainer: Arc::new(Mutex::new(ml_trainer)),
         shell_data_manager: Arc::new(shell_data_manager),
+        events,
+        logs,
+        stream_limiter,
+        heavy_work,
+        snapshot_streams,
+        disk_space,
+        config_writer: config_writer.clone(),
+        camera_cache: CameraListCache::default(),
+        data_usage: UsageCache::default(),
+        session_claims,
+        stream_stalls: broadcast::channel(16).0,
+        broadcast_lag: LagCounters::default(),
+        tasks: tasks.clone(),
+        training_jobs: TrainingJobs::default(),
+        idempotency: IdempotencyCache::default(),
+        reference_sheet: ReferenceSheetCache::default(),
+        shell_targets: ShellTargets::default(),
+        detection: DetectionGates::default(),
+        messages,
     });
+    sync_shell_targets(&state);
+    apply_camera_orientations(&state, &current_user_config(&state).await).await;
+
+    tasks.spawn_tracked(
+        "stream_health_watchdog",
+        watch_stream_health(
+            state.clone(),
+            Duration::from_secs(state.settings.stream_stall_seconds),
+            STREAM_HEALTH_INTERVAL,
+        ),
+    );
+    if state.settings.data_usage_refresh_seconds > 0 {
+        tasks.spawn_tracked(
+            "data_usage_refresh",
+            refresh_data_usage_periodically(
+                state.clone(),
+                Duration::from_secs(state.settings.data_usage_refresh_seconds),
+            ),
+        );
+    }
 
+    if !state.settings.read_only {
+        tasks.spawn_tracked(
+            "trash_sweep",
+            purge_trash_periodically(state.clone(), TRASH_SWEEP_INTERVAL),
+        );
+    }
+
+    match state
+        .settings
+        .static_assets
+        .resolve(std::path::Path::new(STATIC_DIRECTORY))
+    {
+        StaticAssetSource::Directory => {
+            info!("Serving static assets from the {STATIC_DIRECTORY} directory")
+        }
+        _ => info!("Serving static assets embedded in the binary"),
+    }
     let app = create_router(state);
 
     let addr = format!("{host}:{port}");
@@ -281,11 +1134,167 @@ pub async fn start_server(
 
     info!("Web server listening on http://{addr}");
 
-    axum::serve(listener, app)
+    let served = axum::serve(listener, app)
+        .with_graceful_shutdown(shutdown_signal(tasks.shutdown_token()))
         .await
-        .map_err(|e| OurError::App(format!("Server error: {e}")))?;
+        .map_err(|e| OurError::App(format!("Server error: {e}")));
 
-    Ok(())
+    // Don't lose camera selections made in the last coalescing window
+    let flushed = config_writer.flush().await;
+    tasks.shutdown(DEFAULT_SHUTDOWN_DEADLINE).await;
+    served.and(flushed)
+}
+
+/// Hand the configured camera orientations to both camera managers
+async fn apply_camera_orientations(state: &AppState, user_config: &UserConfig) {
+    let orientations = configured_orientations(user_config);
+    let (esphome, usb) = tokio::join!(
+        state.camera_manager.set_orientations(orientations.clone()),
+        state.usb_camera_manager.set_orientations(orientations),
+    );
+    for result in [esphome, usb] {
+        if let Err(e) = result {
+            warn!("Failed to apply camera orientations: {e}");
+        }
+    }
+}
+
+/// Periodically ask the camera managers to check the cameras with open streams,
+/// and broadcast the streams that have stalled
+async fn watch_stream_health(state: Arc<AppState>, threshold: Duration, every: Duration) {
+    let mut ticker = tokio::time::interval(every);
+    let mut had_open_streams = false;
+    loop {
+        ticker.tick().await;
+        let open_streams = state.stream_limiter.active_cameras();
+        // One more check after the last stream closes, so the managers stop watching it
+        if open_streams.is_empty() && !had_open_streams {
+            continue;
+        }
+        had_open_streams = !open_streams.is_empty();
+
+        let (esphome, usb) = tokio::join!(
+            state
+                .camera_manager
+                .check_stream_health(open_streams.clone(), threshold),
+            state
+                .usb_camera_manager
+                .check_stream_health(open_streams, threshold),
+        );
+        for result in [esphome, usb] {
+            match result {
+                Ok(stalled) => {
+                    for stall in stalled {
+                        // Nobody listening just means no stream is open any more
+                        state.stream_stalls.send(stall).ok();
+                    }
+                }
+                Err(e) => debug!("Stream health check failed: {e}"),
+            }
+        }
+    }
+}
+
+/// Rescan data disk usage on a timer, so `/api/data/usage` rarely has to wait for a walk
+async fn refresh_data_usage_periodically(state: Arc<AppState>, every: Duration) {
+    let mut ticker = tokio::time::interval(every);
+    loop {
+        ticker.tick().await;
+        // Skipped while other heavy work runs; a request can still ask for a scan
+        let _permit = match state.heavy_work.try_acquire("disk usage scan") {
+            Ok(permit) => permit,
+            Err(busy) => {
+                debug!(
+                    "Skipped a disk usage scan while {} is running",
+                    busy.running.join(", ")
+                );
+                continue;
+            }
+        };
+        if let Err(e) = refresh_data_usage(&state).await {
+            warn!("Failed to scan data disk usage: {e}");
+        }
+    }
+}
+
+/// Permanently delete shells that have been in the trash longer than `trash_retention_days`
+async fn purge_trash_periodically(state: Arc<AppState>, every: Duration) {
+    let mut ticker = tokio::time::interval(every);
+    loop {
+        ticker.tick().await;
+        let trash = shell_trash(&state);
+        let retention_days = state.settings.trash_retention_days;
+        let purged = tokio::task::spawn_blocking(move || {
+            trash.purge_expired(retention_days, chrono::Utc::now())
+        })
+        .await
+        .map_err(|e| OurError::App(format!("Trash sweep task failed: {e}")))
+        .and_then(|result| result);
+        match purged {
+            Ok(purged) => {
+                for session_id in purged {
+                    state.events.record(
+                        "shell_data",
+                        "ShellPurged",
+                        format!("{session_id} purged from the trash"),
+                    );
+                }
+            }
+            Err(e) => warn!("Failed to purge expired trash: {e}"),
+        }
+    }
+}
+
+/// Walk the image and data directories off the async runtime and cache the result
+async fn refresh_data_usage(state: &AppState) -> OurResult<UsageReport> {
+    let shell_data_manager = state.shell_data_manager.clone();
+    let image_directory = state.settings.image_directory.clone();
+    let data_directory = state.settings.data_directory.clone();
+    let report = tokio::task::spawn_blocking(move || {
+        let shells = shell_data_manager.list_shells()?;
+        Ok::<_, OurError>(data_usage::scan(
+            &image_directory,
+            &data_directory,
+            &shells,
+            MAX_SCAN_DEPTH,
+        ))
+    })
+    .await
+    .map_err(|e| OurError::App(format!("Disk usage scan task failed: {e}")))??;
+    debug!(
+        "Scanned {} files ({} bytes) in {}ms",
+        report.files, report.total_bytes, report.scan_duration_ms
+    );
+    state.data_usage.store(report.clone());
+    Ok(report)
+}
+
+/// Resolves once `camera_id` is reported stalled; never resolves if the broadcaster goes away
+async fn stream_stalled(
+    stalls: &mut broadcast::Receiver<StreamStalled>,
+    camera_id: &str,
+    lag: &LagCounters,
+) {
+    while let Some(stall) = recv_skipping_lag(stalls, "stream_stalls", lag).await {
+        if stall.camera_id == camera_id {
+            return;
+        }
+    }
+    std::future::pending::<()>().await
+}
+
+/// Resolves when the process is asked to stop, or the task registry starts shutting down
+async fn shutdown_signal(shutdown: CancellationToken) {
+    tokio::select! {
+        signal = tokio::signal::ctrl_c() => {
+            if let Err(e) = signal {
+                error!("Failed to listen for shutdown signal: {e}");
+                shutdown.cancelled().await;
+            }
+        }
+        _ = shutdown.cancelled() => {}
+    }
+    info!("Shutting down web server");
 }
 
 // Handler implementations
@@ -297,6 +1306,12 @@ async fn dashboard(
         machine_name: state.settings.machine_name.clone(),
         host: state.settings.host.clone(),
         port: state.settings.port,
+        version: {
+            let build_info = BuildInfo::current();
+            format!("{} ({})", build_info.version, build_info.short_commit())
+        },
+        read_only: state.settings.read_only,
+        bootstrap: dashboard_bootstrap(&state).await,
     };
 
     template.render().map(Html::from).map_err(|e| {
@@ -308,6 +1323,63 @@ async fn dashboard(
     })
 }
 
+/// Gather the dashboard's initial state, falling back to cached data for anything slow
+async fn dashboard_bootstrap(state: &AppState) -> DashboardBootstrap {
+    let machine_status = async {
+        match state
+            .controller
+            .send_command(ControllerCommand::GetStatus)
+            .await
+        {
+            Ok(ControllerResponse::StatusData(status)) => status.status,
+            Ok(_) => "Error".to_string(),
+            Err(_) => "Offline".to_string(),
+        }
+    };
+    let (cameras, machine_status, user_config) = tokio::join!(
+        tokio::time::timeout(DASHBOARD_MANAGER_TIMEOUT, collect_cameras(state)),
+        tokio::time::timeout(DASHBOARD_MANAGER_TIMEOUT, machine_status),
+        tokio::time::timeout(DASHBOARD_MANAGER_TIMEOUT, current_user_config(state)),
+    );
+
+    let (cameras, cameras_stale) = match cameras {
+        Ok(cameras) => (cameras, false),
+        Err(_) => {
+            warn!("Camera managers were slow, rendering the dashboard from the cached camera list");
+            (state.camera_cache.get().unwrap_or_default(), true)
+        }
+    };
+    let (machine_status, machine_status_stale) = match machine_status {
+        Ok(machine_status) => (machine_status, false),
+        Err(_) => ("Unknown".to_string(), true),
+    };
+    let (selected_cameras, active_camera_preset) = match user_config {
+        Ok(user_config) => (
+            user_config.selected_cameras,
+            user_config.active_camera_preset,
+        ),
+        Err(_) => (
+            cameras
+                .iter()
+                .filter(|camera| camera.is_selected)
+                .map(|camera| camera.camera.id.clone())
+                .collect(),
+            None,
+        ),
+    };
+
+    DashboardBootstrap {
+        cameras,
+        cameras_stale,
+        machine_status,
+        machine_status_stale,
+        // TODO: Implement actual sorted count tracking, as for /api/status
+        total_sorted: 0,
+        selected_cameras,
+        active_camera_preset,
+    }
+}
+
 #[axum::debug_handler]
 async fn config_page(
     State(_state): State<Arc<AppState>>,
@@ -323,6 +1395,20 @@ async fn config_page(
     })
 }
 
+async fn setup_page() -> Result<Html<String>, (StatusCode, &'static str)> {
+    let template = SetupTemplate {
+        view_types: ViewType::ALL.iter().map(ToString::to_string).collect(),
+    };
+
+    template.render().map(Html::from).map_err(|e| {
+        error!("Failed to render setup template: {e}");
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Template rendering failed",
+        )
+    })
+}
+
 #[axum::debug_handler]
 async fn shell_edit_page(
     Path(session_id): Path<String>,
@@ -334,6 +1420,9 @@ async fn shell_edit_page(
         Ok(None) => {
             return Err((StatusCode::NOT_FOUND, "Shell session not found"));
         }
+        Err(OurError::InvalidName { .. }) => {
+            return Err((StatusCode::BAD_REQUEST, "Invalid session_id"));
+        }
         Err(e) => {
             error!("Failed to get shell data for session {}: {}", session_id, e);
             return Err((
@@ -359,9 +1448,38 @@ async fn tagging_page(
     Path(session_id): Path<String>,
     State(state): State<Arc<AppState>>,
 ) -> Result<Html<String>, (StatusCode, &'static str)> {
-    // TODO: Get captured images for this session from images directory
-    // For now, create empty captured images list
-    let captured_images = Vec::new();
+    // A session that hasn't been saved yet has no images to show
+    let shell = state.shell_data_manager.load_shell(&session_id).ok();
+    let captured_images: Vec<CapturedImageData> = shell
+        .as_ref()
+        .and_then(|shell| shell.captured_images.as_ref())
+        .map(|images| {
+            images
+                .iter()
+                .map(|image| CapturedImageData {
+                    filename: image.filename.clone(),
+                    camera_index: image.camera_index,
+                    camera_name: image.camera_name.clone(),
+                    view_type: image.view_type.to_string(),
+                    view_review: image
+                        .view_guess
+                        .filter(|guess| guess.needs_review)
+                        .map(|guess| {
+                            format!(
+                                "Guessed {} view, {:.0}% sure; check it",
+                                guess.view_type,
+                                guess.confidence * 100.0
+                            )
+                        })
+                        .unwrap_or_default(),
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    let missing_cameras = shell
+        .as_ref()
+        .map(Shell::missing_cameras)
+        .unwrap_or_default();
 
     // Get supported case types from ML trainer
     let supported_case_types = {
@@ -377,7 +1495,18 @@ async fn tagging_page(
         };
 
         match ml_trainer.get_supported_case_types() {
-            Ok(types) => types,
+            Ok(types) => types
+                .into_iter()
+                .map(|name| TaggingCaseType {
+                    required_views: ml_trainer
+                        .required_views(&name)
+                        .iter()
+                        .map(ToString::to_string)
+                        .collect::<Vec<_>>()
+                        .join(","),
+                    name,
+                })
+                .collect(),
             Err(e) => {
                 error!("Failed to get supported case types: {}", e);
                 // Return empty list as fallback
@@ -389,7 +1518,7 @@ async fn tagging_page(
     // Create comma-separated list of image filenames
     let image_filenames = captured_images
         .iter()
-        .map(|img: &CapturedImageData| img.filename.clone())
+        .map(|img| img.filename.clone())
         .collect::<Vec<String>>()
         .join(",");
 
@@ -398,6 +1527,7 @@ async fn tagging_page(
         captured_images,
         supported_case_types,
         image_filenames,
+        missing_cameras,
     };
 
     template.render().map(Html::from).map_err(|e| {
@@ -409,21 +1539,32 @@ async fn tagging_page(
     })
 }
 
-async fn status(State(state): State<Arc<AppState>>) -> Json<StatusData> {
+/// Machine status, tagged with a hash of the body since it has no single owner
+async fn status(State(state): State<Arc<AppState>>, headers: HeaderMap) -> Response {
     // Get machine status for the overall system status
-    let machine_status = match state
+    let (machine_status, maintenance, overheating) = match state
         .controller
         .send_command(ControllerCommand::GetStatus)
         .await
     {
-        Ok(ControllerResponse::StatusData(status)) => status.status,
+        Ok(ControllerResponse::StatusData(status)) => {
+            (status.status, status.maintenance, status.overheating)
+        }
         Ok(_) => {
             error!("Unexpected response type for machine status");
-            "Error".to_string()
+            (
+                "Error".to_string(),
+                MaintenanceStatus::default(),
+                Vec::new(),
+            )
         }
         Err(e) => {
             error!("Failed to get machine status: {e}");
-            "Offline".to_string()
+            (
+                "Offline".to_string(),
+                MaintenanceStatus::default(),
+                Vec::new(),
+            )
         }
     };
 
@@ -431,24 +1572,262 @@ async fn status(State(state): State<Arc<AppState>>) -> Json<StatusData> {
     // For now, return a placeholder value
     let total_sorted = 0;
 
-    Json(StatusData {
+    let disk_space = match state.disk_space.report() {
+        Ok(report) => {
+            if report.low {
+                warn!(
+                    "Low disk space: {} MB free on {}",
+                    report.free_mb,
+                    report.path.display()
+                );
+            }
+            Some(report)
+        }
+        Err(e) => {
+            error!("Failed to check disk space: {e}");
+            None
+        }
+    };
+
+    let data = StatusData {
         status: machine_status,
         total_sorted,
-    })
-}
+        disk_space,
+        // Setup can't be saved on a read-only server, so don't offer it
+        needs_setup: !state.settings.read_only && !state.settings.sources.user_config_path.exists(),
+        maintenance,
+        overheating,
+        read_only: state.settings.read_only,
+        active_camera_preset: current_user_config(&state).await.active_camera_preset,
+    };
+    match body_etag("status", &data) {
+        Ok(etag) if if_none_match(&headers, &etag) => not_modified(&etag),
+        Ok(etag) => with_etag(&etag, Json(data)),
+        Err(e) => {
+            error!("Failed to tag status response: {e}");
+            Json(data).into_response()
+        }
+    }
+}
+
+/// Optional body of `POST /api/machine/next-case`
+#[derive(Deserialize, Default)]
+#[serde(rename_all = "snake_case", default, deny_unknown_fields)]
+struct NextCaseRequest {
+    /// Used when there's no `Idempotency-Key` header
+    idempotency_key: Option<String>,
+}
+
+/// Advance the feeder, once per idempotency key when one is sent
+async fn trigger_next_case(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    payload: Option<ExtractJson<NextCaseRequest>>,
+) -> Response {
+    let request = payload
+        .map(|ExtractJson(request)| request)
+        .unwrap_or_default();
+    let key = match idempotency::request_key(&headers, request.idempotency_key) {
+        Ok(key) => key,
+        Err(e) => return invalid_idempotency_key(e),
+    };
+    state
+        .idempotency
+        .run("next_case", key, || async {
+            next_case_once(&state).await.into_response()
+        })
+        .await
+}
+
+fn invalid_idempotency_key(message: String) -> Response {
+    (
+        StatusCode::BAD_REQUEST,
+        Json(ApiResponse::<()>::error(ErrorCode::InvalidRequest, message)),
+    )
+        .into_response()
+}
+
+/// How the next case request went
+#[derive(Serialize)]
+#[serde(rename_all = "snake_case")]
+struct NextCaseData {
+    /// Requests made to the controller, more than one when it was briefly unreachable
+    attempts: u32,
+}
 
-async fn trigger_next_case(State(state): State<Arc<AppState>>) -> Json<ApiResponse<()>> {
+async fn next_case_once(state: &AppState) -> (StatusCode, Json<ApiResponse<NextCaseData>>) {
     match state
         .controller
         .send_command(ControllerCommand::NextCase)
         .await
     {
-        Ok(_) => Json(ApiResponse::success(())),
+        Ok(ControllerResponse::Sent { attempts, .. }) => (
+            StatusCode::OK,
+            Json(ApiResponse::success(NextCaseData { attempts })),
+        ),
+        Ok(ControllerResponse::Error(e)) => {
+            warn!("Next case refused: {e}");
+            (
+                StatusCode::OK,
+                Json(ApiResponse::error(ErrorCode::ControllerError, e)),
+            )
+        }
+        Ok(ControllerResponse::CalibrationFailed(e)) => {
+            warn!("Next case refused: {e}");
+            let (status_code, code) = refusal_status(&e);
+            (status_code, Json(ApiResponse::error(code, e.to_string())))
+        }
+        Ok(_) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(ApiResponse::error(
+                ErrorCode::InternalError,
+                "Unexpected response from controller monitor".to_string(),
+            )),
+        ),
         Err(e) => {
             error!("Failed to trigger next case: {e}");
-            Json(ApiResponse::<()>::error(format!(
-                "Failed to trigger next case: {e}",
-            )))
+            (
+                StatusCode::OK,
+                Json(ApiResponse::error(
+                    ErrorCode::ControllerError,
+                    format!("Failed to trigger next case: {e}",),
+                )),
+            )
+        }
+    }
+}
+
+/// Body of `POST /api/machine/maintenance`
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+struct MaintenanceRequest {
+    enabled: bool,
+    #[serde(default)]
+    reason: Option<String>,
+}
+
+/// Longest reason accepted for turning maintenance mode on
+const MAX_MAINTENANCE_REASON_LENGTH: usize = 200;
+
+/// Turn maintenance mode on or off, refusing motion commands while it is on
+async fn set_maintenance(
+    State(state): State<Arc<AppState>>,
+    Json(request): Json<MaintenanceRequest>,
+) -> (StatusCode, Json<ApiResponse<MaintenanceStatus>>) {
+    let reason = request
+        .reason
+        .map(|reason| reason.trim().to_string())
+        .filter(|reason| !reason.is_empty());
+    if reason
+        .as_ref()
+        .is_some_and(|reason| reason.chars().count() > MAX_MAINTENANCE_REASON_LENGTH)
+    {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(ApiResponse::failure(ApiError::validation(
+                "Invalid maintenance request",
+                BTreeMap::from([(
+                    "reason".to_string(),
+                    format!("must be at most {MAX_MAINTENANCE_REASON_LENGTH} characters"),
+                )]),
+            ))),
+        );
+    }
+
+    let command = ControllerCommand::SetMaintenance {
+        enabled: request.enabled,
+        reason,
+    };
+    match state.controller.send_command(command).await {
+        Ok(ControllerResponse::Maintenance(status)) => {
+            (StatusCode::OK, Json(ApiResponse::success(status)))
+        }
+        Ok(ControllerResponse::Error(e)) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(ApiResponse::error(ErrorCode::ControllerError, e)),
+        ),
+        Ok(_) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(ApiResponse::error(
+                ErrorCode::InternalError,
+                "Unexpected response from controller monitor".to_string(),
+            )),
+        ),
+        Err(e) => (
+            StatusCode::SERVICE_UNAVAILABLE,
+            Json(ApiResponse::error(
+                ErrorCode::ControllerError,
+                format!("Controller monitor unavailable: {e}"),
+            )),
+        ),
+    }
+}
+
+/// Options for `POST /api/machine/self-test`, all optional
+#[derive(Deserialize, Default)]
+#[serde(rename_all = "snake_case", default, deny_unknown_fields)]
+struct SelfTestRequest {
+    trigger_next_case: bool,
+    step_timeout_ms: Option<u64>,
+}
+
+/// Run the hardware self-test and append its report to the self-test log
+async fn run_self_test(
+    State(state): State<Arc<AppState>>,
+    payload: Option<ExtractJson<SelfTestRequest>>,
+) -> (StatusCode, Json<ApiResponse<SelfTestReport>>) {
+    let request = payload
+        .map(|ExtractJson(request)| request)
+        .unwrap_or_default();
+    let options = SelfTestOptions {
+        trigger_next_case: request.trigger_next_case,
+        step_timeout: request
+            .step_timeout_ms
+            .map(Duration::from_millis)
+            .unwrap_or(DEFAULT_STEP_TIMEOUT),
+    };
+
+    match state
+        .controller
+        .send_command(ControllerCommand::SelfTest(options))
+        .await
+        .map_err(|e| e.to_string())
+    {
+        Ok(ControllerResponse::SelfTest(report)) => {
+            let log_path = state.settings.data_directory.join(SELF_TEST_LOG_FILE);
+            if let Err(e) = self_test::append_to_log(&log_path, &report).await {
+                error!("Failed to record self-test report: {e}");
+            }
+            (StatusCode::OK, Json(ApiResponse::success(*report)))
+        }
+        Ok(ControllerResponse::Error(e)) => {
+            warn!("Self-test refused: {e}");
+            (
+                StatusCode::CONFLICT,
+                Json(ApiResponse::error(ErrorCode::MachineBusy, e)),
+            )
+        }
+        Ok(ControllerResponse::CalibrationFailed(e)) => {
+            warn!("Self-test refused: {e}");
+            let (status_code, code) = refusal_status(&e);
+            (status_code, Json(ApiResponse::error(code, e.to_string())))
+        }
+        Ok(_) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(ApiResponse::error(
+                ErrorCode::InternalError,
+                "Unexpected response from controller monitor".to_string(),
+            )),
+        ),
+        Err(e) => {
+            error!("Failed to run self-test: {e}");
+            (
+                StatusCode::SERVICE_UNAVAILABLE,
+                Json(ApiResponse::error(
+                    ErrorCode::ControllerError,
+                    format!("Failed to run self-test: {e}"),
+                )),
+            )
         }
     }
 }
@@ -469,6 +1848,8 @@ async fn machine_status(
                 ready: false,
                 active_jobs: 0,
                 last_update: chrono::Utc::now(),
+                maintenance: MaintenanceStatus::default(),
+                overheating: Vec::new(),
             };
             Json(ApiResponse::success(fallback_status))
         }
@@ -479,12 +1860,34 @@ async fn machine_status(
                 ready: false,
                 active_jobs: 0,
                 last_update: chrono::Utc::now(),
+                maintenance: MaintenanceStatus::default(),
+                overheating: Vec::new(),
             };
             Json(ApiResponse::success(fallback_status))
         }
     }
 }
 
+/// Query parameters for the controller health history
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case")]
+struct HealthHistoryQuery {
+    /// How many hours back to report, limited to the retention
+    hours: Option<u64>,
+}
+
+/// The controller's recent health checks, bucketed for the status page's uptime chart
+async fn health_history(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<HealthHistoryQuery>,
+) -> Json<ApiResponse<HealthHistoryReport>> {
+    let report = state
+        .controller
+        .health_history()
+        .report(query.hours.unwrap_or(24), chrono::Utc::now());
+    Json(ApiResponse::success(report))
+}
+
 async fn sensor_readings(
     State(state): State<Arc<AppState>>,
 ) -> Json<ApiResponse<crate::controller_monitor::SensorReadings>> {
@@ -499,10 +1902,7 @@ async fn sensor_readings(
             let fallback_readings = crate::controller_monitor::SensorReadings {
                 case_ready: false,
                 case_in_view: false,
-                timestamp: std::time::SystemTime::now()
-                    .duration_since(std::time::UNIX_EPOCH)
-                    .map(|d| d.as_secs())
-                    .unwrap_or(0),
+                timestamp: chrono::Utc::now(),
             };
             Json(ApiResponse::success(fallback_readings))
         }
@@ -511,25 +1911,29 @@ async fn sensor_readings(
             let fallback_readings = crate::controller_monitor::SensorReadings {
                 case_ready: false,
                 case_in_view: false,
-                timestamp: std::time::SystemTime::now()
-                    .duration_since(std::time::UNIX_EPOCH)
-                    .map(|d| d.as_secs())
-                    .unwrap_or(0),
+                timestamp: chrono::Utc::now(),
             };
             Json(ApiResponse::success(fallback_readings))
         }
     }
 }
 
-async fn hardware_status(
-    State(state): State<Arc<AppState>>,
-) -> Json<ApiResponse<HashMap<String, String>>> {
-    match state
+/// The controller's connection details, with when it was last seen and checked
+#[derive(Debug, Serialize)]
+struct HardwareStatus {
+    #[serde(flatten)]
+    details: HashMap<String, String>,
+    #[serde(flatten)]
+    times: ControllerTimes,
+}
+
+async fn hardware_status(State(state): State<Arc<AppState>>) -> Json<ApiResponse<HardwareStatus>> {
+    let mut status = match state
         .controller
         .send_command(ControllerCommand::GetHardwareStatus)
         .await
     {
-        Ok(ControllerResponse::HardwareData(status)) => Json(ApiResponse::success(status)),
+        Ok(ControllerResponse::HardwareData(status)) => status,
         Ok(_) => {
             error!("Unexpected response type for hardware status");
             let mut fallback_status = HashMap::new();
@@ -538,7 +1942,7 @@ async fn hardware_status(
                 "esphome_hostname".to_string(),
                 state.settings.esphome_hostname.clone(),
             );
-            Json(ApiResponse::success(fallback_status))
+            fallback_status
         }
         Err(e) => {
             error!("Failed to get hardware status: {e}");
@@ -548,20 +1952,175 @@ async fn hardware_status(
                 "esphome_hostname".to_string(),
                 state.settings.esphome_hostname.clone(),
             );
-            Json(ApiResponse::success(fallback_status))
+            fallback_status
+        }
+    };
+
+    status.insert(
+        "active_streams".to_string(),
+        state.stream_limiter.total_active().to_string(),
+    );
+    status.insert(
+        "max_concurrent_streams".to_string(),
+        state.stream_limiter.max_per_camera().to_string(),
+    );
+    Json(ApiResponse::success(HardwareStatus {
+        details: status,
+        times: state.controller.get_status().await.times(),
+    }))
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case")]
+struct JogRequest {
+    servo: String,
+    delta: i16,
+}
+
+/// The HTTP status and error code for a refused machine command
+fn refusal_status(e: &CalibrationError) -> (StatusCode, ErrorCode) {
+    match e {
+        CalibrationError::Maintenance(_) => (StatusCode::CONFLICT, ErrorCode::MaintenanceMode),
+        CalibrationError::SortCycleActive
+        | CalibrationError::Calibrating
+        | CalibrationError::NotCalibrating => (StatusCode::CONFLICT, ErrorCode::MachineBusy),
+        CalibrationError::UnknownServo(_) => (StatusCode::BAD_REQUEST, ErrorCode::InvalidRequest),
+        CalibrationError::Device(_) => (StatusCode::BAD_GATEWAY, ErrorCode::ControllerError),
+    }
+}
+
+/// Send a calibration command, mapping refusals to HTTP status codes
+async fn calibration_command(
+    state: &AppState,
+    command: ControllerCommand,
+) -> Result<CalibrationStatus, (StatusCode, ApiError)> {
+    match state.controller.send_command(command).await {
+        Ok(ControllerResponse::Calibration(status)) => Ok(status),
+        Ok(ControllerResponse::CalibrationFailed(e)) => {
+            let (status_code, code) = refusal_status(&e);
+            Err((status_code, ApiError::new(code, e.to_string())))
+        }
+        Ok(ControllerResponse::Error(e)) => Err((
+            StatusCode::INTERNAL_SERVER_ERROR,
+            ApiError::new(ErrorCode::ControllerError, e),
+        )),
+        Ok(_) => Err((
+            StatusCode::INTERNAL_SERVER_ERROR,
+            ApiError::new(
+                ErrorCode::InternalError,
+                "Unexpected response from controller monitor",
+            ),
+        )),
+        Err(e) => Err((
+            StatusCode::SERVICE_UNAVAILABLE,
+            ApiError::new(
+                ErrorCode::ControllerError,
+                format!("Controller monitor unavailable: {e}"),
+            ),
+        )),
+    }
+}
+
+fn calibration_reply(
+    result: Result<CalibrationStatus, (StatusCode, ApiError)>,
+) -> (StatusCode, Json<ApiResponse<CalibrationStatus>>) {
+    match result {
+        Ok(status) => (StatusCode::OK, Json(ApiResponse::success(status))),
+        Err((status_code, error)) => {
+            warn!("Calibration request failed: {}", error.message);
+            (status_code, Json(ApiResponse::failure(error)))
         }
     }
 }
 
-async fn list_cameras(State(state): State<Arc<AppState>>) -> Json<ApiResponse<Vec<CameraInfo>>> {
+async fn get_calibration(
+    State(state): State<Arc<AppState>>,
+) -> (StatusCode, Json<ApiResponse<CalibrationStatus>>) {
+    calibration_reply(calibration_command(&state, ControllerCommand::GetCalibration).await)
+}
+
+/// Pause automation so the servos can be jogged into position
+async fn start_calibration(
+    State(state): State<Arc<AppState>>,
+) -> (StatusCode, Json<ApiResponse<CalibrationStatus>>) {
+    calibration_reply(calibration_command(&state, ControllerCommand::StartCalibration).await)
+}
+
+/// Nudge a servo relative to its current position
+async fn jog_servo(
+    State(state): State<Arc<AppState>>,
+    Json(request): Json<JogRequest>,
+) -> (StatusCode, Json<ApiResponse<CalibrationStatus>>) {
+    let command = ControllerCommand::JogServo {
+        servo: request.servo,
+        delta: request.delta,
+    };
+    calibration_reply(calibration_command(&state, command).await)
+}
+
+/// Save the calibrated servo positions to the user config and resume automation
+async fn save_calibration(
+    State(state): State<Arc<AppState>>,
+) -> (StatusCode, Json<ApiResponse<CalibrationStatus>>) {
+    let status = match calibration_command(&state, ControllerCommand::SaveCalibration).await {
+        Ok(status) => status,
+        Err(e) => return calibration_reply(Err(e)),
+    };
+
+    let positions = status.positions.clone();
+    let saved = state
+        .config_writer
+        .update(move |user_config| user_config.servo_positions = positions)
+        .await;
+    match saved {
+        Ok(()) => calibration_reply(Ok(status)),
+        Err(e) => calibration_reply(Err((
+            StatusCode::INTERNAL_SERVER_ERROR,
+            ApiError::new(
+                ErrorCode::InternalError,
+                format!("Failed to save servo positions: {e}"),
+            ),
+        ))),
+    }
+}
+
+async fn list_cameras(State(state): State<Arc<AppState>>, headers: HeaderMap) -> Response {
+    // Versions are read before collecting, so a change made meanwhile gives a newer ETag next time
+    let etag = version_etag(
+        "cameras",
+        &[
+            state.camera_manager.version(),
+            state.usb_camera_manager.version(),
+            state.stream_limiter.version(),
+            state.config_writer.version(),
+        ],
+    );
+    if if_none_match(&headers, &etag) {
+        return not_modified(&etag);
+    }
+    let cameras = collect_cameras(&state).await;
+    server_timing::time_sync(SERIALIZE, None, || {
+        with_etag(&etag, Json(ApiResponse::success(cameras)))
+    })
+}
+
+/// Cameras from both managers, remembering the list for the dashboard
+async fn collect_cameras(state: &AppState) -> Vec<CameraInfo> {
     let mut all_cameras = Vec::new();
 
     // Load saved camera selections from config
-    let user_config = Settings::load_user_config();
-    let saved_selections = user_config.get_selected_cameras();
+    let user_config = current_user_config(state).await;
+    let active_preset = user_config.active_camera_preset();
+    let preset_for = |camera_id: &str| {
+        active_preset
+            .filter(|(_, preset)| preset.contains(camera_id))
+            .map(|(name, _)| name.to_string())
+    };
 
     // Get ESPHome camera status
     let esphome_status = state.camera_manager.get_status().await.unwrap_or_default();
+    let now = chrono::Utc::now();
+    let stale_threshold = Duration::from_secs(state.settings.camera_stale_seconds);
 
     // Get ESPHome cameras
     match state.camera_manager.list_cameras().await {
@@ -571,23 +2130,44 @@ async fn list_cameras(State(state): State<Arc<AppState>>) -> Json<ApiResponse<Ve
                 .map(|cam| {
                     // Check both in-memory status and saved config for selection
                     let is_selected_in_memory = esphome_status.selected_cameras.contains(&cam.id);
-                    let is_selected_in_config = saved_selections.contains(&cam.id);
+                    let is_selected_in_config = user_config.is_camera_selected(&cam.id);
                     let is_selected = is_selected_in_memory || is_selected_in_config;
                     let is_active = is_selected_in_memory && esphome_status.streaming;
+                    let capture_stats = esphome_status
+                        .capture_stats
+                        .get(&cam.id)
+                        .cloned()
+                        .unwrap_or_default();
+                    let stream_health = esphome_status
+                        .stream_health
+                        .get(&cam.id)
+                        .cloned()
+                        .unwrap_or_default();
+                    let active_streams = state.stream_limiter.active_for(&cam.id);
+
+                    let camera_config = user_config.get_camera_config(&cam.id);
+                    let orientation = camera_config.orientation();
+                    let offline_for_secs = cam.offline_for(now).map(|offline| offline.as_secs());
+                    let stale = cam.is_stale(now, stale_threshold);
+                    let stream_mode = state.snapshot_streams.mode(&cam.id);
+                    let preset = preset_for(&cam.id);
 
                     CameraInfo {
-                        id: cam.id,
-                        name: cam.name,
-                        hostname: Some(cam.hostname),
-                        online: cam.online,
+                        camera: Descriptor::from(cam).with_nickname(camera_config.nickname),
                         view_type: None,
-                        camera_type: CameraType::EspHome,
-                        index: None,
-                        vendor_id: None,
-                        product_id: None,
-                        serial_number: None,
                         is_active,
                         is_selected,
+                        capture_stats,
+                        active_streams,
+                        stream_health,
+                        orientation,
+                        // The MJPEG stream is proxied untouched, so the dashboard turns it with CSS
+                        stream_oriented: false,
+                        offline_for_secs,
+                        stale,
+                        pre_capture: None,
+                        stream_mode: Some(stream_mode),
+                        preset,
                     }
                 })
                 .collect();
@@ -614,23 +2194,43 @@ async fn list_cameras(State(state): State<Arc<AppState>>) -> Json<ApiResponse<Ve
                     // Check both in-memory status and saved config for selection
                     let is_selected_in_memory =
                         usb_status.selected_cameras().contains(&cam.hardware_id);
-                    let is_selected_in_config = saved_selections.contains(&cam.hardware_id);
+                    let is_selected_in_config = user_config.is_camera_selected(&cam.hardware_id);
                     let is_selected = is_selected_in_memory || is_selected_in_config;
                     let is_active = is_selected_in_memory && usb_status.streaming;
 
+                    let capture_stats = usb_status
+                        .capture_stats
+                        .get(&cam.hardware_id)
+                        .cloned()
+                        .unwrap_or_default();
+                    let stream_health = usb_status
+                        .stream_health
+                        .get(&cam.hardware_id)
+                        .cloned()
+                        .unwrap_or_default();
+
+                    let active_streams = state.stream_limiter.active_for(&cam.hardware_id);
+
+                    let camera_config = user_config.get_camera_config(&cam.hardware_id);
+                    let orientation = camera_config.orientation();
+                    let pre_capture = usb_status.pre_capture.get(&cam.hardware_id).cloned();
+                    let preset = preset_for(&cam.hardware_id);
+
                     CameraInfo {
-                        id: cam.hardware_id.clone(),
-                        name: cam.name,
-                        hostname: None,
-                        online: cam.connected,
+                        camera: Descriptor::from(cam).with_nickname(camera_config.nickname),
                         view_type: None,
-                        camera_type: CameraType::Usb,
-                        index: Some(cam.index),
-                        vendor_id: cam.vendor_id,
-                        product_id: cam.product_id,
-                        serial_number: cam.serial_number,
                         is_active,
                         is_selected,
+                        capture_stats,
+                        active_streams,
+                        stream_health,
+                        orientation,
+                        stream_oriented: true,
+                        offline_for_secs: None,
+                        stale: false,
+                        pre_capture,
+                        stream_mode: None,
+                        preset,
                     }
                 })
                 .collect();
@@ -642,14 +2242,26 @@ async fn list_cameras(State(state): State<Arc<AppState>>) -> Json<ApiResponse<Ve
     }
 
     // Sort cameras by human-facing name for consistency
-    all_cameras.sort_by(|a, b| a.name.cmp(&b.name));
+    all_cameras.sort_by(|a, b| a.camera.name.cmp(&b.camera.name));
+
+    state.camera_cache.store(&all_cameras);
+    all_cameras
+}
 
-    Json(ApiResponse::success(all_cameras))
+/// The user config as the config writer holds it, falling back to the file if the writer is gone
+async fn current_user_config(state: &AppState) -> UserConfig {
+    match state.config_writer.current().await {
+        Ok(user_config) => user_config,
+        Err(e) => {
+            warn!("Reading user config from disk: {e}");
+            state.settings.load_user_config()
+        }
+    }
 }
 
 /// Restore saved camera selections from persistent config
 async fn restore_saved_camera_selections(state: &Arc<AppState>) {
-    let user_config = Settings::load_user_config();
+    let user_config = current_user_config(state).await;
     let saved_selections = user_config.get_selected_cameras();
 
     if saved_selections.is_empty() {
@@ -658,17 +2270,10 @@ async fn restore_saved_camera_selections(state: &Arc<AppState>) {
 
     info!("Restoring saved camera selections: {:?}", saved_selections);
 
-    // Separate camera IDs by type
-    let mut esphome_cameras = Vec::new();
-    let mut usb_cameras = Vec::new();
-
-    for camera_id in saved_selections {
-        if camera_id.starts_with(USB_DEVICE_PREFIX_WITH_COLON) {
-            usb_cameras.push(camera_id.clone());
-        } else {
-            esphome_cameras.push(camera_id.clone());
-        }
-    }
+    let IdsByKind {
+        esphome: esphome_cameras,
+        usb: usb_cameras,
+    } = saved_selections.iter().cloned().collect();
 
     // Restore ESPHome camera selections
     if !esphome_cameras.is_empty() {
@@ -689,37 +2294,132 @@ async fn restore_saved_camera_selections(state: &Arc<AppState>) {
     }
 }
 
-async fn detect_cameras(State(state): State<Arc<AppState>>) -> Json<ApiResponse<String>> {
-    info!("Camera detection requested - triggering async detection");
-
-    // Trigger detection asynchronously without waiting for results
-    let camera_manager = state.camera_manager.clone();
-    let usb_camera_manager = state.usb_camera_manager.clone();
-    let state_clone = state.clone();
+/// Query parameters for camera detection
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case")]
+struct DetectQuery {
+    /// Ask every USB camera that isn't streaming for its formats, instead of using cached ones
+    #[serde(default)]
+    deep: bool,
+    /// Scan even if the last detection is within `detect_freshness_s`
+    #[serde(default)]
+    force: bool,
+}
 
-    tokio::spawn(async move {
-        info!("Starting async camera detection");
+/// How USB detection went, and that ESPHome detection has started
+#[derive(Serialize)]
+#[serde(rename_all = "snake_case")]
+struct DetectionStarted {
+    message: String,
+    /// `None` if USB detection failed
+    usb: Option<DetectionReport>,
+    /// Neither manager scanned: the results are from a recent or running detection
+    cached: bool,
+}
 
-        // Detect ESPHome cameras
-        if let Err(e) = camera_manager.detect_cameras().await {
-            error!("Failed to detect ESPHome cameras: {e}");
+/// Detect cameras, reusing a running or recent detection unless `force` is set
+///
+/// Every dashboard load calls this, so the managers only scan again once
+/// `detect_freshness_s` has passed since their last scan finished.
+async fn detect_cameras(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<DetectQuery>,
+) -> Json<ApiResponse<DetectionStarted>> {
+    info!(
+        "Camera detection requested (deep: {}, force: {})",
+        query.deep, query.force
+    );
+    let freshness = Duration::from_secs(state.settings.detect_freshness_s);
+    // A deep detection asks for something a quick one didn't, so it always scans
+    let force = query.force || query.deep;
+
+    // USB detection is quick now that formats are cached, so its report can be returned
+    let (usb_detection, usb_cached) = state
+        .detection
+        .usb
+        .run(force, freshness, || async {
+            let detection = state.usb_camera_manager.detect_cameras(query.deep).await?;
+            let message = format!(
+                "Detected {} USB camera(s) in {}ms",
+                detection.cameras.len(),
+                detection.report.duration_ms
+            );
+            Ok::<_, OurError>((detection.report, message))
+        })
+        .await;
+    let (usb, usb_message) = match usb_detection {
+        Ok((report, message)) => (Some(report), message),
+        Err(e) => {
+            error!("Failed to detect USB cameras: {e}");
+            (None, format!("USB camera detection failed: {e}"))
         }
+    };
 
-        // Detect USB cameras
-        if let Err(e) = usb_camera_manager.detect_cameras().await {
-            error!("Failed to detect USB cameras: {e}");
+    // ESPHome detection waits on network timeouts, so it carries on after the response
+    let esphome_scanning = match state.detection.esphome.try_start(force, freshness) {
+        Some(permit) => {
+            let state = state.clone();
+            tokio::spawn(async move {
+                info!("Starting async camera detection");
+                let detected = async {
+                    state.camera_manager.detect_cameras().await?;
+                    // The manager answers in order, so the list comes back once detection is done
+                    state.camera_manager.list_cameras().await.map(drop)
+                };
+                match detected.await {
+                    Ok(()) => {
+                        // Restore saved camera selections after detection
+                        restore_saved_camera_selections(&state).await;
+                        permit.finish(());
+                        info!("Async camera detection completed");
+                    }
+                    Err(e) => error!("Failed to detect ESPHome cameras: {e}"),
+                }
+            });
+            true
         }
+        None => false,
+    };
+    let esphome_message = if esphome_scanning {
+        "ESPHome camera detection started"
+    } else {
+        "ESPHome cameras were detected recently or are being detected"
+    };
+    let cached = usb_cached && !esphome_scanning;
+    if cached {
+        debug!("Camera detection answered from the last detection");
+    }
 
-        // Restore saved camera selections after detection
-        restore_saved_camera_selections(&state_clone).await;
+    Json(ApiResponse::success(DetectionStarted {
+        message: format!("{usb_message}. {esphome_message}, use /api/cameras to check results."),
+        usb,
+        cached,
+    }))
+}
 
-        info!("Async camera detection completed");
-    });
+/// Hand each camera ID to the manager that owns it
+async fn select_in_managers(state: &AppState, camera_ids: Vec<CameraId>) -> Result<(), String> {
+    let IdsByKind {
+        esphome: esphome_cameras,
+        usb: usb_cameras,
+    } = camera_ids.into_iter().collect();
 
-    // Return immediately with status message
-    Json(ApiResponse::success(
-        "Camera detection started. Use /api/cameras to check results.".to_string(),
-    ))
+    // Select ESPHome cameras if any
+    if !esphome_cameras.is_empty()
+        && let Err(e) = state.camera_manager.select_cameras(esphome_cameras).await
+    {
+        error!("Failed to select ESPHome cameras: {e}");
+        return Err(format!("Failed to select ESPHome cameras: {e}"));
+    }
+
+    // Select USB cameras if any
+    if !usb_cameras.is_empty()
+        && let Err(e) = state.usb_camera_manager.select_cameras(usb_cameras).await
+    {
+        error!("Failed to select USB cameras: {e}");
+        return Err(format!("Failed to select USB cameras: {e}"));
+    }
+    Ok(())
 }
 
 #[instrument(level = "info", skip(state))]
@@ -730,44 +2430,17 @@ async fn select_cameras(
     // Store camera IDs for persistence before consuming them
     let camera_ids_for_config = payload.camera_ids.clone();
 
-    // Separate camera IDs by type
-    let mut esphome_cameras = Vec::new();
-    let mut usb_cameras = Vec::new();
-
-    for camera_id in payload.camera_ids {
-        if camera_id.starts_with(USB_DEVICE_PREFIX_WITH_COLON) {
-            usb_cameras.push(camera_id);
-        } else {
-            esphome_cameras.push(camera_id);
-        }
+    if let Err(message) = select_in_managers(&state, payload.camera_ids).await {
+        return Json(ApiResponse::<()>::error(ErrorCode::CameraError, message));
     }
 
-    // Select ESPHome cameras if any
-    if !esphome_cameras.is_empty()
-        && let Err(e) = state.camera_manager.select_cameras(esphome_cameras).await {
-            error!("Failed to select ESPHome cameras: {e}");
-            return Json(ApiResponse::<()>::error(format!(
-                "Failed to select ESPHome cameras: {e}"
-            )));
-        }
-
-    // Select USB cameras if any
-    if !usb_cameras.is_empty()
-        && let Err(e) = state.usb_camera_manager.select_cameras(usb_cameras).await {
-            error!("Failed to select USB cameras: {e}");
-            return Json(ApiResponse::<()>::error(format!(
-                "Failed to select USB cameras: {e}"
-            )));
-        }
-
-    // Save selected camera IDs to persistent configuration
-    let mut user_config = Settings::load_user_config();
-    user_config.set_selected_cameras(camera_ids_for_config);
-    if let Err(e) = Settings::save_user_config(&user_config) {
+    // Queue the selection for the config writer, which batches rapid changes
+    if let Err(e) = state
+        .config_writer
+        .mutate(move |user_config| user_config.set_selected_cameras(camera_ids_for_config))
+    {
         error!("Failed to save camera selections to config: {e}");
         // Don't fail the request, just log the error
-    } else {
-        info!("Saved camera selections to persistent configuration");
     }
 
     Json(ApiResponse::success(()))
@@ -787,17 +2460,10 @@ async fn start_cameras(
             payload.camera_ids
         );
 
-        // Separate camera IDs by type
-        let mut esphome_cameras = Vec::new();
-        let mut usb_cameras = Vec::new();
-
-        for camera_id in &payload.camera_ids {
-            if camera_id.starts_with(USB_DEVICE_PREFIX_WITH_COLON) {
-                usb_cameras.push(camera_id.clone());
-            } else {
-                esphome_cameras.push(camera_id.clone());
-            }
-        }
+        let IdsByKind {
+            esphome: esphome_cameras,
+            usb: usb_cameras,
+        } = payload.camera_ids.iter().cloned().collect();
 
         // Select ESPHome cameras if any
         if !esphome_cameras.is_empty()
@@ -813,14 +2479,14 @@ async fn start_cameras(
                 errors.push(format!("Failed to select USB cameras: {e}"));
             }
 
-        // Save selected camera IDs to persistent configuration
-        let mut user_config = Settings::load_user_config();
-        user_config.set_selected_cameras(payload.camera_ids.clone());
-        if let Err(e) = Settings::save_user_config(&user_config) {
+        // Queue the selection for the config writer, which batches rapid changes
+        let camera_ids = payload.camera_ids.clone();
+        if let Err(e) = state
+            .config_writer
+            .mutate(move |user_config| user_config.set_selected_cameras(camera_ids))
+        {
             error!("Failed to save camera selections to config: {e}");
             // Don't fail the request, just log the error
-        } else {
-            info!("Saved camera selections to persistent configuration");
         }
     } else {
         info!("Starting streaming with no specific camera selection");
@@ -857,10 +2523,12 @@ async fn start_cameras(
         }
     } else {
         // No cameras started
-        Json(ApiResponse::<()>::error(format!(
-            "Failed to start cameras: {}",
-            errors.join(", ")
-        )))
+        Json(ApiResponse::<()>::localized(
+            ErrorCode::CameraError,
+            Message::CamerasStartFailed {
+                errors: errors.join(", "),
+            },
+        ))
     }
 }
 
@@ -893,679 +2561,11524 @@ async fn stop_cameras(State(state): State<Arc<AppState>>) -> Json<ApiResponse<()
     if stopped_any || errors.is_empty() {
         Json(ApiResponse::success(()))
     } else {
-        Json(ApiResponse::<()>::error(format!(
-            "Failed to stop cameras: {}",
-            errors.join(", ")
-        )))
+        Json(ApiResponse::<()>::localized(
+            ErrorCode::CameraError,
+            Message::CamerasStopFailed {
+                errors: errors.join(", "),
+            },
+        ))
     }
 }
 
+/// Capture every selected camera into a new session, once per idempotency key when one is sent
 async fn capture_images(
     State(state): State<Arc<AppState>>,
-) -> Json<ApiResponse<HashMap<String, String>>> {
-    let status = state.camera_manager.get_status().await.unwrap_or_default();
-    let mut results = HashMap::new();
-
-    for camera_id in &status.selected_cameras {
-        match state.camera_manager.capture_image(camera_id.clone()).await {
-            Ok(image_data) => {
-                results.insert(
-                    camera_id.clone(),
-                    format!("Captured {} bytes", image_data.len()),
-                );
-            }
+    headers: HeaderMap,
+    body: axum::body::Bytes,
+) -> Response {
+    // The body is optional: an empty one takes a single frame from each camera
+    let request: CaptureRequest = if body.is_empty() {
+        CaptureRequest::default()
+    } else {
+        match serde_json::from_slice(&body) {
+            Ok(request) => request,
             Err(e) => {
-                error!("Failed to capture from camera {camera_id}: {e}");
-                results.insert(camera_id.clone(), format!("Error: {e}"));
+                return (
+                    StatusCode::BAD_REQUEST,
+                    Json(ApiResponse::<()>::error(
+                        ErrorCode::InvalidRequest,
+                        format!("Invalid capture request: {e}"),
+                    )),
+                )
+                    .into_response();
             }
         }
+    };
+    if let Some(Err(errors)) = request.burst.map(|burst| burst.validate()) {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(ApiResponse::<()>::failure(ApiError::validation(
+                "Invalid burst",
+                errors,
+            ))),
+        )
+            .into_response();
     }
 
-    Json(ApiResponse::success(results))
-}
-
-async fn camera_stream(
-    Path(camera_id): Path<String>,
-    State(state): State<Arc<AppState>>,
-) -> Result<Response<Body>, StatusCode> {
-    // Determine camera type and route to appropriate manager
-    if camera_id.starts_with(USB_DEVICE_PREFIX_WITH_COLON) {
-        stream_usb_camera(&state, &camera_id).await
-    } else {
-        stream_esphome_camera(&state, &camera_id).await
-    }
+    let key = match idempotency::request_key(&headers, request.idempotency_key.clone()) {
+        Ok(key) => key,
+        Err(e) => return invalid_idempotency_key(e),
+    };
+    let batches = match state.shell_data_manager.batches() {
+        Ok(batches) => batches,
+        Err(e) => return batch_failed::<()>("read batches", e).into_response(),
+    };
+    let batch_id = match &request.batch_id {
+        Some(batch_id) if batches.get(batch_id).is_none() => {
+            return batch_not_found::<()>(batch_id).into_response();
+        }
+        Some(batch_id) => Some(batch_id.clone()),
+        None => batches.active().map(|batch| batch.id.clone()),
+    };
+    state
+        .idempotency
+        .run("capture", key, || capture_once(&state, request, batch_id))
+        .await
 }
 
-async fn stream_usb_camera(
+async fn capture_once(
     state: &Arc<AppState>,
-    camera_id: &str,
-) -> Result<Response<Body>, StatusCode> {
-    let state_clone = state.clone();
-    let camera_id_clone = camera_id.to_string();
+    request: CaptureRequest,
+    batch_id: Option<String>,
+) -> Response {
+    if let Err(e) = state.disk_space.check("capture") {
+        error!("{e}");
+        return Json(ApiResponse::<()>::error(
+            ErrorCode::InsufficientStorage,
+            e.to_string(),
+        ))
+        .into_response();
+    }
 
-    // Create an MJPEG stream
-    let stream = async_stream::stream! {
-        // Send initial boundary
-        yield Ok::<Vec<u8>, Box<dyn std::error::Error + Send + Sync>>(
-            b"--frame\r\n".to_vec()
-        );
+    let saved_selection = current_user_config(state).await.selected_cameras;
+    let preflight = capture_preflight::check(
+        &state.camera_manager,
+        &state.usb_camera_manager,
+        &saved_selection,
+    )
+    .await;
+    if !preflight.offline.is_empty() {
+        let summary = preflight.offline_summary();
+        if !request.allow_partial {
+            warn!("Refused a capture, selected cameras are offline: {summary}");
+            return (
+                StatusCode::SERVICE_UNAVAILABLE,
+                Json(ApiResponse::localized_with_data(
+                    ErrorCode::CamerasOffline,
+                    Message::CamerasOffline { summary },
+                    OfflineCameras {
+                        offline: preflight.offline,
+                    },
+                )),
+            )
+                .into_response();
+        }
+        warn!("Capturing without offline cameras: {summary}");
+    }
 
-        loop {
-            // Check if streaming should continue
-            match state_clone.usb_camera_manager.get_status().await {
-                Ok(status) => {
-                    if !status.streaming {
-                        info!("USB camera streaming stopped for camera {}", camera_id_clone);
-                        break;
-                    }
-                }
-                Err(e) => {
-                    error!("Failed to get USB camera status: {e}");
-                    break;
-                }
-            }
+    if let Some(burst) = request.burst {
+        return capture_burst(state, burst, &preflight, batch_id)
+            .await
+            .into_response();
+    }
 
-            match state_clone.usb_camera_manager.capture_streaming_frame(&camera_id_clone).await {
-                Ok(frame_data) => {
-                    // Create MJPEG frame with proper headers
-                    let header = format!(
-                        "Content-Type: image/jpeg\r\nContent-Length: {}\r\n\r\n",
-                        frame_data.len()
-                    );
+    let session_id = ShellDataManager::generate_session_id();
+    let mut coverage = CaptureCoverage::new(preflight.selected.clone());
+    for camera in &preflight.offline {
+        coverage.skip(&camera.camera_id, camera.reason.clone());
+    }
+    if coverage.expected.is_empty() {
+        return (
+            StatusCode::BAD_GATEWAY,
+            Json(ApiResponse::<()>::localized(
+                ErrorCode::CameraError,
+                Message::NoCamerasSelected,
+            )),
+        )
+            .into_response();
+    }
 
-                    // Yield the header
-                    yield Ok(header.as_bytes().to_vec());
+    let started_at = chrono::Utc::now();
+    let (mut results, images, snapshot) =
+        match capture_into_session::<()>(state, &session_id, &preflight.ready, &mut coverage).await
+        {
+            Ok(captured) => captured,
+            Err(response) => return response.into_response(),
+        };
+    for camera in preflight.offline {
+        results.insert(
+            camera.camera_id,
+            CameraCapture::Skipped {
+                reason: camera.reason,
+            },
+        );
+    }
+    let capture = SessionCapture {
+        session_id: session_id.clone(),
+        results,
+        images: images.clone(),
+        missing_cameras: coverage.missing(),
+        batch_id: batch_id.clone(),
+    };
+    if images.is_empty() {
+        return (
+            StatusCode::BAD_GATEWAY,
+            Json(ApiResponse::localized_with_data(
+                ErrorCode::CameraError,
+                Message::NoCameraDeliveredImage,
+                capture,
+            )),
+        )
+            .into_response();
+    }
 
-                    // Yield the frame data
-                    yield Ok(frame_data);
+    let cameras = session_summary::cameras_from_coverage(&coverage);
+    let mut shell = Shell::new(String::new(), String::new());
+    shell.date_captured = started_at;
+    shell.include = false;
+    shell.image_filenames = images.iter().map(|image| image.filename.clone()).collect();
+    shell.captured_images = Some(images);
+    shell.capture = Some(coverage);
+    shell.batch_id = batch_id;
+    shell.settings_snapshot = Some(snapshot);
+    let saved = server_timing::time_sync(DISK, Some("shell"), || {
+        state.shell_data_manager.save_shell(&session_id, &shell)
+    });
+    if let Err(e) = saved {
+        error!("Failed to save capture session {session_id}: {e}");
+        return (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(ApiResponse::<()>::localized(
+                ErrorCode::InternalError,
+                Message::CaptureSaveFailed {
+                    error: e.to_string(),
+                },
+            )),
+        )
+            .into_response();
+    }
+    write_session_summary(state, &session_id, started_at, &shell, cameras).await;
+    if capture.missing_cameras.is_empty() {
+        info!("Capture session {session_id} saved images from every camera");
+    } else {
+        warn!(
+            "Capture session {session_id} is missing cameras: {}",
+            capture.missing_cameras.join(", ")
+        );
+    }
+    server_timing::time_sync(SERIALIZE, None, || {
+        Json(ApiResponse::success(capture)).into_response()
+    })
+}
 
-                    // Yield the boundary for next frame
-                    yield Ok(b"\r\n--frame\r\n".to_vec());
-                }
-                Err(e) => {
-                    error!("Failed to capture frame from USB camera {}: {e}", camera_id_clone);
-                    // Don't break the stream, just wait and try again
-                    tokio::time::sleep(tokio::time::Duration::from_millis(200)).await;
-                    continue;
-                }
-            }
+/// Capture the cameras still missing from a session and add their images to it
+///
+/// Only cameras the session expected and got no image from are captured; the
+/// failures they replace are cleared from the session's coverage.
+async fn recapture_session(
+    Path(session_id): Path<String>,
+    State(state): State<Arc<AppState>>,
+) -> (StatusCode, Json<ApiResponse<SessionCapture>>) {
+    let shell = match state.shell_data_manager.load_shell(&session_id) {
+        Ok(shell) => shell,
+        Err(e) => {
+            return (
+                StatusCode::NOT_FOUND,
+                Json(ApiResponse::error(
+                    ErrorCode::ShellNotFound,
+                    format!("Session {session_id} not found: {e}"),
+                )),
+            );
+        }
+    };
+    let Some(mut coverage) = shell.capture.clone() else {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(ApiResponse::error(
+                ErrorCode::InvalidRequest,
+                format!("Session {session_id} has no record of which cameras it expected"),
+            )),
+        );
+    };
+    let missing = coverage.missing();
+    if missing.is_empty() {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(ApiResponse::error(
+                ErrorCode::InvalidRequest,
+                format!("Session {session_id} already has an image from every camera"),
+            )),
+        );
+    }
+    if let Err(e) = state.disk_space.check("capture") {
+        error!("{e}");
+        return (
+            StatusCode::INSUFFICIENT_STORAGE,
+            Json(ApiResponse::error(
+                ErrorCode::InsufficientStorage,
+                e.to_string(),
+            )),
+        );
+    }
 
-            // Control frame rate - roughly 5 FPS for streaming
-            tokio::time::sleep(tokio::time::Duration::from_millis(200)).await;
+    let cameras: IdsByKind = missing.into_iter().map(CameraId::from).collect();
+    let (results, images, snapshot) =
+        match capture_into_session(&state, &session_id, &cameras, &mut coverage).await {
+            Ok(captured) => captured,
+            Err(response) => return response,
+        };
+
+    let mut updated = shell.clone();
+    updated
+        .image_filenames
+        .extend(images.iter().map(|image| image.filename.clone()));
+    updated
+        .captured_images
+        .get_or_insert_with(Vec::new)
+        .extend(images.iter().cloned());
+    updated.capture = Some(coverage.clone());
+    match &mut updated.settings_snapshot {
+        Some(existing) => existing.merge_cameras(snapshot),
+        None => updated.settings_snapshot = Some(snapshot),
+    }
+    let capture = SessionCapture {
+        session_id: session_id.clone(),
+        results,
+        images,
+        missing_cameras: coverage.missing(),
+        batch_id: updated.batch_id.clone(),
+    };
+    match state
+        .shell_data_manager
+        .save_shell_at_revision(&session_id, &updated, shell.revision)
+    {
+        Ok(RevisionCheck::Saved(_)) => {
+            let cameras = session_summary::cameras_from_coverage(&coverage);
+            write_session_summary(
+                &state,
+                &session_id,
+                updated.date_captured,
+                &updated,
+                cameras,
+            )
+            .await;
         }
+        Ok(RevisionCheck::Conflict(_)) => {
+            return (
+                StatusCode::CONFLICT,
+                Json(ApiResponse::error_with_data(
+                    ErrorCode::RevisionConflict,
+                    format!("Session {session_id} changed while its cameras were recaptured"),
+                    capture,
+                )),
+            );
+        }
+        Err(e) => {
+            error!("Failed to save recaptured session {session_id}: {e}");
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ApiResponse::error(
+                    ErrorCode::InternalError,
+                    format!("Failed to save recaptured session: {e}"),
+                )),
+            );
+        }
+    }
+    if capture.images.is_empty() {
+        return (
+            StatusCode::BAD_GATEWAY,
+            Json(ApiResponse::localized_with_data(
+                ErrorCode::CameraError,
+                Message::NoMissingCameraDeliveredImage,
+                capture,
+            )),
+        );
+    }
+    info!(
+        "Recaptured {} cameras into session {session_id}",
+        capture.images.len()
+    );
+    (StatusCode::OK, Json(ApiResponse::success(capture)))
+}
+
+/// A session's settings snapshot, or the response explaining why there isn't one
+fn session_settings<T>(
+    state: &AppState,
+    session_id: &str,
+) -> Result<SettingsSnapshot, (StatusCode, Json<ApiResponse<T>>)> {
+    let failure = |status: StatusCode, code: ErrorCode, message: String| {
+        (status, Json(ApiResponse::error(code, message)))
     };
+    match state.shell_data_manager.get_shell(session_id) {
+        Ok(Some(shell)) => shell.settings_snapshot.ok_or_else(|| {
+            failure(
+                StatusCode::NOT_FOUND,
+                ErrorCode::ShellNotFound,
+                format!("Session {session_id} was captured before settings were recorded"),
+            )
+        }),
+        Ok(None) => Err(failure(
+            StatusCode::NOT_FOUND,
+            ErrorCode::ShellNotFound,
+            format!("Session {session_id} not found"),
+        )),
+        Err(OurError::InvalidName { .. }) => Err(failure(
+            StatusCode::BAD_REQUEST,
+            ErrorCode::InvalidRequest,
+            Message::InvalidSessionId.render(),
+        )),
+        Err(e) => {
+            error!("Failed to load session {session_id} for its settings: {e}");
+            Err(failure(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                ErrorCode::InternalError,
+                Message::ShellLoadFailed {
+                    error: e.to_string(),
+                }
+                .render(),
+            ))
+        }
+    }
+}
 
-    // Convert the stream to the format expected by Body::from_stream
-    let byte_stream = stream.map(|result| result.map(axum::body::Bytes::from));
+/// The settings a session was captured with
+async fn get_session_settings(
+    Path(session_id): Path<String>,
+    State(state): State<Arc<AppState>>,
+) -> (StatusCode, Json<ApiResponse<SettingsSnapshot>>) {
+    match session_settings(&state, &session_id) {
+        Ok(snapshot) => (StatusCode::OK, Json(ApiResponse::success(snapshot))),
+        Err(response) => response,
+    }
+}
 
-    let body = Body::from_stream(byte_stream);
+/// Which capture settings differ between two sessions
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "snake_case")]
+struct SessionSettingsDiff {
+    a: String,
+    b: String,
+    differences: Vec<SettingDifference>,
+}
 
-    Response::builder()
-        .header("Content-Type", "multipart/x-mixed-replace; boundary=frame")
-        .header("Cache-Control", "no-cache, no-store, must-revalidate")
-        .header("Pragma", "no-cache")
-        .header("Expires", "0")
-        .header("Connection", "close")
-        .body(body)
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)
+/// Compare the settings two sessions were captured with
+async fn diff_session_settings(
+    Path((a, b)): Path<(String, String)>,
+    State(state): State<Arc<AppState>>,
+) -> (StatusCode, Json<ApiResponse<SessionSettingsDiff>>) {
+    let before = match session_settings(&state, &a) {
+        Ok(snapshot) => snapshot,
+        Err(response) => return response,
+    };
+    let after = match session_settings(&state, &b) {
+        Ok(snapshot) => snapshot,
+        Err(response) => return response,
+    };
+    let differences = before.diff(&after);
+    (
+        StatusCode::OK,
+        Json(ApiResponse::success(SessionSettingsDiff {
+            a,
+            b,
+            differences,
+        })),
+    )
 }
 
-async fn stream_esphome_camera(
-    state: &Arc<AppState>,
-    camera_id: &str,
-) -> Result<Response<Body>, StatusCode> {
-    // Get camera info to find the stream URL
-    match state.camera_manager.list_cameras().await {
-        Ok(cameras) => {
-            let camera = cameras
-                .iter()
-                .find(|c| c.id == camera_id)
-                .ok_or(StatusCode::NOT_FOUND)?;
+/// Each camera's outcome, the images saved and the settings they were captured with
+type CapturedSession = (
+    BTreeMap<String, CameraCapture>,
+    Vec<CapturedImage>,
+    SettingsSnapshot,
+);
+
+/// Capture one frame from each camera and save it as `{session}_camera_{index}.jpg`
+///
+/// A camera's index is its position in the coverage's expected cameras, and
+/// every camera's outcome is recorded in the coverage. One camera failing
+/// doesn't stop the rest being saved. The cameras' settings are snapshotted
+/// just before they are captured.
+async fn capture_into_session<T: Serialize>(
+    state: &AppState,
+    session_id: &str,
+    cameras: &IdsByKind,
+    coverage: &mut CaptureCoverage,
+) -> Result<CapturedSession, (StatusCode, Json<ApiResponse<T>>)> {
+    let image_directory = state.settings.image_directory.clone();
+    if let Err(e) = tokio::fs::create_dir_all(&image_directory).await {
+        error!("Failed to create image directory: {e}");
+        return Err((
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(ApiResponse::localized(
+                ErrorCode::InternalError,
+                Message::ImageDirectoryFailed {
+                    error: e.to_string(),
+                },
+            )),
+        ));
+    }
+    let camera_configs = current_user_config(state).await.camera_configs;
+    let snapshot = settings_snapshot(state, cameras, &camera_configs).await;
+
+    let mut results = BTreeMap::new();
+    let mut images = Vec::new();
+    for (camera_id, result) in capture_cameras(state, cameras).await {
+        let camera_index = coverage.camera_index(&camera_id).unwrap_or_default();
+        let filename = format!("{session_id}_camera_{camera_index}.jpg");
+        let saved = match result {
+            Ok(jpeg) => {
+                let config = camera_configs.get(&camera_id);
+                let overlay = CaptureOverlay::for_capture(
+                    &state.settings,
+                    &camera_id,
+                    config,
+                    session_id,
+                    chrono::Utc::now(),
+                );
+                async {
+                    let (jpeg, guess) = guess_view(config, jpeg).await?;
+                    let jpeg = stamp_overlay(overlay, jpeg).await?;
+                    server_timing::time(
+                        DISK,
+                        Some("image"),
+                        tokio::fs::write(image_directory.join(&filename), &jpeg),
+                    )
+                    .await?;
+                    Ok::<_, OurError>((jpeg.len(), guess))
+                }
+                .await
+            }
+            Err(e) => Err(e),
+        };
+        match saved {
+            Ok((bytes, guess)) => {
+                let config = camera_configs.get(&camera_id);
+                let mut image = camera_image(camera_index, filename.clone(), &camera_id, config);
+                if let Some(guess) = guess {
+                    image.apply_view_guess(guess);
+                }
+                images.push(image);
+                coverage.record(&camera_id, Ok(()));
+                results.insert(camera_id, CameraCapture::Captured { filename, bytes });
+            }
+            Err(e) => {
+                error!("Failed to capture from camera {camera_id}: {e}");
+                let error = e.to_string();
+                coverage.record(&camera_id, Err(error.clone()));
+                results.insert(camera_id, CameraCapture::Failed { error });
+            }
+        }
+    }
+    Ok((results, images, snapshot))
+}
 
-            // Proxy the request to the ESPHome camera's stream URL
-            match reqwest::get(camera.stream_url.clone()).await {
-                Ok(response) => {
-                    let status = response.status();
-                    let headers = response.headers().clone();
-                    let body = response
-                        .bytes()
-                        .await
-                        .map_err(|_| StatusCode::BAD_GATEWAY)?;
+/// The settings `cameras` are about to be captured with
+///
+/// USB cameras are asked for their format and brightness; one that doesn't
+/// answer is recorded without them rather than holding up the capture.
+async fn settings_snapshot(
+    state: &AppState,
+    cameras: &IdsByKind,
+    camera_configs: &HashMap<String, CameraConfig>,
+) -> SettingsSnapshot {
+    let mut observed: Vec<(String, ObservedCamera)> = cameras
+        .esphome
+        .iter()
+        .map(|camera_id| (camera_id.to_string(), ObservedCamera::default()))
+        .collect();
+    if !cameras.usb.is_empty() {
+        let status = state
+            .usb_camera_manager
+            .get_status()
+            .await
+            .inspect_err(|e| {
+                warn!("Couldn't read USB camera formats for the settings snapshot: {e}")
+            })
+            .ok();
+        for camera_id in &cameras.usb {
+            let hardware_id = camera_id.to_string();
+            let format = status
+                .as_ref()
+                .and_then(|status| status.cameras.get(&hardware_id))
+                .and_then(|camera| camera.current_format.clone());
+            let brightness = state
+                .usb_camera_manager
+                .get_brightness(hardware_id.clone())
+                .await
+                .inspect_err(|e| warn!("Couldn't read the brightness of {hardware_id}: {e}"))
+                .ok();
+            observed.push((hardware_id, ObservedCamera { format, brightness }));
+        }
+    }
+    SettingsSnapshot::new(&state.settings, camera_configs, observed)
+}
 
-                    let mut builder = Response::builder().status(status);
+/// Capture one frame from each camera, ESPHome cameras first
+async fn capture_cameras(
+    state: &AppState,
+    cameras: &IdsByKind,
+) -> Vec<(String, OurResult<Vec<u8>>)> {
+    let mut captures = Vec::new();
+    for camera_id in &cameras.esphome {
+        let result = state
+            .camera_manager
+            .capture_image(camera_id.to_string())
+            .await;
+        captures.push((camera_id.to_string(), result));
+    }
 
-                    // Copy relevant headers
-                    for (name, value) in headers.iter() {
-                        if name == "content-type" || name == "cache-control" || name == "connection"
-                        {
-                            builder = builder.header(name, value);
+    // USB cameras are held for the whole capture so their settings can't change between images
+    let usb_cameras: Vec<String> = cameras.usb.iter().map(ToString::to_string).collect();
+    if !usb_cameras.is_empty() {
+        let session_id = ShellDataManager::generate_session_id();
+        match state
+            .usb_camera_manager
+            .capture_session(&session_id, &usb_cameras)
+            .await
+        {
+            Ok(usb_captures) => captures.extend(usb_captures),
+            Err(e) => {
+                error!("Failed to run USB capture session {session_id}: {e}");
+                let message = e.to_string();
+                captures.extend(
+                    usb_cameras
+                        .into_iter()
+                        .map(|camera_id| (camera_id, Err(OurError::App(message.clone())))),
+                );
+            }
+        }
+    }
+    captures
+}
+
+/// Take a burst from every selected camera that is online into a new session
+///
+/// Rounds start `interval_ms` apart, each taking a frame from every camera.
+/// Every frame is saved and scored, then the session is saved untagged with
+/// each camera's sharpest frame marked preferred.
+async fn capture_burst(
+    state: &AppState,
+    burst: BurstRequest,
+    preflight: &Preflight,
+    batch_id: Option<String>,
+) -> (StatusCode, Json<ApiResponse<BurstCapture>>) {
+    let session_id = ShellDataManager::generate_session_id();
+    let image_directory = state.settings.image_directory.clone();
+    if let Err(e) = tokio::fs::create_dir_all(&image_directory).await {
+        error!("Failed to create image directory: {e}");
+        return (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(ApiResponse::localized(
+                ErrorCode::InternalError,
+                Message::ImageDirectoryFailed {
+                    error: e.to_string(),
+                },
+            )),
+        );
+    }
+    let camera_configs = current_user_config(state).await.camera_configs;
+    let snapshot = settings_snapshot(state, &preflight.ready, &camera_configs).await;
+
+    // Cameras are numbered in the order they first answer
+    let mut camera_indices: HashMap<String, u32> = HashMap::new();
+    let mut captured: HashMap<String, u32> = HashMap::new();
+    let mut last_errors: HashMap<String, String> = HashMap::new();
+    let mut images = Vec::new();
+    let started_at = chrono::Utc::now();
+    let started = tokio::time::Instant::now();
+    for sequence in 0..burst.frames {
+        tokio::time::sleep_until(started + burst.interval() * sequence).await;
+        for (camera_id, result) in capture_cameras(state, &preflight.ready).await {
+            let next_index = camera_indices.len() as u32;
+            let camera_index = *camera_indices
+                .entry(camera_id.clone())
+                .or_insert(next_index);
+            let saved = match result {
+                Ok(jpeg) => {
+                    let filename = burst::burst_filename(&session_id, camera_index, sequence);
+                    let overlay = CaptureOverlay::for_capture(
+                        &state.settings,
+                        &camera_id,
+                        camera_configs.get(&camera_id),
+                        &session_id,
+                        chrono::Utc::now(),
+                    );
+                    match guess_view(camera_configs.get(&camera_id), jpeg).await {
+                        Ok((jpeg, guess)) => {
+                            save_burst_frame(image_directory.join(&filename), jpeg, overlay)
+                                .await
+                                .map(|sharpness| (filename, sharpness, guess))
                         }
+                        Err(e) => Err(e),
                     }
-
-                    builder
-                        .body(Body::from(body))
-                        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)
+                }
+                Err(e) => Err(e),
+            };
+            match saved {
+                Ok((filename, sharpness, guess)) => {
+                    let config = camera_configs.get(&camera_id);
+                    let mut image = camera_image(camera_index, filename, &camera_id, config);
+                    image.sequence = Some(sequence);
+                    image.sharpness = sharpness;
+                    if let Some(guess) = guess {
+                        image.apply_view_guess(guess);
+                    }
+                    images.push(image);
+                    *captured.entry(camera_id).or_default() += 1;
                 }
                 Err(e) => {
-                    error!("Failed to proxy ESPHome camera stream {}: {e}", camera_id);
-                    Err(StatusCode::BAD_GATEWAY)
+                    error!("Failed to capture burst frame {sequence} from camera {camera_id}: {e}");
+                    last_errors.insert(camera_id, e.to_string());
                 }
             }
         }
+    }
+
+    let results: HashMap<String, String> = camera_indices
+        .keys()
+        .map(|camera_id| {
+            let count = captured.get(camera_id).copied().unwrap_or(0);
+            let outcome = match last_errors.get(camera_id) {
+                Some(e) => format!(
+                    "Captured {count} of {} frames, last error: {e}",
+                    burst.frames
+                ),
+                None => format!("Captured {count} frames"),
+            };
+            (camera_id.clone(), outcome)
+        })
+        .chain(preflight.offline.iter().map(|camera| {
+            (
+                camera.camera_id.clone(),
+                format!("Skipped, offline: {}", camera.reason),
+            )
+        }))
+        .collect();
+    if images.is_empty() {
+        let message = if results.is_empty() {
+            "No cameras are selected".to_string()
+        } else {
+            "No burst frames were captured".to_string()
+        };
+        return (
+            StatusCode::BAD_GATEWAY,
+            Json(ApiResponse::error_with_data(
+                ErrorCode::CameraError,
+                message,
+                BurstCapture {
+                    session_id,
+                    results,
+                    images,
+                    batch_id,
+                },
+            )),
+        );
+    }
+
+    burst::mark_preferred(&mut images);
+    let mut shell = Shell::new(String::new(), String::new());
+    shell.date_captured = started_at;
+    shell.include = false;
+    shell.image_filenames = images.iter().map(|image| image.filename.clone()).collect();
+    shell.captured_images = Some(images.clone());
+    shell.batch_id = batch_id.clone();
+    shell.settings_snapshot = Some(snapshot);
+    if let Err(e) = state.shell_data_manager.save_shell(&session_id, &shell) {
+        error!("Failed to save burst session {session_id}: {e}");
+        return (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(ApiResponse::error(
+                ErrorCode::InternalError,
+                format!("Failed to save burst session: {e}"),
+            )),
+        );
+    }
+    let mut cameras: Vec<CameraSummary> = camera_indices
+        .iter()
+        .map(|(camera_id, camera_index)| {
+            let (status, reason) = if captured.contains_key(camera_id) {
+                (CameraStatus::Captured, None)
+            } else {
+                (CameraStatus::Failed, last_errors.get(camera_id).cloned())
+            };
+            CameraSummary::new(camera_id.clone(), Some(*camera_index), status, reason)
+        })
+        .collect();
+    cameras.sort_by_key(|camera| camera.camera_index);
+    cameras.extend(preflight.offline.iter().map(|camera| {
+        CameraSummary::new(
+            camera.camera_id.clone(),
+            None,
+            CameraStatus::Skipped,
+            Some(camera.reason.clone()),
+        )
+    }));
+    write_session_summary(state, &session_id, started_at, &shell, cameras).await;
+    info!(
+        "Burst session {session_id} saved {} frames from {} cameras",
+        images.len(),
+        camera_indices.len()
+    );
+    (
+        StatusCode::OK,
+        Json(ApiResponse::success(BurstCapture {
+            session_id,
+            results,
+            images,
+            batch_id,
+        })),
+    )
+}
+
+/// Write a session's summary beside its images, see [`crate::session_summary`]
+///
+/// The shell record is saved by now, so a summary that can't be written is
+/// logged rather than failing the capture.
+async fn write_session_summary(
+    state: &AppState,
+    session_id: &str,
+    started_at: chrono::DateTime<chrono::Utc>,
+    shell: &Shell,
+    mut cameras: Vec<CameraSummary>,
+) {
+    let camera_configs = current_user_config(state).await.camera_configs;
+    for camera in &mut cameras {
+        camera.nickname = camera_configs
+            .get(&camera.camera_id)
+            .and_then(|config| config.nickname.clone());
+    }
+    let summary_session_id = session_id.to_string();
+    let machine = state.settings.machine_name.clone();
+    let image_directory = state.settings.image_directory.clone();
+    let shell = shell.clone();
+    let written = server_timing::time(
+        DISK,
+        Some("summary"),
+        tokio::task::spawn_blocking(move || {
+            SessionSummary::new(
+                &summary_session_id,
+                &machine,
+                started_at,
+                &shell,
+                cameras,
+                &image_directory,
+            )
+            .write(&image_directory)
+        }),
+    )
+    .await
+    .map_err(|e| OurError::App(format!("Session summary task failed: {e}")))
+    .and_then(|written| written);
+    if let Err(e) = written {
+        warn!("Failed to write the summary of session {session_id}: {e}");
+    }
+}
+
+/// Write a burst frame and score its sharpness, which is left unset if the frame won't decode
+///
+/// The sharpness is scored before the overlay is stamped on, so the text doesn't count.
+async fn save_burst_frame(
+    path: PathBuf,
+    jpeg: Vec<u8>,
+    overlay: Option<CaptureOverlay>,
+) -> OurResult<Option<f64>> {
+    let (jpeg, scored) = tokio::task::spawn_blocking(move || {
+        let scored = burst::sharpness(&jpeg);
+        (jpeg, scored)
+    })
+    .await
+    .map_err(|e| OurError::App(format!("Sharpness scoring failed: {e}")))?;
+    let jpeg = stamp_overlay(overlay, jpeg).await?;
+    tokio::fs::write(&path, &jpeg).await?;
+    Ok(scored
+        .inspect_err(|e| warn!("Couldn't score the sharpness of {}: {e}", path.display()))
+        .ok())
+}
+
+/// A guess at the view of a frame from a camera with no view type, made before the overlay is stamped on
+///
+/// Frames from cameras with a view type, and frames the guess can't be made
+/// for, come back with no guess.
+async fn guess_view(
+    config: Option<&CameraConfig>,
+    jpeg: Vec<u8>,
+) -> OurResult<(Vec<u8>, Option<ViewGuess>)> {
+    let view_type = config.and_then(|config| config.view_type);
+    if view_type.is_some_and(|view_type| view_type != ViewType::Unknown) {
+        return Ok((jpeg, None));
+    }
+    let (jpeg, guess) = tokio::task::spawn_blocking(move || {
+        let guess = view_classifier::classify_bytes(&jpeg);
+        (jpeg, guess)
+    })
+    .await
+    .map_err(|e| OurError::App(format!("View classification failed: {e}")))?;
+    let guess = guess
+        .inspect_err(|e| warn!("Couldn't guess the view of a captured frame: {e}"))
+        .ok()
+        .flatten();
+    Ok((jpeg, guess))
+}
+
+/// The captured frame with its overlay stamped on, or as it was if there's none or it can't be drawn
+async fn stamp_overlay(overlay: Option<CaptureOverlay>, jpeg: Vec<u8>) -> OurResult<Vec<u8>> {
+    let Some(overlay) = overlay else {
+        return Ok(jpeg);
+    };
+    let (jpeg, stamped) = tokio::task::spawn_blocking(move || {
+        let stamped = overlay.apply_to_jpeg(&jpeg, DEFAULT_JPEG_QUALITY);
+        (jpeg, stamped)
+    })
+    .await
+    .map_err(|e| OurError::App(format!("Capture overlay failed: {e}")))?;
+    Ok(match stamped {
+        Ok(Some(stamped)) => stamped,
+        Ok(None) => jpeg,
         Err(e) => {
-            error!("Failed to get camera list for streaming: {e}");
-            Err(StatusCode::INTERNAL_SERVER_ERROR)
+            warn!("Failed to stamp the capture overlay, saving the frame without it: {e}");
+            jpeg
         }
+    })
+}
+
+/// The record of a captured frame, with the camera's nickname, view and region
+fn camera_image(
+    camera_index: u32,
+    filename: String,
+    camera_id: &str,
+    config: Option<&CameraConfig>,
+) -> CapturedImage {
+    let camera_name = config
+        .and_then(|config| config.nickname.clone())
+        .unwrap_or_else(|| camera_id.to_string());
+    let view_type = config
+        .and_then(|config| config.view_type)
+        .unwrap_or_default();
+    let mut image = CapturedImage::new(camera_index, filename, camera_name, view_type);
+    if let Some(region) = config.and_then(CameraConfig::region) {
+        image.set_region(&CameraRegion::new(
+            view_type,
+            i32::try_from(region.x).ok(),
+            i32::try_from(region.y).ok(),
+            i32::try_from(region.width).ok(),
+            i32::try_from(region.height).ok(),
+        ));
     }
+    image
 }
 
-#[derive(Deserialize)]
-#[allow(dead_code)]
-struct ViewTypeRequest {
-    view_type: String,
+/// Optional body of `POST /api/cameras/capture`
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+struct CaptureRequest {
+    burst: Option<BurstRequest>,
+    /// Batch the session goes into, instead of the active batch
+    batch_id: Option<String>,
+    /// Used when there's no `Idempotency-Key` header
+    idempotency_key: Option<String>,
+    /// Capture the cameras that are online instead of refusing when some are offline
+    #[serde(default)]
+    allow_partial: bool,
 }
 
-async fn set_camera_view_type(
-    Path(_index): Path<usize>,
-    State(_state): State<Arc<AppState>>,
-    Json(_payload): Json<ViewTypeRequest>,
-) -> Json<ApiResponse<()>> {
-    // TODO: Implement view type setting
-    Json(ApiResponse::success(()))
+/// How one camera fared in a capture
+#[derive(Debug, Serialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+enum CameraCapture {
+    Captured {
+        filename: String,
+        bytes: usize,
+    },
+    Failed {
+        error: String,
+    },
+    /// The camera was offline when the capture started
+    Skipped {
+        reason: String,
+    },
 }
 
-#[derive(Deserialize)]
-#[allow(dead_code)]
-struct RegionRequest {
-    x: i32,
-    y: i32,
-    width: i32,
-    height: i32,
+/// The selected cameras that kept a capture from starting
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "snake_case")]
+struct OfflineCameras {
+    offline: Vec<OfflineCamera>,
 }
 
-async fn set_camera_region(
-    Path(_index): Path<usize>,
-    State(_state): State<Arc<AppState>>,
-    Json(_payload): Json<RegionRequest>,
-) -> Json<ApiResponse<()>> {
-    // TODO: Implement region setting
-    Json(ApiResponse::success(()))
+/// The session a capture or recapture saved into
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "snake_case")]
+struct SessionCapture {
+    session_id: String,
+    /// How each camera fared, keyed by camera ID
+    results: BTreeMap<String, CameraCapture>,
+    /// Images this capture added to the session
+    images: Vec<CapturedImage>,
+    /// Cameras the session still has no image from
+    missing_cameras: Vec<String>,
+    /// Batch the session is in
+    batch_id: Option<String>,
 }
 
-async fn clear_camera_region(
-    Path(_index): Path<usize>,
-    State(_state): State<Arc<AppState>>,
-) -> Json<ApiResponse<()>> {
-    // TODO: Implement region clearing
-    Json(ApiResponse::success(()))
+/// The session a burst capture saved
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "snake_case")]
+struct BurstCapture {
+    session_id: String,
+    /// How each camera fared, keyed by camera ID
+    results: HashMap<String, String>,
+    images: Vec<CapturedImage>,
+    /// Batch the session is in
+    batch_id: Option<String>,
 }
 
-async fn list_shells(
+/// Get capture counters for a camera
+async fn get_camera_stats(
+    Path(camera_id): Path<CameraId>,
     State(state): State<Arc<AppState>>,
-) -> Json<ApiResponse<Vec<HashMap<String, serde_json::Value>>>> {
-    match state.shell_data_manager.list_shells() {
-        Ok(shells) => {
-            let shell_data: Vec<HashMap<String, serde_json::Value>> = shells
-                .into_iter()
-                .map(|(session_id, shell)| {
-                    let mut data = HashMap::new();
-                    data.insert(
-                        "session_id".to_string(),
-                        serde_json::Value::String(session_id),
-                    );
-                    data.insert(
-                        "brand".to_string(),
-                        serde_json::Value::String(shell.brand.clone()),
-                    );
-                    data.insert(
-                        "shell_type".to_string(),
-                        serde_json::Value::String(shell.shell_type.clone()),
-                    );
-                    data.insert(
-                        "date_captured".to_string(),
-                        serde_json::Value::String(shell.date_captured.to_rfc3339()),
-                    );
-                    data.insert(
-                        "include".to_string(),
-                        serde_json::Value::Bool(shell.include),
-                    );
-                    data.insert(
-                        "image_count".to_string(),
-                        serde_json::Value::Number(serde_json::Number::from(shell.image_count())),
-                    );
-                    data.insert(
-                        "has_complete_regions".to_string(),
-                        serde_json::Value::Bool(shell.has_complete_regions()),
-                    );
-                    data
-                })
-                .collect();
+) -> Json<ApiResponse<CaptureStats>> {
+    let stats = if camera_id.kind() == CameraKind::Usb {
+        state.usb_camera_manager.get_status().await.map(|status| {
+            status.cameras.contains_key(camera_id.as_str()).then(|| {
+                status
+                    .capture_stats
+                    .get(camera_id.as_str())
+                    .cloned()
+                    .unwrap_or_default()
+            })
+        })
+    } else {
+        state.camera_manager.get_status().await.map(|status| {
+            status.cameras.contains_key(camera_id.as_str()).then(|| {
+                status
+                    .capture_stats
+                    .get(camera_id.as_str())
+                    .cloned()
+                    .unwrap_or_default()
+            })
+        })
+    };
 
-            Json(ApiResponse::success(shell_data))
-        }
+    match stats {
+        Ok(Some(stats)) => Json(ApiResponse::success(stats)),
+        Ok(None) => Json(ApiResponse::localized(
+            ErrorCode::CameraNotFound,
+            Message::CameraNotFound {
+                camera_id: camera_id.to_string(),
+            },
+        )),
         Err(e) => {
-            error!("Failed to list shells: {}", e);
-            Json(ApiResponse::error(format!("Failed to list shells: {e}")))
+            error!("Failed to get capture stats for camera {camera_id}: {e}");
+            Json(ApiResponse::localized(
+                ErrorCode::CameraError,
+                Message::CaptureStatsFailed {
+                    error: e.to_string(),
+                },
+            ))
         }
     }
 }
 
-async fn save_shell_data(
+/// Reset capture counters for a camera
+async fn reset_camera_stats(
+    Path(camera_id): Path<CameraId>,
     State(state): State<Arc<AppState>>,
-    ExtractJson(payload): ExtractJson<SaveShellRequest>,
-) -> Json<ApiResponse<HashMap<String, String>>> {
-    let mut shell = Shell::new(payload.brand, payload.shell_type);
-    shell.include = payload.include;
-    shell.image_filenames = payload.image_filenames;
+) -> (StatusCode, Json<ApiResponse<()>>) {
+    let result = if camera_id.kind() == CameraKind::Usb {
+        state
+            .usb_camera_manager
+            .reset_capture_stats(camera_id.to_string())
+            .await
+    } else {
+        state
+            .camera_manager
+            .reset_capture_stats(camera_id.to_string())
+            .await
+    };
 
-    match state
-        .shell_data_manager
-        .save_shell(&payload.session_id, &shell)
-    {
-        Ok(()) => {
-            let mut response = HashMap::new();
-            response.insert("session_id".to_string(), payload.session_id);
-            response.insert(
-                "message".to_string(),
-                "Shell data saved successfully".to_string(),
-            );
-            Json(ApiResponse::success(response))
+    match result {
+        Ok(true) => {
+            info!("Reset capture stats for camera {camera_id}");
+            (StatusCode::OK, Json(ApiResponse::success(())))
         }
+        Ok(false) => (
+            StatusCode::NOT_FOUND,
+            Json(ApiResponse::localized(
+                ErrorCode::CameraNotFound,
+                Message::CameraNotFound {
+                    camera_id: camera_id.to_string(),
+                },
+            )),
+        ),
         Err(e) => {
-            error!(
-                "Failed to save shell data for session {}: {}",
-                payload.session_id, e
-            );
-            Json(ApiResponse::error(format!(
-                "Failed to save shell data: {e}"
-            )))
+            error!("Failed to reset capture stats for camera {camera_id}: {e}");
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ApiResponse::localized(
+                    ErrorCode::CameraError,
+                    Message::CaptureStatsResetFailed {
+                        error: e.to_string(),
+                    },
+                )),
+            )
         }
     }
 }
 
-async fn toggle_shell_training(
-    Path(session_id): Path<String>,
+async fn camera_stream(
+    Path(camera_id): Path<CameraId>,
     State(state): State<Arc<AppState>>,
-) -> Json<ApiResponse<HashMap<String, bool>>> {
-    match state.shell_data_manager.toggle_shell_training(&session_id) {
-        Ok(include_flag) => {
-            let mut response = HashMap::new();
-            response.insert("include".to_string(), include_flag);
-            Json(ApiResponse::success(response))
-        }
-        Err(e) => {
-            error!(
-                "Failed to toggle training for session {}: {}",
-                session_id, e
-            );
-            Json(ApiResponse::error(format!(
-                "Failed to toggle training: {e}"
-            )))
-        }
+) -> Response {
+    // Refuse new streams once this camera is at the limit
+    let Some(stream_guard) = state.stream_limiter.try_acquire(camera_id.as_str()) else {
+        let limit = state.stream_limiter.max_per_camera();
+        info!("Rejecting stream for camera {camera_id}: {limit} streams already open");
+        return (
+            StatusCode::TOO_MANY_REQUESTS,
+            Json(ApiResponse::<()>::localized(
+                ErrorCode::StreamLimitReached,
+                Message::StreamLimitReached {
+                    camera_id: camera_id.to_string(),
+                    limit,
+                },
+            )),
+        )
+            .into_response();
+    };
+
+    // Determine camera type and route to appropriate manager
+    if camera_id.kind() == CameraKind::Usb {
+        stream_usb_camera(&state, camera_id.as_str(), stream_guard)
+            .await
+            .into_response()
+    } else {
+        stream_esphome_camera(&state, camera_id.as_str(), stream_guard)
+            .await
+            .into_response()
     }
 }
 
-async fn ml_list_shells(
+/// A single JPEG from a camera, cheap enough for the dashboard to poll
+async fn camera_snapshot(
+    Path(camera_id): Path<CameraId>,
     State(state): State<Arc<AppState>>,
-) -> Json<ApiResponse<Vec<HashMap<String, serde_json::Value>>>> {
-    match state.shell_data_manager.get_shells_for_training() {
-        Ok(shells) => {
-            let shell_data: Vec<HashMap<String, serde_json::Value>> = shells
-                .into_iter()
-                .map(|(session_id, shell)| {
-                    let mut data = HashMap::new();
-                    data.insert(
-                        "session_id".to_string(),
-                        serde_json::Value::String(session_id),
-                    );
-                    data.insert(
-                        "brand".to_string(),
-                        serde_json::Value::String(shell.brand.clone()),
-                    );
-                    data.insert(
-                        "shell_type".to_string(),
-                        serde_json::Value::String(shell.shell_type.clone()),
-                    );
-                    data.insert(
-                        "date_captured".to_string(),
-                        serde_json::Value::String(shell.date_captured.to_rfc3339()),
-                    );
-                    data.insert(
-                        "include".to_string(),
-                        serde_json::Value::Bool(shell.include),
-                    );
-                    data.insert(
-                        "image_count".to_string(),
-                        serde_json::Value::Number(serde_json::Number::from(shell.image_count())),
-                    );
-                    data.insert(
-                        "has_complete_regions".to_string(),
-                        serde_json::Value::Bool(shell.has_complete_regions()),
-                    );
-                    data.insert(
-                        "case_type_key".to_string(),
-                        serde_json::Value::String(shell.get_case_type_key()),
-                    );
+) -> Response {
+    let started = std::time::Instant::now();
+
+    let frame = if camera_id.kind() == CameraKind::Usb {
+        // Reuse the open stream when there is one, it's much faster than opening the device
+        match state.usb_camera_manager.get_status().await {
+            Ok(status) if status.streaming => {
+                state
+                    .usb_camera_manager
+                    .capture_streaming_frame(camera_id.as_str())
+                    .await
+            }
+            Ok(_) => {
+                state
+                    .usb_camera_manager
+                    .capture_image(camera_id.to_string())
+                    .await
+            }
+            Err(e) => Err(e),
+        }
+    } else {
+        state
+            .camera_manager
+            .capture_image(camera_id.to_string())
+            .await
+    };
 
-                    // Add image filenames if available
-                    if !shell.image_filenames.is_empty() {
-                        let filenames: Vec<serde_json::Value> = shell
-                            .image_filenames
-                            .into_iter()
-                            .map(serde_json::Value::String)
-                            .collect();
-                        data.insert(
-                            "image_filenames".to_string(),
-                            serde_json::Value::Array(filenames),
-                        );
-                    }
+    let frame = match frame {
+        Ok(frame) => frame,
+        Err(e) => {
+            error!("Failed to take snapshot from camera {camera_id}: {e}");
+            return (
+                StatusCode::BAD_GATEWAY,
+                Json(ApiResponse::<()>::localized(
+                    ErrorCode::CameraError,
+                    Message::SnapshotFailed {
+                        error: e.to_string(),
+                    },
+                )),
+            )
+                .into_response();
+        }
+    };
 
-                    data
-                })
-                .collect();
+    info!(
+        "Snapshot from camera {} took {}ms ({} bytes)",
+        camera_id,
+        started.elapsed().as_millis(),
+        frame.len()
+    );
 
-            Json(ApiResponse::success(shell_data))
+    (
+        [
+            ("Content-Type", "image/jpeg".to_string()),
+            ("Content-Length", frame.len().to_string()),
+            ("Cache-Control", "private, max-age=2".to_string()),
+        ],
+        frame,
+    )
+        .into_response()
+}
+
+/// Serve a captured image from the image directory
+async fn serve_image(Path(filename): Path<String>, State(state): State<Arc<AppState>>) -> Response {
+    let filename = match SafeName::file_name("filename", &filename) {
+        Ok(filename) => filename,
+        Err(e) => {
+            return (
+                StatusCode::BAD_REQUEST,
+                Json(ApiResponse::<()>::error(
+                    ErrorCode::InvalidRequest,
+                    e.to_string(),
+                )),
+            )
+                .into_response();
         }
+    };
+    let content_type = match std::path::Path::new(filename.as_str())
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .map(str::to_ascii_lowercase)
+        .as_deref()
+    {
+        Some("jpg" | "jpeg") => "image/jpeg",
+        Some("png") => "image/png",
+        _ => return StatusCode::NOT_FOUND.into_response(),
+    };
+
+    match tokio::fs::read(state.settings.image_directory.join(&filename)).await {
+        Ok(data) => (
+            [
+                ("Content-Type", content_type.to_string()),
+                ("Content-Length", data.len().to_string()),
+                ("Cache-Control", "private, max-age=3600".to_string()),
+            ],
+            data,
+        )
+            .into_response(),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => StatusCode::NOT_FOUND.into_response(),
         Err(e) => {
-            error!("Failed to list shells for ML training: {}", e);
-            Json(ApiResponse::error(format!(
-                "Failed to list shells for ML training: {e}"
-            )))
+            error!("Failed to read image {filename}: {e}");
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
         }
     }
 }
 
-async fn generate_composites(State(_state): State<Arc<AppState>>) -> Json<ApiResponse<()>> {
-    // TODO: Implement composite generation
-    Json(ApiResponse::success(()))
-}
-
-async fn list_case_types(
+/// Read the configured camera control entities from an ESPHome camera
+async fn get_esp_settings(
+    Path(camera_id): Path<CameraId>,
     State(state): State<Arc<AppState>>,
-) -> Json<ApiResponse<Vec<HashMap<String, serde_json::Value>>>> {
-    let ml_trainer = match state.ml_trainer.lock() {
-        Ok(trainer) => trainer,
-        Err(_) => {
-            error!("Failed to acquire ML trainer lock");
-            return Json(ApiResponse::error(
-                "Failed to access ML trainer".to_string(),
-            ));
+) -> (StatusCode, Json<ApiResponse<Vec<EspEntityState>>>) {
+    if camera_id.kind() == CameraKind::Usb {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(ApiResponse::localized(
+                ErrorCode::CameraUnsupported,
+                Message::EspSettingsUnsupported,
+            )),
+        );
+    }
+
+    match state
+        .camera_manager
+        .get_esp_settings(
+            camera_id.to_string(),
+            state.settings.esphome_camera_entities.clone(),
+        )
+        .await
+    {
+        Ok(states) => (StatusCode::OK, Json(ApiResponse::success(states))),
+        Err(e) => {
+            error!("Failed to read settings from camera {camera_id}: {e}");
+            (
+                StatusCode::BAD_GATEWAY,
+                Json(ApiResponse::localized(
+                    ErrorCode::CameraError,
+                    Message::EspSettingsReadFailed {
+                        error: e.to_string(),
+                    },
+                )),
+            )
         }
-    };
+    }
+}
 
-    match ml_trainer.get_training_summary() {
-        Ok(summary) => {
-            let case_types: Vec<HashMap<String, serde_json::Value>> = summary
-                .into_iter()
-                .map(|(name, summary_data)| {
-                    let mut data = HashMap::new();
-                    data.insert("name".to_string(), serde_json::Value::String(name));
-                    data.insert(
-                        "designation".to_string(),
-                        serde_json::Value::String(summary_data.designation),
-                    );
-                    data.insert(
-                        "brand".to_string(),
-                        summary_data
-                            .brand
-                            .map(serde_json::Value::String)
-                            .unwrap_or(serde_json::Value::Null),
-                    );
-                    data.insert(
-                        "reference_count".to_string(),
-                        serde_json::Value::Number(serde_json::Number::from(
-                            summary_data.reference_count,
-                        )),
-                    );
-                    data.insert(
-                        "training_count".to_string(),
-                        serde_json::Value::Number(serde_json::Number::from(
-                            summary_data.training_count,
-                        )),
-                    );
-                    data.insert(
-                        "shell_count".to_string(),
-                        serde_json::Value::Number(serde_json::Number::from(
-                            summary_data.shell_count,
-                        )),
-                    );
-                    data.insert(
-                        "ready_for_training".to_string(),
-                        serde_json::Value::Bool(summary_data.ready_for_training),
-                    );
-                    data.insert(
-                        "updated_at".to_string(),
-                        serde_json::Value::String(summary_data.updated_at.to_rfc3339()),
-                    );
-                    data
-                })
-                .collect();
+/// Write camera control entities on an ESPHome camera, keyed by `domain/object_id`
+async fn set_esp_settings(
+    Path(camera_id): Path<CameraId>,
+    State(state): State<Arc<AppState>>,
+    Json(updates): Json<BTreeMap<String, serde_json::Value>>,
+) -> (StatusCode, Json<ApiResponse<Vec<EspSettingResult>>>) {
+    if camera_id.kind() == CameraKind::Usb {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(ApiResponse::localized(
+                ErrorCode::CameraUnsupported,
+                Message::EspSettingsUnsupported,
+            )),
+        );
+    }
 
-            Json(ApiResponse::success(case_types))
+    // Entities outside the configured set are reported as failures without touching the device
+    let mut rejected = Vec::new();
+    let mut allowed = Vec::new();
+    for (entity, value) in updates {
+        let value = match value {
+            serde_json::Value::String(value) => value,
+            other => other.to_string(),
+        };
+        match entity.parse::<EspEntity>() {
+            Ok(entity) if state.settings.esphome_camera_entities.contains(&entity) => {
+                allowed.push((entity, value));
+            }
+            Ok(entity) => rejected.push(EspSettingResult {
+                error: Some(format!("{entity} is not a configurable camera setting")),
+                entity,
+                value,
+                success: false,
+            }),
+            Err(e) => {
+                return (
+                    StatusCode::BAD_REQUEST,
+                    Json(ApiResponse::error(
+                        ErrorCode::InvalidRequest,
+                        format!("Invalid camera setting: {e}"),
+                    )),
+                );
+            }
         }
+    }
+
+    let mut results = match state
+        .camera_manager
+        .set_esp_settings(camera_id.to_string(), allowed)
+        .await
+    {
+        Ok(results) => results,
         Err(e) => {
-            error!("Failed to get training summary: {}", e);
-            Json(ApiResponse::error(format!("Failed to get case types: {e}")))
+            error!("Failed to update settings on camera {camera_id}: {e}");
+            return (
+                StatusCode::BAD_GATEWAY,
+                Json(ApiResponse::localized(
+                    ErrorCode::CameraError,
+                    Message::EspSettingsUpdateFailed {
+                        error: e.to_string(),
+                    },
+                )),
+            );
+        }
+    };
+
+    // A new frame size makes the stored resolution wrong, so the next probe detects it again
+    if results
+        .iter()
+        .any(|result| result.success && result.entity.is_framesize())
+    {
+        let config_camera_id = camera_id.to_string();
+        let cleared = state.config_writer.mutate(move |user_config| {
+            if let Some(camera_config) = user_config.camera_configs.get_mut(&config_camera_id) {
+                camera_config.detected_resolution_width = None;
+                camera_config.detected_resolution_height = None;
+                camera_config.resolution_detection_timestamp = None;
+            }
+        });
+        if let Err(e) = cleared {
+            error!("Failed to clear detected resolution for camera {camera_id}: {e}");
         }
     }
-}
 
-#[derive(Deserialize)]
-#[allow(dead_code)]
-struct CreateCaseTypeRequest {
-    name: String,
-    designation: Option<String>,
+    results.extend(rejected);
+    (StatusCode::OK, Json(ApiResponse::success(results)))
 }
 
-#[derive(Deserialize, Debug)]
-struct SelectCamerasRequest {
-    camera_ids: Vec<String>,
-}
+async fn stream_usb_camera(
+    state: &Arc<AppState>,
+    camera_id: &str,
+    stream_guard: StreamGuard,
+) -> Result<Response<Body>, StatusCode> {
+    let state_clone = state.clone();
+    let camera_id_clone = camera_id.to_string();
+    let mut stalls = state.stream_stalls.subscribe();
+
+    // Create an MJPEG stream
+    let stream = async_stream::stream! {
+        // Held for the life of the body so the slot is released on disconnect
+        let _stream_guard = stream_guard;
+
+        // Send initial boundary
+        yield Ok::<Vec<u8>, Box<dyn std::error::Error + Send + Sync>>(
+            b"--frame\r\n".to_vec()
+        );
+
+        loop {
+            // Check if streaming should continue
+            match state_clone.usb_camera_manager.get_status().await {
+                Ok(status) => {
+                    if !status.streaming {
+                        info!("USB camera streaming stopped for camera {}", camera_id_clone);
+                        break;
+                    }
+                }
+                Err(e) => {
+                    error!("Failed to get USB camera status: {e}");
+                    break;
+                }
+            }
+
+            let frame = tokio::select! {
+                frame = state_clone.usb_camera_manager.capture_streaming_frame(&camera_id_clone) => frame,
+                _ = stream_stalled(&mut stalls, &camera_id_clone, &state_clone.broadcast_lag) => {
+                    // Ending the body lets the browser notice and reconnect
+                    info!("Ending stalled stream for camera {}", camera_id_clone);
+                    break;
+                }
+            };
+
+            match frame {
+                Ok(frame_data) => {
+                    // Create MJPEG frame with proper headers
+                    let header = format!(
+                        "Content-Type: image/jpeg\r\nContent-Length: {}\r\n\r\n",
+                        frame_data.len()
+                    );
+
+                    // Yield the header
+                    yield Ok(header.as_bytes().to_vec());
+
+                    // Yield the frame data
+                    yield Ok(frame_data);
+
+                    // Yield the boundary for next frame
+                    yield Ok(b"\r\n--frame\r\n".to_vec());
+                }
+                Err(e) => {
+                    error!("Failed to capture frame from USB camera {}: {e}", camera_id_clone);
+                    // Don't break the stream, just wait and try again
+                    tokio::time::sleep(tokio::time::Duration::from_millis(200)).await;
+                    continue;
+                }
+            }
+
+            // Control frame rate - roughly 5 FPS for streaming
+            tokio::time::sleep(tokio::time::Duration::from_millis(200)).await;
+        }
+    };
+
+    // Convert the stream to the format expected by Body::from_stream
+    let byte_stream = stream.map(|result| result.map(axum::body::Bytes::from));
+
+    let body = Body::from_stream(byte_stream);
+
+    Response::builder()
+        .header("Content-Type", "multipart/x-mixed-replace; boundary=frame")
+        .header("Cache-Control", "no-cache, no-store, must-revalidate")
+        .header("Pragma", "no-cache")
+        .header("Expires", "0")
+        .header("Connection", "close")
+        .body(body)
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)
+}
+
+async fn stream_esphome_camera(
+    state: &Arc<AppState>,
+    camera_id: &str,
+    stream_guard: StreamGuard,
+) -> Result<Response<Body>, StatusCode> {
+    // Get camera info to find the stream URL
+    match state.camera_manager.list_cameras().await {
+        Ok(cameras) => {
+            let camera = cameras
+                .iter()
+                .find(|c| c.id == camera_id)
+                .ok_or(StatusCode::NOT_FOUND)?;
+
+            // Proxy the ESPHome camera's stream, or its snapshots when it has no stream
+            match state.snapshot_streams.open(camera).await {
+                Ok(EspStream::Polled(frames)) => {
+                    polled_esphome_stream(state, camera_id, frames, stream_guard)
+                }
+                Ok(EspStream::Mjpeg(response)) => {
+                    let status = response.status();
+                    let headers = response.headers().clone();
+                    let mut frame_counter = headers
+                        .get(axum::http::header::CONTENT_TYPE)
+                        .and_then(|value| value.to_str().ok())
+                        .and_then(FrameCounter::from_content_type);
+                    let mut upstream = response.bytes_stream();
+                    let mut stalls = state.stream_stalls.subscribe();
+                    let state = state.clone();
+                    let camera_id = camera_id.to_string();
+                    let body = async_stream::stream! {
+                        // Held for the life of the body so the slot is released on disconnect
+                        let _stream_guard = stream_guard;
+                        loop {
+                            let chunk = tokio::select! {
+                                chunk = upstream.next() => chunk,
+                                _ = stream_stalled(&mut stalls, &camera_id, &state.broadcast_lag) => {
+                                    // Ending the body lets the browser notice and reconnect
+                                    info!("Ending stalled stream for camera {camera_id}");
+                                    break;
+                                }
+                            };
+                            let Some(chunk) = chunk else {
+                                break;
+                            };
+                            let recorded = match &chunk {
+                                // Without a boundary to count, every chunk counts as activity
+                                Ok(bytes) => {
+                                    let frames = frame_counter
+                                        .as_mut()
+                                        .map_or(1, |counter| counter.feed(bytes));
+                                    state.camera_manager.record_stream_frames(&camera_id, frames)
+                                }
+                                Err(e) => state
+                                    .camera_manager
+                                    .record_stream_failure(&camera_id, &e.to_string()),
+                            };
+                            if let Err(e) = recorded {
+                                debug!("Failed to record stream health for camera {camera_id}: {e}");
+                            }
+                            yield chunk;
+                        }
+                    };
+
+                    let mut builder = Response::builder().status(status);
+
+                    // Copy relevant headers
+                    for (name, value) in headers.iter() {
+                        if name == "content-type" || name == "cache-control" || name == "connection"
+                        {
+                            builder = builder.header(name, value);
+                        }
+                    }
+
+                    builder
+                        .body(Body::from_stream(body))
+                        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)
+                }
+                Err(e) => {
+                    error!("Failed to proxy ESPHome camera stream {}: {e}", camera_id);
+                    let error = e.to_string();
+                    if let Err(e) = state
+                        .camera_manager
+                        .record_stream_failure(camera_id, &error)
+                    {
+                        debug!("Failed to record stream health for camera {camera_id}: {e}");
+                    }
+                    Err(StatusCode::BAD_GATEWAY)
+                }
+            }
+        }
+        Err(e) => {
+            error!("Failed to get camera list for streaming: {e}");
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+/// An MJPEG stream of the snapshots an ESPHome camera's poller shares with every viewer
+fn polled_esphome_stream(
+    state: &Arc<AppState>,
+    camera_id: &str,
+    mut frames: broadcast::Receiver<axum::body::Bytes>,
+    stream_guard: StreamGuard,
+) -> Result<Response<Body>, StatusCode> {
+    let mut stalls = state.stream_stalls.subscribe();
+    let state = state.clone();
+    let camera_id = camera_id.to_string();
+    let stream = async_stream::stream! {
+        // Held for the life of the body so the slot is released on disconnect
+        let _stream_guard = stream_guard;
+
+        yield Ok::<axum::body::Bytes, std::io::Error>(
+            axum::body::Bytes::from_static(b"--frame\r\n")
+        );
+
+        loop {
+            let frame = tokio::select! {
+                frame = recv_skipping_lag(&mut frames, "snapshot_frames", &state.broadcast_lag) => frame,
+                _ = stream_stalled(&mut stalls, &camera_id, &state.broadcast_lag) => {
+                    // Ending the body lets the browser notice and reconnect
+                    info!("Ending stalled stream for camera {camera_id}");
+                    break;
+                }
+            };
+            // The poller stopped because the camera's own stream is back; reconnecting picks it up
+            let Some(frame) = frame else {
+                break;
+            };
+            yield Ok(axum::body::Bytes::from(format!(
+                "Content-Type: image/jpeg\r\nContent-Length: {}\r\n\r\n",
+                frame.len()
+            )));
+            yield Ok(frame);
+            yield Ok(axum::body::Bytes::from_static(b"\r\n--frame\r\n"));
+        }
+    };
+
+    Response::builder()
+        .header("Content-Type", "multipart/x-mixed-replace; boundary=frame")
+        .header("Cache-Control", "no-cache, no-store, must-revalidate")
+        .header("Pragma", "no-cache")
+        .header("Expires", "0")
+        .header("Connection", "close")
+        .body(Body::from_stream(stream))
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case")]
+#[allow(dead_code)]
+struct ViewTypeRequest {
+    view_type: String,
+}
+
+async fn set_camera_view_type(
+    Path(_index): Path<usize>,
+    State(_state): State<Arc<AppState>>,
+    Json(_payload): Json<ViewTypeRequest>,
+) -> Json<ApiResponse<()>> {
+    // TODO: Implement view type setting
+    Json(ApiResponse::success(()))
+}
+
+/// A region of interest in the camera's oriented frame: pixels of the image
+/// after its configured rotation and mirror, as the dashboard shows it and as
+/// captures are saved, with the origin at the top left
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case")]
+struct RegionRequest {
+    x: i32,
+    y: i32,
+    width: i32,
+    height: i32,
+}
+
+/// Where a camera's frame size was found
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+enum FrameSizeSource {
+    /// The format of the camera's open USB stream
+    Stream,
+    /// The resolution saved in the camera's config
+    Config,
+    /// A frame taken from the camera
+    Snapshot,
+}
+
+/// A camera's current frame, so the dashboard can map its canvas onto frame pixels
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "snake_case")]
+struct FrameInfo {
+    camera_id: String,
+    /// Size of the oriented frame, as snapshots and captures are
+    #[serde(flatten)]
+    frame: FrameSize,
+    source: FrameSizeSource,
+    region: Option<FrameRegion>,
+    /// The frame the region was drawn on
+    region_frame: Option<FrameSize>,
+    /// The region was drawn on a frame of a different size and should be redrawn
+    region_outdated: bool,
+}
+
+impl FrameInfo {
+    fn new(
+        camera_id: String,
+        (frame, source): (FrameSize, FrameSizeSource),
+        camera_config: Option<&CameraConfig>,
+    ) -> Self {
+        let region = camera_config.and_then(CameraConfig::region);
+        let region_frame = camera_config.and_then(CameraConfig::region_frame);
+        Self {
+            camera_id,
+            frame,
+            source,
+            region,
+            region_outdated: region_frame.is_some_and(|drawn_on| drawn_on != frame),
+            region_frame,
+        }
+    }
+}
+
+/// The size of a camera's oriented frame: from its open USB stream, its saved
+/// resolution, or failing those by taking a frame and reading its size
+async fn current_frame_size(
+    state: &AppState,
+    camera_id: &CameraId,
+    camera_config: Option<&CameraConfig>,
+) -> Result<(FrameSize, FrameSizeSource), (StatusCode, ApiError)> {
+    let not_found = || {
+        (
+            StatusCode::NOT_FOUND,
+            ApiError::localized(
+                ErrorCode::CameraNotFound,
+                &Message::CameraNotFound {
+                    camera_id: camera_id.to_string(),
+                },
+            ),
+        )
+    };
+    let manager_error = |e: OurError| {
+        (
+            StatusCode::BAD_GATEWAY,
+            ApiError::localized(
+                ErrorCode::CameraError,
+                &Message::CameraReadFailed {
+                    camera_id: camera_id.to_string(),
+                    error: e.to_string(),
+                },
+            ),
+        )
+    };
+    let orientation = camera_config
+        .map(CameraConfig::orientation)
+        .unwrap_or_default();
+    let usb = camera_id.kind() == CameraKind::Usb;
+
+    if usb {
+        let status = state
+            .usb_camera_manager
+            .get_status()
+            .await
+            .map_err(manager_error)?;
+        let camera = status
+            .cameras
+            .get(camera_id.as_str())
+            .ok_or_else(not_found)?;
+        if status.streaming
+            && let Some(format) = &camera.current_format
+        {
+            let (width, height) = orientation.oriented_size(format.width, format.height);
+            return Ok((FrameSize { width, height }, FrameSizeSource::Stream));
+        }
+    } else {
+        let cameras = state
+            .camera_manager
+            .list_cameras()
+            .await
+            .map_err(manager_error)?;
+        if !cameras.iter().any(|camera| camera.id == camera_id.as_str()) {
+            return Err(not_found());
+        }
+    }
+
+    if let Some(config) = camera_config {
+        let saved = config
+            .manual_resolution_width
+            .zip(config.manual_resolution_height)
+            .or(config
+                .detected_resolution_width
+                .zip(config.detected_resolution_height));
+        if let Some((width, height)) = saved
+            && let (Ok(width @ 1..), Ok(height @ 1..)) =
+                (u32::try_from(width), u32::try_from(height))
+        {
+            let (width, height) = orientation.oriented_size(width, height);
+            return Ok((FrameSize { width, height }, FrameSizeSource::Config));
+        }
+    }
+
+    let frame = if usb {
+        state
+            .usb_camera_manager
+            .capture_image(camera_id.to_string())
+            .await
+    } else {
+        state
+            .camera_manager
+            .capture_image(camera_id.to_string())
+            .await
+    }
+    .map_err(manager_error)?;
+    // Frames come from the managers already turned upright
+    let size = FrameSize::of_image(&frame).map_err(manager_error)?;
+    Ok((size, FrameSizeSource::Snapshot))
+}
+
+/// The true size of a camera's frame, and whether its region was drawn on a frame that size
+async fn get_frame_info(
+    Path(camera_id): Path<CameraId>,
+    State(state): State<Arc<AppState>>,
+) -> (StatusCode, Json<ApiResponse<FrameInfo>>) {
+    let user_config = current_user_config(&state).await;
+    let camera_config = user_config.camera_configs.get(camera_id.as_str());
+    match current_frame_size(&state, &camera_id, camera_config).await {
+        Ok(frame) => (
+            StatusCode::OK,
+            Json(ApiResponse::success(FrameInfo::new(
+                camera_id.into_string(),
+                frame,
+                camera_config,
+            ))),
+        ),
+        Err((status, error)) => {
+            warn!("Failed to get frame info for camera {camera_id}: {error}");
+            (status, Json(ApiResponse::failure(error)))
+        }
+    }
+}
+
+/// Save a camera's region of interest after checking it against the camera's
+/// current frame; coordinates are in the oriented frame (see [`RegionRequest`])
+async fn set_camera_region(
+    Path(camera_id): Path<CameraId>,
+    State(state): State<Arc<AppState>>,
+    Json(payload): Json<RegionRequest>,
+) -> (StatusCode, Json<ApiResponse<FrameInfo>>) {
+    let user_config = current_user_config(&state).await;
+    let camera_config = user_config.camera_configs.get(camera_id.as_str());
+    let (frame, source) = match current_frame_size(&state, &camera_id, camera_config).await {
+        Ok(frame) => frame,
+        Err((status, error)) => {
+            warn!("Failed to get frame info for camera {camera_id}: {error}");
+            return (status, Json(ApiResponse::failure(error)));
+        }
+    };
+    let region = match FrameRegion::validate(
+        payload.x,
+        payload.y,
+        payload.width,
+        payload.height,
+        frame,
+        state.settings.min_region_area,
+    ) {
+        Ok(region) => region,
+        Err(field_errors) => {
+            return (
+                StatusCode::BAD_REQUEST,
+                Json(ApiResponse::failure(ApiError::validation(
+                    &format!(
+                        "Invalid region for the {}x{} frame",
+                        frame.width, frame.height
+                    ),
+                    field_errors,
+                ))),
+            );
+        }
+    };
+
+    let mut camera_config = camera_config.cloned().unwrap_or_default();
+    camera_config.set_region(region, frame);
+    let saved_config = camera_config.clone();
+    let config_camera_id = camera_id.to_string();
+    let saved = state
+        .config_writer
+        .update(move |user_config| {
+            user_config
+                .camera_configs
+                .entry(config_camera_id)
+                .or_default()
+                .set_region(region, frame);
+        })
+        .await;
+    if let Err(e) = saved {
+        error!("Failed to save region for camera {camera_id}: {e}");
+        return (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(ApiResponse::error(
+                ErrorCode::InternalError,
+                format!("Failed to save region: {e}"),
+            )),
+        );
+    }
+
+    info!(
+        "Saved region {}x{} at ({}, {}) for camera {camera_id} on its {}x{} frame",
+        region.width, region.height, region.x, region.y, frame.width, frame.height
+    );
+    (
+        StatusCode::OK,
+        Json(ApiResponse::success(FrameInfo::new(
+            camera_id.into_string(),
+            (frame, source),
+            Some(&saved_config),
+        ))),
+    )
+}
+
+async fn clear_camera_region(
+    Path(camera_id): Path<String>,
+    State(state): State<Arc<AppState>>,
+) -> (StatusCode, Json<ApiResponse<()>>) {
+    let config_camera_id = camera_id.clone();
+    let cleared = state
+        .config_writer
+        .update(move |user_config| {
+            if let Some(camera_config) = user_config.camera_configs.get_mut(&config_camera_id) {
+                camera_config.clear_region();
+            }
+        })
+        .await;
+    match cleared {
+        Ok(()) => (StatusCode::OK, Json(ApiResponse::success(()))),
+        Err(e) => {
+            error!("Failed to clear region for camera {camera_id}: {e}");
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ApiResponse::error(
+                    ErrorCode::InternalError,
+                    format!("Failed to clear region: {e}"),
+                )),
+            )
+        }
+    }
+}
+
+async fn list_shells(State(state): State<Arc<AppState>>, headers: HeaderMap) -> Response {
+    let required_views = case_type_required_views(&state);
+    let etag = version_etag(
+        "shells",
+        &[
+            state.shell_data_manager.version(),
+            hash_version(&required_views),
+        ],
+    );
+    if if_none_match(&headers, &etag) {
+        return not_modified(&etag);
+    }
+
+    match server_timing::time_sync(DISK, None, || state.shell_data_manager.list_shells()) {
+        Ok(shells) => {
+            let shell_data: Vec<HashMap<String, serde_json::Value>> = shells
+                .into_iter()
+                .map(|(session_id, shell)| {
+                    let mut data = HashMap::new();
+                    data.insert(
+                        "session_id".to_string(),
+                        serde_json::Value::String(session_id),
+                    );
+                    data.insert(
+                        "brand".to_string(),
+                        serde_json::Value::String(shell.brand.clone()),
+                    );
+                    data.insert(
+                        "shell_type".to_string(),
+                        serde_json::Value::String(shell.shell_type.clone()),
+                    );
+                    data.insert(
+                        "date_captured".to_string(),
+                        serde_json::Value::String(shell.date_captured.to_rfc3339()),
+                    );
+                    data.insert(
+                        "include".to_string(),
+                        serde_json::Value::Bool(shell.include),
+                    );
+                    data.insert(
+                        "image_count".to_string(),
+                        serde_json::Value::Number(serde_json::Number::from(shell.image_count())),
+                    );
+                    data.insert(
+                        "has_complete_regions".to_string(),
+                        serde_json::Value::Bool(shell.has_complete_regions()),
+                    );
+                    data.insert(
+                        "notes".to_string(),
+                        shell
+                            .notes
+                            .clone()
+                            .map(serde_json::Value::String)
+                            .unwrap_or(serde_json::Value::Null),
+                    );
+                    data.insert(
+                        "flags".to_string(),
+                        serde_json::Value::Array(
+                            shell
+                                .flags
+                                .iter()
+                                .map(|flag| serde_json::Value::String(flag.to_string()))
+                                .collect(),
+                        ),
+                    );
+                    data.insert(
+                        "missing_views".to_string(),
+                        serde_json::json!(shell.missing_views(required_views_for(
+                            &required_views,
+                            &shell.get_case_type_key()
+                        ))),
+                    );
+                    data.insert(
+                        "missing_cameras".to_string(),
+                        serde_json::json!(shell.missing_cameras()),
+                    );
+                    data.insert("batch_id".to_string(), serde_json::json!(shell.batch_id));
+                    data.insert(
+                        "weight_grains".to_string(),
+                        serde_json::json!(shell.weight_grains),
+                    );
+                    data.insert("length_mm".to_string(), serde_json::json!(shell.length_mm));
+                    data
+                })
+                .collect();
+
+            server_timing::time_sync(SERIALIZE, None, || {
+                with_etag(&etag, Json(ApiResponse::success(shell_data)))
+            })
+        }
+        Err(e) => {
+            error!("Failed to list shells: {}", e);
+            Json(ApiResponse::<()>::localized(
+                ErrorCode::InternalError,
+                Message::ShellListFailed {
+                    error: e.to_string(),
+                },
+            ))
+            .into_response()
+        }
+    }
+}
+
+/// Query parameters for shell search
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case")]
+struct ShellSearchQuery {
+    q: Option<String>,
+    /// Comma-separated fields to match, e.g. `brand,shell_type`
+    fields: Option<String>,
+    include: Option<bool>,
+    /// Only shells carrying this problem flag, e.g. `Blurry`
+    flag: Option<String>,
+    /// Only shells of this brand, ignoring case
+    brand: Option<String>,
+    /// Only shells of this type, ignoring case
+    shell_type: Option<String>,
+    /// Only shells captured at or after this RFC 3339 time
+    since: Option<chrono::DateTime<chrono::Utc>>,
+    /// Only shells captured before this RFC 3339 time
+    until: Option<chrono::DateTime<chrono::Utc>>,
+    /// Only shells in this batch
+    batch_id: Option<String>,
+    /// Only shells weighed at this many grains or more
+    min_weight: Option<f32>,
+    /// Only shells weighed at this many grains or less
+    max_weight: Option<f32>,
+    limit: Option<usize>,
+    offset: Option<usize>,
+}
+
+impl ShellSearchQuery {
+    fn filter(&self) -> OurResult<ShellFilter> {
+        let fields = match &self.fields {
+            Some(fields) => fields
+                .split(',')
+                .filter(|field| !field.trim().is_empty())
+                .map(str::parse::<SearchField>)
+                .collect::<OurResult<Vec<_>>>()?,
+            None => Vec::new(),
+        };
+
+        Ok(ShellFilter {
+            query: self.q.clone(),
+            fields,
+            include: self.include,
+            flag: self.flag.as_deref().map(str::parse).transpose()?,
+            brand: self.brand.clone(),
+            shell_type: self.shell_type.clone(),
+            captured_after: self.since,
+            captured_before: self.until,
+            batch_id: self.batch_id.clone(),
+            min_weight_grains: self.min_weight,
+            max_weight_grains: self.max_weight,
+        })
+    }
+}
+
+/// Shell records that loaded and data directory files that didn't
+#[derive(Serialize)]
+#[serde(rename_all = "snake_case")]
+struct ShellIntegrityData {
+    valid: usize,
+    skipped: Vec<SkippedFile>,
+    /// Shells whose type isn't a supported case designation
+    unknown_types: Vec<UnknownShellType>,
+    /// Sessions whose summary disagrees with their shell record, see [`crate::session_summary`]
+    summary_mismatches: Vec<SummaryMismatch>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "snake_case")]
+struct SummaryMismatch {
+    session_id: String,
+    differences: Vec<String>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "snake_case")]
+struct UnknownShellType {
+    session_id: String,
+    shell_type: String,
+    /// The canonical designation the type normalises to, if it only differs in case or spacing
+    canonical: Option<String>,
+}
+
+/// Sessions whose summary disagrees with their shell record; sessions without one are passed over
+fn summary_mismatches(
+    image_directory: &std::path::Path,
+    shells: &[(String, Shell)],
+) -> Vec<SummaryMismatch> {
+    shells
+        .iter()
+        .filter_map(|(session_id, shell)| {
+            let differences = match SessionSummary::load(image_directory, session_id) {
+                Ok(summary) => summary?.differences(shell),
+                Err(e) => vec![e.to_string()],
+            };
+            (!differences.is_empty()).then(|| SummaryMismatch {
+                session_id: session_id.clone(),
+                differences,
+            })
+        })
+        .collect()
+}
+
+async fn shell_integrity(
+    State(state): State<Arc<AppState>>,
+) -> (StatusCode, Json<ApiResponse<ShellIntegrityData>>) {
+    let designations = case_designations(&state).await;
+    match state.shell_data_manager.check_shells() {
+        Ok(report) => (
+            StatusCode::OK,
+            Json(ApiResponse::success(ShellIntegrityData {
+                valid: report.shells.len(),
+                summary_mismatches: summary_mismatches(
+                    &state.settings.image_directory,
+                    &report.shells,
+                ),
+                skipped: report.skipped,
+                unknown_types: report
+                    .shells
+                    .into_iter()
+                    .filter_map(|(session_id, shell)| {
+                        let canonical = designations.normalize(&shell.shell_type);
+                        (canonical != Some(shell.shell_type.as_str())).then(|| UnknownShellType {
+                            session_id,
+                            canonical: canonical.map(str::to_string),
+                            shell_type: shell.shell_type,
+                        })
+                    })
+                    .collect(),
+            })),
+        ),
+        Err(e) => {
+            error!("Failed to check shell records: {e}");
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ApiResponse::localized(
+                    ErrorCode::InternalError,
+                    Message::ShellRecordsCheckFailed {
+                        error: e.to_string(),
+                    },
+                )),
+            )
+        }
+    }
+}
+
+#[derive(Deserialize, Default)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+struct PropagateRegionFilter {
+    /// Shell type or `brand_shell_type`
+    case_type: Option<String>,
+    /// Only shells captured at or after this RFC 3339 time
+    since: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+struct PropagateRegionRequest {
+    camera_name: Option<String>,
+    camera_index: Option<u32>,
+    region: RegionRequest,
+    view_type: ViewType,
+    #[serde(default)]
+    filter: PropagateRegionFilter,
+    #[serde(default)]
+    dry_run: bool,
+}
+
+impl PropagateRegionRequest {
+    /// Check the request before any shell is touched
+    fn propagation(self) -> Result<RegionPropagation, String> {
+        let camera = match (self.camera_name, self.camera_index) {
+            (Some(name), None) => CameraSelector::Name(name),
+            (None, Some(index)) => CameraSelector::Index(index),
+            _ => return Err("Give exactly one of camera_name or camera_index".to_string()),
+        };
+        let RegionRequest {
+            x,
+            y,
+            width,
+            height,
+        } = self.region;
+        if x < 0 || y < 0 || width <= 0 || height <= 0 {
+            return Err(format!(
+                "Region must have a non-negative origin and a positive size, got {width}x{height} at ({x}, {y})"
+            ));
+        }
+        Ok(RegionPropagation {
+            camera,
+            region: CameraRegion::new(self.view_type, Some(x), Some(y), Some(width), Some(height)),
+            case_type: self.filter.case_type,
+            since: self.filter.since,
+            dry_run: self.dry_run,
+        })
+    }
+}
+
+/// Copy a camera's region onto its already-captured images, then redraw the affected composites in the background
+async fn propagate_region(
+    State(state): State<Arc<AppState>>,
+    ExtractJson(payload): ExtractJson<PropagateRegionRequest>,
+) -> (StatusCode, Json<ApiResponse<RegionPropagationReport>>) {
+    let propagation = match payload.propagation() {
+        Ok(propagation) => propagation,
+        Err(message) => {
+            return (
+                StatusCode::BAD_REQUEST,
+                Json(ApiResponse::error(ErrorCode::InvalidRequest, message)),
+            );
+        }
+    };
+
+    let shell_data_manager = state.shell_data_manager.clone();
+    let report = match tokio::task::spawn_blocking(move || {
+        shell_data_manager.propagate_region(&propagation)
+    })
+    .await
+    {
+        Ok(Ok(report)) => report,
+        Ok(Err(e)) => {
+            error!("Failed to propagate region: {e}");
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ApiResponse::error(
+                    ErrorCode::InternalError,
+                    format!("Failed to propagate region: {e}"),
+                )),
+            );
+        }
+        Err(e) => {
+            error!("Region propagation task failed: {e}");
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ApiResponse::error(
+                    ErrorCode::InternalError,
+                    format!("Failed to propagate region: {e}"),
+                )),
+            );
+        }
+    };
+
+    if !report.dry_run && !report.updated_sessions.is_empty() {
+        let ml_trainer = state.ml_trainer.clone();
+        let session_ids = report.updated_sessions.clone();
+        let heavy_work = state.heavy_work.clone();
+        state.tasks.spawn_job("composite refresh", async move {
+            let _permit = match heavy_work.acquire("composite refresh").await {
+                Ok(permit) => permit,
+                Err(e) => {
+                    error!("Failed to refresh composites: {e}");
+                    return;
+                }
+            };
+            let refreshed = tokio::task::spawn_blocking(move || match ml_trainer.lock() {
+                Ok(trainer) => trainer.refresh_composites(&session_ids),
+                Err(_) => Err(OurError::App("ML trainer lock poisoned".to_string())),
+            })
+            .await;
+            match refreshed {
+                Ok(Ok(report)) => info!(
+                    "Refreshed {} composites after a region change, {} failed",
+                    report.generated.len(),
+                    report.failed.len()
+                ),
+                Ok(Err(e)) => error!("Failed to refresh composites: {e}"),
+                Err(e) => error!("Composite refresh task failed: {e}"),
+            }
+        });
+    }
+
+    (StatusCode::OK, Json(ApiResponse::success(report)))
+}
+
+/// Search shells by brand, shell type or session ID, best matches first
+async fn search_shells(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<ShellSearchQuery>,
+) -> (StatusCode, Json<ApiResponse<Vec<ShellSummary>>>) {
+    let filter = match query.filter() {
+        Ok(filter) => filter,
+        Err(e) => {
+            return (
+                StatusCode::BAD_REQUEST,
+                Json(ApiResponse::error(ErrorCode::InvalidRequest, e.to_string())),
+            );
+        }
+    };
+
+    match state.shell_data_manager.search_shells(&filter) {
+        Ok(shells) => {
+            let required_views = case_type_required_views(&state);
+            let shells = shells
+                .into_iter()
+                .skip(query.offset.unwrap_or(0))
+                .take(query.limit.unwrap_or(usize::MAX))
+                .map(|shell| {
+                    let case_type = format!("{}_{}", shell.brand, shell.shell_type);
+                    shell.with_required_views(required_views_for(&required_views, &case_type))
+                })
+                .collect();
+            (StatusCode::OK, Json(ApiResponse::success(shells)))
+        }
+        Err(e) => {
+            error!("Failed to search shells: {}", e);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ApiResponse::localized(
+                    ErrorCode::InternalError,
+                    Message::ShellSearchFailed {
+                        error: e.to_string(),
+                    },
+                )),
+            )
+        }
+    }
+}
+
+/// Brands shells are already tagged with, for autocompleting the brand while tagging
+async fn list_shell_brands(
+    State(state): State<Arc<AppState>>,
+) -> (StatusCode, Json<ApiResponse<Vec<String>>>) {
+    match state.shell_data_manager.brands() {
+        Ok(brands) => (StatusCode::OK, Json(ApiResponse::success(brands))),
+        Err(e) => {
+            error!("Failed to list shell brands: {e}");
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ApiResponse::localized(
+                    ErrorCode::InternalError,
+                    Message::ShellBrandsFailed {
+                        error: e.to_string(),
+                    },
+                )),
+            )
+        }
+    }
+}
+
+/// One session's shell record, with its images and revision
+async fn get_shell(
+    Path(session_id): Path<String>,
+    State(state): State<Arc<AppState>>,
+) -> (StatusCode, Json<ApiResponse<Shell>>) {
+    match state.shell_data_manager.get_shell(&session_id) {
+        Ok(Some(shell)) => (StatusCode::OK, Json(ApiResponse::success(shell))),
+        Ok(None) => (
+            StatusCode::NOT_FOUND,
+            Json(ApiResponse::localized(
+                ErrorCode::ShellNotFound,
+                Message::ShellNotFound {
+                    session_id: session_id.to_string(),
+                },
+            )),
+        ),
+        Err(OurError::InvalidName { .. }) => (
+            StatusCode::BAD_REQUEST,
+            Json(ApiResponse::localized(
+                ErrorCode::InvalidRequest,
+                Message::InvalidSessionId,
+            )),
+        ),
+        Err(e) => {
+            error!("Failed to load shell data for session {session_id}: {e}");
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ApiResponse::localized(
+                    ErrorCode::InternalError,
+                    Message::ShellLoadFailed {
+                        error: e.to_string(),
+                    },
+                )),
+            )
+        }
+    }
+}
+
+/// The trash deleted shells are moved into
+fn shell_trash(state: &AppState) -> Trash {
+    Trash::new(
+        &state.settings.data_directory,
+        &state.settings.image_directory,
+    )
+}
+
+/// Query parameters for deleting a shell
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case")]
+struct DeleteShellQuery {
+    /// Delete the record and its images for good instead of moving them to the trash
+    #[serde(default)]
+    permanent: bool,
+}
+
+/// What deleting a shell did
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "snake_case")]
+struct DeletedShell {
+    session_id: String,
+    permanent: bool,
+    /// What was moved to the trash, unless the delete was permanent
+    trashed: Option<TrashRecord>,
+}
+
+/// Move a shell and its images to the trash, or delete them for good with `?permanent=true`
+async fn delete_shell(
+    Path(session_id): Path<String>,
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<DeleteShellQuery>,
+) -> (StatusCode, Json<ApiResponse<DeletedShell>>) {
+    let trash = shell_trash(&state);
+    let shell_data_manager = state.shell_data_manager.clone();
+    let id = session_id.clone();
+    let result = tokio::task::spawn_blocking(move || {
+        if query.permanent {
+            trash
+                .delete_permanently(&shell_data_manager, &id)
+                .map(|deleted| deleted.then_some(None))
+        } else {
+            trash
+                .trash_shell(&shell_data_manager, &id, chrono::Utc::now())
+                .map(|trashed| trashed.map(Some))
+        }
+    })
+    .await
+    .map_err(|e| OurError::App(format!("Shell delete task failed: {e}")))
+    .and_then(|result| result);
+
+    match result {
+        Ok(Some(trashed)) => {
+            let (kind, summary) = match &trashed {
+                Some(record) => (
+                    "ShellTrashed",
+                    format!(
+                        "{session_id} ({} {}) moved to the trash with {} images",
+                        record.brand,
+                        record.shell_type,
+                        record.images.len()
+                    ),
+                ),
+                None => ("ShellDeleted", format!("{session_id} deleted permanently")),
+            };
+            state.events.record("shell_data", kind, summary);
+            observe_shell_targets(&state);
+            (
+                StatusCode::OK,
+                Json(ApiResponse::success(DeletedShell {
+                    session_id,
+                    permanent: trashed.is_none(),
+                    trashed,
+                })),
+            )
+        }
+        Ok(None) => (
+            StatusCode::NOT_FOUND,
+            Json(ApiResponse::localized(
+                ErrorCode::ShellNotFound,
+                Message::ShellNotFound {
+                    session_id: session_id.to_string(),
+                },
+            )),
+        ),
+        Err(OurError::InvalidName { .. }) => (
+            StatusCode::BAD_REQUEST,
+            Json(ApiResponse::localized(
+                ErrorCode::InvalidRequest,
+                Message::InvalidSessionId,
+            )),
+        ),
+        Err(e) => {
+            error!("Failed to delete shell {session_id}: {e}");
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ApiResponse::localized(
+                    ErrorCode::InternalError,
+                    Message::ShellDeleteFailed {
+                        error: e.to_string(),
+                    },
+                )),
+            )
+        }
+    }
+}
+
+/// Shells in the trash, most recently deleted first, with how long until each is purged
+async fn list_trash(
+    State(state): State<Arc<AppState>>,
+) -> (StatusCode, Json<ApiResponse<Vec<TrashEntry>>>) {
+    let trash = shell_trash(&state);
+    let retention_days = state.settings.trash_retention_days;
+    let result =
+        tokio::task::spawn_blocking(move || trash.list(retention_days, chrono::Utc::now()))
+            .await
+            .map_err(|e| OurError::App(format!("Trash listing task failed: {e}")))
+            .and_then(|result| result);
+    match result {
+        Ok(entries) => (StatusCode::OK, Json(ApiResponse::success(entries))),
+        Err(e) => {
+            error!("Failed to list the trash: {e}");
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ApiResponse::localized(
+                    ErrorCode::InternalError,
+                    Message::TrashListFailed {
+                        error: e.to_string(),
+                    },
+                )),
+            )
+        }
+    }
+}
+
+/// Put a trashed shell and its images back
+async fn restore_shell(
+    Path(session_id): Path<String>,
+    State(state): State<Arc<AppState>>,
+) -> (StatusCode, Json<ApiResponse<Shell>>) {
+    let trash = shell_trash(&state);
+    let shell_data_manager = state.shell_data_manager.clone();
+    let id = session_id.clone();
+    let result = tokio::task::spawn_blocking(move || trash.restore(&shell_data_manager, &id))
+        .await
+        .map_err(|e| OurError::App(format!("Shell restore task failed: {e}")))
+        .and_then(|result| result);
+
+    match result {
+        Ok(Restore::Restored(shell)) => {
+            state.events.record(
+                "shell_data",
+                "ShellRestored",
+                format!("{session_id} restored from the trash"),
+            );
+            observe_shell_targets(&state);
+            (StatusCode::OK, Json(ApiResponse::success(shell)))
+        }
+        Ok(Restore::NotFound) => (
+            StatusCode::NOT_FOUND,
+            Json(ApiResponse::localized(
+                ErrorCode::ShellNotFound,
+                Message::ShellNotInTrash {
+                    session_id: session_id.to_string(),
+                },
+            )),
+        ),
+        Ok(Restore::Conflict(path)) => (
+            StatusCode::CONFLICT,
+            Json(ApiResponse::localized(
+                ErrorCode::ShellExists,
+                Message::ShellRestoreConflict {
+                    session_id: session_id.clone(),
+                    path: path.display().to_string(),
+                },
+            )),
+        ),
+        Err(OurError::InvalidName { .. }) => (
+            StatusCode::BAD_REQUEST,
+            Json(ApiResponse::localized(
+                ErrorCode::InvalidRequest,
+                Message::InvalidSessionId,
+            )),
+        ),
+        Err(e) => {
+            error!("Failed to restore shell {session_id}: {e}");
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ApiResponse::localized(
+                    ErrorCode::InternalError,
+                    Message::ShellRestoreFailed {
+                        error: e.to_string(),
+                    },
+                )),
+            )
+        }
+    }
+}
+
+/// The views each case type with a record requires, by case type name
+fn case_type_required_views(state: &AppState) -> BTreeMap<String, Vec<ViewType>> {
+    match state.ml_trainer.lock() {
+        Ok(trainer) => trainer
+            .get_case_types()
+            .iter()
+            .map(|(name, case_type)| (name.clone(), case_type.required_views.clone()))
+            .collect(),
+        Err(_) => {
+            error!("Failed to acquire ML trainer lock");
+            BTreeMap::new()
+        }
+    }
+}
+
+/// The views `case_type` requires, the defaults for case types without a record
+fn required_views_for<'a>(
+    required_views: &'a BTreeMap<String, Vec<ViewType>>,
+    case_type: &str,
+) -> &'a [ViewType] {
+    required_views
+        .get(case_type)
+        .map(Vec::as_slice)
+        .unwrap_or(&DEFAULT_REQUIRED_VIEWS)
+}
+
+/// Supported case types, including any added through the API since startup
+async fn case_designations(state: &AppState) -> CaseDesignations {
+    let user_config = current_user_config(state).await;
+    CaseDesignations::new(
+        state
+            .settings
+            .supported_case_types
+            .iter()
+            .chain(&user_config.extra_case_types),
+    )
+}
+
+async fn list_case_designations(
+    State(state): State<Arc<AppState>>,
+) -> (StatusCode, Json<ApiResponse<Vec<CaseDesignation>>>) {
+    let designations = case_designations(&state).await;
+    (
+        StatusCode::OK,
+        Json(ApiResponse::success(designations.designations().to_vec())),
+    )
+}
+
+/// The canonical form of `shell_type`, adding it as a new case type if `allow_new_type` is set
+async fn resolve_shell_type(
+    state: &AppState,
+    shell_type: &str,
+    allow_new_type: bool,
+) -> Result<String, (StatusCode, ApiError)> {
+    let designations = case_designations(state).await;
+    match designations.normalize(shell_type) {
+        Some(canonical) => Ok(canonical.to_string()),
+        None if allow_new_type => {
+            let new_type = shell_type.trim().to_string();
+            let added = new_type.clone();
+            if let Err(e) = state
+                .config_writer
+                .update(move |user_config| {
+                    if !user_config.extra_case_types.contains(&added) {
+                        user_config.extra_case_types.push(added);
+                    }
+                })
+                .await
+            {
+                error!("Failed to save new case type {new_type}: {e}");
+                return Err((
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    ApiError::new(
+                        ErrorCode::InternalError,
+                        format!("Failed to save new case type: {e}"),
+                    ),
+                ));
+            }
+            info!("Added case type {new_type}");
+            Ok(new_type)
+        }
+        None => {
+            let known: Vec<&str> = designations
+                .designations()
+                .iter()
+                .map(|known| known.designation.as_str())
+                .collect();
+            Err((
+                StatusCode::BAD_REQUEST,
+                ApiError::validation(
+                    "Invalid shell",
+                    BTreeMap::from([(
+                        "shell_type".to_string(),
+                        format!(
+                            "unknown case type {shell_type:?}, expected one of {}; set allow_new_type to add it",
+                            known.join(", ")
+                        ),
+                    )]),
+                ),
+            ))
+        }
+    }
+}
+
+/// A 409 for a save by someone other than the session's claimant, unless it is forced
+fn claim_conflict(
+    state: &AppState,
+    session_id: &str,
+    claimant: Option<&str>,
+    force: bool,
+) -> Option<(StatusCode, Json<ApiResponse<SavedShellData>>)> {
+    let holder = match state.session_claims.holder(session_id, chrono::Utc::now()) {
+        Ok(holder) => holder?,
+        Err(e) => {
+            warn!("Failed to check the claim on session {session_id}: {e}");
+            return None;
+        }
+    };
+    if claimant.is_some_and(|claimant| holder.is_held_by(claimant)) {
+        return None;
+    }
+    if force {
+        warn!(
+            "Saving session {session_id} over {}'s claim",
+            holder.claimant
+        );
+        return None;
+    }
+    Some((
+        StatusCode::CONFLICT,
+        Json(ApiResponse::error_with_data(
+            ErrorCode::SessionClaimed,
+            format!(
+                "Session {session_id} is being tagged by {} until {}; resend with force: true to save anyway",
+                holder.claimant, holder.expires_at
+            ),
+            SavedShellData {
+                session_id: session_id.to_string(),
+                message: "Shell data was not saved".to_string(),
+                revision: 0,
+                current: None,
+                claimed_by: Some(holder),
+                warnings: Vec::new(),
+            },
+        )),
+    ))
+}
+
+/// Save `shell` if it is still at `expected_revision`, answering 409 with the current copy if not
+fn save_shell_at_revision(
+    state: &AppState,
+    session_id: String,
+    shell: &Shell,
+    expected_revision: u64,
+) -> (StatusCode, Json<ApiResponse<SavedShellData>>) {
+    match state
+        .shell_data_manager
+        .save_shell_at_revision(&session_id, shell, expected_revision)
+    {
+        Ok(RevisionCheck::Saved(saved)) => (
+            StatusCode::OK,
+            Json(ApiResponse::success(SavedShellData {
+                session_id,
+                message: "Shell data saved successfully".to_string(),
+                revision: saved.revision,
+                current: None,
+                claimed_by: None,
+                warnings: check_measurements(
+                    &state.settings.measurement_ranges,
+                    &saved.shell_type,
+                    saved.weight_grains,
+                    saved.length_mm,
+                ),
+            })),
+        ),
+        Ok(RevisionCheck::Conflict(current)) => (
+            StatusCode::CONFLICT,
+            Json(ApiResponse::error_with_data(
+                ErrorCode::RevisionConflict,
+                format!(
+                    "Shell {session_id} was changed elsewhere (revision {} is newer than {expected_revision}); reload it and try again",
+                    current.revision
+                ),
+                SavedShellData {
+                    session_id,
+                    message: "Shell data was not saved".to_string(),
+                    revision: current.revision,
+                    current: Some(current),
+                    claimed_by: None,
+                    warnings: Vec::new(),
+                },
+            )),
+        ),
+        Err(OurError::App(message)) if message.contains("file not found") => (
+            StatusCode::NOT_FOUND,
+            Json(ApiResponse::localized(
+                ErrorCode::ShellNotFound,
+                Message::ShellGone {
+                    session_id: session_id.to_string(),
+                },
+            )),
+        ),
+        Err(e) => {
+            error!(
+                "Failed to save shell data for session {}: {}",
+                session_id, e
+            );
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ApiResponse::localized(
+                    ErrorCode::InternalError,
+                    Message::ShellSaveFailed {
+                        error: e.to_string(),
+                    },
+                )),
+            )
+        }
+    }
+}
+
+async fn save_shell_data(
+    State(state): State<Arc<AppState>>,
+    ExtractJson(payload): ExtractJson<SaveShellRequest>,
+) -> (StatusCode, Json<ApiResponse<SavedShellData>>) {
+    if let Err(error) = payload.validate() {
+        return (StatusCode::BAD_REQUEST, Json(ApiResponse::failure(error)));
+    }
+    if let Some(conflict) = claim_conflict(
+        &state,
+        &payload.session_id,
+        payload.claimant.as_deref(),
+        payload.force,
+    ) {
+        return conflict;
+    }
+
+    let shell_type =
+        match resolve_shell_type(&state, &payload.shell_type, payload.allow_new_type).await {
+            Ok(shell_type) => shell_type,
+            Err((status, error)) => return (status, Json(ApiResponse::failure(error))),
+        };
+
+    let mut shell = Shell::new(payload.brand, shell_type);
+    shell.include = payload.include;
+    shell.image_filenames = payload.image_filenames;
+    shell.notes = payload
+        .notes
+        .map(|notes| notes.trim().to_string())
+        .filter(|notes| !notes.is_empty());
+    shell.flags = payload.flags;
+    // Keep what was recorded at capture, such as which burst frames were preferred
+    if let Ok(Some(existing)) = state.shell_data_manager.get_shell(&payload.session_id) {
+        shell.batch_id = existing.batch_id;
+        shell.weight_grains = existing.weight_grains;
+        shell.length_mm = existing.length_mm;
+        shell.measured_at = existing.measured_at;
+        shell.captured_images = existing.captured_images.map(|images| {
+            images
+                .into_iter()
+                .filter(|image| shell.image_filenames.contains(&image.filename))
+                .map(|mut image| {
+                    if let Some(view_type) = payload.view_types.get(&image.filename) {
+                        image.choose_view_type(*view_type);
+                    }
+                    image
+                })
+                .collect()
+        });
+    }
+    if payload.weight_grains.is_some() || payload.length_mm.is_some() {
+        shell.weight_grains = payload.weight_grains.or(shell.weight_grains);
+        shell.length_mm = payload.length_mm.or(shell.length_mm);
+        shell.measured_at = Some(chrono::Utc::now());
+    }
+
+    let saved = save_shell_at_revision(&state, payload.session_id, &shell, payload.revision);
+    if saved.0 == StatusCode::OK {
+        observe_shell_targets(&state);
+    }
+    saved
+}
+
+async fn update_shell(
+    Path(session_id): Path<String>,
+    State(state): State<Arc<AppState>>,
+    ExtractJson(payload): ExtractJson<UpdateShellRequest>,
+) -> (StatusCode, Json<ApiResponse<SavedShellData>>) {
+    if let Err(error) = payload.validate() {
+        return (StatusCode::BAD_REQUEST, Json(ApiResponse::failure(error)));
+    }
+    if let Some(conflict) = claim_conflict(
+        &state,
+        &session_id,
+        payload.claimant.as_deref(),
+        payload.force,
+    ) {
+        return conflict;
+    }
+
+    let mut shell = match state.shell_data_manager.get_shell(&session_id) {
+        Ok(Some(shell)) => shell,
+        Ok(None) => {
+            return (
+                StatusCode::NOT_FOUND,
+                Json(ApiResponse::localized(
+                    ErrorCode::ShellNotFound,
+                    Message::ShellNotFound {
+                        session_id: session_id.to_string(),
+                    },
+                )),
+            );
+        }
+        Err(OurError::InvalidName { .. }) => {
+            return (
+                StatusCode::BAD_REQUEST,
+                Json(ApiResponse::localized(
+                    ErrorCode::InvalidRequest,
+                    Message::InvalidSessionId,
+                )),
+            );
+        }
+        Err(e) => {
+            error!("Failed to load shell data for session {session_id}: {e}");
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ApiResponse::localized(
+                    ErrorCode::InternalError,
+                    Message::ShellLoadFailed {
+                        error: e.to_string(),
+                    },
+                )),
+            );
+        }
+    };
+
+    if let Some(brand) = payload.brand {
+        shell.brand = brand.trim().to_string();
+    }
+    if let Some(shell_type) = payload.shell_type {
+        shell.shell_type =
+            match resolve_shell_type(&state, &shell_type, payload.allow_new_type).await {
+                Ok(shell_type) => shell_type,
+                Err((status, error)) => return (status, Json(ApiResponse::failure(error))),
+            };
+    }
+    if let Some(include) = payload.include {
+        shell.include = include;
+    }
+    if let Some(notes) = payload.notes {
+        shell.notes = Some(notes.trim().to_string()).filter(|notes| !notes.is_empty());
+    }
+    if let Some(flags) = payload.flags {
+        shell.flags = flags;
+    }
+    if payload.weight_grains.is_some() || payload.length_mm.is_some() {
+        if let Some(weight_grains) = payload.weight_grains {
+            shell.weight_grains = weight_grains;
+        }
+        if let Some(length_mm) = payload.length_mm {
+            shell.length_mm = length_mm;
+        }
+        let measured = shell.weight_grains.is_some() || shell.length_mm.is_some();
+        shell.measured_at = measured.then(chrono::Utc::now);
+    }
+    for image in shell.captured_images.iter_mut().flatten() {
+        if let Some(view_type) = payload.view_types.get(&image.filename) {
+            image.choose_view_type(*view_type);
+        }
+    }
+
+    let saved = save_shell_at_revision(&state, session_id, &shell, payload.revision);
+    if saved.0 == StatusCode::OK {
+        observe_shell_targets(&state);
+    }
+    saved
+}
+
+/// A reading from a scale or calipers; the shell is saved at its current revision unless one is given
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+struct MeasurementReading {
+    weight_grains: Option<f32>,
+    length_mm: Option<f32>,
+    /// When the reading was taken, defaulting to when it arrives
+    measured_at: Option<chrono::DateTime<chrono::Utc>>,
+    /// What took the reading, e.g. `scale:/dev/ttyUSB0`, for the log
+    source: Option<String>,
+    /// Refuse the reading if the shell has been saved since this revision
+    revision: Option<u64>,
+}
+
+impl MeasurementReading {
+    fn validate(&self) -> Result<(), ApiError> {
+        let mut errors = BTreeMap::new();
+        if self.weight_grains.is_none() && self.length_mm.is_none() {
+            errors.insert(
+                "weight_grains".to_string(),
+                "give weight_grains, length_mm or both".to_string(),
+            );
+        }
+        measurement_errors(&mut errors, self.weight_grains, self.length_mm);
+        shell_validation(errors)
+    }
+}
+
+/// Record a shell's weight or length, warning if it is unlike its designation
+///
+/// Claims aren't checked: a reading is taken at the bench, not while tagging.
+async fn record_shell_measurements(
+    Path(session_id): Path<String>,
+    State(state): State<Arc<AppState>>,
+    ExtractJson(reading): ExtractJson<MeasurementReading>,
+) -> (StatusCode, Json<ApiResponse<SavedShellData>>) {
+    if let Err(error) = reading.validate() {
+        return (StatusCode::BAD_REQUEST, Json(ApiResponse::failure(error)));
+    }
+    let mut shell = match state.shell_data_manager.get_shell(&session_id) {
+        Ok(Some(shell)) => shell,
+        Ok(None) => {
+            return (
+                StatusCode::NOT_FOUND,
+                Json(ApiResponse::localized(
+                    ErrorCode::ShellNotFound,
+                    Message::ShellNotFound {
+                        session_id: session_id.to_string(),
+                    },
+                )),
+            );
+        }
+        Err(OurError::InvalidName { .. }) => {
+            return (
+                StatusCode::BAD_REQUEST,
+                Json(ApiResponse::localized(
+                    ErrorCode::InvalidRequest,
+                    Message::InvalidSessionId,
+                )),
+            );
+        }
+        Err(e) => {
+            error!("Failed to load shell data for session {session_id}: {e}");
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ApiResponse::localized(
+                    ErrorCode::InternalError,
+                    Message::ShellLoadFailed {
+                        error: e.to_string(),
+                    },
+                )),
+            );
+        }
+    };
+
+    shell.weight_grains = reading.weight_grains.or(shell.weight_grains);
+    shell.length_mm = reading.length_mm.or(shell.length_mm);
+    shell.measured_at = Some(reading.measured_at.unwrap_or_else(chrono::Utc::now));
+    let revision = reading.revision.unwrap_or(shell.revision);
+    let saved = save_shell_at_revision(&state, session_id.clone(), &shell, revision);
+    if saved.0 == StatusCode::OK {
+        info!(
+            "Recorded measurements for {session_id} from {}",
+            reading.source.as_deref().unwrap_or("the API")
+        );
+    }
+    saved
+}
+
+/// A request to claim a session for tagging
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+struct ClaimSessionRequest {
+    claimant: String,
+    /// How long the claim lasts, defaulting to `session_claim_seconds`
+    #[serde(default)]
+    duration_seconds: Option<u64>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case")]
+struct ReleaseSessionQuery {
+    claimant: String,
+}
+
+/// Longest claim that can be asked for
+const MAX_CLAIM_SECONDS: u64 = 24 * 60 * 60;
+
+/// The problem with a session ID and claimant, if there is one
+fn validate_claim(session_id: &str, claimant: &str) -> Result<(), String> {
+    SafeName::new("session_id", session_id).map_err(|e| e.to_string())?;
+    let length = claimant.trim().chars().count();
+    if length == 0 || length > MAX_CLAIMANT_LENGTH {
+        return Err(format!(
+            "claimant must be between 1 and {MAX_CLAIMANT_LENGTH} characters"
+        ));
+    }
+    Ok(())
+}
+
+/// Claim a session for tagging, or renew your claim
+async fn claim_session(
+    Path(session_id): Path<String>,
+    State(state): State<Arc<AppState>>,
+    ExtractJson(payload): ExtractJson<ClaimSessionRequest>,
+) -> (StatusCode, Json<ApiResponse<SessionClaim>>) {
+    if let Err(message) = validate_claim(&session_id, &payload.claimant) {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(ApiResponse::error(ErrorCode::InvalidRequest, message)),
+        );
+    }
+    let seconds = payload
+        .duration_seconds
+        .unwrap_or(state.settings.session_claim_seconds);
+    if seconds == 0 || seconds > MAX_CLAIM_SECONDS {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(ApiResponse::error(
+                ErrorCode::InvalidRequest,
+                format!("duration_seconds must be between 1 and {MAX_CLAIM_SECONDS}"),
+            )),
+        );
+    }
+
+    match state.session_claims.claim(
+        &session_id,
+        &payload.claimant,
+        Duration::from_secs(seconds),
+        chrono::Utc::now(),
+    ) {
+        Ok(ClaimOutcome::Claimed(claim)) => (StatusCode::OK, Json(ApiResponse::success(claim))),
+        Ok(ClaimOutcome::Held(claim)) => (
+            StatusCode::CONFLICT,
+            Json(ApiResponse::error_with_data(
+                ErrorCode::SessionClaimed,
+                format!(
+                    "Session {session_id} is being tagged by {} until {}",
+                    claim.claimant, claim.expires_at
+                ),
+                claim,
+            )),
+        ),
+        Err(e) => {
+            error!("Failed to claim session {session_id}: {e}");
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ApiResponse::error(
+                    ErrorCode::InternalError,
+                    format!("Failed to claim session: {e}"),
+                )),
+            )
+        }
+    }
+}
+
+/// Give up a claim on a session
+async fn release_session(
+    Path(session_id): Path<String>,
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<ReleaseSessionQuery>,
+) -> (StatusCode, Json<ApiResponse<()>>) {
+    if let Err(message) = validate_claim(&session_id, &query.claimant) {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(ApiResponse::error(ErrorCode::InvalidRequest, message)),
+        );
+    }
+    match state
+        .session_claims
+        .release(&session_id, &query.claimant, chrono::Utc::now())
+    {
+        Ok(None) => (StatusCode::OK, Json(ApiResponse::success(()))),
+        Ok(Some(holder)) => (
+            StatusCode::CONFLICT,
+            Json(ApiResponse::error(
+                ErrorCode::SessionClaimed,
+                format!(
+                    "Session {session_id} is claimed by {}, not {}",
+                    holder.claimant,
+                    query.claimant.trim()
+                ),
+            )),
+        ),
+        Err(e) => {
+            error!("Failed to release session {session_id}: {e}");
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ApiResponse::error(
+                    ErrorCode::InternalError,
+                    format!("Failed to release session: {e}"),
+                )),
+            )
+        }
+    }
+}
+
+/// Every session someone is tagging, soonest claim to expire first
+async fn list_session_claims(
+    State(state): State<Arc<AppState>>,
+) -> (StatusCode, Json<ApiResponse<Vec<SessionClaim>>>) {
+    match state.session_claims.active(chrono::Utc::now()) {
+        Ok(claims) => (StatusCode::OK, Json(ApiResponse::success(claims))),
+        Err(e) => {
+            error!("Failed to list session claims: {e}");
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ApiResponse::error(
+                    ErrorCode::InternalError,
+                    format!("Failed to list session claims: {e}"),
+                )),
+            )
+        }
+    }
+}
+
+async fn toggle_shell_training(
+    Path(session_id): Path<String>,
+    State(state): State<Arc<AppState>>,
+) -> (StatusCode, Json<ApiResponse<HashMap<String, bool>>>) {
+    match state.shell_data_manager.toggle_shell_training(&session_id) {
+        Ok(include_flag) => {
+            observe_shell_targets(&state);
+            let mut response = HashMap::new();
+            response.insert("include".to_string(), include_flag);
+            (StatusCode::OK, Json(ApiResponse::success(response)))
+        }
+        Err(e @ OurError::InvalidName { .. }) => (
+            StatusCode::BAD_REQUEST,
+            Json(ApiResponse::error(ErrorCode::InvalidRequest, e.to_string())),
+        ),
+        Err(e) => {
+            error!(
+                "Failed to toggle training for session {}: {}",
+                session_id, e
+            );
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ApiResponse::localized(
+                    ErrorCode::InternalError,
+                    Message::ShellToggleFailed {
+                        error: e.to_string(),
+                    },
+                )),
+            )
+        }
+    }
+}
+
+/// A shell image's view as guessed from the image
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "snake_case")]
+struct ClassifiedView {
+    session_id: String,
+    filename: String,
+    /// The image's view type after the guess, kept if it was already set
+    view_type: ViewType,
+    guess: ViewGuess,
+    /// The shell's revision after the guess was saved
+    revision: u64,
+}
+
+/// Guess a shell image's view from the image and record it, setting the view type if it was unknown
+async fn classify_shell_image_view(
+    Path((session_id, filename)): Path<(String, String)>,
+    State(state): State<Arc<AppState>>,
+) -> (StatusCode, Json<ApiResponse<ClassifiedView>>) {
+    let failure = |status: StatusCode, code: ErrorCode, message: String| {
+        (status, Json(ApiResponse::error(code, message)))
+    };
+    let filename = match SafeName::file_name("filename", &filename) {
+        Ok(filename) => filename.as_str().to_string(),
+        Err(e) => {
+            return failure(
+                StatusCode::BAD_REQUEST,
+                ErrorCode::InvalidRequest,
+                e.to_string(),
+            );
+        }
+    };
+    let mut shell = match state.shell_data_manager.get_shell(&session_id) {
+        Ok(Some(shell)) => shell,
+        Ok(None) => {
+            return failure(
+                StatusCode::NOT_FOUND,
+                ErrorCode::ShellNotFound,
+                Message::ShellNotFound {
+                    session_id: session_id.to_string(),
+                }
+                .render(),
+            );
+        }
+        Err(e @ OurError::InvalidName { .. }) => {
+            return failure(
+                StatusCode::BAD_REQUEST,
+                ErrorCode::InvalidRequest,
+                e.to_string(),
+            );
+        }
+        Err(e) => {
+            error!("Failed to load shell {session_id} to classify {filename}: {e}");
+            return failure(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                ErrorCode::InternalError,
+                format!("Failed to load shell: {e}"),
+            );
+        }
+    };
+    if !shell
+        .captured_images
+        .iter()
+        .flatten()
+        .any(|image| image.filename == filename)
+    {
+        return failure(
+            StatusCode::NOT_FOUND,
+            ErrorCode::InvalidRequest,
+            format!("Shell {session_id} has no image {filename}"),
+        );
+    }
+
+    let path = state.settings.image_directory.join(&filename);
+    let guess = match tokio::task::spawn_blocking(move || view_classifier::classify_file(&path))
+        .await
+        .map_err(|e| OurError::App(format!("View classification task failed: {e}")))
+        .and_then(|guess| guess)
+    {
+        Ok(Some(guess)) => guess,
+        Ok(None) => {
+            return failure(
+                StatusCode::UNPROCESSABLE_ENTITY,
+                ErrorCode::InvalidRequest,
+                format!("No case outline stands out in {filename}, so its view can't be guessed"),
+            );
+        }
+        Err(e) => {
+            error!("Failed to classify the view of {filename}: {e}");
+            return failure(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                ErrorCode::InternalError,
+                format!("Failed to classify {filename}: {e}"),
+            );
+        }
+    };
+
+    let mut view_type = ViewType::Unknown;
+    for image in shell.captured_images.iter_mut().flatten() {
+        if image.filename == filename {
+            image.apply_view_guess(guess);
+            view_type = image.view_type;
+        }
+    }
+    let revision = shell.revision;
+    match state
+        .shell_data_manager
+        .save_shell_at_revision(&session_id, &shell, revision)
+    {
+        Ok(RevisionCheck::Saved(saved)) => {
+            info!(
+                "Guessed {filename} in shell {session_id} is a {} view ({:.0}% sure)",
+                guess.view_type,
+                guess.confidence * 100.0
+            );
+            (
+                StatusCode::OK,
+                Json(ApiResponse::success(ClassifiedView {
+                    session_id,
+                    filename,
+                    view_type,
+                    guess,
+                    revision: saved.revision,
+                })),
+            )
+        }
+        Ok(RevisionCheck::Conflict(current)) => failure(
+            StatusCode::CONFLICT,
+            ErrorCode::RevisionConflict,
+            format!(
+                "Shell {session_id} was changed elsewhere (revision {} is newer than {revision}); try again",
+                current.revision
+            ),
+        ),
+        Err(e) => {
+            error!("Failed to save the view guess for shell {session_id}: {e}");
+            failure(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                ErrorCode::InternalError,
+                format!("Failed to save shell: {e}"),
+            )
+        }
+    }
+}
+
+/// Stream a zip of a session's shell record, images and composite
+async fn export_shell_bundle(
+    Path(session_id): Path<String>,
+    State(state): State<Arc<AppState>>,
+) -> Response {
+    let error_response = |status: StatusCode, code: ErrorCode, message: String| {
+        (status, Json(ApiResponse::<()>::error(code, message))).into_response()
+    };
+    match state.shell_data_manager.get_shell(&session_id) {
+        Ok(Some(_)) => {}
+        Ok(None) => {
+            return error_response(
+                StatusCode::NOT_FOUND,
+                ErrorCode::ShellNotFound,
+                format!("Shell not found: {session_id}"),
+            );
+        }
+        Err(e @ OurError::InvalidName { .. }) => {
+            return error_response(
+                StatusCode::BAD_REQUEST,
+                ErrorCode::InvalidRequest,
+                e.to_string(),
+            );
+        }
+        Err(e) => {
+            error!("Failed to load shell {session_id} for export: {e}");
+            return error_response(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                ErrorCode::InternalError,
+                format!("Failed to load shell: {e}"),
+            );
+        }
+    }
+
+    let shell_data_manager = state.shell_data_manager.clone();
+    let settings = state.settings.clone();
+    let export_id = session_id.clone();
+    let bundle = match tokio::task::spawn_blocking(move || {
+        session_bundle::export_to_temp_file(&shell_data_manager, &settings, &export_id)
+    })
+    .await
+    {
+        Ok(Ok((bundle, _))) => bundle,
+        Ok(Err(e)) => {
+            error!("Failed to export session {session_id}: {e}");
+            return error_response(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                ErrorCode::InternalError,
+                format!("Failed to export session: {e}"),
+            );
+        }
+        Err(e) => {
+            error!("Session export task failed: {e}");
+            return error_response(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                ErrorCode::InternalError,
+                format!("Failed to export session: {e}"),
+            );
+        }
+    };
+    download_response(bundle, "application/zip", &format!("{session_id}.zip")).await
+}
+
+/// Stream a finished export as an attachment, removing the file once it's sent or dropped
+async fn download_response(export: TempBundle, content_type: &str, filename: &str) -> Response {
+    let mut file = match tokio::fs::File::open(export.path()).await {
+        Ok(file) => file,
+        Err(e) => {
+            error!("Failed to open export {}: {e}", export.path().display());
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ApiResponse::<()>::error(
+                    ErrorCode::InternalError,
+                    format!("Failed to open export: {e}"),
+                )),
+            )
+                .into_response();
+        }
+    };
+    let length = file.metadata().await.map(|metadata| metadata.len()).ok();
+
+    let stream = async_stream::stream! {
+        // The temporary file is removed once the response has been sent or dropped
+        let _export = export;
+        let mut buffer = vec![0; 64 * 1024];
+        loop {
+            match file.read(&mut buffer).await {
+                Ok(0) => break,
+                Ok(read) => yield Ok(axum::body::Bytes::copy_from_slice(&buffer[..read])),
+                Err(e) => {
+                    yield Err::<axum::body::Bytes, std::io::Error>(e);
+                    break;
+                }
+            }
+        }
+    };
+
+    let mut response = Response::builder()
+        .header("Content-Type", content_type)
+        .header(
+            "Content-Disposition",
+            format!("attachment; filename=\"{filename}\""),
+        );
+    if let Some(length) = length {
+        response = response.header("Content-Length", length);
+    }
+    response
+        .body(Body::from_stream(stream))
+        .unwrap_or_else(|e| {
+            error!("Failed to build export response: {e}");
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        })
+}
+
+/// Camera name of images uploaded to a shell without one
+const EXTERNAL_CAMERA_NAME: &str = "external";
+
+/// Details of one image uploaded to a shell, keyed in the `metadata` field by its file name
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+struct ShellImageMetadata {
+    #[serde(default)]
+    view_type: ViewType,
+    /// Defaults to [`EXTERNAL_CAMERA_NAME`]
+    camera_name: Option<String>,
+}
+
+/// The images added to a shell by an upload
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "snake_case")]
+struct UploadedShellImages {
+    session_id: String,
+    /// The shell's revision after the images were added
+    revision: u64,
+    /// The stored file names, in upload order, served from `/images/{filename}`
+    filenames: Vec<String>,
+}
+
+/// Remove images stored for an upload that couldn't be saved to its shell
+fn remove_stored_images(image_directory: &std::path::Path, filenames: &[String]) {
+    for filename in filenames {
+        if let Err(e) = std::fs::remove_file(image_directory.join(filename)) {
+            warn!("Failed to remove uploaded image {filename}: {e}");
+        }
+    }
+}
+
+/// Add uploaded photos, such as macro shots from another camera, to a shell's images
+///
+/// Every file field is an image; an optional `metadata` field holds a JSON
+/// object of [`ShellImageMetadata`] by uploaded file name. Images are stored
+/// as JPEG without a region.
+async fn upload_shell_images(
+    Path(session_id): Path<String>,
+    State(state): State<Arc<AppState>>,
+    mut multipart: Multipart,
+) -> (StatusCode, Json<ApiResponse<UploadedShellImages>>) {
+    let failure = |status: StatusCode, code: ErrorCode, message: String| {
+        (status, Json(ApiResponse::error(code, message)))
+    };
+    if let Err(e) = state.disk_space.check("image upload") {
+        error!("{e}");
+        return failure(
+            StatusCode::INSUFFICIENT_STORAGE,
+            ErrorCode::InsufficientStorage,
+            e.to_string(),
+        );
+    }
+    match state.shell_data_manager.get_shell(&session_id) {
+        Ok(Some(_)) => {}
+        Ok(None) => {
+            return failure(
+                StatusCode::NOT_FOUND,
+                ErrorCode::ShellNotFound,
+                Message::ShellNotFound {
+                    session_id: session_id.to_string(),
+                }
+                .render(),
+            );
+        }
+        Err(e @ OurError::InvalidName { .. }) => {
+            return failure(
+                StatusCode::BAD_REQUEST,
+                ErrorCode::InvalidRequest,
+                e.to_string(),
+            );
+        }
+        Err(e) => {
+            error!("Failed to load shell {session_id} for an image upload: {e}");
+            return failure(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                ErrorCode::InternalError,
+                format!("Failed to load shell: {e}"),
+            );
+        }
+    }
+
+    let limit_bytes = state.settings.max_upload_bytes();
+    let image_directory = state.settings.image_directory.clone();
+    let mut metadata: HashMap<String, ShellImageMetadata> = HashMap::new();
+    let mut files = Vec::new();
+    loop {
+        let mut field = match multipart.next_field().await {
+            Ok(Some(field)) => field,
+            Ok(None) => break,
+            Err(e) => return multipart_rejection(e, limit_bytes),
+        };
+        let Some(file_name) = field.file_name().map(|name| name.to_string()) else {
+            if field.name() == Some("metadata") {
+                let parsed = match field.text().await {
+                    Ok(text) => serde_json::from_str(&text).map_err(|e| e.to_string()),
+                    Err(e) => Err(e.body_text()),
+                };
+                metadata = match parsed {
+                    Ok(parsed) => parsed,
+                    Err(e) => {
+                        return failure(
+                            StatusCode::BAD_REQUEST,
+                            ErrorCode::InvalidRequest,
+                            format!("Invalid metadata: {e}"),
+                        );
+                    }
+                };
+            }
+            continue;
+        };
+        let upload = TempUpload::new(&image_directory);
+        match upload::spool_field(&mut field, upload.path()).await {
+            Ok(_) => files.push((file_name, upload)),
+            Err(SpoolError::TooLarge) => {
+                warn!("Upload of {file_name} for shell {session_id} is over the limit");
+                return payload_too_large(limit_bytes);
+            }
+            Err(e) => {
+                error!("Failed to spool uploaded file {file_name}: {e}");
+                let (status, code) = match e {
+                    SpoolError::Write(_) => {
+                        (StatusCode::INTERNAL_SERVER_ERROR, ErrorCode::InternalError)
+                    }
+                    _ => (StatusCode::BAD_REQUEST, ErrorCode::InvalidRequest),
+                };
+                return failure(status, code, format!("{file_name}: {e}"));
+            }
+        }
+    }
+
+    if files.is_empty() {
+        return failure(
+            StatusCode::BAD_REQUEST,
+            ErrorCode::InvalidRequest,
+            "No image files were uploaded".to_string(),
+        );
+    }
+    if let Some(unknown) = metadata
+        .keys()
+        .find(|name| !files.iter().any(|(file_name, _)| file_name == *name))
+    {
+        return failure(
+            StatusCode::BAD_REQUEST,
+            ErrorCode::InvalidRequest,
+            format!("metadata names {unknown}, which wasn't uploaded"),
+        );
+    }
+    for (file_name, upload) in &files {
+        if let Err(e) = image_ingest::detect_upload_kind(upload.path()) {
+            return failure(
+                StatusCode::BAD_REQUEST,
+                ErrorCode::InvalidRequest,
+                format!("{file_name}: {e}"),
+            );
+        }
+    }
+
+    let quality = state.settings.image_jpeg_quality;
+    let stem = format!("{session_id}_{EXTERNAL_CAMERA_NAME}");
+    let directory = image_directory.clone();
+    // Photos uploaded without a view type get a guessed one
+    let unset_views: HashSet<String> = files
+        .iter()
+        .map(|(file_name, _)| file_name)
+        .filter(|file_name| {
+            metadata
+                .get(*file_name)
+                .is_none_or(|details| details.view_type == ViewType::Unknown)
+        })
+        .cloned()
+        .collect();
+    let stored = tokio::task::spawn_blocking(move || {
+        let mut stored = Vec::new();
+        for (file_name, upload) in files {
+            match image_ingest::store_upload_as_jpeg(upload.path(), &directory, &stem, quality) {
+                Ok(filename) => {
+                    let guess = if unset_views.contains(&file_name) {
+                        view_classifier::classify_file(&directory.join(&filename))
+                            .inspect_err(|e| warn!("Couldn't guess the view of {file_name}: {e}"))
+                            .ok()
+                            .flatten()
+                    } else {
+                        None
+                    };
+                    stored.push((file_name, filename, guess));
+                }
+                Err(e) => {
+                    let filenames: Vec<String> = stored
+                        .into_iter()
+                        .map(|(_, filename, _)| filename)
+                        .collect();
+                    remove_stored_images(&directory, &filenames);
+                    return Err(OurError::App(format!("{file_name}: {e}")));
+                }
+            }
+        }
+        Ok(stored)
+    })
+    .await
+    .map_err(|e| OurError::App(format!("Image upload task failed: {e}")))
+    .and_then(|stored| stored);
+    let stored = match stored {
+        Ok(stored) => stored,
+        Err(e) => {
+            error!("Failed to store images uploaded to shell {session_id}: {e}");
+            return failure(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                ErrorCode::InternalError,
+                format!("Failed to store {e}"),
+            );
+        }
+    };
+    let filenames: Vec<String> = stored
+        .iter()
+        .map(|(_, filename, _)| filename.clone())
+        .collect();
+
+    // Reloaded now, so edits made while the upload arrived are kept
+    let mut shell = match state.shell_data_manager.get_shell(&session_id) {
+        Ok(Some(shell)) => shell,
+        Ok(None) => {
+            remove_stored_images(&image_directory, &filenames);
+            return failure(
+                StatusCode::NOT_FOUND,
+                ErrorCode::ShellNotFound,
+                Message::ShellGone {
+                    session_id: session_id.to_string(),
+                }
+                .render(),
+            );
+        }
+        Err(e) => {
+            error!("Failed to load shell {session_id} for an image upload: {e}");
+            remove_stored_images(&image_directory, &filenames);
+            return failure(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                ErrorCode::InternalError,
+                format!("Failed to load shell: {e}"),
+            );
+        }
+    };
+    let first_index = shell
+        .captured_images
+        .iter()
+        .flatten()
+        .map(|image| image.camera_index + 1)
+        .max()
+        .unwrap_or(0);
+    for (camera_index, (file_name, filename, guess)) in (first_index..).zip(&stored) {
+        let details = metadata.remove(file_name).unwrap_or_default();
+        let camera_name = details
+            .camera_name
+            .map(|name| name.trim().to_string())
+            .filter(|name| !name.is_empty())
+            .unwrap_or_else(|| EXTERNAL_CAMERA_NAME.to_string());
+        shell.add_image(filename.clone());
+        let mut image = CapturedImage::new(
+            camera_index,
+            filename.clone(),
+            camera_name,
+            details.view_type,
+        );
+        if let Some(guess) = guess {
+            image.apply_view_guess(*guess);
+        }
+        shell.add_captured_image(image);
+    }
+
+    let revision = shell.revision;
+    match state
+        .shell_data_manager
+        .save_shell_at_revision(&session_id, &shell, revision)
+    {
+        Ok(RevisionCheck::Saved(saved)) => {
+            info!(
+                "Added {} uploaded images to shell {session_id}",
+                filenames.len()
+            );
+            (
+                StatusCode::OK,
+                Json(ApiResponse::success(UploadedShellImages {
+                    session_id,
+                    revision: saved.revision,
+                    filenames,
+                })),
+            )
+        }
+        Ok(RevisionCheck::Conflict(current)) => {
+            remove_stored_images(&image_directory, &filenames);
+            failure(
+                StatusCode::CONFLICT,
+                ErrorCode::RevisionConflict,
+                format!(
+                    "Shell {session_id} was changed elsewhere (revision {} is newer than {revision}); upload the images again",
+                    current.revision
+                ),
+            )
+        }
+        Err(e) => {
+            error!("Failed to save images uploaded to shell {session_id}: {e}");
+            remove_stored_images(&image_directory, &filenames);
+            failure(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                ErrorCode::InternalError,
+                Message::ShellSaveFailed {
+                    error: e.to_string(),
+                }
+                .render(),
+            )
+        }
+    }
+}
+
+/// Recreate a session from an uploaded bundle under a fresh session ID
+async fn import_shell_bundle(
+    State(state): State<Arc<AppState>>,
+    mut multipart: Multipart,
+) -> (StatusCode, Json<ApiResponse<ImportedSession>>) {
+    if let Err(e) = state.disk_space.check("bundle import") {
+        error!("{e}");
+        return (
+            StatusCode::INSUFFICIENT_STORAGE,
+            Json(ApiResponse::error(
+                ErrorCode::InsufficientStorage,
+                e.to_string(),
+            )),
+        );
+    }
+
+    // Spool the upload to disk, since the zip index is at the end of the file
+    let upload = TempBundle::new("import");
+    let mut field = loop {
+        match multipart.next_field().await {
+            Ok(Some(field)) if field.file_name().is_some() => break field,
+            Ok(Some(_)) => continue,
+            Ok(None) => {
+                return (
+                    StatusCode::BAD_REQUEST,
+                    Json(ApiResponse::error(
+                        ErrorCode::InvalidRequest,
+                        "No bundle file was uploaded".to_string(),
+                    )),
+                );
+            }
+            Err(e) => return multipart_rejection(e, MAX_BUNDLE_BYTES as usize),
+        }
+    };
+    match upload::spool_field(&mut field, upload.path()).await {
+        Ok(_) => {}
+        Err(SpoolError::TooLarge) => return payload_too_large(MAX_BUNDLE_BYTES as usize),
+        Err(e) => {
+            error!("{e}");
+            return (
+                StatusCode::BAD_REQUEST,
+                Json(ApiResponse::error(ErrorCode::InvalidRequest, e.to_string())),
+            );
+        }
+    }
+
+    let shell_data_manager = state.shell_data_manager.clone();
+    let settings = state.settings.clone();
+    match tokio::task::spawn_blocking(move || {
+        let file = std::fs::File::open(upload.path())
+            .map_err(|e| OurError::App(format!("Failed to read upload: {e}")))?;
+        session_bundle::import_session(
+            &shell_data_manager,
+            &settings,
+            std::io::BufReader::new(file),
+        )
+    })
+    .await
+    {
+        Ok(Ok(imported)) => (StatusCode::OK, Json(ApiResponse::success(imported))),
+        Ok(Err(e)) => {
+            warn!("Rejected session bundle: {e}");
+            (
+                StatusCode::BAD_REQUEST,
+                Json(ApiResponse::error(
+                    ErrorCode::InvalidRequest,
+                    format!("Failed to import bundle: {e}"),
+                )),
+            )
+        }
+        Err(e) => {
+            error!("Session import task failed: {e}");
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ApiResponse::error(
+                    ErrorCode::InternalError,
+                    format!("Failed to import bundle: {e}"),
+                )),
+            )
+        }
+    }
+}
+
+async fn ml_list_shells(
+    State(state): State<Arc<AppState>>,
+) -> Json<ApiResponse<Vec<HashMap<String, serde_json::Value>>>> {
+    match state.shell_data_manager.get_shells_for_training() {
+        Ok(shells) => {
+            let shell_data: Vec<HashMap<String, serde_json::Value>> = shells
+                .into_iter()
+                .map(|(session_id, shell)| {
+                    let mut data = HashMap::new();
+                    data.insert(
+                        "session_id".to_string(),
+                        serde_json::Value::String(session_id),
+                    );
+                    data.insert(
+                        "brand".to_string(),
+                        serde_json::Value::String(shell.brand.clone()),
+                    );
+                    data.insert(
+                        "shell_type".to_string(),
+                        serde_json::Value::String(shell.shell_type.clone()),
+                    );
+                    data.insert(
+                        "date_captured".to_string(),
+                        serde_json::Value::String(shell.date_captured.to_rfc3339()),
+                    );
+                    data.insert(
+                        "include".to_string(),
+                        serde_json::Value::Bool(shell.include),
+                    );
+                    data.insert(
+                        "image_count".to_string(),
+                        serde_json::Value::Number(serde_json::Number::from(shell.image_count())),
+                    );
+                    data.insert(
+                        "has_complete_regions".to_string(),
+                        serde_json::Value::Bool(shell.has_complete_regions()),
+                    );
+                    data.insert(
+                        "case_type_key".to_string(),
+                        serde_json::Value::String(shell.get_case_type_key()),
+                    );
+
+                    // Add image filenames if available
+                    if !shell.image_filenames.is_empty() {
+                        let filenames: Vec<serde_json::Value> = shell
+                            .image_filenames
+                            .into_iter()
+                            .map(serde_json::Value::String)
+                            .collect();
+                        data.insert(
+                            "image_filenames".to_string(),
+                            serde_json::Value::Array(filenames),
+                        );
+                    }
+
+                    data
+                })
+                .collect();
+
+            Json(ApiResponse::success(shell_data))
+        }
+        Err(e) => {
+            error!("Failed to list shells for ML training: {}", e);
+            Json(ApiResponse::error(
+                ErrorCode::InternalError,
+                format!("Failed to list shells for ML training: {e}"),
+            ))
+        }
+    }
+}
+
+/// Generate composites for the training set, skipping those already drawn with the current layout
+/// HTTP 429 for heavy work refused a permit, saying when to retry
+fn heavy_work_busy(busy: HeavyWorkBusy, wanted: &str) -> Response {
+    let message = busy.message(wanted);
+    info!("{message}");
+    (
+        StatusCode::TOO_MANY_REQUESTS,
+        [("Retry-After", busy.retry_after.as_secs().to_string())],
+        Json(ApiResponse::<()>::error(ErrorCode::ServerBusy, message)),
+    )
+        .into_response()
+}
+
+async fn generate_composites(
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<ApiResponse<CompositeBatchReport>>, Response> {
+    let _permit = state
+        .heavy_work
+        .try_acquire("composite generation")
+        .map_err(|busy| heavy_work_busy(busy, "composite generation"))?;
+    let ml_trainer = match state.ml_trainer.lock() {
+        Ok(trainer) => trainer,
+        Err(_) => {
+            error!("Failed to acquire ML trainer lock");
+            return Ok(Json(ApiResponse::error(
+                ErrorCode::InternalError,
+                "Failed to access ML trainer".to_string(),
+            )));
+        }
+    };
+
+    match ml_trainer.generate_all_composites() {
+        Ok(report) => Ok(Json(ApiResponse::success(report))),
+        Err(e) => {
+            error!("Failed to generate composites: {}", e);
+            Ok(Json(ApiResponse::error(
+                ErrorCode::InternalError,
+                format!("Failed to generate composites: {e}"),
+            )))
+        }
+    }
+}
+
+/// Query parameters for the reference sheet
+#[derive(Deserialize)]
+struct ReferenceSheetQuery {
+    #[serde(default)]
+    format: SheetFormat,
+    /// Columns in the grid, `reference_sheet_columns` when unset
+    columns: Option<u32>,
+}
+
+/// A grid with one example image of each case type, for printing
+async fn get_reference_sheet(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<ReferenceSheetQuery>,
+) -> Response {
+    let error_response = |status: StatusCode, code: ErrorCode, message: String| {
+        (status, Json(ApiResponse::<()>::error(code, message))).into_response()
+    };
+    if query.format == SheetFormat::Pdf {
+        return error_response(
+            StatusCode::BAD_REQUEST,
+            ErrorCode::InvalidRequest,
+            "PDF reference sheets aren't supported yet, use format=jpg".to_string(),
+        );
+    }
+    let columns = query
+        .columns
+        .unwrap_or(state.settings.reference_sheet_columns);
+    if !(1..=MAX_SHEET_COLUMNS).contains(&columns) {
+        return error_response(
+            StatusCode::BAD_REQUEST,
+            ErrorCode::InvalidRequest,
+            format!("columns must be between 1 and {MAX_SHEET_COLUMNS}"),
+        );
+    }
+
+    let ml_trainer = state.ml_trainer.clone();
+    let image_directory = state.settings.image_directory.clone();
+    let entries = tokio::task::spawn_blocking(move || {
+        let trainer = ml_trainer
+            .lock()
+            .map_err(|_| OurError::App("Failed to access ML trainer".to_string()))?;
+        let entries = sheet_entries(
+            trainer.get_case_types(),
+            &trainer.training_shells()?,
+            &image_directory,
+        );
+        let key = sheet_key(&entries, columns)?;
+        Ok::<_, OurError>((entries, key))
+    })
+    .await;
+    let sheet = match entries {
+        Ok(Ok((entries, _))) if entries.is_empty() => {
+            return error_response(
+                StatusCode::NOT_FOUND,
+                ErrorCode::CaseTypeNotFound,
+                "There are no case types to put on the reference sheet".to_string(),
+            );
+        }
+        Ok(Ok((entries, key))) => match state.reference_sheet.get(&key) {
+            Some(jpeg) => Ok(Ok(jpeg)),
+            None => {
+                let permit = match state.heavy_work.try_acquire("reference sheet") {
+                    Ok(permit) => permit,
+                    Err(busy) => return heavy_work_busy(busy, "reference sheet"),
+                };
+                let cache = state.reference_sheet.clone();
+                let quality = state.settings.image_jpeg_quality;
+                tokio::task::spawn_blocking(move || {
+                    let _permit = permit;
+                    let jpeg = Arc::new(encode_jpeg(&render_sheet(&entries, columns)?, quality)?);
+                    cache.store(key, jpeg.clone());
+                    Ok(jpeg)
+                })
+                .await
+            }
+        },
+        Ok(Err(e)) => Ok(Err(e)),
+        Err(e) => Err(e),
+    };
+
+    match sheet {
+        Ok(Ok(jpeg)) => (
+            [
+                ("Content-Type", "image/jpeg".to_string()),
+                ("Content-Length", jpeg.len().to_string()),
+            ],
+            jpeg.as_ref().clone(),
+        )
+            .into_response(),
+        Ok(Err(e)) => {
+            error!("Failed to draw the reference sheet: {e}");
+            error_response(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                ErrorCode::InternalError,
+                format!("Failed to draw the reference sheet: {e}"),
+            )
+        }
+        Err(e) => {
+            error!("Reference sheet task failed: {e}");
+            error_response(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                ErrorCode::InternalError,
+                format!("Failed to draw the reference sheet: {e}"),
+            )
+        }
+    }
+}
+
+/// The report from the last case type reconciliation, run at startup
+async fn get_reconcile_report(
+    State(state): State<Arc<AppState>>,
+) -> Json<ApiResponse<Option<ReconcileReport>>> {
+    let ml_trainer = match state.ml_trainer.lock() {
+        Ok(trainer) => trainer,
+        Err(_) => {
+            error!("Failed to acquire ML trainer lock");
+            return Json(ApiResponse::error(
+                ErrorCode::InternalError,
+                "Failed to access ML trainer".to_string(),
+            ));
+        }
+    };
+
+    Json(ApiResponse::success(ml_trainer.last_reconcile().cloned()))
+}
+
+/// Reconcile the case type records with the image directories now
+async fn reconcile_case_types(
+    State(state): State<Arc<AppState>>,
+) -> Json<ApiResponse<ReconcileReport>> {
+    let mut ml_trainer = match state.ml_trainer.lock() {
+        Ok(trainer) => trainer,
+        Err(_) => {
+            error!("Failed to acquire ML trainer lock");
+            return Json(ApiResponse::error(
+                ErrorCode::InternalError,
+                "Failed to access ML trainer".to_string(),
+            ));
+        }
+    };
+
+    match ml_trainer.reconcile_case_types() {
+        Ok(report) => Json(ApiResponse::success(report)),
+        Err(e) => {
+            error!("Failed to reconcile case types: {}", e);
+            Json(ApiResponse::error(
+                ErrorCode::InternalError,
+                format!("Failed to reconcile case types: {e}"),
+            ))
+        }
+    }
+}
+
+async fn get_composite_layout(
+    State(state): State<Arc<AppState>>,
+) -> Json<ApiResponse<CompositeLayout>> {
+    let ml_trainer = match state.ml_trainer.lock() {
+        Ok(trainer) => trainer,
+        Err(_) => {
+            error!("Failed to acquire ML trainer lock");
+            return Json(ApiResponse::error(
+                ErrorCode::InternalError,
+                "Failed to access ML trainer".to_string(),
+            ));
+        }
+    };
+
+    match ml_trainer.composite_layout() {
+        Ok(layout) => Json(ApiResponse::success(layout)),
+        Err(e) => {
+            error!("Failed to load composite layout: {}", e);
+            Json(ApiResponse::error(
+                ErrorCode::InternalError,
+                format!("Failed to load composite layout: {e}"),
+            ))
+        }
+    }
+}
+
+/// Replace the composite layout; existing composites are redrawn on the next generation run
+async fn update_composite_layout(
+    State(state): State<Arc<AppState>>,
+    ExtractJson(layout): ExtractJson<CompositeLayout>,
+) -> (StatusCode, Json<ApiResponse<CompositeLayout>>) {
+    if let Err(e) = layout.validate() {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(ApiResponse::error(ErrorCode::InvalidRequest, e.to_string())),
+        );
+    }
+
+    let ml_trainer = match state.ml_trainer.lock() {
+        Ok(trainer) => trainer,
+        Err(_) => {
+            error!("Failed to acquire ML trainer lock");
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ApiResponse::error(
+                    ErrorCode::InternalError,
+                    "Failed to access ML trainer".to_string(),
+                )),
+            );
+        }
+    };
+
+    match ml_trainer.set_composite_layout(&layout) {
+        Ok(()) => (StatusCode::OK, Json(ApiResponse::success(layout))),
+        Err(e) => {
+            error!("Failed to save composite layout: {}", e);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ApiResponse::error(
+                    ErrorCode::InternalError,
+                    format!("Failed to save composite layout: {e}"),
+                )),
+            )
+        }
+    }
+}
+
+/// Progress towards every case type target, or why it couldn't be worked out
+fn shell_target_progress(state: &AppState) -> OurResult<Vec<TargetProgress>> {
+    state
+        .ml_trainer
+        .lock()
+        .map_err(|_| OurError::App("Failed to access ML trainer".to_string()))?
+        .target_progress()
+}
+
+/// Take the current progress as reached, so targets met before startup aren't announced
+fn sync_shell_targets(state: &AppState) {
+    match shell_target_progress(state) {
+        Ok(progress) => state.shell_targets.sync(&progress),
+        Err(e) => warn!("Failed to check shell targets: {e}"),
+    }
+}
+
+/// Announce the case types a change to the shells took across their target
+fn observe_shell_targets(state: &AppState) {
+    match shell_target_progress(state) {
+        Ok(progress) => {
+            for reached in state.shell_targets.observe(&progress) {
+                info!("{} ({} shells)", reached.message, reached.current);
+            }
+        }
+        Err(e) => warn!("Failed to check shell targets: {e}"),
+    }
+}
+
+/// Every case type with a target, least complete first
+async fn get_ml_progress(
+    State(state): State<Arc<AppState>>,
+) -> (StatusCode, Json<ApiResponse<Vec<TargetProgress>>>) {
+    match shell_target_progress(&state) {
+        Ok(progress) => (StatusCode::OK, Json(ApiResponse::success(progress))),
+        Err(e) => {
+            error!("Failed to get shell target progress: {e}");
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ApiResponse::error(
+                    ErrorCode::InternalError,
+                    format!("Failed to get shell target progress: {e}"),
+                )),
+            )
+        }
+    }
+}
+
+/// Query parameters for the work queue
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case")]
+struct WorkQueueQuery {
+    /// Most items to list, `DEFAULT_WORK_QUEUE_LIMIT` when unset
+    limit: Option<usize>,
+}
+
+/// Untagged sessions, incomplete shells and case types under target, most pressing first
+async fn get_work_queue(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<WorkQueueQuery>,
+) -> (StatusCode, Json<ApiResponse<WorkQueue>>) {
+    let case_types = match state.ml_trainer.lock() {
+        Ok(trainer) => trainer.get_case_types().clone(),
+        Err(_) => {
+            error!("Failed to access ML trainer for the work queue");
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ApiResponse::error(
+                    ErrorCode::InternalError,
+                    "Failed to access ML trainer".to_string(),
+                )),
+            );
+        }
+    };
+    let summaries = match state
+        .shell_data_manager
+        .search_shells(&ShellFilter::default())
+    {
+        Ok(summaries) => summaries,
+        Err(e) => {
+            error!("Failed to list shells for the work queue: {e}");
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ApiResponse::localized(
+                    ErrorCode::InternalError,
+                    Message::ShellListFailed {
+                        error: e.to_string(),
+                    },
+                )),
+            );
+        }
+    };
+    let items = work_queue::work_items(
+        &summaries,
+        &case_types,
+        &state.settings.training_excluded_flags,
+        chrono::Utc::now(),
+    );
+    let queue = WorkQueue::new(items, query.limit.unwrap_or(DEFAULT_WORK_QUEUE_LIMIT));
+    (StatusCode::OK, Json(ApiResponse::success(queue)))
+}
+
+/// Mean and spread of each case type's weights and lengths, and the shells that stand out
+async fn get_measurement_report(
+    State(state): State<Arc<AppState>>,
+) -> (StatusCode, Json<ApiResponse<Vec<CaseTypeMeasurements>>>) {
+    match state
+        .shell_data_manager
+        .search_shells(&ShellFilter::default())
+    {
+        Ok(summaries) => (
+            StatusCode::OK,
+            Json(ApiResponse::success(measurements::measurement_report(
+                &summaries,
+                &state.settings.measurement_ranges,
+            ))),
+        ),
+        Err(e) => {
+            error!("Failed to list shells for the measurement report: {e}");
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ApiResponse::localized(
+                    ErrorCode::InternalError,
+                    Message::ShellListFailed {
+                        error: e.to_string(),
+                    },
+                )),
+            )
+        }
+    }
+}
+
+/// Push case types reaching their target as server-sent `target_reached` events
+async fn stream_target_events(
+    State(state): State<Arc<AppState>>,
+) -> Sse<impl futures_util::Stream<Item = Result<SseEvent, Infallible>>> {
+    let mut receiver = state.shell_targets.subscribe();
+    let shutdown = state.tasks.shutdown_token();
+
+    let stream = async_stream::stream! {
+        loop {
+            let received = tokio::select! {
+                received = recv_skipping_lag(&mut receiver, "target_events", &state.broadcast_lag) => received,
+                _ = shutdown.cancelled() => break,
+            };
+            let Some(reached) = received else {
+                break;
+            };
+            match SseEvent::default().event("target_reached").json_data(&reached) {
+                Ok(event) => yield Ok(event),
+                Err(e) => warn!("Failed to encode target reached event: {e}"),
+            }
+        }
+    };
+
+    Sse::new(stream).keep_alive(KeepAlive::default())
+}
+
+async fn list_case_types(
+    State(state): State<Arc<AppState>>,
+) -> Json<ApiResponse<Vec<HashMap<String, serde_json::Value>>>> {
+    let ml_trainer = match state.ml_trainer.lock() {
+        Ok(trainer) => trainer,
+        Err(_) => {
+            error!("Failed to acquire ML trainer lock");
+            return Json(ApiResponse::error(
+                ErrorCode::InternalError,
+                "Failed to access ML trainer".to_string(),
+            ));
+        }
+    };
+
+    // Shown next to each case type's counts, so the types that need more data stand out
+    let active_metrics = active_model_metrics(&ml_trainer);
+
+    match ml_trainer.get_training_summary() {
+        Ok(summary) => {
+            let case_types: Vec<HashMap<String, serde_json::Value>> = summary
+                .into_iter()
+                .map(|(name, summary_data)| {
+                    let mut data = HashMap::new();
+                    data.insert(
+                        "model_recall".to_string(),
+                        serde_json::json!(
+                            active_metrics
+                                .as_ref()
+                                .and_then(|metrics| metrics.recall(&name))
+                        ),
+                    );
+                    data.insert("name".to_string(), serde_json::Value::String(name));
+                    data.insert(
+                        "designation".to_string(),
+                        serde_json::Value::String(summary_data.designation),
+                    );
+                    data.insert(
+                        "brand".to_string(),
+                        summary_data
+                            .brand
+                            .map(serde_json::Value::String)
+                            .unwrap_or(serde_json::Value::Null),
+                    );
+                    data.insert(
+                        "reference_count".to_string(),
+                        serde_json::Value::Number(serde_json::Number::from(
+                            summary_data.reference_count,
+                        )),
+                    );
+                    data.insert(
+                        "training_count".to_string(),
+                        serde_json::Value::Number(serde_json::Number::from(
+                            summary_data.training_count,
+                        )),
+                    );
+                    data.insert(
+                        "shell_count".to_string(),
+                        serde_json::Value::Number(serde_json::Number::from(
+                            summary_data.shell_count,
+                        )),
+                    );
+                    data.insert(
+                        "ready_for_training".to_string(),
+                        serde_json::Value::Bool(summary_data.ready_for_training),
+                    );
+                    data.insert(
+                        "updated_at".to_string(),
+                        serde_json::Value::String(summary_data.updated_at.to_rfc3339()),
+                    );
+                    data.insert(
+                        "required_views".to_string(),
+                        serde_json::json!(summary_data.required_views),
+                    );
+                    data.insert(
+                        "incomplete_shells".to_string(),
+                        serde_json::json!(summary_data.incomplete_shells),
+                    );
+                    data.insert("target".to_string(), serde_json::json!(summary_data.target));
+                    data
+                })
+                .collect();
+
+            Json(ApiResponse::success(case_types))
+        }
+        Err(e) => {
+            error!("Failed to get training summary: {}", e);
+            Json(ApiResponse::error(
+                ErrorCode::InternalError,
+                format!("Failed to get case types: {e}"),
+            ))
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case")]
+struct CreateCaseTypeRequest {
+    name: String,
+    designation: Option<String>,
+    brand: Option<String>,
+}
+
+/// Changes to a case type; fields left out are kept
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+struct UpdateCaseTypeRequest {
+    required_views: Option<Vec<ViewType>>,
+    /// A new target, or null to clear it
+    #[serde(default, with = "serde_with::rust::double_option")]
+    target_shell_count: Option<Option<u32>>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "snake_case")]
+struct SelectCamerasRequest {
+    camera_ids: Vec<CameraId>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case")]
+struct BrightnessRequest {
+    brightness: i64,
+    /// Whether to wait for a running capture session or fail straight away
+    #[serde(default)]
+    when_busy: BusyPolicy,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+struct SaveShellRequest {
+    session_id: String,
+    brand: String,
+    shell_type: String,
+    #[serde(default = "default_include")]
+    include: bool,
+    image_filenames: Vec<String>,
+    /// View type selected on the tagging page for each image filename
+    #[serde(default)]
+    view_types: HashMap<String, ViewType>,
+    #[serde(default)]
+    notes: Option<String>,
+    #[serde(default)]
+    flags: Vec<ShellFlag>,
+    /// Weight of the case; left out keeps any weight already recorded
+    #[serde(default)]
+    weight_grains: Option<f32>,
+    /// Length of the case; left out keeps any length already recorded
+    #[serde(default)]
+    length_mm: Option<f32>,
+    /// Accept a shell_type that isn't a supported case type, adding it to the list
+    #[serde(default)]
+    allow_new_type: bool,
+    /// Revision of the shell the client loaded; 0 for a shell that hasn't been saved
+    #[serde(default)]
+    revision: u64,
+    /// Who is saving, checked against the session's tagging claim
+    #[serde(default)]
+    claimant: Option<String>,
+    /// Save even if someone else has claimed the session
+    #[serde(default)]
+    force: bool,
+}
+
+/// Changes to a saved shell; fields left out are kept
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+struct UpdateShellRequest {
+    /// Revision of the shell the client loaded
+    revision: u64,
+    brand: Option<String>,
+    shell_type: Option<String>,
+    include: Option<bool>,
+    notes: Option<String>,
+    flags: Option<Vec<ShellFlag>>,
+    /// A new weight, or null to clear it
+    #[serde(default, with = "serde_with::rust::double_option")]
+    weight_grains: Option<Option<f32>>,
+    /// A new length, or null to clear it
+    #[serde(default, with = "serde_with::rust::double_option")]
+    length_mm: Option<Option<f32>>,
+    /// New view type for each captured image filename
+    #[serde(default)]
+    view_types: HashMap<String, ViewType>,
+    /// Accept a shell_type that isn't a supported case type, adding it to the list
+    #[serde(default)]
+    allow_new_type: bool,
+    /// Who is saving, checked against the session's tagging claim
+    #[serde(default)]
+    claimant: Option<String>,
+    /// Save even if someone else has claimed the session
+    #[serde(default)]
+    force: bool,
+}
+
+impl UpdateShellRequest {
+    /// Check the request before the shell is loaded, naming every invalid field
+    fn validate(&self) -> Result<(), ApiError> {
+        let mut errors = BTreeMap::new();
+        if self
+            .brand
+            .as_ref()
+            .is_some_and(|brand| brand.trim().is_empty())
+        {
+            errors.insert("brand".to_string(), "must not be empty".to_string());
+        }
+        if self
+            .shell_type
+            .as_ref()
+            .is_some_and(|shell_type| shell_type.trim().is_empty())
+        {
+            errors.insert("shell_type".to_string(), "must not be empty".to_string());
+        }
+        if let Some(error) = self.notes.as_deref().and_then(notes_error) {
+            errors.insert("notes".to_string(), error);
+        }
+        measurement_errors(
+            &mut errors,
+            self.weight_grains.flatten(),
+            self.length_mm.flatten(),
+        );
+        shell_validation(errors)
+    }
+}
+
+/// Note the weight and length that aren't plausible readings
+fn measurement_errors(
+    errors: &mut BTreeMap<String, String>,
+    weight_grains: Option<f32>,
+    length_mm: Option<f32>,
+) {
+    for (field, value) in [("weight_grains", weight_grains), ("length_mm", length_mm)] {
+        if let Some(error) = value.and_then(measurement_error) {
+            errors.insert(field.to_string(), error);
+        }
+    }
+}
+
+/// The outcome of a shell save
+#[derive(Serialize)]
+#[serde(rename_all = "snake_case")]
+struct SavedShellData {
+    session_id: String,
+    message: String,
+    /// Revision now on disk, to send with the next save
+    revision: u64,
+    /// The server's copy, when the save was refused because it was stale
+    #[serde(skip_serializing_if = "Option::is_none")]
+    current: Option<Shell>,
+    /// Someone else's claim on the session, when the save was refused for it
+    #[serde(skip_serializing_if = "Option::is_none")]
+    claimed_by: Option<SessionClaim>,
+    /// Measurements outside the range expected of the shell's designation
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    warnings: Vec<MeasurementWarning>,
+}
+
+/// A validation failure for the invalid fields of a shell save, if there are any
+fn shell_validation(errors: BTreeMap<String, String>) -> Result<(), ApiError> {
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(ApiError::validation("Invalid shell", errors))
+    }
+}
+
+fn default_include() -> bool {
+    true
+}
+
+impl SaveShellRequest {
+    /// Check the request before anything is written to disk, naming every invalid field
+    fn validate(&self) -> Result<(), ApiError> {
+        let mut errors = BTreeMap::new();
+        if uuid::Uuid::parse_str(&self.session_id).is_err() {
+            errors.insert(
+                "session_id".to_string(),
+                format!("{:?} is not a session ID", self.session_id),
+            );
+        }
+        if let Some(Err(OurError::InvalidName { reason, .. })) = self
+            .image_filenames
+            .iter()
+            .map(|filename| SafeName::file_name("image_filenames", filename))
+            .find(Result::is_err)
+        {
+            errors.insert("image_filenames".to_string(), reason);
+        }
+        if self.brand.trim().is_empty() {
+            errors.insert("brand".to_string(), "must not be empty".to_string());
+        }
+        if self.shell_type.trim().is_empty() {
+            errors.insert("shell_type".to_string(), "must not be empty".to_string());
+        }
+        if let Some(filename) = self
+            .view_types
+            .keys()
+            .find(|filename| !self.image_filenames.contains(filename))
+        {
+            errors.insert(
+                "view_types".to_string(),
+                format!("references unknown image {filename}"),
+            );
+        }
+        if let Some(error) = self.notes.as_deref().and_then(notes_error) {
+            errors.insert("notes".to_string(), error);
+        }
+        measurement_errors(&mut errors, self.weight_grains, self.length_mm);
+        shell_validation(errors)
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "snake_case")]
+struct BrightnessResponse {
+    brightness: i64,
+}
+
+async fn create_case_type(
+    State(state): State<Arc<AppState>>,
+    Json(payload): Json<CreateCaseTypeRequest>,
+) -> (StatusCode, Json<ApiResponse<CaseType>>) {
+    let Some(designation) = payload.designation.filter(|d| !d.trim().is_empty()) else {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(ApiResponse::error(
+                ErrorCode::InvalidRequest,
+                "A designation is required".to_string(),
+            )),
+        );
+    };
+    if let Err(e) = SafeName::new("name", &payload.name) {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(ApiResponse::error(ErrorCode::InvalidRequest, e.to_string())),
+        );
+    }
+
+    let mut ml_trainer = match state.ml_trainer.lock() {
+        Ok(trainer) => trainer,
+        Err(_) => {
+            error!("Failed to acquire ML trainer lock");
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ApiResponse::error(
+                    ErrorCode::InternalError,
+                    "Failed to access ML trainer".to_string(),
+                )),
+            );
+        }
+    };
+
+    if ml_trainer.get_case_type(&payload.name).is_some() {
+        return (
+            StatusCode::CONFLICT,
+            Json(ApiResponse::error(
+                ErrorCode::CaseTypeExists,
+                format!("Case type '{}' already exists", payload.name),
+            )),
+        );
+    }
+
+    match ml_trainer.add_case_type(payload.name, designation, payload.brand) {
+        Ok(case_type) => (StatusCode::CREATED, Json(ApiResponse::success(case_type))),
+        Err(e) => {
+            error!("Failed to create case type: {}", e);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ApiResponse::error(
+                    ErrorCode::InternalError,
+                    format!("Failed to create case type: {e}"),
+                )),
+            )
+        }
+    }
+}
+
+async fn update_case_type(
+    Path(name): Path<String>,
+    State(state): State<Arc<AppState>>,
+    Json(payload): Json<UpdateCaseTypeRequest>,
+) -> (StatusCode, Json<ApiResponse<CaseType>>) {
+    let mut ml_trainer = match state.ml_trainer.lock() {
+        Ok(trainer) => trainer,
+        Err(_) => {
+            error!("Failed to acquire ML trainer lock");
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ApiResponse::error(
+                    ErrorCode::InternalError,
+                    "Failed to access ML trainer".to_string(),
+                )),
+            );
+        }
+    };
+
+    let Some(case_type) = ml_trainer.get_case_type(&name).cloned() else {
+        return (
+            StatusCode::NOT_FOUND,
+            Json(ApiResponse::error(
+                ErrorCode::CaseTypeNotFound,
+                format!("Case type '{name}' not found"),
+            )),
+        );
+    };
+    if payload
+        .required_views
+        .as_ref()
+        .is_some_and(|views| views.is_empty() || views.contains(&ViewType::Unknown))
+    {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(ApiResponse::error(
+                ErrorCode::InvalidRequest,
+                "required_views must list at least one of side, tail or mouth".to_string(),
+            )),
+        );
+    }
+
+    let mut updated = Ok(case_type);
+    if let Some(required_views) = payload.required_views {
+        updated = ml_trainer.set_required_views(&name, required_views);
+    }
+    if updated.is_ok()
+        && let Some(target_shell_count) = payload.target_shell_count
+    {
+        updated = ml_trainer.set_target_shell_count(&name, target_shell_count);
+        // A new target is a new starting point, not a crossing
+        match ml_trainer.target_progress() {
+            Ok(progress) => state.shell_targets.sync(&progress),
+            Err(e) => warn!("Failed to check shell targets: {e}"),
+        }
+    }
+
+    match updated {
+        Ok(case_type) => (StatusCode::OK, Json(ApiResponse::success(case_type))),
+        Err(e) => {
+            error!("Failed to update case type {name}: {e}");
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ApiResponse::error(
+                    ErrorCode::InternalError,
+                    format!("Failed to update case type: {e}"),
+                )),
+            )
+        }
+    }
+}
+
+/// Which case type image collection an upload is destined for
+#[derive(Clone, Copy)]
+enum CaseTypeImageKind {
+    Reference,
+    Training,
+}
+
+async fn upload_reference_images(
+    Path(name): Path<String>,
+    State(state): State<Arc<AppState>>,
+    multipart: Multipart,
+) -> (StatusCode, Json<ApiResponse<Vec<String>>>) {
+    upload_case_type_images(&state, &name, multipart, CaseTypeImageKind::Reference).await
+}
+
+async fn upload_training_images(
+    Path(name): Path<String>,
+    State(state): State<Arc<AppState>>,
+    multipart: Multipart,
+) -> (StatusCode, Json<ApiResponse<Vec<String>>>) {
+    upload_case_type_images(&state, &name, multipart, CaseTypeImageKind::Training).await
+}
+
+/// Store every file in a multipart upload against a case type, converting to JPEG as needed
+///
+/// Files are spooled into the case type's directory as they arrive, so an
+/// upload never has to fit in memory.
+async fn upload_case_type_images(
+    state: &Arc<AppState>,
+    case_type_name: &str,
+    mut multipart: Multipart,
+    kind: CaseTypeImageKind,
+) -> (StatusCode, Json<ApiResponse<Vec<String>>>) {
+    let limit_bytes = state.settings.max_upload_bytes();
+    let target_dir = {
+        let ml_trainer = match state.ml_trainer.lock() {
+            Ok(trainer) => trainer,
+            Err(_) => {
+                error!("Failed to acquire ML trainer lock");
+                return (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    Json(ApiResponse::error(
+                        ErrorCode::InternalError,
+                        "Failed to access ML trainer".to_string(),
+                    )),
+                );
+            }
+        };
+        if ml_trainer.get_case_type(case_type_name).is_none() {
+            return (
+                StatusCode::NOT_FOUND,
+                Json(ApiResponse::error(
+                    ErrorCode::CaseTypeNotFound,
+                    format!("Case type '{case_type_name}' not found"),
+                )),
+            );
+        }
+        match kind {
+            CaseTypeImageKind::Reference => ml_trainer.reference_image_dir(case_type_name),
+            CaseTypeImageKind::Training => ml_trainer.training_image_dir(case_type_name),
+        }
+    };
+    let target_dir = match target_dir {
+        Ok(target_dir) => target_dir,
+        Err(e) => {
+            return (
+                StatusCode::BAD_REQUEST,
+                Json(ApiResponse::error(ErrorCode::InvalidRequest, e.to_string())),
+            );
+        }
+    };
+
+    // Spool the whole upload before taking the ML trainer lock
+    let mut files = Vec::new();
+    loop {
+        let mut field = match multipart.next_field().await {
+            Ok(Some(field)) => field,
+            Ok(None) => break,
+            Err(e) => return multipart_rejection(e, limit_bytes),
+        };
+        let Some(file_name) = field.file_name().map(|name| name.to_string()) else {
+            continue;
+        };
+        let upload = TempUpload::new(&target_dir);
+        match upload::spool_field(&mut field, upload.path()).await {
+            Ok(_) => files.push((file_name, upload)),
+            Err(SpoolError::TooLarge) => {
+                warn!("Upload of {file_name} for {case_type_name} is over the limit");
+                return payload_too_large(limit_bytes);
+            }
+            Err(e) => {
+                error!("Failed to spool uploaded file {file_name}: {e}");
+                let (status, code) = match e {
+                    SpoolError::Write(_) => {
+                        (StatusCode::INTERNAL_SERVER_ERROR, ErrorCode::InternalError)
+                    }
+                    _ => (StatusCode::BAD_REQUEST, ErrorCode::InvalidRequest),
+                };
+                return (
+                    status,
+                    Json(ApiResponse::error(code, format!("{file_name}: {e}"))),
+                );
+            }
+        }
+    }
+
+    if files.is_empty() {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(ApiResponse::error(
+                ErrorCode::InvalidRequest,
+                "No image files were uploaded".to_string(),
+            )),
+        );
+    }
+
+    let mut ml_trainer = match state.ml_trainer.lock() {
+        Ok(trainer) => trainer,
+        Err(_) => {
+            error!("Failed to acquire ML trainer lock");
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ApiResponse::error(
+                    ErrorCode::InternalError,
+                    "Failed to access ML trainer".to_string(),
+                )),
+            );
+        }
+    };
+
+    let mut stored = Vec::new();
+    for (file_name, upload) in files {
+        let result = match kind {
+            CaseTypeImageKind::Reference => {
+                ml_trainer.add_reference_image_upload(case_type_name, &file_name, upload.path())
+            }
+            CaseTypeImageKind::Training => {
+                ml_trainer.add_training_image_upload(case_type_name, &file_name, upload.path())
+            }
+        };
+        match result {
+            Ok(path) => {
+                info!("Stored uploaded image {file_name} for {case_type_name}");
+                stored.push(path.display().to_string());
+            }
+            Err(e) => {
+                error!("Failed to store uploaded image {file_name}: {e}");
+                return (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    Json(ApiResponse::error(
+                        ErrorCode::InternalError,
+                        format!("Failed to store {file_name}: {e}"),
+                    )),
+                );
+            }
+        }
+    }
+
+    (StatusCode::OK, Json(ApiResponse::success(stored)))
+}
+
+async fn train_model(
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<ApiResponse<ModelMetadata>>, Response> {
+    let _permit = state
+        .heavy_work
+        .try_acquire("model training")
+        .map_err(|busy| heavy_work_busy(busy, "model training"))?;
+    let mut ml_trainer = match state.ml_trainer.lock() {
+        Ok(trainer) => trainer,
+        Err(_) => {
+            error!("Failed to acquire ML trainer lock");
+            return Ok(Json(ApiResponse::error(
+                ErrorCode::InternalError,
+                "Failed to access ML trainer".to_string(),
+            )));
+        }
+    };
+
+    match ml_trainer.train_model(None) {
+        Ok(metadata) => Ok(Json(ApiResponse::success(metadata))),
+        Err(e) => {
+            error!("Failed to train model: {}", e);
+            Ok(Json(ApiResponse::error(
+                ErrorCode::InternalError,
+                format!("Failed to train model: {e}"),
+            )))
+        }
+    }
+}
+
+/// Which case types a training job should train, all of them if none are given
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+struct StartTrainingJobRequest {
+    #[serde(default)]
+    case_types: Option<Vec<String>>,
+}
+
+/// Snapshot the training data and train a model from it in the background
+async fn start_training_job(
+    State(state): State<Arc<AppState>>,
+    Json(request): Json<StartTrainingJobRequest>,
+) -> (StatusCode, Json<ApiResponse<TrainingJob>>) {
+    let run = {
+        let mut ml_trainer = match state.ml_trainer.lock() {
+            Ok(trainer) => trainer,
+            Err(_) => {
+                error!("Failed to acquire ML trainer lock");
+                return (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    Json(ApiResponse::error(
+                        ErrorCode::InternalError,
+                        "Failed to access ML trainer".to_string(),
+                    )),
+                );
+            }
+        };
+        if ml_trainer.training_runs().is_running() {
+            return (
+                StatusCode::CONFLICT,
+                Json(ApiResponse::error(
+                    ErrorCode::MachineBusy,
+                    "A training run is already in progress".to_string(),
+                )),
+            );
+        }
+        match ml_trainer.start_training(request.case_types) {
+            Ok(run) => run,
+            Err(e @ OurError::DatasetTooSmall(_)) => {
+                return (
+                    StatusCode::BAD_REQUEST,
+                    Json(ApiResponse::error(
+                        ErrorCode::DatasetTooSmall,
+                        e.to_string(),
+                    )),
+                );
+            }
+            Err(e) => {
+                error!("Failed to start training: {}", e);
+                return (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    Json(ApiResponse::error(
+                        ErrorCode::InternalError,
+                        format!("Failed to start training: {e}"),
+                    )),
+                );
+            }
+        }
+    };
+
+    let job = state.training_jobs.queue(run.case_types.clone());
+    let jobs = state.training_jobs.clone();
+    let ml_trainer = state.ml_trainer.clone();
+    let job_id = job.id.clone();
+    let heavy_work = state.heavy_work.clone();
+    state.tasks.spawn_job("model training", async move {
+        let permit = match heavy_work.try_acquire("model training") {
+            Ok(permit) => Ok(permit),
+            Err(busy) => {
+                info!(
+                    "Training job {job_id} is waiting for {} to finish",
+                    busy.running.join(", ")
+                );
+                jobs.progress(&job_id, TrainingPhase::WaitingForResources, 0);
+                heavy_work.acquire("model training").await
+            }
+        };
+        let _permit = match permit {
+            Ok(permit) => permit,
+            Err(e) => {
+                error!("Training job {job_id} couldn't start: {e}");
+                jobs.fail(&job_id, e.to_string());
+                return;
+            }
+        };
+        jobs.progress(&job_id, TrainingPhase::Training, 10);
+        let trained = tokio::task::spawn_blocking(move || match ml_trainer.lock() {
+            Ok(trainer) => trainer.finish_training(run).map(|model| {
+                let metadata_path = trainer.metadata_path(&model.name);
+                let worst_confused = worst_confused_pairs(&trainer, &model.name);
+                (model, metadata_path, worst_confused)
+            }),
+            Err(_) => Err(OurError::App("ML trainer lock poisoned".to_string())),
+        })
+        .await;
+        match trained {
+            Ok(Ok((model, metadata_path, worst_confused))) => {
+                jobs.complete(&job_id, model, metadata_path, worst_confused)
+            }
+            Ok(Err(e)) => {
+                error!("Training job {job_id} failed: {e}");
+                jobs.fail(&job_id, e.to_string());
+            }
+            Err(e) => {
+                error!("Training job {job_id} task failed: {e}");
+                jobs.fail(&job_id, format!("Training task failed: {e}"));
+            }
+        }
+    });
+
+    (StatusCode::ACCEPTED, Json(ApiResponse::success(job)))
+}
+
+/// The validation metrics of the active model, when it has any
+fn active_model_metrics(trainer: &MLTrainer) -> Option<ModelMetrics> {
+    let model = match trainer.active_model() {
+        Ok(model) => model?,
+        Err(e) => {
+            warn!("Failed to find the active model: {e}");
+            return None;
+        }
+    };
+    let metrics = SafeName::new("name", &model.name).and_then(|name| trainer.model_metrics(&name));
+    match metrics {
+        Ok(metrics) => metrics,
+        Err(e) => {
+            warn!("Failed to read metrics of model {}: {e}", model.name);
+            None
+        }
+    }
+}
+
+/// The case types a freshly trained model mixed up most, for the job's summary
+fn worst_confused_pairs(trainer: &MLTrainer, model_name: &str) -> Vec<ConfusedPair> {
+    let metrics = SafeName::new("name", model_name).and_then(|name| trainer.model_metrics(&name));
+    match metrics {
+        Ok(Some(metrics)) => metrics.worst_confused(WORST_CONFUSED_PAIRS),
+        Ok(None) => Vec::new(),
+        Err(e) => {
+            warn!("Failed to read metrics of model {model_name}: {e}");
+            Vec::new()
+        }
+    }
+}
+
+/// The validation metrics of a trained model
+async fn get_model_metrics(
+    State(state): State<Arc<AppState>>,
+    Path(name): Path<String>,
+) -> (StatusCode, Json<ApiResponse<ModelMetrics>>) {
+    let name = match SafeName::new("name", &name) {
+        Ok(name) => name,
+        Err(e) => {
+            return (
+                StatusCode::BAD_REQUEST,
+                Json(ApiResponse::error(ErrorCode::InvalidRequest, e.to_string())),
+            );
+        }
+    };
+    let ml_trainer = match state.ml_trainer.lock() {
+        Ok(trainer) => trainer,
+        Err(_) => {
+            error!("Failed to acquire ML trainer lock");
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ApiResponse::error(
+                    ErrorCode::InternalError,
+                    "Failed to access ML trainer".to_string(),
+                )),
+            );
+        }
+    };
+    match ml_trainer.model_metrics(&name) {
+        Ok(Some(metrics)) => (StatusCode::OK, Json(ApiResponse::success(metrics))),
+        Ok(None) => {
+            let message = if ml_trainer.has_model(&name) {
+                format!("Model {name} was trained before metrics were recorded")
+            } else {
+                format!("Model {name} not found")
+            };
+            (
+                StatusCode::NOT_FOUND,
+                Json(ApiResponse::error(ErrorCode::ModelNotFound, message)),
+            )
+        }
+        Err(e) => {
+            error!("Failed to read metrics of model {name}: {e}");
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ApiResponse::error(
+                    ErrorCode::InternalError,
+                    format!("Failed to read model metrics: {e}"),
+                )),
+            )
+        }
+    }
+}
+
+async fn get_training_job(
+    State(state): State<Arc<AppState>>,
+    Path(job_id): Path<String>,
+) -> (StatusCode, Json<ApiResponse<TrainingJob>>) {
+    match state.training_jobs.get(&job_id) {
+        Some(job) => (StatusCode::OK, Json(ApiResponse::success(job))),
+        None => (
+            StatusCode::NOT_FOUND,
+            Json(ApiResponse::error(
+                ErrorCode::TrainingJobNotFound,
+                format!("Training job {job_id} not found"),
+            )),
+        ),
+    }
+}
+
+async fn get_config(State(state): State<Arc<AppState>>) -> Json<ConfigData> {
+    // Includes changes the config writer hasn't written to disk yet
+    let user_config = current_user_config(&state).await;
+    let config_data = ConfigData {
+        auto_start_cameras: user_config.auto_start_esp32_cameras,
+        auto_detect_cameras: user_config.auto_detect_cameras,
+        camera_orientations: Some(
+            user_config
+                .camera_configs
+                .iter()
+                .map(|(camera_id, config)| (camera_id.clone(), config.orientation()))
+                .collect(),
+        ),
+        esphome_hostname: user_config.esphome_hostname,
+        network_camera_hostnames: user_config.network_camera_hostnames,
+        directories: Some(DataDirectories::from_settings(&state.settings)),
+    };
+    Json(config_data)
+}
+
+/// Every configurable field with its type, default and constraints
+async fn get_config_schema() -> Json<ApiResponse<config_schema::ConfigSchema>> {
+    Json(ApiResponse::success(config_schema::config_schema()))
+}
+
+/// Check a candidate config without saving it
+async fn validate_config(
+    Json(candidate): Json<serde_json::Value>,
+) -> Json<ApiResponse<ConfigValidation>> {
+    Json(ApiResponse::success(ConfigValidation::check(&candidate)))
+}
+
+async fn save_config(
+    State(state): State<Arc<AppState>>,
+    Json(config): Json<ConfigData>,
+) -> (StatusCode, Json<ApiResponse<()>>) {
+    let validation = ConfigValidation::check(&serde_json::to_value(&config).unwrap_or_default());
+    if !validation.valid {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(ApiResponse::failure(ApiError::validation(
+                &Message::InvalidConfiguration.render(),
+                validation.errors,
+            ))),
+        );
+    }
+
+    info!(
+        "Config save requested: auto_start={}, auto_detect={}, esphome={}, cameras={:?}",
+        config.auto_start_cameras,
+        config.auto_detect_cameras,
+        config.esphome_hostname,
+        config.network_camera_hostnames
+    );
+
+    // Load the config as it was to check for changes
+    let previous_config = current_user_config(&state).await;
+
+    // Check if ESPHome hostname has changed
+    let hostname_changed = previous_config.esphome_hostname != config.esphome_hostname;
+    let camera_hostnames_changed =
+        previous_config.network_camera_hostnames != config.network_camera_hostnames;
+
+    // Update controller monitor configuration if hostname changed
+    if hostname_changed {
+        // Create updated settings for the controller
+        let mut new_settings = state.settings.clone();
+        new_settings.esphome_hostname = config.esphome_hostname.clone();
+        new_settings.network_camera_hostnames = config.network_camera_hostnames.clone();
+
+        match state.controller.update_config(new_settings).await {
+            Ok(()) => {
+                info!("Controller monitor configuration updated successfully");
+            }
+            Err(e) => {
+                error!("Failed to update controller monitor configuration: {}", e);
+                return (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    Json(ApiResponse::localized(
+                        ErrorCode::ControllerError,
+                        Message::ControllerConfigFailed {
+                            error: e.to_string(),
+                        },
+                    )),
+                );
+            }
+        }
+    }
+
+    // TODO: Also update camera manager configuration if camera hostnames changed
+    if camera_hostnames_changed {
+        info!("Camera hostname configuration changed - camera manager restart needed");
+    }
+
+    // Save changes to persistent user config file, waiting for the write so failures are reported
+    let saved = state
+        .config_writer
+        .update(move |user_config| {
+            user_config.esphome_hostname = config.esphome_hostname;
+            user_config.network_camera_hostnames = config.network_camera_hostnames;
+            user_config.auto_detect_cameras = config.auto_detect_cameras;
+            user_config.auto_start_esp32_cameras = config.auto_start_cameras;
+            for (camera_id, orientation) in config.camera_orientations.unwrap_or_default() {
+                let camera_config = user_config.camera_configs.entry(camera_id).or_default();
+                camera_config.rotation = orientation.rotation;
+                camera_config.mirror = orientation.mirror;
+            }
+        })
+        .await;
+
+    match saved {
+        Ok(()) => {
+            info!("Configuration saved to user config file successfully");
+        }
+        Err(e) => {
+            error!("Failed to save configuration to file: {}", e);
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ApiResponse::localized(
+                    ErrorCode::InternalError,
+                    Message::ConfigSaveFailed {
+                        error: e.to_string(),
+                    },
+                )),
+            );
+        }
+    }
+
+    apply_camera_orientations(&state, &current_user_config(&state).await).await;
+
+    info!("Configuration updated successfully");
+
+    (StatusCode::OK, Json(ApiResponse::success(())))
+}
+
+/// What the setup page shows before anything is scanned
+#[derive(Serialize)]
+#[serde(rename_all = "snake_case")]
+struct SetupStatusData {
+    /// A user config file has been written
+    config_exists: bool,
+    config_path: PathBuf,
+    /// The configured controller as of its last health check
+    controller: ControllerProbe,
+    /// Cameras detected so far
+    cameras: Vec<CameraInfo>,
+}
+
+async fn setup_status(State(state): State<Arc<AppState>>) -> Json<ApiResponse<SetupStatusData>> {
+    let config_path = state.settings.sources.user_config_path.clone();
+    let controller = ControllerProbe::from_status(&state.controller.get_status().await);
+    Json(ApiResponse::success(SetupStatusData {
+        config_exists: config_path.exists(),
+        config_path,
+        controller,
+        cameras: collect_cameras(&state).await,
+    }))
+}
+
+/// Options for `POST /api/setup/scan`, all optional
+#[derive(Deserialize, Default)]
+#[serde(rename_all = "snake_case", default, deny_unknown_fields)]
+struct SetupScanRequest {
+    /// Controller to check instead of the configured one
+    controller_hostname: Option<String>,
+    /// Leave the ESPHome camera hostnames unprobed
+    skip_network_cameras: bool,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "snake_case")]
+struct SetupScanData {
+    controller: ControllerProbe,
+    cameras: Vec<CameraInfo>,
+    /// Detection steps that failed
+    errors: Vec<String>,
+}
+
+/// Detect cameras and check the controller, the same way the dashboard and health check do
+async fn setup_scan(
+    State(state): State<Arc<AppState>>,
+    payload: Option<ExtractJson<SetupScanRequest>>,
+) -> (StatusCode, Json<ApiResponse<SetupScanData>>) {
+    let request = payload
+        .map(|ExtractJson(request)| request)
+        .unwrap_or_default();
+    let hostname = match request.controller_hostname {
+        Some(hostname) => hostname.trim().to_string(),
+        None => current_user_config(&state).await.esphome_hostname,
+    };
+    if let Some(Err(e)) = config_schema::settings_field("esphome_hostname")
+        .map(|field| field.validate(&serde_json::json!(hostname)))
+    {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(ApiResponse::failure(ApiError::validation(
+                "Invalid setup",
+                BTreeMap::from([("controller_hostname".to_string(), e)]),
+            ))),
+        );
+    }
+
+    let mut errors = Vec::new();
+    if let Err(e) = state.usb_camera_manager.detect_cameras(false).await {
+        errors.push(format!("USB camera detection failed: {e}"));
+    }
+    if !request.skip_network_cameras
+        && let Err(e) = state.camera_manager.detect_cameras().await
+    {
+        errors.push(format!("ESPHome camera detection failed: {e}"));
+    }
+
+    let timeout = Duration::from_millis(state.settings.hardware_request_timeout_ms);
+    let unreachable = |error: String| ControllerProbe {
+        hostname: hostname.clone(),
+        reachable: false,
+        response_time_ms: None,
+        error: Some(error),
+    };
+    let controller = match state
+        .controller
+        .send_command_with_timeout(
+            ControllerCommand::Probe {
+                hostname: hostname.clone(),
+            },
+            timeout,
+        )
+        .await
+    {
+        Ok(ControllerResponse::Probe(probe)) => probe,
+        Ok(_) => unreachable("Unexpected response from controller monitor".to_string()),
+        Err(e) => unreachable(e.to_string()),
+    };
+    for error in &errors {
+        warn!("Setup scan: {error}");
+    }
+
+    (
+        StatusCode::OK,
+        Json(ApiResponse::success(SetupScanData {
+            controller,
+            cameras: collect_cameras(&state).await,
+            errors,
+        })),
+    )
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "snake_case")]
+struct SetupApplyData {
+    config_path: PathBuf,
+    /// The data directory changed, which takes effect after a restart
+    restart_required: bool,
+}
+
+/// Save the setup choices to the user config and pass the new controller hostname on
+async fn apply_setup(
+    State(state): State<Arc<AppState>>,
+    ExtractJson(choices): ExtractJson<SetupChoices>,
+) -> (StatusCode, Json<ApiResponse<SetupApplyData>>) {
+    let detected: Vec<String> = collect_cameras(&state)
+        .await
+        .into_iter()
+        .map(|camera| camera.camera.id.into_string())
+        .collect();
+    if let Err(errors) = choices.validate(&detected) {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(ApiResponse::error(
+                ErrorCode::InvalidRequest,
+                format!("Invalid setup: {}", errors.join("; ")),
+            )),
+        );
+    }
+    if let Some(data_directory) = &choices.data_directory
+        && let Err(e) = tokio::fs::create_dir_all(data_directory).await
+    {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(ApiResponse::error(
+                ErrorCode::InvalidRequest,
+                format!(
+                    "Invalid setup: data_directory: cannot create {}: {e}",
+                    data_directory.display()
+                ),
+            )),
+        );
+    }
+
+    let restart_required = choices
+        .data_directory
+        .as_ref()
+        .is_some_and(|data_directory| *data_directory != state.settings.data_directory);
+    let mut new_settings = state.settings.clone();
+    new_settings.esphome_hostname = choices.controller_hostname.trim().to_string();
+    let selected: Vec<CameraId> = choices
+        .cameras
+        .iter()
+        .map(|camera| CameraId::from(camera.camera_id.as_str()))
+        .collect();
+
+    if let Err(e) = state
+        .config_writer
+        .update(move |user_config| choices.apply_to(user_config))
+        .await
+    {
+        error!("Failed to save setup: {e}");
+        return (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(ApiResponse::error(
+                ErrorCode::InternalError,
+                format!("Failed to save setup: {e}"),
+            )),
+        );
+    }
+    if let Err(e) = state.controller.update_config(new_settings).await {
+        error!("Failed to update controller monitor configuration: {e}");
+        return (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(ApiResponse::error(
+                ErrorCode::InternalError,
+                format!("Setup saved, but the controller configuration could not be updated: {e}"),
+            )),
+        );
+    }
+    if let Err(message) = select_in_managers(&state, selected).await {
+        return (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(ApiResponse::error(
+                ErrorCode::InternalError,
+                format!("Setup saved, but {message}"),
+            )),
+        );
+    }
+
+    info!("First-run setup saved");
+    (
+        StatusCode::OK,
+        Json(ApiResponse::success(SetupApplyData {
+            config_path: state.settings.sources.user_config_path.clone(),
+            restart_required,
+        })),
+    )
+}
+
+/// Import an ESPHome camera inventory (JSON or YAML) into the user config
+async fn import_camera_inventory(
+    State(state): State<Arc<AppState>>,
+    body: String,
+) -> (StatusCode, Json<ApiResponse<ImportReport>>) {
+    let entries = match camera_inventory::parse_inventory(&body) {
+        Ok(entries) => entries,
+        Err(e) => {
+            return (
+                StatusCode::BAD_REQUEST,
+                Json(ApiResponse::error(
+                    ErrorCode::InvalidRequest,
+                    format!("Invalid camera inventory: {e}"),
+                )),
+            );
+        }
+    };
+
+    // Report against the current config; the writer merges again onto whatever is latest
+    let mut user_config = current_user_config(&state).await;
+    let report = camera_inventory::merge_inventory(&mut user_config, &entries);
+
+    let saved = state
+        .config_writer
+        .update(move |user_config| {
+            camera_inventory::merge_inventory(user_config, &entries);
+        })
+        .await;
+    if let Err(e) = saved {
+        error!("Failed to save imported camera inventory: {}", e);
+        return (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(ApiResponse::localized(
+                ErrorCode::InternalError,
+                Message::ConfigSaveFailed {
+                    error: e.to_string(),
+                },
+            )),
+        );
+    }
+
+    info!(
+        "Imported camera inventory: {} added, {} updated, {} unchanged",
+        report.added.len(),
+        report.updated.len(),
+        report.unchanged.len()
+    );
+    (StatusCode::OK, Json(ApiResponse::success(report)))
+}
+
+/// Query parameters for the camera inventory export
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case")]
+struct InventoryExportQuery {
+    format: Option<InventoryFormat>,
+}
+
+/// Export the network cameras in the user config as an inventory document
+async fn export_camera_inventory(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<InventoryExportQuery>,
+) -> Result<Response<Body>, StatusCode> {
+    let format = query.format.unwrap_or(InventoryFormat::Yaml);
+    let entries = camera_inventory::export_inventory(&current_user_config(&state).await);
+    let document = camera_inventory::render_inventory(&entries, format).map_err(|e| {
+        error!("Failed to render camera inventory: {e}");
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    let content_type = match format {
+        InventoryFormat::Json => "application/json",
+        InventoryFormat::Yaml => "application/yaml",
+    };
+    Response::builder()
+        .header("Content-Type", content_type)
+        .body(Body::from(document))
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)
+}
+
+async fn delete_camera_config(
+    Path(index): Path<usize>,
+    State(_state): State<Arc<AppState>>,
+) -> Json<ApiResponse<()>> {
+    // For now, just acknowledge the delete request
+    // In a full implementation, this would remove the camera from the configuration
+    info!("Camera {} delete requested", index);
+    Json(ApiResponse::success(()))
+}
+
+async fn clear_camera_configs(State(_state): State<Arc<AppState>>) -> Json<ApiResponse<()>> {
+    // For now, just acknowledge the clear request
+    // In a full implementation, this would remove all cameras from configuration
+    info!("Clear all cameras requested");
+    Json(ApiResponse::success(()))
+}
+
+async fn reset_config(State(_state): State<Arc<AppState>>) -> Json<ApiResponse<()>> {
+    // For now, just acknowledge the reset request
+    // In a full implementation, this would reset configuration to defaults
+    info!("Config reset to defaults requested");
+    Json(ApiResponse::success(()))
+}
+
+async fn get_camera_brightness(
+    Path(camera_id): Path<CameraId>,
+    State(state): State<Arc<AppState>>,
+) -> Json<ApiResponse<BrightnessResponse>> {
+    info!("Getting brightness for camera: {}", camera_id);
+
+    // Determine camera type and route to appropriate manager
+    if camera_id.kind() == CameraKind::Usb {
+        match state
+            .usb_camera_manager
+            .get_brightness(camera_id.into_string())
+            .await
+        {
+            Ok(brightness) => {
+                info!("Current brightness for USB camera: {}", brightness);
+                Json(ApiResponse::success(BrightnessResponse { brightness }))
+            }
+            Err(e) => {
+                error!("Failed to get USB camera brightness: {}", e);
+                Json(ApiResponse::<BrightnessResponse>::localized(
+                    ErrorCode::CameraError,
+                    Message::BrightnessReadFailed {
+                        error: e.to_string(),
+                    },
+                ))
+            }
+        }
+    } else {
+        // ESPHome cameras don't support brightness control
+        Json(ApiResponse::<BrightnessResponse>::localized(
+            ErrorCode::CameraUnsupported,
+            Message::BrightnessUnsupported,
+        ))
+    }
+}
+
+async fn set_camera_brightness(
+    Path(camera_id): Path<CameraId>,
+    State(state): State<Arc<AppState>>,
+    Json(payload): Json<BrightnessRequest>,
+) -> Json<ApiResponse<()>> {
+    info!(
+        "Setting brightness for camera: {} to {}",
+        camera_id, payload.brightness
+    );
+
+    // Validate brightness range (typically 0-100 or similar)
+    if payload.brightness < 0 || payload.brightness > 255 {
+        return Json(ApiResponse::failure(ApiError::validation(
+            "Invalid brightness",
+            BTreeMap::from([(
+                "brightness".to_string(),
+                "must be between 0 and 255".to_string(),
+            )]),
+        )));
+    }
+
+    // Determine camera type and route to appropriate manager
+    if camera_id.kind() == CameraKind::Usb {
+        match state
+            .usb_camera_manager
+            .set_brightness(
+                camera_id.into_string(),
+                payload.brightness,
+                payload.when_busy,
+            )
+            .await
+        {
+            Ok(()) => {
+                info!("Successfully set USB camera brightness");
+                Json(ApiResponse::success(()))
+            }
+            Err(e) => {
+                error!("Failed to set USB camera brightness: {}", e);
+                Json(ApiResponse::<()>::localized(
+                    ErrorCode::CameraError,
+                    Message::BrightnessSetFailed {
+                        error: e.to_string(),
+                    },
+                ))
+            }
+        }
+    } else {
+        // ESPHome cameras don't support brightness control
+        Json(ApiResponse::<()>::localized(
+            ErrorCode::CameraUnsupported,
+            Message::BrightnessUnsupported,
+        ))
+    }
+}
+
+/// Query parameters for the data disk usage report
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case")]
+struct DataUsageQuery {
+    /// Scan now instead of answering from the last scan
+    #[serde(default)]
+    refresh: bool,
+    /// How many of the largest sessions to list
+    limit: Option<usize>,
+}
+
+/// Disk usage of the image and data directories, and how old the numbers are
+#[derive(Serialize)]
+#[serde(rename_all = "snake_case")]
+struct DataUsageData {
+    #[serde(flatten)]
+    report: UsageReport,
+    age_seconds: i64,
+}
+
+/// Disk usage by session and case type, from the last scan unless a refresh is asked for
+async fn get_data_usage(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<DataUsageQuery>,
+) -> Result<(StatusCode, Json<ApiResponse<DataUsageData>>), Response> {
+    let cached = state.data_usage.get().filter(|_| !query.refresh);
+    let report = match cached {
+        Some(report) => report,
+        None => {
+            let _permit = state
+                .heavy_work
+                .try_acquire("disk usage scan")
+                .map_err(|busy| heavy_work_busy(busy, "disk usage scan"))?;
+            match refresh_data_usage(&state).await {
+                Ok(report) => report,
+                Err(e) => {
+                    error!("Failed to scan data disk usage: {e}");
+                    return Ok((
+                        StatusCode::INTERNAL_SERVER_ERROR,
+                        Json(ApiResponse::error(
+                            ErrorCode::InternalError,
+                            format!("Failed to scan data disk usage: {e}"),
+                        )),
+                    ));
+                }
+            }
+        }
+    };
+    let age_seconds = (chrono::Utc::now() - report.scanned_at)
+        .num_seconds()
+        .max(0);
+    Ok((
+        StatusCode::OK,
+        Json(ApiResponse::success(DataUsageData {
+            report: report.top_sessions(query.limit.unwrap_or(20)),
+            age_seconds,
+        })),
+    ))
+}
+
+/// Options for `POST /api/data/migrate`
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case", default, deny_unknown_fields)]
+struct MigrateRequest {
+    /// Copy each file into a backup directory before rewriting it
+    backup: bool,
+}
+
+impl Default for MigrateRequest {
+    fn default() -> Self {
+        Self { backup: true }
+    }
+}
+
+/// Rewrite every shell record in an older schema in the current one
+async fn migrate_shell_data(
+    State(state): State<Arc<AppState>>,
+    payload: Option<ExtractJson<MigrateRequest>>,
+) -> (StatusCode, Json<ApiResponse<MigrationReport>>) {
+    let request = payload
+        .map(|ExtractJson(request)| request)
+        .unwrap_or_default();
+    let shell_data_manager = state.shell_data_manager.clone();
+    let result =
+        tokio::task::spawn_blocking(move || shell_data_manager.migrate_all(request.backup))
+            .await
+            .map_err(|e| OurError::App(format!("Migration task failed: {e}")))
+            .and_then(|result| result);
+    match result {
+        Ok(report) => (StatusCode::OK, Json(ApiResponse::success(report))),
+        Err(e) => {
+            error!("Failed to migrate shell records: {e}");
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ApiResponse::error(
+                    ErrorCode::InternalError,
+                    format!("Failed to migrate shell records: {e}"),
+                )),
+            )
+        }
+    }
+}
+
+/// Query parameters for `GET /api/data/export`
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case")]
+struct DataExportQuery {
+    format: Option<ExportFormat>,
+    /// `last`, or an RFC 3339 time
+    since: Option<String>,
+}
+
+/// Export every shell, or those changed since a time or the last export, as an attachment
+async fn export_dataset(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<DataExportQuery>,
+) -> Response {
+    let error_response = |status: StatusCode, code: ErrorCode, message: String| {
+        (status, Json(ApiResponse::<()>::error(code, message))).into_response()
+    };
+    let format = query.format.unwrap_or(ExportFormat::Json);
+    let since = match query.since.as_deref().map(str::parse::<ExportSince>) {
+        None => ExportSince::Everything,
+        Some(Ok(since)) => since,
+        Some(Err(e)) => {
+            return error_response(
+                StatusCode::BAD_REQUEST,
+                ErrorCode::InvalidRequest,
+                e.to_string(),
+            );
+        }
+    };
+
+    if since == ExportSince::LastExport {
+        match data_export::read_manifest(&state.settings.data_directory) {
+            Ok(Some(_)) => {}
+            Ok(None) => {
+                return error_response(
+                    StatusCode::CONFLICT,
+                    ErrorCode::InvalidRequest,
+                    "No earlier export to continue from; run a full export first".to_string(),
+                );
+            }
+            Err(e) => {
+                error!("Failed to read the last export's manifest: {e}");
+                return error_response(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    ErrorCode::InternalError,
+                    format!("Failed to read the last export's manifest: {e}"),
+                );
+            }
+        }
+    }
+
+    let shell_data_manager = state.shell_data_manager.clone();
+    let settings = state.settings.clone();
+    // A read-only server leaves the last export's manifest alone
+    let record = !settings.read_only;
+    let (export, manifest) = match tokio::task::spawn_blocking(move || {
+        data_export::export_to_temp_file(&shell_data_manager, &settings, format, since, record)
+    })
+    .await
+    {
+        Ok(Ok(exported)) => exported,
+        Ok(Err(e)) => {
+            error!("Failed to export data: {e}");
+            return error_response(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                ErrorCode::InternalError,
+                format!("Failed to export data: {e}"),
+            );
+        }
+        Err(e) => {
+            error!("Data export task failed: {e}");
+            return error_response(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                ErrorCode::InternalError,
+                format!("Failed to export data: {e}"),
+            );
+        }
+    };
+    let filename = format!(
+        "shell-sorter-{}{}.{}",
+        if manifest.since.is_some() {
+            "delta"
+        } else {
+            "export"
+        },
+        manifest.exported_at.format("-%Y%m%dT%H%M%SZ"),
+        format.extension()
+    );
+    download_response(export, format.content_type(), &filename).await
+}
+
+/// Import a dataset export, reporting sessions its dataset had that are still missing here
+async fn import_dataset(
+    State(state): State<Arc<AppState>>,
+    mut multipart: Multipart,
+) -> (StatusCode, Json<ApiResponse<ImportedExport>>) {
+    if let Err(e) = state.disk_space.check("data import") {
+        error!("{e}");
+        return (
+            StatusCode::INSUFFICIENT_STORAGE,
+            Json(ApiResponse::error(
+                ErrorCode::InsufficientStorage,
+                e.to_string(),
+            )),
+        );
+    }
+
+    let upload = TempBundle::new("dataset-import");
+    let mut field = loop {
+        match multipart.next_field().await {
+            Ok(Some(field)) if field.file_name().is_some() => break field,
+            Ok(Some(_)) => continue,
+            Ok(None) => {
+                return (
+                    StatusCode::BAD_REQUEST,
+                    Json(ApiResponse::error(
+                        ErrorCode::InvalidRequest,
+                        "No export file was uploaded".to_string(),
+                    )),
+                );
+            }
+            Err(e) => return multipart_rejection(e, MAX_BUNDLE_BYTES as usize),
+        }
+    };
+    match upload::spool_field(&mut field, upload.path()).await {
+        Ok(_) => {}
+        Err(SpoolError::TooLarge) => return payload_too_large(MAX_BUNDLE_BYTES as usize),
+        Err(e) => {
+            error!("{e}");
+            return (
+                StatusCode::BAD_REQUEST,
+                Json(ApiResponse::error(ErrorCode::InvalidRequest, e.to_string())),
+            );
+        }
+    }
+
+    let shell_data_manager = state.shell_data_manager.clone();
+    let settings = state.settings.clone();
+    match tokio::task::spawn_blocking(move || {
+        data_export::import_dataset(
+            &shell_data_manager,
+            &settings,
+            data_export::open_export(upload.path())?,
+        )
+    })
+    .await
+    {
+        Ok(Ok(imported)) => {
+            observe_shell_targets(&state);
+            (StatusCode::OK, Json(ApiResponse::success(imported)))
+        }
+        Ok(Err(e)) => {
+            warn!("Rejected data export: {e}");
+            (
+                StatusCode::BAD_REQUEST,
+                Json(ApiResponse::error(
+                    ErrorCode::InvalidRequest,
+                    format!("Failed to import data: {e}"),
+                )),
+            )
+        }
+        Err(e) => {
+            error!("Data import task failed: {e}");
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ApiResponse::error(
+                    ErrorCode::InternalError,
+                    format!("Failed to import data: {e}"),
+                )),
+            )
+        }
+    }
+}
+
+/// A batch with how many shells it holds
+#[derive(Serialize)]
+#[serde(rename_all = "snake_case")]
+struct BatchEntry {
+    #[serde(flatten)]
+    batch: Batch,
+    shell_count: usize,
+    /// Of those, how many are included in training
+    included_count: usize,
+}
+
+/// Every batch for `GET /api/batches`
+#[derive(Serialize)]
+#[serde(rename_all = "snake_case")]
+struct BatchesData {
+    /// The batch captures go into when they don't name one
+    active_batch_id: Option<String>,
+    batches: Vec<BatchEntry>,
+}
+
+fn batch_not_found<T>(batch_id: &str) -> (StatusCode, Json<ApiResponse<T>>) {
+    (
+        StatusCode::NOT_FOUND,
+        Json(ApiResponse::error(
+            ErrorCode::BatchNotFound,
+            format!("Batch {batch_id} not found"),
+        )),
+    )
+}
+
+fn batch_failed<T>(action: &str, e: OurError) -> (StatusCode, Json<ApiResponse<T>>) {
+    error!("Failed to {action}: {e}");
+    (
+        StatusCode::INTERNAL_SERVER_ERROR,
+        Json(ApiResponse::error(
+            ErrorCode::InternalError,
+            format!("Failed to {action}: {e}"),
+        )),
+    )
+}
+
+/// Every batch with its shell counts, oldest first
+async fn list_batches(
+    State(state): State<Arc<AppState>>,
+) -> (StatusCode, Json<ApiResponse<BatchesData>>) {
+    let batches = match state.shell_data_manager.batches() {
+        Ok(batches) => batches,
+        Err(e) => return batch_failed("read batches", e),
+    };
+    let shells = match state
+        .shell_data_manager
+        .search_shells(&ShellFilter::default())
+    {
+        Ok(shells) => shells,
+        Err(e) => return batch_failed("list shells", e),
+    };
+    let BatchList {
+        active_batch_id,
+        batches,
+    } = batches;
+    let batches = batches
+        .into_iter()
+        .map(|batch| {
+            let in_batch = shells
+                .iter()
+                .filter(|shell| shell.batch_id.as_ref() == Some(&batch.id));
+            let (shell_count, included_count) = in_batch.fold((0, 0), |(all, included), shell| {
+                (all + 1, included + usize::from(shell.include))
+            });
+            BatchEntry {
+                batch,
+                shell_count,
+                included_count,
+            }
+        })
+        .collect();
+    (
+        StatusCode::OK,
+        Json(ApiResponse::success(BatchesData {
+            active_batch_id,
+            batches,
+        })),
+    )
+}
+
+/// Body of `POST /api/batches`
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+struct CreateBatchRequest {
+    name: String,
+    #[serde(default)]
+    notes: Option<String>,
+    /// Make it the batch captures go into
+    #[serde(default)]
+    activate: bool,
+}
+
+async fn create_batch(
+    State(state): State<Arc<AppState>>,
+    ExtractJson(payload): ExtractJson<CreateBatchRequest>,
+) -> (StatusCode, Json<ApiResponse<Batch>>) {
+    let notes = payload
+        .notes
+        .map(|notes| notes.trim().to_string())
+        .filter(|notes| !notes.is_empty());
+    let field_errors: BTreeMap<String, String> = [
+        ("name", batch_name_error(&payload.name)),
+        ("notes", notes.as_deref().and_then(notes_error)),
+    ]
+    .into_iter()
+    .filter_map(|(field, error)| error.map(|error| (field.to_string(), error)))
+    .collect();
+    if !field_errors.is_empty() {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(ApiResponse::failure(ApiError::validation(
+                "Invalid batch",
+                field_errors,
+            ))),
+        );
+    }
+
+    match state.shell_data_manager.batches() {
+        Ok(batches) => {
+            if let Some(existing) = batches.named(&payload.name) {
+                return (
+                    StatusCode::CONFLICT,
+                    Json(ApiResponse::error(
+                        ErrorCode::BatchExists,
+                        format!("A batch named '{}' already exists", existing.name),
+                    )),
+                );
+            }
+        }
+        Err(e) => return batch_failed("read batches", e),
+    }
+
+    let batch = match state.shell_data_manager.create_batch(&payload.name, notes) {
+        Ok(batch) => batch,
+        Err(e) => return batch_failed("create batch", e),
+    };
+    if payload.activate
+        && let Err(e) = state.shell_data_manager.set_active_batch(Some(&batch.id))
+    {
+        return batch_failed("activate batch", e);
+    }
+    (StatusCode::CREATED, Json(ApiResponse::success(batch)))
+}
+
+/// Put every capture that doesn't name a batch into this one
+async fn activate_batch(
+    Path(batch_id): Path<String>,
+    State(state): State<Arc<AppState>>,
+) -> (StatusCode, Json<ApiResponse<Batch>>) {
+    match state.shell_data_manager.set_active_batch(Some(&batch_id)) {
+        Ok(Some(batches)) => match batches.active() {
+            Some(batch) => (StatusCode::OK, Json(ApiResponse::success(batch.clone()))),
+            None => batch_not_found(&batch_id),
+        },
+        Ok(None) => batch_not_found(&batch_id),
+        Err(e) => batch_failed("activate batch", e),
+    }
+}
+
+/// Stop putting captures into a batch unless they name one
+async fn deactivate_batch(
+    State(state): State<Arc<AppState>>,
+) -> (StatusCode, Json<ApiResponse<()>>) {
+    match state.shell_data_manager.set_active_batch(None) {
+        Ok(_) => (StatusCode::OK, Json(ApiResponse::success(()))),
+        Err(e) => batch_failed("deactivate batch", e),
+    }
+}
+
+/// Body of `POST /api/batches/{batch_id}/training`
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+struct BatchTrainingRequest {
+    exclude_from_training: bool,
+}
+
+/// Take every shell in a batch out of training, or put its tagged shells back
+async fn set_batch_training(
+    Path(batch_id): Path<String>,
+    State(state): State<Arc<AppState>>,
+    ExtractJson(payload): ExtractJson<BatchTrainingRequest>,
+) -> (StatusCode, Json<ApiResponse<BatchExclusion>>) {
+    let shell_data_manager = state.shell_data_manager.clone();
+    let id = batch_id.clone();
+    let result = tokio::task::spawn_blocking(move || {
+        shell_data_manager.set_batch_excluded(&id, payload.exclude_from_training)
+    })
+    .await
+    .map_err(|e| OurError::App(format!("Batch update task failed: {e}")))
+    .and_then(|result| result);
+    match result {
+        Ok(Some(exclusion)) => (StatusCode::OK, Json(ApiResponse::success(exclusion))),
+        Ok(None) => batch_not_found(&batch_id),
+        Err(e) => batch_failed("update batch", e),
+    }
+}
+
+/// Remove a batch and take its shells out of training; they keep their `batch_id`
+async fn delete_batch(
+    Path(batch_id): Path<String>,
+    State(state): State<Arc<AppState>>,
+) -> (StatusCode, Json<ApiResponse<BatchExclusion>>) {
+    let shell_data_manager = state.shell_data_manager.clone();
+    let id = batch_id.clone();
+    let result = tokio::task::spawn_blocking(move || shell_data_manager.delete_batch(&id))
+        .await
+        .map_err(|e| OurError::App(format!("Batch delete task failed: {e}")))
+        .and_then(|result| result);
+    match result {
+        Ok(Some(exclusion)) => (StatusCode::OK, Json(ApiResponse::success(exclusion))),
+        Ok(None) => batch_not_found(&batch_id),
+        Err(e) => batch_failed("delete batch", e),
+    }
+}
+
+/// A camera preset with its name
+#[derive(Serialize)]
+#[serde(rename_all = "snake_case")]
+struct NamedCameraPreset {
+    name: String,
+    #[serde(flatten)]
+    preset: CameraPreset,
+}
+
+/// Every camera preset for `GET /api/camera-presets`
+#[derive(Serialize)]
+#[serde(rename_all = "snake_case")]
+struct CameraPresetsData {
+    /// The preset applied last
+    active_preset: Option<String>,
+    presets: Vec<NamedCameraPreset>,
+}
+
+fn camera_preset_not_found<T>(name: &str) -> (StatusCode, Json<ApiResponse<T>>) {
+    (
+        StatusCode::NOT_FOUND,
+        Json(ApiResponse::error(
+            ErrorCode::CameraPresetNotFound,
+            format!("Camera preset '{name}' not found"),
+        )),
+    )
+}
+
+fn invalid_camera_preset<T>(
+    field_errors: BTreeMap<String, String>,
+) -> (StatusCode, Json<ApiResponse<T>>) {
+    (
+        StatusCode::BAD_REQUEST,
+        Json(ApiResponse::failure(ApiError::validation(
+            "Invalid camera preset",
+            field_errors,
+        ))),
+    )
+}
+
+fn camera_preset_save_failed<T>(e: OurError) -> (StatusCode, Json<ApiResponse<T>>) {
+    error!("Failed to save camera presets: {e}");
+    (
+        StatusCode::INTERNAL_SERVER_ERROR,
+        Json(ApiResponse::localized(
+            ErrorCode::InternalError,
+            Message::ConfigSaveFailed {
+                error: e.to_string(),
+            },
+        )),
+    )
+}
+
+/// Take the preset called `name`, ignoring case, out of `user_config`
+fn take_camera_preset(user_config: &mut UserConfig, name: &str) -> Option<(String, CameraPreset)> {
+    let stored = preset_named(&user_config.camera_presets, name)?.to_string();
+    user_config.camera_presets.remove_entry(&stored)
+}
+
+/// The selected cameras with their saved settings and current brightness
+async fn selected_camera_preset(state: &AppState, user_config: &UserConfig) -> CameraPreset {
+    let mut cameras = Vec::new();
+    for camera_id in user_config.get_selected_cameras() {
+        let brightness = match camera_id.kind() {
+            CameraKind::Usb => state
+                .usb_camera_manager
+                .get_brightness(camera_id.as_str().to_string())
+                .await
+                .inspect_err(|e| warn!("Couldn't read the brightness of {camera_id}: {e}"))
+                .ok(),
+            CameraKind::EspHome => None,
+        };
+        cameras.push(PresetCamera {
+            camera_id: camera_id.clone(),
+            config: user_config.get_camera_config(camera_id.as_str()),
+            brightness,
+        });
+    }
+    CameraPreset { cameras }
+}
+
+/// Every camera preset by name, and the one applied last
+async fn list_camera_presets(
+    State(state): State<Arc<AppState>>,
+) -> Json<ApiResponse<CameraPresetsData>> {
+    let UserConfig {
+        camera_presets,
+        active_camera_preset,
+        ..
+    } = current_user_config(&state).await;
+    let presets = camera_presets
+        .into_iter()
+        .map(|(name, preset)| NamedCameraPreset { name, preset })
+        .collect();
+    Json(ApiResponse::success(CameraPresetsData {
+        active_preset: active_camera_preset,
+        presets,
+    }))
+}
+
+/// Body of `POST /api/camera-presets`
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+struct CreateCameraPresetRequest {
+    name: String,
+    /// Left out to keep the cameras selected now, with their current settings
+    #[serde(default)]
+    cameras: Option<Vec<PresetCamera>>,
+}
+
+async fn create_camera_preset(
+    State(state): State<Arc<AppState>>,
+    ExtractJson(payload): ExtractJson<CreateCameraPresetRequest>,
+) -> (StatusCode, Json<ApiResponse<NamedCameraPreset>>) {
+    let name = payload.name.trim().to_string();
+    let user_config = current_user_config(&state).await;
+    let preset = match payload.cameras {
+        Some(cameras) => CameraPreset { cameras },
+        None => selected_camera_preset(&state, &user_config).await,
+    };
+    let mut field_errors = preset.field_errors();
+    if let Some(error) = preset_name_error(&name) {
+        field_errors.insert("name".to_string(), error);
+    }
+    if !field_errors.is_empty() {
+        return invalid_camera_preset(field_errors);
+    }
+    if let Some(existing) = preset_named(&user_config.camera_presets, &name) {
+        return (
+            StatusCode::CONFLICT,
+            Json(ApiResponse::error(
+                ErrorCode::CameraPresetExists,
+                format!("A camera preset named '{existing}' already exists"),
+            )),
+        );
+    }
+
+    let (saved_name, saved_preset) = (name.clone(), preset.clone());
+    if let Err(e) = state
+        .config_writer
+        .update(move |user_config| {
+            user_config.camera_presets.insert(saved_name, saved_preset);
+        })
+        .await
+    {
+        return camera_preset_save_failed(e);
+    }
+    (
+        StatusCode::CREATED,
+        Json(ApiResponse::success(NamedCameraPreset { name, preset })),
+    )
+}
+
+async fn get_camera_preset(
+    Path(name): Path<String>,
+    State(state): State<Arc<AppState>>,
+) -> (StatusCode, Json<ApiResponse<NamedCameraPreset>>) {
+    let mut user_config = current_user_config(&state).await;
+    match take_camera_preset(&mut user_config, &name) {
+        Some((name, preset)) => (
+            StatusCode::OK,
+            Json(ApiResponse::success(NamedCameraPreset { name, preset })),
+        ),
+        None => camera_preset_not_found(&name),
+    }
+}
+
+/// Body of `PUT /api/camera-presets/{name}`
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+struct UpdateCameraPresetRequest {
+    cameras: Vec<PresetCamera>,
+}
+
+/// Replace a preset's cameras; an applied preset isn't applied again
+async fn update_camera_preset(
+    Path(name): Path<String>,
+    State(state): State<Arc<AppState>>,
+    ExtractJson(payload): ExtractJson<UpdateCameraPresetRequest>,
+) -> (StatusCode, Json<ApiResponse<NamedCameraPreset>>) {
+    let preset = CameraPreset {
+        cameras: payload.cameras,
+    };
+    let field_errors = preset.field_errors();
+    if !field_errors.is_empty() {
+        return invalid_camera_preset(field_errors);
+    }
+    let mut user_config = current_user_config(&state).await;
+    let Some((name, _)) = take_camera_preset(&mut user_config, &name) else {
+        return camera_preset_not_found(&name);
+    };
+
+    let (saved_name, saved_preset) = (name.clone(), preset.clone());
+    if let Err(e) = state
+        .config_writer
+        .update(move |user_config| {
+            user_config.camera_presets.insert(saved_name, saved_preset);
+        })
+        .await
+    {
+        return camera_preset_save_failed(e);
+    }
+    (
+        StatusCode::OK,
+        Json(ApiResponse::success(NamedCameraPreset { name, preset })),
+    )
+}
+
+/// Remove a preset; the cameras keep the settings it gave them
+async fn delete_camera_preset(
+    Path(name): Path<String>,
+    State(state): State<Arc<AppState>>,
+) -> (StatusCode, Json<ApiResponse<NamedCameraPreset>>) {
+    let mut user_config = current_user_config(&state).await;
+    let Some((name, preset)) = take_camera_preset(&mut user_config, &name) else {
+        return camera_preset_not_found(&name);
+    };
+
+    let removed = name.clone();
+    if let Err(e) = state
+        .config_writer
+        .update(move |user_config| {
+            user_config.camera_presets.remove(&removed);
+            if user_config.active_camera_preset.as_ref() == Some(&removed) {
+                user_config.active_camera_preset = None;
+            }
+        })
+        .await
+    {
+        return camera_preset_save_failed(e);
+    }
+    (
+        StatusCode::OK,
+        Json(ApiResponse::success(NamedCameraPreset { name, preset })),
+    )
+}
+
+/// What applying a camera preset did to each of its cameras
+#[derive(Serialize)]
+#[serde(rename_all = "snake_case")]
+struct CameraPresetApplied {
+    name: String,
+    cameras: Vec<PresetCameraResult>,
+}
+
+/// Select a preset's cameras and give them its settings
+///
+/// Cameras that aren't connected are reported as missing rather than failing
+/// the request. Their settings and selection are still saved, so they are
+/// picked up when the camera is next detected.
+async fn apply_camera_preset(
+    Path(name): Path<String>,
+    State(state): State<Arc<AppState>>,
+) -> (StatusCode, Json<ApiResponse<CameraPresetApplied>>) {
+    let mut user_config = current_user_config(&state).await;
+    let Some((name, preset)) = take_camera_preset(&mut user_config, &name) else {
+        return camera_preset_not_found(&name);
+    };
+
+    let connected: HashSet<CameraId> = collect_cameras(&state)
+        .await
+        .into_iter()
+        .filter(|camera| camera.camera.online)
+        .map(|camera| camera.camera.id)
+        .collect();
+
+    // Both managers are told, even with nothing to select, so the last preset's cameras are let go
+    let IdsByKind { esphome, usb } = preset
+        .camera_ids()
+        .into_iter()
+        .filter(|camera_id| connected.contains(camera_id))
+        .collect();
+    let (esphome_selected, usb_selected) = tokio::join!(
+        state.camera_manager.select_cameras(esphome),
+        state.usb_camera_manager.select_cameras(usb),
+    );
+    let selection_failed = |kind: CameraKind, e: OurError| {
+        error!("Failed to select {kind} cameras: {e}");
+        format!("Failed to select {kind} cameras: {e}")
+    };
+    let esphome_error = esphome_selected
+        .err()
+        .map(|e| selection_failed(CameraKind::EspHome, e));
+    let usb_error = usb_selected
+        .err()
+        .map(|e| selection_failed(CameraKind::Usb, e));
+
+    let (saved_name, saved_preset) = (name.clone(), preset.clone());
+    if let Err(e) = state
+        .config_writer
+        .update(move |user_config| {
+            user_config.set_selected_cameras(saved_preset.camera_ids());
+            for camera in saved_preset.cameras {
+                user_config.set_camera_config(camera.camera_id.into_string(), camera.config);
+            }
+            user_config.active_camera_preset = Some(saved_name);
+        })
+        .await
+    {
+        return camera_preset_save_failed(e);
+    }
+    apply_camera_orientations(&state, &current_user_config(&state).await).await;
+
+    let mut cameras = Vec::with_capacity(preset.cameras.len());
+    for camera in preset.cameras {
+        let camera_id = camera.camera_id;
+        let (status, error) = if !connected.contains(&camera_id) {
+            (PresetCameraStatus::Missing, None)
+        } else if let Some(error) = match camera_id.kind() {
+            CameraKind::EspHome => &esphome_error,
+            CameraKind::Usb => &usb_error,
+        } {
+            (PresetCameraStatus::Failed, Some(error.clone()))
+        } else if let Some(brightness) = camera.brightness {
+            match state
+                .usb_camera_manager
+                .set_brightness(camera_id.as_str().to_string(), brightness, BusyPolicy::Wait)
+                .await
+            {
+                Ok(()) => (PresetCameraStatus::Applied, None),
+                Err(e) => {
+                    error!("Failed to set the brightness of {camera_id}: {e}");
+                    (
+                        PresetCameraStatus::Failed,
+                        Some(format!("Failed to set brightness: {e}")),
+                    )
+                }
+            }
+        } else {
+            (PresetCameraStatus::Applied, None)
+        };
+        cameras.push(PresetCameraResult {
+            camera_id,
+            status,
+            error,
+        });
+    }
+    info!("Applied camera preset '{name}'");
+    (
+        StatusCode::OK,
+        Json(ApiResponse::success(CameraPresetApplied { name, cameras })),
+    )
+}
+
+/// Query parameters for the event log
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case")]
+struct EventsQuery {
+    limit: Option<usize>,
+    kind: Option<String>,
+}
+
+/// Recent manager events for the diagnostics panel
+#[derive(Serialize)]
+#[serde(rename_all = "snake_case")]
+struct EventsResponse {
+    events: Vec<RecordedEvent>,
+    /// Events dropped because the recorder was busy, so the view may be incomplete
+    dropped: u64,
+    capacity: usize,
+}
+
+/// List recently recorded manager events
+async fn list_events(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<EventsQuery>,
+) -> Json<ApiResponse<EventsResponse>> {
+    let limit = query.limit.unwrap_or(100);
+    let events = state.events.recent(limit, query.kind.as_deref());
+
+    Json(ApiResponse::success(EventsResponse {
+        events,
+        dropped: state.events.dropped_count(),
+        capacity: state.events.capacity(),
+    }))
+}
+
+/// Query parameters for the log buffer and log stream
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case")]
+struct LogsQuery {
+    limit: Option<usize>,
+    /// Only entries at this level or more severe
+    level: Option<LogLevel>,
+    /// Only entries from this module or its submodules
+    target: Option<String>,
+}
+
+/// Recent log entries for the diagnostics panel
+#[derive(Serialize)]
+#[serde(rename_all = "snake_case")]
+struct LogsResponse {
+    entries: Vec<LogEntry>,
+    /// Entries kept in memory, older ones are gone
+    capacity: usize,
+    /// Least severe level captured at all
+    level: LogLevel,
+}
+
+async fn list_logs(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<LogsQuery>,
+) -> Json<ApiResponse<LogsResponse>> {
+    let limit = query.limit.unwrap_or(500);
+    let entries = state
+        .logs
+        .recent(limit, query.level, query.target.as_deref());
+
+    Json(ApiResponse::success(LogsResponse {
+        entries,
+        capacity: state.logs.capacity(),
+        level: state.logs.level(),
+    }))
+}
+
+/// Push new log entries as server-sent `log` events, with a `lagged` event when some were skipped
+async fn stream_logs(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<LogsQuery>,
+) -> Sse<impl futures_util::Stream<Item = Result<SseEvent, Infallible>>> {
+    let mut receiver = state.logs.subscribe();
+    let shutdown = state.tasks.shutdown_token();
+
+    let stream = async_stream::stream! {
+        loop {
+            let received = tokio::select! {
+                received = receiver.recv() => received,
+                _ = shutdown.cancelled() => break,
+            };
+            match received {
+                Ok(entry) => {
+                    if !entry.matches(query.level, query.target.as_deref()) {
+                        continue;
+                    }
+                    match SseEvent::default().event("log").json_data(&entry) {
+                        Ok(event) => yield Ok(event),
+                        Err(e) => warn!("Failed to encode log entry: {e}"),
+                    }
+                }
+                Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                    state.broadcast_lag.record("log_stream", skipped);
+                    yield Ok(SseEvent::default().event("lagged").data(skipped.to_string()));
+                }
+                Err(broadcast::error::RecvError::Closed) => break,
+            }
+        }
+    };
+
+    Sse::new(stream).keep_alive(KeepAlive::default())
+}
+
+/// Background task table for `/api/health`
+#[derive(Serialize)]
+#[serde(rename_all = "snake_case")]
+struct HealthData {
+    /// Every long-lived task is still running
+    healthy: bool,
+    tasks: Vec<TaskInfo>,
+    /// Broadcast subscribers that fell behind, by subscriber name
+    broadcast_lag: BTreeMap<String, LagStats>,
+    /// Replayed and first-seen idempotency keys, by endpoint
+    idempotency: BTreeMap<String, IdempotencyStats>,
+    /// Whether `/static` comes from the directory on disk or the binary
+    static_assets: StaticAssetSource,
+    /// Whether the controller firmware has every entity the sorter uses; an
+    /// incompatible controller doesn't make the server unhealthy
+    controller_firmware: FirmwareCheck,
+    /// Permits for heavy jobs and which jobs hold them
+    heavy_work: HeavyWorkUsage,
+    /// Where images and data are kept
+    directories: DataDirectories,
+    /// Browser origins other than the server's own that may call the API
+    cors: CorsStatus,
+    /// Camera detections that scanned and that were answered from a recent one
+    detection: DetectionHealth,
+}
+
+/// Whether every background component is still running, with the task table
+async fn health(State(state): State<Arc<AppState>>) -> (StatusCode, Json<ApiResponse<HealthData>>) {
+    let tasks = state.tasks.tasks();
+    let healthy = state.tasks.healthy();
+    let status = if healthy {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    (
+        status,
+        Json(ApiResponse::success(HealthData {
+            healthy,
+            tasks,
+            broadcast_lag: state.broadcast_lag.snapshot(),
+            idempotency: state.idempotency.snapshot(),
+            static_assets: state
+                .settings
+                .static_assets
+                .resolve(std::path::Path::new(STATIC_DIRECTORY)),
+            controller_firmware: state.controller.get_status().await.firmware,
+            heavy_work: state.heavy_work.usage(),
+            directories: DataDirectories::from_settings(&state.settings),
+            cors: CorsStatus::new(&state.settings.cors_allowed_origins),
+            detection: DetectionHealth {
+                usb: state.detection.usb.stats(),
+                esphome: state.detection.esphome.stats(),
+            },
+        })),
+    )
+}
+
+/// Version and build details of the running server
+async fn version() -> Json<ApiResponse<BuildInfo>> {
+    Json(ApiResponse::success(BuildInfo::current()))
+}
+
+#[cfg(test)]
+mod wire_format;
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::camera_manager::CameraManager;
+    use crate::controller_monitor::ControllerMonitor;
+    use crate::fixtures::{DEFAULT_FIXTURE_SEED, FixtureBuilder};
+    use crate::shell_data::CapturedImage;
+    use crate::usb_camera_controller::UsbCameraManager;
+    use crate::usb_camera_controller::simulated::SimulatedUsb;
+    use axum::body::to_bytes;
+    use tower::ServiceExt;
+
+    /// Build application state backed by a temporary directory, without running any managers
+    pub(crate) fn test_state(root: &std::path::Path) -> Arc<AppState> {
+        test_state_with_cameras(root, Vec::new()).0
+    }
+
+    /// Like [`test_state`], returning the ESPHome camera manager for `hostnames` so a test can run it
+    pub(crate) fn test_state_with_cameras(
+        root: &std::path::Path,
+        hostnames: Vec<String>,
+    ) -> (Arc<AppState>, CameraManager) {
+        let (state, camera_manager, _controller_monitor) =
+            test_state_with_managers(root, hostnames, Settings::default());
+        (state, camera_manager)
+    }
+
+    /// Like [`test_state_with_cameras`] from `settings`, also returning the controller monitor.
+    ///
+    /// While the unstarted monitor is held, controller requests queue up and are never answered.
+    fn test_state_with_managers(
+        root: &std::path::Path,
+        hostnames: Vec<String>,
+        settings: Settings,
+    ) -> (Arc<AppState>, CameraManager, ControllerMonitor) {
+        // Never run, so USB requests go unanswered
+        let (_usb_camera_manager, usb_camera_manager) = UsbCameraManager::new(
+            EventRecorder::default(),
+            crate::camera_warmup::CaptureWarmup::default(),
+            crate::pre_capture::PreCapture::default(),
+        )
+        .expect("USB camera manager should be created");
+        test_state_with_usb(root, hostnames, settings, usb_camera_manager)
+    }
+
+    /// Like [`test_state_with_managers`], capturing USB images from `usb_camera_manager`
+    fn test_state_with_usb(
+        root: &std::path::Path,
+        hostnames: Vec<String>,
+        settings: Settings,
+        usb_camera_manager: UsbCameraHandle,
+    ) -> (Arc<AppState>, CameraManager, ControllerMonitor) {
+        let mut settings = Settings {
+            image_directory: root.join("images"),
+            data_directory: root.join("data"),
+            models_directory: root.join("models"),
+            references_directory: root.join("references"),
+            ..settings
+        };
+        settings.sources.user_config_path = root.join("shell-sorter.json");
+        let events = EventRecorder::default();
+
+        let (controller_monitor, controller) =
+            ControllerMonitor::new(settings.clone(), events.clone())
+                .expect("controller monitor should be created");
+        let (esphome_camera_manager, camera_manager) =
+            CameraManager::new(hostnames, events.clone())
+                .expect("camera manager should be created");
+        let ml_trainer = MLTrainer::new(settings.clone());
+        let shell_data_manager = ShellDataManager::new(settings.data_directory.clone())
+            .with_training_runs(ml_trainer.training_runs());
+        let (config_writer_task, config_writer) = ConfigWriter::new(
+            settings.sources.user_config_path.clone(),
+            settings.load_user_config(),
+            DEFAULT_COALESCE_WINDOW,
+        );
+        tokio::spawn(config_writer_task.run());
+
+        let state = Arc::new(AppState {
+            config_writer,
+            camera_cache: CameraListCache::default(),
+            data_usage: UsageCache::default(),
+            session_claims: SessionClaims::default(),
+            stream_stalls: broadcast::channel(16).0,
+            broadcast_lag: LagCounters::default(),
+            tasks: TaskRegistry::default(),
+            training_jobs: TrainingJobs::default(),
+            idempotency: IdempotencyCache::default(),
+            reference_sheet: ReferenceSheetCache::default(),
+            shell_targets: ShellTargets::default(),
+            detection: DetectionGates::default(),
+            messages: Arc::new(Catalogues::load(
+                &settings.data_directory,
+                settings.ui_language.as_deref(),
+            )),
+            stream_limiter: StreamLimiter::new(settings.max_concurrent_streams),
+            heavy_work: HeavyWork::new(settings.heavy_work_permits),
+            snapshot_streams: SnapshotStreams::new(
+                camera_manager.clone(),
+                Duration::from_millis(settings.esp_snapshot_poll_ms),
+            )
+            .expect("snapshot streams should be created"),
+            disk_space: DiskSpaceGuard::new(&settings),
+            ml_trainer: Arc::new(Mutex::new(ml_trainer)),
+            shell_data_manager: Arc::new(shell_data_manager),
+            settings,
+            controller,
+            camera_manager: Box::new(camera_manager),
+            usb_camera_manager: Box::new(usb_camera_manager),
+            events,
+            logs: LogBuffer::default(),
+        });
+        (state, esphome_camera_manager, controller_monitor)
+    }
+
+    async fn post_json(
+        state: Arc<AppState>,
+        uri: &str,
+        body: serde_json::Value,
+    ) -> (StatusCode, serde_json::Value) {
+        send_json(state, "POST", uri, body).await
+    }
+
+    /// Send `body` as JSON with `method` and return the status and parsed response
+    async fn send_json(
+        state: Arc<AppState>,
+        method: &str,
+        uri: &str,
+        body: serde_json::Value,
+    ) -> (StatusCode, serde_json::Value) {
+        let request = Request::builder()
+            .method(method)
+            .uri(uri)
+            .header("content-type", "application/json")
+            .body(Body::from(body.to_string()))
+            .expect("request should build");
+        let response = create_router(state)
+            .oneshot(request)
+            .await
+            .expect("router should respond");
+        let status = response.status();
+        let bytes = to_bytes(response.into_body(), usize::MAX)
+            .await
+            .expect("body should be readable");
+        let json = serde_json::from_slice(&bytes).unwrap_or(serde_json::Value::Null);
+        (status, json)
+    }
+
+    async fn get_json(state: Arc<AppState>, uri: &str) -> (StatusCode, serde_json::Value) {
+        let request = Request::builder()
+            .uri(uri)
+            .body(Body::empty())
+            .expect("request should build");
+        let response = create_router(state)
+            .oneshot(request)
+            .await
+            .expect("router should respond");
+        let status = response.status();
+        let bytes = to_bytes(response.into_body(), usize::MAX)
+            .await
+            .expect("body should be readable");
+        let json = serde_json::from_slice(&bytes).unwrap_or(serde_json::Value::Null);
+        (status, json)
+    }
+
+    /// Every (method, path) the server answers, kept by hand so route changes are deliberate
+    const EXPECTED_ROUTES: &[(&str, &str)] = &[
+        ("GET", "/"),
+        ("GET", "/config"),
+        ("GET", "/setup"),
+        ("GET", "/shell-edit/{session_id}"),
+        ("GET", "/tagging/{session_id}"),
+        ("GET", "/images/{filename}"),
+        ("GET", "/api/status"),
+        ("POST", "/api/machine/next-case"),
+        ("GET", "/api/machine/status"),
+        ("GET", "/api/machine/health-history"),
+        ("GET", "/api/machine/sensors"),
+        ("GET", "/api/machine/hardware-status"),
+        ("GET", "/api/machine/calibration"),
+        ("POST", "/api/machine/calibration/start"),
+        ("POST", "/api/machine/calibration/jog"),
+        ("POST", "/api/machine/calibration/save"),
+        ("POST", "/api/machine/self-test"),
+        ("POST", "/api/machine/maintenance"),
+        ("GET", "/api/cameras"),
+        ("GET", "/api/cameras/detect"),
+        ("POST", "/api/cameras/select"),
+        ("POST", "/api/cameras/start-selected"),
+        ("POST", "/api/cameras/stop-all"),
+        ("POST", "/api/cameras/capture"),
+        ("GET", "/api/cameras/{camera_id}/stream"),
+        ("GET", "/api/cameras/{camera_id}/snapshot"),
+        ("GET", "/api/cameras/{camera_id}/esp-settings"),
+        ("POST", "/api/cameras/{camera_id}/esp-settings"),
+        ("GET", "/api/cameras/{camera_id}/stats"),
+        ("POST", "/api/cameras/{camera_id}/stats/reset"),
+        ("GET", "/api/cameras/{camera_id}/brightness"),
+        ("POST", "/api/cameras/{camera_id}/brightness"),
+        ("POST", "/api/cameras/{index}/view-type"),
+        ("GET", "/api/cameras/{camera_id}/frame-info"),
+        ("POST", "/api/cameras/{camera_id}/region"),
+        ("DELETE", "/api/cameras/{camera_id}/region"),
+        ("GET", "/api/camera-presets"),
+        ("POST", "/api/camera-presets"),
+        ("GET", "/api/camera-presets/{name}"),
+        ("PUT", "/api/camera-presets/{name}"),
+        ("DELETE", "/api/camera-presets/{name}"),
+        ("POST", "/api/camera-presets/{name}/apply"),
+        ("GET", "/api/case-designations"),
+        ("GET", "/api/data/usage"),
+        ("POST", "/api/data/migrate"),
+        ("GET", "/api/data/export"),
+        ("GET", "/api/data/measurements"),
+        ("POST", "/api/data/import"),
+        ("GET", "/api/batches"),
+        ("POST", "/api/batches"),
+        ("POST", "/api/batches/deactivate"),
+        ("DELETE", "/api/batches/{batch_id}"),
+        ("POST", "/api/batches/{batch_id}/activate"),
+        ("POST", "/api/batches/{batch_id}/training"),
+        ("GET", "/api/shells"),
+        ("GET", "/api/shells/search"),
+        ("GET", "/api/shells/brands"),
+        ("GET", "/api/shells/integrity"),
+        ("POST", "/api/shells/propagate-region"),
+        ("POST", "/api/shells/save"),
+        ("POST", "/api/shells/import-bundle"),
+        ("GET", "/api/shells/{session_id}"),
+        ("PATCH", "/api/shells/{session_id}"),
+        ("DELETE", "/api/shells/{session_id}"),
+        ("GET", "/api/shells/{session_id}/export"),
+        ("POST", "/api/shells/{session_id}/images"),
+        (
+            "POST",
+            "/api/shells/{session_id}/images/{filename}/classify-view",
+        ),
+        ("POST", "/api/shells/{session_id}/toggle"),
+        ("POST", "/api/shells/{session_id}/measurements"),
+        ("GET", "/api/trash"),
+        ("POST", "/api/trash/{session_id}/restore"),
+        ("GET", "/api/sessions/claims"),
+        ("POST", "/api/sessions/{session_id}/claim"),
+        ("DELETE", "/api/sessions/{session_id}/claim"),
+        ("POST", "/api/sessions/{session_id}/recapture"),
+        ("GET", "/api/sessions/{session_id}/settings"),
+        ("GET", "/api/sessions/{session_id}/diff/{other_session_id}"),
+        ("GET", "/api/ml/shells"),
+        ("POST", "/api/ml/generate-composites"),
+        ("GET", "/api/ml/reference-sheet"),
+        ("GET", "/api/ml/composite-layout"),
+        ("PUT", "/api/ml/composite-layout"),
+        ("GET", "/api/ml/reconcile"),
+        ("POST", "/api/ml/reconcile"),
+        ("GET", "/api/ml/progress"),
+        ("GET", "/api/ml/progress/events"),
+        ("GET", "/api/work-queue"),
+        ("GET", "/api/ml/models/{name}/metrics"),
+        ("GET", "/api/case-types"),
+        ("POST", "/api/case-types"),
+        ("PATCH", "/api/case-types/{name}"),
+        ("POST", "/api/case-types/{name}/reference-images"),
+        ("POST", "/api/case-types/{name}/training-images"),
+        ("POST", "/api/train-model"),
+        ("POST", "/api/training-jobs"),
+        ("GET", "/api/training-jobs/{job_id}"),
+        ("GET", "/api/setup/status"),
+        ("POST", "/api/setup/scan"),
+        ("POST", "/api/setup/apply"),
+        ("GET", "/api/config"),
+        ("POST", "/api/config"),
+        ("DELETE", "/api/config/cameras/{index}"),
+        ("POST", "/api/config/cameras/import"),
+        ("GET", "/api/config/cameras/export"),
+        ("DELETE", "/api/config/cameras"),
+        ("POST", "/api/config/reset"),
+        ("GET", "/api/config/schema"),
+        ("POST", "/api/config/validate"),
+        ("GET", "/api/events"),
+        ("GET", "/api/logs"),
+        ("GET", "/api/logs/stream"),
+        ("GET", "/api/health"),
+        ("GET", "/api/version"),
+    ];
+
+    #[tokio::test]
+    async fn test_wedged_controller_answers_504_within_the_deadline() {
+        let temp_dir = tempfile::TempDir::new().expect("temp dir should be created");
+        let settings = Settings {
+            hardware_request_timeout_ms: 100,
+            ..Settings::default()
+        };
+        let (state, _camera_manager, _wedged_controller) =
+            test_state_with_managers(temp_dir.path(), Vec::new(), settings);
+
+        for (method, uri) in [
+            ("GET", "/api/machine/status"),
+            ("GET", "/api/machine/sensors"),
+            ("POST", "/api/machine/next-case"),
+        ] {
+            let request = Request::builder()
+                .method(method)
+                .uri(uri)
+                .body(Body::empty())
+                .expect("request should build");
+            let started = std::time::Instant::now();
+            let response = tokio::time::timeout(
+                Duration::from_secs(5),
+                create_router(state.clone()).oneshot(request),
+            )
+            .await
+            .expect("the deadline should end the request")
+            .expect("router should respond");
+            let elapsed = started.elapsed();
+            assert!(elapsed >= Duration::from_millis(100), "{uri}: {elapsed:?}");
+            assert!(elapsed < Duration::from_secs(2), "{uri}: {elapsed:?}");
+            assert_eq!(response.status(), StatusCode::GATEWAY_TIMEOUT, "{uri}");
+
+            let bytes = to_bytes(response.into_body(), usize::MAX)
+                .await
+                .expect("body should be readable");
+            let json: serde_json::Value =
+                serde_json::from_slice(&bytes).expect("response should be JSON");
+            assert_eq!(json["success"], false, "{uri}");
+            assert_eq!(json["error"]["code"], "controller_timeout", "{uri}");
+            assert_eq!(
+                json["message"], "Controller monitor did not respond within 100ms",
+                "{uri}"
+            );
+        }
+
+        // Streams have no deadline of their own, and routes without hardware waits are unaffected
+        let streams: Vec<_> = route_table()
+            .into_iter()
+            .filter(|route| route.path.ends_with("/stream") && route.hardware_backend.is_some())
+            .map(|route| route.path)
+            .collect();
+        assert!(streams.is_empty(), "{streams:?}");
+        let request = Request::builder()
+            .uri("/api/version")
+            .body(Body::empty())
+            .expect("request should build");
+        let response = create_router(state)
+            .oneshot(request)
+            .await
+            .expect("router should respond");
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_router_registers_exactly_the_expected_routes() {
+        let table: Vec<(&str, &str)> = route_table()
+            .iter()
+            .map(|route| (route.method.as_str(), route.path))
+            .collect();
+
+        let mut seen = std::collections::HashSet::new();
+        let duplicates: Vec<_> = table.iter().filter(|pair| !seen.insert(*pair)).collect();
+        assert!(duplicates.is_empty(), "registered twice: {duplicates:?}");
+
+        let unexpected: Vec<_> = table
+            .iter()
+            .filter(|pair| !EXPECTED_ROUTES.contains(pair))
+            .collect();
+        let missing: Vec<_> = EXPECTED_ROUTES
+            .iter()
+            .filter(|pair| !table.contains(pair))
+            .collect();
+        assert!(
+            unexpected.is_empty() && missing.is_empty(),
+            "route table changed, update EXPECTED_ROUTES.\nunexpected: {unexpected:?}\nmissing: {missing:?}"
+        );
+        let health = route_table()
+            .into_iter()
+            .find(|route| route.path == "/api/health")
+            .map(|route| route.handler);
+        assert_eq!(health, Some("health"), "routes should name their handler");
+
+        // Ask the built router which methods each path allows, without running any handler
+        let temp_dir = tempfile::TempDir::new().expect("temp dir should be created");
+        let router = create_router(test_state(temp_dir.path()));
+        let mut paths: Vec<&str> = EXPECTED_ROUTES.iter().map(|(_, path)| *path).collect();
+        paths.sort_unstable();
+        paths.dedup();
+        for path in paths {
+            let uri = path
+                .split('/')
+                .map(|segment| {
+                    if segment.starts_with('{') {
+                        "0"
+                    } else {
+                        segment
+                    }
+                })
+                .collect::<Vec<_>>()
+                .join("/");
+            let request = Request::builder()
+                .method("TRACE")
+                .uri(&uri)
+                .body(Body::empty())
+                .expect("request should build");
+            let response = router
+                .clone()
+                .oneshot(request)
+                .await
+                .expect("router should respond");
+            assert_eq!(response.status(), StatusCode::METHOD_NOT_ALLOWED, "{path}");
+
+            let allow = response
+                .headers()
+                .get("allow")
+                .and_then(|allow| allow.to_str().ok())
+                .unwrap_or_default();
+            let mut allowed: Vec<&str> = allow
+                .split(',')
+                .map(str::trim)
+                .filter(|method| *method != "HEAD")
+                .collect();
+            allowed.sort_unstable();
+            let mut expected: Vec<&str> = EXPECTED_ROUTES
+                .iter()
+                .filter(|(_, expected_path)| *expected_path == path)
+                .map(|(method, _)| *method)
+                .collect();
+            expected.sort_unstable();
+            assert_eq!(allowed, expected, "{path}");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_save_shell_accepts_tagging_page_payload() {
+        let temp_dir = tempfile::TempDir::new().expect("temp dir should be created");
+        let state = test_state(temp_dir.path());
+        let session_id = ShellDataManager::generate_session_id();
+
+        // Exactly what templates/tagging.html sends
+        let payload = serde_json::json!({
+            "session_id": session_id,
+            "brand": "Winchester",
+            "shell_type": "9mm",
+            "image_filenames": ["a.jpg", "b.jpg"],
+            "view_types": {"a.jpg": "side", "b.jpg": "tail"},
+            "notes": " dented mouth ",
+            "flags": ["Damaged", "berdan primer"],
+        });
+        let (status, body) = post_json(state.clone(), "/api/shells/save", payload).await;
+        assert_eq!(status, StatusCode::OK, "unexpected response: {body}");
+
+        let shell = state
+            .shell_data_manager
+            .load_shell(&session_id)
+            .expect("shell should be persisted");
+        assert_eq!(shell.brand, "Winchester");
+        assert_eq!(shell.shell_type, "9mm");
+        assert_eq!(shell.image_filenames, vec!["a.jpg", "b.jpg"]);
+        assert!(shell.include);
+        assert_eq!(shell.notes.as_deref(), Some("dented mouth"));
+        assert_eq!(
+            shell.flags,
+            vec![
+                ShellFlag::Damaged,
+                ShellFlag::Other("berdan primer".to_string())
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_save_shell_rejects_bad_payloads() {
+        let temp_dir = tempfile::TempDir::new().expect("temp dir should be created");
+        let state = test_state(temp_dir.path());
+        let session_id = ShellDataManager::generate_session_id();
+
+        let empty_brand = serde_json::json!({
+            "session_id": session_id,
+            "brand": " ",
+            "shell_type": "9mm",
+            "image_filenames": [],
+        });
+        let (status, _) = post_json(state.clone(), "/api/shells/save", empty_brand).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+
+        let bad_session = serde_json::json!({
+            "session_id": "../../etc/passwd",
+            "brand": "Winchester",
+            "shell_type": "9mm",
+            "image_filenames": [],
+        });
+        let (status, _) = post_json(state.clone(), "/api/shells/save", bad_session).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+
+        let unknown_field = serde_json::json!({
+            "session_id": session_id,
+            "brand": "Winchester",
+            "shell_typ": "9mm",
+            "image_filenames": [],
+        });
+        let (status, _) = post_json(state.clone(), "/api/shells/save", unknown_field).await;
+        assert!(status.is_client_error());
+
+        assert!(state.shell_data_manager.load_shell(&session_id).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_shell_measurements_are_saved_checked_and_reported() {
+        let temp_dir = tempfile::TempDir::new().expect("temp dir should be created");
+        let state = test_state(temp_dir.path());
+        let mut sessions = Vec::new();
+        for weight in [60.0, 61.0, 60.5] {
+            let session_id = ShellDataManager::generate_session_id();
+            let payload = serde_json::json!({
+                "session_id": session_id,
+                "brand": "Winchester",
+                "shell_type": "9mm",
+                "image_filenames": [],
+                "weight_grains": weight,
+            });
+            let (status, body) = post_json(state.clone(), "/api/shells/save", payload).await;
+            assert_eq!(status, StatusCode::OK, "unexpected response: {body}");
+            assert!(body["data"].get("warnings").is_none(), "{body}");
+            sessions.push(session_id);
+        }
+        let (_, shell) = get_json(state.clone(), &format!("/api/shells/{}", sessions[0])).await;
+        assert_eq!(shell["data"]["weight_grains"], 60.0);
+        assert!(shell["data"]["measured_at"].is_string(), "{shell}");
+
+        // A 9mm at 180 grains is saved, with a warning
+        let uri = format!("/api/shells/{}", sessions[1]);
+        let heavy = serde_json::json!({"revision": 1, "weight_grains": 180.0});
+        let (status, body) = send_json(state.clone(), "PATCH", &uri, heavy).await;
+        assert_eq!(status, StatusCode::OK, "unexpected response: {body}");
+        assert_eq!(body["data"]["warnings"][0]["measurement"], "weight_grains");
+        assert_eq!(body["data"]["warnings"][0]["expected"]["max"], 66.0);
+        let negative = serde_json::json!({"revision": 2, "length_mm": -1.0});
+        let (status, body) = send_json(state.clone(), "PATCH", &uri, negative).await;
+        assert_eq!(
+            status,
+            StatusCode::BAD_REQUEST,
+            "unexpected response: {body}"
+        );
+
+        // A scale pushes a reading without knowing the revision
+        let uri = format!("/api/shells/{}/measurements", sessions[2]);
+        let reading =
+            serde_json::json!({"weight_grains": 48.0, "length_mm": 17.3, "source": "scale"});
+        let (status, body) = post_json(state.clone(), &uri, reading).await;
+        assert_eq!(status, StatusCode::OK, "unexpected response: {body}");
+        assert_eq!(body["data"]["revision"], 2);
+        assert_eq!(
+            body["data"]["warnings"].as_array().map(Vec::len),
+            Some(2),
+            "{body}"
+        );
+        let (status, _) = post_json(state.clone(), &uri, serde_json::json!({})).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        let missing = "/api/shells/missing/measurements";
+        let (status, _) = post_json(
+            state.clone(),
+            missing,
+            serde_json::json!({"weight_grains": 60}),
+        )
+        .await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+
+        let (status, body) = get_json(
+            state.clone(),
+            "/api/shells/search?min_weight=50&max_weight=100",
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK, "unexpected response: {body}");
+        let found: Vec<&str> = body["data"]
+            .as_array()
+            .expect("search should list shells")
+            .iter()
+            .filter_map(|shell| shell["session_id"].as_str())
+            .collect();
+        assert_eq!(found, vec![sessions[0].as_str()]);
+
+        let (status, body) = get_json(state.clone(), "/api/data/measurements").await;
+        assert_eq!(status, StatusCode::OK, "unexpected response: {body}");
+        let nine = &body["data"][0];
+        assert_eq!(nine["case_type"], "Winchester_9mm");
+        assert_eq!(nine["weight_grains"]["count"], 3);
+        assert_eq!(nine["weight_grains"]["max"], 180.0);
+        let mut out_of_range: Vec<&str> = nine["out_of_range"]
+            .as_array()
+            .expect("out of range shells should be listed")
+            .iter()
+            .filter_map(serde_json::Value::as_str)
+            .collect();
+        out_of_range.sort();
+        let mut expected = vec![sessions[1].as_str(), sessions[2].as_str()];
+        expected.sort();
+        assert_eq!(out_of_range, expected);
+    }
+
+    #[tokio::test]
+    async fn test_second_tab_saving_a_stale_shell_gets_a_conflict() {
+        let temp_dir = tempfile::TempDir::new().expect("temp dir should be created");
+        let state = test_state(temp_dir.path());
+        let session_id = ShellDataManager::generate_session_id();
+        let payload = serde_json::json!({
+            "session_id": session_id,
+            "brand": "Winchester",
+            "shell_type": "9mm",
+            "image_filenames": [],
+        });
+        let (status, body) = post_json(state.clone(), "/api/shells/save", payload).await;
+        assert_eq!(status, StatusCode::OK, "unexpected response: {body}");
+        assert_eq!(body["data"]["revision"], 1);
+
+        // The tablet and the desktop both open the shell at revision 1
+        let uri = format!("/api/shells/{session_id}");
+        let tablet = serde_json::json!({"revision": 1, "brand": "Federal"});
+        let (status, body) = send_json(state.clone(), "PATCH", &uri, tablet).await;
+        assert_eq!(status, StatusCode::OK, "unexpected response: {body}");
+        assert_eq!(body["data"]["revision"], 2);
+
+        let desktop = serde_json::json!({"revision": 1, "notes": "dented", "include": false});
+        let (status, body) = send_json(state.clone(), "PATCH", &uri, desktop).await;
+        assert_eq!(status, StatusCode::CONFLICT, "unexpected response: {body}");
+        assert_eq!(body["success"], false);
+        assert_eq!(body["error"]["code"], "revision_conflict");
+        assert_eq!(body["data"]["revision"], 2);
+        assert_eq!(body["data"]["current"]["brand"], "Federal");
+        assert_eq!(body["data"]["current"]["revision"], 2);
+
+        // The tablet's edit survived and the desktop's didn't land
+        let shell = state
+            .shell_data_manager
+            .load_shell(&session_id)
+            .expect("shell should load");
+        assert_eq!(shell.brand, "Federal");
+        assert_eq!(shell.notes, None);
+        assert!(shell.include);
+
+        // Resaving from the tagging page at the old revision is refused the same way
+        let retag = serde_json::json!({
+            "session_id": session_id,
+            "brand": "Remington",
+            "shell_type": "9mm",
+            "image_filenames": [],
+            "revision": 1,
+        });
+        let (status, body) = post_json(state.clone(), "/api/shells/save", retag).await;
+        assert_eq!(status, StatusCode::CONFLICT, "unexpected response: {body}");
+
+        // Toggling skips the check and moves the revision on
+        let (status, _) = post_json(
+            state.clone(),
+            &format!("/api/shells/{session_id}/toggle"),
+            serde_json::json!({}),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        let desktop = serde_json::json!({"revision": 3, "notes": "dented"});
+        let (status, body) = send_json(state.clone(), "PATCH", &uri, desktop).await;
+        assert_eq!(status, StatusCode::OK, "unexpected response: {body}");
+        assert_eq!(body["data"]["revision"], 4);
+
+        let (status, _) = send_json(
+            state.clone(),
+            "PATCH",
+            "/api/shells/missing",
+            serde_json::json!({"revision": 1}),
+        )
+        .await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_tagging_reads_the_shell_and_known_brands() {
+        let temp_dir = tempfile::TempDir::new().expect("temp dir should be created");
+        let state = test_state(temp_dir.path());
+        for brand in ["Winchester", "federal", " Federal ", "Blazer"] {
+            let session_id = ShellDataManager::generate_session_id();
+            let shell = Shell::new(brand.to_string(), "9mm".to_string());
+            state
+                .shell_data_manager
+                .save_shell(&session_id, &shell)
+                .expect("shell should save");
+        }
+        let session_id = ShellDataManager::generate_session_id();
+        let mut shell = Shell::new(String::new(), "9mm".to_string());
+        shell.add_image(format!("{session_id}_camera_0.jpg"));
+        state
+            .shell_data_manager
+            .save_shell(&session_id, &shell)
+            .expect("shell should save");
+
+        let (status, body) = get_json(state.clone(), "/api/shells/brands").await;
+        assert_eq!(status, StatusCode::OK, "unexpected response: {body}");
+        assert_eq!(
+            body["data"],
+            serde_json::json!(["Blazer", "Federal", "Winchester"])
+        );
+
+        let (status, body) = get_json(state.clone(), &format!("/api/shells/{session_id}")).await;
+        assert_eq!(status, StatusCode::OK, "unexpected response: {body}");
+        assert_eq!(
+            body["data"]["image_filenames"],
+            serde_json::json!([format!("{session_id}_camera_0.jpg")])
+        );
+        assert_eq!(body["data"]["revision"], 1);
+
+        let (status, body) = get_json(state.clone(), "/api/shells/missing").await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_eq!(body["error"]["code"], "shell_not_found");
+    }
+
+    #[tokio::test]
+    async fn test_deleted_shells_go_to_the_trash_until_restored() {
+        let temp_dir = tempfile::TempDir::new().expect("temp dir should be created");
+        let state = test_state(temp_dir.path());
+        let images = temp_dir.path().join("images");
+        std::fs::create_dir_all(&images).expect("image directory should be created");
+        let session_id = ShellDataManager::generate_session_id();
+        let filename = format!("{session_id}_camera_0.jpg");
+        std::fs::write(images.join(&filename), b"jpeg").expect("image should be written");
+        let mut shell = Shell::new("Starline".to_string(), "45acp".to_string());
+        shell.add_captured_image(CapturedImage::new(
+            0,
+            filename.clone(),
+            "Camera 0".to_string(),
+            ViewType::Side,
+        ));
+        state
+            .shell_data_manager
+            .save_shell(&session_id, &shell)
+            .expect("shell should save");
+        let shell_uri = format!("/api/shells/{session_id}");
+
+        let (status, body) =
+            send_json(state.clone(), "DELETE", &shell_uri, serde_json::Value::Null).await;
+        assert_eq!(status, StatusCode::OK, "unexpected response: {body}");
+        assert_eq!(body["data"]["permanent"], false);
+        assert_eq!(
+            body["data"]["trashed"]["images"],
+            serde_json::json!([&filename])
+        );
+        assert!(!images.join(&filename).exists());
+
+        let (status, _) = get_json(state.clone(), &shell_uri).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        let (_, body) = get_json(state.clone(), "/api/shells").await;
+        assert_eq!(body["data"], serde_json::json!([]));
+        let (status, body) = get_json(state.clone(), "/api/trash").await;
+        assert_eq!(status, StatusCode::OK, "unexpected response: {body}");
+        assert_eq!(body["data"][0]["session_id"], session_id);
+        assert_eq!(body["data"][0]["brand"], "Starline");
+        assert_eq!(
+            state.events.recent(10, Some("ShellTrashed")).len(),
+            1,
+            "the delete should be recorded"
+        );
+
+        let restore_uri = format!("/api/trash/{session_id}/restore");
+        let (status, body) = post_json(state.clone(), &restore_uri, serde_json::Value::Null).await;
+        assert_eq!(status, StatusCode::OK, "unexpected response: {body}");
+        assert_eq!(body["data"]["brand"], "Starline");
+        assert_eq!(
+            std::fs::read(images.join(&filename)).expect("image should be back"),
+            b"jpeg"
+        );
+        let (status, _) = get_json(state.clone(), &shell_uri).await;
+        assert_eq!(status, StatusCode::OK);
+        let (status, body) = post_json(state.clone(), &restore_uri, serde_json::Value::Null).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_eq!(body["error"]["code"], "shell_not_found");
+
+        let (status, body) = send_json(
+            state.clone(),
+            "DELETE",
+            &format!("{shell_uri}?permanent=true"),
+            serde_json::Value::Null,
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK, "unexpected response: {body}");
+        assert_eq!(body["data"]["permanent"], true);
+        assert!(!images.join(&filename).exists());
+        let (_, body) = get_json(state.clone(), "/api/trash").await;
+        assert_eq!(body["data"], serde_json::json!([]));
+        assert_eq!(state.events.recent(10, Some("ShellDeleted")).len(), 1);
+
+        let (status, body) =
+            send_json(state.clone(), "DELETE", &shell_uri, serde_json::Value::Null).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_eq!(body["error"]["code"], "shell_not_found");
+    }
+
+    #[tokio::test]
+    async fn test_saving_over_a_claim_needs_force() {
+        let temp_dir = tempfile::TempDir::new().expect("temp dir should be created");
+        let state = test_state(temp_dir.path());
+        let session_id = ShellDataManager::generate_session_id();
+        let claim_uri = format!("/api/sessions/{session_id}/claim");
+
+        let (status, body) = post_json(
+            state.clone(),
+            &claim_uri,
+            serde_json::json!({"claimant": "Alice"}),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK, "unexpected response: {body}");
+        assert_eq!(body["data"]["claimant"], "Alice");
+
+        let (status, body) = post_json(
+            state.clone(),
+            &claim_uri,
+            serde_json::json!({"claimant": "Bob"}),
+        )
+        .await;
+        assert_eq!(status, StatusCode::CONFLICT, "unexpected response: {body}");
+        assert_eq!(body["error"]["code"], "session_claimed");
+        assert_eq!(body["data"]["claimant"], "Alice");
+
+        let (_, body) = get_json(state.clone(), "/api/sessions/claims").await;
+        assert_eq!(body["data"][0]["session_id"], session_id.as_str());
+
+        let save = |claimant: &str, force: bool| {
+            serde_json::json!({
+                "session_id": session_id,
+                "brand": claimant,
+                "shell_type": "9mm",
+                "image_filenames": [],
+                "claimant": claimant,
+                "force": force,
+            })
+        };
+        let (status, body) = post_json(state.clone(), "/api/shells/save", save("Bob", false)).await;
+        assert_eq!(status, StatusCode::CONFLICT, "unexpected response: {body}");
+        assert_eq!(body["error"]["code"], "session_claimed");
+        assert_eq!(body["data"]["claimed_by"]["claimant"], "Alice");
+        assert!(state.shell_data_manager.load_shell(&session_id).is_err());
+
+        // The claimant saves without forcing; anyone else has to force it
+        let (status, body) =
+            post_json(state.clone(), "/api/shells/save", save("Alice", false)).await;
+        assert_eq!(status, StatusCode::OK, "unexpected response: {body}");
+        let mut forced = save("Bob", true);
+        forced["revision"] = 1.into();
+        let (status, body) = post_json(state.clone(), "/api/shells/save", forced).await;
+        assert_eq!(status, StatusCode::OK, "unexpected response: {body}");
+        let shell = state
+            .shell_data_manager
+            .load_shell(&session_id)
+            .expect("shell should load");
+        assert_eq!(shell.brand, "Bob");
+
+        // Only the holder can release the claim, after which anyone can save
+        let (status, _) = send_json(
+            state.clone(),
+            "DELETE",
+            &format!("{claim_uri}?claimant=Bob"),
+            serde_json::json!({}),
+        )
+        .await;
+        assert_eq!(status, StatusCode::CONFLICT);
+        let (status, _) = send_json(
+            state.clone(),
+            "DELETE",
+            &format!("{claim_uri}?claimant=Alice"),
+            serde_json::json!({}),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        let patch = serde_json::json!({"revision": 2, "notes": "checked", "claimant": "Bob"});
+        let (status, body) = send_json(
+            state.clone(),
+            "PATCH",
+            &format!("/api/shells/{session_id}"),
+            patch,
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK, "unexpected response: {body}");
+
+        let (status, _) = post_json(
+            state.clone(),
+            "/api/sessions/..%2Fescape/claim",
+            serde_json::json!({"claimant": "Alice"}),
+        )
+        .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_data_usage_is_cached_until_refreshed() {
+        let temp_dir = tempfile::TempDir::new().expect("temp dir should be created");
+        let state = test_state(temp_dir.path());
+        let images = &state.settings.image_directory;
+        let fixtures = FixtureBuilder::new(DEFAULT_FIXTURE_SEED)
+            .shells(1)
+            .case_types([("Winchester", "9mm")])
+            .views(&[ViewType::Side])
+            .write(&state.settings.data_directory, images)
+            .expect("fixtures should be written");
+        let session_id = fixtures.session_ids()[0];
+        let image_bytes = std::fs::metadata(&fixtures.images[0])
+            .expect("image should exist")
+            .len();
+
+        let (status, body) = get_json(state.clone(), "/api/data/usage").await;
+        assert_eq!(status, StatusCode::OK, "{body}");
+        assert_eq!(body["data"]["image_bytes"], image_bytes);
+        assert_eq!(body["data"]["sessions"][0]["session_id"], session_id);
+        assert_eq!(body["data"]["case_types"][0]["case_type"], "Winchester_9mm");
+        assert_eq!(
+            body["data"]["case_types"][0]["bytes"],
+            body["data"]["sessions"][0]["bytes"]
+        );
+
+        std::fs::write(images.join("stray.jpg"), [0u8; 50]).expect("image should be written");
+        let (_, cached) = get_json(state.clone(), "/api/data/usage").await;
+        assert_eq!(cached["data"]["image_bytes"], image_bytes);
+        assert_eq!(cached["data"]["scanned_at"], body["data"]["scanned_at"]);
+
+        let (_, refreshed) = get_json(state.clone(), "/api/data/usage?refresh=true&limit=0").await;
+        assert_eq!(refreshed["data"]["image_bytes"], image_bytes + 50);
+        assert_eq!(refreshed["data"]["unattributed_bytes"], 50);
+        assert_eq!(refreshed["data"]["sessions"], serde_json::json!([]));
+    }
+
+    #[tokio::test]
+    async fn test_reference_sheet_has_a_tile_per_case_type() {
+        let temp_dir = tempfile::TempDir::new().expect("temp dir should be created");
+        let state = test_state(temp_dir.path());
+        let (status, body) = get_json(state.clone(), "/api/ml/reference-sheet").await;
+        assert_eq!(status, StatusCode::NOT_FOUND, "{body}");
+        assert_eq!(body["error"]["code"], "case_type_not_found");
+
+        FixtureBuilder::new(DEFAULT_FIXTURE_SEED)
+            .shells(3)
+            .case_types([
+                ("Winchester", "9mm"),
+                ("Federal", "308win"),
+                ("PMC", "40sw"),
+            ])
+            .with_case_types_file()
+            .write(
+                &state.settings.data_directory,
+                &state.settings.image_directory,
+            )
+            .expect("fixtures should be written");
+        state
+            .ml_trainer
+            .lock()
+            .expect("trainer lock")
+            .load_case_types()
+            .expect("case types should load");
+
+        let fetch = |uri: &'static str| {
+            let state = state.clone();
+            async move {
+                let request = Request::builder()
+                    .uri(uri)
+                    .body(Body::empty())
+                    .expect("request should build");
+                let response = create_router(state)
+                    .oneshot(request)
+                    .await
+                    .expect("router should respond");
+                assert_eq!(response.status(), StatusCode::OK);
+                assert_eq!(response.headers()["Content-Type"], "image/jpeg");
+                to_bytes(response.into_body(), usize::MAX)
+                    .await
+                    .expect("body should be readable")
+            }
+        };
+        let jpeg = fetch("/api/ml/reference-sheet?format=jpg&columns=2").await;
+        let sheet = image::load_from_memory(&jpeg).expect("sheet should decode");
+        assert_eq!(
+            (sheet.width(), sheet.height()),
+            (
+                2 * crate::reference_sheet::TILE_WIDTH,
+                2 * (crate::reference_sheet::TILE_HEIGHT + crate::reference_sheet::LABEL_HEIGHT)
+            )
+        );
+        assert_eq!(
+            fetch("/api/ml/reference-sheet?columns=2").await,
+            jpeg,
+            "an unchanged sheet comes from the cache"
+        );
+        let wide = fetch("/api/ml/reference-sheet").await;
+        let wide = image::load_from_memory(&wide).expect("sheet should decode");
+        assert_eq!(wide.width(), 3 * crate::reference_sheet::TILE_WIDTH);
+
+        let (status, _) = get_json(state.clone(), "/api/ml/reference-sheet?format=pdf").await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        let (status, _) = get_json(state, "/api/ml/reference-sheet?columns=0").await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_migrate_rewrites_old_shell_records() {
+        let temp_dir = tempfile::TempDir::new().expect("temp dir should be created");
+        let state = test_state(temp_dir.path());
+        let data_directory = &state.settings.data_directory;
+        std::fs::create_dir_all(data_directory).expect("data directory should be created");
+        std::fs::write(
+            data_directory.join("old.json"),
+            r#"{"date_captured": "2024-11-03T14:22:51", "brand": "Remington", "shell_type": "45acp", "image_filenames": [], "include": true}"#,
+        )
+        .expect("shell should be written");
+
+        let (status, body) = post_json(
+            state.clone(),
+            "/api/data/migrate",
+            serde_json::json!({"backup": false}),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK, "{body}");
+        assert_eq!(body["data"]["migrated"]["old"], 1, "{body}");
+        assert_eq!(body["data"]["backup_directory"], serde_json::Value::Null);
+        let shell = state
+            .shell_data_manager
+            .load_shell("old")
+            .expect("migrated shell should load");
+        assert_eq!(
+            shell.schema_version,
+            crate::shell_migrations::CURRENT_SHELL_SCHEMA
+        );
+
+        let (_, again) = post_json(state.clone(), "/api/data/migrate", serde_json::json!({})).await;
+        assert_eq!(again["data"]["migrated"], serde_json::json!({}));
+        assert_eq!(again["data"]["current"], 1);
+    }
+
+    #[tokio::test]
+    async fn test_save_shell_normalises_case_designations() {
+        let temp_dir = tempfile::TempDir::new().expect("temp dir should be created");
+        let state = test_state(temp_dir.path());
+        let save = |shell_type: &str, allow_new_type: bool| {
+            let session_id = ShellDataManager::generate_session_id();
+            let payload = serde_json::json!({
+                "session_id": session_id,
+                "brand": "Winchester",
+                "shell_type": shell_type,
+                "image_filenames": [],
+                "allow_new_type": allow_new_type,
+            });
+            let state = state.clone();
+            async move {
+                let (status, body) = post_json(state.clone(), "/api/shells/save", payload).await;
+                let stored = state
+                    .shell_data_manager
+                    .load_shell(&session_id)
+                    .ok()
+                    .map(|shell| shell.shell_type);
+                (status, body, stored)
+            }
+        };
+
+        for value in ["9MM", " 9mm ", "9x19"] {
+            let (status, body, stored) = save(value, false).await;
+            assert_eq!(status, StatusCode::OK, "{value:?}: {body}");
+            assert_eq!(stored.as_deref(), Some("9mm"), "{value:?}");
+        }
+
+        let (status, body, stored) = save("10mm", false).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["error"]["code"], "validation_failed", "{body}");
+        assert!(
+            body["error"]["field_errors"]["shell_type"]
+                .as_str()
+                .is_some_and(|error| error.contains("allow_new_type")),
+            "{body}"
+        );
+        assert_eq!(stored, None);
+
+        let (status, body, stored) = save(" 10mm", true).await;
+        assert_eq!(status, StatusCode::OK, "{body}");
+        assert_eq!(stored.as_deref(), Some("10mm"));
+        let user_config = state
+            .config_writer
+            .current()
+            .await
+            .expect("config writer should answer");
+        assert_eq!(user_config.extra_case_types, vec!["10mm"]);
+
+        // The new type is now known without allow_new_type, and listed
+        let (status, _, stored) = save("10MM", false).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(stored.as_deref(), Some("10mm"));
+        let request = Request::builder()
+            .uri("/api/case-designations")
+            .body(Body::empty())
+            .expect("request should build");
+        let response = create_router(state.clone())
+            .oneshot(request)
+            .await
+            .expect("router should respond");
+        let bytes = to_bytes(response.into_body(), usize::MAX)
+            .await
+            .expect("body should be readable");
+        let json: serde_json::Value =
+            serde_json::from_slice(&bytes).expect("response should be JSON");
+        let designations = json["data"].as_array().expect("designations are a list");
+        assert_eq!(
+            designations.first().map(|d| &d["designation"]),
+            Some(&"9mm".into())
+        );
+        assert!(
+            designations[0]["aliases"]
+                .as_array()
+                .is_some_and(|aliases| aliases.contains(&"9x19".into()))
+        );
+        assert_eq!(
+            designations.last().map(|d| &d["designation"]),
+            Some(&"10mm".into())
+        );
+    }
+
+    #[tokio::test]
+    async fn test_path_traversal_is_refused_at_every_entry_point() {
+        let temp_dir = tempfile::TempDir::new().expect("temp dir should be created");
+        // Everything the server may touch lives one level down, so an escape lands in temp_dir
+        let root = temp_dir.path().join("sandbox");
+        let state = test_state(&root);
+        std::fs::create_dir_all(&state.settings.image_directory)
+            .expect("image dir should be created");
+        std::fs::write(state.settings.image_directory.join("shot.jpg"), b"jpeg")
+            .expect("image should be written");
+        std::fs::write(temp_dir.path().join("secret.jpg"), b"secret")
+            .expect("secret should be written");
+
+        let get = |uri: &str| {
+            let request = Request::builder()
+                .uri(uri)
+                .body(Body::empty())
+                .expect("request should build");
+            create_router(state.clone()).oneshot(request)
+        };
+        let response = get("/images/shot.jpg")
+            .await
+            .expect("router should respond");
+        assert_eq!(response.status(), StatusCode::OK);
+        for uri in [
+            "/images/..%2F..%2Fsecret.jpg",
+            "/images/..%2Fsecret.jpg",
+            "/images/..",
+        ] {
+            let response = get(uri).await.expect("router should respond");
+            assert_eq!(response.status(), StatusCode::BAD_REQUEST, "{uri}");
+        }
+
+        let (status, json) = post_json(
+            state.clone(),
+            "/api/shells/save",
+            serde_json::json!({
+                "session_id": ShellDataManager::generate_session_id(),
+                "brand": "Winchester",
+                "shell_type": "9mm",
+                "image_filenames": ["../../secret.jpg"],
+            }),
+        )
+        .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(json["error"]["code"], "validation_failed", "{json}");
+        assert!(
+            json["error"]["field_errors"]["image_filenames"]
+                .as_str()
+                .is_some_and(|error| error.ends_with("must not contain '/'")),
+            "{json}"
+        );
+
+        let (status, json) = post_json(
+            state.clone(),
+            "/api/case-types",
+            serde_json::json!({"name": "../../escape", "designation": "9mm"}),
+        )
+        .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(json["error"]["code"], "invalid_request", "{json}");
+        assert!(
+            json["message"]
+                .as_str()
+                .is_some_and(|message| message.contains("name")),
+            "{json}"
+        );
+
+        let (status, _) = post_json(
+            state.clone(),
+            "/api/shells/..%2F..%2Fescape/toggle",
+            serde_json::Value::Null,
+        )
+        .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+
+        let mut outside: Vec<_> = std::fs::read_dir(temp_dir.path())
+            .expect("temp dir should be readable")
+            .map(|entry| entry.expect("entry should be readable").file_name())
+            .collect();
+        outside.sort();
+        assert_eq!(outside, ["sandbox", "secret.jpg"]);
+    }
+
+    /// Post `data` as the file `file_name` in a multipart upload to `uri`
+    async fn post_upload(
+        state: Arc<AppState>,
+        uri: &str,
+        file_name: &str,
+        data: &[u8],
+    ) -> (StatusCode, serde_json::Value) {
+        let mut body = format!(
+            "--upload-boundary\r\nContent-Disposition: form-data; name=\"files\"; filename=\"{file_name}\"\r\nContent-Type: image/jpeg\r\n\r\n"
+        )
+        .into_bytes();
+        body.extend_from_slice(data);
+        body.extend_from_slice(b"\r\n--upload-boundary--\r\n");
+        let request = Request::builder()
+            .method("POST")
+            .uri(uri)
+            .header(
+                "Content-Type",
+                "multipart/form-data; boundary=upload-boundary",
+            )
+            .body(Body::from(body))
+            .expect("request should build");
+        let response = create_router(state)
+            .oneshot(request)
+            .await
+            .expect("router should respond");
+        let status = response.status();
+        let bytes = to_bytes(response.into_body(), usize::MAX)
+            .await
+            .expect("body should be readable");
+        (
+            status,
+            serde_json::from_slice(&bytes).expect("response should be JSON"),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_uploaded_images_join_the_shell() {
+        let temp_dir = tempfile::TempDir::new().expect("temp dir should be created");
+        let state = test_state(temp_dir.path());
+        let session_id = "3f2b8c1e-5a4d-4e6f-9b7a-1c2d3e4f5a6b";
+        let mut shell = Shell::new("Winchester".to_string(), "9mm".to_string());
+        shell.add_image(format!("{session_id}_camera_0.jpg"));
+        shell.add_captured_image(CapturedImage::new(
+            0,
+            format!("{session_id}_camera_0.jpg"),
+            "Top".to_string(),
+            ViewType::Side,
+        ));
+        state
+            .shell_data_manager
+            .save_shell(session_id, &shell)
+            .expect("shell should be saved");
+        let images = state.settings.image_directory.clone();
+        std::fs::create_dir_all(&images).expect("image directory should be created");
+        // An earlier upload already holds the first name
+        std::fs::write(images.join(format!("{session_id}_external_1.jpg")), b"old")
+            .expect("image should be written");
+
+        let jpeg = encode_jpeg(&image::RgbImage::new(8, 8), 90).expect("JPEG should encode");
+        let mut png = Vec::new();
+        image::RgbImage::new(8, 8)
+            .write_to(&mut std::io::Cursor::new(&mut png), image::ImageFormat::Png)
+            .expect("PNG should encode");
+        let upload = |parts: &[(&str, Option<&str>, &[u8])]| {
+            let mut body = Vec::new();
+            for (name, file_name, data) in parts {
+                let disposition = match file_name {
+                    Some(file_name) => format!("name=\"{name}\"; filename=\"{file_name}\""),
+                    None => format!("name=\"{name}\""),
+                };
+                body.extend_from_slice(
+                    format!(
+                        "--upload-boundary\r\nContent-Disposition: form-data; {disposition}\r\n\r\n"
+                    )
+                    .as_bytes(),
+                );
+                body.extend_from_slice(data);
+                body.extend_from_slice(b"\r\n");
+            }
+            body.extend_from_slice(b"--upload-boundary--\r\n");
+            let state = state.clone();
+            async move {
+                let request = Request::builder()
+                    .method("POST")
+                    .uri(format!("/api/shells/{session_id}/images"))
+                    .header(
+                        "Content-Type",
+                        "multipart/form-data; boundary=upload-boundary",
+                    )
+                    .body(Body::from(body))
+                    .expect("request should build");
+                let response = create_router(state)
+                    .oneshot(request)
+                    .await
+                    .expect("router should respond");
+                let status = response.status();
+                let bytes = to_bytes(response.into_body(), usize::MAX)
+                    .await
+                    .expect("body should be readable");
+                let body: serde_json::Value =
+                    serde_json::from_slice(&bytes).expect("response should be JSON");
+                (status, body)
+            }
+        };
+
+        let (status, body) = upload(&[("files", Some("not-an-image.txt"), b"hello")]).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST, "{body}");
+
+        let metadata = br#"{"macro.png": {"view_type": "tail", "camera_name": "Macro lens"}}"#;
+        let (status, body) = upload(&[
+            ("files", Some("headstamp.jpg"), &jpeg),
+            ("files", Some("macro.png"), &png),
+            ("metadata", None, metadata),
+        ])
+        .await;
+        assert_eq!(status, StatusCode::OK, "{body}");
+        let expected = [
+            format!("{session_id}_external_2.jpg"),
+            format!("{session_id}_external_3.jpg"),
+        ];
+        assert_eq!(body["data"]["filenames"], serde_json::json!(expected));
+        assert_eq!(body["data"]["revision"], 2);
+        for filename in &expected {
+            let stored = std::fs::read(images.join(filename)).expect("image should be stored");
+            assert!(
+                stored.starts_with(&[0xFF, 0xD8, 0xFF]),
+                "{filename} is a JPEG"
+            );
+        }
+        // Only the stored images and the one already there are left in the directory
+        let mut left: Vec<String> = std::fs::read_dir(&images)
+            .expect("image directory should be readable")
+            .map(|entry| {
+                entry
+                    .expect("entry should be readable")
+                    .file_name()
+                    .to_string_lossy()
+                    .to_string()
+            })
+            .collect();
+        left.sort();
+        assert_eq!(left.len(), 3, "{left:?}");
+
+        let shell = state
+            .shell_data_manager
+            .get_shell(session_id)
+            .expect("shell should load")
+            .expect("shell should exist");
+        let added: Vec<(u32, &str, &str, ViewType, bool)> = shell
+            .captured_images
+            .iter()
+            .flatten()
+            .skip(1)
+            .map(|image| {
+                (
+                    image.camera_index,
+                    image.filename.as_str(),
+                    image.camera_name.as_str(),
+                    image.view_type,
+                    image.has_complete_region(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            added,
+            vec![
+                (
+                    1,
+                    expected[0].as_str(),
+                    "external",
+                    ViewType::Unknown,
+                    false
+                ),
+                (2, expected[1].as_str(), "Macro lens", ViewType::Tail, false),
+            ]
+        );
+        assert!(
+            expected
+                .iter()
+                .all(|filename| shell.image_filenames.contains(filename))
+        );
+
+        // Training sees them like any captured image
+        let training = state
+            .ml_trainer
+            .lock()
+            .expect("trainer lock should be held")
+            .training_shells()
+            .expect("training shells should load");
+        let training_images: Vec<&str> = training[0]
+            .1
+            .training_images()
+            .map(|image| image.filename.as_str())
+            .collect();
+        assert_eq!(training_images.len(), 3);
+        assert!(training_images.contains(&expected[1].as_str()));
+    }
+
+    #[tokio::test]
+    async fn test_view_is_guessed_for_images_without_one() {
+        let temp_dir = tempfile::TempDir::new().expect("temp dir should be created");
+        let state = test_state(temp_dir.path());
+        let session_id = "5d1e7a2b-8c3f-4b6a-9e0d-2f4a6c8e0b1d";
+        let images = state.settings.image_directory.clone();
+        std::fs::create_dir_all(&images).expect("image directory should be created");
+        // A headstamp seen end on: a bright circle against the backdrop
+        let mut headstamp = image::GrayImage::from_pixel(400, 300, image::Luma([20]));
+        imageproc::drawing::draw_filled_circle_mut(
+            &mut headstamp,
+            (200, 150),
+            90,
+            image::Luma([220]),
+        );
+        let jpeg = encode_jpeg(&image::DynamicImage::ImageLuma8(headstamp).to_rgb8(), 90)
+            .expect("JPEG should encode");
+        let filename = format!("{session_id}_camera_0.jpg");
+        std::fs::write(images.join(&filename), &jpeg).expect("image should be written");
+        let mut shell = Shell::new("Winchester".to_string(), "9mm".to_string());
+        shell.add_image(filename.clone());
+        shell.add_captured_image(CapturedImage::new(
+            0,
+            filename.clone(),
+            "Handheld".to_string(),
+            ViewType::Unknown,
+        ));
+        state
+            .shell_data_manager
+            .save_shell(session_id, &shell)
+            .expect("shell should be saved");
+
+        let (status, body) = post_json(
+            state.clone(),
+            &format!("/api/shells/{session_id}/images/{filename}/classify-view"),
+            serde_json::json!({}),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK, "{body}");
+        assert_eq!(body["data"]["view_type"], "tail");
+        assert_eq!(body["data"]["guess"]["view_type"], "tail");
+        assert_eq!(body["data"]["guess"]["needs_review"], false);
+        let shell = state
+            .shell_data_manager
+            .get_shell(session_id)
+            .expect("shell should load")
+            .expect("shell should exist");
+        let image = &shell
+            .captured_images
+            .as_ref()
+            .expect("images should be kept")[0];
+        assert_eq!(image.view_type, ViewType::Tail);
+        assert!(image.view_guess.is_some());
+
+        let (status, body) = post_json(
+            state.clone(),
+            &format!("/api/shells/{session_id}/images/{session_id}_camera_9.jpg/classify-view"),
+            serde_json::json!({}),
+        )
+        .await;
+        assert_eq!(status, StatusCode::NOT_FOUND, "{body}");
+
+        // Uploads without a view type get a guess too
+        let (status, body) = post_upload(
+            state.clone(),
+            &format!("/api/shells/{session_id}/images"),
+            "headstamp.jpg",
+            &jpeg,
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK, "{body}");
+        let shell = state
+            .shell_data_manager
+            .get_shell(session_id)
+            .expect("shell should load")
+            .expect("shell should exist");
+        let uploaded = shell
+            .captured_images
+            .iter()
+            .flatten()
+            .find(|image| image.camera_name == "external")
+            .expect("upload should be recorded");
+        assert_eq!(uploaded.view_type, ViewType::Tail);
+        assert_eq!(
+            uploaded.view_guess.map(|guess| guess.view_type),
+            Some(ViewType::Tail)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_request_bodies_are_limited() {
+        let temp_dir = tempfile::TempDir::new().expect("temp dir should be created");
+        let settings = Settings {
+            max_upload_mb: 1,
+            ..Settings::default()
+        };
+        let (state, _, _) = test_state_with_managers(temp_dir.path(), Vec::new(), settings);
+        state
+            .ml_trainer
+            .lock()
+            .expect("trainer lock should be held")
+            .add_case_type("Winchester_9mm".to_string(), "9mm".to_string(), None)
+            .expect("case type should be created");
+        let uri = "/api/case-types/Winchester_9mm/training-images";
+        let image_dir = temp_dir.path().join("images/Winchester_9mm");
+        let stored = || {
+            let mut names: Vec<String> = std::fs::read_dir(&image_dir)
+                .expect("image directory should be readable")
+                .map(|entry| {
+                    entry
+                        .expect("entry should be readable")
+                        .file_name()
+                        .to_string_lossy()
+                        .to_string()
+                })
+                .collect();
+            names.sort();
+            names
+        };
+
+        let mut jpeg = vec![0xFF, 0xD8, 0xFF, 0xE0];
+        jpeg.resize(64 * 1024, 0);
+        let (status, body) = post_upload(state.clone(), uri, "side.jpg", &jpeg).await;
+        assert_eq!(status, StatusCode::OK, "{body}");
+        assert_eq!(stored(), ["side.jpg"]);
+        assert_eq!(
+            std::fs::read(image_dir.join("side.jpg")).expect("image should be stored"),
+            jpeg
+        );
+
+        // Just over the limit once the multipart framing is counted
+        jpeg.resize(1024 * 1024, 0);
+        let (status, body) = post_upload(state.clone(), uri, "tail.jpg", &jpeg).await;
+        assert_eq!(status, StatusCode::PAYLOAD_TOO_LARGE, "{body}");
+        assert_eq!(body["error"]["code"], "payload_too_large");
+        assert!(
+            body["message"]
+                .as_str()
+                .is_some_and(|message| message.contains("1 MB limit")),
+            "{body}"
+        );
+        // The spooled part was removed with nothing stored
+        assert_eq!(stored(), ["side.jpg"]);
+
+        let (status, body) = post_upload(
+            state.clone(),
+            "/api/case-types/Missing/training-images",
+            "a.jpg",
+            b"",
+        )
+        .await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_eq!(body["error"]["code"], "case_type_not_found");
+
+        // JSON routes are limited too, and answer the same way
+        let notes = "x".repeat(MAX_JSON_BODY_BYTES);
+        let (status, body) = post_json(
+            state,
+            "/api/shells/save",
+            serde_json::json!({"brand": "Winchester", "notes": notes}),
+        )
+        .await;
+        assert_eq!(status, StatusCode::PAYLOAD_TOO_LARGE);
+        assert_eq!(body["error"]["code"], "payload_too_large");
+        assert!(
+            body["message"]
+                .as_str()
+                .is_some_and(|message| message.contains("1 MB limit")),
+            "{body}"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_session_bundle_export_and_import() {
+        let temp_dir = tempfile::TempDir::new().expect("temp dir should be created");
+        let state = test_state(temp_dir.path());
+        std::fs::create_dir_all(&state.settings.image_directory)
+            .expect("image dir should be created");
+        let image_bytes: Vec<u8> = (0..=255).cycle().take(10_000).collect();
+        std::fs::write(
+            state.settings.image_directory.join("original_camera_0.jpg"),
+            &image_bytes,
+        )
+        .expect("image should be written");
+        let mut shell = Shell::new("Winchester".to_string(), "9mm".to_string());
+        shell.add_image("original_camera_0.jpg".to_string());
+        state
+            .shell_data_manager
+            .save_shell("original", &shell)
+            .expect("shell should be saved");
+
+        let request = Request::builder()
+            .uri("/api/shells/original/export")
+            .body(Body::empty())
+            .expect("request should build");
+        let response = create_router(state.clone())
+            .oneshot(request)
+            .await
+            .expect("router should respond");
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            response.headers().get("Content-Type"),
+            Some(&HeaderValue::from_static("application/zip"))
+        );
+        let bundle = to_bytes(response.into_body(), usize::MAX)
+            .await
+            .expect("body should be read");
+
+        let request = Request::builder()
+            .uri("/api/shells/missing/export")
+            .body(Body::empty())
+            .expect("request should build");
+        let response = create_router(state.clone())
+            .oneshot(request)
+            .await
+            .expect("router should respond");
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        let mut body = b"--bundle-boundary\r\nContent-Disposition: form-data; name=\"bundle\"; filename=\"original.zip\"\r\nContent-Type: application/zip\r\n\r\n".to_vec();
+        body.extend_from_slice(&bundle);
+        body.extend_from_slice(b"\r\n--bundle-boundary--\r\n");
+        let request = Request::builder()
+            .method("POST")
+            .uri("/api/shells/import-bundle")
+            .header(
+                "Content-Type",
+                "multipart/form-data; boundary=bundle-boundary",
+            )
+            .body(Body::from(body))
+            .expect("request should build");
+        let response = create_router(state.clone())
+            .oneshot(request)
+            .await
+            .expect("router should respond");
+        assert_eq!(response.status(), StatusCode::OK);
+        let json: serde_json::Value = serde_json::from_slice(
+            &to_bytes(response.into_body(), usize::MAX)
+                .await
+                .expect("body should be read"),
+        )
+        .expect("response should be JSON");
+        let session_id = json["data"]["session_id"]
+            .as_str()
+            .expect("import should return the new session ID");
+        assert_ne!(session_id, "original");
+
+        let imported = state
+            .shell_data_manager
+            .load_shell(session_id)
+            .expect("imported shell should load");
+        assert_eq!(
+            imported.image_filenames,
+            [format!("{session_id}_camera_0.jpg")]
+        );
+        let copied = std::fs::read(
+            state
+                .settings
+                .image_directory
+                .join(&imported.image_filenames[0]),
+        )
+        .expect("imported image should exist");
+        assert_eq!(copied, image_bytes);
+    }
+
+    #[tokio::test]
+    async fn test_dataset_export_continues_from_the_last_one() {
+        let temp_dir = tempfile::TempDir::new().expect("temp dir should be created");
+        let state = test_state(temp_dir.path());
+        let export = |uri: &'static str| {
+            let state = state.clone();
+            async move {
+                let request = Request::builder()
+                    .uri(uri)
+                    .body(Body::empty())
+                    .expect("request should build");
+                let response = create_router(state)
+                    .oneshot(request)
+                    .await
+                    .expect("router should respond");
+                let status = response.status();
+                let content_type = response
+                    .headers()
+                    .get("Content-Type")
+                    .and_then(|value| value.to_str().ok())
+                    .unwrap_or_default()
+                    .to_string();
+                let body = to_bytes(response.into_body(), usize::MAX)
+                    .await
+                    .expect("body should be read");
+                (status, content_type, body)
+            }
+        };
+        let save = |brand: &str| {
+            state
+                .shell_data_manager
+                .save_shell(
+                    &ShellDataManager::generate_session_id(),
+                    &Shell::new(brand.to_string(), "9mm".to_string()),
+                )
+                .expect("shell should be saved");
+        };
+        save("Winchester");
+
+        let (status, _, _) = export("/api/data/export?since=last").await;
+        assert_eq!(status, StatusCode::CONFLICT);
+        let (status, _, _) = export("/api/data/export?since=yesterday").await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+
+        let (status, content_type, body) = export("/api/data/export?format=json").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(content_type, "application/json");
+        let full: serde_json::Value = serde_json::from_slice(&body).expect("export should be JSON");
+        assert_eq!(full["manifest"]["shell_count"], 1, "{full}");
+        assert_eq!(full["manifest"]["since"], serde_json::Value::Null);
+
+        save("Federal");
+        save("Remington");
+        let (status, content_type, body) = export("/api/data/export?format=json&since=last").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(content_type, "application/json");
+        let delta: serde_json::Value =
+            serde_json::from_slice(&body).expect("export should be JSON");
+        assert_eq!(delta["manifest"]["shell_count"], 2, "{delta}");
+        assert_eq!(delta["manifest"]["since"], full["manifest"]["exported_at"]);
+        assert_eq!(
+            delta["manifest"]["sessions"].as_array().map(Vec::len),
+            Some(3)
+        );
+
+        let (status, content_type, body) =
+            export("/api/data/export?format=tar.gz&since=last").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(content_type, "application/gzip");
+        assert!(body.starts_with(&[0x1f, 0x8b]));
+    }
+
+    #[tokio::test]
+    async fn test_propagate_region_only_changes_the_targeted_camera() {
+        let temp_dir = tempfile::TempDir::new().expect("temp dir should be created");
+        let state = test_state(temp_dir.path());
+
+        // A dozen shells, each seen by a left and a right camera
+        let mut sessions = Vec::new();
+        for number in 0..12 {
+            let shell_type = if number % 2 == 0 { "9mm" } else { "45acp" };
+            let mut shell = Shell::new("Winchester".to_string(), shell_type.to_string());
+            for (index, camera_name) in ["left", "right"].into_iter().enumerate() {
+                let mut image = crate::shell_data::CapturedImage::new(
+                    index as u32,
+                    format!("{number}_{camera_name}.jpg"),
+                    camera_name.to_string(),
+                    ViewType::Side,
+                );
+                image.set_region(&CameraRegion::new(
+                    ViewType::Side,
+                    Some(1),
+                    Some(1),
+                    Some(10),
+                    Some(10),
+                ));
+                shell.add_captured_image(image);
+            }
+            let session_id = ShellDataManager::generate_session_id();
+            state
+                .shell_data_manager
+                .save_shell(&session_id, &shell)
+                .expect("shell should be saved");
+            let saved = state
+                .shell_data_manager
+                .load_shell(&session_id)
+                .expect("shell should load");
+            sessions.push((session_id, saved));
+        }
+
+        let request = serde_json::json!({
+            "camera_name": "left",
+            "region": {"x": 5, "y": 6, "width": 70, "height": 80},
+            "view_type": "tail",
+            "filter": {"case_type": "9mm"},
+            "dry_run": true,
+        });
+        let (status, body) = post_json(
+            state.clone(),
+            "/api/shells/propagate-region",
+            request.clone(),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK, "{body}");
+        assert_eq!(body["data"]["shells_matched"], 6);
+        assert_eq!(body["data"]["images_updated"], 6);
+        for (session_id, shell) in &sessions {
+            let saved = state
+                .shell_data_manager
+                .load_shell(session_id)
+                .expect("shell should load");
+            assert_eq!(&saved, shell, "a dry run changes nothing");
+        }
+
+        let mut request = request;
+        request["dry_run"] = false.into();
+        let (status, body) =
+            post_json(state.clone(), "/api/shells/propagate-region", request).await;
+        assert_eq!(status, StatusCode::OK, "{body}");
+        assert_eq!(body["data"]["shells_updated"], 6);
+        assert_eq!(body["data"]["images_updated"], 6);
+
+        let propagated = CameraRegion::new(ViewType::Tail, Some(5), Some(6), Some(70), Some(80));
+        for (session_id, original) in &sessions {
+            let saved = state
+                .shell_data_manager
+                .load_shell(session_id)
+                .expect("shell should load");
+            let images = saved.captured_images.as_deref().unwrap_or_default();
+            let original_images = original.captured_images.as_deref().unwrap_or_default();
+            if original.shell_type == "9mm" {
+                assert!(saved.region_updated_at.is_some());
+                assert_eq!(images[0].get_region(), propagated);
+            } else {
+                assert_eq!(&saved, original);
+            }
+            // The other camera's images are never touched
+            assert_eq!(images[1], original_images[1]);
+        }
+
+        // Running it again finds nothing left to change
+        let again = serde_json::json!({
+            "camera_index": 0,
+            "region": {"x": 5, "y": 6, "width": 70, "height": 80},
+            "view_type": "tail",
+            "filter": {"case_type": "Winchester_9mm"},
+        });
+        let (_, body) = post_json(state.clone(), "/api/shells/propagate-region", again).await;
+        assert_eq!(body["data"]["shells_matched"], 6);
+        assert_eq!(body["data"]["shells_updated"], 0);
+
+        let both_cameras = serde_json::json!({
+            "camera_name": "left",
+            "camera_index": 0,
+            "region": {"x": 5, "y": 6, "width": 70, "height": 80},
+            "view_type": "tail",
+        });
+        let (status, _) =
+            post_json(state.clone(), "/api/shells/propagate-region", both_cameras).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_maintenance_mode_blocks_motion_until_turned_off() {
+        let temp_dir = tempfile::TempDir::new().expect("temp dir should be created");
+        let settings = Settings {
+            // Nothing listens here, so commands that get through fail quickly
+            esphome_hostname: "127.0.0.1:1".to_string(),
+            ..Settings::default()
+        };
+        let (state, _camera_manager, controller_monitor) =
+            test_state_with_managers(temp_dir.path(), Vec::new(), settings);
+        tokio::spawn(controller_monitor.run());
+
+        let (code, body) = post_json(
+            state.clone(),
+            "/api/machine/maintenance",
+            serde_json::json!({"enabled": true, "reason": "x".repeat(MAX_MAINTENANCE_REASON_LENGTH + 1)}),
+        )
+        .await;
+        assert_eq!(code, StatusCode::BAD_REQUEST, "{body}");
+        assert_eq!(body["error"]["code"], "validation_failed");
+
+        let (code, body) = post_json(
+            state.clone(),
+            "/api/machine/maintenance",
+            serde_json::json!({"enabled": true, "reason": " clearing jam "}),
+        )
+        .await;
+        assert_eq!(code, StatusCode::OK, "{body}");
+        assert_eq!(body["data"]["enabled"], true);
+        assert_eq!(body["data"]["reason"], "clearing jam");
+
+        for uri in ["/api/machine/next-case", "/api/machine/calibration/start"] {
+            let (code, body) = post_json(state.clone(), uri, serde_json::json!({})).await;
+            assert_eq!(code, StatusCode::CONFLICT, "{uri}: {body}");
+            assert_eq!(body["error"]["code"], "maintenance_mode", "{uri}: {body}");
+        }
+        let (_, status) = get_json(state.clone(), "/api/status").await;
+        assert_eq!(status["maintenance"]["enabled"], true, "{status}");
+        assert_eq!(status["maintenance"]["reason"], "clearing jam");
+
+        let (code, body) = post_json(
+            state.clone(),
+            "/api/machine/maintenance",
+            serde_json::json!({"enabled": false}),
+        )
+        .await;
+        assert_eq!(code, StatusCode::OK, "{body}");
+        assert_eq!(body["data"]["enabled"], false);
+        let (_, status) = get_json(state.clone(), "/api/status").await;
+        assert_eq!(status["maintenance"]["enabled"], false, "{status}");
+        // The press now reaches the controller, which isn't there
+        let (code, body) = post_json(
+            state.clone(),
+            "/api/machine/next-case",
+            serde_json::json!({}),
+        )
+        .await;
+        assert_eq!(code, StatusCode::OK, "{body}");
+        assert_ne!(body["error"]["code"], "maintenance_mode", "{body}");
+    }
+
+    #[tokio::test]
+    async fn test_read_only_server_refuses_changes_but_answers_reads() {
+        let temp_dir = tempfile::TempDir::new().expect("temp dir should be created");
+        let settings = Settings {
+            esphome_hostname: "127.0.0.1:1".to_string(),
+            read_only: true,
+            ..Settings::default()
+        };
+        let (state, _camera_manager, controller_monitor) =
+            test_state_with_managers(temp_dir.path(), Vec::new(), settings);
+        tokio::spawn(controller_monitor.run());
+
+        // Only routes that change nothing are left working
+        for route in route_table() {
+            if route.method == RouteMethod::Get {
+                assert!(!route.mutating, "{} {}", route.method.as_str(), route.path);
+            }
+        }
+        let validate = route_table()
+            .into_iter()
+            .find(|route| route.path == "/api/config/validate");
+        assert_eq!(validate.map(|route| route.mutating), Some(false));
+
+        for (method, uri) in [
+            ("POST", "/api/shells/save"),
+            ("PATCH", "/api/shells/20260101_000000"),
+            ("POST", "/api/config"),
+            ("POST", "/api/case-types"),
+            ("POST", "/api/cameras/capture"),
+            ("POST", "/api/machine/next-case"),
+            ("POST", "/api/training-jobs"),
+        ] {
+            let (code, body) = send_json(state.clone(), method, uri, serde_json::json!({})).await;
+            assert_eq!(code, StatusCode::FORBIDDEN, "{method} {uri}: {body}");
+            assert_eq!(body["error"]["code"], "read_only", "{method} {uri}: {body}");
+        }
+
+        let (code, body) = get_json(state.clone(), "/api/shells").await;
+        assert_eq!(code, StatusCode::OK, "{body}");
+        let request = Request::builder()
+            .uri("/api/data/export")
+            .body(Body::empty())
+            .expect("request should build");
+        let response = create_router(state.clone())
+            .oneshot(request)
+            .await
+            .expect("router should respond");
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(
+            !state
+                .settings
+                .data_directory
+                .join(data_export::EXPORT_MANIFEST_FILENAME)
+                .exists()
+        );
+        let (code, body) =
+            post_json(state.clone(), "/api/config/validate", serde_json::json!({})).await;
+        assert_eq!(code, StatusCode::OK, "{body}");
+        let (code, status) = get_json(state.clone(), "/api/status").await;
+        assert_eq!(code, StatusCode::OK, "{status}");
+        assert_eq!(status["read_only"], true, "{status}");
+        assert_eq!(status["needs_setup"], false, "{status}");
+        assert!(!state.settings.sources.user_config_path.exists());
+    }
+
+    #[tokio::test]
+    async fn test_first_run_setup_against_simulated_hardware() {
+        // One simulated ESPHome device answering as both the controller and a camera
+        let device = Router::new()
+            .route("/", get(|| async { "ESPHome" }))
+            .route("/text_sensor/device_info", get(|| async { "simulated" }));
+        let listener = TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("listener should bind");
+        let hostname = listener
+            .local_addr()
+            .expect("listener should have an address")
+            .to_string();
+        tokio::spawn(async move { axum::serve(listener, device).await });
+
+        let temp_dir = tempfile::TempDir::new().expect("temp dir should be created");
+        let (state, camera_manager, controller_monitor) =
+            test_state_with_managers(temp_dir.path(), vec![hostname.clone()], Settings::default());
+        tokio::spawn(camera_manager.run());
+        tokio::spawn(controller_monitor.run());
+
+        let (_, status) = get_json(state.clone(), "/api/status").await;
+        assert_eq!(status["needs_setup"], true);
+        let (_, setup) = get_json(state.clone(), "/api/setup/status").await;
+        assert_eq!(setup["data"]["config_exists"], false);
+        assert_eq!(setup["data"]["cameras"], serde_json::json!([]));
+
+        // Scanning finds the camera and reaches the controller
+        let (code, scan) = post_json(
+            state.clone(),
+            "/api/setup/scan",
+            serde_json::json!({"controller_hostname": hostname}),
+        )
+        .await;
+        assert_eq!(code, StatusCode::OK, "{scan}");
+        assert_eq!(scan["data"]["controller"]["reachable"], true, "{scan}");
+        let camera_id = crate::camera_manager::esphome_camera_id(&hostname);
+        let found: Vec<&str> = scan["data"]["cameras"]
+            .as_array()
+            .expect("cameras are a list")
+            .iter()
+            .filter_map(|camera| camera["id"].as_str())
+            .collect();
+        assert_eq!(found, vec![camera_id.as_str()]);
+        // The USB manager isn't running in tests, which the scan reports without failing
+        let errors = scan["data"]["errors"]
+            .as_array()
+            .expect("errors are a list");
+        assert!(
+            errors
+                .iter()
+                .all(|error| error.as_str().is_some_and(|e| e.starts_with("USB"))),
+            "{errors:?}"
+        );
+
+        let (_, scan) = post_json(
+            state.clone(),
+            "/api/setup/scan",
+            serde_json::json!({"controller_hostname": "127.0.0.1:1", "skip_network_cameras": true}),
+        )
+        .await;
+        assert_eq!(scan["data"]["controller"]["reachable"], false);
+        assert!(scan["data"]["controller"]["error"].is_string(), "{scan}");
+
+        // Choices naming hardware that wasn't found are refused
+        let (code, body) = post_json(
+            state.clone(),
+            "/api/setup/apply",
+            serde_json::json!({
+                "controller_hostname": hostname,
+                "cameras": [{"camera_id": "usb:missing"}],
+            }),
+        )
+        .await;
+        assert_eq!(code, StatusCode::BAD_REQUEST);
+        assert_eq!(body["error"]["code"], "invalid_request", "{body}");
+        assert!(
+            body["message"]
+                .as_str()
+                .is_some_and(|message| message.contains("cameras.usb:missing")),
+            "{body}"
+        );
+        assert!(!state.settings.sources.user_config_path.exists());
+
+        let data_directory = temp_dir.path().join("shells");
+        let (code, body) = post_json(
+            state.clone(),
+            "/api/setup/apply",
+            serde_json::json!({
+                "controller_hostname": hostname,
+                "cameras": [{"camera_id": camera_id, "nickname": "Top", "view_type": "side"}],
+                "data_directory": data_directory,
+            }),
+        )
+        .await;
+        assert_eq!(code, StatusCode::OK, "{body}");
+        assert_eq!(body["data"]["restart_required"], true);
+        assert!(data_directory.is_dir());
+
+        let saved = Settings::load_user_config_from(&state.settings.sources.user_config_path);
+        assert_eq!(saved.esphome_hostname, hostname);
+        assert_eq!(
+            saved.get_selected_cameras(),
+            [CameraId::from(camera_id.as_str())]
+        );
+        let camera = saved.get_camera_config(&camera_id);
+        assert_eq!(camera.nickname.as_deref(), Some("Top"));
+        assert_eq!(camera.view_type, Some(ViewType::Side));
+        assert_eq!(saved.data_directory, Some(data_directory));
+        assert_eq!(state.controller.get_status().await.hostname, hostname);
+
+        let (_, status) = get_json(state.clone(), "/api/status").await;
+        assert_eq!(status["needs_setup"], false);
+        let (_, setup) = get_json(state, "/api/setup/status").await;
+        assert_eq!(setup["data"]["config_exists"], true);
+        assert_eq!(setup["data"]["cameras"][0]["is_selected"], true);
+    }
+
+    #[tokio::test]
+    async fn test_stream_limit_rejects_extra_streams() {
+        let temp_dir = tempfile::TempDir::new().expect("temp dir should be created");
+        let state = test_state(temp_dir.path());
+        let limit = state.settings.max_concurrent_streams;
+        let camera_id = "usb:simulated";
+
+        let open_stream = || {
+            let request = Request::builder()
+                .uri(format!("/api/cameras/{camera_id}/stream"))
+                .body(Body::empty())
+                .expect("request should build");
+            create_router(state.clone()).oneshot(request)
+        };
+
+        // Holding the responses keeps their bodies, and so their stream slots, alive
+        let mut open_streams = Vec::new();
+        for _ in 0..limit {
+            let response = open_stream().await.expect("router should respond");
+            assert_eq!(response.status(), StatusCode::OK);
+            open_streams.push(response);
+        }
+        assert_eq!(state.stream_limiter.active_for(camera_id), limit);
+
+        let rejected = open_stream().await.expect("router should respond");
+        assert_eq!(rejected.status(), StatusCode::TOO_MANY_REQUESTS);
+        let bytes = to_bytes(rejected.into_body(), usize::MAX)
+            .await
+            .expect("body should be readable");
+        let body: serde_json::Value =
+            serde_json::from_slice(&bytes).expect("rejection should be JSON");
+        assert_eq!(body["success"], false);
+        assert_eq!(body["error"]["code"], "stream_limit_reached");
+
+        // Disconnecting a client frees its slot
+        open_streams.pop();
+        assert_eq!(state.stream_limiter.active_for(camera_id), limit - 1);
+        let response = open_stream().await.expect("router should respond");
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_snapshot_returns_single_jpeg() {
+        // A simulated ESPHome camera serving a tiny JPEG
+        let jpeg = crate::image_ingest::encode_jpeg(
+            &image::RgbImage::from_pixel(4, 4, image::Rgb([10, 200, 10])),
+            80,
+        )
+        .expect("JPEG should encode");
+        let device_jpeg = jpeg.clone();
+        let device = Router::new()
+            .route("/text_sensor/device_info", get(|| async { "simulated" }))
+            .route(
+                "/camera/snapshot",
+                get(move || {
+                    let jpeg = device_jpeg.clone();
+                    async move { ([("Content-Type", "image/jpeg")], jpeg) }
+                }),
+            );
+        let listener = TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("listener should bind");
+        let hostname = listener
+            .local_addr()
+            .expect("listener should have an address")
+            .to_string();
+        tokio::spawn(async move { axum::serve(listener, device).await });
+
+        let temp_dir = tempfile::TempDir::new().expect("temp dir should be created");
+        let (state, camera_manager) =
+            test_state_with_cameras(temp_dir.path(), vec![hostname.clone()]);
+        tokio::spawn(camera_manager.run());
+        state
+            .camera_manager
+            .detect_cameras()
+            .await
+            .expect("detection should be requested");
+
+        let request = Request::builder()
+            .uri(format!(
+                "/api/cameras/{}/snapshot",
+                crate::camera_manager::esphome_camera_id(&hostname)
+            ))
+            .body(Body::empty())
+            .expect("request should build");
+        let response = create_router(state)
+            .oneshot(request)
+            .await
+            .expect("router should respond");
+
+        assert_eq!(response.status(), StatusCode::OK);
+        let headers = response.headers();
+        assert_eq!(headers["Content-Type"], "image/jpeg");
+        assert_eq!(headers["Content-Length"], jpeg.len().to_string().as_str());
+        assert_eq!(headers["Cache-Control"], "private, max-age=2");
+
+        let bytes = to_bytes(response.into_body(), usize::MAX)
+            .await
+            .expect("body should be readable");
+        assert_eq!(&bytes[..3], &[0xFF, 0xD8, 0xFF]);
+        assert_eq!(bytes.as_ref(), jpeg.as_slice());
+    }
+
+    #[tokio::test]
+    async fn test_burst_capture_prefers_the_sharpest_frame() {
+        // A simulated ESPHome camera that is only in focus for its second frame
+        let board = image::GrayImage::from_fn(64, 48, |x, y| {
+            image::Luma([if (x / 8 + y / 8) % 2 == 0 { 30 } else { 220 }])
+        });
+        let frames: Vec<Vec<u8>> = [3.0, 0.0, 1.5]
+            .iter()
+            .map(|&sigma| {
+                let frame = if sigma > 0.0 {
+                    imageproc::filter::gaussian_blur_f32(&board, sigma)
+                } else {
+                    board.clone()
+                };
+                crate::image_ingest::encode_jpeg(
+                    &image::DynamicImage::ImageLuma8(frame).to_rgb8(),
+                    90,
+                )
+                .expect("JPEG should encode")
+            })
+            .collect();
+        let served = Arc::new(Mutex::new(0_usize));
+        let device = Router::new()
+            .route("/text_sensor/device_info", get(|| async { "simulated" }))
+            .route(
+                "/camera/snapshot",
+                get(move || {
+                    let jpeg = served
+                        .lock()
+                        .map(|mut served| {
+                            *served += 1;
+                            frames[(*served - 1).min(frames.len() - 1)].clone()
+                        })
+                        .unwrap_or_default();
+                    async move { ([("Content-Type", "image/jpeg")], jpeg) }
+                }),
+            );
+        let listener = TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("listener should bind");
+        let hostname = listener
+            .local_addr()
+            .expect("listener should have an address")
+            .to_string();
+        tokio::spawn(async move { axum::serve(listener, device).await });
+        let camera_id = crate::camera_manager::esphome_camera_id(&hostname);
+
+        let temp_dir = tempfile::TempDir::new().expect("temp dir should be created");
+        let mut user_config = UserConfig::default();
+        user_config.set_camera_config(
+            camera_id.clone(),
+            crate::config::CameraConfig {
+                nickname: Some("left".to_string()),
+                view_type: Some(ViewType::Side),
+                ..Default::default()
+            },
+        );
+        std::fs::write(
+            temp_dir.path().join("shell-sorter.json"),
+            serde_json::to_string(&user_config).expect("config should serialise"),
+        )
+        .expect("config should be written");
+        let (state, camera_manager) =
+            test_state_with_cameras(temp_dir.path(), vec![hostname.clone()]);
+        tokio::spawn(camera_manager.run());
+        state
+            .camera_manager
+            .detect_cameras()
+            .await
+            .expect("detection should be requested");
+        state
+            .camera_manager
+            .select_cameras(vec![CameraId::from(camera_id.as_str())])
+            .await
+            .expect("camera should be selected");
+
+        let (status, body) = post_json(
+            state.clone(),
+            "/api/cameras/capture",
+            serde_json::json!({"burst": {"frames": 3, "interval_ms": 50}}),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK, "{body}");
+        assert_eq!(body["data"]["results"][&camera_id], "Captured 3 frames");
+        let session_id = body["data"]["session_id"]
+            .as_str()
+            .expect("burst should name its session")
+            .to_string();
+
+        let shell = state
+            .shell_data_manager
+            .load_shell(&session_id)
+            .expect("burst session should be saved");
+        assert!(!shell.include);
+        let images = shell.captured_images.clone().unwrap_or_default();
+        assert_eq!(
+            images
+                .iter()
+                .map(|image| (image.filename.clone(), image.sequence, image.preferred))
+                .collect::<Vec<_>>(),
+            (0..3)
+                .map(|sequence| (
+                    format!("{session_id}_camera_0_{sequence}.jpg"),
+                    Some(sequence),
+                    sequence == 1
+                ))
+                .collect::<Vec<_>>()
+        );
+        assert!(images.iter().all(|image| image.camera_name == "left"
+            && image.view_type == ViewType::Side
+            && image.sharpness.is_some()));
+        assert!(images.iter().all(|image| {
+            state
+                .settings
+                .image_directory
+                .join(&image.filename)
+                .exists()
+        }));
+        assert_eq!(
+            shell
+                .training_images()
+                .map(|image| image.filename.as_str())
+                .collect::<Vec<_>>(),
+            vec![format!("{session_id}_camera_0_1.jpg")]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_burst_capture_caps() {
+        let temp_dir = tempfile::TempDir::new().expect("temp dir should be created");
+        let state = test_state(temp_dir.path());
+
+        let (status, body) = post_json(
+            state.clone(),
+            "/api/cameras/capture",
+            serde_json::json!({"burst": {"frames": 11, "interval_ms": 49}}),
+        )
+        .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["error"]["code"], "validation_failed", "{body}");
+        let message = body["message"].as_str().unwrap_or_default();
+        assert!(
+            message.contains("burst.frames") && message.contains("burst.interval_ms"),
+            "{message}"
+        );
+
+        let (status, body) = post_json(
+            state.clone(),
+            "/api/cameras/capture",
+            serde_json::json!({"burst": {"frames": 2}}),
+        )
+        .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["error"]["code"], "invalid_request", "{body}");
+
+        // With no cameras selected a burst has nothing to save
+        let (status, body) = post_json(
+            state,
+            "/api/cameras/capture",
+            serde_json::json!({"burst": {"frames": 2, "interval_ms": 50}}),
+        )
+        .await;
+        assert_eq!(status, StatusCode::BAD_GATEWAY);
+        assert_eq!(body["message"], "No cameras are selected");
+    }
+
+    #[tokio::test]
+    async fn test_detect_reports_cached_formats_until_a_deep_detection() {
+        let temp_dir = tempfile::TempDir::new().expect("temp dir should be created");
+        let usb = SimulatedUsb::start(2, Duration::ZERO);
+        // Without a freshness window every request scans, using the formats cache
+        let (state, camera_manager, _controller_monitor) = test_state_with_usb(
+            temp_dir.path(),
+            Vec::new(),
+            Settings {
+                detect_freshness_s: 0,
+                ..Settings::default()
+            },
+            usb.handle.clone(),
+        );
+        tokio::spawn(camera_manager.run());
+
+        for (uri, deep, source) in [
+            ("/api/cameras/detect", false, "fresh"),
+            ("/api/cameras/detect", false, "cached"),
+            ("/api/cameras/detect?deep=true", true, "fresh"),
+        ] {
+            let (status, body) = get_json(state.clone(), uri).await;
+            assert_eq!(status, StatusCode::OK, "{body}");
+            let report = &body["data"]["usb"];
+            assert_eq!(report["deep"], deep, "{body}");
+            assert!(report["duration_ms"].is_u64(), "{body}");
+            for hardware_id in &usb.hardware_ids {
+                assert_eq!(report["formats"][hardware_id], source, "{uri}: {body}");
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_debug_timing_header_breaks_down_the_request() {
+        let temp_dir = tempfile::TempDir::new().expect("temp dir should be created");
+        let usb = SimulatedUsb::start(1, Duration::ZERO);
+        let (state, camera_manager, _controller_monitor) = test_state_with_usb(
+            temp_dir.path(),
+            Vec::new(),
+            Settings::default(),
+            usb.handle.clone(),
+        );
+        tokio::spawn(camera_manager.run());
+
+        let server_timing = |uri: &'static str, debug_timing: bool| {
+            let state = state.clone();
+            async move {
+                let mut request = Request::builder().uri(uri);
+                if debug_timing {
+                    request = request.header("X-Debug-Timing", "1");
+                }
+                let response = create_router(state)
+                    .oneshot(request.body(Body::empty()).expect("request should build"))
+                    .await
+                    .expect("router should respond");
+                assert_eq!(response.status(), StatusCode::OK, "{uri}");
+                response
+                    .headers()
+                    .get("Server-Timing")
+                    .map(|value| value.to_str().expect("header should be text").to_string())
+            }
+        };
+
+        // Nothing is collected unless asked for
+        assert_eq!(server_timing("/api/cameras", false).await, None);
+
+        let cameras = server_timing("/api/cameras", true)
+            .await
+            .expect("the breakdown should be returned");
+        for metric in [
+            "actor;desc=\"Camera manager\";dur=",
+            "actor;desc=\"USB camera manager\";dur=",
+            "serialize;dur=",
+            "total;dur=",
+        ] {
+            assert!(cameras.contains(metric), "{metric} missing from {cameras}");
+        }
+
+        let shells = server_timing("/api/shells", true)
+            .await
+            .expect("the breakdown should be returned");
+        for metric in ["disk;dur=", "serialize;dur=", "total;dur="] {
+            assert!(shells.contains(metric), "{metric} missing from {shells}");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_error_messages_follow_accept_language() {
+        let temp_dir = tempfile::TempDir::new().expect("temp dir should be created");
+        let data_dir = temp_dir.path().join("data");
+        std::fs::create_dir_all(&data_dir).expect("data dir should be created");
+        std::fs::write(
+            data_dir.join("messages.de.json"),
+            r#"{"shell_not_found": "Hülse {session_id} nicht gefunden"}"#,
+        )
+        .expect("catalogue should be written");
+        let state = test_state(temp_dir.path());
+
+        let error_message = |uri: &'static str, accept_language: Option<&'static str>| {
+            let state = state.clone();
+            async move {
+                let mut request = Request::builder().uri(uri);
+                if let Some(language) = accept_language {
+                    request = request.header("Accept-Language", language);
+                }
+                let response = create_router(state)
+                    .oneshot(request.body(Body::empty()).expect("request should build"))
+                    .await
+                    .expect("router should respond");
+                let bytes = to_bytes(response.into_body(), usize::MAX)
+                    .await
+                    .expect("body should be readable");
+                let body: serde_json::Value =
+                    serde_json::from_slice(&bytes).expect("body should be JSON");
+                (
+                    body["error"]["code"]
+                        .as_str()
+                        .unwrap_or_default()
+                        .to_string(),
+                    body["message"].as_str().unwrap_or_default().to_string(),
+                )
+            }
+        };
+
+        assert_eq!(
+            error_message("/api/shells/missing", None).await,
+            (
+                "shell_not_found".to_string(),
+                "Shell missing not found".to_string()
+            )
+        );
+        // Codes stay the same whatever the language
+        assert_eq!(
+            error_message("/api/shells/missing", Some("fr;q=0.9, de-CH;q=0.8")).await,
+            (
+                "shell_not_found".to_string(),
+                "Hülse missing nicht gefunden".to_string()
+            )
+        );
+        // Keys the override doesn't translate fall back to English
+        assert_eq!(
+            error_message("/api/shells/bad%20id", Some("de")).await,
+            (
+                "invalid_request".to_string(),
+                "Invalid session_id".to_string()
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn test_resetting_stats_of_an_unknown_camera_is_not_found() {
+        let temp_dir = tempfile::TempDir::new().expect("temp dir should be created");
+        let usb = SimulatedUsb::start(1, Duration::ZERO);
+        let (state, camera_manager, _controller_monitor) = test_state_with_usb(
+            temp_dir.path(),
+            Vec::new(),
+            Settings::default(),
+            usb.handle.clone(),
+        );
+        tokio::spawn(camera_manager.run());
+
+        let uri = format!("/api/cameras/{}/stats/reset", usb.hardware_ids[0]);
+        let (status, body) = post_json(state.clone(), &uri, serde_json::json!({})).await;
+        assert_eq!(status, StatusCode::OK, "{body}");
+        for camera_id in ["usb:DEAD:BEEF:0", "esp32cam9.local"] {
+            let uri = format!("/api/cameras/{camera_id}/stats/reset");
+            let (status, body) = post_json(state.clone(), &uri, serde_json::json!({})).await;
+            assert_eq!(status, StatusCode::NOT_FOUND, "{camera_id}: {body}");
+            assert_eq!(body["error"]["code"], "camera_not_found", "{body}");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_a_burst_of_detect_requests_scans_once() {
+        let temp_dir = tempfile::TempDir::new().expect("temp dir should be created");
+        let usb = SimulatedUsb::start(2, Duration::ZERO);
+        let (state, camera_manager, _controller_monitor) = test_state_with_usb(
+            temp_dir.path(),
+            Vec::new(),
+            Settings::default(),
+            usb.handle.clone(),
+        );
+        tokio::spawn(camera_manager.run());
+
+        // As if several dashboard tabs loaded at once
+        let responses = futures_util::future::join_all(
+            (0..6).map(|_| get_json(state.clone(), "/api/cameras/detect")),
+        )
+        .await;
+        for (status, body) in &responses {
+            assert_eq!(*status, StatusCode::OK, "{body}");
+            let formats = body["data"]["usb"]["formats"].as_object();
+            assert_eq!(formats.map(|formats| formats.len()), Some(2), "{body}");
+        }
+        let cached = responses
+            .iter()
+            .filter(|(_, body)| body["data"]["cached"] == true)
+            .count();
+        assert_eq!(cached, 5, "{responses:?}");
+        let (_, health) = get_json(state.clone(), "/api/health").await;
+        assert_eq!(
+            health["data"]["detection"],
+            serde_json::json!({
+                "usb": {"scans": 1, "cached": 5},
+                "esphome": {"scans": 1, "cached": 5},
+            })
+        );
+
+        // The Detect Cameras button scans whatever the window says
+        let (status, body) = get_json(state.clone(), "/api/cameras/detect?force=true").await;
+        assert_eq!(status, StatusCode::OK, "{body}");
+        assert_eq!(body["data"]["cached"], false);
+        assert_eq!(
+            state.detection.usb.stats(),
+            DetectionStats {
+                scans: 2,
+                cached: 5
+            }
+        );
+    }
+
+    /// POST `body` with an `Idempotency-Key` header, returning the status, replay header and body
+    async fn post_with_key(
+        state: Arc<AppState>,
+        uri: &str,
+        key: &str,
+        body: serde_json::Value,
+    ) -> (StatusCode, Option<String>, serde_json::Value) {
+        let request = Request::builder()
+            .method("POST")
+            .uri(uri)
+            .header("content-type", "application/json")
+            .header(idempotency::IDEMPOTENCY_KEY_HEADER, key)
+            .body(Body::from(body.to_string()))
+            .expect("request should build");
+        let response = create_router(state)
+            .oneshot(request)
+            .await
+            .expect("router should respond");
+        let status = response.status();
+        let replayed = response
+            .headers()
+            .get(idempotency::REPLAYED_HEADER)
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_string);
+        let bytes = to_bytes(response.into_body(), usize::MAX)
+            .await
+            .expect("body should be readable");
+        let json = serde_json::from_slice(&bytes).unwrap_or(serde_json::Value::Null);
+        (status, replayed, json)
+    }
+
+    #[tokio::test]
+    async fn test_retried_capture_with_the_same_key_is_replayed() {
+        let temp_dir = tempfile::TempDir::new().expect("temp dir should be created");
+        let usb = SimulatedUsb::start(1, Duration::ZERO);
+        let (state, camera_manager, _controller_monitor) = test_state_with_usb(
+            temp_dir.path(),
+            Vec::new(),
+            Settings::default(),
+            usb.handle.clone(),
+        );
+        tokio::spawn(camera_manager.run());
+        let uri = "/api/cameras/capture";
+
+        let (status, replayed, first) =
+            post_with_key(state.clone(), uri, "tablet-1", serde_json::json!({})).await;
+        assert_eq!(status, StatusCode::OK, "{first}");
+        assert_eq!(replayed, None);
+        let (status, replayed, retry) =
+            post_with_key(state.clone(), uri, "tablet-1", serde_json::json!({})).await;
+        assert_eq!(status, StatusCode::OK, "{retry}");
+        assert_eq!(replayed.as_deref(), Some("true"));
+        assert_eq!(retry, first);
+
+        // The key may come in the body instead, and a new key captures again
+        let (_, second) = post_json(
+            state.clone(),
+            uri,
+            serde_json::json!({"idempotency_key": "tablet-2"}),
+        )
+        .await;
+        assert_ne!(second["data"]["session_id"], first["data"]["session_id"]);
+        let (_, retry) = post_json(
+            state.clone(),
+            uri,
+            serde_json::json!({"idempotency_key": "tablet-2"}),
+        )
+        .await;
+        assert_eq!(retry, second);
+        assert_eq!(
+            state
+                .shell_data_manager
+                .list_shells()
+                .expect("shells should list")
+                .len(),
+            2
+        );
+
+        let (status, _, body) =
+            post_with_key(state.clone(), uri, "has space", serde_json::json!({})).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["error"]["code"], "invalid_request", "{body}");
+
+        let (_, health) = get_json(state.clone(), "/api/health").await;
+        let stats = &health["data"]["idempotency"]["capture"];
+        assert_eq!(
+            (&stats["hits"], &stats["misses"]),
+            (&2.into(), &2.into()),
+            "{health}"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_retried_next_case_presses_the_button_once() {
+        let presses = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let counter = presses.clone();
+        let device = Router::new().route(
+            "/button/trigger_next_case/press",
+            axum::routing::post(move || async move {
+                counter.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            }),
+        );
+        let listener = TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("listener should bind");
+        let hostname = listener
+            .local_addr()
+            .expect("listener should have an address")
+            .to_string();
+        tokio::spawn(async move { axum::serve(listener, device).await });
+
+        let temp_dir = tempfile::TempDir::new().expect("temp dir should be created");
+        let settings = Settings {
+            esphome_hostname: hostname,
+            ..Settings::default()
+        };
+        let (state, _camera_manager, controller_monitor) =
+            test_state_with_managers(temp_dir.path(), Vec::new(), settings);
+        tokio::spawn(controller_monitor.run());
+        let uri = "/api/machine/next-case";
+        let pressed = || presses.load(std::sync::atomic::Ordering::SeqCst);
+
+        let (status, _, first) =
+            post_with_key(state.clone(), uri, "feed-1", serde_json::json!({})).await;
+        assert_eq!(status, StatusCode::OK, "{first}");
+        assert_eq!(first["success"], true, "{first}");
+        assert_eq!(first["data"]["attempts"], 1, "{first}");
+        let (_, replayed, retry) =
+            post_with_key(state.clone(), uri, "feed-1", serde_json::json!({})).await;
+        assert_eq!(replayed.as_deref(), Some("true"));
+        assert_eq!(retry, first);
+        assert_eq!(pressed(), 1);
+
+        post_with_key(state.clone(), uri, "feed-2", serde_json::json!({})).await;
+        assert_eq!(pressed(), 2);
+        // Without a key every request presses
+        post_json(state.clone(), uri, serde_json::json!({})).await;
+        post_json(state.clone(), uri, serde_json::json!({})).await;
+        assert_eq!(pressed(), 4);
+    }
+
+    #[tokio::test]
+    async fn test_captures_go_into_the_active_batch_until_it_is_deactivated() {
+        let temp_dir = tempfile::TempDir::new().expect("temp dir should be created");
+        let usb = SimulatedUsb::start(1, Duration::ZERO);
+        let (state, camera_manager, _controller_monitor) = test_state_with_usb(
+            temp_dir.path(),
+            Vec::new(),
+            Settings::default(),
+            usb.handle.clone(),
+        );
+        tokio::spawn(camera_manager.run());
+        let capture = |body: serde_json::Value| {
+            let state = state.clone();
+            async move {
+                let (status, body) = post_json(state, "/api/cameras/capture", body).await;
+                assert_eq!(status, StatusCode::OK, "{body}");
+                body["data"]["session_id"]
+                    .as_str()
+                    .expect("capture should name its session")
+                    .to_string()
+            }
+        };
+        let batch_of = |session_id: &str| {
+            state
+                .shell_data_manager
+                .load_shell(session_id)
+                .expect("session should be saved")
+                .batch_id
+        };
+
+        let (status, lot) = post_json(
+            state.clone(),
+            "/api/batches",
+            serde_json::json!({"name": "gun show lot 3", "activate": true}),
+        )
+        .await;
+        assert_eq!(status, StatusCode::CREATED, "{lot}");
+        let lot = lot["data"]["id"]
+            .as_str()
+            .expect("batch has an ID")
+            .to_string();
+        let (status, pickup) = post_json(
+            state.clone(),
+            "/api/batches",
+            serde_json::json!({"name": "range pickup June"}),
+        )
+        .await;
+        assert_eq!(status, StatusCode::CREATED, "{pickup}");
+        let pickup = pickup["data"]["id"]
+            .as_str()
+            .expect("batch has an ID")
+            .to_string();
+        let (status, body) = post_json(
+            state.clone(),
+            "/api/batches",
+            serde_json::json!({"name": "Gun Show Lot 3"}),
+        )
+        .await;
+        assert_eq!(status, StatusCode::CONFLICT);
+        assert_eq!(body["error"]["code"], "batch_exists");
+
+        // Every capture inherits the active batch unless it names another
+        let first = capture(serde_json::json!({})).await;
+        let second = capture(serde_json::json!({})).await;
+        let named = capture(serde_json::json!({"batch_id": pickup})).await;
+        assert_eq!(batch_of(&first).as_deref(), Some(lot.as_str()));
+        assert_eq!(batch_of(&second).as_deref(), Some(lot.as_str()));
+        assert_eq!(batch_of(&named).as_deref(), Some(pickup.as_str()));
+        let (status, body) = post_json(
+            state.clone(),
+            "/api/cameras/capture",
+            serde_json::json!({"batch_id": "missing"}),
+        )
+        .await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_eq!(body["error"]["code"], "batch_not_found");
+
+        let (status, _) = post_json(
+            state.clone(),
+            &format!("/api/batches/{pickup}/activate"),
+            serde_json::json!({}),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        let switched = capture(serde_json::json!({})).await;
+        assert_eq!(batch_of(&switched).as_deref(), Some(pickup.as_str()));
+        let (status, _) = post_json(
+            state.clone(),
+            "/api/batches/deactivate",
+            serde_json::json!({}),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        let loose = capture(serde_json::json!({})).await;
+        assert_eq!(batch_of(&loose), None);
+
+        let (_, listed) = get_json(state.clone(), "/api/batches").await;
+        assert_eq!(listed["data"]["active_batch_id"], serde_json::Value::Null);
+        assert_eq!(listed["data"]["batches"][0]["name"], "gun show lot 3");
+        assert_eq!(listed["data"]["batches"][0]["shell_count"], 2);
+        assert_eq!(listed["data"]["batches"][1]["shell_count"], 2);
+        let (_, found) =
+            get_json(state.clone(), &format!("/api/shells/search?batch_id={lot}")).await;
+        let mut found: Vec<&str> = found["data"]
+            .as_array()
+            .expect("search returns a list")
+            .iter()
+            .filter_map(|shell| shell["session_id"].as_str())
+            .collect();
+        found.sort();
+        let mut expected = vec![first.as_str(), second.as_str()];
+        expected.sort();
+        assert_eq!(found, expected);
+
+        let (status, body) = post_json(
+            state.clone(),
+            "/api/batches/missing/training",
+            serde_json::json!({"exclude_from_training": true}),
+        )
+        .await;
+        assert_eq!(status, StatusCode::NOT_FOUND, "{body}");
+        let (status, body) = send_json(
+            state.clone(),
+            "DELETE",
+            &format!("/api/batches/{lot}"),
+            serde_json::json!({}),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK, "{body}");
+        let (_, listed) = get_json(state.clone(), "/api/batches").await;
+        assert_eq!(listed["data"]["batches"][0]["id"], pickup.as_str());
+    }
+
+    #[tokio::test]
+    async fn test_applying_camera_presets_flips_the_selection_and_configs() {
+        let temp_dir = tempfile::TempDir::new().expect("temp dir should be created");
+        let usb = SimulatedUsb::start(2, Duration::ZERO);
+        let (state, camera_manager, _controller_monitor) = test_state_with_usb(
+            temp_dir.path(),
+            Vec::new(),
+            Settings::default(),
+            usb.handle.clone(),
+        );
+        tokio::spawn(camera_manager.run());
+        let (top, tail) = (&usb.hardware_ids[0], &usb.hardware_ids[1]);
+        let unplugged = "usb:DEAD:BEEF:0";
+
+        let (status, body) = post_json(
+            state.clone(),
+            "/api/camera-presets",
+            serde_json::json!({
+                "name": "bench",
+                "cameras": [{
+                    "camera_id": top,
+                    "config": {
+                        "nickname": "bench top",
+                        "view_type": "side",
+                        "region_x": 10,
+                        "region_y": 20,
+                        "region_width": 300,
+                        "region_height": 200,
+                    },
+                    "brightness": 60,
+                }],
+            }),
+        )
+        .await;
+        assert_eq!(status, StatusCode::CREATED, "{body}");
+        let (status, body) = post_json(
+            state.clone(),
+            "/api/camera-presets",
+            serde_json::json!({
+                "name": "sorter",
+                "cameras": [
+                    {
+                        "camera_id": tail,
+                        "config": {
+                            "nickname": "sorter tail",
+                            "view_type": "tail",
+                            "manual_resolution_width": 1280,
+                            "manual_resolution_height": 720,
+                        },
+                        "brightness": 80,
+                    },
+                    {"camera_id": unplugged, "config": {"nickname": "spare"}},
+                ],
+            }),
+        )
+        .await;
+        assert_eq!(status, StatusCode::CREATED, "{body}");
+        let (status, body) = post_json(
+            state.clone(),
+            "/api/camera-presets",
+            serde_json::json!({"name": "Bench", "cameras": []}),
+        )
+        .await;
+        assert_eq!(status, StatusCode::CONFLICT, "{body}");
+        assert_eq!(body["error"]["code"], "camera_preset_exists");
+        let (status, body) = post_json(
+            state.clone(),
+            "/api/camera-presets",
+            serde_json::json!({
+                "name": "dim",
+                "cameras": [{"camera_id": top, "brightness": 300}],
+            }),
+        )
+        .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST, "{body}");
+        assert_eq!(
+            body["error"]["field_errors"]["cameras.0.brightness"],
+            "must be between 0 and 255"
+        );
+
+        let camera = |cameras: &serde_json::Value, camera_id: &str| {
+            cameras["data"]
+                .as_array()
+                .expect("cameras are a list")
+                .iter()
+                .find(|camera| camera["id"] == camera_id)
+                .cloned()
+                .expect("camera should be listed")
+        };
+
+        let (status, body) = post_json(
+            state.clone(),
+            "/api/camera-presets/bench/apply",
+            serde_json::json!({}),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK, "{body}");
+        assert_eq!(
+            body["data"]["cameras"],
+            serde_json::json!([{"camera_id": top, "status": "applied"}])
+        );
+        let user_config = current_user_config(&state).await;
+        assert_eq!(
+            user_config.selected_cameras,
+            vec![CameraId::from(top.as_str())]
+        );
+        let config = &user_config.camera_configs[top];
+        assert_eq!(config.nickname.as_deref(), Some("bench top"));
+        assert_eq!(config.view_type, Some(ViewType::Side));
+        assert_eq!(config.region_width, Some(300));
+        assert_eq!(
+            usb.handle
+                .get_brightness(top.clone())
+                .await
+                .expect("brightness should be read"),
+            60
+        );
+        let (_, cameras) = get_json(state.clone(), "/api/cameras").await;
+        assert_eq!(camera(&cameras, top)["is_selected"], true, "{cameras}");
+        assert_eq!(camera(&cameras, top)["preset"], "bench", "{cameras}");
+        assert_eq!(camera(&cameras, tail)["is_selected"], false, "{cameras}");
+        assert_eq!(camera(&cameras, tail)["preset"], serde_json::Value::Null);
+
+        // The unplugged camera is reported but doesn't stop the rest
+        let (status, body) = post_json(
+            state.clone(),
+            "/api/camera-presets/sorter/apply",
+            serde_json::json!({}),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK, "{body}");
+        assert_eq!(
+            body["data"]["cameras"],
+            serde_json::json!([
+                {"camera_id": tail, "status": "applied"},
+                {"camera_id": unplugged, "status": "missing"},
+            ])
+        );
+        let user_config = current_user_config(&state).await;
+        assert_eq!(
+            user_config.selected_cameras,
+            vec![CameraId::from(tail.as_str()), CameraId::from(unplugged)]
+        );
+        let config = &user_config.camera_configs[tail];
+        assert_eq!(config.nickname.as_deref(), Some("sorter tail"));
+        assert_eq!(config.view_type, Some(ViewType::Tail));
+        assert_eq!(config.manual_resolution_width, Some(1280));
+        assert_eq!(
+            user_config.camera_configs[unplugged].nickname.as_deref(),
+            Some("spare")
+        );
+        assert_eq!(
+            usb.handle
+                .get_brightness(tail.clone())
+                .await
+                .expect("brightness should be read"),
+            80
+        );
+        let (_, cameras) = get_json(state.clone(), "/api/cameras").await;
+        assert_eq!(camera(&cameras, top)["is_selected"], false, "{cameras}");
+        assert_eq!(camera(&cameras, top)["preset"], serde_json::Value::Null);
+        assert_eq!(camera(&cameras, tail)["is_selected"], true, "{cameras}");
+        assert_eq!(camera(&cameras, tail)["preset"], "sorter", "{cameras}");
+        let (_, status) = get_json(state.clone(), "/api/status").await;
+        assert_eq!(status["active_camera_preset"], "sorter", "{status}");
+
+        // Back again
+        let (status, body) = post_json(
+            state.clone(),
+            "/api/camera-presets/BENCH/apply",
+            serde_json::json!({}),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK, "{body}");
+        assert_eq!(body["data"]["name"], "bench");
+        let user_config = current_user_config(&state).await;
+        assert_eq!(
+            user_config.selected_cameras,
+            vec![CameraId::from(top.as_str())]
+        );
+        assert_eq!(user_config.active_camera_preset.as_deref(), Some("bench"));
+
+        // Leaving out the cameras keeps the current selection and settings
+        let (status, body) = post_json(
+            state.clone(),
+            "/api/camera-presets",
+            serde_json::json!({"name": "current"}),
+        )
+        .await;
+        assert_eq!(status, StatusCode::CREATED, "{body}");
+        assert_eq!(body["data"]["cameras"][0]["camera_id"], top.as_str());
+        assert_eq!(
+            body["data"]["cameras"][0]["config"]["nickname"],
+            "bench top"
+        );
+        assert_eq!(body["data"]["cameras"][0]["brightness"], 60);
+
+        let (status, body) = send_json(
+            state.clone(),
+            "DELETE",
+            "/api/camera-presets/bench",
+            serde_json::json!({}),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK, "{body}");
+        let (_, listed) = get_json(state.clone(), "/api/camera-presets").await;
+        assert_eq!(listed["data"]["active_preset"], serde_json::Value::Null);
+        let names: Vec<&str> = listed["data"]["presets"]
+            .as_array()
+            .expect("presets are a list")
+            .iter()
+            .filter_map(|preset| preset["name"].as_str())
+            .collect();
+        assert_eq!(names, ["current", "sorter"]);
+        let (status, body) = get_json(state.clone(), "/api/camera-presets/bench").await;
+        assert_eq!(status, StatusCode::NOT_FOUND, "{body}");
+        assert_eq!(body["error"]["code"], "camera_preset_not_found");
+    }
+
+    #[tokio::test]
+    async fn test_capture_keeps_going_when_a_camera_drops_out() {
+        let temp_dir = tempfile::TempDir::new().expect("temp dir should be created");
+        let usb = SimulatedUsb::start(2, Duration::ZERO);
+        let (state, camera_manager, _controller_monitor) = test_state_with_usb(
+            temp_dir.path(),
+            Vec::new(),
+            Settings::default(),
+            usb.handle.clone(),
+        );
+        tokio::spawn(camera_manager.run());
+        let (gone, present) = (usb.hardware_ids[0].clone(), usb.hardware_ids[1].clone());
+        usb.unplug(&gone);
+
+        let (status, body) =
+            post_json(state.clone(), "/api/cameras/capture", serde_json::json!({})).await;
+        assert_eq!(status, StatusCode::OK, "{body}");
+        let session_id = body["data"]["session_id"]
+            .as_str()
+            .expect("capture should name its session")
+            .to_string();
+        assert_eq!(body["data"]["results"][&present]["status"], "captured");
+        assert_eq!(body["data"]["results"][&gone]["status"], "failed");
+        assert!(
+            body["data"]["results"][&gone]["error"]
+                .as_str()
+                .is_some_and(|error| error.contains("is disconnected")),
+            "{body}"
+        );
+        assert_eq!(body["data"]["missing_cameras"], serde_json::json!([gone]));
+
+        let shell = state
+            .shell_data_manager
+            .load_shell(&session_id)
+            .expect("partial session should be saved");
+        assert!(!shell.include);
+        assert_eq!(
+            shell.image_filenames,
+            vec![format!("{session_id}_camera_1.jpg")]
+        );
+        let coverage = shell.capture.clone().expect("coverage should be recorded");
+        assert_eq!(coverage.expected, usb.hardware_ids);
+        assert_eq!(coverage.delivered, vec![present.clone()]);
+        assert!(coverage.failed.contains_key(&gone));
+        let usb_status = usb.handle.get_status().await.expect("status should load");
+        assert!(!usb_status.cameras[&gone].connected);
+
+        // The summary beside the images tells the same story
+        let summary = |state: &AppState| {
+            SessionSummary::load(&state.settings.image_directory, &session_id)
+                .expect("summary should load")
+                .expect("the capture should write a summary")
+        };
+        let statuses = |summary: &SessionSummary| -> Vec<(String, CameraStatus)> {
+            summary
+                .cameras
+                .iter()
+                .map(|camera| (camera.camera_id.clone(), camera.status))
+                .collect()
+        };
+        let first = summary(&state);
+        assert_eq!(
+            statuses(&first),
+            vec![
+                (gone.clone(), CameraStatus::Failed),
+                (present.clone(), CameraStatus::Captured)
+            ]
+        );
+        assert!(
+            first.cameras[0]
+                .reason
+                .as_deref()
+                .is_some_and(|reason| reason.contains("is disconnected"))
+        );
+        assert_eq!(
+            first.images().collect::<Vec<_>>(),
+            vec![format!("{session_id}_camera_1.jpg")]
+        );
+
+        let (_, shells) = get_json(state.clone(), "/api/shells").await;
+        assert_eq!(
+            shells["data"][0]["missing_cameras"],
+            serde_json::json!([gone]),
+            "{shells}"
+        );
+        let page = tagging_page(Path(session_id.clone()), State(state.clone()))
+            .await
+            .expect("tagging page should render")
+            .0;
+        assert!(page.contains("recapture-btn"));
+        assert!(page.contains(&format!("{session_id}_camera_1.jpg")));
+
+        // Still unplugged, so the recapture has nothing to add
+        let recapture_uri = format!("/api/sessions/{session_id}/recapture");
+        let (status, body) = post_json(state.clone(), &recapture_uri, serde_json::json!({})).await;
+        assert_eq!(status, StatusCode::BAD_GATEWAY, "{body}");
+        assert_eq!(body["data"]["missing_cameras"], serde_json::json!([gone]));
+
+        usb.plug_in(&gone);
+        let (status, body) = post_json(state.clone(), &recapture_uri, serde_json::json!({})).await;
+        assert_eq!(status, StatusCode::OK, "{body}");
+        assert_eq!(body["data"]["results"][&gone]["status"], "captured");
+        assert!(body["data"]["results"].get(&present).is_none());
+        assert_eq!(body["data"]["missing_cameras"], serde_json::json!([]));
+
+        let shell = state
+            .shell_data_manager
+            .load_shell(&session_id)
+            .expect("recaptured session should load");
+        assert_eq!(
+            shell.image_filenames,
+            vec![
+                format!("{session_id}_camera_1.jpg"),
+                format!("{session_id}_camera_0.jpg")
+            ]
+        );
+        assert!(shell.missing_cameras().is_empty());
+        assert!(
+            shell
+                .capture
+                .is_some_and(|coverage| coverage.failed.is_empty())
+        );
+        assert!(
+            state
+                .settings
+                .image_directory
+                .join(format!("{session_id}_camera_0.jpg"))
+                .exists()
+        );
+        let recaptured = summary(&state);
+        assert_eq!(
+            statuses(&recaptured),
+            vec![
+                (gone.clone(), CameraStatus::Captured),
+                (present.clone(), CameraStatus::Captured)
+            ]
+        );
+        assert_eq!(recaptured.started_at, first.started_at);
+        let (_, integrity) = get_json(state.clone(), "/api/shells/integrity").await;
+        assert_eq!(
+            integrity["data"]["summary_mismatches"],
+            serde_json::json!([]),
+            "{integrity}"
+        );
+
+        let (status, body) = post_json(state.clone(), &recapture_uri, serde_json::json!({})).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["error"]["code"], "invalid_request");
+        let (status, body) = post_json(
+            state,
+            "/api/sessions/no-such-session/recapture",
+            serde_json::json!({}),
+        )
+        .await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_eq!(body["error"]["code"], "shell_not_found");
+    }
+
+    #[tokio::test]
+    async fn test_capture_sessions_record_their_settings() {
+        let temp_dir = tempfile::TempDir::new().expect("temp dir should be created");
+        let usb = SimulatedUsb::start(1, Duration::ZERO);
+        let (state, camera_manager, _controller_monitor) = test_state_with_usb(
+            temp_dir.path(),
+            Vec::new(),
+            Settings {
+                image_jpeg_quality: 82,
+                ..Settings::default()
+            },
+            usb.handle.clone(),
+        );
+        tokio::spawn(camera_manager.run());
+        let camera_id = usb.hardware_ids[0].clone();
+        let capture = |state: Arc<AppState>| async move {
+            let (status, body) =
+                post_json(state, "/api/cameras/capture", serde_json::json!({})).await;
+            assert_eq!(status, StatusCode::OK, "{body}");
+            body["data"]["session_id"]
+                .as_str()
+                .expect("capture should name its session")
+                .to_string()
+        };
+
+        usb.handle
+            .set_brightness(camera_id.clone(), 70, BusyPolicy::Wait)
+            .await
+            .expect("brightness should be set");
+        let first = capture(state.clone()).await;
+        let (status, body) =
+            get_json(state.clone(), &format!("/api/sessions/{first}/settings")).await;
+        assert_eq!(status, StatusCode::OK, "{body}");
+        let snapshot = &body["data"];
+        assert_eq!(
+            snapshot["version"],
+            crate::settings_snapshot::SETTINGS_SNAPSHOT_VERSION
+        );
+        assert_eq!(snapshot["software_version"], env!("CARGO_PKG_VERSION"));
+        assert_eq!(snapshot["jpeg_quality"], 82);
+        let camera = &snapshot["cameras"][&camera_id];
+        assert_eq!(camera["brightness"], 70, "{snapshot}");
+        assert_eq!(camera["orientation"]["rotation"], 0);
+        assert_eq!(camera["view_type"], serde_json::Value::Null);
+        assert_eq!(camera["overlay"], false);
+        // Only capture settings are kept, not the whole configuration
+        assert!(snapshot.get("esphome_hostname").is_none(), "{snapshot}");
+
+        // Turned upside down and brightened before the next session
+        let configured = camera_id.clone();
+        state
+            .config_writer
+            .mutate(move |config| {
+                config.camera_configs.insert(
+                    configured,
+                    CameraConfig {
+                        view_type: Some(ViewType::Side),
+                        rotation: crate::orientation::Rotation::Half,
+                        ..CameraConfig::default()
+                    },
+                );
+            })
+            .expect("camera config should be saved");
+        usb.handle
+            .set_brightness(camera_id.clone(), 90, BusyPolicy::Wait)
+            .await
+            .expect("brightness should be set");
+        let second = capture(state.clone()).await;
+
+        let (status, body) = get_json(
+            state.clone(),
+            &format!("/api/sessions/{first}/diff/{second}"),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK, "{body}");
+        assert_eq!(body["data"]["a"], first.as_str());
+        assert_eq!(body["data"]["b"], second.as_str());
+        assert_eq!(
+            body["data"]["differences"],
+            serde_json::json!([
+                {"camera": camera_id, "setting": "brightness", "a": 70, "b": 90},
+                {"camera": camera_id, "setting": "orientation.rotation", "a": 0, "b": 180},
+                {"camera": camera_id, "setting": "view_type", "a": null, "b": "side"},
+            ])
+        );
+        let (_, body) = get_json(
+            state.clone(),
+            &format!("/api/sessions/{first}/diff/{first}"),
+        )
+        .await;
+        assert_eq!(body["data"]["differences"], serde_json::json!([]));
+
+        // Sessions from before snapshots were recorded have none to show
+        let session_id = "7e2c4a9b-1d3f-4e5a-8b6c-0f2e4d6a8c1b";
+        state
+            .shell_data_manager
+            .save_shell(
+                session_id,
+                &Shell::new("Winchester".to_string(), "9mm".to_string()),
+            )
+            .expect("shell should be saved");
+        let (status, _) = get_json(
+            state.clone(),
+            &format!("/api/sessions/{session_id}/settings"),
+        )
+        .await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        let (status, _) =
+            get_json(state, &format!("/api/sessions/{first}/diff/{session_id}")).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_capture_refuses_offline_cameras_unless_partial_is_allowed() {
+        let temp_dir = tempfile::TempDir::new().expect("temp dir should be created");
+        let usb = SimulatedUsb::start(1, Duration::ZERO);
+        let (state, camera_manager, _controller_monitor) = test_state_with_usb(
+            temp_dir.path(),
+            Vec::new(),
+            Settings::default(),
+            usb.handle.clone(),
+        );
+        tokio::spawn(camera_manager.run());
+        let present = usb.hardware_ids[0].clone();
+        // Saved as selected, but no such camera is plugged in
+        let absent = "usb:sim:9".to_string();
+        let selection = vec![
+            CameraId::from(present.as_str()),
+            CameraId::from(absent.as_str()),
+        ];
+        state
+            .config_writer
+            .mutate(move |config| config.set_selected_cameras(selection))
+            .expect("selection should save");
+
+        let (status, body) =
+            post_json(state.clone(), "/api/cameras/capture", serde_json::json!({})).await;
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE, "{body}");
+        assert_eq!(body["error"]["code"], "cameras_offline");
+        assert_eq!(
+            body["data"]["offline"],
+            serde_json::json!([{"camera_id": absent, "reason": "not detected"}])
+        );
+        assert!(
+            state
+                .shell_data_manager
+                .list_shells()
+                .expect("shells should list")
+                .is_empty()
+        );
+
+        let (status, body) = post_json(
+            state.clone(),
+            "/api/cameras/capture",
+            serde_json::json!({"allow_partial": true}),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK, "{body}");
+        assert_eq!(body["data"]["results"][&present]["status"], "captured");
+        assert_eq!(body["data"]["results"][&absent]["status"], "skipped");
+        assert_eq!(body["data"]["results"][&absent]["reason"], "not detected");
+        assert_eq!(body["data"]["missing_cameras"], serde_json::json!([absent]));
+        let session_id = body["data"]["session_id"]
+            .as_str()
+            .expect("capture should name its session");
+        let coverage = state
+            .shell_data_manager
+            .load_shell(session_id)
+            .expect("partial session should be saved")
+            .capture
+            .expect("coverage should be recorded");
+        assert_eq!(coverage.expected, vec![present.clone(), absent.clone()]);
+        assert_eq!(coverage.delivered, vec![present.clone()]);
+        assert_eq!(
+            coverage.skipped,
+            BTreeMap::from([(absent.clone(), "not detected".to_string())])
+        );
+
+        let (status, body) = post_json(
+            state,
+            "/api/cameras/capture",
+            serde_json::json!({"allow_partial": true, "burst": {"frames": 2, "interval_ms": 50}}),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK, "{body}");
+        assert_eq!(body["data"]["results"][&present], "Captured 2 frames");
+        assert_eq!(
+            body["data"]["results"][&absent],
+            "Skipped, offline: not detected"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_saved_orientation_turns_snapshots() {
+        // A simulated ESPHome camera serving a wide JPEG
+        let jpeg = crate::image_ingest::encode_jpeg(
+            &image::RgbImage::from_pixel(8, 4, image::Rgb([10, 200, 10])),
+            80,
+        )
+        .expect("JPEG should encode");
+        let device = Router::new()
+            .route("/text_sensor/device_info", get(|| async { "simulated" }))
+            .route(
+                "/camera/snapshot",
+                get(move || {
+                    let jpeg = jpeg.clone();
+                    async move { ([("Content-Type", "image/jpeg")], jpeg) }
+                }),
+            );
+        let listener = TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("listener should bind");
+        let hostname = listener
+            .local_addr()
+            .expect("listener should have an address")
+            .to_string();
+        tokio::spawn(async move { axum::serve(listener, device).await });
+
+        let temp_dir = tempfile::TempDir::new().expect("temp dir should be created");
+        let (state, camera_manager) =
+            test_state_with_cameras(temp_dir.path(), vec![hostname.clone()]);
+        tokio::spawn(camera_manager.run());
+        state
+            .camera_manager
+            .detect_cameras()
+            .await
+            .expect("detection should be requested");
+        let camera_id = crate::camera_manager::esphome_camera_id(&hostname);
+
+        let configured_id = camera_id.clone();
+        state
+            .config_writer
+            .update(move |user_config| {
+                user_config
+                    .camera_configs
+                    .entry(configured_id)
+                    .or_default()
+                    .rotation = crate::orientation::Rotation::Clockwise90;
+            })
+            .await
+            .expect("config should be saved");
+        apply_camera_orientations(&state, &current_user_config(&state).await).await;
+
+        let request = Request::builder()
+            .uri("/api/config")
+            .body(Body::empty())
+            .expect("request should build");
+        let response = create_router(state.clone())
+            .oneshot(request)
+            .await
+            .expect("router should respond");
+        let body = to_bytes(response.into_body(), usize::MAX)
+            .await
+            .expect("body should be readable");
+        let body: serde_json::Value = serde_json::from_slice(&body).expect("body should be JSON");
+        assert_eq!(
+            body["camera_orientations"][&camera_id],
+            serde_json::json!({"rotation": 90, "mirror": false})
+        );
+        assert_eq!(
+            body["directories"]["data_directory"],
+            serde_json::json!(state.settings.data_directory)
+        );
+
+        let request = Request::builder()
+            .uri(format!("/api/cameras/{camera_id}/snapshot"))
+            .body(Body::empty())
+            .expect("request should build");
+        let response = create_router(state.clone())
+            .oneshot(request)
+            .await
+            .expect("router should respond");
+        assert_eq!(response.status(), StatusCode::OK);
+        let bytes = to_bytes(response.into_body(), usize::MAX)
+            .await
+            .expect("body should be readable");
+        let snapshot = image::load_from_memory(&bytes).expect("snapshot should decode");
+        assert_eq!((snapshot.width(), snapshot.height()), (4, 8));
+
+        // The live stream isn't turned on the server, so the dashboard is told to do it
+        let cameras = collect_cameras(&state).await;
+        let camera = cameras
+            .iter()
+            .find(|camera| camera.camera.id.as_str() == camera_id)
+            .expect("camera should be listed");
+        assert_eq!(
+            camera.orientation.rotation,
+            crate::orientation::Rotation::Clockwise90
+        );
+        assert!(!camera.stream_oriented);
+    }
+
+    #[tokio::test]
+    async fn test_regions_are_checked_against_the_camera_frame() {
+        // A simulated ESPHome camera whose frames are 64x48, mounted on its side
+        let jpeg = crate::image_ingest::encode_jpeg(&image::RgbImage::new(64, 48), 80)
+            .expect("JPEG should encode");
+        let device = Router::new()
+            .route("/text_sensor/device_info", get(|| async { "simulated" }))
+            .route(
+                "/camera/snapshot",
+                get(move || {
+                    let jpeg = jpeg.clone();
+                    async move { ([("Content-Type", "image/jpeg")], jpeg) }
+                }),
+            );
+        let listener = TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("listener should bind");
+        let hostname = listener
+            .local_addr()
+            .expect("listener should have an address")
+            .to_string();
+        tokio::spawn(async move { axum::serve(listener, device).await });
+
+        let temp_dir = tempfile::TempDir::new().expect("temp dir should be created");
+        let (state, camera_manager) =
+            test_state_with_cameras(temp_dir.path(), vec![hostname.clone()]);
+        tokio::spawn(camera_manager.run());
+        state
+            .camera_manager
+            .detect_cameras()
+            .await
+            .expect("detection should be requested");
+        let camera_id = crate::camera_manager::esphome_camera_id(&hostname);
+        let configured_id = camera_id.clone();
+        state
+            .config_writer
+            .update(move |user_config| {
+                user_config
+                    .camera_configs
+                    .entry(configured_id)
+                    .or_default()
+                    .rotation = crate::orientation::Rotation::Clockwise90;
+            })
+            .await
+            .expect("config should be saved");
+        apply_camera_orientations(&state, &current_user_config(&state).await).await;
+        let frame_info_uri = format!("/api/cameras/{camera_id}/frame-info");
+        let region_uri = format!("/api/cameras/{camera_id}/region");
+
+        let (status, body) = get_json(state.clone(), &frame_info_uri).await;
+        assert_eq!(status, StatusCode::OK, "{body}");
+        assert_eq!(
+            (&body["data"]["width"], &body["data"]["height"]),
+            (&serde_json::json!(48), &serde_json::json!(64))
+        );
+        assert_eq!(body["data"]["source"], "snapshot");
+        assert_eq!(body["data"]["region"], serde_json::Value::Null);
+
+        // A region ending exactly on the bottom right corner fits
+        let region = serde_json::json!({"x": 16, "y": 32, "width": 32, "height": 32});
+        let (status, body) = post_json(state.clone(), &region_uri, region.clone()).await;
+        assert_eq!(status, StatusCode::OK, "{body}");
+        assert_eq!(body["data"]["region"], region);
+        assert_eq!(
+            body["data"]["region_frame"],
+            serde_json::json!({"width": 48, "height": 64})
+        );
+        let saved = current_user_config(&state).await.camera_configs[&camera_id].clone();
+        assert_eq!(
+            (saved.region_frame_width, saved.region_frame_height),
+            (Some(48), Some(64))
+        );
+
+        // One pixel further is refused, as is a region under the minimum area
+        let (status, body) = post_json(
+            state.clone(),
+            &region_uri,
+            serde_json::json!({"x": 17, "y": 32, "width": 32, "height": 32}),
+        )
+        .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["error"]["code"], "validation_failed");
+        assert_eq!(
+            body["error"]["field_errors"]["width"],
+            "runs past the right edge: x + width is 49, the frame width is 48"
+        );
+        let (status, body) = post_json(
+            state.clone(),
+            &region_uri,
+            serde_json::json!({"x": 0, "y": 0, "width": 31, "height": 32}),
+        )
+        .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert!(
+            body["error"]["field_errors"]["region"].is_string(),
+            "{body}"
+        );
+        let saved = current_user_config(&state).await.camera_configs[&camera_id].region();
+        assert_eq!(saved.map(|region| region.x), Some(16));
+
+        // The camera changing resolution flags the region as drawn for another frame
+        let configured_id = camera_id.clone();
+        state
+            .config_writer
+            .update(move |user_config| {
+                let camera_config = user_config.camera_configs.entry(configured_id).or_default();
+                camera_config.detected_resolution_width = Some(640);
+                camera_config.detected_resolution_height = Some(480);
+            })
+            .await
+            .expect("config should be saved");
+        let (_, body) = get_json(state.clone(), &frame_info_uri).await;
+        assert_eq!(body["data"]["source"], "config");
+        assert_eq!(
+            (&body["data"]["width"], &body["data"]["height"]),
+            (&serde_json::json!(480), &serde_json::json!(640))
+        );
+        assert_eq!(body["data"]["region_outdated"], true);
+
+        let (status, _) = send_json(
+            state.clone(),
+            "DELETE",
+            &region_uri,
+            serde_json::Value::Null,
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        let (_, body) = get_json(state.clone(), &frame_info_uri).await;
+        assert_eq!(body["data"]["region"], serde_json::Value::Null);
+        assert_eq!(body["data"]["region_outdated"], false);
+
+        let (status, body) = get_json(state, "/api/cameras/esphome_missing/frame-info").await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_eq!(body["error"]["code"], "camera_not_found");
+    }
+
+    #[tokio::test]
+    async fn test_stalled_stream_is_reported_and_ended() {
+        // A simulated ESPHome camera that sends a few frames and then hangs
+        let device = Router::new()
+            .route("/text_sensor/device_info", get(|| async { "simulated" }))
+            .route(
+                "/camera/stream",
+                get(|| async {
+                    let frames = async_stream::stream! {
+                        for _ in 0..3 {
+                            yield Ok::<_, std::io::Error>(axum::body::Bytes::from_static(
+                                b"--frame\r\nContent-Type: image/jpeg\r\n\r\njpeg\r\n",
+                            ));
+                        }
+                        std::future::pending::<()>().await;
+                    };
+                    (
+                        [("Content-Type", "multipart/x-mixed-replace;boundary=frame")],
+                        Body::from_stream(frames),
+                    )
+                }),
+            );
+        let listener = TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("listener should bind");
+        let hostname = listener
+            .local_addr()
+            .expect("listener should have an address")
+            .to_string();
+        tokio::spawn(async move { axum::serve(listener, device).await });
+
+        let temp_dir = tempfile::TempDir::new().expect("temp dir should be created");
+        let (state, camera_manager) =
+            test_state_with_cameras(temp_dir.path(), vec![hostname.clone()]);
+        tokio::spawn(camera_manager.run());
+        state
+            .camera_manager
+            .detect_cameras()
+            .await
+            .expect("detection should be requested");
+        let camera_id = crate::camera_manager::esphome_camera_id(&hostname);
+
+        let mut stalls = state.stream_stalls.subscribe();
+        tokio::spawn(watch_stream_health(
+            state.clone(),
+            Duration::from_millis(300),
+            Duration::from_millis(50),
+        ));
+
+        let request = Request::builder()
+            .uri(format!("/api/cameras/{camera_id}/stream"))
+            .body(Body::empty())
+            .expect("request should build");
+        let response = create_router(state.clone())
+            .oneshot(request)
+            .await
+            .expect("router should respond");
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = tokio::spawn(to_bytes(response.into_body(), usize::MAX));
 
-#[derive(Deserialize)]
-struct BrightnessRequest {
-    brightness: i64,
-}
+        let stall = tokio::time::timeout(Duration::from_secs(5), stalls.recv())
+            .await
+            .expect("the stall should be reported")
+            .expect("the stall channel should be open");
+        assert_eq!(stall.camera_id, camera_id);
 
-#[derive(Deserialize)]
-struct SaveShellRequest {
-    session_id: String,
-    brand: String,
-    shell_type: String,
-    include: bool,
-    image_filenames: Vec<String>,
-}
+        // The body ends rather than hanging, so the browser can reconnect
+        let bytes = tokio::time::timeout(Duration::from_secs(5), body)
+            .await
+            .expect("the stalled stream should end")
+            .expect("the body task should finish")
+            .expect("body should be readable");
+        assert!(bytes.starts_with(b"--frame"));
+
+        let request = Request::builder()
+            .uri("/api/cameras")
+            .body(Body::empty())
+            .expect("request should build");
+        let response = create_router(state)
+            .oneshot(request)
+            .await
+            .expect("router should respond");
+        let body = to_bytes(response.into_body(), usize::MAX)
+            .await
+            .expect("body should be readable");
+        let json: serde_json::Value =
+            serde_json::from_slice(&body).expect("response should be JSON");
+        let health = &json["data"][0]["stream_health"];
+        assert_eq!(health["stalled"], true);
+        assert!(health["last_frame"].is_string(), "{health}");
+    }
 
-#[derive(Serialize)]
-struct BrightnessResponse {
-    brightness: i64,
-}
+    #[tokio::test]
+    async fn test_esp_settings_passthrough() {
+        // A simulated ESP32-CAM whose vflip switch is broken
+        let framesize_sets = Arc::new(Mutex::new(Vec::new()));
+        let recorded_sets = framesize_sets.clone();
+        let device = Router::new()
+            .route("/text_sensor/device_info", get(|| async { "simulated" }))
+            .route(
+                "/select/framesize",
+                get(|| async {
+                    Json(serde_json::json!({
+                        "id": "select-framesize",
+                        "state": "SVGA",
+                        "option": ["VGA", "SVGA", "UXGA"]
+                    }))
+                }),
+            )
+            .route(
+                "/select/framesize/set",
+                post(move |Query(query): Query<HashMap<String, String>>| {
+                    let recorded_sets = recorded_sets.clone();
+                    async move {
+                        if let Ok(mut sets) = recorded_sets.lock() {
+                            sets.extend(query.get("option").cloned());
+                        }
+                    }
+                }),
+            )
+            .route(
+                "/number/jpeg_quality",
+                get(|| async { Json(serde_json::json!({"id": "number-jpeg_quality", "value": 10, "state": "10"})) }),
+            )
+            .route(
+                "/switch/vflip/turn_on",
+                post(|| async { StatusCode::INTERNAL_SERVER_ERROR }),
+            );
+        let listener = TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("listener should bind");
+        let hostname = listener
+            .local_addr()
+            .expect("listener should have an address")
+            .to_string();
+        tokio::spawn(async move { axum::serve(listener, device).await });
+        let camera_id = crate::camera_manager::esphome_camera_id(&hostname);
+
+        let temp_dir = tempfile::TempDir::new().expect("temp dir should be created");
+        let mut user_config = UserConfig::default();
+        user_config.set_camera_config(
+            camera_id.clone(),
+            crate::config::CameraConfig {
+                detected_resolution_width: Some(800),
+                detected_resolution_height: Some(600),
+                ..Default::default()
+            },
+        );
+        std::fs::write(
+            temp_dir.path().join("shell-sorter.json"),
+            serde_json::to_string(&user_config).expect("config should serialise"),
+        )
+        .expect("config should be written");
+
+        let (state, camera_manager) =
+            test_state_with_cameras(temp_dir.path(), vec![hostname.clone()]);
+        tokio::spawn(camera_manager.run());
+        state
+            .camera_manager
+            .detect_cameras()
+            .await
+            .expect("detection should be requested");
+
+        let request = Request::builder()
+            .uri(format!("/api/cameras/{camera_id}/esp-settings"))
+            .body(Body::empty())
+            .expect("request should build");
+        let response = create_router(state.clone())
+            .oneshot(request)
+            .await
+            .expect("router should respond");
+        assert_eq!(response.status(), StatusCode::OK);
+        let bytes = to_bytes(response.into_body(), usize::MAX)
+            .await
+            .expect("body should be readable");
+        let body: serde_json::Value = serde_json::from_slice(&bytes).expect("body should be JSON");
+        assert_eq!(body["data"][0]["entity"], "select/framesize");
+        assert_eq!(body["data"][0]["state"], "SVGA");
+        assert_eq!(body["data"][0]["options"][2], "UXGA");
+        assert_eq!(body["data"][1]["state"], "10");
+        // The device doesn't serve the switch's state
+        assert!(body["data"][2]["error"].is_string());
+
+        let (status, body) = post_json(
+            state.clone(),
+            &format!("/api/cameras/{camera_id}/esp-settings"),
+            serde_json::json!({
+                "select/framesize": "UXGA",
+                "switch/vflip": true,
+                "select/special_effect": "sepia"
+            }),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        let results = body["data"].as_array().expect("results should be a list");
+        let result_for = |entity: &str| {
+            results
+                .iter()
+                .find(|result| result["entity"] == entity)
+                .cloned()
+                .expect("every entity should have a result")
+        };
+        assert_eq!(result_for("select/framesize")["success"], true);
+        assert_eq!(result_for("switch/vflip")["success"], false);
+        assert_eq!(result_for("select/special_effect")["success"], false);
+        assert_eq!(
+            *framesize_sets.lock().expect("lock should not be poisoned"),
+            vec!["UXGA"]
+        );
 
-async fn create_case_type(
-    State(_state): State<Arc<AppState>>,
-    Json(_payload): Json<CreateCaseTypeRequest>,
-) -> Json<ApiResponse<()>> {
-    // TODO: Implement case type creation
-    Json(ApiResponse::success(()))
-}
+        // The framesize change cleared the stored resolution
+        let camera_config = state
+            .config_writer
+            .current()
+            .await
+            .expect("config should be readable")
+            .get_camera_config(&camera_id);
+        assert_eq!(camera_config.detected_resolution_width, None);
+        assert_eq!(camera_config.detected_resolution_height, None);
+    }
 
-async fn train_model(State(_state): State<Arc<AppState>>) -> Json<ApiResponse<()>> {
-    // TODO: Implement model training
-    Json(ApiResponse::success(()))
-}
+    #[tokio::test]
+    async fn test_dashboard_renders_detected_cameras() {
+        let device = Router::new().route("/text_sensor/device_info", get(|| async { "simulated" }));
+        let listener = TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("listener should bind");
+        let port = listener
+            .local_addr()
+            .expect("listener should have an address")
+            .port();
+        tokio::spawn(async move { axum::serve(listener, device).await });
+
+        // Named by hostname so the camera name doesn't collide with the server's host in the page
+        let hostname = format!("localhost:{port}");
+        let temp_dir = tempfile::TempDir::new().expect("temp dir should be created");
+        let (state, camera_manager) =
+            test_state_with_cameras(temp_dir.path(), vec![hostname.clone()]);
+        tokio::spawn(camera_manager.run());
+        state
+            .camera_manager
+            .detect_cameras()
+            .await
+            .expect("detection should be requested");
+
+        let request = Request::builder()
+            .uri("/")
+            .body(Body::empty())
+            .expect("request should build");
+        let response = create_router(state)
+            .oneshot(request)
+            .await
+            .expect("router should respond");
+        assert_eq!(response.status(), StatusCode::OK);
+        let bytes = to_bytes(response.into_body(), usize::MAX)
+            .await
+            .expect("body should be readable");
+        let html = String::from_utf8(bytes.to_vec()).expect("page should be UTF-8");
+
+        assert!(html.contains(r#"<span class="camera-name">localhost</span>"#));
+        assert!(!html.contains("No cameras detected"));
+
+        let bootstrap = html
+            .split(r#"<script type="application/json" id="dashboard-bootstrap">"#)
+            .nth(1)
+            .and_then(|rest| rest.split("</script>").next())
+            .expect("page should embed bootstrap data");
+        let bootstrap: serde_json::Value =
+            serde_json::from_str(bootstrap).expect("bootstrap should be JSON");
+        assert_eq!(bootstrap["cameras"][0]["id"], "esphome_localhost");
+        assert_eq!(bootstrap["cameras_stale"], false);
+    }
 
-async fn get_config(State(_state): State<Arc<AppState>>) -> Json<ConfigData> {
-    // Load current configuration from user config file to ensure it's up to date
-    let user_config = Settings::load_user_config();
-    let config_data = ConfigData {
-        auto_start_cameras: user_config.auto_start_esp32_cameras,
-        auto_detect_cameras: user_config.auto_detect_cameras,
-        esphome_hostname: user_config.esphome_hostname,
-        network_camera_hostnames: user_config.network_camera_hostnames,
-    };
-    Json(config_data)
-}
+    #[tokio::test]
+    async fn test_training_job_reports_progress_and_the_model() {
+        let temp_dir = tempfile::TempDir::new().expect("temp dir should be created");
+        let state = test_state(temp_dir.path());
+        state
+            .ml_trainer
+            .lock()
+            .expect("trainer lock should be held")
+            .initialize()
+            .expect("trainer should initialise");
+
+        // Nothing to train on yet, so the job is refused outright
+        let (status, body) =
+            post_json(state.clone(), "/api/training-jobs", serde_json::json!({})).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST, "{body}");
+        assert_eq!(body["error"]["code"], "dataset_too_small");
+
+        let mut shell = Shell::new("Winchester".to_string(), "9mm".to_string());
+        for (index, view_type) in DEFAULT_REQUIRED_VIEWS.iter().enumerate() {
+            shell.add_captured_image(CapturedImage::new(
+                index as u32,
+                format!("{view_type}.jpg"),
+                format!("Camera {index}"),
+                *view_type,
+            ));
+        }
+        for session_id in ["one", "two"] {
+            state
+                .shell_data_manager
+                .save_shell(session_id, &shell)
+                .expect("shell should be saved");
+        }
 
-async fn save_config(
-    State(state): State<Arc<AppState>>,
-    Json(config): Json<ConfigData>,
-) -> Json<ApiResponse<()>> {
-    info!(
-        "Config save requested: auto_start={}, auto_detect={}, esphome={}, cameras={:?}",
-        config.auto_start_cameras,
-        config.auto_detect_cameras,
-        config.esphome_hostname,
-        config.network_camera_hostnames
-    );
+        let (status, body) = post_json(
+            state.clone(),
+            "/api/training-jobs",
+            serde_json::json!({"case_types": ["Winchester_9mm"]}),
+        )
+        .await;
+        assert_eq!(status, StatusCode::ACCEPTED, "{body}");
+        assert_eq!(
+            body["data"]["case_types"],
+            serde_json::json!(["Winchester_9mm"])
+        );
+        let job_uri = format!(
+            "/api/training-jobs/{}",
+            body["data"]["id"].as_str().expect("job should have an ID")
+        );
 
-    // Load current user config to check for changes
-    let current_user_config = Settings::load_user_config();
+        let job = finished_job(state.clone(), &job_uri).await;
+        assert_eq!(job["phase"], "completed", "{job}");
+        assert_eq!(job["percent"], 100);
+        assert_eq!(
+            job["model"]["class_counts"],
+            serde_json::json!({"Winchester_9mm": 2})
+        );
+        // One case type can't be mixed up with another
+        assert_eq!(job["worst_confused"], serde_json::json!([]));
+        let metadata_path = job["metadata_path"]
+            .as_str()
+            .expect("job should name the metadata file");
+        assert!(std::path::Path::new(metadata_path).is_file());
+
+        let (status, body) = get_json(state, "/api/training-jobs/missing").await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_eq!(body["error"]["code"], "training_job_not_found");
+    }
 
-    // Check if ESPHome hostname has changed
-    let hostname_changed = current_user_config.esphome_hostname != config.esphome_hostname;
-    let camera_hostnames_changed =
-        current_user_config.network_camera_hostnames != config.network_camera_hostnames;
+    #[tokio::test]
+    async fn test_heavy_work_is_refused_or_queued_while_a_permit_is_held() {
+        let temp_dir = tempfile::TempDir::new().expect("temp dir should be created");
+        let state = test_state(temp_dir.path());
+        state
+            .ml_trainer
+            .lock()
+            .expect("trainer lock should be held")
+            .initialize()
+            .expect("trainer should initialise");
+        assert_eq!(state.settings.heavy_work_permits, 1);
+        let long_running = state
+            .heavy_work
+            .try_acquire("fake heavy task")
+            .expect("the permit should be free");
+
+        let request = Request::builder()
+            .method("POST")
+            .uri("/api/ml/generate-composites")
+            .body(Body::empty())
+            .expect("request should build");
+        let response = create_router(state.clone())
+            .oneshot(request)
+            .await
+            .expect("router should respond");
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(response.headers()["Retry-After"], "10");
+        let body: serde_json::Value = serde_json::from_slice(
+            &to_bytes(response.into_body(), usize::MAX)
+                .await
+                .expect("body should be readable"),
+        )
+        .expect("body should be JSON");
+        assert_eq!(body["error"]["code"], "server_busy");
+        assert!(
+            body["error"]["message"]
+                .as_str()
+                .is_some_and(|message| message.contains("fake heavy task")),
+            "{body}"
+        );
+        let (status, _) = get_json(state.clone(), "/api/data/usage").await;
+        assert_eq!(status, StatusCode::TOO_MANY_REQUESTS);
+
+        // Training jobs queue for the permit instead of being refused
+        let mut shell = Shell::new("Winchester".to_string(), "9mm".to_string());
+        for (index, view_type) in DEFAULT_REQUIRED_VIEWS.iter().enumerate() {
+            shell.add_captured_image(CapturedImage::new(
+                index as u32,
+                format!("{view_type}.jpg"),
+                format!("Camera {index}"),
+                *view_type,
+            ));
+        }
+        for session_id in ["one", "two"] {
+            state
+                .shell_data_manager
+                .save_shell(session_id, &shell)
+                .expect("shell should be saved");
+        }
+        let (status, body) =
+            post_json(state.clone(), "/api/training-jobs", serde_json::json!({})).await;
+        assert_eq!(status, StatusCode::ACCEPTED, "{body}");
+        let job_uri = format!(
+            "/api/training-jobs/{}",
+            body["data"]["id"].as_str().expect("job should have an ID")
+        );
+        tokio::time::timeout(Duration::from_secs(10), async {
+            while get_json(state.clone(), &job_uri).await.1["data"]["phase"]
+                != "waiting_for_resources"
+            {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("the job should wait for the permit");
+        let (_, health) = get_json(state.clone(), "/api/health").await;
+        assert_eq!(
+            health["data"]["directories"]["image_directory"],
+            serde_json::json!(state.settings.image_directory)
+        );
+        let heavy_work = &health["data"]["heavy_work"];
+        assert_eq!(heavy_work["permits"], 1);
+        assert_eq!(heavy_work["in_use"], 1);
+        assert_eq!(heavy_work["waiting"], 1);
+        assert_eq!(heavy_work["running"][0]["name"], "fake heavy task");
+
+        drop(long_running);
+        let job = finished_job(state.clone(), &job_uri).await;
+        assert_eq!(job["phase"], "completed", "{job}");
+        let (status, body) = post_json(
+            state.clone(),
+            "/api/ml/generate-composites",
+            serde_json::json!({}),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK, "{body}");
+        assert_eq!(state.heavy_work.usage().in_use, 0);
+    }
 
-    // Update controller monitor configuration if hostname changed
-    if hostname_changed {
-        // Create updated settings for the controller
-        let mut new_settings = state.settings.clone();
-        new_settings.esphome_hostname = config.esphome_hostname.clone();
-        new_settings.network_camera_hostnames = config.network_camera_hostnames.clone();
+    /// The training job at `job_uri` once it has completed or failed
+    async fn finished_job(state: Arc<AppState>, job_uri: &str) -> serde_json::Value {
+        tokio::time::timeout(Duration::from_secs(10), async {
+            loop {
+                let (_, body) = get_json(state.clone(), job_uri).await;
+                if body["data"]["phase"] == "completed" || body["data"]["phase"] == "failed" {
+                    return body["data"].clone();
+                }
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("the job should finish")
+    }
 
-        match state.controller.update_config(new_settings).await {
-            Ok(()) => {
-                info!("Controller monitor configuration updated successfully");
+    #[tokio::test]
+    async fn test_model_metrics_and_recall_by_case_type() {
+        let temp_dir = tempfile::TempDir::new().expect("temp dir should be created");
+        let state = test_state(temp_dir.path());
+        state
+            .ml_trainer
+            .lock()
+            .expect("trainer lock should be held")
+            .initialize()
+            .expect("trainer should initialise");
+
+        for (brand, designation, session_ids) in [
+            ("Winchester", "9mm", ["w1", "w2"]),
+            ("Federal", "40sw", ["f1", "f2"]),
+        ] {
+            let mut shell = Shell::new(brand.to_string(), designation.to_string());
+            for (index, view_type) in DEFAULT_REQUIRED_VIEWS.iter().enumerate() {
+                shell.add_captured_image(CapturedImage::new(
+                    index as u32,
+                    format!("{view_type}.jpg"),
+                    format!("Camera {index}"),
+                    *view_type,
+                ));
             }
-            Err(e) => {
-                error!("Failed to update controller monitor configuration: {}", e);
-                return Json(ApiResponse::<()>::error(format!(
-                    "Failed to update controller configuration: {e}"
-                )));
+            for session_id in session_ids {
+                state
+                    .shell_data_manager
+                    .save_shell(session_id, &shell)
+                    .expect("shell should be saved");
             }
         }
+
+        let (status, body) =
+            post_json(state.clone(), "/api/training-jobs", serde_json::json!({})).await;
+        assert_eq!(status, StatusCode::ACCEPTED, "{body}");
+        let job_uri = format!(
+            "/api/training-jobs/{}",
+            body["data"]["id"].as_str().expect("job should have an ID")
+        );
+        let job = finished_job(state.clone(), &job_uri).await;
+        assert_eq!(job["phase"], "completed", "{job}");
+
+        // The placeholder model calls everything 40 S&W, so every 9mm held back is mistaken for it
+        assert_eq!(
+            job["worst_confused"],
+            serde_json::json!([{
+                "actual": "Winchester_9mm",
+                "predicted": "Federal_40sw",
+                "count": 1,
+                "rate": 1.0,
+            }])
+        );
+        let model_name = job["model"]["name"]
+            .as_str()
+            .expect("model should be named");
+        let (status, body) = get_json(
+            state.clone(),
+            &format!("/api/ml/models/{model_name}/metrics"),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK, "{body}");
+        assert_eq!(
+            body["data"]["confusion"],
+            serde_json::json!([[1, 0], [1, 0]])
+        );
+        assert_eq!(body["data"]["accuracy"], 0.5);
+        assert_eq!(body["data"]["per_class"]["Winchester_9mm"]["recall"], 0.0);
+
+        let (_, body) = get_json(state.clone(), "/api/case-types").await;
+        let recalls: BTreeMap<String, serde_json::Value> = body["data"]
+            .as_array()
+            .expect("case types should be listed")
+            .iter()
+            .map(|case_type| {
+                (
+                    case_type["name"].as_str().unwrap_or_default().to_string(),
+                    case_type["model_recall"].clone(),
+                )
+            })
+            .collect();
+        assert_eq!(recalls["Federal_40sw"], 1.0);
+        assert_eq!(recalls["Winchester_9mm"], 0.0);
+
+        let (status, body) = get_json(
+            state.clone(),
+            "/api/ml/models/shell_classifier_missing/metrics",
+        )
+        .await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_eq!(body["error"]["code"], "model_not_found");
+        let (status, _) = get_json(state, "/api/ml/models/..%2Fsecrets/metrics").await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
     }
 
-    // TODO: Also update camera manager configuration if camera hostnames changed
-    if camera_hostnames_changed {
-        info!("Camera hostname configuration changed - camera manager restart needed");
+    #[tokio::test]
+    async fn test_create_case_type() {
+        let temp_dir = tempfile::TempDir::new().expect("temp dir should be created");
+        let state = test_state(temp_dir.path());
+        let body = serde_json::json!({
+            "name": "Winchester_9mm",
+            "designation": "9mm",
+            "brand": "Winchester"
+        });
+
+        let (status, json) = post_json(state.clone(), "/api/case-types", body.clone()).await;
+        assert_eq!(status, StatusCode::CREATED);
+        assert_eq!(json["data"]["brand"], "Winchester");
+        assert!(
+            temp_dir
+                .path()
+                .join("images")
+                .join("Winchester_9mm")
+                .is_dir()
+        );
+
+        let (status, json) = post_json(state.clone(), "/api/case-types", body).await;
+        assert_eq!(status, StatusCode::CONFLICT);
+        assert_eq!(json["error"]["code"], "case_type_exists");
+        assert_eq!(json["message"], "Case type 'Winchester_9mm' already exists");
+
+        let (status, _) = post_json(
+            state,
+            "/api/case-types",
+            serde_json::json!({"name": "../9mm", "designation": "9mm"}),
+        )
+        .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
     }
 
-    // Save changes to persistent user config file
-    let mut user_config = current_user_config;
-    user_config.esphome_hostname = config.esphome_hostname;
-    user_config.network_camera_hostnames = config.network_camera_hostnames;
-    user_config.auto_detect_cameras = config.auto_detect_cameras;
-    user_config.auto_start_esp32_cameras = config.auto_start_cameras;
+    #[tokio::test]
+    async fn test_case_type_required_views() {
+        let temp_dir = tempfile::TempDir::new().expect("temp dir should be created");
+        let state = test_state(temp_dir.path());
+        let (status, _) = post_json(
+            state.clone(),
+            "/api/case-types",
+            serde_json::json!({"name": "Federal_308win", "designation": "308win"}),
+        )
+        .await;
+        assert_eq!(status, StatusCode::CREATED);
+
+        let mut shell = Shell::new("Federal".to_string(), "308win".to_string());
+        for view_type in [ViewType::Side, ViewType::Tail] {
+            shell.add_captured_image(CapturedImage::new(
+                0,
+                format!("{view_type}.jpg"),
+                "Camera 0".to_string(),
+                view_type,
+            ));
+        }
+        let session_id = ShellDataManager::generate_session_id();
+        state
+            .shell_data_manager
+            .save_shell(&session_id, &shell)
+            .expect("shell should be saved");
+        let (_, shells) = get_json(state.clone(), "/api/shells").await;
+        assert_eq!(shells["data"][0]["missing_views"], serde_json::json!([]));
+
+        let (status, body) = send_json(
+            state.clone(),
+            "PATCH",
+            "/api/case-types/Federal_308win",
+            serde_json::json!({"required_views": ["side", "tail", "mouth"]}),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK, "{body}");
+        assert_eq!(
+            body["data"]["required_views"],
+            serde_json::json!(["side", "tail", "mouth"])
+        );
 
-    match Settings::save_user_config(&user_config) {
-        Ok(()) => {
-            info!("Configuration saved to user config file successfully");
+        // The list's ETag changes with the case type, so the new requirement shows straight away
+        let (_, shells) = get_json(state.clone(), "/api/shells").await;
+        assert_eq!(
+            shells["data"][0]["missing_views"],
+            serde_json::json!(["mouth"])
+        );
+        let (_, found) = get_json(state.clone(), "/api/shells/search?brand=federal").await;
+        assert_eq!(
+            found["data"][0]["missing_views"],
+            serde_json::json!(["mouth"])
+        );
+        let (_, case_types) = get_json(state.clone(), "/api/case-types").await;
+        assert_eq!(
+            case_types["data"][0]["incomplete_shells"][0]["session_id"],
+            session_id
+        );
+
+        for (uri, body, expected) in [
+            (
+                "/api/case-types/Federal_308win",
+                serde_json::json!({"required_views": []}),
+                StatusCode::BAD_REQUEST,
+            ),
+            (
+                "/api/case-types/Federal_308win",
+                serde_json::json!({"required_views": ["top"]}),
+                StatusCode::UNPROCESSABLE_ENTITY,
+            ),
+            (
+                "/api/case-types/Nobody_9mm",
+                serde_json::json!({"required_views": ["side"]}),
+                StatusCode::NOT_FOUND,
+            ),
+        ] {
+            let (status, body) = send_json(state.clone(), "PATCH", uri, body).await;
+            assert_eq!(status, expected, "{uri}: {body}");
         }
-        Err(e) => {
-            error!("Failed to save configuration to file: {}", e);
-            return Json(ApiResponse::<()>::error(format!(
-                "Failed to save configuration to file: {e}"
-            )));
+    }
+
+    #[tokio::test]
+    async fn test_case_type_targets_and_progress() {
+        let temp_dir = tempfile::TempDir::new().expect("temp dir should be created");
+        let state = test_state(temp_dir.path());
+        let (status, _) = post_json(
+            state.clone(),
+            "/api/case-types",
+            serde_json::json!({"name": "Federal_308win", "designation": "308win", "brand": "Federal"}),
+        )
+        .await;
+        assert_eq!(status, StatusCode::CREATED);
+        let (_, case_types) = get_json(state.clone(), "/api/case-types").await;
+        assert_eq!(case_types["data"][0]["target"], serde_json::Value::Null);
+
+        let (status, body) = send_json(
+            state.clone(),
+            "PATCH",
+            "/api/case-types/Federal_308win",
+            serde_json::json!({"target_shell_count": 1}),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK, "{body}");
+        assert_eq!(body["data"]["target_shell_count"], 1);
+        // Leaving the target out of a later change keeps it
+        let (_, body) = send_json(
+            state.clone(),
+            "PATCH",
+            "/api/case-types/Federal_308win",
+            serde_json::json!({"required_views": ["side", "tail"]}),
+        )
+        .await;
+        assert_eq!(body["data"]["target_shell_count"], 1);
+
+        let (status, progress) = get_json(state.clone(), "/api/ml/progress").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(progress["data"][0]["label"], "Federal 308win");
+        assert_eq!(progress["data"][0]["percent"], 0.0);
+
+        // Including a complete shell takes the type to its target
+        let mut shell = Shell::new("Federal".to_string(), "308win".to_string());
+        shell.include = false;
+        for view_type in [ViewType::Side, ViewType::Tail] {
+            shell.add_captured_image(CapturedImage::new(
+                0,
+                format!("{view_type}.jpg"),
+                "Camera 0".to_string(),
+                view_type,
+            ));
         }
+        let session_id = ShellDataManager::generate_session_id();
+        state
+            .shell_data_manager
+            .save_shell(&session_id, &shell)
+            .expect("shell should be saved");
+        let mut reached = state.shell_targets.subscribe();
+        let (status, _) = post_json(
+            state.clone(),
+            &format!("/api/shells/{session_id}/toggle"),
+            serde_json::json!({}),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(
+            reached
+                .try_recv()
+                .expect("target reached should be broadcast")
+                .message,
+            "Federal 308win target reached"
+        );
+
+        let (_, case_types) = get_json(state.clone(), "/api/case-types").await;
+        assert_eq!(case_types["data"][0]["target"]["current"], 1);
+        assert_eq!(case_types["data"][0]["target"]["percent"], 100.0);
+        assert_eq!(case_types["data"][0]["ready_for_training"], true);
+
+        let (status, body) = send_json(
+            state.clone(),
+            "PATCH",
+            "/api/case-types/Federal_308win",
+            serde_json::json!({"target_shell_count": null}),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK, "{body}");
+        assert_eq!(body["data"]["target_shell_count"], serde_json::Value::Null);
+        let (_, progress) = get_json(state, "/api/ml/progress").await;
+        assert_eq!(progress["data"], serde_json::json!([]));
     }
 
-    info!("Configuration updated successfully");
+    #[tokio::test]
+    async fn test_work_queue_lists_untagged_sessions_first() {
+        let temp_dir = tempfile::TempDir::new().expect("temp dir should be created");
+        let state = test_state(temp_dir.path());
+        for brand in ["", "Winchester"] {
+            state
+                .shell_data_manager
+                .save_shell(
+                    &ShellDataManager::generate_session_id(),
+                    &Shell::new(brand.to_string(), "9mm".to_string()),
+                )
+                .expect("shell should be saved");
+        }
 
-    Json(ApiResponse::success(()))
-}
+        let (status, body) = get_json(state.clone(), "/api/work-queue?limit=1").await;
+        assert_eq!(status, StatusCode::OK, "{body}");
+        assert_eq!(body["data"]["counts"]["untagged_session"], 1);
+        assert_eq!(body["data"]["counts"]["incomplete_shell"], 1);
+        let items = body["data"]["items"]
+            .as_array()
+            .expect("items should be a list");
+        assert_eq!(items.len(), 1);
+        assert_eq!(items[0]["kind"], "untagged_session");
+        assert_eq!(items[0]["priority"], 1);
+    }
 
-async fn delete_camera_config(
-    Path(index): Path<usize>,
-    State(_state): State<Arc<AppState>>,
-) -> Json<ApiResponse<()>> {
-    // For now, just acknowledge the delete request
-    // In a full implementation, this would remove the camera from the configuration
-    info!("Camera {} delete requested", index);
-    Json(ApiResponse::success(()))
-}
+    #[tokio::test]
+    async fn test_config_schema_and_validation() {
+        let temp_dir = tempfile::TempDir::new().expect("temp dir should be created");
+        let state = test_state(temp_dir.path());
+
+        let request = Request::builder()
+            .uri("/api/config/schema")
+            .body(Body::empty())
+            .expect("request should build");
+        let response = create_router(state.clone())
+            .oneshot(request)
+            .await
+            .expect("router should respond");
+        let bytes = to_bytes(response.into_body(), usize::MAX)
+            .await
+            .expect("body should be readable");
+        let json: serde_json::Value =
+            serde_json::from_slice(&bytes).expect("response should be JSON");
+        let settings = json["data"]["settings"]
+            .as_array()
+            .expect("settings should be listed");
+        let port = settings
+            .iter()
+            .find(|field| field["name"] == "port")
+            .expect("port should be described");
+        assert_eq!(port["type"], "integer");
+        assert_eq!(port["default"], 8000);
+        assert_eq!(port["restart_required"], true);
+
+        let candidate = serde_json::json!({
+            "auto_start_cameras": true,
+            "auto_detect_cameras": "yes",
+            "esphome_hostname": "controller.local",
+            "network_camera_hostnames": ["esp32cam1.local", "not a host"]
+        });
+        let (status, json) =
+            post_json(state.clone(), "/api/config/validate", candidate.clone()).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(json["data"]["valid"], false);
+        let errors = json["data"]["errors"]
+            .as_object()
+            .expect("errors should be an object");
+        assert_eq!(
+            errors.keys().collect::<Vec<_>>(),
+            vec!["auto_detect_cameras", "network_camera_hostnames"]
+        );
 
-async fn clear_camera_configs(State(_state): State<Arc<AppState>>) -> Json<ApiResponse<()>> {
-    // For now, just acknowledge the clear request
-    // In a full implementation, this would remove all cameras from configuration
-    info!("Clear all cameras requested");
-    Json(ApiResponse::success(()))
-}
+        // Saving refuses the bad hostname and leaves the config alone
+        let mut candidate = candidate;
+        candidate["auto_detect_cameras"] = serde_json::json!(true);
+        let (status, _) = post_json(state.clone(), "/api/config", candidate).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_ne!(
+            current_user_config(&state).await.esphome_hostname,
+            "controller.local"
+        );
+    }
 
-async fn reset_config(State(_state): State<Arc<AppState>>) -> Json<ApiResponse<()>> {
-    // For now, just acknowledge the reset request
-    // In a full implementation, this would reset configuration to defaults
-    info!("Config reset to defaults requested");
-    Json(ApiResponse::success(()))
-}
+    #[tokio::test]
+    async fn test_health_lists_background_tasks() {
+        let temp_dir = tempfile::TempDir::new().expect("temp dir should be created");
+        let state = test_state(temp_dir.path());
+        state
+            .tasks
+            .spawn_tracked("camera_manager", std::future::pending());
+        state.tasks.spawn_tracked("short_lived", async {});
+        tokio::task::yield_now().await;
+
+        let request = Request::builder()
+            .uri("/api/health")
+            .body(Body::empty())
+            .expect("request should build");
+        let response = create_router(state)
+            .oneshot(request)
+            .await
+            .expect("router should respond");
+        // A long-lived component that has stopped makes the server unhealthy
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        let bytes = to_bytes(response.into_body(), usize::MAX)
+            .await
+            .expect("body should be readable");
+        let json: serde_json::Value =
+            serde_json::from_slice(&bytes).expect("response should be JSON");
+        assert_eq!(json["data"]["healthy"], false);
+        assert_eq!(json["data"]["tasks"][0]["name"], "camera_manager");
+        assert_eq!(json["data"]["tasks"][0]["state"], "running");
+        assert_eq!(json["data"]["tasks"][1]["state"], "completed");
+    }
 
-async fn get_camera_brightness(
-    Path(camera_id): Path<String>,
-    State(state): State<Arc<AppState>>,
-) -> Json<ApiResponse<BrightnessResponse>> {
-    info!("Getting brightness for camera: {}", camera_id);
+    #[tokio::test]
+    async fn test_cors_preflight_allows_only_listed_origins() {
+        let temp_dir = tempfile::TempDir::new().expect("temp dir should be created");
+        let preflight = |origin: &str, uri: &str| {
+            Request::builder()
+                .method(Method::OPTIONS)
+                .uri(uri)
+                .header("origin", origin)
+                .header("access-control-request-method", "PATCH")
+                .header("access-control-request-headers", "content-type")
+                .body(Body::empty())
+                .expect("request should build")
+        };
 
-    // Determine camera type and route to appropriate manager
-    if camera_id.starts_with(USB_DEVICE_PREFIX_WITH_COLON) {
-        match state.usb_camera_manager.get_brightness(camera_id).await {
-            Ok(brightness) => {
-                info!("Current brightness for USB camera: {}", brightness);
-                Json(ApiResponse::success(BrightnessResponse { brightness }))
-            }
-            Err(e) => {
-                error!("Failed to get USB camera brightness: {}", e);
-                Json(ApiResponse::<BrightnessResponse>::error(format!(
-                    "Failed to get camera brightness: {e}"
-                )))
-            }
-        }
-    } else {
-        // ESPHome cameras don't support brightness control
-        Json(ApiResponse::<BrightnessResponse>::error(
-            "ESPHome cameras do not support brightness control".to_string(),
-        ))
+        // Off by default: nothing is allowed
+        let router = create_router(test_state(temp_dir.path()));
+        let response = router
+            .oneshot(preflight(
+                "http://localhost:5173",
+                "/api/case-types/Federal_9mm",
+            ))
+            .await
+            .expect("router should respond");
+        assert!(
+            response
+                .headers()
+                .get("access-control-allow-origin")
+                .is_none()
+        );
+
+        let settings = Settings {
+            cors_allowed_origins: vec!["http://localhost:5173".to_string()],
+            ..Settings::default()
+        };
+        let (state, _, _) = test_state_with_managers(temp_dir.path(), Vec::new(), settings);
+        let router = create_router(state.clone());
+        let response = router
+            .clone()
+            .oneshot(preflight(
+                "http://localhost:5173",
+                "/api/case-types/Federal_9mm",
+            ))
+            .await
+            .expect("router should respond");
+        assert_eq!(response.status(), StatusCode::OK);
+        let headers = response.headers();
+        let header = |name: &str| headers.get(name).and_then(|value| value.to_str().ok());
+        assert_eq!(
+            header("access-control-allow-origin"),
+            Some("http://localhost:5173")
+        );
+        assert_eq!(header("access-control-allow-credentials"), Some("true"));
+        let methods = header("access-control-allow-methods").expect("methods should be listed");
+        assert!(methods.contains("PATCH"), "{methods}");
+        let allowed = header("access-control-allow-headers").expect("headers should be listed");
+        assert!(allowed.contains("x-debug-timing"), "{allowed}");
+
+        // Another origin isn't echoed back
+        let response = router
+            .clone()
+            .oneshot(preflight(
+                "http://evil.example",
+                "/api/case-types/Federal_9mm",
+            ))
+            .await
+            .expect("router should respond");
+        assert!(
+            response
+                .headers()
+                .get("access-control-allow-origin")
+                .is_none()
+        );
+        // Pages outside /api aren't opened up
+        let response = router
+            .oneshot(preflight("http://localhost:5173", "/"))
+            .await
+            .expect("router should respond");
+        assert!(
+            response
+                .headers()
+                .get("access-control-allow-origin")
+                .is_none()
+        );
+
+        let (_, health) = get_json(state, "/api/health").await;
+        assert_eq!(
+            health["data"]["cors"],
+            serde_json::json!({"enabled": true, "allowed_origins": ["http://localhost:5173"]})
+        );
     }
-}
 
-async fn set_camera_brightness(
-    Path(camera_id): Path<String>,
-    State(state): State<Arc<AppState>>,
-    Json(payload): Json<BrightnessRequest>,
-) -> Json<ApiResponse<()>> {
-    info!(
-        "Setting brightness for camera: {} to {}",
-        camera_id, payload.brightness
-    );
+    #[tokio::test]
+    async fn test_logs_are_listed_and_streamed() {
+        use tracing_subscriber::layer::SubscriberExt;
 
-    // Validate brightness range (typically 0-100 or similar)
-    if payload.brightness < 0 || payload.brightness > 255 {
-        return Json(ApiResponse::<()>::error(
-            "Brightness must be between 0 and 255".to_string(),
-        ));
+        let temp_dir = tempfile::TempDir::new().expect("temp dir should be created");
+        let state = test_state(temp_dir.path());
+        let emit = |emit: &dyn Fn()| {
+            let subscriber = tracing_subscriber::registry().with(state.logs.layer());
+            tracing::subscriber::with_default(subscriber, emit);
+        };
+        emit(&|| {
+            tracing::info!(target: "shell_sorter::server", "listening");
+            tracing::warn!(target: "shell_sorter::usb_camera_controller", "no frames");
+            tracing::warn!(target: "shell_sorter::controller_monitor", "slow response");
+        });
+
+        let request = Request::builder()
+            .uri("/api/logs?limit=10&level=warn&target=shell_sorter::usb_camera_controller")
+            .body(Body::empty())
+            .expect("request should build");
+        let response = create_router(state.clone())
+            .oneshot(request)
+            .await
+            .expect("router should respond");
+        assert_eq!(response.status(), StatusCode::OK);
+        let bytes = to_bytes(response.into_body(), usize::MAX)
+            .await
+            .expect("body should be readable");
+        let json: serde_json::Value =
+            serde_json::from_slice(&bytes).expect("response should be JSON");
+        let entries = json["data"]["entries"]
+            .as_array()
+            .expect("entries should be a list");
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0]["message"], "no frames");
+        assert_eq!(entries[0]["level"], "warn");
+
+        let request = Request::builder()
+            .uri("/api/logs/stream?level=warn")
+            .body(Body::empty())
+            .expect("request should build");
+        let response = create_router(state.clone())
+            .oneshot(request)
+            .await
+            .expect("router should respond");
+        assert_eq!(
+            response.headers()["content-type"],
+            "text/event-stream",
+            "{:?}",
+            response.headers()
+        );
+        emit(&|| {
+            tracing::info!("filtered out");
+            tracing::error!("servo jammed");
+        });
+        let mut body = response.into_body().into_data_stream();
+        let frame = body
+            .next()
+            .await
+            .expect("an event should be streamed")
+            .expect("the frame should be readable");
+        let frame = String::from_utf8_lossy(&frame);
+        assert!(frame.starts_with("event: log\n"), "{frame}");
+        assert!(frame.contains("servo jammed"), "{frame}");
     }
 
-    // Determine camera type and route to appropriate manager
-    if camera_id.starts_with(USB_DEVICE_PREFIX_WITH_COLON) {
-        match state
-            .usb_camera_manager
-            .set_brightness(camera_id, payload.brightness)
+    #[tokio::test]
+    async fn test_version_reports_build_info() {
+        let temp_dir = tempfile::TempDir::new().expect("temp dir should be created");
+        let request = Request::builder()
+            .uri("/api/version")
+            .body(Body::empty())
+            .expect("request should build");
+        let response = create_router(test_state(temp_dir.path()))
+            .oneshot(request)
             .await
-        {
-            Ok(()) => {
-                info!("Successfully set USB camera brightness");
-                Json(ApiResponse::success(()))
-            }
-            Err(e) => {
-                error!("Failed to set USB camera brightness: {}", e);
-                Json(ApiResponse::<()>::error(format!(
-                    "Failed to set camera brightness: {e}"
-                )))
+            .expect("router should respond");
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let bytes = to_bytes(response.into_body(), usize::MAX)
+            .await
+            .expect("body should be readable");
+        let body: serde_json::Value = serde_json::from_slice(&bytes).expect("body should be JSON");
+
+        let version = body["data"]["version"].as_str().unwrap_or_default();
+        let release = version.split(['-', '+']).next().unwrap_or_default();
+        let parts: Vec<&str> = release.split('.').collect();
+        assert_eq!(parts.len(), 3, "{version} should be semver");
+        assert!(parts.iter().all(|part| part.parse::<u64>().is_ok()));
+
+        let commit = body["data"]["commit"].as_str().unwrap_or_default();
+        assert!(
+            commit == "unknown" || commit.len() >= 7,
+            "bad commit {commit}"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_list_endpoints_answer_if_none_match() {
+        let temp_dir = tempfile::TempDir::new().expect("temp dir should be created");
+        let state = test_state(temp_dir.path());
+
+        let get = |uri: &'static str, etag: Option<String>| {
+            let state = state.clone();
+            async move {
+                let mut request = Request::builder().uri(uri);
+                if let Some(etag) = etag {
+                    request = request.header("if-none-match", etag);
+                }
+                let response = create_router(state)
+                    .oneshot(request.body(Body::empty()).expect("request should build"))
+                    .await
+                    .expect("router should respond");
+                let etag = response
+                    .headers()
+                    .get("etag")
+                    .and_then(|value| value.to_str().ok())
+                    .map(str::to_string);
+                let status = response.status();
+                let bytes = to_bytes(response.into_body(), usize::MAX)
+                    .await
+                    .expect("body should be readable");
+                (status, etag, bytes.len())
             }
+        };
+
+        let (status, etag, _) = get("/api/shells", None).await;
+        assert_eq!(status, StatusCode::OK);
+        let etag = etag.expect("shell list should carry an ETag");
+        assert!(etag.starts_with("W/\""), "{etag}");
+
+        for _ in 0..2 {
+            let (status, repeat_etag, length) = get("/api/shells", Some(etag.clone())).await;
+            assert_eq!(status, StatusCode::NOT_MODIFIED);
+            assert_eq!(repeat_etag.as_ref(), Some(&etag));
+            assert_eq!(length, 0);
         }
-    } else {
-        // ESPHome cameras don't support brightness control
-        Json(ApiResponse::<()>::error(
-            "ESPHome cameras do not support brightness control".to_string(),
-        ))
+
+        state
+            .shell_data_manager
+            .save_shell(
+                &ShellDataManager::generate_session_id(),
+                &Shell::new("Winchester".to_string(), "9mm".to_string()),
+            )
+            .expect("shell should save");
+        let (status, new_etag, length) = get("/api/shells", Some(etag.clone())).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_ne!(new_etag, Some(etag));
+        assert!(length > 0);
+
+        // Camera selections saved to the user config change the camera list's ETag
+        let (status, etag, _) = get("/api/cameras", None).await;
+        assert_eq!(status, StatusCode::OK);
+        let etag = etag.expect("camera list should carry an ETag");
+        let (status, _, _) = get("/api/cameras", Some(etag.clone())).await;
+        assert_eq!(status, StatusCode::NOT_MODIFIED);
+        state
+            .config_writer
+            .mutate(|config| config.set_selected_cameras(vec![CameraId::from("usb:0")]))
+            .expect("mutation should queue");
+        state
+            .config_writer
+            .current()
+            .await
+            .expect("config writer should answer");
+        let (status, _, _) = get("/api/cameras", Some(etag)).await;
+        assert_eq!(status, StatusCode::OK);
+
+        let (status, etag, _) = get("/api/status", None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(etag.is_some());
     }
 }
@@ -0,0 +1,312 @@
+//! Golden JSON for the types the web UI and CLI parse.
+//!
+//! Each test serialises a fixed value and compares it with a file under
+//! `tests/fixtures/wire_format`. A failure means the API changed shape: if
+//! that was intended, update the golden file to the JSON printed by the test.
+
+use super::*;
+use crate::orientation::Rotation;
+use crate::shell_data::{CaptureCoverage, CapturedImage};
+use crate::shell_migrations::CURRENT_SHELL_SCHEMA;
+use chrono::{DateTime, Utc};
+use serde::de::DeserializeOwned;
+
+fn timestamp(value: &str) -> DateTime<Utc> {
+    value.parse().expect("timestamp should parse")
+}
+
+fn golden(name: &str) -> serde_json::Value {
+    let path = std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/fixtures/wire_format")
+        .join(format!("{name}.json"));
+    let text = std::fs::read_to_string(&path).expect("golden file should be readable");
+    serde_json::from_str(&text).expect("golden file should be JSON")
+}
+
+/// `value` serialises exactly to the golden file `name`
+fn assert_golden(name: &str, value: impl Serialize) {
+    let actual = serde_json::to_value(value).expect("value should serialise");
+    assert_eq!(
+        actual,
+        golden(name),
+        "wire format of {name} changed; if intended, set tests/fixtures/wire_format/{name}.json to:\n{}",
+        serde_json::to_string_pretty(&actual).unwrap_or_default()
+    );
+}
+
+/// The golden file `name` deserialises and serialises back unchanged
+fn assert_round_trip<T: Serialize + DeserializeOwned>(name: &str) {
+    let parsed: T = serde_json::from_value(golden(name)).expect("golden should deserialise");
+    assert_golden(name, parsed);
+}
+
+#[test]
+fn test_camera_info_wire_format() {
+    let camera = CameraInfo {
+        camera: Descriptor {
+            id: CameraId::from("usb:046d:0825:0"),
+            kind: CameraKind::Usb,
+            name: "USB Camera 0".to_string(),
+            nickname: Some("top".to_string()),
+            online: true,
+            esp: None,
+            usb: Some(crate::camera::UsbDetails {
+                index: 0,
+                vendor_id: Some("046d".to_string()),
+                product_id: Some("0825".to_string()),
+                serial_number: None,
+                supported_formats: vec![crate::usb_camera_controller::CameraFormatInfo {
+                    width: 1920,
+                    height: 1080,
+                    fps: 30,
+                    format: "MJPEG".to_string(),
+                }],
+                current_format: None,
+            }),
+        },
+        view_type: Some(ViewType::Side.to_string()),
+        is_active: true,
+        is_selected: true,
+        capture_stats: CaptureStats {
+            attempted: 3,
+            succeeded: 2,
+            failed: 1,
+            last_error: Some("timed out".to_string()),
+            last_success: Some(timestamp("2025-07-01T12:00:00Z")),
+            average_latency_ms: Some(120.5),
+            last_warmup: None,
+            average_processing_ms: Some(48.25),
+            passthrough_frames: 12,
+            processed_frames: 3,
+            index_recoveries: 1,
+        },
+        active_streams: 1,
+        stream_health: StreamHealth::default(),
+        orientation: Orientation {
+            rotation: Rotation::Clockwise90,
+            mirror: true,
+        },
+        stream_oriented: false,
+        offline_for_secs: None,
+        stale: false,
+        pre_capture: None,
+        stream_mode: None,
+        preset: Some("bench".to_string()),
+    };
+    assert_golden("camera_info", camera);
+}
+
+#[test]
+fn test_api_response_wire_format() {
+    assert_golden(
+        "api_response",
+        serde_json::json!({
+            "success": ApiResponse::success(vec!["9mm"]),
+            "error": ApiResponse::<()>::error(
+                ErrorCode::CameraNotFound,
+                "Camera not found".to_string(),
+            ),
+            "validation": ApiResponse::<()>::failure(ApiError::validation(
+                "Invalid configuration",
+                BTreeMap::from([(
+                    "esphome_hostname".to_string(),
+                    "Must not be empty".to_string(),
+                )]),
+            )),
+        }),
+    );
+}
+
+#[test]
+fn test_shell_wire_format() {
+    let mut image = CapturedImage::new(
+        1,
+        "3f2a9c1e-0000-4000-8000-000000000001_camera_1.jpg".to_string(),
+        "left".to_string(),
+        ViewType::Tail,
+    );
+    image.region_x = Some(10);
+    image.region_y = Some(20);
+    image.region_width = Some(300);
+    image.region_height = Some(200);
+    let shell = Shell {
+        date_captured: timestamp("2025-07-01T12:00:00Z"),
+        brand: "Winchester".to_string(),
+        shell_type: "9mm".to_string(),
+        image_filenames: vec![image.filename.clone()],
+        captured_images: Some(vec![image]),
+        include: true,
+        notes: Some("dented rim".to_string()),
+        flags: vec![ShellFlag::Blurry, ShellFlag::Other("scratched".to_string())],
+        region_updated_at: Some(timestamp("2025-07-02T08:30:00Z")),
+        revision: 3,
+        capture: Some(CaptureCoverage {
+            expected: vec!["esphome_left".to_string(), "usb:046d:0825:0".to_string()],
+            delivered: vec!["esphome_left".to_string()],
+            failed: BTreeMap::from([(
+                "usb:046d:0825:0".to_string(),
+                "USB camera usb:046d:0825:0 is disconnected".to_string(),
+            )]),
+            skipped: BTreeMap::new(),
+        }),
+        batch_id: None,
+        settings_snapshot: None,
+        weight_grains: None,
+        length_mm: None,
+        measured_at: None,
+        schema_version: CURRENT_SHELL_SCHEMA,
+    };
+    assert_golden("shell", &shell);
+    assert_round_trip::<Shell>("shell");
+}
+
+#[test]
+fn test_status_data_wire_format() {
+    assert_golden(
+        "status_data",
+        StatusData {
+            status: "Ready".to_string(),
+            total_sorted: 42,
+            disk_space: Some(DiskSpaceReport {
+                path: PathBuf::from("/srv/shell-sorter/images"),
+                free_mb: 1800,
+                min_free_mb: 500,
+                low: false,
+            }),
+            needs_setup: false,
+            maintenance: MaintenanceStatus::default(),
+            overheating: vec![TemperatureReading {
+                sensor: "esp32cam1.local/esp_temperature"
+                    .parse()
+                    .expect("sensor should parse"),
+                celsius: 72.5,
+                read_at: timestamp("2026-10-16T09:30:00Z"),
+                overheated: true,
+            }],
+            read_only: false,
+            active_camera_preset: Some("bench".to_string()),
+        },
+    );
+}
+
+#[test]
+fn test_controller_times_wire_format() {
+    assert_golden(
+        "sensor_readings",
+        crate::controller_monitor::SensorReadings {
+            case_ready: true,
+            case_in_view: false,
+            timestamp: timestamp("2026-10-16T09:30:00Z"),
+        },
+    );
+    assert_round_trip::<crate::controller_monitor::SensorReadings>("sensor_readings");
+
+    assert_golden(
+        "hardware_status",
+        HardwareStatus {
+            details: HashMap::from([
+                ("controller".to_string(), "Connected".to_string()),
+                (
+                    "esphome_hostname".to_string(),
+                    "shell-sorter-controller.local".to_string(),
+                ),
+            ]),
+            times: ControllerTimes {
+                last_seen: Some(timestamp("2026-10-16T09:29:55.250Z")),
+                last_seen_age_seconds: Some(5),
+                last_check: Some(timestamp("2026-10-16T09:29:55Z")),
+                last_check_age_seconds: Some(5),
+            },
+        },
+    );
+    // Before the first health check
+    assert_eq!(
+        serde_json::to_value(crate::controller_monitor::ControllerStatus::default().times())
+            .expect("times should serialise"),
+        serde_json::json!({
+            "last_seen": null,
+            "last_seen_age_seconds": null,
+            "last_check": null,
+            "last_check_age_seconds": null,
+        })
+    );
+}
+
+#[test]
+fn test_config_data_wire_format() {
+    assert_golden(
+        "config_data",
+        ConfigData {
+            auto_start_cameras: true,
+            auto_detect_cameras: false,
+            esphome_hostname: "shell-sorter-controller.local".to_string(),
+            network_camera_hostnames: vec!["esp32cam1.local".to_string()],
+            camera_orientations: Some(BTreeMap::from([(
+                "esp32cam1.local".to_string(),
+                Orientation {
+                    rotation: Rotation::Half,
+                    mirror: false,
+                },
+            )])),
+            directories: None,
+        },
+    );
+    assert_round_trip::<ConfigData>("config_data");
+}
+
+#[test]
+fn test_model_metrics_wire_format() {
+    let classes = vec!["Federal_40sw".to_string(), "Winchester_9mm".to_string()];
+    let predictions = [
+        ("Winchester_9mm", "Winchester_9mm"),
+        ("Winchester_9mm", "Federal_40sw"),
+        ("Federal_40sw", "Federal_40sw"),
+        ("Federal_40sw", "Federal_40sw"),
+    ]
+    .map(|(actual, predicted)| (actual.to_string(), predicted.to_string()));
+    let metrics =
+        ModelMetrics::from_predictions("shell_classifier_20250701_120000", &classes, &predictions);
+    assert_golden("model_metrics", &metrics);
+    assert_round_trip::<ModelMetrics>("model_metrics");
+}
+
+#[test]
+fn test_enum_wire_formats() {
+    /// `value` serialises to `expected` and parses back to itself
+    fn pin<T>(value: T, expected: serde_json::Value)
+    where
+        T: Serialize + DeserializeOwned + PartialEq + std::fmt::Debug,
+    {
+        assert_eq!(
+            serde_json::to_value(&value).expect("value should serialise"),
+            expected,
+            "{value:?}"
+        );
+        assert_eq!(
+            serde_json::from_value::<T>(expected).expect("value should deserialise"),
+            value
+        );
+    }
+
+    pin(CameraKind::EspHome, serde_json::json!("esphome"));
+    pin(CameraKind::Usb, serde_json::json!("usb"));
+    pin(StreamMode::Mjpeg, serde_json::json!("mjpeg"));
+    pin(StreamMode::SnapshotPoll, serde_json::json!("snapshot_poll"));
+    pin(ViewType::Side, serde_json::json!("side"));
+    pin(ViewType::Tail, serde_json::json!("tail"));
+    pin(ViewType::Mouth, serde_json::json!("mouth"));
+    pin(ViewType::Unknown, serde_json::json!("unknown"));
+    pin(ShellFlag::Damaged, serde_json::json!("Damaged"));
+    pin(
+        ShellFlag::WrongOrientation,
+        serde_json::json!("WrongOrientation"),
+    );
+    pin(ShellFlag::Blurry, serde_json::json!("Blurry"));
+    pin(
+        ShellFlag::Other("scratched".to_string()),
+        serde_json::json!("scratched"),
+    );
+    pin(Rotation::None, serde_json::json!(0));
+    pin(Rotation::Clockwise270, serde_json::json!(270));
+    pin(LogLevel::Warn, serde_json::json!("warn"));
+}
@@ -0,0 +1,187 @@
+//! Opt-in breakdown of where a request's time went, for the `Server-Timing` header.
+//!
+//! With `debug` on, or an `X-Debug-Timing: 1` request header, the server runs
+//! the request inside [`collect`], which puts a collector in a task-local.
+//! [`time`] and [`time_sync`] record how long each named step took into it:
+//! round trips to a manager, disk work, serialising the response. The steps
+//! come back as a `Server-Timing` header, which browser devtools show in the
+//! network panel. Without a collector they only run the step, so requests
+//! that didn't ask pay nothing but a task-local lookup.
+//!
+//! Task-locals don't follow work into `tokio::spawn` or `spawn_blocking`, so
+//! time the await on the spawned work rather than the work inside it.
+
+use std::fmt::Write;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use axum::http::{HeaderMap, HeaderValue};
+
+/// Request header asking for the breakdown when `debug` is off
+pub const DEBUG_TIMING_HEADER: &str = "x-debug-timing";
+
+/// Response header the breakdown is returned in
+pub const SERVER_TIMING_HEADER: &str = "server-timing";
+
+/// Waiting on a manager task for its answer, described by the manager's name
+pub const ACTOR: &str = "actor";
+/// Reading or writing the data or image directories
+pub const DISK: &str = "disk";
+/// Turning the response body into JSON
+pub const SERIALIZE: &str = "serialize";
+/// The whole request, as seen by the timing middleware
+pub const TOTAL: &str = "total";
+
+tokio::task_local! {
+    static COLLECTOR: ServerTiming;
+}
+
+/// One timed step of a request
+#[derive(Debug, Clone, PartialEq)]
+pub struct Metric {
+    pub name: &'static str,
+    pub description: Option<String>,
+    pub duration: Duration,
+}
+
+/// The steps recorded for one request, in the order they finished
+#[derive(Debug, Clone, Default)]
+pub struct ServerTiming {
+    metrics: Arc<Mutex<Vec<Metric>>>,
+}
+
+impl ServerTiming {
+    fn push(&self, metric: Metric) {
+        if let Ok(mut metrics) = self.metrics.lock() {
+            metrics.push(metric);
+        }
+    }
+
+    pub fn metrics(&self) -> Vec<Metric> {
+        self.metrics
+            .lock()
+            .map(|metrics| metrics.clone())
+            .unwrap_or_default()
+    }
+
+    /// The `Server-Timing` header value, durations in milliseconds
+    pub fn header_value(&self) -> Option<HeaderValue> {
+        let mut value = String::new();
+        for metric in self.metrics() {
+            if !value.is_empty() {
+                value.push_str(", ");
+            }
+            value.push_str(metric.name);
+            if let Some(description) = &metric.description {
+                // Quotes and backslashes would end the quoted string early
+                let description = description.replace(['"', '\\'], "");
+                let _ = write!(value, ";desc=\"{description}\"");
+            }
+            let _ = write!(value, ";dur={:.3}", metric.duration.as_secs_f64() * 1000.0);
+        }
+        HeaderValue::from_str(&value).ok()
+    }
+}
+
+/// Whether the request asked for timing with `X-Debug-Timing: 1`
+pub fn requested(headers: &HeaderMap) -> bool {
+    headers
+        .get(DEBUG_TIMING_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.trim() == "1")
+}
+
+/// Run `future` collecting the steps timed within it, adding its own duration as [`TOTAL`]
+pub async fn collect<F: Future>(future: F) -> (F::Output, ServerTiming) {
+    let timing = ServerTiming::default();
+    let started = Instant::now();
+    let output = COLLECTOR.scope(timing.clone(), future).await;
+    timing.push(Metric {
+        name: TOTAL,
+        description: None,
+        duration: started.elapsed(),
+    });
+    (output, timing)
+}
+
+/// Whether the current request is collecting timings
+pub fn is_enabled() -> bool {
+    COLLECTOR.try_with(|_| ()).is_ok()
+}
+
+fn record(name: &'static str, description: Option<&str>, duration: Duration) {
+    let _ = COLLECTOR.try_with(|timing| {
+        timing.push(Metric {
+            name,
+            description: description.map(str::to_string),
+            duration,
+        })
+    });
+}
+
+/// Await `future`, recording how long it took as `name` when the request is collecting
+pub async fn time<F: Future>(
+    name: &'static str,
+    description: Option<&str>,
+    future: F,
+) -> F::Output {
+    if !is_enabled() {
+        return future.await;
+    }
+    let started = Instant::now();
+    let output = future.await;
+    record(name, description, started.elapsed());
+    output
+}
+
+/// [`time`] for synchronous work
+pub fn time_sync<T>(name: &'static str, description: Option<&str>, work: impl FnOnce() -> T) -> T {
+    if !is_enabled() {
+        return work();
+    }
+    let started = Instant::now();
+    let output = work();
+    record(name, description, started.elapsed());
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_steps_are_only_recorded_while_collecting() {
+        // Outside a collector the steps just run
+        assert!(!is_enabled());
+        assert_eq!(time(DISK, None, async { 1 }).await, 1);
+
+        let (output, timing) = collect(async {
+            assert!(is_enabled());
+            let answer = time(ACTOR, Some("Camera manager"), async { 2 }).await;
+            time_sync(SERIALIZE, None, || answer * 2)
+        })
+        .await;
+        assert_eq!(output, 4);
+        let names: Vec<&str> = timing.metrics().iter().map(|metric| metric.name).collect();
+        assert_eq!(names, vec![ACTOR, SERIALIZE, TOTAL]);
+
+        let header = timing.header_value().expect("header should be valid");
+        let header = header.to_str().expect("header should be text");
+        assert!(
+            header.starts_with("actor;desc=\"Camera manager\";dur="),
+            "{header}"
+        );
+        assert!(header.contains(", serialize;dur="), "{header}");
+    }
+
+    #[test]
+    fn test_only_a_one_asks_for_timing() {
+        let mut headers = HeaderMap::new();
+        assert!(!requested(&headers));
+        headers.insert(DEBUG_TIMING_HEADER, HeaderValue::from_static("0"));
+        assert!(!requested(&headers));
+        headers.insert(DEBUG_TIMING_HEADER, HeaderValue::from_static("1"));
+        assert!(requested(&headers));
+    }
+}
@@ -0,0 +1,676 @@
+//! Single-file bundles of one capture session, for sharing problem cases.
+//!
+//! A bundle is a zip holding `manifest.json`, `shell.json`, every image the
+//! shell references under `images/`, the session summary as `session.json`
+//! when the capture wrote one, and the composite with its sidecar metadata
+//! when one has been generated. Entries are copied one at a time, so large
+//! images never all sit in memory. Where the summary and the shell record
+//! disagree about the images, the manifest lists how.
+//!
+//! Importing recreates the session under a fresh session ID so a bundle can be
+//! imported next to the session it came from. Image filenames that start with
+//! the old session ID are renamed to start with the new one and the rest get
+//! the new ID as a prefix, in the shell record and the session summary alike.
+//! Only the entry names above are accepted, and every
+//! image name goes through [`SafeName::file_name`], so nothing in a bundle can
+//! be written outside the image and data directories.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Seek, Write};
+use std::path::{Path, PathBuf};
+use tracing::{info, warn};
+use uuid::Uuid;
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipArchive, ZipWriter};
+
+use crate::composite;
+use crate::config::Settings;
+use crate::safe_name::SafeName;
+use crate::session_summary::SessionSummary;
+use crate::shell_data::{Shell, ShellDataManager};
+use crate::{OurError, OurResult};
+
+/// Bundle entry describing the export
+pub const MANIFEST_FILE: &str = "manifest.json";
+/// Bundle entry holding the shell record
+pub const SHELL_FILE: &str = "shell.json";
+/// Prefix of the bundle entries holding images
+pub const IMAGES_PREFIX: &str = "images/";
+/// Bundle entry holding the session summary, see [`crate::session_summary`]
+pub const SUMMARY_FILE: &str = "session.json";
+/// Bundle entry holding the composite image
+pub const COMPOSITE_FILE: &str = "composite.jpg";
+/// Bundle entry holding the composite's sidecar metadata
+pub const COMPOSITE_METADATA_FILE: &str = "composite.json";
+
+/// Written into every manifest; bundles from a newer format are refused
+///
+/// Format 2 added [`SUMMARY_FILE`].
+pub const BUNDLE_FORMAT_VERSION: u32 = 2;
+
+/// Largest bundle, and largest single entry, accepted on import
+pub const MAX_BUNDLE_BYTES: u64 = 512 * 1024 * 1024;
+
+/// Largest `manifest.json` or `shell.json` accepted on import
+const MAX_JSON_BYTES: u64 = 1024 * 1024;
+
+/// What a bundle contains and where it came from
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct BundleManifest {
+    pub format_version: u32,
+    /// Version of shell-sorter that wrote the bundle
+    pub crate_version: String,
+    pub exported_at: DateTime<Utc>,
+    /// Session ID on the machine that exported it
+    pub session_id: String,
+    /// Image filenames, each stored under `images/`
+    pub images: Vec<String>,
+    /// Whether `composite.jpg` is included
+    pub composite: bool,
+    /// Whether `session.json` is included
+    #[serde(default)]
+    pub summary: bool,
+    /// How the session summary disagrees with the shell record about the images
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub summary_differences: Vec<String>,
+}
+
+/// A session recreated from a bundle
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct ImportedSession {
+    /// The fresh session ID the shell was saved under
+    pub session_id: String,
+    /// The session ID in the bundle's manifest
+    pub source_session_id: String,
+    /// Image filenames as written to the image directory
+    pub images: Vec<String>,
+    pub composite: bool,
+}
+
+/// A bundle written to a temporary file, removed when this is dropped
+#[derive(Debug)]
+pub struct TempBundle {
+    path: PathBuf,
+}
+
+impl TempBundle {
+    /// A new, not yet created, path in the system temporary directory
+    pub fn new(purpose: &str) -> Self {
+        Self {
+            path: std::env::temp_dir()
+                .join(format!("shell-sorter-{purpose}-{}.zip", Uuid::new_v4())),
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for TempBundle {
+    fn drop(&mut self) {
+        if let Err(e) = fs::remove_file(&self.path)
+            && e.kind() != io::ErrorKind::NotFound
+        {
+            warn!("Failed to remove {}: {e}", self.path.display());
+        }
+    }
+}
+
+/// Every image a shell refers to, in order and without repeats
+pub(crate) fn referenced_images(shell: &Shell) -> Vec<String> {
+    let mut seen = BTreeSet::new();
+    shell
+        .image_filenames
+        .iter()
+        .chain(
+            shell
+                .captured_images
+                .iter()
+                .flatten()
+                .map(|image| &image.filename),
+        )
+        .filter(|filename| seen.insert(filename.as_str()))
+        .cloned()
+        .collect()
+}
+
+fn bundle_error(action: &str, e: impl std::fmt::Display) -> OurError {
+    OurError::App(format!("Failed to {action}: {e}"))
+}
+
+/// Write the bundle for `session_id` to `writer`
+pub fn export_session<W: Write + Seek>(
+    shell_data: &ShellDataManager,
+    settings: &Settings,
+    session_id: &str,
+    writer: W,
+) -> OurResult<BundleManifest> {
+    let shell = shell_data.load_shell(session_id)?;
+    let images = referenced_images(&shell);
+    let image_paths = images
+        .iter()
+        .map(|filename| {
+            let filename = SafeName::file_name("image filename", filename)?;
+            let path = settings.image_directory.join(&filename);
+            if !path.is_file() {
+                return Err(OurError::App(format!(
+                    "Image {filename} of session {session_id} is missing"
+                )));
+            }
+            Ok(path)
+        })
+        .collect::<OurResult<Vec<_>>>()?;
+
+    let summary = SessionSummary::load(&settings.image_directory, session_id)?;
+    let summary_differences = summary
+        .as_ref()
+        .map(|summary| summary.differences(&shell))
+        .unwrap_or_default();
+    for difference in &summary_differences {
+        warn!("Exporting session {session_id}: {difference}");
+    }
+
+    let composite_path = composite::composite_path(&settings.data_directory, session_id);
+    let composite_metadata_path = composite::metadata_path(&composite_path);
+    let manifest = BundleManifest {
+        format_version: BUNDLE_FORMAT_VERSION,
+        crate_version: env!("CARGO_PKG_VERSION").to_string(),
+        exported_at: Utc::now(),
+        session_id: session_id.to_string(),
+        images: images.clone(),
+        composite: composite_path.is_file(),
+        summary: summary.is_some(),
+        summary_differences,
+    };
+
+    // JPEGs don't shrink, so only the JSON is deflated
+    let deflated = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+    let stored = SimpleFileOptions::default()
+        .compression_method(CompressionMethod::Stored)
+        .large_file(true);
+
+    let mut zip = ZipWriter::new(writer);
+    zip.start_file(MANIFEST_FILE, deflated)
+        .map_err(|e| bundle_error("write bundle manifest", e))?;
+    serde_json::to_writer_pretty(&mut zip, &manifest)?;
+    zip.start_file(SHELL_FILE, deflated)
+        .map_err(|e| bundle_error("write bundle shell", e))?;
+    serde_json::to_writer_pretty(&mut zip, &shell)?;
+    if let Some(summary) = &summary {
+        zip.start_file(SUMMARY_FILE, deflated)
+            .map_err(|e| bundle_error("write bundle session summary", e))?;
+        zip.write_all(summary.to_json()?.as_bytes())
+            .map_err(|e| bundle_error("write bundle session summary", e))?;
+    }
+
+    let mut copy_entry = |name: String, path: &Path, options: SimpleFileOptions| -> OurResult<()> {
+        zip.start_file(name.as_str(), options)
+            .map_err(|e| bundle_error(&format!("add {name} to bundle"), e))?;
+        let mut file =
+            File::open(path).map_err(|e| bundle_error(&format!("open {}", path.display()), e))?;
+        io::copy(&mut file, &mut zip)
+            .map_err(|e| bundle_error(&format!("add {name} to bundle"), e))?;
+        Ok(())
+    };
+    for (filename, path) in images.iter().zip(&image_paths) {
+        copy_entry(format!("{IMAGES_PREFIX}{filename}"), path, stored)?;
+    }
+    if manifest.composite {
+        copy_entry(COMPOSITE_FILE.to_string(), &composite_path, stored)?;
+        if composite_metadata_path.is_file() {
+            copy_entry(
+                COMPOSITE_METADATA_FILE.to_string(),
+                &composite_metadata_path,
+                deflated,
+            )?;
+        }
+    }
+    zip.finish()
+        .map_err(|e| bundle_error("finish bundle", e))?
+        .flush()
+        .map_err(|e| bundle_error("finish bundle", e))?;
+
+    info!(
+        "Exported session {session_id} with {} images",
+        manifest.images.len()
+    );
+    Ok(manifest)
+}
+
+/// Export `session_id` to a temporary file, ready to be streamed
+pub fn export_to_temp_file(
+    shell_data: &ShellDataManager,
+    settings: &Settings,
+    session_id: &str,
+) -> OurResult<(TempBundle, BundleManifest)> {
+    let bundle = TempBundle::new("export");
+    let file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create_new(true)
+        .open(bundle.path())
+        .map_err(|e| bundle_error("create export file", e))?;
+    let manifest = export_session(shell_data, settings, session_id, io::BufWriter::new(file))?;
+    Ok((bundle, manifest))
+}
+
+/// What a bundle entry is, judged by its name alone
+enum BundleEntry {
+    Manifest,
+    Shell,
+    Summary,
+    Image(SafeName),
+    Composite,
+    CompositeMetadata,
+}
+
+impl BundleEntry {
+    fn classify(name: &str) -> OurResult<Self> {
+        match name {
+            MANIFEST_FILE => Ok(Self::Manifest),
+            SHELL_FILE => Ok(Self::Shell),
+            SUMMARY_FILE => Ok(Self::Summary),
+            COMPOSITE_FILE => Ok(Self::Composite),
+            COMPOSITE_METADATA_FILE => Ok(Self::CompositeMetadata),
+            _ => match name.strip_prefix(IMAGES_PREFIX) {
+                Some(filename) => Ok(Self::Image(SafeName::file_name("bundle image", filename)?)),
+                None => Err(OurError::InvalidName {
+                    field: "bundle entry".to_string(),
+                    reason: format!("{name:?} is not part of a session bundle"),
+                }),
+            },
+        }
+    }
+}
+
+/// The name an imported image is saved under
+fn remap_filename(
+    filename: &str,
+    old_session_id: &str,
+    new_session_id: &str,
+) -> OurResult<SafeName> {
+    let renamed = match filename.strip_prefix(old_session_id) {
+        Some(rest) if !old_session_id.is_empty() => format!("{new_session_id}{rest}"),
+        _ => format!("{new_session_id}_{filename}"),
+    };
+    SafeName::file_name("image filename", &renamed)
+}
+
+/// Copy at most [`MAX_BUNDLE_BYTES`] of entry `index` to a new file at `path`
+///
+/// A partly written file is removed again.
+fn extract_entry<R: Read + Seek>(
+    archive: &mut ZipArchive<R>,
+    index: usize,
+    path: &Path,
+) -> OurResult<()> {
+    let entry = archive
+        .by_index(index)
+        .map_err(|e| bundle_error("read bundle", e))?;
+    let mut file = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(path)
+        .map_err(|e| bundle_error(&format!("create {}", path.display()), e))?;
+    let result = match io::copy(&mut entry.take(MAX_BUNDLE_BYTES + 1), &mut file) {
+        Ok(copied) if copied > MAX_BUNDLE_BYTES => Err(OurError::App(format!(
+            "{} is larger than {MAX_BUNDLE_BYTES} bytes",
+            path.display()
+        ))),
+        Ok(_) => Ok(()),
+        Err(e) => Err(bundle_error(&format!("write {}", path.display()), e)),
+    };
+    if result.is_err() {
+        fs::remove_file(path).ok();
+    }
+    result
+}
+
+fn read_json<T: for<'de> Deserialize<'de>>(entry: &mut impl Read, name: &str) -> OurResult<T> {
+    let mut contents = Vec::new();
+    entry
+        .take(MAX_JSON_BYTES)
+        .read_to_end(&mut contents)
+        .map_err(|e| bundle_error(&format!("read {name}"), e))?;
+    serde_json::from_slice(&contents)
+        .map_err(|e| OurError::App(format!("Invalid {name} in bundle: {e}")))
+}
+
+/// Recreate the session in `reader` under a fresh session ID
+///
+/// Nothing is left behind if the bundle turns out to be invalid part way through.
+pub fn import_session<R: Read + Seek>(
+    shell_data: &ShellDataManager,
+    settings: &Settings,
+    reader: R,
+) -> OurResult<ImportedSession> {
+    let mut archive =
+        ZipArchive::new(reader).map_err(|e| OurError::App(format!("Not a session bundle: {e}")))?;
+
+    // Check every entry name before anything is written
+    let mut manifest: Option<BundleManifest> = None;
+    let mut shell: Option<Shell> = None;
+    let mut summary: Option<SessionSummary> = None;
+    let mut images = BTreeMap::new();
+    let mut composite = None;
+    let mut composite_metadata = None;
+    for index in 0..archive.len() {
+        let mut entry = archive
+            .by_index(index)
+            .map_err(|e| bundle_error("read bundle", e))?;
+        if entry.is_dir() {
+            continue;
+        }
+        match BundleEntry::classify(entry.name())? {
+            BundleEntry::Manifest => manifest = Some(read_json(&mut entry, MANIFEST_FILE)?),
+            BundleEntry::Shell => shell = Some(read_json(&mut entry, SHELL_FILE)?),
+            BundleEntry::Summary => summary = Some(read_json(&mut entry, SUMMARY_FILE)?),
+            BundleEntry::Image(filename) => {
+                images.insert(filename.to_string(), index);
+            }
+            BundleEntry::Composite => composite = Some(index),
+            BundleEntry::CompositeMetadata => composite_metadata = Some(index),
+        }
+    }
+    let manifest =
+        manifest.ok_or_else(|| OurError::App(format!("Bundle has no {MANIFEST_FILE}")))?;
+    let mut shell = shell.ok_or_else(|| OurError::App(format!("Bundle has no {SHELL_FILE}")))?;
+    if manifest.format_version > BUNDLE_FORMAT_VERSION {
+        return Err(OurError::App(format!(
+            "Bundle format {} is newer than this version of shell-sorter supports ({BUNDLE_FORMAT_VERSION})",
+            manifest.format_version
+        )));
+    }
+    if let Some(missing) = referenced_images(&shell)
+        .into_iter()
+        .find(|filename| !images.contains_key(filename))
+    {
+        return Err(OurError::App(format!(
+            "Bundle is missing image {missing:?} referenced by {SHELL_FILE}"
+        )));
+    }
+
+    let session_id = ShellDataManager::generate_session_id();
+    let renamed = images
+        .keys()
+        .map(|filename| {
+            remap_filename(filename, &manifest.session_id, &session_id)
+                .map(|new_name| (filename.clone(), new_name))
+        })
+        .collect::<OurResult<BTreeMap<_, _>>>()?;
+
+    let mut written = Vec::new();
+    let result = (|| -> OurResult<()> {
+        fs::create_dir_all(&settings.image_directory)
+            .map_err(|e| bundle_error("create image directory", e))?;
+        for (filename, index) in &images {
+            let Some(new_name) = renamed.get(filename) else {
+                continue;
+            };
+            let path = settings.image_directory.join(new_name);
+            extract_entry(&mut archive, *index, &path)?;
+            written.push(path);
+        }
+
+        if let Some(index) = composite {
+            let path = composite::composite_path(&settings.data_directory, &session_id);
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)
+                    .map_err(|e| bundle_error("create composites directory", e))?;
+            }
+            extract_entry(&mut archive, index, &path)?;
+            written.push(path.clone());
+            if let Some(index) = composite_metadata {
+                let metadata_path = composite::metadata_path(&path);
+                extract_entry(&mut archive, index, &metadata_path)?;
+                written.push(metadata_path);
+            }
+        }
+
+        let rename = |filename: &mut String| {
+            if let Some(new_name) = renamed.get(filename.as_str()) {
+                *filename = new_name.to_string();
+            }
+        };
+        shell.image_filenames.iter_mut().for_each(rename);
+        shell
+            .captured_images
+            .iter_mut()
+            .flatten()
+            .for_each(|image| rename(&mut image.filename));
+        if let Some(mut summary) = summary {
+            summary.session_id = session_id.clone();
+            summary.rename_images(|filename| renamed.get(filename).map(SafeName::to_string));
+            if let Some(snapshot) = &mut summary.settings_snapshot {
+                snapshot.shell_record = format!("{session_id}.json");
+            }
+            written.push(summary.write(&settings.image_directory)?);
+        }
+        shell_data.save_shell(&session_id, &shell)
+    })();
+
+    if let Err(e) = result {
+        for path in &written {
+            if let Err(remove_error) = fs::remove_file(path) {
+                warn!("Failed to remove {}: {remove_error}", path.display());
+            }
+        }
+        return Err(e);
+    }
+
+    info!(
+        "Imported session {} from bundle as {session_id}",
+        manifest.session_id
+    );
+    Ok(ImportedSession {
+        session_id,
+        source_session_id: manifest.session_id,
+        images: renamed.values().map(SafeName::to_string).collect(),
+        composite: composite.is_some(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ViewType;
+    use crate::session_summary::{CameraStatus, CameraSummary};
+    use crate::shell_data::CapturedImage;
+    use std::io::Cursor;
+    use tempfile::TempDir;
+
+    fn test_settings(temp_dir: &TempDir) -> Settings {
+        Settings {
+            image_directory: temp_dir.path().join("images"),
+            data_directory: temp_dir.path().join("data"),
+            ..Settings::default()
+        }
+    }
+
+    #[test]
+    fn test_bundle_round_trip() {
+        let temp_dir = TempDir::new().expect("Failed to create temp dir");
+        let settings = test_settings(&temp_dir);
+        let shell_data = ShellDataManager::new(settings.data_directory.clone());
+
+        let session_id = "original";
+        let side = format!("{session_id}_camera_0.jpg");
+        let tail = "tail.png".to_string();
+        fs::create_dir_all(&settings.image_directory).expect("image directory should be created");
+        let side_bytes: Vec<u8> = (0..=255).cycle().take(70_000).collect();
+        fs::write(settings.image_directory.join(&side), &side_bytes)
+            .expect("image should be written");
+        fs::write(settings.image_directory.join(&tail), b"tail image")
+            .expect("image should be written");
+        let composite_path = composite::composite_path(&settings.data_directory, session_id);
+        fs::create_dir_all(
+            composite_path
+                .parent()
+                .expect("composite should have a parent"),
+        )
+        .expect("composites directory should be created");
+        fs::write(&composite_path, b"composite").expect("composite should be written");
+        fs::write(composite::metadata_path(&composite_path), b"{}")
+            .expect("composite metadata should be written");
+
+        let mut shell = Shell::new("Winchester".to_string(), "9mm".to_string());
+        shell.add_image(side.clone());
+        shell.add_captured_image(CapturedImage::new(
+            0,
+            side.clone(),
+            "left".to_string(),
+            ViewType::Side,
+        ));
+        shell.add_captured_image(CapturedImage::new(
+            1,
+            tail.clone(),
+            "right".to_string(),
+            ViewType::Tail,
+        ));
+        shell_data
+            .save_shell(session_id, &shell)
+            .expect("shell should be saved");
+        let cameras = vec![
+            CameraSummary::new("left".to_string(), Some(0), CameraStatus::Captured, None),
+            CameraSummary::new("right".to_string(), Some(1), CameraStatus::Captured, None),
+        ];
+        SessionSummary::new(
+            session_id,
+            "Shell Sorter v1.0",
+            Utc::now(),
+            &shell,
+            cameras,
+            &settings.image_directory,
+        )
+        .write(&settings.image_directory)
+        .expect("summary should be written");
+
+        let mut bundle = Cursor::new(Vec::new());
+        let manifest = export_session(&shell_data, &settings, session_id, &mut bundle)
+            .expect("export should work");
+        assert_eq!(manifest.images, vec![side.clone(), tail.clone()]);
+        assert!(manifest.composite);
+        assert!(manifest.summary);
+        assert!(
+            manifest.summary_differences.is_empty(),
+            "{:?}",
+            manifest.summary_differences
+        );
+
+        bundle.set_position(0);
+        let imported = import_session(&shell_data, &settings, bundle).expect("import should work");
+        assert_ne!(imported.session_id, session_id);
+        assert_eq!(imported.source_session_id, session_id);
+        assert!(imported.composite);
+
+        let copy = shell_data
+            .load_shell(&imported.session_id)
+            .expect("imported shell should load");
+        let new_side = format!("{}_camera_0.jpg", imported.session_id);
+        let new_tail = format!("{}_tail.png", imported.session_id);
+        assert_eq!(copy.image_filenames, vec![new_side.clone()]);
+        let captured: Vec<&str> = copy
+            .captured_images
+            .iter()
+            .flatten()
+            .map(|image| image.filename.as_str())
+            .collect();
+        assert_eq!(captured, vec![new_side.as_str(), new_tail.as_str()]);
+        assert_eq!(copy.brand, shell.brand);
+
+        let read =
+            |name: &str| fs::read(settings.image_directory.join(name)).expect("image should exist");
+        assert_eq!(read(&new_side), side_bytes);
+        assert_eq!(read(&new_tail), b"tail image");
+        let new_composite =
+            composite::composite_path(&settings.data_directory, &imported.session_id);
+        assert_eq!(
+            fs::read(&new_composite).expect("composite should exist"),
+            b"composite"
+        );
+        assert!(composite::metadata_path(&new_composite).is_file());
+
+        let summary = SessionSummary::load(&settings.image_directory, &imported.session_id)
+            .expect("summary should load")
+            .expect("summary should be imported");
+        assert_eq!(summary.session_id, imported.session_id);
+        assert_eq!(
+            summary.images().collect::<Vec<_>>(),
+            vec![new_side.as_str(), new_tail.as_str()]
+        );
+        assert!(summary.differences(&copy).is_empty());
+    }
+
+    fn bundle_with(entries: &[(&str, &[u8])]) -> Cursor<Vec<u8>> {
+        let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+        for (name, contents) in entries {
+            zip.start_file(*name, SimpleFileOptions::default())
+                .expect("entry should start");
+            zip.write_all(contents).expect("entry should be written");
+        }
+        let mut bundle = zip.finish().expect("bundle should finish");
+        bundle.set_position(0);
+        bundle
+    }
+
+    #[test]
+    fn test_import_rejects_entries_outside_the_bundle_layout() {
+        let temp_dir = TempDir::new().expect("Failed to create temp dir");
+        let settings = test_settings(&temp_dir);
+        let shell_data = ShellDataManager::new(settings.data_directory.clone());
+        let manifest = serde_json::to_vec(&BundleManifest {
+            format_version: BUNDLE_FORMAT_VERSION,
+            crate_version: "0.0.0".to_string(),
+            exported_at: Utc::now(),
+            session_id: "s".to_string(),
+            images: vec!["a.jpg".to_string()],
+            composite: false,
+            summary: false,
+            summary_differences: Vec::new(),
+        })
+        .expect("manifest should serialize");
+        let mut shell = Shell::new("Winchester".to_string(), "9mm".to_string());
+        shell.add_image("a.jpg".to_string());
+        let shell = serde_json::to_vec(&shell).expect("shell should serialize");
+
+        for evil in [
+            "images/../../escape.jpg",
+            "../escape.jpg",
+            "images/.hidden",
+            "other.txt",
+        ] {
+            let bundle = bundle_with(&[
+                (MANIFEST_FILE, &manifest),
+                (SHELL_FILE, &shell),
+                ("images/a.jpg", b"a"),
+                (evil, b"evil"),
+            ]);
+            assert!(
+                import_session(&shell_data, &settings, bundle).is_err(),
+                "{evil}"
+            );
+        }
+
+        // A shell whose image isn't in the bundle writes nothing
+        let bundle = bundle_with(&[(MANIFEST_FILE, &manifest), (SHELL_FILE, &shell)]);
+        assert!(import_session(&shell_data, &settings, bundle).is_err());
+
+        assert!(!temp_dir.path().join("escape.jpg").exists());
+        assert!(
+            shell_data
+                .list_shells()
+                .expect("shells should list")
+                .is_empty()
+        );
+        let images = fs::read_dir(&settings.image_directory)
+            .map(|entries| entries.count())
+            .unwrap_or_default();
+        assert_eq!(images, 0);
+    }
+}
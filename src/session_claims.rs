@@ -0,0 +1,323 @@
+//! Advisory claims on capture sessions, so two people tagging at once don't
+//! overwrite each other.
+//!
+//! A claim records who is working on a session until it expires. Claiming a
+//! session you already hold renews it; claims nobody renews lapse on their own,
+//! so an abandoned browser tab never locks a session for good. Nothing here is
+//! enforced: saving over someone else's claim only needs `force`. Claims live
+//! in memory and are lost on restart unless a file is configured to keep them.
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+
+use crate::shell_data::write_atomic;
+use crate::{OurError, OurResult};
+
+/// How long a claim lasts unless it is renewed
+pub const DEFAULT_CLAIM_DURATION: Duration = Duration::from_secs(15 * 60);
+
+/// Longest claimant name accepted
+pub const MAX_CLAIMANT_LENGTH: usize = 100;
+
+/// Someone working on a session
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct SessionClaim {
+    pub session_id: String,
+    pub claimant: String,
+    pub claimed_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+}
+
+impl SessionClaim {
+    pub fn is_active(&self, now: DateTime<Utc>) -> bool {
+        self.expires_at > now
+    }
+
+    /// Whether `claimant` is the one holding this claim, ignoring case and surrounding spaces
+    pub fn is_held_by(&self, claimant: &str) -> bool {
+        self.claimant.to_lowercase() == claimant.trim().to_lowercase()
+    }
+}
+
+/// What happened to a claim request
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ClaimOutcome {
+    /// The session is now claimed, or the claim was renewed
+    Claimed(SessionClaim),
+    /// Someone else holds the session
+    Held(SessionClaim),
+}
+
+/// Active claims by session ID, shared by every request handler
+#[derive(Debug, Clone, Default)]
+pub struct SessionClaims {
+    claims: Arc<Mutex<HashMap<String, SessionClaim>>>,
+    /// Where claims are kept across restarts, if anywhere
+    path: Option<PathBuf>,
+}
+
+impl SessionClaims {
+    /// Claims kept in `path`, loading any that haven't expired yet
+    pub fn persisted(path: PathBuf) -> Self {
+        let now = Utc::now();
+        let claims = match std::fs::read_to_string(&path) {
+            Ok(json) => match serde_json::from_str::<Vec<SessionClaim>>(&json) {
+                Ok(claims) => claims
+                    .into_iter()
+                    .filter(|claim| claim.is_active(now))
+                    .map(|claim| (claim.session_id.clone(), claim))
+                    .collect(),
+                Err(e) => {
+                    warn!(
+                        "Ignoring unreadable session claims in {}: {e}",
+                        path.display()
+                    );
+                    HashMap::new()
+                }
+            },
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => HashMap::new(),
+            Err(e) => {
+                warn!("Failed to read session claims from {}: {e}", path.display());
+                HashMap::new()
+            }
+        };
+        Self {
+            claims: Arc::new(Mutex::new(claims)),
+            path: Some(path),
+        }
+    }
+
+    fn lock(&self) -> OurResult<MutexGuard<'_, HashMap<String, SessionClaim>>> {
+        self.claims
+            .lock()
+            .map_err(|_| OurError::App("Session claims lock poisoned".to_string()))
+    }
+
+    /// Drop expired claims and write the rest to the claims file, if there is one
+    fn save(
+        &self,
+        claims: &mut HashMap<String, SessionClaim>,
+        now: DateTime<Utc>,
+    ) -> OurResult<()> {
+        claims.retain(|_, claim| claim.is_active(now));
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        let mut active: Vec<&SessionClaim> = claims.values().collect();
+        active.sort_by(|a, b| a.session_id.cmp(&b.session_id));
+        let json = serde_json::to_string_pretty(&active)?;
+        write_atomic(path, json.as_bytes())
+            .map_err(|e| OurError::App(format!("Failed to save session claims: {e}")))
+    }
+
+    /// Claim `session_id` for `claimant` until `duration` from `now`, renewing their own claim
+    pub fn claim(
+        &self,
+        session_id: &str,
+        claimant: &str,
+        duration: Duration,
+        now: DateTime<Utc>,
+    ) -> OurResult<ClaimOutcome> {
+        let mut claims = self.lock()?;
+        let claimed_at = match claims.get(session_id) {
+            Some(claim) if claim.is_active(now) && !claim.is_held_by(claimant) => {
+                return Ok(ClaimOutcome::Held(claim.clone()));
+            }
+            Some(claim) if claim.is_active(now) => claim.claimed_at,
+            _ => now,
+        };
+        let duration = chrono::Duration::from_std(duration)
+            .map_err(|e| OurError::App(format!("Invalid claim duration: {e}")))?;
+        let claim = SessionClaim {
+            session_id: session_id.to_string(),
+            claimant: claimant.trim().to_string(),
+            claimed_at,
+            expires_at: now + duration,
+        };
+        claims.insert(session_id.to_string(), claim.clone());
+        self.save(&mut claims, now)?;
+        info!(
+            "Session {session_id} claimed by {} until {}",
+            claim.claimant, claim.expires_at
+        );
+        Ok(ClaimOutcome::Claimed(claim))
+    }
+
+    /// Give up `claimant`'s claim on `session_id`, returning the other holder's claim if it isn't theirs
+    pub fn release(
+        &self,
+        session_id: &str,
+        claimant: &str,
+        now: DateTime<Utc>,
+    ) -> OurResult<Option<SessionClaim>> {
+        let mut claims = self.lock()?;
+        match claims.get(session_id) {
+            Some(claim) if claim.is_active(now) && !claim.is_held_by(claimant) => {
+                return Ok(Some(claim.clone()));
+            }
+            Some(_) => {
+                claims.remove(session_id);
+                info!("Session {session_id} released by {}", claimant.trim());
+            }
+            None => {}
+        }
+        self.save(&mut claims, now)?;
+        Ok(None)
+    }
+
+    /// The claim on `session_id`, if it hasn't expired
+    pub fn holder(&self, session_id: &str, now: DateTime<Utc>) -> OurResult<Option<SessionClaim>> {
+        Ok(self
+            .lock()?
+            .get(session_id)
+            .filter(|claim| claim.is_active(now))
+            .cloned())
+    }
+
+    /// Every claim that hasn't expired, soonest to expire first
+    pub fn active(&self, now: DateTime<Utc>) -> OurResult<Vec<SessionClaim>> {
+        let mut active: Vec<SessionClaim> = self
+            .lock()?
+            .values()
+            .filter(|claim| claim.is_active(now))
+            .cloned()
+            .collect();
+        active.sort_by(|a, b| {
+            a.expires_at
+                .cmp(&b.expires_at)
+                .then_with(|| a.session_id.cmp(&b.session_id))
+        });
+        Ok(active)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MINUTE: Duration = Duration::from_secs(60);
+
+    fn claimed(outcome: ClaimOutcome) -> SessionClaim {
+        match outcome {
+            ClaimOutcome::Claimed(claim) => claim,
+            ClaimOutcome::Held(claim) => panic!("session is held by {}", claim.claimant),
+        }
+    }
+
+    #[test]
+    fn test_claims_renew_and_expire() {
+        let claims = SessionClaims::default();
+        let start = Utc::now();
+        let later = |minutes| start + chrono::Duration::minutes(minutes);
+
+        let alice = claimed(
+            claims
+                .claim("session", "Alice", DEFAULT_CLAIM_DURATION, start)
+                .expect("claim should succeed"),
+        );
+        assert_eq!(alice.expires_at, later(15));
+
+        // Someone else is turned away while the claim is active
+        match claims
+            .claim("session", "Bob", DEFAULT_CLAIM_DURATION, later(5))
+            .expect("claim should succeed")
+        {
+            ClaimOutcome::Held(claim) => assert_eq!(claim.claimant, "Alice"),
+            ClaimOutcome::Claimed(_) => panic!("Bob took Alice's claim"),
+        }
+
+        // Renewing keeps the original claim time and pushes the expiry back
+        let renewed = claimed(
+            claims
+                .claim("session", " alice ", DEFAULT_CLAIM_DURATION, later(10))
+                .expect("claim should succeed"),
+        );
+        assert_eq!(renewed.claimed_at, start);
+        assert_eq!(renewed.expires_at, later(25));
+        assert!(
+            claims
+                .holder("session", later(20))
+                .expect("holder should be readable")
+                .is_some()
+        );
+
+        // An abandoned claim lapses and anyone can take the session
+        assert_eq!(
+            claims
+                .holder("session", later(25))
+                .expect("holder should be readable"),
+            None
+        );
+        let bob = claimed(
+            claims
+                .claim("session", "Bob", 5 * MINUTE, later(30))
+                .expect("claim should succeed"),
+        );
+        assert_eq!(bob.claimed_at, later(30));
+        assert_eq!(
+            claims.active(later(31)).expect("claims should be readable"),
+            vec![bob]
+        );
+
+        // Only the holder can release
+        assert!(
+            claims
+                .release("session", "Alice", later(31))
+                .expect("release should succeed")
+                .is_some()
+        );
+        assert_eq!(
+            claims
+                .release("session", "Bob", later(31))
+                .expect("release should succeed"),
+            None
+        );
+        assert!(
+            claims
+                .active(later(31))
+                .expect("claims should be readable")
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn test_persisted_claims_survive_a_restart() {
+        let temp_dir = tempfile::TempDir::new().expect("temp dir should be created");
+        let path = temp_dir.path().join("claims.json");
+        let now = Utc::now();
+
+        let claims = SessionClaims::persisted(path.clone());
+        claims
+            .claim("kept", "Alice", DEFAULT_CLAIM_DURATION, now)
+            .expect("claim should succeed");
+        claims
+            .claim(
+                "lapsed",
+                "Bob",
+                Duration::from_secs(1),
+                now - chrono::Duration::hours(1),
+            )
+            .expect("claim should succeed");
+
+        let reloaded = SessionClaims::persisted(path);
+        let active = reloaded.active(now).expect("claims should be readable");
+        assert_eq!(active.len(), 1);
+        assert_eq!(active[0].session_id, "kept");
+        assert_eq!(active[0].claimant, "Alice");
+
+        // Without a file, a new instance starts empty
+        assert!(
+            SessionClaims::default()
+                .active(now)
+                .expect("claims should be readable")
+                .is_empty()
+        );
+    }
+}
@@ -0,0 +1,468 @@
+//! A summary of what a capture session produced, written beside its images.
+//!
+//! Every capture writes `{session_id}_session.json` into the image directory,
+//! next to the session's `{session_id}_camera_{index}.jpg` images: when the
+//! session started and finished, the machine that captured it, and for each
+//! camera it expected, how the camera fared and the filename, size and
+//! sharpness of every image it delivered. Scripts can see what a capture
+//! produced without asking the API, and a copy of the image directory stays
+//! self-describing. The settings the cameras were on stay in the shell record,
+//! which the summary points to.
+//!
+//! Recapturing a session's missing cameras writes its summary again. Fields
+//! are always written in the same order so summaries diff cleanly, and
+//! [`SESSION_SUMMARY_VERSION`] is bumped when the layout changes. The shell
+//! record stays the source of truth: [`SessionSummary::differences`] is how
+//! the integrity check and bundle export notice the two drifting apart.
+
+use std::collections::BTreeSet;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::safe_name::SafeName;
+use crate::session_bundle::referenced_images;
+use crate::shell_data::{CaptureCoverage, CapturedImage, Shell, write_atomic};
+use crate::{OurError, OurResult};
+
+/// The layout of [`SessionSummary`], bumped when it changes
+pub const SESSION_SUMMARY_VERSION: u32 = 1;
+
+/// What a session summary's filename ends with, after the session ID
+pub const SESSION_SUMMARY_SUFFIX: &str = "_session.json";
+
+/// What a capture session produced, see the module docs
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct SessionSummary {
+    pub schema_version: u32,
+    pub session_id: String,
+    /// `machine_name` of the machine that captured the session
+    pub machine: String,
+    pub started_at: DateTime<Utc>,
+    pub finished_at: DateTime<Utc>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub batch_id: Option<String>,
+    /// Where the settings the session was captured with are kept, if they were recorded
+    pub settings_snapshot: Option<SnapshotReference>,
+    /// Every camera the session expected, in capture order
+    pub cameras: Vec<CameraSummary>,
+}
+
+/// The settings snapshot in a session's shell record
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct SnapshotReference {
+    /// The shell record holding it, relative to the data directory
+    pub shell_record: String,
+    /// See [`crate::settings_snapshot::SETTINGS_SNAPSHOT_VERSION`]
+    pub version: u32,
+    pub software_version: String,
+    pub commit: String,
+}
+
+/// How a camera fared in a session
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CameraStatus {
+    Captured,
+    Failed,
+    /// Left out because it was offline when the capture started
+    Skipped,
+}
+
+/// One camera's part in a session
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct CameraSummary {
+    /// An ESPHome camera's hostname or a USB camera's hardware ID
+    pub camera_id: String,
+    /// The index in its images' filenames, unset for cameras skipped before one was given
+    pub camera_index: Option<u32>,
+    pub nickname: Option<String>,
+    pub status: CameraStatus,
+    /// Why the camera failed or was skipped
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+    pub images: Vec<ImageSummary>,
+}
+
+impl CameraSummary {
+    /// A camera with no images yet, which [`SessionSummary::new`] fills in
+    pub fn new(
+        camera_id: String,
+        camera_index: Option<u32>,
+        status: CameraStatus,
+        reason: Option<String>,
+    ) -> Self {
+        Self {
+            camera_id,
+            camera_index,
+            nickname: None,
+            status,
+            reason,
+            images: Vec::new(),
+        }
+    }
+}
+
+/// One image a camera delivered
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct ImageSummary {
+    pub filename: String,
+    /// Read from the saved file, unset if it couldn't be
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    /// See [`crate::burst::sharpness`]; only burst frames are scored
+    pub sharpness: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sequence: Option<u32>,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub preferred: bool,
+}
+
+impl ImageSummary {
+    fn new(image: &CapturedImage, image_directory: &Path) -> Self {
+        let (width, height) = image_dimensions(&image_directory.join(&image.filename))
+            .map_or((None, None), |(width, height)| (Some(width), Some(height)));
+        Self {
+            filename: image.filename.clone(),
+            width,
+            height,
+            sharpness: image.sharpness,
+            sequence: image.sequence,
+            preferred: image.preferred,
+        }
+    }
+}
+
+/// Width and height from an image file's header, without decoding the rest
+fn image_dimensions(path: &Path) -> Option<(u32, u32)> {
+    image::ImageReader::open(path)
+        .and_then(|reader| reader.with_guessed_format())
+        .ok()?
+        .into_dimensions()
+        .ok()
+}
+
+/// The cameras `coverage` expected, in capture order, with how each fared
+pub fn cameras_from_coverage(coverage: &CaptureCoverage) -> Vec<CameraSummary> {
+    coverage
+        .expected
+        .iter()
+        .map(|camera_id| {
+            let (status, reason) = if coverage.delivered.contains(camera_id) {
+                (CameraStatus::Captured, None)
+            } else if let Some(reason) = coverage.skipped.get(camera_id) {
+                (CameraStatus::Skipped, Some(reason.clone()))
+            } else {
+                (
+                    CameraStatus::Failed,
+                    coverage.failed.get(camera_id).cloned(),
+                )
+            };
+            CameraSummary::new(
+                camera_id.clone(),
+                coverage.camera_index(camera_id),
+                status,
+                reason,
+            )
+        })
+        .collect()
+}
+
+/// The name of `session_id`'s summary in the image directory
+pub fn summary_filename(session_id: &str) -> OurResult<SafeName> {
+    SafeName::file_name(
+        "session summary",
+        &format!("{session_id}{SESSION_SUMMARY_SUFFIX}"),
+    )
+}
+
+impl SessionSummary {
+    /// The summary of `shell`, captured on `machine` from `started_at` until now
+    ///
+    /// Each camera is given the shell's images with its camera index, sized
+    /// from the files in `image_directory`.
+    pub fn new(
+        session_id: &str,
+        machine: &str,
+        started_at: DateTime<Utc>,
+        shell: &Shell,
+        mut cameras: Vec<CameraSummary>,
+        image_directory: &Path,
+    ) -> Self {
+        for camera in &mut cameras {
+            camera.images = shell
+                .captured_images
+                .iter()
+                .flatten()
+                .filter(|image| Some(image.camera_index) == camera.camera_index)
+                .map(|image| ImageSummary::new(image, image_directory))
+                .collect();
+        }
+        Self {
+            schema_version: SESSION_SUMMARY_VERSION,
+            session_id: session_id.to_string(),
+            machine: machine.to_string(),
+            started_at,
+            finished_at: Utc::now(),
+            batch_id: shell.batch_id.clone(),
+            settings_snapshot: shell
+                .settings_snapshot
+                .as_ref()
+                .map(|snapshot| SnapshotReference {
+                    shell_record: format!("{session_id}.json"),
+                    version: snapshot.version,
+                    software_version: snapshot.software_version.clone(),
+                    commit: snapshot.commit.clone(),
+                }),
+            cameras,
+        }
+    }
+
+    /// Every image filename in the summary, in camera order
+    pub fn images(&self) -> impl Iterator<Item = &str> {
+        self.cameras
+            .iter()
+            .flat_map(|camera| &camera.images)
+            .map(|image| image.filename.as_str())
+    }
+
+    /// Give every image a new filename, as when a session is imported under a new ID
+    pub fn rename_images(&mut self, rename: impl Fn(&str) -> Option<String>) {
+        for image in self
+            .cameras
+            .iter_mut()
+            .flat_map(|camera| &mut camera.images)
+        {
+            if let Some(filename) = rename(&image.filename) {
+                image.filename = filename;
+            }
+        }
+    }
+
+    /// How the summary and `shell`'s record disagree about the captured images, empty if they don't
+    ///
+    /// Images added to the record later under camera indices of their own,
+    /// like uploads, weren't captured and aren't expected in the summary.
+    pub fn differences(&self, shell: &Shell) -> Vec<String> {
+        let summarised: BTreeSet<&str> = self.images().collect();
+        let referenced = referenced_images(shell);
+        let camera_indices: BTreeSet<u32> = self
+            .cameras
+            .iter()
+            .filter_map(|camera| camera.camera_index)
+            .collect();
+        let unsummarised = shell
+            .captured_images
+            .iter()
+            .flatten()
+            .filter(|image| camera_indices.contains(&image.camera_index))
+            .map(|image| image.filename.as_str())
+            .filter(|filename| !summarised.contains(filename))
+            .collect::<BTreeSet<_>>();
+        summarised
+            .iter()
+            .filter(|filename| !referenced.iter().any(|referenced| referenced == *filename))
+            .map(|filename| {
+                format!("{filename} is in the session summary but not the shell record")
+            })
+            .chain(unsummarised.into_iter().map(|filename| {
+                format!("{filename} is in the shell record but not the session summary")
+            }))
+            .collect()
+    }
+
+    /// The summary as written to disk, pretty-printed with a trailing newline
+    pub fn to_json(&self) -> OurResult<String> {
+        let mut json = serde_json::to_string_pretty(self)?;
+        json.push('\n');
+        Ok(json)
+    }
+
+    /// Write the summary into `image_directory`, replacing any earlier one whole
+    pub fn write(&self, image_directory: &Path) -> OurResult<PathBuf> {
+        let path = image_directory.join(summary_filename(&self.session_id)?);
+        write_atomic(&path, self.to_json()?.as_bytes()).map_err(|e| {
+            OurError::App(format!(
+                "Failed to write session summary {}: {e}",
+                path.display()
+            ))
+        })?;
+        Ok(path)
+    }
+
+    /// `session_id`'s summary, `None` for sessions captured before summaries were written
+    pub fn load(image_directory: &Path, session_id: &str) -> OurResult<Option<Self>> {
+        let path = image_directory.join(summary_filename(session_id)?);
+        match fs::read_to_string(&path) {
+            Ok(json) => serde_json::from_str(&json).map(Some).map_err(|e| {
+                OurError::App(format!(
+                    "Failed to parse session summary {}: {e}",
+                    path.display()
+                ))
+            }),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(OurError::App(format!(
+                "Failed to read session summary {}: {e}",
+                path.display()
+            ))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ViewType;
+    use crate::settings_snapshot::SettingsSnapshot;
+    use std::collections::BTreeMap;
+    use tempfile::TempDir;
+
+    fn timestamp(value: &str) -> DateTime<Utc> {
+        value.parse().expect("timestamp should parse")
+    }
+
+    fn write_jpeg(path: &Path, width: u32, height: u32) {
+        image::RgbImage::new(width, height)
+            .save(path)
+            .expect("image should be written");
+    }
+
+    /// A session whose second camera failed and third was offline
+    fn captured_session(image_directory: &Path) -> (Shell, CaptureCoverage) {
+        let session_id = "3f2a9c1e-0000-4000-8000-000000000001";
+        let filename = format!("{session_id}_camera_0.jpg");
+        write_jpeg(&image_directory.join(&filename), 64, 48);
+
+        let mut coverage = CaptureCoverage::new(vec![
+            "esphome_left".to_string(),
+            "usb:046d:0825:0".to_string(),
+            "esphome_right".to_string(),
+        ]);
+        coverage.skip("esphome_right", "not responding".to_string());
+        coverage.record("esphome_left", Ok(()));
+        coverage.record(
+            "usb:046d:0825:0",
+            Err("USB camera usb:046d:0825:0 is disconnected".to_string()),
+        );
+
+        let mut shell = Shell::new(String::new(), String::new());
+        shell.add_image(filename.clone());
+        shell.add_captured_image(CapturedImage::new(
+            0,
+            filename,
+            "left".to_string(),
+            ViewType::Side,
+        ));
+        shell.batch_id = Some("lot-7".to_string());
+        shell.settings_snapshot = Some(SettingsSnapshot {
+            version: 1,
+            software_version: "0.1.0".to_string(),
+            commit: "abc1234".to_string(),
+            jpeg_quality: 90,
+            flash_light: None,
+            cameras: BTreeMap::new(),
+        });
+        (shell, coverage)
+    }
+
+    #[test]
+    fn test_summary_format_is_pinned() {
+        let temp_dir = TempDir::new().expect("temp dir should be created");
+        let (shell, coverage) = captured_session(temp_dir.path());
+        let mut cameras = cameras_from_coverage(&coverage);
+        cameras[0].nickname = Some("left".to_string());
+        let mut summary = SessionSummary::new(
+            "3f2a9c1e-0000-4000-8000-000000000001",
+            "Shell Sorter v1.0",
+            timestamp("2025-07-01T12:00:00Z"),
+            &shell,
+            cameras,
+            temp_dir.path(),
+        );
+        summary.finished_at = timestamp("2025-07-01T12:00:03Z");
+
+        let golden =
+            Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/session_summary/v1.json");
+        let expected = fs::read_to_string(&golden).expect("golden file should be readable");
+        let actual = summary.to_json().expect("summary should serialise");
+        assert_eq!(
+            actual,
+            expected,
+            "session summary format changed; if intended, bump SESSION_SUMMARY_VERSION and set {} to:\n{actual}",
+            golden.display()
+        );
+        let parsed: SessionSummary =
+            serde_json::from_str(&expected).expect("golden file should parse");
+        assert_eq!(parsed, summary);
+    }
+
+    #[test]
+    fn test_summary_is_written_and_cross_checked() {
+        let temp_dir = TempDir::new().expect("temp dir should be created");
+        let (mut shell, coverage) = captured_session(temp_dir.path());
+        let session_id = "3f2a9c1e-0000-4000-8000-000000000001";
+        assert_eq!(
+            SessionSummary::load(temp_dir.path(), session_id).expect("load should succeed"),
+            None
+        );
+
+        let summary = SessionSummary::new(
+            session_id,
+            "Shell Sorter v1.0",
+            Utc::now(),
+            &shell,
+            cameras_from_coverage(&coverage),
+            temp_dir.path(),
+        );
+        let path = summary
+            .write(temp_dir.path())
+            .expect("summary should be written");
+        assert_eq!(
+            path,
+            temp_dir.path().join(format!("{session_id}_session.json"))
+        );
+        let loaded = SessionSummary::load(temp_dir.path(), session_id)
+            .expect("load should succeed")
+            .expect("summary should exist");
+        assert_eq!(loaded, summary);
+        assert!(loaded.differences(&shell).is_empty());
+
+        // An upload has a camera index of its own and isn't part of the capture
+        shell.add_captured_image(CapturedImage::new(
+            3,
+            "upload.jpg".to_string(),
+            "external".to_string(),
+            ViewType::Unknown,
+        ));
+        assert!(loaded.differences(&shell).is_empty());
+
+        // An image under a captured camera's index that the summary doesn't know of is reported
+        shell.add_captured_image(CapturedImage::new(
+            0,
+            "extra.jpg".to_string(),
+            "left".to_string(),
+            ViewType::Side,
+        ));
+        assert_eq!(
+            loaded.differences(&shell),
+            vec!["extra.jpg is in the shell record but not the session summary".to_string()]
+        );
+
+        // As is a summarised image the record has lost
+        shell.image_filenames.clear();
+        shell.captured_images = None;
+        assert_eq!(
+            loaded.differences(&shell),
+            vec![format!(
+                "{session_id}_camera_0.jpg is in the session summary but not the shell record"
+            )]
+        );
+    }
+}
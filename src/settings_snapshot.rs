@@ -0,0 +1,430 @@
+//! The settings a capture session was taken with, to tell configuration changes
+//! apart from other reasons image quality changes.
+//!
+//! A snapshot keeps only what changes what lands in the image: each camera's
+//! view type, orientation, region, resolution and brightness, whether the
+//! overlay is stamped on, the JPEG quality, the flash light and the software
+//! version. It is stored on the session's shell record, so it goes wherever
+//! the record goes, exports and bundles included.
+//!
+//! Snapshots carry their own [`SETTINGS_SNAPSHOT_VERSION`] rather than
+//! changing the shell schema: recording another setting only adds a field,
+//! which older snapshots read as unset, and the version says which fields a
+//! snapshot could have had.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::build_info::BuildInfo;
+use crate::config::{CameraConfig, Settings, ViewType};
+use crate::frame_region::{FrameRegion, FrameSize};
+use crate::orientation::Orientation;
+use crate::usb_camera_controller::CameraFormatInfo;
+
+/// The layout of [`SettingsSnapshot`], bumped when a field is added
+pub const SETTINGS_SNAPSHOT_VERSION: u32 = 1;
+
+/// Capture-relevant settings in effect when a session was captured
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct SettingsSnapshot {
+    pub version: u32,
+    pub software_version: String,
+    pub commit: String,
+    pub jpeg_quality: u8,
+    /// ESPHome light used as the flash; whether it was lit isn't tracked
+    #[serde(default)]
+    pub flash_light: Option<String>,
+    /// Settings of each camera captured from, by camera ID
+    #[serde(default)]
+    pub cameras: BTreeMap<String, CameraSettings>,
+}
+
+/// One camera's settings at capture time
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct CameraSettings {
+    #[serde(default)]
+    pub view_type: Option<ViewType>,
+    #[serde(default)]
+    pub orientation: Orientation,
+    #[serde(default)]
+    pub region: Option<FrameRegion>,
+    /// The USB camera's current format, or an ESPHome camera's manual or detected resolution
+    #[serde(default)]
+    pub resolution: Option<FrameSize>,
+    /// USB cameras only
+    #[serde(default)]
+    pub pixel_format: Option<String>,
+    /// USB cameras only
+    #[serde(default)]
+    pub fps: Option<u32>,
+    /// Software brightness from 0 to 100, USB cameras only
+    #[serde(default)]
+    pub brightness: Option<i64>,
+    /// Whether captures are stamped with the overlay
+    #[serde(default)]
+    pub overlay: bool,
+}
+
+/// What a camera reported about itself when the session was captured
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ObservedCamera {
+    pub format: Option<CameraFormatInfo>,
+    pub brightness: Option<i64>,
+}
+
+impl SettingsSnapshot {
+    /// What `cameras` are captured with, from the settings, their configs and what they report
+    pub fn new(
+        settings: &Settings,
+        camera_configs: &HashMap<String, CameraConfig>,
+        cameras: impl IntoIterator<Item = (String, ObservedCamera)>,
+    ) -> Self {
+        let build = BuildInfo::current();
+        Self {
+            version: SETTINGS_SNAPSHOT_VERSION,
+            software_version: build.version.to_string(),
+            commit: build.commit.to_string(),
+            jpeg_quality: settings.image_jpeg_quality,
+            flash_light: settings.controller_flash_light.clone(),
+            cameras: cameras
+                .into_iter()
+                .map(|(camera_id, observed)| {
+                    let camera =
+                        CameraSettings::new(settings, camera_configs.get(&camera_id), observed);
+                    (camera_id, camera)
+                })
+                .collect(),
+        }
+    }
+
+    /// Take on `other`'s cameras, replacing this snapshot's settings for any camera in both
+    pub fn merge_cameras(&mut self, other: SettingsSnapshot) {
+        self.cameras.extend(other.cameras);
+    }
+
+    /// The settings that differ between this snapshot, `a`, and `other`, `b`
+    ///
+    /// Session-wide settings come first, then each camera's in camera order. A
+    /// camera only one snapshot has is reported once, as `captured`.
+    pub fn diff(&self, other: &SettingsSnapshot) -> Vec<SettingDifference> {
+        let mut differences = Vec::new();
+        let session_wide = |snapshot: &SettingsSnapshot| {
+            let mut value = serde_json::to_value(snapshot).unwrap_or_default();
+            if let Some(fields) = value.as_object_mut() {
+                fields.remove("cameras");
+            }
+            value
+        };
+        compare(
+            None,
+            session_wide(self),
+            session_wide(other),
+            &mut differences,
+        );
+
+        let camera_ids: BTreeSet<&String> =
+            self.cameras.keys().chain(other.cameras.keys()).collect();
+        for camera_id in camera_ids {
+            match (self.cameras.get(camera_id), other.cameras.get(camera_id)) {
+                (Some(a), Some(b)) => compare(
+                    Some(camera_id),
+                    serde_json::to_value(a).unwrap_or_default(),
+                    serde_json::to_value(b).unwrap_or_default(),
+                    &mut differences,
+                ),
+                (a, b) => differences.push(SettingDifference {
+                    camera: Some(camera_id.clone()),
+                    setting: "captured".to_string(),
+                    a: Value::Bool(a.is_some()),
+                    b: Value::Bool(b.is_some()),
+                }),
+            }
+        }
+        differences
+    }
+}
+
+impl CameraSettings {
+    fn new(settings: &Settings, config: Option<&CameraConfig>, observed: ObservedCamera) -> Self {
+        let configured_resolution = config.and_then(|config| {
+            let width = config
+                .manual_resolution_width
+                .or(config.detected_resolution_width)?;
+            let height = config
+                .manual_resolution_height
+                .or(config.detected_resolution_height)?;
+            Some(FrameSize {
+                width: u32::try_from(width).ok()?,
+                height: u32::try_from(height).ok()?,
+            })
+        });
+        let format = observed.format;
+        Self {
+            view_type: config.and_then(|config| config.view_type),
+            orientation: config.map(CameraConfig::orientation).unwrap_or_default(),
+            region: config.and_then(CameraConfig::region),
+            resolution: format
+                .as_ref()
+                .map(|format| FrameSize {
+                    width: format.width,
+                    height: format.height,
+                })
+                .or(configured_resolution),
+            pixel_format: format.as_ref().map(|format| format.format.clone()),
+            fps: format.as_ref().map(|format| format.fps),
+            brightness: observed.brightness,
+            overlay: config
+                .and_then(|config| config.capture_overlay)
+                .unwrap_or(settings.capture_overlay),
+        }
+    }
+}
+
+/// A setting with different values in two sessions
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub struct SettingDifference {
+    /// The camera the setting belongs to, absent for session-wide settings
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub camera: Option<String>,
+    /// Dotted path to the setting, e.g. `orientation.rotation`
+    pub setting: String,
+    /// The value in the first session, null when it had none
+    pub a: Value,
+    /// The value in the second session, null when it had none
+    pub b: Value,
+}
+
+/// Note every leaf that differs between `a` and `b`
+fn compare(camera: Option<&String>, a: Value, b: Value, into: &mut Vec<SettingDifference>) {
+    let (mut a_leaves, mut b_leaves) = (BTreeMap::new(), BTreeMap::new());
+    leaves(String::new(), a, &mut a_leaves);
+    leaves(String::new(), b, &mut b_leaves);
+    let settings: BTreeSet<String> = a_leaves.keys().chain(b_leaves.keys()).cloned().collect();
+    for setting in settings {
+        let a = a_leaves.remove(&setting).unwrap_or(Value::Null);
+        let b = b_leaves.remove(&setting).unwrap_or(Value::Null);
+        if a != b {
+            into.push(SettingDifference {
+                camera: camera.cloned(),
+                setting,
+                a,
+                b,
+            });
+        }
+    }
+}
+
+/// Flatten `value` into its non-object values by dotted path
+fn leaves(path: String, value: Value, into: &mut BTreeMap<String, Value>) {
+    match value {
+        Value::Object(fields) => {
+            for (field, value) in fields {
+                let path = if path.is_empty() {
+                    field
+                } else {
+                    format!("{path}.{field}")
+                };
+                leaves(path, value, into);
+            }
+        }
+        value => {
+            into.insert(path, value);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::orientation::Rotation;
+    use serde_json::json;
+
+    fn usb_camera(brightness: i64) -> ObservedCamera {
+        ObservedCamera {
+            format: Some(CameraFormatInfo {
+                width: 1920,
+                height: 1080,
+                fps: 30,
+                format: "MJPEG".to_string(),
+            }),
+            brightness: Some(brightness),
+        }
+    }
+
+    #[test]
+    fn test_snapshot_keeps_each_cameras_effective_settings() {
+        let settings = Settings {
+            image_jpeg_quality: 85,
+            capture_overlay: true,
+            ..Settings::default()
+        };
+        let camera_configs = HashMap::from([
+            (
+                "esp32cam1.local".to_string(),
+                CameraConfig {
+                    view_type: Some(ViewType::Tail),
+                    detected_resolution_width: Some(800),
+                    detected_resolution_height: Some(600),
+                    manual_resolution_width: Some(1600),
+                    manual_resolution_height: Some(1200),
+                    region_x: Some(10),
+                    region_y: Some(20),
+                    region_width: Some(300),
+                    region_height: Some(200),
+                    rotation: Rotation::Half,
+                    capture_overlay: Some(false),
+                    ..CameraConfig::default()
+                },
+            ),
+            (
+                "usb:046d:0825:0".to_string(),
+                CameraConfig {
+                    view_type: Some(ViewType::Side),
+                    mirror: true,
+                    ..CameraConfig::default()
+                },
+            ),
+        ]);
+        let snapshot = SettingsSnapshot::new(
+            &settings,
+            &camera_configs,
+            [
+                ("esp32cam1.local".to_string(), ObservedCamera::default()),
+                ("usb:046d:0825:0".to_string(), usb_camera(70)),
+            ],
+        );
+
+        assert_eq!(snapshot.version, SETTINGS_SNAPSHOT_VERSION);
+        assert_eq!(snapshot.software_version, env!("CARGO_PKG_VERSION"));
+        assert_eq!(snapshot.jpeg_quality, 85);
+        assert_eq!(snapshot.flash_light, settings.controller_flash_light);
+        assert_eq!(
+            snapshot.cameras["esp32cam1.local"],
+            CameraSettings {
+                view_type: Some(ViewType::Tail),
+                orientation: Orientation {
+                    rotation: Rotation::Half,
+                    mirror: false,
+                },
+                region: Some(FrameRegion {
+                    x: 10,
+                    y: 20,
+                    width: 300,
+                    height: 200,
+                }),
+                // Set by hand, so it wins over the detected one
+                resolution: Some(FrameSize {
+                    width: 1600,
+                    height: 1200,
+                }),
+                pixel_format: None,
+                fps: None,
+                brightness: None,
+                overlay: false,
+            }
+        );
+        let usb = &snapshot.cameras["usb:046d:0825:0"];
+        assert_eq!(
+            usb.resolution,
+            Some(FrameSize {
+                width: 1920,
+                height: 1080,
+            })
+        );
+        assert_eq!(usb.pixel_format.as_deref(), Some("MJPEG"));
+        assert_eq!(usb.fps, Some(30));
+        assert_eq!(usb.brightness, Some(70));
+        assert!(usb.orientation.mirror);
+        assert!(
+            usb.overlay,
+            "the global setting applies without an override"
+        );
+        assert_eq!(usb.region, None);
+    }
+
+    #[test]
+    fn test_diff_names_each_changed_setting() {
+        let settings = Settings::default();
+        let configs = HashMap::new();
+        let before = SettingsSnapshot::new(
+            &settings,
+            &configs,
+            [
+                ("usb:046d:0825:0".to_string(), usb_camera(50)),
+                ("usb:046d:0825:1".to_string(), usb_camera(50)),
+            ],
+        );
+        assert!(before.diff(&before).is_empty());
+
+        let mut after = SettingsSnapshot::new(
+            &Settings {
+                image_jpeg_quality: 70,
+                ..Settings::default()
+            },
+            &configs,
+            [
+                ("usb:046d:0825:0".to_string(), usb_camera(80)),
+                ("esp32cam1.local".to_string(), ObservedCamera::default()),
+            ],
+        );
+        after
+            .cameras
+            .get_mut("usb:046d:0825:0")
+            .expect("camera should be in the snapshot")
+            .orientation
+            .rotation = Rotation::Clockwise90;
+
+        let diff = before.diff(&after);
+        let differences: Vec<(Option<&str>, &str, &Value, &Value)> = diff
+            .iter()
+            .map(|difference| {
+                (
+                    difference.camera.as_deref(),
+                    difference.setting.as_str(),
+                    &difference.a,
+                    &difference.b,
+                )
+            })
+            .collect();
+        assert_eq!(
+            differences,
+            vec![
+                (
+                    None,
+                    "jpeg_quality",
+                    &json!(settings.image_jpeg_quality),
+                    &json!(70)
+                ),
+                (
+                    Some("esp32cam1.local"),
+                    "captured",
+                    &json!(false),
+                    &json!(true)
+                ),
+                (
+                    Some("usb:046d:0825:0"),
+                    "brightness",
+                    &json!(50),
+                    &json!(80)
+                ),
+                (
+                    Some("usb:046d:0825:0"),
+                    "orientation.rotation",
+                    &json!(0),
+                    &json!(90)
+                ),
+                (
+                    Some("usb:046d:0825:1"),
+                    "captured",
+                    &json!(true),
+                    &json!(false)
+                ),
+            ]
+        );
+    }
+}
@@ -0,0 +1,197 @@
+//! First-run setup: the choices the setup page collects and how they are saved.
+//!
+//! A fresh install has no user config file, so `/api/status` reports
+//! `needs_setup` and the dashboard sends the user to `/setup`. That page scans
+//! for hardware through the normal detection paths, then posts the user's
+//! [`SetupChoices`], which are checked here and written to the user config.
+
+use std::collections::HashSet;
+use std::path::PathBuf;
+
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::camera::CameraId;
+use crate::config::{UserConfig, ViewType};
+use crate::config_schema::{camera_field, settings_field};
+
+/// A detected camera the user chose to use
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+pub struct SetupCamera {
+    /// Camera ID as listed by `/api/cameras`
+    pub camera_id: String,
+    #[serde(default)]
+    pub nickname: Option<String>,
+    #[serde(default)]
+    pub view_type: Option<ViewType>,
+}
+
+/// Everything the setup page asks for
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+pub struct SetupChoices {
+    pub controller_hostname: String,
+    /// Cameras to select, with their nicknames and view types
+    #[serde(default)]
+    pub cameras: Vec<SetupCamera>,
+    /// Where shell records are stored; takes effect on the next start
+    #[serde(default)]
+    pub data_directory: Option<PathBuf>,
+}
+
+impl SetupChoices {
+    /// Every problem with the choices, given the IDs of the cameras that were detected
+    pub fn validate(&self, detected_cameras: &[String]) -> Result<(), Vec<String>> {
+        let mut errors = Vec::new();
+        let mut check = |field: &str, result: Option<Result<(), String>>| {
+            if let Some(Err(error)) = result {
+                errors.push(format!("{field}: {error}"));
+            }
+        };
+
+        check(
+            "controller_hostname",
+            settings_field("esphome_hostname")
+                .map(|field| field.validate(&json!(self.controller_hostname.trim()))),
+        );
+
+        let mut seen = HashSet::new();
+        for camera in &self.cameras {
+            let field = format!("cameras.{}", camera.camera_id);
+            if !detected_cameras.contains(&camera.camera_id) {
+                check(&field, Some(Err("camera was not detected".to_string())));
+            }
+            if !seen.insert(camera.camera_id.as_str()) {
+                check(&field, Some(Err("camera listed twice".to_string())));
+            }
+            if let Some(nickname) = &camera.nickname {
+                let result = if nickname.trim().is_empty() {
+                    Err("nickname must not be blank".to_string())
+                } else {
+                    camera_field("nickname")
+                        .map(|field| field.validate(&json!(nickname)))
+                        .unwrap_or(Ok(()))
+                };
+                check(&format!("{field}.nickname"), Some(result));
+            }
+        }
+
+        if self
+            .data_directory
+            .as_ref()
+            .is_some_and(|directory| directory.as_os_str().is_empty())
+        {
+            check("data_directory", Some(Err("must not be empty".to_string())));
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+
+    /// Write the choices into `user_config`, replacing the camera selection
+    pub fn apply_to(self, user_config: &mut UserConfig) {
+        user_config.esphome_hostname = self.controller_hostname.trim().to_string();
+        user_config.set_selected_cameras(
+            self.cameras
+                .iter()
+                .map(|camera| CameraId::from(camera.camera_id.as_str()))
+                .collect(),
+        );
+        for camera in self.cameras {
+            let camera_config = user_config
+                .camera_configs
+                .entry(camera.camera_id)
+                .or_default();
+            if let Some(nickname) = camera.nickname {
+                camera_config.nickname = Some(nickname.trim().to_string());
+            }
+            if let Some(view_type) = camera.view_type {
+                camera_config.view_type = Some(view_type);
+            }
+        }
+        if let Some(data_directory) = self.data_directory {
+            user_config.data_directory = Some(data_directory);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn choices(value: serde_json::Value) -> SetupChoices {
+        serde_json::from_value(value).expect("choices should parse")
+    }
+
+    #[test]
+    fn test_validate_reports_every_problem() {
+        let detected = vec!["usb:1234:5678:0".to_string()];
+        let bad = choices(json!({
+            "controller_hostname": "not a hostname!",
+            "cameras": [
+                {"camera_id": "usb:1234:5678:0", "nickname": " "},
+                {"camera_id": "usb:1234:5678:0"},
+                {"camera_id": "esp32cam9.local"},
+            ],
+            "data_directory": "",
+        }));
+        let errors = bad.validate(&detected).expect_err("choices are invalid");
+        let fields: Vec<&str> = errors
+            .iter()
+            .filter_map(|error| error.split(':').next())
+            .collect();
+        assert_eq!(
+            fields,
+            vec![
+                "controller_hostname",
+                "cameras.usb",
+                "cameras.usb",
+                "cameras.esp32cam9.local",
+                "data_directory",
+            ],
+            "{errors:?}"
+        );
+
+        let good = choices(json!({
+            "controller_hostname": "sorter.local",
+            "cameras": [{"camera_id": "usb:1234:5678:0", "nickname": "Top", "view_type": "side"}],
+        }));
+        assert_eq!(good.validate(&detected), Ok(()));
+    }
+
+    #[test]
+    fn test_apply_replaces_selection_and_keeps_other_camera_settings() {
+        let mut user_config = UserConfig::default();
+        user_config.set_selected_cameras(vec![CameraId::from("old")]);
+        user_config
+            .camera_configs
+            .entry("usb:1234:5678:0".to_string())
+            .or_default()
+            .region_x = Some(10);
+
+        choices(json!({
+            "controller_hostname": " sorter.local ",
+            "cameras": [{"camera_id": "usb:1234:5678:0", "nickname": " Top ", "view_type": "tail"}],
+            "data_directory": "/srv/shells",
+        }))
+        .apply_to(&mut user_config);
+
+        assert_eq!(user_config.esphome_hostname, "sorter.local");
+        assert_eq!(
+            user_config.get_selected_cameras(),
+            [CameraId::from("usb:1234:5678:0")]
+        );
+        let camera = user_config.get_camera_config("usb:1234:5678:0");
+        assert_eq!(camera.nickname.as_deref(), Some("Top"));
+        assert_eq!(camera.view_type, Some(ViewType::Tail));
+        assert_eq!(camera.region_x, Some(10));
+        assert_eq!(
+            user_config.data_directory,
+            Some(PathBuf::from("/srv/shells"))
+        );
+    }
+}
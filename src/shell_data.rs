@@ -6,17 +6,27 @@
 
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 use std::fs;
-use std::path::PathBuf;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard, RwLock};
 use tracing::{debug, info, warn};
 use uuid::Uuid;
 
+use crate::batches::{BATCHES_FILENAME, Batch, BatchExclusion, BatchList};
 use crate::config::ViewType;
+use crate::etag::DataVersion;
+use crate::safe_name::SafeName;
+use crate::settings_snapshot::SettingsSnapshot;
+use crate::shell_migrations::{CURRENT_SHELL_SCHEMA, migrate};
+use crate::training_runs::TrainingRuns;
+use crate::view_classifier::ViewGuess;
 use crate::{OurError, OurResult};
 
 /// Camera region information for image processing
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub struct CameraRegion {
     pub view_type: ViewType,
     pub region_x: Option<i32>,
@@ -80,6 +90,7 @@ impl CameraRegion {
 
 /// Information about a captured image including camera and region data
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub struct CapturedImage {
     pub camera_index: u32,
     pub filename: String,
@@ -89,6 +100,18 @@ pub struct CapturedImage {
     pub region_y: Option<i32>,
     pub region_width: Option<i32>,
     pub region_height: Option<i32>,
+    /// Position in a burst capture, counting from 0; absent for single captures
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sequence: Option<u32>,
+    /// Variance of the image's Laplacian, see [`crate::burst::sharpness`]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sharpness: Option<f64>,
+    /// The sharpest frame of its camera's burst, the one training uses
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub preferred: bool,
+    /// The view the image looked like, when its camera had no view type
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub view_guess: Option<ViewGuess>,
 }
 
 impl CapturedImage {
@@ -108,9 +131,32 @@ impl CapturedImage {
             region_y: None,
             region_width: None,
             region_height: None,
+            sequence: None,
+            sharpness: None,
+            preferred: false,
+            view_guess: None,
         }
     }
 
+    /// Set the view type someone chose, which settles any guess
+    pub fn choose_view_type(&mut self, view_type: ViewType) {
+        self.view_type = view_type;
+        self.view_guess = None;
+    }
+
+    /// Record a guess at the view, taking its view type if none is set yet
+    pub fn apply_view_guess(&mut self, guess: ViewGuess) {
+        if self.view_type == ViewType::Unknown {
+            self.view_type = guess.view_type;
+        }
+        self.view_guess = Some(guess);
+    }
+
+    /// Whether training uses this image: burst frames only when preferred
+    pub fn is_training_image(&self) -> bool {
+        self.sequence.is_none() || self.preferred
+    }
+
     /// Set the region data for this captured image
     pub fn set_region(&mut self, region: &CameraRegion) {
         self.view_type = region.view_type;
@@ -140,8 +186,119 @@ impl CapturedImage {
     }
 }
 
+/// Which cameras a capture session expected an image from, and which delivered one
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct CaptureCoverage {
+    /// Cameras selected when the session was captured, in capture order
+    pub expected: Vec<String>,
+    /// Cameras with an image in the session
+    pub delivered: Vec<String>,
+    /// Why each camera without an image failed, until it is recaptured
+    #[serde(default)]
+    pub failed: BTreeMap<String, String>,
+    /// Cameras left out because they were offline when the capture started, with why
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub skipped: BTreeMap<String, String>,
+}
+
+impl CaptureCoverage {
+    pub fn new(expected: Vec<String>) -> Self {
+        Self {
+            expected,
+            ..Self::default()
+        }
+    }
+
+    /// Note a camera's capture, replacing any earlier failure of the same camera
+    pub fn record(&mut self, camera_id: &str, result: Result<(), String>) {
+        match result {
+            Ok(()) => {
+                self.failed.remove(camera_id);
+                self.skipped.remove(camera_id);
+                if !self.delivered.iter().any(|id| id == camera_id) {
+                    self.delivered.push(camera_id.to_string());
+                }
+            }
+            Err(error) => {
+                self.failed.insert(camera_id.to_string(), error);
+            }
+        }
+    }
+
+    /// Note a camera that was offline and not captured from
+    pub fn skip(&mut self, camera_id: &str, reason: String) {
+        self.skipped.insert(camera_id.to_string(), reason);
+    }
+
+    /// Expected cameras without an image, in capture order
+    pub fn missing(&self) -> Vec<String> {
+        self.expected
+            .iter()
+            .filter(|camera_id| !self.delivered.contains(camera_id))
+            .cloned()
+            .collect()
+    }
+
+    /// Position of the camera in the capture order, used to number its images
+    pub fn camera_index(&self, camera_id: &str) -> Option<u32> {
+        self.expected
+            .iter()
+            .position(|id| id == camera_id)
+            .and_then(|index| u32::try_from(index).ok())
+    }
+}
+
+/// Problem noted on a shell while tagging, stored as its name or free text
+#[derive(
+    Debug, Clone, PartialEq, Eq, Hash, serde_with::SerializeDisplay, serde_with::DeserializeFromStr,
+)]
+pub enum ShellFlag {
+    Damaged,
+    WrongOrientation,
+    Blurry,
+    Other(String),
+}
+
+impl std::fmt::Display for ShellFlag {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ShellFlag::Damaged => write!(f, "Damaged"),
+            ShellFlag::WrongOrientation => write!(f, "WrongOrientation"),
+            ShellFlag::Blurry => write!(f, "Blurry"),
+            ShellFlag::Other(reason) => write!(f, "{reason}"),
+        }
+    }
+}
+
+impl std::str::FromStr for ShellFlag {
+    type Err = OurError;
+
+    /// Parse a known flag name case-insensitively; any other text is an `Other` flag
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        match s.to_lowercase().as_str() {
+            "" => Err(OurError::App("Shell flag must not be empty".to_string())),
+            "damaged" => Ok(ShellFlag::Damaged),
+            "wrongorientation" | "wrong_orientation" => Ok(ShellFlag::WrongOrientation),
+            "blurry" => Ok(ShellFlag::Blurry),
+            _ => Ok(ShellFlag::Other(s.to_string())),
+        }
+    }
+}
+
+/// Longest notes accepted on a shell
+pub const MAX_SHELL_NOTES_LENGTH: usize = 2000;
+
+/// Why `notes` can't be saved on a shell, if they can't
+pub fn notes_error(notes: &str) -> Option<String> {
+    (notes.chars().count() > MAX_SHELL_NOTES_LENGTH)
+        .then(|| format!("must be at most {MAX_SHELL_NOTES_LENGTH} characters"))
+}
+
 /// Model representing a shell case with metadata and captured images
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub struct Shell {
     /// Date when the shell was captured
     pub date_captured: DateTime<Utc>,
@@ -155,6 +312,44 @@ pub struct Shell {
     pub captured_images: Option<Vec<CapturedImage>>,
     /// Whether to include this shell in the training set
     pub include: bool,
+    /// Free-text notes from tagging
+    #[serde(default)]
+    pub notes: Option<String>,
+    /// Problems noted while tagging
+    #[serde(default)]
+    pub flags: Vec<ShellFlag>,
+    /// When image regions were last changed after capture, e.g. by propagating a camera's region
+    #[serde(default)]
+    pub region_updated_at: Option<DateTime<Utc>>,
+    /// Bumped on every save, so an edit made from an older copy can be refused
+    #[serde(default)]
+    pub revision: u64,
+    /// Which cameras delivered, for sessions captured since this was recorded
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub capture: Option<CaptureCoverage>,
+    /// The lot the shell came from, see [`crate::batches`]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub batch_id: Option<String>,
+    /// Settings the session was captured with, see [`crate::settings_snapshot`]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub settings_snapshot: Option<SettingsSnapshot>,
+    /// Weight of the case, see [`crate::measurements`]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub weight_grains: Option<f32>,
+    /// Overall length of the case
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub length_mm: Option<f32>,
+    /// When the weight or length was last recorded
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub measured_at: Option<DateTime<Utc>>,
+    /// Layout the record was written in, see [`crate::shell_migrations`]
+    #[serde(default = "unversioned")]
+    pub schema_version: u32,
+}
+
+/// Records written before `schema_version` existed are version 1
+fn unversioned() -> u32 {
+    1
 }
 
 impl Shell {
@@ -167,6 +362,17 @@ impl Shell {
             image_filenames: Vec::new(),
             captured_images: None,
             include: true,
+            notes: None,
+            flags: Vec::new(),
+            region_updated_at: None,
+            revision: 0,
+            capture: None,
+            batch_id: None,
+            settings_snapshot: None,
+            weight_grains: None,
+            length_mm: None,
+            measured_at: None,
+            schema_version: CURRENT_SHELL_SCHEMA,
         }
     }
 
@@ -184,6 +390,14 @@ impl Shell {
         }
     }
 
+    /// Selected cameras the capture got no image from
+    pub fn missing_cameras(&self) -> Vec<String> {
+        self.capture
+            .as_ref()
+            .map(CaptureCoverage::missing)
+            .unwrap_or_default()
+    }
+
     /// Get the shell type key for case type management (brand_shell_type)
     pub fn get_case_type_key(&self) -> String {
         format!("{}_{}", self.brand, self.shell_type)
@@ -197,6 +411,14 @@ impl Shell {
             .unwrap_or(0)
     }
 
+    /// The captured images training uses, skipping burst frames that weren't preferred
+    pub fn training_images(&self) -> impl Iterator<Item = &CapturedImage> {
+        self.captured_images
+            .iter()
+            .flatten()
+            .filter(|image| image.is_training_image())
+    }
+
     /// Check if this shell has images with complete region data
     pub fn has_complete_regions(&self) -> bool {
         self.captured_images
@@ -205,6 +427,33 @@ impl Shell {
             .unwrap_or(false)
     }
 
+    /// The views in `required` that no captured image shows, in the order given
+    pub fn missing_views(&self, required: &[ViewType]) -> Vec<ViewType> {
+        let captured = self.images_by_view_type();
+        required
+            .iter()
+            .filter(|view_type| !captured.contains_key(view_type))
+            .copied()
+            .collect()
+    }
+
+    /// Whether the shell carries any of the given flags
+    pub fn has_any_flag(&self, flags: &[ShellFlag]) -> bool {
+        self.flags.iter().any(|flag| flags.contains(flag))
+    }
+
+    /// Give every image from a camera the region, returning how many changed
+    pub fn apply_camera_region(&mut self, camera: &CameraSelector, region: &CameraRegion) -> usize {
+        let mut changed = 0;
+        for image in self.captured_images.iter_mut().flatten() {
+            if camera.matches(image) && image.get_region() != *region {
+                image.set_region(region);
+                changed += 1;
+            }
+        }
+        changed
+    }
+
     /// Get images grouped by view type
     pub fn images_by_view_type(&self) -> HashMap<ViewType, Vec<&CapturedImage>> {
         let mut grouped = HashMap::new();
@@ -220,15 +469,513 @@ impl Shell {
     }
 }
 
+/// Write a file via a temporary file and rename, so a crash never leaves it truncated
+pub(crate) fn write_atomic(path: &Path, contents: &[u8]) -> std::io::Result<()> {
+    let file_name = path
+        .file_name()
+        .ok_or_else(|| std::io::Error::other(format!("{} has no file name", path.display())))?;
+    let temp_path = path.with_file_name(format!(".{}.tmp", file_name.to_string_lossy()));
+
+    let result = (|| {
+        let mut file = fs::File::create(&temp_path)?;
+        file.write_all(contents)?;
+        file.sync_all()?;
+        fs::rename(&temp_path, path)
+    })();
+    if result.is_err() {
+        fs::remove_file(&temp_path).ok();
+    }
+    result
+}
+
+/// Read a shell record, upgrading it from an older schema, with the version it was written in
+fn read_shell(path: &Path) -> OurResult<(Shell, u32)> {
+    let json_data = fs::read_to_string(path)
+        .map_err(|e| OurError::App(format!("Failed to read shell data: {e}")))?;
+    let record = serde_json::from_str(&json_data)
+        .map_err(|e| OurError::App(format!("Failed to parse shell data: {e}")))?;
+    let (record, from) = migrate(record)?;
+    let shell = serde_json::from_value(record)
+        .map_err(|e| OurError::App(format!("Failed to parse shell data: {e}")))?;
+    Ok((shell, from))
+}
+
+/// What rewriting the shell records in the current schema did
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub struct MigrationReport {
+    /// Sessions rewritten, with the schema version each was in
+    pub migrated: BTreeMap<String, u32>,
+    /// Shell records that were already in the current schema
+    pub current: usize,
+    /// Files that couldn't be migrated, left as they were
+    pub skipped: Vec<SkippedFile>,
+    /// Where the migrated files were copied first, if they were
+    pub backup_directory: Option<PathBuf>,
+}
+
+/// What happened to a save that was checked against the revision the editor loaded
+#[derive(Debug, Clone, PartialEq)]
+pub enum RevisionCheck {
+    /// The shell as written, with its new revision
+    Saved(Shell),
+    /// Someone else saved first; the copy now on disk
+    Conflict(Shell),
+}
+
+/// Files in the data directory that hold something other than a shell
+const NON_SHELL_FILES: [&str; 4] = [
+    "case_types.json",
+    crate::composite::LAYOUT_FILENAME,
+    BATCHES_FILENAME,
+    crate::data_export::EXPORT_MANIFEST_FILENAME,
+];
+
+/// A file left out of a directory listing, and why
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub struct SkippedFile {
+    pub path: PathBuf,
+    pub reason: String,
+}
+
+/// Outcome of listing the shells in the data directory
+#[derive(Debug, Default)]
+pub struct ShellIntegrityReport {
+    /// Shells that loaded, newest first
+    pub shells: Vec<(String, Shell)>,
+    /// Files that looked like shell records but couldn't be loaded
+    pub skipped: Vec<SkippedFile>,
+}
+
+/// The `.json` files directly inside `directory`, by file stem.
+///
+/// Only the top level is read: subdirectories and dotfiles (editor swap files,
+/// interrupted atomic writes) are passed over, and names that aren't UTF-8 are
+/// reported in `skipped` rather than converted lossily into an ID that can't be
+/// loaded again. Only failing to read the directory itself is an error.
+pub(crate) fn json_files_in(
+    directory: &Path,
+    skipped: &mut Vec<SkippedFile>,
+) -> OurResult<Vec<(String, PathBuf)>> {
+    let entries = fs::read_dir(directory).map_err(|e| {
+        OurError::App(format!(
+            "Failed to read directory {}: {e}",
+            directory.display()
+        ))
+    })?;
+
+    let mut files = Vec::new();
+    for entry in entries {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(e) => {
+                skipped.push(SkippedFile {
+                    path: directory.to_path_buf(),
+                    reason: format!("Failed to read directory entry: {e}"),
+                });
+                continue;
+            }
+        };
+        let path = entry.path();
+
+        let Some(file_name) = entry.file_name().to_str().map(str::to_string) else {
+            warn!("Skipping non-UTF-8 file name {}", path.display());
+            skipped.push(SkippedFile {
+                path,
+                reason: "File name is not valid UTF-8".to_string(),
+            });
+            continue;
+        };
+        if file_name.starts_with('.') {
+            continue;
+        }
+        let Some(stem) = file_name.strip_suffix(".json") else {
+            continue;
+        };
+        // A directory named like a shell record is still not one
+        match entry.file_type() {
+            Ok(file_type) if file_type.is_file() => {}
+            Ok(file_type) if file_type.is_symlink() && path.is_file() => {}
+            Ok(_) => {
+                debug!("Skipping non-file {}", path.display());
+                continue;
+            }
+            Err(e) => {
+                skipped.push(SkippedFile {
+                    path,
+                    reason: format!("Failed to read file type: {e}"),
+                });
+                continue;
+            }
+        }
+        files.push((stem.to_string(), path));
+    }
+    Ok(files)
+}
+
+/// Shell metadata kept in the in-memory index, in the same shape as `/api/shells` entries
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub struct ShellSummary {
+    pub session_id: String,
+    pub brand: String,
+    pub shell_type: String,
+    pub date_captured: DateTime<Utc>,
+    pub include: bool,
+    pub image_count: usize,
+    pub has_complete_regions: bool,
+    pub notes: Option<String>,
+    pub flags: Vec<ShellFlag>,
+    /// Views its case type requires that the shell has no image of, once filled in by
+    /// [`ShellSummary::with_required_views`]
+    pub missing_views: Vec<ViewType>,
+    /// Selected cameras the capture got no image from; recapture them before tagging
+    pub missing_cameras: Vec<String>,
+    pub batch_id: Option<String>,
+    pub weight_grains: Option<f32>,
+    pub length_mm: Option<f32>,
+    /// Views the shell has images of
+    #[serde(skip)]
+    pub views: BTreeSet<ViewType>,
+}
+
+impl ShellSummary {
+    /// Summarise a shell for the index
+    pub fn new(session_id: &str, shell: &Shell) -> Self {
+        Self {
+            session_id: session_id.to_string(),
+            brand: shell.brand.clone(),
+            shell_type: shell.shell_type.clone(),
+            date_captured: shell.date_captured,
+            include: shell.include,
+            image_count: shell.image_count(),
+            has_complete_regions: shell.has_complete_regions(),
+            notes: shell.notes.clone(),
+            flags: shell.flags.clone(),
+            missing_views: Vec::new(),
+            missing_cameras: shell.missing_cameras(),
+            batch_id: shell.batch_id.clone(),
+            weight_grains: shell.weight_grains,
+            length_mm: shell.length_mm,
+            views: shell
+                .captured_images
+                .iter()
+                .flatten()
+                .map(|image| image.view_type)
+                .collect(),
+        }
+    }
+
+    /// The summary with `missing_views` set against the views its case type requires
+    pub fn with_required_views(mut self, required: &[ViewType]) -> Self {
+        self.missing_views = required
+            .iter()
+            .filter(|view_type| !self.views.contains(view_type))
+            .copied()
+            .collect();
+        self
+    }
+}
+
+/// Shell fields a text search can match against
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SearchField {
+    Brand,
+    ShellType,
+    SessionId,
+    Notes,
+}
+
+impl SearchField {
+    /// All searchable fields
+    pub const ALL: [SearchField; 4] = [
+        SearchField::Brand,
+        SearchField::ShellType,
+        SearchField::SessionId,
+        SearchField::Notes,
+    ];
+
+    fn value<'a>(&self, summary: &'a ShellSummary) -> &'a str {
+        match self {
+            SearchField::Brand => &summary.brand,
+            SearchField::ShellType => &summary.shell_type,
+            SearchField::SessionId => &summary.session_id,
+            SearchField::Notes => summary.notes.as_deref().unwrap_or_default(),
+        }
+    }
+}
+
+impl std::str::FromStr for SearchField {
+    type Err = OurError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "brand" => Ok(SearchField::Brand),
+            "shell_type" => Ok(SearchField::ShellType),
+            "session_id" => Ok(SearchField::SessionId),
+            "notes" => Ok(SearchField::Notes),
+            _ => Err(OurError::App(format!("Invalid search field: {s}"))),
+        }
+    }
+}
+
+/// Criteria for selecting shells from the index
+#[derive(Debug, Clone, Default)]
+pub struct ShellFilter {
+    /// Case-insensitive substring to search for
+    pub query: Option<String>,
+    /// Fields the query is matched against; empty means all fields
+    pub fields: Vec<SearchField>,
+    /// Only shells with this include flag
+    pub include: Option<bool>,
+    /// Only shells carrying this problem flag
+    pub flag: Option<ShellFlag>,
+    /// Only shells of this brand, ignoring case
+    pub brand: Option<String>,
+    /// Only shells of this type, ignoring case
+    pub shell_type: Option<String>,
+    /// Only shells captured at or after this time
+    pub captured_after: Option<DateTime<Utc>>,
+    /// Only shells captured before this time
+    pub captured_before: Option<DateTime<Utc>>,
+    /// Only shells in this batch
+    pub batch_id: Option<String>,
+    /// Only shells weighed at this many grains or more
+    pub min_weight_grains: Option<f32>,
+    /// Only shells weighed at this many grains or less
+    pub max_weight_grains: Option<f32>,
+}
+
+impl ShellFilter {
+    /// Rank a shell against the filter, lower is better, or `None` if it doesn't match
+    ///
+    /// Prefix matches on brand rank first, then other prefix matches, then
+    /// substring matches anywhere.
+    pub fn rank(&self, summary: &ShellSummary) -> Option<u8> {
+        if self
+            .include
+            .is_some_and(|include| summary.include != include)
+            || self
+                .captured_after
+                .is_some_and(|after| summary.date_captured < after)
+            || self
+                .captured_before
+                .is_some_and(|before| summary.date_captured >= before)
+            || self
+                .flag
+                .as_ref()
+                .is_some_and(|flag| !summary.flags.contains(flag))
+            || self
+                .brand
+                .as_ref()
+                .is_some_and(|brand| !summary.brand.eq_ignore_ascii_case(brand))
+            || self
+                .shell_type
+                .as_ref()
+                .is_some_and(|shell_type| !summary.shell_type.eq_ignore_ascii_case(shell_type))
+            || self
+                .batch_id
+                .as_ref()
+                .is_some_and(|batch_id| summary.batch_id.as_ref() != Some(batch_id))
+            || self
+                .min_weight_grains
+                .is_some_and(|min| summary.weight_grains.is_none_or(|weight| weight < min))
+            || self
+                .max_weight_grains
+                .is_some_and(|max| summary.weight_grains.is_none_or(|weight| weight > max))
+        {
+            return None;
+        }
+
+        let Some(query) = self
+            .query
+            .as_deref()
+            .map(str::trim)
+            .filter(|query| !query.is_empty())
+        else {
+            return Some(0);
+        };
+        let query = query.to_lowercase();
+
+        let fields: &[SearchField] = if self.fields.is_empty() {
+            &SearchField::ALL
+        } else {
+            &self.fields
+        };
+        fields
+            .iter()
+            .filter_map(|field| {
+                let value = field.value(summary).to_lowercase();
+                if value.starts_with(&query) {
+                    Some(if *field == SearchField::Brand { 0 } else { 1 })
+                } else if value.contains(&query) {
+                    Some(2)
+                } else {
+                    None
+                }
+            })
+            .min()
+    }
+}
+
+/// Identifies the camera a captured image came from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CameraSelector {
+    Name(String),
+    Index(u32),
+}
+
+impl CameraSelector {
+    /// Whether the image was taken by this camera
+    pub fn matches(&self, image: &CapturedImage) -> bool {
+        match self {
+            CameraSelector::Name(name) => image.camera_name == *name,
+            CameraSelector::Index(index) => image.camera_index == *index,
+        }
+    }
+}
+
+/// A region to copy onto already-captured images
+#[derive(Debug, Clone)]
+pub struct RegionPropagation {
+    pub camera: CameraSelector,
+    pub region: CameraRegion,
+    /// Only shells of this case type, matched against the shell type or `brand_shell_type`
+    pub case_type: Option<String>,
+    /// Only shells captured at or after this time
+    pub since: Option<DateTime<Utc>>,
+    /// Count what would change without saving anything
+    pub dry_run: bool,
+}
+
+impl RegionPropagation {
+    fn selects(&self, shell: &Shell) -> bool {
+        self.case_type.as_ref().is_none_or(|case_type| {
+            shell.shell_type == *case_type || shell.get_case_type_key() == *case_type
+        }) && self.since.is_none_or(|since| shell.date_captured >= since)
+    }
+}
+
+/// What a region propagation changed, or would change on a dry run
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub struct RegionPropagationReport {
+    pub dry_run: bool,
+    /// Shells matching the filter
+    pub shells_matched: usize,
+    /// Shells with at least one image whose region changed
+    pub shells_updated: usize,
+    pub images_updated: usize,
+    /// Session IDs of the updated shells
+    pub updated_sessions: Vec<String>,
+    /// Shell files that couldn't be read
+    pub skipped: Vec<SkippedFile>,
+}
+
 /// Shell data manager for persistence and CRUD operations
 pub struct ShellDataManager {
     data_directory: PathBuf,
+    /// Shell metadata by session ID, built from disk on first search
+    index: RwLock<Option<HashMap<String, ShellSummary>>>,
+    /// Training run to notify when shells change
+    training_runs: TrainingRuns,
+    /// Bumped whenever a shell is saved or deleted
+    version: DataVersion,
+    /// Held from reading a shell's revision until the new copy is written
+    write_lock: Mutex<()>,
 }
 
 impl ShellDataManager {
     /// Create a new shell data manager
     pub fn new(data_directory: PathBuf) -> Self {
-        Self { data_directory }
+        Self {
+            data_directory,
+            index: RwLock::new(None),
+            training_runs: TrainingRuns::default(),
+            version: DataVersion::default(),
+            write_lock: Mutex::new(()),
+        }
+    }
+
+    /// Share a training run tracker, so changes made through this manager are recorded against it
+    pub fn with_training_runs(mut self, training_runs: TrainingRuns) -> Self {
+        self.training_runs = training_runs;
+        self
+    }
+
+    /// The training run tracker notified when shells change
+    pub fn training_runs(&self) -> &TrainingRuns {
+        &self.training_runs
+    }
+
+    /// Bumped whenever a shell is saved or deleted through this manager
+    pub fn version(&self) -> u64 {
+        self.version.get()
+    }
+
+    /// Safely lock the index for writing
+    fn lock_index_write(
+        &self,
+    ) -> OurResult<std::sync::RwLockWriteGuard<'_, Option<HashMap<String, ShellSummary>>>> {
+        self.index
+            .write()
+            .map_err(|_| OurError::App("Shell index lock poisoned".to_string()))
+    }
+
+    /// Update the index entry for a shell if the index has been built
+    fn update_index(&self, session_id: &str, shell: Option<&Shell>) -> OurResult<()> {
+        if let Some(index) = self.lock_index_write()?.as_mut() {
+            match shell {
+                Some(shell) => {
+                    index.insert(session_id.to_string(), ShellSummary::new(session_id, shell));
+                }
+                None => {
+                    index.remove(session_id);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Search the metadata index, best matches first and newest first within a rank
+    pub fn search_shells(&self, filter: &ShellFilter) -> OurResult<Vec<ShellSummary>> {
+        let mut index = self.lock_index_write()?;
+        if index.is_none() {
+            let summaries = self
+                .list_shells()?
+                .iter()
+                .map(|(session_id, shell)| {
+                    (session_id.clone(), ShellSummary::new(session_id, shell))
+                })
+                .collect();
+            *index = Some(summaries);
+        }
+
+        let mut ranked: Vec<(u8, &ShellSummary)> = index
+            .iter()
+            .flat_map(|summaries| summaries.values())
+            .filter_map(|summary| filter.rank(summary).map(|rank| (rank, summary)))
+            .collect();
+        ranked.sort_by_key(|(rank, summary)| (*rank, std::cmp::Reverse(summary.date_captured)));
+
+        Ok(ranked
+            .into_iter()
+            .map(|(_, summary)| summary.clone())
+            .collect())
+    }
+
+    /// Every brand shells are tagged with, sorted and once each ignoring case
+    pub fn brands(&self) -> OurResult<Vec<String>> {
+        let mut brands: Vec<String> = self
+            .search_shells(&ShellFilter::default())?
+            .into_iter()
+            .map(|summary| summary.brand.trim().to_string())
+            .filter(|brand| !brand.is_empty())
+            .collect();
+        brands.sort_by_key(|brand| (brand.to_lowercase(), brand.clone()));
+        brands.dedup_by(|later, earlier| later.eq_ignore_ascii_case(earlier));
+        Ok(brands)
     }
 
     /// Generate a new session ID for shell data
@@ -236,9 +983,70 @@ impl ShellDataManager {
         Uuid::new_v4().to_string()
     }
 
-    /// Save shell data to a JSON file with the given session ID
+    /// Where a session's shell data lives, refusing ids that would leave the data directory
+    fn shell_path(&self, session_id: &str) -> OurResult<PathBuf> {
+        let session_id = SafeName::new("session_id", session_id)?;
+        Ok(self.data_directory.join(format!("{session_id}.json")))
+    }
+
+    /// Serialise writes, so a revision can't change between being checked and replaced
+    fn lock_writes(&self) -> OurResult<MutexGuard<'_, ()>> {
+        self.write_lock
+            .lock()
+            .map_err(|_| OurError::App("Shell write lock poisoned".to_string()))
+    }
+
+    /// Save shell data to a JSON file with the given session ID, whatever revision is on disk
     pub fn save_shell(&self, session_id: &str, shell: &Shell) -> OurResult<()> {
-        let file_path = self.data_directory.join(format!("{session_id}.json"));
+        let _writing = self.lock_writes()?;
+        let current_revision = self
+            .get_shell(session_id)
+            .ok()
+            .flatten()
+            .map_or(0, |current| current.revision);
+        self.write_revision(session_id, shell, current_revision + 1)?;
+        Ok(())
+    }
+
+    /// Save shell data only if the copy on disk is still at `expected_revision`
+    ///
+    /// A shell that hasn't been saved yet is at revision 0.
+    pub fn save_shell_at_revision(
+        &self,
+        session_id: &str,
+        shell: &Shell,
+        expected_revision: u64,
+    ) -> OurResult<RevisionCheck> {
+        let _writing = self.lock_writes()?;
+        let current = self.get_shell(session_id)?;
+        let current_revision = current.as_ref().map_or(0, |current| current.revision);
+        match current {
+            Some(current) if current.revision != expected_revision => {
+                info!(
+                    "Refused save of session {session_id} at revision {expected_revision}, it is at {}",
+                    current.revision
+                );
+                return Ok(RevisionCheck::Conflict(current));
+            }
+            None if expected_revision != 0 => {
+                return Err(OurError::App(format!(
+                    "Shell data file not found: {session_id}"
+                )));
+            }
+            _ => {}
+        }
+        self.write_revision(session_id, shell, current_revision + 1)
+            .map(RevisionCheck::Saved)
+    }
+
+    /// Write `shell` as `revision`; callers hold the write lock
+    fn write_revision(&self, session_id: &str, shell: &Shell, revision: u64) -> OurResult<Shell> {
+        let file_path = self.shell_path(session_id)?;
+        let shell = Shell {
+            revision,
+            schema_version: CURRENT_SHELL_SCHEMA,
+            ..shell.clone()
+        };
 
         // Ensure the data directory exists
         if let Some(parent) = file_path.parent() {
@@ -246,19 +1054,26 @@ impl ShellDataManager {
                 .map_err(|e| OurError::App(format!("Failed to create data directory: {e}")))?;
         }
 
-        let json_data = serde_json::to_string_pretty(shell)
+        let json_data = serde_json::to_string_pretty(&shell)
             .map_err(|e| OurError::App(format!("Failed to serialize shell data: {e}")))?;
 
-        fs::write(&file_path, json_data)
+        write_atomic(&file_path, json_data.as_bytes())
             .map_err(|e| OurError::App(format!("Failed to write shell data: {e}")))?;
+        self.update_index(session_id, Some(&shell))?;
+        self.version.bump();
+        self.training_runs
+            .record_mutation(format!("shell {session_id} saved"));
 
-        info!("Saved shell data for session {}", session_id);
-        Ok(())
+        info!(
+            "Saved shell data for session {} at revision {}",
+            session_id, shell.revision
+        );
+        Ok(shell)
     }
 
     /// Load shell data from a JSON file
     pub fn load_shell(&self, session_id: &str) -> OurResult<Shell> {
-        let file_path = self.data_directory.join(format!("{session_id}.json"));
+        let file_path = self.shell_path(session_id)?;
 
         if !file_path.exists() {
             return Err(OurError::App(format!(
@@ -266,16 +1081,26 @@ impl ShellDataManager {
             )));
         }
 
-        let json_data = fs::read_to_string(&file_path)
-            .map_err(|e| OurError::App(format!("Failed to read shell data: {e}")))?;
-
-        let shell: Shell = serde_json::from_str(&json_data)
-            .map_err(|e| OurError::App(format!("Failed to parse shell data: {e}")))?;
-
-        debug!("Loaded shell data for session {}", session_id);
+        let (shell, from) = read_shell(&file_path)?;
+        if from < CURRENT_SHELL_SCHEMA {
+            debug!("Loaded shell data for session {session_id} from schema version {from}");
+        } else {
+            debug!("Loaded shell data for session {}", session_id);
+        }
         Ok(shell)
     }
 
+    /// When the shell's record was last written, or None if there isn't one
+    pub fn modified_at(&self, session_id: &str) -> OurResult<Option<DateTime<Utc>>> {
+        match fs::metadata(self.shell_path(session_id)?).and_then(|metadata| metadata.modified()) {
+            Ok(modified) => Ok(Some(DateTime::<Utc>::from(modified))),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(OurError::App(format!(
+                "Failed to read when shell {session_id} was written: {e}"
+            ))),
+        }
+    }
+
     /// Get shell data, returning None if not found
     pub fn get_shell(&self, session_id: &str) -> OurResult<Option<Shell>> {
         match self.load_shell(session_id) {
@@ -287,11 +1112,16 @@ impl ShellDataManager {
 
     /// Delete shell data file
     pub fn delete_shell(&self, session_id: &str) -> OurResult<()> {
-        let file_path = self.data_directory.join(format!("{session_id}.json"));
+        let file_path = self.shell_path(session_id)?;
+        let _writing = self.lock_writes()?;
 
         if file_path.exists() {
             fs::remove_file(&file_path)
                 .map_err(|e| OurError::App(format!("Failed to delete shell data: {e}")))?;
+            self.update_index(session_id, None)?;
+            self.version.bump();
+            self.training_runs
+                .record_mutation(format!("shell {session_id} deleted"));
             info!("Deleted shell data for session {}", session_id);
         } else {
             warn!("Shell data file not found for deletion: {}", session_id);
@@ -300,47 +1130,233 @@ impl ShellDataManager {
         Ok(())
     }
 
+    /// Move a shell's record out of the data directory to `destination`, false if it has none
+    ///
+    /// To everything reading the data directory the shell is gone, as if deleted.
+    pub fn move_shell_out(&self, session_id: &str, destination: &Path) -> OurResult<bool> {
+        let file_path = self.shell_path(session_id)?;
+        let _writing = self.lock_writes()?;
+
+        if !file_path.exists() {
+            return Ok(false);
+        }
+        fs::rename(&file_path, destination)
+            .map_err(|e| OurError::App(format!("Failed to move shell data: {e}")))?;
+        self.update_index(session_id, None)?;
+        self.version.bump();
+        self.training_runs
+            .record_mutation(format!("shell {session_id} deleted"));
+        Ok(true)
+    }
+
+    /// Move a record moved out by [`Self::move_shell_out`] back, returning the shell
+    ///
+    /// Refuses to replace a shell saved under the same session ID since.
+    pub fn move_shell_in(&self, session_id: &str, source: &Path) -> OurResult<Shell> {
+        let file_path = self.shell_path(session_id)?;
+        let _writing = self.lock_writes()?;
+
+        if file_path.exists() {
+            return Err(OurError::App(format!(
+                "A shell with session ID {session_id} exists already"
+            )));
+        }
+        let (shell, _) = read_shell(source)?;
+        fs::rename(source, &file_path)
+            .map_err(|e| OurError::App(format!("Failed to move shell data: {e}")))?;
+        self.update_index(session_id, Some(&shell))?;
+        self.version.bump();
+        self.training_runs
+            .record_mutation(format!("shell {session_id} saved"));
+        Ok(shell)
+    }
+
     /// List all shell data files
     pub fn list_shells(&self) -> OurResult<Vec<(String, Shell)>> {
-        let mut shells = Vec::new();
+        let report = self.check_shells()?;
+        for skipped in &report.skipped {
+            warn!("Skipped {}: {}", skipped.path.display(), skipped.reason);
+        }
+        Ok(report.shells)
+    }
+
+    /// Load every shell record, reporting the files that couldn't be loaded instead of failing
+    pub fn check_shells(&self) -> OurResult<ShellIntegrityReport> {
+        let mut report = ShellIntegrityReport::default();
 
         if !self.data_directory.exists() {
-            return Ok(shells);
+            return Ok(report);
+        }
+
+        for (session_id, path) in json_files_in(&self.data_directory, &mut report.skipped)? {
+            if NON_SHELL_FILES.contains(&format!("{session_id}.json").as_str()) {
+                continue;
+            }
+            match self.load_shell(&session_id) {
+                Ok(shell) => report.shells.push((session_id, shell)),
+                Err(e) => report.skipped.push(SkippedFile {
+                    path,
+                    reason: e.to_string(),
+                }),
+            }
         }
 
-        let entries = fs::read_dir(&self.data_directory)
-            .map_err(|e| OurError::App(format!("Failed to read data directory: {e}")))?;
+        // Sort by date captured, newest first
+        report
+            .shells
+            .sort_by_key(|(_, shell)| std::cmp::Reverse(shell.date_captured));
 
-        for entry in entries {
-            let entry =
-                entry.map_err(|e| OurError::App(format!("Failed to read directory entry: {e}")))?;
-            let path = entry.path();
+        info!(
+            "Listed {} shell records, skipped {} files",
+            report.shells.len(),
+            report.skipped.len()
+        );
+        Ok(report)
+    }
 
-            if path.is_file() && path.extension() == Some(std::ffi::OsStr::new("json")) {
-                // Skip case_types.json and other non-shell files
-                if let Some(file_name) = path.file_stem() {
-                    let file_name_str = file_name.to_string_lossy();
-                    if file_name_str == "case_types" {
-                        continue;
-                    }
+    /// Rewrite every shell record older than [`CURRENT_SHELL_SCHEMA`] in the current schema
+    ///
+    /// With `backup`, each file is copied into a new `schema-backup-{time}`
+    /// directory inside the data directory before it is replaced; failing to
+    /// back one up stops the migration. Revisions are kept, since the records
+    /// say the same thing in a newer layout.
+    pub fn migrate_all(&self, backup: bool) -> OurResult<MigrationReport> {
+        let mut report = MigrationReport::default();
+        if !self.data_directory.exists() {
+            return Ok(report);
+        }
+
+        let _writing = self.lock_writes()?;
+        for (session_id, path) in json_files_in(&self.data_directory, &mut report.skipped)? {
+            if NON_SHELL_FILES.contains(&format!("{session_id}.json").as_str()) {
+                continue;
+            }
+            let (shell, from) = match read_shell(&path) {
+                Ok(read) => read,
+                Err(e) => {
+                    report.skipped.push(SkippedFile {
+                        path,
+                        reason: e.to_string(),
+                    });
+                    continue;
+                }
+            };
+            if from == CURRENT_SHELL_SCHEMA {
+                report.current += 1;
+                continue;
+            }
 
-                    match self.load_shell(&file_name_str) {
-                        Ok(shell) => {
-                            shells.push((file_name_str.to_string(), shell));
-                        }
-                        Err(e) => {
-                            warn!("Failed to load shell data from {}: {}", path.display(), e);
-                        }
+            if backup {
+                let backup_directory = match &report.backup_directory {
+                    Some(directory) => directory.clone(),
+                    None => {
+                        let directory = self.data_directory.join(format!(
+                            "schema-backup-{}",
+                            Utc::now().format("%Y%m%dT%H%M%SZ")
+                        ));
+                        fs::create_dir_all(&directory).map_err(|e| {
+                            OurError::App(format!("Failed to create backup directory: {e}"))
+                        })?;
+                        report.backup_directory = Some(directory.clone());
+                        directory
                     }
+                };
+                fs::copy(&path, backup_directory.join(format!("{session_id}.json"))).map_err(
+                    |e| OurError::App(format!("Failed to back up {}: {e}", path.display())),
+                )?;
+            }
+
+            let shell = Shell {
+                schema_version: CURRENT_SHELL_SCHEMA,
+                ..shell
+            };
+            let written = serde_json::to_string_pretty(&shell)
+                .map_err(|e| OurError::App(format!("Failed to serialize shell data: {e}")))
+                .and_then(|json_data| {
+                    write_atomic(&path, json_data.as_bytes())
+                        .map_err(|e| OurError::App(format!("Failed to write shell data: {e}")))
+                });
+            match written {
+                Ok(()) => {
+                    report.migrated.insert(session_id, from);
                 }
+                Err(e) => report.skipped.push(SkippedFile {
+                    path,
+                    reason: e.to_string(),
+                }),
             }
         }
 
-        // Sort by date captured, newest first
-        shells.sort_by(|a, b| b.1.date_captured.cmp(&a.1.date_captured));
+        info!(
+            "Migrated {} shell records to schema version {CURRENT_SHELL_SCHEMA}, {} already current, skipped {}",
+            report.migrated.len(),
+            report.current,
+            report.skipped.len()
+        );
+        Ok(report)
+    }
 
-        info!("Listed {} shell records", shells.len());
-        Ok(shells)
+    /// Copy a camera's region onto the images it already captured
+    ///
+    /// Shells are loaded and saved one at a time, so memory use doesn't grow
+    /// with the size of the data directory.
+    pub fn propagate_region(
+        &self,
+        propagation: &RegionPropagation,
+    ) -> OurResult<RegionPropagationReport> {
+        let mut report = RegionPropagationReport {
+            dry_run: propagation.dry_run,
+            ..RegionPropagationReport::default()
+        };
+        if !self.data_directory.exists() {
+            return Ok(report);
+        }
+
+        let now = Utc::now();
+        for (session_id, path) in json_files_in(&self.data_directory, &mut report.skipped)? {
+            if NON_SHELL_FILES.contains(&format!("{session_id}.json").as_str()) {
+                continue;
+            }
+            let mut shell = match self.load_shell(&session_id) {
+                Ok(shell) => shell,
+                Err(e) => {
+                    report.skipped.push(SkippedFile {
+                        path,
+                        reason: e.to_string(),
+                    });
+                    continue;
+                }
+            };
+            if !propagation.selects(&shell) {
+                continue;
+            }
+            report.shells_matched += 1;
+
+            let changed = shell.apply_camera_region(&propagation.camera, &propagation.region);
+            if changed == 0 {
+                continue;
+            }
+            if !propagation.dry_run {
+                shell.region_updated_at = Some(now);
+                self.save_shell(&session_id, &shell)?;
+            }
+            report.shells_updated += 1;
+            report.images_updated += changed;
+            report.updated_sessions.push(session_id);
+        }
+
+        info!(
+            "Region propagation{} updated {} images in {} of {} matching shells",
+            if propagation.dry_run {
+                " (dry run)"
+            } else {
+                ""
+            },
+            report.images_updated,
+            report.shells_updated,
+            report.shells_matched
+        );
+        Ok(report)
     }
 
     /// Get shells filtered by criteria
@@ -375,10 +1391,14 @@ impl ShellDataManager {
     }
 
     /// Toggle the include flag for a shell
+    ///
+    /// A single-field flip can't clobber other edits, so this skips the revision
+    /// check, but it still bumps the revision.
     pub fn toggle_shell_training(&self, session_id: &str) -> OurResult<bool> {
+        let _writing = self.lock_writes()?;
         let mut shell = self.load_shell(session_id)?;
         shell.include = !shell.include;
-        self.save_shell(session_id, &shell)?;
+        self.write_revision(session_id, &shell, shell.revision + 1)?;
 
         info!(
             "Toggled training flag for session {} to {}",
@@ -387,6 +1407,150 @@ impl ShellDataManager {
         Ok(shell.include)
     }
 
+    fn batches_path(&self) -> PathBuf {
+        self.data_directory.join(BATCHES_FILENAME)
+    }
+
+    /// Every batch, and which is active; none before the first is created
+    pub fn batches(&self) -> OurResult<BatchList> {
+        match fs::read_to_string(self.batches_path()) {
+            Ok(contents) => serde_json::from_str(&contents)
+                .map_err(|e| OurError::App(format!("Failed to parse batches: {e}"))),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(BatchList::default()),
+            Err(e) => Err(OurError::App(format!("Failed to read batches: {e}"))),
+        }
+    }
+
+    /// Write the batches; callers hold the write lock
+    fn write_batches(&self, batches: &BatchList) -> OurResult<()> {
+        fs::create_dir_all(&self.data_directory)
+            .map_err(|e| OurError::App(format!("Failed to create data directory: {e}")))?;
+        let json_data = serde_json::to_string_pretty(batches)
+            .map_err(|e| OurError::App(format!("Failed to serialize batches: {e}")))?;
+        write_atomic(&self.batches_path(), json_data.as_bytes())
+            .map_err(|e| OurError::App(format!("Failed to write batches: {e}")))
+    }
+
+    /// Add a batch, refusing a name another batch already has
+    pub fn create_batch(&self, name: &str, notes: Option<String>) -> OurResult<Batch> {
+        let _writing = self.lock_writes()?;
+        let mut batches = self.batches()?;
+        if let Some(existing) = batches.named(name) {
+            return Err(OurError::App(format!(
+                "A batch named {} already exists",
+                existing.name
+            )));
+        }
+        let batch = Batch {
+            id: Uuid::new_v4().to_string(),
+            name: name.trim().to_string(),
+            notes,
+            created_at: Utc::now(),
+            exclude_from_training: false,
+        };
+        batches.batches.push(batch.clone());
+        self.write_batches(&batches)?;
+        info!("Created batch {} ({})", batch.name, batch.id);
+        Ok(batch)
+    }
+
+    /// Put captures that don't name a batch into this one, or into none; `None` if there's no such batch
+    pub fn set_active_batch(&self, batch_id: Option<&str>) -> OurResult<Option<BatchList>> {
+        let _writing = self.lock_writes()?;
+        let mut batches = self.batches()?;
+        if batch_id.is_some_and(|batch_id| batches.get(batch_id).is_none()) {
+            return Ok(None);
+        }
+        batches.active_batch_id = batch_id.map(str::to_string);
+        self.write_batches(&batches)?;
+        match batches.active() {
+            Some(batch) => info!("Captures now go into batch {}", batch.name),
+            None => info!("Captures no longer go into a batch"),
+        }
+        Ok(Some(batches))
+    }
+
+    /// Keep a batch's shells out of training, or return them; `None` if there's no such batch
+    ///
+    /// Excluding clears the include flag of every shell in the batch. Returning
+    /// the batch sets it again on those that are tagged with a brand and type,
+    /// so untagged captures stay out of training.
+    pub fn set_batch_excluded(
+        &self,
+        batch_id: &str,
+        exclude: bool,
+    ) -> OurResult<Option<BatchExclusion>> {
+        let _writing = self.lock_writes()?;
+        let mut batches = self.batches()?;
+        let Some(batch) = batches
+            .batches
+            .iter_mut()
+            .find(|batch| batch.id == batch_id)
+        else {
+            return Ok(None);
+        };
+        batch.exclude_from_training = exclude;
+        let batch = batch.clone();
+        self.write_batches(&batches)?;
+        let shells_changed = self.set_batch_include(batch_id, !exclude)?;
+        info!(
+            "Batch {} is {} training, {shells_changed} shells changed",
+            batch.name,
+            if exclude { "excluded from" } else { "back in" }
+        );
+        Ok(Some(BatchExclusion {
+            batch,
+            shells_changed,
+        }))
+    }
+
+    /// Remove a batch, keeping its shells out of training; `None` if there's no such batch
+    ///
+    /// The shells keep their `batch_id`, so where they came from is still known.
+    pub fn delete_batch(&self, batch_id: &str) -> OurResult<Option<BatchExclusion>> {
+        let _writing = self.lock_writes()?;
+        let mut batches = self.batches()?;
+        let Some(position) = batches
+            .batches
+            .iter()
+            .position(|batch| batch.id == batch_id)
+        else {
+            return Ok(None);
+        };
+        let batch = batches.batches.remove(position);
+        if batches.active_batch_id.as_deref() == Some(batch_id) {
+            batches.active_batch_id = None;
+        }
+        self.write_batches(&batches)?;
+        let shells_changed = self.set_batch_include(batch_id, false)?;
+        info!(
+            "Deleted batch {}, {shells_changed} of its shells taken out of training",
+            batch.name
+        );
+        Ok(Some(BatchExclusion {
+            batch,
+            shells_changed,
+        }))
+    }
+
+    /// Set the include flag of a batch's shells, returning how many changed; callers hold the write lock
+    fn set_batch_include(&self, batch_id: &str, include: bool) -> OurResult<usize> {
+        let mut changed = 0;
+        for (session_id, mut shell) in self.list_shells()? {
+            let tagged = !shell.brand.trim().is_empty() && !shell.shell_type.trim().is_empty();
+            if shell.batch_id.as_deref() != Some(batch_id)
+                || shell.include == include
+                || (include && !tagged)
+            {
+                continue;
+            }
+            shell.include = include;
+            self.write_revision(&session_id, &shell, shell.revision + 1)?;
+            changed += 1;
+        }
+        Ok(changed)
+    }
+
     /// Check if the data directory exists and is writable
     pub fn validate_data_directory(&self) -> OurResult<()> {
         if !self.data_directory.exists() {
@@ -479,6 +1643,40 @@ mod tests {
         assert_eq!(image.region_x, Some(5));
     }
 
+    #[test]
+    fn test_session_ids_stay_in_the_data_directory() {
+        let temp_dir = TempDir::new().expect("Test operation should succeed");
+        let manager = ShellDataManager::new(temp_dir.path().join("data"));
+        let shell = Shell::new("TestBrand".to_string(), "TestType".to_string());
+        let outside = temp_dir.path().join("escape.json");
+        fs::write(
+            &outside,
+            serde_json::to_string(&shell).expect("Test operation should succeed"),
+        )
+        .expect("Test operation should succeed");
+
+        for session_id in ["../escape", "..", "a/b", ""] {
+            assert!(
+                manager.save_shell(session_id, &shell).is_err(),
+                "{session_id:?}"
+            );
+            assert!(manager.load_shell(session_id).is_err(), "{session_id:?}");
+            assert!(manager.delete_shell(session_id).is_err(), "{session_id:?}");
+        }
+        assert!(matches!(
+            manager.get_shell("../escape"),
+            Err(OurError::InvalidName { ref field, .. }) if field == "session_id"
+        ));
+
+        assert!(outside.exists());
+        let mut entries: Vec<_> = fs::read_dir(temp_dir.path())
+            .expect("Test operation should succeed")
+            .map(|entry| entry.expect("Test operation should succeed").file_name())
+            .collect();
+        entries.sort();
+        assert_eq!(entries, ["escape.json"]);
+    }
+
     #[test]
     fn test_shell_data_manager() {
         let temp_dir = TempDir::new().expect("Test operation should succeed");
@@ -519,4 +1717,493 @@ mod tests {
             .expect("Test operation should succeed");
         assert_eq!(shells_after_delete.len(), 0);
     }
+
+    #[test]
+    fn test_search_shells() {
+        let temp_dir = TempDir::new().expect("Test operation should succeed");
+        let manager = ShellDataManager::new(temp_dir.path().to_path_buf());
+
+        let mut older = Shell::new("Winchester".to_string(), "9mm".to_string());
+        older.date_captured = Utc::now() - chrono::Duration::days(2);
+        manager
+            .save_shell("older", &older)
+            .expect("Test operation should succeed");
+        manager
+            .save_shell(
+                "newer",
+                &Shell::new("Federal".to_string(), "winchester-magnum".to_string()),
+            )
+            .expect("Test operation should succeed");
+        let mut excluded = Shell::new("Win Co".to_string(), "45acp".to_string());
+        excluded.include = false;
+        manager
+            .save_shell("excluded", &excluded)
+            .expect("Test operation should succeed");
+
+        let search = |filter: ShellFilter| -> Vec<String> {
+            manager
+                .search_shells(&filter)
+                .expect("Test operation should succeed")
+                .into_iter()
+                .map(|summary| summary.session_id)
+                .collect()
+        };
+
+        // Brand prefix matches rank ahead of newer matches in other fields
+        let filter = ShellFilter {
+            query: Some("WIN".to_string()),
+            ..ShellFilter::default()
+        };
+        assert_eq!(search(filter), vec!["excluded", "older", "newer"]);
+
+        // Restricting fields
+        let filter = ShellFilter {
+            query: Some("win".to_string()),
+            fields: vec![SearchField::ShellType],
+            ..ShellFilter::default()
+        };
+        assert_eq!(search(filter), vec!["newer"]);
+
+        // Composes with the include filter
+        let filter = ShellFilter {
+            query: Some("win".to_string()),
+            include: Some(true),
+            ..ShellFilter::default()
+        };
+        assert_eq!(search(filter), vec!["older", "newer"]);
+
+        // Exact brand and type filters, ignoring case
+        let filter = ShellFilter {
+            brand: Some("winchester".to_string()),
+            shell_type: Some("9MM".to_string()),
+            ..ShellFilter::default()
+        };
+        assert_eq!(search(filter), vec!["older"]);
+
+        // The index follows saves and deletes after it has been built
+        manager
+            .delete_shell("older")
+            .expect("Test operation should succeed");
+        let filter = ShellFilter {
+            query: Some("9mm".to_string()),
+            ..ShellFilter::default()
+        };
+        assert!(search(filter).is_empty());
+        assert_eq!(search(ShellFilter::default()).len(), 2);
+    }
+
+    #[test]
+    fn test_list_shells_skips_stray_files() {
+        let temp_dir = TempDir::new().expect("Test operation should succeed");
+        let manager = ShellDataManager::new(temp_dir.path().to_path_buf());
+        let shell = Shell::new("Winchester".to_string(), "9mm".to_string());
+        manager
+            .save_shell("good", &shell)
+            .expect("Test operation should succeed");
+
+        // A subdirectory, even one named like a record, with a shell inside it
+        let nested = temp_dir.path().join("nested.json");
+        fs::create_dir(&nested).expect("Test operation should succeed");
+        fs::write(
+            nested.join("inner.json"),
+            serde_json::to_string(&shell).expect("Test operation should succeed"),
+        )
+        .expect("Test operation should succeed");
+        fs::write(temp_dir.path().join(".good.json.swp"), b"\x00swap")
+            .expect("Test operation should succeed");
+        fs::write(
+            temp_dir.path().join("garbage.json"),
+            [0xff, 0xfe, 0x00, 0x9c],
+        )
+        .expect("Test operation should succeed");
+        fs::write(temp_dir.path().join("case_types.json"), b"{}")
+            .expect("Test operation should succeed");
+        #[cfg(unix)]
+        {
+            use std::os::unix::ffi::OsStrExt;
+            let name = std::ffi::OsStr::from_bytes(b"bad\xff.json");
+            fs::write(temp_dir.path().join(name), b"{}").expect("Test operation should succeed");
+        }
+
+        let shells = manager
+            .list_shells()
+            .expect("Test operation should succeed");
+        assert_eq!(shells.len(), 1);
+        assert_eq!(shells[0].0, "good");
+
+        let report = manager
+            .check_shells()
+            .expect("Test operation should succeed");
+        let skipped: Vec<String> = report
+            .skipped
+            .iter()
+            .map(|file| {
+                file.path
+                    .file_name()
+                    .map(|name| name.to_string_lossy().into_owned())
+            })
+            .collect::<Option<_>>()
+            .expect("Test operation should succeed");
+        #[cfg(unix)]
+        assert_eq!(skipped.len(), 2, "{skipped:?}");
+        assert!(skipped.contains(&"garbage.json".to_string()), "{skipped:?}");
+    }
+
+    #[test]
+    fn test_save_shell_writes_atomically() {
+        let temp_dir = TempDir::new().expect("Test operation should succeed");
+        let manager = ShellDataManager::new(temp_dir.path().to_path_buf());
+        let shell = Shell::new("Winchester".to_string(), "9mm".to_string());
+
+        manager
+            .save_shell("session", &shell)
+            .expect("Test operation should succeed");
+        manager
+            .save_shell("session", &shell)
+            .expect("Test operation should succeed");
+
+        // Only the shell file is left behind, with no temporary files
+        let files: Vec<String> = fs::read_dir(temp_dir.path())
+            .expect("Test operation should succeed")
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.file_name().to_string_lossy().into_owned())
+            .collect();
+        assert_eq!(files, vec!["session.json"]);
+        assert_eq!(
+            manager
+                .load_shell("session")
+                .expect("Test operation should succeed"),
+            Shell {
+                revision: 2,
+                ..shell
+            }
+        );
+    }
+
+    #[test]
+    fn test_stale_revision_is_refused_with_the_current_copy() {
+        let temp_dir = TempDir::new().expect("Test operation should succeed");
+        let manager = ShellDataManager::new(temp_dir.path().to_path_buf());
+        let shell = Shell::new("Winchester".to_string(), "9mm".to_string());
+
+        let saved = match manager
+            .save_shell_at_revision("session", &shell, 0)
+            .expect("Test operation should succeed")
+        {
+            RevisionCheck::Saved(saved) => saved,
+            RevisionCheck::Conflict(current) => panic!("new shell conflicted with {current:?}"),
+        };
+        assert_eq!(saved.revision, 1);
+
+        // Two editors load revision 1; the first save wins
+        let mut tablet = manager
+            .load_shell("session")
+            .expect("Test operation should succeed");
+        let mut desktop = tablet.clone();
+        tablet.brand = "Federal".to_string();
+        desktop.notes = Some("dented".to_string());
+        assert!(matches!(
+            manager.save_shell_at_revision("session", &tablet, tablet.revision),
+            Ok(RevisionCheck::Saved(Shell { revision: 2, .. }))
+        ));
+        match manager
+            .save_shell_at_revision("session", &desktop, desktop.revision)
+            .expect("Test operation should succeed")
+        {
+            RevisionCheck::Conflict(current) => {
+                assert_eq!(current.brand, "Federal");
+                assert_eq!(current.revision, 2);
+                assert_eq!(current.notes, None);
+            }
+            RevisionCheck::Saved(saved) => panic!("stale save overwrote the shell: {saved:?}"),
+        }
+
+        // Toggling skips the check but still moves the revision on
+        manager
+            .toggle_shell_training("session")
+            .expect("Test operation should succeed");
+        let current = manager
+            .load_shell("session")
+            .expect("Test operation should succeed");
+        assert_eq!((current.revision, current.include), (3, false));
+
+        // A shell that was deleted can't be saved at an old revision
+        assert!(
+            manager
+                .save_shell_at_revision("missing", &shell, 4)
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_shell_without_notes_or_flags_still_loads() {
+        // Shell files written before notes and flags existed
+        let old_shell = r#"{
+            "date_captured": "2025-01-01T12:00:00Z",
+            "brand": "Winchester",
+            "shell_type": "9mm",
+            "image_filenames": ["a.jpg"],
+            "captured_images": null,
+            "include": true
+        }"#;
+        let shell: Shell = serde_json::from_str(old_shell).expect("old shell should parse");
+        assert_eq!(shell.notes, None);
+        assert!(shell.flags.is_empty());
+
+        let mut flagged = shell.clone();
+        flagged.notes = Some("dented mouth".to_string());
+        flagged.flags = vec![
+            ShellFlag::Blurry,
+            "berdan primer".parse().expect("flag should parse"),
+        ];
+        let json = serde_json::to_string(&flagged).expect("shell should serialize");
+        assert!(json.contains(r#""flags":["Blurry","berdan primer"]"#));
+        let round_trip: Shell = serde_json::from_str(&json).expect("shell should parse");
+        assert_eq!(round_trip, flagged);
+        assert!(round_trip.has_any_flag(&[ShellFlag::Blurry]));
+        assert!(!round_trip.has_any_flag(&[ShellFlag::Damaged]));
+        assert_eq!(
+            "wrong_orientation".parse::<ShellFlag>().ok(),
+            Some(ShellFlag::WrongOrientation)
+        );
+    }
+
+    /// Each fixture of an old schema, by file stem, with the version its name starts with
+    fn schema_fixtures() -> Vec<(String, PathBuf, u32)> {
+        let directory = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/shell_schema");
+        let mut fixtures: Vec<(String, PathBuf, u32)> = fs::read_dir(&directory)
+            .expect("fixture directory should be readable")
+            .map(|entry| {
+                let path = entry.expect("fixture should be listed").path();
+                let name = path
+                    .file_stem()
+                    .and_then(|stem| stem.to_str())
+                    .expect("fixture names are UTF-8")
+                    .to_string();
+                let version = name
+                    .strip_prefix('v')
+                    .and_then(|rest| rest.split('_').next())
+                    .and_then(|version| version.parse().ok())
+                    .expect("fixtures are named v{version}[_description].json");
+                (name, path, version)
+            })
+            .collect();
+        fixtures.sort();
+        fixtures
+    }
+
+    #[test]
+    fn test_every_schema_fixture_loads_and_round_trips() {
+        let temp_dir = TempDir::new().expect("Test operation should succeed");
+        let manager = ShellDataManager::new(temp_dir.path().to_path_buf());
+        let mut versions = BTreeSet::new();
+
+        for (name, path, version) in schema_fixtures() {
+            let raw: serde_json::Value =
+                serde_json::from_str(&fs::read_to_string(&path).expect("fixture should read"))
+                    .expect("fixture should be JSON");
+            assert_eq!(
+                crate::shell_migrations::schema_version(&raw).ok(),
+                Some(version),
+                "{name}"
+            );
+            versions.insert(version);
+
+            fs::copy(&path, temp_dir.path().join(format!("{name}.json")))
+                .expect("fixture should copy");
+            let shell = manager
+                .load_shell(&name)
+                .unwrap_or_else(|e| panic!("{name} should load: {e}"));
+            assert_eq!(shell.schema_version, CURRENT_SHELL_SCHEMA, "{name}");
+
+            manager
+                .save_shell(&name, &shell)
+                .expect("shell should save");
+            let saved: serde_json::Value = serde_json::from_str(
+                &fs::read_to_string(temp_dir.path().join(format!("{name}.json")))
+                    .expect("saved shell should read"),
+            )
+            .expect("saved shell should be JSON");
+            assert_eq!(saved["schema_version"], CURRENT_SHELL_SCHEMA, "{name}");
+            assert_eq!(
+                manager.load_shell(&name).expect("saved shell should load"),
+                Shell {
+                    revision: shell.revision + 1,
+                    ..shell
+                },
+                "{name}"
+            );
+        }
+        assert_eq!(
+            versions,
+            (1..=CURRENT_SHELL_SCHEMA).collect(),
+            "every schema version needs a fixture"
+        );
+
+        // Python wrote times without an offset, in UTC
+        let python = manager
+            .load_shell("v1_python")
+            .expect("fixture should load");
+        assert_eq!(
+            python.date_captured,
+            "2024-11-03T14:22:51.482913Z"
+                .parse::<DateTime<Utc>>()
+                .expect("timestamp should parse")
+        );
+        let rust = manager.load_shell("v1").expect("fixture should load");
+        assert_eq!(rust.notes.as_deref(), Some("dented rim"));
+        assert!(rust.capture.is_some());
+    }
+
+    #[test]
+    fn test_migrate_all_rewrites_old_records_after_backing_them_up() {
+        let temp_dir = TempDir::new().expect("Test operation should succeed");
+        let manager = ShellDataManager::new(temp_dir.path().to_path_buf());
+        let fixtures = schema_fixtures();
+        for (name, path, _) in &fixtures {
+            fs::copy(path, temp_dir.path().join(format!("{name}.json")))
+                .expect("fixture should copy");
+        }
+        fs::write(temp_dir.path().join("broken.json"), "{").expect("file should write");
+        fs::write(
+            temp_dir.path().join("future.json"),
+            r#"{"schema_version": 99}"#,
+        )
+        .expect("file should write");
+
+        let report = manager.migrate_all(true).expect("migration should run");
+        let old: BTreeMap<String, u32> = fixtures
+            .iter()
+            .filter(|(_, _, version)| *version < CURRENT_SHELL_SCHEMA)
+            .map(|(name, _, version)| (name.clone(), *version))
+            .collect();
+        assert_eq!(report.migrated, old);
+        assert_eq!(report.current, fixtures.len() - old.len());
+        assert_eq!(report.skipped.len(), 2, "{:?}", report.skipped);
+
+        let backup_directory = report
+            .backup_directory
+            .clone()
+            .expect("originals should be backed up");
+        for (name, path, version) in &fixtures {
+            let original = fs::read(path).expect("fixture should read");
+            let backup = fs::read(backup_directory.join(format!("{name}.json")));
+            if *version < CURRENT_SHELL_SCHEMA {
+                assert_eq!(backup.expect("backup should exist"), original, "{name}");
+            } else {
+                assert!(
+                    backup.is_err(),
+                    "{name} was current and needn't be backed up"
+                );
+            }
+            let migrated: serde_json::Value = serde_json::from_str(
+                &fs::read_to_string(temp_dir.path().join(format!("{name}.json")))
+                    .expect("migrated shell should read"),
+            )
+            .expect("migrated shell should be JSON");
+            assert_eq!(migrated["schema_version"], CURRENT_SHELL_SCHEMA, "{name}");
+        }
+        // Migrating isn't an edit
+        assert_eq!(
+            manager
+                .load_shell("v1")
+                .expect("shell should load")
+                .revision,
+            4
+        );
+
+        let again = manager.migrate_all(false).expect("migration should run");
+        assert!(again.migrated.is_empty());
+        assert_eq!(again.current, fixtures.len());
+        assert_eq!(again.backup_directory, None);
+        // The backup directory isn't mistaken for shell records
+        assert_eq!(
+            manager.list_shells().expect("shells should list").len(),
+            fixtures.len()
+        );
+    }
+
+    #[test]
+    fn test_excluding_a_batch_flips_its_shells_in_one_go() {
+        let temp_dir = TempDir::new().expect("Test operation should succeed");
+        let manager = ShellDataManager::new(temp_dir.path().to_path_buf());
+        let lot = manager
+            .create_batch(" gun show lot 3 ", Some("mixed headstamps".to_string()))
+            .expect("batch should be created");
+        assert_eq!(lot.name, "gun show lot 3");
+        assert!(manager.create_batch("Gun Show Lot 3", None).is_err());
+        let other = manager
+            .create_batch("range pickup June", None)
+            .expect("batch should be created");
+
+        let save = |session_id: &str, brand: &str, batch: &Batch, include: bool| {
+            let mut shell = Shell::new(brand.to_string(), "9mm".to_string());
+            shell.include = include;
+            shell.batch_id = Some(batch.id.clone());
+            manager
+                .save_shell(session_id, &shell)
+                .expect("shell should save");
+        };
+        save("tagged", "Winchester", &lot, true);
+        save("untagged", "", &lot, false);
+        save("elsewhere", "Federal", &other, true);
+
+        let excluded = manager
+            .set_batch_excluded(&lot.id, true)
+            .expect("batch should update")
+            .expect("batch exists");
+        assert!(excluded.batch.exclude_from_training);
+        assert_eq!(excluded.shells_changed, 1);
+        let include = |session_id: &str| {
+            manager
+                .load_shell(session_id)
+                .expect("shell should load")
+                .include
+        };
+        assert!(!include("tagged"));
+        assert!(include("elsewhere"));
+
+        // Returning the lot only puts tagged shells back
+        let returned = manager
+            .set_batch_excluded(&lot.id, false)
+            .expect("batch should update")
+            .expect("batch exists");
+        assert_eq!(returned.shells_changed, 1);
+        assert!(include("tagged"));
+        assert!(!include("untagged"));
+
+        manager
+            .set_active_batch(Some(&lot.id))
+            .expect("batch should activate")
+            .expect("batch exists");
+        let deleted = manager
+            .delete_batch(&lot.id)
+            .expect("batch should delete")
+            .expect("batch exists");
+        assert_eq!(deleted.shells_changed, 1);
+        assert!(!include("tagged"));
+        let batches = manager.batches().expect("batches should read");
+        assert_eq!(batches.batches, vec![other.clone()]);
+        assert_eq!(batches.active_batch_id, None);
+        // The shells still say where they came from, and batches.json isn't a shell
+        assert_eq!(
+            manager
+                .search_shells(&ShellFilter {
+                    batch_id: Some(lot.id.clone()),
+                    ..ShellFilter::default()
+                })
+                .expect("search should succeed")
+                .len(),
+            2
+        );
+        assert_eq!(manager.list_shells().expect("shells should list").len(), 3);
+
+        assert_eq!(manager.delete_batch(&lot.id).expect("delete runs"), None);
+        assert_eq!(
+            manager
+                .set_active_batch(Some("missing"))
+                .expect("activation runs"),
+            None
+        );
+    }
 }
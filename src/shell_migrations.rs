@@ -0,0 +1,125 @@
+//! Versions of the shell record layout, and upgrading old records to the current one.
+//!
+//! Every saved shell carries a `schema_version`; records written before it
+//! existed have none and are version 1. When the layout changes,
+//! [`CURRENT_SHELL_SCHEMA`] goes up by one and a step taking the previous
+//! version to it is added to [`MIGRATIONS`]. Loading a shell runs the steps
+//! from its version up, on the raw JSON, before it is deserialised; saving
+//! always writes the current version. Old files therefore keep loading
+//! forever, and `data migrate` can rewrite them so they don't need upgrading
+//! on every load.
+//!
+//! Each step only has to know the version before it, and fixtures of every
+//! version are kept in `tests/fixtures/shell_schema` to prove they still load.
+
+use serde_json::Value;
+
+use crate::{OurError, OurResult};
+
+/// The layout [`crate::shell_data::Shell`] is written in
+pub const CURRENT_SHELL_SCHEMA: u32 = 2;
+
+/// A step taking a shell record from one version to the next
+type Migration = fn(Value) -> Value;
+
+/// `MIGRATIONS[n]` takes a record from version `n + 1` to `n + 2`
+const MIGRATIONS: [Migration; (CURRENT_SHELL_SCHEMA - 1) as usize] = [migrate_v1_to_v2];
+
+/// The version a raw shell record was written in; no `schema_version` means version 1
+pub fn schema_version(record: &Value) -> OurResult<u32> {
+    match record.get("schema_version") {
+        None | Some(Value::Null) => Ok(1),
+        Some(version) => version
+            .as_u64()
+            .and_then(|version| u32::try_from(version).ok())
+            .filter(|&version| version >= 1)
+            .ok_or_else(|| OurError::App(format!("Invalid shell schema_version {version}"))),
+    }
+}
+
+/// Bring a raw shell record up to [`CURRENT_SHELL_SCHEMA`], returning it and the version it was in
+///
+/// Records from a newer version of the software are refused rather than
+/// loaded with fields missing.
+pub fn migrate(mut record: Value) -> OurResult<(Value, u32)> {
+    if !record.is_object() {
+        return Err(OurError::App(
+            "Shell record is not a JSON object".to_string(),
+        ));
+    }
+    let from = schema_version(&record)?;
+    if from > CURRENT_SHELL_SCHEMA {
+        return Err(OurError::App(format!(
+            "Shell record is schema version {from}, newer than the {CURRENT_SHELL_SCHEMA} this version understands"
+        )));
+    }
+    for step in &MIGRATIONS[(from - 1) as usize..] {
+        record = step(record);
+    }
+    Ok((record, from))
+}
+
+/// Version 2 writes every field and a UTC offset on `date_captured`
+///
+/// Version 1 records come from the Python implementation and early Rust
+/// builds. Python's `isoformat()` leaves the offset off, and those times were
+/// always UTC. Fields added to version 1 over time are filled in with the
+/// values they were read as when missing.
+fn migrate_v1_to_v2(mut record: Value) -> Value {
+    if let Some(fields) = record.as_object_mut() {
+        if let Some(Value::String(date)) = fields.get_mut("date_captured")
+            && chrono::DateTime::parse_from_rfc3339(date).is_err()
+            && chrono::NaiveDateTime::parse_from_str(date, "%Y-%m-%dT%H:%M:%S%.f").is_ok()
+        {
+            date.push('Z');
+        }
+        for (field, missing) in [
+            ("captured_images", Value::Null),
+            ("notes", Value::Null),
+            ("flags", Value::Array(Vec::new())),
+            ("region_updated_at", Value::Null),
+            ("revision", Value::from(0)),
+        ] {
+            fields.entry(field).or_insert(missing);
+        }
+        fields.insert("schema_version".to_string(), Value::from(2));
+    }
+    record
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_versions_are_read_and_checked() {
+        assert_eq!(schema_version(&json!({})).ok(), Some(1));
+        assert_eq!(schema_version(&json!({"schema_version": 2})).ok(), Some(2));
+        assert!(schema_version(&json!({"schema_version": 0})).is_err());
+        assert!(schema_version(&json!({"schema_version": "2"})).is_err());
+
+        let newer = json!({"schema_version": CURRENT_SHELL_SCHEMA + 1});
+        let error = migrate(newer).expect_err("newer records are refused");
+        assert!(error.to_string().contains("newer"), "{error}");
+        assert!(migrate(json!([])).is_err());
+    }
+
+    #[test]
+    fn test_v1_dates_without_an_offset_are_utc() {
+        let (record, from) = migrate(json!({
+            "date_captured": "2024-03-01T09:15:00.123456",
+            "notes": "kept",
+        }))
+        .expect("v1 record migrates");
+        assert_eq!(from, 1);
+        assert_eq!(record["date_captured"], "2024-03-01T09:15:00.123456Z");
+        assert_eq!(record["notes"], "kept");
+        assert_eq!(record["flags"], json!([]));
+        assert_eq!(record["schema_version"], CURRENT_SHELL_SCHEMA);
+
+        // Already current records are left alone
+        let (again, from) = migrate(record.clone()).expect("current record loads");
+        assert_eq!((again, from), (record, CURRENT_SHELL_SCHEMA));
+    }
+}
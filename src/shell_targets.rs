@@ -0,0 +1,230 @@
+//! Shell count targets per case type, and how far along each is.
+//!
+//! A case type can carry a `target_shell_count`, a goal like "50 Winchester
+//! 9mm before training". Its progress counts the shells of that type that go
+//! into training, the same count as `shell_count` in `/api/case-types`.
+//! Targets are only for planning what to feed the machine next; they don't
+//! change whether a case type is ready for training.
+//!
+//! [`ShellTargets`] remembers which targets have been reached, so a tagged
+//! shell that takes a case type over its target broadcasts one
+//! [`TargetReached`]. A case type that drops back below its target, say after
+//! a shell is excluded, reports again when it next crosses it.
+
+use std::collections::HashSet;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast;
+
+use crate::ml_training::CaseType;
+
+/// How many target reached events a slow subscriber can fall behind by
+const TARGET_EVENT_BACKLOG: usize = 16;
+
+/// Progress of one case type towards its target
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct TargetProgress {
+    pub case_type: String,
+    /// Brand and designation, e.g. "Winchester 9mm"
+    pub label: String,
+    pub current: usize,
+    pub target: u32,
+    /// `current` as a percentage of `target` to one decimal place, over 100 past the target
+    pub percent: f64,
+    pub reached: bool,
+}
+
+impl TargetProgress {
+    /// Progress of `case_type` with `current` shells, if it has a target
+    pub fn new(case_type: &CaseType, current: usize) -> Option<Self> {
+        let target = case_type.target_shell_count?;
+        Some(Self {
+            case_type: case_type.name.clone(),
+            label: match &case_type.brand {
+                Some(brand) => format!("{brand} {}", case_type.designation),
+                None => case_type.name.clone(),
+            },
+            current,
+            target,
+            percent: percent_of_target(current, target),
+            reached: current >= target as usize,
+        })
+    }
+}
+
+/// `current` as a percentage of `target`, rounded to one decimal place
+///
+/// A target of zero is already met, so it is 100%.
+pub fn percent_of_target(current: usize, target: u32) -> f64 {
+    if target == 0 {
+        return 100.0;
+    }
+    (current as f64 * 1000.0 / f64::from(target)).round() / 10.0
+}
+
+/// Least complete first, so the top of the list is what to feed the machine next
+pub fn sort_by_completion(progress: &mut [TargetProgress]) {
+    progress.sort_by(|a, b| {
+        a.percent
+            .total_cmp(&b.percent)
+            .then_with(|| a.case_type.cmp(&b.case_type))
+    });
+}
+
+/// A case type reaching its target, broadcast once per crossing
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub struct TargetReached {
+    pub case_type: String,
+    pub label: String,
+    pub target: u32,
+    pub current: usize,
+    pub at: DateTime<Utc>,
+    /// Text for a notification, e.g. "Winchester 9mm target reached"
+    pub message: String,
+}
+
+/// Which targets have been reached, and the channel announcing new ones
+#[derive(Debug, Clone)]
+pub struct ShellTargets {
+    reached: Arc<Mutex<HashSet<String>>>,
+    sender: broadcast::Sender<TargetReached>,
+}
+
+impl Default for ShellTargets {
+    fn default() -> Self {
+        Self {
+            reached: Arc::new(Mutex::new(HashSet::new())),
+            sender: broadcast::channel(TARGET_EVENT_BACKLOG).0,
+        }
+    }
+}
+
+impl ShellTargets {
+    // A poisoned lock only means a handler panicked mid-update; the set is still usable
+    fn lock_reached(&self) -> MutexGuard<'_, HashSet<String>> {
+        self.reached
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Take `progress` as the starting point without announcing anything,
+    /// e.g. at startup or when a target is changed
+    pub fn sync(&self, progress: &[TargetProgress]) {
+        *self.lock_reached() = progress
+            .iter()
+            .filter(|progress| progress.reached)
+            .map(|progress| progress.case_type.clone())
+            .collect();
+    }
+
+    /// Note the latest progress, broadcasting and returning the targets newly reached
+    pub fn observe(&self, progress: &[TargetProgress]) -> Vec<TargetReached> {
+        let now = Utc::now();
+        let mut reached = self.lock_reached();
+        let newly_reached: Vec<TargetReached> = progress
+            .iter()
+            .filter(|progress| progress.reached && !reached.contains(&progress.case_type))
+            .map(|progress| TargetReached {
+                case_type: progress.case_type.clone(),
+                label: progress.label.clone(),
+                target: progress.target,
+                current: progress.current,
+                at: now,
+                message: format!("{} target reached", progress.label),
+            })
+            .collect();
+        *reached = progress
+            .iter()
+            .filter(|progress| progress.reached)
+            .map(|progress| progress.case_type.clone())
+            .collect();
+        drop(reached);
+
+        for event in &newly_reached {
+            // Nobody listening is fine, the event is only a notification
+            let _ = self.sender.send(event.clone());
+        }
+        newly_reached
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<TargetReached> {
+        self.sender.subscribe()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn winchester(target: Option<u32>) -> CaseType {
+        let mut case_type = CaseType::new(
+            "Winchester_9mm".to_string(),
+            "9mm".to_string(),
+            Some("Winchester".to_string()),
+        );
+        case_type.target_shell_count = target;
+        case_type
+    }
+
+    #[test]
+    fn test_progress_percentages_and_order() {
+        assert_eq!(percent_of_target(0, 50), 0.0);
+        assert_eq!(percent_of_target(1, 3), 33.3);
+        assert_eq!(percent_of_target(2, 3), 66.7);
+        assert_eq!(percent_of_target(50, 50), 100.0);
+        assert_eq!(percent_of_target(75, 50), 150.0);
+        assert_eq!(percent_of_target(3, 0), 100.0);
+
+        assert_eq!(TargetProgress::new(&winchester(None), 10), None);
+        let progress = TargetProgress::new(&winchester(Some(50)), 10).expect("target is set");
+        assert_eq!(progress.label, "Winchester 9mm");
+        assert_eq!((progress.percent, progress.reached), (20.0, false));
+
+        let mut federal = CaseType::new("Federal_308win".to_string(), "308win".to_string(), None);
+        federal.target_shell_count = Some(4);
+        let mut all = vec![
+            progress,
+            TargetProgress::new(&federal, 1).expect("target is set"),
+        ];
+        sort_by_completion(&mut all);
+        assert_eq!(all[0].case_type, "Winchester_9mm");
+        assert_eq!(all[1].label, "Federal_308win");
+    }
+
+    #[test]
+    fn test_crossing_a_target_is_announced_once() {
+        let targets = ShellTargets::default();
+        let mut events = targets.subscribe();
+        let at = |current| {
+            vec![TargetProgress::new(&winchester(Some(3)), current).expect("target is set")]
+        };
+
+        targets.sync(&at(2));
+        assert!(targets.observe(&at(2)).is_empty());
+        let reached = targets.observe(&at(3));
+        assert_eq!(reached.len(), 1);
+        assert_eq!(reached[0].message, "Winchester 9mm target reached");
+        assert!(targets.observe(&at(4)).is_empty());
+        assert_eq!(
+            events
+                .try_recv()
+                .expect("event should be broadcast")
+                .current,
+            3
+        );
+        assert!(events.try_recv().is_err());
+
+        // Falling back below and crossing again is a new crossing
+        assert!(targets.observe(&at(2)).is_empty());
+        assert_eq!(targets.observe(&at(3)).len(), 1);
+
+        // Targets already met when the server starts aren't announced
+        let restarted = ShellTargets::default();
+        restarted.sync(&at(5));
+        assert!(restarted.observe(&at(6)).is_empty());
+    }
+}
@@ -0,0 +1,331 @@
+//! Live streams for ESPHome cameras that only serve snapshots.
+//!
+//! Some ESP32-CAM builds have no MJPEG stream, only `/camera/snapshot`. When a
+//! camera's stream answers 404, or fails to open
+//! [`STREAM_FAILURES_BEFORE_FALLBACK`] times in a row, [`SnapshotStreams`]
+//! serves it by polling the snapshot URL instead. One poller runs per camera
+//! however many viewers are watching, and it goes easy on the ESP32: it never
+//! polls faster than [`MIN_SNAPSHOT_POLL_INTERVAL`] and backs off while
+//! snapshots fail or time out. Every [`STREAM_RETRY_INTERVAL`] it tries the
+//! real stream again; once that answers, the polled streams end so the
+//! browsers reconnect to the real one.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+use axum::body::Bytes;
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast;
+use tracing::{debug, info, warn};
+use url::Url;
+
+use crate::camera_manager::{CameraHandle, CameraInfo};
+use crate::{OurError, OurResult};
+
+/// How often a camera's snapshot is polled unless configured otherwise
+pub const DEFAULT_SNAPSHOT_POLL_INTERVAL: Duration = Duration::from_millis(500);
+/// Snapshots are never polled faster than this, whatever the configuration says
+pub const MIN_SNAPSHOT_POLL_INTERVAL: Duration = Duration::from_millis(200);
+/// Longest wait between snapshots while they keep failing
+const MAX_POLL_BACKOFF: Duration = Duration::from_secs(10);
+/// How long a snapshot may take before it counts as timed out
+const SNAPSHOT_TIMEOUT: Duration = Duration::from_secs(5);
+/// Failed attempts to open a camera's stream before it is polled instead
+pub const STREAM_FAILURES_BEFORE_FALLBACK: u32 = 3;
+/// How often a polled camera's real stream is tried again
+pub const STREAM_RETRY_INTERVAL: Duration = Duration::from_secs(30);
+/// Polled frames kept for viewers that fall behind
+const POLLED_FRAME_BUFFER: usize = 4;
+
+/// How a camera's live stream is being served
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StreamMode {
+    /// The camera's own MJPEG stream is proxied
+    #[default]
+    Mjpeg,
+    /// Frames are polled from the camera's snapshot URL
+    SnapshotPoll,
+}
+
+/// An open live stream of an ESPHome camera
+pub enum EspStream {
+    /// The camera's own MJPEG stream
+    Mjpeg(reqwest::Response),
+    /// JPEG frames polled from the snapshot URL, shared with every other viewer
+    Polled(broadcast::Receiver<Bytes>),
+}
+
+#[derive(Default)]
+struct CameraStreams {
+    mode: StreamMode,
+    /// Attempts to open the stream that failed since it last opened
+    stream_failures: u32,
+    /// When a polled camera's stream may be tried again
+    retry_stream_at: Option<Instant>,
+    /// The running poller's frames
+    poller: Option<broadcast::Sender<Bytes>>,
+}
+
+/// Opens ESPHome camera streams, polling snapshots for cameras without one
+#[derive(Clone)]
+pub struct SnapshotStreams {
+    client: reqwest::Client,
+    cameras: CameraHandle,
+    poll_interval: Duration,
+    retry_interval: Duration,
+    streams: Arc<Mutex<HashMap<String, CameraStreams>>>,
+}
+
+impl SnapshotStreams {
+    /// Poll every `poll_interval`, raised to [`MIN_SNAPSHOT_POLL_INTERVAL`] if it is shorter
+    pub fn new(cameras: CameraHandle, poll_interval: Duration) -> OurResult<Self> {
+        // No overall timeout, it would cut off streams that are still running
+        let client = reqwest::Client::builder()
+            .connect_timeout(SNAPSHOT_TIMEOUT)
+            .build()?;
+        Ok(Self {
+            client,
+            cameras,
+            poll_interval: poll_interval.max(MIN_SNAPSHOT_POLL_INTERVAL),
+            retry_interval: STREAM_RETRY_INTERVAL,
+            streams: Arc::new(Mutex::new(HashMap::new())),
+        })
+    }
+
+    /// Try a polled camera's real stream this often instead of every [`STREAM_RETRY_INTERVAL`]
+    pub fn with_retry_interval(self, retry_interval: Duration) -> Self {
+        Self {
+            retry_interval,
+            ..self
+        }
+    }
+
+    /// The interval snapshots are polled at
+    pub fn poll_interval(&self) -> Duration {
+        self.poll_interval
+    }
+
+    // A poisoned lock only means a stream panicked mid-update; the state is still usable
+    fn lock_streams(&self) -> MutexGuard<'_, HashMap<String, CameraStreams>> {
+        self.streams
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// How the camera's live stream is being served
+    pub fn mode(&self, camera_id: &str) -> StreamMode {
+        self.lock_streams()
+            .get(camera_id)
+            .map(|streams| streams.mode)
+            .unwrap_or_default()
+    }
+
+    /// Open the camera's stream, or join its snapshot poller when it has no stream
+    pub async fn open(&self, camera: &CameraInfo) -> OurResult<EspStream> {
+        if self.should_try_stream(&camera.id) {
+            match self.client.get(camera.stream_url.clone()).send().await {
+                Ok(response) if response.status().is_success() => {
+                    self.stream_opened(&camera.id);
+                    return Ok(EspStream::Mjpeg(response));
+                }
+                Ok(response) if response.status() == StatusCode::NOT_FOUND => {
+                    info!(
+                        "Camera {} has no stream, polling its snapshots instead",
+                        camera.id
+                    );
+                    self.fall_back(&camera.id);
+                }
+                Ok(response) => {
+                    let status = response.status();
+                    let error = format!("Stream request failed with status: {status}");
+                    if !self.stream_failed(&camera.id) {
+                        return Err(OurError::App(error));
+                    }
+                    info!(
+                        "Camera {} stream keeps failing ({error}), polling its snapshots instead",
+                        camera.id
+                    );
+                }
+                Err(e) => {
+                    if !self.stream_failed(&camera.id) {
+                        return Err(OurError::App(format!("Failed to open stream: {e}")));
+                    }
+                    info!(
+                        "Camera {} stream keeps failing ({e}), polling its snapshots instead",
+                        camera.id
+                    );
+                }
+            }
+        }
+        Ok(EspStream::Polled(self.subscribe(camera)))
+    }
+
+    fn should_try_stream(&self, camera_id: &str) -> bool {
+        self.lock_streams()
+            .get(camera_id)
+            .is_none_or(|streams| match streams.mode {
+                StreamMode::Mjpeg => true,
+                // Viewers join a running poller; the poller itself retries the stream
+                StreamMode::SnapshotPoll => {
+                    streams.poller.is_none()
+                        && streams
+                            .retry_stream_at
+                            .is_none_or(|retry_at| Instant::now() >= retry_at)
+                }
+            })
+    }
+
+    fn stream_opened(&self, camera_id: &str) {
+        let mut streams = self.lock_streams();
+        let streams = streams.entry(camera_id.to_string()).or_default();
+        streams.mode = StreamMode::Mjpeg;
+        streams.stream_failures = 0;
+        streams.retry_stream_at = None;
+    }
+
+    fn fall_back(&self, camera_id: &str) {
+        let mut streams = self.lock_streams();
+        let streams = streams.entry(camera_id.to_string()).or_default();
+        streams.mode = StreamMode::SnapshotPoll;
+        streams.retry_stream_at = Some(Instant::now() + self.retry_interval);
+    }
+
+    /// Count a failed attempt to open the stream, returning true once the camera should be polled
+    fn stream_failed(&self, camera_id: &str) -> bool {
+        let failures = {
+            let mut streams = self.lock_streams();
+            let streams = streams.entry(camera_id.to_string()).or_default();
+            streams.stream_failures = streams.stream_failures.saturating_add(1);
+            streams.stream_failures
+        };
+        let fall_back = failures >= STREAM_FAILURES_BEFORE_FALLBACK;
+        if fall_back {
+            self.fall_back(camera_id);
+        }
+        fall_back
+    }
+
+    /// Join the camera's poller, starting it if nobody is watching yet
+    fn subscribe(&self, camera: &CameraInfo) -> broadcast::Receiver<Bytes> {
+        let mut streams = self.lock_streams();
+        let streams = streams.entry(camera.id.clone()).or_default();
+        if let Some(frames) = &streams.poller {
+            return frames.subscribe();
+        }
+        let (frames, receiver) = broadcast::channel(POLLED_FRAME_BUFFER);
+        streams.poller = Some(frames.clone());
+        tokio::spawn(self.clone().poll(camera.clone(), frames));
+        receiver
+    }
+
+    /// Poll the camera's snapshot until nobody is watching or its stream comes back
+    async fn poll(self, camera: CameraInfo, frames: broadcast::Sender<Bytes>) {
+        info!(
+            "Polling snapshots of camera {} every {}ms",
+            camera.id,
+            self.poll_interval.as_millis()
+        );
+        let mut delay = self.poll_interval;
+        loop {
+            if self.retry_due(&camera.id) && self.stream_is_back(&camera.stream_url).await {
+                info!(
+                    "Camera {} stream is back, ending snapshot polling",
+                    camera.id
+                );
+                let mut streams = self.lock_streams();
+                if let Some(streams) = streams.get_mut(&camera.id) {
+                    streams.mode = StreamMode::Mjpeg;
+                    streams.stream_failures = 0;
+                    streams.retry_stream_at = None;
+                    // Dropping the last sender ends every viewer's stream
+                    streams.poller = None;
+                }
+                break;
+            }
+
+            let recorded = match self.snapshot(&camera.snapshot_url).await {
+                Ok(frame) => {
+                    delay = self.poll_interval;
+                    // Nobody may be listening between viewers, that's checked below
+                    let _ = frames.send(frame);
+                    self.cameras.record_stream_frames(&camera.id, 1)
+                }
+                Err(e) => {
+                    delay = (delay * 2).min(MAX_POLL_BACKOFF);
+                    warn!(
+                        "Snapshot poll of camera {} failed, next in {}ms: {e}",
+                        camera.id,
+                        delay.as_millis()
+                    );
+                    self.cameras
+                        .record_stream_failure(&camera.id, &e.to_string())
+                }
+            };
+            if let Err(e) = recorded {
+                debug!(
+                    "Failed to record stream health for camera {}: {e}",
+                    camera.id
+                );
+            }
+
+            tokio::time::sleep(delay).await;
+
+            // Checked under the lock so a viewer can't join a poller that is about to stop
+            let mut streams = self.lock_streams();
+            if frames.receiver_count() == 0 {
+                if let Some(streams) = streams.get_mut(&camera.id) {
+                    streams.poller = None;
+                }
+                info!(
+                    "Stopped polling snapshots of camera {}, nobody is watching",
+                    camera.id
+                );
+                break;
+            }
+        }
+    }
+
+    fn retry_due(&self, camera_id: &str) -> bool {
+        let mut streams = self.lock_streams();
+        let Some(streams) = streams.get_mut(camera_id) else {
+            return false;
+        };
+        match streams.retry_stream_at {
+            Some(retry_at) if Instant::now() >= retry_at => {
+                streams.retry_stream_at = Some(Instant::now() + self.retry_interval);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Whether the camera's stream answers again; the response is dropped unread
+    async fn stream_is_back(&self, stream_url: &Url) -> bool {
+        self.client
+            .get(stream_url.clone())
+            .timeout(SNAPSHOT_TIMEOUT)
+            .send()
+            .await
+            .is_ok_and(|response| response.status().is_success())
+    }
+
+    async fn snapshot(&self, snapshot_url: &Url) -> OurResult<Bytes> {
+        let response = self
+            .client
+            .get(snapshot_url.clone())
+            .timeout(SNAPSHOT_TIMEOUT)
+            .send()
+            .await
+            .map_err(|e| OurError::App(format!("Failed to request snapshot: {e}")))?;
+        if !response.status().is_success() {
+            let status = response.status();
+            return Err(OurError::App(format!(
+                "Snapshot request failed with status: {status}"
+            )));
+        }
+        Ok(response.bytes().await?)
+    }
+}
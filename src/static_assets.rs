@@ -0,0 +1,409 @@
+//! The CSS, JavaScript and icons under `/static`.
+//!
+//! Every file in `shell_sorter/static` is compiled into the binary, so a copied
+//! binary serves the web pages on its own. When the directory exists next to
+//! the working directory it is served instead, so frontend changes show up on
+//! reload without a rebuild; the `static_assets` setting can force either one.
+//!
+//! Browsers also ask for a few icons and the web app manifest at the root, so
+//! those are routed from the same files, with a manifest built from the
+//! machine name.
+
+use std::borrow::Cow;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use axum::extract::Path as UrlPath;
+use axum::http::header::{CACHE_CONTROL, CONTENT_TYPE};
+use axum::http::{HeaderMap, HeaderValue, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::routing::get;
+use axum::{Json, Router};
+use rust_embed::RustEmbed;
+use serde::{Deserialize, Serialize};
+use tower_http::services::ServeDir;
+
+use crate::etag::{if_none_match, not_modified, with_etag};
+use crate::{OurError, OurResult};
+
+/// Where the static files live in the source tree, relative to the working directory when served from disk
+pub const STATIC_DIRECTORY: &str = "shell_sorter/static";
+
+#[derive(RustEmbed)]
+#[folder = "shell_sorter/static/"]
+struct EmbeddedAssets;
+
+/// Which copy of the static files is served
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StaticAssetSource {
+    /// The directory if it exists, otherwise the embedded copy
+    #[default]
+    Auto,
+    /// Always the directory on disk
+    Directory,
+    /// Always the copy compiled into the binary
+    Embedded,
+}
+
+impl StaticAssetSource {
+    pub const NAMES: [&str; 3] = ["auto", "directory", "embedded"];
+
+    /// Settle `Auto` to whichever source will actually be used
+    pub fn resolve(self, directory: &Path) -> Self {
+        match self {
+            StaticAssetSource::Auto if directory.is_dir() => StaticAssetSource::Directory,
+            StaticAssetSource::Auto => StaticAssetSource::Embedded,
+            forced => forced,
+        }
+    }
+}
+
+impl FromStr for StaticAssetSource {
+    type Err = OurError;
+
+    fn from_str(s: &str) -> OurResult<Self> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "auto" => Ok(StaticAssetSource::Auto),
+            "directory" => Ok(StaticAssetSource::Directory),
+            "embedded" => Ok(StaticAssetSource::Embedded),
+            _ => Err(OurError::Config(format!(
+                "Invalid static asset source {s:?}, expected one of: {}",
+                Self::NAMES.join(", ")
+            ))),
+        }
+    }
+}
+
+impl fmt::Display for StaticAssetSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            StaticAssetSource::Auto => "auto",
+            StaticAssetSource::Directory => "directory",
+            StaticAssetSource::Embedded => "embedded",
+        };
+        f.write_str(name)
+    }
+}
+
+/// Routes serving `/static` from `directory` or the embedded copy, as `source` decides
+pub fn static_router<S>(source: StaticAssetSource, directory: &Path) -> Router<S>
+where
+    S: Clone + Send + Sync + 'static,
+{
+    match source.resolve(directory) {
+        StaticAssetSource::Directory => {
+            Router::new().nest_service("/static", ServeDir::new(directory))
+        }
+        _ => Router::new().route("/static/{*path}", get(embedded_asset)),
+    }
+}
+
+/// Cache lifetime of the icons and manifest served at the root, a week
+pub const ROOT_ASSET_CACHE_CONTROL: &str = "public, max-age=604800";
+/// Path of the generated web app manifest
+pub const MANIFEST_PATH: &str = "/site.webmanifest";
+/// Icons browsers ask for at the root, with the static file served for each
+pub const ROOT_ICONS: [(&str, &str); 3] = [
+    ("/favicon.ico", "favicon-32.png"),
+    ("/favicon.svg", "favicon.svg"),
+    ("/apple-touch-icon.png", "apple-touch-icon.png"),
+];
+/// Paths browsers and crawlers try on their own, which aren't worth a warning when missing
+const BROWSER_PROBES: [&str; 4] = [
+    "/robots.txt",
+    "/apple-touch-icon-precomposed.png",
+    "/browserconfig.xml",
+    "/manifest.json",
+];
+/// The dashboard header's colour, for the browser's own chrome
+const THEME_COLOUR: &str = "#667eea";
+/// The page background, shown while a pinned dashboard starts
+const BACKGROUND_COLOUR: &str = "#f5f5f5";
+
+/// Whether a missing path is one browsers request unprompted
+pub fn is_browser_probe(path: &str) -> bool {
+    path == MANIFEST_PATH
+        || path.starts_with("/.well-known/")
+        || ROOT_ICONS.iter().any(|(icon, _)| *icon == path)
+        || BROWSER_PROBES.contains(&path)
+}
+
+/// An icon listed in the manifest
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ManifestIcon {
+    pub src: &'static str,
+    pub sizes: &'static str,
+    #[serde(rename = "type")]
+    pub content_type: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub purpose: Option<&'static str>,
+}
+
+/// Web app manifest, so the dashboard can be pinned to a tablet's home screen and open full-screen
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub struct WebManifest {
+    pub name: String,
+    pub short_name: String,
+    pub start_url: &'static str,
+    pub display: &'static str,
+    pub background_color: &'static str,
+    pub theme_color: &'static str,
+    pub icons: Vec<ManifestIcon>,
+}
+
+impl WebManifest {
+    /// The manifest for a machine named `machine_name`
+    pub fn new(machine_name: &str) -> Self {
+        let icon = |purpose| ManifestIcon {
+            src: "/static/favicon.png",
+            sizes: "512x512",
+            content_type: "image/png",
+            purpose,
+        };
+        Self {
+            name: machine_name.to_string(),
+            short_name: "Shell Sorter".to_string(),
+            start_url: "/",
+            display: "standalone",
+            background_color: BACKGROUND_COLOUR,
+            theme_color: THEME_COLOUR,
+            icons: vec![
+                icon(None),
+                icon(Some("maskable")),
+                ManifestIcon {
+                    src: "/apple-touch-icon.png",
+                    sizes: "180x180",
+                    content_type: "image/png",
+                    purpose: None,
+                },
+            ],
+        }
+    }
+}
+
+/// Routes serving the root icons from the same copy as `/static`, and `manifest`
+pub fn root_asset_router<S>(
+    source: StaticAssetSource,
+    directory: &Path,
+    manifest: WebManifest,
+) -> Router<S>
+where
+    S: Clone + Send + Sync + 'static,
+{
+    let source = source.resolve(directory);
+    let mut router = Router::new().route(
+        MANIFEST_PATH,
+        get(move || {
+            let manifest = manifest.clone();
+            async move {
+                (
+                    [
+                        (CONTENT_TYPE, "application/manifest+json"),
+                        (CACHE_CONTROL, ROOT_ASSET_CACHE_CONTROL),
+                    ],
+                    Json(manifest),
+                )
+            }
+        }),
+    );
+    for (path, file) in ROOT_ICONS {
+        let directory = directory.to_path_buf();
+        router = router.route(
+            path,
+            get(move || root_icon(source, directory.clone(), file)),
+        );
+    }
+    router
+}
+
+/// A static file served at the root, cached for [`ROOT_ASSET_CACHE_CONTROL`]
+async fn root_icon(source: StaticAssetSource, directory: PathBuf, file: &'static str) -> Response {
+    let data: Option<Cow<'static, [u8]>> = match source {
+        StaticAssetSource::Directory => tokio::fs::read(directory.join(file))
+            .await
+            .ok()
+            .map(Cow::Owned),
+        _ => EmbeddedAssets::get(file).map(|file| file.data),
+    };
+    let Some(data) = data else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    let content_type = if file.ends_with(".svg") {
+        "image/svg+xml"
+    } else {
+        "image/png"
+    };
+    (
+        [
+            (CONTENT_TYPE, content_type),
+            (CACHE_CONTROL, ROOT_ASSET_CACHE_CONTROL),
+        ],
+        data,
+    )
+        .into_response()
+}
+
+/// One embedded file, with its content type and an ETag from its hash
+async fn embedded_asset(UrlPath(path): UrlPath<String>, headers: HeaderMap) -> Response {
+    let Some(file) = EmbeddedAssets::get(&path) else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    let hash: String = file
+        .metadata
+        .sha256_hash()
+        .iter()
+        .take(16)
+        .map(|byte| format!("{byte:02x}"))
+        .collect();
+    let etag = format!("\"{hash}\"");
+    if if_none_match(&headers, &etag) {
+        return not_modified(&etag);
+    }
+    let content_type = HeaderValue::from_str(file.metadata.mimetype())
+        .unwrap_or(HeaderValue::from_static("application/octet-stream"));
+    with_etag(&etag, ([(CONTENT_TYPE, content_type)], file.data))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::{Body, to_bytes};
+    use axum::http::Request;
+    use axum::http::header::{ETAG, IF_NONE_MATCH};
+    use tower::ServiceExt;
+
+    async fn get_static(router: Router, uri: &str, etag: Option<&str>) -> Response {
+        let mut request = Request::builder().uri(uri);
+        if let Some(etag) = etag {
+            request = request.header(IF_NONE_MATCH, etag);
+        }
+        router
+            .oneshot(request.body(Body::empty()).expect("request should build"))
+            .await
+            .expect("request should be handled")
+    }
+
+    #[tokio::test]
+    async fn test_embedded_assets_serve_without_the_directory() {
+        let temp_dir = tempfile::TempDir::new().expect("temp dir should be created");
+        let missing = temp_dir.path().join(STATIC_DIRECTORY);
+        assert_eq!(
+            StaticAssetSource::Auto.resolve(&missing),
+            StaticAssetSource::Embedded
+        );
+
+        for (uri, content_type) in [
+            ("/static/style.css", "text/css"),
+            ("/static/config.js", "text/javascript"),
+            ("/static/favicon.svg", "image/svg+xml"),
+            ("/static/favicon.png", "image/png"),
+        ] {
+            let router = static_router(StaticAssetSource::Auto, &missing);
+            let response = get_static(router, uri, None).await;
+            assert_eq!(response.status(), StatusCode::OK, "{uri}");
+            assert_eq!(response.headers()[CONTENT_TYPE], content_type, "{uri}");
+
+            let etag = response.headers()[ETAG]
+                .to_str()
+                .expect("ETag should be text")
+                .to_string();
+            let router = static_router(StaticAssetSource::Auto, &missing);
+            let response = get_static(router, uri, Some(&etag)).await;
+            assert_eq!(response.status(), StatusCode::NOT_MODIFIED, "{uri}");
+        }
+
+        let router = static_router(StaticAssetSource::Embedded, &missing);
+        let response = get_static(router, "/static/missing.css", None).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_root_icons_and_manifest_are_served() {
+        let temp_dir = tempfile::TempDir::new().expect("temp dir should be created");
+        std::fs::write(temp_dir.path().join("favicon.svg"), "<svg/>")
+            .expect("file should be written");
+        let missing = temp_dir.path().join("missing");
+        for directory in [temp_dir.path(), missing.as_path()] {
+            for (uri, content_type) in [
+                ("/favicon.ico", "image/png"),
+                ("/favicon.svg", "image/svg+xml"),
+                ("/apple-touch-icon.png", "image/png"),
+                ("/site.webmanifest", "application/manifest+json"),
+            ] {
+                let router = root_asset_router(
+                    StaticAssetSource::Auto,
+                    directory,
+                    WebManifest::new("Bench sorter"),
+                );
+                let response = get_static(router, uri, None).await;
+                // The directory only has the SVG, and the embedded copy is ignored while it exists
+                let expected = if directory == temp_dir.path() && content_type == "image/png" {
+                    StatusCode::NOT_FOUND
+                } else {
+                    StatusCode::OK
+                };
+                assert_eq!(response.status(), expected, "{uri}");
+                if expected == StatusCode::OK {
+                    assert_eq!(response.headers()[CONTENT_TYPE], content_type, "{uri}");
+                    assert_eq!(
+                        response.headers()[CACHE_CONTROL],
+                        ROOT_ASSET_CACHE_CONTROL,
+                        "{uri}"
+                    );
+                }
+            }
+        }
+
+        let router = root_asset_router(
+            StaticAssetSource::Embedded,
+            temp_dir.path(),
+            WebManifest::new("Bench sorter"),
+        );
+        let response = get_static(router, MANIFEST_PATH, None).await;
+        let body = to_bytes(response.into_body(), usize::MAX)
+            .await
+            .expect("body should be read");
+        let manifest: serde_json::Value =
+            serde_json::from_slice(&body).expect("manifest should be JSON");
+        assert_eq!(manifest["name"], "Bench sorter");
+        assert_eq!(manifest["display"], "standalone");
+        assert_eq!(manifest["start_url"], "/");
+        assert_eq!(manifest["icons"][1]["purpose"], "maskable");
+
+        assert!(is_browser_probe("/favicon.ico"));
+        assert!(is_browser_probe(
+            "/.well-known/appspecific/com.chrome.devtools.json"
+        ));
+        assert!(!is_browser_probe("/api/nothing"));
+    }
+
+    #[tokio::test]
+    async fn test_directory_wins_when_present() {
+        let temp_dir = tempfile::TempDir::new().expect("temp dir should be created");
+        std::fs::write(temp_dir.path().join("style.css"), "body { color: red; }")
+            .expect("file should be written");
+        assert_eq!(
+            StaticAssetSource::Auto.resolve(temp_dir.path()),
+            StaticAssetSource::Directory
+        );
+
+        let router = static_router(StaticAssetSource::Auto, temp_dir.path());
+        let response = get_static(router, "/static/style.css", None).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = to_bytes(response.into_body(), usize::MAX)
+            .await
+            .expect("body should be read");
+        assert_eq!(&body[..], b"body { color: red; }");
+
+        // Forcing the embedded copy ignores the directory
+        let router = static_router(StaticAssetSource::Embedded, temp_dir.path());
+        let response = get_static(router, "/static/style.css", None).await;
+        let body = to_bytes(response.into_body(), usize::MAX)
+            .await
+            .expect("body should be read");
+        assert_ne!(&body[..], b"body { color: red; }");
+    }
+}
@@ -0,0 +1,245 @@
+//! Per-camera live stream health.
+//!
+//! Both camera managers keep a [`StreamHealth`] per camera inside their status
+//! structures, next to the capture counters. A stream that silently stops
+//! producing frames (an ESP32 that rebooted, a saturated USB bus) otherwise
+//! leaves the dashboard showing its last frame with nothing to say it is stale.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, Instant};
+
+/// How long a watched stream may go without a frame before it counts as stalled
+pub const DEFAULT_STALL_THRESHOLD: Duration = Duration::from_secs(5);
+
+/// Frames older than this don't count towards the measured frame rate
+const FPS_WINDOW: Duration = Duration::from_secs(5);
+
+/// Sent when a stream that should be producing frames has stopped
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub struct StreamStalled {
+    pub camera_id: String,
+}
+
+/// Live stream health for a single camera
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct StreamHealth {
+    /// When the last frame was produced
+    pub last_frame: Option<DateTime<Utc>>,
+    /// Frames per second over the last few seconds
+    pub fps: f64,
+    /// Frame attempts that failed since the last good frame
+    pub consecutive_failures: u32,
+    /// No frame has been produced for the stall threshold while a stream was open
+    pub stalled: bool,
+    #[serde(skip)]
+    recent_frames: VecDeque<Instant>,
+    /// When the stream started being watched, for streams that never produce a frame
+    #[serde(skip)]
+    watched_since: Option<Instant>,
+    /// The stall has been reported for the current watch
+    #[serde(skip)]
+    stall_reported: bool,
+}
+
+impl StreamHealth {
+    /// Record `count` frames produced at `now`
+    pub fn record_frames(&mut self, count: usize, now: Instant) {
+        if count == 0 {
+            return;
+        }
+        self.recent_frames.extend(std::iter::repeat_n(now, count));
+        self.last_frame = Some(Utc::now());
+        self.consecutive_failures = 0;
+        self.stalled = false;
+        self.stall_reported = false;
+        self.update_fps(now);
+    }
+
+    /// Record a failed attempt to produce a frame
+    pub fn record_failure(&mut self) {
+        self.consecutive_failures = self.consecutive_failures.saturating_add(1);
+    }
+
+    /// Start watching for stalls, unless already watching
+    pub fn watch(&mut self, now: Instant) {
+        self.watched_since.get_or_insert(now);
+    }
+
+    /// Stop watching once nobody has the stream open; a stall stays visible until frames arrive again
+    pub fn unwatch(&mut self) {
+        self.watched_since = None;
+        self.stall_reported = false;
+    }
+
+    /// Refresh the frame rate and check for a stall, returning true when it hasn't been reported yet
+    pub fn check_stall(&mut self, now: Instant, threshold: Duration) -> bool {
+        self.update_fps(now);
+        let Some(watched_since) = self.watched_since else {
+            return false;
+        };
+        let last_activity = self
+            .recent_frames
+            .back()
+            .copied()
+            .map_or(watched_since, |frame| frame.max(watched_since));
+        // Only a frame clears a stall, so a reconnecting stream doesn't look healthy
+        if now.saturating_duration_since(last_activity) < threshold || self.stall_reported {
+            return false;
+        }
+        self.stalled = true;
+        self.stall_reported = true;
+        true
+    }
+
+    fn update_fps(&mut self, now: Instant) {
+        // Keep the newest frame even when it's old, so stall checks can see it
+        while self.recent_frames.len() > 1
+            && self
+                .recent_frames
+                .front()
+                .is_some_and(|frame| now.saturating_duration_since(*frame) > FPS_WINDOW)
+        {
+            self.recent_frames.pop_front();
+        }
+        self.fps = match (self.recent_frames.front(), self.recent_frames.back()) {
+            (Some(first), Some(last)) if now.saturating_duration_since(*last) <= FPS_WINDOW => {
+                let span = last.saturating_duration_since(*first).as_secs_f64();
+                if span > 0.0 {
+                    (self.recent_frames.len() - 1) as f64 / span
+                } else {
+                    0.0
+                }
+            }
+            _ => 0.0,
+        };
+    }
+}
+
+/// Watch the cameras with open streams, stop watching the rest, and return the streams that just stalled
+pub fn check_streams(
+    health: &mut HashMap<String, StreamHealth>,
+    open_streams: &[String],
+    now: Instant,
+    threshold: Duration,
+) -> Vec<StreamStalled> {
+    for camera_id in open_streams {
+        health.entry(camera_id.clone()).or_default().watch(now);
+    }
+    let mut stalled = Vec::new();
+    for (camera_id, camera_health) in health.iter_mut() {
+        if !open_streams.contains(camera_id) {
+            camera_health.unwatch();
+        } else if camera_health.check_stall(now, threshold) {
+            stalled.push(StreamStalled {
+                camera_id: camera_id.clone(),
+            });
+        }
+    }
+    stalled
+}
+
+/// Counts multipart boundaries in an MJPEG byte stream, one per frame
+pub struct FrameCounter {
+    delimiter: Vec<u8>,
+    tail: Vec<u8>,
+}
+
+impl FrameCounter {
+    /// Counter for the boundary named in a `multipart/x-mixed-replace` content type
+    pub fn from_content_type(content_type: &str) -> Option<Self> {
+        let boundary = content_type
+            .split(';')
+            .filter_map(|part| part.trim().strip_prefix("boundary="))
+            .map(|boundary| boundary.trim_matches('"'))
+            .find(|boundary| !boundary.is_empty())?;
+        let boundary = boundary.strip_prefix("--").unwrap_or(boundary);
+        Some(Self {
+            delimiter: format!("--{boundary}").into_bytes(),
+            tail: Vec::new(),
+        })
+    }
+
+    /// Number of boundaries completed by this chunk, including ones split across chunks
+    pub fn feed(&mut self, chunk: &[u8]) -> usize {
+        let mut window = std::mem::take(&mut self.tail);
+        window.extend_from_slice(chunk);
+        let count = window
+            .windows(self.delimiter.len())
+            .filter(|candidate| *candidate == self.delimiter.as_slice())
+            .count();
+        let keep = (self.delimiter.len() - 1).min(window.len());
+        self.tail = window.split_off(window.len() - keep);
+        count
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stall_detection_and_fps() {
+        let start = Instant::now();
+        let threshold = Duration::from_secs(5);
+        let mut health = StreamHealth::default();
+
+        // Unwatched streams never stall
+        assert!(!health.check_stall(start + Duration::from_secs(60), threshold));
+
+        health.watch(start);
+        for i in 0..10 {
+            health.record_frames(1, start + Duration::from_millis(200 * i));
+        }
+        assert!((health.fps - 5.0).abs() < 0.01, "{}", health.fps);
+        assert!(health.last_frame.is_some());
+
+        health.record_failure();
+        health.record_failure();
+        assert_eq!(health.consecutive_failures, 2);
+
+        // The frames stop: stalled once, reported once, and the rate decays
+        let last = start + Duration::from_millis(1800);
+        assert!(!health.check_stall(last + Duration::from_secs(4), threshold));
+        assert!(health.check_stall(last + threshold, threshold));
+        assert!(health.stalled);
+        assert!(!health.check_stall(last + Duration::from_secs(20), threshold));
+        assert_eq!(health.fps, 0.0);
+
+        // A frame clears the stall and the failure count
+        health.record_frames(1, last + Duration::from_secs(21));
+        assert!(!health.stalled);
+        assert_eq!(health.consecutive_failures, 0);
+
+        // A watched stream that never produced a frame stalls too
+        let mut silent = StreamHealth::default();
+        silent.watch(start);
+        assert!(silent.check_stall(start + threshold, threshold));
+
+        // Closing the stream keeps the stall visible, and a reconnect that stalls is reported again
+        silent.unwatch();
+        assert!(silent.stalled);
+        let reconnected = start + Duration::from_secs(30);
+        silent.watch(reconnected);
+        assert!(!silent.check_stall(reconnected + Duration::from_secs(1), threshold));
+        assert!(silent.stalled);
+        assert!(silent.check_stall(reconnected + threshold, threshold));
+    }
+
+    #[test]
+    fn test_frame_counter_handles_split_boundaries() {
+        let mut counter =
+            FrameCounter::from_content_type("multipart/x-mixed-replace;boundary=123456789000")
+                .expect("content type has a boundary");
+        assert_eq!(
+            counter.feed(b"--123456789000\r\nContent-Type: image/jpeg\r\n"),
+            1
+        );
+        assert_eq!(counter.feed(b"\xff\xd8jpeg\xff\xd9\r\n--1234"), 0);
+        assert_eq!(counter.feed(b"56789000\r\n--123456789000"), 2);
+        assert!(FrameCounter::from_content_type("image/jpeg").is_none());
+    }
+}
@@ -0,0 +1,95 @@
+//! Limits on concurrent live camera streams.
+//!
+//! Every open stream holds a [`StreamGuard`] inside its response body, so the
+//! count drops as soon as the client disconnects and the body is dropped.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use crate::etag::DataVersion;
+
+/// Tracks active streams per camera and enforces a per-camera limit
+#[derive(Clone)]
+pub struct StreamLimiter {
+    max_per_camera: usize,
+    active: Arc<Mutex<HashMap<String, usize>>>,
+    version: DataVersion,
+}
+
+impl StreamLimiter {
+    /// Create a limiter allowing `max_per_camera` concurrent streams of each camera
+    pub fn new(max_per_camera: usize) -> Self {
+        Self {
+            max_per_camera,
+            active: Arc::new(Mutex::new(HashMap::new())),
+            version: DataVersion::default(),
+        }
+    }
+
+    /// The configured per-camera limit
+    pub fn max_per_camera(&self) -> usize {
+        self.max_per_camera
+    }
+
+    // A poisoned lock only means another stream panicked mid-update; the counts are still usable
+    fn lock_active(&self) -> MutexGuard<'_, HashMap<String, usize>> {
+        self.active
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Reserve a stream slot for a camera, or `None` if it is at the limit
+    pub fn try_acquire(&self, camera_id: &str) -> Option<StreamGuard> {
+        let mut active = self.lock_active();
+        let count = active.entry(camera_id.to_string()).or_insert(0);
+        if *count >= self.max_per_camera {
+            return None;
+        }
+        *count += 1;
+        self.version.bump();
+
+        Some(StreamGuard {
+            limiter: self.clone(),
+            camera_id: camera_id.to_string(),
+        })
+    }
+
+    /// Number of open streams for a camera
+    pub fn active_for(&self, camera_id: &str) -> usize {
+        self.lock_active().get(camera_id).copied().unwrap_or(0)
+    }
+
+    /// Cameras with at least one open stream
+    pub fn active_cameras(&self) -> Vec<String> {
+        self.lock_active().keys().cloned().collect()
+    }
+
+    /// Bumped whenever a stream opens or closes
+    pub fn version(&self) -> u64 {
+        self.version.get()
+    }
+
+    /// Number of open streams across all cameras
+    pub fn total_active(&self) -> usize {
+        self.lock_active().values().sum()
+    }
+}
+
+/// Releases a stream slot when dropped
+pub struct StreamGuard {
+    limiter: StreamLimiter,
+    camera_id: String,
+}
+
+impl Drop for StreamGuard {
+    fn drop(&mut self) {
+        let mut active = self.limiter.lock_active();
+        if let Some(count) = active.get_mut(&self.camera_id) {
+            *count = count.saturating_sub(1);
+            self.limiter.version.bump();
+            if *count == 0 {
+                active.remove(&self.camera_id);
+            }
+        }
+    }
+}
@@ -0,0 +1,576 @@
+//! Tagging a session from a terminal, for `shell-sorter data tag`.
+//!
+//! The prompts run against a [`Prompter`], so the same flow is driven by a
+//! terminal over SSH or by scripted input in tests. Answers are checked with
+//! the rules the server applies to a shell update, so a typo is asked again
+//! rather than failing the save. Brands complete from the ones already in use
+//! and case types from the server's designations: a unique prefix is filled in
+//! and an ambiguous one lists what it could be.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io::{BufRead, Write};
+
+use serde::Serialize;
+
+use crate::api::ApiError;
+use crate::case_designation::CaseDesignations;
+use crate::frame_region::FrameSize;
+use crate::shell_data::{Shell, notes_error};
+use crate::{OurError, OurResult};
+
+/// Where the tagging prompts are shown and answered
+pub trait Prompter {
+    /// Show a line to whoever is tagging
+    fn say(&mut self, line: &str);
+    /// Ask for a line of input, or `None` once input has ended
+    fn ask(&mut self, prompt: &str) -> OurResult<Option<String>>;
+}
+
+/// Prompts on stdin and stdout
+#[derive(Debug, Default)]
+pub struct TerminalPrompter;
+
+impl Prompter for TerminalPrompter {
+    fn say(&mut self, line: &str) {
+        println!("{line}");
+    }
+
+    fn ask(&mut self, prompt: &str) -> OurResult<Option<String>> {
+        print!("{prompt}");
+        std::io::stdout().flush()?;
+        let mut line = String::new();
+        if std::io::stdin().lock().read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        Ok(Some(line.trim_end_matches(['\r', '\n']).to_string()))
+    }
+}
+
+/// Tags given on the command line, which skip their prompts
+#[derive(Debug, Clone, Default)]
+pub struct PresetTags {
+    pub brand: Option<String>,
+    pub shell_type: Option<String>,
+    pub include: Option<bool>,
+    pub notes: Option<String>,
+}
+
+impl PresetTags {
+    /// Brand, type and include were all given, so there is nothing to ask
+    pub fn is_complete(&self) -> bool {
+        self.brand.is_some() && self.shell_type.is_some() && self.include.is_some()
+    }
+}
+
+/// Tags to save on a shell, sent as a shell update
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub struct ShellTags {
+    pub brand: String,
+    pub shell_type: String,
+    pub include: bool,
+    /// Empty clears the shell's notes
+    pub notes: String,
+}
+
+impl ShellTags {
+    /// The tags as the server will store them, or an error naming every field it would refuse
+    pub fn checked(mut self, designations: &CaseDesignations) -> OurResult<Self> {
+        let mut errors = BTreeMap::new();
+        self.brand = self.brand.trim().to_string();
+        if self.brand.is_empty() {
+            errors.insert("brand".to_string(), "must not be empty".to_string());
+        }
+        match resolve_shell_type(designations, &self.shell_type) {
+            Ok(shell_type) => self.shell_type = shell_type.to_string(),
+            Err(error) => {
+                errors.insert("shell_type".to_string(), error);
+            }
+        }
+        self.notes = self.notes.trim().to_string();
+        if let Some(error) = notes_error(&self.notes) {
+            errors.insert("notes".to_string(), error);
+        }
+        if errors.is_empty() {
+            Ok(self)
+        } else {
+            Err(OurError::App(
+                ApiError::validation("Invalid tags", errors).message,
+            ))
+        }
+    }
+}
+
+impl fmt::Display for ShellTags {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let training = if self.include {
+            "included in training"
+        } else {
+            "left out of training"
+        };
+        write!(f, "{} {}, {training}", self.brand, self.shell_type)?;
+        if !self.notes.is_empty() {
+            write!(f, ", notes: {}", self.notes)?;
+        }
+        Ok(())
+    }
+}
+
+/// What a partly typed answer could be
+#[derive(Debug, PartialEq, Eq)]
+pub enum Completion<'a> {
+    /// Typed in full, or the only known value starting with the input
+    Unique(&'a str),
+    /// Several known values start with the input
+    Ambiguous(Vec<&'a str>),
+    /// Nothing known starts with the input
+    Unknown,
+}
+
+/// Complete `input` against `known`, ignoring case
+pub fn complete<'a>(input: &str, known: &'a [String]) -> Completion<'a> {
+    let input = input.trim().to_lowercase();
+    if let Some(exact) = known.iter().find(|value| value.to_lowercase() == input) {
+        return Completion::Unique(exact);
+    }
+    let matches: Vec<&str> = known
+        .iter()
+        .filter(|value| value.to_lowercase().starts_with(&input))
+        .map(String::as_str)
+        .collect();
+    match matches.as_slice() {
+        [] => Completion::Unknown,
+        [only] => Completion::Unique(only),
+        _ => Completion::Ambiguous(matches),
+    }
+}
+
+/// A session image as listed before tagging
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SessionImage {
+    pub filename: String,
+    /// Camera that captured it, when the shell recorded one
+    pub camera: Option<String>,
+    /// Its dimensions, once the image has been fetched
+    pub size: Option<FrameSize>,
+}
+
+impl SessionImage {
+    /// Every image on a shell, with the camera that took each one
+    pub fn of_shell(shell: &Shell) -> Vec<Self> {
+        let captured = shell.captured_images.as_deref().unwrap_or_default();
+        let mut images: Vec<Self> = shell
+            .image_filenames
+            .iter()
+            .map(|filename| Self {
+                filename: filename.clone(),
+                camera: captured
+                    .iter()
+                    .find(|image| &image.filename == filename)
+                    .map(|image| image.camera_name.clone()),
+                size: None,
+            })
+            .collect();
+        for image in captured {
+            if !images.iter().any(|known| known.filename == image.filename) {
+                images.push(Self {
+                    filename: image.filename.clone(),
+                    camera: Some(image.camera_name.clone()),
+                    size: None,
+                });
+            }
+        }
+        images
+    }
+}
+
+impl fmt::Display for SessionImage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}  ", self.filename)?;
+        match self.size {
+            Some(size) => write!(f, "{}x{}", size.width, size.height)?,
+            None => write!(f, "size unknown")?,
+        }
+        write!(
+            f,
+            "  {}",
+            self.camera.as_deref().unwrap_or("camera unknown")
+        )
+    }
+}
+
+/// Ask for every tag that wasn't preset, offering the shell's current tags as defaults
+///
+/// When brand, type and include are all preset nothing is asked, and the notes
+/// are kept unless they were preset too.
+pub fn prompt_tags(
+    prompter: &mut dyn Prompter,
+    shell: &Shell,
+    brands: &[String],
+    designations: &CaseDesignations,
+    preset: PresetTags,
+) -> OurResult<ShellTags> {
+    let scripted = preset.is_complete();
+    let current_notes = shell.notes.as_deref().unwrap_or_default();
+    let brand = match preset.brand {
+        Some(brand) => brand,
+        None => ask_brand(prompter, &shell.brand, brands)?,
+    };
+    let shell_type = match preset.shell_type {
+        Some(shell_type) => shell_type,
+        None => ask_shell_type(prompter, &shell.shell_type, designations)?,
+    };
+    let include = match preset.include {
+        Some(include) => include,
+        None => confirm(prompter, "Include in training?", shell.include)?,
+    };
+    let notes = match preset.notes {
+        Some(notes) => notes,
+        None if scripted => current_notes.to_string(),
+        None => ask_notes(prompter, current_notes)?,
+    };
+    Ok(ShellTags {
+        brand,
+        shell_type,
+        include,
+        notes,
+    })
+}
+
+/// Ask a yes or no question, where an empty answer takes `default`
+pub fn confirm(prompter: &mut dyn Prompter, question: &str, default: bool) -> OurResult<bool> {
+    let choices = if default { "[Y/n]" } else { "[y/N]" };
+    loop {
+        let answer = ask(prompter, &format!("{question} {choices}: "))?;
+        match answer.trim().to_lowercase().as_str() {
+            "" => return Ok(default),
+            "y" | "yes" => return Ok(true),
+            "n" | "no" => return Ok(false),
+            _ => prompter.say("  Answer y or n"),
+        }
+    }
+}
+
+/// Ask for a line, treating the end of input as giving up
+fn ask(prompter: &mut dyn Prompter, prompt: &str) -> OurResult<String> {
+    prompter.ask(prompt)?.ok_or_else(|| {
+        OurError::App("Tagging cancelled: input ended before every tag was given".to_string())
+    })
+}
+
+/// `label` with the value an empty answer keeps
+fn with_default(label: &str, current: &str) -> String {
+    match current.trim() {
+        "" => format!("{label}: "),
+        current => format!("{label} [{current}]: "),
+    }
+}
+
+fn ask_brand(prompter: &mut dyn Prompter, current: &str, brands: &[String]) -> OurResult<String> {
+    loop {
+        let answer = ask(prompter, &with_default("Brand", current))?;
+        let answer = answer.trim();
+        if answer.is_empty() {
+            if !current.trim().is_empty() {
+                return Ok(current.trim().to_string());
+            }
+            prompter.say("  brand: must not be empty");
+            continue;
+        }
+        match complete(answer, brands) {
+            Completion::Unique(brand) => {
+                if brand != answer {
+                    prompter.say(&format!("  Brand: {brand}"));
+                }
+                return Ok(brand.to_string());
+            }
+            Completion::Ambiguous(matches) => {
+                prompter.say(&format!("  Could be: {}", matches.join(", ")));
+            }
+            Completion::Unknown => {
+                prompter.say(&format!("  New brand: {answer}"));
+                return Ok(answer.to_string());
+            }
+        }
+    }
+}
+
+fn ask_shell_type(
+    prompter: &mut dyn Prompter,
+    current: &str,
+    designations: &CaseDesignations,
+) -> OurResult<String> {
+    let known: Vec<String> = designations
+        .designations()
+        .iter()
+        .map(|known| known.designation.clone())
+        .collect();
+    prompter.say(&format!("Case types: {}", known.join(", ")));
+    let current = designations.normalize(current).unwrap_or_default();
+    loop {
+        let answer = ask(prompter, &with_default("Type", current))?;
+        let answer = answer.trim();
+        if answer.is_empty() && !current.is_empty() {
+            return Ok(current.to_string());
+        }
+        if let Some(shell_type) = designations.normalize(answer) {
+            return Ok(shell_type.to_string());
+        }
+        match complete(answer, &known) {
+            Completion::Unique(shell_type) if !answer.is_empty() => {
+                prompter.say(&format!("  Type: {shell_type}"));
+                return Ok(shell_type.to_string());
+            }
+            Completion::Ambiguous(matches) if !answer.is_empty() => {
+                prompter.say(&format!("  Could be: {}", matches.join(", ")));
+            }
+            _ => {
+                if let Err(error) = resolve_shell_type(designations, answer) {
+                    prompter.say(&format!("  shell_type: {error}"));
+                }
+            }
+        }
+    }
+}
+
+fn ask_notes(prompter: &mut dyn Prompter, current: &str) -> OurResult<String> {
+    let label = if current.trim().is_empty() {
+        "Notes (optional)"
+    } else {
+        "Notes (- to clear)"
+    };
+    loop {
+        let answer = ask(prompter, &with_default(label, current))?;
+        let notes = match answer.trim() {
+            "" => current.trim(),
+            "-" => "",
+            notes => notes,
+        };
+        match notes_error(notes) {
+            Some(error) => prompter.say(&format!("  notes: {error}")),
+            None => return Ok(notes.to_string()),
+        }
+    }
+}
+
+/// The canonical case type for `value`, refused the way the server refuses it
+fn resolve_shell_type<'a>(
+    designations: &'a CaseDesignations,
+    value: &str,
+) -> Result<&'a str, String> {
+    if value.trim().is_empty() {
+        return Err("must not be empty".to_string());
+    }
+    designations.normalize(value).ok_or_else(|| {
+        let known: Vec<&str> = designations
+            .designations()
+            .iter()
+            .map(|known| known.designation.as_str())
+            .collect();
+        format!(
+            "unknown case type {value:?}, expected one of {}",
+            known.join(", ")
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::VecDeque;
+
+    /// Answers prompts from a script, recording everything shown
+    #[derive(Default)]
+    struct ScriptedPrompter {
+        answers: VecDeque<String>,
+        prompts: Vec<String>,
+        said: Vec<String>,
+    }
+
+    impl ScriptedPrompter {
+        fn new(answers: &[&str]) -> Self {
+            Self {
+                answers: answers.iter().map(|answer| answer.to_string()).collect(),
+                ..Self::default()
+            }
+        }
+    }
+
+    impl Prompter for ScriptedPrompter {
+        fn say(&mut self, line: &str) {
+            self.said.push(line.to_string());
+        }
+
+        fn ask(&mut self, prompt: &str) -> OurResult<Option<String>> {
+            self.prompts.push(prompt.to_string());
+            Ok(self.answers.pop_front())
+        }
+    }
+
+    fn designations() -> CaseDesignations {
+        let case_types = ["9mm", "40sw", "45acp", "38special", "357mag"].map(String::from);
+        CaseDesignations::new(&case_types)
+    }
+
+    fn brands() -> Vec<String> {
+        ["Federal", "Fiocchi", "Winchester"]
+            .map(String::from)
+            .to_vec()
+    }
+
+    #[test]
+    fn test_prompts_complete_and_ask_again_until_valid() {
+        let mut shell = Shell::new(String::new(), "unknown".to_string());
+        shell.notes = Some("dented".to_string());
+        let long_notes = "x".repeat(2001);
+        let mut prompter = ScriptedPrompter::new(&[
+            "",    // brand has no default
+            "f",   // Federal or Fiocchi
+            "fio", // Fiocchi
+            "4",   // 40sw or 45acp
+            "10mm",
+            "9x19",
+            "maybe",
+            "n",
+            &long_notes,
+            "-",
+        ]);
+
+        let tags = prompt_tags(
+            &mut prompter,
+            &shell,
+            &brands(),
+            &designations(),
+            PresetTags::default(),
+        )
+        .expect("tags should be given");
+        assert_eq!(
+            tags,
+            ShellTags {
+                brand: "Fiocchi".to_string(),
+                shell_type: "9mm".to_string(),
+                include: false,
+                notes: String::new(),
+            }
+        );
+        assert_eq!(
+            prompter.said,
+            [
+                "  brand: must not be empty",
+                "  Could be: Federal, Fiocchi",
+                "  Brand: Fiocchi",
+                "Case types: 9mm, 40sw, 45acp, 38special, 357mag",
+                "  Could be: 40sw, 45acp",
+                "  shell_type: unknown case type \"10mm\", expected one of 9mm, 40sw, 45acp, 38special, 357mag",
+                "  Answer y or n",
+                "  notes: must be at most 2000 characters",
+            ]
+        );
+        assert_eq!(prompter.prompts[0], "Brand: ");
+        assert_eq!(prompter.prompts[3], "Type: ");
+        assert_eq!(prompter.prompts[8], "Notes (- to clear) [dented]: ");
+    }
+
+    #[test]
+    fn test_defaults_and_presets_skip_prompts() {
+        let mut shell = Shell::new("Winchester".to_string(), "9MM".to_string());
+        shell.include = false;
+        // Enter keeps every current tag
+        let mut prompter = ScriptedPrompter::new(&["", "", "", ""]);
+        let tags = prompt_tags(
+            &mut prompter,
+            &shell,
+            &brands(),
+            &designations(),
+            PresetTags::default(),
+        )
+        .expect("tags should be given");
+        assert_eq!(tags.to_string(), "Winchester 9mm, left out of training");
+        assert_eq!(
+            prompter.prompts,
+            [
+                "Brand [Winchester]: ",
+                "Type [9mm]: ",
+                "Include in training? [y/N]: ",
+                "Notes (optional): "
+            ]
+        );
+
+        // Flags for every required tag mean nothing is asked
+        let preset = PresetTags {
+            brand: Some(" Remington ".to_string()),
+            shell_type: Some(".45 ACP".to_string()),
+            include: Some(true),
+            notes: None,
+        };
+        assert!(preset.is_complete());
+        shell.notes = Some("scuffed".to_string());
+        let mut prompter = ScriptedPrompter::new(&[]);
+        let tags = prompt_tags(&mut prompter, &shell, &[], &designations(), preset)
+            .expect("tags should be given")
+            .checked(&designations())
+            .expect("tags should be valid");
+        assert_eq!(
+            tags.to_string(),
+            "Remington 45acp, included in training, notes: scuffed"
+        );
+        assert!(prompter.prompts.is_empty());
+
+        // Running out of input cancels rather than saving half the tags
+        let mut prompter = ScriptedPrompter::new(&["Federal"]);
+        assert!(
+            prompt_tags(
+                &mut prompter,
+                &shell,
+                &brands(),
+                &designations(),
+                PresetTags::default()
+            )
+            .is_err()
+        );
+    }
+
+    #[test]
+    fn test_flags_are_checked_like_the_server() {
+        let tags = ShellTags {
+            brand: "  ".to_string(),
+            shell_type: "10mm".to_string(),
+            include: true,
+            notes: "x".repeat(2001),
+        };
+        let error = tags
+            .checked(&designations())
+            .expect_err("invalid tags should be refused");
+        assert_eq!(
+            error.to_string(),
+            "Application error: Invalid tags: brand: must not be empty; notes: must be at most 2000 characters; shell_type: unknown case type \"10mm\", expected one of 9mm, 40sw, 45acp, 38special, 357mag"
+        );
+    }
+
+    #[test]
+    fn test_session_images_name_their_camera() {
+        let mut shell = Shell::new("Federal".to_string(), "9mm".to_string());
+        shell.add_image("s_camera_0.jpg".to_string());
+        shell.add_image("s_camera_1.jpg".to_string());
+        shell.add_captured_image(crate::shell_data::CapturedImage::new(
+            1,
+            "s_camera_1.jpg".to_string(),
+            "Side".to_string(),
+            crate::config::ViewType::Side,
+        ));
+        let mut images = SessionImage::of_shell(&shell);
+        images[1].size = Some(FrameSize {
+            width: 1600,
+            height: 1200,
+        });
+        let lines: Vec<String> = images.iter().map(ToString::to_string).collect();
+        assert_eq!(
+            lines,
+            [
+                "s_camera_0.jpg  size unknown  camera unknown",
+                "s_camera_1.jpg  1600x1200  Side"
+            ]
+        );
+    }
+}
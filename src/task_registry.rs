@@ -0,0 +1,294 @@
+//! Tracking for long-lived background tasks.
+//!
+//! Components are started through [`TaskRegistry::spawn_tracked`] instead of a
+//! bare `tokio::spawn`, so `/api/health` can list what is running and shutdown
+//! can cancel everything and wait for it with a deadline. One-off work queued
+//! by a request goes through [`TaskRegistry::spawn_job`]; jobs are expected to
+//! finish, so they don't count against health, and only the most recent
+//! finished ones are kept.
+
+use std::future::Future;
+use std::panic::AssertUnwindSafe;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use futures_util::FutureExt;
+use serde::Serialize;
+use tokio::task::JoinHandle;
+use tokio_util::sync::CancellationToken;
+use tracing::{error, info, warn};
+
+/// How long shutdown waits for tasks before aborting them
+pub const DEFAULT_SHUTDOWN_DEADLINE: Duration = Duration::from_secs(5);
+
+/// Finished jobs kept for `/api/health`
+pub const MAX_FINISHED_JOBS: usize = 20;
+
+/// Whether a task should run for the life of the server
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TaskKind {
+    /// A component that should keep running until shutdown
+    Service,
+    /// One-off work that finishes on its own
+    Job,
+}
+
+/// Lifecycle of a tracked task
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TaskState {
+    Running,
+    Completed,
+    Panicked,
+    /// Still running at the shutdown deadline, so it was aborted
+    Aborted,
+}
+
+/// A tracked task as listed by `/api/health`
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub struct TaskInfo {
+    pub name: String,
+    pub kind: TaskKind,
+    pub state: TaskState,
+    pub started_at: DateTime<Utc>,
+    pub finished_at: Option<DateTime<Utc>>,
+}
+
+struct TrackedTask {
+    id: u64,
+    info: TaskInfo,
+    handle: Option<JoinHandle<()>>,
+}
+
+/// Named background tasks sharing one shutdown token
+#[derive(Clone, Default)]
+pub struct TaskRegistry {
+    shutdown: CancellationToken,
+    tasks: Arc<Mutex<Vec<TrackedTask>>>,
+    next_id: Arc<AtomicU64>,
+}
+
+impl TaskRegistry {
+    // A poisoned lock only means a task panicked while recording its state; the table is still usable
+    fn lock_tasks(&self) -> MutexGuard<'_, Vec<TrackedTask>> {
+        self.tasks
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Spawn a task that is simply dropped when shutdown starts
+    pub fn spawn_tracked<F>(&self, name: impl Into<String>, task: F)
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        self.spawn_tracked_with_token(name, |token| Self::until_cancelled(token, task));
+    }
+
+    /// Spawn a task that watches its cancellation token and finishes its own work on shutdown
+    pub fn spawn_tracked_with_token<F, Fut>(&self, name: impl Into<String>, task: F)
+    where
+        F: FnOnce(CancellationToken) -> Fut,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        let future = task(self.shutdown.child_token());
+        self.spawn_kind(TaskKind::Service, name.into(), future);
+    }
+
+    /// Spawn one-off work, dropped if it is still running when shutdown starts
+    pub fn spawn_job<F>(&self, name: impl Into<String>, task: F)
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        let future = Self::until_cancelled(self.shutdown.child_token(), task);
+        self.spawn_kind(TaskKind::Job, name.into(), future);
+    }
+
+    async fn until_cancelled<F: Future<Output = ()>>(token: CancellationToken, task: F) {
+        tokio::select! {
+            _ = token.cancelled() => {}
+            _ = task => {}
+        }
+    }
+
+    fn spawn_kind<F>(&self, kind: TaskKind, name: String, future: F)
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let mut tasks = self.lock_tasks();
+        let registry = self.clone();
+        let task_name = name.clone();
+        let handle = tokio::spawn(async move {
+            let state = match AssertUnwindSafe(future).catch_unwind().await {
+                Ok(()) => TaskState::Completed,
+                Err(_) => {
+                    error!("Background task {task_name} panicked");
+                    TaskState::Panicked
+                }
+            };
+            registry.finish(id, state);
+        });
+        tasks.push(TrackedTask {
+            id,
+            info: TaskInfo {
+                name,
+                kind,
+                state: TaskState::Running,
+                started_at: Utc::now(),
+                finished_at: None,
+            },
+            handle: Some(handle),
+        });
+    }
+
+    fn finish(&self, id: u64, state: TaskState) {
+        let mut tasks = self.lock_tasks();
+        if let Some(task) = tasks.iter_mut().find(|task| task.id == id)
+            && task.info.state == TaskState::Running
+        {
+            task.info.state = state;
+            task.info.finished_at = Some(Utc::now());
+        }
+
+        // Forget the oldest finished jobs; services are always kept
+        let finished_jobs = tasks
+            .iter()
+            .filter(|task| task.info.kind == TaskKind::Job && task.info.state != TaskState::Running)
+            .count();
+        let mut excess = finished_jobs.saturating_sub(MAX_FINISHED_JOBS);
+        tasks.retain(|task| {
+            let forget = excess > 0
+                && task.info.kind == TaskKind::Job
+                && task.info.state != TaskState::Running;
+            if forget {
+                excess -= 1;
+            }
+            !forget
+        });
+    }
+
+    /// Whether every service is still running; finished jobs don't count
+    pub fn healthy(&self) -> bool {
+        self.lock_tasks()
+            .iter()
+            .filter(|task| task.info.kind == TaskKind::Service)
+            .all(|task| task.info.state == TaskState::Running)
+    }
+
+    /// Every task spawned so far, in the order they were started
+    pub fn tasks(&self) -> Vec<TaskInfo> {
+        self.lock_tasks()
+            .iter()
+            .map(|task| task.info.clone())
+            .collect()
+    }
+
+    /// Token cancelled when shutdown starts
+    pub fn shutdown_token(&self) -> CancellationToken {
+        self.shutdown.clone()
+    }
+
+    /// Cancel every task and wait for them to finish, aborting any still running at the deadline
+    pub async fn shutdown(&self, deadline: Duration) -> Vec<TaskInfo> {
+        self.shutdown.cancel();
+        let handles: Vec<(u64, String, JoinHandle<()>)> = self
+            .lock_tasks()
+            .iter_mut()
+            .filter_map(|task| {
+                let handle = task.handle.take()?;
+                Some((task.id, task.info.name.clone(), handle))
+            })
+            .collect();
+
+        let deadline = tokio::time::Instant::now() + deadline;
+        for (id, name, mut handle) in handles {
+            if tokio::time::timeout_at(deadline, &mut handle)
+                .await
+                .is_err()
+            {
+                handle.abort();
+                warn!("Background task {name} didn't stop in time, aborting it");
+                self.finish(id, TaskState::Aborted);
+            }
+        }
+
+        let tasks = self.tasks();
+        info!("Stopped {} background tasks", tasks.len());
+        tasks
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_shutdown_cancels_and_awaits_tasks() {
+        let registry = TaskRegistry::default();
+        registry.spawn_tracked_with_token("listener", |token| async move {
+            token.cancelled().await;
+            // Some clean-up work after being asked to stop
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        });
+        registry.spawn_tracked("forever", std::future::pending());
+        registry.spawn_tracked("panics", async { panic!("task failure") });
+        registry.spawn_tracked_with_token("stubborn", |_token| async {
+            tokio::time::sleep(Duration::from_secs(60)).await;
+        });
+
+        tokio::task::yield_now().await;
+        let states = |tasks: Vec<TaskInfo>| {
+            tasks
+                .into_iter()
+                .map(|task| (task.name, task.state))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(registry.tasks()[0].state, TaskState::Running);
+
+        let started = std::time::Instant::now();
+        let tasks = registry.shutdown(Duration::from_millis(500)).await;
+        assert!(started.elapsed() < Duration::from_secs(2));
+        assert_eq!(
+            states(tasks),
+            vec![
+                ("listener".to_string(), TaskState::Completed),
+                ("forever".to_string(), TaskState::Completed),
+                ("panics".to_string(), TaskState::Panicked),
+                ("stubborn".to_string(), TaskState::Aborted),
+            ]
+        );
+        assert!(registry.tasks()[0].finished_at.is_some());
+    }
+
+    #[tokio::test]
+    async fn test_finished_jobs_are_healthy_and_pruned() {
+        let registry = TaskRegistry::default();
+        registry.spawn_tracked("service", std::future::pending());
+        for _ in 0..MAX_FINISHED_JOBS + 5 {
+            registry.spawn_job("job", async {});
+        }
+        registry.spawn_job("failing job", async { panic!("job failure") });
+        for _ in 0..100 {
+            if registry.tasks().len() <= MAX_FINISHED_JOBS + 1 {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+
+        let tasks = registry.tasks();
+        assert_eq!(tasks.len(), MAX_FINISHED_JOBS + 1);
+        assert_eq!(
+            (tasks[0].name.as_str(), tasks[0].kind),
+            ("service", TaskKind::Service)
+        );
+        assert!(tasks.iter().any(|task| task.state == TaskState::Panicked));
+        assert!(registry.healthy());
+
+        registry.shutdown(Duration::from_millis(100)).await;
+        assert!(!registry.healthy());
+    }
+}
@@ -0,0 +1,249 @@
+//! Temperature sensors on the ESPHome devices, and warnings when they run hot.
+//!
+//! ESP32-CAM modules throttle and send corrupted frames once the enclosure gets
+//! hot, and ESPHome can expose their internal temperature as a sensor. Each
+//! entry of `esphome_temperature_entities` names one sensor as
+//! `device/object_id`; the controller monitor reads them after every health
+//! check. Sensors that are missing or don't give a number are skipped.
+//!
+//! [`TemperatureWatch`] follows each sensor through its excursions over
+//! `temperature_warning_c`: a sensor reaching the threshold is reported once,
+//! and only reported again after it has cooled [`TEMPERATURE_HYSTERESIS_C`]
+//! below it, so a reading hovering around the threshold doesn't repeat the
+//! warning every check.
+
+use std::collections::HashSet;
+use std::fmt;
+use std::str::FromStr;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::camera_manager::is_esphome_object_id;
+use crate::{OurError, OurResult};
+
+/// Temperature that warns unless configured otherwise, in °C
+pub const DEFAULT_TEMPERATURE_WARNING_C: f64 = 70.0;
+/// How far below the threshold a sensor has to cool before it can warn again
+pub const TEMPERATURE_HYSTERESIS_C: f64 = 2.0;
+
+/// A temperature sensor on an ESPHome device, written as `device/object_id`
+#[derive(
+    Debug, Clone, PartialEq, Eq, Hash, serde_with::SerializeDisplay, serde_with::DeserializeFromStr,
+)]
+pub struct TemperatureSensor {
+    /// Hostname of the device, optionally with a port
+    pub device: String,
+    pub object_id: String,
+}
+
+impl TemperatureSensor {
+    /// The sensor's ESPHome REST API URL
+    pub fn url(&self) -> String {
+        format!("http://{}/sensor/{}", self.device, self.object_id)
+    }
+}
+
+impl fmt::Display for TemperatureSensor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.device, self.object_id)
+    }
+}
+
+impl FromStr for TemperatureSensor {
+    type Err = OurError;
+
+    fn from_str(s: &str) -> OurResult<Self> {
+        let (device, object_id) = s.trim().rsplit_once('/').ok_or_else(|| {
+            OurError::Config(format!(
+                "Temperature sensor '{s}' should be device/object_id"
+            ))
+        })?;
+        if device.is_empty() || device.contains("://") {
+            return Err(OurError::Config(format!(
+                "Invalid device in temperature sensor '{s}', expected a hostname"
+            )));
+        }
+        if !is_esphome_object_id(object_id) {
+            return Err(OurError::Config(format!(
+                "Invalid ESPHome object id in temperature sensor '{s}'"
+            )));
+        }
+        Ok(Self {
+            device: device.to_string(),
+            object_id: object_id.to_string(),
+        })
+    }
+}
+
+/// Read a temperature in °C from an ESPHome sensor response
+///
+/// Takes the JSON from `GET /sensor/<object_id>`, preferring its numeric
+/// `value` and falling back to the `state` text, or the bare state text, e.g.
+/// `45.3 °C`. Readings in °F are converted. `NaN` means the sensor has no
+/// reading yet, so gives `None` like anything else that isn't a number.
+pub fn parse_sensor_state(body: &str) -> Option<f64> {
+    let celsius = match serde_json::from_str::<serde_json::Value>(body) {
+        Ok(serde_json::Value::Object(entity)) => match entity.get("value") {
+            Some(serde_json::Value::Number(value)) => value.as_f64(),
+            _ => entity
+                .get("state")
+                .and_then(serde_json::Value::as_str)
+                .and_then(parse_state_text),
+        },
+        Ok(serde_json::Value::Number(value)) => value.as_f64(),
+        _ => parse_state_text(body),
+    }?;
+    celsius.is_finite().then_some(celsius)
+}
+
+/// A state like `45.3 °C`, `113.5°F` or `45.3`
+fn parse_state_text(state: &str) -> Option<f64> {
+    let state = state.trim();
+    let number_end = state
+        .find(|c: char| !(c.is_ascii_digit() || matches!(c, '.' | '-' | '+' | 'e' | 'E')))
+        .unwrap_or(state.len());
+    let (number, unit) = state.split_at(number_end);
+    let value: f64 = number.parse().ok()?;
+    match unit.trim().trim_start_matches('°').trim() {
+        "" | "C" | "c" => Some(value),
+        "F" | "f" => Some((value - 32.0) * 5.0 / 9.0),
+        _ => None,
+    }
+}
+
+/// The latest reading of one sensor
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct TemperatureReading {
+    pub sensor: TemperatureSensor,
+    pub celsius: f64,
+    pub read_at: DateTime<Utc>,
+    /// Whether the sensor is in an excursion over the warning threshold
+    pub overheated: bool,
+}
+
+/// A sensor reaching the warning threshold, broadcast once per excursion
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub struct Overheated {
+    pub sensor: TemperatureSensor,
+    pub celsius: f64,
+    pub threshold_c: f64,
+    pub at: DateTime<Utc>,
+}
+
+impl Overheated {
+    pub fn message(&self) -> String {
+        format!(
+            "{} is at {:.1} °C, over the {:.1} °C warning threshold",
+            self.sensor, self.celsius, self.threshold_c
+        )
+    }
+}
+
+/// Which sensors are over the threshold, so each excursion is only reported once
+#[derive(Debug, Clone, Default)]
+pub struct TemperatureWatch {
+    overheated: HashSet<TemperatureSensor>,
+}
+
+impl TemperatureWatch {
+    /// Note a reading, returning the warning if it starts an excursion
+    pub fn observe(
+        &mut self,
+        sensor: &TemperatureSensor,
+        celsius: f64,
+        threshold_c: f64,
+        at: DateTime<Utc>,
+    ) -> Option<Overheated> {
+        if self.overheated.contains(sensor) {
+            if celsius < threshold_c - TEMPERATURE_HYSTERESIS_C {
+                self.overheated.remove(sensor);
+            }
+            return None;
+        }
+        if celsius < threshold_c {
+            return None;
+        }
+        self.overheated.insert(sensor.clone());
+        Some(Overheated {
+            sensor: sensor.clone(),
+            celsius,
+            threshold_c,
+            at,
+        })
+    }
+
+    /// Whether `sensor` is in an excursion
+    pub fn is_overheated(&self, sensor: &TemperatureSensor) -> bool {
+        self.overheated.contains(sensor)
+    }
+
+    /// Forget sensors that are no longer configured
+    pub fn retain(&mut self, sensors: &[TemperatureSensor]) {
+        self.overheated.retain(|sensor| sensors.contains(sensor));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sensor_states_are_read_with_their_units() {
+        assert_eq!(
+            parse_sensor_state(r#"{"id":"sensor-esp_temp","value":45.25,"state":"45.2 °C"}"#),
+            Some(45.25)
+        );
+        assert_eq!(
+            parse_sensor_state(r#"{"id":"sensor-esp_temp","state":"51.5 °C"}"#),
+            Some(51.5)
+        );
+        let fahrenheit = parse_sensor_state("122°F").expect("°F should be read");
+        assert!((fahrenheit - 50.0).abs() < 1e-9, "{fahrenheit}");
+        assert_eq!(parse_sensor_state(" 38 "), Some(38.0));
+        assert_eq!(parse_sensor_state("-4.5 C"), Some(-4.5));
+        assert_eq!(
+            parse_sensor_state(r#"{"id":"sensor-esp_temp","value":null,"state":"NA"}"#),
+            None
+        );
+        assert_eq!(parse_sensor_state("nan °C"), None);
+        assert_eq!(parse_sensor_state("45 %"), None);
+        assert_eq!(parse_sensor_state(""), None);
+
+        let sensor: TemperatureSensor = "esp32cam1.local:8080/esp_temperature"
+            .parse()
+            .expect("sensor should parse");
+        assert_eq!(sensor.device, "esp32cam1.local:8080");
+        assert_eq!(
+            sensor.url(),
+            "http://esp32cam1.local:8080/sensor/esp_temperature"
+        );
+        assert!("esp_temperature".parse::<TemperatureSensor>().is_err());
+        assert!(
+            "http://cam.local/esp_temperature"
+                .parse::<TemperatureSensor>()
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_each_excursion_warns_once() {
+        let sensor: TemperatureSensor = "cam.local/esp_temperature"
+            .parse()
+            .expect("sensor should parse");
+        let now = Utc::now();
+        let mut watch = TemperatureWatch::default();
+        let warnings: Vec<f64> = [60.0, 70.0, 75.0, 69.0, 68.5, 71.0, 67.9, 70.5]
+            .into_iter()
+            .filter_map(|celsius| watch.observe(&sensor, celsius, 70.0, now))
+            .map(|warning| warning.celsius)
+            .collect();
+        // 69 and 68.5 are within the hysteresis, 67.9 ends the excursion
+        assert_eq!(warnings, vec![70.0, 70.5]);
+        assert!(watch.is_overheated(&sensor));
+        watch.retain(&[]);
+        assert!(!watch.is_overheated(&sensor));
+    }
+}
@@ -0,0 +1,189 @@
+//! Model training run in the background, so clients can follow its progress.
+//!
+//! Starting a job snapshots the training data straight away, so a dataset too
+//! small to train on fails the request that asked for it rather than the job.
+//! The model is then trained on the task registry and the job records which
+//! phase it has reached; once it finishes it holds the model's metadata or why
+//! it failed. Jobs live in memory, and only the most recent are kept.
+
+use std::collections::VecDeque;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::ml_training::ModelMetadata;
+use crate::model_metrics::ConfusedPair;
+
+/// Training jobs kept, oldest dropped first
+pub const MAX_TRAINING_JOBS: usize = 20;
+/// Mistaken case type pairs reported when a job completes
+pub const WORST_CONFUSED_PAIRS: usize = 3;
+
+/// How far a training job has got
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TrainingPhase {
+    /// The dataset is snapshotted and the job is waiting to run
+    Queued,
+    /// Other heavy work holds every permit, so the job waits for one
+    WaitingForResources,
+    Training,
+    Completed,
+    Failed,
+}
+
+impl TrainingPhase {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TrainingPhase::Queued => "queued",
+            TrainingPhase::WaitingForResources => "waiting_for_resources",
+            TrainingPhase::Training => "training",
+            TrainingPhase::Completed => "completed",
+            TrainingPhase::Failed => "failed",
+        }
+    }
+
+    pub fn is_finished(&self) -> bool {
+        matches!(self, TrainingPhase::Completed | TrainingPhase::Failed)
+    }
+}
+
+/// A training job as reported by `/api/training-jobs/{job_id}`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct TrainingJob {
+    pub id: String,
+    pub phase: TrainingPhase,
+    /// How much of the job is done, from 0 to 100
+    pub percent: u8,
+    pub case_types: Vec<String>,
+    pub started_at: DateTime<Utc>,
+    pub finished_at: Option<DateTime<Utc>>,
+    /// The trained model, once the job has completed
+    pub model: Option<ModelMetadata>,
+    /// Where the model's metadata was saved
+    pub metadata_path: Option<PathBuf>,
+    /// The case types the model mixed up most on its validation shells, worst first
+    #[serde(default)]
+    pub worst_confused: Vec<ConfusedPair>,
+    /// Why the job failed
+    pub error: Option<String>,
+}
+
+/// Recent training jobs, shared by the request handlers and the jobs themselves
+#[derive(Debug, Clone, Default)]
+pub struct TrainingJobs(Arc<Mutex<VecDeque<TrainingJob>>>);
+
+impl TrainingJobs {
+    // A poisoned lock only means a job panicked while recording its progress; the list is still usable
+    fn lock_jobs(&self) -> MutexGuard<'_, VecDeque<TrainingJob>> {
+        self.0
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Record a new job training `case_types`, queued until it is run
+    pub fn queue(&self, case_types: Vec<String>) -> TrainingJob {
+        let job = TrainingJob {
+            id: uuid::Uuid::new_v4().to_string(),
+            phase: TrainingPhase::Queued,
+            percent: 0,
+            case_types,
+            started_at: Utc::now(),
+            finished_at: None,
+            model: None,
+            metadata_path: None,
+            worst_confused: Vec::new(),
+            error: None,
+        };
+        let mut jobs = self.lock_jobs();
+        jobs.push_back(job.clone());
+        while jobs.len() > MAX_TRAINING_JOBS {
+            jobs.pop_front();
+        }
+        job
+    }
+
+    pub fn get(&self, job_id: &str) -> Option<TrainingJob> {
+        self.lock_jobs()
+            .iter()
+            .find(|job| job.id == job_id)
+            .cloned()
+    }
+
+    /// Record that the job has reached `phase`, `percent` of the way through
+    pub fn progress(&self, job_id: &str, phase: TrainingPhase, percent: u8) {
+        self.update(job_id, |job| {
+            job.phase = phase;
+            job.percent = percent.min(100);
+        });
+    }
+
+    pub fn complete(
+        &self,
+        job_id: &str,
+        model: ModelMetadata,
+        metadata_path: PathBuf,
+        worst_confused: Vec<ConfusedPair>,
+    ) {
+        self.update(job_id, |job| {
+            job.phase = TrainingPhase::Completed;
+            job.percent = 100;
+            job.finished_at = Some(Utc::now());
+            job.model = Some(model);
+            job.metadata_path = Some(metadata_path);
+            job.worst_confused = worst_confused;
+        });
+    }
+
+    pub fn fail(&self, job_id: &str, error: String) {
+        self.update(job_id, |job| {
+            job.phase = TrainingPhase::Failed;
+            job.finished_at = Some(Utc::now());
+            job.error = Some(error);
+        });
+    }
+
+    fn update(&self, job_id: &str, change: impl FnOnce(&mut TrainingJob)) {
+        if let Some(job) = self.lock_jobs().iter_mut().find(|job| job.id == job_id) {
+            change(job);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_jobs_record_progress_and_keep_the_most_recent() {
+        let jobs = TrainingJobs::default();
+        let first = jobs.queue(vec!["Winchester_9mm".to_string()]);
+        assert_eq!(first.phase, TrainingPhase::Queued);
+
+        jobs.progress(&first.id, TrainingPhase::Training, 250);
+        let training = jobs.get(&first.id).expect("job should be listed");
+        assert_eq!(
+            (training.phase, training.percent),
+            (TrainingPhase::Training, 100)
+        );
+        assert!(!training.phase.is_finished());
+
+        jobs.fail(&first.id, "lock poisoned".to_string());
+        let failed = jobs.get(&first.id).expect("job should be listed");
+        assert!(failed.phase.is_finished());
+        assert_eq!(failed.error.as_deref(), Some("lock poisoned"));
+        assert!(failed.finished_at.is_some());
+
+        let later: Vec<TrainingJob> = (0..MAX_TRAINING_JOBS)
+            .map(|_| jobs.queue(Vec::new()))
+            .collect();
+        assert!(jobs.get(&first.id).is_none());
+        assert!(later.iter().all(|job| jobs.get(&job.id).is_some()));
+        // Progress on a job that was dropped is ignored
+        jobs.progress(&first.id, TrainingPhase::Training, 50);
+        assert!(jobs.get(&first.id).is_none());
+    }
+}
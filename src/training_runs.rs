@@ -0,0 +1,166 @@
+//! Tracking of running training jobs.
+//!
+//! A training run snapshots the shells and images it will use and hashes them
+//! before it starts. Changes to the training data while it runs (toggling or
+//! deleting shells, deleting case types) are allowed to proceed, but are
+//! recorded against the run so its model metadata can say the dataset changed
+//! underneath it.
+
+use serde::{Deserialize, Serialize};
+use std::sync::{Arc, Mutex, MutexGuard};
+use tracing::warn;
+
+use crate::{OurError, OurResult};
+
+/// One shell as seen by a training run
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct SnapshotEntry {
+    pub session_id: String,
+    pub case_type: String,
+    pub image_paths: Vec<String>,
+}
+
+/// The dataset a training run uses, fixed when the run starts
+#[derive(Debug, Clone, PartialEq)]
+pub struct DatasetSnapshot {
+    pub entries: Vec<SnapshotEntry>,
+    /// Case type training images included in the run
+    pub training_images: Vec<String>,
+    /// Hash of the entries and training images
+    pub hash: String,
+}
+
+impl DatasetSnapshot {
+    /// Build a snapshot, sorting entries so the hash doesn't depend on directory order
+    pub fn new(
+        mut entries: Vec<SnapshotEntry>,
+        mut training_images: Vec<String>,
+    ) -> OurResult<Self> {
+        entries.sort();
+        training_images.sort();
+        let hash = content_hash(&serde_json::to_vec(&(&entries, &training_images))?);
+        Ok(Self {
+            entries,
+            training_images,
+            hash,
+        })
+    }
+
+    /// Number of shells in the snapshot
+    pub fn shell_count(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Number of shell images and case type training images in the snapshot
+    pub fn image_count(&self) -> usize {
+        self.entries
+            .iter()
+            .map(|entry| entry.image_paths.len())
+            .sum::<usize>()
+            + self.training_images.len()
+    }
+}
+
+/// FNV-1a hash of some bytes, as 16 hex digits
+pub(crate) fn content_hash(bytes: &[u8]) -> String {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for byte in bytes {
+        hash ^= u64::from(*byte);
+        hash = hash.wrapping_mul(0x0100_0000_01b3);
+    }
+    format!("{hash:016x}")
+}
+
+#[derive(Debug, Default)]
+struct ActiveRun {
+    /// Descriptions of changes made while the run was in progress
+    mutations: Vec<String>,
+}
+
+/// Shared record of the training run in progress, if any
+#[derive(Clone, Default)]
+pub struct TrainingRuns {
+    active: Arc<Mutex<Option<ActiveRun>>>,
+}
+
+impl TrainingRuns {
+    // A poisoned lock only means a training run panicked; the record is still usable
+    fn lock_active(&self) -> MutexGuard<'_, Option<ActiveRun>> {
+        self.active
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Mark a run as started, failing if one is already running
+    pub fn start(&self) -> OurResult<TrainingRunGuard> {
+        let mut active = self.lock_active();
+        if active.is_some() {
+            return Err(OurError::App(
+                "A training run is already in progress".to_string(),
+            ));
+        }
+        *active = Some(ActiveRun::default());
+        Ok(TrainingRunGuard { runs: self.clone() })
+    }
+
+    /// Whether a training run is in progress
+    pub fn is_running(&self) -> bool {
+        self.lock_active().is_some()
+    }
+
+    /// Record a change to the training data against the running job, if there is one
+    pub fn record_mutation(&self, description: impl Into<String>) {
+        if let Some(run) = self.lock_active().as_mut() {
+            let description = description.into();
+            warn!(
+                "Training data changed during a training run: {}",
+                description
+            );
+            run.mutations.push(description);
+        }
+    }
+}
+
+/// Ends the training run when finished or dropped
+pub struct TrainingRunGuard {
+    runs: TrainingRuns,
+}
+
+impl TrainingRunGuard {
+    /// End the run, returning the changes made to the dataset while it ran
+    pub fn finish(self) -> Vec<String> {
+        self.runs
+            .lock_active()
+            .take()
+            .map(|run| run.mutations)
+            .unwrap_or_default()
+    }
+}
+
+impl Drop for TrainingRunGuard {
+    fn drop(&mut self) {
+        self.runs.lock_active().take();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_runs_record_mutations_only_while_running() {
+        let runs = TrainingRuns::default();
+        runs.record_mutation("before");
+
+        let guard = runs.start().expect("run should start");
+        assert!(runs.start().is_err());
+        runs.record_mutation("during");
+        assert_eq!(guard.finish(), vec!["during"]);
+
+        assert!(!runs.is_running());
+        runs.record_mutation("after");
+        let guard = runs.start().expect("run should start");
+        assert!(guard.finish().is_empty());
+    }
+}
@@ -0,0 +1,630 @@
+//! Deleted shells, kept for a while so a mistaken delete can be undone.
+//!
+//! Deleting a shell moves its record, its images and its session summary into
+//! `trash/{session_id}/` in the data directory, beside a [`TRASH_RECORD_FILENAME`]
+//! noting when and what was moved. Nothing reading the data directory sees it
+//! there, and restoring moves everything back where it came from. Trash older
+//! than `trash_retention_days` is purged for good by a sweep the server runs
+//! every [`TRASH_SWEEP_INTERVAL`].
+
+use std::fs;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+
+use crate::safe_name::SafeName;
+use crate::session_summary;
+use crate::shell_data::{Shell, ShellDataManager};
+use crate::{OurError, OurResult};
+
+/// Directory in the data directory trashed shells are kept in
+pub const TRASH_DIRECTORY: &str = "trash";
+
+/// What a trashed shell's directory holds besides its record and images
+pub const TRASH_RECORD_FILENAME: &str = "trashed.json";
+
+/// Days trash is kept unless `trash_retention_days` says otherwise
+pub const DEFAULT_TRASH_RETENTION_DAYS: u32 = 30;
+
+/// How often the server purges expired trash
+pub const TRASH_SWEEP_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// Subdirectory of a trashed shell's directory its images are kept in
+const IMAGES_DIRECTORY: &str = "images";
+
+/// What was moved into the trash, and when
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct TrashRecord {
+    pub session_id: String,
+    pub deleted_at: DateTime<Utc>,
+    pub brand: String,
+    pub shell_type: String,
+    /// Files moved with the record, relative to the image directory: its images and session summary
+    pub images: Vec<String>,
+}
+
+/// A trashed shell as listed, with how long it has left
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub struct TrashEntry {
+    #[serde(flatten)]
+    pub record: TrashRecord,
+    pub age_seconds: i64,
+    pub purge_at: DateTime<Utc>,
+}
+
+/// What restoring a shell from the trash did
+#[derive(Debug, Clone, PartialEq)]
+pub enum Restore {
+    Restored(Shell),
+    /// Nothing is in the trash under the session ID
+    NotFound,
+    /// Something saved since is in the way; the path that would be replaced
+    Conflict(PathBuf),
+}
+
+/// Whether trash deleted at `deleted_at` is past its retention at `now`
+pub fn is_expired(deleted_at: DateTime<Utc>, retention_days: u32, now: DateTime<Utc>) -> bool {
+    now >= purge_at(deleted_at, retention_days)
+}
+
+fn purge_at(deleted_at: DateTime<Utc>, retention_days: u32) -> DateTime<Utc> {
+    deleted_at + chrono::Duration::days(i64::from(retention_days))
+}
+
+/// Move a file, copying it when it has to cross filesystems
+fn move_file(from: &Path, to: &Path) -> io::Result<()> {
+    if let Some(parent) = to.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    if fs::rename(from, to).is_ok() {
+        return Ok(());
+    }
+    fs::copy(from, to)?;
+    fs::remove_file(from)
+}
+
+/// An image filename that stays inside the directory it's relative to
+fn is_relative_inside(filename: &str) -> bool {
+    let path = Path::new(filename);
+    !filename.is_empty()
+        && path
+            .components()
+            .all(|component| matches!(component, Component::Normal(_)))
+}
+
+/// The trash for a data directory, see the module docs
+#[derive(Debug, Clone)]
+pub struct Trash {
+    directory: PathBuf,
+    image_directory: PathBuf,
+}
+
+impl Trash {
+    pub fn new(data_directory: &Path, image_directory: &Path) -> Self {
+        Self {
+            directory: data_directory.join(TRASH_DIRECTORY),
+            image_directory: image_directory.to_path_buf(),
+        }
+    }
+
+    fn shell_directory(&self, session_id: &str) -> OurResult<PathBuf> {
+        Ok(self
+            .directory
+            .join(SafeName::new("session_id", session_id)?))
+    }
+
+    /// Move a shell and its images into the trash, `None` if there is no such shell
+    pub fn trash_shell(
+        &self,
+        shells: &ShellDataManager,
+        session_id: &str,
+        now: DateTime<Utc>,
+    ) -> OurResult<Option<TrashRecord>> {
+        let directory = self.shell_directory(session_id)?;
+        let shell = match shells.load_shell(session_id) {
+            Ok(shell) => shell,
+            Err(_) if shells.modified_at(session_id)?.is_none() => return Ok(None),
+            Err(e) => return Err(e),
+        };
+
+        // A shell deleted, saved again under its ID and deleted again replaces the older trash
+        if directory.exists() {
+            warn!("Replacing the trashed copy of shell {session_id}");
+            fs::remove_dir_all(&directory)
+                .map_err(|e| OurError::App(format!("Failed to clear old trash: {e}")))?;
+        }
+        fs::create_dir_all(&directory)
+            .map_err(|e| OurError::App(format!("Failed to create trash directory: {e}")))?;
+
+        let summary = session_summary::summary_filename(session_id)?.to_string();
+        let mut images: Vec<String> = Vec::new();
+        for filename in shell
+            .captured_images
+            .iter()
+            .flatten()
+            .map(|image| &image.filename)
+            .chain([&summary])
+        {
+            if images.contains(filename) || !is_relative_inside(filename) {
+                continue;
+            }
+            let source = self.image_directory.join(filename);
+            if !source.is_file() {
+                continue;
+            }
+            let target = directory.join(IMAGES_DIRECTORY).join(filename);
+            if let Err(e) = move_file(&source, &target) {
+                self.put_images_back(&directory, &images);
+                fs::remove_dir_all(&directory).ok();
+                return Err(OurError::App(format!(
+                    "Failed to move {filename} to the trash: {e}"
+                )));
+            }
+            images.push(filename.clone());
+        }
+
+        let record = TrashRecord {
+            session_id: session_id.to_string(),
+            deleted_at: now,
+            brand: shell.brand,
+            shell_type: shell.shell_type,
+            images,
+        };
+        let moved = serde_json::to_string_pretty(&record)
+            .map_err(OurError::from)
+            .and_then(|json| {
+                fs::write(directory.join(TRASH_RECORD_FILENAME), json)
+                    .map_err(|e| OurError::App(format!("Failed to write trash record: {e}")))
+            })
+            .and_then(|()| {
+                shells.move_shell_out(session_id, &directory.join(format!("{session_id}.json")))
+            });
+        match moved {
+            Ok(true) => {}
+            // Deleted by someone else while the images were moved
+            Ok(false) => {
+                self.put_images_back(&directory, &record.images);
+                fs::remove_dir_all(&directory).ok();
+                return Ok(None);
+            }
+            Err(e) => {
+                self.put_images_back(&directory, &record.images);
+                fs::remove_dir_all(&directory).ok();
+                return Err(e);
+            }
+        }
+
+        info!(
+            "Moved shell {session_id} and {} images to the trash",
+            record.images.len()
+        );
+        Ok(Some(record))
+    }
+
+    /// Delete a shell, its images and any trashed copy for good, false if there was none
+    pub fn delete_permanently(
+        &self,
+        shells: &ShellDataManager,
+        session_id: &str,
+    ) -> OurResult<bool> {
+        let directory = self.shell_directory(session_id)?;
+        let live = shells.get_shell(session_id)?;
+        if let Some(shell) = &live {
+            // The record goes first so nothing is left pointing at missing images
+            shells.delete_shell(session_id)?;
+            let summary = session_summary::summary_filename(session_id)?.to_string();
+            for filename in shell
+                .captured_images
+                .iter()
+                .flatten()
+                .map(|image| &image.filename)
+                .chain([&summary])
+            {
+                if !is_relative_inside(filename) {
+                    continue;
+                }
+                match fs::remove_file(self.image_directory.join(filename)) {
+                    Ok(()) => {}
+                    Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+                    Err(e) => warn!("Failed to delete {filename}: {e}"),
+                }
+            }
+        }
+
+        let trashed = directory.exists();
+        if trashed {
+            fs::remove_dir_all(&directory)
+                .map_err(|e| OurError::App(format!("Failed to purge trashed shell: {e}")))?;
+        }
+        if live.is_some() || trashed {
+            info!("Permanently deleted shell {session_id}");
+        }
+        Ok(live.is_some() || trashed)
+    }
+
+    /// Undo moving `images` into a trashed shell's directory, as far as possible
+    fn put_images_back(&self, directory: &Path, images: &[String]) {
+        for filename in images {
+            let trashed = directory.join(IMAGES_DIRECTORY).join(filename);
+            if let Err(e) = move_file(&trashed, &self.image_directory.join(filename)) {
+                warn!("Failed to put {filename} back from the trash: {e}");
+            }
+        }
+    }
+
+    fn read_record(&self, directory: &Path) -> OurResult<Option<TrashRecord>> {
+        match fs::read_to_string(directory.join(TRASH_RECORD_FILENAME)) {
+            Ok(json) => Ok(Some(serde_json::from_str(&json)?)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(OurError::App(format!(
+                "Failed to read trash record in {}: {e}",
+                directory.display()
+            ))),
+        }
+    }
+
+    /// Put a trashed shell and its images back where they were
+    pub fn restore(&self, shells: &ShellDataManager, session_id: &str) -> OurResult<Restore> {
+        let directory = self.shell_directory(session_id)?;
+        let Some(record) = self.read_record(&directory)? else {
+            return Ok(Restore::NotFound);
+        };
+        if shells.modified_at(session_id)?.is_some() {
+            return Ok(Restore::Conflict(PathBuf::from(format!(
+                "{session_id}.json"
+            ))));
+        }
+        if let Some(taken) = record
+            .images
+            .iter()
+            .map(|filename| self.image_directory.join(filename))
+            .find(|path| path.exists())
+        {
+            return Ok(Restore::Conflict(taken));
+        }
+
+        let mut restored: Vec<String> = Vec::new();
+        for filename in &record.images {
+            let trashed = directory.join(IMAGES_DIRECTORY).join(filename);
+            if let Err(e) = move_file(&trashed, &self.image_directory.join(filename)) {
+                self.take_images_back(&directory, &restored);
+                return Err(OurError::App(format!(
+                    "Failed to restore {filename} from the trash: {e}"
+                )));
+            }
+            restored.push(filename.clone());
+        }
+        let shell =
+            match shells.move_shell_in(session_id, &directory.join(format!("{session_id}.json"))) {
+                Ok(shell) => shell,
+                Err(e) => {
+                    self.take_images_back(&directory, &restored);
+                    return Err(e);
+                }
+            };
+        if let Err(e) = fs::remove_dir_all(&directory) {
+            warn!("Failed to clear the trash of restored shell {session_id}: {e}");
+        }
+
+        info!(
+            "Restored shell {session_id} and {} images from the trash",
+            restored.len()
+        );
+        Ok(Restore::Restored(shell))
+    }
+
+    /// Undo putting `images` back during a failed restore
+    fn take_images_back(&self, directory: &Path, images: &[String]) {
+        for filename in images {
+            let target = directory.join(IMAGES_DIRECTORY).join(filename);
+            if let Err(e) = move_file(&self.image_directory.join(filename), &target) {
+                warn!("Failed to return {filename} to the trash: {e}");
+            }
+        }
+    }
+
+    /// Every trashed shell, most recently deleted first
+    pub fn list(&self, retention_days: u32, now: DateTime<Utc>) -> OurResult<Vec<TrashEntry>> {
+        let mut entries: Vec<TrashEntry> = self
+            .records()?
+            .into_iter()
+            .map(|record| TrashEntry {
+                age_seconds: (now - record.deleted_at).num_seconds().max(0),
+                purge_at: purge_at(record.deleted_at, retention_days),
+                record,
+            })
+            .collect();
+        entries.sort_by_key(|entry| std::cmp::Reverse(entry.record.deleted_at));
+        Ok(entries)
+    }
+
+    fn records(&self) -> OurResult<Vec<TrashRecord>> {
+        let entries = match fs::read_dir(&self.directory) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => {
+                return Err(OurError::App(format!("Failed to read the trash: {e}")));
+            }
+        };
+
+        let mut records = Vec::new();
+        for entry in entries {
+            let path = entry
+                .map_err(|e| OurError::App(format!("Failed to read the trash: {e}")))?
+                .path();
+            if !path.is_dir() {
+                continue;
+            }
+            match self.read_record(&path) {
+                Ok(Some(record)) => records.push(record),
+                Ok(None) => warn!("{} has no trash record", path.display()),
+                Err(e) => warn!("Skipping {}: {e}", path.display()),
+            }
+        }
+        Ok(records)
+    }
+
+    /// Permanently delete trash past its retention, returning the session IDs purged
+    pub fn purge_expired(&self, retention_days: u32, now: DateTime<Utc>) -> OurResult<Vec<String>> {
+        let mut purged = Vec::new();
+        for record in self.records()? {
+            if !is_expired(record.deleted_at, retention_days, now) {
+                continue;
+            }
+            let directory = self.shell_directory(&record.session_id)?;
+            match fs::remove_dir_all(&directory) {
+                Ok(()) => purged.push(record.session_id),
+                Err(e) => warn!("Failed to purge {}: {e}", directory.display()),
+            }
+        }
+        purged.sort();
+        if !purged.is_empty() {
+            info!("Purged {} shells from the trash", purged.len());
+        }
+        Ok(purged)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ViewType;
+    use crate::shell_data::CapturedImage;
+    use chrono::TimeZone;
+    use tempfile::TempDir;
+
+    fn deleted_at() -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2026, 3, 1, 12, 0, 0)
+            .single()
+            .expect("time should be valid")
+    }
+
+    /// A shell saved with two images on disk
+    fn saved_shell(root: &Path) -> (ShellDataManager, Trash, Shell) {
+        let images = root.join("images");
+        fs::create_dir_all(&images).expect("image directory should be created");
+        let mut shell = Shell::new("Starline".to_string(), "45acp".to_string());
+        for (index, (filename, view_type)) in [
+            ("rare_side.jpg", ViewType::Side),
+            ("rare_tail.jpg", ViewType::Tail),
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            fs::write(images.join(filename), filename).expect("image should be written");
+            shell.add_captured_image(CapturedImage::new(
+                index as u32,
+                filename.to_string(),
+                format!("Camera {index}"),
+                view_type,
+            ));
+        }
+        let shells = ShellDataManager::new(root.join("data"));
+        shells
+            .save_shell("rare", &shell)
+            .expect("shell should be saved");
+        let shell = shells.load_shell("rare").expect("shell should load");
+        (shells, Trash::new(&root.join("data"), &images), shell)
+    }
+
+    #[test]
+    fn test_trash_and_restore_round_trip() {
+        let temp_dir = TempDir::new().expect("temp dir should be created");
+        let (shells, trash, shell) = saved_shell(temp_dir.path());
+        let images = temp_dir.path().join("images");
+
+        let record = trash
+            .trash_shell(&shells, "rare", deleted_at())
+            .expect("shell should be trashed")
+            .expect("shell should exist");
+        assert_eq!(record.images, vec!["rare_side.jpg", "rare_tail.jpg"]);
+        assert!(!images.join("rare_side.jpg").exists());
+
+        // Gone from listings and searches
+        assert!(shells.list_shells().expect("shells should list").is_empty());
+        assert!(
+            shells
+                .search_shells(&Default::default())
+                .expect("shells should search")
+                .is_empty()
+        );
+        let listed = trash
+            .list(30, deleted_at() + chrono::Duration::hours(2))
+            .expect("trash should list");
+        assert_eq!(listed.len(), 1);
+        assert_eq!(listed[0].age_seconds, 2 * 60 * 60);
+        assert_eq!(
+            listed[0].purge_at,
+            deleted_at() + chrono::Duration::days(30)
+        );
+
+        // Trashing again finds nothing
+        assert_eq!(
+            trash
+                .trash_shell(&shells, "rare", deleted_at())
+                .expect("trashing should succeed"),
+            None
+        );
+
+        let Restore::Restored(restored) = trash
+            .restore(&shells, "rare")
+            .expect("restore should succeed")
+        else {
+            panic!("shell should be restored");
+        };
+        assert_eq!(restored, shell);
+        assert_eq!(
+            fs::read(images.join("rare_tail.jpg")).expect("image should be back"),
+            b"rare_tail.jpg"
+        );
+        assert_eq!(shells.list_shells().expect("shells should list").len(), 1);
+        assert!(
+            trash
+                .list(30, deleted_at())
+                .expect("trash should list")
+                .is_empty()
+        );
+        assert_eq!(
+            trash
+                .restore(&shells, "rare")
+                .expect("restore should succeed"),
+            Restore::NotFound
+        );
+    }
+
+    #[test]
+    fn test_restore_never_replaces_newer_files() {
+        let temp_dir = TempDir::new().expect("temp dir should be created");
+        let (shells, trash, shell) = saved_shell(temp_dir.path());
+        trash
+            .trash_shell(&shells, "rare", deleted_at())
+            .expect("shell should be trashed");
+
+        // A new capture reused an image name
+        let taken = temp_dir.path().join("images").join("rare_side.jpg");
+        fs::write(&taken, b"newer").expect("image should be written");
+        assert_eq!(
+            trash
+                .restore(&shells, "rare")
+                .expect("restore should answer"),
+            Restore::Conflict(taken.clone())
+        );
+        fs::remove_file(&taken).expect("image should be removed");
+
+        // A shell saved under the same ID since
+        shells
+            .save_shell("rare", &shell)
+            .expect("shell should be saved");
+        assert!(matches!(
+            trash
+                .restore(&shells, "rare")
+                .expect("restore should answer"),
+            Restore::Conflict(_)
+        ));
+        assert_eq!(
+            trash
+                .list(30, deleted_at())
+                .expect("trash should list")
+                .len(),
+            1
+        );
+    }
+
+    #[test]
+    fn test_permanent_delete_removes_images_and_trash() {
+        let temp_dir = TempDir::new().expect("temp dir should be created");
+        let (shells, trash, shell) = saved_shell(temp_dir.path());
+        let images = temp_dir.path().join("images");
+        trash
+            .trash_shell(&shells, "rare", deleted_at())
+            .expect("shell should be trashed");
+        shells
+            .save_shell("rare", &shell)
+            .expect("shell should be saved");
+        fs::write(images.join("rare_side.jpg"), b"newer").expect("image should be written");
+
+        assert!(
+            trash
+                .delete_permanently(&shells, "rare")
+                .expect("delete should succeed")
+        );
+        assert!(shells.list_shells().expect("shells should list").is_empty());
+        assert!(!images.join("rare_side.jpg").exists());
+        assert!(
+            trash
+                .list(30, deleted_at())
+                .expect("trash should list")
+                .is_empty()
+        );
+        assert!(
+            !trash
+                .delete_permanently(&shells, "rare")
+                .expect("delete should succeed")
+        );
+    }
+
+    #[test]
+    fn test_purge_only_expired_trash() {
+        let temp_dir = TempDir::new().expect("temp dir should be created");
+        let (shells, trash, shell) = saved_shell(temp_dir.path());
+        trash
+            .trash_shell(&shells, "rare", deleted_at())
+            .expect("shell should be trashed");
+        let mut newer = shell.clone();
+        newer.captured_images = None;
+        shells
+            .save_shell("newer", &newer)
+            .expect("shell should be saved");
+        trash
+            .trash_shell(&shells, "newer", deleted_at() + chrono::Duration::days(10))
+            .expect("shell should be trashed");
+
+        assert!(!is_expired(
+            deleted_at(),
+            30,
+            deleted_at() + chrono::Duration::days(29)
+        ));
+        assert!(is_expired(
+            deleted_at(),
+            30,
+            deleted_at() + chrono::Duration::days(30)
+        ));
+        assert!(is_expired(deleted_at(), 0, deleted_at()));
+
+        let day_29 = deleted_at() + chrono::Duration::days(29);
+        assert!(
+            trash
+                .purge_expired(30, day_29)
+                .expect("purge should succeed")
+                .is_empty()
+        );
+        let day_31 = deleted_at() + chrono::Duration::days(31);
+        assert_eq!(
+            trash
+                .purge_expired(30, day_31)
+                .expect("purge should succeed"),
+            vec!["rare"]
+        );
+        let remaining: Vec<String> = trash
+            .list(30, day_31)
+            .expect("trash should list")
+            .into_iter()
+            .map(|entry| entry.record.session_id)
+            .collect();
+        assert_eq!(remaining, vec!["newer"]);
+        assert!(
+            !temp_dir
+                .path()
+                .join("data")
+                .join(TRASH_DIRECTORY)
+                .join("rare")
+                .exists()
+        );
+    }
+}
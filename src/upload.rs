@@ -0,0 +1,116 @@
+//! Request size limits, and taking file uploads without holding them in memory.
+//!
+//! The server runs on a small board, so every request body is capped: JSON
+//! requests at [`MAX_JSON_BODY_BYTES`] and file uploads at the `max_upload_mb`
+//! setting. Uploaded files are written to disk a chunk at a time as they
+//! arrive, into a [`TempUpload`] in the directory they will end up in, and
+//! renamed into place once accepted. An upload that fails or runs over its
+//! limit leaves nothing behind: the temp file goes when the [`TempUpload`] is
+//! dropped.
+
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use axum::extract::multipart::Field;
+use axum::http::StatusCode;
+use tokio::io::AsyncWriteExt;
+use tracing::warn;
+use uuid::Uuid;
+
+/// Largest file upload accepted unless configured otherwise, in megabytes
+pub const DEFAULT_MAX_UPLOAD_MB: u64 = 32;
+/// Largest body accepted by routes that take JSON or nothing at all
+pub const MAX_JSON_BODY_BYTES: usize = 1024 * 1024;
+
+/// A file being uploaded, removed when dropped unless it was moved away first
+pub struct TempUpload {
+    path: PathBuf,
+}
+
+impl TempUpload {
+    /// A new, not yet created, hidden file in `directory`
+    pub fn new(directory: &Path) -> Self {
+        Self {
+            path: directory.join(format!(".upload-{}.part", Uuid::new_v4())),
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for TempUpload {
+    fn drop(&mut self) {
+        if let Err(e) = std::fs::remove_file(&self.path)
+            && e.kind() != io::ErrorKind::NotFound
+        {
+            warn!("Failed to remove {}: {e}", self.path.display());
+        }
+    }
+}
+
+/// Why an upload couldn't be written to disk
+#[derive(Debug)]
+pub enum SpoolError {
+    /// The request ran past its body limit
+    TooLarge,
+    /// The upload couldn't be read from the request
+    Read(String),
+    /// The upload couldn't be written to disk
+    Write(String),
+}
+
+impl fmt::Display for SpoolError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SpoolError::TooLarge => f.write_str("Upload is larger than the limit"),
+            SpoolError::Read(e) => write!(f, "Failed to read upload: {e}"),
+            SpoolError::Write(e) => write!(f, "Failed to store upload: {e}"),
+        }
+    }
+}
+
+/// Write a multipart field to `path` as it arrives, returning how many bytes it held
+///
+/// The directory is created if needed. On error the partial file is left for
+/// the caller's [`TempUpload`] to remove.
+pub async fn spool_field(field: &mut Field<'_>, path: &Path) -> Result<u64, SpoolError> {
+    let write_error = |e: io::Error| SpoolError::Write(e.to_string());
+    if let Some(directory) = path.parent() {
+        tokio::fs::create_dir_all(directory)
+            .await
+            .map_err(write_error)?;
+    }
+    let mut file = tokio::fs::File::create(path).await.map_err(write_error)?;
+    let mut written = 0;
+    loop {
+        match field.chunk().await {
+            Ok(Some(chunk)) => {
+                file.write_all(&chunk).await.map_err(write_error)?;
+                written += chunk.len() as u64;
+            }
+            Ok(None) => break,
+            Err(e) if e.status() == StatusCode::PAYLOAD_TOO_LARGE => {
+                return Err(SpoolError::TooLarge);
+            }
+            Err(e) => return Err(SpoolError::Read(e.body_text())),
+        }
+    }
+    file.flush().await.map_err(write_error)?;
+    Ok(written)
+}
+
+/// The message for a request over `limit_bytes`
+pub fn too_large_message(limit_bytes: usize) -> String {
+    const MEGABYTE: usize = 1024 * 1024;
+    if limit_bytes >= MEGABYTE && limit_bytes.is_multiple_of(MEGABYTE) {
+        format!(
+            "Request body is larger than the {} MB limit",
+            limit_bytes / MEGABYTE
+        )
+    } else {
+        format!("Request body is larger than the {limit_bytes} byte limit")
+    }
+}
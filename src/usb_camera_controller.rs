@@ -3,7 +3,6 @@
 //! This module provides direct USB camera access with hardware-based device identification
 //! using vendor/product IDs and serial numbers for stable camera mapping across system reboots.
 
-use image::DynamicImage;
 use nokhwa::{
     Camera,
     pixel_format::RgbFormat,
@@ -13,16 +12,39 @@ use nokhwa::{
     },
 };
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 use tokio::sync::RwLock;
 use tokio::sync::{mpsc, oneshot};
 use tracing::{debug, error, info, warn};
 
+use crate::camera::CameraId;
+use crate::camera_lock::{Admission, BusyPolicy, CameraLocks};
+use crate::camera_warmup::{CaptureWarmup, WarmupTiming, WarmupTracker, capture_settled};
+use crate::capture_stats::CaptureStats;
+use crate::etag::DataVersion;
+use crate::event_log::EventRecorder;
+use crate::frame_processing::{FramePath, FrameProcessing, RawFrame, process_frame};
+use crate::orientation::Orientation;
+use crate::pre_capture::{FrameRing, PreCapture, PreCaptureStats};
+use crate::protocol::request;
+use crate::stream_health::{StreamHealth, StreamStalled, check_streams};
+use crate::task_registry::TaskRegistry;
+use crate::usb_formats::{DetectionReport, FormatCache, FormatSource, common_formats};
+use crate::usb_ids::{DescriptionParser, HardwareIds};
 use crate::{OurError, OurResult, constants::USB_DEVICE_PREFIX};
 
+/// Name used in errors about the USB camera manager's channel
+const MANAGER_NAME: &str = "USB camera manager";
+/// How long asking the OS which cameras are attached may take
+const DETECTION_TIMEOUT: Duration = Duration::from_secs(2);
+/// How errors opening a camera start, which is how a camera at a stale index fails
+const CREATE_FAILED: &str = "Failed to create camera";
+
 /// USB Camera device information with hardware identification
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub struct UsbCameraInfo {
     /// Camera index for access
     pub index: u32,
@@ -54,7 +76,8 @@ impl UsbCameraInfo {
 }
 
 /// Camera format information
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub struct CameraFormatInfo {
     pub width: u32,
     pub height: u32,
@@ -64,6 +87,7 @@ pub struct CameraFormatInfo {
 
 /// USB Camera status information
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[serde(rename_all = "snake_case")]
 pub struct UsbCameraStatus {
     /// Available USB cameras by hardware ID
     pub cameras: HashMap<String, UsbCameraInfo>,
@@ -71,15 +95,35 @@ pub struct UsbCameraStatus {
     pub streaming: bool,
     /// Last detection timestamp
     pub last_detection: Option<chrono::DateTime<chrono::Utc>>,
+    /// How the last detection went
+    #[serde(default)]
+    pub detection: Option<DetectionReport>,
+    /// Capture counters by hardware ID
+    #[serde(default)]
+    pub capture_stats: HashMap<String, CaptureStats>,
+    /// Live stream health by hardware ID
+    #[serde(default)]
+    pub stream_health: HashMap<String, StreamHealth>,
+    /// Capture session holding each camera against control writes, by hardware ID
+    #[serde(default)]
+    pub capture_locks: HashMap<String, String>,
+    /// Pre-capture buffer use by hardware ID, for cameras that are buffering
+    #[serde(default)]
+    pub pre_capture: HashMap<String, PreCaptureStats>,
 }
 
 impl UsbCameraStatus {
-    /// Get currently selected cameras
+    /// Get currently selected cameras, in index order
     pub fn selected_cameras(&self) -> Vec<String> {
-        self.cameras
-            .iter()
-            .filter(|(_, camera)| camera.connected)
-            .map(|(hardware_id, _)| hardware_id.clone())
+        let mut selected: Vec<&UsbCameraInfo> = self
+            .cameras
+            .values()
+            .filter(|camera| camera.connected)
+            .collect();
+        selected.sort_by(|a, b| (a.index, &a.hardware_id).cmp(&(b.index, &b.hardware_id)));
+        selected
+            .into_iter()
+            .map(|camera| camera.hardware_id.clone())
             .collect()
     }
 
@@ -92,12 +136,20 @@ impl UsbCameraStatus {
     }
 }
 
+/// Cameras found by a detection, and how it went
+#[derive(Debug, Clone)]
+pub struct UsbDetection {
+    pub cameras: Vec<UsbCameraInfo>,
+    pub report: DetectionReport,
+}
+
 /// USB Camera control commands
 #[derive(Debug)]
 pub enum UsbCameraRequest {
-    /// Detect and enumerate USB cameras
+    /// Detect and enumerate USB cameras, asking every camera for its formats if `deep`
     DetectCameras {
-        respond_to: oneshot::Sender<OurResult<Vec<UsbCameraInfo>>>,
+        deep: bool,
+        respond_to: oneshot::Sender<OurResult<UsbDetection>>,
     },
     /// List currently known cameras
     ListCameras {
@@ -105,7 +157,7 @@ pub enum UsbCameraRequest {
     },
     /// Select cameras for operations
     SelectCameras {
-        hardware_ids: Vec<String>,
+        hardware_ids: Vec<CameraId>,
         respond_to: oneshot::Sender<OurResult<()>>,
     },
     /// Start streaming from selected cameras
@@ -129,6 +181,7 @@ pub enum UsbCameraRequest {
     SetBrightness {
         hardware_id: String,
         brightness: i64,
+        when_busy: BusyPolicy,
         respond_to: oneshot::Sender<OurResult<()>>,
     },
     /// Get camera brightness
@@ -140,6 +193,18 @@ pub enum UsbCameraRequest {
     SetCameraFormat {
         hardware_id: String,
         format: CameraFormatInfo,
+        when_busy: BusyPolicy,
+        respond_to: oneshot::Sender<OurResult<()>>,
+    },
+    /// Hold cameras against control writes for the length of a capture session
+    BeginCaptureSession {
+        session_id: String,
+        hardware_ids: Vec<String>,
+        respond_to: oneshot::Sender<OurResult<()>>,
+    },
+    /// Release a capture session's cameras and apply the writes waiting on them
+    EndCaptureSession {
+        session_id: String,
         respond_to: oneshot::Sender<OurResult<()>>,
     },
     /// Capture streaming frame from specific camera
@@ -147,6 +212,235 @@ pub enum UsbCameraRequest {
         hardware_id: String,
         response_sender: oneshot::Sender<OurResult<Vec<u8>>>,
     },
+    /// Check the open streams for stalls
+    CheckStreamHealth {
+        open_streams: Vec<String>,
+        threshold: Duration,
+        respond_to: oneshot::Sender<OurResult<Vec<StreamStalled>>>,
+    },
+    /// Reset capture counters for a camera
+    /// Answers whether the camera is known
+    ResetCaptureStats {
+        hardware_id: String,
+        respond_to: oneshot::Sender<OurResult<bool>>,
+    },
+    /// Replace the orientation of every camera; cameras not listed are used as-is
+    SetOrientations {
+        orientations: HashMap<String, Orientation>,
+        respond_to: oneshot::Sender<OurResult<()>>,
+    },
+}
+
+impl UsbCameraRequest {
+    /// The request variant name, used as the event kind
+    pub fn kind(&self) -> &'static str {
+        match self {
+            UsbCameraRequest::DetectCameras { .. } => "DetectCameras",
+            UsbCameraRequest::ListCameras { .. } => "ListCameras",
+            UsbCameraRequest::SelectCameras { .. } => "SelectCameras",
+            UsbCameraRequest::StartStreaming { .. } => "StartStreaming",
+            UsbCameraRequest::StopStreaming { .. } => "StopStreaming",
+            UsbCameraRequest::CaptureImage { .. } => "CaptureImage",
+            UsbCameraRequest::GetStatus { .. } => "GetStatus",
+            UsbCameraRequest::SetBrightness { .. } => "SetBrightness",
+            UsbCameraRequest::GetBrightness { .. } => "GetBrightness",
+            UsbCameraRequest::SetCameraFormat { .. } => "SetCameraFormat",
+            UsbCameraRequest::CaptureStreamingFrame { .. } => "CaptureStreamingFrame",
+            UsbCameraRequest::CheckStreamHealth { .. } => "CheckStreamHealth",
+            UsbCameraRequest::ResetCaptureStats { .. } => "ResetCaptureStats",
+            UsbCameraRequest::SetOrientations { .. } => "SetOrientations",
+            UsbCameraRequest::BeginCaptureSession { .. } => "BeginCaptureSession",
+            UsbCameraRequest::EndCaptureSession { .. } => "EndCaptureSession",
+        }
+    }
+
+    /// Compact rendering of the request without its responder handle
+    pub fn summary(&self) -> String {
+        match self {
+            UsbCameraRequest::SelectCameras { hardware_ids, .. } => {
+                format!("SelectCameras {{ hardware_ids: {hardware_ids:?} }}")
+            }
+            UsbCameraRequest::CaptureImage { hardware_id, .. }
+            | UsbCameraRequest::GetBrightness { hardware_id, .. }
+            | UsbCameraRequest::CaptureStreamingFrame { hardware_id, .. }
+            | UsbCameraRequest::ResetCaptureStats { hardware_id, .. } => {
+                format!("{} {{ hardware_id: {hardware_id:?} }}", self.kind())
+            }
+            UsbCameraRequest::SetBrightness {
+                hardware_id,
+                brightness,
+                ..
+            } => format!(
+                "SetBrightness {{ hardware_id: {hardware_id:?}, brightness: {brightness} }}"
+            ),
+            UsbCameraRequest::SetCameraFormat {
+                hardware_id,
+                format,
+                ..
+            } => format!("SetCameraFormat {{ hardware_id: {hardware_id:?}, format: {format:?} }}"),
+            UsbCameraRequest::SetOrientations { orientations, .. } => {
+                format!("SetOrientations {{ orientations: {orientations:?} }}")
+            }
+            UsbCameraRequest::BeginCaptureSession {
+                session_id,
+                hardware_ids,
+                ..
+            } => format!(
+                "BeginCaptureSession {{ session_id: {session_id:?}, hardware_ids: {hardware_ids:?} }}"
+            ),
+            UsbCameraRequest::EndCaptureSession { session_id, .. } => {
+                format!("EndCaptureSession {{ session_id: {session_id:?} }}")
+            }
+            UsbCameraRequest::DetectCameras { deep, .. } => {
+                format!("DetectCameras {{ deep: {deep} }}")
+            }
+            other => other.kind().to_string(),
+        }
+    }
+}
+
+/// Where the manager finds cameras and gets their frames, so it can be run against simulated ones
+trait FrameGrabber: Send + Sync {
+    /// The cameras attached right now. This blocks
+    fn query(&self) -> OurResult<Vec<NokhwaCameraInfo>>;
+
+    /// Open the camera and ask which formats it supports. This blocks
+    fn formats(&self, camera: &UsbCameraInfo) -> OurResult<Vec<CameraFormatInfo>>;
+
+    /// Open the camera, let it settle for `warmup` and take a frame. This blocks
+    fn grab(
+        &self,
+        camera: &UsbCameraInfo,
+        warmup: CaptureWarmup,
+    ) -> OurResult<(Box<dyn RawFrame>, WarmupTiming)>;
+
+    /// Whether the camera is still plugged in, asked after a grab fails. This blocks
+    fn is_attached(&self, camera: &UsbCameraInfo) -> OurResult<bool>;
+}
+
+/// Grabs frames from real cameras through nokhwa
+struct NokhwaGrabber {
+    backend: ApiBackend,
+}
+
+impl FrameGrabber for NokhwaGrabber {
+    fn query(&self) -> OurResult<Vec<NokhwaCameraInfo>> {
+        nokhwa::query(self.backend)
+            .map_err(|e| OurError::App(format!("Failed to query cameras: {e}")))
+    }
+
+    fn formats(&self, camera: &UsbCameraInfo) -> OurResult<Vec<CameraFormatInfo>> {
+        let hardware_id = &camera.hardware_id;
+        let format = RequestedFormat::new::<RgbFormat>(RequestedFormatType::None);
+        let mut device = Camera::new(CameraIndex::Index(camera.index), format)
+            .map_err(|e| OurError::App(format!("{CREATE_FAILED} {hardware_id}: {e}")))?;
+        let formats = device.compatible_camera_formats().map_err(|e| {
+            OurError::App(format!(
+                "Failed to list formats of camera {hardware_id}: {e}"
+            ))
+        })?;
+        Ok(formats
+            .into_iter()
+            .map(|format| CameraFormatInfo {
+                width: format.width(),
+                height: format.height(),
+                fps: format.frame_rate(),
+                format: format.format().to_string(),
+            })
+            .collect())
+    }
+
+    fn grab(
+        &self,
+        camera: &UsbCameraInfo,
+        warmup: CaptureWarmup,
+    ) -> OurResult<(Box<dyn RawFrame>, WarmupTiming)> {
+        let hardware_id = &camera.hardware_id;
+        let camera_index = CameraIndex::Index(camera.index);
+        // Use highest resolution for best quality
+        let format =
+            RequestedFormat::new::<RgbFormat>(RequestedFormatType::AbsoluteHighestResolution);
+        debug!("Opening camera {hardware_id} with index {camera_index} and format {format:?}");
+        let mut device = Camera::new(camera_index, format)
+            .map_err(|e| OurError::App(format!("{CREATE_FAILED} {hardware_id}: {e}")))?;
+        device
+            .open_stream()
+            .map_err(|e| OurError::App(format!("Failed to open camera stream: {e}")))?;
+
+        let result = capture_settled(&mut device, warmup);
+        if let Err(e) = &result {
+            warn!("Failed to capture frame from camera {hardware_id}: {e}");
+        }
+        if let Err(e) = device.stop_stream() {
+            warn!("Failed to stop camera stream: {e}");
+        }
+        let (frame, warmup_timing) = result?;
+        Ok((Box::new(frame), warmup_timing))
+    }
+
+    fn is_attached(&self, camera: &UsbCameraInfo) -> OurResult<bool> {
+        // Indexes shift when another camera is unplugged, so the name is what's matched
+        let attached = self.query()?;
+        Ok(attached.iter().any(|info| info.human_name() == camera.name))
+    }
+}
+
+/// Whether a grab failed because the camera couldn't be opened at its index
+fn could_not_open(error: &OurError) -> bool {
+    error.to_string().contains(CREATE_FAILED)
+}
+
+/// Who is waiting for a capture
+#[derive(Debug)]
+enum CaptureReply {
+    Image(oneshot::Sender<OurResult<Vec<u8>>>),
+    StreamingFrame(oneshot::Sender<OurResult<Vec<u8>>>),
+}
+
+/// A grabbed frame back from processing, for the manager to record and answer
+#[derive(Debug)]
+struct CaptureDone {
+    hardware_id: String,
+    started: Instant,
+    reply: CaptureReply,
+    result: OurResult<(Vec<u8>, WarmupTiming)>,
+    /// How the frame was processed and how long it took, if it got that far
+    processing: Option<(Duration, FramePath)>,
+}
+
+/// A settings change that must not land in the middle of a capture session
+#[derive(Debug)]
+enum ControlWrite {
+    Brightness {
+        hardware_id: String,
+        brightness: i64,
+        respond_to: oneshot::Sender<OurResult<()>>,
+    },
+    Format {
+        hardware_id: String,
+        format: CameraFormatInfo,
+        respond_to: oneshot::Sender<OurResult<()>>,
+    },
+}
+
+impl ControlWrite {
+    fn hardware_id(&self) -> &str {
+        match self {
+            ControlWrite::Brightness { hardware_id, .. }
+            | ControlWrite::Format { hardware_id, .. } => hardware_id,
+        }
+    }
+
+    /// Answer the write with `error` without applying it
+    fn reject(self, error: OurError) {
+        let respond_to = match self {
+            ControlWrite::Brightness { respond_to, .. }
+            | ControlWrite::Format { respond_to, .. } => respond_to,
+        };
+        if respond_to.send(Err(error)).is_err() {
+            debug!("Failed to send camera busy response");
+        }
+    }
 }
 
 /// USB Camera Manager implementation
@@ -159,6 +453,33 @@ pub struct UsbCameraManager {
     backend: ApiBackend,
     /// Software brightness adjustments per camera (hardware_id -> brightness_offset)
     brightness_adjustments: HashMap<String, f32>,
+    /// Rotation and mirroring per camera, applied before encoding
+    orientations: HashMap<String, Orientation>,
+    /// Recorder for incoming requests
+    events: EventRecorder,
+    /// Bumped whenever the camera status changes
+    version: DataVersion,
+    /// Frames discarded after opening a camera that wasn't already streaming
+    warmup: CaptureWarmup,
+    /// When each camera last produced a frame
+    warmup_tracker: WarmupTracker,
+    /// Cameras held by capture sessions, and the control writes waiting on them
+    locks: CameraLocks<ControlWrite>,
+    /// How many streamed frames to keep and which one a capture takes
+    pre_capture: PreCapture,
+    /// Recent streamed frames by hardware ID
+    frame_rings: HashMap<String, FrameRing>,
+    /// Source of camera frames
+    grabber: Arc<dyn FrameGrabber>,
+    /// Cameras deselected because they were unplugged, selected again once they deliver
+    unplugged: HashSet<String>,
+    /// Formats cameras have listed, so detection needn't open them every time
+    formats: FormatCache,
+    /// Reads USB IDs out of camera descriptions
+    descriptions: DescriptionParser,
+    /// Captures whose frames have been processed, sent from the blocking pool
+    done_sender: mpsc::UnboundedSender<CaptureDone>,
+    done_receiver: mpsc::UnboundedReceiver<CaptureDone>,
 }
 
 /// Handle for communicating with USB Camera Manager
@@ -167,155 +488,209 @@ pub struct UsbCameraHandle {
     request_sender: mpsc::UnboundedSender<UsbCameraRequest>,
     #[allow(dead_code)]
     status: Arc<RwLock<UsbCameraStatus>>,
+    version: DataVersion,
 }
 
 impl UsbCameraHandle {
-    /// Detect available USB cameras
-    pub async fn detect_cameras(&self) -> OurResult<Vec<UsbCameraInfo>> {
-        let (sender, receiver) = oneshot::channel();
-        self.request_sender
-            .send(UsbCameraRequest::DetectCameras { respond_to: sender })
-            .map_err(|_| OurError::App("USB camera manager channel closed".to_string()))?;
-        receiver
-            .await
-            .map_err(|_| OurError::App("USB camera manager response failed".to_string()))?
+    /// Bumped whenever the camera list, selection, streaming state or capture stats change
+    pub fn version(&self) -> u64 {
+        self.version.get()
+    }
+
+    /// Detect available USB cameras; `deep` asks every camera that isn't streaming for its formats again
+    pub async fn detect_cameras(&self, deep: bool) -> OurResult<UsbDetection> {
+        request(&self.request_sender, MANAGER_NAME, |respond_to| {
+            UsbCameraRequest::DetectCameras { deep, respond_to }
+        })
+        .await?
     }
 
     /// List currently known cameras
     pub async fn list_cameras(&self) -> OurResult<Vec<UsbCameraInfo>> {
-        let (sender, receiver) = oneshot::channel();
-        self.request_sender
-            .send(UsbCameraRequest::ListCameras { respond_to: sender })
-            .map_err(|_| OurError::App("USB camera manager channel closed".to_string()))?;
-        receiver
-            .await
-            .map_err(|_| OurError::App("USB camera manager response failed".to_string()))?
+        request(&self.request_sender, MANAGER_NAME, |respond_to| {
+            UsbCameraRequest::ListCameras { respond_to }
+        })
+        .await?
     }
 
     /// Select cameras for operations
-    pub async fn select_cameras(&self, hardware_ids: Vec<String>) -> OurResult<()> {
-        let (sender, receiver) = oneshot::channel();
-        self.request_sender
-            .send(UsbCameraRequest::SelectCameras {
+    pub async fn select_cameras(&self, hardware_ids: Vec<CameraId>) -> OurResult<()> {
+        request(&self.request_sender, MANAGER_NAME, |respond_to| {
+            UsbCameraRequest::SelectCameras {
                 hardware_ids,
-                respond_to: sender,
-            })
-            .map_err(|_| OurError::App("USB camera manager channel closed".to_string()))?;
-        receiver
-            .await
-            .map_err(|_| OurError::App("USB camera manager response failed".to_string()))?
+                respond_to,
+            }
+        })
+        .await?
     }
 
     /// Start streaming from selected cameras
     pub async fn start_streaming(&self) -> OurResult<()> {
-        let (sender, receiver) = oneshot::channel();
-        self.request_sender
-            .send(UsbCameraRequest::StartStreaming { respond_to: sender })
-            .map_err(|_| OurError::App("USB camera manager channel closed".to_string()))?;
-        receiver
-            .await
-            .map_err(|_| OurError::App("USB camera manager response failed".to_string()))?
+        request(&self.request_sender, MANAGER_NAME, |respond_to| {
+            UsbCameraRequest::StartStreaming { respond_to }
+        })
+        .await?
     }
 
     /// Capture a single frame from a specific camera for streaming
     pub async fn capture_streaming_frame(&self, hardware_id: &str) -> OurResult<Vec<u8>> {
-        let (request_sender, response_receiver) = oneshot::channel();
-
-        let request = UsbCameraRequest::CaptureStreamingFrame {
-            hardware_id: hardware_id.to_string(),
-            response_sender: request_sender,
-        };
+        request(&self.request_sender, MANAGER_NAME, |response_sender| {
+            UsbCameraRequest::CaptureStreamingFrame {
+                hardware_id: hardware_id.to_string(),
+                response_sender,
+            }
+        })
+        .await?
+    }
 
-        self.request_sender
-            .send(request)
-            .map_err(|_| OurError::App("USB camera manager channel closed".to_string()))?;
-        response_receiver
-            .await
-            .map_err(|_| OurError::App("USB camera manager response failed".to_string()))?
+    /// Check the cameras with open streams for stalls, returning the ones that just stalled
+    pub async fn check_stream_health(
+        &self,
+        open_streams: Vec<String>,
+        threshold: Duration,
+    ) -> OurResult<Vec<StreamStalled>> {
+        request(&self.request_sender, MANAGER_NAME, |respond_to| {
+            UsbCameraRequest::CheckStreamHealth {
+                open_streams,
+                threshold,
+                respond_to,
+            }
+        })
+        .await?
     }
 
     /// Stop all streaming
     pub async fn stop_streaming(&self) -> OurResult<()> {
-        let (sender, receiver) = oneshot::channel();
-        self.request_sender
-            .send(UsbCameraRequest::StopStreaming { respond_to: sender })
-            .map_err(|_| OurError::App("USB camera manager channel closed".to_string()))?;
-        receiver
-            .await
-            .map_err(|_| OurError::App("USB camera manager response failed".to_string()))?
+        request(&self.request_sender, MANAGER_NAME, |respond_to| {
+            UsbCameraRequest::StopStreaming { respond_to }
+        })
+        .await?
     }
 
     /// Get current status including selected cameras and streaming state
     pub async fn get_status(&self) -> OurResult<UsbCameraStatus> {
-        let (sender, receiver) = oneshot::channel();
-        self.request_sender
-            .send(UsbCameraRequest::GetStatus { respond_to: sender })
-            .map_err(|_| OurError::App("USB camera manager channel closed".to_string()))?;
-        receiver
-            .await
-            .map_err(|_| OurError::App("USB camera manager response failed".to_string()))?
+        request(&self.request_sender, MANAGER_NAME, |respond_to| {
+            UsbCameraRequest::GetStatus { respond_to }
+        })
+        .await?
+    }
+
+    /// Reset capture counters for a camera, returning false if there's no such camera
+    pub async fn reset_capture_stats(&self, hardware_id: String) -> OurResult<bool> {
+        request(&self.request_sender, MANAGER_NAME, |respond_to| {
+            UsbCameraRequest::ResetCaptureStats {
+                hardware_id,
+                respond_to,
+            }
+        })
+        .await?
     }
 
     /// Capture image from specific camera
     pub async fn capture_image(&self, hardware_id: String) -> OurResult<Vec<u8>> {
-        let (sender, receiver) = oneshot::channel();
-        self.request_sender
-            .send(UsbCameraRequest::CaptureImage {
+        request(&self.request_sender, MANAGER_NAME, |respond_to| {
+            UsbCameraRequest::CaptureImage {
                 hardware_id,
-                respond_to: sender,
-            })
-            .map_err(|_| OurError::App("USB camera manager channel closed".to_string()))?;
-        receiver
-            .await
-            .map_err(|_| OurError::App("USB camera manager response failed".to_string()))?
+                respond_to,
+            }
+        })
+        .await?
     }
 
-    /// Set camera format
+    /// Set camera format, waiting for or failing on a capture session per `when_busy`
     pub async fn set_camera_format(
         &self,
         hardware_id: String,
         format: CameraFormatInfo,
+        when_busy: BusyPolicy,
     ) -> OurResult<()> {
-        let (sender, receiver) = oneshot::channel();
-        self.request_sender
-            .send(UsbCameraRequest::SetCameraFormat {
+        request(&self.request_sender, MANAGER_NAME, |respond_to| {
+            UsbCameraRequest::SetCameraFormat {
                 hardware_id,
                 format,
-                respond_to: sender,
-            })
-            .map_err(|_| OurError::App("USB camera manager channel closed".to_string()))?;
-        receiver
-            .await
-            .map_err(|_| OurError::App("USB camera manager response failed".to_string()))?
+                when_busy,
+                respond_to,
+            }
+        })
+        .await?
     }
 
-    /// Set camera brightness
-    pub async fn set_brightness(&self, hardware_id: String, brightness: i64) -> OurResult<()> {
-        let (sender, receiver) = oneshot::channel();
-        self.request_sender
-            .send(UsbCameraRequest::SetBrightness {
+    /// Replace the orientation of every camera
+    pub async fn set_orientations(
+        &self,
+        orientations: HashMap<String, Orientation>,
+    ) -> OurResult<()> {
+        request(&self.request_sender, MANAGER_NAME, |respond_to| {
+            UsbCameraRequest::SetOrientations {
+                orientations,
+                respond_to,
+            }
+        })
+        .await?
+    }
+
+    /// Set camera brightness, waiting for or failing on a capture session per `when_busy`
+    pub async fn set_brightness(
+        &self,
+        hardware_id: String,
+        brightness: i64,
+        when_busy: BusyPolicy,
+    ) -> OurResult<()> {
+        request(&self.request_sender, MANAGER_NAME, |respond_to| {
+            UsbCameraRequest::SetBrightness {
                 hardware_id,
                 brightness,
-                respond_to: sender,
-            })
-            .map_err(|_| OurError::App("USB camera manager channel closed".to_string()))?;
-        receiver
-            .await
-            .map_err(|_| OurError::App("USB camera manager response failed".to_string()))?
+                when_busy,
+                respond_to,
+            }
+        })
+        .await?
+    }
+
+    /// Capture from every camera at once, holding them against control writes until all are done
+    ///
+    /// The manager grabs the frames one after another but processes them in
+    /// parallel, so the results come back together.
+    pub async fn capture_session(
+        &self,
+        session_id: &str,
+        hardware_ids: &[String],
+    ) -> OurResult<Vec<(String, OurResult<Vec<u8>>)>> {
+        request(&self.request_sender, MANAGER_NAME, |respond_to| {
+            UsbCameraRequest::BeginCaptureSession {
+                session_id: session_id.to_string(),
+                hardware_ids: hardware_ids.to_vec(),
+                respond_to,
+            }
+        })
+        .await??;
+
+        let results =
+            futures_util::future::join_all(hardware_ids.iter().map(|hardware_id| async move {
+                let result = self.capture_image(hardware_id.clone()).await;
+                (hardware_id.clone(), result)
+            }))
+            .await;
+
+        request(&self.request_sender, MANAGER_NAME, |respond_to| {
+            UsbCameraRequest::EndCaptureSession {
+                session_id: session_id.to_string(),
+                respond_to,
+            }
+        })
+        .await??;
+        Ok(results)
     }
 
     /// Get camera brightness
     pub async fn get_brightness(&self, hardware_id: String) -> OurResult<i64> {
-        let (sender, receiver) = oneshot::channel();
-        self.request_sender
-            .send(UsbCameraRequest::GetBrightness {
+        request(&self.request_sender, MANAGER_NAME, |respond_to| {
+            UsbCameraRequest::GetBrightness {
                 hardware_id,
-                respond_to: sender,
-            })
-            .map_err(|_| OurError::App("USB camera manager channel closed".to_string()))?;
-        receiver
-            .await
-            .map_err(|_| OurError::App("USB camera manager response failed".to_string()))?
+                respond_to,
+            }
+        })
+        .await?
     }
 }
 
@@ -327,6 +702,7 @@ impl UsbCameraManager {
 
     /// Get mutable access to camera status
     async fn get_status_mut(&mut self) -> tokio::sync::RwLockWriteGuard<'_, UsbCameraStatus> {
+        self.version.bump();
         self.status.write().await
     }
 
@@ -341,62 +717,32 @@ impl UsbCameraManager {
             .ok_or_else(|| OurError::App(format!("Camera with ID '{hardware_id}' not found")))
     }
 
-    /// Create a new camera instance with efficient error handling
-    async fn create_camera(&self, hardware_id: &str) -> OurResult<Camera> {
-        let camera_info = self.get_camera_info(hardware_id).await?;
-        let camera_index = CameraIndex::Index(camera_info.index);
-
-        // Use highest resolution for best quality
-        let format =
-            RequestedFormat::new::<RgbFormat>(RequestedFormatType::AbsoluteHighestResolution);
-        debug!(
-            "Creating camera {} with index {} and format {:?}",
-            hardware_id, camera_index, format
-        );
-        // Wrap Camera::new in catch_unwind to handle macOS AVFoundation panics
-        std::panic::catch_unwind(|| Camera::new(camera_index, format))
-            .map_err(|_| OurError::App(format!("Camera creation panicked for {hardware_id} (likely AVFoundation issue on macOS)")))
-            .and_then(|result| result.map_err(|e| OurError::App(format!("Failed to create camera {hardware_id}: {e}"))))
-    }
-
-    /// Apply software brightness adjustment to an image
-    fn apply_brightness_adjustment(&self, image: &mut image::RgbImage, hardware_id: &str) {
-        if let Some(&brightness_offset) = self.brightness_adjustments.get(hardware_id)
-            && brightness_offset != 0.0 {
-                debug!(
-                    "Applying brightness adjustment of {} to camera {}",
-                    brightness_offset, hardware_id
-                );
-
-                // Convert brightness from -100 to +100 range to a multiplier
-                // -100 = 0.0 (black), 0 = 1.0 (no change), +100 = 4.0 (quadruple brightness)
-                // This provides much brighter images for dark cameras like FaceTime
-                let brightness_multiplier = if brightness_offset >= 0.0 {
-                    // For positive adjustments: 0 to +100 maps to 1.0 to 4.0
-                    1.0 + (brightness_offset / 100.0) * 3.0
-                } else {
-                    // For negative adjustments: -100 to 0 maps to 0.0 to 1.0
-                    (brightness_offset + 100.0) / 100.0
-                };
-
-                // Apply brightness adjustment to each pixel
-                for pixel in image.pixels_mut() {
-                    let [r, g, b] = pixel.0;
-
-                    // Apply brightness adjustment and clamp to valid range
-                    let new_r = ((r as f32 * brightness_multiplier).clamp(0.0, 255.0)) as u8;
-                    let new_g = ((g as f32 * brightness_multiplier).clamp(0.0, 255.0)) as u8;
-                    let new_b = ((b as f32 * brightness_multiplier).clamp(0.0, 255.0)) as u8;
-
-                    *pixel = image::Rgb([new_r, new_g, new_b]);
-                }
-            }
+    /// How frames from a camera are processed, from its brightness and orientation
+    fn frame_processing(&self, hardware_id: &str) -> FrameProcessing {
+        FrameProcessing {
+            brightness_offset: self
+                .brightness_adjustments
+                .get(hardware_id)
+                .copied()
+                .unwrap_or(0.0),
+            orientation: self
+                .orientations
+                .get(hardware_id)
+                .copied()
+                .unwrap_or_default(),
+        }
     }
 
     /// Create new USB camera manager
-    pub fn new() -> OurResult<(UsbCameraManager, UsbCameraHandle)> {
+    pub fn new(
+        events: EventRecorder,
+        warmup: CaptureWarmup,
+        pre_capture: PreCapture,
+    ) -> OurResult<(UsbCameraManager, UsbCameraHandle)> {
         let (request_sender, request_receiver) = mpsc::unbounded_channel();
+        let (done_sender, done_receiver) = mpsc::unbounded_channel();
         let status = Arc::new(RwLock::new(UsbCameraStatus::default()));
+        let version = DataVersion::default();
 
         let backend = Self::select_best_backend()?;
 
@@ -405,11 +751,26 @@ impl UsbCameraManager {
             request_receiver,
             backend,
             brightness_adjustments: HashMap::new(),
+            orientations: HashMap::new(),
+            events,
+            version: version.clone(),
+            warmup,
+            warmup_tracker: WarmupTracker::default(),
+            locks: CameraLocks::default(),
+            pre_capture,
+            frame_rings: HashMap::new(),
+            unplugged: HashSet::new(),
+            formats: FormatCache::default(),
+            descriptions: DescriptionParser::new()?,
+            grabber: Arc::new(NokhwaGrabber { backend }),
+            done_sender,
+            done_receiver,
         };
 
         let handle = UsbCameraHandle {
             request_sender,
             status,
+            version,
         };
 
         Ok((manager, handle))
@@ -448,9 +809,16 @@ impl UsbCameraManager {
         // Detection will happen on-demand when detect_cameras is called
         info!("USB camera manager ready - camera detection will happen on-demand");
 
-        // Main event loop - simplified without tokio::select!
-        while let Some(request) = self.request_receiver.recv().await {
-            self.handle_request(request).await;
+        // Frames are processed off this loop, so requests keep being answered
+        // while captures finish in the background
+        loop {
+            tokio::select! {
+                request = self.request_receiver.recv() => match request {
+                    Some(request) => self.handle_request(request).await,
+                    None => break,
+                },
+                Some(done) = self.done_receiver.recv() => self.finish_capture(done).await,
+            }
         }
 
         info!("USB camera manager shutting down");
@@ -459,13 +827,14 @@ impl UsbCameraManager {
 
     /// Handle a single camera request
     async fn handle_request(&mut self, request: UsbCameraRequest) {
+        // The periodic stall check would crowd everything else out of the event log
+        if !matches!(request, UsbCameraRequest::CheckStreamHealth { .. }) {
+            self.events
+                .record("usb_camera_manager", request.kind(), request.summary());
+        }
         match request {
-            UsbCameraRequest::DetectCameras { respond_to } => {
-                let result = self.detect_cameras_internal().await;
-                let _cameras = match &result {
-                    Ok(cameras) => cameras.clone(),
-                    Err(_) => Vec::new(),
-                };
+            UsbCameraRequest::DetectCameras { deep, respond_to } => {
+                let result = self.detect_cameras_internal(deep).await;
                 if respond_to.send(result).is_err() {
                     debug!("Failed to send camera detection response");
                 }
@@ -501,9 +870,20 @@ impl UsbCameraManager {
                 hardware_id,
                 respond_to,
             } => {
-                let result = self.capture_image_internal(&hardware_id).await;
-                if respond_to.send(result).is_err() {
-                    debug!("Failed to send image capture response");
+                let started = Instant::now();
+                match self.buffered_frame(&hardware_id, started) {
+                    Some(frame) => {
+                        let result = self
+                            .record_capture(hardware_id, Ok(frame), started.elapsed(), None)
+                            .await;
+                        if respond_to.send(result).is_err() {
+                            debug!("Failed to send image capture response");
+                        }
+                    }
+                    None => {
+                        self.start_capture(hardware_id, started, CaptureReply::Image(respond_to))
+                            .await;
+                    }
                 }
             }
             UsbCameraRequest::GetStatus { respond_to } => {
@@ -515,31 +895,88 @@ impl UsbCameraManager {
             UsbCameraRequest::SetCameraFormat {
                 hardware_id,
                 format,
+                when_busy,
                 respond_to,
             } => {
-                let result = self.set_camera_format_internal(&hardware_id, format).await;
+                let write = ControlWrite::Format {
+                    hardware_id,
+                    format,
+                    respond_to,
+                };
+                self.admit_control_write(write, when_busy).await;
+            }
+            UsbCameraRequest::BeginCaptureSession {
+                session_id,
+                hardware_ids,
+                respond_to,
+            } => {
+                let result = self.locks.acquire(&session_id, &hardware_ids);
+                self.get_status_mut().await.capture_locks = self.locks.holders();
                 if respond_to.send(result).is_err() {
-                    debug!("Failed to send camera format response");
+                    debug!("Failed to send capture session start response");
+                }
+            }
+            UsbCameraRequest::EndCaptureSession {
+                session_id,
+                respond_to,
+            } => {
+                let waiting = self.locks.release(&session_id);
+                self.get_status_mut().await.capture_locks = self.locks.holders();
+                if !waiting.is_empty() {
+                    debug!(
+                        "Applying {} control writes held back by capture session {session_id}",
+                        waiting.len()
+                    );
+                }
+                for write in waiting {
+                    self.apply_control_write(write).await;
+                }
+                if respond_to.send(Ok(())).is_err() {
+                    debug!("Failed to send capture session end response");
                 }
             }
             UsbCameraRequest::CaptureStreamingFrame {
                 hardware_id,
                 response_sender,
             } => {
-                let result = self.capture_streaming_frame_internal(&hardware_id).await;
-                if response_sender.send(result).is_err() {
-                    debug!("Failed to send streaming frame response");
+                self.start_capture(
+                    hardware_id,
+                    Instant::now(),
+                    CaptureReply::StreamingFrame(response_sender),
+                )
+                .await;
+            }
+            UsbCameraRequest::CheckStreamHealth {
+                open_streams,
+                threshold,
+                respond_to,
+            } => {
+                let stalled = self.check_stream_health(&open_streams, threshold).await;
+                if respond_to.send(Ok(stalled)).is_err() {
+                    debug!("Failed to send stream health response");
+                }
+            }
+            UsbCameraRequest::SetOrientations {
+                orientations,
+                respond_to,
+            } => {
+                self.orientations = orientations;
+                if respond_to.send(Ok(())).is_err() {
+                    debug!("Failed to send orientation response");
                 }
             }
             UsbCameraRequest::SetBrightness {
                 hardware_id,
                 brightness,
+                when_busy,
                 respond_to,
             } => {
-                let result = self.set_brightness_internal(&hardware_id, brightness).await;
-                if respond_to.send(result).is_err() {
-                    debug!("Failed to send brightness set response");
-                }
+                let write = ControlWrite::Brightness {
+                    hardware_id,
+                    brightness,
+                    respond_to,
+                };
+                self.admit_control_write(write, when_busy).await;
             }
             UsbCameraRequest::GetBrightness {
                 hardware_id,
@@ -550,44 +987,465 @@ impl UsbCameraManager {
                     debug!("Failed to send brightness get response");
                 }
             }
+            UsbCameraRequest::ResetCaptureStats {
+                hardware_id,
+                respond_to,
+            } => {
+                let mut status = self.get_status_mut().await;
+                let known = status.cameras.contains_key(&hardware_id);
+                status.capture_stats.remove(&hardware_id);
+                drop(status);
+                if respond_to.send(Ok(known)).is_err() {
+                    debug!("Failed to send capture stats reset response");
+                }
+            }
         }
     }
 
-    // Implementation methods continue...
-    async fn detect_cameras_internal(&mut self) -> OurResult<Vec<UsbCameraInfo>> {
-        info!("Detecting USB cameras with backend: {:?}", self.backend);
-
-        // Use spawn_blocking with timeout to prevent hanging
-        let backend = self.backend;
-        let cameras = tokio::time::timeout(
-            std::time::Duration::from_secs(2), // 2 second timeout for faster API response
-            tokio::task::spawn_blocking(move || nokhwa::query(backend)),
-        )
-        .await;
-
-        let cameras = match cameras {
-            Ok(Ok(Ok(camera_list))) => camera_list,
-            Ok(Ok(Err(e))) => {
-                error!("Failed to query cameras: {e}");
-                return Err(OurError::App(format!("Failed to query cameras: {e}")));
-            }
-            Ok(Err(e)) => {
-                error!("Camera detection task panicked: {e}");
-                return Err(OurError::App(format!(
-                    "Camera detection task panicked: {e}"
-                )));
+    /// Grab a frame and send it to the blocking pool for processing
+    ///
+    /// The capture is answered by [`Self::finish_capture`] once the processed
+    /// frame comes back on the done channel.
+    async fn start_capture(&mut self, hardware_id: String, started: Instant, reply: CaptureReply) {
+        let mut grabbed = self.grab_frame(&hardware_id).await;
+        if grabbed.as_ref().is_err_and(could_not_open) && self.rematch_indexes(&hardware_id).await {
+            // Once, so a camera that really is gone fails promptly
+            grabbed = self.grab_frame(&hardware_id).await;
+        }
+        let (frame, warmup_timing) = match grabbed {
+            Ok(grabbed) => {
+                self.mark_attached(&hardware_id).await;
+                grabbed
             }
-            Err(_) => {
-                error!("Camera detection timed out after 2 seconds");
-                return Err(OurError::App("Camera detection timed out".to_string()));
+            Err(e) => {
+                let e = self.check_attached(&hardware_id, e).await;
+                let done = CaptureDone {
+                    hardware_id,
+                    started,
+                    reply,
+                    result: Err(e),
+                    processing: None,
+                };
+                self.finish_capture(done).await;
+                return;
             }
         };
 
+        let processing = self.frame_processing(&hardware_id);
+        let done_sender = self.done_sender.clone();
+        tokio::task::spawn_blocking(move || {
+            let processed = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+                process_frame(frame, processing)
+            }))
+            .map_err(|_| OurError::App(format!("Processing a frame from {hardware_id} panicked")))
+            .and_then(|result| result);
+            let (result, processing) = match processed {
+                Ok(processed) => {
+                    debug!(
+                        "Frame from {hardware_id} took the {:?} path with {processing:?}",
+                        processed.path
+                    );
+                    (
+                        Ok((processed.jpeg, warmup_timing)),
+                        Some((processed.duration, processed.path)),
+                    )
+                }
+                Err(e) => (Err(e), None),
+            };
+            let done = CaptureDone {
+                hardware_id,
+                started,
+                reply,
+                result,
+                processing,
+            };
+            if done_sender.send(done).is_err() {
+                debug!("USB camera manager stopped before a frame was processed");
+            }
+        });
+    }
+
+    /// Grab a frame from a camera on the blocking pool
+    async fn grab_frame(&self, hardware_id: &str) -> OurResult<(Box<dyn RawFrame>, WarmupTiming)> {
+        let camera_info = self.get_camera_info(hardware_id).await?;
+        let warmup = self
+            .warmup_tracker
+            .warmup_for(hardware_id, self.warmup, Instant::now());
+        let grabber = self.grabber.clone();
+        // Catch panics from the camera backend, such as AVFoundation on macOS
+        tokio::task::spawn_blocking(move || {
+            std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+                grabber.grab(&camera_info, warmup)
+            }))
+            .map_err(|_| {
+                OurError::App(format!(
+                    "Camera operation panicked for {} (likely AVFoundation issue on macOS)",
+                    camera_info.hardware_id
+                ))
+            })
+            .and_then(|result| result)
+        })
+        .await
+        .map_err(|e| OurError::App(format!("Camera task failed: {e}")))?
+    }
+
+    /// After a camera couldn't be opened, look for it at a new index
+    ///
+    /// A USB hub that resets brings its cameras back with the same hardware IDs
+    /// but the indexes shuffled, so every known camera is matched to its index
+    /// again. Selections and settings are keyed by hardware ID and stay put.
+    /// Returns whether `hardware_id` moved, so opening it is worth another try.
+    async fn rematch_indexes(&mut self, hardware_id: &str) -> bool {
+        let grabber = self.grabber.clone();
+        let queried = tokio::time::timeout(
+            DETECTION_TIMEOUT,
+            tokio::task::spawn_blocking(move || grabber.query()),
+        )
+        .await;
+        let attached = match queried {
+            Ok(Ok(Ok(attached))) => attached,
+            Ok(Ok(Err(e))) => {
+                debug!("Couldn't look for USB camera {hardware_id} at a new index: {e}");
+                return false;
+            }
+            Ok(Err(e)) => {
+                debug!("Re-detection for USB camera {hardware_id} failed: {e}");
+                return false;
+            }
+            Err(_) => {
+                debug!("Re-detection for USB camera {hardware_id} timed out");
+                return false;
+            }
+        };
+        let found: Vec<UsbCameraInfo> = attached
+            .iter()
+            .enumerate()
+            .map(|(index, camera_info)| self.create_camera_info(index as u32, camera_info))
+            .collect();
+
+        let mut moved = Vec::new();
+        {
+            let mut status = self.get_status_mut().await;
+            for found in &found {
+                if let Some(camera) = status.cameras.get_mut(&found.hardware_id)
+                    && camera.index != found.index
+                {
+                    moved.push((found.hardware_id.clone(), camera.index, found.index));
+                    camera.index = found.index;
+                }
+            }
+            for (moved_id, _, _) in &moved {
+                status
+                    .capture_stats
+                    .entry(moved_id.clone())
+                    .or_default()
+                    .index_recoveries += 1;
+            }
+        }
+        for (moved_id, from, to) in &moved {
+            info!("USB camera {moved_id} re-enumerated from index {from} to {to}");
+            self.events.record(
+                "usb_camera_manager",
+                "CameraIndexChanged",
+                format!(
+                    "CameraIndexChanged {{ hardware_id: {moved_id:?}, from: {from}, to: {to} }}"
+                ),
+            );
+        }
+        moved.iter().any(|(moved_id, _, _)| moved_id == hardware_id)
+    }
+
+    /// After a failed grab, mark the camera disconnected if it was unplugged
+    ///
+    /// Returns the error to answer the capture with, which says so when the camera is gone.
+    async fn check_attached(&mut self, hardware_id: &str, error: OurError) -> OurError {
+        let Ok(camera) = self.get_camera_info(hardware_id).await else {
+            return error;
+        };
+        let grabber = self.grabber.clone();
+        let attached = tokio::time::timeout(
+            DETECTION_TIMEOUT,
+            tokio::task::spawn_blocking(move || grabber.is_attached(&camera)),
+        )
+        .await;
+        match attached {
+            Ok(Ok(Ok(false))) => {
+                warn!("USB camera {hardware_id} is no longer attached: {error}");
+                let deselected = {
+                    let mut status = self.get_status_mut().await;
+                    status.last_detection = Some(chrono::Utc::now());
+                    status
+                        .cameras
+                        .get_mut(hardware_id)
+                        .filter(|camera| camera.connected)
+                        .map(|camera| camera.connected = false)
+                        .is_some()
+                };
+                if deselected {
+                    self.unplugged.insert(hardware_id.to_string());
+                }
+                self.events.record(
+                    "usb_camera_manager",
+                    "CameraDisconnected",
+                    format!("CameraDisconnected {{ hardware_id: {hardware_id:?} }}"),
+                );
+                OurError::Camera(format!("USB camera {hardware_id} is disconnected: {error}"))
+            }
+            Ok(Ok(Ok(true))) => error,
+            Ok(Ok(Err(e))) => {
+                debug!("Couldn't check whether USB camera {hardware_id} is attached: {e}");
+                error
+            }
+            Ok(Err(e)) => {
+                debug!("Attachment check for USB camera {hardware_id} failed: {e}");
+                error
+            }
+            Err(_) => {
+                debug!("Attachment check for USB camera {hardware_id} timed out");
+                error
+            }
+        }
+    }
+
+    /// Mark an unplugged camera connected again once it delivers a frame
+    async fn mark_attached(&mut self, hardware_id: &str) {
+        if self.unplugged.remove(hardware_id) {
+            info!("USB camera {hardware_id} is attached again");
+            if let Some(camera) = self.get_status_mut().await.cameras.get_mut(hardware_id) {
+                camera.connected = true;
+            }
+        }
+    }
+
+    /// Record a finished capture and answer whoever asked for it
+    async fn finish_capture(&mut self, done: CaptureDone) {
+        let CaptureDone {
+            hardware_id,
+            started,
+            reply,
+            result,
+            processing,
+        } = done;
+        match reply {
+            CaptureReply::Image(respond_to) => {
+                let result = self
+                    .record_capture(hardware_id, result, started.elapsed(), processing)
+                    .await;
+                if respond_to.send(result).is_err() {
+                    debug!("Failed to send image capture response");
+                }
+            }
+            CaptureReply::StreamingFrame(response_sender) => {
+                let streaming = {
+                    let mut status = self.get_status_mut().await;
+                    let health = status.stream_health.entry(hardware_id.clone()).or_default();
+                    match &result {
+                        Ok(_) => health.record_frames(1, Instant::now()),
+                        Err(_) => health.record_failure(),
+                    }
+                    status.streaming
+                };
+                let result = self
+                    .record_capture(hardware_id.clone(), result, started.elapsed(), processing)
+                    .await;
+                // Streaming may have stopped while the frame was processed
+                if let Ok(jpeg_data) = &result
+                    && streaming
+                {
+                    self.buffer_frame(&hardware_id, jpeg_data).await;
+                }
+                if response_sender.send(result).is_err() {
+                    debug!("Failed to send streaming frame response");
+                }
+            }
+        }
+    }
+
+    /// Apply a control write now, unless a capture session holds its camera
+    async fn admit_control_write(&mut self, write: ControlWrite, when_busy: BusyPolicy) {
+        let hardware_id = write.hardware_id().to_string();
+        match self.locks.admit(&hardware_id, when_busy, write) {
+            Admission::Proceed(write) => self.apply_control_write(write).await,
+            Admission::Queued => {
+                debug!("Holding control write for camera {hardware_id} until its capture finishes");
+            }
+            Admission::Busy(write, error) => {
+                info!("Rejecting control write: {error}");
+                write.reject(error);
+            }
+        }
+    }
+
+    async fn apply_control_write(&mut self, write: ControlWrite) {
+        match write {
+            ControlWrite::Brightness {
+                hardware_id,
+                brightness,
+                respond_to,
+            } => {
+                let result = self.set_brightness_internal(&hardware_id, brightness).await;
+                if respond_to.send(result).is_err() {
+                    debug!("Failed to send brightness set response");
+                }
+            }
+            ControlWrite::Format {
+                hardware_id,
+                format,
+                respond_to,
+            } => {
+                let result = self.set_camera_format_internal(&hardware_id, format).await;
+                if respond_to.send(result).is_err() {
+                    debug!("Failed to send camera format response");
+                }
+            }
+        }
+    }
+
+    /// Watch the open streams of known cameras and record the ones that just stalled
+    async fn check_stream_health(
+        &mut self,
+        open_streams: &[String],
+        threshold: Duration,
+    ) -> Vec<StreamStalled> {
+        let mut status = self.get_status_mut().await;
+        let open_streams: Vec<String> = open_streams
+            .iter()
+            .filter(|hardware_id| status.cameras.contains_key(*hardware_id))
+            .cloned()
+            .collect();
+        let stalled = check_streams(
+            &mut status.stream_health,
+            &open_streams,
+            Instant::now(),
+            threshold,
+        );
+        drop(status);
+
+        for stall in &stalled {
+            warn!(
+                "USB camera {} stream stalled: no frame for {}s",
+                stall.camera_id,
+                threshold.as_secs()
+            );
+            self.events.record(
+                "usb_camera_manager",
+                "StreamStalled",
+                format!("StreamStalled {{ camera_id: {:?} }}", stall.camera_id),
+            );
+        }
+        stalled
+    }
+
+    /// Keep a streamed frame in the camera's pre-capture buffer, if buffering is on
+    async fn buffer_frame(&mut self, hardware_id: &str, jpeg_data: &[u8]) {
+        if !self.pre_capture.enabled() {
+            return;
+        }
+        let capacity = self.pre_capture.frames;
+        let ring = self
+            .frame_rings
+            .entry(hardware_id.to_string())
+            .or_insert_with(|| FrameRing::new(capacity));
+        ring.push(jpeg_data.to_vec(), Instant::now());
+        let stats = ring.stats();
+        self.get_status_mut()
+            .await
+            .pre_capture
+            .insert(hardware_id.to_string(), stats);
+    }
+
+    /// The buffered frame taken closest to the capture offset before `requested_at`
+    fn buffered_frame(
+        &self,
+        hardware_id: &str,
+        requested_at: Instant,
+    ) -> Option<(Vec<u8>, WarmupTiming)> {
+        let target = requested_at.checked_sub(self.pre_capture.offset)?;
+        let frame = self.frame_rings.get(hardware_id)?.closest_to(target)?;
+        debug!(
+            "Using buffered frame from {}ms before the capture of {hardware_id}",
+            self.pre_capture.offset.as_millis()
+        );
+        Some((frame.to_vec(), WarmupTiming::default()))
+    }
+
+    /// Update the capture counters and warm-up tracking for a camera
+    async fn record_capture(
+        &mut self,
+        hardware_id: String,
+        result: OurResult<(Vec<u8>, WarmupTiming)>,
+        latency: std::time::Duration,
+        processing: Option<(Duration, FramePath)>,
+    ) -> OurResult<Vec<u8>> {
+        if result.is_ok() {
+            self.warmup_tracker
+                .record_frame(&hardware_id, Instant::now());
+        }
+        let mut status = self.get_status_mut().await;
+        let stats = status.capture_stats.entry(hardware_id).or_default();
+        stats.record(&result, latency);
+        if let Ok((_, warmup)) = &result {
+            stats.record_warmup(*warmup);
+        }
+        if let Some((processing, path)) = processing {
+            stats.record_processing(processing, path);
+        }
+        result.map(|(jpeg_data, _)| jpeg_data)
+    }
+
+    // Implementation methods continue...
+    async fn detect_cameras_internal(&mut self, deep: bool) -> OurResult<UsbDetection> {
+        info!("Detecting USB cameras with backend: {:?}", self.backend);
+        let started = Instant::now();
+
+        // Use spawn_blocking with timeout to prevent hanging
+        let grabber = self.grabber.clone();
+        let cameras = tokio::time::timeout(
+            DETECTION_TIMEOUT,
+            tokio::task::spawn_blocking(move || grabber.query()),
+        )
+        .await;
+
+        let cameras = match cameras {
+            Ok(Ok(Ok(camera_list))) => camera_list,
+            Ok(Ok(Err(e))) => {
+                error!("{e}");
+                return Err(e);
+            }
+            Ok(Err(e)) => {
+                error!("Camera detection task panicked: {e}");
+                return Err(OurError::App(format!(
+                    "Camera detection task panicked: {e}"
+                )));
+            }
+            Err(_) => {
+                error!("Camera detection timed out after 2 seconds");
+                return Err(OurError::App("Camera detection timed out".to_string()));
+            }
+        };
+
+        // What was known before, so streaming cameras keep their formats without being opened
+        let (streaming, known) = {
+            let status = self.get_status().await;
+            (status.streaming, status.cameras.clone())
+        };
+
         let mut detected_cameras = Vec::new();
         let mut detected_hardware_ids = std::collections::HashSet::new();
+        let mut report = DetectionReport {
+            deep,
+            ..DetectionReport::default()
+        };
 
         for (index, camera_info) in cameras.iter().enumerate() {
-            let usb_camera_info = self.create_camera_info(index as u32, camera_info).await;
+            let mut usb_camera_info = self.create_camera_info(index as u32, camera_info);
+            let known = known
+                .get(&usb_camera_info.hardware_id)
+                .filter(|known| streaming && known.connected);
+            let source = self.load_formats(&mut usb_camera_info, known, deep).await;
+            report
+                .formats
+                .insert(usb_camera_info.hardware_id.clone(), source);
             detected_cameras.push(usb_camera_info.clone());
             detected_hardware_ids.insert(usb_camera_info.hardware_id.clone());
 
@@ -617,98 +1475,112 @@ impl UsbCameraManager {
             status.cameras.remove(&hardware_id);
         }
 
+        report.duration_ms = u64::try_from(started.elapsed().as_millis()).unwrap_or(u64::MAX);
         // Update last detection time
         {
             let mut status = self.get_status_mut().await;
             status.last_detection = Some(chrono::Utc::now());
+            status.detection = Some(report.clone());
         }
 
-        info!("Detected {} USB cameras", detected_cameras.len());
-        Ok(detected_cameras)
+        info!(
+            "Detected {} USB cameras in {}ms ({} formats fresh, {} cached)",
+            detected_cameras.len(),
+            report.duration_ms,
+            report.count(FormatSource::Fresh),
+            report.count(FormatSource::Cached)
+        );
+        Ok(UsbDetection {
+            cameras: detected_cameras,
+            report,
+        })
     }
 
-    /// Create camera info from nokhwa camera info
-    async fn create_camera_info(
-        &self,
-        index: u32,
-        camera_info: &NokhwaCameraInfo,
-    ) -> UsbCameraInfo {
-        // Extract hardware identification
-        let (vendor_id, product_id, serial_number) =
-            self.extract_hardware_identifiers(index, camera_info);
-        let hardware_id =
-            self.generate_hardware_id(index, camera_info, &vendor_id, &product_id, &serial_number);
+    /// Fill in the camera's formats, only opening it when nothing usable is known
+    ///
+    /// `streaming` is what was known about the camera if it is streaming; it is
+    /// never opened, whatever `deep` says.
+    async fn load_formats(
+        &mut self,
+        camera: &mut UsbCameraInfo,
+        streaming: Option<&UsbCameraInfo>,
+        deep: bool,
+    ) -> FormatSource {
+        let now = Instant::now();
+        if let Some(streaming) = streaming {
+            camera.supported_formats = self
+                .formats
+                .get(&camera.hardware_id, now)
+                .map(<[_]>::to_vec)
+                .unwrap_or_else(|| streaming.supported_formats.clone());
+            camera.current_format = streaming.current_format.clone();
+            return FormatSource::Streaming;
+        }
+        if !deep && let Some(formats) = self.formats.get(&camera.hardware_id, now) {
+            camera.supported_formats = formats.to_vec();
+            return FormatSource::Cached;
+        }
 
-        // Try to get supported formats
-        let supported_formats = self.get_camera_formats(index).await.unwrap_or_default();
+        let grabber = self.grabber.clone();
+        let device = camera.clone();
+        let asked = tokio::time::timeout(
+            DETECTION_TIMEOUT,
+            tokio::task::spawn_blocking(move || grabber.formats(&device)),
+        )
+        .await;
+        let problem = match asked {
+            Ok(Ok(Ok(formats))) if !formats.is_empty() => {
+                self.formats
+                    .insert(camera.hardware_id.clone(), formats.clone(), Instant::now());
+                camera.supported_formats = formats;
+                return FormatSource::Fresh;
+            }
+            Ok(Ok(Ok(_))) => "it listed none".to_string(),
+            Ok(Ok(Err(e))) => e.to_string(),
+            Ok(Err(e)) => format!("format query task panicked: {e}"),
+            Err(_) => "timed out".to_string(),
+        };
+        // Not cached, so the next detection asks again
+        warn!(
+            "Failed to get formats of camera {}, listing common ones: {problem}",
+            camera.hardware_id
+        );
+        camera.supported_formats = common_formats();
+        FormatSource::Fallback
+    }
+
+    /// Create camera info from nokhwa camera info, without its formats
+    fn create_camera_info(&self, index: u32, camera_info: &NokhwaCameraInfo) -> UsbCameraInfo {
+        let ids = self.extract_hardware_identifiers(index, camera_info);
+        let hardware_id = self.generate_hardware_id(index, camera_info, &ids);
 
         UsbCameraInfo {
             index,
             name: camera_info.human_name().to_string(),
-            vendor_id,
-            product_id,
-            serial_number,
+            vendor_id: ids.vendor_id,
+            product_id: ids.product_id,
+            serial_number: ids.serial_number,
             hardware_id,
             connected: true,
-            supported_formats,
+            supported_formats: Vec::new(),
             current_format: None,
         }
     }
 
-    /// Extract hardware identifiers from system
+    /// Extract hardware identifiers from the camera description
     fn extract_hardware_identifiers(
         &self,
         index: u32,
         camera_info: &NokhwaCameraInfo,
-    ) -> (Option<String>, Option<String>, Option<String>) {
-        // For now, extract what we can from the camera description
-        // TODO: Implement platform-specific hardware ID extraction
+    ) -> HardwareIds {
         let desc = camera_info.description();
         debug!("Extracting hardware info for camera {}: {}", index, desc);
 
-        // Try to parse vendor/product IDs from description if available
-        // Many cameras include this in their description string
-        let vendor_id = self.parse_vendor_id_from_description(desc);
-        let product_id = self.parse_product_id_from_description(desc);
-        let serial_number = self.parse_serial_from_description(desc);
-
-        (vendor_id, product_id, serial_number)
-    }
-
-    /// Parse vendor ID from camera description
-    fn parse_vendor_id_from_description(&self, description: &str) -> Option<String> {
-        // Look for common patterns like "VID_1234" or "Vendor:1234"
-        if let Some(captures) = regex::Regex::new(r"(?i)vid[_:]([0-9a-f]{4})")
-            .ok()?
-            .captures(description)
-        {
-            return captures.get(1).map(|m| m.as_str().to_uppercase());
-        }
-        None
-    }
-
-    /// Parse product ID from camera description
-    fn parse_product_id_from_description(&self, description: &str) -> Option<String> {
-        // Look for common patterns like "PID_5678" or "Product:5678"
-        if let Some(captures) = regex::Regex::new(r"(?i)pid[_:]([0-9a-f]{4})")
-            .ok()?
-            .captures(description)
-        {
-            return captures.get(1).map(|m| m.as_str().to_uppercase());
-        }
-        None
-    }
-
-    /// Parse serial number from camera description
-    fn parse_serial_from_description(&self, description: &str) -> Option<String> {
-        // Look for serial number patterns
-        if let Some(captures) = regex::Regex::new(r"(?i)s[en]r?[_:]([0-9a-f]+)")
-            .ok()?
-            .captures(description)
-        {
-            return captures.get(1).map(|m| m.as_str().to_uppercase());
-        }
-        None
+        // The camera still works without IDs, it just isn't told apart by them
+        self.descriptions.parse(desc).unwrap_or_else(|e| {
+            warn!("Ignoring the USB IDs in camera {index}'s description {desc:?}: {e}");
+            HardwareIds::default()
+        })
     }
 
     /// Generate stable hardware ID for camera
@@ -716,22 +1588,23 @@ impl UsbCameraManager {
         &self,
         index: u32,
         camera_info: &NokhwaCameraInfo,
-        vendor_id: &Option<String>,
-        product_id: &Option<String>,
-        serial_number: &Option<String>,
+        ids: &HardwareIds,
     ) -> String {
         // Create stable identifier based on available hardware info
         let mut parts = vec![USB_DEVICE_PREFIX.to_string()];
 
-        if let (Some(vid), Some(pid)) = (vendor_id, product_id) {
+        if let (Some(vid), Some(pid)) = (&ids.vendor_id, &ids.product_id) {
             parts.push(format!("{vid}:{pid}"));
 
-            if let Some(serial) = serial_number {
+            if let Some(serial) = &ids.serial_number {
                 parts.push(serial.clone());
             } else {
                 // Use camera name as fallback if no serial
                 parts.push(camera_info.human_name().replace(' ', "_").to_lowercase());
             }
+        } else if let Some(bus_path) = &ids.bus_path {
+            // The same port keeps the same ID, whichever index the camera gets
+            parts.push(bus_path.clone());
         } else {
             // Fallback to description-based ID
             let desc = camera_info.description().replace(' ', "_").to_lowercase();
@@ -741,48 +1614,6 @@ impl UsbCameraManager {
         parts.join(":")
     }
 
-    /// Get supported camera formats
-    async fn get_camera_formats(&self, index: u32) -> OurResult<Vec<CameraFormatInfo>> {
-        debug!("Getting default formats for camera {}", index);
-
-        // Instead of trying to access the camera (which can panic or fail),
-        // return a reasonable set of common formats that most USB cameras support
-        let default_formats = vec![
-            CameraFormatInfo {
-                width: 320,
-                height: 240,
-                fps: 30,
-                format: "MJPEG".to_string(),
-            },
-            CameraFormatInfo {
-                width: 640,
-                height: 480,
-                fps: 30,
-                format: "MJPEG".to_string(),
-            },
-            CameraFormatInfo {
-                width: 1280,
-                height: 720,
-                fps: 30,
-                format: "MJPEG".to_string(),
-            },
-            CameraFormatInfo {
-                width: 1920,
-                height: 1080,
-                fps: 30,
-                format: "MJPEG".to_string(),
-            },
-        ];
-
-        debug!(
-            "Returning {} default formats for camera {}",
-            default_formats.len(),
-            index
-        );
-
-        Ok(default_formats)
-    }
-
     /// List currently known cameras
     async fn list_cameras_internal(&self) -> Vec<UsbCameraInfo> {
         let status = self.get_status().await;
@@ -790,7 +1621,11 @@ impl UsbCameraManager {
     }
 
     /// Select cameras for operations
-    async fn select_cameras_internal(&mut self, hardware_ids: Vec<String>) -> OurResult<()> {
+    async fn select_cameras_internal(&mut self, hardware_ids: Vec<CameraId>) -> OurResult<()> {
+        let hardware_ids: Vec<String> = hardware_ids
+            .into_iter()
+            .map(CameraId::into_string)
+            .collect();
         let mut status = self.get_status_mut().await;
 
         // Validate that all requested cameras exist
@@ -834,130 +1669,15 @@ impl UsbCameraManager {
             camera.stop()
         });
         status.streaming = false;
+        status.pre_capture.clear();
+        drop(status);
+        self.warmup_tracker.clear();
+        self.frame_rings.clear();
 
         info!("Disabled streaming for {} cameras", camera_count);
         Ok(())
     }
 
-    /// Capture streaming frame from specific camera
-    async fn capture_streaming_frame_internal(&mut self, hardware_id: &str) -> OurResult<Vec<u8>> {
-        // Get camera info and brightness adjustment
-        let camera_info = self.get_camera_info(hardware_id).await?.clone();
-        let brightness_offset = self
-            .brightness_adjustments
-            .get(hardware_id)
-            .copied()
-            .unwrap_or(0.0);
-        let hardware_id = hardware_id.to_string();
-
-        // Move entire camera operation to blocking task to handle AVFoundation panics
-        tokio::task::spawn_blocking(move || {
-            std::panic::catch_unwind(|| {
-                let camera_index = CameraIndex::Index(camera_info.index);
-                let format = RequestedFormat::new::<RgbFormat>(RequestedFormatType::AbsoluteHighestResolution);
-                // Create camera
-                let mut camera = Camera::new(camera_index, format)
-                    .map_err(|e| OurError::App(format!("Failed to create camera {hardware_id}: {e}")))?;
-
-                // Open camera stream
-                camera
-                    .open_stream()
-                    .map_err(|e| OurError::App(format!("Failed to open camera stream: {e}")))?;
-
-                let result = match camera.frame() {
-                    Ok(frame) => {
-                        // Convert frame to RGB image
-                        let mut image = frame
-                            .decode_image::<RgbFormat>()
-                            .map_err(|e| OurError::App(format!("Failed to decode frame: {e}")))?;
-
-                        // Apply software brightness adjustment if needed
-                        if brightness_offset != 0.0 {
-                            let brightness_multiplier = if brightness_offset >= 0.0 {
-                                1.0 + (brightness_offset / 100.0) * 3.0
-                            } else {
-                                1.0 + (brightness_offset / 100.0)
-                            };
-
-                            for pixel in image.pixels_mut() {
-                                let r = (pixel[0] as f32 * brightness_multiplier).min(255.0) as u8;
-                                let g = (pixel[1] as f32 * brightness_multiplier).min(255.0) as u8;
-                                let b = (pixel[2] as f32 * brightness_multiplier).min(255.0) as u8;
-                                *pixel = image::Rgb([r, g, b]);
-                            }
-                        }
-
-                        // Convert to JPEG
-                        let mut jpeg_data = Vec::new();
-                        let mut cursor = std::io::Cursor::new(&mut jpeg_data);
-
-                        DynamicImage::ImageRgb8(image)
-                            .write_to(&mut cursor, image::ImageFormat::Jpeg)
-                            .map_err(|e| OurError::App(format!("Failed to encode JPEG: {e}")))?;
-
-                        Ok(jpeg_data)
-                    }
-                    Err(e) => Err(OurError::App(format!("Failed to capture frame: {e}")))
-                };
-
-                // Clean up camera
-                if let Err(e) = camera.stop_stream() {
-                    warn!("Failed to stop camera stream: {e}");
-                }
-
-                result
-            })
-            .map_err(|_| OurError::App(format!("Camera operation panicked for {hardware_id} (likely AVFoundation issue on macOS)")))
-            .and_then(|result| result)
-        })
-        .await
-        .map_err(|e| OurError::App(format!("Camera task failed: {e}")))?
-    }
-
-    async fn capture_image_internal(&mut self, hardware_id: &str) -> OurResult<Vec<u8>> {
-        // Create camera instance
-        let mut camera = self.create_camera(hardware_id).await?;
-
-        // Open camera stream
-        camera
-            .open_stream()
-            .map_err(|e| OurError::App(format!("Failed to open camera stream: {e}")))?;
-
-        match camera.frame() {
-            Ok(frame) => {
-                // Convert frame to RGB image
-                let mut image = frame
-                    .decode_image::<RgbFormat>()
-                    .map_err(|e| OurError::App(format!("Failed to decode frame: {e}")))?;
-
-                // Apply software brightness adjustment
-                self.apply_brightness_adjustment(&mut image, hardware_id);
-
-                // Convert to JPEG
-                let mut jpeg_data = Vec::new();
-                let mut cursor = std::io::Cursor::new(&mut jpeg_data);
-
-                DynamicImage::ImageRgb8(image)
-                    .write_to(&mut cursor, image::ImageFormat::Jpeg)
-                    .map_err(|e| OurError::App(format!("Failed to encode JPEG: {e}")))?;
-
-                // Clean up camera
-                if let Err(e) = camera.stop_stream() {
-                    warn!("Failed to stop camera stream: {e}");
-                }
-
-                Ok(jpeg_data)
-            }
-            Err(e) => {
-                warn!("Failed to capture frame from camera {hardware_id}: {e}");
-                if let Err(stop_err) = camera.stop_stream() {
-                    warn!("Failed to stop camera stream after error: {stop_err}");
-                }
-                Err(OurError::App(format!("Failed to capture frame: {e}")))
-            }
-        }
-    }
-
     /// Get current status
     async fn get_status_internal(&self) -> UsbCameraStatus {
         self.get_status().await.clone()
@@ -1045,10 +1765,15 @@ impl UsbCameraManager {
 }
 
 /// Start USB camera manager in separate task
-pub async fn start_usb_camera_manager() -> OurResult<UsbCameraHandle> {
-    let (mut manager, handle) = UsbCameraManager::new()?;
-
-    tokio::spawn(async move {
+pub async fn start_usb_camera_manager(
+    events: EventRecorder,
+    warmup: CaptureWarmup,
+    pre_capture: PreCapture,
+    tasks: &TaskRegistry,
+) -> OurResult<UsbCameraHandle> {
+    let (mut manager, handle) = UsbCameraManager::new(events, warmup, pre_capture)?;
+
+    tasks.spawn_tracked("usb_camera_manager", async move {
         if let Err(e) = manager.run().await {
             error!("USB camera manager error: {e}");
         }
@@ -1056,3 +1781,402 @@ pub async fn start_usb_camera_manager() -> OurResult<UsbCameraHandle> {
 
     Ok(handle)
 }
+
+/// USB cameras that only exist in memory, for tests anywhere in the crate
+#[cfg(test)]
+pub(crate) mod simulated {
+    use super::*;
+    use image::RgbImage;
+    use std::collections::HashSet;
+    use std::sync::Mutex;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// A frame that takes `decode_time` to decode, like a large frame on a slow machine
+    struct SlowFrame {
+        decode_time: Duration,
+        decoding: Arc<AtomicUsize>,
+    }
+
+    impl RawFrame for SlowFrame {
+        fn decode(self: Box<Self>) -> OurResult<RgbImage> {
+            self.decoding.fetch_add(1, Ordering::SeqCst);
+            std::thread::sleep(self.decode_time);
+            Ok(RgbImage::from_pixel(8, 6, image::Rgb([90, 120, 60])))
+        }
+    }
+
+    /// The ID detection gives simulated camera `number`, from the serial in its description
+    fn hardware_id(number: u32) -> String {
+        format!("usb:1D6B:0102:{number:X}")
+    }
+
+    /// Cameras that hand out [`SlowFrame`]s, counting the frames being decoded
+    /// and the times they are asked for their formats
+    struct SimulatedCameras {
+        /// Camera numbers in the order the OS lists them, which gives their indexes
+        order: Arc<Mutex<Vec<u32>>>,
+        decode_time: Duration,
+        decoding: Arc<AtomicUsize>,
+        format_queries: Arc<AtomicUsize>,
+        unplugged: Arc<Mutex<HashSet<String>>>,
+    }
+
+    impl SimulatedCameras {
+        fn is_unplugged(&self, hardware_id: &str) -> bool {
+            self.unplugged
+                .lock()
+                .expect("unplugged set should lock")
+                .contains(hardware_id)
+        }
+
+        /// The attached cameras' numbers by index; unplugging one moves the ones after it down
+        fn attached(&self) -> Vec<u32> {
+            self.order
+                .lock()
+                .expect("camera order should lock")
+                .iter()
+                .copied()
+                .filter(|&number| !self.is_unplugged(&hardware_id(number)))
+                .collect()
+        }
+    }
+
+    impl FrameGrabber for SimulatedCameras {
+        fn query(&self) -> OurResult<Vec<NokhwaCameraInfo>> {
+            Ok(self
+                .attached()
+                .into_iter()
+                .zip(0..)
+                .map(|(number, index)| {
+                    let name = format!("Simulated camera {number}");
+                    let description = format!("VID_1D6B PID_0102 SN_{number:X}");
+                    NokhwaCameraInfo::new(&name, &description, "", CameraIndex::Index(index))
+                })
+                .collect())
+        }
+
+        fn formats(&self, _camera: &UsbCameraInfo) -> OurResult<Vec<CameraFormatInfo>> {
+            self.format_queries.fetch_add(1, Ordering::SeqCst);
+            Ok(vec![CameraFormatInfo {
+                width: 640,
+                height: 480,
+                fps: 30,
+                format: "MJPEG".to_string(),
+            }])
+        }
+
+        fn grab(
+            &self,
+            camera: &UsbCameraInfo,
+            _warmup: CaptureWarmup,
+        ) -> OurResult<(Box<dyn RawFrame>, WarmupTiming)> {
+            // Whatever is at a stale index isn't this camera
+            let at_index = self
+                .attached()
+                .get(camera.index as usize)
+                .map(|&number| hardware_id(number));
+            if at_index.as_ref() != Some(&camera.hardware_id) {
+                return Err(OurError::App(format!(
+                    "{CREATE_FAILED} {}: No such device",
+                    camera.hardware_id
+                )));
+            }
+            let frame = SlowFrame {
+                decode_time: self.decode_time,
+                decoding: self.decoding.clone(),
+            };
+            Ok((Box::new(frame), WarmupTiming::default()))
+        }
+
+        fn is_attached(&self, camera: &UsbCameraInfo) -> OurResult<bool> {
+            Ok(!self.is_unplugged(&camera.hardware_id))
+        }
+    }
+
+    /// A running manager with simulated cameras `usb:1D6B:0102:0`, `usb:1D6B:0102:1`, ...
+    pub(crate) struct SimulatedUsb {
+        pub(crate) handle: UsbCameraHandle,
+        pub(crate) hardware_ids: Vec<String>,
+        /// Frames that have started decoding
+        pub(crate) decoding: Arc<AtomicUsize>,
+        /// Times a camera has been asked for its formats
+        pub(crate) format_queries: Arc<AtomicUsize>,
+        order: Arc<Mutex<Vec<u32>>>,
+        unplugged: Arc<Mutex<HashSet<String>>>,
+    }
+
+    impl SimulatedUsb {
+        /// Start a manager with `count` cameras whose frames take `decode_time` to decode
+        pub(crate) fn start(count: usize, decode_time: Duration) -> Self {
+            let (mut manager, handle) = UsbCameraManager::new(
+                EventRecorder::default(),
+                CaptureWarmup::NONE,
+                PreCapture::default(),
+            )
+            .expect("manager should start");
+            let count = u32::try_from(count).expect("camera count should fit");
+            let decoding = Arc::new(AtomicUsize::new(0));
+            let format_queries = Arc::new(AtomicUsize::new(0));
+            let unplugged = Arc::new(Mutex::new(HashSet::new()));
+            let order = Arc::new(Mutex::new((0..count).collect()));
+            manager.grabber = Arc::new(SimulatedCameras {
+                order: order.clone(),
+                decode_time,
+                decoding: decoding.clone(),
+                format_queries: format_queries.clone(),
+                unplugged: unplugged.clone(),
+            });
+
+            let hardware_ids: Vec<String> = (0..count).map(hardware_id).collect();
+            {
+                let mut status = manager
+                    .status
+                    .try_write()
+                    .expect("status is not shared yet");
+                for (index, hardware_id) in (0..count).zip(&hardware_ids) {
+                    let camera = UsbCameraInfo {
+                        index,
+                        name: format!("Simulated camera {index}"),
+                        vendor_id: Some("1D6B".to_string()),
+                        product_id: Some("0102".to_string()),
+                        serial_number: Some(format!("{index:X}")),
+                        hardware_id: hardware_id.clone(),
+                        connected: true,
+                        supported_formats: Vec::new(),
+                        current_format: None,
+                    };
+                    status.cameras.insert(hardware_id.clone(), camera);
+                }
+            }
+            tokio::spawn(async move { manager.run().await });
+            Self {
+                handle,
+                hardware_ids,
+                decoding,
+                format_queries,
+                order,
+                unplugged,
+            }
+        }
+
+        /// Reset the hub: the same cameras come back, listed in reverse order
+        pub(crate) fn re_enumerate(&self) {
+            self.order
+                .lock()
+                .expect("camera order should lock")
+                .reverse();
+        }
+
+        /// Knock the camera's cable out
+        pub(crate) fn unplug(&self, hardware_id: &str) {
+            self.unplugged
+                .lock()
+                .expect("unplugged set should lock")
+                .insert(hardware_id.to_string());
+        }
+
+        pub(crate) fn plug_in(&self, hardware_id: &str) {
+            self.unplugged
+                .lock()
+                .expect("unplugged set should lock")
+                .remove(hardware_id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::simulated::SimulatedUsb;
+    use super::*;
+    use std::sync::atomic::Ordering;
+
+    #[tokio::test]
+    async fn test_status_is_answered_while_a_frame_is_processed() {
+        let SimulatedUsb {
+            handle: cameras,
+            decoding,
+            hardware_ids,
+            ..
+        } = SimulatedUsb::start(1, Duration::from_millis(500));
+        let hardware_id = hardware_ids[0].clone();
+        let capture = tokio::spawn({
+            let cameras = cameras.clone();
+            let hardware_id = hardware_id.clone();
+            async move { cameras.capture_image(hardware_id).await }
+        });
+
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while decoding.load(Ordering::SeqCst) == 0 {
+                tokio::time::sleep(Duration::from_millis(5)).await;
+            }
+        })
+        .await
+        .expect("frame should reach processing");
+
+        let status = tokio::time::timeout(Duration::from_millis(250), cameras.get_status())
+            .await
+            .expect("status should be answered while the frame is processed")
+            .expect("status should be returned");
+        assert!(!capture.is_finished());
+        assert!(!status.capture_stats.contains_key(&hardware_id));
+
+        let jpeg = capture
+            .await
+            .expect("capture task should finish")
+            .expect("capture should succeed");
+        let image = image::load_from_memory(&jpeg).expect("capture should be a JPEG");
+        assert_eq!((image.width(), image.height()), (8, 6));
+
+        let status = cameras
+            .get_status()
+            .await
+            .expect("status should be returned");
+        let stats = &status.capture_stats[&hardware_id];
+        assert_eq!((stats.attempted, stats.succeeded), (1, 1));
+        assert!(stats.average_processing_ms.unwrap_or_default() >= 500.0);
+    }
+
+    #[tokio::test]
+    async fn test_capture_session_processes_frames_in_parallel() {
+        let decode_time = Duration::from_millis(300);
+        let SimulatedUsb {
+            handle: cameras,
+            hardware_ids,
+            ..
+        } = SimulatedUsb::start(4, decode_time);
+
+        let started = Instant::now();
+        let results = cameras
+            .capture_session("session", &hardware_ids)
+            .await
+            .expect("session should run");
+        let elapsed = started.elapsed();
+
+        let captured: Vec<&String> = results.iter().map(|(hardware_id, _)| hardware_id).collect();
+        assert_eq!(captured, hardware_ids.iter().collect::<Vec<_>>());
+        assert!(results.iter().all(|(_, result)| result.is_ok()));
+        // One camera after another would take at least four decodes
+        assert!(elapsed < decode_time * 3, "session took {elapsed:?}");
+    }
+
+    #[tokio::test]
+    async fn test_unplugged_camera_is_marked_disconnected_until_it_delivers() {
+        let usb = SimulatedUsb::start(2, Duration::ZERO);
+        let (gone, present) = (&usb.hardware_ids[0], &usb.hardware_ids[1]);
+        usb.unplug(gone);
+
+        let results = usb
+            .handle
+            .capture_session("session", &usb.hardware_ids)
+            .await
+            .expect("session should run");
+        let errors: Vec<String> = results
+            .iter()
+            .filter_map(|(_, result)| result.as_ref().err().map(ToString::to_string))
+            .collect();
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].contains("is disconnected"), "{errors:?}");
+        let status = usb.handle.get_status().await.expect("status should load");
+        assert!(!status.cameras[gone].connected);
+        assert!(status.cameras[present].connected);
+        assert!(status.last_detection.is_some());
+
+        usb.plug_in(gone);
+        usb.handle
+            .capture_image(gone.clone())
+            .await
+            .expect("plugged in camera should capture");
+        let status = usb.handle.get_status().await.expect("status should load");
+        assert!(status.cameras[gone].connected);
+    }
+
+    #[tokio::test]
+    async fn test_capture_follows_a_camera_to_its_new_index() {
+        let usb = SimulatedUsb::start(2, Duration::ZERO);
+        let (first, second) = (&usb.hardware_ids[0], &usb.hardware_ids[1]);
+        usb.handle
+            .select_cameras(vec![CameraId::from(first.as_str())])
+            .await
+            .expect("selection");
+        usb.re_enumerate();
+
+        usb.handle
+            .capture_image(first.clone())
+            .await
+            .expect("capture should find the camera at its new index");
+        let status = usb.handle.get_status().await.expect("status should load");
+        assert_eq!(status.cameras[first].index, 1);
+        assert_eq!(status.cameras[second].index, 0);
+        assert_eq!(status.selected_cameras(), vec![first.clone()]);
+        let stats = &status.capture_stats[first];
+        assert_eq!((stats.attempted, stats.succeeded), (1, 1));
+        assert_eq!(stats.index_recoveries, 1);
+
+        // Every camera was matched again, so the other one needn't fail first
+        usb.handle
+            .capture_image(second.clone())
+            .await
+            .expect("capture should succeed");
+        let status = usb.handle.get_status().await.expect("status should load");
+        let stats = &status.capture_stats[second];
+        assert_eq!((stats.succeeded, stats.failed), (1, 0));
+        assert_eq!(stats.index_recoveries, 1);
+        assert_eq!(status.capture_stats[first].index_recoveries, 1);
+    }
+
+    #[tokio::test]
+    async fn test_detection_reuses_formats_unless_asked_to_refresh() {
+        let usb = SimulatedUsb::start(2, Duration::ZERO);
+        let sources = |detection: &UsbDetection| -> Vec<FormatSource> {
+            detection.report.formats.values().copied().collect()
+        };
+
+        let first = usb.handle.detect_cameras(false).await.expect("detection");
+        let ids: Vec<&String> = first.report.formats.keys().collect();
+        assert_eq!(ids, usb.hardware_ids.iter().collect::<Vec<_>>());
+        assert_eq!(sources(&first), vec![FormatSource::Fresh; 2]);
+        assert_eq!(first.cameras[0].supported_formats.len(), 1);
+        assert_eq!(usb.format_queries.load(Ordering::SeqCst), 2);
+
+        let again = usb.handle.detect_cameras(false).await.expect("detection");
+        assert_eq!(sources(&again), vec![FormatSource::Cached; 2]);
+        assert_eq!(
+            again.cameras[1].supported_formats,
+            first.cameras[1].supported_formats
+        );
+        assert_eq!(usb.format_queries.load(Ordering::SeqCst), 2);
+
+        let deep = usb.handle.detect_cameras(true).await.expect("detection");
+        assert!(deep.report.deep);
+        assert_eq!(sources(&deep), vec![FormatSource::Fresh; 2]);
+        assert_eq!(usb.format_queries.load(Ordering::SeqCst), 4);
+
+        // A streaming camera keeps its format and isn't opened, even for a deep detection
+        let streaming = &usb.hardware_ids[0];
+        usb.handle
+            .select_cameras(vec![CameraId::from(streaming.as_str())])
+            .await
+            .expect("selection");
+        usb.handle.start_streaming().await.expect("streaming");
+        let format = first.cameras[0].supported_formats[0].clone();
+        usb.handle
+            .set_camera_format(streaming.clone(), format.clone(), BusyPolicy::Reject)
+            .await
+            .expect("format");
+        let deep = usb.handle.detect_cameras(true).await.expect("detection");
+        assert_eq!(
+            sources(&deep),
+            vec![FormatSource::Streaming, FormatSource::Fresh]
+        );
+        assert_eq!(usb.format_queries.load(Ordering::SeqCst), 5);
+        let camera = deep
+            .cameras
+            .iter()
+            .find(|camera| &camera.hardware_id == streaming)
+            .expect("streaming camera should be detected");
+        assert_eq!(camera.current_format.as_ref(), Some(&format));
+
+        let status = usb.handle.get_status().await.expect("status should load");
+        assert_eq!(status.detection.as_ref(), Some(&deep.report));
+    }
+}
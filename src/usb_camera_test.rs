@@ -0,0 +1,505 @@
+//! Commissioning check for one USB camera, as run by `camera usb test`.
+//!
+//! Runs the camera through detection, selection, streaming and capture, then
+//! the controls: a brightness get/set/get round trip, switching to a middle
+//! resolution and checking the captured frame has it, a few captures in a
+//! row to catch flaky open and close behaviour, and the latency of streamed
+//! frames. Each step is reported with its timing in the same shape as the
+//! controller self-test, so a provisioning script can read either. The
+//! brightness and format steps can be skipped for cameras without those
+//! controls; if the camera can't be found, selected or streamed the rest are
+//! skipped. Streaming is always stopped again.
+
+use std::fmt;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use crate::camera::CameraId;
+use crate::camera_lock::BusyPolicy;
+use crate::self_test::{SelfTestStep, StepOutcome};
+use crate::usb_camera_controller::{CameraFormatInfo, UsbCameraHandle, UsbCameraInfo};
+use crate::{OurError, OurResult};
+
+/// Captures taken one after another by the repeated capture step
+pub const REPEATED_CAPTURES: usize = 3;
+/// Streamed frames timed by the latency step
+pub const LATENCY_FRAMES: usize = 5;
+/// How far the brightness round trip moves the brightness
+const BRIGHTNESS_STEP: i64 = 10;
+
+/// Steps that can be left out for cameras without the control
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SkippableStep {
+    Brightness,
+    Format,
+}
+
+impl SkippableStep {
+    /// Every skippable step, as written on the command line
+    pub const NAMES: [&str; 2] = ["brightness", "format"];
+
+    /// Steps named in a comma separated list, e.g. `brightness,format`
+    pub fn parse_list(list: &str) -> OurResult<Vec<Self>> {
+        list.split(',')
+            .map(str::trim)
+            .filter(|name| !name.is_empty())
+            .map(str::parse)
+            .collect()
+    }
+}
+
+impl FromStr for SkippableStep {
+    type Err = OurError;
+
+    fn from_str(s: &str) -> OurResult<Self> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "brightness" => Ok(Self::Brightness),
+            "format" => Ok(Self::Format),
+            _ => Err(OurError::App(format!(
+                "Can't skip {s:?}, expected one of: {}",
+                Self::NAMES.join(", ")
+            ))),
+        }
+    }
+}
+
+impl fmt::Display for SkippableStep {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Brightness => "brightness",
+            Self::Format => "format",
+        })
+    }
+}
+
+/// Everything the camera test did, in order
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct UsbCameraTestReport {
+    pub hardware_id: String,
+    /// The name the camera reports, if it was found
+    pub camera_name: Option<String>,
+    pub started_at: DateTime<Utc>,
+    pub duration_ms: u64,
+    /// No step failed
+    pub passed: bool,
+    pub steps: Vec<SelfTestStep>,
+}
+
+impl UsbCameraTestReport {
+    /// The steps that failed, by name
+    pub fn failed_steps(&self) -> Vec<&str> {
+        self.steps
+            .iter()
+            .filter(|step| step.outcome == StepOutcome::Failed)
+            .map(|step| step.name.as_str())
+            .collect()
+    }
+}
+
+/// The resolution in the middle of what the camera supports, by pixel count
+///
+/// With an even number of resolutions the smaller of the middle two is used.
+pub fn middle_format(formats: &[CameraFormatInfo]) -> Option<CameraFormatInfo> {
+    let mut formats = formats.to_vec();
+    formats.sort_by_key(|format| {
+        (
+            u64::from(format.width) * u64::from(format.height),
+            format.fps,
+        )
+    });
+    formats.dedup_by_key(|format| (format.width, format.height));
+    formats.get(formats.len().saturating_sub(1) / 2).cloned()
+}
+
+/// The width and height of an encoded frame
+fn frame_dimensions(frame: &[u8]) -> Result<(u32, u32), String> {
+    image::load_from_memory(frame)
+        .map(|image| (image.width(), image.height()))
+        .map_err(|e| format!("the frame couldn't be decoded: {e}"))
+}
+
+/// The steps run so far
+#[derive(Default)]
+struct Steps(Vec<SelfTestStep>);
+
+impl Steps {
+    /// Run one step, recording its outcome, and return whether it passed
+    async fn step<F>(&mut self, name: &str, run: F) -> bool
+    where
+        F: Future<Output = Result<String, String>>,
+    {
+        let started = Instant::now();
+        let (outcome, detail) = match run.await {
+            Ok(detail) => (StepOutcome::Passed, detail),
+            Err(e) => (StepOutcome::Failed, e),
+        };
+        self.0.push(SelfTestStep {
+            name: name.to_string(),
+            outcome,
+            duration_ms: started.elapsed().as_millis() as u64,
+            detail: Some(detail).filter(|detail| !detail.is_empty()),
+        });
+        outcome == StepOutcome::Passed
+    }
+
+    fn skip(&mut self, name: &str, reason: &str) {
+        self.0.push(SelfTestStep {
+            name: name.to_string(),
+            outcome: StepOutcome::Skipped,
+            duration_ms: 0,
+            detail: Some(reason.to_string()),
+        });
+    }
+}
+
+/// The calls each step makes to one camera
+#[derive(Clone, Copy)]
+struct CameraTest<'a> {
+    cameras: &'a UsbCameraHandle,
+    hardware_id: &'a str,
+}
+
+impl CameraTest<'_> {
+    async fn capture(&self) -> Result<Vec<u8>, String> {
+        self.cameras
+            .capture_image(self.hardware_id.to_string())
+            .await
+            .map_err(|e| e.to_string())
+    }
+
+    async fn capture_checked(&self) -> Result<String, String> {
+        let frame = self.capture().await?;
+        let (width, height) = frame_dimensions(&frame)?;
+        Ok(format!("{width}x{height}, {} bytes", frame.len()))
+    }
+
+    async fn detect(&self) -> Result<UsbCameraInfo, String> {
+        let detection = self
+            .cameras
+            .detect_cameras(false)
+            .await
+            .map_err(|e| e.to_string())?;
+        detection
+            .cameras
+            .into_iter()
+            .find(|camera| camera.hardware_id == self.hardware_id)
+            .ok_or_else(|| format!("no camera with hardware ID {}", self.hardware_id))
+    }
+
+    async fn brightness_round_trip(&self) -> Result<String, String> {
+        let hardware_id = self.hardware_id.to_string();
+        let original = self
+            .cameras
+            .get_brightness(hardware_id.clone())
+            .await
+            .map_err(|e| format!("reading brightness failed: {e}"))?;
+        let target = if original + BRIGHTNESS_STEP <= 100 {
+            original + BRIGHTNESS_STEP
+        } else {
+            original - BRIGHTNESS_STEP
+        };
+        self.cameras
+            .set_brightness(hardware_id.clone(), target, BusyPolicy::Wait)
+            .await
+            .map_err(|e| format!("setting brightness to {target} failed: {e}"))?;
+        let read_back = self
+            .cameras
+            .get_brightness(hardware_id.clone())
+            .await
+            .map_err(|e| format!("reading brightness back failed: {e}"))?;
+        if let Err(e) = self
+            .cameras
+            .set_brightness(hardware_id, original, BusyPolicy::Wait)
+            .await
+        {
+            warn!(
+                "Failed to restore the brightness of {} to {original}: {e}",
+                self.hardware_id
+            );
+        }
+        if read_back == target {
+            Ok(format!("{original} -> {target} -> {read_back}, restored"))
+        } else {
+            Err(format!("set {target} but read back {read_back}"))
+        }
+    }
+
+    async fn set_format(&self, camera: &UsbCameraInfo) -> Result<String, String> {
+        let format = middle_format(&camera.supported_formats)
+            .ok_or_else(|| "the camera reported no formats".to_string())?;
+        self.cameras
+            .set_camera_format(
+                self.hardware_id.to_string(),
+                format.clone(),
+                BusyPolicy::Wait,
+            )
+            .await
+            .map_err(|e| format!("setting {}x{} failed: {e}", format.width, format.height))?;
+        let result = match self.capture().await {
+            Ok(frame) => match frame_dimensions(&frame)? {
+                (width, height) if (width, height) == (format.width, format.height) => {
+                    Ok(format!("{width}x{height} {}", format.format))
+                }
+                (width, height) => Err(format!(
+                    "set {}x{} but the frame was {width}x{height}",
+                    format.width, format.height
+                )),
+            },
+            Err(e) => Err(format!(
+                "capture at {}x{} failed: {e}",
+                format.width, format.height
+            )),
+        };
+        if let Some(previous) = camera.current_format.clone()
+            && let Err(e) = self
+                .cameras
+                .set_camera_format(self.hardware_id.to_string(), previous, BusyPolicy::Wait)
+                .await
+        {
+            warn!("Failed to restore the format of {}: {e}", self.hardware_id);
+        }
+        result
+    }
+
+    async fn repeated_captures(&self) -> Result<String, String> {
+        let mut timings = Vec::with_capacity(REPEATED_CAPTURES);
+        for attempt in 1..=REPEATED_CAPTURES {
+            let started = Instant::now();
+            self.capture()
+                .await
+                .map_err(|e| format!("capture {attempt} of {REPEATED_CAPTURES} failed: {e}"))?;
+            timings.push(format!("{}ms", started.elapsed().as_millis()));
+        }
+        Ok(timings.join(", "))
+    }
+
+    async fn streaming_latency(&self) -> Result<String, String> {
+        let mut latencies: Vec<Duration> = Vec::with_capacity(LATENCY_FRAMES);
+        for frame in 1..=LATENCY_FRAMES {
+            let started = Instant::now();
+            self.cameras
+                .capture_streaming_frame(self.hardware_id)
+                .await
+                .map_err(|e| format!("streamed frame {frame} of {LATENCY_FRAMES} failed: {e}"))?;
+            latencies.push(started.elapsed());
+        }
+        let total: Duration = latencies.iter().sum();
+        let max = latencies.iter().max().copied().unwrap_or_default();
+        Ok(format!(
+            "mean {}ms, max {}ms over {LATENCY_FRAMES} frames",
+            (total / LATENCY_FRAMES as u32).as_millis(),
+            max.as_millis()
+        ))
+    }
+}
+
+/// Test the camera `hardware_id`, leaving out the `skip` steps
+pub async fn run(
+    cameras: &UsbCameraHandle,
+    hardware_id: &str,
+    skip: &[SkippableStep],
+) -> UsbCameraTestReport {
+    let started_at = Utc::now();
+    let started = Instant::now();
+    let test = CameraTest {
+        cameras,
+        hardware_id,
+    };
+    let mut steps = Steps::default();
+
+    let camera = test.detect().await;
+    let found = steps
+        .step(
+            "detect camera",
+            std::future::ready(
+                camera
+                    .as_ref()
+                    .map(|camera| camera.name.clone())
+                    .map_err(Clone::clone),
+            ),
+        )
+        .await;
+    let ready = found
+        && steps
+            .step("select camera", async {
+                cameras
+                    .select_cameras(vec![CameraId::from(hardware_id)])
+                    .await
+                    .map(|_| String::new())
+                    .map_err(|e| e.to_string())
+            })
+            .await
+        && steps
+            .step("start streaming", async {
+                cameras
+                    .start_streaming()
+                    .await
+                    .map(|_| String::new())
+                    .map_err(|e| e.to_string())
+            })
+            .await;
+
+    let camera = camera.ok();
+    match camera.as_ref().filter(|_| ready) {
+        Some(camera) => {
+            steps.step("capture", test.capture_checked()).await;
+            if skip.contains(&SkippableStep::Brightness) {
+                steps.skip("brightness round trip", "skipped with --skip brightness");
+            } else {
+                steps
+                    .step("brightness round trip", test.brightness_round_trip())
+                    .await;
+            }
+            if skip.contains(&SkippableStep::Format) {
+                steps.skip("set format", "skipped with --skip format");
+            } else {
+                steps.step("set format", test.set_format(camera)).await;
+            }
+            steps
+                .step("repeated captures", test.repeated_captures())
+                .await;
+            steps
+                .step("streaming frame latency", test.streaming_latency())
+                .await;
+        }
+        None => {
+            for name in [
+                "capture",
+                "brightness round trip",
+                "set format",
+                "repeated captures",
+                "streaming frame latency",
+            ] {
+                steps.skip(name, "an earlier step failed");
+            }
+        }
+    }
+
+    // Streaming may have started even if a later step failed, so it's always stopped
+    steps
+        .step("stop streaming", async {
+            cameras
+                .stop_streaming()
+                .await
+                .map(|_| String::new())
+                .map_err(|e| e.to_string())
+        })
+        .await;
+
+    let steps = steps.0;
+    UsbCameraTestReport {
+        hardware_id: hardware_id.to_string(),
+        camera_name: camera.map(|camera| camera.name),
+        started_at,
+        duration_ms: started.elapsed().as_millis() as u64,
+        passed: steps.iter().all(|step| step.outcome != StepOutcome::Failed),
+        steps,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::usb_camera_controller::simulated::SimulatedUsb;
+
+    fn format(width: u32, height: u32) -> CameraFormatInfo {
+        CameraFormatInfo {
+            width,
+            height,
+            fps: 30,
+            format: "MJPEG".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_middle_format_and_skip_list() {
+        let formats = [
+            format(1920, 1080),
+            format(640, 480),
+            format(1280, 720),
+            format(640, 480),
+            format(320, 240),
+        ];
+        assert_eq!(middle_format(&formats), Some(format(640, 480)));
+        assert_eq!(middle_format(&formats[..1]), Some(format(1920, 1080)));
+        assert_eq!(middle_format(&[]), None);
+
+        assert_eq!(
+            SkippableStep::parse_list("brightness, format,").expect("list should parse"),
+            vec![SkippableStep::Brightness, SkippableStep::Format]
+        );
+        assert!(SkippableStep::parse_list("focus").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_report_covers_every_step() {
+        let usb = SimulatedUsb::start(1, Duration::ZERO);
+        let hardware_id = usb.hardware_ids[0].clone();
+
+        // Simulated frames are always 8x6, so switching to 640x480 doesn't show in them
+        let report = run(&usb.handle, &hardware_id, &[]).await;
+        let outcomes: Vec<(&str, StepOutcome)> = report
+            .steps
+            .iter()
+            .map(|step| (step.name.as_str(), step.outcome))
+            .collect();
+        assert_eq!(
+            outcomes,
+            vec![
+                ("detect camera", StepOutcome::Passed),
+                ("select camera", StepOutcome::Passed),
+                ("start streaming", StepOutcome::Passed),
+                ("capture", StepOutcome::Passed),
+                ("brightness round trip", StepOutcome::Passed),
+                ("set format", StepOutcome::Failed),
+                ("repeated captures", StepOutcome::Passed),
+                ("streaming frame latency", StepOutcome::Passed),
+                ("stop streaming", StepOutcome::Passed),
+            ]
+        );
+        assert!(!report.passed);
+        assert_eq!(report.failed_steps(), vec!["set format"]);
+        assert_eq!(report.camera_name.as_deref(), Some("Simulated camera 0"));
+        assert_eq!(
+            report.steps[4].detail.as_deref(),
+            Some("50 -> 60 -> 60, restored")
+        );
+        assert_eq!(
+            usb.handle
+                .get_brightness(hardware_id.clone())
+                .await
+                .expect("brightness should be read"),
+            50
+        );
+
+        let skipped = run(&usb.handle, &hardware_id, &[SkippableStep::Format]).await;
+        assert!(skipped.passed, "{:?}", skipped.failed_steps());
+        assert_eq!(skipped.steps[5].outcome, StepOutcome::Skipped);
+        assert!(
+            !usb.handle
+                .get_status()
+                .await
+                .expect("status should be returned")
+                .streaming
+        );
+
+        // An unknown camera skips everything but stopping the stream
+        let missing = run(&usb.handle, "usb:sim:9", &[]).await;
+        assert!(!missing.passed);
+        assert_eq!(missing.failed_steps(), vec!["detect camera"]);
+        assert_eq!(
+            missing
+                .steps
+                .iter()
+                .filter(|step| step.outcome == StepOutcome::Skipped)
+                .count(),
+            5
+        );
+    }
+}
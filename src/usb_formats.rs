@@ -0,0 +1,135 @@
+//! What each USB camera's formats are, remembered between detections.
+//!
+//! Asking a camera which formats it supports means opening the device. On Linux
+//! opening a busy V4L2 device logs kernel warnings and can make the stream that
+//! is using it stutter, and the answer only changes with the camera's firmware.
+//! So detection asks each camera once and keeps the answer in a [`FormatCache`]
+//! for [`FORMAT_CACHE_TTL`]. Cameras that are streaming are never asked, and a
+//! deep detection asks every other camera again.
+
+use std::collections::{BTreeMap, HashMap};
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+
+use crate::usb_camera_controller::CameraFormatInfo;
+
+/// How long a camera's formats are remembered before it is asked again
+pub const FORMAT_CACHE_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Where a detected camera's format list came from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FormatSource {
+    /// The camera was asked during this detection
+    Fresh,
+    /// Remembered from an earlier detection
+    Cached,
+    /// The camera is streaming, so what was already known was kept
+    Streaming,
+    /// The camera couldn't be asked, so common formats are listed instead
+    Fallback,
+}
+
+/// How a USB camera detection went
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct DetectionReport {
+    /// Every camera was asked for its formats, whatever was cached
+    pub deep: bool,
+    pub duration_ms: u64,
+    /// Where each camera's formats came from, by hardware ID
+    pub formats: BTreeMap<String, FormatSource>,
+}
+
+impl DetectionReport {
+    /// How many cameras got their formats from `source`
+    pub fn count(&self, source: FormatSource) -> usize {
+        self.formats
+            .values()
+            .filter(|&&from| from == source)
+            .count()
+    }
+}
+
+struct CachedFormats {
+    formats: Vec<CameraFormatInfo>,
+    asked_at: Instant,
+}
+
+/// Format lists by hardware ID, each kept until it is older than the TTL
+pub struct FormatCache {
+    ttl: Duration,
+    entries: HashMap<String, CachedFormats>,
+}
+
+impl Default for FormatCache {
+    fn default() -> Self {
+        Self::new(FORMAT_CACHE_TTL)
+    }
+}
+
+impl FormatCache {
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            entries: HashMap::new(),
+        }
+    }
+
+    /// The camera's formats, if it was asked for them less than the TTL before `now`
+    pub fn get(&self, hardware_id: &str, now: Instant) -> Option<&[CameraFormatInfo]> {
+        self.entries
+            .get(hardware_id)
+            .filter(|entry| now.saturating_duration_since(entry.asked_at) < self.ttl)
+            .map(|entry| entry.formats.as_slice())
+    }
+
+    /// Remember what the camera answered at `now`, dropping entries that have expired
+    pub fn insert(&mut self, hardware_id: String, formats: Vec<CameraFormatInfo>, now: Instant) {
+        self.entries
+            .retain(|_, entry| now.saturating_duration_since(entry.asked_at) < self.ttl);
+        self.entries.insert(
+            hardware_id,
+            CachedFormats {
+                formats,
+                asked_at: now,
+            },
+        );
+    }
+}
+
+/// Formats most USB cameras support, listed for a camera that couldn't be asked
+pub fn common_formats() -> Vec<CameraFormatInfo> {
+    [(320, 240), (640, 480), (1280, 720), (1920, 1080)]
+        .into_iter()
+        .map(|(width, height)| CameraFormatInfo {
+            width,
+            height,
+            fps: 30,
+            format: "MJPEG".to_string(),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_formats_are_remembered_until_they_expire() {
+        let ttl = Duration::from_secs(60);
+        let mut cache = FormatCache::new(ttl);
+        let now = Instant::now();
+        assert!(cache.get("usb:cam", now).is_none());
+
+        cache.insert("usb:cam".to_string(), common_formats(), now);
+        assert_eq!(cache.get("usb:cam", now + ttl / 2).map(<[_]>::len), Some(4));
+        assert!(cache.get("usb:cam", now + ttl).is_none());
+
+        // An expired entry goes when something else is remembered
+        cache.insert("usb:other".to_string(), Vec::new(), now + ttl);
+        assert_eq!(cache.entries.len(), 1);
+        assert_eq!(cache.get("usb:other", now + ttl), Some(&[][..]));
+    }
+}
@@ -0,0 +1,294 @@
+//! Vendor, product and serial IDs read out of a USB camera's description.
+//!
+//! nokhwa doesn't report a camera's USB IDs directly, but the description each
+//! platform gives often contains them, in whichever format that platform uses:
+//! `VID_046D&PID_0825` in a Windows device path, `VendorID_1133 ProductID_2085`
+//! (decimal) from macOS, `046d:0825` as `lsusb` prints it, `(0x046d, 0x0825)`,
+//! or a Linux bus path like `usb-0000:00:14.0-3`. The patterns are compiled once
+//! into a [`DescriptionParser`] that the USB camera manager keeps, since
+//! detection parses every camera's description on every scan.
+
+use std::fmt;
+
+use regex::{Captures, Regex};
+
+use crate::{OurError, OurResult};
+
+/// IDs found in a camera description; a description with none of them is fine
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct HardwareIds {
+    /// Four upper case hex digits, e.g. `046D`
+    pub vendor_id: Option<String>,
+    /// Four upper case hex digits, e.g. `0825`
+    pub product_id: Option<String>,
+    pub serial_number: Option<String>,
+    /// The port the camera is plugged into, e.g. `usb-0000:00:14.0-3`
+    pub bus_path: Option<String>,
+}
+
+/// Why a description that names USB IDs couldn't be read
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DescriptionError {
+    /// A vendor ID marker is followed by something that isn't a 16-bit ID
+    InvalidVendorId(String),
+    /// A product ID marker is followed by something that isn't a 16-bit ID
+    InvalidProductId(String),
+    /// There's a vendor ID but no product ID to go with it
+    MissingProductId { vendor_id: String },
+    /// There's a product ID but no vendor ID to go with it
+    MissingVendorId { product_id: String },
+}
+
+impl fmt::Display for DescriptionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DescriptionError::InvalidVendorId(value) => {
+                write!(f, "vendor ID '{value}' is not four hex digits")
+            }
+            DescriptionError::InvalidProductId(value) => {
+                write!(f, "product ID '{value}' is not four hex digits")
+            }
+            DescriptionError::MissingProductId { vendor_id } => {
+                write!(f, "vendor ID {vendor_id} has no product ID with it")
+            }
+            DescriptionError::MissingVendorId { product_id } => {
+                write!(f, "product ID {product_id} has no vendor ID with it")
+            }
+        }
+    }
+}
+
+impl std::error::Error for DescriptionError {}
+
+/// The compiled description patterns
+#[derive(Debug, Clone)]
+pub struct DescriptionParser {
+    /// `VID_046D`, `vid:046d`
+    vendor_marker: Regex,
+    /// `PID_0825`, `pid:0825`
+    product_marker: Regex,
+    /// `VendorID_1133`, in decimal
+    vendor_decimal: Regex,
+    /// `ProductID_2085`, in decimal
+    product_decimal: Regex,
+    /// `(0x046d, 0x0825)`
+    hex_tuple: Regex,
+    /// `046d:0825`
+    id_pair: Regex,
+    /// `SN_1234`, `SNR:1234`, `SER_1234`
+    serial: Regex,
+    /// `usb-0000:00:14.0-3`, `usb-xhci-hcd.0-1.2`
+    bus_path: Regex,
+}
+
+impl DescriptionParser {
+    pub fn new() -> OurResult<Self> {
+        let compile = |pattern: &str| {
+            Regex::new(pattern).map_err(|e| {
+                OurError::App(format!("Invalid USB description pattern {pattern}: {e}"))
+            })
+        };
+        Ok(Self {
+            vendor_marker: compile(r"(?i)\bvid[_:]([0-9a-z]+)")?,
+            product_marker: compile(r"(?i)\bpid[_:]([0-9a-z]+)")?,
+            vendor_decimal: compile(r"(?i)\bvendorid_([0-9a-z]+)")?,
+            product_decimal: compile(r"(?i)\bproductid_([0-9a-z]+)")?,
+            hex_tuple: compile(r"(?i)\(\s*0x([0-9a-f]{4})\s*,\s*0x([0-9a-f]{4})\s*\)")?,
+            id_pair: compile(r"(?i)\b([0-9a-f]{4}):([0-9a-f]{4})\b")?,
+            serial: compile(r"(?i)s[en]r?[_:]([0-9a-f]+)")?,
+            bus_path: compile(r"(?i)\busb-[0-9a-z.:]+(?:-[0-9a-z.:]+)*-[0-9]+(?:\.[0-9]+)*\b")?,
+        })
+    }
+
+    /// Find the IDs in `description`, refusing one that names them wrongly
+    pub fn parse(&self, description: &str) -> Result<HardwareIds, DescriptionError> {
+        let (vendor_id, product_id) = self.vendor_and_product(description)?;
+        Ok(HardwareIds {
+            vendor_id,
+            product_id,
+            serial_number: first_group(&self.serial, description).map(|s| s.to_uppercase()),
+            bus_path: self
+                .bus_path
+                .find(description)
+                .map(|m| m.as_str().to_lowercase()),
+        })
+    }
+
+    fn vendor_and_product(
+        &self,
+        description: &str,
+    ) -> Result<(Option<String>, Option<String>), DescriptionError> {
+        // Explicitly named IDs first, as they can't be mistaken for anything else
+        let vendor = named_id(
+            description,
+            &self.vendor_marker,
+            &self.vendor_decimal,
+            DescriptionError::InvalidVendorId,
+        )?;
+        let product = named_id(
+            description,
+            &self.product_marker,
+            &self.product_decimal,
+            DescriptionError::InvalidProductId,
+        )?;
+        match (vendor, product) {
+            (Some(vendor_id), Some(product_id)) => Ok((Some(vendor_id), Some(product_id))),
+            (Some(vendor_id), None) => Err(DescriptionError::MissingProductId { vendor_id }),
+            (None, Some(product_id)) => Err(DescriptionError::MissingVendorId { product_id }),
+            (None, None) => {
+                let pair = self
+                    .hex_tuple
+                    .captures(description)
+                    .or_else(|| self.id_pair.captures(description));
+                Ok(match pair.as_ref().and_then(id_pair) {
+                    Some((vendor_id, product_id)) => (Some(vendor_id), Some(product_id)),
+                    None => (None, None),
+                })
+            }
+        }
+    }
+}
+
+/// An ID after a hex marker like `VID_`, or failing that a decimal one like `VendorID_`
+fn named_id(
+    description: &str,
+    hex: &Regex,
+    decimal: &Regex,
+    invalid: fn(String) -> DescriptionError,
+) -> Result<Option<String>, DescriptionError> {
+    if let Some(value) = first_group(hex, description) {
+        return hex_id(value)
+            .map(Some)
+            .ok_or_else(|| invalid(value.to_string()));
+    }
+    match first_group(decimal, description) {
+        Some(value) => decimal_id(value)
+            .map(Some)
+            .ok_or_else(|| invalid(value.to_string())),
+        None => Ok(None),
+    }
+}
+
+fn first_group<'a>(pattern: &Regex, description: &'a str) -> Option<&'a str> {
+    pattern
+        .captures(description)
+        .and_then(|captures| captures.get(1))
+        .map(|m| m.as_str())
+}
+
+fn id_pair(captures: &Captures<'_>) -> Option<(String, String)> {
+    Some((
+        hex_id(captures.get(1)?.as_str())?,
+        hex_id(captures.get(2)?.as_str())?,
+    ))
+}
+
+/// Exactly four hex digits, upper cased
+fn hex_id(value: &str) -> Option<String> {
+    (value.len() == 4 && value.chars().all(|c| c.is_ascii_hexdigit())).then(|| value.to_uppercase())
+}
+
+/// A decimal 16-bit ID as four upper case hex digits
+fn decimal_id(value: &str) -> Option<String> {
+    value.parse::<u16>().ok().map(|id| format!("{id:04X}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ids(vendor: &str, product: &str) -> HardwareIds {
+        HardwareIds {
+            vendor_id: Some(vendor.to_string()),
+            product_id: Some(product.to_string()),
+            ..HardwareIds::default()
+        }
+    }
+
+    fn bus(path: &str) -> HardwareIds {
+        HardwareIds {
+            bus_path: Some(path.to_string()),
+            ..HardwareIds::default()
+        }
+    }
+
+    #[test]
+    fn test_real_world_descriptions() {
+        let parser = DescriptionParser::new().expect("patterns should compile");
+        let cases = [
+            // Windows Media Foundation symbolic link
+            (
+                r"\\?\usb#vid_046d&pid_0825&mi_00#6&2bd3f3e1&0&0000#{e5323777-f976-4f5b-9b55-b94699c46e44}\global",
+                Ok(ids("046D", "0825")),
+            ),
+            // Windows device instance path
+            (
+                r"USB\VID_0C45&PID_6366&MI_00\7&1B3E6A53&0&0000",
+                Ok(ids("0C45", "6366")),
+            ),
+            // Windows device instance path with a serial number
+            (
+                r"USB\VID_1BCF&PID_2C99\SN_200901010001",
+                Ok(HardwareIds {
+                    serial_number: Some("200901010001".to_string()),
+                    ..ids("1BCF", "2C99")
+                }),
+            ),
+            // A cheap UVC module naming its IDs in the product string
+            (
+                "ELP USB Camera VID:32E4 PID:9422 SN:20200610",
+                Ok(HardwareIds {
+                    serial_number: Some("20200610".to_string()),
+                    ..ids("32E4", "9422")
+                }),
+            ),
+            // macOS AVFoundation, with the IDs in decimal
+            (
+                "Logitech: UVC Camera VendorID_1133 ProductID_2085 - AVCaptureDeviceTypeExternal, Unspecified f0",
+                Ok(ids("046D", "0825")),
+            ),
+            // macOS unique ID, which packs the location in with the IDs
+            ("0x14200000046d0825", Ok(HardwareIds::default())),
+            // lsusb
+            (
+                "Bus 001 Device 004: ID 046d:0825 Logitech, Inc. Webcam C270",
+                Ok(ids("046D", "0825")),
+            ),
+            // pyusb-style tuple
+            (
+                "HD Pro Webcam C920 (0x046d, 0x082d)",
+                Ok(ids("046D", "082D")),
+            ),
+            // Linux, as nokhwa lists cameras
+            (
+                "Video4Linux Device @ /dev/video0",
+                Ok(HardwareIds::default()),
+            ),
+            // Linux V4L2 bus info on a PC and on a Raspberry Pi 4 and 5
+            ("usb-0000:00:14.0-3", Ok(bus("usb-0000:00:14.0-3"))),
+            (
+                "usb-0000:01:00.0-1.4 (6, 1, 21)",
+                Ok(bus("usb-0000:01:00.0-1.4")),
+            ),
+            ("usb-xhci-hcd.0-1", Ok(bus("usb-xhci-hcd.0-1"))),
+            // Named IDs that aren't IDs, and half a pair
+            (
+                "USB Camera VID_04XZ&PID_0825",
+                Err(DescriptionError::InvalidVendorId("04XZ".to_string())),
+            ),
+            (
+                "UVC Camera VendorID_99999 ProductID_2085",
+                Err(DescriptionError::InvalidVendorId("99999".to_string())),
+            ),
+            (
+                "USB Camera VID_046D",
+                Err(DescriptionError::MissingProductId {
+                    vendor_id: "046D".to_string(),
+                }),
+            ),
+        ];
+        for (description, expected) in cases {
+            assert_eq!(parser.parse(description), expected, "{description}");
+        }
+    }
+}
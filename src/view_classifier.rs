@@ -0,0 +1,188 @@
+//! Guessing whether an image shows a case from the side or from its tail.
+//!
+//! Fixed cameras have a configured view type, but handheld re-shoots and
+//! uploaded photos arrive as [`ViewType::Unknown`]. A tail view looks straight
+//! at the headstamp, so the case's outline is a circle; a side view shows the
+//! profile, which is longer than it is wide. The outline is the convex hull of
+//! the strongest edges, and two measures of it say how round it is: the short
+//! side of its smallest enclosing rectangle over the long side, and how much of
+//! its enclosing circle it fills. Both are 1 for a circle and fall away as the
+//! shape stretches. There is no model behind this, so guesses near the
+//! boundary are marked for someone to check.
+
+use std::path::Path;
+
+use image::{DynamicImage, GrayImage};
+use imageproc::contours::find_contours;
+use imageproc::geometry::{contour_area, convex_hull, min_area_rect};
+use imageproc::point::Point;
+use serde::{Deserialize, Serialize};
+
+use crate::OurResult;
+use crate::config::ViewType;
+
+/// Longest side images are shrunk to before looking for the outline
+const ANALYSIS_SIZE: u32 = 256;
+
+/// Edge strengths for the Canny detector's hysteresis
+const EDGE_LOW: f32 = 40.0;
+const EDGE_HIGH: f32 = 100.0;
+
+/// Edge curves shorter than this fraction of the longest are left out of the outline
+const MIN_CURVE_FRACTION: f64 = 0.25;
+
+/// Outlines covering less of the image than this are taken to be noise
+const MIN_OUTLINE_FRACTION: f64 = 0.01;
+
+/// Roundness at which a tail view becomes more likely than a side view
+const TAIL_ROUNDNESS: f64 = 0.75;
+
+/// Guesses less confident than this are flagged for review
+pub const REVIEW_BELOW_CONFIDENCE: f64 = 0.75;
+
+/// A view type worked out from the image rather than the camera
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct ViewGuess {
+    pub view_type: ViewType,
+    /// From 0.5, a coin toss, to 1
+    pub confidence: f64,
+    /// Too unsure to trust without someone checking
+    pub needs_review: bool,
+}
+
+impl ViewGuess {
+    fn new(view_type: ViewType, confidence: f64) -> Self {
+        let confidence = (confidence * 100.0).round() / 100.0;
+        Self {
+            view_type,
+            confidence,
+            needs_review: confidence < REVIEW_BELOW_CONFIDENCE,
+        }
+    }
+}
+
+/// Guess the view of an encoded image, `None` when no outline stands out
+///
+/// This decodes the image, so run it off async tasks.
+pub fn classify_bytes(image_bytes: &[u8]) -> OurResult<Option<ViewGuess>> {
+    Ok(classify(&image::load_from_memory(image_bytes)?))
+}
+
+/// Guess the view of an image file, `None` when no outline stands out
+pub fn classify_file(path: &Path) -> OurResult<Option<ViewGuess>> {
+    Ok(classify(&image::open(path)?))
+}
+
+/// Guess the view of a decoded image, `None` when no outline stands out
+pub fn classify(image: &DynamicImage) -> Option<ViewGuess> {
+    let luma = image.thumbnail(ANALYSIS_SIZE, ANALYSIS_SIZE).to_luma8();
+    let roundness = roundness(&luma)?;
+    let distance = ((roundness - TAIL_ROUNDNESS).abs() / (1.0 - TAIL_ROUNDNESS)).min(1.0);
+    let view_type = if roundness >= TAIL_ROUNDNESS {
+        ViewType::Tail
+    } else {
+        ViewType::Side
+    };
+    Some(ViewGuess::new(view_type, 0.5 + distance / 2.0))
+}
+
+/// How round the dominant outline is, from near 0 for a thin line to 1 for a circle
+fn roundness(luma: &GrayImage) -> Option<f64> {
+    let edges = imageproc::edges::canny(luma, EDGE_LOW, EDGE_HIGH);
+    let curves = find_contours::<i32>(&edges);
+    let longest = curves.iter().map(|curve| curve.points.len()).max()?;
+    let points: Vec<Point<i32>> = curves
+        .into_iter()
+        .filter(|curve| curve.points.len() as f64 >= longest as f64 * MIN_CURVE_FRACTION)
+        .flat_map(|curve| curve.points)
+        .collect();
+    let hull = convex_hull(points);
+    let area = contour_area(&hull);
+    let image_area = f64::from(luma.width()) * f64::from(luma.height());
+    if hull.len() < 3 || area < image_area * MIN_OUTLINE_FRACTION {
+        return None;
+    }
+
+    let [a, b, c, _] = min_area_rect(&hull);
+    let (first, second) = (distance(a, b), distance(b, c));
+    let elongation = first.min(second) / first.max(second);
+    let centre = (
+        (f64::from(a.x) + f64::from(c.x)) / 2.0,
+        (f64::from(a.y) + f64::from(c.y)) / 2.0,
+    );
+    let radius = hull
+        .iter()
+        .map(|point| (f64::from(point.x) - centre.0).hypot(f64::from(point.y) - centre.1))
+        .fold(0.0, f64::max);
+    let fill = (area / (std::f64::consts::PI * radius * radius)).min(1.0);
+    Some(elongation * fill)
+}
+
+fn distance(a: Point<i32>, b: Point<i32>) -> f64 {
+    f64::from(a.x - b.x).hypot(f64::from(a.y - b.y))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Luma;
+    use imageproc::drawing::{draw_filled_circle_mut, draw_filled_ellipse_mut};
+
+    /// A bright shape on a dark background, as a case looks against the backdrop
+    fn shape(draw: impl FnOnce(&mut GrayImage)) -> DynamicImage {
+        let mut image = GrayImage::from_pixel(400, 300, Luma([20]));
+        draw(&mut image);
+        DynamicImage::ImageLuma8(image)
+    }
+
+    #[test]
+    fn test_a_circle_is_a_tail_view() {
+        let headstamp = shape(|image| draw_filled_circle_mut(image, (200, 150), 90, Luma([220])));
+        let guess = classify(&headstamp).expect("circle should be found");
+        assert_eq!(guess.view_type, ViewType::Tail);
+        assert!(guess.confidence > 0.8, "{guess:?}");
+        assert!(!guess.needs_review);
+    }
+
+    #[test]
+    fn test_an_elongated_shape_is_a_side_view() {
+        let profile = shape(|image| {
+            draw_filled_ellipse_mut(image, (200, 150), 150, 45, Luma([220]));
+        });
+        let guess = classify(&profile).expect("profile should be found");
+        assert_eq!(guess.view_type, ViewType::Side);
+        assert!(guess.confidence > 0.8, "{guess:?}");
+        assert!(!guess.needs_review);
+    }
+
+    #[test]
+    fn test_rounder_shapes_score_rounder() {
+        let scores = [150, 110, 70].map(|half_width| {
+            let image = shape(|image| {
+                draw_filled_ellipse_mut(image, (200, 150), half_width, 60, Luma([220]));
+            })
+            .thumbnail(ANALYSIS_SIZE, ANALYSIS_SIZE)
+            .to_luma8();
+            roundness(&image).expect("ellipse should be found")
+        });
+        assert!(
+            scores.windows(2).all(|pair| pair[0] < pair[1]),
+            "{scores:?}"
+        );
+        // Barely wider than tall is close to the boundary, so it wants a second look
+        let nearly_round = shape(|image| {
+            draw_filled_ellipse_mut(image, (200, 150), 80, 68, Luma([220]));
+        });
+        let guess = classify(&nearly_round).expect("ellipse should be found");
+        assert!(guess.needs_review, "{guess:?}");
+    }
+
+    #[test]
+    fn test_a_blank_frame_has_no_guess() {
+        assert_eq!(classify(&shape(|_| {})), None);
+        // A speck is noise, not a case
+        let speck = shape(|image| draw_filled_circle_mut(image, (40, 40), 3, Luma([220])));
+        assert_eq!(classify(&speck), None);
+    }
+}
@@ -0,0 +1,413 @@
+//! What needs doing next, for the dashboard.
+//!
+//! Sessions waiting to be tagged, shells that can't be trained on until they
+//! have their required views and regions, and case types short of their target
+//! shell count, merged into one list with the most pressing first. Everything
+//! comes from the shell index and the case types held in memory, so the
+//! dashboard can ask as often as it likes without the data directory being read.
+
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashMap};
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+use crate::config::ViewType;
+use crate::ml_training::{CaseType, DEFAULT_REQUIRED_VIEWS};
+use crate::shell_data::{ShellFlag, ShellSummary};
+use crate::shell_targets::TargetProgress;
+
+/// Items listed when the request doesn't give a limit
+pub const DEFAULT_WORK_QUEUE_LIMIT: usize = 50;
+
+/// What sort of work an item is, most pressing first
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WorkKind {
+    /// A captured session with no brand or case type yet
+    UntaggedSession,
+    /// A shell marked for training that lacks a required view or a region
+    IncompleteShell,
+    /// A case type with fewer training shells than its target
+    UnderTarget,
+}
+
+impl WorkKind {
+    pub const ALL: [WorkKind; 3] = [
+        WorkKind::UntaggedSession,
+        WorkKind::IncompleteShell,
+        WorkKind::UnderTarget,
+    ];
+}
+
+/// One thing to do
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub struct WorkItem {
+    pub kind: WorkKind,
+    /// The session ID, or the case type name for a case type
+    pub id: String,
+    pub title: String,
+    /// Seconds since the session was captured; null for case types
+    pub age_seconds: Option<u64>,
+    /// Place in the queue, 1 being the most pressing
+    pub priority: usize,
+    pub session_id: Option<String>,
+    pub case_type: Option<String>,
+    /// The page the work is done on
+    pub link: Option<String>,
+    /// Required views the shell has no image of
+    pub missing_views: Vec<ViewType>,
+    /// Whether an image of the shell has no region
+    pub missing_regions: bool,
+    /// How far a case type is from its target
+    pub progress: Option<TargetProgress>,
+}
+
+impl WorkItem {
+    fn session(kind: WorkKind, summary: &ShellSummary, title: String, now: DateTime<Utc>) -> Self {
+        let link = match kind {
+            WorkKind::UntaggedSession => format!("/tagging/{}", summary.session_id),
+            _ => format!("/shell-edit/{}", summary.session_id),
+        };
+        Self {
+            kind,
+            id: summary.session_id.clone(),
+            title,
+            age_seconds: Some(age_seconds(summary.date_captured, now)),
+            priority: 0,
+            session_id: Some(summary.session_id.clone()),
+            case_type: None,
+            link: Some(link),
+            missing_views: Vec::new(),
+            missing_regions: false,
+            progress: None,
+        }
+    }
+}
+
+/// The queue as the dashboard shows it
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub struct WorkQueue {
+    /// Items of each kind, before the limit, for the dashboard's badges
+    pub counts: BTreeMap<WorkKind, usize>,
+    pub total: usize,
+    pub items: Vec<WorkItem>,
+}
+
+impl WorkQueue {
+    /// The `limit` most pressing of `items`, numbered in order
+    pub fn new(mut items: Vec<WorkItem>, limit: usize) -> Self {
+        let mut counts: BTreeMap<WorkKind, usize> =
+            WorkKind::ALL.iter().map(|kind| (*kind, 0)).collect();
+        for item in &items {
+            *counts.entry(item.kind).or_default() += 1;
+        }
+        let total = items.len();
+        prioritise(&mut items);
+        items.truncate(limit);
+        Self {
+            counts,
+            total,
+            items,
+        }
+    }
+}
+
+/// Whole seconds from `captured` to `now`, zero for a capture stamped in the future
+fn age_seconds(captured: DateTime<Utc>, now: DateTime<Utc>) -> u64 {
+    u64::try_from((now - captured).num_seconds()).unwrap_or_default()
+}
+
+/// Whether a shell still needs its brand or case type
+fn is_untagged(summary: &ShellSummary) -> bool {
+    summary.brand.trim().is_empty() || summary.shell_type.trim().is_empty()
+}
+
+/// The shell as a session to tag, if it hasn't been tagged
+pub fn untagged_session(summary: &ShellSummary, now: DateTime<Utc>) -> Option<WorkItem> {
+    if !is_untagged(summary) {
+        return None;
+    }
+    let title = format!(
+        "Tag the session captured {}",
+        summary.date_captured.format("%Y-%m-%d %H:%M")
+    );
+    Some(WorkItem::session(
+        WorkKind::UntaggedSession,
+        summary,
+        title,
+        now,
+    ))
+}
+
+/// The shell as one to finish, if it is meant for training but lacks a view or region
+pub fn incomplete_shell(
+    summary: &ShellSummary,
+    required: &[ViewType],
+    now: DateTime<Utc>,
+) -> Option<WorkItem> {
+    if is_untagged(summary) || !summary.include {
+        return None;
+    }
+    let summary = summary.clone().with_required_views(required);
+    let missing_regions = !summary.has_complete_regions;
+    if summary.missing_views.is_empty() && !missing_regions {
+        return None;
+    }
+    let mut missing: Vec<String> = summary
+        .missing_views
+        .iter()
+        .map(|view_type| format!("{view_type} view"))
+        .collect();
+    if missing_regions {
+        missing.push("regions".to_string());
+    }
+    let title = format!(
+        "{} {} needs {}",
+        summary.brand,
+        summary.shell_type,
+        missing.join(", ")
+    );
+    let mut item = WorkItem::session(WorkKind::IncompleteShell, &summary, title, now);
+    item.case_type = Some(format!("{}_{}", summary.brand, summary.shell_type));
+    item.missing_views = summary.missing_views;
+    item.missing_regions = missing_regions;
+    Some(item)
+}
+
+/// The case type as one to capture more of, if it is short of its target
+pub fn under_target(progress: TargetProgress) -> Option<WorkItem> {
+    if progress.reached {
+        return None;
+    }
+    Some(WorkItem {
+        kind: WorkKind::UnderTarget,
+        id: progress.case_type.clone(),
+        title: format!(
+            "Capture {} more {}",
+            progress.target as usize - progress.current,
+            progress.label
+        ),
+        age_seconds: None,
+        priority: 0,
+        session_id: None,
+        case_type: Some(progress.case_type.clone()),
+        link: None,
+        missing_views: Vec::new(),
+        missing_regions: false,
+        progress: Some(progress),
+    })
+}
+
+/// Which of two items to do first
+///
+/// Untagged sessions come before incomplete shells, which come before case
+/// types under their target. Sessions and shells go oldest first, case types
+/// least complete first.
+pub fn compare(a: &WorkItem, b: &WorkItem) -> Ordering {
+    let percent = |item: &WorkItem| item.progress.as_ref().map(|progress| progress.percent);
+    a.kind
+        .cmp(&b.kind)
+        .then_with(|| b.age_seconds.cmp(&a.age_seconds))
+        .then_with(|| {
+            percent(a)
+                .partial_cmp(&percent(b))
+                .unwrap_or(Ordering::Equal)
+        })
+        .then_with(|| a.id.cmp(&b.id))
+}
+
+/// Sort `items` most pressing first and number their priorities from 1
+pub fn prioritise(items: &mut [WorkItem]) {
+    items.sort_by(compare);
+    for (position, item) in items.iter_mut().enumerate() {
+        item.priority = position + 1;
+    }
+}
+
+/// Every piece of work the shells and case types call for, not yet in order
+///
+/// A case type's progress counts the shells training would use: marked for
+/// training, with every required view and none of `excluded_flags`.
+pub fn work_items(
+    summaries: &[ShellSummary],
+    case_types: &HashMap<String, CaseType>,
+    excluded_flags: &[ShellFlag],
+    now: DateTime<Utc>,
+) -> Vec<WorkItem> {
+    let required_views = |case_type: &str| {
+        case_types
+            .get(case_type)
+            .map(|case_type| case_type.required_views.as_slice())
+            .unwrap_or(&DEFAULT_REQUIRED_VIEWS)
+    };
+
+    let mut items = Vec::new();
+    let mut training_shells: HashMap<String, usize> = HashMap::new();
+    for summary in summaries {
+        let case_type = format!("{}_{}", summary.brand, summary.shell_type);
+        let required = required_views(&case_type);
+        if let Some(item) = untagged_session(summary, now) {
+            items.push(item);
+            continue;
+        }
+        if let Some(item) = incomplete_shell(summary, required, now) {
+            items.push(item);
+        }
+        let trainable = summary.include
+            && !summary
+                .flags
+                .iter()
+                .any(|flag| excluded_flags.contains(flag))
+            && required.iter().all(|view| summary.views.contains(view));
+        if trainable {
+            *training_shells.entry(case_type).or_default() += 1;
+        }
+    }
+
+    items.extend(
+        case_types
+            .values()
+            .filter_map(|case_type| {
+                TargetProgress::new(
+                    case_type,
+                    training_shells.get(&case_type.name).copied().unwrap_or(0),
+                )
+            })
+            .filter_map(under_target),
+    );
+    items
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shell_data::Shell;
+
+    fn at(time: &str) -> DateTime<Utc> {
+        time.parse().expect("time should parse")
+    }
+
+    fn summary(session_id: &str, brand: &str, captured: &str) -> ShellSummary {
+        let mut shell = Shell::new(brand.to_string(), "9mm".to_string());
+        shell.date_captured = at(captured);
+        shell.include = !brand.is_empty();
+        ShellSummary::new(session_id, &shell)
+    }
+
+    fn progress(case_type: &str, current: usize, target: u32) -> TargetProgress {
+        let mut case_type = CaseType::new(case_type.to_string(), "9mm".to_string(), None);
+        case_type.target_shell_count = Some(target);
+        TargetProgress::new(&case_type, current).expect("case type should have a target")
+    }
+
+    #[test]
+    fn test_work_is_ordered_by_kind_then_age_then_completion() {
+        let now = at("2026-10-16T09:00:00Z");
+        let mut items = vec![
+            under_target(progress("Federal_9mm", 30, 40)).expect("under target"),
+            incomplete_shell(
+                &summary("old-shell", "Winchester", "2026-10-01T09:00:00Z"),
+                &DEFAULT_REQUIRED_VIEWS,
+                now,
+            )
+            .expect("no views, so incomplete"),
+            untagged_session(&summary("new", "", "2026-10-16T08:00:00Z"), now).expect("untagged"),
+            under_target(progress("Winchester_9mm", 2, 40)).expect("under target"),
+            untagged_session(&summary("old", "", "2026-10-15T08:00:00Z"), now).expect("untagged"),
+        ];
+        prioritise(&mut items);
+
+        let order: Vec<(&str, usize)> = items
+            .iter()
+            .map(|item| (item.id.as_str(), item.priority))
+            .collect();
+        assert_eq!(
+            order,
+            vec![
+                ("old", 1),
+                ("new", 2),
+                ("old-shell", 3),
+                ("Winchester_9mm", 4),
+                ("Federal_9mm", 5),
+            ]
+        );
+        assert_eq!(items[0].age_seconds, Some(25 * 3600));
+        assert_eq!(items[0].link.as_deref(), Some("/tagging/old"));
+        assert_eq!(items[2].link.as_deref(), Some("/shell-edit/old-shell"));
+        assert_eq!(items[2].case_type.as_deref(), Some("Winchester_9mm"));
+        assert_eq!(items[2].missing_views, DEFAULT_REQUIRED_VIEWS.to_vec());
+        assert!(items[2].missing_regions);
+        assert_eq!(items[3].title, "Capture 38 more Winchester_9mm");
+    }
+
+    #[test]
+    fn test_only_unfinished_work_is_listed() {
+        let now = at("2026-10-16T09:00:00Z");
+        assert!(under_target(progress("Federal_9mm", 40, 40)).is_none());
+        let tagged = summary("tagged", "Winchester", "2026-10-16T08:00:00Z");
+        assert!(untagged_session(&tagged, now).is_none());
+        // Nothing required, but the shell has no images to carry regions
+        assert!(incomplete_shell(&tagged, &[], now).is_some());
+
+        let mut excluded = tagged.clone();
+        excluded.include = false;
+        assert!(incomplete_shell(&excluded, &DEFAULT_REQUIRED_VIEWS, now).is_none());
+        // A capture stamped after now isn't given a negative age
+        let ahead = summary("ahead", "", "2026-10-16T10:00:00Z");
+        assert_eq!(
+            untagged_session(&ahead, now).and_then(|item| item.age_seconds),
+            Some(0)
+        );
+    }
+
+    #[test]
+    fn test_queue_counts_every_item_but_lists_up_to_the_limit() {
+        let now = at("2026-10-16T09:00:00Z");
+        let mut case_type = CaseType::new("Winchester_9mm".to_string(), "9mm".to_string(), None);
+        case_type.target_shell_count = Some(5);
+        case_type.required_views = Vec::new();
+        let case_types = HashMap::from([(case_type.name.clone(), case_type)]);
+        let summaries = vec![
+            summary("a", "", "2026-10-16T07:00:00Z"),
+            summary("b", "", "2026-10-16T08:00:00Z"),
+            summary("c", "Winchester", "2026-10-16T06:00:00Z"),
+        ];
+
+        let queue = WorkQueue::new(work_items(&summaries, &case_types, &[], now), 2);
+        assert_eq!(queue.total, 4);
+        assert_eq!(
+            queue.counts,
+            BTreeMap::from([
+                (WorkKind::UntaggedSession, 2),
+                (WorkKind::IncompleteShell, 1),
+                (WorkKind::UnderTarget, 1),
+            ])
+        );
+        let ids: Vec<&str> = queue.items.iter().map(|item| item.id.as_str()).collect();
+        assert_eq!(ids, vec!["a", "b"]);
+
+        // Shell "c" has every view its type requires, so it counts towards the target
+        let queue = WorkQueue::new(work_items(&summaries, &case_types, &[], now), 10);
+        let last = queue.items.last().expect("queue should have items");
+        assert_eq!(last.kind, WorkKind::UnderTarget);
+        assert_eq!(
+            last.progress.as_ref().map(|progress| progress.current),
+            Some(1)
+        );
+        // Unless it carries a flag that keeps it out of training
+        let mut flagged = summaries.clone();
+        flagged[2].flags = vec![ShellFlag::Blurry];
+        let items = work_items(&flagged, &case_types, &[ShellFlag::Blurry], now);
+        let under = items
+            .iter()
+            .find(|item| item.kind == WorkKind::UnderTarget)
+            .expect("case type should be under target");
+        assert_eq!(
+            under.progress.as_ref().map(|progress| progress.current),
+            Some(0)
+        );
+    }
+}
@@ -0,0 +1,463 @@
+use std::time::Duration;
+
+use axum::http::{Method, StatusCode};
+use shell_sorter::config::Settings;
+use shell_sorter::controller_entities::{ControllerEntity, FirmwareCheck, FirmwareState};
+use shell_sorter::controller_monitor::{
+    CalibrationError, ControllerCommand, ControllerHandle, ControllerMonitor, ControllerResponse,
+    SensorReadings,
+};
+use shell_sorter::event_log::EventRecorder;
+use shell_sorter::temperature::TemperatureSensor;
+use tokio::sync::broadcast::error::TryRecvError;
+
+use crate::support::fake_esphome::FakeEsphome;
+
+/// Start a controller monitor talking to `device`
+fn monitor(device: &FakeEsphome) -> ControllerHandle {
+    let settings = Settings {
+        esphome_hostname: device.hostname(),
+        ..Settings::default()
+    };
+    let (monitor, controller) = ControllerMonitor::new(settings, EventRecorder::default())
+        .expect("controller monitor should be created");
+    tokio::spawn(monitor.run());
+    controller
+}
+
+/// Wait for the monitor's first firmware check
+async fn firmware_checked(controller: &ControllerHandle) -> FirmwareCheck {
+    tokio::time::timeout(Duration::from_secs(10), async {
+        loop {
+            let firmware = controller.get_status().await.firmware;
+            if firmware.state != FirmwareState::Unknown {
+                return firmware;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .expect("the firmware should be checked")
+}
+
+async fn sensors(controller: &ControllerHandle) -> SensorReadings {
+    match controller.send_command(ControllerCommand::GetSensors).await {
+        Ok(ControllerResponse::SensorData(readings)) => readings,
+        other => panic!("expected sensor readings, got {other:?}"),
+    }
+}
+
+#[tokio::test]
+async fn test_sensor_readings_follow_the_device() {
+    let device = FakeEsphome::builder()
+        .sensor("case_ready_to_feed", [false, true])
+        .sensor("case_in_camera_view", [true])
+        .start()
+        .await;
+    let controller = monitor(&device);
+
+    let first = sensors(&controller).await;
+    assert!(!first.case_ready);
+    assert!(first.case_in_view);
+    let second = sensors(&controller).await;
+    assert!(second.case_ready);
+    assert!(second.timestamp >= first.timestamp);
+
+    // A case leaving the camera is seen on the next read
+    device.script_sensor("case_in_camera_view", [false]);
+    assert!(!sensors(&controller).await.case_in_view);
+    assert_eq!(
+        device.count(Method::GET, "/binary_sensor/case_ready_to_feed/state"),
+        3
+    );
+
+    // A sensor that fails reads as off
+    device.fail(
+        "/binary_sensor/case_ready_to_feed",
+        StatusCode::INTERNAL_SERVER_ERROR,
+    );
+    assert!(!sensors(&controller).await.case_ready);
+}
+
+#[tokio::test]
+async fn test_next_case_presses_the_button() {
+    let device = FakeEsphome::builder()
+        .require_auth("admin", "shellsorter")
+        .start()
+        .await;
+    let controller = monitor(&device);
+
+    assert!(matches!(
+        controller.send_command(ControllerCommand::NextCase).await,
+        Ok(ControllerResponse::Sent { .. })
+    ));
+    assert_eq!(device.presses("trigger_next_case"), 1);
+
+    // The device failing the press is reported, and recovering works again
+    device.fail("/button/trigger_next_case", StatusCode::SERVICE_UNAVAILABLE);
+    match controller.send_command(ControllerCommand::NextCase).await {
+        Ok(ControllerResponse::Error(e)) => assert!(e.contains("503"), "{e}"),
+        other => panic!("expected the failed press to be reported, got {other:?}"),
+    }
+    device.recover("/button/trigger_next_case");
+    assert!(matches!(
+        controller.send_command(ControllerCommand::NextCase).await,
+        Ok(ControllerResponse::Sent { .. })
+    ));
+    assert_eq!(device.presses("trigger_next_case"), 3);
+}
+
+#[tokio::test]
+async fn test_commands_are_retried_while_the_controller_is_briefly_offline() {
+    let device = FakeEsphome::start().await;
+    let controller = monitor(&device);
+    // The health check has just seen the device online
+    firmware_checked(&controller).await;
+    let times = controller.get_status().await.times();
+    assert!(
+        times.last_seen.is_some() && times.last_check.is_some(),
+        "{times:?}"
+    );
+    assert!(times.last_seen_age_seconds.is_some_and(|age| age < 10));
+
+    device.reset_connections(2);
+    match controller.send_command(ControllerCommand::NextCase).await {
+        Ok(ControllerResponse::Sent { attempts, .. }) => assert_eq!(attempts, 3),
+        other => panic!("expected the press to get through, got {other:?}"),
+    }
+    assert_eq!(device.presses("trigger_next_case"), 1);
+
+    // An HTTP error means the device answered, so it isn't retried
+    device.fail("/button/trigger_next_case", StatusCode::SERVICE_UNAVAILABLE);
+    match controller.send_command(ControllerCommand::NextCase).await {
+        Ok(ControllerResponse::Error(e)) => assert!(!e.contains("attempts"), "{e}"),
+        other => panic!("expected the failed press to be reported, got {other:?}"),
+    }
+    assert_eq!(device.presses("trigger_next_case"), 2);
+
+    // Without a retry window the first reset is reported
+    let offline = FakeEsphome::start().await;
+    let settings = Settings {
+        esphome_hostname: offline.hostname(),
+        controller_retry_window_s: 0,
+        ..Settings::default()
+    };
+    let (monitor, controller) = ControllerMonitor::new(settings, EventRecorder::default())
+        .expect("controller monitor should be created");
+    tokio::spawn(monitor.run());
+    firmware_checked(&controller).await;
+    offline.reset_connections(1);
+    match controller.send_command(ControllerCommand::NextCase).await {
+        Ok(ControllerResponse::Error(e)) => assert!(!e.contains("attempts"), "{e}"),
+        other => panic!("expected the reset to be reported, got {other:?}"),
+    }
+    assert_eq!(offline.presses("trigger_next_case"), 0);
+    assert!(matches!(
+        controller.send_command(ControllerCommand::NextCase).await,
+        Ok(ControllerResponse::Sent { attempts: 1, .. })
+    ));
+}
+
+#[tokio::test]
+async fn test_maintenance_mode_refuses_motion_commands() {
+    let device = FakeEsphome::builder().start().await;
+    let controller = monitor(&device);
+
+    let enabled = controller
+        .send_command(ControllerCommand::SetMaintenance {
+            enabled: true,
+            reason: Some("clearing jam".to_string()),
+        })
+        .await;
+    assert!(
+        matches!(&enabled, Ok(ControllerResponse::Maintenance(status)) if status.enabled),
+        "{enabled:?}"
+    );
+
+    let motion = [
+        ControllerCommand::NextCase,
+        ControllerCommand::TriggerVibration,
+        ControllerCommand::SetServoPosition {
+            servo: "case_feeder_servo_position".to_string(),
+            position: 30,
+        },
+        ControllerCommand::StartCalibration,
+        ControllerCommand::JogServo {
+            servo: "case_feeder_servo_position".to_string(),
+            delta: 5,
+        },
+    ];
+    for command in motion {
+        let kind = command.kind();
+        match controller.send_command(command).await {
+            Ok(ControllerResponse::CalibrationFailed(CalibrationError::Maintenance(reason))) => {
+                assert_eq!(reason.as_deref(), Some("clearing jam"));
+            }
+            other => panic!("expected {kind} to be refused, got {other:?}"),
+        }
+    }
+    // Nothing reached the device, but sensors can still be read
+    assert!(
+        !device
+            .calls()
+            .iter()
+            .any(|call| call.method == Method::POST),
+        "{:?}",
+        device.calls()
+    );
+    sensors(&controller).await;
+    match controller.send_command(ControllerCommand::GetStatus).await {
+        Ok(ControllerResponse::StatusData(status)) => {
+            assert_eq!(status.status, "Maintenance");
+            assert!(!status.ready);
+        }
+        other => panic!("expected machine status, got {other:?}"),
+    }
+
+    let disabled = controller
+        .send_command(ControllerCommand::SetMaintenance {
+            enabled: false,
+            reason: None,
+        })
+        .await;
+    assert!(
+        matches!(&disabled, Ok(ControllerResponse::Maintenance(status)) if !status.enabled),
+        "{disabled:?}"
+    );
+    assert!(matches!(
+        controller.send_command(ControllerCommand::NextCase).await,
+        Ok(ControllerResponse::Sent { .. })
+    ));
+    assert_eq!(device.presses("trigger_next_case"), 1);
+}
+
+#[tokio::test]
+async fn test_controller_commands_reach_their_entities() {
+    let device = FakeEsphome::builder()
+        .device_info("Shell sorter controller 2025.1")
+        .start()
+        .await;
+    let controller = monitor(&device);
+    assert_eq!(
+        firmware_checked(&controller).await.state,
+        FirmwareState::Compatible
+    );
+
+    assert!(matches!(
+        controller
+            .send_command(ControllerCommand::SetServoPosition {
+                servo: "case_feeder_servo_position".to_string(),
+                position: 30,
+            })
+            .await,
+        Ok(ControllerResponse::Sent { .. })
+    ));
+    assert_eq!(
+        device.state("number/case_feeder_servo_position").as_deref(),
+        Some("30")
+    );
+    assert!(device.calls().iter().any(|call| {
+        call.method == Method::POST
+            && call.path == "/number/case_feeder_servo_position/set"
+            && call.query.as_deref() == Some("value=30")
+    }));
+    assert!(matches!(
+        controller
+            .send_command(ControllerCommand::TriggerVibration)
+            .await,
+        Ok(ControllerResponse::Sent { .. })
+    ));
+    assert_eq!(
+        device.state("switch/vibration_motor").as_deref(),
+        Some("ON")
+    );
+
+    match controller
+        .send_command(ControllerCommand::GetHardwareStatus)
+        .await
+    {
+        Ok(ControllerResponse::HardwareData(status)) => {
+            assert_eq!(
+                status.get("controller").map(String::as_str),
+                Some("Connected")
+            );
+            assert_eq!(
+                status.get("device_info").map(String::as_str),
+                Some("Shell sorter controller 2025.1")
+            );
+        }
+        other => panic!("expected hardware status, got {other:?}"),
+    }
+}
+
+#[tokio::test]
+async fn test_firmware_missing_an_entity_is_incompatible() {
+    // A controller flashed with the next-case button renamed
+    let device = FakeEsphome::builder()
+        .without_entity("button/trigger_next_case")
+        .entity("button/feed_case")
+        .start()
+        .await;
+    let controller = monitor(&device);
+
+    let firmware = firmware_checked(&controller).await;
+    assert_eq!(firmware.state, FirmwareState::Incompatible);
+    assert_eq!(
+        firmware.missing_entities,
+        vec![ControllerEntity::new("button", "trigger_next_case")]
+    );
+
+    // Feeding fails without contacting the controller, and names the entity
+    match controller.send_command(ControllerCommand::NextCase).await {
+        Ok(ControllerResponse::Error(e)) => {
+            assert!(e.contains("button/trigger_next_case"), "{e}")
+        }
+        other => panic!("expected the missing button to be refused, got {other:?}"),
+    }
+    assert!(
+        !device
+            .calls()
+            .iter()
+            .any(|call| call.path.ends_with("/press"))
+    );
+    // Everything else still works
+    assert!(matches!(
+        controller
+            .send_command(ControllerCommand::TriggerVibration)
+            .await,
+        Ok(ControllerResponse::Sent { .. })
+    ));
+
+    match controller
+        .send_command(ControllerCommand::GetHardwareStatus)
+        .await
+    {
+        Ok(ControllerResponse::HardwareData(status)) => {
+            assert_eq!(
+                status.get("controller_firmware").map(String::as_str),
+                Some("incompatible")
+            );
+            assert_eq!(
+                status.get("missing_entities").map(String::as_str),
+                Some("button/trigger_next_case")
+            );
+        }
+        other => panic!("expected hardware status, got {other:?}"),
+    }
+
+    // Remapping the button to the firmware's new name clears it
+    let remapped = Settings {
+        esphome_hostname: device.hostname(),
+        controller_next_case_button: "feed_case".to_string(),
+        ..Settings::default()
+    };
+    controller
+        .update_config(remapped)
+        .await
+        .expect("settings should be updated");
+    assert_eq!(
+        firmware_checked(&controller).await.state,
+        FirmwareState::Compatible
+    );
+    assert!(matches!(
+        controller.send_command(ControllerCommand::NextCase).await,
+        Ok(ControllerResponse::Sent { .. })
+    ));
+    assert_eq!(device.presses("feed_case"), 1);
+}
+
+#[tokio::test]
+async fn test_each_overheating_excursion_is_reported_once() {
+    let device = FakeEsphome::builder()
+        .numeric_sensor(
+            "esp_temperature",
+            [
+                "55.0 °C", "68.5 °C", "71.0 °C", "74.5 °C", "67.5 °C", "72.0 °C",
+            ],
+        )
+        .start()
+        .await;
+    let sensor: TemperatureSensor = format!("{}/esp_temperature", device.hostname())
+        .parse()
+        .expect("sensor should parse");
+    let settings = Settings {
+        esphome_hostname: device.hostname(),
+        // The second sensor isn't on the device, so is skipped
+        esphome_temperature_entities: vec![
+            sensor.clone(),
+            format!("{}/enclosure_temperature", device.hostname())
+                .parse()
+                .expect("sensor should parse"),
+        ],
+        temperature_warning_c: 70.0,
+        overheat_pauses_automation: true,
+        ..Settings::default()
+    };
+    let events = EventRecorder::default();
+    let (monitor, controller) = ControllerMonitor::new(settings.clone(), events.clone())
+        .expect("controller monitor should be created");
+    let mut overheats = controller.subscribe_overheats();
+    tokio::spawn(monitor.run());
+
+    // Each settings update checks again straight away, reading the next temperature
+    for (check, expected) in [55.0, 68.5, 71.0, 74.5, 67.5, 72.0].into_iter().enumerate() {
+        if check > 0 {
+            controller
+                .update_config(settings.clone())
+                .await
+                .expect("settings should be updated");
+        }
+        tokio::time::timeout(Duration::from_secs(10), async {
+            loop {
+                let temperatures = controller.get_status().await.temperatures;
+                if temperatures.len() == 1 && temperatures[0].celsius == expected {
+                    return;
+                }
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .unwrap_or_else(|_| panic!("check {check} should read {expected} °C"));
+    }
+
+    // 71 starts an excursion, 74.5 continues it, 67.5 ends it and 72 starts another
+    let first = overheats
+        .try_recv()
+        .expect("the first excursion is reported");
+    assert_eq!((first.sensor, first.celsius), (sensor.clone(), 71.0));
+    let second = overheats
+        .try_recv()
+        .expect("the second excursion is reported");
+    assert_eq!(second.celsius, 72.0);
+    assert!(matches!(overheats.try_recv(), Err(TryRecvError::Empty)));
+    assert!(!events.recent(10, Some("Overheated")).is_empty());
+
+    match controller.send_command(ControllerCommand::GetStatus).await {
+        Ok(ControllerResponse::StatusData(status)) => {
+            assert_eq!(status.overheating.len(), 1);
+            assert!(status.maintenance.enabled);
+            let reason = status.maintenance.reason.unwrap_or_default();
+            assert!(reason.starts_with("Overheating:"), "{reason}");
+        }
+        other => panic!("expected machine status, got {other:?}"),
+    }
+    match controller
+        .send_command(ControllerCommand::GetHardwareStatus)
+        .await
+    {
+        Ok(ControllerResponse::HardwareData(status)) => assert_eq!(
+            status
+                .get(&format!("temperature_{sensor}"))
+                .map(String::as_str),
+            Some("72.0 °C (overheated)")
+        ),
+        other => panic!("expected hardware status, got {other:?}"),
+    }
+    // Automation is paused until someone turns maintenance mode off
+    assert!(matches!(
+        controller.send_command(ControllerCommand::NextCase).await,
+        Ok(ControllerResponse::CalibrationFailed(
+            CalibrationError::Maintenance(_)
+        ))
+    ));
+}
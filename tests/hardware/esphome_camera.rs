@@ -0,0 +1,297 @@
+use std::time::Duration;
+
+use axum::http::{Method, StatusCode};
+use shell_sorter::camera_manager::{CameraHandle, CameraInfo, CameraManager, EspEntity};
+use shell_sorter::event_log::EventRecorder;
+use shell_sorter::snapshot_stream::{
+    EspStream, MIN_SNAPSHOT_POLL_INTERVAL, STREAM_FAILURES_BEFORE_FALLBACK, SnapshotStreams,
+    StreamMode,
+};
+use tokio::sync::broadcast;
+
+use crate::support::fake_esphome::{FakeEsphome, STREAM_BOUNDARY};
+
+/// Start a camera manager for the camera at `device` and ask it to detect cameras
+async fn detect(device: &FakeEsphome) -> CameraHandle {
+    let (manager, cameras) = CameraManager::new(vec![device.hostname()], EventRecorder::default())
+        .expect("camera manager should be created");
+    tokio::spawn(manager.run());
+    cameras
+        .detect_cameras()
+        .await
+        .expect("detection should be requested");
+    cameras
+}
+
+/// The cameras the manager knows once it has handled the detection
+async fn detected(cameras: &CameraHandle) -> Vec<CameraInfo> {
+    // Detection is queued ahead of this request, so the list reflects it
+    cameras
+        .list_cameras()
+        .await
+        .expect("cameras should be listed")
+}
+
+#[tokio::test]
+async fn test_probe_detects_the_camera() {
+    let device = FakeEsphome::builder()
+        .latency(Duration::from_millis(50))
+        .start()
+        .await;
+    let cameras = detect(&device).await;
+
+    let found = detected(&cameras).await;
+    assert_eq!(found.len(), 1);
+    let camera = &found[0];
+    assert!(camera.online);
+    assert_eq!(camera.id, "esphome_127.0.0.1");
+    assert_eq!(camera.hostname, device.hostname());
+    assert_eq!(camera.snapshot_url.path(), "/camera/snapshot");
+    assert_eq!(device.count(Method::GET, "/text_sensor/device_info"), 1);
+}
+
+#[tokio::test]
+async fn test_probe_failure_is_not_a_camera() {
+    let device = FakeEsphome::builder()
+        .fail("/text_sensor/device_info", StatusCode::SERVICE_UNAVAILABLE)
+        .start()
+        .await;
+    let cameras = detect(&device).await;
+
+    assert!(detected(&cameras).await.is_empty());
+
+    // A camera seen before stays listed but goes offline when it stops answering
+    device.recover("/text_sensor/device_info");
+    cameras
+        .detect_cameras()
+        .await
+        .expect("detection should be requested");
+    assert!(detected(&cameras).await[0].online);
+    device.fail("/text_sensor/device_info", StatusCode::SERVICE_UNAVAILABLE);
+    cameras
+        .detect_cameras()
+        .await
+        .expect("detection should be requested");
+    let offline = detected(&cameras).await;
+    assert!(!offline[0].online);
+    assert!(
+        offline[0]
+            .last_error
+            .as_deref()
+            .is_some_and(|error| error.contains("503")),
+        "{offline:?}"
+    );
+}
+
+#[tokio::test]
+async fn test_snapshot_is_captured() {
+    let device = FakeEsphome::start().await;
+    let cameras = detect(&device).await;
+    let camera_id = detected(&cameras).await[0].id.clone();
+
+    let jpeg = cameras
+        .capture_image(camera_id.clone())
+        .await
+        .expect("snapshot should be captured");
+    assert_eq!(jpeg, device.snapshot());
+
+    device.fail("/camera/snapshot", StatusCode::INTERNAL_SERVER_ERROR);
+    let error = cameras
+        .capture_image(camera_id.clone())
+        .await
+        .expect_err("failed snapshot should be an error");
+    assert!(error.to_string().contains("500"), "{error}");
+
+    let status = cameras.get_status().await.expect("status should be read");
+    let stats = &status.capture_stats[&camera_id];
+    assert_eq!((stats.attempted, stats.succeeded, stats.failed), (2, 1, 1));
+    // A failed snapshot alone doesn't take the camera offline
+    assert!(status.cameras[&camera_id].online);
+    assert_eq!(device.count(Method::GET, "/camera/snapshot"), 2);
+}
+
+#[tokio::test]
+async fn test_stream_sends_frames() {
+    let device = FakeEsphome::builder().stream_frames(4).start().await;
+    let cameras = detect(&device).await;
+    let camera = detected(&cameras).await.remove(0);
+
+    let response = reqwest::get(camera.stream_url)
+        .await
+        .expect("stream should be requested");
+    assert!(
+        response
+            .headers()
+            .get(reqwest::header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .is_some_and(|value| value.starts_with("multipart/x-mixed-replace"))
+    );
+    let body = response.bytes().await.expect("stream should be read");
+    let boundary = format!("--{STREAM_BOUNDARY}\r\n");
+    let frames = body
+        .windows(boundary.len())
+        .filter(|window| *window == boundary.as_bytes())
+        .count();
+    assert_eq!(frames, 4);
+}
+
+#[tokio::test]
+async fn test_esp_settings_round_trip() {
+    let device = FakeEsphome::builder()
+        .select("framesize", &["800x600", "1024x768", "1600x1200"])
+        .start()
+        .await;
+    let cameras = detect(&device).await;
+    let camera_id = detected(&cameras).await[0].id.clone();
+    let framesize: EspEntity = "select/framesize".parse().expect("entity should parse");
+
+    let states = cameras
+        .get_esp_settings(camera_id.clone(), vec![framesize.clone()])
+        .await
+        .expect("settings should be read");
+    assert_eq!(states[0].state.as_deref(), Some("800x600"));
+    assert_eq!(states[0].options.len(), 3);
+
+    let results = cameras
+        .set_esp_settings(camera_id, vec![(framesize, "1600x1200".to_string())])
+        .await
+        .expect("settings should be written");
+    assert!(results[0].success, "{results:?}");
+    assert_eq!(
+        device.state("select/framesize").as_deref(),
+        Some("1600x1200")
+    );
+}
+
+/// Snapshot streams polling as fast as they are allowed to
+fn snapshot_streams(cameras: &CameraHandle) -> SnapshotStreams {
+    SnapshotStreams::new(cameras.clone(), Duration::ZERO).expect("streams should be created")
+}
+
+/// The polled frames of a stream that fell back to snapshots
+fn polled(stream: EspStream) -> broadcast::Receiver<axum::body::Bytes> {
+    match stream {
+        EspStream::Polled(frames) => frames,
+        EspStream::Mjpeg(_) => panic!("stream should have fallen back to snapshots"),
+    }
+}
+
+async fn next_frame(frames: &mut broadcast::Receiver<axum::body::Bytes>) -> Vec<u8> {
+    tokio::time::timeout(Duration::from_secs(5), frames.recv())
+        .await
+        .expect("a frame should arrive")
+        .expect("the poller should still be running")
+        .to_vec()
+}
+
+#[tokio::test]
+async fn test_missing_stream_falls_back_to_snapshots() {
+    let device = FakeEsphome::builder()
+        .fail("/camera/stream", StatusCode::NOT_FOUND)
+        .start()
+        .await;
+    let cameras = detect(&device).await;
+    let camera = detected(&cameras).await.remove(0);
+    let streams = snapshot_streams(&cameras);
+    // Configured faster than the cameras can take, so the minimum applies
+    assert_eq!(streams.poll_interval(), MIN_SNAPSHOT_POLL_INTERVAL);
+    assert_eq!(streams.mode(&camera.id), StreamMode::Mjpeg);
+
+    let mut frames = polled(streams.open(&camera).await.expect("stream should open"));
+    assert_eq!(next_frame(&mut frames).await, device.snapshot());
+    assert_eq!(streams.mode(&camera.id), StreamMode::SnapshotPoll);
+    // One 404 is enough to fall back
+    assert_eq!(device.count(Method::GET, "/camera/stream"), 1);
+
+    let status = cameras.get_status().await.expect("status should be read");
+    assert!(status.stream_health[&camera.id].last_frame.is_some());
+}
+
+#[tokio::test]
+async fn test_failing_stream_falls_back_after_repeated_failures() {
+    let device = FakeEsphome::builder()
+        .fail("/camera/stream", StatusCode::SERVICE_UNAVAILABLE)
+        .start()
+        .await;
+    let cameras = detect(&device).await;
+    let camera = detected(&cameras).await.remove(0);
+    let streams = snapshot_streams(&cameras);
+
+    for _ in 1..STREAM_FAILURES_BEFORE_FALLBACK {
+        let error = match streams.open(&camera).await {
+            Err(error) => error,
+            Ok(_) => panic!("a failing stream should not open"),
+        };
+        assert!(error.to_string().contains("503"), "{error}");
+        assert_eq!(streams.mode(&camera.id), StreamMode::Mjpeg);
+    }
+    let mut frames = polled(streams.open(&camera).await.expect("stream should open"));
+    assert_eq!(next_frame(&mut frames).await, device.snapshot());
+    assert_eq!(streams.mode(&camera.id), StreamMode::SnapshotPoll);
+}
+
+#[tokio::test]
+async fn test_viewers_share_one_snapshot_poller() {
+    let device = FakeEsphome::builder()
+        .fail("/camera/stream", StatusCode::NOT_FOUND)
+        .start()
+        .await;
+    let cameras = detect(&device).await;
+    let camera = detected(&cameras).await.remove(0);
+    let streams = snapshot_streams(&cameras);
+
+    let (first, second) = tokio::join!(streams.open(&camera), streams.open(&camera));
+    let mut first = polled(first.expect("first viewer should open"));
+    let mut second = polled(second.expect("second viewer should open"));
+    for _ in 0..3 {
+        assert_eq!(next_frame(&mut first).await, device.snapshot());
+        assert_eq!(next_frame(&mut second).await, device.snapshot());
+    }
+    // Both viewers were served by the same polls, not one poller each
+    let polls = device.count(Method::GET, "/camera/snapshot");
+    assert!((3..=4).contains(&polls), "{polls} snapshots for 3 frames");
+
+    // A third viewer joins the running poller without trying the stream again
+    let stream_requests = device.count(Method::GET, "/camera/stream");
+    let mut third = polled(
+        streams
+            .open(&camera)
+            .await
+            .expect("third viewer should open"),
+    );
+    next_frame(&mut third).await;
+    assert_eq!(device.count(Method::GET, "/camera/stream"), stream_requests);
+
+    // Once nobody is watching the poller stops
+    drop((first, second, third));
+    tokio::time::sleep(MIN_SNAPSHOT_POLL_INTERVAL * 2).await;
+    let stopped_at = device.count(Method::GET, "/camera/snapshot");
+    tokio::time::sleep(MIN_SNAPSHOT_POLL_INTERVAL * 3).await;
+    assert_eq!(device.count(Method::GET, "/camera/snapshot"), stopped_at);
+}
+
+#[tokio::test]
+async fn test_polled_stream_ends_when_the_real_stream_is_back() {
+    let device = FakeEsphome::builder()
+        .fail("/camera/stream", StatusCode::NOT_FOUND)
+        .start()
+        .await;
+    let cameras = detect(&device).await;
+    let camera = detected(&cameras).await.remove(0);
+    let streams = snapshot_streams(&cameras).with_retry_interval(Duration::from_millis(300));
+
+    let mut frames = polled(streams.open(&camera).await.expect("stream should open"));
+    next_frame(&mut frames).await;
+    device.recover("/camera/stream");
+
+    let ended = tokio::time::timeout(Duration::from_secs(5), async {
+        while frames.recv().await.is_ok() {}
+    })
+    .await;
+    assert!(ended.is_ok(), "the polled stream should end");
+    assert_eq!(streams.mode(&camera.id), StreamMode::Mjpeg);
+    assert!(matches!(
+        streams.open(&camera).await,
+        Ok(EspStream::Mjpeg(_))
+    ));
+}
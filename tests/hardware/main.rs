@@ -0,0 +1,7 @@
+//! Controller and ESPHome camera tests against a fake ESPHome device.
+
+#[path = "../support/mod.rs"]
+mod support;
+
+mod controller_monitor;
+mod esphome_camera;
@@ -0,0 +1,569 @@
+//! A fake ESPHome device for integration tests.
+//!
+//! [`FakeEsphome`] serves the parts of the ESPHome REST API the sorter talks to
+//! on a local port: binary sensors, sensors, buttons, switches, numbers,
+//! lights, selects, text sensors, and the camera snapshot and MJPEG stream. It
+//! starts with the entities the default settings drive. Binary sensor and
+//! sensor values can be scripted, every request is recorded for assertions, and the builder adds
+//! latency, basic auth and failing paths; failures can also be switched on and
+//! off while a test runs, and the next few connections can be reset to look
+//! like the device dropping off WiFi. Like ESPHome's web server, it closes
+//! every connection after answering.
+
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+
+use axum::Router;
+use axum::extract::{OriginalUri, State};
+use axum::http::{HeaderMap, HeaderValue, Method, StatusCode, header};
+use axum::response::{IntoResponse, Response};
+use image::codecs::jpeg::JpegEncoder;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::task::JoinHandle;
+
+/// Boundary between frames of the MJPEG stream
+pub const STREAM_BOUNDARY: &str = "frame";
+
+/// Entities a new device has: everything the sorter drives with its default settings
+const DEFAULT_ENTITIES: &[&str] = &[
+    "button/trigger_next_case",
+    "binary_sensor/case_ready_to_feed",
+    "binary_sensor/case_in_camera_view",
+    "switch/vibration_motor",
+    "light/flash",
+    "number/case_feeder_servo_position",
+    "number/case_position_servo_position",
+    "text_sensor/device_info",
+];
+
+/// One request the device received
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Call {
+    pub method: Method,
+    pub path: String,
+    pub query: Option<String>,
+}
+
+/// Everything the device knows, shared with its request handler
+struct Device {
+    /// Entities as `domain/object_id`
+    entities: HashSet<String>,
+    /// States of switches, lights, numbers, selects and text sensors by entity
+    states: HashMap<String, String>,
+    /// Options of select entities
+    options: HashMap<String, Vec<String>>,
+    /// Binary sensor readings still to come by entity; the last one repeats
+    sensors: HashMap<String, VecDeque<bool>>,
+    /// Numeric sensor states still to come by entity, e.g. `45.5 °C`; the last one repeats
+    numeric_sensors: HashMap<String, VecDeque<String>>,
+    /// Paths starting with these prefixes answer with the status
+    failures: Vec<(String, StatusCode)>,
+    /// Connections still to reset as soon as they are accepted
+    resets: usize,
+    /// Expected `Authorization` header, if the device wants one
+    authorization: Option<String>,
+    latency: Duration,
+    stream_frames: usize,
+    snapshot: Vec<u8>,
+    calls: Vec<Call>,
+}
+
+/// Sets up a [`FakeEsphome`] before it starts answering
+pub struct FakeEsphomeBuilder {
+    device: Device,
+}
+
+impl FakeEsphomeBuilder {
+    /// Wait this long before every response
+    pub fn latency(mut self, latency: Duration) -> Self {
+        self.device.latency = latency;
+        self
+    }
+
+    /// Answer 401 to requests without these basic auth credentials
+    pub fn require_auth(mut self, username: &str, password: &str) -> Self {
+        let credentials = base64(format!("{username}:{password}").as_bytes());
+        self.device.authorization = Some(format!("Basic {credentials}"));
+        self
+    }
+
+    /// Answer `status` to every path starting with `path_prefix`
+    pub fn fail(mut self, path_prefix: &str, status: StatusCode) -> Self {
+        self.device.failures.push((path_prefix.to_string(), status));
+        self
+    }
+
+    /// Add an entity, written as `domain/object_id`
+    pub fn entity(mut self, entity: &str) -> Self {
+        self.device.entities.insert(entity.to_string());
+        self
+    }
+
+    /// Leave out one of the default entities, as firmware that renamed it would
+    pub fn without_entity(mut self, entity: &str) -> Self {
+        self.device.entities.remove(entity);
+        self
+    }
+
+    /// Readings the binary sensor `name` gives in turn, the last one repeating
+    pub fn sensor(mut self, name: &str, readings: impl IntoIterator<Item = bool>) -> Self {
+        let entity = format!("binary_sensor/{name}");
+        self.device
+            .sensors
+            .insert(entity.clone(), readings.into_iter().collect());
+        self.device.entities.insert(entity);
+        self
+    }
+
+    /// States the numeric sensor `name` gives in turn, e.g. `45.5 °C`, the last one repeating
+    pub fn numeric_sensor<S: Into<String>>(
+        mut self,
+        name: &str,
+        states: impl IntoIterator<Item = S>,
+    ) -> Self {
+        let entity = format!("sensor/{name}");
+        self.device
+            .numeric_sensors
+            .insert(entity.clone(), states.into_iter().map(Into::into).collect());
+        self.device.entities.insert(entity);
+        self
+    }
+
+    /// A select entity with its options, starting on the first one
+    pub fn select(mut self, name: &str, options: &[&str]) -> Self {
+        let entity = format!("select/{name}");
+        let options: Vec<String> = options.iter().map(|option| option.to_string()).collect();
+        if let Some(first) = options.first() {
+            self.device.states.insert(entity.clone(), first.clone());
+        }
+        self.device.options.insert(entity.clone(), options);
+        self.device.entities.insert(entity);
+        self
+    }
+
+    /// Text of the `device_info` text sensor
+    pub fn device_info(mut self, info: &str) -> Self {
+        self.device
+            .states
+            .insert("text_sensor/device_info".to_string(), info.to_string());
+        self
+    }
+
+    /// Frames the MJPEG stream sends before it ends
+    pub fn stream_frames(mut self, frames: usize) -> Self {
+        self.device.stream_frames = frames;
+        self
+    }
+
+    /// Start answering on a free local port
+    pub async fn start(self) -> FakeEsphome {
+        let device = Arc::new(Mutex::new(self.device));
+        let router = Router::new().fallback(answer).with_state(device.clone());
+        let listener = TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("fake ESPHome device should bind");
+        let address = listener
+            .local_addr()
+            .expect("fake ESPHome device should have an address");
+        let listener = ResettingListener {
+            listener,
+            device: device.clone(),
+        };
+        let server = tokio::spawn(async move {
+            axum::serve(listener, router)
+                .await
+                .expect("fake ESPHome device should serve");
+        });
+        FakeEsphome {
+            address,
+            device,
+            server,
+        }
+    }
+}
+
+/// A running fake ESPHome device; it stops when dropped
+pub struct FakeEsphome {
+    address: SocketAddr,
+    device: Arc<Mutex<Device>>,
+    server: JoinHandle<()>,
+}
+
+impl Drop for FakeEsphome {
+    fn drop(&mut self) {
+        self.server.abort();
+    }
+}
+
+impl FakeEsphome {
+    pub fn builder() -> FakeEsphomeBuilder {
+        let mut states = HashMap::new();
+        states.insert(
+            "text_sensor/device_info".to_string(),
+            "Fake ESPHome device".to_string(),
+        );
+        FakeEsphomeBuilder {
+            device: Device {
+                entities: DEFAULT_ENTITIES
+                    .iter()
+                    .map(|entity| entity.to_string())
+                    .collect(),
+                states,
+                options: HashMap::new(),
+                sensors: HashMap::new(),
+                numeric_sensors: HashMap::new(),
+                failures: Vec::new(),
+                resets: 0,
+                authorization: None,
+                latency: Duration::ZERO,
+                stream_frames: 3,
+                snapshot: snapshot_jpeg(),
+                calls: Vec::new(),
+            },
+        }
+    }
+
+    /// A device with the default entities and no failures
+    pub async fn start() -> Self {
+        Self::builder().start().await
+    }
+
+    /// `host:port` to configure the sorter with
+    pub fn hostname(&self) -> String {
+        self.address.to_string()
+    }
+
+    fn device(&self) -> MutexGuard<'_, Device> {
+        self.device
+            .lock()
+            .expect("fake ESPHome device lock should not be poisoned")
+    }
+
+    /// Replace the readings the binary sensor `name` gives from now on
+    pub fn script_sensor(&self, name: &str, readings: impl IntoIterator<Item = bool>) {
+        self.device().sensors.insert(
+            format!("binary_sensor/{name}"),
+            readings.into_iter().collect(),
+        );
+    }
+
+    /// Start answering `status` to paths starting with `path_prefix`
+    pub fn fail(&self, path_prefix: &str, status: StatusCode) {
+        self.device()
+            .failures
+            .push((path_prefix.to_string(), status));
+    }
+
+    /// Stop failing paths starting with `path_prefix`
+    pub fn recover(&self, path_prefix: &str) {
+        self.device()
+            .failures
+            .retain(|(prefix, _)| prefix != path_prefix);
+    }
+
+    /// Reset the next `connections` connections without answering, as if the device were offline
+    pub fn reset_connections(&self, connections: usize) {
+        self.device().resets = connections;
+    }
+
+    /// Every request so far, oldest first
+    pub fn calls(&self) -> Vec<Call> {
+        self.device().calls.clone()
+    }
+
+    /// How many `method` requests were made to `path`
+    pub fn count(&self, method: Method, path: &str) -> usize {
+        self.device()
+            .calls
+            .iter()
+            .filter(|call| call.method == method && call.path == path)
+            .count()
+    }
+
+    /// How many times the button `name` was pressed
+    pub fn presses(&self, name: &str) -> usize {
+        self.count(Method::POST, &format!("/button/{name}/press"))
+    }
+
+    /// Current state of an entity written as `domain/object_id`, e.g. `ON` or a number's value
+    pub fn state(&self, entity: &str) -> Option<String> {
+        self.device().states.get(entity).cloned()
+    }
+
+    /// The JPEG the camera snapshot and stream serve
+    pub fn snapshot(&self) -> Vec<u8> {
+        self.device().snapshot.clone()
+    }
+}
+
+/// Answer one request the way an ESPHome device would
+async fn answer(
+    State(device): State<Arc<Mutex<Device>>>,
+    method: Method,
+    OriginalUri(uri): OriginalUri,
+    headers: HeaderMap,
+) -> Response {
+    let (latency, mut response) = {
+        let mut device = device
+            .lock()
+            .expect("fake ESPHome device lock should not be poisoned");
+        device.calls.push(Call {
+            method: method.clone(),
+            path: uri.path().to_string(),
+            query: uri.query().map(str::to_string),
+        });
+        let response = device.respond(&method, uri.path(), uri.query(), &headers);
+        (device.latency, response)
+    };
+    tokio::time::sleep(latency).await;
+    response
+        .headers_mut()
+        .insert(header::CONNECTION, HeaderValue::from_static("close"));
+    response
+}
+
+/// Accepts connections for the device, resetting the ones it was told to
+struct ResettingListener {
+    listener: TcpListener,
+    device: Arc<Mutex<Device>>,
+}
+
+impl axum::serve::Listener for ResettingListener {
+    type Io = TcpStream;
+    type Addr = SocketAddr;
+
+    async fn accept(&mut self) -> (Self::Io, Self::Addr) {
+        loop {
+            let Ok((stream, address)) = self.listener.accept().await else {
+                continue;
+            };
+            let reset = {
+                let mut device = self
+                    .device
+                    .lock()
+                    .expect("fake ESPHome device lock should not be poisoned");
+                let reset = device.resets > 0;
+                device.resets = device.resets.saturating_sub(1);
+                reset
+            };
+            if !reset {
+                return (stream, address);
+            }
+            // Closing with a zero linger sends a reset rather than a clean close
+            stream
+                .set_zero_linger()
+                .expect("connection should take a zero linger");
+        }
+    }
+
+    fn local_addr(&self) -> std::io::Result<Self::Addr> {
+        self.listener.local_addr()
+    }
+}
+
+impl Device {
+    fn respond(
+        &mut self,
+        method: &Method,
+        path: &str,
+        query: Option<&str>,
+        headers: &HeaderMap,
+    ) -> Response {
+        if let Some(expected) = &self.authorization {
+            let given = headers
+                .get(header::AUTHORIZATION)
+                .and_then(|value| value.to_str().ok());
+            if given != Some(expected.as_str()) {
+                return StatusCode::UNAUTHORIZED.into_response();
+            }
+        }
+        if let Some((_, status)) = self
+            .failures
+            .iter()
+            .find(|(prefix, _)| path.starts_with(prefix.as_str()))
+        {
+            return (*status, "injected failure").into_response();
+        }
+
+        let segments: Vec<&str> = path.trim_matches('/').split('/').collect();
+        match (method, segments.as_slice()) {
+            (&Method::GET, [""]) => "<html>ESPHome Web Server</html>".into_response(),
+            (&Method::GET, ["camera", "snapshot"]) => (
+                [(header::CONTENT_TYPE, "image/jpeg")],
+                self.snapshot.clone(),
+            )
+                .into_response(),
+            (&Method::GET, ["camera", "stream"]) => self.stream(),
+            (_, [domain, name, ..]) if !self.entities.contains(&format!("{domain}/{name}")) => {
+                StatusCode::NOT_FOUND.into_response()
+            }
+            (&Method::GET, ["sensor", name]) => self.numeric_sensor_json(name),
+            (&Method::GET, [domain, name]) => self.entity_json(domain, name),
+            (&Method::GET, [domain, name, "state"]) => self.read_state(&format!("{domain}/{name}")),
+            (&Method::POST, [domain, name, action]) => {
+                self.act(&format!("{domain}/{name}"), action, query)
+            }
+            _ => StatusCode::NOT_FOUND.into_response(),
+        }
+    }
+
+    /// The next binary sensor reading, or the state of any other entity, as plain text
+    fn read_state(&mut self, entity: &str) -> Response {
+        if entity.starts_with("binary_sensor/") {
+            return if self.next_reading(entity) {
+                "ON"
+            } else {
+                "OFF"
+            }
+            .into_response();
+        }
+        self.states
+            .get(entity)
+            .cloned()
+            .unwrap_or_default()
+            .into_response()
+    }
+
+    fn next_reading(&mut self, entity: &str) -> bool {
+        let Some(readings) = self.sensors.get_mut(entity) else {
+            return false;
+        };
+        if readings.len() > 1 {
+            readings.pop_front().unwrap_or_default()
+        } else {
+            readings.front().copied().unwrap_or_default()
+        }
+    }
+
+    /// The numeric sensor's next state, with its value the way ESPHome's REST API gives both
+    fn numeric_sensor_json(&mut self, name: &str) -> Response {
+        let id = format!("sensor-{name}");
+        let Some(states) = self.numeric_sensors.get_mut(&format!("sensor/{name}")) else {
+            return axum::Json(serde_json::json!({ "id": id, "state": "NA" })).into_response();
+        };
+        let state = if states.len() > 1 {
+            states.pop_front().unwrap_or_default()
+        } else {
+            states.front().cloned().unwrap_or_default()
+        };
+        let value = state
+            .split_whitespace()
+            .next()
+            .and_then(|number| number.parse::<f64>().ok());
+        axum::Json(serde_json::json!({ "id": id, "value": value, "state": state })).into_response()
+    }
+
+    /// The entity's JSON, as ESPHome's REST API returns it
+    fn entity_json(&self, domain: &str, name: &str) -> Response {
+        let entity = format!("{domain}/{name}");
+        let state = match domain {
+            "binary_sensor" => {
+                let on = self
+                    .sensors
+                    .get(&entity)
+                    .and_then(|readings| readings.front())
+                    .copied()
+                    .unwrap_or_default();
+                Some(if on { "ON" } else { "OFF" }.to_string())
+            }
+            _ => self.states.get(&entity).cloned(),
+        };
+        let mut body = serde_json::json!({
+            "id": format!("{domain}-{name}"),
+            "state": state,
+        });
+        if let Some(options) = self.options.get(&entity) {
+            body["option"] = serde_json::json!(options);
+        }
+        axum::Json(body).into_response()
+    }
+
+    /// Press a button, switch a switch or light, or set a number or select
+    fn act(&mut self, entity: &str, action: &str, query: Option<&str>) -> Response {
+        let parameter = |key: &str| {
+            query?.split('&').find_map(|pair| {
+                let (name, value) = pair.split_once('=')?;
+                (name == key).then(|| value.replace("%20", " ").replace('+', " "))
+            })
+        };
+        let state = match action {
+            "press" => None,
+            "turn_on" => Some("ON".to_string()),
+            "turn_off" => Some("OFF".to_string()),
+            "toggle" => Some(
+                if self.states.get(entity).map(String::as_str) == Some("ON") {
+                    "OFF"
+                } else {
+                    "ON"
+                }
+                .to_string(),
+            ),
+            "set" => match parameter("value").or_else(|| parameter("option")) {
+                Some(value) => Some(value),
+                None => return StatusCode::BAD_REQUEST.into_response(),
+            },
+            _ => return StatusCode::NOT_FOUND.into_response(),
+        };
+        if let Some(state) = state {
+            self.states.insert(entity.to_string(), state);
+        }
+        StatusCode::OK.into_response()
+    }
+
+    /// A multipart MJPEG stream of the snapshot that ends after `stream_frames` frames
+    fn stream(&self) -> Response {
+        let mut body = Vec::new();
+        for _ in 0..self.stream_frames {
+            body.extend_from_slice(
+                format!(
+                    "--{STREAM_BOUNDARY}\r\nContent-Type: image/jpeg\r\nContent-Length: {}\r\n\r\n",
+                    self.snapshot.len()
+                )
+                .as_bytes(),
+            );
+            body.extend_from_slice(&self.snapshot);
+            body.extend_from_slice(b"\r\n");
+        }
+        (
+            [(
+                header::CONTENT_TYPE,
+                format!("multipart/x-mixed-replace; boundary={STREAM_BOUNDARY}"),
+            )],
+            body,
+        )
+            .into_response()
+    }
+}
+
+/// A small grey JPEG
+fn snapshot_jpeg() -> Vec<u8> {
+    let image = image::RgbImage::from_pixel(16, 12, image::Rgb([128, 128, 128]));
+    let mut jpeg = Vec::new();
+    JpegEncoder::new_with_quality(&mut jpeg, 80)
+        .encode_image(&image)
+        .expect("snapshot should encode");
+    jpeg
+}
+
+/// Standard base64 with padding, for the basic auth header
+fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut encoded = String::new();
+    for chunk in bytes.chunks(3) {
+        let group = chunk.iter().enumerate().fold(0u32, |group, (i, byte)| {
+            group | u32::from(*byte) << (16 - 8 * i)
+        });
+        for i in 0..4 {
+            if i <= chunk.len() {
+                encoded.push(char::from(
+                    ALPHABET[(group >> (18 - 6 * i) & 0x3f) as usize],
+                ));
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}